@@ -0,0 +1,23 @@
+-- Versioned score weights. The schema with the highest version is used to
+-- compute package scores, so the weights can be tuned without a redeploy.
+-- Every package version records the schema version that was active when it
+-- was published in `meta.scoreSchemaVersion`.
+CREATE TABLE score_schemas (
+    version integer NOT NULL PRIMARY KEY,
+    weights jsonb NOT NULL,
+    max_score integer NOT NULL CHECK (max_score > 0),
+    created_by uuid REFERENCES users(id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO score_schemas (version, weights, max_score) VALUES (1, '{
+  "hasReadme": 2,
+  "hasReadmeExamples": 1,
+  "allEntrypointsDocs": 1,
+  "percentageDocumentedSymbols": 5,
+  "allFastCheck": 5,
+  "hasProvenance": 1,
+  "hasDescription": 1,
+  "atLeastOneRuntimeCompatible": 1,
+  "multipleRuntimesCompatible": 1
+}'::jsonb, 17);
@@ -0,0 +1,3 @@
+-- Translations of the package description, keyed by lowercase BCP 47 language
+-- tag. The `description` column stays the untranslated fallback.
+ALTER TABLE packages ADD COLUMN localized_descriptions jsonb NOT NULL DEFAULT '{}'::jsonb;
@@ -0,0 +1,22 @@
+-- Admin-triggered jobs that re-run score generation for every package version
+-- from its stored doc nodes and readme. Progress is written back after every
+-- batch so it can be polled while the job runs.
+CREATE TYPE score_recompute_job_status AS ENUM ('running', 'completed', 'failed');
+
+CREATE TABLE score_recompute_jobs (
+    id uuid NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    status score_recompute_job_status NOT NULL DEFAULT 'running',
+    total_versions integer NOT NULL,
+    processed_versions integer NOT NULL DEFAULT 0,
+    failed_versions integer NOT NULL DEFAULT 0,
+    error text,
+    created_by uuid REFERENCES users(id),
+    finished_at TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+SELECT manage_updated_at('score_recompute_jobs');
+
+-- At most one job may run at a time.
+CREATE UNIQUE INDEX score_recompute_jobs_running_idx
+    ON score_recompute_jobs ((true)) WHERE status = 'running';
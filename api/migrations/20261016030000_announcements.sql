@@ -0,0 +1,20 @@
+-- Registry-wide announcements written by staff, used to tell CLI and web
+-- clients about incidents, policy changes and deprecations. Each entry is
+-- tagged with the features it affects, so clients can show only the ones that
+-- are relevant to them.
+CREATE TYPE announcement_severity AS ENUM ('info', 'warning', 'critical');
+
+CREATE TABLE announcements (
+    id uuid NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    title text NOT NULL,
+    body text NOT NULL,
+    severity announcement_severity NOT NULL,
+    features text[] NOT NULL DEFAULT '{}',
+    expires_at TIMESTAMPTZ,
+    created_by uuid REFERENCES users(id) ON DELETE SET NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+SELECT manage_updated_at('announcements');
+
+CREATE INDEX announcements_created_at_idx ON announcements (created_at DESC);
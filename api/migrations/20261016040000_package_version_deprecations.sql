@@ -0,0 +1,15 @@
+-- Exported symbols that are tagged with `@deprecated`, collected from the doc
+-- nodes of each entrypoint at publish time.
+CREATE TABLE package_version_deprecations (
+    scope text NOT NULL,
+    name text NOT NULL,
+    version text NOT NULL,
+    export text NOT NULL,
+    symbol text NOT NULL,
+    message text,
+    updated_at timestamptz NOT NULL DEFAULT now(),
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (scope, name, version, export, symbol),
+    FOREIGN KEY (scope, name, version) REFERENCES package_versions (scope, name, version) ON DELETE CASCADE
+);
+SELECT manage_updated_at('package_version_deprecations');
@@ -0,0 +1,12 @@
+-- Scope members whose new-member cooldown was lifted early by another scope
+-- admin, allowing them to publish to and manage popular packages right away.
+CREATE TABLE scope_member_cooldown_overrides (
+    scope text NOT NULL,
+    user_id uuid NOT NULL,
+    lifted_by uuid NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    updated_at timestamptz NOT NULL DEFAULT now(),
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (scope, user_id),
+    FOREIGN KEY (scope, user_id) REFERENCES scope_members (scope, user_id) ON DELETE CASCADE
+);
+SELECT manage_updated_at('scope_member_cooldown_overrides');
@@ -0,0 +1,3 @@
+-- Dependencies that a package only imports through the optional dependency
+-- pattern (a dynamic import inside a try block), so it works without them.
+ALTER TABLE package_version_dependencies ADD COLUMN is_optional boolean NOT NULL DEFAULT false;
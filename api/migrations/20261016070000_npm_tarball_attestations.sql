@@ -0,0 +1,3 @@
+-- The transparency log entry of the publish attestation the registry signed
+-- for an npm tarball, if any.
+ALTER TABLE npm_tarballs ADD COLUMN rekor_log_id TEXT;
@@ -0,0 +1,27 @@
+-- Admin-triggered jobs that build the npm tarballs for the current revision
+-- for all versions of a package, a scope, or the whole registry. Progress is
+-- written back after every batch so it can be polled while the job runs.
+CREATE TYPE npm_tarball_rebuild_job_status AS ENUM ('running', 'completed', 'failed');
+
+CREATE TABLE npm_tarball_rebuild_jobs (
+    id uuid NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    status npm_tarball_rebuild_job_status NOT NULL DEFAULT 'running',
+    scope text REFERENCES scopes(scope) ON DELETE CASCADE,
+    name text,
+    verify boolean NOT NULL DEFAULT false,
+    concurrency integer NOT NULL,
+    total_versions integer NOT NULL,
+    processed_versions integer NOT NULL DEFAULT 0,
+    failed_versions integer NOT NULL DEFAULT 0,
+    error text,
+    created_by uuid REFERENCES users(id),
+    finished_at TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CONSTRAINT npm_tarball_rebuild_jobs_name_requires_scope CHECK (name IS NULL OR scope IS NOT NULL)
+);
+SELECT manage_updated_at('npm_tarball_rebuild_jobs');
+
+-- At most one job may run at a time.
+CREATE UNIQUE INDEX npm_tarball_rebuild_jobs_running_idx
+    ON npm_tarball_rebuild_jobs ((true)) WHERE status = 'running';
@@ -0,0 +1,18 @@
+-- Exported symbols of each entrypoint, collected from the doc nodes at publish
+-- time, so packages can be searched by the symbols they export.
+CREATE TABLE package_version_symbols (
+    scope text NOT NULL,
+    name text NOT NULL,
+    version text NOT NULL,
+    export text NOT NULL,
+    symbol text NOT NULL,
+    kind text NOT NULL,
+    updated_at timestamptz NOT NULL DEFAULT now(),
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (scope, name, version, export, symbol, kind),
+    FOREIGN KEY (scope, name, version) REFERENCES package_versions (scope, name, version) ON DELETE CASCADE
+);
+SELECT manage_updated_at('package_version_symbols');
+
+-- Symbol search matches case insensitive prefixes.
+CREATE INDEX package_version_symbols_symbol_idx ON package_version_symbols (lower(symbol) text_pattern_ops);
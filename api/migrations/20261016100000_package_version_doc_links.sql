@@ -0,0 +1,15 @@
+-- The documentation page of every exported symbol, including namespace
+-- members, so that symbol permalinks can be resolved regardless of which
+-- entrypoint the symbol is exported from in a given version.
+CREATE TABLE package_version_doc_links (
+    scope text NOT NULL,
+    name text NOT NULL,
+    version text NOT NULL,
+    identifier text NOT NULL,
+    path text NOT NULL,
+    updated_at timestamptz NOT NULL DEFAULT now(),
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (scope, name, version, identifier),
+    FOREIGN KEY (scope, name, version) REFERENCES package_versions (scope, name, version) ON DELETE CASCADE
+);
+SELECT manage_updated_at('package_version_doc_links');
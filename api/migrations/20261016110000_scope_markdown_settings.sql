@@ -0,0 +1,11 @@
+-- Markdown extensions a scope allows when rendering markdown through the
+-- render endpoint, on top of the strict defaults. Scopes without a row use
+-- the defaults.
+CREATE TABLE scope_markdown_settings (
+    scope text PRIMARY KEY REFERENCES scopes (scope) ON DELETE CASCADE,
+    allow_html boolean NOT NULL DEFAULT false,
+    admonitions text[] NOT NULL DEFAULT '{}',
+    updated_at timestamptz NOT NULL DEFAULT now(),
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+SELECT manage_updated_at('scope_markdown_settings');
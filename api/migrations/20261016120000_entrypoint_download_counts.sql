@@ -0,0 +1,27 @@
+-- Fetches of the entrypoint modules of package versions, keyed by export, so
+-- that downloads can be broken down by entrypoint. Like the version download
+-- counts, the 4h buckets are scraped and pruned, and the 24h buckets are
+-- derived from them.
+CREATE TABLE entrypoint_download_counts_4h (
+  scope TEXT NOT NULL,
+  package TEXT NOT NULL,
+  version TEXT NOT NULL,
+  entrypoint TEXT NOT NULL,
+  time_bucket TIMESTAMP WITH TIME ZONE NOT NULL,
+  count INTEGER NOT NULL,
+  PRIMARY KEY (scope, package, version, entrypoint, time_bucket),
+  FOREIGN KEY (scope, package, version) REFERENCES package_versions (scope, name, version) ON DELETE CASCADE
+);
+
+CREATE TABLE entrypoint_download_counts_24h (
+  scope TEXT NOT NULL,
+  package TEXT NOT NULL,
+  version TEXT NOT NULL,
+  entrypoint TEXT NOT NULL,
+  time_bucket TIMESTAMP WITH TIME ZONE NOT NULL,
+  count INTEGER NOT NULL,
+  PRIMARY KEY (scope, package, version, entrypoint, time_bucket),
+  FOREIGN KEY (scope, package, version) REFERENCES package_versions (scope, name, version) ON DELETE CASCADE
+);
+
+CREATE INDEX entrypoint_download_counts_24h_package_idx ON entrypoint_download_counts_24h (scope, package, time_bucket DESC);
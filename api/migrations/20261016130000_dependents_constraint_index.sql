@@ -0,0 +1,5 @@
+-- Listing the dependents of a package for a version range filters its
+-- dependency rows by constraint, so index the constraints of each dependency.
+CREATE INDEX idx_package_version_deps_kind_name_constraint
+  ON package_version_dependencies (dependency_kind, dependency_name, dependency_constraint)
+  INCLUDE (package_scope, package_name, package_version);
@@ -0,0 +1,48 @@
+-- Webhooks registered by scope admins. Every event of a scope that a webhook
+-- subscribes to queues a delivery, which the `deliver_webhooks` task sends as
+-- a JSON payload signed with the webhook's secret.
+CREATE TYPE webhook_event AS ENUM (
+    'version_published',
+    'version_yanked',
+    'new_dependent',
+    'scope_member_added',
+    'scope_member_updated',
+    'scope_member_removed'
+);
+
+CREATE TABLE scope_webhooks (
+    id uuid NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    scope text NOT NULL REFERENCES scopes (scope) ON DELETE CASCADE,
+    url text NOT NULL,
+    secret text NOT NULL,
+    events webhook_event[] NOT NULL,
+    description text NOT NULL DEFAULT '',
+    is_active boolean NOT NULL DEFAULT true,
+    created_by uuid REFERENCES users (id) ON DELETE SET NULL,
+    updated_at timestamptz NOT NULL DEFAULT now(),
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+SELECT manage_updated_at('scope_webhooks');
+CREATE INDEX scope_webhooks_scope_idx ON scope_webhooks (scope);
+
+-- Failed deliveries stay `pending` and are retried with backoff at
+-- `next_attempt_at`, until they succeed or run out of attempts.
+CREATE TYPE webhook_delivery_status AS ENUM ('pending', 'success', 'failure');
+
+CREATE TABLE webhook_deliveries (
+    id uuid NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    webhook_id uuid NOT NULL REFERENCES scope_webhooks (id) ON DELETE CASCADE,
+    event webhook_event NOT NULL,
+    payload jsonb NOT NULL,
+    status webhook_delivery_status NOT NULL DEFAULT 'pending',
+    attempts integer NOT NULL DEFAULT 0,
+    next_attempt_at timestamptz NOT NULL DEFAULT now(),
+    last_response_status integer,
+    last_error text,
+    delivered_at timestamptz,
+    updated_at timestamptz NOT NULL DEFAULT now(),
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+SELECT manage_updated_at('webhook_deliveries');
+CREATE INDEX webhook_deliveries_webhook_id_idx ON webhook_deliveries (webhook_id, created_at DESC);
+CREATE INDEX webhook_deliveries_pending_idx ON webhook_deliveries (next_attempt_at) WHERE status = 'pending';
@@ -0,0 +1,14 @@
+-- Named release channels of a package, like `beta` or `canary`, that point
+-- at a version. A `latest` tag overrides the version that is otherwise picked
+-- as the latest one.
+CREATE TABLE package_dist_tags (
+    scope text NOT NULL,
+    name text NOT NULL,
+    tag text NOT NULL,
+    version text NOT NULL,
+    updated_at timestamptz NOT NULL DEFAULT now(),
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (scope, name, tag),
+    FOREIGN KEY (scope, name, version) REFERENCES package_versions (scope, name, version) ON DELETE CASCADE
+);
+SELECT manage_updated_at('package_dist_tags');
@@ -0,0 +1,19 @@
+-- When and why a version was yanked. Both are cleared when the version is
+-- unyanked.
+ALTER TABLE package_versions ADD COLUMN yanked_at timestamptz;
+ALTER TABLE package_versions ADD COLUMN yank_reason text;
+
+UPDATE package_versions
+SET yanked_at = COALESCE(
+    (SELECT max(audit_logs.created_at)
+     FROM audit_logs
+     WHERE audit_logs.action = 'yank_package_version'
+       AND audit_logs.meta->>'scope' = package_versions.scope
+       AND audit_logs.meta->>'name' = package_versions.name
+       AND audit_logs.meta->>'version' = package_versions.version),
+    package_versions.updated_at
+)
+WHERE is_yanked;
+
+ALTER TABLE package_versions ADD CONSTRAINT package_versions_yank_tombstone_check
+    CHECK (is_yanked = (yanked_at IS NOT NULL) AND (is_yanked OR yank_reason IS NULL));
@@ -0,0 +1,43 @@
+-- Moving a package to another scope renames its rows in place, so every
+-- foreign key to a package or a package version has to follow the rename.
+DO $$
+DECLARE
+    fk record;
+BEGIN
+    FOR fk IN
+        SELECT con.conname, con.conrelid::regclass AS tbl, pg_get_constraintdef(con.oid) AS def
+        FROM pg_constraint con
+        WHERE con.contype = 'f'
+          AND con.confrelid IN ('packages'::regclass, 'package_versions'::regclass)
+          AND con.confupdtype <> 'c'
+    LOOP
+        EXECUTE format('ALTER TABLE %s DROP CONSTRAINT %I', fk.tbl, fk.conname);
+        EXECUTE format('ALTER TABLE %s ADD CONSTRAINT %I %s ON UPDATE CASCADE', fk.tbl, fk.conname, fk.def);
+    END LOOP;
+END $$;
+
+-- A pending request to move a package to another scope. It is created by an
+-- admin of the package's scope and accepted by an admin of the target scope.
+CREATE TABLE package_transfers (
+    scope text NOT NULL,
+    name text NOT NULL,
+    target_scope text NOT NULL REFERENCES scopes (scope) ON DELETE CASCADE,
+    requested_by uuid NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (scope, name),
+    FOREIGN KEY (scope, name) REFERENCES packages (scope, name) ON UPDATE CASCADE ON DELETE CASCADE,
+    CHECK (scope <> target_scope)
+);
+CREATE INDEX package_transfers_target_scope_idx ON package_transfers (target_scope);
+
+-- Where a package that was moved to another scope lives now. Redirects are
+-- kept after the move, so that the old name keeps resolving.
+CREATE TABLE package_redirects (
+    scope text NOT NULL,
+    name text NOT NULL,
+    target_scope text NOT NULL,
+    target_name text NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (scope, name)
+);
+CREATE INDEX package_redirects_target_idx ON package_redirects (target_scope, target_name);
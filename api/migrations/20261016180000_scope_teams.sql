@@ -0,0 +1,42 @@
+-- Teams group members of a scope, and grant them permissions on specific
+-- packages. A package that at least one team has been granted access to is
+-- restricted: members that are not scope admins may then only do what one of
+-- their teams grants them for the package.
+CREATE TABLE scope_teams (
+    scope text NOT NULL REFERENCES scopes (scope) ON DELETE CASCADE,
+    name text NOT NULL,
+    description text NOT NULL DEFAULT '',
+    updated_at timestamptz NOT NULL DEFAULT now(),
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (scope, name),
+    CONSTRAINT scope_teams_name_check CHECK (name ~ '^[a-z0-9][a-z0-9-]{0,31}$')
+);
+SELECT manage_updated_at('scope_teams');
+
+-- Leaving the scope removes the user from all of its teams.
+CREATE TABLE scope_team_members (
+    scope text NOT NULL,
+    team text NOT NULL,
+    user_id uuid NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (scope, team, user_id),
+    FOREIGN KEY (scope, team) REFERENCES scope_teams (scope, name) ON DELETE CASCADE,
+    FOREIGN KEY (scope, user_id) REFERENCES scope_members (scope, user_id) ON DELETE CASCADE
+);
+CREATE INDEX scope_team_members_user_idx ON scope_team_members (scope, user_id);
+
+CREATE TABLE scope_team_packages (
+    scope text NOT NULL,
+    team text NOT NULL,
+    package text NOT NULL,
+    can_publish boolean NOT NULL DEFAULT false,
+    can_yank boolean NOT NULL DEFAULT false,
+    can_manage_tokens boolean NOT NULL DEFAULT false,
+    updated_at timestamptz NOT NULL DEFAULT now(),
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (scope, team, package),
+    FOREIGN KEY (scope, team) REFERENCES scope_teams (scope, name) ON DELETE CASCADE,
+    FOREIGN KEY (scope, package) REFERENCES packages (scope, name) ON DELETE CASCADE ON UPDATE CASCADE
+);
+SELECT manage_updated_at('scope_team_packages');
+CREATE INDEX scope_team_packages_package_idx ON scope_team_packages (scope, package);
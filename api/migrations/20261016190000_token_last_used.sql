@@ -0,0 +1,3 @@
+-- When a token was last used to authenticate a request. This is only updated
+-- at most once a minute per token.
+ALTER TABLE tokens ADD COLUMN last_used_at timestamptz;
@@ -0,0 +1,4 @@
+-- Scope admins can list the audit logs of their scope, which are found by the
+-- scope a logged action was taken in, or the scope a package was moved to.
+CREATE INDEX audit_logs_scope_created_at_idx ON audit_logs ((meta->>'scope'), created_at DESC);
+CREATE INDEX audit_logs_target_scope_idx ON audit_logs ((meta->>'target_scope'));
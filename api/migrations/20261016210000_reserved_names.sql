@@ -0,0 +1,694 @@
+-- Scope and package names that can not be claimed through the API, managed by
+-- staff. Scope names are stored without hyphens, as they are compared with
+-- the hyphens of the requested name removed. Once `expires_at` has passed, the
+-- name can be claimed again.
+CREATE TYPE reserved_name_kind AS ENUM ('scope', 'package');
+
+CREATE TABLE reserved_names (
+    kind reserved_name_kind NOT NULL,
+    name text NOT NULL,
+    reason text,
+    expires_at TIMESTAMPTZ,
+    created_by uuid REFERENCES users(id) ON DELETE SET NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (kind, name)
+);
+SELECT manage_updated_at('reserved_names');
+
+-- The scopes that were previously reserved in `reserved_scopes.json`.
+INSERT INTO reserved_names (kind, name) VALUES
+    ('scope', 'abbrev'),
+    ('scope', 'accepts'),
+    ('scope', 'acorn'),
+    ('scope', 'adobe'),
+    ('scope', 'agentbase'),
+    ('scope', 'ai'),
+    ('scope', 'airbnb'),
+    ('scope', 'airtable'),
+    ('scope', 'ajv'),
+    ('scope', 'ajvkeywords'),
+    ('scope', 'algolia'),
+    ('scope', 'amazon'),
+    ('scope', 'android'),
+    ('scope', 'angular'),
+    ('scope', 'ansiescapes'),
+    ('scope', 'ansiregex'),
+    ('scope', 'ansistyles'),
+    ('scope', 'antd'),
+    ('scope', 'anymatch'),
+    ('scope', 'anypromise'),
+    ('scope', 'apache'),
+    ('scope', 'apollo'),
+    ('scope', 'apple'),
+    ('scope', 'appwrite'),
+    ('scope', 'archiver'),
+    ('scope', 'argparse'),
+    ('scope', 'arrayunion'),
+    ('scope', 'assemblyai'),
+    ('scope', 'assert'),
+    ('scope', 'astro'),
+    ('scope', 'astrojs'),
+    ('scope', 'asttypes'),
+    ('scope', 'async'),
+    ('scope', 'atlassian'),
+    ('scope', 'aurelia'),
+    ('scope', 'auth0'),
+    ('scope', 'autoprefixer'),
+    ('scope', 'aws'),
+    ('scope', 'awssdk'),
+    ('scope', 'axecore'),
+    ('scope', 'axios'),
+    ('scope', 'azure'),
+    ('scope', 'babel'),
+    ('scope', 'backbone'),
+    ('scope', 'balancedmatch'),
+    ('scope', 'base64js'),
+    ('scope', 'bcrypt'),
+    ('scope', 'bcryptjs'),
+    ('scope', 'begin'),
+    ('scope', 'biginteger'),
+    ('scope', 'bigjs'),
+    ('scope', 'bignumberjs'),
+    ('scope', 'bindings'),
+    ('scope', 'bitcoin'),
+    ('scope', 'bitwarden'),
+    ('scope', 'bl'),
+    ('scope', 'bluebird'),
+    ('scope', 'bnjs'),
+    ('scope', 'bodyparser'),
+    ('scope', 'bootstrap'),
+    ('scope', 'boxen'),
+    ('scope', 'braceexpansion'),
+    ('scope', 'brex'),
+    ('scope', 'browserify'),
+    ('scope', 'browsersync'),
+    ('scope', 'buffer'),
+    ('scope', 'buffercrc32'),
+    ('scope', 'bun'),
+    ('scope', 'bunyan'),
+    ('scope', 'busboy'),
+    ('scope', 'bytes'),
+    ('scope', 'callsites'),
+    ('scope', 'camelcase'),
+    ('scope', 'canbus'),
+    ('scope', 'caniuselite'),
+    ('scope', 'canonical'),
+    ('scope', 'canvas'),
+    ('scope', 'cfw'),
+    ('scope', 'chalk'),
+    ('scope', 'changecase'),
+    ('scope', 'cheerio'),
+    ('scope', 'chokidar'),
+    ('scope', 'ciinfo'),
+    ('scope', 'circleci'),
+    ('scope', 'classnames'),
+    ('scope', 'clicursor'),
+    ('scope', 'clispinners'),
+    ('scope', 'clitable'),
+    ('scope', 'cliui'),
+    ('scope', 'clone'),
+    ('scope', 'clonedeep'),
+    ('scope', 'cloudflare'),
+    ('scope', 'cloudinary'),
+    ('scope', 'clsx'),
+    ('scope', 'co'),
+    ('scope', 'code'),
+    ('scope', 'codecademy'),
+    ('scope', 'coinbase'),
+    ('scope', 'color'),
+    ('scope', 'colorconvert'),
+    ('scope', 'colorname'),
+    ('scope', 'colors'),
+    ('scope', 'combinedstream'),
+    ('scope', 'commander'),
+    ('scope', 'commandlineargs'),
+    ('scope', 'commontags'),
+    ('scope', 'compressible'),
+    ('scope', 'compression'),
+    ('scope', 'concatmap'),
+    ('scope', 'concatstream'),
+    ('scope', 'concurrently'),
+    ('scope', 'config'),
+    ('scope', 'configstore'),
+    ('scope', 'connect'),
+    ('scope', 'contentful'),
+    ('scope', 'contenttype'),
+    ('scope', 'convertsourcemap'),
+    ('scope', 'cookie'),
+    ('scope', 'cookieparser'),
+    ('scope', 'copywebpackplugin'),
+    ('scope', 'corejs'),
+    ('scope', 'cors'),
+    ('scope', 'cosmiconfig'),
+    ('scope', 'couchbase'),
+    ('scope', 'crossenv'),
+    ('scope', 'crossfetch'),
+    ('scope', 'crossspawn'),
+    ('scope', 'cryptojs'),
+    ('scope', 'css'),
+    ('scope', 'cssloader'),
+    ('scope', 'cssnano'),
+    ('scope', 'csv'),
+    ('scope', 'd3'),
+    ('scope', 'databricks'),
+    ('scope', 'datadog'),
+    ('scope', 'datastax'),
+    ('scope', 'datefns'),
+    ('scope', 'dateformat'),
+    ('scope', 'dayjs'),
+    ('scope', 'debounce'),
+    ('scope', 'debug'),
+    ('scope', 'decamelize'),
+    ('scope', 'decimaljs'),
+    ('scope', 'deco'),
+    ('scope', 'decocx'),
+    ('scope', 'deel'),
+    ('scope', 'deepequal'),
+    ('scope', 'deepis'),
+    ('scope', 'deepmerge'),
+    ('scope', 'del'),
+    ('scope', 'depd'),
+    ('scope', 'diff'),
+    ('scope', 'digitalocean'),
+    ('scope', 'docker'),
+    ('scope', 'doctrine'),
+    ('scope', 'dom'),
+    ('scope', 'domhandler'),
+    ('scope', 'dompurify'),
+    ('scope', 'doordash'),
+    ('scope', 'dotenv'),
+    ('scope', 'dotenvexpand'),
+    ('scope', 'dropbox'),
+    ('scope', 'ejs'),
+    ('scope', 'emacs'),
+    ('scope', 'ember'),
+    ('scope', 'emojiregex'),
+    ('scope', 'encoding'),
+    ('scope', 'endofstream'),
+    ('scope', 'enhance'),
+    ('scope', 'enquirer'),
+    ('scope', 'error'),
+    ('scope', 'errors'),
+    ('scope', 'es'),
+    ('scope', 'es6promise'),
+    ('scope', 'esbuild'),
+    ('scope', 'escapehtml'),
+    ('scope', 'escapestringregexp'),
+    ('scope', 'escodegen'),
+    ('scope', 'eslint'),
+    ('scope', 'espree'),
+    ('scope', 'esprima'),
+    ('scope', 'esrecurse'),
+    ('scope', 'estraverse'),
+    ('scope', 'esutils'),
+    ('scope', 'ethereum'),
+    ('scope', 'eventemitter2'),
+    ('scope', 'eventemitter3'),
+    ('scope', 'events'),
+    ('scope', 'example'),
+    ('scope', 'execa'),
+    ('scope', 'expect'),
+    ('scope', 'express'),
+    ('scope', 'expressvalidator'),
+    ('scope', 'extend'),
+    ('scope', 'facebook'),
+    ('scope', 'faker'),
+    ('scope', 'fastdeepequal'),
+    ('scope', 'fastglob'),
+    ('scope', 'fastify'),
+    ('scope', 'fastjsonstablestringify'),
+    ('scope', 'fastlevenshtein'),
+    ('scope', 'fastly'),
+    ('scope', 'fastq'),
+    ('scope', 'fastxmlparser'),
+    ('scope', 'fauna'),
+    ('scope', 'fbwatchman'),
+    ('scope', 'fetch'),
+    ('scope', 'ffmpeg'),
+    ('scope', 'figma'),
+    ('scope', 'figures'),
+    ('scope', 'fileentrycache'),
+    ('scope', 'fileloader'),
+    ('scope', 'filesize'),
+    ('scope', 'filetype'),
+    ('scope', 'finalhandler'),
+    ('scope', 'findup'),
+    ('scope', 'firebase'),
+    ('scope', 'firebaseadmin'),
+    ('scope', 'flat'),
+    ('scope', 'flatcache'),
+    ('scope', 'flock'),
+    ('scope', 'flyio'),
+    ('scope', 'followredirects'),
+    ('scope', 'fontawesome'),
+    ('scope', 'foreach'),
+    ('scope', 'formdata'),
+    ('scope', 'formidable'),
+    ('scope', 'front'),
+    ('scope', 'fsevents'),
+    ('scope', 'fsextra'),
+    ('scope', 'function'),
+    ('scope', 'gatsby'),
+    ('scope', 'gatsbyjs'),
+    ('scope', 'gcloud'),
+    ('scope', 'gcp'),
+    ('scope', 'gensync'),
+    ('scope', 'getcallerfile'),
+    ('scope', 'getintrinsic'),
+    ('scope', 'getstream'),
+    ('scope', 'github'),
+    ('scope', 'gitlab'),
+    ('scope', 'globals'),
+    ('scope', 'globby'),
+    ('scope', 'globparent'),
+    ('scope', 'google'),
+    ('scope', 'googleapis'),
+    ('scope', 'googleauthlibrary'),
+    ('scope', 'googlecloud'),
+    ('scope', 'got'),
+    ('scope', 'gracefulfs'),
+    ('scope', 'grafana'),
+    ('scope', 'grammy'),
+    ('scope', 'graphql'),
+    ('scope', 'grunt'),
+    ('scope', 'gulp'),
+    ('scope', 'has'),
+    ('scope', 'hasflag'),
+    ('scope', 'hashicorp'),
+    ('scope', 'he'),
+    ('scope', 'helmet'),
+    ('scope', 'heroku'),
+    ('scope', 'highlightjs'),
+    ('scope', 'history'),
+    ('scope', 'hoistnonreactstatics'),
+    ('scope', 'hostedgitinfo'),
+    ('scope', 'html'),
+    ('scope', 'htmlentities'),
+    ('scope', 'htmlescaper'),
+    ('scope', 'htmlminifier'),
+    ('scope', 'httperrors'),
+    ('scope', 'httpproxy'),
+    ('scope', 'httpproxyagent'),
+    ('scope', 'httpproxymiddleware'),
+    ('scope', 'httpserver'),
+    ('scope', 'httpsproxyagent'),
+    ('scope', 'humansignals'),
+    ('scope', 'i18next'),
+    ('scope', 'ibm'),
+    ('scope', 'iconvlite'),
+    ('scope', 'ieee754'),
+    ('scope', 'ignore'),
+    ('scope', 'imagesize'),
+    ('scope', 'immutable'),
+    ('scope', 'india'),
+    ('scope', 'inflight'),
+    ('scope', 'inherits'),
+    ('scope', 'ini'),
+    ('scope', 'inquirer'),
+    ('scope', 'instacart'),
+    ('scope', 'intel'),
+    ('scope', 'internal'),
+    ('scope', 'invalid'),
+    ('scope', 'invariant'),
+    ('scope', 'ionic'),
+    ('scope', 'ioredis'),
+    ('scope', 'ip'),
+    ('scope', 'ipaddrjs'),
+    ('scope', 'isarray'),
+    ('scope', 'isdateobject'),
+    ('scope', 'isdocker'),
+    ('scope', 'isexe'),
+    ('scope', 'isextglob'),
+    ('scope', 'isgeneratorfunction'),
+    ('scope', 'isglob'),
+    ('scope', 'isnumber'),
+    ('scope', 'isomorphicfetch'),
+    ('scope', 'ispathinside'),
+    ('scope', 'isplainobject'),
+    ('scope', 'isstream'),
+    ('scope', 'istypedarray'),
+    ('scope', 'javascript'),
+    ('scope', 'jest'),
+    ('scope', 'jestcli'),
+    ('scope', 'jetbrains'),
+    ('scope', 'joi'),
+    ('scope', 'jquery'),
+    ('scope', 'jsbeautify'),
+    ('scope', 'jscodeshift'),
+    ('scope', 'jsdom'),
+    ('scope', 'jsesc'),
+    ('scope', 'json5'),
+    ('scope', 'jsonbuffer'),
+    ('scope', 'jsonfile'),
+    ('scope', 'jsonschematraverse'),
+    ('scope', 'jsonstablestringifywithoutjsonify'),
+    ('scope', 'jsonstream'),
+    ('scope', 'jsonstringifysafe'),
+    ('scope', 'jsonwebtoken'),
+    ('scope', 'jstokens'),
+    ('scope', 'jsyaml'),
+    ('scope', 'jszip'),
+    ('scope', 'jws'),
+    ('scope', 'karma'),
+    ('scope', 'kindof'),
+    ('scope', 'knex'),
+    ('scope', 'koa'),
+    ('scope', 'langchain'),
+    ('scope', 'lattice'),
+    ('scope', 'launchdarkly'),
+    ('scope', 'less'),
+    ('scope', 'lint'),
+    ('scope', 'lintstaged'),
+    ('scope', 'llm'),
+    ('scope', 'loaderutils'),
+    ('scope', 'local'),
+    ('scope', 'localforage'),
+    ('scope', 'lodash'),
+    ('scope', 'log4js'),
+    ('scope', 'logrocket'),
+    ('scope', 'logsymbols'),
+    ('scope', 'logupdate'),
+    ('scope', 'long'),
+    ('scope', 'looseenvify'),
+    ('scope', 'lrucache'),
+    ('scope', 'luxon'),
+    ('scope', 'magicstring'),
+    ('scope', 'mailchimp'),
+    ('scope', 'makedir'),
+    ('scope', 'makefetchhappen'),
+    ('scope', 'markdown'),
+    ('scope', 'markdownit'),
+    ('scope', 'marked'),
+    ('scope', 'md5'),
+    ('scope', 'memoizeone'),
+    ('scope', 'meow'),
+    ('scope', 'mergestream'),
+    ('scope', 'meta'),
+    ('scope', 'micromatch'),
+    ('scope', 'microsoft'),
+    ('scope', 'mime'),
+    ('scope', 'mimedb'),
+    ('scope', 'mimetypes'),
+    ('scope', 'minimatch'),
+    ('scope', 'minimist'),
+    ('scope', 'minipass'),
+    ('scope', 'mitt'),
+    ('scope', 'mkdirp'),
+    ('scope', 'mocha'),
+    ('scope', 'moment'),
+    ('scope', 'momenttimezone'),
+    ('scope', 'mongodb'),
+    ('scope', 'mongoose'),
+    ('scope', 'morgan'),
+    ('scope', 'mozilla'),
+    ('scope', 'ms'),
+    ('scope', 'multer'),
+    ('scope', 'mux'),
+    ('scope', 'mysql'),
+    ('scope', 'mysql2'),
+    ('scope', 'mz'),
+    ('scope', 'nan'),
+    ('scope', 'nanoid'),
+    ('scope', 'neon'),
+    ('scope', 'neovim'),
+    ('scope', 'netlify'),
+    ('scope', 'next'),
+    ('scope', 'nextjs'),
+    ('scope', 'node'),
+    ('scope', 'nodeemoji'),
+    ('scope', 'nodefetch'),
+    ('scope', 'nodeint64'),
+    ('scope', 'nodejs'),
+    ('scope', 'nodemailer'),
+    ('scope', 'nodenotifier'),
+    ('scope', 'nodesass'),
+    ('scope', 'nopt'),
+    ('scope', 'normalizepackagedata'),
+    ('scope', 'normalizepath'),
+    ('scope', 'notion'),
+    ('scope', 'npm'),
+    ('scope', 'npmlog'),
+    ('scope', 'npmrunall'),
+    ('scope', 'npmrunpath'),
+    ('scope', 'nuxt'),
+    ('scope', 'objectassign'),
+    ('scope', 'objecthash'),
+    ('scope', 'okta'),
+    ('scope', 'once'),
+    ('scope', 'onetime'),
+    ('scope', 'onfinished'),
+    ('scope', 'open'),
+    ('scope', 'openai'),
+    ('scope', 'optimist'),
+    ('scope', 'ora'),
+    ('scope', 'oracle'),
+    ('scope', 'orama'),
+    ('scope', 'orm'),
+    ('scope', 'pagerduty'),
+    ('scope', 'parcel'),
+    ('scope', 'parentmodule'),
+    ('scope', 'parse5'),
+    ('scope', 'parseurl'),
+    ('scope', 'partykit'),
+    ('scope', 'path'),
+    ('scope', 'pathexists'),
+    ('scope', 'pathkey'),
+    ('scope', 'pathtoregexp'),
+    ('scope', 'pg'),
+    ('scope', 'picocolors'),
+    ('scope', 'picomatch'),
+    ('scope', 'pify'),
+    ('scope', 'pinecone'),
+    ('scope', 'pirates'),
+    ('scope', 'plaid'),
+    ('scope', 'plimit'),
+    ('scope', 'plocate'),
+    ('scope', 'plugin'),
+    ('scope', 'pluralize'),
+    ('scope', 'pm2'),
+    ('scope', 'pmap'),
+    ('scope', 'pnpm'),
+    ('scope', 'polished'),
+    ('scope', 'polymer'),
+    ('scope', 'postcss'),
+    ('scope', 'postcssnested'),
+    ('scope', 'postgres'),
+    ('scope', 'postman'),
+    ('scope', 'preact'),
+    ('scope', 'prettier'),
+    ('scope', 'prettybytes'),
+    ('scope', 'prettyformat'),
+    ('scope', 'prisma'),
+    ('scope', 'prismjs'),
+    ('scope', 'private'),
+    ('scope', 'process'),
+    ('scope', 'promise'),
+    ('scope', 'prompts'),
+    ('scope', 'proptypes'),
+    ('scope', 'pug'),
+    ('scope', 'pump'),
+    ('scope', 'punycode'),
+    ('scope', 'puppeteer'),
+    ('scope', 'qs'),
+    ('scope', 'querystring'),
+    ('scope', 'queuemicrotask'),
+    ('scope', 'qwik'),
+    ('scope', 'ramda'),
+    ('scope', 'randombytes'),
+    ('scope', 'rawbody'),
+    ('scope', 'rc'),
+    ('scope', 'react'),
+    ('scope', 'reacticons'),
+    ('scope', 'reactnative'),
+    ('scope', 'reactredux'),
+    ('scope', 'reactrouter'),
+    ('scope', 'reactselect'),
+    ('scope', 'reacttransitiongroup'),
+    ('scope', 'read'),
+    ('scope', 'readme'),
+    ('scope', 'recast'),
+    ('scope', 'reddit'),
+    ('scope', 'redis'),
+    ('scope', 'redux'),
+    ('scope', 'reduxthunk'),
+    ('scope', 'redwoodjs'),
+    ('scope', 'reflectmetadata'),
+    ('scope', 'regeneratorruntime'),
+    ('scope', 'remix'),
+    ('scope', 'render'),
+    ('scope', 'replit'),
+    ('scope', 'requiredirectory'),
+    ('scope', 'reselect'),
+    ('scope', 'resolvefrom'),
+    ('scope', 'resolveurlloader'),
+    ('scope', 'restorecursor'),
+    ('scope', 'retool'),
+    ('scope', 'retry'),
+    ('scope', 'reusify'),
+    ('scope', 'rimraf'),
+    ('scope', 'ringcentral'),
+    ('scope', 'rippling'),
+    ('scope', 'rollup'),
+    ('scope', 'runasync'),
+    ('scope', 'runparallel'),
+    ('scope', 'rx'),
+    ('scope', 'rxjs'),
+    ('scope', 'safebuffer'),
+    ('scope', 'salesforce'),
+    ('scope', 'samsung'),
+    ('scope', 'sanitizehtml'),
+    ('scope', 'sassloader'),
+    ('scope', 'sax'),
+    ('scope', 'scaleai'),
+    ('scope', 'scheduler'),
+    ('scope', 'schemautils'),
+    ('scope', 'scout'),
+    ('scope', 'segment'),
+    ('scope', 'semver'),
+    ('scope', 'send'),
+    ('scope', 'sendbird'),
+    ('scope', 'sendgrid'),
+    ('scope', 'sentry'),
+    ('scope', 'serializejavascript'),
+    ('scope', 'servefavicon'),
+    ('scope', 'servestatic'),
+    ('scope', 'shadcn'),
+    ('scope', 'sharp'),
+    ('scope', 'shebangcommand'),
+    ('scope', 'shelljs'),
+    ('scope', 'shellquote'),
+    ('scope', 'shopify'),
+    ('scope', 'sift'),
+    ('scope', 'signalexit'),
+    ('scope', 'simplegit'),
+    ('scope', 'sinon'),
+    ('scope', 'slash'),
+    ('scope', 'snowflake'),
+    ('scope', 'snyk'),
+    ('scope', 'socket.io'),
+    ('scope', 'socketioclient'),
+    ('scope', 'solid'),
+    ('scope', 'solidjs'),
+    ('scope', 'solidstart'),
+    ('scope', 'sourcegraph'),
+    ('scope', 'sourcemap'),
+    ('scope', 'sourcemaploader'),
+    ('scope', 'sourcemapsupport'),
+    ('scope', 'split'),
+    ('scope', 'splunk'),
+    ('scope', 'spotify'),
+    ('scope', 'sprintfjs'),
+    ('scope', 'sqlite'),
+    ('scope', 'sqlite3'),
+    ('scope', 'src'),
+    ('scope', 'ssh2'),
+    ('scope', 'sst'),
+    ('scope', 'standard'),
+    ('scope', 'statuses'),
+    ('scope', 'std'),
+    ('scope', 'stndard'),
+    ('scope', 'storybook'),
+    ('scope', 'stringwidth'),
+    ('scope', 'stripansi'),
+    ('scope', 'stripe'),
+    ('scope', 'stripfinalnewline'),
+    ('scope', 'stripjsoncomments'),
+    ('scope', 'styledcomponents'),
+    ('scope', 'stylelint'),
+    ('scope', 'stylus'),
+    ('scope', 'supabase'),
+    ('scope', 'superagent'),
+    ('scope', 'supertest'),
+    ('scope', 'supportscolor'),
+    ('scope', 'svelte'),
+    ('scope', 'sveltejs'),
+    ('scope', 'sveltekit'),
+    ('scope', 'svg'),
+    ('scope', 'svgo'),
+    ('scope', 'swiper'),
+    ('scope', 'tailwind'),
+    ('scope', 'tailwindcss'),
+    ('scope', 'tar'),
+    ('scope', 'tarstream'),
+    ('scope', 'telegram'),
+    ('scope', 'temp'),
+    ('scope', 'tesla'),
+    ('scope', 'texttable'),
+    ('scope', 'three'),
+    ('scope', 'through'),
+    ('scope', 'through2'),
+    ('scope', 'tmp'),
+    ('scope', 'toughcookie'),
+    ('scope', 'ts'),
+    ('scope', 'tsconfigpaths'),
+    ('scope', 'tslib'),
+    ('scope', 'tsnode'),
+    ('scope', 'tweetnacl'),
+    ('scope', 'twilio'),
+    ('scope', 'twitch'),
+    ('scope', 'twitter'),
+    ('scope', 'type'),
+    ('scope', 'typecheck'),
+    ('scope', 'typedetect'),
+    ('scope', 'typefest'),
+    ('scope', 'typeis'),
+    ('scope', 'types'),
+    ('scope', 'typescript'),
+    ('scope', 'typescripteslint'),
+    ('scope', 'uaparserjs'),
+    ('scope', 'uber'),
+    ('scope', 'uglifyjs'),
+    ('scope', 'underscore'),
+    ('scope', 'unified'),
+    ('scope', 'universalify'),
+    ('scope', 'untildify'),
+    ('scope', 'updatenotifier'),
+    ('scope', 'upstash'),
+    ('scope', 'urijs'),
+    ('scope', 'urljoin'),
+    ('scope', 'urlloader'),
+    ('scope', 'urlparse'),
+    ('scope', 'validatenpmpackagename'),
+    ('scope', 'validator'),
+    ('scope', 'vercel'),
+    ('scope', 'vite'),
+    ('scope', 'vmware'),
+    ('scope', 'vscode'),
+    ('scope', 'vue'),
+    ('scope', 'vuejs'),
+    ('scope', 'vuerouter'),
+    ('scope', 'webflow'),
+    ('scope', 'webpack'),
+    ('scope', 'webpackdevmiddleware'),
+    ('scope', 'webpackmerge'),
+    ('scope', 'whatwgfetch'),
+    ('scope', 'whatwgurl'),
+    ('scope', 'which'),
+    ('scope', 'wrapansi'),
+    ('scope', 'wrappy'),
+    ('scope', 'write'),
+    ('scope', 'writefileatomic'),
+    ('scope', 'ws'),
+    ('scope', 'xlsx'),
+    ('scope', 'xml'),
+    ('scope', 'xml2js'),
+    ('scope', 'xtend'),
+    ('scope', 'y18n'),
+    ('scope', 'yallist'),
+    ('scope', 'yaml'),
+    ('scope', 'yargs'),
+    ('scope', 'yargsparser'),
+    ('scope', 'yarn'),
+    ('scope', 'ycombinator'),
+    ('scope', 'yeomangenerator'),
+    ('scope', 'yo'),
+    ('scope', 'yoctoqueue'),
+    ('scope', 'youtube'),
+    ('scope', 'yup'),
+    ('scope', 'zalando'),
+    ('scope', 'zapier'),
+    ('scope', 'zed'),
+    ('scope', 'zendesk'),
+    ('scope', 'zod'),
+    ('scope', 'zonejs');
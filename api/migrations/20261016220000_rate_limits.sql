@@ -0,0 +1,37 @@
+-- Request rate limits of the API, adjustable by staff at runtime. Every request
+-- is counted against the limit of the tier of its principal, unless the token
+-- it was made with, or the scope it targets, has an override.
+CREATE TYPE rate_limit_tier AS ENUM ('anonymous', 'user', 'github_actions');
+
+CREATE TABLE rate_limit_tiers (
+    tier rate_limit_tier NOT NULL PRIMARY KEY,
+    requests_per_minute integer NOT NULL CHECK (requests_per_minute > 0),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+SELECT manage_updated_at('rate_limit_tiers');
+
+INSERT INTO rate_limit_tiers (tier, requests_per_minute) VALUES
+    ('anonymous', 600),
+    ('user', 1200),
+    ('github_actions', 1200);
+
+CREATE TABLE scope_rate_limits (
+    scope text NOT NULL PRIMARY KEY REFERENCES scopes(scope) ON DELETE CASCADE ON UPDATE CASCADE,
+    requests_per_minute integer NOT NULL CHECK (requests_per_minute > 0),
+    reason text,
+    created_by uuid REFERENCES users(id) ON DELETE SET NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+SELECT manage_updated_at('scope_rate_limits');
+
+CREATE TABLE token_rate_limits (
+    token_id uuid NOT NULL PRIMARY KEY REFERENCES tokens(id) ON DELETE CASCADE,
+    requests_per_minute integer NOT NULL CHECK (requests_per_minute > 0),
+    reason text,
+    created_by uuid REFERENCES users(id) ON DELETE SET NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+SELECT manage_updated_at('token_rate_limits');
@@ -0,0 +1,6 @@
+-- The changes a publish makes relative to the latest version of the package:
+-- the files that were added, removed or modified, and the doc diff of the
+-- exported API. Computed while the tarball is processed, so authors can review
+-- it on the publishing task. NULL for the first version of a package and for
+-- tasks created before this migration.
+ALTER TABLE publishing_tasks ADD COLUMN preview_diff jsonb;
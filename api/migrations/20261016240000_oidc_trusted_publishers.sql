@@ -0,0 +1,25 @@
+-- Issuers of OpenID Connect tokens that a scope trusts for publishing, in
+-- addition to the built-in CI providers. Usually self-hosted CI systems.
+CREATE TABLE scope_oidc_issuers (
+    id uuid NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+    scope text NOT NULL REFERENCES scopes (scope) ON DELETE CASCADE,
+    issuer text NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    UNIQUE (scope, issuer)
+);
+CREATE INDEX scope_oidc_issuers_issuer_idx ON scope_oidc_issuers (issuer);
+
+-- The CI projects that may publish a package with an OpenID Connect token,
+-- without an access token. A token matches if it was issued by `issuer` to
+-- `subject`, as derived from its claims by the provider of the issuer.
+CREATE TABLE package_trusted_publishers (
+    id uuid NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+    scope text NOT NULL,
+    package text NOT NULL,
+    issuer text NOT NULL,
+    subject text NOT NULL,
+    created_by uuid REFERENCES users (id) ON DELETE SET NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    FOREIGN KEY (scope, package) REFERENCES packages (scope, name) ON DELETE CASCADE ON UPDATE CASCADE,
+    UNIQUE (scope, package, issuer, subject)
+);
@@ -0,0 +1,3 @@
+-- Keep the full Sigstore bundle of a provenance statement so it can be served
+-- and re-verified later, not just its transparency log index.
+ALTER TABLE package_versions ADD COLUMN provenance_bundle JSONB;
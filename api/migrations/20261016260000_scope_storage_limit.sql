@@ -0,0 +1,3 @@
+-- The total size of the files of all package versions in a scope is limited,
+-- 5 GiB unless overridden by staff.
+ALTER TABLE scopes ADD COLUMN storage_limit BIGINT NOT NULL DEFAULT 5368709120;
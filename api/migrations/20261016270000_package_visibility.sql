@@ -0,0 +1,3 @@
+CREATE TYPE package_visibility AS ENUM ('public', 'private');
+
+ALTER TABLE packages ADD COLUMN visibility package_visibility NOT NULL DEFAULT 'public';
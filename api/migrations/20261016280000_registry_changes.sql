@@ -0,0 +1,109 @@
+CREATE TYPE registry_change_kind AS ENUM (
+    'version_published',
+    'version_yanked',
+    'version_unyanked',
+    'version_deleted',
+    'package_updated',
+    'package_deleted'
+);
+
+-- An append-only log of changes to public packages, so that mirrors can follow
+-- the registry without scraping it. `seq` is the cursor handed out to clients.
+-- Rows are written by triggers, so that every code path that changes packages
+-- is covered, and are never updated or deleted.
+CREATE TABLE registry_changes (
+    seq bigserial PRIMARY KEY,
+    kind registry_change_kind NOT NULL,
+    scope text NOT NULL,
+    name text NOT NULL,
+    version text,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+-- Records a change to a package version or dist tag, unless the package is
+-- private.
+CREATE OR REPLACE FUNCTION record_registry_change(
+    _kind registry_change_kind,
+    _scope text,
+    _name text,
+    _version text
+) RETURNS VOID AS $$
+BEGIN
+    IF EXISTS (
+        SELECT 1 FROM packages
+        WHERE scope = _scope AND name = _name AND visibility = 'private'
+    ) THEN
+        RETURN;
+    END IF;
+    INSERT INTO registry_changes (kind, scope, name, version)
+    VALUES (_kind, _scope, _name, _version);
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION record_package_version_change() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        PERFORM record_registry_change('version_published', NEW.scope, NEW.name, NEW.version);
+    ELSIF TG_OP = 'DELETE' THEN
+        PERFORM record_registry_change('version_deleted', OLD.scope, OLD.name, OLD.version);
+    ELSIF NEW.is_yanked THEN
+        PERFORM record_registry_change('version_yanked', NEW.scope, NEW.name, NEW.version);
+    ELSE
+        PERFORM record_registry_change('version_unyanked', NEW.scope, NEW.name, NEW.version);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER record_registry_change_insert AFTER INSERT ON package_versions
+    FOR EACH ROW EXECUTE PROCEDURE record_package_version_change();
+CREATE TRIGGER record_registry_change_delete AFTER DELETE ON package_versions
+    FOR EACH ROW EXECUTE PROCEDURE record_package_version_change();
+CREATE TRIGGER record_registry_change_yank AFTER UPDATE ON package_versions
+    FOR EACH ROW WHEN (OLD.is_yanked IS DISTINCT FROM NEW.is_yanked)
+    EXECUTE PROCEDURE record_package_version_change();
+
+CREATE OR REPLACE FUNCTION record_package_change() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        IF OLD.visibility = 'public' THEN
+            INSERT INTO registry_changes (kind, scope, name)
+            VALUES ('package_deleted', OLD.scope, OLD.name);
+        END IF;
+    -- A package that was just made private is still recorded, so that mirrors
+    -- drop it when they fail to fetch it.
+    ELSIF OLD.visibility = 'public' OR NEW.visibility = 'public' THEN
+        INSERT INTO registry_changes (kind, scope, name)
+        VALUES ('package_updated', NEW.scope, NEW.name);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER record_registry_change_update AFTER UPDATE ON packages
+    FOR EACH ROW WHEN (
+        (OLD.description, OLD.github_repository_id, OLD.runtime_compat,
+         OLD.readme_source, OLD.localized_descriptions, OLD.is_archived,
+         OLD.visibility)
+        IS DISTINCT FROM
+        (NEW.description, NEW.github_repository_id, NEW.runtime_compat,
+         NEW.readme_source, NEW.localized_descriptions, NEW.is_archived,
+         NEW.visibility)
+    )
+    EXECUTE PROCEDURE record_package_change();
+CREATE TRIGGER record_registry_change_delete AFTER DELETE ON packages
+    FOR EACH ROW EXECUTE PROCEDURE record_package_change();
+
+CREATE OR REPLACE FUNCTION record_package_dist_tag_change() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        PERFORM record_registry_change('package_updated', OLD.scope, OLD.name, NULL);
+    ELSE
+        PERFORM record_registry_change('package_updated', NEW.scope, NEW.name, NULL);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER record_registry_change AFTER INSERT OR UPDATE OR DELETE ON package_dist_tags
+    FOR EACH ROW EXECUTE PROCEDURE record_package_dist_tag_change();
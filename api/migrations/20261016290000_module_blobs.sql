@@ -0,0 +1,13 @@
+-- Module files are stored once per distinct content, keyed by its hash (the
+-- same `sha256-...` as `package_files.checksum`), and shared between all the
+-- versions that contain them. Blobs that no package file refers to anymore are
+-- deleted by the `gc_module_blobs` task once they have not been referenced by
+-- a publish for a while.
+CREATE TABLE module_blobs (
+    hash text PRIMARY KEY,
+    size bigint NOT NULL CHECK (size >= 0),
+    last_referenced_at timestamptz NOT NULL DEFAULT now(),
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX package_files_checksum_idx ON package_files (checksum);
@@ -0,0 +1,21 @@
+-- Resumable publish uploads. The tarball is uploaded in chunks, which are
+-- stored in the publishing bucket under the hash of their content and listed
+-- here in order. Committing the upload assembles the tarball and creates the
+-- publishing task. Uploads that are never committed are deleted by the
+-- `clean_publish_uploads` task once they expire.
+CREATE TABLE publish_uploads (
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id uuid REFERENCES users(id) ON DELETE SET NULL,
+    package_scope text NOT NULL,
+    package_name text NOT NULL,
+    package_version text NOT NULL,
+    config_file text NOT NULL,
+    chunks text[] NOT NULL DEFAULT '{}',
+    size bigint NOT NULL DEFAULT 0 CHECK (size >= 0),
+    expires_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+SELECT manage_updated_at('publish_uploads');
+
+CREATE INDEX publish_uploads_expires_at_idx ON publish_uploads (expires_at);
@@ -0,0 +1,35 @@
+-- Jobs that reconcile the contents of the buckets against the database, and
+-- delete (or, in a dry run, only report) the objects of versions and
+-- publishing tasks that don't exist, like those left behind by failed
+-- publishes. They are started by the `gc_orphaned_objects` task or by admins.
+CREATE TYPE orphan_gc_job_status AS ENUM ('running', 'completed', 'failed');
+
+CREATE TABLE orphan_gc_jobs (
+    id uuid NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    status orphan_gc_job_status NOT NULL DEFAULT 'running',
+    dry_run boolean NOT NULL,
+    scanned_packages integer NOT NULL DEFAULT 0,
+    scanned_objects integer NOT NULL DEFAULT 0,
+    orphaned_objects integer NOT NULL DEFAULT 0,
+    deleted_objects integer NOT NULL DEFAULT 0,
+    error text,
+    created_by uuid REFERENCES users(id) ON DELETE SET NULL,
+    finished_at TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+SELECT manage_updated_at('orphan_gc_jobs');
+
+-- At most one job may run at a time.
+CREATE UNIQUE INDEX orphan_gc_jobs_running_idx
+    ON orphan_gc_jobs ((true)) WHERE status = 'running';
+
+-- The report of a job: every orphaned object it found.
+CREATE TABLE orphaned_objects (
+    job_id uuid NOT NULL REFERENCES orphan_gc_jobs(id) ON DELETE CASCADE,
+    bucket text NOT NULL,
+    path text NOT NULL,
+    deleted boolean NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (job_id, bucket, path)
+);
@@ -0,0 +1,60 @@
+-- SHA-256 digests of the artifacts stored for a package version, recorded when
+-- they are written, as `sha256-<hex>`. The digests of the files of a version
+-- are their checksums in `package_files`. Artifacts written before digests
+-- were recorded have none, and are not verified.
+ALTER TABLE npm_tarballs ADD COLUMN sha256 text;
+
+CREATE TYPE doc_artifact_kind AS ENUM ('doc_nodes', 'markdown', 'type_graph');
+
+CREATE TABLE package_version_doc_artifacts (
+    scope text NOT NULL,
+    name text NOT NULL,
+    version text NOT NULL,
+    kind doc_artifact_kind NOT NULL,
+    checksum text NOT NULL,
+    size bigint NOT NULL CHECK (size >= 0),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (scope, name, version, kind),
+    FOREIGN KEY (scope, name, version) REFERENCES package_versions (scope, name, version) ON UPDATE CASCADE ON DELETE CASCADE
+);
+SELECT manage_updated_at('package_version_doc_artifacts');
+
+-- Jobs that download the stored artifacts of package versions and compare them
+-- against their digests. They are started by admins.
+CREATE TYPE integrity_scrub_job_status AS ENUM ('running', 'completed', 'failed');
+
+CREATE TABLE integrity_scrub_jobs (
+    id uuid NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    status integrity_scrub_job_status NOT NULL DEFAULT 'running',
+    scope text REFERENCES scopes(scope) ON DELETE CASCADE,
+    name text,
+    total_versions integer NOT NULL,
+    checked_versions integer NOT NULL DEFAULT 0,
+    checked_objects integer NOT NULL DEFAULT 0,
+    failed_objects integer NOT NULL DEFAULT 0,
+    error text,
+    created_by uuid REFERENCES users(id) ON DELETE SET NULL,
+    finished_at TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CONSTRAINT integrity_scrub_jobs_name_requires_scope CHECK (name IS NULL OR scope IS NOT NULL)
+);
+SELECT manage_updated_at('integrity_scrub_jobs');
+
+-- At most one job may run at a time.
+CREATE UNIQUE INDEX integrity_scrub_jobs_running_idx
+    ON integrity_scrub_jobs ((true)) WHERE status = 'running';
+
+-- The report of a job: every object that is missing or does not match its
+-- digest. `actual` is the digest of the stored object, `NULL` if it is
+-- missing.
+CREATE TABLE integrity_scrub_failures (
+    job_id uuid NOT NULL REFERENCES integrity_scrub_jobs(id) ON DELETE CASCADE,
+    bucket text NOT NULL,
+    path text NOT NULL,
+    expected text NOT NULL,
+    actual text,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (job_id, bucket, path)
+);
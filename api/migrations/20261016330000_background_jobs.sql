@@ -0,0 +1,34 @@
+-- A queue of units of background work, like building the npm tarball of a
+-- single version. Jobs are claimed by the `run_jobs` task, highest priority
+-- first, and retried with backoff when they fail. Jobs that fail
+-- `max_attempts` times are dead: they stay in the queue until an admin
+-- retries them.
+CREATE TYPE background_job_kind AS ENUM ('npm_tarball_build', 'score_recompute');
+
+CREATE TYPE background_job_status AS ENUM ('pending', 'running', 'completed', 'dead');
+
+CREATE TABLE background_jobs (
+    id uuid NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    kind background_job_kind NOT NULL,
+    payload jsonb NOT NULL,
+    status background_job_status NOT NULL DEFAULT 'pending',
+    priority integer NOT NULL DEFAULT 0,
+    attempts integer NOT NULL DEFAULT 0,
+    max_attempts integer NOT NULL,
+    -- When a pending job is due, or until when a running job is leased to the
+    -- worker that claimed it. A running job whose lease expired is claimed
+    -- again, so that it is retried if its worker died.
+    run_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_error text,
+    created_by uuid REFERENCES users(id) ON DELETE SET NULL,
+    finished_at TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CONSTRAINT background_jobs_max_attempts_positive CHECK (max_attempts > 0)
+);
+SELECT manage_updated_at('background_jobs');
+
+CREATE INDEX background_jobs_due_idx
+    ON background_jobs (priority DESC, run_at) WHERE status IN ('pending', 'running');
+CREATE INDEX background_jobs_status_idx
+    ON background_jobs (status, created_at DESC);
@@ -0,0 +1,18 @@
+-- Downloads of each package over the last week and month, so that they don't
+-- have to be summed up from the daily download counts for every request that
+-- sorts by them or shows them. Refreshed by the `refresh_download_rollups`
+-- task; packages without downloads in the last month have no row.
+CREATE TABLE package_download_rollups (
+    scope text NOT NULL,
+    package text NOT NULL,
+    weekly_downloads bigint NOT NULL,
+    monthly_downloads bigint NOT NULL,
+    updated_at timestamptz NOT NULL DEFAULT now(),
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (scope, package),
+    FOREIGN KEY (scope, package) REFERENCES packages (scope, name) ON UPDATE CASCADE ON DELETE CASCADE
+);
+SELECT manage_updated_at('package_download_rollups');
+
+CREATE INDEX package_download_rollups_weekly_idx ON package_download_rollups (weekly_downloads DESC);
+CREATE INDEX package_download_rollups_monthly_idx ON package_download_rollups (monthly_downloads DESC);
@@ -0,0 +1,18 @@
+-- Feature flags, to roll out features to some scopes or users before everyone
+-- else. A flag is on for everyone if it is enabled, and otherwise only for the
+-- scopes and users it targets. Flags that don't exist are off.
+CREATE TABLE feature_flags (
+    name text NOT NULL PRIMARY KEY CHECK (name ~ '^[a-z0-9]+(-[a-z0-9]+)*$'),
+    description text NOT NULL DEFAULT '',
+    enabled boolean NOT NULL DEFAULT false,
+    scopes text[] NOT NULL DEFAULT '{}',
+    user_ids uuid[] NOT NULL DEFAULT '{}',
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+SELECT manage_updated_at('feature_flags');
+
+INSERT INTO feature_flags (name, description) VALUES (
+    'unstable-bytes-imports',
+    'Allow published modules to import files as bytes, with `with { type: "bytes" }`.'
+);
@@ -0,0 +1,8 @@
+-- Deleted packages and package versions are kept as tombstones, which are left
+-- out of all queries, so that a deletion can be undone by staff. Tombstones
+-- are purged once they are older than the retention window.
+ALTER TABLE packages ADD COLUMN deleted_at timestamptz;
+ALTER TABLE package_versions ADD COLUMN deleted_at timestamptz;
+
+CREATE INDEX packages_deleted_at_idx ON packages (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX package_versions_deleted_at_idx ON package_versions (deleted_at) WHERE deleted_at IS NOT NULL;
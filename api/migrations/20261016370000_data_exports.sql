@@ -0,0 +1,22 @@
+-- Exports of all data about a user, which users can request to exercise their
+-- right of access. The archive is built by a background job, stored in the
+-- publishing bucket, and handed out through signed URLs until it expires.
+ALTER TYPE background_job_kind ADD VALUE 'data_export';
+
+CREATE TYPE data_export_status AS ENUM ('pending', 'completed', 'failed', 'expired');
+
+CREATE TABLE data_exports (
+    id uuid NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id uuid NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    status data_export_status NOT NULL DEFAULT 'pending',
+    -- The size of the archive in bytes, once it is completed.
+    size bigint,
+    error text,
+    completed_at TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+SELECT manage_updated_at('data_exports');
+
+CREATE INDEX data_exports_user_id_idx ON data_exports (user_id, created_at DESC);
+CREATE INDEX data_exports_completed_at_idx ON data_exports (completed_at) WHERE status = 'completed';
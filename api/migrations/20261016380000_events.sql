@@ -0,0 +1,12 @@
+-- Typed domain events (versions published, packages created, tokens used, ...)
+-- emitted onto the internal event bus. This table is one of the sinks of the
+-- bus, and is read by downstream analytics.
+CREATE TABLE events (
+    id uuid NOT NULL PRIMARY KEY,
+    type text NOT NULL,
+    payload jsonb NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX events_type_created_at_idx ON events (type, created_at);
+CREATE INDEX events_created_at_idx ON events (created_at);
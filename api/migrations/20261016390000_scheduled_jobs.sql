@@ -0,0 +1,30 @@
+-- The periodic tasks run by the scheduler built into the API. Only the
+-- instance that holds the lease in `scheduler_leader` starts runs, and a job
+-- is not started while the lease of its previous run has not expired.
+CREATE TYPE scheduled_job_status AS ENUM ('succeeded', 'failed');
+
+CREATE TABLE scheduled_jobs (
+    name text NOT NULL PRIMARY KEY,
+    -- The cron expression the job is scheduled with.
+    schedule text NOT NULL,
+    next_run_at TIMESTAMPTZ NOT NULL,
+    -- The current run, if the job is running.
+    run_id uuid,
+    running_since TIMESTAMPTZ,
+    lease_expires_at TIMESTAMPTZ,
+    last_started_at TIMESTAMPTZ,
+    last_finished_at TIMESTAMPTZ,
+    last_status scheduled_job_status,
+    last_error text,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+SELECT manage_updated_at('scheduled_jobs');
+
+-- A single row, held by the instance that currently runs the scheduler.
+CREATE TABLE scheduler_leader (
+    id boolean NOT NULL PRIMARY KEY DEFAULT true CHECK (id),
+    holder uuid NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    acquired_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
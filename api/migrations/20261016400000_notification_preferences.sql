@@ -0,0 +1,17 @@
+-- Which notification emails users want to receive. Rows are created the first
+-- time they are read, users without a row receive all notifications. The
+-- unsubscribe token is put into the unsubscribe links of notification emails,
+-- so that users can unsubscribe without signing in.
+ALTER TYPE background_job_kind ADD VALUE 'publish_failure_email';
+
+CREATE TABLE notification_preferences (
+    user_id uuid NOT NULL PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    scope_invites boolean NOT NULL DEFAULT true,
+    publish_failures boolean NOT NULL DEFAULT true,
+    scope_digests boolean NOT NULL DEFAULT true,
+    unsubscribe_token uuid NOT NULL UNIQUE DEFAULT uuid_generate_v4(),
+    last_digest_sent_at TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+SELECT manage_updated_at('notification_preferences');
@@ -0,0 +1,44 @@
+ALTER TYPE webhook_event ADD VALUE 'version_quarantined';
+ALTER TYPE webhook_event ADD VALUE 'version_released';
+
+-- Reports of malicious or infringing packages, and the moderation state they
+-- are in. Staff move a report from 'reported' to 'under_review', and then to
+-- 'actioned' or 'dismissed'.
+CREATE TYPE abuse_report_reason AS ENUM ('malware', 'infringement', 'spam', 'other');
+CREATE TYPE abuse_report_status AS ENUM ('reported', 'under_review', 'actioned', 'dismissed');
+
+CREATE TABLE abuse_reports (
+    id uuid NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    scope text NOT NULL,
+    name text NOT NULL,
+    version text,
+    reason abuse_report_reason NOT NULL,
+    description text NOT NULL,
+    reporter_id uuid REFERENCES users(id) ON DELETE SET NULL,
+    status abuse_report_status NOT NULL DEFAULT 'reported',
+    reviewer_id uuid REFERENCES users(id) ON DELETE SET NULL,
+    resolution text,
+    resolved_at TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (scope, name) REFERENCES packages (scope, name) ON DELETE CASCADE
+);
+SELECT manage_updated_at('abuse_reports');
+
+CREATE INDEX abuse_reports_status_created_at_idx ON abuse_reports (status, created_at DESC);
+CREATE INDEX abuse_reports_scope_name_idx ON abuse_reports (scope, name);
+
+-- Package versions that staff quarantined. They are left out of the package
+-- and npm manifests, and their artifacts are moved out of the public buckets,
+-- but kept until the quarantine is released or the version is deleted.
+CREATE TABLE package_version_quarantines (
+    scope text NOT NULL,
+    name text NOT NULL,
+    version text NOT NULL,
+    reason text NOT NULL,
+    report_id uuid REFERENCES abuse_reports(id) ON DELETE SET NULL,
+    quarantined_by uuid REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (scope, name, version),
+    FOREIGN KEY (scope, name, version) REFERENCES package_versions (scope, name, version) ON DELETE CASCADE
+);
@@ -0,0 +1,8 @@
+-- Rotating a personal access token replaces its secret, but keeps the old one
+-- working until `previous_hash_expires_at`, so that it can be swapped out
+-- without downtime.
+ALTER TABLE tokens ADD COLUMN previous_hash text UNIQUE;
+ALTER TABLE tokens ADD COLUMN previous_hash_expires_at timestamptz;
+ALTER TABLE tokens ADD COLUMN rotated_at timestamptz;
+
+CREATE INDEX tokens_permissions_scope_idx ON tokens USING gin (permissions jsonb_path_ops) WHERE type = 'personal';
@@ -0,0 +1,11 @@
+-- The skeleton that a scope or package name shares with names that look
+-- alike, like 'rnoment' and 'moment'. Must stay in sync with
+-- `confusable_skeleton` in the jsr_types crate.
+CREATE FUNCTION confusable_skeleton(name text) RETURNS text
+    LANGUAGE sql IMMUTABLE STRICT PARALLEL SAFE
+    AS $$
+        SELECT replace(replace(replace(translate(name, '01-', 'ol'), 'rn', 'm'), 'vv', 'w'), 'cl', 'd')
+    $$;
+
+CREATE INDEX scopes_confusable_skeleton_idx ON scopes (confusable_skeleton(scope));
+CREATE INDEX packages_confusable_skeleton_idx ON packages (scope, confusable_skeleton(name));
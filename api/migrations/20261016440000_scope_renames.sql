@@ -0,0 +1,42 @@
+-- Renaming a scope renames its rows in place, so every foreign key to a row
+-- that is keyed by its scope has to follow the rename.
+DO $$
+DECLARE
+    fk record;
+BEGIN
+    FOR fk IN
+        SELECT con.conname, con.conrelid::regclass AS tbl, pg_get_constraintdef(con.oid) AS def
+        FROM pg_constraint con
+        WHERE con.contype = 'f'
+          AND con.confupdtype <> 'c'
+          AND EXISTS (
+              SELECT 1 FROM unnest(con.confkey) AS key
+              JOIN pg_attribute att ON att.attrelid = con.confrelid AND att.attnum = key
+              WHERE att.attname = 'scope'
+          )
+    LOOP
+        EXECUTE format('ALTER TABLE %s DROP CONSTRAINT %I', fk.tbl, fk.conname);
+        EXECUTE format('ALTER TABLE %s ADD CONSTRAINT %I %s ON UPDATE CASCADE', fk.tbl, fk.conname, fk.def);
+    END LOOP;
+END $$;
+
+-- A pending request to rename a scope. It is created by an admin of the scope
+-- and approved by staff.
+CREATE TABLE scope_renames (
+    scope text NOT NULL PRIMARY KEY REFERENCES scopes (scope) ON UPDATE CASCADE ON DELETE CASCADE,
+    target_scope text NOT NULL,
+    requested_by uuid NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    CHECK (scope <> target_scope)
+);
+
+-- The old names of renamed scopes. They are kept after the rename, so that
+-- `jsr:` specifiers and URLs with an old name keep resolving, and so that an
+-- old name can not be taken by another scope. Renaming the scope again
+-- updates its redirects through `ON UPDATE CASCADE`.
+CREATE TABLE scope_redirects (
+    scope text NOT NULL PRIMARY KEY,
+    target_scope text NOT NULL REFERENCES scopes (scope) ON UPDATE CASCADE ON DELETE CASCADE,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE INDEX scope_redirects_target_scope_idx ON scope_redirects (target_scope);
@@ -0,0 +1,17 @@
+-- Overrides of the limits that publishes to a scope are checked against. A
+-- NULL limit means the registry-wide default applies.
+CREATE TABLE scope_publish_limits (
+    scope text NOT NULL PRIMARY KEY REFERENCES scopes(scope) ON DELETE CASCADE ON UPDATE CASCADE,
+    max_tarball_size bigint CHECK (max_tarball_size > 0),
+    max_file_size bigint CHECK (max_file_size > 0),
+    max_total_file_size bigint CHECK (max_total_file_size > 0),
+    max_file_count integer CHECK (max_file_count > 0),
+    max_path_depth integer CHECK (max_path_depth > 0),
+    max_path_length integer CHECK (max_path_length > 0),
+    max_module_count integer CHECK (max_module_count > 0),
+    reason text,
+    created_by uuid REFERENCES users(id) ON DELETE SET NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+SELECT manage_updated_at('scope_publish_limits');
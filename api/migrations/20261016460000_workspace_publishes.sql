@@ -0,0 +1,14 @@
+-- Publishes of several packages of a workspace at once. The publishing tasks
+-- of the members are processed together, and either all of them create their
+-- package version or none does.
+CREATE TABLE workspace_publishes (
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id uuid REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE publishing_tasks
+    ADD COLUMN workspace_publish_id uuid REFERENCES workspace_publishes(id) ON DELETE CASCADE;
+
+CREATE INDEX publishing_tasks_workspace_publish_id_idx ON publishing_tasks (workspace_publish_id)
+    WHERE workspace_publish_id IS NOT NULL;
@@ -0,0 +1,43 @@
+-- A key that sorts versions by semver precedence, like the `Ord` impl of
+-- `Version` in the jsr_types crate, which the collation of the version column
+-- does not follow for pre-releases and build metadata. The key is the major,
+-- minor and patch version padded with zeros, then '2' for releases and '1' for
+-- pre-releases, the pre-release identifiers, an empty end marker and the build
+-- identifiers. Numeric identifiers are padded with zeros and prefixed with '0'
+-- so that they sort before alphanumeric ones, which are prefixed with '1'.
+CREATE FUNCTION semver_sort_key(version text) RETURNS text[]
+    LANGUAGE sql IMMUTABLE STRICT PARALLEL SAFE
+    AS $$
+        WITH parts AS (
+            SELECT
+                split_part(split_part(version, '+', 1), '-', 1) AS core,
+                CASE WHEN position('-' IN split_part(version, '+', 1)) > 0
+                    THEN substr(split_part(version, '+', 1), position('-' IN split_part(version, '+', 1)) + 1)
+                END AS pre,
+                CASE WHEN position('+' IN version) > 0
+                    THEN substr(version, position('+' IN version) + 1)
+                END AS build
+        )
+        SELECT
+            ARRAY[
+                lpad(split_part(core, '.', 1), 20, '0'),
+                lpad(split_part(core, '.', 2), 20, '0'),
+                lpad(split_part(core, '.', 3), 20, '0'),
+                CASE WHEN pre IS NULL THEN '2' ELSE '1' END
+            ]
+            || coalesce((
+                SELECT array_agg(CASE WHEN id ~ '^[0-9]{1,20}$' THEN '0' || lpad(id, 20, '0') ELSE '1' || id END ORDER BY n)
+                FROM unnest(string_to_array(pre, '.')) WITH ORDINALITY AS t(id, n)
+            ), '{}')
+            || ARRAY['']
+            || coalesce((
+                SELECT array_agg(CASE WHEN id ~ '^[0-9]{1,20}$' THEN '0' || lpad(id, 20, '0') ELSE '1' || id END ORDER BY n)
+                FROM unnest(string_to_array(build, '.')) WITH ORDINALITY AS t(id, n)
+            ), '{}')
+        FROM parts
+    $$;
+
+ALTER TABLE package_versions
+    ADD COLUMN version_sort_key text[] COLLATE "C" GENERATED ALWAYS AS (semver_sort_key(version)) STORED;
+
+CREATE INDEX package_versions_version_sort_key_idx ON package_versions (scope, name, version_sort_key);
@@ -0,0 +1,11 @@
+-- Tokens are looked up by the SHA-256 hash of their secret before the secret is
+-- hashed with Argon2, so that made up secrets never cost an Argon2 hash.
+ALTER TABLE tokens ADD COLUMN lookup_hash text UNIQUE;
+ALTER TABLE tokens ADD COLUMN previous_lookup_hash text UNIQUE;
+
+-- Tokens that were not rehashed yet are still stored with the SHA-256 hash.
+-- The SHA-256 hash of tokens that were hashed with Argon2 is unknown, so they
+-- could never be found again.
+UPDATE tokens SET lookup_hash = hash WHERE hash NOT LIKE '$argon2%';
+DELETE FROM tokens WHERE lookup_hash IS NULL;
+ALTER TABLE tokens ALTER COLUMN lookup_hash SET NOT NULL;
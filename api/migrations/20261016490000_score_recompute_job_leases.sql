@@ -0,0 +1,3 @@
+-- Score recompute jobs hold a lease while they run, so that a job whose
+-- instance went away does not block new jobs forever.
+ALTER TABLE score_recompute_jobs ADD COLUMN lease_expires_at timestamptz NOT NULL DEFAULT now();
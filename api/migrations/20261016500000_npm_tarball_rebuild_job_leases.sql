@@ -0,0 +1,3 @@
+-- npm tarball rebuild jobs hold a lease while they run, so that a job whose
+-- instance went away does not block new jobs forever.
+ALTER TABLE npm_tarball_rebuild_jobs ADD COLUMN lease_expires_at timestamptz NOT NULL DEFAULT now();
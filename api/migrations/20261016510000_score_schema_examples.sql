@@ -0,0 +1,34 @@
+-- The example check only checks that examples resolve against the package's
+-- exports, so the factor is named after that.
+UPDATE package_versions
+SET meta = (meta - 'allExamplesTypecheck') || jsonb_build_object('allExamplesResolve', meta->'allExamplesTypecheck')
+WHERE meta ? 'allExamplesTypecheck';
+
+UPDATE score_schemas
+SET weights = (weights - 'allExamplesTypecheck') || jsonb_build_object('allExamplesResolve', weights->'allExamplesTypecheck')
+WHERE weights ? 'allExamplesTypecheck';
+
+-- Weights that a schema does not list fall back to the built-in defaults when
+-- it is loaded, so schemas created before a factor existed list it with no
+-- weight.
+UPDATE score_schemas
+SET weights = weights || ('{"allExamplesResolve": 0, "hasTests": 0}'::jsonb - ARRAY(SELECT jsonb_object_keys(weights)))
+WHERE NOT weights ? 'allExamplesResolve' OR NOT weights ? 'hasTests';
+
+-- A new schema adds the example check factor, and bumps the max score by its
+-- weight.
+INSERT INTO score_schemas (version, weights, max_score)
+SELECT COALESCE(MAX(version), 0) + 1, '{
+  "hasReadme": 2,
+  "hasReadmeExamples": 1,
+  "allEntrypointsDocs": 1,
+  "percentageDocumentedSymbols": 5,
+  "allFastCheck": 5,
+  "hasProvenance": 1,
+  "hasDescription": 1,
+  "atLeastOneRuntimeCompatible": 1,
+  "multipleRuntimesCompatible": 1,
+  "allExamplesResolve": 1,
+  "hasTests": 0
+}'::jsonb, 18
+FROM score_schemas;
@@ -0,0 +1,16 @@
+-- A new schema adds the test factor, and bumps the max score by its weight.
+INSERT INTO score_schemas (version, weights, max_score)
+SELECT COALESCE(MAX(version), 0) + 1, '{
+  "hasReadme": 2,
+  "hasReadmeExamples": 1,
+  "allEntrypointsDocs": 1,
+  "percentageDocumentedSymbols": 5,
+  "allFastCheck": 5,
+  "hasProvenance": 1,
+  "hasDescription": 1,
+  "atLeastOneRuntimeCompatible": 1,
+  "multipleRuntimesCompatible": 1,
+  "allExamplesResolve": 1,
+  "hasTests": 1
+}'::jsonb, 19
+FROM score_schemas;
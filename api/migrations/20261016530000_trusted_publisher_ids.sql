@@ -0,0 +1,6 @@
+-- GitHub and GitLab trusted publishers are identified by the numeric id of the
+-- repository or project instead of its name, which can be taken by someone
+-- else after a rename. Publishers that name a project can never match again.
+DELETE FROM package_trusted_publishers
+WHERE issuer IN ('https://token.actions.githubusercontent.com', 'https://gitlab.com')
+  AND subject !~ '^[0-9]+$';
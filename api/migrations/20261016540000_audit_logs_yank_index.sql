@@ -0,0 +1,3 @@
+-- Package snapshots look up the yanks of every version of a package around a
+-- point in time, which are found by the version they were logged for.
+CREATE INDEX audit_logs_yank_package_version_idx ON audit_logs ((meta->>'scope'), (meta->>'name'), (meta->>'version'), created_at) WHERE action = 'yank_package_version';
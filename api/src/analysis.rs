@@ -3,17 +3,22 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Instant;
 
-use bytes::Bytes;
+use comrak::nodes::NodeValue;
 use deno_ast::LineAndColumnDisplay;
 use deno_ast::MediaType;
 use deno_ast::ModuleSpecifier;
 use deno_ast::ParsedSource;
 use deno_ast::SourceRange;
 use deno_ast::SourceRangedForSpanned;
+use deno_ast::swc::ast;
 use deno_ast::swc::common::Span;
 use deno_ast::swc::common::comments::CommentKind;
+use deno_ast::swc::ecma_visit::Visit;
+use deno_ast::swc::ecma_visit::VisitWith;
 use deno_doc::ParseOutput;
+use deno_doc::Symbol;
 use deno_error::JsErrorBox;
 use deno_graph::BuildFastCheckTypeGraphOptions;
 use deno_graph::BuildOptions;
@@ -36,44 +41,156 @@ use deno_semver::npm::NpmPackageReqReference;
 use deno_semver::package::PackageNv;
 use deno_semver::package::PackageReqReference;
 use futures::FutureExt;
+use indexmap::IndexMap;
 use once_cell::sync::Lazy;
+use percent_encoding::percent_decode_str;
 use regex::Regex;
-use regex::bytes::Regex as BytesRegex;
+use sha2::Digest;
+use sha2::Sha256;
 use tracing::Instrument;
 use tracing::instrument;
 use url::Url;
 
+use crate::db::CoverageMetric;
+use crate::db::CoverageSummary;
+use crate::db::DegradedDocsEntrypoint;
 use crate::db::DependencyKind;
+use crate::db::EntrypointDocsCoverage;
 use crate::db::ExportsMap;
 use crate::db::PackageVersionMeta;
+use crate::db::PackageVersionScoreDetails;
+use crate::db::ReadmeHeading;
+use crate::db::ReadmeQuality;
+use crate::db::RuntimeCompat;
 use crate::ids::PackageName;
 use crate::ids::PackagePath;
 use crate::ids::ScopeName;
 use crate::ids::Version;
+use crate::metrics::PublishStage;
+use crate::module_files::CachedVersionFiles;
+use crate::module_files::VersionFiles;
 use crate::npm::NpmTarball;
 use crate::npm::NpmTarballFiles;
 use crate::npm::NpmTarballOptions;
 use crate::npm::create_npm_tarball;
+use crate::npm::npm_exclude_match;
 use crate::s3::BucketWithQueue;
-use crate::s3_paths;
+use crate::spilled_files::SpilledFiles;
 use crate::tarball::PublishError;
+use crate::type_graph::TypeGraph;
+use crate::type_graph::generate_type_graph;
 
 pub struct PackageAnalysisData {
   pub exports: ExportsMap,
   pub files: HashMap<PackagePath, Vec<u8>>,
+  /// Files too large to be kept in memory. The analysis never reads them,
+  /// they are only included in the npm tarball.
+  pub spilled_files: SpilledFiles,
+  /// Directories declared as containing tests in the `test.include` field of
+  /// the config file, as absolute package paths.
+  pub test_include: Vec<String>,
+  pub coverage: Option<CoverageSummary>,
+  /// Whether the npm tarball should also include a CommonJS build.
+  pub npm_cjs: bool,
+  /// Whether the declarations of every entrypoint should be rolled up into a
+  /// single file in the npm tarball.
+  pub npm_dts_rollup: bool,
+  /// Peer dependencies declared in the config file.
+  pub peer_dependencies: Vec<(DependencyKind, PackageReqReference)>,
+  /// The runtime compatibility the package is marked with at publish time.
+  pub runtime_compat: RuntimeCompat,
+  /// Executables declared in the config file, mapped to the entrypoint they
+  /// run.
+  pub bin: IndexMap<String, String>,
+  /// The subpath pattern entries of the exports in the config file. `exports`
+  /// already contains the entrypoints they expand to.
+  pub export_patterns: IndexMap<String, String>,
+  /// Patterns of files to leave out of the npm tarball. They must not be
+  /// part of the module graph.
+  pub npm_exclude: Vec<String>,
+  /// Whether modules may import files as bytes, which is gated behind the
+  /// `unstable-bytes-imports` feature flag.
+  pub unstable_bytes_imports: bool,
+  /// The most modules of the package the module graph may contain, see
+  /// [crate::publish_limits::PublishLimits].
+  pub max_module_count: usize,
 }
 
 pub struct PackageAnalysisOutput {
   pub data: PackageAnalysisData,
   pub module_graph_2: HashMap<String, ModuleInfo>,
-  pub doc_nodes_bytes: Bytes,
+  pub doc_nodes: ParseOutput,
   pub doc_search_json: serde_json::Value,
+  /// The documentation of all exports as a single markdown document.
+  pub docs_markdown: String,
+  pub type_graph: TypeGraph,
   pub dependencies: HashSet<(DependencyKind, PackageReqReference)>,
+  /// The subset of `dependencies` that is only imported through the optional
+  /// dependency pattern, see [OptionalImportCollector].
+  pub optional_dependencies: HashSet<(DependencyKind, PackageReqReference)>,
+  pub deprecations: Vec<DeprecatedSymbol>,
+  pub symbols: Vec<ExportedSymbol>,
+  pub doc_links: Vec<DocLink>,
+  /// Highlighted source views of the text files of the package.
+  pub source_views: Vec<SourceView>,
   pub npm_tarball: NpmTarball,
   pub readme_path: Option<PackagePath>,
   pub meta: PackageVersionMeta,
 }
 
+/// A package of a workspace publish, as it is analyzed together with the
+/// other packages of the publish, see [analyze_workspace].
+pub struct WorkspacePackage {
+  pub scope: ScopeName,
+  pub name: PackageName,
+  pub version: Version,
+  pub exports: ExportsMap,
+  pub files: HashMap<PackagePath, Vec<u8>>,
+  /// The JSR dependencies of the package that resolve to the version of
+  /// another package of the publish.
+  pub workspace_dependencies: Vec<PackageReqReference>,
+}
+
+/// An exported symbol that is tagged with `@deprecated`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeprecatedSymbol {
+  /// The export key of the entrypoint the symbol is exported from.
+  pub export: String,
+  pub symbol: String,
+  /// The text following the `@deprecated` tag, if any.
+  pub message: Option<String>,
+}
+
+/// A symbol exported from an entrypoint, indexed for symbol search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportedSymbol {
+  /// The export key of the entrypoint the symbol is exported from.
+  pub export: String,
+  pub symbol: String,
+  /// The kind of declaration, as named by deno_doc, e.g. `function` or
+  /// `typeAlias`.
+  pub kind: &'static str,
+}
+
+/// A source file rendered for the file browser.
+#[derive(Debug, Clone)]
+pub struct SourceView {
+  /// The hash of the source file, in the same format as the checksums of
+  /// package files.
+  pub hash: String,
+  pub html: String,
+}
+
+/// The documentation page of a symbol, used to resolve symbol permalinks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocLink {
+  /// The name of the symbol, with the names of the namespaces it is declared
+  /// in as a dot separated prefix.
+  pub identifier: String,
+  /// The path of the documentation page, relative to the package.
+  pub path: String,
+}
+
 // We have to spawn another tokio runtime, because
 // `deno_graph::ModuleGraph::build` is not thread-safe.
 #[tokio::main(flavor = "current_thread")]
@@ -100,7 +217,22 @@ async fn analyze_package_inner(
   config_file: PackagePath,
   data: PackageAnalysisData,
 ) -> Result<PackageAnalysisOutput, PublishError> {
-  let PackageAnalysisData { exports, files } = data;
+  let PackageAnalysisData {
+    exports,
+    files,
+    spilled_files,
+    test_include,
+    coverage,
+    npm_cjs,
+    npm_dts_rollup,
+    peer_dependencies,
+    runtime_compat,
+    bin,
+    export_patterns,
+    npm_exclude,
+    unstable_bytes_imports,
+    max_module_count,
+  } = data;
   let mut roots = vec![];
   let mut main_entrypoint = None;
 
@@ -140,12 +272,16 @@ async fn analyze_package_inner(
     exports: exports.clone().into_inner(),
   };
   let workspace_members = vec![workspace_member.clone()];
+  let analysis_start = Instant::now();
   let mut graph = ModuleGraph::new(GraphKind::All);
   graph
     .build(
       roots.clone(),
       vec![],
-      &SyncLoader { files: &files },
+      &SyncLoader {
+        files: &files,
+        spilled_files: &spilled_files,
+      },
       BuildOptions {
         is_dynamic: false,
         module_analyzer: &module_analyzer,
@@ -155,7 +291,7 @@ async fn analyze_package_inner(
         jsr_version_resolver: Default::default(),
         passthrough_jsr_specifiers: true,
         resolver: Some(&JsrResolver {
-          member: workspace_member,
+          members: vec![workspace_member],
         }),
         npm_resolver: None,
         reporter: None,
@@ -163,16 +299,27 @@ async fn analyze_package_inner(
         locker: None,
         skip_dynamic_deps: false,
         module_info_cacher: Default::default(),
-        unstable_bytes_imports: false,
+        unstable_bytes_imports,
         unstable_text_imports: false,
         jsr_metadata_store: None,
         unstable_css_imports: false,
       },
     )
     .await;
+  crate::metrics::observe_graph_build(analysis_start.elapsed());
   graph
     .valid()
     .map_err(|e| PublishError::GraphError(Box::new(e)))?;
+  let module_count = graph
+    .modules()
+    .filter(|module| module.specifier().scheme() == "file")
+    .count();
+  if module_count > max_module_count {
+    return Err(PublishError::TooManyModules {
+      count: module_count,
+      max_count: max_module_count,
+    });
+  }
   graph.build_fast_check_type_graph(BuildFastCheckTypeGraphOptions {
     fast_check_cache: None,
     fast_check_dts: true,
@@ -183,6 +330,44 @@ async fn analyze_package_inner(
   });
 
   let dependencies = collect_dependencies(&graph)?;
+  let optional_dependencies = collect_optional_dependencies(
+    &graph,
+    &module_analyzer.analyzer,
+    &dependencies,
+  );
+
+  for module in graph.modules() {
+    if module.specifier().scheme() == "file"
+      && let Some(pattern) =
+        npm_exclude_match(module.specifier().path(), &npm_exclude)
+    {
+      return Err(PublishError::ConfigFileNpmExcludeInvalid {
+        path: Box::new(config_file.clone()),
+        error: format!(
+          "'{pattern}' excludes '{}', which is imported from the exports of the package",
+          module.specifier().path()
+        ),
+      });
+    }
+  }
+
+  for (command, path) in bin.iter() {
+    let specifier = Url::parse(&format!(
+      "file:///{}",
+      path.trim_start_matches('.').trim_start_matches('/')
+    ))
+    .unwrap();
+    if let Some(parsed_source) =
+      module_analyzer.analyzer.get_parsed_source(&specifier)
+    {
+      check_bin_module(&parsed_source).map_err(|error| {
+        PublishError::ConfigFileBinInvalid {
+          path: Box::new(config_file.clone()),
+          error: format!("'{command}' runs '{path}', which {error}"),
+        }
+      })?;
+    }
+  }
 
   for module in graph.modules() {
     // Check for global type augementation.
@@ -200,7 +385,7 @@ async fn analyze_package_inner(
     }
   }
 
-  let all_fast_check = graph
+  let entrypoints_with_slow_types = graph
     .modules()
     .filter_map(|module| {
       if roots.contains(module.specifier()) {
@@ -209,15 +394,52 @@ async fn analyze_package_inner(
         None
       }
     })
-    .all(|js| {
-      js.maybe_types_dependency.is_some() || js.fast_check_module().is_some()
-    });
+    .filter(|js| {
+      js.maybe_types_dependency.is_none() && js.fast_check_module().is_none()
+    })
+    .map(|js| js.specifier.path().to_string())
+    .collect::<Vec<_>>();
+
+  crate::metrics::observe_publish_stage(
+    PublishStage::Analysis,
+    analysis_start.elapsed(),
+  );
 
-  let doc_nodes =
-    crate::docs::generate_docs(roots, &graph, &module_analyzer.analyzer)
-      .map_err(PublishError::DocError)?;
+  // A pathological entrypoint only loses its docs, recorded in the meta,
+  // instead of failing the publish.
+  let docs_start = Instant::now();
+  let crate::docs::GeneratedDocNodes {
+    doc_nodes,
+    degraded_entrypoints,
+  } = crate::docs::generate_docs(
+    roots,
+    &graph,
+    &module_analyzer.analyzer,
+    crate::docs::DOC_GENERATION_ENTRYPOINT_TIMEOUT,
+  )
+  .map_err(PublishError::DocError)?;
+  crate::metrics::observe_publish_stage(
+    PublishStage::Docs,
+    docs_start.elapsed(),
+  );
+  let degraded_docs_entrypoints = degraded_entrypoints
+    .iter()
+    .map(|specifier| {
+      tracing::warn!("doc generation timed out for {specifier}");
+      DegradedDocsEntrypoint {
+        path: specifier.path().to_string(),
+        reason: format!(
+          "generating the docs timed out after {} seconds",
+          crate::docs::DOC_GENERATION_ENTRYPOINT_TIMEOUT.as_secs()
+        ),
+      }
+    })
+    .collect::<Vec<_>>();
+  let main_entrypoint = main_entrypoint
+    .filter(|main_entrypoint| doc_nodes.contains_key(main_entrypoint));
 
   let module_graph_2 = module_analyzer.take_module_graph_2();
+  let npm_tarball_start = Instant::now();
   let npm_tarball = create_npm_tarball(NpmTarballOptions {
     graph: &graph,
     analyzer: &module_analyzer.analyzer,
@@ -226,29 +448,104 @@ async fn analyze_package_inner(
     package: &name,
     version: &version,
     exports: &exports,
-    files: NpmTarballFiles::WithBytes(&files),
+    files: NpmTarballFiles::WithBytes {
+      files: &files,
+      spilled_files: &spilled_files,
+    },
     dependencies: dependencies.iter(),
+    optional_dependencies: &optional_dependencies
+      .iter()
+      .cloned()
+      .collect::<Vec<_>>(),
+    peer_dependencies: &peer_dependencies,
+    runtime_compat: &runtime_compat,
+    bin: &bin,
+    export_patterns: &export_patterns,
+    exclude: &npm_exclude,
+    cjs: npm_cjs,
+    dts_rollup: npm_dts_rollup,
   })
   .await
   .map_err(PublishError::NpmTarballError)?;
+  crate::metrics::observe_publish_stage(
+    PublishStage::NpmTarball,
+    npm_tarball_start.elapsed(),
+  );
 
-  let (meta, readme_path) = {
+  let (mut meta, readme_path) = {
     let readme = files
       .iter()
       .find(|file| file.0.case_insensitive().is_readme());
 
+    let examples = collect_examples(readme, &doc_nodes);
+    let examples_checked = examples.len() as u32;
+    let examples_start = Instant::now();
+    let failing_examples = check_examples(
+      examples,
+      &files,
+      &spilled_files,
+      workspace_members[0].clone(),
+      &doc_nodes,
+      unstable_bytes_imports,
+    )
+    .await;
+    crate::metrics::observe_publish_stage(
+      PublishStage::Examples,
+      examples_start.elapsed(),
+    );
+
+    let file_paths = files
+      .keys()
+      .chain(spilled_files.paths())
+      .collect::<HashSet<_>>();
+
+    let test_files = file_paths
+      .iter()
+      .filter(|path| is_test_file(path, &test_include))
+      .count() as u32;
+
+    let readme_quality = readme
+      .map(|(path, readme)| readme_quality(path, readme, Some(&file_paths)));
+
     (
       generate_score(
         main_entrypoint.clone(),
+        &exports,
         &doc_nodes,
         &readme,
-        all_fast_check,
+        readme_quality,
+        entrypoints_with_slow_types,
+        examples_checked,
+        failing_examples,
+        test_files,
+        coverage.clone(),
       ),
       readme.map(|readme| readme.0.clone()),
     )
   };
 
-  let doc_nodes_bytes = crate::docs::serialize_doc_nodes(&doc_nodes);
+  meta.npm_cjs = npm_cjs;
+  meta.npm_dts_rollup = npm_dts_rollup;
+  meta.degraded_docs_entrypoints = degraded_docs_entrypoints;
+  meta.npm_bin = bin.clone();
+  meta.export_patterns = export_patterns.clone();
+  meta.npm_exclude = npm_exclude.clone();
+  meta.npm_peer_dependencies = peer_dependencies
+    .iter()
+    .map(|(kind, req)| peer_dependency_specifier(*kind, req))
+    .collect();
+
+  let deprecations = collect_deprecations(&exports, &doc_nodes);
+  let symbols = collect_symbols(&exports, &doc_nodes);
+  let doc_links = collect_doc_links(&exports, &doc_nodes);
+  let source_views = render_source_views(&files);
+
+  let stored_doc_nodes = doc_nodes.clone();
+
+  let docs_markdown = crate::docs_markdown::generate_docs_markdown(
+    &scope, &name, &version, &exports, &doc_nodes,
+  );
+  let type_graph = generate_type_graph(&exports, &doc_nodes);
 
   let info = crate::docs::get_docs_info(&exports, None);
 
@@ -273,129 +570,1178 @@ async fn analyze_package_inner(
     registry_url.to_string(),
     None,
   );
-  let search_index = deno_doc::html::generate_search_index(&ctx);
-  let doc_search_json = if let serde_json::Value::Object(mut obj) = search_index
-  {
-    obj.remove("nodes").unwrap()
-  } else {
-    unreachable!()
-  };
+  // A package too large for a complete search index still publishes, with
+  // the truncation recorded in its meta.
+  let search_index = crate::docs::generate_search_index(&ctx, None);
+  meta.search_index_truncated = search_index.truncated;
+  let doc_search_json = serde_json::to_value(search_index.nodes).unwrap();
 
   Ok(PackageAnalysisOutput {
-    data: PackageAnalysisData { exports, files },
+    data: PackageAnalysisData {
+      exports,
+      files,
+      spilled_files,
+      test_include,
+      coverage,
+      npm_cjs,
+      npm_dts_rollup,
+      peer_dependencies,
+      runtime_compat,
+      bin,
+      export_patterns,
+      npm_exclude,
+      unstable_bytes_imports,
+      max_module_count,
+    },
     module_graph_2,
-    doc_nodes_bytes,
+    doc_nodes: stored_doc_nodes,
     doc_search_json,
+    docs_markdown,
+    type_graph,
     dependencies,
+    optional_dependencies,
+    deprecations,
+    symbols,
+    doc_links,
+    source_views,
     npm_tarball,
     readme_path,
     meta,
   })
 }
 
-static INDENTED_CODE_BLOCK_RE: Lazy<BytesRegex> =
-  Lazy::new(|| BytesRegex::new(r#"\n\s*?\n( {4}|\t)[^\S\n]*\S"#).unwrap());
+/// Checks that the packages of a workspace publish work together. Every
+/// package was already analyzed on its own, but imports of the other packages
+/// of the publish could not be followed, because their versions are not
+/// published yet. Here they are built into one module graph, with `jsr:`
+/// imports of the packages resolved to their files.
+#[tokio::main(flavor = "current_thread")]
+pub async fn analyze_workspace(
+  span: tracing::Span,
+  packages: Vec<WorkspacePackage>,
+  unstable_bytes_imports: bool,
+) -> Result<(), PublishError> {
+  analyze_workspace_inner(packages, unstable_bytes_imports)
+    .instrument(span)
+    .await
+}
+
+#[instrument(name = "analyze_workspace", skip(packages), err)]
+async fn analyze_workspace_inner(
+  packages: Vec<WorkspacePackage>,
+  unstable_bytes_imports: bool,
+) -> Result<(), PublishError> {
+  let members = packages
+    .iter()
+    .map(|package| WorkspaceMember {
+      base: Url::parse(&format!(
+        "file:///@{}/{}/",
+        package.scope, package.name
+      ))
+      .unwrap(),
+      name: StackString::from_string(format!(
+        "@{}/{}",
+        package.scope, package.name
+      )),
+      version: Some(package.version.0.clone()),
+      exports: package.exports.clone().into_inner(),
+    })
+    .collect::<Vec<_>>();
+
+  // Dependencies that are not imported, like peer dependencies, are not part
+  // of the module graph, so their exports are checked here.
+  for package in &packages {
+    for req in &package.workspace_dependencies {
+      let Some(member) =
+        members.iter().find(|member| member.name == req.req.name)
+      else {
+        continue;
+      };
+      let exports_key = crate::tarball::dependency_exports_key(req);
+      if !member.exports.contains_key(&exports_key) {
+        return Err(PublishError::InvalidJsrDependencySubPath {
+          req: Box::new(req.clone()),
+          resolved_version: Version(member.version.clone().unwrap()),
+          exports_key,
+        });
+      }
+    }
+  }
+
+  let roots = members
+    .iter()
+    .flat_map(|member| {
+      member
+        .exports
+        .values()
+        .map(|export| member.base.join(export).unwrap())
+    })
+    .collect::<Vec<_>>();
 
+  let no_files = HashMap::new();
+  let no_spilled_files = SpilledFiles::default();
+  let loader = WorkspaceLoader {
+    packages: members
+      .iter()
+      .zip(&packages)
+      .map(|(member, package)| (member.base.clone(), &package.files))
+      .collect(),
+    external: SyncLoader {
+      files: &no_files,
+      spilled_files: &no_spilled_files,
+    },
+  };
+  let module_analyzer = ModuleAnalyzer::default();
+  let mut graph = ModuleGraph::new(GraphKind::All);
+  graph
+    .build(
+      roots,
+      vec![],
+      &loader,
+      BuildOptions {
+        is_dynamic: false,
+        module_analyzer: &module_analyzer,
+        file_system: &NullFileSystem,
+        jsr_url_provider: &PassthroughJsrUrlProvider,
+        jsr_version_resolver: Default::default(),
+        passthrough_jsr_specifiers: true,
+        resolver: Some(&JsrResolver { members }),
+        npm_resolver: None,
+        reporter: None,
+        executor: Default::default(),
+        locker: None,
+        skip_dynamic_deps: false,
+        module_info_cacher: Default::default(),
+        unstable_bytes_imports,
+        unstable_text_imports: false,
+        jsr_metadata_store: None,
+        unstable_css_imports: false,
+      },
+    )
+    .await;
+  graph
+    .valid()
+    .map_err(|e| PublishError::GraphError(Box::new(e)))?;
+
+  Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
 fn generate_score(
   main_entrypoint: Option<ModuleSpecifier>,
+  exports: &ExportsMap,
   documents_by_url: &ParseOutput,
   readme: &Option<(&PackagePath, &Vec<u8>)>,
-  all_fast_check: bool,
+  readme_quality: Option<ReadmeQuality>,
+  entrypoints_with_slow_types: Vec<String>,
+  examples_checked: u32,
+  failing_examples: Vec<String>,
+  test_files: u32,
+  coverage: Option<CoverageSummary>,
 ) -> PackageVersionMeta {
   let main_entrypoint_doc = main_entrypoint.as_ref().map(|main_entrypoint| {
     &documents_by_url.get(main_entrypoint).unwrap().module_doc
   });
 
-  let has_readme_examples = readme.is_some_and(|(_, readme)| {
-    readme
-      .windows(3)
-      .any(|chars| chars == b"```" || chars == b"~~~")
-      || INDENTED_CODE_BLOCK_RE.is_match(readme)
-  }) || main_entrypoint_doc.is_some_and(|js_doc| {
-    js_doc
-      .doc
+  let has_readme_examples = readme_quality
+    .as_ref()
+    .is_some_and(|readme| readme.code_blocks > 0)
+    || main_entrypoint_doc.is_some_and(|js_doc| {
+      js_doc
+        .doc
+        .as_ref()
+        .is_some_and(|doc| doc.contains("```") || doc.contains("~~~"))
+        || js_doc
+          .tags
+          .iter()
+          .any(|tag| matches!(tag, deno_doc::js_doc::JsDocTag::Example { .. }))
+    });
+
+  let entrypoints_without_module_doc = entrypoints_without_module_doc(
+    documents_by_url,
+    main_entrypoint,
+    readme.is_some(),
+  );
+  let (documented_symbols, total_symbols) =
+    count_symbols_with_docs(documents_by_url);
+  let entrypoint_docs = entrypoint_docs_coverage(exports, documents_by_url);
+
+  PackageVersionMeta {
+    has_readme: readme.is_some()
+      || main_entrypoint_doc
+        .is_some_and(|doc| doc.doc.as_ref().is_some_and(|doc| !doc.is_empty())),
+    has_readme_examples,
+    all_entrypoints_docs: entrypoints_without_module_doc.is_empty(),
+    percentage_documented_symbols: if total_symbols == 0 {
+      1.0
+    } else {
+      (documented_symbols as f32) / (total_symbols as f32)
+    },
+    all_fast_check: entrypoints_with_slow_types.is_empty(),
+    has_provenance: false, // Provenance score is updated after version publish
+    all_examples_resolve: examples_checked > 0 && failing_examples.is_empty(),
+    has_tests: test_files > 0 || coverage.is_some(),
+    // Set from the config file by the caller
+    npm_cjs: false,
+    npm_dts_rollup: false,
+    npm_peer_dependencies: vec![],
+    npm_bin: IndexMap::new(),
+    export_patterns: IndexMap::new(),
+    npm_exclude: vec![],
+    // Set by the caller once the search index is generated
+    search_index_truncated: false,
+    // Set by the caller from the result of generating the docs
+    degraded_docs_entrypoints: vec![],
+    score_schema_version: crate::score::active_schema().version,
+    score_details: PackageVersionScoreDetails {
+      entrypoints_without_module_doc,
+      entrypoints_with_slow_types,
+      documented_symbols,
+      total_symbols,
+      examples_checked,
+      failing_examples,
+      test_files,
+      coverage,
+      entrypoint_docs,
+      readme: readme_quality,
+    },
+  }
+}
+
+/// Re-runs [generate_score] for an already published version from its stored
+/// doc nodes and readme. Slow types, example checks, tests, provenance and
+/// broken relative readme links can not be derived from those, so they are
+/// carried over from `previous`.
+pub fn regenerate_score(
+  exports: &ExportsMap,
+  doc_nodes: &ParseOutput,
+  readme: Option<(&PackagePath, &Vec<u8>)>,
+  previous: &PackageVersionMeta,
+) -> PackageVersionMeta {
+  let main_entrypoint = exports
+    .iter()
+    .find(|(key, _)| *key == ".")
+    .map(|(_, path)| {
+      Url::parse(&format!("file://{}", path.strip_prefix('.').unwrap()))
+        .unwrap()
+    })
+    .filter(|url| doc_nodes.contains_key(url));
+
+  let readme_quality = readme.map(|(path, readme)| {
+    let mut quality = readme_quality(path, readme, None);
+    if let Some(previous) = &previous.score_details.readme {
+      quality.broken_links = previous.broken_links.clone();
+    }
+    quality
+  });
+
+  let mut meta = generate_score(
+    main_entrypoint,
+    exports,
+    doc_nodes,
+    &readme,
+    readme_quality,
+    previous.score_details.entrypoints_with_slow_types.clone(),
+    previous.score_details.examples_checked,
+    previous.score_details.failing_examples.clone(),
+    previous.score_details.test_files,
+    previous.score_details.coverage.clone(),
+  );
+  meta.all_fast_check = previous.all_fast_check;
+  meta.all_examples_resolve = previous.all_examples_resolve;
+  meta.has_tests = previous.has_tests;
+  meta.has_provenance = previous.has_provenance;
+  meta.npm_cjs = previous.npm_cjs;
+  meta.npm_dts_rollup = previous.npm_dts_rollup;
+  meta.search_index_truncated = previous.search_index_truncated;
+  meta.degraded_docs_entrypoints = previous.degraded_docs_entrypoints.clone();
+  meta.npm_bin = previous.npm_bin.clone();
+  meta.export_patterns = previous.export_patterns.clone();
+  meta.npm_exclude = previous.npm_exclude.clone();
+  meta.npm_peer_dependencies = previous.npm_peer_dependencies.clone();
+  meta
+}
+
+const TEST_FILE_EXTENSIONS: &[&str] =
+  &[".ts", ".tsx", ".mts", ".js", ".jsx", ".mjs"];
+
+/// Whether `path` is a test file, either by following the `deno test` naming
+/// convention (`test.ts`, `*_test.ts`, `*.test.ts`), or by being declared as
+/// a test file or located in a declared test directory.
+fn is_test_file(path: &PackagePath, test_include: &[String]) -> bool {
+  let path = path.to_string();
+  let Some(extension) = TEST_FILE_EXTENSIONS
+    .iter()
+    .find(|extension| path.ends_with(*extension))
+  else {
+    return false;
+  };
+
+  if test_include.iter().any(|dir| {
+    path
+      .strip_prefix(dir.trim_end_matches('/'))
+      .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+  }) {
+    return true;
+  }
+
+  let file_name = path.rsplit('/').next().unwrap();
+  let stem = file_name.strip_suffix(extension).unwrap();
+  stem == "test" || stem.ends_with("_test") || stem.ends_with(".test")
+}
+
+#[derive(serde::Deserialize)]
+struct IstanbulCoverageSummary {
+  total: IstanbulCoverageTotals,
+}
+
+#[derive(serde::Deserialize)]
+struct IstanbulCoverageTotals {
+  lines: IstanbulCoverageMetric,
+  functions: Option<IstanbulCoverageMetric>,
+  branches: Option<IstanbulCoverageMetric>,
+}
+
+#[derive(serde::Deserialize)]
+struct IstanbulCoverageMetric {
+  total: u32,
+  covered: u32,
+}
+
+impl From<IstanbulCoverageMetric> for CoverageMetric {
+  fn from(value: IstanbulCoverageMetric) -> Self {
+    Self {
+      total: value.total,
+      covered: value.covered,
+    }
+  }
+}
+
+/// Parses a coverage summary, either an lcov report (as written by
+/// `deno coverage --lcov`) or an Istanbul `json-summary` report.
+pub fn parse_coverage_summary(bytes: &[u8]) -> Result<CoverageSummary, String> {
+  let text = std::str::from_utf8(bytes)
+    .map_err(|_| "the coverage summary is not valid UTF-8".to_string())?;
+
+  let summary = if text.trim_start().starts_with('{') {
+    let summary: IstanbulCoverageSummary = serde_json::from_str(text)
+      .map_err(|err| format!("invalid Istanbul coverage summary: {err}"))?;
+    CoverageSummary {
+      lines: summary.total.lines.into(),
+      functions: summary.total.functions.map(Into::into),
+      branches: summary.total.branches.map(Into::into),
+    }
+  } else {
+    parse_lcov_summary(text)?
+  };
+
+  for (name, metric) in [
+    ("lines", Some(summary.lines)),
+    ("functions", summary.functions),
+    ("branches", summary.branches),
+  ] {
+    if let Some(metric) = metric
+      && metric.covered > metric.total
+    {
+      return Err(format!(
+        "{} {name} are covered, but there are only {} in total",
+        metric.covered, metric.total
+      ));
+    }
+  }
+
+  Ok(summary)
+}
+
+/// Sums up the per-file totals (`LF`/`LH`, `FNF`/`FNH`, `BRF`/`BRH`) of an
+/// lcov report.
+fn parse_lcov_summary(text: &str) -> Result<CoverageSummary, String> {
+  let mut lines: Option<CoverageMetric> = None;
+  let mut functions: Option<CoverageMetric> = None;
+  let mut branches: Option<CoverageMetric> = None;
+
+  for line in text.lines() {
+    let Some((key, value)) = line.trim().split_once(':') else {
+      continue;
+    };
+    let (metric, is_total) = match key {
+      "LF" => (&mut lines, true),
+      "LH" => (&mut lines, false),
+      "FNF" => (&mut functions, true),
+      "FNH" => (&mut functions, false),
+      "BRF" => (&mut branches, true),
+      "BRH" => (&mut branches, false),
+      _ => continue,
+    };
+    let value = value
+      .trim()
+      .parse::<u32>()
+      .map_err(|_| format!("invalid lcov line '{line}'"))?;
+    let metric = metric.get_or_insert(CoverageMetric {
+      total: 0,
+      covered: 0,
+    });
+    let field = if is_total {
+      &mut metric.total
+    } else {
+      &mut metric.covered
+    };
+    *field = field.saturating_add(value);
+  }
+
+  let lines = lines.ok_or_else(|| {
+    "the coverage summary is neither an lcov report with line coverage (LF \
+     and LH records) nor an Istanbul json-summary report"
+      .to_string()
+  })?;
+
+  Ok(CoverageSummary {
+    lines,
+    functions,
+    branches,
+  })
+}
+
+fn entrypoints_without_module_doc(
+  documents_by_url: &ParseOutput,
+  main_entrypoint: Option<ModuleSpecifier>,
+  has_readme: bool,
+) -> Vec<String> {
+  let mut missing = vec![];
+
+  for (specifier, document) in documents_by_url {
+    // Skip WASM modules as their docs are auto-generated from binary
+    if specifier.path().ends_with(".wasm") {
+      continue;
+    }
+    if !document.module_doc.is_empty() {
+      continue;
+    }
+
+    if main_entrypoint
       .as_ref()
-      .is_some_and(|doc| doc.contains("```") || doc.contains("~~~"))
-      || js_doc
-        .tags
+      .is_some_and(|main_entrypoint| main_entrypoint == specifier)
+      && has_readme
+    {
+      continue;
+    }
+
+    missing.push(specifier.path().to_string());
+  }
+
+  missing
+}
+
+/// Returns the number of documented symbols and the total number of
+/// non-private symbols across all entrypoints.
+fn count_symbols_with_docs(documents_by_url: &ParseOutput) -> (u32, u32) {
+  let mut total_symbols = 0;
+  let mut documented_symbols = 0;
+
+  for (specifier, document) in documents_by_url {
+    // Skip WASM modules as their docs are auto-generated from binary
+    if specifier.path().ends_with(".wasm") {
+      continue;
+    }
+
+    let (documented, total) = count_declarations_with_docs(&document.symbols);
+    documented_symbols += documented;
+    total_symbols += total;
+  }
+
+  (documented_symbols, total_symbols)
+}
+
+fn count_declarations_with_docs(symbols: &[Symbol]) -> (u32, u32) {
+  let mut total_symbols = 0;
+  let mut documented_symbols = 0;
+
+  for symbol in symbols {
+    for decl in &symbol.declarations {
+      if decl.declaration_kind != deno_doc::node::DeclarationKind::Private {
+        total_symbols += 1;
+
+        if !decl.js_doc.is_empty() {
+          documented_symbols += 1;
+        }
+      }
+    }
+  }
+
+  (documented_symbols, total_symbols)
+}
+
+/// Returns the documentation coverage of every export, so that undocumented
+/// export subpaths can be pointed out individually.
+fn entrypoint_docs_coverage(
+  exports: &ExportsMap,
+  documents_by_url: &ParseOutput,
+) -> Vec<EntrypointDocsCoverage> {
+  exports
+    .iter()
+    .filter_map(|(export, path)| {
+      let specifier =
+        Url::parse(&format!("file://{}", path.strip_prefix('.')?)).ok()?;
+      // Skip WASM modules as their docs are auto-generated from binary
+      if specifier.path().ends_with(".wasm") {
+        return None;
+      }
+      let document = documents_by_url.get(&specifier)?;
+      let (documented_symbols, total_symbols) =
+        count_declarations_with_docs(&document.symbols);
+      Some(EntrypointDocsCoverage {
+        export: export.clone(),
+        path: specifier.path().to_string(),
+        has_module_doc: !document.module_doc.is_empty(),
+        documented_symbols,
+        total_symbols,
+      })
+    })
+    .collect()
+}
+
+/// Returns every non-private symbol of an entrypoint that has a declaration
+/// tagged with `@deprecated`, in the order of the exports map.
+fn collect_deprecations(
+  exports: &ExportsMap,
+  documents_by_url: &ParseOutput,
+) -> Vec<DeprecatedSymbol> {
+  let mut deprecations = vec![];
+
+  for (export, path) in exports.iter() {
+    let Some(path) = path.strip_prefix('.') else {
+      continue;
+    };
+    let Ok(specifier) = Url::parse(&format!("file://{path}")) else {
+      continue;
+    };
+    let Some(document) = documents_by_url.get(&specifier) else {
+      continue;
+    };
+
+    for symbol in &document.symbols {
+      let message = symbol
+        .declarations
         .iter()
-        .any(|tag| matches!(tag, deno_doc::js_doc::JsDocTag::Example { .. }))
-  });
+        .filter(|decl| {
+          decl.declaration_kind != deno_doc::node::DeclarationKind::Private
+        })
+        .flat_map(|decl| &decl.js_doc.tags)
+        .find_map(|tag| match tag {
+          deno_doc::js_doc::JsDocTag::Deprecated { doc } => Some(
+            doc
+              .as_deref()
+              .map(str::trim)
+              .filter(|doc| !doc.is_empty())
+              .map(|doc| doc.to_string()),
+          ),
+          _ => None,
+        });
+      if let Some(message) = message {
+        deprecations.push(DeprecatedSymbol {
+          export: export.clone(),
+          symbol: symbol.name.to_string(),
+          message,
+        });
+      }
+    }
+  }
+
+  deprecations
+}
+
+fn collect_symbols(
+  exports: &ExportsMap,
+  documents_by_url: &ParseOutput,
+) -> Vec<ExportedSymbol> {
+  let mut symbols = vec![];
+
+  for (export, path) in exports.iter() {
+    let Some(path) = path.strip_prefix('.') else {
+      continue;
+    };
+    let Ok(specifier) = Url::parse(&format!("file://{path}")) else {
+      continue;
+    };
+    let Some(document) = documents_by_url.get(&specifier) else {
+      continue;
+    };
+
+    for symbol in &document.symbols {
+      // overloads and merged declarations share a name, and often a kind
+      let mut kinds = vec![];
+      for decl in &symbol.declarations {
+        if decl.declaration_kind == deno_doc::node::DeclarationKind::Private {
+          continue;
+        }
+        let kind = match decl.def {
+          deno_doc::DeclarationDef::Function(_) => "function",
+          deno_doc::DeclarationDef::Variable(_) => "variable",
+          deno_doc::DeclarationDef::Enum(_) => "enum",
+          deno_doc::DeclarationDef::Class(_) => "class",
+          deno_doc::DeclarationDef::TypeAlias(_) => "typeAlias",
+          deno_doc::DeclarationDef::Namespace(_) => "namespace",
+          deno_doc::DeclarationDef::Interface(_) => "interface",
+          deno_doc::DeclarationDef::Reference(_) => continue,
+        };
+        if !kinds.contains(&kind) {
+          kinds.push(kind);
+        }
+      }
+      symbols.extend(kinds.into_iter().map(|kind| ExportedSymbol {
+        export: export.clone(),
+        symbol: symbol.name.to_string(),
+        kind,
+      }));
+    }
+  }
+
+  symbols
+}
+
+/// Files larger than this are not rendered at publish time, but only once
+/// they are first viewed.
+const MAX_PRERENDERED_SOURCE_VIEW_SIZE: usize = 512 * 1024;
+
+fn render_source_views(
+  files: &HashMap<PackagePath, Vec<u8>>,
+) -> Vec<SourceView> {
+  let mut hashes = HashSet::new();
+  let mut views = vec![];
+
+  for (path, bytes) in files {
+    if bytes.len() > MAX_PRERENDERED_SOURCE_VIEW_SIZE {
+      continue;
+    }
+    let Ok(source) = std::str::from_utf8(bytes) else {
+      continue;
+    };
+    let hash = format!("sha256-{:x}", Sha256::digest(bytes));
+    if !hashes.insert(hash.clone()) {
+      continue;
+    }
+    match crate::tree_sitter::render_source_view(path, source) {
+      Ok(html) => views.push(SourceView { hash, html }),
+      Err(err) => {
+        tracing::warn!("failed to render source view of {path}: {err}");
+      }
+    }
+  }
+
+  views
+}
+
+/// Collects the documentation page of every public symbol. A symbol that is
+/// exported from multiple entrypoints links to the main entrypoint, or
+/// otherwise the first one it is exported from.
+fn collect_doc_links(
+  exports: &ExportsMap,
+  documents_by_url: &ParseOutput,
+) -> Vec<DocLink> {
+  fn collect(
+    links: &mut IndexMap<String, String>,
+    entrypoint: &str,
+    prefix: &str,
+    symbols: &[Arc<deno_doc::Symbol>],
+  ) {
+    for symbol in symbols {
+      let public = symbol.declarations.iter().filter(|decl| {
+        decl.declaration_kind != deno_doc::node::DeclarationKind::Private
+      });
+      let mut public = public.peekable();
+      if public.peek().is_none() {
+        continue;
+      }
+
+      let identifier = format!("{prefix}{}", symbol.name);
+      for decl in public {
+        if let deno_doc::DeclarationDef::Namespace(def) = &decl.def {
+          collect(links, entrypoint, &format!("{identifier}."), &def.elements);
+        }
+      }
+      if !links.contains_key(&identifier) {
+        let path = format!("/doc{entrypoint}/~/{identifier}");
+        links.insert(identifier, path);
+      }
+    }
+  }
+
+  let mut links = IndexMap::new();
+
+  let mut exports = exports.iter().collect::<Vec<_>>();
+  exports.sort_by_key(|(export, _)| *export != ".");
+  for (export, path) in exports {
+    let Some(path) = path.strip_prefix('.') else {
+      continue;
+    };
+    let Ok(specifier) = Url::parse(&format!("file://{path}")) else {
+      continue;
+    };
+    let Some(document) = documents_by_url.get(&specifier) else {
+      continue;
+    };
+
+    let entrypoint = export.strip_prefix('.').unwrap_or_default();
+    collect(&mut links, entrypoint, "", &document.symbols);
+  }
+
+  links
+    .into_iter()
+    .map(|(identifier, path)| DocLink { identifier, path })
+    .collect()
+}
+
+/// Upper bound on the number of examples checked per version, so a package
+/// with hundreds of examples can not make publishing arbitrarily slow.
+const MAX_CHECKED_EXAMPLES: usize = 100;
+
+static FENCED_CODE_BLOCK_RE: Lazy<Regex> = Lazy::new(|| {
+  Regex::new(
+    r"(?ms)^[ \t]*(?:```|~~~)[ \t]*([\w-]*)[^\n]*\n(.*?)^[ \t]*(?:```|~~~)[ \t]*$",
+  )
+  .unwrap()
+});
+
+/// A code example from the readme or a JSDoc `@example` tag.
+struct Example {
+  /// Describes where the example comes from, for use in score hints.
+  origin: String,
+  media_type: MediaType,
+  source: String,
+}
+
+/// Returns the language and contents of all fenced code blocks in `text`.
+/// Blocks in a language other than JavaScript or TypeScript are skipped.
+fn fenced_code_blocks(text: &str) -> Vec<(MediaType, String)> {
+  FENCED_CODE_BLOCK_RE
+    .captures_iter(text)
+    .filter_map(|captures| {
+      let media_type = match &captures[1] {
+        "ts" | "typescript" | "mts" => MediaType::TypeScript,
+        "tsx" => MediaType::Tsx,
+        "js" | "javascript" | "mjs" => MediaType::JavaScript,
+        "jsx" => MediaType::Jsx,
+        _ => return None,
+      };
+      Some((media_type, captures[2].to_string()))
+    })
+    .collect()
+}
+
+/// Parses the readme and collects its [ReadmeQuality]. Relative links are only
+/// checked if the files of the package are given, anchor links always are.
+fn readme_quality(
+  path: &PackagePath,
+  readme: &[u8],
+  files: Option<&HashSet<&PackagePath>>,
+) -> ReadmeQuality {
+  let readme = String::from_utf8_lossy(readme);
+  let arena = comrak::Arena::new();
+  let root =
+    comrak::parse_document(&arena, &readme, &comrak::Options::default());
+
+  let mut quality = ReadmeQuality::default();
+  let mut anchors = HashSet::new();
+  let mut urls = vec![];
+
+  for node in root.descendants() {
+    match &node.data.borrow().value {
+      NodeValue::Heading(heading) => {
+        let text = markdown_text(node);
+        // Like GitHub, repeated headings get a numeric suffix.
+        let slug = heading_slug(&text);
+        let mut anchor = slug.clone();
+        let mut i = 1;
+        while !anchors.insert(anchor.clone()) {
+          anchor = format!("{slug}-{i}");
+          i += 1;
+        }
+        quality.headings.push(ReadmeHeading {
+          level: heading.level,
+          text,
+        });
+      }
+      NodeValue::CodeBlock(block) => {
+        quality.code_blocks += 1;
+        quality.has_install_instructions |=
+          is_install_instruction(&block.literal);
+      }
+      NodeValue::Code(code) => {
+        quality.has_install_instructions |=
+          is_install_instruction(&code.literal);
+      }
+      NodeValue::Link(link) => {
+        quality.links += 1;
+        urls.push(link.url.clone());
+      }
+      NodeValue::Image(image) => {
+        quality.images += 1;
+        if markdown_text(node).trim().is_empty() {
+          quality.images_without_alt += 1;
+        }
+        urls.push(image.url.clone());
+      }
+      _ => {}
+    }
+  }
+
+  let mut seen = HashSet::new();
+  quality.broken_links = urls
+    .into_iter()
+    .filter(|url| seen.insert(url.clone()))
+    .filter(|url| is_broken_link(url, path, &anchors, files))
+    .collect();
+
+  quality
+}
+
+/// The plain text content of a markdown node, e.g. of a heading or the alt
+/// text of an image.
+fn markdown_text<'a>(node: &'a comrak::nodes::AstNode<'a>) -> String {
+  let mut text = String::new();
+  for node in node.descendants() {
+    match &node.data.borrow().value {
+      NodeValue::Text(value) => text.push_str(value),
+      NodeValue::Code(code) => text.push_str(&code.literal),
+      NodeValue::SoftBreak | NodeValue::LineBreak => text.push(' '),
+      _ => {}
+    }
+  }
+  text
+}
+
+/// The anchor GitHub generates for a heading.
+fn heading_slug(text: &str) -> String {
+  text
+    .trim()
+    .to_lowercase()
+    .chars()
+    .filter_map(|c| match c {
+      ' ' => Some('-'),
+      '-' | '_' => Some(c),
+      c if c.is_alphanumeric() => Some(c),
+      _ => None,
+    })
+    .collect()
+}
+
+fn is_install_instruction(code: &str) -> bool {
+  ["deno add", "jsr add", "jsr:@", "@jsr/"]
+    .iter()
+    .any(|pattern| code.contains(pattern))
+}
+
+fn is_broken_link(
+  url: &str,
+  readme_path: &PackagePath,
+  anchors: &HashSet<String>,
+  files: Option<&HashSet<&PackagePath>>,
+) -> bool {
+  if let Some(anchor) = url.strip_prefix('#') {
+    let anchor = percent_decode_str(anchor)
+      .decode_utf8_lossy()
+      .to_lowercase();
+    return !anchor.is_empty() && !anchors.contains(&anchor);
+  }
+  // Absolute URLs can not be checked without fetching them.
+  if url.starts_with("//") || Url::parse(url).is_ok() {
+    return false;
+  }
+  let Some(files) = files else {
+    return false;
+  };
+  let base = Url::parse(&format!("file://{readme_path}")).unwrap();
+  let Ok(target) = base.join(url) else {
+    return true;
+  };
+  let target = percent_decode_str(target.path()).decode_utf8_lossy();
+  let target = target.trim_end_matches('/');
+  // Links to directories are valid as long as the directory contains files.
+  let dir_prefix = format!("{target}/");
+  !files.iter().any(|file| {
+    let file = file.to_string();
+    file == target || file.starts_with(&dir_prefix)
+  })
+}
+
+/// Collects the JavaScript and TypeScript examples from the readme and from
+/// the `@example` tags of the module docs and exported symbols.
+fn collect_examples(
+  readme: Option<(&PackagePath, &Vec<u8>)>,
+  doc_nodes: &ParseOutput,
+) -> Vec<Example> {
+  let mut examples = vec![];
+
+  if let Some((path, readme)) = readme {
+    let readme = String::from_utf8_lossy(readme);
+    for (i, (media_type, source)) in
+      fenced_code_blocks(&readme).into_iter().enumerate()
+    {
+      examples.push(Example {
+        origin: format!("example {} in '{path}'", i + 1),
+        media_type,
+        source,
+      });
+    }
+  }
+
+  let mut push_js_doc_examples =
+    |js_doc: &deno_doc::js_doc::JsDoc, owner: &str| {
+      for tag in &js_doc.tags {
+        let deno_doc::js_doc::JsDocTag::Example { doc, .. } = tag else {
+          continue;
+        };
+        // Examples are usually fenced, but a bare example is plain code.
+        let blocks = if doc.contains("```") || doc.contains("~~~") {
+          fenced_code_blocks(doc)
+        } else {
+          vec![(MediaType::TypeScript, doc.to_string())]
+        };
+        for (media_type, source) in blocks {
+          examples.push(Example {
+            origin: format!("@example of {owner}"),
+            media_type,
+            source,
+          });
+        }
+      }
+    };
+
+  for (specifier, document) in doc_nodes {
+    if specifier.path().ends_with(".wasm") {
+      continue;
+    }
+    push_js_doc_examples(
+      &document.module_doc,
+      &format!("module '{}'", specifier.path()),
+    );
+    for symbol in &document.symbols {
+      for decl in &symbol.declarations {
+        push_js_doc_examples(&decl.js_doc, &format!("'{}'", symbol.name));
+      }
+    }
+  }
+
+  examples.truncate(MAX_CHECKED_EXAMPLES);
+  examples
+}
+
+/// Checks that every example parses, that all of its imports resolve, and
+/// that every name it imports from this package is actually exported by the
+/// imported entrypoint. Returns a description of each failing example.
+///
+/// Examples import the package by its `jsr:` specifier or by its bare name,
+/// which are resolved to the package's own files. Other bare specifiers are
+/// assumed to come from an import map and are treated as external.
+async fn check_examples(
+  examples: Vec<Example>,
+  files: &HashMap<PackagePath, Vec<u8>>,
+  spilled_files: &SpilledFiles,
+  workspace_member: WorkspaceMember,
+  doc_nodes: &ParseOutput,
+  unstable_bytes_imports: bool,
+) -> Vec<String> {
+  if examples.is_empty() {
+    return vec![];
+  }
+
+  let examples = examples
+    .into_iter()
+    .enumerate()
+    .map(|(i, example)| {
+      let url = Url::parse(&format!(
+        "file:///$jsr_example_{i}{}",
+        example.media_type.as_ts_extension()
+      ))
+      .unwrap();
+      (url, example)
+    })
+    .collect::<IndexMap<_, _>>();
+
+  let module_analyzer = ModuleAnalyzer::default();
+  let mut graph = ModuleGraph::new(GraphKind::All);
+  graph
+    .build(
+      examples.keys().cloned().collect(),
+      vec![],
+      &ExampleLoader {
+        package: SyncLoader {
+          files,
+          spilled_files,
+        },
+        examples: &examples,
+      },
+      BuildOptions {
+        is_dynamic: false,
+        module_analyzer: &module_analyzer,
+        file_system: &NullFileSystem,
+        jsr_url_provider: &PassthroughJsrUrlProvider,
+        jsr_version_resolver: Default::default(),
+        passthrough_jsr_specifiers: true,
+        resolver: Some(&ExampleResolver {
+          package: JsrResolver {
+            members: vec![workspace_member],
+          },
+        }),
+        npm_resolver: None,
+        reporter: None,
+        executor: Default::default(),
+        locker: None,
+        skip_dynamic_deps: true,
+        module_info_cacher: Default::default(),
+        unstable_bytes_imports,
+        unstable_text_imports: false,
+        jsr_metadata_store: None,
+        unstable_css_imports: false,
+      },
+    )
+    .await;
 
-  PackageVersionMeta {
-    has_readme: readme.is_some()
-      || main_entrypoint_doc
-        .is_some_and(|doc| doc.doc.as_ref().is_some_and(|doc| !doc.is_empty())),
-    has_readme_examples,
-    all_entrypoints_docs: all_entrypoints_have_module_doc(
-      documents_by_url,
-      main_entrypoint,
-      readme.is_some(),
-    ),
-    percentage_documented_symbols: percentage_of_symbols_with_docs(
-      documents_by_url,
-    ),
-    all_fast_check,
-    has_provenance: false, // Provenance score is updated after version publish
+  let mut failing = vec![];
+  for (url, example) in &examples {
+    if let Err(err) = check_example(&graph, &module_analyzer, url, doc_nodes) {
+      failing.push(format!("{}: {err}", example.origin));
+    }
   }
+  failing
 }
 
-fn all_entrypoints_have_module_doc(
-  documents_by_url: &ParseOutput,
-  main_entrypoint: Option<ModuleSpecifier>,
-  has_readme: bool,
-) -> bool {
-  'modules: for (specifier, document) in documents_by_url {
-    // Skip WASM modules as their docs are auto-generated from binary
-    if specifier.path().ends_with(".wasm") {
-      continue;
-    }
-    if !document.module_doc.is_empty() {
-      continue 'modules;
-    }
+fn check_example(
+  graph: &ModuleGraph,
+  module_analyzer: &ModuleAnalyzer,
+  url: &ModuleSpecifier,
+  doc_nodes: &ParseOutput,
+) -> Result<(), String> {
+  let module = match graph.try_get(url) {
+    Ok(Some(module)) => module,
+    Ok(None) => return Ok(()),
+    Err(err) => return Err(err.to_string()),
+  };
+  let Some(js) = module.js() else {
+    return Ok(());
+  };
 
-    if main_entrypoint
-      .as_ref()
-      .is_some_and(|main_entrypoint| main_entrypoint == specifier)
-      && has_readme
-    {
-      continue 'modules;
+  let mut resolved_imports = HashMap::new();
+  for (specifier_text, dep) in &js.dependencies {
+    match &dep.maybe_code {
+      deno_graph::Resolution::Ok(resolved) => {
+        let specifier = graph.resolve(&resolved.specifier);
+        if let Err(err) = graph.try_get(specifier) {
+          return Err(err.to_string());
+        }
+        resolved_imports.insert(specifier_text.as_str(), specifier);
+      }
+      deno_graph::Resolution::Err(err) => return Err(err.to_string()),
+      deno_graph::Resolution::None => {}
     }
+  }
 
-    return false;
+  let Some(parsed_source) = module_analyzer.analyzer.get_parsed_source(url)
+  else {
+    return Ok(());
+  };
+  for (specifier_text, name) in named_imports(&parsed_source) {
+    let Some(document) = resolved_imports
+      .get(specifier_text.as_str())
+      .and_then(|specifier| doc_nodes.get(*specifier))
+    else {
+      continue;
+    };
+    if !document.symbols.iter().any(|symbol| *symbol.name == *name) {
+      return Err(format!("'{name}' is not exported from '{specifier_text}'"));
+    }
   }
 
-  true
+  Ok(())
 }
 
-fn percentage_of_symbols_with_docs(documents_by_url: &ParseOutput) -> f32 {
-  let mut total_symbols = 0;
-  let mut documented_symbols = 0;
+/// Returns the module specifier and imported name of every named and default
+/// import in the module.
+fn named_imports(parsed_source: &ParsedSource) -> Vec<(String, String)> {
+  let text_info = parsed_source.text_info_lazy();
+  let unquote = |range: SourceRange| {
+    let text = text_info.range_text(&range);
+    text[1..text.len() - 1].to_string()
+  };
 
-  for (specifier, document) in documents_by_url {
-    // Skip WASM modules as their docs are auto-generated from binary
-    if specifier.path().ends_with(".wasm") {
+  let mut imports = vec![];
+  for item in parsed_source.program_ref().body() {
+    let deno_ast::ModuleItemRef::ModuleDecl(ast::ModuleDecl::Import(import)) =
+      item
+    else {
       continue;
+    };
+    if import.type_only {
+      continue;
+    }
+    let specifier_text = unquote(import.src.range());
+    for specifier in &import.specifiers {
+      let name = match specifier {
+        ast::ImportSpecifier::Named(named) => match &named.imported {
+          Some(ast::ModuleExportName::Ident(ident)) => ident.sym.to_string(),
+          Some(ast::ModuleExportName::Str(str)) => unquote(str.range()),
+          None => named.local.sym.to_string(),
+        },
+        ast::ImportSpecifier::Default(_) => "default".to_string(),
+        ast::ImportSpecifier::Namespace(_) => continue,
+      };
+      imports.push((specifier_text.clone(), name));
     }
+  }
+  imports
+}
 
-    for symbol in &document.symbols {
-      for decl in &symbol.declarations {
-        if decl.declaration_kind != deno_doc::node::DeclarationKind::Private {
-          total_symbols += 1;
+struct ExampleResolver {
+  package: JsrResolver,
+}
 
-          if !decl.js_doc.is_empty() {
-            documented_symbols += 1;
-          }
-        }
-      }
+impl deno_graph::source::Resolver for ExampleResolver {
+  fn resolve(
+    &self,
+    specifier_text: &str,
+    referrer_range: &deno_graph::Range,
+    kind: deno_graph::source::ResolutionKind,
+  ) -> Result<ModuleSpecifier, deno_graph::source::ResolveError> {
+    let is_bare = !specifier_text.starts_with('.')
+      && !specifier_text.starts_with('/')
+      && Url::parse(specifier_text).is_err();
+    if !is_bare {
+      return self.package.resolve(specifier_text, referrer_range, kind);
+    }
+    if specifier_text.starts_with('@') {
+      self.package.resolve(
+        &format!("jsr:{specifier_text}"),
+        referrer_range,
+        kind,
+      )
+    } else {
+      Ok(ModuleSpecifier::parse(&format!("npm:{specifier_text}")).unwrap())
     }
   }
+}
 
-  if total_symbols == 0 {
-    return 1.0;
-  }
+struct ExampleLoader<'a> {
+  package: SyncLoader<'a>,
+  examples: &'a IndexMap<ModuleSpecifier, Example>,
+}
 
-  (documented_symbols as f32) / (total_symbols as f32)
+impl deno_graph::source::Loader for ExampleLoader<'_> {
+  fn load(
+    &self,
+    specifier: &ModuleSpecifier,
+    _options: LoadOptions,
+  ) -> deno_graph::source::LoadFuture {
+    let result = match self.examples.get(specifier) {
+      Some(example) => Ok(Some(deno_graph::source::LoadResponse::Module {
+        content: example.source.clone().into_bytes().into(),
+        mtime: None,
+        specifier: specifier.clone(),
+        maybe_headers: None,
+      })),
+      None => self.package.load_sync(specifier),
+    };
+    async move { result }.boxed()
+  }
 }
 
 pub struct PassthroughJsrUrlProvider;
@@ -418,9 +1764,11 @@ impl JsrUrlProvider for PassthroughJsrUrlProvider {
   }
 }
 
+/// Resolves `jsr:` imports of the given workspace members to their files.
+/// Other `jsr:` imports are left as is.
 #[derive(Debug)]
 pub struct JsrResolver {
-  pub member: WorkspaceMember,
+  pub members: Vec<WorkspaceMember>,
 }
 
 impl deno_graph::source::Resolver for JsrResolver {
@@ -431,24 +1779,25 @@ impl deno_graph::source::Resolver for JsrResolver {
     _kind: deno_graph::source::ResolutionKind,
   ) -> Result<ModuleSpecifier, deno_graph::source::ResolveError> {
     if let Ok(package_ref) = JsrPackageReqReference::from_str(specifier_text)
-      && self.member.name == package_ref.req().name
-      && self
-        .member
-        .version
-        .as_ref()
-        .map(|v| package_ref.req().version_req.matches(v))
-        .unwrap_or(true)
+      && let Some(member) = self.members.iter().find(|member| {
+        member.name == package_ref.req().name
+          && member
+            .version
+            .as_ref()
+            .map(|v| package_ref.req().version_req.matches(v))
+            .unwrap_or(true)
+      })
     {
       let export_name = package_ref.sub_path().unwrap_or(".");
-      let Some(export) = self.member.exports.get(export_name) else {
+      let Some(export) = member.exports.get(export_name) else {
         return Err(deno_graph::source::ResolveError::Other(
           JsErrorBox::generic(format!(
             "export '{}' not found in jsr:{}",
-            export_name, self.member.name
+            export_name, member.name
           )),
         ));
       };
-      return Ok(self.member.base.join(export).unwrap());
+      return Ok(member.base.join(export).unwrap());
     }
 
     Ok(deno_graph::resolve_import(
@@ -460,6 +1809,7 @@ impl deno_graph::source::Resolver for JsrResolver {
 
 struct SyncLoader<'a> {
   files: &'a HashMap<PackagePath, Vec<u8>>,
+  spilled_files: &'a SpilledFiles,
 }
 
 impl SyncLoader<'_> {
@@ -472,8 +1822,19 @@ impl SyncLoader<'_> {
         let Ok(path) = PackagePath::new(specifier.path().to_string()) else {
           return Ok(None);
         };
-        let Some(bytes) = self.files.get(&path).cloned() else {
-          return Ok(None);
+        let bytes = match self.files.get(&path) {
+          Some(bytes) => bytes.clone(),
+          // Spilled files are never part of the module graph of the package
+          // itself, but examples may still import them.
+          None => match self.spilled_files.read(&path) {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => return Ok(None),
+            Err(err) => {
+              return Err(LoadError::Other(Arc::new(JsErrorBox::from_err(
+                err,
+              ))));
+            }
+          },
         };
         Ok(Some(deno_graph::source::LoadResponse::Module {
           content: bytes.into(),
@@ -504,6 +1865,39 @@ impl deno_graph::source::Loader for SyncLoader<'_> {
   }
 }
 
+/// Loads the files of the packages of a workspace publish, each under the
+/// base URL of its workspace member.
+struct WorkspaceLoader<'a> {
+  packages: Vec<(Url, &'a HashMap<PackagePath, Vec<u8>>)>,
+  /// Loads everything that is not a file of one of the packages.
+  external: SyncLoader<'a>,
+}
+
+impl deno_graph::source::Loader for WorkspaceLoader<'_> {
+  fn load(
+    &self,
+    specifier: &ModuleSpecifier,
+    _options: LoadOptions,
+  ) -> deno_graph::source::LoadFuture {
+    let result = if specifier.scheme() == "file" {
+      let bytes = self.packages.iter().find_map(|(base, files)| {
+        let path = specifier.as_str().strip_prefix(base.as_str())?;
+        let path = PackagePath::new(format!("/{path}")).ok()?;
+        files.get(&path)
+      });
+      Ok(bytes.map(|bytes| deno_graph::source::LoadResponse::Module {
+        content: bytes.clone().into(),
+        mtime: None,
+        specifier: specifier.clone(),
+        maybe_headers: None,
+      }))
+    } else {
+      self.external.load_sync(specifier)
+    };
+    async move { result }.boxed()
+  }
+}
+
 pub struct RebuildNpmTarballData {
   pub scope: ScopeName,
   pub name: PackageName,
@@ -511,6 +1905,17 @@ pub struct RebuildNpmTarballData {
   pub exports: ExportsMap,
   pub files: HashSet<PackagePath>,
   pub dependencies: Vec<(DependencyKind, PackageReqReference)>,
+  pub optional_dependencies: Vec<(DependencyKind, PackageReqReference)>,
+  pub peer_dependencies: Vec<(DependencyKind, PackageReqReference)>,
+  pub runtime_compat: RuntimeCompat,
+  pub bin: IndexMap<String, String>,
+  pub export_patterns: IndexMap<String, String>,
+  pub npm_exclude: Vec<String>,
+  pub cjs: bool,
+  pub dts_rollup: bool,
+  /// The sha512 hash of a tarball previously built from the same inputs. If
+  /// set, the rebuilt tarball must be byte-identical to it.
+  pub expected_sha512: Option<String>,
 }
 
 // We have to spawn another tokio runtime, because
@@ -544,6 +1949,15 @@ async fn rebuild_npm_tarball_inner(
     exports,
     files,
     dependencies,
+    optional_dependencies,
+    peer_dependencies,
+    runtime_compat,
+    bin,
+    export_patterns,
+    npm_exclude,
+    cjs,
+    dts_rollup,
+    expected_sha512,
   } = data;
 
   let mut roots = vec![];
@@ -561,6 +1975,10 @@ async fn rebuild_npm_tarball_inner(
     roots.push(url);
   }
 
+  let version_files = CachedVersionFiles::new(
+    VersionFiles::load(&modules_bucket, &scope, &name, &version).await?,
+  );
+
   let module_analyzer = ModuleAnalyzer::default();
 
   let mut graph = deno_graph::ModuleGraph::new(GraphKind::All);
@@ -571,41 +1989,48 @@ async fn rebuild_npm_tarball_inner(
     exports: exports.clone().into_inner(),
   };
   let workspace_members = vec![workspace_member.clone()];
-  graph
-    .build(
-      roots.clone(),
-      vec![],
-      &S3Loader {
-        files: &files,
-        bucket: &modules_bucket,
-        scope: &scope,
-        name: &name,
-        version: &version,
-      },
-      BuildOptions {
-        is_dynamic: false,
-        module_analyzer: &module_analyzer,
-        // todo: use the data in the package for the file system
-        file_system: &NullFileSystem,
-        jsr_url_provider: &PassthroughJsrUrlProvider,
-        jsr_version_resolver: Default::default(),
-        passthrough_jsr_specifiers: true,
-        resolver: Some(&JsrResolver {
-          member: workspace_member,
-        }),
-        npm_resolver: Default::default(),
-        reporter: Default::default(),
-        executor: Default::default(),
-        locker: None,
-        skip_dynamic_deps: false,
-        module_info_cacher: Default::default(),
-        unstable_bytes_imports: false,
-        unstable_text_imports: false,
-        jsr_metadata_store: None,
-        unstable_css_imports: false,
-      },
-    )
-    .await;
+  // Every file that ends up in the tarball is needed eventually, so they are
+  // downloaded while the graph is built instead of one import at a time.
+  // Excluded files are never part of the graph.
+  let prefetch = version_files.prefetch(
+    files
+      .iter()
+      .filter(|path| npm_exclude_match(path, &npm_exclude).is_none()),
+  );
+  let loader = BucketLoader {
+    files: &files,
+    version_files: &version_files,
+  };
+  let build = graph.build(
+    roots.clone(),
+    vec![],
+    &loader,
+    BuildOptions {
+      is_dynamic: false,
+      module_analyzer: &module_analyzer,
+      // todo: use the data in the package for the file system
+      file_system: &NullFileSystem,
+      jsr_url_provider: &PassthroughJsrUrlProvider,
+      jsr_version_resolver: Default::default(),
+      passthrough_jsr_specifiers: true,
+      resolver: Some(&JsrResolver {
+        members: vec![workspace_member],
+      }),
+      npm_resolver: Default::default(),
+      reporter: Default::default(),
+      executor: Default::default(),
+      locker: None,
+      skip_dynamic_deps: false,
+      module_info_cacher: Default::default(),
+      // The version was checked when it was published, so it only uses bytes
+      // imports if they were enabled for it at the time.
+      unstable_bytes_imports: true,
+      unstable_text_imports: false,
+      jsr_metadata_store: None,
+      unstable_css_imports: false,
+    },
+  );
+  futures::join!(build, prefetch);
   graph.valid()?;
   graph.build_fast_check_type_graph(BuildFastCheckTypeGraphOptions {
     fast_check_cache: Default::default(),
@@ -626,24 +2051,38 @@ async fn rebuild_npm_tarball_inner(
     exports: &exports,
     files: NpmTarballFiles::FromBucket {
       files: &files,
-      modules_bucket: &modules_bucket,
+      version_files: &version_files,
     },
     dependencies: dependencies.iter(),
+    optional_dependencies: &optional_dependencies,
+    peer_dependencies: &peer_dependencies,
+    runtime_compat: &runtime_compat,
+    bin: &bin,
+    export_patterns: &export_patterns,
+    exclude: &npm_exclude,
+    cjs,
+    dts_rollup,
   })
   .await?;
 
+  if let Some(expected_sha512) = expected_sha512
+    && npm_tarball.sha512 != expected_sha512
+  {
+    anyhow::bail!(
+      "rebuilt npm tarball for @{scope}/{name}@{version} is not reproducible: expected sha512 {expected_sha512}, got {}",
+      npm_tarball.sha512
+    );
+  }
+
   Ok(npm_tarball)
 }
 
-struct S3Loader<'a> {
+struct BucketLoader<'a> {
   files: &'a HashSet<PackagePath>,
-  bucket: &'a BucketWithQueue,
-  scope: &'a ScopeName,
-  name: &'a PackageName,
-  version: &'a Version,
+  version_files: &'a CachedVersionFiles,
 }
 
-impl S3Loader<'_> {
+impl BucketLoader<'_> {
   fn load_inner(
     &self,
     specifier: &ModuleSpecifier,
@@ -657,14 +2096,12 @@ impl S3Loader<'_> {
         if !self.files.contains(&path) {
           return async move { Ok(None) }.boxed();
         };
-        let s3_path =
-          s3_paths::file_path(self.scope, self.name, self.version, &path);
-        let bucket = self.bucket.clone();
+        let version_files = self.version_files.clone();
         async move {
-          let Some(bytes) = bucket
-            .download(s3_path.into())
-            .await
-            .map_err(|e| LoadError::Other(Arc::new(JsErrorBox::from_err(e))))?
+          let Some(bytes) =
+            version_files.download(&path).await.map_err(|e| {
+              LoadError::Other(Arc::new(JsErrorBox::generic(e.to_string())))
+            })?
           else {
             return Ok(None);
           };
@@ -693,7 +2130,7 @@ impl S3Loader<'_> {
   }
 }
 
-impl deno_graph::source::Loader for S3Loader<'_> {
+impl deno_graph::source::Loader for BucketLoader<'_> {
   fn load(
     &self,
     specifier: &ModuleSpecifier,
@@ -770,6 +2207,238 @@ impl deno_graph::analysis::ModuleAnalyzer for ModuleAnalyzer {
   }
 }
 
+/// Parses an entry of the `peerDependencies` field of the config file, which
+/// must be a `jsr:` or `npm:` specifier with a version constraint and without
+/// a sub path.
+pub fn parse_peer_dependency(
+  specifier: &str,
+) -> Result<(DependencyKind, PackageReqReference), String> {
+  let (kind, req) = if specifier.starts_with("jsr:") {
+    let req = JsrPackageReqReference::from_str(specifier)
+      .map_err(|err| err.to_string())?;
+    (DependencyKind::Jsr, req.into_inner())
+  } else if specifier.starts_with("npm:") {
+    let req = NpmPackageReqReference::from_str(specifier)
+      .map_err(|err| err.to_string())?;
+    (DependencyKind::Npm, req.into_inner())
+  } else {
+    return Err(format!(
+      "'{specifier}' must be a 'jsr:' or 'npm:' specifier"
+    ));
+  };
+
+  if req.req.version_req.version_text() == "*" {
+    return Err(format!("'{specifier}' is missing a version constraint"));
+  }
+  if req.sub_path.is_some() {
+    return Err(format!("'{specifier}' must not have a sub path"));
+  }
+
+  Ok((kind, req))
+}
+
+/// The inverse of [parse_peer_dependency], used to store peer dependencies in
+/// the [PackageVersionMeta].
+fn peer_dependency_specifier(
+  kind: DependencyKind,
+  req: &PackageReqReference,
+) -> String {
+  match kind {
+    DependencyKind::Jsr => format!("jsr:{req}"),
+    DependencyKind::Npm => format!("npm:{req}"),
+  }
+}
+
+/// Finds top level uses of globals that only exist in one runtime, such as
+/// `Deno.args`. Code in functions is not checked, as it only runs when called.
+#[derive(Default)]
+struct RuntimeGlobalFinder {
+  found: Option<(&'static str, SourceRange)>,
+}
+
+const RUNTIME_GLOBALS: &[&str] = &["Deno", "Bun"];
+
+impl Visit for RuntimeGlobalFinder {
+  fn visit_function(&mut self, _node: &ast::Function) {}
+
+  fn visit_arrow_expr(&mut self, _node: &ast::ArrowExpr) {}
+
+  fn visit_class(&mut self, _node: &ast::Class) {}
+
+  fn visit_member_expr(&mut self, node: &ast::MemberExpr) {
+    node.visit_children_with(self);
+    if self.found.is_none()
+      && let ast::Expr::Ident(ident) = &*node.obj
+      && let Some(global) = RUNTIME_GLOBALS
+        .iter()
+        .find(|global| ident.sym.as_ref() == **global)
+    {
+      self.found = Some((*global, ident.range()));
+    }
+  }
+}
+
+/// Checks that a module used as a `bin` entrypoint can run in both Node.js,
+/// through the npm tarball, and Deno, through `deno install`.
+fn check_bin_module(parsed_source: &ParsedSource) -> Result<(), String> {
+  let mut finder = RuntimeGlobalFinder::default();
+  match parsed_source.program_ref() {
+    deno_ast::ProgramRef::Module(module) => module.visit_with(&mut finder),
+    deno_ast::ProgramRef::Script(script) => script.visit_with(&mut finder),
+  }
+  if let Some((global, range)) = finder.found {
+    let LineAndColumnDisplay {
+      line_number,
+      column_number,
+    } = parsed_source
+      .text_info_lazy()
+      .line_and_column_display(range.start);
+    return Err(format!(
+      "uses the '{global}' global at the top level ({line_number}:{column_number})"
+    ));
+  }
+  Ok(())
+}
+
+/// Collects the specifiers a module imports, split by whether they are only
+/// imported through the optional dependency pattern: a dynamic `import()` of a
+/// string literal directly inside the `try` block of a `try` statement with a
+/// `catch` clause, so that the module can handle the dependency not being
+/// installed.
+#[derive(Default)]
+struct OptionalImportCollector {
+  in_try_block: bool,
+  optional: HashSet<String>,
+  required: HashSet<String>,
+}
+
+impl OptionalImportCollector {
+  fn is_optional(&self, specifier: &str) -> bool {
+    self.optional.contains(specifier) && !self.required.contains(specifier)
+  }
+}
+
+impl Visit for OptionalImportCollector {
+  fn visit_try_stmt(&mut self, node: &ast::TryStmt) {
+    let in_try_block = self.in_try_block;
+    self.in_try_block = node.handler.is_some();
+    node.block.visit_with(self);
+    self.in_try_block = in_try_block;
+    node.handler.visit_with(self);
+    node.finalizer.visit_with(self);
+  }
+
+  // Functions declared in a `try` block may be called from anywhere.
+  fn visit_function(&mut self, node: &ast::Function) {
+    let in_try_block = std::mem::take(&mut self.in_try_block);
+    node.visit_children_with(self);
+    self.in_try_block = in_try_block;
+  }
+
+  fn visit_arrow_expr(&mut self, node: &ast::ArrowExpr) {
+    let in_try_block = std::mem::take(&mut self.in_try_block);
+    node.visit_children_with(self);
+    self.in_try_block = in_try_block;
+  }
+
+  fn visit_import_decl(&mut self, node: &ast::ImportDecl) {
+    if let Some(value) = node.src.value.as_str() {
+      self.required.insert(value.to_string());
+    }
+  }
+
+  fn visit_named_export(&mut self, node: &ast::NamedExport) {
+    if let Some(src) = &node.src
+      && let Some(value) = src.value.as_str()
+    {
+      self.required.insert(value.to_string());
+    }
+  }
+
+  fn visit_export_all(&mut self, node: &ast::ExportAll) {
+    if let Some(value) = node.src.value.as_str() {
+      self.required.insert(value.to_string());
+    }
+  }
+
+  fn visit_ts_import_type(&mut self, node: &ast::TsImportType) {
+    node.visit_children_with(self);
+    if let Some(value) = node.arg.value.as_str() {
+      self.required.insert(value.to_string());
+    }
+  }
+
+  fn visit_call_expr(&mut self, node: &ast::CallExpr) {
+    node.visit_children_with(self);
+    if let ast::Callee::Import(_) = node.callee
+      && let Some(arg) = node.args.first()
+      && let ast::Expr::Lit(ast::Lit::Str(lit_str)) = &*arg.expr
+      && let Some(value) = lit_str.value.as_str()
+    {
+      if self.in_try_block {
+        self.optional.insert(value.to_string());
+      } else {
+        self.required.insert(value.to_string());
+      }
+    }
+  }
+}
+
+/// Returns the dependencies that no module of the package imports other than
+/// through the optional dependency pattern of [OptionalImportCollector].
+fn collect_optional_dependencies(
+  graph: &ModuleGraph,
+  analyzer: &CapturingModuleAnalyzer,
+  dependencies: &HashSet<(DependencyKind, PackageReqReference)>,
+) -> HashSet<(DependencyKind, PackageReqReference)> {
+  let mut optional = HashSet::new();
+  let mut required = HashSet::new();
+
+  for module in graph.modules() {
+    let Some(js) = module.js() else {
+      continue;
+    };
+    let Some(parsed_source) = analyzer.get_parsed_source(&js.specifier) else {
+      continue;
+    };
+    let mut collector = OptionalImportCollector::default();
+    match parsed_source.program_ref() {
+      deno_ast::ProgramRef::Module(module) => module.visit_with(&mut collector),
+      deno_ast::ProgramRef::Script(script) => script.visit_with(&mut collector),
+    }
+
+    for (text, dependency) in &js.dependencies {
+      let code = dependency.maybe_code.maybe_specifier();
+      let types = dependency.maybe_type.maybe_specifier();
+      if let Some(specifier) = code {
+        let specifier = graph.resolve(specifier).clone();
+        if collector.is_optional(text) {
+          optional.insert(specifier);
+        } else {
+          required.insert(specifier);
+        }
+      }
+      if let Some(specifier) = types {
+        required.insert(graph.resolve(specifier).clone());
+      }
+    }
+  }
+
+  optional
+    .difference(&required)
+    .filter_map(|specifier| match specifier.scheme() {
+      "jsr" => JsrPackageReqReference::from_str(specifier.as_str())
+        .ok()
+        .map(|req| (DependencyKind::Jsr, req.into_inner())),
+      "npm" => NpmPackageReqReference::from_str(specifier.as_str())
+        .ok()
+        .map(|req| (DependencyKind::Npm, req.into_inner())),
+      _ => None,
+    })
+    .filter(|dependency| dependencies.contains(dependency))
+    .collect()
+}
+
 fn collect_dependencies(
   graph: &ModuleGraph,
 ) -> Result<HashSet<(DependencyKind, PackageReqReference)>, PublishError> {
@@ -844,8 +2513,6 @@ fn check_for_banned_extensions(
 fn check_for_banned_syntax(
   parsed_source: &ParsedSource,
 ) -> Result<(), PublishError> {
-  use deno_ast::swc::ast;
-
   let line_col = |range: &SourceRange| -> (usize, usize) {
     let LineAndColumnDisplay {
       line_number,
@@ -1037,6 +2704,171 @@ mod tests {
     );
   }
 
+  #[test]
+  fn fenced_code_blocks() {
+    let blocks = super::fenced_code_blocks(
+      "# foo\n\n```ts\nimport { a } from \"@scope/foo\";\n```\n\n```sh\ndeno add jsr:@scope/foo\n```\n\n~~~js title=\"x\"\nconsole.log(1);\n~~~\n",
+    );
+    assert_eq!(
+      blocks,
+      vec![
+        (
+          deno_ast::MediaType::TypeScript,
+          "import { a } from \"@scope/foo\";\n".to_string()
+        ),
+        (
+          deno_ast::MediaType::JavaScript,
+          "console.log(1);\n".to_string()
+        ),
+      ]
+    );
+  }
+
+  #[test]
+  fn readme_quality() {
+    let readme_path =
+      crate::ids::PackagePath::new("/README.md".to_string()).unwrap();
+    let files = ["/README.md", "/mod.ts", "/docs/guide.md"]
+      .into_iter()
+      .map(|path| crate::ids::PackagePath::new(path.to_string()).unwrap())
+      .collect::<Vec<_>>();
+    let files = files.iter().collect::<std::collections::HashSet<_>>();
+    let readme = r#"# @scope/foo
+
+## Installation
+
+```sh
+deno add jsr:@scope/foo
+```
+
+## Usage
+
+See the [guide](./docs/guide.md), the [docs](docs/), the
+[missing page](./docs/missing.md), [usage](#usage),
+[the API](#api-reference) and [Deno](https://deno.com).
+
+![logo](./logo.png) ![](https://example.com/badge.svg)
+
+    const indented = true;
+"#;
+
+    let quality =
+      super::readme_quality(&readme_path, readme.as_bytes(), Some(&files));
+    assert_eq!(
+      quality.headings,
+      vec![
+        crate::db::ReadmeHeading {
+          level: 1,
+          text: "@scope/foo".to_string()
+        },
+        crate::db::ReadmeHeading {
+          level: 2,
+          text: "Installation".to_string()
+        },
+        crate::db::ReadmeHeading {
+          level: 2,
+          text: "Usage".to_string()
+        },
+      ]
+    );
+    assert_eq!(quality.code_blocks, 2);
+    assert!(quality.has_install_instructions);
+    assert_eq!(quality.links, 6);
+    assert_eq!(quality.images, 2);
+    assert_eq!(quality.images_without_alt, 1);
+    assert_eq!(
+      quality.broken_links,
+      vec!["./docs/missing.md", "#api-reference", "./logo.png"]
+    );
+
+    // Without the package files, only anchor links can be checked.
+    let quality = super::readme_quality(&readme_path, readme.as_bytes(), None);
+    assert_eq!(quality.broken_links, vec!["#api-reference"]);
+
+    let quality = super::readme_quality(
+      &readme_path,
+      b"# foo\n\nInline `code` and ``` fences ``` are not code blocks.\n",
+      None,
+    );
+    assert_eq!(quality.code_blocks, 0);
+    assert!(!quality.has_install_instructions);
+  }
+
+  #[test]
+  fn named_imports() {
+    let x = parse(
+      r#"import { a, b as c, "d" as e } from "jsr:@scope/foo";
+import f from "./mod.ts";
+import * as g from "@scope/foo/bar";
+import type { H } from "@scope/foo";"#,
+    );
+    assert_eq!(
+      super::named_imports(&x),
+      vec![
+        ("jsr:@scope/foo".to_string(), "a".to_string()),
+        ("jsr:@scope/foo".to_string(), "b".to_string()),
+        ("jsr:@scope/foo".to_string(), "d".to_string()),
+        ("./mod.ts".to_string(), "default".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn is_test_file() {
+    let is_test_file = |path: &str, test_include: &[&str]| {
+      let path = crate::ids::PackagePath::new(path.to_string()).unwrap();
+      let test_include = test_include
+        .iter()
+        .map(|dir| dir.to_string())
+        .collect::<Vec<_>>();
+      super::is_test_file(&path, &test_include)
+    };
+    assert!(is_test_file("/test.ts", &[]));
+    assert!(is_test_file("/src/foo_test.ts", &[]));
+    assert!(is_test_file("/src/foo.test.mjs", &[]));
+    assert!(!is_test_file("/src/foo.ts", &[]));
+    assert!(!is_test_file("/src/latest.ts", &[]));
+    assert!(!is_test_file("/foo_test.json", &[]));
+    assert!(is_test_file("/tests/foo.ts", &["/tests"]));
+    assert!(is_test_file("/tests/nested/foo.ts", &["/tests/"]));
+    assert!(!is_test_file("/tests2/foo.ts", &["/tests"]));
+  }
+
+  #[test]
+  fn parse_coverage_summary() {
+    let summary = super::parse_coverage_summary(
+      b"SF:/mod.ts\nFNF:4\nFNH:3\nLF:10\nLH:8\nend_of_record\nSF:/util.ts\nLF:10\nLH:10\nend_of_record\n",
+    )
+    .unwrap();
+    assert_eq!(
+      summary.lines,
+      crate::db::CoverageMetric {
+        total: 20,
+        covered: 18
+      }
+    );
+    assert_eq!(
+      summary.functions,
+      Some(crate::db::CoverageMetric {
+        total: 4,
+        covered: 3
+      })
+    );
+    assert_eq!(summary.branches, None);
+
+    let summary = super::parse_coverage_summary(
+      br#"{"total":{"lines":{"total":10,"covered":5,"skipped":0,"pct":50},"branches":{"total":2,"covered":1,"skipped":0,"pct":50}}}"#,
+    )
+    .unwrap();
+    assert_eq!(summary.lines.covered, 5);
+    assert_eq!(summary.branches.unwrap().total, 2);
+    assert_eq!(summary.functions, None);
+
+    assert!(super::parse_coverage_summary(b"SF:/mod.ts\n").is_err());
+    assert!(super::parse_coverage_summary(b"LF:1\nLH:2\n").is_err());
+    assert!(super::parse_coverage_summary(br#"{"lines":{}}"#).is_err());
+  }
+
   #[test]
   fn banned_triple_slash_directives() {
     let x = parse("let x = 1;");
@@ -3,8 +3,11 @@ use crate::NpmUrl;
 use crate::RegistryUrl;
 use crate::external::algolia::AlgoliaClient;
 use crate::s3::Buckets;
+use crate::tarball::bucket_tarball_path;
 use hyper::Body;
 use hyper::Request;
+use hyper::Response;
+use hyper::StatusCode;
 use routerify::Router;
 use routerify::prelude::RequestExt;
 use routerify_query::RequestQueryExt;
@@ -14,9 +17,22 @@ use tracing::field;
 use tracing::instrument;
 
 use crate::db::*;
+use crate::events;
+use crate::events::DomainEvent;
+use crate::feature_flags::FeatureFlags;
 use crate::iam::ReqIamExt;
+use crate::ids::PackageName;
 use crate::ids::ScopeDescription;
+use crate::ids::ScopeName;
+use crate::ids::Version;
+use crate::malware_scan::MalwareScanners;
+use crate::npm::NpmSigner;
+use crate::npm::upload_npm_version_manifest;
 use crate::publish::publish_task;
+use crate::publish::upload_package_manifest;
+use crate::quarantine;
+use crate::rate_limit::RateLimiter;
+use crate::tombstones::TombstoneRetention;
 use crate::util;
 use crate::util::ApiResult;
 use crate::util::LicenseStore;
@@ -29,6 +45,7 @@ use crate::util::sort;
 use super::ApiError;
 use super::PublishQueue;
 use super::map_unique_violation;
+use super::scope_renames;
 use super::types::*;
 
 pub fn admin_router() -> Router<Body, ApiError> {
@@ -47,9 +64,183 @@ pub fn admin_router() -> Router<Body, ApiError> {
       "/publishing_tasks/:publishing_task/requeue",
       util::auth(util::json(requeue_publishing_tasks)),
     )
+    .get(
+      "/publishing_tasks/:publishing_task/tarball",
+      util::auth(util::json(get_publishing_task_tarball)),
+    )
     .get("/tickets", util::auth(util::json(list_tickets)))
     .patch("/tickets/:id", util::auth(util::json(patch_ticket)))
     .get("/audit_logs", util::auth(util::json(list_audit_logs)))
+    .get("/score_schemas", util::auth(util::json(list_score_schemas)))
+    .post(
+      "/score_schemas",
+      util::auth(util::json(create_score_schema)),
+    )
+    .get(
+      "/score_recompute_jobs",
+      util::auth(util::json(list_score_recompute_jobs)),
+    )
+    .post(
+      "/score_recompute_jobs",
+      util::auth(util::json(create_score_recompute_job)),
+    )
+    .get(
+      "/score_recompute_jobs/:id",
+      util::auth(util::json(get_score_recompute_job)),
+    )
+    .get(
+      "/npm_tarball_rebuild_jobs",
+      util::auth(util::json(list_npm_tarball_rebuild_jobs)),
+    )
+    .post(
+      "/npm_tarball_rebuild_jobs",
+      util::auth(util::json(create_npm_tarball_rebuild_job)),
+    )
+    .get(
+      "/npm_tarball_rebuild_jobs/:id",
+      util::auth(util::json(get_npm_tarball_rebuild_job)),
+    )
+    .get(
+      "/orphan_gc_jobs",
+      util::auth(util::json(list_orphan_gc_jobs)),
+    )
+    .post(
+      "/orphan_gc_jobs",
+      util::auth(util::json(create_orphan_gc_job)),
+    )
+    .get(
+      "/orphan_gc_jobs/:id",
+      util::auth(util::json(get_orphan_gc_job)),
+    )
+    .get(
+      "/orphan_gc_jobs/:id/objects",
+      util::auth(util::json(list_orphaned_objects)),
+    )
+    .get(
+      "/integrity_scrub_jobs",
+      util::auth(util::json(list_integrity_scrub_jobs)),
+    )
+    .post(
+      "/integrity_scrub_jobs",
+      util::auth(util::json(create_integrity_scrub_job)),
+    )
+    .get(
+      "/integrity_scrub_jobs/:id",
+      util::auth(util::json(get_integrity_scrub_job)),
+    )
+    .get(
+      "/integrity_scrub_jobs/:id/failures",
+      util::auth(util::json(list_integrity_scrub_failures)),
+    )
+    .get(
+      "/database_pools",
+      util::auth(util::json(get_database_pools)),
+    )
+    .get(
+      "/metadata_cache",
+      util::auth(util::json(get_metadata_cache)),
+    )
+    .get("/jobs", util::auth(util::json(list_background_jobs)))
+    .post("/jobs", util::auth(util::json(create_background_job)))
+    .get("/jobs/:id", util::auth(util::json(get_background_job)))
+    .post(
+      "/jobs/:id/retry",
+      util::auth(util::json(retry_background_job)),
+    )
+    .get(
+      "/scheduled_jobs",
+      util::auth(util::json(list_scheduled_jobs)),
+    )
+    .post(
+      "/announcements",
+      util::auth(util::json(create_announcement)),
+    )
+    .put(
+      "/announcements/:id",
+      util::auth(util::json(update_announcement)),
+    )
+    .delete("/announcements/:id", util::auth(delete_announcement))
+    .get(
+      "/reserved_names",
+      util::auth(util::json(list_reserved_names)),
+    )
+    .post("/reserved_names", util::auth(util::json(reserve_name)))
+    .delete(
+      "/reserved_names/:kind/:name",
+      util::auth(delete_reserved_name),
+    )
+    .get("/rate_limits", util::auth(util::json(list_rate_limits)))
+    .put(
+      "/rate_limits/tiers/:tier",
+      util::auth(util::json(update_rate_limit_tier)),
+    )
+    .put(
+      "/rate_limits/scopes/:scope",
+      util::auth(util::json(update_scope_rate_limit)),
+    )
+    .delete(
+      "/rate_limits/scopes/:scope",
+      util::auth(delete_scope_rate_limit),
+    )
+    .put(
+      "/publish_limits/scopes/:scope",
+      util::auth(util::json(update_scope_publish_limits)),
+    )
+    .delete(
+      "/publish_limits/scopes/:scope",
+      util::auth(delete_scope_publish_limits),
+    )
+    .put(
+      "/rate_limits/tokens/:token_id",
+      util::auth(util::json(update_token_rate_limit)),
+    )
+    .delete(
+      "/rate_limits/tokens/:token_id",
+      util::auth(delete_token_rate_limit),
+    )
+    .get("/feature_flags", util::auth(util::json(list_feature_flags)))
+    .get(
+      "/feature_flags/:name",
+      util::auth(util::json(get_feature_flag)),
+    )
+    .put(
+      "/feature_flags/:name",
+      util::auth(util::json(update_feature_flag)),
+    )
+    .delete("/feature_flags/:name", util::auth(delete_feature_flag))
+    .get(
+      "/storage_metrics",
+      util::auth(util::json(get_storage_metrics)),
+    )
+    .get("/tombstones", util::auth(util::json(list_tombstones)))
+    .post(
+      "/packages/:scope/:package/restore",
+      util::auth(util::json(restore_package)),
+    )
+    .post(
+      "/packages/:scope/:package/versions/:version/restore",
+      util::auth(util::json(restore_package_version)),
+    )
+    .get("/reports", util::auth(util::json(list_abuse_reports)))
+    .get("/reports/:id", util::auth(util::json(get_abuse_report)))
+    .patch("/reports/:id", util::auth(util::json(patch_abuse_report)))
+    .get("/quarantines", util::auth(util::json(list_quarantines)))
+    .post(
+      "/packages/:scope/:package/versions/:version/quarantine",
+      util::auth(util::json(quarantine_package_version)),
+    )
+    .delete(
+      "/packages/:scope/:package/versions/:version/quarantine",
+      util::auth(release_package_version),
+    )
+    .get(
+      "/scope_renames",
+      util::auth(util::json(scope_renames::list_handler)),
+    )
+    .post(
+      "/scope_renames/:scope/approve",
+      util::auth(util::json(scope_renames::approve_handler)),
+    )
     .build()
     .unwrap()
 }
@@ -146,6 +337,7 @@ pub async fn patch_scopes(mut req: Request<Body>) -> ApiResult<ApiFullScope> {
     package_limit,
     new_package_per_week_limit,
     publish_attempts_per_week_limit,
+    storage_limit,
   } = decode_json(&mut req).await?;
 
   let iam = req.iam();
@@ -156,9 +348,10 @@ pub async fn patch_scopes(mut req: Request<Body>) -> ApiResult<ApiFullScope> {
   if package_limit.is_none()
     && new_package_per_week_limit.is_none()
     && publish_attempts_per_week_limit.is_none()
+    && storage_limit.is_none()
   {
     return Err(ApiError::MalformedRequest {
-      msg: "missing 'packageLimit', 'newPackagePerWeekLimit' or 'publishAttemptsPerWeekLimit' parameter".into(),
+      msg: "missing 'packageLimit', 'newPackagePerWeekLimit', 'publishAttemptsPerWeekLimit' or 'storageLimit' parameter".into(),
     });
   }
 
@@ -169,6 +362,7 @@ pub async fn patch_scopes(mut req: Request<Body>) -> ApiResult<ApiFullScope> {
       package_limit,
       new_package_per_week_limit,
       publish_attempts_per_week_limit,
+      storage_limit,
     )
     .await?;
 
@@ -225,7 +419,14 @@ pub async fn list_packages(
   let maybe_sort = sort(&req);
 
   let (total, packages) = db
-    .list_packages(start, limit, maybe_search, maybe_github_id, maybe_sort)
+    .list_packages(
+      start,
+      limit,
+      maybe_search,
+      maybe_github_id,
+      maybe_sort,
+      &PackageSearchFilters::default(),
+    )
     .await?;
   Ok(ApiList {
     items: packages.into_iter().map(|package| package.into()).collect(),
@@ -258,6 +459,53 @@ pub async fn list_publishing_tasks(
   })
 }
 
+/// How long the signed URL of a raw publish upload stays valid.
+const PUBLISHING_TASK_TARBALL_URL_EXPIRY: std::time::Duration =
+  std::time::Duration::from_secs(15 * 60);
+
+/// Returns a signed URL to download the tarball that was uploaded for a
+/// publishing task, as it was uploaded, to debug failed publishes.
+#[instrument(
+  name = "GET /api/admin/publishing_tasks/:publishing_task/tarball",
+  skip(req),
+  fields(publishing_task)
+)]
+pub async fn get_publishing_task_tarball(
+  req: Request<Body>,
+) -> ApiResult<ApiSignedUrl> {
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let publishing_task_id = req.param_uuid("publishing_task")?;
+  Span::current()
+    .record("publishing_task", field::display(&publishing_task_id));
+
+  let db = req.data::<Database>().unwrap();
+  let (task, _) = db
+    .get_publishing_task(publishing_task_id)
+    .await?
+    .ok_or(ApiError::PublishNotFound)?;
+
+  let buckets = req.data::<Buckets>().unwrap();
+  let filename = format!(
+    "{}-{}-{}.tgz",
+    task.package_scope, task.package_name, task.package_version
+  );
+  let expires_at = chrono::Utc::now()
+    + chrono::Duration::from_std(PUBLISHING_TASK_TARBALL_URL_EXPIRY).unwrap();
+  let url = buckets
+    .publishing_bucket
+    .bucket
+    .signed_url(
+      &bucket_tarball_path(task.id),
+      PUBLISHING_TASK_TARBALL_URL_EXPIRY,
+      Some(&filename),
+    )
+    .await?;
+
+  Ok(ApiSignedUrl { url, expires_at })
+}
+
 #[instrument(
   name = "POST /api/admin/publishing_tasks/:publishing_task/requeue",
   skip(req),
@@ -297,23 +545,29 @@ pub async fn requeue_publishing_tasks(req: Request<Body>) -> ApiResult<()> {
   } else {
     let buckets = req.data::<Buckets>().unwrap().clone();
     let license_store = req.data::<LicenseStore>().unwrap().clone();
+    let malware_scanners = req.data::<MalwareScanners>().unwrap().clone();
     let registry = req.data::<RegistryUrl>().unwrap().0.clone();
     let npm_url = req.data::<NpmUrl>().unwrap().0.clone();
+    let npm_signer = req.data::<NpmSigner>().unwrap().clone();
     let cache_purge = req
-      .data::<crate::external::cloudflare::CachePurge>()
+      .data::<crate::cache_purge::CachePurge>()
       .unwrap()
       .clone();
+    let feature_flags = req.data::<FeatureFlags>().unwrap().clone();
 
     let span = Span::current();
     let fut = publish_task(
       publishing_task_id,
       buckets,
       license_store,
+      malware_scanners,
       registry,
       npm_url,
+      npm_signer,
       db,
       algolia_client,
       cache_purge,
+      feature_flags,
     )
     .instrument(span);
     tokio::spawn(fut);
@@ -389,127 +643,3686 @@ pub async fn list_audit_logs(
   })
 }
 
-#[cfg(test)]
-mod tests {
-  use crate::api::ApiFullScope;
-  use crate::api::ApiFullUser;
-  use crate::api::ApiList;
-  use crate::api::ApiScope;
-  use crate::util::test::ApiResultExt;
-  use crate::util::test::TestSetup;
-  use hyper::StatusCode;
-  use serde_json::json;
+#[instrument(name = "GET /api/admin/score_schemas", skip(req))]
+pub async fn list_score_schemas(
+  req: Request<Body>,
+) -> ApiResult<Vec<ApiScoreSchema>> {
+  let iam = req.iam();
+  iam.check_admin_access()?;
 
-  #[tokio::test]
-  async fn list_users() {
-    let mut t = TestSetup::new().await;
+  let db = req.data::<Database>().unwrap();
+  let schemas = db.list_score_schemas().await?;
 
-    let token = t.staff_user.token.clone();
-    let users = t
-      .http()
-      .get("/api/admin/users")
-      .token(Some(&token))
-      .call()
-      .await
-      .unwrap()
-      .expect_ok::<ApiList<ApiFullUser>>()
-      .await;
-    assert_eq!(users.items.len(), 5);
+  Ok(schemas.into_iter().map(|schema| schema.into()).collect())
+}
 
-    let path = format!("/api/admin/users?query={}", t.user2.user.id);
-    let users = t
-      .http()
-      .get(path)
-      .token(Some(&token))
-      .call()
-      .await
-      .unwrap()
-      .expect_ok::<ApiList<ApiFullUser>>()
-      .await;
-    assert_eq!(users.items.len(), 1);
-    assert_eq!(users.items[0].id, t.user2.user.id);
+/// Creates a new score schema. It becomes active on all instances within the
+/// refresh interval of [crate::score].
+#[instrument(name = "POST /api/admin/score_schemas", skip(req))]
+pub async fn create_score_schema(
+  mut req: Request<Body>,
+) -> ApiResult<ApiScoreSchema> {
+  let ApiAdminCreateScoreSchemaRequest { weights, max_score } =
+    decode_json(&mut req).await?;
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  if max_score <= 0 {
+    return Err(ApiError::MalformedRequest {
+      msg: "'maxScore' must be greater than 0".into(),
+    });
   }
 
-  #[tokio::test]
-  async fn scope_management() {
-    let mut t = TestSetup::new().await;
+  let db = req.data::<Database>().unwrap();
+  let schema = db
+    .create_score_schema(&staff.id, &weights, max_score)
+    .await?;
 
-    assert_eq!(t.scope.package_limit, 250);
-    assert_eq!(t.scope.new_package_per_week_limit, 200);
-    assert_eq!(t.scope.publish_attempts_per_week_limit, 1000);
+  Ok(schema.into())
+}
 
-    let path = format!("/api/admin/scopes/{}", t.scope.scope);
-    let token = t.staff_user.token.clone();
-    let res_scope = t
-      .http()
-      .patch(path)
-      .body_json(json!({
-        "packageLimit": 101,
-        "newPackagePerWeekLimit": 101,
-        "publishAttemptsPerWeekLimit": 101,
-      }))
-      .token(Some(&token))
-      .call()
-      .await
-      .unwrap()
-      .expect_ok::<ApiFullScope>()
-      .await;
-    assert_eq!(res_scope.quotas.package_limit, 101);
-    assert_eq!(res_scope.quotas.new_package_per_week_limit, 101);
-    assert_eq!(res_scope.quotas.publish_attempts_per_week_limit, 101);
-  }
+#[instrument(name = "GET /api/admin/score_recompute_jobs", skip(req))]
+pub async fn list_score_recompute_jobs(
+  req: Request<Body>,
+) -> ApiResult<Vec<ApiScoreRecomputeJob>> {
+  let iam = req.iam();
+  iam.check_admin_access()?;
 
-  #[tokio::test]
-  async fn assign_scope() {
-    let mut t = TestSetup::new().await;
+  let db = req.data::<Database>().unwrap();
+  let jobs = db.list_score_recompute_jobs().await?;
 
-    // create a scope for a user2
-    let path = "/api/admin/scopes";
-    let token = t.staff_user.token.clone();
-    let user2_id = t.user2.user.id;
-    let scope = t
-      .http()
-      .post(path)
-      .body_json(json!({
-        "scope": "test-scope",
-        "userId": user2_id,
-      }))
-      .token(Some(&token))
-      .call()
-      .await
-      .unwrap()
-      .expect_ok::<ApiScope>()
-      .await;
-    assert_eq!(scope.scope.to_string(), "test-scope");
+  Ok(jobs.into_iter().map(|job| job.into()).collect())
+}
 
-    // create a scope with a reserved name
-    let res = t
-      .http()
-      .post(path)
-      .body_json(json!({
-        "scope": "react",
-        "userId": user2_id,
-      }))
-      .token(Some(&token))
-      .call()
-      .await
-      .unwrap()
-      .expect_ok::<ApiScope>()
-      .await;
-    assert_eq!(res.scope.to_string(), "react");
+/// Starts re-running score generation for every package version in the
+/// background. Poll `GET /api/admin/score_recompute_jobs/:id` for progress.
+#[instrument(name = "POST /api/admin/score_recompute_jobs", skip(req))]
+pub async fn create_score_recompute_job(
+  req: Request<Body>,
+) -> ApiResult<ApiScoreRecomputeJob> {
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
 
-    // create a scope with an existing name
-    t.http()
-      .post(path)
-      .body_json(json!({
-        "scope": "test-scope",
-        "userId": user2_id,
-      }))
-      .token(Some(&token))
-      .call()
-      .await
-      .unwrap()
-      .expect_err_code(StatusCode::CONFLICT, "scopeAlreadyExists")
+  let db = req.data::<Database>().unwrap().clone();
+  let buckets = req.data::<Buckets>().unwrap().clone();
+
+  let job = db
+    .create_score_recompute_job(
+      Some(&staff.id),
+      chrono::Duration::seconds(crate::background_jobs::ADMIN_JOB_LEASE_SECS),
+    )
+    .await?
+    .ok_or(ApiError::ScoreRecomputeJobAlreadyRunning)?;
+
+  let span = Span::current();
+  let fut =
+    crate::score::recompute_scores(db, buckets, job.id).instrument(span);
+  tokio::spawn(fut);
+
+  Ok(job.into())
+}
+
+#[instrument(name = "GET /api/admin/score_recompute_jobs/:id", skip(req))]
+pub async fn get_score_recompute_job(
+  req: Request<Body>,
+) -> ApiResult<ApiScoreRecomputeJob> {
+  let id = req.param_uuid("id")?;
+  Span::current().record("id", field::display(id));
+
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let job = db
+    .get_score_recompute_job(id)
+    .await?
+    .ok_or(ApiError::ScoreRecomputeJobNotFound)?;
+
+  Ok(job.into())
+}
+
+/// Number of versions an npm tarball rebuild job builds at the same time,
+/// unless the request asks for a different number.
+const DEFAULT_NPM_TARBALL_REBUILD_CONCURRENCY: i32 = 8;
+const MAX_NPM_TARBALL_REBUILD_CONCURRENCY: i32 = 32;
+
+#[instrument(name = "GET /api/admin/npm_tarball_rebuild_jobs", skip(req))]
+pub async fn list_npm_tarball_rebuild_jobs(
+  req: Request<Body>,
+) -> ApiResult<Vec<ApiNpmTarballRebuildJob>> {
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let jobs = db.list_npm_tarball_rebuild_jobs().await?;
+
+  Ok(jobs.into_iter().map(|job| job.into()).collect())
+}
+
+/// Starts building the npm tarballs for the current revision for all versions
+/// of a package, a scope, or the whole registry in the background. Poll
+/// `GET /api/admin/npm_tarball_rebuild_jobs/:id` for progress.
+#[instrument(name = "POST /api/admin/npm_tarball_rebuild_jobs", skip(req))]
+pub async fn create_npm_tarball_rebuild_job(
+  mut req: Request<Body>,
+) -> ApiResult<ApiNpmTarballRebuildJob> {
+  let ApiAdminCreateNpmTarballRebuildJobRequest {
+    scope,
+    package,
+    verify,
+    concurrency,
+  } = decode_json(&mut req).await?;
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  if package.is_some() && scope.is_none() {
+    return Err(ApiError::MalformedRequest {
+      msg: "'scope' is required when 'package' is set".into(),
+    });
+  }
+  let concurrency =
+    concurrency.unwrap_or(DEFAULT_NPM_TARBALL_REBUILD_CONCURRENCY);
+  if !(1..=MAX_NPM_TARBALL_REBUILD_CONCURRENCY).contains(&concurrency) {
+    return Err(ApiError::MalformedRequest {
+      msg: format!(
+        "'concurrency' must be between 1 and {MAX_NPM_TARBALL_REBUILD_CONCURRENCY}"
+      )
+      .into(),
+    });
+  }
+
+  let db = req.data::<Database>().unwrap().clone();
+  if let Some(scope) = &scope {
+    db.get_scope(scope).await?.ok_or(ApiError::ScopeNotFound)?;
+    if let Some(package) = &package {
+      db.get_package(scope, package)
+        .await?
+        .ok_or(ApiError::PackageNotFound)?;
+    }
+  }
+
+  let job = db
+    .create_npm_tarball_rebuild_job(
+      &staff.id,
+      scope.as_ref(),
+      package.as_ref(),
+      verify,
+      concurrency,
+      chrono::Duration::seconds(crate::background_jobs::ADMIN_JOB_LEASE_SECS),
+    )
+    .await?
+    .ok_or(ApiError::NpmTarballRebuildJobAlreadyRunning)?;
+
+  let buckets = req.data::<Buckets>().unwrap().clone();
+  let registry_url = req.data::<RegistryUrl>().unwrap().0.clone();
+  let npm_url = req.data::<NpmUrl>().unwrap().0.clone();
+  let npm_signer = req.data::<NpmSigner>().unwrap().clone();
+  let cache_purge = req
+    .data::<crate::cache_purge::CachePurge>()
+    .unwrap()
+    .clone();
+
+  let span = Span::current();
+  let fut = crate::npm::rebuild_npm_tarballs(
+    db,
+    buckets,
+    registry_url,
+    npm_url,
+    npm_signer,
+    cache_purge,
+    job.clone(),
+  )
+  .instrument(span);
+  tokio::spawn(fut);
+
+  Ok(job.into())
+}
+
+#[instrument(name = "GET /api/admin/npm_tarball_rebuild_jobs/:id", skip(req))]
+pub async fn get_npm_tarball_rebuild_job(
+  req: Request<Body>,
+) -> ApiResult<ApiNpmTarballRebuildJob> {
+  let id = req.param_uuid("id")?;
+  Span::current().record("id", field::display(id));
+
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let job = db
+    .get_npm_tarball_rebuild_job(id)
+    .await?
+    .ok_or(ApiError::NpmTarballRebuildJobNotFound)?;
+
+  Ok(job.into())
+}
+
+#[instrument(name = "GET /api/admin/orphan_gc_jobs", skip(req))]
+pub async fn list_orphan_gc_jobs(
+  req: Request<Body>,
+) -> ApiResult<Vec<ApiOrphanGcJob>> {
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let jobs = db.list_orphan_gc_jobs().await?;
+
+  Ok(jobs.into_iter().map(|job| job.into()).collect())
+}
+
+/// Starts looking for orphaned objects in the buckets in the background, and
+/// deletes them unless `dryRun` is set. Poll
+/// `GET /api/admin/orphan_gc_jobs/:id` for progress.
+#[instrument(name = "POST /api/admin/orphan_gc_jobs", skip(req))]
+pub async fn create_orphan_gc_job(
+  mut req: Request<Body>,
+) -> ApiResult<ApiOrphanGcJob> {
+  let ApiAdminCreateOrphanGcJobRequest { dry_run } =
+    decode_json(&mut req).await?;
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap().clone();
+  let job = db
+    .create_orphan_gc_job(Some(&staff.id), dry_run)
+    .await?
+    .ok_or(ApiError::OrphanGcJobAlreadyRunning)?;
+
+  let buckets = req.data::<Buckets>().unwrap().clone();
+  let span = Span::current();
+  let fut = crate::orphan_gc::gc_orphaned_objects(db, buckets, job.clone())
+    .instrument(span);
+  tokio::spawn(fut);
+
+  Ok(job.into())
+}
+
+#[instrument(name = "GET /api/admin/orphan_gc_jobs/:id", skip(req), fields(id))]
+pub async fn get_orphan_gc_job(
+  req: Request<Body>,
+) -> ApiResult<ApiOrphanGcJob> {
+  let id = req.param_uuid("id")?;
+  Span::current().record("id", field::display(id));
+
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let job = db
+    .get_orphan_gc_job(id)
+    .await?
+    .ok_or(ApiError::OrphanGcJobNotFound)?;
+
+  Ok(job.into())
+}
+
+/// The orphaned objects that a job found, and whether they were deleted.
+#[instrument(
+  name = "GET /api/admin/orphan_gc_jobs/:id/objects",
+  skip(req),
+  fields(id)
+)]
+pub async fn list_orphaned_objects(
+  req: Request<Body>,
+) -> ApiResult<ApiList<ApiOrphanedObject>> {
+  let id = req.param_uuid("id")?;
+  Span::current().record("id", field::display(id));
+
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  db.get_orphan_gc_job(id)
+    .await?
+    .ok_or(ApiError::OrphanGcJobNotFound)?;
+
+  let (start, limit) = pagination(&req);
+  let (total, objects) = db.list_orphaned_objects(id, start, limit).await?;
+  Ok(ApiList {
+    items: objects.into_iter().map(|object| object.into()).collect(),
+    total,
+  })
+}
+
+#[instrument(name = "GET /api/admin/integrity_scrub_jobs", skip(req))]
+pub async fn list_integrity_scrub_jobs(
+  req: Request<Body>,
+) -> ApiResult<Vec<ApiIntegrityScrubJob>> {
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let jobs = db.list_integrity_scrub_jobs().await?;
+
+  Ok(jobs.into_iter().map(|job| job.into()).collect())
+}
+
+/// Starts checking the stored artifacts of all versions of a package, a scope,
+/// or the whole registry against their recorded digests in the background.
+/// Poll `GET /api/admin/integrity_scrub_jobs/:id` for progress.
+#[instrument(name = "POST /api/admin/integrity_scrub_jobs", skip(req))]
+pub async fn create_integrity_scrub_job(
+  mut req: Request<Body>,
+) -> ApiResult<ApiIntegrityScrubJob> {
+  let ApiAdminCreateIntegrityScrubJobRequest { scope, package } =
+    decode_json(&mut req).await?;
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  if package.is_some() && scope.is_none() {
+    return Err(ApiError::MalformedRequest {
+      msg: "'scope' is required when 'package' is set".into(),
+    });
+  }
+
+  let db = req.data::<Database>().unwrap().clone();
+  if let Some(scope) = &scope {
+    db.get_scope(scope).await?.ok_or(ApiError::ScopeNotFound)?;
+    if let Some(package) = &package {
+      db.get_package(scope, package)
+        .await?
+        .ok_or(ApiError::PackageNotFound)?;
+    }
+  }
+
+  let job = db
+    .create_integrity_scrub_job(&staff.id, scope.as_ref(), package.as_ref())
+    .await?
+    .ok_or(ApiError::IntegrityScrubJobAlreadyRunning)?;
+
+  let buckets = req.data::<Buckets>().unwrap().clone();
+  let span = Span::current();
+  let fut = crate::integrity::scrub_integrity(db, buckets, job.clone())
+    .instrument(span);
+  tokio::spawn(fut);
+
+  Ok(job.into())
+}
+
+#[instrument(
+  name = "GET /api/admin/integrity_scrub_jobs/:id",
+  skip(req),
+  fields(id)
+)]
+pub async fn get_integrity_scrub_job(
+  req: Request<Body>,
+) -> ApiResult<ApiIntegrityScrubJob> {
+  let id = req.param_uuid("id")?;
+  Span::current().record("id", field::display(id));
+
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let job = db
+    .get_integrity_scrub_job(id)
+    .await?
+    .ok_or(ApiError::IntegrityScrubJobNotFound)?;
+
+  Ok(job.into())
+}
+
+/// The objects that a job found to be missing or not to match their digest.
+#[instrument(
+  name = "GET /api/admin/integrity_scrub_jobs/:id/failures",
+  skip(req),
+  fields(id)
+)]
+pub async fn list_integrity_scrub_failures(
+  req: Request<Body>,
+) -> ApiResult<ApiList<ApiIntegrityScrubFailure>> {
+  let id = req.param_uuid("id")?;
+  Span::current().record("id", field::display(id));
+
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  db.get_integrity_scrub_job(id)
+    .await?
+    .ok_or(ApiError::IntegrityScrubJobNotFound)?;
+
+  let (start, limit) = pagination(&req);
+  let (total, failures) =
+    db.list_integrity_scrub_failures(id, start, limit).await?;
+  Ok(ApiList {
+    items: failures.into_iter().map(|failure| failure.into()).collect(),
+    total,
+  })
+}
+
+/// The health of the connection pools of the primary database and its read
+/// replica on the instance that serves the request.
+#[instrument(name = "GET /api/admin/database_pools", skip(req))]
+pub async fn get_database_pools(
+  req: Request<Body>,
+) -> ApiResult<ApiDatabasePools> {
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let (primary, replica) = db.pool_health();
+  Ok(ApiDatabasePools {
+    primary: primary.into(),
+    replica: replica.map(Into::into),
+    sticky_to_primary: db.is_sticky_to_primary(),
+  })
+}
+
+/// The hits and misses of the metadata cache on the instance that serves the
+/// request.
+#[instrument(name = "GET /api/admin/metadata_cache", skip(req))]
+pub async fn get_metadata_cache(
+  req: Request<Body>,
+) -> ApiResult<ApiMetadataCache> {
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  Ok(db.metadata_cache_stats().into())
+}
+
+/// The jobs run by the scheduler built into the API, with their last run.
+#[instrument(name = "GET /api/admin/scheduled_jobs", skip(req))]
+pub async fn list_scheduled_jobs(
+  req: Request<Body>,
+) -> ApiResult<Vec<ApiScheduledJob>> {
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let jobs = db.list_scheduled_jobs().await?;
+
+  Ok(jobs.into_iter().map(|job| job.into()).collect())
+}
+
+/// Background jobs, newest first. Filter with the `status` and `kind` query
+/// parameters.
+#[instrument(name = "GET /api/admin/jobs", skip(req), fields(status, kind))]
+pub async fn list_background_jobs(
+  req: Request<Body>,
+) -> ApiResult<ApiList<ApiBackgroundJob>> {
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let status = match req.query("status").map(|status| status.as_str()) {
+    None => None,
+    Some("pending") => Some(BackgroundJobStatus::Pending),
+    Some("running") => Some(BackgroundJobStatus::Running),
+    Some("completed") => Some(BackgroundJobStatus::Completed),
+    Some("dead") => Some(BackgroundJobStatus::Dead),
+    Some(_) => {
+      return Err(ApiError::MalformedRequest {
+        msg: "invalid 'status' query parameter, expected one of: pending, \
+           running, completed, dead"
+          .into(),
+      });
+    }
+  };
+  Span::current().record("status", field::debug(&status));
+  let kind = match req.query("kind").map(|kind| kind.as_str()) {
+    None => None,
+    Some("npmTarballBuild") => Some(BackgroundJobKind::NpmTarballBuild),
+    Some("scoreRecompute") => Some(BackgroundJobKind::ScoreRecompute),
+    Some("dataExport") => Some(BackgroundJobKind::DataExport),
+    Some("publishFailureEmail") => Some(BackgroundJobKind::PublishFailureEmail),
+    Some(_) => {
+      return Err(ApiError::MalformedRequest {
+        msg: "invalid 'kind' query parameter, expected one of: \
+           npmTarballBuild, scoreRecompute, dataExport, publishFailureEmail"
+          .into(),
+      });
+    }
+  };
+  Span::current().record("kind", field::debug(&kind));
+
+  let db = req.data::<Database>().unwrap();
+  let (start, limit) = pagination(&req);
+  let (total, jobs) =
+    db.list_background_jobs(status, kind, start, limit).await?;
+  Ok(ApiList {
+    items: jobs.into_iter().map(|job| job.into()).collect(),
+    total,
+  })
+}
+
+/// Queues a background job for a version. It is run by the next run of the
+/// `run_jobs` task.
+#[instrument(name = "POST /api/admin/jobs", skip(req))]
+pub async fn create_background_job(
+  mut req: Request<Body>,
+) -> ApiResult<ApiBackgroundJob> {
+  let ApiAdminCreateBackgroundJobRequest {
+    kind,
+    scope,
+    package,
+    version,
+    priority,
+    max_attempts,
+  } = decode_json(&mut req).await?;
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  // Data exports are requested by users themselves.
+  if kind == BackgroundJobKind::DataExport {
+    return Err(ApiError::MalformedRequest {
+      msg: "'kind' must be a job for a package version".into(),
+    });
+  }
+
+  let max_attempts =
+    max_attempts.unwrap_or(crate::background_jobs::DEFAULT_MAX_ATTEMPTS);
+  if !(1..=crate::background_jobs::MAX_MAX_ATTEMPTS).contains(&max_attempts) {
+    return Err(ApiError::MalformedRequest {
+      msg: format!(
+        "'maxAttempts' must be between 1 and {}",
+        crate::background_jobs::MAX_MAX_ATTEMPTS
+      )
+      .into(),
+    });
+  }
+
+  let db = req.data::<Database>().unwrap();
+  db.get_package_version(&scope, &package, &version)
+    .await?
+    .ok_or(ApiError::PackageVersionNotFound)?;
+
+  let payload = crate::background_jobs::VersionJobPayload {
+    scope,
+    package,
+    version,
+  };
+  let job = db
+    .enqueue_background_job(NewBackgroundJob {
+      kind,
+      payload: serde_json::to_value(payload).unwrap(),
+      priority,
+      max_attempts,
+      created_by: Some(&staff.id),
+    })
+    .await?;
+
+  Ok(job.into())
+}
+
+#[instrument(name = "GET /api/admin/jobs/:id", skip(req), fields(id))]
+pub async fn get_background_job(
+  req: Request<Body>,
+) -> ApiResult<ApiBackgroundJob> {
+  let id = req.param_uuid("id")?;
+  Span::current().record("id", field::display(id));
+
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let job = db
+    .get_background_job(id)
+    .await?
+    .ok_or(ApiError::BackgroundJobNotFound)?;
+
+  Ok(job.into())
+}
+
+/// Requeues a dead job with a fresh set of attempts.
+#[instrument(name = "POST /api/admin/jobs/:id/retry", skip(req), fields(id))]
+pub async fn retry_background_job(
+  req: Request<Body>,
+) -> ApiResult<ApiBackgroundJob> {
+  let id = req.param_uuid("id")?;
+  Span::current().record("id", field::display(id));
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  match db.retry_background_job(&staff.id, id).await? {
+    Some(job) => Ok(job.into()),
+    None => {
+      db.get_background_job(id)
+        .await?
+        .ok_or(ApiError::BackgroundJobNotFound)?;
+      Err(ApiError::BackgroundJobNotDead)
+    }
+  }
+}
+
+const MAX_ANNOUNCEMENT_TITLE_LENGTH: usize = 200;
+const MAX_ANNOUNCEMENT_BODY_LENGTH: usize = 10_000;
+const MAX_ANNOUNCEMENT_FEATURES: usize = 10;
+const MAX_ANNOUNCEMENT_FEATURE_LENGTH: usize = 32;
+
+fn validate_announcement(
+  request: &ApiAdminAnnouncementRequest,
+) -> Result<(), ApiError> {
+  if request.title.trim().is_empty() {
+    return Err(ApiError::MalformedRequest {
+      msg: "'title' must not be empty".into(),
+    });
+  }
+  if request.title.len() > MAX_ANNOUNCEMENT_TITLE_LENGTH {
+    return Err(ApiError::MalformedRequest {
+      msg: format!(
+        "'title' must be at most {MAX_ANNOUNCEMENT_TITLE_LENGTH} characters"
+      )
+      .into(),
+    });
+  }
+  if request.body.len() > MAX_ANNOUNCEMENT_BODY_LENGTH {
+    return Err(ApiError::MalformedRequest {
+      msg: format!(
+        "'body' must be at most {MAX_ANNOUNCEMENT_BODY_LENGTH} characters"
+      )
+      .into(),
+    });
+  }
+  if request.features.len() > MAX_ANNOUNCEMENT_FEATURES {
+    return Err(ApiError::MalformedRequest {
+      msg: format!(
+        "'features' must have at most {MAX_ANNOUNCEMENT_FEATURES} entries"
+      )
+      .into(),
+    });
+  }
+  for feature in &request.features {
+    let is_valid = !feature.is_empty()
+      && feature.len() <= MAX_ANNOUNCEMENT_FEATURE_LENGTH
+      && !feature.starts_with('-')
+      && feature
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if !is_valid {
+      return Err(ApiError::MalformedRequest {
+        msg: format!("'{feature}' is not a valid feature, features must be lowercase kebab-case").into(),
+      });
+    }
+  }
+  Ok(())
+}
+
+/// Publishes a new announcement. It shows up in `GET /api/announcements` and
+/// the announcements feed immediately.
+#[instrument(name = "POST /api/admin/announcements", skip(req))]
+pub async fn create_announcement(
+  mut req: Request<Body>,
+) -> ApiResult<ApiAnnouncement> {
+  let request: ApiAdminAnnouncementRequest = decode_json(&mut req).await?;
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  validate_announcement(&request)?;
+
+  let db = req.data::<Database>().unwrap();
+  let announcement = db
+    .create_announcement(
+      &staff.id,
+      NewAnnouncement {
+        title: &request.title,
+        body: &request.body,
+        severity: request.severity,
+        features: &request.features,
+        expires_at: request.expires_at,
+      },
+    )
+    .await?;
+
+  Ok(announcement.into())
+}
+
+/// Replaces an announcement, e.g. to post an update on an incident or to
+/// expire it once resolved.
+#[instrument(name = "PUT /api/admin/announcements/:id", skip(req), fields(id))]
+pub async fn update_announcement(
+  mut req: Request<Body>,
+) -> ApiResult<ApiAnnouncement> {
+  let id = req.param_uuid("id")?;
+  Span::current().record("id", field::display(id));
+  let request: ApiAdminAnnouncementRequest = decode_json(&mut req).await?;
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  validate_announcement(&request)?;
+
+  let db = req.data::<Database>().unwrap();
+  let announcement = db
+    .update_announcement(
+      &staff.id,
+      id,
+      NewAnnouncement {
+        title: &request.title,
+        body: &request.body,
+        severity: request.severity,
+        features: &request.features,
+        expires_at: request.expires_at,
+      },
+    )
+    .await?
+    .ok_or(ApiError::AnnouncementNotFound)?;
+
+  Ok(announcement.into())
+}
+
+#[instrument(
+  name = "DELETE /api/admin/announcements/:id",
+  skip(req),
+  fields(id)
+)]
+pub async fn delete_announcement(
+  req: Request<Body>,
+) -> ApiResult<Response<Body>> {
+  let id = req.param_uuid("id")?;
+  Span::current().record("id", field::display(id));
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  db.delete_announcement(&staff.id, id)
+    .await?
+    .ok_or(ApiError::AnnouncementNotFound)?;
+
+  Ok(
+    Response::builder()
+      .status(StatusCode::NO_CONTENT)
+      .body(Body::empty())
+      .unwrap(),
+  )
+}
+
+fn parse_reserved_name_kind(
+  name: &str,
+  value: &str,
+) -> Result<ReservedNameKind, ApiError> {
+  match value {
+    "scope" => Ok(ReservedNameKind::Scope),
+    "package" => Ok(ReservedNameKind::Package),
+    _ => Err(ApiError::MalformedRequest {
+      msg: format!("'{name}' must be either 'scope' or 'package'").into(),
+    }),
+  }
+}
+
+/// Validates a name to reserve, and brings it into the form it is compared in
+/// when a scope or package is created.
+fn normalize_reserved_name(
+  kind: ReservedNameKind,
+  name: &str,
+) -> Result<String, ApiError> {
+  match kind {
+    ReservedNameKind::Scope => ScopeName::try_from(name)
+      .map(|scope| scope.replace('-', ""))
+      .map_err(|err| ApiError::MalformedRequest {
+        msg: format!("invalid scope name: {err}").into(),
+      }),
+    ReservedNameKind::Package => PackageName::try_from(name)
+      .map(|package| package.to_string())
+      .map_err(|err| ApiError::MalformedRequest {
+        msg: format!("invalid package name: {err}").into(),
+      }),
+  }
+}
+
+#[instrument(name = "GET /api/admin/reserved_names", skip(req))]
+pub async fn list_reserved_names(
+  req: Request<Body>,
+) -> ApiResult<ApiList<ApiReservedName>> {
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let (start, limit) = pagination(&req);
+  let maybe_search = search(&req);
+  let maybe_kind = req
+    .query("kind")
+    .map(|kind| parse_reserved_name_kind("kind", kind))
+    .transpose()?;
+
+  let (total, reserved_names) = db
+    .list_reserved_names(start, limit, maybe_kind, maybe_search)
+    .await?;
+  Ok(ApiList {
+    items: reserved_names.into_iter().map(|name| name.into()).collect(),
+    total,
+  })
+}
+
+/// Reserves a scope or package name, so that it can not be claimed through the
+/// API until the reservation expires or is removed. Reserving an already
+/// reserved name replaces its reason and expiry.
+#[instrument(name = "POST /api/admin/reserved_names", skip(req))]
+pub async fn reserve_name(
+  mut req: Request<Body>,
+) -> ApiResult<ApiReservedName> {
+  let request: ApiAdminReserveNameRequest = decode_json(&mut req).await?;
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  let name = normalize_reserved_name(request.kind, &request.name)?;
+
+  let db = req.data::<Database>().unwrap();
+  let reserved_name = db
+    .upsert_reserved_name(
+      &staff.id,
+      NewReservedName {
+        kind: request.kind,
+        name: &name,
+        reason: request.reason.as_deref(),
+        expires_at: request.expires_at,
+      },
+    )
+    .await?;
+
+  Ok(reserved_name.into())
+}
+
+#[instrument(
+  name = "DELETE /api/admin/reserved_names/:kind/:name",
+  skip(req),
+  fields(kind, name)
+)]
+pub async fn delete_reserved_name(
+  req: Request<Body>,
+) -> ApiResult<Response<Body>> {
+  let kind = parse_reserved_name_kind("kind", util::param(&req, "kind")?)?;
+  let name = normalize_reserved_name(kind, util::param(&req, "name")?)?;
+  Span::current().record("kind", field::debug(kind));
+  Span::current().record("name", field::display(&name));
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  db.delete_reserved_name(&staff.id, kind, &name)
+    .await?
+    .ok_or(ApiError::ReservedNameNotFound)?;
+
+  Ok(
+    Response::builder()
+      .status(StatusCode::NO_CONTENT)
+      .body(Body::empty())
+      .unwrap(),
+  )
+}
+
+fn validate_rate_limit(
+  request: &ApiAdminUpdateRateLimitRequest,
+) -> Result<i32, ApiError> {
+  match i32::try_from(request.requests_per_minute) {
+    Ok(requests_per_minute) if requests_per_minute > 0 => {
+      Ok(requests_per_minute)
+    }
+    _ => Err(ApiError::MalformedRequest {
+      msg: "'requestsPerMinute' must be a positive 32-bit integer".into(),
+    }),
+  }
+}
+
+#[instrument(name = "GET /api/admin/rate_limits", skip(req))]
+pub async fn list_rate_limits(req: Request<Body>) -> ApiResult<ApiRateLimits> {
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let (tiers, scopes, tokens) = db.list_rate_limits().await?;
+
+  Ok(ApiRateLimits {
+    tiers: tiers.into_iter().map(|tier| tier.into()).collect(),
+    scopes: scopes.into_iter().map(|scope| scope.into()).collect(),
+    tokens: tokens.into_iter().map(|token| token.into()).collect(),
+  })
+}
+
+/// The counters of the requests to each bucket, and whether its circuit is
+/// open. They only cover the API instance handling the request.
+#[instrument(name = "GET /api/admin/storage_metrics", skip(req))]
+pub async fn get_storage_metrics(
+  req: Request<Body>,
+) -> ApiResult<Vec<ApiBucketMetrics>> {
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let buckets = req.data::<Buckets>().unwrap();
+  Ok(
+    [
+      &buckets.publishing_bucket,
+      &buckets.modules_bucket,
+      &buckets.docs_bucket,
+      &buckets.npm_bucket,
+    ]
+    .into_iter()
+    .map(|bucket| ApiBucketMetrics {
+      bucket: bucket.bucket.name.clone(),
+      circuit_open: bucket.is_circuit_open(),
+      operations: bucket.metrics(),
+    })
+    .collect(),
+  )
+}
+
+/// Changes the default limit of a tier. Like all rate limit changes, this takes
+/// effect immediately on the instance handling the request, and on every other
+/// API instance within a minute.
+#[instrument(
+  name = "PUT /api/admin/rate_limits/tiers/:tier",
+  skip(req),
+  fields(tier)
+)]
+pub async fn update_rate_limit_tier(
+  mut req: Request<Body>,
+) -> ApiResult<ApiRateLimitTier> {
+  let tier = match util::param(&req, "tier")?.as_str() {
+    "anonymous" => RateLimitTier::Anonymous,
+    "user" => RateLimitTier::User,
+    "github_actions" => RateLimitTier::GithubActions,
+    _ => {
+      return Err(ApiError::MalformedRequest {
+        msg: "'tier' must be one of 'anonymous', 'user' or 'github_actions'"
+          .into(),
+      });
+    }
+  };
+  Span::current().record("tier", field::debug(tier));
+  let request: ApiAdminUpdateRateLimitRequest = decode_json(&mut req).await?;
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  let requests_per_minute = validate_rate_limit(&request)?;
+
+  let db = req.data::<Database>().unwrap();
+  let tier_limit = db
+    .update_rate_limit_tier(&staff.id, tier, requests_per_minute)
+    .await?;
+
+  req
+    .data::<RateLimiter>()
+    .unwrap()
+    .invalidate_policies()
+    .await;
+
+  Ok(tier_limit.into())
+}
+
+#[instrument(
+  name = "PUT /api/admin/rate_limits/scopes/:scope",
+  skip(req),
+  fields(scope)
+)]
+pub async fn update_scope_rate_limit(
+  mut req: Request<Body>,
+) -> ApiResult<ApiScopeRateLimit> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+  let request: ApiAdminUpdateRateLimitRequest = decode_json(&mut req).await?;
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  let requests_per_minute = validate_rate_limit(&request)?;
+
+  let db = req.data::<Database>().unwrap();
+  db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?;
+  let rate_limit = db
+    .upsert_scope_rate_limit(
+      &staff.id,
+      &scope,
+      requests_per_minute,
+      request.reason.as_deref(),
+    )
+    .await?;
+
+  req
+    .data::<RateLimiter>()
+    .unwrap()
+    .invalidate_policies()
+    .await;
+
+  Ok(rate_limit.into())
+}
+
+#[instrument(
+  name = "DELETE /api/admin/rate_limits/scopes/:scope",
+  skip(req),
+  fields(scope)
+)]
+pub async fn delete_scope_rate_limit(
+  req: Request<Body>,
+) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  db.delete_scope_rate_limit(&staff.id, &scope)
+    .await?
+    .ok_or(ApiError::RateLimitOverrideNotFound)?;
+
+  req
+    .data::<RateLimiter>()
+    .unwrap()
+    .invalidate_policies()
+    .await;
+
+  Ok(
+    Response::builder()
+      .status(StatusCode::NO_CONTENT)
+      .body(Body::empty())
+      .unwrap(),
+  )
+}
+
+/// Checks that a publish limit override is positive, and fits the column it
+/// is stored in.
+fn validate_publish_limit<T: TryFrom<u64>>(
+  name: &str,
+  limit: Option<u64>,
+) -> Result<Option<T>, ApiError> {
+  limit
+    .map(|limit| match T::try_from(limit) {
+      Ok(limit_value) if limit > 0 => Ok(limit_value),
+      _ => Err(ApiError::MalformedRequest {
+        msg: format!(
+          "'{name}' must be a positive integer that is not too large"
+        )
+        .into(),
+      }),
+    })
+    .transpose()
+}
+
+/// Replaces the publish limit overrides of a scope. Limits that are left out
+/// fall back to the registry-wide defaults.
+#[instrument(
+  name = "PUT /api/admin/publish_limits/scopes/:scope",
+  skip(req),
+  fields(scope)
+)]
+pub async fn update_scope_publish_limits(
+  mut req: Request<Body>,
+) -> ApiResult<ApiScopePublishLimits> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+  let request: ApiAdminUpdatePublishLimitsRequest =
+    decode_json(&mut req).await?;
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  let new_limits = NewScopePublishLimits {
+    max_tarball_size: validate_publish_limit(
+      "maxTarballSize",
+      request.max_tarball_size,
+    )?,
+    max_file_size: validate_publish_limit(
+      "maxFileSize",
+      request.max_file_size,
+    )?,
+    max_total_file_size: validate_publish_limit(
+      "maxTotalFileSize",
+      request.max_total_file_size,
+    )?,
+    max_file_count: validate_publish_limit(
+      "maxFileCount",
+      request.max_file_count.map(u64::from),
+    )?,
+    max_path_depth: validate_publish_limit(
+      "maxPathDepth",
+      request.max_path_depth.map(u64::from),
+    )?,
+    max_path_length: validate_publish_limit(
+      "maxPathLength",
+      request.max_path_length.map(u64::from),
+    )?,
+    max_module_count: validate_publish_limit(
+      "maxModuleCount",
+      request.max_module_count.map(u64::from),
+    )?,
+    reason: request.reason.as_deref(),
+  };
+
+  let db = req.data::<Database>().unwrap();
+  db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?;
+  let limits = db
+    .upsert_scope_publish_limits(&staff.id, &scope, new_limits)
+    .await?;
+
+  Ok(limits.into())
+}
+
+#[instrument(
+  name = "DELETE /api/admin/publish_limits/scopes/:scope",
+  skip(req),
+  fields(scope)
+)]
+pub async fn delete_scope_publish_limits(
+  req: Request<Body>,
+) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  db.delete_scope_publish_limits(&staff.id, &scope)
+    .await?
+    .ok_or(ApiError::PublishLimitsOverrideNotFound)?;
+
+  Ok(
+    Response::builder()
+      .status(StatusCode::NO_CONTENT)
+      .body(Body::empty())
+      .unwrap(),
+  )
+}
+
+#[instrument(
+  name = "PUT /api/admin/rate_limits/tokens/:token_id",
+  skip(req),
+  fields(token_id)
+)]
+pub async fn update_token_rate_limit(
+  mut req: Request<Body>,
+) -> ApiResult<ApiTokenRateLimit> {
+  let token_id = req.param_uuid("token_id")?;
+  Span::current().record("token_id", field::display(token_id));
+  let request: ApiAdminUpdateRateLimitRequest = decode_json(&mut req).await?;
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  let requests_per_minute = validate_rate_limit(&request)?;
+
+  let db = req.data::<Database>().unwrap();
+  let rate_limit = db
+    .upsert_token_rate_limit(
+      &staff.id,
+      token_id,
+      requests_per_minute,
+      request.reason.as_deref(),
+    )
+    .await?
+    .ok_or(ApiError::TokenNotFound)?;
+
+  req
+    .data::<RateLimiter>()
+    .unwrap()
+    .invalidate_policies()
+    .await;
+
+  Ok(rate_limit.into())
+}
+
+#[instrument(
+  name = "DELETE /api/admin/rate_limits/tokens/:token_id",
+  skip(req),
+  fields(token_id)
+)]
+pub async fn delete_token_rate_limit(
+  req: Request<Body>,
+) -> ApiResult<Response<Body>> {
+  let token_id = req.param_uuid("token_id")?;
+  Span::current().record("token_id", field::display(token_id));
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  db.delete_token_rate_limit(&staff.id, token_id)
+    .await?
+    .ok_or(ApiError::RateLimitOverrideNotFound)?;
+
+  req
+    .data::<RateLimiter>()
+    .unwrap()
+    .invalidate_policies()
+    .await;
+
+  Ok(
+    Response::builder()
+      .status(StatusCode::NO_CONTENT)
+      .body(Body::empty())
+      .unwrap(),
+  )
+}
+
+const MAX_FEATURE_FLAG_NAME_LENGTH: usize = 64;
+const MAX_FEATURE_FLAG_DESCRIPTION_LENGTH: usize = 1000;
+
+fn validate_feature_flag(
+  name: &str,
+  request: &ApiAdminFeatureFlagRequest,
+) -> Result<(), ApiError> {
+  let is_valid_name = name.len() <= MAX_FEATURE_FLAG_NAME_LENGTH
+    && name.split('-').all(|part| {
+      !part.is_empty()
+        && part
+          .chars()
+          .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+    });
+  if !is_valid_name {
+    return Err(ApiError::MalformedRequest {
+      msg: format!("'{name}' is not a valid feature flag name, names must be lowercase kebab-case and at most {MAX_FEATURE_FLAG_NAME_LENGTH} characters").into(),
+    });
+  }
+  if request.description.len() > MAX_FEATURE_FLAG_DESCRIPTION_LENGTH {
+    return Err(ApiError::MalformedRequest {
+      msg: format!(
+        "'description' must be at most {MAX_FEATURE_FLAG_DESCRIPTION_LENGTH} characters"
+      )
+      .into(),
+    });
+  }
+  Ok(())
+}
+
+#[instrument(name = "GET /api/admin/feature_flags", skip(req))]
+pub async fn list_feature_flags(
+  req: Request<Body>,
+) -> ApiResult<Vec<ApiFeatureFlag>> {
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let flags = db.list_feature_flags().await?;
+
+  Ok(flags.into_iter().map(|flag| flag.into()).collect())
+}
+
+#[instrument(
+  name = "GET /api/admin/feature_flags/:name",
+  skip(req),
+  fields(name)
+)]
+pub async fn get_feature_flag(req: Request<Body>) -> ApiResult<ApiFeatureFlag> {
+  let name = util::param(&req, "name")?;
+  Span::current().record("name", field::display(name));
+
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let flag = db
+    .get_feature_flag(name)
+    .await?
+    .ok_or(ApiError::FeatureFlagNotFound)?;
+
+  Ok(flag.into())
+}
+
+/// Creates or replaces a feature flag. Like rate limits, changes take effect
+/// immediately on the instance handling the request, and on every other API
+/// instance within a minute.
+#[instrument(
+  name = "PUT /api/admin/feature_flags/:name",
+  skip(req),
+  fields(name)
+)]
+pub async fn update_feature_flag(
+  mut req: Request<Body>,
+) -> ApiResult<ApiFeatureFlag> {
+  let name = util::param(&req, "name")?.clone();
+  Span::current().record("name", field::display(&name));
+  let request: ApiAdminFeatureFlagRequest = decode_json(&mut req).await?;
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  validate_feature_flag(&name, &request)?;
+
+  let db = req.data::<Database>().unwrap();
+  let flag = db
+    .upsert_feature_flag(
+      &staff.id,
+      NewFeatureFlag {
+        name: &name,
+        description: &request.description,
+        enabled: request.enabled,
+        scopes: &request.scopes,
+        user_ids: &request.user_ids,
+      },
+    )
+    .await?;
+
+  req.data::<FeatureFlags>().unwrap().invalidate().await;
+
+  Ok(flag.into())
+}
+
+#[instrument(
+  name = "DELETE /api/admin/feature_flags/:name",
+  skip(req),
+  fields(name)
+)]
+pub async fn delete_feature_flag(
+  req: Request<Body>,
+) -> ApiResult<Response<Body>> {
+  let name = util::param(&req, "name")?;
+  Span::current().record("name", field::display(name));
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  db.delete_feature_flag(&staff.id, name)
+    .await?
+    .ok_or(ApiError::FeatureFlagNotFound)?;
+
+  req.data::<FeatureFlags>().unwrap().invalidate().await;
+
+  Ok(
+    Response::builder()
+      .status(StatusCode::NO_CONTENT)
+      .body(Body::empty())
+      .unwrap(),
+  )
+}
+
+#[instrument(name = "GET /api/admin/tombstones", skip(req))]
+pub async fn list_tombstones(
+  req: Request<Body>,
+) -> ApiResult<Vec<ApiTombstone>> {
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let retention = *req.data::<TombstoneRetention>().unwrap();
+  let tombstones = db.list_tombstones().await?;
+
+  Ok(
+    tombstones
+      .into_iter()
+      .map(|tombstone| (tombstone, retention).into())
+      .collect(),
+  )
+}
+
+#[instrument(
+  name = "POST /api/admin/packages/:scope/:package/restore",
+  skip(req),
+  fields(scope, package)
+)]
+pub async fn restore_package(req: Request<Body>) -> ApiResult<ApiPackage> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  db.restore_package(&staff.id, &scope, &package)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+
+  republish_restored_package(&req, &scope, &package).await
+}
+
+#[instrument(
+  name = "POST /api/admin/packages/:scope/:package/versions/:version/restore",
+  skip(req),
+  fields(scope, package, version)
+)]
+pub async fn restore_package_version(
+  req: Request<Body>,
+) -> ApiResult<ApiPackageVersion> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  let version = req.param_version()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+  Span::current().record("version", field::display(&version));
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let buckets = req.data::<Buckets>().unwrap();
+  let package_version = db
+    .restore_package_version(&staff.id, &scope, &package, &version)
+    .await?
+    .ok_or(ApiError::PackageVersionNotFound)?;
+
+  // The artifacts of a version that is also quarantined stay in quarantine.
+  if db
+    .get_package_version_quarantine(&scope, &package, &version)
+    .await?
+    .is_none()
+  {
+    quarantine::release_artifacts(db, buckets, &scope, &package, &version)
+      .await?;
+  }
+  republish_restored_package(&req, &scope, &package).await?;
+  purge_version_caches(&req, &scope, &package, &version).await?;
+
+  Ok(package_version.into())
+}
+
+#[instrument(name = "GET /api/admin/reports", skip(req), fields(status))]
+pub async fn list_abuse_reports(
+  req: Request<Body>,
+) -> ApiResult<ApiList<ApiAbuseReport>> {
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let status = match req.query("status").map(|status| status.as_str()) {
+    None => None,
+    Some("reported") => Some(AbuseReportStatus::Reported),
+    Some("underReview") => Some(AbuseReportStatus::UnderReview),
+    Some("actioned") => Some(AbuseReportStatus::Actioned),
+    Some("dismissed") => Some(AbuseReportStatus::Dismissed),
+    Some(_) => {
+      return Err(ApiError::MalformedRequest {
+        msg: "invalid 'status' query parameter, expected one of: reported, \
+           underReview, actioned, dismissed"
+          .into(),
+      });
+    }
+  };
+  Span::current().record("status", field::debug(&status));
+
+  let db = req.data::<Database>().unwrap();
+  let (start, limit) = pagination(&req);
+  let (total, reports) = db.list_abuse_reports(status, start, limit).await?;
+  Ok(ApiList {
+    items: reports.into_iter().map(|report| report.into()).collect(),
+    total,
+  })
+}
+
+#[instrument(name = "GET /api/admin/reports/:id", skip(req), fields(id))]
+pub async fn get_abuse_report(req: Request<Body>) -> ApiResult<ApiAbuseReport> {
+  let id = req.param_uuid("id")?;
+  Span::current().record("id", field::display(id));
+
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let report = db
+    .get_abuse_report(id)
+    .await?
+    .ok_or(ApiError::AbuseReportNotFound)?;
+  Ok(report.into())
+}
+
+/// Moves an abuse report through the moderation states, see
+/// [AbuseReportStatus::can_transition_to].
+#[instrument(name = "PATCH /api/admin/reports/:id", skip(req), fields(id))]
+pub async fn patch_abuse_report(
+  mut req: Request<Body>,
+) -> ApiResult<ApiAbuseReport> {
+  let id = req.param_uuid("id")?;
+  Span::current().record("id", field::display(id));
+
+  let ApiAdminUpdateAbuseReportRequest { status, resolution } =
+    decode_json(&mut req).await?;
+  let resolution = resolution
+    .as_deref()
+    .map(str::trim)
+    .filter(|resolution| !resolution.is_empty());
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let report = db
+    .get_abuse_report(id)
+    .await?
+    .ok_or(ApiError::AbuseReportNotFound)?;
+  if !report.status.can_transition_to(status) {
+    return Err(ApiError::InvalidAbuseReportTransition {
+      from: report.status.as_str().to_owned(),
+      to: status.as_str().to_owned(),
+    });
+  }
+
+  // The report can only have been moved in the meantime if another staff
+  // member is reviewing it too.
+  let report = db
+    .update_abuse_report_status(
+      &staff.id,
+      id,
+      report.status,
+      status,
+      resolution,
+    )
+    .await?
+    .ok_or(ApiError::InvalidAbuseReportTransition {
+      from: report.status.as_str().to_owned(),
+      to: status.as_str().to_owned(),
+    })?;
+
+  events::emit(
+    db,
+    DomainEvent::AbuseReportUpdated {
+      report_id: report.id,
+      status: report.status,
+      user_id: staff.id,
+    },
+  )
+  .await;
+
+  Ok(report.into())
+}
+
+#[instrument(name = "GET /api/admin/quarantines", skip(req))]
+pub async fn list_quarantines(
+  req: Request<Body>,
+) -> ApiResult<ApiList<ApiPackageVersionQuarantine>> {
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let (start, limit) = pagination(&req);
+  let (total, quarantines) =
+    db.list_package_version_quarantines(start, limit).await?;
+  Ok(ApiList {
+    items: quarantines
+      .into_iter()
+      .map(|quarantine| quarantine.into())
+      .collect(),
+    total,
+  })
+}
+
+/// Blocks a package version from being downloaded, see [crate::quarantine].
+#[instrument(
+  name = "POST /api/admin/packages/:scope/:package/versions/:version/quarantine",
+  skip(req),
+  fields(scope, package, version)
+)]
+pub async fn quarantine_package_version(
+  mut req: Request<Body>,
+) -> ApiResult<ApiPackageVersionQuarantine> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  let version = req.param_version()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+  Span::current().record("version", field::display(&version));
+
+  let ApiAdminQuarantineVersionRequest { reason, report_id } =
+    decode_json(&mut req).await?;
+  let reason = reason.trim();
+  if reason.is_empty() {
+    return Err(ApiError::MalformedRequest {
+      msg: "missing 'reason' parameter".into(),
+    });
+  }
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let buckets = req.data::<Buckets>().unwrap();
+  db.get_package_version(&scope, &package, &version)
+    .await?
+    .ok_or(ApiError::PackageVersionNotFound)?;
+  if let Some(report_id) = report_id {
+    db.get_abuse_report(report_id)
+      .await?
+      .ok_or(ApiError::AbuseReportNotFound)?;
+  }
+
+  let quarantine = db
+    .quarantine_package_version(
+      &staff.id, &scope, &package, &version, reason, report_id,
+    )
+    .await
+    .map_err(|e| {
+      map_unique_violation(e, ApiError::PackageVersionAlreadyQuarantined)
+    })?;
+
+  // The manifests go first, so that nothing resolves to the version anymore
+  // by the time its artifacts are gone.
+  upload_package_manifests(&req, &scope, &package).await?;
+  quarantine::quarantine_artifacts(db, buckets, &scope, &package, &version)
+    .await?;
+  purge_version_caches(&req, &scope, &package, &version).await?;
+
+  events::emit(
+    db,
+    DomainEvent::VersionQuarantined {
+      scope,
+      package,
+      version,
+      report_id,
+      user_id: Some(staff.id),
+    },
+  )
+  .await;
+
+  Ok(quarantine.into())
+}
+
+#[instrument(
+  name = "DELETE /api/admin/packages/:scope/:package/versions/:version/quarantine",
+  skip(req),
+  fields(scope, package, version)
+)]
+pub async fn release_package_version(
+  req: Request<Body>,
+) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  let version = req.param_version()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+  Span::current().record("version", field::display(&version));
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let buckets = req.data::<Buckets>().unwrap();
+  db.get_package_version_quarantine(&scope, &package, &version)
+    .await?
+    .ok_or(ApiError::PackageVersionNotQuarantined)?;
+
+  // The artifacts go back first, so that everything the manifests point to
+  // exists once the version is listed in them again. Those of a deleted
+  // version stay where they are until it is restored.
+  if db
+    .get_package_version(&scope, &package, &version)
+    .await?
+    .is_some()
+  {
+    quarantine::release_artifacts(db, buckets, &scope, &package, &version)
+      .await?;
+  }
+  db.release_package_version_quarantine(&staff.id, &scope, &package, &version)
+    .await?
+    .ok_or(ApiError::PackageVersionNotQuarantined)?;
+  upload_package_manifests(&req, &scope, &package).await?;
+  purge_version_caches(&req, &scope, &package, &version).await?;
+
+  events::emit(
+    db,
+    DomainEvent::VersionReleased {
+      scope,
+      package,
+      version,
+      user_id: staff.id,
+    },
+  )
+  .await;
+
+  Ok(
+    Response::builder()
+      .status(StatusCode::NO_CONTENT)
+      .body(Body::empty())
+      .unwrap(),
+  )
+}
+
+async fn purge_version_caches(
+  req: &Request<Body>,
+  scope: &ScopeName,
+  package: &PackageName,
+  version: &Version,
+) -> ApiResult<()> {
+  let db = req.data::<Database>().unwrap();
+  let registry_url = &req.data::<RegistryUrl>().unwrap().0;
+  let npm_url = &req.data::<NpmUrl>().unwrap().0;
+  let cache_purge = req.data::<crate::cache_purge::CachePurge>().unwrap();
+  quarantine::purge_version_caches(
+    db,
+    cache_purge,
+    registry_url,
+    npm_url,
+    scope,
+    package,
+    version,
+  )
+  .await?;
+  Ok(())
+}
+
+/// Uploads the manifests of a package after it, or one of its versions, was
+/// restored, and updates it in Algolia.
+async fn republish_restored_package(
+  req: &Request<Body>,
+  scope: &ScopeName,
+  package: &PackageName,
+) -> ApiResult<ApiPackage> {
+  upload_package_manifests(req, scope, package).await?;
+
+  let db = req.data::<Database>().unwrap();
+  let (package, repo, meta) = db
+    .get_package(scope, package)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+
+  let algolia_client = req.data::<Option<AlgoliaClient>>().unwrap();
+  if let Some(algolia_client) = algolia_client {
+    algolia_client.upsert_package(&package, &meta);
+  }
+
+  Ok(ApiPackage::from((package, repo, meta)))
+}
+
+/// Uploads the package and npm manifests of a package after the versions
+/// listed in them changed.
+async fn upload_package_manifests(
+  req: &Request<Body>,
+  scope: &ScopeName,
+  package: &PackageName,
+) -> ApiResult<()> {
+  let db = req.data::<Database>().unwrap();
+  let buckets = req.data::<Buckets>().unwrap();
+  let registry_url = &req.data::<RegistryUrl>().unwrap().0;
+  let npm_url = &req.data::<NpmUrl>().unwrap().0;
+  let npm_signer = req.data::<NpmSigner>().unwrap();
+  let cache_purge = req.data::<crate::cache_purge::CachePurge>().unwrap();
+
+  upload_package_manifest(
+    db,
+    buckets,
+    registry_url,
+    cache_purge,
+    scope,
+    package,
+  )
+  .await?;
+  upload_npm_version_manifest(
+    db,
+    buckets,
+    npm_url,
+    npm_signer,
+    cache_purge,
+    scope,
+    package,
+  )
+  .await?;
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::api::ApiAbuseReport;
+  use crate::api::ApiBackgroundJob;
+  use crate::api::ApiBucketMetrics;
+  use crate::api::ApiDatabasePools;
+  use crate::api::ApiFeatureFlag;
+  use crate::api::ApiFullScope;
+  use crate::api::ApiFullUser;
+  use crate::api::ApiIntegrityScrubFailure;
+  use crate::api::ApiIntegrityScrubJob;
+  use crate::api::ApiList;
+  use crate::api::ApiMetadataCache;
+  use crate::api::ApiNpmTarballRebuildJob;
+  use crate::api::ApiOrphanGcJob;
+  use crate::api::ApiOrphanedObject;
+  use crate::api::ApiPackage;
+  use crate::api::ApiPackageVersion;
+  use crate::api::ApiPackageVersionQuarantine;
+  use crate::api::ApiRateLimitTier;
+  use crate::api::ApiRateLimits;
+  use crate::api::ApiReservedName;
+  use crate::api::ApiScheduledJob;
+  use crate::api::ApiScope;
+  use crate::api::ApiScopeRateLimit;
+  use crate::api::ApiScoreRecomputeJob;
+  use crate::api::ApiScoreSchema;
+  use crate::api::ApiSignedUrl;
+  use crate::api::ApiTombstone;
+  use crate::db::AbuseReportStatus;
+  use crate::db::IntegrityScrubJobStatus;
+  use crate::db::NpmTarballRebuildJobStatus;
+  use crate::db::OrphanGcJobStatus;
+  use crate::db::PublishingTaskStatus;
+  use crate::db::RateLimitTier;
+  use crate::db::ReservedNameKind;
+  use crate::db::ScheduledJobStatus;
+  use crate::db::ScoreRecomputeJobStatus;
+  use crate::feature_flags::FeatureFlags;
+  use crate::feature_flags::UNSTABLE_BYTES_IMPORTS;
+  use crate::ids::PackageName;
+  use crate::ids::ScopeName;
+  use crate::ids::Version;
+  use crate::metadata::PackageMetadata;
+  use crate::publish::tests::create_mock_tarball;
+  use crate::publish::tests::process_tarball_setup;
+  use crate::s3::ContentEncoding;
+  use crate::s3::S3UploadOptions;
+  use crate::s3::UploadTaskBody;
+  use crate::s3_policy::StorageOperation;
+  use crate::util::test::ApiResultExt;
+  use crate::util::test::TestSetup;
+  use hyper::StatusCode;
+  use serde_json::json;
+
+  #[tokio::test]
+  async fn list_users() {
+    let mut t = TestSetup::new().await;
+
+    let token = t.staff_user.token.clone();
+    let users = t
+      .http()
+      .get("/api/admin/users")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiList<ApiFullUser>>()
+      .await;
+    assert_eq!(users.items.len(), 5);
+
+    let path = format!("/api/admin/users?query={}", t.user2.user.id);
+    let users = t
+      .http()
+      .get(path)
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiList<ApiFullUser>>()
+      .await;
+    assert_eq!(users.items.len(), 1);
+    assert_eq!(users.items[0].id, t.user2.user.id);
+  }
+
+  #[tokio::test]
+  async fn storage_metrics() {
+    let mut t = TestSetup::new().await;
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success);
+
+    let token = t.staff_user.token.clone();
+    let metrics = t
+      .http()
+      .get("/api/admin/storage_metrics")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<Vec<ApiBucketMetrics>>()
+      .await;
+    assert_eq!(metrics.len(), 4);
+    let modules = metrics
+      .iter()
+      .find(|metrics| metrics.bucket == t.buckets.modules_bucket.bucket.name)
+      .unwrap();
+    assert!(!modules.circuit_open);
+    let upload = modules
+      .operations
+      .iter()
+      .find(|metrics| metrics.operation == StorageOperation::Upload)
+      .unwrap();
+    assert!(upload.requests > 0);
+    assert_eq!(upload.failures, 0);
+
+    let token = t.user1.token.clone();
+    t.http()
+      .get("/api/admin/storage_metrics")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err(StatusCode::FORBIDDEN)
+      .await;
+  }
+
+  #[tokio::test]
+  async fn scope_management() {
+    let mut t = TestSetup::new().await;
+
+    assert_eq!(t.scope.package_limit, 250);
+    assert_eq!(t.scope.new_package_per_week_limit, 200);
+    assert_eq!(t.scope.publish_attempts_per_week_limit, 1000);
+
+    let path = format!("/api/admin/scopes/{}", t.scope.scope);
+    let token = t.staff_user.token.clone();
+    let res_scope = t
+      .http()
+      .patch(path)
+      .body_json(json!({
+        "packageLimit": 101,
+        "newPackagePerWeekLimit": 101,
+        "publishAttemptsPerWeekLimit": 101,
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiFullScope>()
+      .await;
+    assert_eq!(res_scope.quotas.package_limit, 101);
+    assert_eq!(res_scope.quotas.new_package_per_week_limit, 101);
+    assert_eq!(res_scope.quotas.publish_attempts_per_week_limit, 101);
+
+    let path = format!("/api/admin/scopes/{}", t.scope.scope);
+    let res_scope = t
+      .http()
+      .patch(path)
+      .body_json(json!({ "storageLimit": 1024 }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiFullScope>()
+      .await;
+    assert_eq!(res_scope.quotas.storage_limit, 1024);
+    assert_eq!(res_scope.quotas.package_limit, 101);
+  }
+
+  #[tokio::test]
+  async fn score_schemas() {
+    let mut t = TestSetup::new().await;
+
+    let token = t.staff_user.token.clone();
+    let schemas = t
+      .http()
+      .get("/api/admin/score_schemas")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<Vec<ApiScoreSchema>>()
+      .await;
+    assert_eq!(schemas.len(), 3);
+    assert_eq!(schemas[0].version, 3);
+    assert_eq!(schemas[0].max_score, 19);
+    assert_eq!(schemas[0].weights.has_tests, 1);
+    assert_eq!(schemas[1].version, 2);
+    assert_eq!(schemas[1].max_score, 18);
+    assert_eq!(schemas[1].weights.all_examples_resolve, 1);
+    assert_eq!(schemas[1].weights.has_tests, 0);
+    assert_eq!(schemas[2].version, 1);
+    assert_eq!(schemas[2].max_score, 17);
+    assert_eq!(schemas[2].weights.all_examples_resolve, 0);
+
+    let schema = t
+      .http()
+      .post("/api/admin/score_schemas")
+      .body_json(json!({
+        "weights": { "allFastCheck": 3 },
+        "maxScore": 15,
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiScoreSchema>()
+      .await;
+    assert_eq!(schema.version, 4);
+    assert_eq!(schema.max_score, 15);
+    assert_eq!(schema.weights.all_fast_check, 3);
+    // unspecified weights fall back to the defaults
+    assert_eq!(schema.weights.has_readme, 2);
+
+    let token = t.user1.token.clone();
+    t.http()
+      .post("/api/admin/score_schemas")
+      .body_json(json!({ "weights": {}, "maxScore": 15 }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err(StatusCode::FORBIDDEN)
+      .await;
+  }
+
+  #[tokio::test]
+  async fn score_recompute_jobs() {
+    let mut t = TestSetup::new().await;
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success);
+
+    let scope = ScopeName::new("scope".to_string()).unwrap();
+    let name = PackageName::new("foo".to_string()).unwrap();
+    let version = Version::new("1.2.3").unwrap();
+    let published = t
+      .db()
+      .get_package_version(&scope, &name, &version)
+      .await
+      .unwrap()
+      .unwrap()
+      .meta;
+    assert_eq!(published.score_schema_version, 3);
+    let mut stale = published.clone();
+    stale.has_readme = !published.has_readme;
+    stale.all_entrypoints_docs = !published.all_entrypoints_docs;
+    stale.percentage_documented_symbols = 0.0;
+    t.db()
+      .update_package_version_meta(&scope, &name, &version, &stale)
+      .await
+      .unwrap();
+
+    let token = t.staff_user.token.clone();
+    let job = t
+      .http()
+      .post("/api/admin/score_recompute_jobs")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiScoreRecomputeJob>()
+      .await;
+
+    let mut job_status = job.status;
+    for _ in 0..50 {
+      if job_status != ScoreRecomputeJobStatus::Running {
+        break;
+      }
+      tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+      let job = t
+        .http()
+        .get(format!("/api/admin/score_recompute_jobs/{}", job.id))
+        .token(Some(&token))
+        .call()
+        .await
+        .unwrap()
+        .expect_ok::<ApiScoreRecomputeJob>()
+        .await;
+      job_status = job.status;
+    }
+    assert_eq!(job_status, ScoreRecomputeJobStatus::Completed);
+
+    let meta = t
+      .db()
+      .get_package_version(&scope, &name, &version)
+      .await
+      .unwrap()
+      .unwrap()
+      .meta;
+    assert_eq!(meta.has_readme, published.has_readme);
+    assert_eq!(meta.all_entrypoints_docs, published.all_entrypoints_docs);
+    assert_eq!(
+      meta.percentage_documented_symbols,
+      published.percentage_documented_symbols
+    );
+
+    let jobs = t
+      .http()
+      .get("/api/admin/score_recompute_jobs")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<Vec<ApiScoreRecomputeJob>>()
+      .await;
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].id, job.id);
+
+    // A running job whose lease expired does not block new jobs.
+    let orphaned = t
+      .db()
+      .create_score_recompute_job(None, chrono::Duration::seconds(-1))
+      .await
+      .unwrap()
+      .unwrap();
+    t.http()
+      .post("/api/admin/score_recompute_jobs")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiScoreRecomputeJob>()
+      .await;
+    let orphaned = t
+      .db()
+      .get_score_recompute_job(orphaned.id)
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(orphaned.status, ScoreRecomputeJobStatus::Failed);
+
+    t.http()
+      .get(format!(
+        "/api/admin/score_recompute_jobs/{}",
+        uuid::Uuid::nil()
+      ))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "scoreRecomputeJobNotFound")
+      .await;
+
+    let token = t.user1.token.clone();
+    t.http()
+      .post("/api/admin/score_recompute_jobs")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err(StatusCode::FORBIDDEN)
+      .await;
+  }
+
+  #[tokio::test]
+  async fn npm_tarball_rebuild_jobs() {
+    let mut t = TestSetup::new().await;
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success);
+
+    let name = PackageName::new("foo".to_string()).unwrap();
+    let version = Version::new("1.2.3").unwrap();
+    let revision = crate::npm::NPM_TARBALL_REVISION;
+    let npm_tarball_path = crate::s3_paths::npm_tarball_path(
+      &t.scope.scope,
+      &name,
+      &version,
+      revision,
+    );
+    t.db()
+      .delete_npm_tarballs_for_version(&t.scope.scope, &name, &version)
+      .await
+      .unwrap();
+    t.buckets
+      .npm_bucket
+      .delete_file(npm_tarball_path.clone().into())
+      .await
+      .unwrap();
+
+    let token = t.staff_user.token.clone();
+    let scope = t.scope.scope.clone();
+    let job = t
+      .http()
+      .post("/api/admin/npm_tarball_rebuild_jobs")
+      .body_json(json!({ "scope": scope, "concurrency": 2 }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiNpmTarballRebuildJob>()
+      .await;
+    assert_eq!(job.scope, Some(scope.clone()));
+    assert_eq!(job.package, None);
+    assert!(!job.verify);
+    assert_eq!(job.concurrency, 2);
+
+    let mut job_status = job.status;
+    for _ in 0..50 {
+      if job_status != NpmTarballRebuildJobStatus::Running {
+        break;
+      }
+      tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+      let job = t
+        .http()
+        .get(format!("/api/admin/npm_tarball_rebuild_jobs/{}", job.id))
+        .token(Some(&token))
+        .call()
+        .await
+        .unwrap()
+        .expect_ok::<ApiNpmTarballRebuildJob>()
+        .await;
+      job_status = job.status;
+    }
+    assert_eq!(job_status, NpmTarballRebuildJobStatus::Completed);
+
+    let npm_tarball = t
+      .db()
+      .get_npm_tarball(&scope, &name, &version, revision as i32)
+      .await
+      .unwrap()
+      .unwrap();
+    let tarball = t
+      .buckets
+      .npm_bucket
+      .download(npm_tarball_path.into())
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(tarball.len(), npm_tarball.size as usize);
+
+    let jobs = t
+      .http()
+      .get("/api/admin/npm_tarball_rebuild_jobs")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<Vec<ApiNpmTarballRebuildJob>>()
+      .await;
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].id, job.id);
+    assert_eq!(jobs[0].total_versions, 1);
+    assert_eq!(jobs[0].processed_versions, jobs[0].total_versions);
+    assert_eq!(jobs[0].failed_versions, 0);
+
+    // A running job whose lease expired does not block new jobs.
+    let orphaned = t
+      .db()
+      .create_npm_tarball_rebuild_job(
+        &t.staff_user.user.id,
+        None,
+        None,
+        false,
+        1,
+        chrono::Duration::seconds(-1),
+      )
+      .await
+      .unwrap()
+      .unwrap();
+    t.http()
+      .post("/api/admin/npm_tarball_rebuild_jobs")
+      .body_json(json!({ "scope": scope }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiNpmTarballRebuildJob>()
+      .await;
+    let orphaned = t
+      .db()
+      .get_npm_tarball_rebuild_job(orphaned.id)
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(orphaned.status, NpmTarballRebuildJobStatus::Failed);
+
+    t.http()
+      .get(format!(
+        "/api/admin/npm_tarball_rebuild_jobs/{}",
+        uuid::Uuid::nil()
+      ))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "npmTarballRebuildJobNotFound")
+      .await;
+
+    t.http()
+      .post("/api/admin/npm_tarball_rebuild_jobs")
+      .body_json(json!({ "package": "foo" }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+
+    t.http()
+      .post("/api/admin/npm_tarball_rebuild_jobs")
+      .body_json(json!({ "concurrency": 0 }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+
+    t.http()
+      .post("/api/admin/npm_tarball_rebuild_jobs")
+      .body_json(json!({ "scope": scope, "package": "doesnotexist" }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "packageNotFound")
+      .await;
+
+    let token = t.user1.token.clone();
+    t.http()
+      .post("/api/admin/npm_tarball_rebuild_jobs")
+      .body_json(json!({}))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err(StatusCode::FORBIDDEN)
+      .await;
+  }
+
+  #[tokio::test]
+  async fn orphan_gc_jobs() {
+    let mut t = TestSetup::new().await;
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success);
+
+    let orphan = "@scope/foo/9.9.9_meta.json";
+    let orphan_tarball =
+      crate::tarball::bucket_tarball_path(uuid::Uuid::new_v4());
+    let options = S3UploadOptions {
+      content_type: None,
+      cache_control: None,
+      content_encoding: ContentEncoding::Identity,
+    };
+    t.buckets
+      .modules_bucket
+      .upload(
+        orphan.into(),
+        UploadTaskBody::Bytes("{}".into()),
+        options.clone(),
+      )
+      .await
+      .unwrap();
+    t.buckets
+      .publishing_bucket
+      .upload(
+        orphan_tarball.clone().into(),
+        UploadTaskBody::Bytes("".into()),
+        options,
+      )
+      .await
+      .unwrap();
+
+    let token = t.staff_user.token.clone();
+    for dry_run in [true, false] {
+      let job = t
+        .http()
+        .post("/api/admin/orphan_gc_jobs")
+        .body_json(json!({ "dryRun": dry_run }))
+        .token(Some(&token))
+        .call()
+        .await
+        .unwrap()
+        .expect_ok::<ApiOrphanGcJob>()
+        .await;
+      assert_eq!(job.dry_run, dry_run);
+
+      let mut job_status = job.status;
+      for _ in 0..50 {
+        if job_status != OrphanGcJobStatus::Running {
+          break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let job = t
+          .http()
+          .get(format!("/api/admin/orphan_gc_jobs/{}", job.id))
+          .token(Some(&token))
+          .call()
+          .await
+          .unwrap()
+          .expect_ok::<ApiOrphanGcJob>()
+          .await;
+        job_status = job.status;
+      }
+      assert_eq!(job_status, OrphanGcJobStatus::Completed);
+
+      let objects = t
+        .http()
+        .get(format!("/api/admin/orphan_gc_jobs/{}/objects", job.id))
+        .token(Some(&token))
+        .call()
+        .await
+        .unwrap()
+        .expect_ok::<ApiList<ApiOrphanedObject>>()
+        .await;
+      assert_eq!(objects.total, 2);
+      let paths = objects
+        .items
+        .iter()
+        .map(|object| (object.bucket.as_str(), object.path.as_str()))
+        .collect::<Vec<_>>();
+      assert_eq!(
+        paths,
+        vec![("modules", orphan), ("publishing", orphan_tarball.as_str())]
+      );
+      assert!(objects.items.iter().all(|object| object.deleted != dry_run));
+
+      let exists = t
+        .buckets
+        .modules_bucket
+        .download(orphan.into())
+        .await
+        .unwrap()
+        .is_some();
+      assert_eq!(exists, dry_run);
+    }
+
+    // The objects of the published version are kept.
+    assert!(
+      t.buckets
+        .modules_bucket
+        .download("@scope/foo/1.2.3_meta.json".into())
+        .await
+        .unwrap()
+        .is_some()
+    );
+
+    let jobs = t
+      .http()
+      .get("/api/admin/orphan_gc_jobs")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<Vec<ApiOrphanGcJob>>()
+      .await;
+    assert_eq!(jobs.len(), 2);
+    assert!(jobs[0].scanned_packages >= 1);
+    assert_eq!(jobs[0].orphaned_objects, 2);
+    assert_eq!(jobs[0].deleted_objects, 2);
+
+    t.http()
+      .get(format!("/api/admin/orphan_gc_jobs/{}", uuid::Uuid::nil()))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "orphanGcJobNotFound")
+      .await;
+
+    let token = t.user1.token.clone();
+    t.http()
+      .post("/api/admin/orphan_gc_jobs")
+      .body_json(json!({}))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err(StatusCode::FORBIDDEN)
+      .await;
+  }
+
+  #[tokio::test]
+  async fn integrity_scrub_jobs() {
+    let mut t = TestSetup::new().await;
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success);
+
+    let token = t.staff_user.token.clone();
+    let doc_nodes_path = crate::s3_paths::docs_v2_path(
+      &ScopeName::try_from("scope").unwrap(),
+      &PackageName::try_from("foo").unwrap(),
+      &Version::new("1.2.3").unwrap(),
+    );
+    for corrupt in [false, true] {
+      if corrupt {
+        t.buckets
+          .docs_bucket
+          .upload(
+            doc_nodes_path.as_str().into(),
+            UploadTaskBody::Bytes("corrupted".into()),
+            S3UploadOptions {
+              content_type: None,
+              cache_control: None,
+              content_encoding: ContentEncoding::Identity,
+            },
+          )
+          .await
+          .unwrap();
+      }
+
+      let job = t
+        .http()
+        .post("/api/admin/integrity_scrub_jobs")
+        .body_json(json!({ "scope": "scope", "package": "foo" }))
+        .token(Some(&token))
+        .call()
+        .await
+        .unwrap()
+        .expect_ok::<ApiIntegrityScrubJob>()
+        .await;
+      assert_eq!(job.total_versions, 1);
+
+      let mut job = job;
+      for _ in 0..50 {
+        if job.status != IntegrityScrubJobStatus::Running {
+          break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        job = t
+          .http()
+          .get(format!("/api/admin/integrity_scrub_jobs/{}", job.id))
+          .token(Some(&token))
+          .call()
+          .await
+          .unwrap()
+          .expect_ok::<ApiIntegrityScrubJob>()
+          .await;
+      }
+      assert_eq!(job.status, IntegrityScrubJobStatus::Completed);
+      assert_eq!(job.checked_versions, 1);
+      assert!(job.checked_objects > 1);
+
+      let failures = t
+        .http()
+        .get(format!(
+          "/api/admin/integrity_scrub_jobs/{}/failures",
+          job.id
+        ))
+        .token(Some(&token))
+        .call()
+        .await
+        .unwrap()
+        .expect_ok::<ApiList<ApiIntegrityScrubFailure>>()
+        .await;
+      if corrupt {
+        assert_eq!(job.failed_objects, 1);
+        assert_eq!(failures.total, 1);
+        let failure = &failures.items[0];
+        assert_eq!(failure.bucket, "docs");
+        assert_eq!(failure.path, doc_nodes_path);
+        assert_eq!(
+          failure.actual.as_deref(),
+          Some(crate::integrity::sha256_digest(b"corrupted").as_str())
+        );
+      } else {
+        assert_eq!(job.failed_objects, 0);
+        assert_eq!(failures.total, 0);
+      }
+    }
+
+    t.http()
+      .post("/api/admin/integrity_scrub_jobs")
+      .body_json(json!({ "package": "foo" }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err(StatusCode::BAD_REQUEST)
+      .await;
+
+    t.http()
+      .get(format!(
+        "/api/admin/integrity_scrub_jobs/{}",
+        uuid::Uuid::nil()
+      ))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "integrityScrubJobNotFound")
+      .await;
+
+    let token = t.user1.token.clone();
+    t.http()
+      .post("/api/admin/integrity_scrub_jobs")
+      .body_json(json!({}))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err(StatusCode::FORBIDDEN)
+      .await;
+  }
+
+  #[tokio::test]
+  async fn database_pools() {
+    let mut t = TestSetup::new().await;
+
+    let token = t.staff_user.token.clone();
+    let pools = t
+      .http()
+      .get("/api/admin/database_pools")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiDatabasePools>()
+      .await;
+    assert_eq!(pools.primary.max_connections, 1);
+    assert!(!pools.primary.closed);
+    assert!(pools.replica.is_none());
+    assert!(!pools.sticky_to_primary);
+
+    let token = t.user1.token.clone();
+    t.http()
+      .get("/api/admin/database_pools")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err(StatusCode::FORBIDDEN)
+      .await;
+  }
+
+  #[tokio::test]
+  async fn metadata_cache() {
+    let mut t = TestSetup::new().await;
+
+    let token = t.staff_user.token.clone();
+    let cache = t
+      .http()
+      .get("/api/admin/metadata_cache")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiMetadataCache>()
+      .await;
+    assert!(!cache.enabled);
+    assert_eq!(cache.package.hits, 0);
+    assert_eq!(cache.errors, 0);
+
+    let token = t.user1.token.clone();
+    t.http()
+      .get("/api/admin/metadata_cache")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err(StatusCode::FORBIDDEN)
+      .await;
+  }
+
+  #[tokio::test]
+  async fn scheduled_jobs() {
+    let mut t = TestSetup::new().await;
+
+    let due = chrono::Utc::now() - chrono::Duration::minutes(1);
+    t.db()
+      .register_scheduled_job("gc_module_blobs", "30 * * * *", due)
+      .await
+      .unwrap();
+    // Registering again with the same schedule keeps the next run.
+    t.db()
+      .register_scheduled_job("gc_module_blobs", "30 * * * *", due)
+      .await
+      .unwrap();
+
+    let next_run_at = chrono::Utc::now() + chrono::Duration::hours(1);
+    let lease = chrono::Duration::minutes(5);
+    let run_id = uuid::Uuid::new_v4();
+    assert!(
+      t.db()
+        .claim_scheduled_job("gc_module_blobs", run_id, next_run_at, lease)
+        .await
+        .unwrap()
+    );
+    // Runs do not overlap, and the job is not due anymore.
+    assert!(
+      !t.db()
+        .claim_scheduled_job(
+          "gc_module_blobs",
+          uuid::Uuid::new_v4(),
+          next_run_at,
+          lease
+        )
+        .await
+        .unwrap()
+    );
+
+    let token = t.staff_user.token.clone();
+    let jobs = t
+      .http()
+      .get("/api/admin/scheduled_jobs")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<Vec<ApiScheduledJob>>()
+      .await;
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].name, "gc_module_blobs");
+    assert_eq!(jobs[0].schedule, "30 * * * *");
+    assert!(jobs[0].running);
+    assert!(jobs[0].last_status.is_none());
+
+    t.db()
+      .finish_scheduled_job(
+        "gc_module_blobs",
+        run_id,
+        ScheduledJobStatus::Failed,
+        Some("boom"),
+        due,
+      )
+      .await
+      .unwrap();
+
+    let jobs = t
+      .http()
+      .get("/api/admin/scheduled_jobs")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<Vec<ApiScheduledJob>>()
+      .await;
+    assert!(!jobs[0].running);
+    assert_eq!(jobs[0].last_status, Some(ScheduledJobStatus::Failed));
+    assert_eq!(jobs[0].last_error.as_deref(), Some("boom"));
+    assert!(jobs[0].last_finished_at.is_some());
+    // The next run is not moved back.
+    assert!(jobs[0].next_run_at > chrono::Utc::now());
+
+    let token = t.user1.token.clone();
+    t.http()
+      .get("/api/admin/scheduled_jobs")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err(StatusCode::FORBIDDEN)
+      .await;
+  }
+
+  #[tokio::test]
+  async fn background_jobs() {
+    let mut t = TestSetup::new().await;
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success);
+
+    let token = t.staff_user.token.clone();
+    let job = t
+      .http()
+      .post("/api/admin/jobs")
+      .body_json(json!({
+        "kind": "scoreRecompute",
+        "scope": "scope",
+        "package": "foo",
+        "version": "1.2.3",
+        "maxAttempts": 1,
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiBackgroundJob>()
+      .await;
+    assert_eq!(job.kind, BackgroundJobKind::ScoreRecompute);
+    assert_eq!(job.status, BackgroundJobStatus::Pending);
+    assert_eq!(job.attempts, 0);
+    assert_eq!(job.payload["version"], "1.2.3");
+
+    let jobs = t
+      .http()
+      .get("/api/admin/jobs?status=pending&kind=scoreRecompute")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiList<ApiBackgroundJob>>()
+      .await;
+    assert_eq!(jobs.total, 1);
+    assert_eq!(jobs.items[0].id, job.id);
+
+    t.http()
+      .post(format!("/api/admin/jobs/{}/retry", job.id))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::CONFLICT, "backgroundJobNotDead")
+      .await;
+
+    // The job only has one attempt, so it is dead after it fails once.
+    let db = t.db();
+    let claimed = db
+      .claim_background_jobs(10, chrono::Duration::minutes(1))
+      .await
+      .unwrap();
+    assert_eq!(claimed.len(), 1);
+    assert_eq!(claimed[0].attempts, 1);
+    db.fail_background_job(job.id, "failed", chrono::Utc::now())
+      .await
+      .unwrap();
+
+    let job = t
+      .http()
+      .get(format!("/api/admin/jobs/{}", job.id))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiBackgroundJob>()
+      .await;
+    assert_eq!(job.status, BackgroundJobStatus::Dead);
+    assert_eq!(job.last_error.as_deref(), Some("failed"));
+
+    let job = t
+      .http()
+      .post(format!("/api/admin/jobs/{}/retry", job.id))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiBackgroundJob>()
+      .await;
+    assert_eq!(job.status, BackgroundJobStatus::Pending);
+    assert_eq!(job.attempts, 0);
+
+    t.http()
+      .post("/api/admin/jobs")
+      .body_json(json!({
+        "kind": "npmTarballBuild",
+        "scope": "scope",
+        "package": "foo",
+        "version": "9.9.9",
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "packageVersionNotFound")
+      .await;
+
+    t.http()
+      .post("/api/admin/jobs")
+      .body_json(json!({
+        "kind": "npmTarballBuild",
+        "scope": "scope",
+        "package": "foo",
+        "version": "1.2.3",
+        "maxAttempts": 0,
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err(StatusCode::BAD_REQUEST)
+      .await;
+
+    t.http()
+      .get("/api/admin/jobs?status=unknown")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err(StatusCode::BAD_REQUEST)
+      .await;
+
+    t.http()
+      .get(format!("/api/admin/jobs/{}", uuid::Uuid::nil()))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "backgroundJobNotFound")
+      .await;
+
+    let token = t.user1.token.clone();
+    t.http()
+      .get("/api/admin/jobs")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err(StatusCode::FORBIDDEN)
+      .await;
+  }
+
+  #[tokio::test]
+  async fn assign_scope() {
+    let mut t = TestSetup::new().await;
+
+    // create a scope for a user2
+    let path = "/api/admin/scopes";
+    let token = t.staff_user.token.clone();
+    let user2_id = t.user2.user.id;
+    let scope = t
+      .http()
+      .post(path)
+      .body_json(json!({
+        "scope": "test-scope",
+        "userId": user2_id,
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiScope>()
+      .await;
+    assert_eq!(scope.scope.to_string(), "test-scope");
+
+    // create a scope with a reserved name
+    let res = t
+      .http()
+      .post(path)
+      .body_json(json!({
+        "scope": "react",
+        "userId": user2_id,
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiScope>()
+      .await;
+    assert_eq!(res.scope.to_string(), "react");
+
+    // create a scope with an existing name
+    t.http()
+      .post(path)
+      .body_json(json!({
+        "scope": "test-scope",
+        "userId": user2_id,
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::CONFLICT, "scopeAlreadyExists")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn reserved_names() {
+    let mut t = TestSetup::new().await;
+
+    let token = t.staff_user.token.clone();
+    let reserved = t
+      .http()
+      .post("/api/admin/reserved_names")
+      .body_json(json!({
+        "kind": "package",
+        "name": "trademarked",
+        "reason": "Trademark dispute",
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiReservedName>()
+      .await;
+    assert_eq!(reserved.kind, ReservedNameKind::Package);
+    assert_eq!(reserved.reason.as_deref(), Some("Trademark dispute"));
+
+    // scope names are stored without hyphens
+    let reserved = t
+      .http()
+      .post("/api/admin/reserved_names")
+      .body_json(json!({ "kind": "scope", "name": "my-brand" }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiReservedName>()
+      .await;
+    assert_eq!(reserved.name, "mybrand");
+
+    t.http()
+      .post("/api/admin/reserved_names")
+      .body_json(json!({ "kind": "scope", "name": "Not A Scope" }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+
+    let list = t
+      .http()
+      .get("/api/admin/reserved_names?kind=package")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiList<ApiReservedName>>()
+      .await;
+    assert_eq!(list.total, 1);
+    assert_eq!(list.items[0].name, "trademarked");
+
+    t.http()
+      .post("/api/scopes/scope/packages")
+      .body_json(json!({ "package": "trademarked" }))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "packageNameReserved")
+      .await;
+    t.http()
+      .post("/api/scopes")
+      .body_json(json!({ "scope": "mybrand", "description": "" }))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "scopeNameReserved")
+      .await;
+
+    // an expired reservation no longer applies
+    t.http()
+      .post("/api/admin/reserved_names")
+      .body_json(json!({
+        "kind": "package",
+        "name": "trademarked",
+        "expiresAt": "2020-01-01T00:00:00Z",
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiReservedName>()
+      .await;
+    t.http()
+      .post("/api/scopes/scope/packages")
+      .body_json(json!({ "package": "trademarked" }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPackage>()
+      .await;
+
+    t.http()
+      .delete("/api/admin/reserved_names/scope/my-brand")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok_no_content()
+      .await;
+    t.http()
+      .delete("/api/admin/reserved_names/scope/my-brand")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "reservedNameNotFound")
+      .await;
+    t.http()
+      .post("/api/scopes")
+      .body_json(json!({ "scope": "my-brand", "description": "" }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiScope>()
+      .await;
+
+    let token = t.user1.token.clone();
+    t.http()
+      .get("/api/admin/reserved_names")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err(StatusCode::FORBIDDEN)
+      .await;
+  }
+
+  #[tokio::test]
+  async fn rate_limits() {
+    let mut t = TestSetup::new().await;
+
+    let token = t.staff_user.token.clone();
+    let rate_limits = t
+      .http()
+      .get("/api/admin/rate_limits")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiRateLimits>()
+      .await;
+    assert_eq!(rate_limits.tiers.len(), 3);
+    assert!(rate_limits.scopes.is_empty());
+
+    let rate_limit = t
+      .http()
+      .put("/api/admin/rate_limits/scopes/scope")
+      .body_json(json!({ "requestsPerMinute": 2, "reason": "abuse" }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiScopeRateLimit>()
+      .await;
+    assert_eq!(rate_limit.requests_per_minute, 2);
+
+    t.http()
+      .put("/api/admin/rate_limits/scopes/doesnotexist")
+      .body_json(json!({ "requestsPerMinute": 2 }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "scopeNotFound")
+      .await;
+    t.http()
+      .put("/api/admin/rate_limits/tiers/user")
+      .body_json(json!({ "requestsPerMinute": 0 }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+
+    // requests to the scope are limited separately from other requests
+    for remaining in ["1", "0"] {
+      let resp = t.http().get("/api/scopes/scope").call().await.unwrap();
+      assert_eq!(resp.status(), StatusCode::OK);
+      assert_eq!(resp.headers()["ratelimit-limit"], "2");
+      assert_eq!(resp.headers()["ratelimit-remaining"], remaining);
+    }
+    let mut resp = t.http().get("/api/scopes/scope").call().await.unwrap();
+    assert!(resp.headers().contains_key("retry-after"));
+    resp
+      .expect_err_code(StatusCode::TOO_MANY_REQUESTS, "rateLimitExceeded")
+      .await;
+    let resp = t.http().get("/api/user").call().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()["ratelimit-limit"], "1200");
+
+    t.http()
+      .delete("/api/admin/rate_limits/scopes/scope")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok_no_content()
+      .await;
+    t.http()
+      .delete("/api/admin/rate_limits/scopes/scope")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "rateLimitOverrideNotFound")
+      .await;
+
+    let tier = t
+      .http()
+      .put("/api/admin/rate_limits/tiers/user")
+      .body_json(json!({ "requestsPerMinute": 5000 }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiRateLimitTier>()
+      .await;
+    assert_eq!(tier.tier, RateLimitTier::User);
+    let resp = t.http().get("/api/scopes/scope").call().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()["ratelimit-limit"], "5000");
+
+    let token = t.user1.token.clone();
+    t.http()
+      .get("/api/admin/rate_limits")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err(StatusCode::FORBIDDEN)
+      .await;
+  }
+
+  #[tokio::test]
+  async fn feature_flags() {
+    let mut t = TestSetup::new().await;
+
+    let token = t.staff_user.token.clone();
+    let flags = t
+      .http()
+      .get("/api/admin/feature_flags")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<Vec<ApiFeatureFlag>>()
+      .await;
+    assert!(
+      flags
+        .iter()
+        .any(|flag| flag.name == UNSTABLE_BYTES_IMPORTS && !flag.enabled)
+    );
+
+    let scope = ScopeName::try_from("scope").unwrap();
+    let other_scope = ScopeName::try_from("other").unwrap();
+    let flag = t
+      .http()
+      .put("/api/admin/feature_flags/new-thing")
+      .body_json(json!({ "enabled": false, "scopes": ["scope"] }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiFeatureFlag>()
+      .await;
+    assert_eq!(flag.scopes, vec![scope.clone()]);
+
+    let feature_flags = FeatureFlags::new();
+    let db = t.db();
+    assert!(
+      feature_flags
+        .is_enabled(&db, "new-thing", Some(&scope), None)
+        .await
+        .unwrap()
+    );
+    assert!(
+      !feature_flags
+        .is_enabled(&db, "new-thing", Some(&other_scope), None)
+        .await
+        .unwrap()
+    );
+    assert!(
+      !feature_flags
+        .is_enabled(&db, "does-not-exist", Some(&scope), None)
+        .await
+        .unwrap()
+    );
+
+    let user_id = t.user1.user.id;
+    let flag = t
+      .http()
+      .put("/api/admin/feature_flags/new-thing")
+      .body_json(json!({ "enabled": false, "userIds": [user_id] }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiFeatureFlag>()
+      .await;
+    assert!(flag.scopes.is_empty());
+    feature_flags.invalidate().await;
+    assert!(
+      feature_flags
+        .is_enabled(&db, "new-thing", Some(&other_scope), Some(user_id))
+        .await
+        .unwrap()
+    );
+    assert!(
+      !feature_flags
+        .is_enabled(&db, "new-thing", Some(&scope), None)
+        .await
+        .unwrap()
+    );
+
+    t.http()
+      .put("/api/admin/feature_flags/Not_Kebab")
+      .body_json(json!({ "enabled": true }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+
+    t.http()
+      .delete("/api/admin/feature_flags/new-thing")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok_no_content()
+      .await;
+    t.http()
+      .get("/api/admin/feature_flags/new-thing")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "featureFlagNotFound")
+      .await;
+
+    let token = t.user1.token.clone();
+    t.http()
+      .get("/api/admin/feature_flags")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err(StatusCode::FORBIDDEN)
+      .await;
+  }
+
+  #[tokio::test]
+  async fn tombstones() {
+    let mut t = TestSetup::new().await;
+
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{:?}", task);
+
+    let token = t.staff_user.token.clone();
+    t.http()
+      .delete("/api/scopes/scope/packages/foo/versions/1.2.3")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok_no_content()
+      .await;
+    t.http()
+      .get("/api/scopes/scope/packages/foo/versions/1.2.3")
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "packageVersionNotFound")
+      .await;
+
+    // Its artifacts are no longer served, but kept.
+    let scope = ScopeName::try_from("scope").unwrap();
+    let package = PackageName::try_from("foo").unwrap();
+    let version = Version::try_from("1.2.3").unwrap();
+    let meta_path =
+      crate::s3_paths::version_metadata(&scope, &package, &version);
+    let deleted_path = format!(
+      "{}modules/{meta_path}",
+      crate::s3_paths::quarantine_directory(&scope, &package, &version)
+    );
+    let buckets = t.buckets();
+    assert!(
+      buckets
+        .modules_bucket
+        .download(meta_path.clone().into())
+        .await
+        .unwrap()
+        .is_none()
+    );
+    assert!(
+      buckets
+        .publishing_bucket
+        .download(deleted_path.clone().into())
+        .await
+        .unwrap()
+        .is_some()
+    );
+
+    // Once all versions are deleted, the package can be deleted too.
+    t.http()
+      .delete("/api/scopes/scope/packages/foo")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok_no_content()
+      .await;
+    t.http()
+      .get("/api/scopes/scope/packages/foo")
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "packageNotFound")
+      .await;
+    t.http()
+      .post("/api/scopes/scope/packages")
+      .body_json(json!({ "package": "foo" }))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::CONFLICT, "packageDeleted")
+      .await;
+
+    let tombstones = t
+      .http()
+      .get("/api/admin/tombstones")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<Vec<ApiTombstone>>()
+      .await;
+    assert_eq!(tombstones.len(), 2);
+    assert_eq!(tombstones[0].version, None);
+    assert_eq!(
+      tombstones[1].version,
+      Some(Version::try_from("1.2.3").unwrap())
+    );
+    assert_eq!(
+      tombstones[1].purge_at,
+      tombstones[1].deleted_at + chrono::Duration::days(30)
+    );
+
+    // A version can not be restored while its package is deleted.
+    t.http()
+      .post("/api/admin/packages/scope/foo/versions/1.2.3/restore")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "packageVersionNotFound")
+      .await;
+
+    let package = t
+      .http()
+      .post("/api/admin/packages/scope/foo/restore")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPackage>()
+      .await;
+    assert_eq!(package.latest_version, None);
+
+    let version = t
+      .http()
+      .post("/api/admin/packages/scope/foo/versions/1.2.3/restore")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPackageVersion>()
+      .await;
+    assert_eq!(version.version, Version::try_from("1.2.3").unwrap());
+    t.http()
+      .get("/api/scopes/scope/packages/foo/versions/1.2.3")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPackageVersion>()
+      .await;
+    assert!(
+      buckets
+        .modules_bucket
+        .download(meta_path.into())
+        .await
+        .unwrap()
+        .is_some()
+    );
+    assert!(
+      buckets
+        .publishing_bucket
+        .download(deleted_path.into())
+        .await
+        .unwrap()
+        .is_none()
+    );
+
+    let tombstones = t
+      .http()
+      .get("/api/admin/tombstones")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<Vec<ApiTombstone>>()
+      .await;
+    assert!(tombstones.is_empty());
+
+    let token = t.user1.token.clone();
+    t.http()
+      .post("/api/admin/packages/scope/foo/restore")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err(StatusCode::FORBIDDEN)
+      .await;
+  }
+
+  #[tokio::test]
+  async fn publishing_task_tarball() {
+    let mut t = TestSetup::new().await;
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+
+    let token = t.staff_user.token.clone();
+    let signed = t
+      .http()
+      .get(format!("/api/admin/publishing_tasks/{}/tarball", task.id))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiSignedUrl>()
+      .await;
+    assert!(signed.expires_at > chrono::Utc::now());
+
+    // The tarball is downloaded straight from the bucket.
+    let resp = crate::util::shared_http_client()
+      .get(&signed.url)
+      .send()
+      .await
+      .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+      resp.headers()["content-disposition"],
+      "attachment; filename=\"scope-foo-1.2.3.tgz\""
+    );
+
+    let token = t.user1.token.clone();
+    t.http()
+      .get(format!("/api/admin/publishing_tasks/{}/tarball", task.id))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err(StatusCode::FORBIDDEN)
+      .await;
+  }
+
+  #[tokio::test]
+  async fn abuse_reports_and_quarantines() {
+    let mut t = TestSetup::new().await;
+
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{:?}", task);
+
+    let token = t.user2.token.clone();
+    let report = t
+      .http()
+      .post("/api/reports")
+      .body_json(json!({
+        "scope": "scope",
+        "package": "foo",
+        "version": "1.2.3",
+        "reason": "malware",
+        "description": "Sends environment variables to a remote server.",
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiAbuseReport>()
+      .await;
+
+    let staff_token = t.staff_user.token.clone();
+    let reports = t
+      .http()
+      .get("/api/admin/reports?status=reported")
+      .token(Some(&staff_token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiList<ApiAbuseReport>>()
+      .await;
+    assert_eq!(reports.total, 1);
+    assert_eq!(reports.items[0].id, report.id);
+
+    // A report is reviewed before it is actioned.
+    t.http()
+      .patch(format!("/api/admin/reports/{}", report.id))
+      .body_json(json!({ "status": "actioned" }))
+      .token(Some(&staff_token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::CONFLICT, "invalidAbuseReportTransition")
+      .await;
+    let report = t
+      .http()
+      .patch(format!("/api/admin/reports/{}", report.id))
+      .body_json(json!({ "status": "underReview" }))
+      .token(Some(&staff_token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiAbuseReport>()
+      .await;
+    assert_eq!(report.status, AbuseReportStatus::UnderReview);
+    assert_eq!(report.reviewer_id, Some(t.staff_user.user.id));
+    assert_eq!(report.resolved_at, None);
+
+    let quarantine = t
+      .http()
+      .post("/api/admin/packages/scope/foo/versions/1.2.3/quarantine")
+      .body_json(json!({
+        "reason": "Exfiltrates credentials.",
+        "reportId": report.id,
+      }))
+      .token(Some(&staff_token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPackageVersionQuarantine>()
+      .await;
+    assert_eq!(quarantine.report_id, Some(report.id));
+    t.http()
+      .post("/api/admin/packages/scope/foo/versions/1.2.3/quarantine")
+      .body_json(json!({ "reason": "Exfiltrates credentials." }))
+      .token(Some(&staff_token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::CONFLICT, "packageVersionAlreadyQuarantined")
+      .await;
+
+    // The version is blocked from being downloaded, but kept.
+    t.http()
+      .get("/api/scopes/scope/packages/foo/versions/1.2.3/tarball")
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::GONE, "packageVersionQuarantined")
+      .await;
+    let scope = ScopeName::try_from("scope").unwrap();
+    let package = PackageName::try_from("foo").unwrap();
+    let version = Version::try_from("1.2.3").unwrap();
+    let meta_path =
+      crate::s3_paths::version_metadata(&scope, &package, &version);
+    let quarantine_path = format!(
+      "{}modules/{meta_path}",
+      crate::s3_paths::quarantine_directory(&scope, &package, &version)
+    );
+    let buckets = t.buckets();
+    assert!(
+      buckets
+        .modules_bucket
+        .download(meta_path.clone().into())
+        .await
+        .unwrap()
+        .is_none()
+    );
+    assert!(
+      buckets
+        .publishing_bucket
+        .download(quarantine_path.clone().into())
+        .await
+        .unwrap()
+        .is_some()
+    );
+    let metadata = PackageMetadata::create(&t.db(), &scope, &package)
+      .await
+      .unwrap();
+    assert!(metadata.versions.is_empty());
+
+    let quarantines = t
+      .http()
+      .get("/api/admin/quarantines")
+      .token(Some(&staff_token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiList<ApiPackageVersionQuarantine>>()
+      .await;
+    assert_eq!(quarantines.total, 1);
+
+    let report = t
+      .http()
+      .patch(format!("/api/admin/reports/{}", report.id))
+      .body_json(json!({
+        "status": "actioned",
+        "resolution": "Quarantined 1.2.3.",
+      }))
+      .token(Some(&staff_token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiAbuseReport>()
+      .await;
+    assert_eq!(report.status, AbuseReportStatus::Actioned);
+    assert_eq!(report.resolution.as_deref(), Some("Quarantined 1.2.3."));
+    assert!(report.resolved_at.is_some());
+
+    t.http()
+      .delete("/api/admin/packages/scope/foo/versions/1.2.3/quarantine")
+      .token(Some(&staff_token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok_no_content()
+      .await;
+    assert!(
+      buckets
+        .modules_bucket
+        .download(meta_path.into())
+        .await
+        .unwrap()
+        .is_some()
+    );
+    assert!(
+      buckets
+        .publishing_bucket
+        .download(quarantine_path.into())
+        .await
+        .unwrap()
+        .is_none()
+    );
+    let metadata = PackageMetadata::create(&t.db(), &scope, &package)
+      .await
+      .unwrap();
+    assert!(metadata.versions.contains_key(&version));
+    t.http()
+      .delete("/api/admin/packages/scope/foo/versions/1.2.3/quarantine")
+      .token(Some(&staff_token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "packageVersionNotQuarantined")
+      .await;
+
+    t.http()
+      .get("/api/admin/reports")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err(StatusCode::FORBIDDEN)
       .await;
   }
 }
@@ -0,0 +1,358 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+use std::fmt::Write;
+
+use hyper::Body;
+use hyper::Request;
+use hyper::Response;
+use routerify::Router;
+use routerify::prelude::RequestExt;
+use routerify_query::RequestQueryExt;
+use tracing::instrument;
+
+use crate::RegistryUrl;
+use crate::db::Announcement;
+use crate::db::Database;
+use crate::util;
+use crate::util::ApiResult;
+use crate::util::CacheDuration;
+
+use super::ApiAnnouncement;
+use super::ApiError;
+
+/// The number of announcements returned by the list endpoint and the feed.
+const ANNOUNCEMENTS_LIMIT: i64 = 50;
+
+pub fn announcements_router() -> Router<Body, ApiError> {
+  Router::builder()
+    .get(
+      "/",
+      util::cache_shared(CacheDuration::ONE_MINUTE, util::json(list_handler)),
+    )
+    .get(
+      "/feed.xml",
+      util::cache_shared(CacheDuration::ONE_MINUTE, feed_handler),
+    )
+    .build()
+    .unwrap()
+}
+
+/// Reads the `feature` and `active` query parameters shared by the list
+/// endpoint and the feed.
+async fn list_announcements(
+  req: &Request<Body>,
+) -> ApiResult<Vec<Announcement>> {
+  let db = req.data::<Database>().unwrap();
+  let feature = req.query("feature").map(|feature| feature.as_str());
+  let active_only = req.query("active").is_some_and(|active| active == "true");
+
+  let announcements = db
+    .list_announcements(feature, active_only, ANNOUNCEMENTS_LIMIT)
+    .await?;
+  Ok(announcements)
+}
+
+#[instrument(name = "GET /api/announcements", skip(req))]
+pub async fn list_handler(
+  req: Request<Body>,
+) -> ApiResult<Vec<ApiAnnouncement>> {
+  let announcements = list_announcements(&req).await?;
+  Ok(announcements.into_iter().map(|a| a.into()).collect())
+}
+
+/// Serves the announcements as an Atom feed, for feed readers and status page
+/// integrations.
+#[instrument(name = "GET /api/announcements/feed.xml", skip(req))]
+pub async fn feed_handler(req: Request<Body>) -> ApiResult<Response<Body>> {
+  let registry_url = &req.data::<RegistryUrl>().unwrap().0;
+  let announcements = list_announcements(&req).await?;
+
+  let feed = render_atom_feed(registry_url, &announcements);
+
+  let response = Response::builder()
+    .header("Content-Type", "application/atom+xml; charset=utf-8")
+    .body(Body::from(feed))
+    .unwrap();
+  Ok(response)
+}
+
+fn render_atom_feed(
+  registry_url: &url::Url,
+  announcements: &[Announcement],
+) -> String {
+  let updated = announcements
+    .iter()
+    .map(|announcement| announcement.updated_at)
+    .max()
+    .unwrap_or(chrono::DateTime::UNIX_EPOCH);
+
+  let mut feed = String::new();
+  feed.push_str(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+  feed.push('\n');
+  feed.push_str(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#);
+  feed.push('\n');
+  writeln!(feed, "  <id>{registry_url}announcements</id>").unwrap();
+  feed.push_str("  <title>JSR announcements</title>\n");
+  writeln!(feed, "  <updated>{}</updated>", updated.to_rfc3339()).unwrap();
+  writeln!(feed, r#"  <link rel="alternate" href="{registry_url}"/>"#).unwrap();
+
+  for announcement in announcements {
+    feed.push_str("  <entry>\n");
+    writeln!(feed, "    <id>urn:uuid:{}</id>", announcement.id).unwrap();
+    writeln!(
+      feed,
+      "    <title>{}</title>",
+      xml_escape(&announcement.title)
+    )
+    .unwrap();
+    writeln!(
+      feed,
+      "    <published>{}</published>",
+      announcement.created_at.to_rfc3339()
+    )
+    .unwrap();
+    writeln!(
+      feed,
+      "    <updated>{}</updated>",
+      announcement.updated_at.to_rfc3339()
+    )
+    .unwrap();
+    let severity = serde_json::to_value(announcement.severity).unwrap();
+    writeln!(
+      feed,
+      r#"    <category scheme="{registry_url}announcements/severity" term="{}"/>"#,
+      severity.as_str().unwrap()
+    )
+    .unwrap();
+    for feature in &announcement.features {
+      writeln!(
+        feed,
+        r#"    <category scheme="{registry_url}announcements/feature" term="{}"/>"#,
+        xml_escape(feature)
+      )
+      .unwrap();
+    }
+    writeln!(
+      feed,
+      r#"    <content type="text">{}</content>"#,
+      xml_escape(&announcement.body)
+    )
+    .unwrap();
+    feed.push_str("  </entry>\n");
+  }
+
+  feed.push_str("</feed>\n");
+  feed
+}
+
+pub(super) fn xml_escape(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&apos;"),
+      c => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::StatusCode;
+  use serde_json::json;
+
+  use crate::api::ApiAnnouncement;
+  use crate::db::AnnouncementSeverity;
+  use crate::util::test::ApiResultExt;
+  use crate::util::test::TestSetup;
+
+  #[test]
+  fn xml_escape() {
+    assert_eq!(
+      super::xml_escape(r#"<a href="x">Tom & Jerry's</a>"#),
+      "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;"
+    );
+  }
+
+  #[tokio::test]
+  async fn announcements() {
+    let mut t = TestSetup::new().await;
+
+    let token = t.staff_user.token.clone();
+    let publish_incident = t
+      .http()
+      .post("/api/admin/announcements")
+      .body_json(json!({
+        "title": "Publishing is degraded",
+        "body": "Publishes may take longer than usual.",
+        "severity": "critical",
+        "features": ["publish"],
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiAnnouncement>()
+      .await;
+    assert_eq!(publish_incident.severity, AnnouncementSeverity::Critical);
+    assert_eq!(publish_incident.features, vec!["publish".to_string()]);
+
+    let general = t
+      .http()
+      .post("/api/admin/announcements")
+      .body_json(json!({
+        "title": "New terms of use",
+        "body": "We updated our terms of use.",
+        "severity": "info",
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiAnnouncement>()
+      .await;
+
+    t.http()
+      .post("/api/admin/announcements")
+      .body_json(json!({
+        "title": "npm tarballs are stale",
+        "body": "...",
+        "severity": "warning",
+        "features": ["npm"],
+        "expiresAt": "2000-01-01T00:00:00Z",
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiAnnouncement>()
+      .await;
+
+    t.http()
+      .post("/api/admin/announcements")
+      .body_json(json!({
+        "title": "Invalid",
+        "body": "...",
+        "severity": "info",
+        "features": ["Not A Tag"],
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+
+    let announcements = t
+      .http()
+      .get("/api/announcements")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<Vec<ApiAnnouncement>>()
+      .await;
+    assert_eq!(announcements.len(), 3);
+
+    // announcements without features apply to every feature
+    let announcements = t
+      .http()
+      .get("/api/announcements?feature=publish&active=true")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<Vec<ApiAnnouncement>>()
+      .await;
+    let ids = announcements.iter().map(|a| a.id).collect::<Vec<_>>();
+    assert_eq!(ids, vec![general.id, publish_incident.id]);
+
+    // expired announcements are only left out of the active list
+    let announcements = t
+      .http()
+      .get("/api/announcements?feature=npm&active=true")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<Vec<ApiAnnouncement>>()
+      .await;
+    assert_eq!(announcements.len(), 1);
+    assert_eq!(announcements[0].id, general.id);
+
+    let mut resp = t
+      .http()
+      .get("/api/announcements/feed.xml?feature=publish")
+      .call()
+      .await
+      .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bytes = hyper::body::to_bytes(resp.body_mut()).await.unwrap();
+    let feed = std::str::from_utf8(&bytes).unwrap();
+    assert!(feed.contains(&format!("urn:uuid:{}", publish_incident.id)));
+    assert!(feed.contains(r#"term="critical""#));
+    assert!(feed.contains(r#"term="publish""#));
+
+    let updated = t
+      .http()
+      .put(format!("/api/admin/announcements/{}", publish_incident.id))
+      .body_json(json!({
+        "title": "Publishing is degraded (resolved)",
+        "body": "Publishing is back to normal.",
+        "severity": "info",
+        "features": ["publish"],
+        "expiresAt": "2000-01-01T00:00:00Z",
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiAnnouncement>()
+      .await;
+    assert_eq!(updated.severity, AnnouncementSeverity::Info);
+    assert!(updated.expires_at.is_some());
+
+    t.http()
+      .delete(format!("/api/admin/announcements/{}", general.id))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok_no_content()
+      .await;
+
+    let announcements = t
+      .http()
+      .get("/api/announcements?active=true")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<Vec<ApiAnnouncement>>()
+      .await;
+    assert!(announcements.is_empty());
+
+    t.http()
+      .delete(format!("/api/admin/announcements/{}", general.id))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "announcementNotFound")
+      .await;
+
+    let token = t.user1.token.clone();
+    t.http()
+      .post("/api/admin/announcements")
+      .body_json(json!({
+        "title": "Not staff",
+        "body": "...",
+        "severity": "info",
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err(StatusCode::FORBIDDEN)
+      .await;
+  }
+}
@@ -0,0 +1,320 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+//! Dist tags are named release channels of a package, like `beta` or
+//! `canary`, that point at a version. They are part of the package's
+//! `meta.json` and of its npm `dist-tags`. A `latest` tag overrides the
+//! version that is otherwise picked as the latest one.
+
+use deno_semver::VersionReq;
+use hyper::Body;
+use hyper::Request;
+use hyper::Response;
+use hyper::StatusCode;
+use routerify::prelude::RequestExt;
+use tracing::Span;
+use tracing::field;
+use tracing::instrument;
+
+use crate::NpmUrl;
+use crate::RegistryUrl;
+use crate::cache_purge::CachePurge;
+use crate::db::Database;
+use crate::iam::ReqIamExt;
+use crate::ids::PackageName;
+use crate::ids::ScopeName;
+use crate::npm::NpmSigner;
+use crate::npm::upload_npm_version_manifest;
+use crate::publish::upload_package_manifest;
+use crate::s3::Buckets;
+use crate::util::ApiResult;
+use crate::util::RequestIdExt;
+use crate::util::decode_json;
+use crate::util::param;
+
+use super::ApiDistTag;
+use super::ApiError;
+use super::ApiSetDistTagRequest;
+
+const MAX_DIST_TAG_LENGTH: usize = 32;
+
+/// Tags must not be mistakable for versions or version ranges in places that
+/// accept both, like `npm install @jsr/scope__pkg@<tag>`.
+fn validate_dist_tag(tag: &str) -> Result<(), ApiError> {
+  let valid = tag.len() <= MAX_DIST_TAG_LENGTH
+    && tag.starts_with(|c: char| c.is_ascii_lowercase())
+    && tag
+      .chars()
+      .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    && VersionReq::parse_from_specifier(tag).is_err();
+  if !valid {
+    return Err(ApiError::DistTagInvalid {
+      tag: tag.to_string(),
+    });
+  }
+  Ok(())
+}
+
+#[instrument(
+  name = "GET /api/scopes/:scope/packages/:package/dist_tags",
+  skip(req),
+  fields(scope, package)
+)]
+pub async fn list_handler(req: Request<Body>) -> ApiResult<Vec<ApiDistTag>> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+
+  let db = req.data::<Database>().unwrap();
+  db.get_package(&scope, &package)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+
+  let dist_tags = db.list_package_dist_tags(&scope, &package).await?;
+  Ok(dist_tags.into_iter().map(ApiDistTag::from).collect())
+}
+
+#[instrument(
+  name = "PUT /api/scopes/:scope/packages/:package/dist_tags/:tag",
+  skip(req),
+  fields(scope, package, tag)
+)]
+pub async fn set_handler(mut req: Request<Body>) -> ApiResult<ApiDistTag> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  let tag = param(&req, "tag")?.clone();
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+  Span::current().record("tag", field::display(&tag));
+  validate_dist_tag(&tag)?;
+
+  let ApiSetDistTagRequest { version } = decode_json(&mut req).await?;
+
+  let db = req.data::<Database>().unwrap();
+  let (res_package, _, _) = db
+    .get_package(&scope, &package)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+
+  let iam = req.iam();
+  let (user, sudo) = iam.check_dist_tag_access(&scope, &package).await?;
+
+  if res_package.is_archived {
+    return Err(ApiError::PackageArchived);
+  }
+
+  let package_version = db
+    .get_package_version(&scope, &package, &version)
+    .await?
+    .ok_or(ApiError::PackageVersionNotFound)?;
+  if package_version.is_yanked {
+    return Err(ApiError::MalformedRequest {
+      msg: "yanked versions can not be tagged".into(),
+    });
+  }
+
+  let dist_tag = db
+    .set_package_dist_tag(&user.id, sudo, &scope, &package, &tag, &version)
+    .await?;
+
+  upload_manifests(&req, &scope, &package).await?;
+
+  Ok(dist_tag.into())
+}
+
+#[instrument(
+  name = "DELETE /api/scopes/:scope/packages/:package/dist_tags/:tag",
+  skip(req),
+  fields(scope, package, tag)
+)]
+pub async fn delete_handler(req: Request<Body>) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  let tag = param(&req, "tag")?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+  Span::current().record("tag", field::display(tag));
+
+  let db = req.data::<Database>().unwrap();
+  let (res_package, _, _) = db
+    .get_package(&scope, &package)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+
+  let iam = req.iam();
+  let (user, sudo) = iam.check_dist_tag_access(&scope, &package).await?;
+
+  if res_package.is_archived {
+    return Err(ApiError::PackageArchived);
+  }
+
+  db.delete_package_dist_tag(&user.id, sudo, &scope, &package, tag)
+    .await?
+    .ok_or(ApiError::DistTagNotFound)?;
+
+  upload_manifests(&req, &scope, &package).await?;
+
+  let resp = Response::builder()
+    .status(StatusCode::NO_CONTENT)
+    .body(Body::empty())
+    .unwrap();
+  Ok(resp)
+}
+
+/// Regenerates the `meta.json` and the npm version manifest of the package,
+/// which both include its dist tags.
+async fn upload_manifests(
+  req: &Request<Body>,
+  scope: &ScopeName,
+  package: &PackageName,
+) -> ApiResult<()> {
+  let db = req.data::<Database>().unwrap();
+  let buckets = req.data::<Buckets>().unwrap();
+  let registry_url = &req.data::<RegistryUrl>().unwrap().0;
+  let npm_url = &req.data::<NpmUrl>().unwrap().0;
+  let npm_signer = req.data::<NpmSigner>().unwrap();
+  let cache_purge = req.data::<CachePurge>().unwrap();
+
+  upload_package_manifest(
+    db,
+    buckets,
+    registry_url,
+    cache_purge,
+    scope,
+    package,
+  )
+  .await?;
+  upload_npm_version_manifest(
+    db,
+    buckets,
+    npm_url,
+    npm_signer,
+    cache_purge,
+    scope,
+    package,
+  )
+  .await?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::StatusCode;
+  use serde_json::json;
+
+  use crate::api::ApiDistTag;
+  use crate::db::PublishingTaskStatus;
+  use crate::publish::tests::create_mock_tarball;
+  use crate::publish::tests::process_tarball_setup;
+  use crate::util::test::ApiResultExt;
+  use crate::util::test::TestSetup;
+
+  #[test]
+  fn validate_dist_tag() {
+    for tag in ["latest", "beta", "canary", "next-2", "rc1"] {
+      assert!(super::validate_dist_tag(tag).is_ok(), "{tag}");
+    }
+    for tag in ["", "Beta", "1.2.3", "v1", "x", "beta_1", "-beta", "a.b"] {
+      assert!(super::validate_dist_tag(tag).is_err(), "{tag}");
+    }
+  }
+
+  #[tokio::test]
+  async fn dist_tags() {
+    let mut t = TestSetup::new().await;
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:?}");
+    let token = t.user1.token.clone();
+
+    let dist_tag = t
+      .http()
+      .put("/api/scopes/scope/packages/foo/dist_tags/beta")
+      .body_json(json!({ "version": "1.2.3" }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiDistTag>()
+      .await;
+    assert_eq!(dist_tag.tag, "beta");
+    assert_eq!(dist_tag.version.to_string(), "1.2.3");
+
+    t.http()
+      .put("/api/scopes/scope/packages/foo/dist_tags/canary")
+      .body_json(json!({ "version": "2.0.0" }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "packageVersionNotFound")
+      .await;
+    t.http()
+      .put("/api/scopes/scope/packages/foo/dist_tags/1.x")
+      .body_json(json!({ "version": "1.2.3" }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "distTagInvalid")
+      .await;
+
+    // Only members of the scope can move tags.
+    let token3 = t.user3.token.clone();
+    t.http()
+      .put("/api/scopes/scope/packages/foo/dist_tags/beta")
+      .body_json(json!({ "version": "1.2.3" }))
+      .token(Some(&token3))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::FORBIDDEN, "actorNotScopeMember")
+      .await;
+
+    let dist_tags = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/dist_tags")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<Vec<ApiDistTag>>()
+      .await;
+    assert_eq!(dist_tags.len(), 1);
+    assert_eq!(dist_tags[0].tag, "beta");
+
+    let npm_dist_tags = t
+      .http()
+      .get("/api/npm/-/package/@jsr%2fscope__foo/dist-tags")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<serde_json::Value>()
+      .await;
+    assert_eq!(npm_dist_tags, json!({ "latest": "1.2.3", "beta": "1.2.3" }));
+
+    let meta = t
+      .buckets
+      .modules_bucket
+      .download("@scope/foo/meta.json".into())
+      .await
+      .unwrap()
+      .unwrap();
+    let meta: serde_json::Value = serde_json::from_slice(&meta).unwrap();
+    assert_eq!(meta["tags"], json!({ "beta": "1.2.3" }));
+
+    t.http()
+      .delete("/api/scopes/scope/packages/foo/dist_tags/beta")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok_no_content()
+      .await;
+    t.http()
+      .delete("/api/scopes/scope/packages/foo/dist_tags/beta")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "distTagNotFound")
+      .await;
+  }
+}
@@ -4,7 +4,10 @@ use hyper::Response;
 use std::borrow::Cow;
 
 use super::ApiPublishingTask;
+use crate::db::PackagePermission;
 use crate::errors;
+use crate::ids::PackageName;
+use crate::ids::ScopeName;
 use crate::s3::S3Error;
 
 errors!(
@@ -13,6 +16,11 @@ errors!(
     fields: { size: u64, max_size: u64 },
     ({ size, max_size }) => "The uploaded tarball ({size} bytes) exceeds the maximum allowed size ({max_size} bytes).",
   },
+  ScopeStorageLimitExceeded {
+    status: BAD_REQUEST,
+    fields: { usage: i64, limit: i64 },
+    ({ usage, limit }) => "The scope's storage quota has been exceeded ({usage} of {limit} bytes used). Please delete old versions or contact help@jsr.io to request a higher limit.",
+  },
   // TODO: re-implement
   // PublishMaxFileSizeExceeded {
   //   status: PAYLOAD_TOO_LARGE,
@@ -43,6 +51,10 @@ errors!(
     status: NOT_FOUND,
     "The requested package version was not found.",
   },
+  ProvenanceNotFound {
+    status: NOT_FOUND,
+    "The requested package version has no stored provenance attestation.",
+  },
   DiffNoIndex {
     status: NOT_FOUND,
     "Diffs do not have an index.",
@@ -122,6 +134,15 @@ errors!(
     fields: { limit: i32 },
     ({ limit }) => "Exceeded limit of {limit} new packages for scope.",
   },
+  RateLimitExceeded {
+    status: TOO_MANY_REQUESTS,
+    fields: { limit: u32 },
+    ({ limit }) => "Exceeded the rate limit of {limit} requests per minute. Try again later.",
+  },
+  DirectOriginRequest {
+    status: FORBIDDEN,
+    "The API can only be accessed through the registry's load balancer.",
+  },
 
   ScopeAlreadyExists {
     status: CONFLICT,
@@ -131,6 +152,22 @@ errors!(
     status: CONFLICT,
     "A package with this or a very similar name already exists.",
   },
+  ScopeNameConfusable {
+    status: CONFLICT,
+    fields: { existing: ScopeName },
+    data_fields: { existing },
+    ({ existing }) => "The scope name looks too much like the existing scope @{existing}.",
+  },
+  PackageNameConfusable {
+    status: CONFLICT,
+    fields: { existing: PackageName },
+    data_fields: { existing },
+    ({ existing }) => "The package name looks too much like the existing package {existing} in this scope.",
+  },
+  PackageDeleted {
+    status: CONFLICT,
+    "A package with this name was recently deleted. Contact support to restore it.",
+  },
   AlreadyInvited {
     status: BAD_REQUEST,
     "This user has already been invited to this scope.",
@@ -199,10 +236,25 @@ errors!(
     status: FORBIDDEN,
     "The actor that this request was authenticated for is not authorized as a scope member for this scope.",
   },
+  ActorMissingPackagePermission {
+    status: FORBIDDEN,
+    fields: { permission: PackagePermission },
+    data_fields: { permission },
+    ({ permission }) => "The actor that this request was authenticated for is not in a team of this scope that grants the {permission} permission for this package.",
+  },
   ScopeRequiresPublishingFromCI {
     status: FORBIDDEN,
     "This scope requires that all packages must be published from CI.",
   },
+  ScopeMemberInCooldown {
+    status: FORBIDDEN,
+    fields: { until: String },
+    ({ until }) => "New scope members cannot publish to or change the settings of popular packages until {until}. Another scope admin can lift this restriction early.",
+  },
+  CannotLiftOwnCooldown {
+    status: FORBIDDEN,
+    "A scope member cannot lift their own new member cooldown.",
+  },
   InvalidBearerToken {
     status: UNAUTHORIZED,
     "The provided bearer token is invalid.",
@@ -256,6 +308,10 @@ errors!(
     status: BAD_REQUEST,
     "The provided package name is not allowed.",
   },
+  PackageNameReserved {
+    status: BAD_REQUEST,
+    "The provided package name is reserved. If you want to claim it, please contact help@jsr.io.",
+  },
   PackageArchived {
     status: BAD_REQUEST,
     "The requested package is archived. Unarchive it to modify settings or publish to it.",
@@ -288,6 +344,249 @@ errors!(
     status: BAD_REQUEST,
     "You cannot disconnect the last connected service.",
   },
+  ScoreRecomputeJobNotFound {
+    status: NOT_FOUND,
+    "The requested score recompute job was not found.",
+  },
+  ScoreRecomputeJobAlreadyRunning {
+    status: CONFLICT,
+    "A score recompute job is already running. Wait for it to finish before starting another one.",
+  },
+  NpmTarballRebuildJobNotFound {
+    status: NOT_FOUND,
+    "The requested npm tarball rebuild job was not found.",
+  },
+  NpmTarballRebuildJobAlreadyRunning {
+    status: CONFLICT,
+    "An npm tarball rebuild job is already running. Wait for it to finish before starting another one.",
+  },
+  OrphanGcJobNotFound {
+    status: NOT_FOUND,
+    "The requested orphan GC job was not found.",
+  },
+  OrphanGcJobAlreadyRunning {
+    status: CONFLICT,
+    "An orphan GC job is already running. Wait for it to finish before starting another one.",
+  },
+  IntegrityScrubJobNotFound {
+    status: NOT_FOUND,
+    "The requested integrity scrub job was not found.",
+  },
+  IntegrityScrubJobAlreadyRunning {
+    status: CONFLICT,
+    "An integrity scrub job is already running. Wait for it to finish before starting another one.",
+  },
+  BackgroundJobNotFound {
+    status: NOT_FOUND,
+    "The requested background job was not found.",
+  },
+  BackgroundJobNotDead {
+    status: CONFLICT,
+    "Only dead background jobs can be retried.",
+  },
+  AnnouncementNotFound {
+    status: NOT_FOUND,
+    "The requested announcement was not found.",
+  },
+  ReservedNameNotFound {
+    status: NOT_FOUND,
+    "The requested reserved name was not found.",
+  },
+  RateLimitOverrideNotFound {
+    status: NOT_FOUND,
+    "The requested rate limit override was not found.",
+  },
+  PublishLimitsOverrideNotFound {
+    status: NOT_FOUND,
+    "The requested publish limits override was not found.",
+  },
+  FeatureFlagNotFound {
+    status: NOT_FOUND,
+    "The requested feature flag was not found.",
+  },
+  DependencyTreeTooLarge {
+    status: BAD_REQUEST,
+    fields: { max_items: usize },
+    ({ max_items }) => "The dependency tree has more than {max_items} items. Pass a smaller 'max_depth', or deduplicate the tree.",
+  },
+  UnresolvableSpecifier {
+    status: NOT_FOUND,
+    fields: { specifier: String },
+    ({ specifier }) => "No version of the package matches '{specifier}'.",
+  },
+  LockfileTooLarge {
+    status: BAD_REQUEST,
+    fields: { max_packages: usize },
+    ({ max_packages }) => "The lockfile would contain more than {max_packages} package versions. Request fewer specifiers at once.",
+  },
+  DistTagNotFound {
+    status: NOT_FOUND,
+    "The requested dist tag was not found.",
+  },
+  DistTagInvalid {
+    status: BAD_REQUEST,
+    fields: { tag: String },
+    ({ tag }) => "The dist tag '{tag}' is invalid. Dist tags must start with a lowercase letter, contain only lowercase letters, digits and hyphens, be at most 32 characters long, and must not be a valid version range.",
+  },
+  WebhookNotFound {
+    status: NOT_FOUND,
+    "The requested webhook was not found.",
+  },
+  WebhookLimitReached {
+    status: BAD_REQUEST,
+    fields: { max_webhooks: usize },
+    ({ max_webhooks }) => "This scope has reached the maximum of {max_webhooks} webhooks.",
+  },
+  WebhookInvalid {
+    status: BAD_REQUEST,
+    fields: { msg: Cow<'static, str> },
+    ({ msg }) => "Invalid webhook: {msg}.",
+  },
+  PackageTransferNotFound {
+    status: NOT_FOUND,
+    "The requested package transfer was not found.",
+  },
+  PackageTransferInvalid {
+    status: BAD_REQUEST,
+    fields: { msg: Cow<'static, str> },
+    ({ msg }) => "Invalid package transfer: {msg}.",
+  },
+  PackageMoved {
+    status: MOVED_PERMANENTLY,
+    fields: { scope: ScopeName, package: PackageName },
+    data_fields: { scope, package },
+    ({ scope, package }) => "The requested package was moved to @{scope}/{package}.",
+  },
+  ScopeMoved {
+    status: MOVED_PERMANENTLY,
+    fields: { scope: ScopeName },
+    data_fields: { scope },
+    ({ scope }) => "The requested scope was renamed to @{scope}.",
+  },
+  ScopeRenameNotFound {
+    status: NOT_FOUND,
+    "The requested scope rename was not found.",
+  },
+  ScopeRenameInvalid {
+    status: BAD_REQUEST,
+    fields: { msg: Cow<'static, str> },
+    ({ msg }) => "Invalid scope rename: {msg}.",
+  },
+  ScopeTeamNotFound {
+    status: NOT_FOUND,
+    "The requested team was not found.",
+  },
+  ScopeTeamAlreadyExists {
+    status: CONFLICT,
+    "A team with this name already exists in this scope.",
+  },
+  ScopeTeamInvalid {
+    status: BAD_REQUEST,
+    fields: { msg: Cow<'static, str> },
+    ({ msg }) => "Invalid team: {msg}.",
+  },
+  ScopeTeamMemberNotFound {
+    status: NOT_FOUND,
+    "The requested user is not a member of this team.",
+  },
+  ScopeTeamPackageNotFound {
+    status: NOT_FOUND,
+    "The requested team was not granted permissions on this package.",
+  },
+  OidcIssuerNotFound {
+    status: NOT_FOUND,
+    "The requested OIDC issuer was not found.",
+  },
+  OidcIssuerAlreadyExists {
+    status: CONFLICT,
+    "This OIDC issuer is already trusted by this scope.",
+  },
+  OidcIssuerInvalid {
+    status: BAD_REQUEST,
+    fields: { msg: Cow<'static, str> },
+    ({ msg }) => "Invalid OIDC issuer: {msg}.",
+  },
+  TrustedPublisherNotFound {
+    status: NOT_FOUND,
+    "The requested trusted publisher was not found.",
+  },
+  TrustedPublisherAlreadyExists {
+    status: CONFLICT,
+    "This CI project is already a trusted publisher of this package.",
+  },
+  TrustedPublisherInvalid {
+    status: BAD_REQUEST,
+    fields: { msg: Cow<'static, str> },
+    ({ msg }) => "Invalid trusted publisher: {msg}.",
+  },
+  PublishNotRetryable {
+    status: CONFLICT,
+    "Only a failed publish, or one that was interrupted after its version was created, can be retried. A failed publish can not be retried while another publish of the same version is in progress or succeeded, and a failed package of a workspace publish can only be published again as part of a new publish.",
+  },
+  PublishTarballNotFound {
+    status: NOT_FOUND,
+    "The tarball of this publish is no longer stored. Publish this version again instead.",
+  },
+  PublishUploadNotFound {
+    status: NOT_FOUND,
+    "The requested publish upload was not found, or it has expired.",
+  },
+  PublishUploadOffsetMismatch {
+    status: CONFLICT,
+    fields: { offset: i64 },
+    data_fields: { offset },
+    ({ offset }) => "The chunk must be appended at offset {offset}, the number of bytes uploaded so far.",
+  },
+  PublishUploadChunkTooLarge {
+    status: PAYLOAD_TOO_LARGE,
+    fields: { max_size: u64 },
+    ({ max_size }) => "The uploaded chunk exceeds the maximum allowed size ({max_size} bytes).",
+  },
+  PublishUploadEmpty {
+    status: BAD_REQUEST,
+    "The publish upload can not be committed, because nothing was uploaded yet.",
+  },
+  WorkspacePublishNotFound {
+    status: NOT_FOUND,
+    "The requested workspace publish was not found.",
+  },
+  DataExportNotFound {
+    status: NOT_FOUND,
+    "The requested data export was not found.",
+  },
+  DataExportInProgress {
+    status: CONFLICT,
+    "A data export is already being prepared. Wait for it to finish before requesting another one.",
+  },
+  DataExportNotReady {
+    status: CONFLICT,
+    "The data export can not be downloaded, because it has not completed or has expired.",
+  },
+  UnsubscribeTokenNotFound {
+    status: NOT_FOUND,
+    "The unsubscribe link is invalid.",
+  },
+  AbuseReportNotFound {
+    status: NOT_FOUND,
+    "The requested abuse report was not found.",
+  },
+  InvalidAbuseReportTransition {
+    status: CONFLICT,
+    fields: { from: String, to: String },
+    ({ from, to }) => "The abuse report can not be moved from '{from}' to '{to}'.",
+  },
+  PackageVersionQuarantined {
+    status: GONE,
+    "The requested package version was quarantined by the registry staff and can not be downloaded.",
+  },
+  PackageVersionAlreadyQuarantined {
+    status: CONFLICT,
+    "The package version is already quarantined.",
+  },
+  PackageVersionNotQuarantined {
+    status: NOT_FOUND,
+    "The package version is not quarantined.",
+  },
 );
 
 pub fn map_unique_violation(err: sqlx::Error, new_err: ApiError) -> ApiError {
@@ -425,6 +724,12 @@ impl From<crate::docs::DocNodeCacheError> for ApiError {
   }
 }
 
+impl From<crate::module_files::ModuleFilesError> for ApiError {
+  fn from(error: crate::module_files::ModuleFilesError) -> ApiError {
+    anyhow::Error::from(error).into()
+  }
+}
+
 impl From<S3Error> for ApiError {
   fn from(error: S3Error) -> ApiError {
     anyhow::Error::from(error).into()
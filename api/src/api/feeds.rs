@@ -0,0 +1,213 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+//! Atom feeds of newly published versions, so that releases can be followed
+//! in a feed reader instead of by polling the JSON API.
+
+use std::fmt::Write;
+
+use hyper::Body;
+use hyper::Request;
+use hyper::Response;
+use routerify::prelude::RequestExt;
+use tracing::Span;
+use tracing::field;
+use tracing::instrument;
+
+use crate::RegistryUrl;
+use crate::db::Database;
+use crate::db::PackageVersion;
+use crate::util::ApiResult;
+use crate::util::RequestIdExt;
+
+use super::ApiError;
+use super::announcements::xml_escape;
+
+/// The number of versions in a feed.
+const FEED_LIMIT: i64 = 50;
+
+#[instrument(
+  name = "GET /api/scopes/:scope/packages/:package/feed.xml",
+  skip(req),
+  fields(scope, package)
+)]
+pub async fn package_feed_handler(
+  req: Request<Body>,
+) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+
+  let db = req.data::<Database>().unwrap();
+  let registry_url = &req.data::<RegistryUrl>().unwrap().0;
+
+  let (package, _, _) = db
+    .get_package(&scope, &package)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+  let versions = db
+    .list_recently_published_versions(&scope, Some(&package.name), FEED_LIMIT)
+    .await?;
+
+  let feed = render_atom_feed(
+    registry_url,
+    &format!("@{scope}/{}", package.name),
+    &package.description,
+    &versions,
+  );
+  Ok(atom_response(feed))
+}
+
+#[instrument(
+  name = "GET /api/scopes/:scope/feed.xml",
+  skip(req),
+  fields(scope)
+)]
+pub async fn scope_feed_handler(
+  req: Request<Body>,
+) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+
+  let db = req.data::<Database>().unwrap();
+  let registry_url = &req.data::<RegistryUrl>().unwrap().0;
+
+  let scope = db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?;
+  let versions = db
+    .list_recently_published_versions(&scope.scope, None, FEED_LIMIT)
+    .await?;
+
+  let feed = render_atom_feed(
+    registry_url,
+    &format!("@{}", scope.scope),
+    &scope.description,
+    &versions,
+  );
+  Ok(atom_response(feed))
+}
+
+fn atom_response(feed: String) -> Response<Body> {
+  Response::builder()
+    .header("Content-Type", "application/atom+xml; charset=utf-8")
+    .body(Body::from(feed))
+    .unwrap()
+}
+
+/// Renders a feed with an entry per version. `name` is the scope or package
+/// the feed is for, whose page on the registry doubles as the feed id.
+fn render_atom_feed(
+  registry_url: &url::Url,
+  name: &str,
+  subtitle: &str,
+  versions: &[PackageVersion],
+) -> String {
+  let updated = versions
+    .iter()
+    .map(|version| version.created_at)
+    .max()
+    .unwrap_or(chrono::DateTime::UNIX_EPOCH);
+
+  let mut feed = String::new();
+  feed.push_str(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+  feed.push('\n');
+  feed.push_str(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#);
+  feed.push('\n');
+  let url = xml_escape(&format!("{registry_url}{name}"));
+  writeln!(feed, "  <id>{url}</id>").unwrap();
+  writeln!(feed, "  <title>{} releases</title>", xml_escape(name)).unwrap();
+  if !subtitle.is_empty() {
+    writeln!(feed, "  <subtitle>{}</subtitle>", xml_escape(subtitle)).unwrap();
+  }
+  writeln!(feed, "  <updated>{}</updated>", updated.to_rfc3339()).unwrap();
+  writeln!(feed, r#"  <link rel="alternate" href="{url}"/>"#).unwrap();
+
+  for version in versions {
+    let package = format!("@{}/{}", version.scope, version.name);
+    let version_url =
+      xml_escape(&format!("{registry_url}{package}@{}", version.version));
+    feed.push_str("  <entry>\n");
+    writeln!(feed, "    <id>{version_url}</id>").unwrap();
+    writeln!(
+      feed,
+      "    <title>{}</title>",
+      xml_escape(&format!("{package}@{}", version.version))
+    )
+    .unwrap();
+    writeln!(feed, r#"    <link rel="alternate" href="{version_url}"/>"#)
+      .unwrap();
+    writeln!(
+      feed,
+      "    <published>{}</published>",
+      version.created_at.to_rfc3339()
+    )
+    .unwrap();
+    writeln!(
+      feed,
+      "    <updated>{}</updated>",
+      version.created_at.to_rfc3339()
+    )
+    .unwrap();
+    writeln!(
+      feed,
+      r#"    <content type="text">{} was published.</content>"#,
+      xml_escape(&format!("Version {} of {package}", version.version))
+    )
+    .unwrap();
+    feed.push_str("  </entry>\n");
+  }
+
+  feed.push_str("</feed>\n");
+  feed
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::StatusCode;
+
+  use crate::db::PublishingTaskStatus;
+  use crate::publish::tests::create_mock_tarball;
+  use crate::publish::tests::process_tarball_setup;
+  use crate::util::test::ApiResultExt;
+  use crate::util::test::TestSetup;
+
+  #[tokio::test]
+  async fn feeds() {
+    let mut t = TestSetup::new().await;
+
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:?}");
+
+    for path in [
+      "/api/scopes/scope/packages/foo/feed.xml",
+      "/api/scopes/scope/feed.xml",
+    ] {
+      let mut resp = t.http().get(path).call().await.unwrap();
+      assert_eq!(resp.status(), StatusCode::OK);
+      assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "application/atom+xml; charset=utf-8"
+      );
+      let bytes = hyper::body::to_bytes(resp.body_mut()).await.unwrap();
+      let feed = std::str::from_utf8(&bytes).unwrap();
+      assert!(feed.contains("<title>@scope/foo@1.2.3</title>"), "{feed}");
+      assert!(
+        feed.contains("<id>http://jsr-tests.test/@scope/foo@1.2.3</id>"),
+        "{feed}"
+      );
+    }
+
+    t.http()
+      .get("/api/scopes/scope/packages/bar/feed.xml")
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "packageNotFound")
+      .await;
+    t.http()
+      .get("/api/scopes/scope2/feed.xml")
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "scopeNotFound")
+      .await;
+  }
+}
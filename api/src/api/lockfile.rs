@@ -0,0 +1,332 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::str::FromStr;
+
+use deno_semver::VersionReq;
+use deno_semver::jsr::JsrPackageReqReference;
+use hyper::Body;
+use hyper::Request;
+use routerify::prelude::RequestExt;
+use sha2::Digest;
+use tracing::Span;
+use tracing::instrument;
+
+use crate::db::Database;
+use crate::db::DependencyKind;
+use crate::db::PackageVersionForResolution;
+use crate::db::PackageVisibility;
+use crate::iam::ReqIamExt;
+use crate::ids::ScopedPackageName;
+use crate::ids::Version;
+use crate::s3::Buckets;
+use crate::util::ApiResult;
+use crate::util::decode_json;
+
+use super::ApiError;
+use super::ApiLockfile;
+use super::ApiLockfileJsrPackage;
+use super::ApiLockfileRequest;
+
+/// The largest number of specifiers that can be requested at once.
+const MAX_SPECIFIERS: usize = 100;
+
+/// The largest number of package versions a lockfile fragment may contain,
+/// including transitive dependencies.
+const MAX_PACKAGES: usize = 1000;
+
+/// A `jsr:` package requirement that is still to be resolved, keyed by its
+/// specifier in the lockfile, like `jsr:@std/path@^1`.
+struct PendingReq {
+  key: String,
+  name: ScopedPackageName,
+  version_req: VersionReq,
+}
+
+impl PendingReq {
+  fn new(name: ScopedPackageName, version_req: VersionReq) -> Self {
+    Self {
+      key: format!("jsr:{}@{}", name, version_req.version_text()),
+      name,
+      version_req,
+    }
+  }
+}
+
+/// Resolves `jsr:` specifiers and their transitive `jsr:` dependencies the
+/// same way `deno install` would, and returns the entries for a `deno.lock`,
+/// so that cold installs don't have to fetch the metadata of every package
+/// to resolve it. `npm:` dependencies are left for the client to resolve.
+#[instrument(name = "POST /api/lockfile", skip(req), err, fields(specifiers))]
+pub async fn lockfile_handler(
+  mut req: Request<Body>,
+) -> ApiResult<ApiLockfile> {
+  let ApiLockfileRequest { specifiers } = decode_json(&mut req).await?;
+  Span::current().record("specifiers", specifiers.len());
+
+  if specifiers.is_empty() || specifiers.len() > MAX_SPECIFIERS {
+    return Err(ApiError::MalformedRequest {
+      msg: format!("expected between 1 and {MAX_SPECIFIERS} specifiers").into(),
+    });
+  }
+
+  let mut queue = VecDeque::new();
+  for specifier in &specifiers {
+    let reference =
+      JsrPackageReqReference::from_str(specifier).map_err(|err| {
+        ApiError::MalformedRequest {
+          msg: format!("invalid jsr specifier '{specifier}': {err}").into(),
+        }
+      })?;
+    let package_req = reference.into_inner().req;
+    let name =
+      ScopedPackageName::new(package_req.name.to_string()).map_err(|err| {
+        ApiError::MalformedRequest {
+          msg: format!("invalid jsr specifier '{specifier}': {err}").into(),
+        }
+      })?;
+    queue.push_back(PendingReq::new(name, package_req.version_req));
+  }
+
+  let db = req.data::<Database>().unwrap();
+  let buckets = req.data::<Buckets>().unwrap();
+  let iam = req.iam();
+
+  let mut versions_by_name =
+    HashMap::<ScopedPackageName, Vec<PackageVersionForResolution>>::new();
+  let mut resolved = BTreeMap::<String, (ScopedPackageName, Version)>::new();
+  // Keyed by `@scope/name@version`, with the integrity and the lockfile keys
+  // of the dependencies.
+  let mut packages = BTreeMap::<String, (String, Vec<String>)>::new();
+
+  while let Some(pending) = queue.pop_front() {
+    if resolved.contains_key(&pending.key) {
+      continue;
+    }
+
+    if !versions_by_name.contains_key(&pending.name) {
+      let visibility = db
+        .get_package_visibility(&pending.name.scope, &pending.name.package)
+        .await?;
+      let readable = match visibility {
+        Some(PackageVisibility::Public) => true,
+        Some(PackageVisibility::Private) => iam
+          .check_private_package_read_access(&pending.name.scope)
+          .await
+          .is_ok(),
+        None => false,
+      };
+      let versions = if readable {
+        db.list_package_versions_for_resolution(
+          &pending.name.scope,
+          &pending.name.package,
+        )
+        .await?
+      } else {
+        vec![]
+      };
+      versions_by_name.insert(pending.name.clone(), versions);
+    }
+
+    let version = versions_by_name[&pending.name]
+      .iter()
+      .filter(|version| {
+        !version.is_yanked && pending.version_req.matches(&version.version.0)
+      })
+      .map(|version| version.version.clone())
+      .max()
+      .ok_or_else(|| ApiError::UnresolvableSpecifier {
+        specifier: pending.key.clone(),
+      })?;
+
+    let nv = format!("{}@{}", pending.name, version);
+    resolved.insert(pending.key, (pending.name.clone(), version.clone()));
+    if packages.contains_key(&nv) {
+      continue;
+    }
+    if packages.len() >= MAX_PACKAGES {
+      return Err(ApiError::LockfileTooLarge {
+        max_packages: MAX_PACKAGES,
+      });
+    }
+
+    let scope = &pending.name.scope;
+    let package = &pending.name.package;
+    let meta = buckets
+      .modules_bucket
+      .download(
+        crate::s3_paths::version_metadata(scope, package, &version).into(),
+      )
+      .await?
+      .ok_or(ApiError::PackageVersionNotFound)?;
+    let integrity = format!("{:x}", sha2::Sha256::digest(&meta));
+
+    let mut dependencies = vec![];
+    for dep in db
+      .list_package_version_dependencies(scope, package, &version)
+      .await?
+    {
+      if dep.dependency_kind != DependencyKind::Jsr {
+        continue;
+      }
+      let (Ok(name), Ok(version_req)) = (
+        ScopedPackageName::new(dep.dependency_name),
+        VersionReq::parse_from_specifier(&dep.dependency_constraint),
+      ) else {
+        continue;
+      };
+      let dependency = PendingReq::new(name, version_req);
+      if !dependencies.contains(&dependency.key) {
+        dependencies.push(dependency.key.clone());
+        queue.push_back(dependency);
+      }
+    }
+
+    packages.insert(nv, (integrity, dependencies));
+  }
+
+  // Like `deno.lock`, refer to a dependency by name alone unless several
+  // versions of it are locked.
+  let mut distinct_versions =
+    HashMap::<&ScopedPackageName, Vec<&Version>>::new();
+  for (name, version) in resolved.values() {
+    let versions = distinct_versions.entry(name).or_default();
+    if !versions.contains(&version) {
+      versions.push(version);
+    }
+  }
+  let dependency_specifier = |key: &String| {
+    let (name, version) = &resolved[key];
+    if distinct_versions[name].len() == 1 {
+      format!("jsr:{name}")
+    } else {
+      format!("jsr:{name}@{version}")
+    }
+  };
+
+  let jsr = packages
+    .into_iter()
+    .map(|(nv, (integrity, dependencies))| {
+      let mut dependencies = dependencies
+        .iter()
+        .map(dependency_specifier)
+        .collect::<Vec<_>>();
+      dependencies.sort();
+      dependencies.dedup();
+      (
+        nv,
+        ApiLockfileJsrPackage {
+          integrity,
+          dependencies,
+        },
+      )
+    })
+    .collect();
+
+  let specifiers = resolved
+    .iter()
+    .map(|(key, (_, version))| (key.clone(), version.to_string()))
+    .collect();
+
+  Ok(ApiLockfile {
+    version: "4".to_string(),
+    specifiers,
+    jsr,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::StatusCode;
+  use serde_json::json;
+  use sha2::Digest;
+
+  use crate::api::ApiLockfile;
+  use crate::db::PublishingTaskStatus;
+  use crate::ids::PackageName;
+  use crate::ids::Version;
+  use crate::publish::tests::create_mock_tarball;
+  use crate::publish::tests::process_tarball_setup;
+  use crate::publish::tests::process_tarball_setup2;
+  use crate::util::test::ApiResultExt;
+  use crate::util::test::TestSetup;
+
+  #[tokio::test]
+  async fn lockfile() {
+    let mut t = TestSetup::new().await;
+
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:?}");
+    let task = process_tarball_setup2(
+      &t,
+      create_mock_tarball("depends_on_ok"),
+      &PackageName::try_from("bar").unwrap(),
+      &Version::try_from("1.2.3").unwrap(),
+      false,
+    )
+    .await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:?}");
+
+    let lockfile = t
+      .unauthed_http()
+      .post("/api/lockfile")
+      .body_json(json!({ "specifiers": ["jsr:@scope/bar@1/mod.ts"] }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiLockfile>()
+      .await;
+    assert_eq!(lockfile.version, "4");
+    assert_eq!(
+      lockfile.specifiers.into_iter().collect::<Vec<_>>(),
+      vec![
+        ("jsr:@scope/bar@1".to_string(), "1.2.3".to_string()),
+        ("jsr:@scope/foo@1".to_string(), "1.2.3".to_string()),
+      ]
+    );
+    assert_eq!(
+      lockfile.jsr.keys().collect::<Vec<_>>(),
+      vec!["@scope/bar@1.2.3", "@scope/foo@1.2.3"]
+    );
+    let bar = &lockfile.jsr["@scope/bar@1.2.3"];
+    // The npm dependency is left for the client to resolve.
+    assert_eq!(bar.dependencies, vec!["jsr:@scope/foo".to_string()]);
+    let meta = t
+      .buckets
+      .modules_bucket
+      .download("@scope/bar/1.2.3_meta.json".into())
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(bar.integrity, format!("{:x}", sha2::Sha256::digest(&meta)));
+    assert!(lockfile.jsr["@scope/foo@1.2.3"].dependencies.is_empty());
+
+    t.http()
+      .post("/api/lockfile")
+      .body_json(json!({ "specifiers": ["jsr:@scope/foo@2"] }))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "unresolvableSpecifier")
+      .await;
+
+    t.http()
+      .post("/api/lockfile")
+      .body_json(json!({ "specifiers": ["npm:express@4"] }))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+
+    t.http()
+      .post("/api/lockfile")
+      .body_json(json!({ "specifiers": [] }))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+  }
+}
@@ -0,0 +1,363 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+//! Endpoints for running read-only mirrors of the registry: an append-only log
+//! of changes to public packages, and a bulk export of version metadata to
+//! bootstrap a mirror from.
+
+use hyper::Body;
+use hyper::Request;
+use routerify::Router;
+use routerify::prelude::RequestExt;
+use routerify_query::RequestQueryExt;
+use tracing::Span;
+use tracing::field;
+use tracing::instrument;
+
+use crate::db::Database;
+use crate::ids::PackageName;
+use crate::ids::ScopeName;
+use crate::ids::Version;
+use crate::util;
+use crate::util::ApiResult;
+use crate::util::CacheDuration;
+
+use super::ApiError;
+use super::ApiRegistryChanges;
+use super::ApiVersionExport;
+
+/// The maximum number of changes or versions returned in one page.
+const MAX_LIMIT: i64 = 1000;
+
+pub fn mirror_router() -> Router<Body, ApiError> {
+  Router::builder()
+    .get(
+      "/changes",
+      util::cache_shared(
+        CacheDuration::ONE_MINUTE,
+        util::json(list_changes_handler),
+      ),
+    )
+    .get(
+      "/versions",
+      util::cache_shared(
+        CacheDuration::ONE_MINUTE,
+        util::json(export_versions_handler),
+      ),
+    )
+    .build()
+    .unwrap()
+}
+
+fn limit(req: &Request<Body>) -> i64 {
+  req
+    .query("limit")
+    .and_then(|limit| limit.parse::<i64>().ok())
+    .unwrap_or(MAX_LIMIT)
+    .clamp(1, MAX_LIMIT)
+}
+
+#[instrument(
+  name = "GET /api/mirror/changes",
+  skip(req),
+  err,
+  fields(since, limit)
+)]
+pub async fn list_changes_handler(
+  req: Request<Body>,
+) -> ApiResult<ApiRegistryChanges> {
+  let db = req.data::<Database>().unwrap();
+  let since = match req.query("since") {
+    Some(since) => {
+      since
+        .parse::<i64>()
+        .map_err(|_| ApiError::MalformedRequest {
+          msg: "since must be a change cursor".into(),
+        })?
+    }
+    None => 0,
+  };
+  let limit = limit(&req);
+  Span::current().record("since", since);
+  Span::current().record("limit", limit);
+
+  let changes = db.list_registry_changes(since, limit).await?;
+  let next_cursor = changes.last().map(|change| change.seq).unwrap_or(since);
+
+  Ok(ApiRegistryChanges {
+    changes: changes.into_iter().map(|change| change.into()).collect(),
+    next_cursor,
+  })
+}
+
+/// Parses an export cursor of the form `@scope/name@version`.
+fn parse_export_cursor(
+  cursor: &str,
+) -> Option<(ScopeName, PackageName, Version)> {
+  let (scope, rest) = cursor.strip_prefix('@')?.split_once('/')?;
+  let (name, version) = rest.split_once('@')?;
+  Some((
+    ScopeName::try_from(scope).ok()?,
+    PackageName::try_from(name).ok()?,
+    Version::try_from(version).ok()?,
+  ))
+}
+
+#[instrument(
+  name = "GET /api/mirror/versions",
+  skip(req),
+  err,
+  fields(after = field::Empty, limit)
+)]
+pub async fn export_versions_handler(
+  req: Request<Body>,
+) -> ApiResult<ApiVersionExport> {
+  let db = req.data::<Database>().unwrap();
+  let after = match req.query("after") {
+    Some(after) => {
+      Span::current().record("after", field::display(after));
+      Some(parse_export_cursor(after).ok_or_else(|| {
+        ApiError::MalformedRequest {
+          msg: "after must be a cursor of the form @scope/name@version".into(),
+        }
+      })?)
+    }
+    None => None,
+  };
+  let limit = limit(&req);
+  Span::current().record("limit", limit);
+
+  // Read before the versions, so that nothing that changes while the page is
+  // read is missed by a mirror that follows the change log from here.
+  let changes_cursor = db.get_latest_registry_change_seq().await?;
+  let versions = db
+    .list_package_versions_for_export(
+      after
+        .as_ref()
+        .map(|(scope, name, version)| (scope, name, version)),
+      limit,
+    )
+    .await?;
+
+  let next = if versions.len() as i64 == limit {
+    versions
+      .last()
+      .map(|v| format!("@{}/{}@{}", v.scope, v.name, v.version))
+  } else {
+    None
+  };
+
+  Ok(ApiVersionExport {
+    versions: versions.into_iter().map(|v| v.into()).collect(),
+    next,
+    changes_cursor,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::StatusCode;
+  use serde_json::json;
+
+  use crate::api::ApiRegistryChanges;
+  use crate::api::ApiVersionExport;
+  use crate::db::PublishingTaskStatus;
+  use crate::db::RegistryChangeKind;
+  use crate::publish::tests::create_mock_tarball;
+  use crate::publish::tests::process_tarball_setup;
+  use crate::util::test::ApiResultExt;
+  use crate::util::test::TestSetup;
+
+  #[test]
+  fn parse_export_cursor() {
+    let (scope, name, version) =
+      super::parse_export_cursor("@scope/foo@1.2.3-beta.1").unwrap();
+    assert_eq!(scope.to_string(), "scope");
+    assert_eq!(name.to_string(), "foo");
+    assert_eq!(version.to_string(), "1.2.3-beta.1");
+
+    assert!(super::parse_export_cursor("scope/foo@1.2.3").is_none());
+    assert!(super::parse_export_cursor("@scope/foo").is_none());
+    assert!(super::parse_export_cursor("@scope/foo@latest").is_none());
+  }
+
+  #[tokio::test]
+  async fn changes() {
+    let mut t = TestSetup::new().await;
+
+    let changes = t
+      .http()
+      .get("/api/mirror/changes")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiRegistryChanges>()
+      .await;
+    let start = changes.next_cursor;
+
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:?}");
+
+    t.http()
+      .patch("/api/scopes/scope/packages/foo/versions/1.2.3")
+      .body_json(json!({ "yanked": true }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok_no_content()
+      .await;
+
+    let changes = t
+      .unauthed_http()
+      .get(format!("/api/mirror/changes?since={start}"))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiRegistryChanges>()
+      .await;
+    let kinds = changes
+      .changes
+      .iter()
+      .filter(|change| &*change.scope == "scope" && &*change.package == "foo")
+      .map(|change| change.kind)
+      .collect::<Vec<_>>();
+    assert!(kinds.contains(&RegistryChangeKind::VersionPublished));
+    assert_eq!(kinds.last(), Some(&RegistryChangeKind::VersionYanked));
+    assert_eq!(
+      changes.next_cursor,
+      changes.changes.last().unwrap().seq,
+      "{changes:?}"
+    );
+
+    // Nothing new after the cursor.
+    let next_cursor = changes.next_cursor;
+    let changes = t
+      .http()
+      .get(format!("/api/mirror/changes?since={next_cursor}"))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiRegistryChanges>()
+      .await;
+    assert!(changes.changes.is_empty());
+    assert_eq!(changes.next_cursor, next_cursor);
+
+    // Changes to private packages are not recorded.
+    t.http()
+      .patch("/api/scopes/scope/packages/foo")
+      .body_json(json!({ "visibility": "private" }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<crate::api::ApiPackage>()
+      .await;
+    t.http()
+      .patch("/api/scopes/scope/packages/foo/versions/1.2.3")
+      .body_json(json!({ "yanked": false }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok_no_content()
+      .await;
+    let changes = t
+      .http()
+      .get(format!("/api/mirror/changes?since={next_cursor}"))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiRegistryChanges>()
+      .await;
+    let kinds = changes
+      .changes
+      .iter()
+      .map(|change| change.kind)
+      .collect::<Vec<_>>();
+    assert_eq!(kinds, vec![RegistryChangeKind::PackageUpdated]);
+
+    t.http()
+      .get("/api/mirror/changes?since=abc")
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn export_versions() {
+    let mut t = TestSetup::new().await;
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:?}");
+
+    let export = t
+      .unauthed_http()
+      .get("/api/mirror/versions")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiVersionExport>()
+      .await;
+    assert!(export.next.is_none());
+    assert!(export.changes_cursor > 0);
+    let version = export
+      .versions
+      .iter()
+      .find(|v| &*v.scope == "scope" && &*v.package == "foo")
+      .unwrap();
+    assert_eq!(version.version.to_string(), "1.2.3");
+
+    // Paginate one version at a time.
+    let mut after: Option<String> = None;
+    let mut count = 0;
+    loop {
+      let url = match &after {
+        Some(after) => format!("/api/mirror/versions?limit=1&after={after}"),
+        None => "/api/mirror/versions?limit=1".to_string(),
+      };
+      let page = t
+        .http()
+        .get(url)
+        .call()
+        .await
+        .unwrap()
+        .expect_ok::<ApiVersionExport>()
+        .await;
+      count += page.versions.len();
+      match page.next {
+        Some(next) => after = Some(next),
+        None => break,
+      }
+    }
+    assert_eq!(count, export.versions.len());
+
+    // Private packages are not exported.
+    t.http()
+      .patch("/api/scopes/scope/packages/foo")
+      .body_json(json!({ "visibility": "private" }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<crate::api::ApiPackage>()
+      .await;
+    let export = t
+      .http()
+      .get("/api/mirror/versions")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiVersionExport>()
+      .await;
+    assert!(
+      !export
+        .versions
+        .iter()
+        .any(|v| &*v.scope == "scope" && &*v.package == "foo")
+    );
+
+    t.http()
+      .get("/api/mirror/versions?after=foo")
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+  }
+}
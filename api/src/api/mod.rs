@@ -1,19 +1,39 @@
 // Copyright 2024 the JSR authors. All rights reserved. MIT license.
 mod admin;
+mod announcements;
 mod authorization;
+mod dist_tags;
 mod errors;
+mod feeds;
+mod lockfile;
+mod mirror;
+mod npm;
 pub mod package;
+mod package_transfers;
+mod publish_uploads;
 mod publishing_task;
+mod registry;
+mod render;
+mod reports;
 mod scope;
+mod scope_renames;
 mod self_user;
+mod teams;
 mod tickets;
+mod trusted_publishers;
 mod types;
 mod users;
+mod validate_config;
+mod webhooks;
+mod workspace_publishes;
 
 pub use self::errors::*;
 pub use self::package::PublishQueue;
 use self::publishing_task::publishing_task_router;
+use self::registry::registry_router;
 use self::self_user::self_user_router;
+use self::self_user::unsubscribe_handler;
+use self::self_user::unsubscribe_page_handler;
 pub use self::types::*;
 use crate::api::tickets::tickets_router;
 use hyper::Body;
@@ -21,14 +41,24 @@ use hyper::Response;
 use package::global_list_handler;
 use package::global_metrics_handler;
 use package::global_stats_handler;
+use package::global_symbol_search_handler;
 use routerify::Middleware;
 use routerify::Router;
 
 use self::admin::admin_router;
+use self::announcements::announcements_router;
 use self::authorization::authorization_router;
+use self::lockfile::lockfile_handler;
+use self::mirror::mirror_router;
+use self::npm::npm_router;
+use self::render::render_markdown_handler;
+use self::reports::reports_router;
 use self::scope::scope_router;
 use self::users::users_router;
+use self::validate_config::validate_config_handler;
+use self::workspace_publishes::workspace_publishes_router;
 
+use crate::rate_limit;
 use crate::util;
 use crate::util::CacheDuration;
 
@@ -42,15 +72,37 @@ pub fn api_router() -> Router<Body, ApiError> {
       ),
     )
     .middleware(Middleware::pre(util::auth_middleware))
+    .middleware(Middleware::pre(rate_limit::rate_limit_middleware))
+    .middleware(Middleware::pre(util::scope_redirect_middleware))
+    .middleware(Middleware::pre(util::private_package_middleware))
+    .middleware(Middleware::post_with_info(
+      rate_limit::rate_limit_headers_middleware,
+    ))
+    .middleware(Middleware::post_with_info(util::etag_middleware))
+    .middleware(Middleware::post_with_info(
+      util::private_package_headers_middleware,
+    ))
     .scope("/admin", admin_router())
     .scope("/scopes", scope_router())
     .scope("/user", self_user_router())
     .scope("/users", users_router())
     .scope("/authorizations", authorization_router())
     .scope("/publishing_tasks", publishing_task_router())
+    .scope("/workspace_publishes", workspace_publishes_router())
+    .scope("/registry", registry_router())
     .get(
       "/packages",
-      util::cache(CacheDuration::FIVE_MINUTES, util::json(global_list_handler)),
+      util::cache(
+        CacheDuration::FIVE_MINUTES,
+        util::vary_accept_language(util::json(global_list_handler)),
+      ),
+    )
+    .get(
+      "/symbols",
+      util::cache(
+        CacheDuration::FIVE_MINUTES,
+        util::json(global_symbol_search_handler),
+      ),
     )
     .get(
       "/stats",
@@ -63,8 +115,21 @@ pub fn api_router() -> Router<Body, ApiError> {
       "/publish_status/:publishing_task_id",
       util::no_store(util::json(publishing_task::get_handler)),
     )
+    .post("/validate-config", util::json(validate_config_handler))
+    .post("/lockfile", util::json(lockfile_handler))
+    .post("/render/markdown", util::json(render_markdown_handler))
+    .get("/unsubscribe", unsubscribe_page_handler)
+    .post("/unsubscribe", util::json(unsubscribe_handler))
     .scope("/tickets", tickets_router())
+    .scope("/reports", reports_router())
+    .scope("/announcements", announcements_router())
+    .scope("/npm", npm_router())
+    .scope("/mirror", mirror_router())
     .get("/.well-known/openapi", openapi_handler)
+    .get(
+      "/schemas/doc_nodes.json",
+      util::cache(CacheDuration::ONE_DAY, doc_nodes_schema_handler),
+    )
     .get(
       "/debug/mem_stats",
       util::auth(crate::jemalloc_profiling::mem_stats_handler),
@@ -87,3 +152,17 @@ async fn openapi_handler(
     .unwrap();
   Ok(resp)
 }
+
+async fn doc_nodes_schema_handler(
+  _: hyper::Request<Body>,
+) -> util::ApiResult<Response<Body>> {
+  let resp = Response::builder()
+    .header("Content-Type", "application/schema+json")
+    .header(
+      "X-Doc-Nodes-Schema-Version",
+      crate::docs::DOC_NODES_SCHEMA_VERSION.to_string(),
+    )
+    .body(Body::from(crate::docs::DOC_NODES_SCHEMA))
+    .unwrap();
+  Ok(resp)
+}
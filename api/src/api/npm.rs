@@ -0,0 +1,494 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+//! The npm registry protocol, so that `@jsr/*` packages can be installed with
+//! `--registry https://api.jsr.io/npm/` directly against the API, in addition
+//! to the static manifests in the npm bucket served at `npm.jsr.io`.
+//!
+//! The lb does not serve the objects of private packages from the npm bucket,
+//! but has the API serve them, so that only those that may read the package
+//! get them: the package metadata, tarballs and attestations.
+
+use hyper::Body;
+use hyper::Request;
+use hyper::Response;
+use hyper::header;
+use indexmap::IndexMap;
+use routerify::Router;
+use routerify::prelude::RequestExt;
+use tracing::Span;
+use tracing::field;
+use tracing::instrument;
+
+use crate::NpmUrl;
+use crate::db::Database;
+use crate::db::Package;
+use crate::db::PackageVisibility;
+use crate::iam::ReqIamExt;
+use crate::ids::PackageName;
+use crate::ids::ScopeName;
+use crate::ids::Version;
+use crate::npm::NpmAbbreviatedPackageInfo;
+use crate::npm::NpmKeys;
+use crate::npm::NpmSigner;
+use crate::npm::generate_npm_version_manifest;
+use crate::npm::parse_npm_mapped_jsr_package_name;
+use crate::s3::BucketWithQueue;
+use crate::s3::Buckets;
+use crate::util;
+use crate::util::ApiResult;
+use crate::util::CacheDuration;
+
+use super::ApiError;
+
+/// The media type of the abbreviated package metadata.
+const NPM_ABBREVIATED_METADATA: &str = "application/vnd.npm.install-v1+json";
+
+pub fn npm_router() -> Router<Body, ApiError> {
+  Router::builder()
+    .get("/-/ping", util::json(ping_handler))
+    .get(
+      "/-/npm/v1/keys",
+      util::cache_shared(CacheDuration::ONE_HOUR, util::json(keys_handler)),
+    )
+    .post(
+      "/-/npm/v1/security/advisories/bulk",
+      util::json(advisories_bulk_handler),
+    )
+    .get(
+      "/-/package/@jsr/:npm_package/dist-tags",
+      util::cache_shared(
+        CacheDuration::ONE_MINUTE,
+        util::json(dist_tags_handler),
+      ),
+    )
+    .get(
+      "/-/package/:npm_name/dist-tags",
+      util::cache_shared(
+        CacheDuration::ONE_MINUTE,
+        util::json(dist_tags_handler),
+      ),
+    )
+    .get(
+      "/-/npm/v1/attestations/@jsr/:npm_package_version",
+      attestations_handler,
+    )
+    .get("/~/:revision/@jsr/:npm_package/:tarball", tarball_handler)
+    .get("/@jsr/:npm_package", package_handler)
+    .get("/:npm_name", package_handler)
+    .build()
+    .unwrap()
+}
+
+/// The JSR scope and package name of the `@jsr/*` package in the path. npm
+/// requests `@jsr%2f<scope>__<package>`, while some other package managers
+/// leave the `/` unencoded.
+fn param_npm_package(
+  req: &Request<Body>,
+) -> ApiResult<(ScopeName, PackageName)> {
+  let name = match req.param("npm_package") {
+    Some(package) => format!("@jsr/{package}"),
+    None => {
+      let name = util::param(req, "npm_name")?;
+      urlencoding::decode(name)
+        .map_err(|_| ApiError::PackageNotFound)?
+        .into_owned()
+    }
+  };
+  parse_npm_mapped_jsr_package_name(&name).ok_or(ApiError::PackageNotFound)
+}
+
+/// npm only sends credentials after being challenged for them, so anonymous
+/// requests for a private package get a `401` instead of a `404`.
+async fn check_package_access(
+  req: &Request<Body>,
+  package: &Package,
+) -> ApiResult<()> {
+  if package.visibility == PackageVisibility::Public {
+    return Ok(());
+  }
+  let iam = req.iam();
+  if iam.is_anonymous() {
+    return Err(ApiError::MissingAuthentication);
+  }
+  iam
+    .check_private_package_read_access(&package.scope)
+    .await?;
+  req.set_context(util::PrivatePackage);
+  Ok(())
+}
+
+#[instrument(name = "GET /api/npm/-/ping", skip(_req))]
+pub async fn ping_handler(_req: Request<Body>) -> ApiResult<serde_json::Value> {
+  Ok(serde_json::json!({}))
+}
+
+#[instrument(name = "GET /api/npm/-/npm/v1/keys", skip(req))]
+pub async fn keys_handler(req: Request<Body>) -> ApiResult<NpmKeys> {
+  let npm_signer = req.data::<NpmSigner>().unwrap();
+  Ok(match &npm_signer.0 {
+    Some(signing_key) => signing_key.keys(),
+    None => NpmKeys { keys: vec![] },
+  })
+}
+
+/// `npm audit` sends the installed versions of every package here. JSR does
+/// not track security advisories, so there are never any to report.
+#[instrument(
+  name = "POST /api/npm/-/npm/v1/security/advisories/bulk",
+  skip(_req)
+)]
+pub async fn advisories_bulk_handler(
+  _req: Request<Body>,
+) -> ApiResult<IndexMap<String, Vec<serde_json::Value>>> {
+  Ok(IndexMap::new())
+}
+
+#[instrument(
+  name = "GET /api/npm/-/package/:npm_name/dist-tags",
+  skip(req),
+  fields(scope, package)
+)]
+pub async fn dist_tags_handler(
+  req: Request<Body>,
+) -> ApiResult<IndexMap<String, Version>> {
+  let (scope, package) = param_npm_package(&req)?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+
+  let db = req.data::<Database>().unwrap();
+  let (res_package, _, _) = db
+    .get_package(&scope, &package)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+  check_package_access(&req, &res_package).await?;
+
+  let npm_url = &req.data::<NpmUrl>().unwrap().0;
+  let npm_signer = req.data::<NpmSigner>().unwrap();
+  let manifest =
+    generate_npm_version_manifest(db, npm_url, npm_signer, &scope, &package)
+      .await?;
+  Ok(manifest.dist_tags)
+}
+
+/// Serves the package metadata, or the abbreviated package metadata if the
+/// client asks for it in the `Accept` header, like `npm install` does.
+///
+/// The lb caches responses by URL only, so this can not be cached there:
+/// clients may cache it themselves for a short while instead.
+#[instrument(
+  name = "GET /api/npm/:npm_name",
+  skip(req),
+  fields(scope, package, abbreviated)
+)]
+pub async fn package_handler(req: Request<Body>) -> ApiResult<Response<Body>> {
+  let (scope, package) = param_npm_package(&req)?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+
+  let abbreviated = req
+    .headers()
+    .get(header::ACCEPT)
+    .and_then(|accept| accept.to_str().ok())
+    .is_some_and(|accept| accept.contains(NPM_ABBREVIATED_METADATA));
+  Span::current().record("abbreviated", abbreviated);
+
+  let db = req.data::<Database>().unwrap();
+  let (res_package, _, _) = db
+    .get_package(&scope, &package)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+  check_package_access(&req, &res_package).await?;
+
+  let npm_url = &req.data::<NpmUrl>().unwrap().0;
+  let npm_signer = req.data::<NpmSigner>().unwrap();
+  let manifest =
+    generate_npm_version_manifest(db, npm_url, npm_signer, &scope, &package)
+      .await?;
+
+  let (content_type, body) = if abbreviated {
+    let manifest = NpmAbbreviatedPackageInfo::from(manifest);
+    (
+      NPM_ABBREVIATED_METADATA,
+      serde_json::to_string(&manifest).unwrap(),
+    )
+  } else {
+    (
+      "application/json",
+      serde_json::to_string(&manifest).unwrap(),
+    )
+  };
+
+  let response = Response::builder()
+    .header(header::CONTENT_TYPE, content_type)
+    .header(header::CACHE_CONTROL, "private, max-age=60")
+    .header(header::VARY, "Accept")
+    .body(Body::from(body))
+    .unwrap();
+  Ok(response)
+}
+
+/// Serves the attestations of a version from the npm bucket. The lb serves
+/// them itself for public packages, and has the API serve those of private
+/// packages, so that only those that may read the package get them.
+#[instrument(
+  name = "GET /api/npm/-/npm/v1/attestations/@jsr/:npm_package_version",
+  skip(req),
+  fields(scope, package, version)
+)]
+pub async fn attestations_handler(
+  req: Request<Body>,
+) -> ApiResult<Response<Body>> {
+  let package_version = util::param(&req, "npm_package_version")?;
+  let (npm_package, version) = package_version
+    .rsplit_once('@')
+    .ok_or(ApiError::PackageVersionNotFound)?;
+  let (scope, package) =
+    parse_npm_mapped_jsr_package_name(&format!("@jsr/{npm_package}"))
+      .ok_or(ApiError::PackageNotFound)?;
+  let version =
+    Version::new(version).map_err(|_| ApiError::PackageVersionNotFound)?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+  Span::current().record("version", field::display(&version));
+
+  check_version_access(&req, &scope, &package, &version).await?;
+
+  let buckets = req.data::<Buckets>().unwrap();
+  let path = crate::s3_paths::npm_attestations_path(&scope, &package, &version);
+  serve_bucket_file(&buckets.npm_bucket, path, "application/json").await
+}
+
+/// Serves an npm tarball from the npm bucket. The lb serves them itself for
+/// public packages, and has the API serve those of private packages, so that
+/// only those that may read the package get them.
+#[instrument(
+  name = "GET /api/npm/~/:revision/@jsr/:npm_package/:tarball",
+  skip(req),
+  fields(scope, package, version, revision)
+)]
+pub async fn tarball_handler(req: Request<Body>) -> ApiResult<Response<Body>> {
+  let (scope, package) = param_npm_package(&req)?;
+  let revision = util::param(&req, "revision")?
+    .parse::<u32>()
+    .map_err(|_| ApiError::PackagePathNotFound)?;
+  let version = util::param(&req, "tarball")?
+    .strip_suffix(".tgz")
+    .and_then(|version| Version::new(version).ok())
+    .ok_or(ApiError::PackagePathNotFound)?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+  Span::current().record("version", field::display(&version));
+  Span::current().record("revision", revision);
+
+  check_version_access(&req, &scope, &package, &version).await?;
+
+  let buckets = req.data::<Buckets>().unwrap();
+  let path =
+    crate::s3_paths::npm_tarball_path(&scope, &package, &version, revision);
+  serve_bucket_file(&buckets.npm_bucket, path, "application/octet-stream").await
+}
+
+/// Checks that the version exists and that the requester may read its
+/// package.
+async fn check_version_access(
+  req: &Request<Body>,
+  scope: &ScopeName,
+  package: &PackageName,
+  version: &Version,
+) -> ApiResult<()> {
+  let db = req.data::<Database>().unwrap();
+  let (res_package, _, _) = db
+    .get_package(scope, package)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+  check_package_access(req, &res_package).await?;
+  db.get_package_version(scope, package, version)
+    .await?
+    .ok_or(ApiError::PackageVersionNotFound)?;
+  Ok(())
+}
+
+async fn serve_bucket_file(
+  bucket: &BucketWithQueue,
+  path: String,
+  content_type: &'static str,
+) -> ApiResult<Response<Body>> {
+  let file = bucket
+    .download(path.into())
+    .await?
+    .ok_or(ApiError::PackagePathNotFound)?;
+  Ok(
+    Response::builder()
+      .header(header::CONTENT_TYPE, content_type)
+      .body(Body::from(file))
+      .unwrap(),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::StatusCode;
+  use hyper::header::ACCEPT;
+  use hyper::header::HeaderValue;
+  use serde_json::json;
+
+  use crate::db::PublishingTaskStatus;
+  use crate::publish::tests::create_mock_tarball;
+  use crate::publish::tests::process_tarball_setup;
+  use crate::util::test::ApiResultExt;
+  use crate::util::test::TestSetup;
+
+  #[tokio::test]
+  async fn npm_registry() {
+    let mut t = TestSetup::new().await;
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success);
+
+    let mut resp = t
+      .http()
+      .get("/api/npm/@jsr%2fscope__foo")
+      .call()
+      .await
+      .unwrap();
+    assert_eq!(
+      resp.headers().get("Content-Type").unwrap(),
+      "application/json"
+    );
+    let packument = resp.expect_ok::<serde_json::Value>().await;
+    assert_eq!(packument["name"], "@jsr/scope__foo");
+    assert_eq!(packument["dist-tags"], json!({ "latest": "1.2.3" }));
+    assert!(packument["time"]["1.2.3"].is_string());
+    assert_eq!(packument["versions"]["1.2.3"]["name"], "@jsr/scope__foo");
+
+    let mut resp = t
+      .http()
+      .get("/api/npm/@jsr/scope__foo")
+      .header(
+        ACCEPT,
+        HeaderValue::from_static(
+          "application/vnd.npm.install-v1+json; q=1.0, application/json; q=0.8, */*",
+        ),
+      )
+      .call()
+      .await
+      .unwrap();
+    assert_eq!(
+      resp.headers().get("Content-Type").unwrap(),
+      "application/vnd.npm.install-v1+json"
+    );
+    let abbreviated = resp.expect_ok::<serde_json::Value>().await;
+    assert_eq!(abbreviated["name"], "@jsr/scope__foo");
+    assert!(abbreviated["modified"].is_string());
+    assert!(abbreviated.get("time").is_none());
+    assert_eq!(abbreviated["versions"]["1.2.3"]["version"], "1.2.3");
+    assert!(
+      abbreviated["versions"]["1.2.3"]
+        .get("description")
+        .is_none()
+    );
+    assert_eq!(
+      abbreviated["versions"]["1.2.3"]["dist"],
+      packument["versions"]["1.2.3"]["dist"]
+    );
+
+    let dist_tags = t
+      .http()
+      .get("/api/npm/-/package/@jsr%2fscope__foo/dist-tags")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<serde_json::Value>()
+      .await;
+    assert_eq!(dist_tags, json!({ "latest": "1.2.3" }));
+
+    let advisories = t
+      .http()
+      .post("/api/npm/-/npm/v1/security/advisories/bulk")
+      .body_json(json!({ "@jsr/scope__foo": ["1.2.3"] }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<serde_json::Value>()
+      .await;
+    assert_eq!(advisories, json!({}));
+
+    t.http()
+      .get("/api/npm/-/ping")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<serde_json::Value>()
+      .await;
+
+    t.http()
+      .get("/api/npm/@jsr%2fscope__bar")
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "packageNotFound")
+      .await;
+    t.http()
+      .get("/api/npm/react")
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "packageNotFound")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn npm_registry_private_package() {
+    let mut t = TestSetup::new().await;
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success);
+
+    t.http()
+      .patch("/api/scopes/scope/packages/foo")
+      .body_json(json!({ "visibility": "private" }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<serde_json::Value>()
+      .await;
+
+    t.unauthed_http()
+      .get("/api/npm/@jsr%2fscope__foo")
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::UNAUTHORIZED, "missingAuthentication")
+      .await;
+
+    let token = t.user2.token.clone();
+    t.http()
+      .get("/api/npm/@jsr%2fscope__foo")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "packageNotFound")
+      .await;
+
+    let packument = t
+      .http()
+      .get("/api/npm/@jsr%2fscope__foo")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<serde_json::Value>()
+      .await;
+    assert_eq!(packument["name"], "@jsr/scope__foo");
+
+    let resp = t
+      .http()
+      .get("/api/npm/-/package/@jsr%2fscope__foo/dist-tags")
+      .call()
+      .await
+      .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+      resp.headers().get("Cache-Control").unwrap(),
+      "private, no-cache"
+    );
+  }
+}
@@ -1,7 +1,8 @@
 // Copyright 2024 the JSR authors. All rights reserved. MIT license.
 use anyhow::Context;
+use chrono::DateTime;
+use chrono::Datelike;
 use chrono::Utc;
-use comrak::adapters::SyntaxHighlighterAdapter;
 use deno_ast::MediaType;
 use deno_ast::ModuleSpecifier;
 use deno_error::JsErrorBox;
@@ -17,6 +18,7 @@ use deno_graph::source::LoadError;
 use deno_graph::source::LoadOptions;
 use deno_graph::source::NullFileSystem;
 use deno_semver::StackString;
+use deno_semver::VersionReq;
 use futures::StreamExt;
 use futures::TryFutureExt;
 use futures::future::Either;
@@ -35,6 +37,7 @@ use serde::Deserialize;
 use serde::Serialize;
 use sha2::Digest;
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::io;
 use std::sync::Arc;
 use std::sync::Mutex;
@@ -53,33 +56,57 @@ use crate::RegistryUrl;
 use crate::analysis::JsrResolver;
 use crate::analysis::ModuleParser;
 use crate::auth;
+use crate::cache_purge::CachePurge;
 use crate::db::CreatePackageResult;
 use crate::db::CreatePublishingTaskResult;
 use crate::db::Database;
 use crate::db::NewGithubRepository;
 use crate::db::NewPublishingTask;
 use crate::db::Package;
+use crate::db::PackagePermission;
+use crate::db::PackageSearchFilters;
+use crate::db::PackageVersionMeta;
+use crate::db::PackageVisibility;
+use crate::db::PublishingTask;
+use crate::db::ReservedNameKind;
 use crate::db::RuntimeCompat;
 use crate::db::User;
+use crate::db::UserPublic;
 use crate::docs::DocsRequest;
 use crate::docs::GeneratedDocsOutput;
+use crate::events;
+use crate::events::DomainEvent;
 use crate::external::algolia::AlgoliaClient;
-use crate::external::cloudflare::CachePurge;
+use crate::feature_flags::FeatureFlags;
+use crate::feature_flags::UNSTABLE_BYTES_IMPORTS;
 use crate::gcp;
+use crate::iam::PublishAccessRestriction;
 use crate::iam::ReqIamExt;
 use crate::ids::PackageName;
 use crate::ids::PackagePath;
 use crate::ids::ScopeName;
 use crate::ids::Version;
+use crate::malware_scan::MalwareScanners;
 use crate::metadata::PackageMetadata;
 use crate::metadata::VersionMetadata;
+use crate::module_files::VersionFiles;
+use crate::npm::NpmSigner;
+use crate::npm::attest_npm_tarball;
 use crate::npm::generate_npm_version_manifest;
 use crate::provenance;
 use crate::publish::publish_task;
+use crate::publish_limits::PublishLimits;
 use crate::s3::Buckets;
 use crate::s3::CACHE_CONTROL_MANIFEST;
+use crate::s3::ContentEncoding;
 use crate::s3::S3UploadOptions;
 use crate::s3::UploadTaskBody;
+use crate::sbom::SbomFormat;
+use crate::sbom::SbomInput;
+use crate::search::PackageSearch;
+use crate::search::PackageSearchQuery;
+use crate::tarball::AnalyzedTarball;
+use crate::tarball::analyze_tarball;
 use crate::tarball::bucket_tarball_path;
 use crate::util;
 use crate::util::LicenseStore;
@@ -95,32 +122,62 @@ use super::ApiCreatePackageRequest;
 use super::ApiDependency;
 use super::ApiDependencyGraphItem;
 use super::ApiDependent;
+use super::ApiDependentsSort;
+use super::ApiDeprecation;
+use super::ApiDocArtifactDigest;
+use super::ApiDocLink;
 use super::ApiDownloadDataPoint;
+use super::ApiDownloadPeriod;
+use super::ApiEntrypointDownloadDataPoint;
 use super::ApiError;
+use super::ApiFileDigest;
+use super::ApiImportMap;
 use super::ApiList;
 use super::ApiMetrics;
+use super::ApiNpmTarballDigest;
 use super::ApiPackage;
 use super::ApiPackageDownloads;
+use super::ApiPackageDownloadsEntrypoint;
 use super::ApiPackageDownloadsRecentVersion;
 use super::ApiPackageScore;
+use super::ApiPackageSearchResult;
+use super::ApiPackageSnapshot;
 use super::ApiPackageVersion;
+use super::ApiPackageVersionDiff;
 use super::ApiPackageVersionDocs;
 use super::ApiPackageVersionSource;
 use super::ApiPackageVersionWithUser;
+use super::ApiProvenance;
 use super::ApiProvenanceStatementRequest;
+use super::ApiProvenanceVerification;
+use super::ApiPublishDiagnostic;
+use super::ApiPublishDryRun;
+use super::ApiPublishDryRunAnalysis;
+use super::ApiPublishDryRunFile;
 use super::ApiPublishingTask;
+use super::ApiPublishingTaskError;
 use super::ApiSource;
 use super::ApiSourceDirEntry;
 use super::ApiSourceDirEntryKind;
 use super::ApiStats;
+use super::ApiStatsDownloadedPackage;
 use super::ApiStatsPackage;
 use super::ApiStatsPackageVersion;
+use super::ApiSymbolSearchResult;
 use super::ApiUpdatePackageGithubRepositoryRequest;
+use super::ApiUpdatePackageLocalizedDescriptionRequest;
+use super::ApiVersionIntegrity;
+use super::dist_tags;
+use super::feeds::package_feed_handler;
+use super::package_transfers;
+use super::publish_uploads;
+use super::teams;
+use super::trusted_publishers;
 
 use super::ApiUpdatePackageRequest;
 use super::ApiUpdatePackageVersionRequest;
 
-pub const MAX_PUBLISH_TARBALL_SIZE: u64 = 20 * 1024 * 1024; // 20mb
+const MAX_YANK_REASON_LENGTH: usize = 250;
 
 pub struct PublishQueue(pub Option<gcp::Queue>);
 
@@ -130,7 +187,10 @@ pub fn package_router() -> Router<Body, ApiError> {
       // Cache-busted on publish/create/delete via `package_api_cache_urls` /
       // `scope_api_cache_urls`.
       "/",
-      util::cache(CacheDuration::ONE_DAY, util::json(list_handler)),
+      util::cache(
+        CacheDuration::ONE_DAY,
+        util::vary_accept_language(util::json(list_handler)),
+      ),
     )
     .post("/", util::json(create_handler))
     .get(
@@ -138,7 +198,10 @@ pub fn package_router() -> Router<Body, ApiError> {
       // `package_api_cache_urls` (this endpoint has no query params, so the
       // canonical URL purge is exact).
       "/:package",
-      util::cache(CacheDuration::THIRTY_DAYS, util::json(get_handler)),
+      util::etag(util::cache(
+        CacheDuration::THIRTY_DAYS,
+        util::vary_accept_language(util::json(get_handler)),
+      )),
     )
     .patch("/:package", util::auth(util::json(update_handler)))
     .delete("/:package", util::auth(delete_handler))
@@ -146,7 +209,62 @@ pub fn package_router() -> Router<Body, ApiError> {
       // Cache-busted on publish/yank/delete. The canonical (unpaginated) URL is
       // purged exactly; paginated variants fall back to a 1-day bound.
       "/:package/versions",
-      util::cache(CacheDuration::ONE_DAY, util::json(list_versions_handler)),
+      util::etag(util::cache(
+        CacheDuration::ONE_DAY,
+        util::json(list_versions_handler),
+      )),
+    )
+    .get(
+      "/:package/feed.xml",
+      util::cache_shared(CacheDuration::FIVE_MINUTES, package_feed_handler),
+    )
+    .get(
+      // Cache-busted when a tag is set or deleted via `package_api_cache_urls`.
+      "/:package/dist_tags",
+      util::etag(util::cache(
+        CacheDuration::ONE_DAY,
+        util::json(dist_tags::list_handler),
+      )),
+    )
+    .put(
+      "/:package/dist_tags/:tag",
+      util::auth(util::json(dist_tags::set_handler)),
+    )
+    .delete(
+      "/:package/dist_tags/:tag",
+      util::auth(dist_tags::delete_handler),
+    )
+    .get(
+      "/:package/transfer",
+      util::auth(util::json(package_transfers::get_handler)),
+    )
+    .post(
+      "/:package/transfer",
+      util::auth(util::json(package_transfers::create_handler)),
+    )
+    .delete(
+      "/:package/transfer",
+      util::auth(package_transfers::delete_handler),
+    )
+    .post(
+      "/:package/transfer/accept",
+      util::auth(util::json(package_transfers::accept_handler)),
+    )
+    .get(
+      "/:package/teams",
+      util::auth(util::json(teams::list_package_teams_handler)),
+    )
+    .get(
+      "/:package/trusted_publishers",
+      util::auth(util::json(trusted_publishers::list_handler)),
+    )
+    .post(
+      "/:package/trusted_publishers",
+      util::auth(util::json(trusted_publishers::create_handler)),
+    )
+    .delete(
+      "/:package/trusted_publishers/:id",
+      util::auth(trusted_publishers::delete_handler),
     )
     .get(
       "/:package/dependents",
@@ -161,13 +279,17 @@ pub fn package_router() -> Router<Body, ApiError> {
       "/:package/downloads",
       util::cache(CacheDuration::ONE_DAY, util::json(get_downloads_handler)),
     )
+    .get(
+      "/:package/snapshot",
+      util::cache(CacheDuration::ONE_HOUR, get_snapshot_handler),
+    )
     .get(
       "/:package/versions/:version",
-      util::cache_versioned(
+      util::etag(util::cache_versioned(
         CacheDuration::ONE_MINUTE,
         CacheDuration::THIRTY_DAYS,
         util::json(get_version_handler),
-      ),
+      )),
     )
     .post(
       "/:package/versions/:version",
@@ -181,10 +303,38 @@ pub fn package_router() -> Router<Body, ApiError> {
       "/:package/versions/:version",
       util::auth(version_delete_handler),
     )
+    .post(
+      "/:package/versions/:version/dry-run",
+      util::auth(util::json(version_dry_run_handler)),
+    )
+    .post(
+      "/:package/versions/:version/uploads",
+      util::auth(util::json(publish_uploads::create_handler)),
+    )
+    .get(
+      "/:package/versions/:version/uploads/:upload",
+      util::auth(util::json(publish_uploads::get_handler)),
+    )
+    .post(
+      "/:package/versions/:version/uploads/:upload/chunks",
+      util::auth(util::json(publish_uploads::append_chunk_handler)),
+    )
+    .post(
+      "/:package/versions/:version/uploads/:upload/commit",
+      util::auth(util::json(publish_uploads::commit_handler)),
+    )
     .post(
       "/:package/versions/:version/provenance",
       util::auth(version_provenance_statements_handler),
     )
+    .get(
+      "/:package/versions/:version/provenance",
+      util::json(get_provenance_handler),
+    )
+    .get(
+      "/:package/versions/:version/provenance/verify",
+      util::json(verify_provenance_handler),
+    )
     .get(
       "/:package/versions/:version/tarball",
       util::cache(CacheDuration::FOREVER, version_tarball_handler),
@@ -200,27 +350,43 @@ pub fn package_router() -> Router<Body, ApiError> {
       // `_shared`: the docs response is identity-independent (no permission/
       // member/sudo branch), so the lb may serve it from its shared cache to
       // authenticated callers too, rather than bypassing cache on auth.
-      util::cache_versioned_shared(
+      util::etag(util::cache_versioned_shared(
         CacheDuration::FIVE_MINUTES,
         CacheDuration::THIRTY_DAYS,
         util::json(get_docs_handler),
-      ),
+      )),
+    )
+    .get(
+      "/:package/versions/:version/docs/archive.tar.gz",
+      util::cache(CacheDuration::FOREVER, get_docs_archive_handler),
+    )
+    .get(
+      "/:package/versions/:version/docs.md",
+      util::cache(CacheDuration::FOREVER, get_docs_markdown_handler),
+    )
+    .get(
+      "/:package/versions/:version/docs/type_graph",
+      util::cache(CacheDuration::FOREVER, get_docs_type_graph_handler),
+    )
+    .get(
+      "/:package/versions/:version/sbom",
+      util::cache(CacheDuration::FOREVER, get_sbom_handler),
     )
     .get(
       "/:package/versions/:version/docs/search",
-      util::cache_versioned(
+      util::etag(util::cache_versioned(
         CacheDuration::FIVE_MINUTES,
         CacheDuration::THIRTY_DAYS,
         util::json(get_docs_search_handler),
-      ),
+      )),
     )
     .get(
       "/:package/versions/:version/docs/search_structured",
-      util::cache_versioned(
+      util::etag(util::cache_versioned(
         CacheDuration::FIVE_MINUTES,
         CacheDuration::THIRTY_DAYS,
         util::json(get_docs_search_structured_handler),
-      ),
+      )),
     )
     .get(
       "/:package/versions/:version/source",
@@ -230,6 +396,7 @@ pub fn package_router() -> Router<Body, ApiError> {
         util::json(get_source_handler),
       ),
     )
+    .get("/:package/module_file", get_module_file_handler)
     .get(
       // Both versions are immutable, so the diff between them never changes.
       // `_shared`: identity-independent (see docs above), so the lb shares it
@@ -240,12 +407,43 @@ pub fn package_router() -> Router<Body, ApiError> {
         util::json(get_diff_handler),
       ),
     )
+    .get(
+      "/:package/diff/:old_version/:new_version/changes",
+      util::cache_shared(
+        CacheDuration::THIRTY_DAYS,
+        util::json(get_diff_changes_handler),
+      ),
+    )
     .get(
       "/:package/versions/:version/dependencies",
-      util::cache_versioned(
+      util::etag(util::cache_versioned(
         CacheDuration::ONE_MINUTE,
         CacheDuration::THIRTY_DAYS,
         util::json(list_dependencies_handler),
+      )),
+    )
+    .get(
+      "/:package/versions/:version/deprecations",
+      util::cache_versioned(
+        CacheDuration::ONE_MINUTE,
+        CacheDuration::THIRTY_DAYS,
+        util::json(list_deprecations_handler),
+      ),
+    )
+    .get(
+      "/:package/versions/:version/integrity",
+      util::cache_versioned(
+        CacheDuration::ONE_MINUTE,
+        CacheDuration::THIRTY_DAYS,
+        util::json(get_integrity_handler),
+      ),
+    )
+    .get(
+      "/:package/versions/:version/symbols/:symbol",
+      util::cache_versioned(
+        CacheDuration::ONE_MINUTE,
+        CacheDuration::THIRTY_DAYS,
+        util::json(get_doc_link_handler),
       ),
     )
     .get(
@@ -255,6 +453,10 @@ pub fn package_router() -> Router<Body, ApiError> {
         util::json(get_dependencies_graph_handler),
       ),
     )
+    .get(
+      "/:package/versions/:version/import_map",
+      util::cache(CacheDuration::ONE_DAY, util::json(get_import_map_handler)),
+    )
     .get(
       "/:package/publishing_tasks",
       util::json(list_publishing_tasks_handler),
@@ -267,10 +469,13 @@ pub fn package_router() -> Router<Body, ApiError> {
     .unwrap()
 }
 
+/// The runtimes package search can be narrowed down to.
+const SEARCH_RUNTIMES: &[&str] = &["browser", "deno", "node", "workerd", "bun"];
+
 #[instrument(name = "GET /api/packages", skip(req), fields(query))]
 pub async fn global_list_handler(
   req: Request<Body>,
-) -> ApiResult<ApiList<ApiPackage>> {
+) -> ApiResult<ApiPackageSearchResult> {
   let db = req.data::<Database>().unwrap();
 
   let (start, limit) = pagination(&req);
@@ -290,20 +495,195 @@ pub async fn global_list_handler(
     })
     .transpose()?;
 
-  let (total, packages) = db
-    .list_packages(start, limit, maybe_search, github_repo_id, None)
-    .await?;
-  Ok(ApiList {
-    items: packages.into_iter().map(ApiPackage::from).collect(),
+  let mut filters = PackageSearchFilters::default();
+  if let Some(runtimes) = req.query("runtime") {
+    for runtime in runtimes.split(',').map(str::trim) {
+      let compat = &mut filters.runtime_compat;
+      let field = match runtime {
+        "browser" => &mut compat.browser,
+        "deno" => &mut compat.deno,
+        "node" => &mut compat.node,
+        "workerd" => &mut compat.workerd,
+        "bun" => &mut compat.bun,
+        _ => {
+          return Err(ApiError::MalformedRequest {
+            msg: format!(
+              "invalid 'runtime' query parameter, expected a comma separated list of: {}",
+              SEARCH_RUNTIMES.join(", ")
+            )
+            .into(),
+          });
+        }
+      };
+      *field = Some(true);
+    }
+  }
+  filters.has_provenance = req
+    .query("hasProvenance")
+    .map(|has_provenance| {
+      has_provenance
+        .parse::<bool>()
+        .map_err(|err| ApiError::MalformedRequest {
+          msg: format!(
+            "failed to parse query parameter 'hasProvenance': {err}"
+          )
+          .into(),
+        })
+    })
+    .transpose()?;
+  filters.license = req
+    .query("license")
+    .map(|license| license.trim().to_string())
+    .filter(|license| !license.is_empty());
+  filters.min_score = req
+    .query("minScore")
+    .map(|min_score| match min_score.parse::<u32>() {
+      Ok(min_score) if min_score <= 100 => Ok(min_score),
+      _ => Err(ApiError::MalformedRequest {
+        msg: "invalid 'minScore' query parameter, expected a number between 0 and 100".into(),
+      }),
+    })
+    .transpose()?;
+  let maybe_sort = match req.query("sort").map(|sort| sort.as_str()) {
+    None | Some("relevance") => None,
+    Some(sort @ ("weekly_downloads" | "monthly_downloads")) => Some(sort),
+    Some(_) => {
+      return Err(ApiError::MalformedRequest {
+        msg: "invalid 'sort' query parameter, expected one of: relevance, \
+           weekly_downloads, monthly_downloads"
+          .into(),
+      });
+    }
+  };
+
+  let package_search = req.data::<PackageSearch>().unwrap();
+  // Text searches are answered by the search backend. Listing all packages,
+  // sorting them, finding those of a repository and filtering by score always
+  // use the database, as the search backend does not know the score.
+  let filters = &filters;
+  let packages_fut = async {
+    match maybe_search {
+      Some(query)
+        if github_repo_id.is_none()
+          && maybe_sort.is_none()
+          && filters.min_score.is_none() =>
+      {
+        let search = PackageSearchQuery {
+          query,
+          filters,
+          start,
+          limit,
+        };
+        Ok::<_, ApiError>(package_search.search_packages(db, &search).await?)
+      }
+      _ => Ok(
+        db.list_packages(
+          start,
+          limit,
+          maybe_search,
+          github_repo_id,
+          maybe_sort,
+          filters,
+        )
+        .await?,
+      ),
+    }
+  };
+  let facets_fut = db
+    .package_search_facets(maybe_search, github_repo_id, filters)
+    .map_err(ApiError::from);
+  let locales = util::preferred_locales(&req);
+
+  let ((total, packages), facets) = tokio::try_join!(packages_fut, facets_fut)?;
+  let items = packages
+    .into_iter()
+    .map(|package| ApiPackage::from(package).localize(&locales))
+    .collect();
+
+  Ok(ApiPackageSearchResult {
+    items,
     total,
+    facets: facets.into(),
   })
 }
 
+/// The declaration kinds symbol search can be narrowed down to.
+const SYMBOL_KINDS: &[&str] = &[
+  "function",
+  "variable",
+  "enum",
+  "class",
+  "typeAlias",
+  "namespace",
+  "interface",
+];
+
+/// Matches beyond this many are not ranked, to bound the cost of a search for
+/// a very common prefix.
+const MAX_SYMBOL_SEARCH_CANDIDATES: i64 = 1000;
+
+#[instrument(name = "GET /api/symbols", skip(req), fields(query, kind))]
+pub async fn global_symbol_search_handler(
+  req: Request<Body>,
+) -> ApiResult<ApiList<ApiSymbolSearchResult>> {
+  let db = req.data::<Database>().unwrap();
+
+  let (start, limit) = pagination(&req);
+  let query = search(&req).map(str::trim).unwrap_or_default();
+  if query.is_empty() {
+    return Err(ApiError::MalformedRequest {
+      msg: "missing 'query' query parameter".into(),
+    });
+  }
+  Span::current().record("query", query);
+
+  let kind = req.query("kind").map(|kind| kind.as_str());
+  if let Some(kind) = kind {
+    if !SYMBOL_KINDS.contains(&kind) {
+      return Err(ApiError::MalformedRequest {
+        msg: format!(
+          "invalid 'kind' query parameter, expected one of: {}",
+          SYMBOL_KINDS.join(", ")
+        )
+        .into(),
+      });
+    }
+    Span::current().record("kind", kind);
+  }
+
+  let candidates = db
+    .search_package_symbols(query, kind, MAX_SYMBOL_SEARCH_CANDIDATES)
+    .await?;
+
+  // Exact matches first, then by package score. The database already orders
+  // by downloads, which the stable sort keeps as the tie breaker.
+  let query = query.to_lowercase();
+  let mut results = candidates
+    .into_iter()
+    .map(ApiSymbolSearchResult::from)
+    .collect::<Vec<_>>();
+  results.sort_by_key(|result| {
+    (
+      std::cmp::Reverse(result.symbol.to_lowercase() == query),
+      std::cmp::Reverse(result.score.unwrap_or(0)),
+    )
+  });
+
+  let total = results.len();
+  let items = results
+    .into_iter()
+    .skip(start as usize)
+    .take(limit as usize)
+    .collect();
+  Ok(ApiList { items, total })
+}
+
 #[instrument(name = "GET /api/stats", skip(req))]
 pub async fn global_stats_handler(req: Request<Body>) -> ApiResult<ApiStats> {
   let db = req.data::<Database>().unwrap();
 
-  let (newest, updated, featured) = db.package_stats().await?;
+  let ((newest, updated, featured), (weekly, monthly)) =
+    tokio::try_join!(db.package_stats(), db.most_downloaded_packages())?;
 
   Ok(ApiStats {
     newest: newest.into_iter().map(ApiStatsPackage::from).collect(),
@@ -312,6 +692,14 @@ pub async fn global_stats_handler(req: Request<Body>) -> ApiResult<ApiStats> {
       .map(ApiStatsPackageVersion::from)
       .collect(),
     featured: featured.into_iter().map(ApiStatsPackage::from).collect(),
+    most_downloaded_this_week: weekly
+      .into_iter()
+      .map(ApiStatsDownloadedPackage::from)
+      .collect(),
+    most_downloaded_this_month: monthly
+      .into_iter()
+      .map(ApiStatsDownloadedPackage::from)
+      .collect(),
   })
 }
 
@@ -342,12 +730,24 @@ pub async fn list_handler(
 
   let iam = req.iam();
   let can_see_archived = iam.check_scope_admin_access(&scope).await.is_ok();
+  let can_see_private =
+    iam.check_private_package_read_access(&scope).await.is_ok();
   let (total, packages) = db
-    .list_packages_by_scope(&scope, can_see_archived, start, limit)
+    .list_packages_by_scope(
+      &scope,
+      can_see_archived,
+      can_see_private,
+      start,
+      limit,
+    )
     .await?;
 
+  let locales = util::preferred_locales(&req);
   Ok(ApiList {
-    items: packages.into_iter().map(ApiPackage::from).collect(),
+    items: packages
+      .into_iter()
+      .map(|package| ApiPackage::from(package).localize(&locales))
+      .collect(),
     total,
   })
 }
@@ -367,13 +767,24 @@ pub async fn create_handler(mut req: Request<Body>) -> ApiResult<ApiPackage> {
   Span::current().record("package", field::display(&package_name));
 
   let iam = req.iam();
-  iam.check_scope_write_access(&scope).await?;
+  let (user, _) = iam.check_scope_write_access(&scope).await?;
 
   let db = req.data::<Database>().unwrap();
 
   if db.check_is_bad_word(&package_name.to_string()).await? {
     return Err(ApiError::PackageNameNotAllowed);
   }
+  if db
+    .check_is_reserved_name(ReservedNameKind::Package, &package_name)
+    .await?
+  {
+    return Err(ApiError::PackageNameReserved);
+  }
+  if let Some(existing) =
+    db.find_confusable_package(&scope, &package_name).await?
+  {
+    return Err(ApiError::PackageNameConfusable { existing });
+  }
 
   let res = db.create_package(&scope, &package_name).await?;
   let package = match res {
@@ -381,6 +792,9 @@ pub async fn create_handler(mut req: Request<Body>) -> ApiResult<ApiPackage> {
     CreatePackageResult::AlreadyExists => {
       return Err(ApiError::PackageAlreadyExists);
     }
+    CreatePackageResult::Deleted => {
+      return Err(ApiError::PackageDeleted);
+    }
     CreatePackageResult::PackageLimitExceeded(limit) => {
       return Err(ApiError::PackageLimitExceeded { limit });
     }
@@ -389,6 +803,16 @@ pub async fn create_handler(mut req: Request<Body>) -> ApiResult<ApiPackage> {
     }
   };
 
+  events::emit(
+    db,
+    DomainEvent::PackageCreated {
+      scope: scope.clone(),
+      package: package_name.clone(),
+      user_id: user.id,
+    },
+  )
+  .await;
+
   let algolia_client = req.data::<Option<AlgoliaClient>>().unwrap();
   if let Some(algolia_client) = algolia_client {
     algolia_client.upsert_package(&package, &Default::default());
@@ -398,8 +822,7 @@ pub async fn create_handler(mut req: Request<Body>) -> ApiResult<ApiPackage> {
   let registry_url = &req.data::<RegistryUrl>().unwrap().0;
   let cache_purge = req.data::<CachePurge>().unwrap();
   cache_purge
-    .purge(crate::s3_paths::scope_api_cache_urls(registry_url, &scope))
-    .await;
+    .purge(crate::s3_paths::scope_api_cache_urls(registry_url, &scope));
 
   Ok(ApiPackage::from((package, None, Default::default())))
 }
@@ -417,12 +840,19 @@ pub async fn get_handler(req: Request<Body>) -> ApiResult<ApiPackage> {
   Span::current().record("package", field::display(&package));
 
   let db = req.data::<Database>().unwrap();
-  let res_package = db
-    .get_package(&scope, &package)
-    .await?
-    .ok_or(ApiError::PackageNotFound)?;
+  let Some(res_package) = db.get_package_cached(&scope, &package).await? else {
+    // The package may have been transferred to another scope.
+    return match db.get_package_redirect(&scope, &package).await? {
+      Some(redirect) => Err(ApiError::PackageMoved {
+        scope: redirect.target_scope,
+        package: redirect.target_name,
+      }),
+      None => Err(ApiError::PackageNotFound),
+    };
+  };
 
-  let mut api_package = ApiPackage::from(res_package);
+  let mut api_package =
+    ApiPackage::from(res_package).localize(&util::preferred_locales(&req));
 
   if let Some(latest_v) = &api_package.latest_version {
     let latest_version = Version::new(latest_v).unwrap();
@@ -443,9 +873,81 @@ pub async fn get_handler(req: Request<Body>) -> ApiResult<ApiPackage> {
     .await?;
   api_package.dependent_count = dependent_count as u64;
 
+  let downloads_last_30_days = db
+    .get_package_downloads_total(
+      &scope,
+      &package,
+      Utc::now() - chrono::Duration::days(30),
+    )
+    .await?;
+  api_package.downloads_last_30_days = downloads_last_30_days.max(0) as u64;
+
   Ok(api_package)
 }
 
+/// Reconstructs the version list of a package as of the `as_of` query
+/// parameter, which is either an RFC 3339 timestamp or a `YYYY-MM-DD` date
+/// (midnight UTC). Snapshots of the present or the future can still change,
+/// so only snapshots of the past are cached.
+#[instrument(
+  name = "GET /api/scopes/:scope/packages/:package/snapshot",
+  skip(req),
+  fields(scope, package)
+)]
+pub async fn get_snapshot_handler(
+  req: Request<Body>,
+) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+
+  let as_of = req.query("as_of").ok_or(ApiError::MalformedRequest {
+    msg: "missing 'as_of' parameter".into(),
+  })?;
+  let as_of = parse_as_of(as_of).ok_or_else(|| ApiError::MalformedRequest {
+    msg: "'as_of' must be an RFC 3339 timestamp or a YYYY-MM-DD date".into(),
+  })?;
+
+  let db = req.data::<Database>().unwrap();
+  let (res_package, _, _) = db
+    .get_package(&scope, &package)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+  if res_package.created_at > as_of {
+    return Err(ApiError::PackageNotFound);
+  }
+
+  let versions = db
+    .list_package_versions_as_of(&scope, &package, as_of)
+    .await?;
+  let metadata = crate::metadata::PackageMetadata::from_versions(
+    &scope,
+    &package,
+    versions,
+    &[],
+  );
+
+  let snapshot: ApiPackageSnapshot = (metadata, as_of).into();
+  let mut res = util::respond_json(&snapshot, StatusCode::OK);
+  if as_of >= Utc::now() {
+    res.headers_mut().insert(
+      hyper::header::CACHE_CONTROL,
+      hyper::header::HeaderValue::from_static("no-store"),
+    );
+  }
+  Ok(res)
+}
+
+fn parse_as_of(as_of: &str) -> Option<DateTime<Utc>> {
+  if let Ok(as_of) = DateTime::parse_from_rfc3339(as_of) {
+    return Some(as_of.with_timezone(&Utc));
+  }
+  let date = chrono::NaiveDate::parse_from_str(as_of, "%Y-%m-%d").ok()?;
+  Some(date.and_hms_opt(0, 0, 0)?.and_utc())
+}
+
 #[instrument(
   name = "PATCH /api/scopes/:scope/packages/:package",
   skip(req),
@@ -467,16 +969,36 @@ pub async fn update_handler(mut req: Request<Body>) -> ApiResult<ApiPackage> {
 
   let iam = req.iam();
   // Updating if a package is featured is allowed for admins, update package
-  // description is allowed for all members, updating the repo
-  // requires admin permissions because it extends who can publish new
-  // versions (anyone with write access to the repo).
+  // description is allowed for everyone who may publish the package, updating
+  // the repo requires the permission to manage tokens because it extends who
+  // can publish new versions (anyone with write access to the repo).
   let (user, sudo) = if matches!(body, ApiUpdatePackageRequest::IsFeatured(_)) {
     let user = iam.check_admin_access()?;
     (user, true)
-  } else if matches!(body, ApiUpdatePackageRequest::Description(_)) {
-    iam.check_scope_write_access(&scope).await?
+  } else if matches!(
+    body,
+    ApiUpdatePackageRequest::Description(_)
+      | ApiUpdatePackageRequest::LocalizedDescription(_)
+  ) {
+    iam
+      .check_package_permission(
+        &scope,
+        &package_name,
+        PackagePermission::Publish,
+      )
+      .await?
+  } else if matches!(body, ApiUpdatePackageRequest::GithubRepository(_)) {
+    iam
+      .check_package_permission(
+        &scope,
+        &package_name,
+        PackagePermission::ManageTokens,
+      )
+      .await?
   } else {
-    iam.check_scope_admin_access(&scope).await?
+    iam
+      .check_package_admin_access(&scope, &package_name)
+      .await?
   };
 
   if package.is_archived
@@ -489,7 +1011,7 @@ pub async fn update_handler(mut req: Request<Body>) -> ApiResult<ApiPackage> {
   // so cache-bust it (and the scope aggregates) afterwards. Built before the
   // match because the GitHub-repository arm consumes `scope`/`package_name`.
   let registry_url = req.data::<RegistryUrl>().unwrap().0.clone();
-  let purge_urls = crate::s3_paths::package_api_cache_urls(
+  let mut purge_urls = crate::s3_paths::package_api_cache_urls(
     &registry_url,
     &scope,
     &package_name,
@@ -498,11 +1020,13 @@ pub async fn update_handler(mut req: Request<Body>) -> ApiResult<ApiPackage> {
   let result = match body {
     ApiUpdatePackageRequest::Description(description) => {
       let npm_url = &req.data::<NpmUrl>().unwrap().0;
+      let npm_signer = req.data::<NpmSigner>().unwrap();
       let buckets = req.data::<Buckets>().unwrap().clone();
       let cache_purge = req.data::<CachePurge>().unwrap();
       let package = update_description(
         db,
         npm_url,
+        npm_signer,
         &buckets,
         cache_purge,
         algolia_client,
@@ -515,6 +1039,36 @@ pub async fn update_handler(mut req: Request<Body>) -> ApiResult<ApiPackage> {
       .await?;
       Ok(ApiPackage::from((package, repo, meta)))
     }
+    ApiUpdatePackageRequest::LocalizedDescription(
+      ApiUpdatePackageLocalizedDescriptionRequest {
+        locale,
+        description,
+      },
+    ) => {
+      let locale = util::normalize_locale(&locale).ok_or_else(|| {
+        ApiError::MalformedRequest {
+          msg: "locale must be a valid BCP 47 language tag".into(),
+        }
+      })?;
+      let description = description
+        .map(normalize_description)
+        .transpose()?
+        .filter(|description| !description.is_empty());
+      let package = db
+        .update_package_localized_description(
+          &user.id,
+          sudo,
+          &scope,
+          &package_name,
+          &locale,
+          description.as_deref(),
+        )
+        .await?;
+      if let Some(algolia_client) = algolia_client {
+        algolia_client.upsert_package(&package, &meta);
+      }
+      Ok(ApiPackage::from((package, repo, meta)))
+    }
     ApiUpdatePackageRequest::GithubRepository(None) => {
       let package = db
         .delete_package_github_repository(&user.id, sudo, &scope, &package_name)
@@ -574,6 +1128,30 @@ pub async fn update_handler(mut req: Request<Body>) -> ApiResult<ApiPackage> {
         )
         .await?;
 
+      // The archived state is part of the package metadata file, so it has to
+      // be regenerated.
+      let buckets = req.data::<Buckets>().unwrap();
+      let package_metadata =
+        PackageMetadata::create(db, &scope, &package_name).await?;
+      let content = serde_json::to_vec(&package_metadata)?;
+      buckets
+        .modules_bucket
+        .upload(
+          crate::s3_paths::package_metadata(&scope, &package_name).into(),
+          UploadTaskBody::Bytes(content.into()),
+          S3UploadOptions {
+            content_type: Some("application/json".into()),
+            cache_control: Some(CACHE_CONTROL_MANIFEST.into()),
+            content_encoding: ContentEncoding::Identity,
+          },
+        )
+        .await?;
+      purge_urls.push(crate::s3_paths::package_metadata_url(
+        &registry_url,
+        &scope,
+        &package_name,
+      ));
+
       if let Some(algolia_client) = algolia_client {
         if package.is_archived {
           algolia_client.delete_package(&scope, &package.name);
@@ -595,18 +1173,144 @@ pub async fn update_handler(mut req: Request<Body>) -> ApiResult<ApiPackage> {
         )
         .await?;
 
+      // The readme is rendered into the docs of every version.
+      purge_urls.extend(
+        versions_api_cache_urls(db, &registry_url, &scope, &package_name)
+          .await?,
+      );
+
       Ok(ApiPackage::from((package, repo, meta)))
     }
-  };
-
-  let result = result?;
-  let cache_purge = req.data::<CachePurge>().unwrap();
-  cache_purge.purge(purge_urls).await;
+    ApiUpdatePackageRequest::Visibility(visibility) => {
+      let visibility = PackageVisibility::from(visibility);
+      let package = db
+        .update_package_visibility(
+          &user.id,
+          sudo,
+          &scope,
+          &package_name,
+          visibility,
+        )
+        .await?;
 
-  Ok(result)
-}
+      // The lb does not serve the files of packages with a marker from the
+      // buckets, but has the API serve them to those that may read them.
+      let buckets = req.data::<Buckets>().unwrap();
+      let marker_path =
+        crate::s3_paths::private_package_marker(&scope, &package_name);
+      match visibility {
+        PackageVisibility::Private => {
+          buckets
+            .modules_bucket
+            .upload(
+              marker_path.into(),
+              UploadTaskBody::Bytes(bytes::Bytes::new()),
+              S3UploadOptions {
+                content_type: None,
+                cache_control: None,
+                content_encoding: ContentEncoding::Identity,
+              },
+            )
+            .await?;
+        }
+        PackageVisibility::Public => {
+          buckets
+            .modules_bucket
+            .delete_file(marker_path.into())
+            .await?;
+        }
+      }
 
-#[allow(clippy::too_many_arguments)]
+      if let Some(algolia_client) = algolia_client {
+        algolia_client.upsert_package(&package, &meta);
+      }
+
+      // Responses for specific versions, and the files the lb serves, are
+      // cached publicly for a long time, and must not outlive the package
+      // becoming private.
+      let npm_url = &req.data::<NpmUrl>().unwrap().0;
+      let cache_purge = req.data::<CachePurge>().unwrap();
+      for version in db
+        .list_package_versions_for_metadata(&scope, &package_name)
+        .await?
+      {
+        crate::quarantine::purge_version_caches(
+          db,
+          cache_purge,
+          &registry_url,
+          npm_url,
+          &scope,
+          &package_name,
+          &version.version,
+        )
+        .await?;
+      }
+      purge_urls.push(crate::s3_paths::package_metadata_url(
+        &registry_url,
+        &scope,
+        &package_name,
+      ));
+      purge_urls.push(crate::s3_paths::npm_version_manifest_url(
+        npm_url,
+        &scope,
+        &package_name,
+      ));
+
+      Ok(ApiPackage::from((package, repo, meta)))
+    }
+  };
+
+  let result = result?;
+  let cache_purge = req.data::<CachePurge>().unwrap();
+  cache_purge.purge(purge_urls);
+
+  Ok(result)
+}
+
+/// The API cache URLs of every version of a package.
+async fn versions_api_cache_urls(
+  db: &Database,
+  registry_url: &Url,
+  scope: &ScopeName,
+  package_name: &PackageName,
+) -> Result<Vec<String>, ApiError> {
+  let versions = db
+    .list_package_versions_for_metadata(scope, package_name)
+    .await?;
+  Ok(
+    versions
+      .iter()
+      .flat_map(|version| {
+        crate::s3_paths::package_version_api_cache_urls(
+          registry_url,
+          scope,
+          package_name,
+          &version.version,
+        )
+      })
+      .collect(),
+  )
+}
+
+fn normalize_description(description: String) -> Result<String, ApiError> {
+  let description = description.trim().replace('\n', " ").replace('\r', "");
+
+  if description.len() > 250 {
+    return Err(ApiError::MalformedRequest {
+      msg: "description must not be longer than 250 characters".into(),
+    });
+  }
+
+  if description.contains(|c: char| c.is_control()) {
+    return Err(ApiError::MalformedRequest {
+      msg: "description must not contain control characters".into(),
+    });
+  }
+
+  Ok(description)
+}
+
+#[allow(clippy::too_many_arguments)]
 #[instrument(
   skip(
     db,
@@ -625,6 +1329,7 @@ pub async fn update_handler(mut req: Request<Body>) -> ApiResult<ApiPackage> {
 async fn update_description(
   db: &Database,
   npm_url: &Url,
+  npm_signer: &NpmSigner,
   buckets: &Buckets,
   cache_purge: &CachePurge,
   algolia_client: &Option<AlgoliaClient>,
@@ -634,19 +1339,7 @@ async fn update_description(
   package_name: &PackageName,
   description: String,
 ) -> Result<Package, ApiError> {
-  let description = description.trim().replace('\n', " ").replace('\r', "");
-
-  if description.len() > 250 {
-    return Err(ApiError::MalformedRequest {
-      msg: "description must not be longer than 250 characters".into(),
-    });
-  }
-
-  if description.contains(|c: char| c.is_control()) {
-    return Err(ApiError::MalformedRequest {
-      msg: "description must not contain control characters".into(),
-    });
-  }
+  let description = normalize_description(description)?;
 
   let (package, _, meta) = db
     .update_package_description(
@@ -664,8 +1357,14 @@ async fn update_description(
 
   let npm_version_manifest_path =
     crate::s3_paths::npm_version_manifest_path(scope, &package.name);
-  let npm_version_manifest =
-    generate_npm_version_manifest(db, npm_url, scope, &package.name).await?;
+  let npm_version_manifest = generate_npm_version_manifest(
+    db,
+    npm_url,
+    npm_signer,
+    scope,
+    &package.name,
+  )
+  .await?;
   let content = serde_json::to_vec_pretty(&npm_version_manifest)?;
   buckets
     .npm_bucket
@@ -675,18 +1374,16 @@ async fn update_description(
       S3UploadOptions {
         content_type: Some("application/json".into()),
         cache_control: Some(CACHE_CONTROL_MANIFEST.into()),
-        gzip_encoded: false,
+        content_encoding: ContentEncoding::Identity,
       },
     )
     .await?;
 
-  cache_purge
-    .purge(vec![crate::s3_paths::npm_version_manifest_url(
-      npm_url,
-      scope,
-      &package.name,
-    )])
-    .await;
+  cache_purge.purge(vec![crate::s3_paths::npm_version_manifest_url(
+    npm_url,
+    scope,
+    &package.name,
+  )]);
 
   Ok(package)
 }
@@ -765,15 +1462,22 @@ pub async fn list_versions_handler(
   Span::current().record("package", field::display(&package));
 
   let (start, limit) = pagination(&req);
+  let prereleases = prerelease_query(&req, true)?;
 
   let db = req.data::<Database>().unwrap();
 
-  db.get_package(&scope, &package)
+  db.get_package_cached(&scope, &package)
     .await?
     .ok_or(ApiError::PackageNotFound)?;
 
   let (total, versions) = db
-    .list_package_versions_paginated(&scope, &package, start, limit)
+    .list_package_versions_paginated_cached(
+      &scope,
+      &package,
+      prereleases,
+      start,
+      limit,
+    )
     .await?;
 
   Ok(ApiList {
@@ -816,13 +1520,11 @@ pub async fn delete_handler(req: Request<Body>) -> ApiResult<Response<Body>> {
 
   let registry_url = &req.data::<RegistryUrl>().unwrap().0;
   let cache_purge = req.data::<CachePurge>().unwrap();
-  cache_purge
-    .purge(crate::s3_paths::package_api_cache_urls(
-      registry_url,
-      &scope,
-      &package,
-    ))
-    .await;
+  cache_purge.purge(crate::s3_paths::package_api_cache_urls(
+    registry_url,
+    &scope,
+    &package,
+  ));
 
   let res = Response::builder()
     .status(StatusCode::NO_CONTENT)
@@ -845,6 +1547,7 @@ pub async fn get_version_handler(
   Span::current().record("scope", field::display(&scope));
   Span::current().record("package", field::display(&package));
   Span::current().record("version", field::display(&version));
+  let prereleases = prerelease_query(&req, false)?;
 
   let db = req.data::<Database>().unwrap();
   let _ = db
@@ -859,6 +1562,26 @@ pub async fn get_version_handler(
       )
       .await?
     }
+    VersionOrLatest::Latest if prereleases => {
+      // The collation of the version column does not order pre-releases by
+      // semver precedence, so the newest version is picked here.
+      let newest = db
+        .list_package_versions_for_metadata(&scope, &package)
+        .await?
+        .into_iter()
+        .filter(|version| !version.is_yanked)
+        .map(|version| version.version)
+        .max();
+      match newest {
+        Some(version) => {
+          db.get_package_version_with_newer_versions_count(
+            &scope, &package, &version,
+          )
+          .await?
+        }
+        None => None,
+      }
+    }
     VersionOrLatest::Latest => {
       db.get_latest_unyanked_version_for_package_with_newer_versions_count(
         &scope, &package,
@@ -872,98 +1595,113 @@ pub async fn get_version_handler(
   Ok(ApiPackageVersion::from(version))
 }
 
-#[instrument(
-  name = "POST /api/scopes/:scope/packages/:package/versions/:version",
-  skip(req),
-  fields(scope, package, version)
-)]
-pub async fn version_publish_handler(
-  req: Request<Body>,
-) -> ApiResult<ApiPublishingTask> {
-  let package_scope = req.param_scope()?;
-  let package_name = req.param_package()?;
-  let package_version = req.param_version()?;
-  Span::current().record("scope", field::display(&package_scope));
-  Span::current().record("package", field::display(&package_name));
-  Span::current().record("version", field::display(&package_version));
-  let config_file =
-    PackagePath::try_from(&**req.query("config").ok_or_else(|| {
-      let msg = "Missing query parameter 'config'".into();
-      ApiError::MalformedRequest { msg }
-    })?)
-    .map_err(|err| {
-      let msg = format!(
-        "failed to parse query parameter 'config' with value '{}': {err}",
-        req.query("config").unwrap()
+/// The `prerelease` query parameter of version listing and resolution: whether
+/// pre-release versions are included.
+fn prerelease_query(
+  req: &Request<Body>,
+  default: bool,
+) -> Result<bool, ApiError> {
+  match req.query("prerelease").map(|prerelease| prerelease.as_str()) {
+    None => Ok(default),
+    Some("true") => Ok(true),
+    Some("false") => Ok(false),
+    Some(prerelease) => Err(ApiError::MalformedRequest {
+      msg: format!(
+        "invalid value for query parameter 'prerelease': '{prerelease}', expected 'true' or 'false'"
       )
-      .into();
-      ApiError::MalformedRequest { msg }
-    })?;
+      .into(),
+    }),
+  }
+}
+
+/// The `config` query parameter of a publish: the path of the config file
+/// within the tarball.
+pub(super) fn config_file_query(
+  req: &Request<Body>,
+) -> Result<PackagePath, ApiError> {
+  PackagePath::try_from(&**req.query("config").ok_or_else(|| {
+    let msg = "Missing query parameter 'config'".into();
+    ApiError::MalformedRequest { msg }
+  })?)
+  .map_err(|err| {
+    let msg = format!(
+      "failed to parse query parameter 'config' with value '{}': {err}",
+      req.query("config").unwrap()
+    )
+    .into();
+    ApiError::MalformedRequest { msg }
+  })
+}
 
+fn check_publish_tarball_headers(
+  req: &Request<Body>,
+  max_tarball_size: u64,
+) -> Result<(), ApiError> {
   // If there is a content-length header, check it isn't too big.
-  // We don't rely on this, we will also check MAX_PAYLOAD_SIZE later.
+  // We don't rely on this, we will also check the size of the body later.
   if let Some(size) = req.body().size_hint().upper()
-    && size > MAX_PUBLISH_TARBALL_SIZE
+    && size > max_tarball_size
   {
     return Err(ApiError::TarballSizeLimitExceeded {
       size,
-      max_size: MAX_PUBLISH_TARBALL_SIZE,
+      max_size: max_tarball_size,
     });
   }
 
   // Ensure the upload is gzip encoded.
   match req.headers().get(hyper::header::CONTENT_ENCODING) {
-    Some(val) if val == "gzip" => (),
-    _ => return Err(ApiError::MissingGzipContentEncoding),
+    Some(val) if val == "gzip" => Ok(()),
+    _ => Err(ApiError::MissingGzipContentEncoding),
   }
+}
+
+#[instrument(
+  name = "POST /api/scopes/:scope/packages/:package/versions/:version",
+  skip(req),
+  fields(scope, package, version)
+)]
+pub async fn version_publish_handler(
+  mut req: Request<Body>,
+) -> ApiResult<ApiPublishingTask> {
+  let package_scope = req.param_scope()?;
+  let package_name = req.param_package()?;
+  let package_version = req.param_version()?;
+  Span::current().record("scope", field::display(&package_scope));
+  Span::current().record("package", field::display(&package_name));
+  Span::current().record("version", field::display(&package_version));
+  let config_file = config_file_query(&req)?;
 
   let db = req.data::<Database>().unwrap().clone();
   let buckets = req.data::<Buckets>().unwrap().clone();
-  let license_store = req.data::<LicenseStore>().unwrap().clone();
-  let registry_url = req.data::<RegistryUrl>().unwrap().0.clone();
-  let npm_url = req.data::<NpmUrl>().unwrap().0.clone();
-  let publish_queue = req.data::<PublishQueue>().unwrap().0.clone();
-  let cache_purge = req.data::<CachePurge>().unwrap().clone();
-  let algolia_client = req.data::<Option<AlgoliaClient>>().unwrap().clone();
+
+  let limits = PublishLimits::for_scope(&db, &package_scope).await?;
+  check_publish_tarball_headers(&req, limits.max_tarball_size)?;
 
   let iam = req.iam();
   let (access_restriction, user_id) = iam
     .check_publish_access(&package_scope, &package_name, &package_version)
     .await?;
 
-  let (package, _, _) = db
-    .get_package(&package_scope, &package_name)
-    .await?
-    .ok_or(ApiError::PackageNotFound)?;
-
-  if package.is_archived {
-    return Err(ApiError::PackageArchived);
-  }
+  let quota =
+    check_package_publishable(&db, &package_scope, &package_name).await?;
+  // The uploaded tarball may not be larger than the remaining storage quota.
+  let remaining_storage = quota.remaining();
 
-  let res = db
-    .create_publishing_task(NewPublishingTask {
+  let (publishing_task, user) = start_publishing_task(
+    &db,
+    NewPublishingTask {
       user_id,
-      package_scope: &package.scope,
-      package_name: &package.name,
+      package_scope: &package_scope,
+      package_name: &package_name,
       package_version: &package_version,
       config_file: &config_file,
-    })
-    .await?;
-  let (publishing_task, user) = match res {
-    CreatePublishingTaskResult::Created(publishing_task) => publishing_task,
-    CreatePublishingTaskResult::Exists(task) => {
-      return Err(ApiError::DuplicateVersionPublish {
-        task: Box::new(task.into()),
-      });
-    }
-    CreatePublishingTaskResult::WeeklyPublishAttemptsLimitExceeded(limit) => {
-      return Err(ApiError::WeeklyPublishAttemptsLimitExceeded { limit });
-    }
-  };
+    },
+  )
+  .await?;
 
   let s3_path = bucket_tarball_path(publishing_task.id);
 
-  let body = req.into_body();
+  let body = std::mem::take(req.body_mut());
   let total_size = Arc::new(AtomicU64::new(0));
   let total_size_ = total_size.clone();
 
@@ -974,7 +1712,8 @@ pub async fn version_publish_handler(
     Ok(bytes) => {
       hash_.lock().unwrap().as_mut().unwrap().update(&bytes);
       total_size_.fetch_add(bytes.len() as u64, Ordering::SeqCst);
-      if total_size_.load(Ordering::SeqCst) > MAX_PUBLISH_TARBALL_SIZE {
+      let size = total_size_.load(Ordering::SeqCst);
+      if size > limits.max_tarball_size || size > remaining_storage {
         Err(io::Error::other("Payload too large"))
       } else {
         Ok(bytes)
@@ -991,77 +1730,417 @@ pub async fn version_publish_handler(
       S3UploadOptions {
         content_type: Some("application/x-tar".into()),
         cache_control: None,
-        gzip_encoded: true,
+        content_encoding: ContentEncoding::Gzip,
       },
     )
     .await;
 
   let hash = hash.lock().unwrap().take().unwrap().finalize();
   let hash = format!("sha256-{:02x}", hash);
-  if let Some(tarball_hash) = access_restriction.tarball_hash
-    && tarball_hash != hash
-  {
-    error!(
-      "Tarball hash mismatch: expected {}, got {}",
-      tarball_hash, hash
-    );
-    return Err(ApiError::MissingPermission);
-  }
+  check_publish_tarball_hash(&access_restriction, &hash)?;
 
   // If the upload failed due to the size limit, we can cancel the task.
   let total_size = total_size.load(Ordering::SeqCst);
-  if total_size > MAX_PUBLISH_TARBALL_SIZE {
+  if total_size > limits.max_tarball_size {
     return Err(ApiError::TarballSizeLimitExceeded {
       size: total_size,
-      max_size: MAX_PUBLISH_TARBALL_SIZE,
+      max_size: limits.max_tarball_size,
     });
   }
+  quota.check(total_size)?;
 
   // Otherwise, we can just propagate the error.
   upload_result?;
 
+  enqueue_publishing_task(&req, publishing_task.id, &hash).await?;
+
+  Ok((publishing_task, user).into())
+}
+
+/// The storage that a publish may still use in the scope of the package.
+pub(super) struct PublishStorageQuota {
+  usage: i64,
+  limit: i64,
+}
+
+impl PublishStorageQuota {
+  pub(super) fn remaining(&self) -> u64 {
+    (self.limit - self.usage) as u64
+  }
+
+  pub(super) fn check(&self, size: u64) -> Result<(), ApiError> {
+    if size > self.remaining() {
+      return Err(ApiError::ScopeStorageLimitExceeded {
+        usage: self.usage + size as i64,
+        limit: self.limit,
+      });
+    }
+    Ok(())
+  }
+}
+
+/// Checks that a new version of the package may be published, and returns the
+/// storage that is left for it in the scope.
+pub(super) async fn check_package_publishable(
+  db: &Database,
+  scope_name: &ScopeName,
+  package_name: &PackageName,
+) -> Result<PublishStorageQuota, ApiError> {
+  let (package, _, _) = db
+    .get_package(scope_name, package_name)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+
+  if package.is_archived {
+    return Err(ApiError::PackageArchived);
+  }
+
+  let scope = db
+    .get_scope(scope_name)
+    .await?
+    .ok_or(ApiError::ScopeNotFound)?;
+  let scope_usage = db.get_scope_usage(scope_name).await?;
+  if scope_usage.storage >= scope.storage_limit {
+    return Err(ApiError::ScopeStorageLimitExceeded {
+      usage: scope_usage.storage,
+      limit: scope.storage_limit,
+    });
+  }
+
+  Ok(PublishStorageQuota {
+    usage: scope_usage.storage,
+    limit: scope.storage_limit,
+  })
+}
+
+pub(super) async fn start_publishing_task(
+  db: &Database,
+  new_task: NewPublishingTask<'_>,
+) -> Result<(PublishingTask, Option<UserPublic>), ApiError> {
+  match db.create_publishing_task(new_task).await? {
+    CreatePublishingTaskResult::Created(publishing_task) => Ok(publishing_task),
+    CreatePublishingTaskResult::Exists(task) => {
+      Err(ApiError::DuplicateVersionPublish {
+        task: Box::new(task.into()),
+      })
+    }
+    CreatePublishingTaskResult::WeeklyPublishAttemptsLimitExceeded(limit) => {
+      Err(ApiError::WeeklyPublishAttemptsLimitExceeded { limit })
+    }
+  }
+}
+
+/// Tokens that were issued for a single publish may only publish the tarball
+/// that they were issued for.
+pub(super) fn check_publish_tarball_hash(
+  access_restriction: &PublishAccessRestriction,
+  hash: &str,
+) -> Result<(), ApiError> {
+  if let Some(tarball_hash) = &access_restriction.tarball_hash
+    && tarball_hash != hash
+  {
+    error!(
+      "Tarball hash mismatch: expected {}, got {}",
+      tarball_hash, hash
+    );
+    return Err(ApiError::MissingPermission);
+  }
+  Ok(())
+}
+
+/// Queues a publishing task whose tarball was uploaded to be processed.
+pub(super) async fn enqueue_publishing_task(
+  req: &Request<Body>,
+  publishing_task_id: Uuid,
+  tarball_hash: &str,
+) -> Result<(), ApiError> {
+  let db = req.data::<Database>().unwrap();
+
   // Record the hash of the uploaded tarball so that a later provenance
   // statement can be bound to the actual published bytes, not just the package
   // name@version.
-  db.set_publishing_task_tarball_hash(publishing_task.id, &hash)
+  db.set_publishing_task_tarball_hash(publishing_task_id, tarball_hash)
     .await?;
 
+  dispatch_publishing_task(req, publishing_task_id).await
+}
+
+/// Hands a publishing task to the publish queue, or processes it in the
+/// background if there is no queue (e.g. local dev).
+pub(super) async fn dispatch_publishing_task(
+  req: &Request<Body>,
+  publishing_task_id: Uuid,
+) -> Result<(), ApiError> {
+  let db = req.data::<Database>().unwrap().clone();
+  let buckets = req.data::<Buckets>().unwrap().clone();
+  let license_store = req.data::<LicenseStore>().unwrap().clone();
+  let malware_scanners = req.data::<MalwareScanners>().unwrap().clone();
+  let registry_url = req.data::<RegistryUrl>().unwrap().0.clone();
+  let npm_url = req.data::<NpmUrl>().unwrap().0.clone();
+  let npm_signer = req.data::<NpmSigner>().unwrap().clone();
+  let publish_queue = req.data::<PublishQueue>().unwrap().0.clone();
+  let cache_purge = req.data::<CachePurge>().unwrap().clone();
+  let algolia_client = req.data::<Option<AlgoliaClient>>().unwrap().clone();
+  let feature_flags = req.data::<FeatureFlags>().unwrap().clone();
+
   if let Some(queue) = publish_queue {
-    let body = serde_json::to_vec(&publishing_task.id).unwrap();
+    let body = serde_json::to_vec(&publishing_task_id).unwrap();
     queue.task_buffer(None, Some(body.into())).await?;
   } else {
     let span = Span::current();
     let fut = publish_task(
-      publishing_task.id,
+      publishing_task_id,
       buckets,
       license_store,
+      malware_scanners,
       registry_url,
       npm_url,
+      npm_signer,
       db,
       algolia_client,
       cache_purge,
+      feature_flags,
     )
     .instrument(span);
     tokio::spawn(fut);
   }
 
-  Ok((publishing_task, user).into())
+  Ok(())
 }
 
+/// Runs all the checks and the analysis of a publish on the uploaded tarball,
+/// and reports the outcome, without publishing anything.
 #[instrument(
-  name = "POST /api/scopes/:scope/packages/:package/versions/:version/provenance",
+  name = "POST /api/scopes/:scope/packages/:package/versions/:version/dry-run",
   skip(req),
   fields(scope, package, version)
 )]
-pub async fn version_provenance_statements_handler(
-  mut req: Request<Body>,
-) -> ApiResult<Response<Body>> {
-  let scope = req.param_scope()?;
-  let package = req.param_package()?;
-  let version = req.param_version()?;
-
-  Span::current().record("scope", field::display(&scope));
-  Span::current().record("package", field::display(&package));
+pub async fn version_dry_run_handler(
+  req: Request<Body>,
+) -> ApiResult<ApiPublishDryRun> {
+  let package_scope = req.param_scope()?;
+  let package_name = req.param_package()?;
+  let package_version = req.param_version()?;
+  Span::current().record("scope", field::display(&package_scope));
+  Span::current().record("package", field::display(&package_name));
+  Span::current().record("version", field::display(&package_version));
+  let config_file = config_file_query(&req)?;
+
+  let db = req.data::<Database>().unwrap().clone();
+  let limits = PublishLimits::for_scope(&db, &package_scope).await?;
+  check_publish_tarball_headers(&req, limits.max_tarball_size)?;
+
+  let buckets = req.data::<Buckets>().unwrap().clone();
+  let license_store = req.data::<LicenseStore>().unwrap().clone();
+  let registry_url = req.data::<RegistryUrl>().unwrap().0.clone();
+  let feature_flags = req.data::<FeatureFlags>().unwrap().clone();
+
+  let iam = req.iam();
+  let (access_restriction, user_id) = iam
+    .check_publish_access(&package_scope, &package_name, &package_version)
+    .await?;
+
+  let (package, _, _) = db
+    .get_package(&package_scope, &package_name)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+
+  if package.is_archived {
+    return Err(ApiError::PackageArchived);
+  }
+
+  let mut body = req.into_body();
+  let mut tarball = Vec::new();
+  while let Some(chunk) = body.data().await {
+    tarball.extend_from_slice(&chunk.map_err(anyhow::Error::from)?);
+    if tarball.len() as u64 > limits.max_tarball_size {
+      return Err(ApiError::TarballSizeLimitExceeded {
+        size: tarball.len() as u64,
+        max_size: limits.max_tarball_size,
+      });
+    }
+  }
+
+  let hash = format!("sha256-{:02x}", sha2::Sha256::digest(&tarball));
+  check_publish_tarball_hash(&access_restriction, &hash)?;
+
+  let unstable_bytes_imports = feature_flags
+    .is_enabled(&db, UNSTABLE_BYTES_IMPORTS, Some(&package.scope), user_id)
+    .await?;
+
+  let analyzed = match analyze_tarball(
+    &db,
+    &license_store,
+    registry_url,
+    &package.scope,
+    &package.name,
+    &package_version,
+    &config_file,
+    unstable_bytes_imports,
+    &[],
+    futures::io::Cursor::new(tarball),
+  )
+  .await
+  {
+    Ok(analyzed) => analyzed,
+    Err(err) => match err.user_error_code() {
+      Some(code) => {
+        return Ok(ApiPublishDryRun {
+          error: Some(ApiPublishingTaskError {
+            code: code.to_owned(),
+            message: err.to_string(),
+          }),
+          analysis: None,
+        });
+      }
+      None => return Err(anyhow::Error::from(err).into()),
+    },
+  };
+
+  let AnalyzedTarball {
+    file_infos,
+    exports,
+    doc_nodes,
+    dependencies,
+    optional_dependencies,
+    deprecations,
+    npm_tarball,
+    meta,
+    license,
+    ..
+  } = analyzed;
+
+  let preview_diff = crate::publish::publish_preview_diff(
+    &db,
+    &buckets,
+    &package.scope,
+    &package.name,
+    &file_infos,
+    &doc_nodes,
+  )
+  .await
+  .unwrap_or_else(|err| {
+    error!("failed to compute the preview diff: {err:#}");
+    None
+  });
+
+  let mut dependencies = dependencies
+    .into_iter()
+    .map(|(kind, req)| ApiDependency {
+      kind: kind.into(),
+      optional: optional_dependencies.contains(&(kind, req.clone())),
+      name: req.req.name.to_string(),
+      constraint: req.req.version_req.version_text().to_string(),
+      path: req.sub_path.as_deref().unwrap_or("").to_string(),
+      archived: false,
+    })
+    .collect::<Vec<_>>();
+  mark_archived_dependencies(&db, &mut dependencies).await?;
+  dependencies.sort_by(|a, b| {
+    (&a.name, &a.constraint, &a.path).cmp(&(&b.name, &b.constraint, &b.path))
+  });
+
+  Ok(ApiPublishDryRun {
+    error: None,
+    analysis: Some(ApiPublishDryRunAnalysis {
+      license,
+      exports: exports.into_inner(),
+      files: file_infos
+        .into_iter()
+        .map(|file| ApiPublishDryRunFile {
+          path: file.path,
+          size: file.size,
+          checksum: file.hash,
+        })
+        .collect(),
+      dependencies,
+      deprecations: deprecations
+        .into_iter()
+        .map(|deprecation| ApiDeprecation {
+          export: deprecation.export,
+          symbol: deprecation.symbol,
+          message: deprecation.message,
+        })
+        .collect(),
+      diagnostics: publish_diagnostics(&meta),
+      score: ApiPackageScore::from((&meta, &package)),
+      npm_package_json: serde_json::from_str(&npm_tarball.package_json)?,
+      npm_tarball_size: npm_tarball.tarball.len() as u64,
+      preview_diff,
+    }),
+  })
+}
+
+/// Problems found by the analysis of a package version that do not fail the
+/// publish.
+fn publish_diagnostics(meta: &PackageVersionMeta) -> Vec<ApiPublishDiagnostic> {
+  let details = &meta.score_details;
+  let mut diagnostics = Vec::new();
+  let mut push = |code: &str, message: String| {
+    diagnostics.push(ApiPublishDiagnostic {
+      code: code.to_owned(),
+      message,
+    })
+  };
+
+  for path in &details.entrypoints_with_slow_types {
+    push(
+      "slowTypes",
+      format!("the entrypoint '{path}' has slow types"),
+    );
+  }
+  for path in &details.entrypoints_without_module_doc {
+    push(
+      "missingModuleDoc",
+      format!("the entrypoint '{path}' has no module doc"),
+    );
+  }
+  for example in &details.failing_examples {
+    push("failingExample", example.clone());
+  }
+  if let Some(readme) = &details.readme {
+    for link in &readme.broken_links {
+      push(
+        "brokenReadmeLink",
+        format!("the readme links to '{link}', which does not exist"),
+      );
+    }
+  }
+  for entrypoint in &meta.degraded_docs_entrypoints {
+    push(
+      "degradedDocs",
+      format!(
+        "the docs of the entrypoint '{}' could not be generated: {}",
+        entrypoint.path, entrypoint.reason
+      ),
+    );
+  }
+  if meta.search_index_truncated {
+    push(
+      "searchIndexTruncated",
+      "the package exceeds the limits of the docs search index, so some of \
+       its symbols can not be searched for"
+        .to_string(),
+    );
+  }
+
+  diagnostics
+}
+
+#[instrument(
+  name = "POST /api/scopes/:scope/packages/:package/versions/:version/provenance",
+  skip(req),
+  fields(scope, package, version)
+)]
+pub async fn version_provenance_statements_handler(
+  mut req: Request<Body>,
+) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  let version = req.param_version()?;
+
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
   Span::current().record("version", field::display(&version));
 
   let body: ApiProvenanceStatementRequest = decode_json(&mut req).await?;
@@ -1093,16 +2172,70 @@ pub async fn version_provenance_statements_handler(
     })?;
 
   let name = format!("pkg:jsr/@{}/{}@{}", scope, package, version);
-  let rekor_log_id =
-    provenance::verify(name, expected_repo, &tarball_hash, body.bundle)?;
+  let verified =
+    provenance::verify(name, expected_repo, &tarball_hash, &body.bundle)?;
 
-  db.insert_provenance_statement(&scope, &package, &version, &rekor_log_id)
-    .await?;
+  let bundle = serde_json::to_value(&body.bundle)?;
+  db.insert_provenance_statement(
+    &scope,
+    &package,
+    &version,
+    &verified.log_index.to_string(),
+    &bundle,
+  )
+  .await?;
 
   if let Some(algolia_client) = algolia_client {
     algolia_client.upsert_package(&db_package, &meta);
   }
 
+  // The provenance covers the tarball that was published to JSR, not the npm
+  // tarball generated from it, so the registry vouches for the latter with a
+  // publish attestation of its own. This is best effort: the provenance has
+  // already been recorded at this point.
+  let npm_signer = req.data::<NpmSigner>().unwrap();
+  if let Some(signing_key) = &npm_signer.0 {
+    let buckets = req.data::<Buckets>().unwrap();
+    let npm_url = &req.data::<NpmUrl>().unwrap().0;
+    let cache_purge = req.data::<CachePurge>().unwrap();
+    if let Err(err) = attest_npm_tarball(
+      db,
+      &buckets.npm_bucket,
+      npm_url,
+      signing_key,
+      &scope,
+      &package,
+      &version,
+    )
+    .await
+    {
+      error!("failed to attest npm tarball: {err:#}");
+    } else {
+      let npm_version_manifest_path =
+        crate::s3_paths::npm_version_manifest_path(&scope, &package);
+      let npm_version_manifest = generate_npm_version_manifest(
+        db, npm_url, npm_signer, &scope, &package,
+      )
+      .await?;
+      let content = serde_json::to_vec_pretty(&npm_version_manifest)?;
+      buckets
+        .npm_bucket
+        .upload(
+          npm_version_manifest_path.into(),
+          crate::s3::UploadTaskBody::Bytes(content.into()),
+          S3UploadOptions {
+            content_type: Some("application/json".into()),
+            cache_control: Some(CACHE_CONTROL_MANIFEST.into()),
+            content_encoding: ContentEncoding::Identity,
+          },
+        )
+        .await?;
+      cache_purge.purge(vec![crate::s3_paths::npm_version_manifest_url(
+        npm_url, &scope, &package,
+      )]);
+    }
+  }
+
   Ok(
     Response::builder()
       .status(StatusCode::NO_CONTENT)
@@ -1111,6 +2244,95 @@ pub async fn version_provenance_statements_handler(
   )
 }
 
+async fn get_provenance_bundle(
+  db: &Database,
+  scope: &ScopeName,
+  package: &PackageName,
+  version: &Version,
+) -> Result<provenance::ProvenanceBundle, ApiError> {
+  db.get_package_version(scope, package, version)
+    .await?
+    .ok_or(ApiError::PackageVersionNotFound)?;
+  let bundle = db
+    .get_provenance_bundle(scope, package, version)
+    .await?
+    .ok_or(ApiError::ProvenanceNotFound)?;
+  Ok(serde_json::from_value(bundle)?)
+}
+
+#[instrument(
+  name = "GET /api/scopes/:scope/packages/:package/versions/:version/provenance",
+  skip(req),
+  fields(scope, package, version)
+)]
+pub async fn get_provenance_handler(
+  req: Request<Body>,
+) -> ApiResult<ApiProvenance> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  let version = req.param_version()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+  Span::current().record("version", field::display(&version));
+
+  let db = req.data::<Database>().unwrap();
+  let bundle = get_provenance_bundle(db, &scope, &package, &version).await?;
+
+  Ok(bundle.into())
+}
+
+/// Verifies the stored provenance bundle of a version again. The repository
+/// linked to the package was checked when the bundle was submitted and may
+/// have changed since, so it is not checked here; the repository the bundle
+/// was built in is returned instead.
+#[instrument(
+  name = "GET /api/scopes/:scope/packages/:package/versions/:version/provenance/verify",
+  skip(req),
+  fields(scope, package, version)
+)]
+pub async fn verify_provenance_handler(
+  req: Request<Body>,
+) -> ApiResult<ApiProvenanceVerification> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  let version = req.param_version()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+  Span::current().record("version", field::display(&version));
+
+  let db = req.data::<Database>().unwrap();
+  let bundle = get_provenance_bundle(db, &scope, &package, &version).await?;
+  let log_index = bundle.tlog_entry().log_index;
+
+  let tarball_hash = db
+    .get_publishing_task_tarball_hash_for_version(&scope, &package, &version)
+    .await?;
+  let name = format!("pkg:jsr/@{}/{}@{}", scope, package, version);
+  let result = match tarball_hash {
+    Some(tarball_hash) => {
+      provenance::verify(name, None, &tarball_hash, &bundle)
+    }
+    None => Err(anyhow::anyhow!(
+      "no tarball hash is recorded for the version"
+    )),
+  };
+
+  Ok(match result {
+    Ok(verified) => ApiProvenanceVerification {
+      verified: true,
+      log_index,
+      repository: Some(verified.repository),
+      error: None,
+    },
+    Err(err) => ApiProvenanceVerification {
+      verified: false,
+      log_index,
+      repository: None,
+      error: Some(format!("{err:#}")),
+    },
+  })
+}
+
 #[instrument(
   name = "PATCH /api/scopes/:scope/packages/:package/versions/:version",
   skip(req),
@@ -1126,17 +2348,47 @@ pub async fn version_update_handler(
   Span::current().record("package", field::display(&package));
   Span::current().record("version", field::display(&version));
 
-  // WARNING: if an additional option gets added, then yanked time rendering needs to be changed in package/versions.tsx
   let body: ApiUpdatePackageVersionRequest = decode_json(&mut req).await?;
+  let reason = body
+    .yank_reason
+    .as_deref()
+    .map(str::trim)
+    .filter(|reason| !reason.is_empty());
+  if let Some(reason) = reason {
+    if !body.yanked {
+      return Err(ApiError::MalformedRequest {
+        msg: "a yank reason can only be given when yanking a version".into(),
+      });
+    }
+    if reason.chars().count() > MAX_YANK_REASON_LENGTH {
+      return Err(ApiError::MalformedRequest {
+        msg: format!(
+          "yank reason must be at most {MAX_YANK_REASON_LENGTH} characters"
+        )
+        .into(),
+      });
+    }
+  }
 
   let db = req.data::<Database>().unwrap();
   let buckets = req.data::<Buckets>().unwrap().clone();
   let registry_url = &req.data::<RegistryUrl>().unwrap().0;
   let npm_url = &req.data::<NpmUrl>().unwrap().0;
+  let npm_signer = req.data::<NpmSigner>().unwrap();
   let cache_purge = req.data::<CachePurge>().unwrap();
 
   let iam = req.iam();
-  let (user, sudo) = iam.check_scope_admin_access(&scope).await?;
+  let (user, sudo) = iam
+    .check_package_permission(&scope, &package, PackagePermission::Yank)
+    .await?;
+
+  let (res_package, _, _) = db
+    .get_package(&scope, &package)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+  if res_package.is_archived {
+    return Err(ApiError::PackageArchived);
+  }
 
   db.yank_package_version(
     &user.id,
@@ -1145,6 +2397,7 @@ pub async fn version_update_handler(
     &package,
     &version,
     body.yanked,
+    reason,
   )
   .await?;
 
@@ -1161,7 +2414,7 @@ pub async fn version_update_handler(
       S3UploadOptions {
         content_type: Some("application/json".into()),
         cache_control: Some(CACHE_CONTROL_MANIFEST.into()),
-        gzip_encoded: false,
+        content_encoding: ContentEncoding::Identity,
       },
     )
     .await?;
@@ -1169,7 +2422,8 @@ pub async fn version_update_handler(
   let npm_version_manifest_path =
     crate::s3_paths::npm_version_manifest_path(&scope, &package);
   let npm_version_manifest =
-    generate_npm_version_manifest(db, npm_url, &scope, &package).await?;
+    generate_npm_version_manifest(db, npm_url, npm_signer, &scope, &package)
+      .await?;
   let content = serde_json::to_vec_pretty(&npm_version_manifest)?;
   buckets
     .npm_bucket
@@ -1179,7 +2433,7 @@ pub async fn version_update_handler(
       S3UploadOptions {
         content_type: Some("application/json".into()),
         cache_control: Some(CACHE_CONTROL_MANIFEST.into()),
-        gzip_encoded: false,
+        content_encoding: ContentEncoding::Identity,
       },
     )
     .await?;
@@ -1193,7 +2447,26 @@ pub async fn version_update_handler(
     &scope,
     &package,
   ));
-  cache_purge.purge(purge_urls).await;
+  purge_urls.extend(crate::s3_paths::package_version_api_cache_urls(
+    registry_url,
+    &scope,
+    &package,
+    &version,
+  ));
+  cache_purge.purge(purge_urls);
+
+  if body.yanked {
+    events::emit(
+      db,
+      DomainEvent::VersionYanked {
+        scope: scope.clone(),
+        package: package.clone(),
+        version: version.clone(),
+        user_id: user.id,
+      },
+    )
+    .await;
+  }
 
   Ok(
     Response::builder()
@@ -1222,6 +2495,7 @@ pub async fn version_delete_handler(
   let buckets = req.data::<Buckets>().unwrap().clone();
   let registry_url = &req.data::<RegistryUrl>().unwrap().0;
   let npm_url = &req.data::<NpmUrl>().unwrap().0;
+  let npm_signer = req.data::<NpmSigner>().unwrap();
   let cache_purge = req.data::<CachePurge>().unwrap();
 
   let iam = req.iam();
@@ -1238,86 +2512,68 @@ pub async fn version_delete_handler(
     return Err(ApiError::DeleteVersionHasDependents);
   }
 
-  db.delete_package_version(&staff.id, &scope, &package, &version)
-    .await?;
-
-  let v1_path = crate::s3_paths::docs_v1_path(&scope, &package, &version);
-  let v2_path = crate::s3_paths::docs_v2_path(&scope, &package, &version);
-  buckets.docs_bucket.delete_file(v1_path.into()).await?;
-  buckets.docs_bucket.delete_file(v2_path.into()).await?;
-
-  let path = crate::s3_paths::version_metadata(&scope, &package, &version);
-  buckets.modules_bucket.delete_file(path.into()).await?;
-
-  let path =
-    crate::s3_paths::file_path_root_directory(&scope, &package, &version);
-  buckets.modules_bucket.delete_directory(path.into()).await?;
-
-  let package_metadata_path =
-    crate::s3_paths::package_metadata(&scope, &package);
-  let package_metadata = PackageMetadata::create(db, &scope, &package).await?;
-
-  let content = serde_json::to_vec(&package_metadata)?;
-  buckets
-    .modules_bucket
-    .upload(
-      package_metadata_path.into(),
-      UploadTaskBody::Bytes(content.into()),
-      S3UploadOptions {
-        content_type: Some("application/json".into()),
-        cache_control: Some(CACHE_CONTROL_MANIFEST.into()),
-        gzip_encoded: false,
-      },
-    )
-    .await?;
-
-  let npm_version_manifest_path =
-    crate::s3_paths::npm_version_manifest_path(&scope, &package);
-  let npm_version_manifest =
-    generate_npm_version_manifest(db, npm_url, &scope, &package).await?;
-  let content = serde_json::to_vec_pretty(&npm_version_manifest)?;
-  buckets
-    .npm_bucket
-    .upload(
-      npm_version_manifest_path.into(),
-      crate::s3::UploadTaskBody::Bytes(content.into()),
-      S3UploadOptions {
-        content_type: Some("application/json".into()),
-        cache_control: Some(CACHE_CONTROL_MANIFEST.into()),
-        gzip_encoded: false,
-      },
-    )
+  // The version becomes a tombstone, so that it can be restored. Its artifacts
+  // are moved out of the public buckets like those of a quarantined version,
+  // and its files are deleted once it is purged.
+  let deleted = db
+    .delete_package_version(&staff.id, &scope, &package, &version)
     .await?;
+  if !deleted {
+    return Err(ApiError::PackageVersionNotFound);
+  }
 
-  let mut purge_urls = vec![
-    crate::s3_paths::package_metadata_url(registry_url, &scope, &package),
-    crate::s3_paths::npm_version_manifest_url(npm_url, &scope, &package),
-  ];
-  purge_urls.extend(crate::s3_paths::package_api_cache_urls(
+  crate::publish::upload_package_manifest(
+    db,
+    &buckets,
     registry_url,
+    cache_purge,
     &scope,
     &package,
-  ));
-  cache_purge.purge(purge_urls).await;
-
-  Ok(
-    Response::builder()
-      .status(StatusCode::NO_CONTENT)
-      .body(Body::empty())
-      .unwrap(),
   )
-}
-
-#[instrument(
-  name = "POST /api/scopes/:scope/packages/:package/versions/:version/tarball",
-  skip(req),
-  fields(scope, package, version)
-)]
-pub async fn version_tarball_handler(
-  req: Request<Body>,
-) -> ApiResult<Response<Body>> {
-  let scope = req.param_scope()?;
-  let package = req.param_package()?;
+  .await?;
+  crate::npm::upload_npm_version_manifest(
+    db,
+    &buckets,
+    npm_url,
+    npm_signer,
+    cache_purge,
+    &scope,
+    &package,
+  )
+  .await?;
+  crate::quarantine::quarantine_artifacts(
+    db, &buckets, &scope, &package, &version,
+  )
+  .await?;
+  crate::quarantine::purge_version_caches(
+    db,
+    cache_purge,
+    registry_url,
+    npm_url,
+    &scope,
+    &package,
+    &version,
+  )
+  .await?;
+
+  Ok(
+    Response::builder()
+      .status(StatusCode::NO_CONTENT)
+      .body(Body::empty())
+      .unwrap(),
+  )
+}
+
+#[instrument(
+  name = "POST /api/scopes/:scope/packages/:package/versions/:version/tarball",
+  skip(req),
+  fields(scope, package, version)
+)]
+pub async fn version_tarball_handler(
+  req: Request<Body>,
+) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
   let version = req.param_version()?;
 
   Span::current().record("scope", field::display(&scope));
@@ -1327,6 +2583,14 @@ pub async fn version_tarball_handler(
   let db = req.data::<Database>().unwrap();
   let buckets = req.data::<Buckets>().unwrap().clone();
 
+  if db
+    .get_package_version_quarantine(&scope, &package, &version)
+    .await?
+    .is_some()
+  {
+    return Err(ApiError::PackageVersionQuarantined);
+  }
+
   let (task, _) = db
     .get_publishing_task_for_version(&scope, &package, &version)
     .await?;
@@ -1411,14 +2675,13 @@ pub async fn get_docs_handler(
     && version.readme_path.is_some();
 
   let readme_fut = if has_readme {
-    let s3_path = crate::s3_paths::file_path(
+    Either::Left(crate::module_files::download_file(
+      &buckets.modules_bucket,
       &scope,
       &package_name,
       &version.version,
       version.readme_path.as_ref().unwrap(),
-    )
-    .into();
-    Either::Left(buckets.modules_bucket.download(s3_path))
+    ))
   } else {
     Either::Right(futures::future::ready(Ok(None)))
   };
@@ -1496,6 +2759,7 @@ pub async fn get_docs_handler(
       toc: docs.toc,
       main: docs.main.into(),
       version: ApiPackageVersion::from(version),
+      package_archived: package.is_archived,
     }),
     GeneratedDocsOutput::Redirect(href) => {
       Ok(ApiPackageVersionDocs::Redirect { symbol: href })
@@ -1503,6 +2767,375 @@ pub async fn get_docs_handler(
   }
 }
 
+/// Serves the documentation of a package version as a static site in a
+/// gzipped tarball, for browsing it without access to JSR. The archive is
+/// generated on the first request for it, and stored for the next ones.
+#[instrument(
+  name = "GET /api/scopes/:scope/packages/:package/versions/:version/docs/archive.tar.gz",
+  skip(req),
+  fields(scope, package, version, generated)
+)]
+pub async fn get_docs_archive_handler(
+  req: Request<Body>,
+) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  let package_name = req.param_package()?;
+  let version = req.param_version()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package_name));
+  Span::current().record("version", field::display(&version));
+
+  let db = req.data::<Database>().unwrap();
+  let buckets = req.data::<Buckets>().unwrap();
+  let (package, repo, _) = db
+    .get_package(&scope, &package_name)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+  let version = db
+    .get_package_version(&scope, &package_name, &version)
+    .await?
+    .ok_or(ApiError::PackageVersionNotFound)?;
+
+  let archive_path =
+    crate::s3_paths::docs_archive_path(&scope, &package_name, &version.version);
+  let stored = buckets
+    .docs_bucket
+    .download(archive_path.clone().into())
+    .await?;
+  Span::current().record("generated", stored.is_none());
+
+  let root = format!("{scope}__{package_name}-{}-docs", version.version);
+  let archive = match stored {
+    Some(archive) => archive,
+    None => {
+      let doc_nodes = crate::docs::download_doc_nodes(
+        &scope,
+        &package_name,
+        &version.version,
+        buckets,
+      )
+      .await?
+      .ok_or_else(|| {
+        error!(
+          "docs not found for {}/{}/{}",
+          scope, package_name, version.version
+        );
+        ApiError::InternalServerError
+      })?;
+
+      let docs_info = crate::docs::get_docs_info(&version.exports, None);
+      let registry_url = &req.data::<RegistryUrl>().unwrap().0;
+
+      let permit = crate::docs::acquire_doc_render_permit().await;
+      let ctx = crate::docs::get_generate_ctx(
+        String::new(),
+        doc_nodes,
+        docs_info.main_entrypoint,
+        docs_info.rewrite_map,
+        scope.clone(),
+        package_name.clone(),
+        version.version.clone(),
+        false,
+        repo,
+        false,
+        package.runtime_compat,
+        registry_url.to_string(),
+        None,
+      );
+      let archive =
+        crate::docs::generate_docs_archive(ctx, registry_url, &root).map_err(
+          |e| {
+            error!("failed to generate docs archive: {}", e);
+            ApiError::InternalServerError
+          },
+        )?;
+      drop(permit);
+
+      let archive = bytes::Bytes::from(archive);
+      buckets
+        .docs_bucket
+        .upload(
+          archive_path.into(),
+          UploadTaskBody::Bytes(archive.clone()),
+          S3UploadOptions {
+            content_type: Some("application/gzip".into()),
+            cache_control: Some(crate::s3::CACHE_CONTROL_IMMUTABLE.into()),
+            content_encoding: ContentEncoding::Identity,
+          },
+        )
+        .await?;
+      archive
+    }
+  };
+
+  Ok(
+    Response::builder()
+      .status(StatusCode::OK)
+      .header(hyper::header::CONTENT_TYPE, "application/gzip")
+      .header(
+        hyper::header::CONTENT_DISPOSITION,
+        format!("attachment; filename=\"{root}.tar.gz\""),
+      )
+      .body(Body::from(archive))
+      .unwrap(),
+  )
+}
+
+/// Serves the documentation of a package version as a single markdown
+/// document. It is stored at publish time; for versions published before
+/// that, it is generated from the stored doc nodes on the first request.
+#[instrument(
+  name = "GET /api/scopes/:scope/packages/:package/versions/:version/docs/type_graph",
+  skip(req),
+  fields(scope, package, version, generated)
+)]
+pub async fn get_docs_type_graph_handler(
+  req: Request<Body>,
+) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  let package_name = req.param_package()?;
+  let version = req.param_version()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package_name));
+  Span::current().record("version", field::display(&version));
+
+  let db = req.data::<Database>().unwrap();
+  let buckets = req.data::<Buckets>().unwrap();
+  let version = db
+    .get_package_version(&scope, &package_name, &version)
+    .await?
+    .ok_or(ApiError::PackageVersionNotFound)?;
+
+  let type_graph_path = crate::s3_paths::docs_type_graph_path(
+    &scope,
+    &package_name,
+    &version.version,
+  );
+  let stored = buckets
+    .docs_bucket
+    .download(type_graph_path.clone().into())
+    .await?;
+  Span::current().record("generated", stored.is_none());
+
+  let type_graph = match stored {
+    Some(type_graph) => type_graph,
+    // Versions published before the graph was stored at publish time.
+    None => {
+      let doc_nodes = crate::docs::download_doc_nodes(
+        &scope,
+        &package_name,
+        &version.version,
+        buckets,
+      )
+      .await?
+      .ok_or_else(|| {
+        error!(
+          "docs not found for {}/{}/{}",
+          scope, package_name, version.version
+        );
+        ApiError::InternalServerError
+      })?;
+
+      let type_graph =
+        crate::type_graph::generate_type_graph(&version.exports, &doc_nodes);
+      let type_graph =
+        bytes::Bytes::from(serde_json::to_vec(&type_graph).unwrap());
+      buckets
+        .docs_bucket
+        .upload(
+          type_graph_path.into(),
+          UploadTaskBody::Bytes(type_graph.clone()),
+          S3UploadOptions {
+            content_type: Some("application/json".into()),
+            cache_control: Some(crate::s3::CACHE_CONTROL_IMMUTABLE.into()),
+            content_encoding: ContentEncoding::Identity,
+          },
+        )
+        .await?;
+      type_graph
+    }
+  };
+
+  Ok(
+    Response::builder()
+      .status(StatusCode::OK)
+      .header(hyper::header::CONTENT_TYPE, "application/json")
+      .body(Body::from(type_graph))
+      .unwrap(),
+  )
+}
+
+/// Serves the software bill of materials of a package version. It is stored
+/// at publish time; for versions published before that, it is generated from
+/// the stored files and dependencies on the first request.
+#[instrument(
+  name = "GET /api/scopes/:scope/packages/:package/versions/:version/sbom",
+  skip(req),
+  fields(scope, package, version, format, generated)
+)]
+pub async fn get_sbom_handler(req: Request<Body>) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  let package_name = req.param_package()?;
+  let version = req.param_version()?;
+  let format = req
+    .query("format")
+    .map(|format| format.parse::<SbomFormat>())
+    .transpose()?
+    .unwrap_or(SbomFormat::CycloneDx);
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package_name));
+  Span::current().record("version", field::display(&version));
+  Span::current().record("format", field::debug(&format));
+
+  let db = req.data::<Database>().unwrap();
+  let buckets = req.data::<Buckets>().unwrap();
+  let version = db
+    .get_package_version(&scope, &package_name, &version)
+    .await?
+    .ok_or(ApiError::PackageVersionNotFound)?;
+
+  let sbom_path =
+    crate::s3_paths::sbom_path(&scope, &package_name, &version.version, format);
+  let stored = buckets
+    .docs_bucket
+    .download(sbom_path.clone().into())
+    .await?;
+  Span::current().record("generated", stored.is_none());
+
+  let sbom = match stored {
+    Some(sbom) => sbom,
+    // Versions published before the SBOM was stored at publish time.
+    None => {
+      let registry_url = req.data::<RegistryUrl>().unwrap();
+      let (files, dependencies) = futures::try_join!(
+        db.list_package_files(&scope, &package_name, &version.version),
+        db.list_package_version_dependencies(
+          &scope,
+          &package_name,
+          &version.version
+        ),
+      )?;
+      let sbom = SbomInput::from_stored(
+        &registry_url.0,
+        &scope,
+        &package_name,
+        &version.version,
+        version.license.as_deref(),
+        version.created_at,
+        &files,
+        &dependencies,
+      )
+      .generate(format);
+      let sbom = bytes::Bytes::from(serde_json::to_vec(&sbom).unwrap());
+      buckets
+        .docs_bucket
+        .upload(
+          sbom_path.into(),
+          UploadTaskBody::Bytes(sbom.clone()),
+          S3UploadOptions {
+            content_type: Some(format.content_type().into()),
+            cache_control: Some(crate::s3::CACHE_CONTROL_IMMUTABLE.into()),
+            content_encoding: ContentEncoding::Identity,
+          },
+        )
+        .await?;
+      sbom
+    }
+  };
+
+  Ok(
+    Response::builder()
+      .status(StatusCode::OK)
+      .header(hyper::header::CONTENT_TYPE, format.content_type())
+      .body(Body::from(sbom))
+      .unwrap(),
+  )
+}
+
+#[instrument(
+  name = "GET /api/scopes/:scope/packages/:package/versions/:version/docs.md",
+  skip(req),
+  fields(scope, package, version, generated)
+)]
+pub async fn get_docs_markdown_handler(
+  req: Request<Body>,
+) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  let package_name = req.param_package()?;
+  let version = req.param_version()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package_name));
+  Span::current().record("version", field::display(&version));
+
+  let db = req.data::<Database>().unwrap();
+  let buckets = req.data::<Buckets>().unwrap();
+  let version = db
+    .get_package_version(&scope, &package_name, &version)
+    .await?
+    .ok_or(ApiError::PackageVersionNotFound)?;
+
+  let markdown_path = crate::s3_paths::docs_markdown_path(
+    &scope,
+    &package_name,
+    &version.version,
+  );
+  let stored = buckets
+    .docs_bucket
+    .download(markdown_path.clone().into())
+    .await?;
+  Span::current().record("generated", stored.is_none());
+
+  let markdown = match stored {
+    Some(markdown) => markdown,
+    None => {
+      let doc_nodes = crate::docs::download_doc_nodes(
+        &scope,
+        &package_name,
+        &version.version,
+        buckets,
+      )
+      .await?
+      .ok_or_else(|| {
+        error!(
+          "docs not found for {}/{}/{}",
+          scope, package_name, version.version
+        );
+        ApiError::InternalServerError
+      })?;
+
+      let markdown =
+        bytes::Bytes::from(crate::docs_markdown::generate_docs_markdown(
+          &scope,
+          &package_name,
+          &version.version,
+          &version.exports,
+          &doc_nodes,
+        ));
+      buckets
+        .docs_bucket
+        .upload(
+          markdown_path.into(),
+          UploadTaskBody::Bytes(markdown.clone()),
+          S3UploadOptions {
+            content_type: Some("text/markdown; charset=utf-8".into()),
+            cache_control: Some(crate::s3::CACHE_CONTROL_IMMUTABLE.into()),
+            content_encoding: ContentEncoding::Identity,
+          },
+        )
+        .await?;
+      markdown
+    }
+  };
+
+  Ok(
+    Response::builder()
+      .status(StatusCode::OK)
+      .header(hyper::header::CONTENT_TYPE, "text/markdown; charset=utf-8")
+      .body(Body::from(markdown))
+      .unwrap(),
+  )
+}
+
 #[instrument(
   name = "GET /api/scopes/:scope/packages/:package/versions/:version/docs/search",
   skip(req),
@@ -1510,13 +3143,17 @@ pub async fn get_docs_handler(
 )]
 pub async fn get_docs_search_handler(
   req: Request<Body>,
-) -> ApiResult<serde_json::Value> {
+) -> ApiResult<crate::docs::SearchIndex> {
   let scope = req.param_scope()?;
   let package_name = req.param_package()?;
   let version_or_latest = req.param_version_or_latest()?;
   Span::current().record("scope", field::display(&scope));
   Span::current().record("package", field::display(&package_name));
   Span::current().record("version", field::display(&version_or_latest));
+  let entrypoint = req.query("entrypoint").cloned();
+  if let Some(entrypoint) = &entrypoint {
+    Span::current().record("entrypoint", entrypoint.as_str());
+  }
 
   let db = req.data::<Database>().unwrap();
   let buckets = req.data::<Buckets>().unwrap();
@@ -1572,7 +3209,8 @@ pub async fn get_docs_search_handler(
   })?;
 
   let _permit = crate::docs::acquire_doc_render_permit().await;
-  let search_index = deno_doc::html::generate_search_index(&ctx);
+  let search_index =
+    crate::docs::generate_search_index(&ctx, entrypoint.as_deref());
 
   Ok(search_index)
 }
@@ -1669,6 +3307,86 @@ pub async fn get_docs_search_structured_handler(
   Ok(search)
 }
 
+/// Serves a file of a package from the modules bucket, like the lb does for
+/// public packages: the metadata of the package or of one of its versions, or
+/// a file of a version. The lb has the API serve the files of private packages
+/// instead, so that only those that may read the package get them. `path` is
+/// relative to the package, like `meta.json`, `1.2.3_meta.json` or
+/// `1.2.3/mod.ts`.
+#[instrument(
+  name = "GET /api/scopes/:scope/packages/:package/module_file",
+  skip(req),
+  fields(scope, package, path)
+)]
+pub async fn get_module_file_handler(
+  req: Request<Body>,
+) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  let path = req.query("path").cloned().unwrap_or_default();
+
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+  Span::current().record("path", field::display(&path));
+
+  let db = req.data::<Database>().unwrap();
+  let buckets = req.data::<Buckets>().unwrap();
+  db.get_package(&scope, &package)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+
+  let (file, content_type) = if path == "meta.json" {
+    let path = crate::s3_paths::package_metadata(&scope, &package);
+    let file = buckets.modules_bucket.download(path.into()).await?;
+    (file, Some("application/json".to_string()))
+  } else if let Some(version) = path.strip_suffix("_meta.json") {
+    let version = module_file_version(db, &scope, &package, version).await?;
+    let path = crate::s3_paths::version_metadata(&scope, &package, &version);
+    let file = buckets.modules_bucket.download(path.into()).await?;
+    (file, Some("application/json".to_string()))
+  } else if let Some((version, file_path)) = path.split_once('/') {
+    let version = module_file_version(db, &scope, &package, version).await?;
+    let file_path = PackagePath::try_from(format!("/{file_path}").as_str())
+      .map_err(|_| ApiError::PackagePathNotFound)?;
+    let file = crate::module_files::download_file(
+      &buckets.modules_bucket,
+      &scope,
+      &package,
+      &version,
+      &file_path,
+    )
+    .await?;
+    let content_type = file
+      .as_ref()
+      .and_then(|file| crate::tarball::file_content_type(&file_path, file));
+    (file, content_type)
+  } else {
+    (None, None)
+  };
+  let file = file.ok_or(ApiError::PackagePathNotFound)?;
+
+  let mut res = Response::builder().status(StatusCode::OK);
+  if let Some(content_type) = content_type {
+    res = res.header(hyper::header::CONTENT_TYPE, content_type);
+  }
+  Ok(res.body(Body::from(file)).unwrap())
+}
+
+/// Parses the version of a module file path, and checks that it exists.
+async fn module_file_version(
+  db: &Database,
+  scope: &ScopeName,
+  package: &PackageName,
+  version: &str,
+) -> ApiResult<Version> {
+  let version =
+    Version::new(version).map_err(|_| ApiError::PackageVersionNotFound)?;
+  db.get_package_version(scope, package, &version)
+    .await?
+    .ok_or(ApiError::PackageVersionNotFound)?;
+  Ok(version)
+}
+
 #[instrument(
   name = "GET /api/scopes/:scope/packages/:package/versions/:version/source",
   skip(req),
@@ -1704,6 +3422,13 @@ pub async fn get_source_handler(
     }
   };
   let version = maybe_version.ok_or(ApiError::PackageVersionNotFound)?;
+  if db
+    .get_package_version_quarantine(&scope, &package, &version.version)
+    .await?
+    .is_some()
+  {
+    return Err(ApiError::PackageVersionQuarantined);
+  }
 
   let file = if path == "meta.json" {
     let source_file_path = crate::s3_paths::package_metadata(&scope, &package);
@@ -1724,16 +3449,14 @@ pub async fn get_source_handler(
       ApiError::MalformedRequest { msg }
     })?;
 
-    let source_file_path = crate::s3_paths::file_path(
+    crate::module_files::download_file(
+      &buckets.modules_bucket,
       &scope,
       &package,
       &version.version,
       &package_path,
-    );
-    buckets
-      .modules_bucket
-      .download(source_file_path.into())
-      .await?
+    )
+    .await?
   } else {
     None
   };
@@ -1743,32 +3466,37 @@ pub async fn get_source_handler(
   let source = if let Some(file) = file {
     let size = file.len();
 
-    let highlighter = deno_doc::html::comrak::ComrakHighlightWrapperAdapter(
-      Some(Arc::new(crate::tree_sitter::ComrakAdapter {
-        show_line_numbers: true,
-      })),
-    );
-
-    let view = if let Ok(file) = String::from_utf8(file.to_vec()) {
-      let mut out = vec![];
-      highlighter.write_pre_tag(&mut out, Default::default())?;
-      highlighter.write_code_tag(&mut out, Default::default())?;
-      highlighter.write_highlighted(
-        &mut out,
-        path_buf
-          .extension()
-          .map(|ext| ext.to_string_lossy())
-          .as_deref()
-          .map(|ext| match ext {
-            "mts" | "cts" => "ts",
-            "mjs" | "cjs" => "js",
-            ext => ext,
-          }),
-        &file,
-      )?;
-      out.extend(b"</code></pre>");
-
-      Some(String::from_utf8(out).context("File is not valid utf8")?)
+    let view = if let Ok(source) = std::str::from_utf8(&file) {
+      let hash = format!("sha256-{:x}", sha2::Sha256::digest(&file));
+      let view_path = crate::s3_paths::source_view_path(&hash);
+      let stored_view = buckets
+        .docs_bucket
+        .download(view_path.as_str().into())
+        .await?;
+      match stored_view.map(|view| String::from_utf8(view.to_vec())) {
+        Some(Ok(view)) => Some(view),
+        // Files published before views were rendered at publish time, or
+        // too large to be rendered then.
+        _ => {
+          let view = crate::tree_sitter::render_source_view(
+            &path_buf.to_string_lossy(),
+            source,
+          )?;
+          buckets
+            .docs_bucket
+            .upload(
+              view_path.into(),
+              UploadTaskBody::Bytes(bytes::Bytes::from(view.clone())),
+              S3UploadOptions {
+                content_type: Some("text/html; charset=utf-8".into()),
+                cache_control: Some(crate::s3::CACHE_CONTROL_IMMUTABLE.into()),
+                content_encoding: ContentEncoding::Identity,
+              },
+            )
+            .await?;
+          Some(view)
+        }
+      }
     } else {
       None
     };
@@ -1851,22 +3579,6 @@ pub async fn get_diff_handler(
   Span::current().record("scope", field::display(&scope));
   Span::current().record("package", field::display(&package_name));
 
-  let old_version = util::param(&req, "old_version")?;
-  let old_version = Version::try_from(old_version.as_str()).map_err(|err| {
-    let msg =
-      format!("failed to parse path parameter 'old_version': {err}").into();
-    ApiError::MalformedRequest { msg }
-  })?;
-  Span::current().record("old_version", field::display(&old_version));
-
-  let new_version = util::param(&req, "new_version")?;
-  let new_version = Version::try_from(new_version.as_str()).map_err(|err| {
-    let msg =
-      format!("failed to parse path parameter 'new_version': {err}").into();
-    ApiError::MalformedRequest { msg }
-  })?;
-  Span::current().record("new_version", field::display(&new_version));
-
   let DocsQueries {
     all_symbols,
     entrypoint,
@@ -1886,51 +3598,17 @@ pub async fn get_diff_handler(
   Span::current().record("full", field::display(full));
 
   let db = req.data::<Database>().unwrap();
-  let buckets = req.data::<Buckets>().unwrap();
   let (package, repo, _) = db
     .get_package(&scope, &package_name)
     .await?
     .ok_or(ApiError::PackageNotFound)?;
 
-  let old_version = db
-    .get_package_version(&scope, &package_name, &old_version)
-    .await?
-    .ok_or(ApiError::PackageVersionNotFound)?;
-  let new_version = db
-    .get_package_version(&scope, &package_name, &new_version)
-    .await?
-    .ok_or(ApiError::PackageVersionNotFound)?;
-
-  let (old_doc_nodes, new_doc_nodes) = futures::future::try_join(
-    crate::docs::download_doc_nodes(
-      &scope,
-      &package_name,
-      &old_version.version,
-      buckets,
-    ),
-    crate::docs::download_doc_nodes(
-      &scope,
-      &package_name,
-      &new_version.version,
-      buckets,
-    ),
-  )
-  .await?;
-
-  let old_doc_nodes = old_doc_nodes.ok_or_else(|| {
-    error!(
-      "docs not found for {}/{}/{}",
-      scope, package_name, old_version.version
-    );
-    ApiError::InternalServerError
-  })?;
-  let new_doc_nodes = new_doc_nodes.ok_or_else(|| {
-    error!(
-      "docs not found for {}/{}/{}",
-      scope, package_name, new_version.version
-    );
-    ApiError::InternalServerError
-  })?;
+  let DiffVersions {
+    old_version,
+    new_version,
+    old_doc_nodes,
+    new_doc_nodes,
+  } = load_diff_versions(&req, &scope, &package_name).await?;
 
   // diffs are applied on top of the new version
   let new_docs_info =
@@ -1993,6 +3671,7 @@ pub async fn get_diff_handler(
       toc: docs.toc,
       main: docs.main.into(),
       version: ApiPackageVersion::from(new_version),
+      package_archived: package.is_archived,
     }),
     GeneratedDocsOutput::Redirect(href) => {
       Ok(ApiPackageVersionDocs::Redirect { symbol: href })
@@ -2000,10 +3679,129 @@ pub async fn get_diff_handler(
   }
 }
 
+/// The JSON counterpart of [get_diff_handler]: the modules and symbols that
+/// were added, removed or changed between two versions, with the changes to
+/// the signature of each changed symbol. Nothing is rendered, so this is
+/// available even while the diff view is disabled.
+#[instrument(
+  name = "GET /api/scopes/:scope/packages/:package/diff/:old_version/:new_version/changes",
+  skip(req),
+  fields(scope, package, old_version, new_version)
+)]
+pub async fn get_diff_changes_handler(
+  req: Request<Body>,
+) -> ApiResult<ApiPackageVersionDiff> {
+  let scope = req.param_scope()?;
+  let package_name = req.param_package()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package_name));
+
+  let db = req.data::<Database>().unwrap();
+  db.get_package(&scope, &package_name)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+
+  let DiffVersions {
+    old_version,
+    new_version,
+    old_doc_nodes,
+    new_doc_nodes,
+  } = load_diff_versions(&req, &scope, &package_name).await?;
+
+  let diff = deno_doc::diff::DocDiff::diff(&old_doc_nodes, &new_doc_nodes);
+
+  Ok(ApiPackageVersionDiff {
+    old_version: ApiPackageVersion::from(old_version),
+    new_version: ApiPackageVersion::from(new_version),
+    diff,
+  })
+}
+
+struct DiffVersions {
+  old_version: crate::db::PackageVersion,
+  new_version: crate::db::PackageVersion,
+  old_doc_nodes: deno_doc::ParseOutput,
+  new_doc_nodes: deno_doc::ParseOutput,
+}
+
+/// Loads the versions in the `old_version` and `new_version` path parameters
+/// of a diff request, and their doc nodes.
+async fn load_diff_versions(
+  req: &Request<Body>,
+  scope: &ScopeName,
+  package_name: &PackageName,
+) -> ApiResult<DiffVersions> {
+  let old_version = util::param(req, "old_version")?;
+  let old_version = Version::try_from(old_version.as_str()).map_err(|err| {
+    let msg =
+      format!("failed to parse path parameter 'old_version': {err}").into();
+    ApiError::MalformedRequest { msg }
+  })?;
+  Span::current().record("old_version", field::display(&old_version));
+
+  let new_version = util::param(req, "new_version")?;
+  let new_version = Version::try_from(new_version.as_str()).map_err(|err| {
+    let msg =
+      format!("failed to parse path parameter 'new_version': {err}").into();
+    ApiError::MalformedRequest { msg }
+  })?;
+  Span::current().record("new_version", field::display(&new_version));
+
+  let db = req.data::<Database>().unwrap();
+  let buckets = req.data::<Buckets>().unwrap();
+
+  let old_version = db
+    .get_package_version(scope, package_name, &old_version)
+    .await?
+    .ok_or(ApiError::PackageVersionNotFound)?;
+  let new_version = db
+    .get_package_version(scope, package_name, &new_version)
+    .await?
+    .ok_or(ApiError::PackageVersionNotFound)?;
+
+  let (old_doc_nodes, new_doc_nodes) = futures::future::try_join(
+    crate::docs::download_doc_nodes(
+      scope,
+      package_name,
+      &old_version.version,
+      buckets,
+    ),
+    crate::docs::download_doc_nodes(
+      scope,
+      package_name,
+      &new_version.version,
+      buckets,
+    ),
+  )
+  .await?;
+
+  let old_doc_nodes = old_doc_nodes.ok_or_else(|| {
+    error!(
+      "docs not found for {}/{}/{}",
+      scope, package_name, old_version.version
+    );
+    ApiError::InternalServerError
+  })?;
+  let new_doc_nodes = new_doc_nodes.ok_or_else(|| {
+    error!(
+      "docs not found for {}/{}/{}",
+      scope, package_name, new_version.version
+    );
+    ApiError::InternalServerError
+  })?;
+
+  Ok(DiffVersions {
+    old_version,
+    new_version,
+    old_doc_nodes,
+    new_doc_nodes,
+  })
+}
+
 #[instrument(
   name = "GET /api/scopes/:scope/packages/:package/dependents",
   skip(req),
-  fields(scope, package)
+  fields(scope, package, version, sort)
 )]
 pub async fn list_dependents_handler(
   req: Request<Body>,
@@ -2019,6 +3817,32 @@ pub async fn list_dependents_handler(
     .and_then(|page| page.parse::<i64>().ok())
     .unwrap_or(10)
     .clamp(1, 10);
+  let sort = match req.query("sort").map(|sort| sort.as_str()) {
+    None | Some("name") => ApiDependentsSort::Name,
+    Some("score") => ApiDependentsSort::Score,
+    Some("downloads") => ApiDependentsSort::Downloads,
+    Some(_) => {
+      return Err(ApiError::MalformedRequest {
+        msg: "invalid 'sort' query parameter, expected one of: name, score, \
+           downloads"
+          .into(),
+      });
+    }
+  };
+  Span::current().record("sort", field::debug(&sort));
+  let maybe_version_req = req
+    .query("version")
+    .map(|version| {
+      Span::current().record("version", field::display(version));
+      VersionReq::parse_from_specifier(version).map_err(|_| {
+        ApiError::MalformedRequest {
+          msg: "invalid 'version' query parameter, expected a version or a \
+                version range"
+            .into(),
+        }
+      })
+    })
+    .transpose()?;
 
   let db = req.data::<Database>().unwrap();
   db.get_package(&scope, &package)
@@ -2027,16 +3851,69 @@ pub async fn list_dependents_handler(
 
   let dep_name = format!("@{}/{}", scope, package);
 
-  let (total, deps) = db
+  // Dependents of a version range are those whose constraint is satisfied by
+  // any published version in that range.
+  let maybe_constraints = if let Some(version_req) = maybe_version_req {
+    let versions = db
+      .list_package_versions_for_resolution(&scope, &package)
+      .await?
+      .into_iter()
+      .map(|version| version.version)
+      .filter(|version| version_req.matches(&version.0))
+      .collect::<Vec<_>>();
+    let constraints = db
+      .list_package_dependent_constraints(
+        crate::db::DependencyKind::Jsr,
+        &dep_name,
+      )
+      .await?
+      .into_iter()
+      .filter(|constraint| {
+        VersionReq::parse_from_specifier(constraint).is_ok_and(|req| {
+          versions.iter().any(|version| req.matches(&version.0))
+        })
+      })
+      .collect::<Vec<_>>();
+    Some(constraints)
+  } else {
+    None
+  };
+
+  // Scores are computed from the meta of the latest version, so sorting by
+  // them has to happen here rather than in the database.
+  let (db_start, db_limit) = match sort {
+    ApiDependentsSort::Score => (0, None),
+    ApiDependentsSort::Name | ApiDependentsSort::Downloads => {
+      (start, Some(limit))
+    }
+  };
+  let (total, mut deps) = db
     .list_package_dependents(
       crate::db::DependencyKind::Jsr,
       &dep_name,
-      start,
-      limit,
+      maybe_constraints.as_deref(),
+      sort == ApiDependentsSort::Downloads,
+      db_start,
+      db_limit,
       versions_per_package_limit,
     )
     .await?;
-  let dependents = deps.into_iter().map(ApiDependent::from).collect::<Vec<_>>();
+  if sort == ApiDependentsSort::Score {
+    deps.sort_by_cached_key(|(_, package, meta, _)| {
+      std::cmp::Reverse(
+        ApiPackageScore::from((meta, package)).score_percentage(),
+      )
+    });
+    deps = deps
+      .into_iter()
+      .skip(start as usize)
+      .take(limit as usize)
+      .collect();
+  }
+  let dependents = deps
+    .into_iter()
+    .map(|(dependent, _, _, _)| ApiDependent::from(dependent))
+    .collect::<Vec<_>>();
 
   Ok(ApiList {
     items: dependents,
@@ -2047,7 +3924,7 @@ pub async fn list_dependents_handler(
 #[instrument(
   name = "GET /api/scopes/:scope/packages/:package/downloads",
   skip(req),
-  fields(scope, package)
+  fields(scope, package, period)
 )]
 pub async fn get_downloads_handler(
   req: Request<Body>,
@@ -2057,13 +3934,30 @@ pub async fn get_downloads_handler(
   Span::current().record("scope", field::display(&scope));
   Span::current().record("package", field::display(&package));
 
+  let period = match req.query("period").map(|period| period.as_str()) {
+    None | Some("daily") => ApiDownloadPeriod::Daily,
+    Some("weekly") => ApiDownloadPeriod::Weekly,
+    Some(_) => {
+      return Err(ApiError::MalformedRequest {
+        msg: "invalid 'period' query parameter, expected one of: daily, weekly"
+          .into(),
+      });
+    }
+  };
+  Span::current().record("period", field::debug(&period));
+
   let db = req.data::<Database>().unwrap();
   db.get_package(&scope, &package)
     .await?
     .ok_or(ApiError::PackageNotFound)?;
 
   let current = Utc::now();
-  let start = current - chrono::Duration::days(90);
+  let start = match period {
+    ApiDownloadPeriod::Daily => current - chrono::Duration::days(90),
+    ApiDownloadPeriod::Weekly => {
+      start_of_week(current - chrono::Duration::weeks(51))
+    }
+  };
 
   let total_fut = async {
     db.get_package_downloads_24h(&scope, &package, start, current)
@@ -2102,21 +3996,110 @@ pub async fn get_downloads_handler(
         .into_iter()
         .map(|(version, data_points)| ApiPackageDownloadsRecentVersion {
           version,
-          downloads: data_points,
+          downloads: downloads_in_period(data_points, period),
+        })
+        .collect(),
+    )
+  };
+
+  let entrypoints_fut = async {
+    let data_points = db
+      .get_package_entrypoint_downloads_24h(&scope, &package, start, current)
+      .await?;
+
+    let mut data_points_by_entrypoint = IndexMap::<_, Vec<_>>::new();
+    for data_point in data_points {
+      data_points_by_entrypoint
+        .entry(data_point.entrypoint)
+        .or_default()
+        .push(ApiEntrypointDownloadDataPoint {
+          time_bucket: data_point.time_bucket,
+          count: data_point.count.max(0) as u64,
+        });
+    }
+    // The main entrypoint first, then the others by name.
+    data_points_by_entrypoint
+      .sort_by(|a, _, b, _| (a != ".").cmp(&(b != ".")).then(a.cmp(b)));
+
+    Ok::<_, ApiError>(
+      data_points_by_entrypoint
+        .into_iter()
+        .map(|(entrypoint, data_points)| {
+          let downloads = match period {
+            ApiDownloadPeriod::Daily => data_points,
+            ApiDownloadPeriod::Weekly => {
+              let mut weeks = IndexMap::<_, u64>::new();
+              for data_point in data_points {
+                *weeks
+                  .entry(start_of_week(data_point.time_bucket))
+                  .or_default() += data_point.count;
+              }
+              weeks
+                .into_iter()
+                .map(|(time_bucket, count)| ApiEntrypointDownloadDataPoint {
+                  time_bucket,
+                  count,
+                })
+                .collect()
+            }
+          };
+          ApiPackageDownloadsEntrypoint {
+            entrypoint,
+            downloads,
+          }
         })
         .collect(),
     )
   };
 
-  let (total, recent_versions) =
-    futures::try_join!(total_fut, recent_versions_fut)?;
+  let (total, recent_versions, entrypoints) =
+    futures::try_join!(total_fut, recent_versions_fut, entrypoints_fut)?;
 
   Ok(ApiPackageDownloads {
-    total: total.into_iter().map(ApiDownloadDataPoint::from).collect(),
+    total: downloads_in_period(
+      total.into_iter().map(ApiDownloadDataPoint::from).collect(),
+      period,
+    ),
     recent_versions,
+    entrypoints,
   })
 }
 
+/// Midnight UTC on the Monday of the week `time` falls in.
+fn start_of_week(time: DateTime<Utc>) -> DateTime<Utc> {
+  let date = time.date_naive();
+  let monday =
+    date - chrono::Duration::days(date.weekday().num_days_from_monday().into());
+  monday.and_hms_opt(0, 0, 0).unwrap().and_utc()
+}
+
+/// Sums daily data points into the buckets of `period`, keeping the download
+/// kinds apart.
+fn downloads_in_period(
+  data_points: Vec<ApiDownloadDataPoint>,
+  period: ApiDownloadPeriod,
+) -> Vec<ApiDownloadDataPoint> {
+  match period {
+    ApiDownloadPeriod::Daily => data_points,
+    ApiDownloadPeriod::Weekly => {
+      let mut weeks = IndexMap::<_, u64>::new();
+      for data_point in data_points {
+        *weeks
+          .entry((start_of_week(data_point.time_bucket), data_point.kind))
+          .or_default() += data_point.count;
+      }
+      weeks
+        .into_iter()
+        .map(|((time_bucket, kind), count)| ApiDownloadDataPoint {
+          time_bucket,
+          kind,
+          count,
+        })
+        .collect()
+    }
+  }
+}
+
 #[instrument(
   name = "GET /api/scopes/:scope/packages/:package/versions/:version/dependencies",
   skip(req),
@@ -2141,49 +4124,202 @@ pub async fn list_dependencies_handler(
   let deps = db
     .list_package_version_dependencies(&scope, &package, &version)
     .await?;
-  let deps = deps
+  let mut deps = deps
     .into_iter()
     .map(ApiDependency::from)
     .collect::<Vec<_>>();
+  mark_archived_dependencies(db, &mut deps).await?;
 
   Ok(deps)
 }
 
-struct DepTreeLoader {
-  scope: ScopeName,
-  package: PackageName,
-  version: crate::ids::Version,
-  bucket: crate::s3::BucketWithQueue,
-  exports: Arc<tokio::sync::Mutex<IndexMap<String, IndexMap<String, String>>>>,
+/// Flags the jsr dependencies that point at an archived package, so that
+/// dependents can be warned about them.
+async fn mark_archived_dependencies(
+  db: &Database,
+  deps: &mut [ApiDependency],
+) -> Result<(), ApiError> {
+  let names = deps
+    .iter()
+    .filter(|dep| dep.kind == ApiDependencyKind::Jsr)
+    .map(|dep| dep.name.clone())
+    .collect::<Vec<_>>();
+  if names.is_empty() {
+    return Ok(());
+  }
+  let archived = db.list_archived_package_names(&names).await?;
+  for dep in deps {
+    dep.archived =
+      dep.kind == ApiDependencyKind::Jsr && archived.contains(&dep.name);
+  }
+  Ok(())
 }
 
-impl DepTreeLoader {
-  fn load_inner(
-    &self,
-    specifier: &ModuleSpecifier,
-  ) -> deno_graph::source::LoadFuture {
-    use futures::FutureExt;
-    let specifier = specifier.clone();
-
-    match specifier.scheme() {
-      "file" => {
-        let Ok(path) = PackagePath::new(specifier.path().to_string()) else {
-          return async move { Ok(None) }.boxed();
-        };
+#[instrument(
+  name = "GET /api/scopes/:scope/packages/:package/versions/:version/deprecations",
+  skip(req),
+  fields(scope, package, version)
+)]
+pub async fn list_deprecations_handler(
+  req: Request<Body>,
+) -> ApiResult<Vec<ApiDeprecation>> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  let version = req.param_version()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+  Span::current().record("version", field::display(&version));
 
-        let scope = self.scope.clone();
-        let package = self.package.clone();
-        let version = self.version.clone();
-        let bucket = self.bucket.clone();
+  let db = req.data::<Database>().unwrap();
 
-        async move {
-          let Some(bytes) = bucket
-            .download(
-              crate::s3_paths::file_path(&scope, &package, &version, &path)
-                .into(),
-            )
-            .await
-            .map_err(|e| LoadError::Other(Arc::new(JsErrorBox::from_err(e))))?
+  db.get_package_version(&scope, &package, &version)
+    .await?
+    .ok_or(ApiError::PackageVersionNotFound)?;
+
+  let deprecations = db
+    .list_package_version_deprecations(&scope, &package, &version)
+    .await?;
+
+  Ok(deprecations.into_iter().map(ApiDeprecation::from).collect())
+}
+
+/// The SHA-256 digests recorded for the files, npm tarballs and docs of a
+/// version, so that mirrors can verify their copies.
+#[instrument(
+  name = "GET /api/scopes/:scope/packages/:package/versions/:version/integrity",
+  skip(req),
+  fields(scope, package, version)
+)]
+pub async fn get_integrity_handler(
+  req: Request<Body>,
+) -> ApiResult<ApiVersionIntegrity> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  let version = req.param_version()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+  Span::current().record("version", field::display(&version));
+
+  let db = req.data::<Database>().unwrap();
+
+  db.get_package_version(&scope, &package, &version)
+    .await?
+    .ok_or(ApiError::PackageVersionNotFound)?;
+
+  let (files, npm_tarballs, docs) = futures::try_join!(
+    db.list_package_files(&scope, &package, &version),
+    db.list_npm_tarballs_for_version(&scope, &package, &version),
+    db.list_package_version_doc_artifacts(&scope, &package, &version),
+  )?;
+
+  Ok(ApiVersionIntegrity {
+    files: files.into_iter().map(ApiFileDigest::from).collect(),
+    npm_tarballs: npm_tarballs
+      .into_iter()
+      .map(ApiNpmTarballDigest::from)
+      .collect(),
+    docs: docs.into_iter().map(ApiDocArtifactDigest::from).collect(),
+  })
+}
+
+#[instrument(
+  name = "GET /api/scopes/:scope/packages/:package/versions/:version/symbols/:symbol",
+  skip(req),
+  fields(scope, package, version, symbol)
+)]
+pub async fn get_doc_link_handler(req: Request<Body>) -> ApiResult<ApiDocLink> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  let version = req.param_version_or_latest()?;
+  let symbol = req.param("symbol").unwrap();
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+  Span::current().record("version", field::display(&version));
+  Span::current().record("symbol", field::display(&symbol));
+
+  let db = req.data::<Database>().unwrap();
+
+  let version = match version {
+    VersionOrLatest::Version(version) => db
+      .get_package_version(&scope, &package, &version)
+      .await?
+      .ok_or(ApiError::PackageVersionNotFound)?,
+    VersionOrLatest::Latest => db
+      .get_latest_unyanked_version_for_package(&scope, &package)
+      .await?
+      .ok_or(ApiError::PackageVersionNotFound)?,
+  };
+
+  let link = db
+    .get_package_version_doc_link(&scope, &package, &version.version, symbol)
+    .await?
+    .ok_or(ApiError::EntrypointOrSymbolNotFound)?;
+
+  Ok(ApiDocLink::from(link))
+}
+
+struct DepTreeLoader {
+  scope: ScopeName,
+  package: PackageName,
+  version: crate::ids::Version,
+  bucket: crate::s3::BucketWithQueue,
+  exports: Arc<tokio::sync::Mutex<IndexMap<String, IndexMap<String, String>>>>,
+  version_files: Arc<tokio::sync::Mutex<HashMap<String, VersionFiles>>>,
+}
+
+/// Returns the files of a package version, and remembers them so that its
+/// manifest is only loaded once per graph.
+async fn load_version_files(
+  cache: &tokio::sync::Mutex<HashMap<String, VersionFiles>>,
+  bucket: &crate::s3::BucketWithQueue,
+  scope: &ScopeName,
+  package: &PackageName,
+  version: &crate::ids::Version,
+) -> Result<VersionFiles, LoadError> {
+  let key = format!("@{scope}/{package}@{version}");
+  let mut cache = cache.lock().await;
+  if let Some(files) = cache.get(&key) {
+    return Ok(files.clone());
+  }
+  let files = VersionFiles::load(bucket, scope, package, version)
+    .await
+    .map_err(|e| LoadError::Other(Arc::new(JsErrorBox::from_err(e))))?;
+  cache.insert(key, files.clone());
+  Ok(files)
+}
+
+impl DepTreeLoader {
+  fn load_inner(
+    &self,
+    specifier: &ModuleSpecifier,
+  ) -> deno_graph::source::LoadFuture {
+    use futures::FutureExt;
+    let specifier = specifier.clone();
+
+    match specifier.scheme() {
+      "file" => {
+        let Ok(path) = PackagePath::new(specifier.path().to_string()) else {
+          return async move { Ok(None) }.boxed();
+        };
+
+        let scope = self.scope.clone();
+        let package = self.package.clone();
+        let version = self.version.clone();
+        let bucket = self.bucket.clone();
+        let version_files = self.version_files.clone();
+
+        async move {
+          let Some(bytes) = load_version_files(
+            &version_files,
+            &bucket,
+            &scope,
+            &package,
+            &version,
+          )
+          .await?
+          .download(&path)
+          .await
+          .map_err(|e| LoadError::Other(Arc::new(JsErrorBox::from_err(e))))?
           else {
             return Ok(None);
           };
@@ -2200,6 +4336,7 @@ impl DepTreeLoader {
       "http" | "https" => {
         let bucket = self.bucket.clone();
         let exports = self.exports.clone();
+        let version_files = self.version_files.clone();
 
         async move {
           let jsr_matches = JSR_DEP_PATH_RE.captures(specifier.path()).unwrap();
@@ -2209,27 +4346,45 @@ impl DepTreeLoader {
           let version = jsr_matches.name("version");
           let path = jsr_matches.name("path").unwrap();
 
-          let full_path: Arc<str> = format!(
-            "@{}/{}/{}{}",
-            scope.as_str(),
-            package.as_str(),
-            version
-              .as_ref()
-              .map(|version| version.as_str())
-              .unwrap_or_default(),
-            if path.as_str().starts_with('/') && version.is_none() {
-              &path.as_str()[1..]
-            } else {
-              path.as_str()
+          // Files of versions are found through their manifest, the package
+          // and version metadata next to them are stored under their path.
+          let bytes = match &version {
+            Some(version) => {
+              let (Ok(scope), Ok(package), Ok(version), Ok(path)) = (
+                ScopeName::try_from(scope.as_str()),
+                PackageName::try_from(package.as_str()),
+                crate::ids::Version::try_from(version.as_str()),
+                PackagePath::try_from(path.as_str()),
+              ) else {
+                return Ok(None);
+              };
+              load_version_files(
+                &version_files,
+                &bucket,
+                &scope,
+                &package,
+                &version,
+              )
+              .await?
+              .download(&path)
+              .await
+              .map_err(|e| {
+                LoadError::Other(Arc::new(JsErrorBox::from_err(e)))
+              })?
             }
-          )
-          .into();
-
-          let Some(bytes) = bucket
-            .download(full_path.clone())
-            .await
-            .map_err(|e| LoadError::Other(Arc::new(JsErrorBox::from_err(e))))?
-          else {
+            None => {
+              let full_path = format!(
+                "@{}/{}/{}",
+                scope.as_str(),
+                package.as_str(),
+                path.as_str().strip_prefix('/').unwrap_or(path.as_str())
+              );
+              bucket.download(full_path.into()).await.map_err(|e| {
+                LoadError::Other(Arc::new(JsErrorBox::from_err(e)))
+              })?
+            }
+          };
+          let Some(bytes) = bytes else {
             return Ok(None);
           };
 
@@ -2362,10 +4517,7 @@ async fn analyze_deps_tree(
   version: crate::ids::Version,
   bucket: crate::s3::BucketWithQueue,
   exports: IndexMap<String, String>,
-) -> Result<
-  IndexMap<DependencyKind, DependencyInfo>,
-  deno_graph::ModuleGraphError,
-> {
+) -> Result<DependencyGraph, deno_graph::ModuleGraphError> {
   let roots = exports
     .values()
     .map(|path| Url::parse(&format!("file://{}", path)).unwrap())
@@ -2386,6 +4538,7 @@ async fn analyze_deps_tree(
     version,
     bucket,
     exports: Default::default(),
+    version_files: Default::default(),
   };
   graph
     .build(
@@ -2400,7 +4553,9 @@ async fn analyze_deps_tree(
         jsr_url_provider: &DepTreeJsrUrlProvider(registry_url),
         jsr_version_resolver: Default::default(),
         passthrough_jsr_specifiers: false,
-        resolver: Some(&JsrResolver { member }),
+        resolver: Some(&JsrResolver {
+          members: vec![member],
+        }),
         npm_resolver: None,
         reporter: None,
         executor: Default::default(),
@@ -2419,6 +4574,7 @@ async fn analyze_deps_tree(
 
   let mut index = 0;
   let mut dependencies = Default::default();
+  let mut imports = Default::default();
 
   let exports_by_identifier = Arc::into_inner(loader.exports)
     .unwrap()
@@ -2441,17 +4597,24 @@ async fn analyze_deps_tree(
     })
     .collect();
 
+  let mut root_ids = IndexSet::new();
   for root in roots {
-    GraphDependencyCollector::collect(
+    root_ids.insert(GraphDependencyCollector::collect(
       &graph,
       &root,
       &exports_by_identifier,
       &mut index,
       &mut dependencies,
-    );
+      &mut imports,
+    ));
   }
 
-  Ok(dependencies)
+  Ok(DependencyGraph {
+    roots: root_ids,
+    dependencies,
+    exports,
+    imports,
+  })
 }
 
 struct GraphDependencyCollector<'a> {
@@ -2459,6 +4622,7 @@ struct GraphDependencyCollector<'a> {
   dependencies: &'a mut IndexMap<DependencyKind, DependencyInfo>,
   exports: &'a IndexMap<String, IndexMap<String, String>>,
   id_index: &'a mut usize,
+  imports: &'a mut IndexMap<String, ModuleSpecifier>,
   visited: IndexSet<DependencyKind>,
 }
 
@@ -2469,7 +4633,8 @@ impl<'a> GraphDependencyCollector<'a> {
     exports: &'a IndexMap<String, IndexMap<String, String>>,
     id_index: &'a mut usize,
     dependencies: &'a mut IndexMap<DependencyKind, DependencyInfo>,
-  ) {
+    imports: &'a mut IndexMap<String, ModuleSpecifier>,
+  ) -> usize {
     let root_module = graph.try_get(root).unwrap().unwrap();
 
     Self {
@@ -2477,10 +4642,11 @@ impl<'a> GraphDependencyCollector<'a> {
       dependencies,
       exports,
       id_index,
+      imports,
       visited: Default::default(),
     }
     .build_module_info(root_module)
-    .unwrap();
+    .unwrap()
   }
 
   fn build_module_info(&mut self, module: &Module) -> Option<usize> {
@@ -2570,7 +4736,13 @@ impl<'a> GraphDependencyCollector<'a> {
           {
             children.insert(child);
           }
-          for dep in module.dependencies.values() {
+          for (specifier, dep) in &module.dependencies {
+            if specifier.starts_with("jsr:")
+              && let Resolution::Ok(resolved) = &dep.maybe_code
+            {
+              let resolved = self.graph.resolve(&resolved.specifier);
+              self.imports.insert(specifier.clone(), resolved.clone());
+            }
             if !dep.maybe_code.is_none()
               && let Some(child) = self.build_resolved_info(&dep.maybe_code)
             {
@@ -2665,7 +4837,7 @@ pub enum DependencyKind {
   },
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct DependencyInfo {
   pub id: usize,
   pub children: IndexSet<usize>,
@@ -2673,10 +4845,211 @@ pub struct DependencyInfo {
   pub media_type: Option<MediaType>,
 }
 
+/// The largest number of items a dependency tree that is not deduplicated is
+/// expanded to.
+const MAX_DEPENDENCY_TREE_ITEMS: usize = 10_000;
+
+#[derive(Debug)]
+pub struct DependencyGraph {
+  /// The ids of the modules of the exports of the package.
+  pub roots: IndexSet<usize>,
+  pub dependencies: IndexMap<DependencyKind, DependencyInfo>,
+  /// The exports of the package, mapping export names to paths.
+  pub exports: IndexMap<String, String>,
+  /// The `jsr:` specifiers imported anywhere in the graph, mapped to the
+  /// module they resolved to.
+  pub imports: IndexMap<String, ModuleSpecifier>,
+}
+
+impl DependencyGraph {
+  /// Lists the modules at most `max_depth` imports away from the exports of
+  /// the package. If `dedupe` is set every module is listed once, otherwise a
+  /// module imported from several places is listed under each of them so that
+  /// the items form a tree. Imports that lead back to an ancestor are listed
+  /// without children, as are the modules at `max_depth`.
+  fn to_api_items(
+    &self,
+    max_depth: Option<usize>,
+    dedupe: bool,
+  ) -> Result<Vec<ApiDependencyGraphItem>, ApiError> {
+    let by_id = self
+      .dependencies
+      .iter()
+      .map(|(kind, info)| (info.id, (kind, info)))
+      .collect::<HashMap<_, _>>();
+
+    if !dedupe {
+      let mut items = vec![];
+      let mut ancestors = vec![];
+      for root in &self.roots {
+        Self::expand_tree(
+          &by_id,
+          *root,
+          0,
+          max_depth,
+          &mut ancestors,
+          &mut items,
+        )?;
+      }
+      return Ok(items);
+    }
+
+    let mut depths = HashMap::new();
+    let mut queue = std::collections::VecDeque::new();
+    for root in &self.roots {
+      if depths.insert(*root, 0).is_none() {
+        queue.push_back(*root);
+      }
+    }
+    while let Some(id) = queue.pop_front() {
+      let depth = depths[&id];
+      if max_depth.is_some_and(|max_depth| depth >= max_depth) {
+        continue;
+      }
+      for child in &by_id[&id].1.children {
+        if !depths.contains_key(child) {
+          depths.insert(*child, depth + 1);
+          queue.push_back(*child);
+        }
+      }
+    }
+
+    Ok(
+      self
+        .dependencies
+        .iter()
+        .filter_map(|(kind, info)| {
+          let depth = *depths.get(&info.id)?;
+          let mut item =
+            ApiDependencyGraphItem::from((kind.clone(), info.clone()));
+          if max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            item.children.clear();
+          }
+          Some(item)
+        })
+        .collect(),
+    )
+  }
+
+  /// Builds an import map for the package version: its exports are mapped
+  /// under the bare package name, and every `jsr:` specifier in the graph is
+  /// mapped to the version it resolved to. `npm:` specifiers are left out.
+  fn to_import_map(
+    &self,
+    registry_url: &Url,
+    scope: &ScopeName,
+    package: &PackageName,
+    version: &crate::ids::Version,
+    format: ImportMapFormat,
+  ) -> IndexMap<String, String> {
+    let mut imports = IndexMap::new();
+    for (name, path) in &self.exports {
+      let subpath = name.strip_prefix('.').unwrap_or(name);
+      let target = match format {
+        ImportMapFormat::Browser => registry_url
+          .join(&format!(
+            "@{scope}/{package}/{version}/{}",
+            path.trim_start_matches("./")
+          ))
+          .unwrap()
+          .to_string(),
+        ImportMapFormat::Deno => {
+          format!("jsr:@{scope}/{package}@{version}{subpath}")
+        }
+      };
+      imports.insert(format!("@{scope}/{package}{subpath}"), target);
+    }
+    for (specifier, resolved) in &self.imports {
+      let target = match format {
+        ImportMapFormat::Browser => Some(resolved.to_string()),
+        ImportMapFormat::Deno => JSR_DEP_PATH_RE
+          .captures(resolved.path())
+          .and_then(|captures| captures.name("version"))
+          .and_then(|version| pin_jsr_specifier(specifier, version.as_str())),
+      };
+      if let Some(target) = target {
+        imports.insert(specifier.clone(), target);
+      }
+    }
+    imports
+  }
+
+  fn expand_tree(
+    by_id: &HashMap<usize, (&DependencyKind, &DependencyInfo)>,
+    id: usize,
+    depth: usize,
+    max_depth: Option<usize>,
+    ancestors: &mut Vec<usize>,
+    items: &mut Vec<ApiDependencyGraphItem>,
+  ) -> Result<usize, ApiError> {
+    if items.len() >= MAX_DEPENDENCY_TREE_ITEMS {
+      return Err(ApiError::DependencyTreeTooLarge {
+        max_items: MAX_DEPENDENCY_TREE_ITEMS,
+      });
+    }
+
+    let (kind, info) = by_id[&id];
+    let item_id = items.len();
+    items.push(ApiDependencyGraphItem {
+      id: item_id,
+      dependency: kind.clone(),
+      children: IndexSet::new(),
+      size: info.size,
+      media_type: info.media_type.map(|media_type| media_type.to_string()),
+    });
+
+    let is_cycle = ancestors.contains(&id);
+    let is_too_deep = max_depth.is_some_and(|max_depth| depth >= max_depth);
+    if !is_cycle && !is_too_deep {
+      ancestors.push(id);
+      for child in &info.children {
+        let child_id = Self::expand_tree(
+          by_id,
+          *child,
+          depth + 1,
+          max_depth,
+          ancestors,
+          items,
+        )?;
+        items[item_id].children.insert(child_id);
+      }
+      ancestors.pop();
+    }
+
+    Ok(item_id)
+  }
+}
+
+/// In-memory cache of the dependency graphs of package versions. Resolving a
+/// graph downloads every module of the version and of its dependencies, and
+/// the resolved versions of dependencies only change when they publish, so a
+/// 1-hour TTL keeps repeated requests with different options cheap.
+#[derive(Clone)]
+pub struct DependencyGraphCache {
+  cache: moka::future::Cache<String, Arc<DependencyGraph>>,
+}
+
+impl DependencyGraphCache {
+  pub fn new() -> Self {
+    Self {
+      cache: moka::future::Cache::builder()
+        .max_capacity(1024)
+        .time_to_live(std::time::Duration::from_secs(3600))
+        .build(),
+    }
+  }
+}
+
+impl Default for DependencyGraphCache {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
 #[instrument(
   name = "GET /api/scopes/:scope/packages/:package/versions/:version/dependencies/graph",
   skip(req),
-  fields(scope, package, version)
+  fields(scope, package, version, max_depth, dedupe)
 )]
 pub async fn get_dependencies_graph_handler(
   req: Request<Body>,
@@ -2688,38 +5061,134 @@ pub async fn get_dependencies_graph_handler(
   Span::current().record("package", field::display(&package));
   Span::current().record("version", field::display(&version));
 
-  let buckets = req.data::<Buckets>().unwrap().clone();
-  let s3_path =
-    crate::s3_paths::version_metadata(&scope, &package, &version).into();
-  let version_meta = buckets
-    .modules_bucket
-    .download(s3_path)
-    .await?
-    .ok_or(ApiError::PackageVersionNotFound)?;
-  let version_meta = serde_json::from_slice::<VersionMetadata>(&version_meta)?;
+  let max_depth = req
+    .query("max_depth")
+    .map(|max_depth| max_depth.parse::<usize>())
+    .transpose()
+    .map_err(|_| ApiError::MalformedRequest {
+      msg: "invalid 'max_depth' query parameter, expected a non-negative \
+            integer"
+        .into(),
+    })?;
+  let dedupe = req.query("dedupe").is_none_or(|dedupe| dedupe != "false");
+  Span::current().record("max_depth", field::debug(&max_depth));
+  Span::current().record("dedupe", dedupe);
 
-  let registry_url = req.data::<RegistryUrl>().unwrap().0.clone();
+  let graph = get_dependency_graph(&req, scope, package, version).await?;
+  graph.to_api_items(max_depth, dedupe)
+}
+
+/// Resolves the dependency graph of a package version, or returns it from the
+/// cache.
+async fn get_dependency_graph(
+  req: &Request<Body>,
+  scope: ScopeName,
+  package: PackageName,
+  version: crate::ids::Version,
+) -> ApiResult<Arc<DependencyGraph>> {
+  let cache = req.data::<DependencyGraphCache>().unwrap();
+  let key = format!("@{scope}/{package}@{version}");
+  let graph = match cache.cache.get(&key).await {
+    Some(graph) => graph,
+    None => {
+      let buckets = req.data::<Buckets>().unwrap().clone();
+      let s3_path =
+        crate::s3_paths::version_metadata(&scope, &package, &version).into();
+      let version_meta = buckets
+        .modules_bucket
+        .download(s3_path)
+        .await?
+        .ok_or(ApiError::PackageVersionNotFound)?;
+      let version_meta =
+        serde_json::from_slice::<VersionMetadata>(&version_meta)?;
+
+      let registry_url = req.data::<RegistryUrl>().unwrap().0.clone();
+
+      let graph = tokio::task::spawn_blocking(|| {
+        analyze_deps_tree(
+          registry_url,
+          scope,
+          package,
+          version,
+          buckets.modules_bucket,
+          version_meta.exports,
+        )
+      })
+      .await
+      .unwrap()
+      .unwrap();
+      let graph = Arc::new(graph);
+      cache.cache.insert(key, graph.clone()).await;
+      graph
+    }
+  };
+
+  Ok(graph)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ImportMapFormat {
+  /// An import map for browsers, mapping specifiers to module URLs.
+  Browser,
+  /// The `imports` block of a `deno.json`, mapping specifiers to `jsr:`
+  /// specifiers pinned to exact versions.
+  Deno,
+}
+
+#[instrument(
+  name = "GET /api/scopes/:scope/packages/:package/versions/:version/import_map",
+  skip(req),
+  fields(scope, package, version, format)
+)]
+pub async fn get_import_map_handler(
+  req: Request<Body>,
+) -> ApiResult<ApiImportMap> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  let version = req.param_version()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+  Span::current().record("version", field::display(&version));
 
-  let deps = tokio::task::spawn_blocking(|| {
-    analyze_deps_tree(
+  let format = match req.query("format").map(|format| format.as_str()) {
+    None | Some("browser") => ImportMapFormat::Browser,
+    Some("deno") => ImportMapFormat::Deno,
+    Some(_) => {
+      return Err(ApiError::MalformedRequest {
+        msg: "invalid 'format' query parameter, expected 'browser' or 'deno'"
+          .into(),
+      });
+    }
+  };
+  Span::current().record("format", field::debug(&format));
+
+  let graph =
+    get_dependency_graph(&req, scope.clone(), package.clone(), version.clone())
+      .await?;
+  let registry_url = &req.data::<RegistryUrl>().unwrap().0;
+
+  Ok(ApiImportMap {
+    imports: graph.to_import_map(
       registry_url,
-      scope,
-      package,
-      version,
-      buckets.modules_bucket,
-      version_meta.exports,
-    )
+      &scope,
+      &package,
+      &version,
+      format,
+    ),
   })
-  .await
-  .unwrap()
-  .unwrap();
-
-  let api_deps = deps
-    .into_iter()
-    .map(ApiDependencyGraphItem::from)
-    .collect::<Vec<_>>();
+}
 
-  Ok(api_deps)
+/// Replaces the version requirement of a `jsr:` specifier, like
+/// `jsr:@std/path@^1/join`, with the given version.
+fn pin_jsr_specifier(specifier: &str, version: &str) -> Option<String> {
+  let specifier = specifier.strip_prefix("jsr:")?.trim_start_matches('/');
+  let (scope, rest) = specifier.strip_prefix('@')?.split_once('/')?;
+  let (name, rest) = rest.split_at(rest.find(['@', '/']).unwrap_or(rest.len()));
+  let subpath = match rest.strip_prefix('@') {
+    Some(req) => req.find('/').map(|index| &req[index..]).unwrap_or(""),
+    None => rest,
+  };
+  Some(format!("jsr:@{scope}/{name}@{version}{subpath}"))
 }
 
 #[instrument(
@@ -2776,37 +5245,58 @@ pub async fn get_score_handler(
 
 #[cfg(test)]
 mod test {
+  use chrono::Utc;
   use hyper::Body;
   use hyper::StatusCode;
+  use indexmap::IndexMap;
   use indexmap::IndexSet;
   use serde_json::json;
 
   use crate::api::ApiDependencyGraphItem;
   use crate::api::ApiDependencyKind;
   use crate::api::ApiDependent;
+  use crate::api::ApiDeprecation;
+  use crate::api::ApiImportMap;
   use crate::api::ApiList;
   use crate::api::ApiMetrics;
   use crate::api::ApiPackage;
+  use crate::api::ApiPackageDownloads;
   use crate::api::ApiPackageScore;
+  use crate::api::ApiPackageSearchResult;
+  use crate::api::ApiPackageSnapshot;
   use crate::api::ApiPackageVersion;
   use crate::api::ApiPackageVersionDocs;
   use crate::api::ApiPackageVersionSource;
+  use crate::api::ApiPublishDryRun;
+  use crate::api::ApiScopeMember;
+  use crate::api::ApiScoreFactorKind;
   use crate::api::ApiSource;
   use crate::api::ApiSourceDirEntry;
   use crate::api::ApiSourceDirEntryKind;
+  use crate::api::ApiStats;
+  use crate::api::ApiSymbolSearchResult;
+  use crate::api::ApiVersionIntegrity;
   use crate::api::{ApiDependency, ApiReadmeSource};
   use crate::db::CreatePackageResult;
   use crate::db::CreatePublishingTaskResult;
+  use crate::db::DocArtifactKind;
+  use crate::db::DownloadKind;
   use crate::db::ExportsMap;
+  use crate::db::ModuleDownloadCount;
   use crate::db::NewGithubRepository;
   use crate::db::NewPackageVersion;
   use crate::db::NewPublishingTask;
   use crate::db::NewScopeInvite;
   use crate::db::PackagePublishPermission;
+  use crate::db::PackageVersionMeta;
+  use crate::db::PackageVersionScoreDetails;
   use crate::db::Permission;
   use crate::db::Permissions;
   use crate::db::PublishingTaskStatus;
+  use crate::db::ReadmeQuality;
+  use crate::db::RuntimeCompat;
   use crate::db::TokenType;
+  use crate::db::VersionDownloadCount;
   use crate::ids::{
     PackageName, PackagePath, ScopeDescription, ScopeName, Version,
   };
@@ -2937,39 +5427,243 @@ mod test {
   }
 
   #[tokio::test]
-  async fn test_packages_create() {
+  async fn test_packages_list_filters() {
     let mut t = TestSetup::new().await;
 
+    // @scope/foo 1.2.3, MIT licensed, without provenance.
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:?}");
+    let foo = PackageName::new("foo".to_owned()).unwrap();
+    let bar = PackageName::new("bar".to_owned()).unwrap();
+    t.ephemeral_database
+      .create_package(&t.scope.scope, &bar)
+      .await
+      .unwrap();
+
+    for (name, runtime_compat) in [
+      (
+        &foo,
+        RuntimeCompat {
+          deno: Some(true),
+          node: Some(true),
+          ..Default::default()
+        },
+      ),
+      (
+        &bar,
+        RuntimeCompat {
+          deno: Some(true),
+          node: Some(false),
+          ..Default::default()
+        },
+      ),
+    ] {
+      t.ephemeral_database
+        .update_package_runtime_compat(
+          &t.user1.user.id,
+          false,
+          &t.scope.scope,
+          name,
+          &runtime_compat,
+        )
+        .await
+        .unwrap();
+    }
+
     let mut resp = t
       .http()
-      .post("/api/scopes/scope/packages")
-      .body_json(json!({
-        "package": "foo"
-      }))
+      .get("/api/packages?runtime=deno")
       .call()
       .await
       .unwrap();
-    let package: ApiPackage = resp.expect_ok().await;
-    assert_eq!(package.name, PackageName::try_from("foo").unwrap());
+    let packages: ApiPackageSearchResult = resp.expect_ok().await;
+    assert_eq!(packages.total, 2);
+    assert_eq!(packages.facets.runtime_compat.deno, 2);
+    assert_eq!(packages.facets.runtime_compat.node, 1);
+    assert_eq!(packages.facets.runtime_compat.browser, 0);
+    assert_eq!(packages.facets.has_provenance, 0);
+    assert_eq!(packages.facets.licenses.len(), 1);
+    assert_eq!(packages.facets.licenses[0].license, "MIT");
+    assert_eq!(packages.facets.licenses[0].count, 1);
 
     let mut resp = t
       .http()
-      .post("/api/scopes/scope/packages")
-      .body_json(json!({
-        "package": "foo"
-      }))
+      .get("/api/packages?runtime=deno,node")
       .call()
       .await
       .unwrap();
-    resp
-      .expect_err_code(StatusCode::CONFLICT, "packageAlreadyExists")
-      .await;
+    let packages: ApiPackageSearchResult = resp.expect_ok().await;
+    assert_eq!(packages.total, 1);
+    assert_eq!(packages.items[0].name, foo);
 
     let mut resp = t
       .http()
-      .post("/api/scopes/scope/packages")
-      .body_json(json!({
-        "package": "f-oo"
+      .get("/api/packages?license=mit")
+      .call()
+      .await
+      .unwrap();
+    let packages: ApiPackageSearchResult = resp.expect_ok().await;
+    assert_eq!(packages.total, 1);
+    assert_eq!(packages.items[0].name, foo);
+
+    let mut resp = t
+      .http()
+      .get("/api/packages?hasProvenance=true")
+      .call()
+      .await
+      .unwrap();
+    let packages: ApiPackageSearchResult = resp.expect_ok().await;
+    assert_eq!(packages.total, 0);
+
+    // Packages without any versions have no score.
+    let mut resp = t
+      .http()
+      .get("/api/packages?minScore=0")
+      .call()
+      .await
+      .unwrap();
+    let packages: ApiPackageSearchResult = resp.expect_ok().await;
+    assert_eq!(packages.total, 1);
+    assert_eq!(packages.items[0].name, foo);
+    assert_eq!(packages.facets.runtime_compat.deno, 1);
+
+    let mut resp = t
+      .http()
+      .get("/api/packages?minScore=100")
+      .call()
+      .await
+      .unwrap();
+    let packages: ApiPackageSearchResult = resp.expect_ok().await;
+    assert_eq!(packages.total, 0);
+
+    for query in ["runtime=deno,ie", "minScore=101", "hasProvenance=maybe"] {
+      let mut resp = t
+        .http()
+        .get(format!("/api/packages?{query}"))
+        .call()
+        .await
+        .unwrap();
+      resp
+        .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+        .await;
+    }
+  }
+
+  #[tokio::test]
+  async fn test_packages_sort_by_downloads() {
+    let mut t = TestSetup::new().await;
+
+    let scope = t.scope.scope.clone();
+    let version = Version::try_from("1.0.0").unwrap();
+    for name in ["foo", "bar"] {
+      let name = PackageName::try_from(name).unwrap();
+      t.ephemeral_database
+        .create_package(&scope, &name)
+        .await
+        .unwrap();
+      t.ephemeral_database
+        .create_package_version_for_test(NewPackageVersion {
+          scope: &scope,
+          name: &name,
+          version: &version,
+          user_id: None,
+          readme_path: None,
+          uses_npm: false,
+          exports: &ExportsMap::mock(),
+          meta: Default::default(),
+          license: "MIT".to_string(),
+        })
+        .await
+        .unwrap();
+    }
+
+    // foo is downloaded more this week, bar more this month.
+    let download =
+      |package: &str, days_ago: i64, count: i64| VersionDownloadCount {
+        scope: scope.clone(),
+        package: package.try_into().unwrap(),
+        version: version.clone(),
+        time_bucket: Utc::now() - chrono::Duration::days(days_ago),
+        kind: DownloadKind::JsrMeta,
+        count,
+      };
+    t.db()
+      .insert_download_entries(vec![
+        download("foo", 1, 5),
+        download("bar", 1, 3),
+        download("bar", 20, 20),
+        download("bar", 60, 100),
+      ])
+      .await
+      .unwrap();
+    assert_eq!(t.db().refresh_package_download_rollups().await.unwrap(), 2);
+
+    for (sort, expected) in [
+      ("weekly_downloads", ["foo", "bar"]),
+      ("monthly_downloads", ["bar", "foo"]),
+    ] {
+      let packages = t
+        .http()
+        .get(format!("/api/packages?sort={sort}"))
+        .call()
+        .await
+        .unwrap()
+        .expect_ok::<ApiPackageSearchResult>()
+        .await;
+      let names = packages
+        .items
+        .iter()
+        .map(|package| package.name.to_string())
+        .collect::<Vec<_>>();
+      assert_eq!(names[..2], expected);
+    }
+
+    let stats = t
+      .http()
+      .get("/api/stats")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiStats>()
+      .await;
+    assert_eq!(stats.most_downloaded_this_week.len(), 2);
+    assert_eq!(stats.most_downloaded_this_week[0].name.to_string(), "foo");
+    assert_eq!(stats.most_downloaded_this_week[0].downloads, 5);
+    assert_eq!(stats.most_downloaded_this_month[0].name.to_string(), "bar");
+    assert_eq!(stats.most_downloaded_this_month[0].downloads, 23);
+
+    let mut resp = t
+      .http()
+      .get("/api/packages?sort=stars")
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn test_packages_create() {
+    let mut t = TestSetup::new().await;
+
+    let mut resp = t
+      .http()
+      .post("/api/scopes/scope/packages")
+      .body_json(json!({
+        "package": "foo"
+      }))
+      .call()
+      .await
+      .unwrap();
+    let package: ApiPackage = resp.expect_ok().await;
+    assert_eq!(package.name, PackageName::try_from("foo").unwrap());
+
+    let mut resp = t
+      .http()
+      .post("/api/scopes/scope/packages")
+      .body_json(json!({
+        "package": "foo"
       }))
       .call()
       .await
@@ -2978,6 +5672,32 @@ mod test {
       .expect_err_code(StatusCode::CONFLICT, "packageAlreadyExists")
       .await;
 
+    let mut resp = t
+      .http()
+      .post("/api/scopes/scope/packages")
+      .body_json(json!({
+        "package": "f-oo"
+      }))
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::CONFLICT, "packageNameConfusable")
+      .await;
+
+    let mut resp = t
+      .http()
+      .post("/api/scopes/scope/packages")
+      .body_json(json!({
+        "package": "f0o"
+      }))
+      .call()
+      .await
+      .unwrap();
+    let err = resp.expect_err(StatusCode::CONFLICT).await;
+    assert_eq!(err.code, "packageNameConfusable");
+    assert_eq!(err.data["existing"], "foo");
+
     let mut resp = t
       .http()
       .post("/api/scopes/scope2/packages")
@@ -3145,6 +5865,186 @@ mod test {
       .await;
   }
 
+  #[tokio::test]
+  async fn test_package_versions_prerelease() {
+    let mut t = TestSetup::new().await;
+
+    let scope = t.scope.scope.clone();
+    let name = PackageName::try_from("foo").unwrap();
+    let res = t
+      .ephemeral_database
+      .create_package(&scope, &name)
+      .await
+      .unwrap();
+    assert!(matches!(res, CreatePackageResult::Ok(_)));
+
+    for version in [
+      "1.0.0-beta.2",
+      "1.0.0+build.10",
+      "1.0.0",
+      "1.0.0-beta.11",
+      "1.0.0+build.9",
+      "1.1.0-rc.1",
+    ] {
+      t.ephemeral_database
+        .create_package_version_for_test(NewPackageVersion {
+          scope: &scope,
+          name: &name,
+          version: &version.try_into().unwrap(),
+          user_id: None,
+          readme_path: None,
+          uses_npm: false,
+          exports: &ExportsMap::mock(),
+          meta: Default::default(),
+          license: "MIT".to_string(),
+        })
+        .await
+        .unwrap();
+    }
+
+    let list = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiList<ApiPackageVersion>>()
+      .await;
+    let versions = list
+      .items
+      .iter()
+      .map(|v| v.version.to_string())
+      .collect::<Vec<_>>();
+    assert_eq!(
+      versions,
+      [
+        "1.1.0-rc.1",
+        "1.0.0+build.10",
+        "1.0.0+build.9",
+        "1.0.0",
+        "1.0.0-beta.11",
+        "1.0.0-beta.2",
+      ]
+    );
+    assert_eq!(list.total, 6);
+
+    let list = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions?prerelease=false&limit=2")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiList<ApiPackageVersion>>()
+      .await;
+    let versions = list
+      .items
+      .iter()
+      .map(|v| v.version.to_string())
+      .collect::<Vec<_>>();
+    assert_eq!(versions, ["1.0.0+build.10", "1.0.0+build.9"]);
+    assert_eq!(list.total, 3);
+
+    let version = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/latest")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPackageVersion>()
+      .await;
+    assert!(!version.version.is_prerelease());
+    let version = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/latest?prerelease=true")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPackageVersion>()
+      .await;
+    assert_eq!(version.version.to_string(), "1.1.0-rc.1");
+
+    t.http()
+      .get("/api/scopes/scope/packages/foo/versions?prerelease=maybe")
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn test_package_versions_etag() {
+    let mut t = TestSetup::new().await;
+
+    let scope = t.scope.scope.clone();
+    let name = PackageName::try_from("foo").unwrap();
+    let res = t
+      .ephemeral_database
+      .create_package(&scope, &name)
+      .await
+      .unwrap();
+    assert!(matches!(res, CreatePackageResult::Ok(_)));
+
+    let resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions")
+      .call()
+      .await
+      .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let etag = resp.headers().get("ETag").unwrap().clone();
+    // Authenticated responses are revalidated rather than cached.
+    assert_eq!(
+      resp.headers().get("Cache-Control").unwrap(),
+      "private, no-cache"
+    );
+
+    let resp = t
+      .unauthed_http()
+      .get("/api/scopes/scope/packages/foo/versions")
+      .header(hyper::header::IF_NONE_MATCH, etag.clone())
+      .call()
+      .await
+      .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(resp.headers().get("ETag").unwrap(), &etag);
+    assert!(
+      resp
+        .headers()
+        .get("Cache-Control")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("public")
+    );
+
+    t.ephemeral_database
+      .create_package_version_for_test(NewPackageVersion {
+        scope: &scope,
+        name: &name,
+        version: &"1.0.0".try_into().unwrap(),
+        user_id: None,
+        readme_path: None,
+        uses_npm: false,
+        exports: &ExportsMap::mock(),
+        meta: Default::default(),
+        license: "MIT".to_string(),
+      })
+      .await
+      .unwrap();
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions")
+      .header(hyper::header::IF_NONE_MATCH, etag.clone())
+      .call()
+      .await
+      .unwrap();
+    assert_ne!(resp.headers().get("ETag").unwrap(), &etag);
+    let list: ApiList<ApiPackageVersion> = resp.expect_ok().await;
+    assert_eq!(list.items.len(), 1);
+  }
+
   #[tokio::test]
   async fn test_package_version() {
     let mut t = TestSetup::new().await;
@@ -3197,14 +6097,10 @@ mod test {
   }
 
   #[tokio::test]
-  async fn test_package_provenance() {
-    use crate::provenance::*;
-    use base64::Engine;
-    use base64::prelude::BASE64_STANDARD;
-
+  async fn test_package_score_factors() {
     let mut t = TestSetup::new().await;
-    let scope = t.scope.scope.clone();
 
+    let scope = t.scope.scope.clone();
     let name = PackageName::try_from("foo").unwrap();
     let res = t
       .ephemeral_database
@@ -3213,40 +6109,167 @@ mod test {
       .unwrap();
     assert!(matches!(res, CreatePackageResult::Ok(_)));
 
+    let version = Version::new("1.0.0").unwrap();
     t.ephemeral_database
       .create_package_version_for_test(NewPackageVersion {
         scope: &scope,
         name: &name,
-        version: &"1.0.0".try_into().unwrap(),
+        version: &version,
         user_id: None,
         readme_path: None,
         uses_npm: false,
         exports: &ExportsMap::mock(),
-        meta: Default::default(),
+        meta: PackageVersionMeta {
+          has_readme: true,
+          has_readme_examples: false,
+          all_entrypoints_docs: false,
+          percentage_documented_symbols: 0.5,
+          all_fast_check: true,
+          has_provenance: false,
+          score_details: PackageVersionScoreDetails {
+            entrypoints_without_module_doc: vec!["/other.ts".to_string()],
+            entrypoints_with_slow_types: vec![],
+            documented_symbols: 5,
+            total_symbols: 10,
+            examples_checked: 2,
+            failing_examples: vec![
+              "example 1 in '/README.md': 'foo' is not exported from \
+               'jsr:@scope/foo'"
+                .to_string(),
+            ],
+            readme: Some(ReadmeQuality {
+              code_blocks: 2,
+              broken_links: vec!["./missing.md".to_string()],
+              ..Default::default()
+            }),
+            ..Default::default()
+          },
+          ..Default::default()
+        },
         license: "MIT".to_string(),
       })
       .await
       .unwrap();
 
-    // Record a tarball hash for 1.0.0 so the provenance endpoint can bind the
-    // attestation to it. Without a recorded hash the request is rejected before
-    // signature verification; 1.0.1 deliberately has none, which exercises that
-    // rejection below.
-    let tarball_digest =
-      "1c3b44ea2ac86f7133791a4a004f633993784da783a3e0f5c226dd7a4141f9f5";
-    let CreatePublishingTaskResult::Created((task, _)) = t
-      .ephemeral_database
-      .create_publishing_task(NewPublishingTask {
-        user_id: None,
-        package_scope: &scope,
-        package_name: &name,
-        package_version: &"1.0.0".try_into().unwrap(),
-        config_file: &"/jsr.json".try_into().unwrap(),
-      })
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/score")
+      .call()
       .await
-      .unwrap()
-    else {
-      unreachable!()
+      .unwrap();
+    let score: ApiPackageScore = resp.expect_ok().await;
+
+    assert_eq!(score.schema_version, 3);
+    assert_eq!(score.max_score, 19);
+    assert_eq!(
+      score.total,
+      score
+        .factors
+        .iter()
+        .map(|factor| factor.points)
+        .sum::<u32>()
+    );
+
+    let readme = score
+      .factors
+      .iter()
+      .find(|f| f.factor == ApiScoreFactorKind::HasReadme)
+      .unwrap();
+    assert_eq!(readme.points, readme.weight);
+    assert!(readme.hints.is_empty());
+
+    let entrypoints = score
+      .factors
+      .iter()
+      .find(|f| f.factor == ApiScoreFactorKind::AllEntrypointsDocs)
+      .unwrap();
+    assert_eq!(entrypoints.points, 0);
+    assert_eq!(entrypoints.hints.len(), 1);
+    assert!(entrypoints.hints[0].contains("/other.ts"));
+
+    let symbols = score
+      .factors
+      .iter()
+      .find(|f| f.factor == ApiScoreFactorKind::PercentageDocumentedSymbols)
+      .unwrap();
+    assert_eq!(symbols.points, 3);
+    assert!(symbols.hints[0].contains("at least 3 more"));
+
+    let examples = score
+      .factors
+      .iter()
+      .find(|f| f.factor == ApiScoreFactorKind::AllExamplesResolve)
+      .unwrap();
+    assert_eq!(examples.points, 0);
+    assert_eq!(examples.hints.len(), 1);
+    assert!(examples.hints[0].contains("'foo' is not exported"));
+
+    let tests = score
+      .factors
+      .iter()
+      .find(|f| f.factor == ApiScoreFactorKind::HasTests)
+      .unwrap();
+    assert_eq!(tests.points, 0);
+    assert_eq!(tests.hints.len(), 1);
+    assert_eq!(score.test_files, 0);
+    assert!(score.coverage.is_none());
+
+    let readme = score.readme.unwrap();
+    assert_eq!(readme.code_blocks, 2);
+    assert_eq!(readme.broken_links, vec!["./missing.md"]);
+  }
+
+  #[tokio::test]
+  async fn test_package_provenance() {
+    use crate::provenance::*;
+    use base64::Engine;
+    use base64::prelude::BASE64_STANDARD;
+
+    let mut t = TestSetup::new().await;
+    let scope = t.scope.scope.clone();
+
+    let name = PackageName::try_from("foo").unwrap();
+    let res = t
+      .ephemeral_database
+      .create_package(&scope, &name)
+      .await
+      .unwrap();
+    assert!(matches!(res, CreatePackageResult::Ok(_)));
+
+    t.ephemeral_database
+      .create_package_version_for_test(NewPackageVersion {
+        scope: &scope,
+        name: &name,
+        version: &"1.0.0".try_into().unwrap(),
+        user_id: None,
+        readme_path: None,
+        uses_npm: false,
+        exports: &ExportsMap::mock(),
+        meta: Default::default(),
+        license: "MIT".to_string(),
+      })
+      .await
+      .unwrap();
+
+    // Record a tarball hash for 1.0.0 so the provenance endpoint can bind the
+    // attestation to it. Without a recorded hash the request is rejected before
+    // signature verification; 1.0.1 deliberately has none, which exercises that
+    // rejection below.
+    let tarball_digest =
+      "1c3b44ea2ac86f7133791a4a004f633993784da783a3e0f5c226dd7a4141f9f5";
+    let CreatePublishingTaskResult::Created((task, _)) = t
+      .ephemeral_database
+      .create_publishing_task(NewPublishingTask {
+        user_id: None,
+        package_scope: &scope,
+        package_name: &name,
+        package_version: &"1.0.0".try_into().unwrap(),
+        config_file: &"/jsr.json".try_into().unwrap(),
+      })
+      .await
+      .unwrap()
+    else {
+      unreachable!()
     };
     t.ephemeral_database
       .set_publishing_task_tarball_hash(
@@ -3325,7 +6348,15 @@ ggHohNAjhbzDaY2iBW/m3NC5dehGUP4T2GBo/cwGhg==
         },
         tlog_entries: [TlogEntry {
           log_index: 73446963,
+          log_id: None,
+          kind_version: None,
+          // Within the validity window of the certificate above.
+          integrated_time: Some(serde_json::json!("1708690600")),
+          inclusion_promise: None,
+          inclusion_proof: None,
+          canonicalized_body: None,
         }],
+        timestamp_verification_data: None,
       },
     };
 
@@ -3364,6 +6395,52 @@ ggHohNAjhbzDaY2iBW/m3NC5dehGUP4T2GBo/cwGhg==
     let score: ApiPackageScore = resp.expect_ok().await;
     assert!(!score.has_provenance);
 
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/1.0.0/provenance")
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::NOT_FOUND, "provenanceNotFound")
+      .await;
+
+    // A stored bundle is served as submitted, and verifying it again reports
+    // why it fails.
+    t.ephemeral_database
+      .insert_provenance_statement(
+        &scope,
+        &name,
+        &"1.0.0".try_into().unwrap(),
+        "73446963",
+        &serde_json::to_value(&bundle).unwrap(),
+      )
+      .await
+      .unwrap();
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/1.0.0/provenance")
+      .call()
+      .await
+      .unwrap();
+    let provenance: serde_json::Value = resp.expect_ok().await;
+    assert_eq!(provenance["logIndex"], 73446963);
+    assert_eq!(provenance["integratedTime"], "2024-02-23T12:16:40Z");
+    assert_eq!(provenance["bundle"], serde_json::to_value(&bundle).unwrap());
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/1.0.0/provenance/verify")
+      .call()
+      .await
+      .unwrap();
+    let verification: serde_json::Value = resp.expect_ok().await;
+    assert_eq!(verification["verified"], false);
+    assert_eq!(verification["logIndex"], 73446963);
+    assert_eq!(
+      verification["error"],
+      "provenance DSSE signature verification failed"
+    );
+
     // Invalid subject.
     update_bundle_subject(
       &mut bundle,
@@ -3569,6 +6646,96 @@ ggHohNAjhbzDaY2iBW/m3NC5dehGUP4T2GBo/cwGhg==
       .await;
   }
 
+  #[tokio::test]
+  async fn update_package_localized_description() {
+    let mut t = TestSetup::new().await;
+
+    let scope = t.scope.scope.clone();
+    let name = PackageName::try_from("foo").unwrap();
+    let res = t
+      .ephemeral_database
+      .create_package(&scope, &name)
+      .await
+      .unwrap();
+    assert!(matches!(res, CreatePackageResult::Ok(_)));
+
+    let mut resp = t
+      .http()
+      .patch("/api/scopes/scope/packages/foo")
+      .body_json(json!({ "description": "hello" }))
+      .call()
+      .await
+      .unwrap();
+    resp.expect_ok::<ApiPackage>().await;
+
+    let mut resp = t
+      .http()
+      .patch("/api/scopes/scope/packages/foo")
+      .body_json(json!({
+        "localizedDescription": { "locale": "de_DE", "description": " hallo\n" }
+      }))
+      .call()
+      .await
+      .unwrap();
+    let package: ApiPackage = resp.expect_ok().await;
+    assert_eq!(package.description, "hello");
+    assert_eq!(package.localized_descriptions["de-de"], "hallo");
+
+    let mut resp = t
+      .http()
+      .patch("/api/scopes/scope/packages/foo")
+      .body_json(json!({
+        "localizedDescription": { "locale": "not a locale", "description": "x" }
+      }))
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo")
+      .header(
+        hyper::header::ACCEPT_LANGUAGE,
+        "fr, de;q=0.8".try_into().unwrap(),
+      )
+      .call()
+      .await
+      .unwrap();
+    assert_eq!(
+      resp.headers().get(hyper::header::VARY).unwrap(),
+      "Accept-Language"
+    );
+    let package: ApiPackage = resp.expect_ok().await;
+    assert_eq!(package.description, "hallo");
+    assert_eq!(package.description_locale.as_deref(), Some("de-de"));
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo?locale=fr")
+      .header(hyper::header::ACCEPT_LANGUAGE, "de".try_into().unwrap())
+      .call()
+      .await
+      .unwrap();
+    let package: ApiPackage = resp.expect_ok().await;
+    assert_eq!(package.description, "hello");
+    assert_eq!(package.description_locale, None);
+
+    let mut resp = t
+      .http()
+      .patch("/api/scopes/scope/packages/foo")
+      .body_json(json!({
+        "localizedDescription": { "locale": "de-DE", "description": null }
+      }))
+      .call()
+      .await
+      .unwrap();
+    let package: ApiPackage = resp.expect_ok().await;
+    assert!(package.localized_descriptions.is_empty());
+  }
+
   #[tokio::test]
   async fn update_package_runtime_compat() {
     let mut t = TestSetup::new().await;
@@ -3800,6 +6967,7 @@ ggHohNAjhbzDaY2iBW/m3NC5dehGUP4T2GBo/cwGhg==
         Some(10),
         Some(100),
         Some(100),
+        None,
       )
       .await
       .unwrap();
@@ -3831,6 +6999,7 @@ ggHohNAjhbzDaY2iBW/m3NC5dehGUP4T2GBo/cwGhg==
         Some(100),
         Some(10),
         Some(100),
+        None,
       )
       .await
       .unwrap();
@@ -3862,6 +7031,7 @@ ggHohNAjhbzDaY2iBW/m3NC5dehGUP4T2GBo/cwGhg==
         Some(100),
         Some(100),
         Some(10),
+        None,
       )
       .await
       .unwrap();
@@ -3910,6 +7080,43 @@ ggHohNAjhbzDaY2iBW/m3NC5dehGUP4T2GBo/cwGhg==
       .await;
   }
 
+  #[tokio::test]
+  async fn test_publishing_storage_limit() {
+    let mut t = TestSetup::new().await;
+
+    t.ephemeral_database
+      .update_scope_limits(
+        &t.staff_user.user.id,
+        &t.scope.scope,
+        None,
+        None,
+        None,
+        Some(16),
+      )
+      .await
+      .unwrap();
+
+    let name = PackageName::new("foo".to_owned()).unwrap();
+    let CreatePackageResult::Ok(_) =
+      t.db().create_package(&t.scope.scope, &name).await.unwrap()
+    else {
+      unreachable!();
+    };
+
+    let data = create_mock_tarball("ok");
+    let mut resp = t
+      .http()
+      .post("/api/scopes/scope/packages/foo/versions/1.2.3?config=/jsr.json")
+      .gzip()
+      .body(Body::from(data))
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::BAD_REQUEST, "scopeStorageLimitExceeded")
+      .await;
+  }
+
   #[tokio::test]
   async fn test_publishing_with_missing_auth() {
     let mut t = TestSetup::new().await;
@@ -3955,6 +7162,83 @@ ggHohNAjhbzDaY2iBW/m3NC5dehGUP4T2GBo/cwGhg==
       .await;
   }
 
+  #[tokio::test]
+  async fn test_publish_dry_run() {
+    let mut t = TestSetup::new().await;
+
+    let scope = t.scope.scope.clone();
+    let name = PackageName::new("foo".to_owned()).unwrap();
+    let CreatePackageResult::Ok(_) =
+      t.db().create_package(&scope, &name).await.unwrap()
+    else {
+      unreachable!();
+    };
+
+    let mut resp = t
+      .http()
+      .post(
+        "/api/scopes/scope/packages/foo/versions/1.2.3/dry-run?config=/jsr.json",
+      )
+      .gzip()
+      .body(Body::from(create_mock_tarball("ok")))
+      .call()
+      .await
+      .unwrap();
+    let dry_run: ApiPublishDryRun = resp.expect_ok().await;
+    assert!(dry_run.error.is_none(), "{dry_run:?}");
+    let analysis = dry_run.analysis.unwrap();
+    assert_eq!(analysis.license, "MIT");
+    assert_eq!(analysis.exports.get("."), Some(&"./mod.ts".to_string()));
+    assert!(
+      analysis
+        .files
+        .iter()
+        .any(|file| file.path.to_string() == "/mod.ts")
+    );
+    assert_eq!(analysis.npm_package_json["name"], "@jsr/scope__foo");
+    assert_eq!(analysis.npm_package_json["version"], "1.2.3");
+    assert!(analysis.npm_tarball_size > 0);
+
+    // Nothing was published.
+    let version = Version::new("1.2.3").unwrap();
+    assert!(
+      t.db()
+        .get_package_version(&scope, &name, &version)
+        .await
+        .unwrap()
+        .is_none()
+    );
+
+    // Errors that would fail the publish are reported in the result.
+    let mut resp = t
+      .http()
+      .post(
+        "/api/scopes/scope/packages/foo/versions/1.2.3/dry-run?config=/jsr.json",
+      )
+      .gzip()
+      .body(Body::from(create_mock_tarball("no_license")))
+      .call()
+      .await
+      .unwrap();
+    let dry_run: ApiPublishDryRun = resp.expect_ok().await;
+    assert!(dry_run.analysis.is_none());
+    assert_eq!(dry_run.error.unwrap().code, "missingLicense");
+
+    let mut resp = t
+      .http()
+      .post(
+        "/api/scopes/scope/packages/bar/versions/1.2.3/dry-run?config=/jsr.json",
+      )
+      .gzip()
+      .body(Body::from(create_mock_tarball("ok")))
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::NOT_FOUND, "packageNotFound")
+      .await;
+  }
+
   #[tokio::test]
   async fn test_package_docs() {
     let mut t = TestSetup::new().await;
@@ -3989,6 +7273,7 @@ ggHohNAjhbzDaY2iBW/m3NC5dehGUP4T2GBo/cwGhg==
         breadcrumbs,
         toc: _,
         main: _,
+        package_archived: _,
       } => {
         assert_eq!(version.version, task.package_version);
         assert!(breadcrumbs.is_none(), "{:?}", breadcrumbs);
@@ -4012,6 +7297,7 @@ ggHohNAjhbzDaY2iBW/m3NC5dehGUP4T2GBo/cwGhg==
         breadcrumbs,
         toc: _,
         main: _,
+        package_archived: _,
       } => {
         assert_eq!(version.version, task.package_version);
         assert!(breadcrumbs.is_some());
@@ -4035,6 +7321,7 @@ ggHohNAjhbzDaY2iBW/m3NC5dehGUP4T2GBo/cwGhg==
         breadcrumbs,
         toc: _,
         main: _,
+        package_archived: _,
       } => {
         assert_eq!(version.version, task.package_version);
         assert!(breadcrumbs.is_some());
@@ -4061,6 +7348,7 @@ ggHohNAjhbzDaY2iBW/m3NC5dehGUP4T2GBo/cwGhg==
         breadcrumbs,
         toc: _,
         main: _,
+        package_archived: _,
       } => {
         assert_eq!(version.version, task.package_version);
         assert!(breadcrumbs.is_some());
@@ -4078,21 +7366,42 @@ ggHohNAjhbzDaY2iBW/m3NC5dehGUP4T2GBo/cwGhg==
     let search: serde_json::Value = resp.expect_ok().await;
     assert_eq!(
       search,
-      json!({"kind":"search","nodes":[{"id":"namespace_hello","kind":[{"kind":"Variable","char":"v","title":"Variable"}],"name":"hello","file":".","doc":"This is a test constant.","url":"/@scope/foo@1.2.3/doc/~/hello","deprecated":false},{"id":"namespace_读取多键1","kind":[{"kind":"Variable","char":"v","title":"Variable"}],"name":"读取多键1","file":".","doc":"","url":"/@scope/foo@1.2.3/doc/~/读取多键1","deprecated":false}]}),
+      json!({"kind":"search","nodes":[{"id":"namespace_hello","kind":[{"kind":"Variable","char":"v","title":"Variable"}],"name":"hello","file":".","doc":"This is a test constant.","url":"/@scope/foo@1.2.3/doc/~/hello","deprecated":false},{"id":"namespace_读取多键1","kind":[{"kind":"Variable","char":"v","title":"Variable"}],"name":"读取多键1","file":".","doc":"","url":"/@scope/foo@1.2.3/doc/~/读取多键1","deprecated":false}],"truncated":false}),
     );
 
-    // symbol doesn't exist
+    // search, restricted to the shard of one entrypoint
     let mut resp = t
       .http()
-      .get("/api/scopes/scope/packages/foo/versions/1.2.3/docs?symbol=asdf")
+      .get("/api/scopes/scope/packages/foo/versions/1.2.3/docs/search?entrypoint=.")
       .call()
       .await
       .unwrap();
-    resp
-      .expect_err_code(StatusCode::NOT_FOUND, "entrypointOrSymbolNotFound")
-      .await;
-
-    // entrypoint doesn't exist
+    let entrypoint_search: serde_json::Value = resp.expect_ok().await;
+    assert_eq!(entrypoint_search, search);
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/1.2.3/docs/search?entrypoint=asdf")
+      .call()
+      .await
+      .unwrap();
+    let entrypoint_search: serde_json::Value = resp.expect_ok().await;
+    assert_eq!(
+      entrypoint_search,
+      json!({"kind":"search","nodes":[],"truncated":false}),
+    );
+
+    // symbol doesn't exist
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/1.2.3/docs?symbol=asdf")
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::NOT_FOUND, "entrypointOrSymbolNotFound")
+      .await;
+
+    // entrypoint doesn't exist
     let mut resp = t
       .http()
       .get("/api/scopes/scope/packages/foo/versions/1.2.3/docs?entrypoint=asdf")
@@ -4162,6 +7471,311 @@ ggHohNAjhbzDaY2iBW/m3NC5dehGUP4T2GBo/cwGhg==
       .await;
   }
 
+  #[tokio::test]
+  async fn test_package_docs_archive() {
+    let mut t = TestSetup::new().await;
+
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{:?}", task);
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/1.2.3/docs/archive.tar.gz")
+      .call()
+      .await
+      .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+      resp.headers().get("Content-Type").unwrap(),
+      "application/gzip"
+    );
+    let archive = hyper::body::to_bytes(resp.body_mut()).await.unwrap();
+
+    let mut tarball =
+      tar::Archive::new(flate2::read::GzDecoder::new(&archive[..]));
+    let mut index = None;
+    let mut paths = vec![];
+    for entry in tarball.entries().unwrap() {
+      let mut entry = entry.unwrap();
+      let path = entry.path().unwrap().to_string_lossy().into_owned();
+      if path == "scope__foo-1.2.3-docs/index.html" {
+        let mut content = String::new();
+        std::io::Read::read_to_string(&mut entry, &mut content).unwrap();
+        index = Some(content);
+      }
+      paths.push(path);
+    }
+    assert!(
+      paths
+        .iter()
+        .any(|path| path.starts_with("scope__foo-1.2.3-docs/")
+          && path.ends_with("/~/hello.html")),
+      "{paths:?}"
+    );
+    // Links between pages are relative, so they work offline.
+    let index = index.unwrap();
+    assert!(index.contains("/~/hello.html\""), "{index}");
+    assert!(!index.contains("href=\"/@scope/foo"), "{index}");
+
+    // The archive is stored, and served from the bucket from now on.
+    let stored = t
+      .buckets
+      .docs_bucket
+      .download("@scope/foo/1.2.3/archive.tar.gz".into())
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(stored, archive);
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/1.0.0/docs/archive.tar.gz")
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::NOT_FOUND, "packageVersionNotFound")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn test_package_docs_type_graph() {
+    let mut t = TestSetup::new().await;
+
+    let task =
+      process_tarball_setup(&t, create_mock_tarball("deprecations")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{:?}", task);
+
+    // The type graph is stored at publish time.
+    let stored = t
+      .buckets
+      .docs_bucket
+      .download("@scope/foo/1.2.3/type_graph.json".into())
+      .await
+      .unwrap()
+      .unwrap();
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/1.2.3/docs/type_graph")
+      .call()
+      .await
+      .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+      resp.headers().get("Content-Type").unwrap(),
+      "application/json"
+    );
+    let body = hyper::body::to_bytes(resp.body_mut()).await.unwrap();
+    assert_eq!(body, stored);
+
+    let type_graph: crate::type_graph::TypeGraph =
+      serde_json::from_slice(&body).unwrap();
+    let symbols = type_graph
+      .nodes
+      .iter()
+      .map(|node| (node.export.as_str(), node.symbol.as_str()))
+      .collect::<Vec<_>>();
+    assert!(symbols.contains(&(".", "hello")), "{symbols:?}");
+    assert!(symbols.contains(&("./fs", "exists")), "{symbols:?}");
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/1.0.0/docs/type_graph")
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::NOT_FOUND, "packageVersionNotFound")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn test_package_sbom() {
+    let mut t = TestSetup::new().await;
+
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{:?}", task);
+
+    // The SBOMs are stored at publish time.
+    let stored = t
+      .buckets
+      .docs_bucket
+      .download("@scope/foo/1.2.3/sbom.cdx.json".into())
+      .await
+      .unwrap()
+      .unwrap();
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/1.2.3/sbom")
+      .call()
+      .await
+      .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+      resp.headers().get("Content-Type").unwrap(),
+      "application/vnd.cyclonedx+json"
+    );
+    let body = hyper::body::to_bytes(resp.body_mut()).await.unwrap();
+    assert_eq!(body, stored);
+    let cyclonedx: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+      cyclonedx["metadata"]["component"]["purl"],
+      "pkg:jsr/%40scope/foo@1.2.3"
+    );
+    assert_eq!(
+      cyclonedx["metadata"]["component"]["licenses"][0]["expression"],
+      "MIT"
+    );
+
+    // Versions published before SBOMs were stored get them generated.
+    t.buckets
+      .docs_bucket
+      .delete_file("@scope/foo/1.2.3/sbom.spdx.json".into())
+      .await
+      .unwrap();
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/1.2.3/sbom?format=spdx")
+      .call()
+      .await
+      .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+      resp.headers().get("Content-Type").unwrap(),
+      "application/spdx+json"
+    );
+    let spdx: serde_json::Value = resp.expect_ok().await;
+    assert_eq!(spdx["spdxVersion"], "SPDX-2.3");
+    assert_eq!(spdx["packages"][0]["name"], "@scope/foo");
+    assert!(
+      spdx["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|file| file["fileName"] == "./mod.ts"),
+      "{spdx}"
+    );
+    assert!(
+      t.buckets
+        .docs_bucket
+        .download("@scope/foo/1.2.3/sbom.spdx.json".into())
+        .await
+        .unwrap()
+        .is_some()
+    );
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/1.2.3/sbom?format=swid")
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn test_package_docs_markdown() {
+    let mut t = TestSetup::new().await;
+
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{:?}", task);
+
+    // The markdown document is stored at publish time.
+    let stored = t
+      .buckets
+      .docs_bucket
+      .download("@scope/foo/1.2.3/docs.md".into())
+      .await
+      .unwrap()
+      .unwrap();
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/1.2.3/docs.md")
+      .call()
+      .await
+      .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+      resp.headers().get("Content-Type").unwrap(),
+      "text/markdown; charset=utf-8"
+    );
+    let markdown = hyper::body::to_bytes(resp.body_mut()).await.unwrap();
+    assert_eq!(markdown, stored);
+
+    let markdown = std::str::from_utf8(&markdown).unwrap();
+    assert!(markdown.starts_with("# @scope/foo@1.2.3\n"), "{markdown}");
+    assert!(markdown.contains("## @scope/foo\n"), "{markdown}");
+    assert!(markdown.contains("This is a test module."), "{markdown}");
+    assert!(markdown.contains("### hello\n"), "{markdown}");
+    assert!(markdown.contains("```ts\nconst hello"), "{markdown}");
+    assert!(markdown.contains("This is a test constant."), "{markdown}");
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/1.0.0/docs.md")
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::NOT_FOUND, "packageVersionNotFound")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn test_package_diff_changes() {
+    let mut t = TestSetup::new().await;
+
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{:?}", task);
+    let task = process_tarball_setup2(
+      &t,
+      create_mock_tarball("diff"),
+      &PackageName::try_from("foo").unwrap(),
+      &Version::try_from("1.3.0").unwrap(),
+      false,
+    )
+    .await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{:?}", task);
+
+    let changes = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/diff/1.2.3/1.3.0/changes")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<serde_json::Value>()
+      .await;
+    assert_eq!(changes["oldVersion"]["version"], "1.2.3");
+    assert_eq!(changes["newVersion"]["version"], "1.3.0");
+    let module = &changes["diff"]["modifiedModules"]["file:///mod.ts"];
+    let names = |key: &str| {
+      module[key]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|symbol| symbol["name"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(names("added"), vec!["goodbye"]);
+    assert_eq!(names("removed"), vec!["读取多键1"]);
+    assert_eq!(names("modified"), vec!["hello"]);
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/diff/1.2.3/2.0.0/changes")
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::NOT_FOUND, "packageVersionNotFound")
+      .await;
+  }
+
   #[tokio::test]
   async fn test_package_docs_prerelease_only() {
     let mut t = TestSetup::new().await;
@@ -4179,34 +7793,224 @@ ggHohNAjhbzDaY2iBW/m3NC5dehGUP4T2GBo/cwGhg==
     .await;
     assert_eq!(task.status, PublishingTaskStatus::Success, "{:?}", task);
 
-    // with no stable release, docs fall back to the latest prerelease for both
-    // the "latest" alias and the explicit prerelease version
-    for path in [
-      "/api/scopes/scope/packages/foo/versions/latest/docs",
-      "/api/scopes/scope/packages/foo/versions/1.2.3-alpha.1/docs",
-    ] {
-      let mut resp = t.http().get(path).call().await.unwrap();
-      let docs: ApiPackageVersionDocs = resp.expect_ok().await;
-      match docs {
-        ApiPackageVersionDocs::Content { version, .. } => {
-          assert_eq!(version.version, task.package_version);
-        }
-        ApiPackageVersionDocs::Redirect { .. } => panic!(),
-      }
-    }
+    // with no stable release, docs fall back to the latest prerelease for both
+    // the "latest" alias and the explicit prerelease version
+    for path in [
+      "/api/scopes/scope/packages/foo/versions/latest/docs",
+      "/api/scopes/scope/packages/foo/versions/1.2.3-alpha.1/docs",
+    ] {
+      let mut resp = t.http().get(path).call().await.unwrap();
+      let docs: ApiPackageVersionDocs = resp.expect_ok().await;
+      match docs {
+        ApiPackageVersionDocs::Content { version, .. } => {
+          assert_eq!(version.version, task.package_version);
+        }
+        ApiPackageVersionDocs::Redirect { .. } => panic!(),
+      }
+    }
+
+    // a version that is not the latest prerelease is still rejected
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/1.0.0/docs")
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::NOT_FOUND, "docsOnlyForLatestVersion")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn yank_version() {
+    let mut t = TestSetup::new().await;
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:?}");
+    let token = t.user1.token.clone();
+
+    t.http()
+      .patch("/api/scopes/scope/packages/foo/versions/1.2.3")
+      .body_json(json!({ "yanked": false, "yankReason": "broken" }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+
+    t.http()
+      .patch("/api/scopes/scope/packages/foo/versions/1.2.3")
+      .body_json(json!({ "yanked": true, "yankReason": "broken build" }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok_no_content()
+      .await;
+
+    let version = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/1.2.3")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPackageVersion>()
+      .await;
+    assert!(version.yanked);
+    assert!(version.yanked_at.is_some());
+    assert_eq!(version.yank_reason.as_deref(), Some("broken build"));
+
+    // The yanked version can still be installed from npm by exact version,
+    // but is not the latest version anymore.
+    let packument = t
+      .http()
+      .get("/api/npm/@jsr%2fscope__foo")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<serde_json::Value>()
+      .await;
+    assert_eq!(packument["dist-tags"], json!({}));
+    assert_eq!(
+      packument["versions"]["1.2.3"]["deprecated"],
+      "This version has been yanked: broken build"
+    );
+
+    t.http()
+      .patch("/api/scopes/scope/packages/foo/versions/1.2.3")
+      .body_json(json!({ "yanked": false }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok_no_content()
+      .await;
+
+    let version = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/1.2.3")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPackageVersion>()
+      .await;
+    assert!(!version.yanked);
+    assert_eq!(version.yanked_at, None);
+    assert_eq!(version.yank_reason, None);
+  }
+
+  #[tokio::test]
+  async fn test_package_snapshot() {
+    let t = TestSetup::new().await;
+
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{:?}", task);
+
+    let before_yank =
+      chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true);
+    t.db()
+      .yank_package_version(
+        &t.user1.user.id,
+        false,
+        &task.package_scope,
+        &task.package_name,
+        &task.package_version,
+        true,
+        None,
+      )
+      .await
+      .unwrap();
+
+    let resp = t
+      .http()
+      .get(format!(
+        "/api/scopes/scope/packages/foo/snapshot?as_of={before_yank}"
+      ))
+      .call()
+      .await
+      .unwrap();
+    assert_ne!(
+      resp.headers().get(hyper::header::CACHE_CONTROL).unwrap(),
+      "no-store"
+    );
+    let snapshot = resp.expect_ok::<ApiPackageSnapshot>().await;
+    assert_eq!(snapshot.latest, Some(task.package_version.clone()));
+    assert!(!snapshot.versions[&task.package_version].yanked);
+
+    let now =
+      chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true);
+    let snapshot = t
+      .http()
+      .get(format!(
+        "/api/scopes/scope/packages/foo/snapshot?as_of={now}"
+      ))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPackageSnapshot>()
+      .await;
+    assert_eq!(snapshot.latest, None);
+    assert!(snapshot.versions[&task.package_version].yanked);
 
-    // a version that is not the latest prerelease is still rejected
-    let mut resp = t
+    // a snapshot of the future can still change, so it is not cached
+    let tomorrow = (chrono::Utc::now() + chrono::Duration::days(1))
+      .to_rfc3339_opts(chrono::SecondsFormat::Micros, true);
+    let resp = t
       .http()
-      .get("/api/scopes/scope/packages/foo/versions/1.0.0/docs")
+      .get(format!(
+        "/api/scopes/scope/packages/foo/snapshot?as_of={tomorrow}"
+      ))
       .call()
       .await
       .unwrap();
-    resp
-      .expect_err_code(StatusCode::NOT_FOUND, "docsOnlyForLatestVersion")
+    assert_eq!(
+      resp.headers().get(hyper::header::CACHE_CONTROL).unwrap(),
+      "no-store"
+    );
+    let snapshot = resp.expect_ok::<ApiPackageSnapshot>().await;
+    assert!(snapshot.versions[&task.package_version].yanked);
+
+    // the package did not exist yet
+    t.http()
+      .get("/api/scopes/scope/packages/foo/snapshot?as_of=2000-01-01")
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "packageNotFound")
+      .await;
+
+    t.http()
+      .get("/api/scopes/scope/packages/foo/snapshot?as_of=yesterday")
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+    t.http()
+      .get("/api/scopes/scope/packages/foo/snapshot")
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
       .await;
   }
 
+  #[test]
+  fn parse_as_of() {
+    assert_eq!(
+      super::parse_as_of("2024-06-01").unwrap().to_rfc3339(),
+      "2024-06-01T00:00:00+00:00"
+    );
+    assert_eq!(
+      super::parse_as_of("2024-06-01T12:00:00+02:00")
+        .unwrap()
+        .to_rfc3339(),
+      "2024-06-01T10:00:00+00:00"
+    );
+    assert!(super::parse_as_of("2024-06-31").is_none());
+    assert!(super::parse_as_of("").is_none());
+  }
+
   #[tokio::test]
   async fn test_package_dependencies_and_dependents() {
     let mut t = TestSetup::new().await;
@@ -4280,13 +8084,17 @@ ggHohNAjhbzDaY2iBW/m3NC5dehGUP4T2GBo/cwGhg==
           kind: ApiDependencyKind::Jsr,
           name: "@scope/foo".to_string(),
           constraint: "1".to_string(),
-          path: "".to_string()
+          path: "".to_string(),
+          optional: false,
+          archived: false,
         },
         ApiDependency {
           kind: ApiDependencyKind::Npm,
           name: "express".to_string(),
           constraint: "4".to_string(),
-          path: "".to_string()
+          path: "".to_string(),
+          optional: false,
+          archived: false,
         },
       ],
     );
@@ -4308,6 +8116,38 @@ ggHohNAjhbzDaY2iBW/m3NC5dehGUP4T2GBo/cwGhg==
       }]
     );
 
+    // Dependents are warned when a dependency gets archived
+    t.http()
+      .patch("/api/scopes/scope/packages/foo")
+      .body_json(json!({ "isArchived": true }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPackage>()
+      .await;
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/bar/versions/1.2.3/dependencies")
+      .call()
+      .await
+      .unwrap();
+    let deps: Vec<ApiDependency> = resp.expect_ok().await;
+    assert_eq!(
+      deps
+        .iter()
+        .map(|dep| (dep.name.as_str(), dep.archived))
+        .collect::<Vec<_>>(),
+      vec![("@scope/foo", true), ("express", false)],
+    );
+    t.http()
+      .patch("/api/scopes/scope/packages/foo")
+      .body_json(json!({ "isArchived": false }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPackage>()
+      .await;
+
     let package_name = PackageName::try_from("bar").unwrap();
     let version = Version::try_from("1.2.4").unwrap();
     let task = crate::publish::tests::process_tarball_setup2(
@@ -4425,6 +8265,285 @@ ggHohNAjhbzDaY2iBW/m3NC5dehGUP4T2GBo/cwGhg==
       ],
     );
     assert_eq!(dependents.total, 2);
+
+    // only dependents of the given versions of foo
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/dependents?version=1.2.3")
+      .call()
+      .await
+      .unwrap();
+    let dependents: ApiList<ApiDependent> = resp.expect_ok().await;
+    assert_eq!(dependents.total, 2);
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/dependents?version=2")
+      .call()
+      .await
+      .unwrap();
+    let dependents: ApiList<ApiDependent> = resp.expect_ok().await;
+    assert_eq!(dependents.items.len(), 0);
+    assert_eq!(dependents.total, 0);
+
+    t.db()
+      .insert_download_entries(vec![VersionDownloadCount {
+        scope: "scope".try_into().unwrap(),
+        package: "baz".try_into().unwrap(),
+        version: "1.2.3".try_into().unwrap(),
+        time_bucket: Utc::now() - chrono::Duration::hours(1),
+        kind: DownloadKind::JsrMeta,
+        count: 10,
+      }])
+      .await
+      .unwrap();
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/dependents?sort=downloads")
+      .call()
+      .await
+      .unwrap();
+    let dependents: ApiList<ApiDependent> = resp.expect_ok().await;
+    let packages = dependents
+      .items
+      .iter()
+      .map(|dependent| dependent.package.to_string())
+      .collect::<Vec<_>>();
+    assert_eq!(packages, vec!["baz", "bar"]);
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/dependents?sort=score&limit=1")
+      .call()
+      .await
+      .unwrap();
+    let dependents: ApiList<ApiDependent> = resp.expect_ok().await;
+    assert_eq!(dependents.items.len(), 1);
+    assert_eq!(dependents.total, 2);
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/dependents?sort=stars")
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn test_package_version_deprecations() {
+    let t = TestSetup::new().await;
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/1.2.3/deprecations")
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::NOT_FOUND, "packageVersionNotFound")
+      .await;
+
+    let task =
+      process_tarball_setup(&t, create_mock_tarball("deprecations")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{:?}", task);
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/1.2.3/deprecations")
+      .call()
+      .await
+      .unwrap();
+    let deprecations: Vec<ApiDeprecation> = resp.expect_ok().await;
+    assert_eq!(
+      deprecations,
+      vec![
+        ApiDeprecation {
+          export: ".".to_string(),
+          symbol: "hello".to_string(),
+          message: Some("Use {@linkcode greet} instead.".to_string()),
+        },
+        ApiDeprecation {
+          export: "./fs".to_string(),
+          symbol: "exists".to_string(),
+          message: None,
+        },
+      ],
+    );
+  }
+
+  #[tokio::test]
+  async fn test_package_version_integrity() {
+    let t = TestSetup::new().await;
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/1.2.3/integrity")
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::NOT_FOUND, "packageVersionNotFound")
+      .await;
+
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{:?}", task);
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/1.2.3/integrity")
+      .call()
+      .await
+      .unwrap();
+    let integrity: ApiVersionIntegrity = resp.expect_ok().await;
+
+    let file = integrity
+      .files
+      .iter()
+      .find(|file| file.path.to_string() == "/mod.ts")
+      .unwrap();
+    let checksum = file.checksum.as_deref().unwrap();
+    let contents = t
+      .buckets
+      .modules_bucket
+      .download(crate::s3_paths::module_blob_path(checksum).as_str().into())
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(crate::integrity::sha256_digest(&contents), checksum);
+
+    assert_eq!(integrity.npm_tarballs.len(), 1);
+    assert!(integrity.npm_tarballs[0].sha256.is_some());
+
+    let kinds = integrity
+      .docs
+      .iter()
+      .map(|artifact| artifact.kind)
+      .collect::<Vec<_>>();
+    assert_eq!(
+      kinds,
+      vec![
+        DocArtifactKind::DocNodes,
+        DocArtifactKind::Markdown,
+        DocArtifactKind::TypeGraph
+      ]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_doc_links() {
+    let t = TestSetup::new().await;
+
+    let task =
+      process_tarball_setup(&t, create_mock_tarball("deprecations")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{:?}", task);
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/latest/symbols/hello")
+      .call()
+      .await
+      .unwrap();
+    let link: ApiDocLink = resp.expect_ok().await;
+    assert_eq!(
+      link,
+      ApiDocLink {
+        version: Version::try_from("1.2.3").unwrap(),
+        identifier: "hello".to_string(),
+        path: "/doc/~/hello".to_string(),
+      }
+    );
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/1.2.3/symbols/exists")
+      .call()
+      .await
+      .unwrap();
+    let link: ApiDocLink = resp.expect_ok().await;
+    assert_eq!(link.path, "/doc/fs/~/exists");
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/latest/symbols/missing")
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::NOT_FOUND, "entrypointOrSymbolNotFound")
+      .await;
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/versions/0.0.1/symbols/hello")
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::NOT_FOUND, "packageVersionNotFound")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn test_symbol_search() {
+    let t = TestSetup::new().await;
+
+    let task =
+      process_tarball_setup(&t, create_mock_tarball("deprecations")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{:?}", task);
+
+    let mut resp = t.http().get("/api/symbols?query=HEL").call().await.unwrap();
+    let results: ApiList<ApiSymbolSearchResult> = resp.expect_ok().await;
+    assert_eq!(results.total, 1);
+    let result = &results.items[0];
+    assert_eq!(result.scope, ScopeName::try_from("scope").unwrap());
+    assert_eq!(result.package, PackageName::try_from("foo").unwrap());
+    assert_eq!(result.version, Version::try_from("1.2.3").unwrap());
+    assert_eq!(result.export, ".");
+    assert_eq!(result.symbol, "hello");
+    assert_eq!(result.kind, "function");
+    assert!(result.score.is_some());
+
+    let mut resp = t
+      .http()
+      .get("/api/symbols?query=e&kind=variable")
+      .call()
+      .await
+      .unwrap();
+    let results: ApiList<ApiSymbolSearchResult> = resp.expect_ok().await;
+    assert_eq!(
+      results
+        .items
+        .iter()
+        .map(|result| (result.export.as_str(), result.symbol.as_str()))
+        .collect::<Vec<_>>(),
+      vec![("./fs", "exists")],
+    );
+
+    let mut resp = t
+      .http()
+      .get("/api/symbols?query=hello&kind=class")
+      .call()
+      .await
+      .unwrap();
+    let results: ApiList<ApiSymbolSearchResult> = resp.expect_ok().await;
+    assert_eq!(results.total, 0);
+
+    let mut resp = t.http().get("/api/symbols").call().await.unwrap();
+    resp
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+
+    let mut resp = t
+      .http()
+      .get("/api/symbols?query=hello&kind=module")
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
   }
 
   #[tokio::test]
@@ -4512,6 +8631,151 @@ ggHohNAjhbzDaY2iBW/m3NC5dehGUP4T2GBo/cwGhg==
         }
       ]
     );
+
+    // only the package's own modules
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/bar/versions/1.2.3/dependencies/graph?max_depth=0")
+      .call()
+      .await
+      .unwrap();
+    let deps: Vec<ApiDependencyGraphItem> = resp.expect_ok().await;
+    assert_eq!(
+      deps,
+      vec![ApiDependencyGraphItem {
+        id: 0,
+        dependency: super::DependencyKind::Root {
+          path: "/mod.ts".to_string()
+        },
+        children: IndexSet::new(),
+        size: Some(117),
+        media_type: Some("TypeScript".to_string())
+      }]
+    );
+
+    // as a tree, parents come before their children
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/bar/versions/1.2.3/dependencies/graph?dedupe=false")
+      .call()
+      .await
+      .unwrap();
+    let deps: Vec<ApiDependencyGraphItem> = resp.expect_ok().await;
+    assert_eq!(deps.len(), 2);
+    assert_eq!(
+      deps[0].dependency,
+      super::DependencyKind::Root {
+        path: "/mod.ts".to_string()
+      }
+    );
+    assert_eq!(deps[0].children, IndexSet::from([1]));
+    assert_eq!(deps[1].id, 1);
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/bar/versions/1.2.3/dependencies/graph?max_depth=-1")
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+  }
+
+  #[test]
+  fn pin_jsr_specifier() {
+    assert_eq!(
+      super::pin_jsr_specifier("jsr:@std/path@^1/join", "1.0.8").as_deref(),
+      Some("jsr:@std/path@1.0.8/join")
+    );
+    assert_eq!(
+      super::pin_jsr_specifier("jsr:@std/path", "1.0.8").as_deref(),
+      Some("jsr:@std/path@1.0.8")
+    );
+    assert_eq!(
+      super::pin_jsr_specifier("jsr:/@std/path/join", "1.0.8").as_deref(),
+      Some("jsr:@std/path@1.0.8/join")
+    );
+    assert_eq!(super::pin_jsr_specifier("npm:express@4", "4.0.0"), None);
+  }
+
+  #[tokio::test]
+  async fn import_map() {
+    let mut t = TestSetup::new().await;
+
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{:?}", task);
+    let package_name = PackageName::try_from("bar").unwrap();
+    let version = Version::try_from("1.2.3").unwrap();
+    let task = process_tarball_setup2(
+      &t,
+      create_mock_tarball("depends_on_ok"),
+      &package_name,
+      &version,
+      false,
+    )
+    .await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{:?}", task);
+
+    let import_map = t
+      .http()
+      .get("/api/scopes/scope/packages/bar/versions/1.2.3/import_map")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiImportMap>()
+      .await;
+    assert_eq!(
+      import_map.imports,
+      IndexMap::from([
+        (
+          "@scope/bar".to_string(),
+          "http://jsr-tests.test/@scope/bar/1.2.3/mod.ts".to_string()
+        ),
+        (
+          "jsr:@scope/foo@1".to_string(),
+          "http://jsr-tests.test/@scope/foo/1.2.3/mod.ts".to_string()
+        ),
+      ])
+    );
+
+    let import_map = t
+      .http()
+      .get(
+        "/api/scopes/scope/packages/bar/versions/1.2.3/import_map?format=deno",
+      )
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiImportMap>()
+      .await;
+    assert_eq!(
+      import_map.imports,
+      IndexMap::from([
+        ("@scope/bar".to_string(), "jsr:@scope/bar@1.2.3".to_string()),
+        (
+          "jsr:@scope/foo@1".to_string(),
+          "jsr:@scope/foo@1.2.3".to_string()
+        ),
+      ])
+    );
+
+    t.http()
+      .get(
+        "/api/scopes/scope/packages/bar/versions/1.2.3/import_map?format=node",
+      )
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+    t.http()
+      .get("/api/scopes/scope/packages/bar/versions/0.0.1/import_map")
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "packageVersionNotFound")
+      .await;
   }
 
   #[tokio::test]
@@ -4642,101 +8906,297 @@ ggHohNAjhbzDaY2iBW/m3NC5dehGUP4T2GBo/cwGhg==
       .ephemeral_database
       .create_package(&scope, &name)
       .await
-      .unwrap();
-    assert!(matches!(res, CreatePackageResult::Ok(_)));
+      .unwrap();
+    assert!(matches!(res, CreatePackageResult::Ok(_)));
+
+    let version = Version::try_from("1.2.3").unwrap();
+    let config_file = PackagePath::try_from("/jsr.json").unwrap();
+    t.db()
+      .create_publishing_task(NewPublishingTask {
+        user_id: Some(t.user1.user.id),
+        package_scope: &scope,
+        package_name: &name,
+        package_version: &version,
+        config_file: &config_file,
+      })
+      .await
+      .unwrap();
+
+    let url = format!("/api/scopes/{}/packages/{}", scope, name);
+    let mut resp = t.http().delete(url).call().await.unwrap();
+    resp
+      .expect_err_code(StatusCode::CONFLICT, "packageNotEmpty")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn archive_package() {
+    let mut t = TestSetup::new().await;
+
+    let scope = t.scope.scope.clone();
+
+    let name = PackageName::try_from("foo").unwrap();
+    let res = t
+      .ephemeral_database
+      .create_package(&scope, &name)
+      .await
+      .unwrap();
+    assert!(matches!(res, CreatePackageResult::Ok(_)));
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo")
+      .call()
+      .await
+      .unwrap();
+    let package: ApiPackage = resp.expect_ok().await;
+    assert!(!package.is_archived);
+
+    let mut resp = t
+      .http()
+      .patch("/api/scopes/scope/packages/foo")
+      .body_json(json!({
+        "isArchived": true
+      }))
+      .call()
+      .await
+      .unwrap();
+    let package: ApiPackage = resp.expect_ok().await;
+    assert!(package.is_archived);
+
+    let mut resp = t
+      .http()
+      .patch("/api/scopes/scope/packages/foo")
+      .body_json(json!({
+        "description": "foo"
+      }))
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::BAD_REQUEST, "packageArchived")
+      .await;
+
+    let data = create_mock_tarball("ok");
+    let mut resp = t
+      .http()
+      .post("/api/scopes/scope/packages/foo/versions/1.2.3?config=/jsr.json")
+      .gzip()
+      .body(Body::from(data))
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::BAD_REQUEST, "packageArchived")
+      .await;
+
+    let mut resp = t
+      .http()
+      .patch("/api/scopes/scope/packages/foo/versions/1.2.3")
+      .body_json(json!({ "yanked": true }))
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::BAD_REQUEST, "packageArchived")
+      .await;
+
+    let mut resp = t
+      .http()
+      .put("/api/scopes/scope/packages/foo/dist_tags/beta")
+      .body_json(json!({ "version": "1.2.3" }))
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::BAD_REQUEST, "packageArchived")
+      .await;
+
+    let mut resp = t
+      .http()
+      .patch("/api/scopes/scope/packages/foo")
+      .body_json(json!({
+        "isArchived": false
+      }))
+      .call()
+      .await
+      .unwrap();
+    let package: ApiPackage = resp.expect_ok().await;
+    assert!(!package.is_archived);
+  }
+
+  #[tokio::test]
+  async fn private_package() {
+    let mut t = TestSetup::new().await;
+
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{:?}", task);
+
+    let mut resp = t
+      .http()
+      .patch("/api/scopes/scope/packages/foo")
+      .body_json(json!({ "visibility": "private" }))
+      .call()
+      .await
+      .unwrap();
+    let package: ApiPackage = resp.expect_ok().await;
+    assert_eq!(package.visibility, ApiPackageVisibility::Private);
+
+    // The lb does not serve the files of marked packages from the buckets.
+    let marker_path: std::sync::Arc<str> = "@scope/foo/_private".into();
+    assert!(
+      t.buckets
+        .modules_bucket
+        .download(marker_path.clone())
+        .await
+        .unwrap()
+        .is_some()
+    );
+
+    for path in [
+      "/api/scopes/scope/packages/foo",
+      "/api/scopes/scope/packages/foo/versions",
+      "/api/scopes/scope/packages/foo/versions/1.2.3",
+      "/api/scopes/scope/packages/foo/versions/1.2.3/docs",
+      "/api/scopes/scope/packages/foo/versions/1.2.3/tarball",
+      "/api/scopes/scope/packages/foo/module_file?path=meta.json",
+      "/api/scopes/scope/packages/foo/module_file?path=1.2.3/mod.ts",
+    ] {
+      t.unauthed_http()
+        .get(path)
+        .call()
+        .await
+        .unwrap()
+        .expect_err_code(StatusCode::NOT_FOUND, "packageNotFound")
+        .await;
+    }
+
+    let token = t.user2.token.clone();
+    t.http()
+      .get("/api/scopes/scope/packages/foo")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "packageNotFound")
+      .await;
+
+    // A token of a scope member that only grants access to another scope
+    // does not grant access to the package either.
+    let token = create_token(
+      &t.db(),
+      t.user1.user.id,
+      TokenType::Web,
+      None,
+      None,
+      Some(Permissions(vec![Permission::PackagePublish(
+        PackagePublishPermission::Scope {
+          scope: ScopeName::new("otherscope".to_owned()).unwrap(),
+        },
+      )])),
+    )
+    .await
+    .unwrap();
+    t.http()
+      .get("/api/scopes/scope/packages/foo")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "packageNotFound")
+      .await;
 
-    let version = Version::try_from("1.2.3").unwrap();
-    let config_file = PackagePath::try_from("/jsr.json").unwrap();
-    t.db()
-      .create_publishing_task(NewPublishingTask {
-        user_id: Some(t.user1.user.id),
-        package_scope: &scope,
-        package_name: &name,
-        package_version: &version,
-        config_file: &config_file,
-      })
+    let packages = t
+      .unauthed_http()
+      .get("/api/scopes/scope/packages")
+      .call()
       .await
-      .unwrap();
-
-    let url = format!("/api/scopes/{}/packages/{}", scope, name);
-    let mut resp = t.http().delete(url).call().await.unwrap();
-    resp
-      .expect_err_code(StatusCode::CONFLICT, "packageNotEmpty")
+      .unwrap()
+      .expect_ok::<ApiList<ApiPackage>>()
       .await;
-  }
-
-  #[tokio::test]
-  async fn archive_package() {
-    let mut t = TestSetup::new().await;
+    assert_eq!(packages.total, 0);
 
-    let scope = t.scope.scope.clone();
-
-    let name = PackageName::try_from("foo").unwrap();
-    let res = t
-      .ephemeral_database
-      .create_package(&scope, &name)
+    // Scope members can still see the package, but the responses must not be
+    // shared with anyone else.
+    let packages = t
+      .http()
+      .get("/api/scopes/scope/packages")
+      .call()
       .await
-      .unwrap();
-    assert!(matches!(res, CreatePackageResult::Ok(_)));
-
-    let mut resp = t
+      .unwrap()
+      .expect_ok::<ApiList<ApiPackage>>()
+      .await;
+    assert_eq!(packages.total, 1);
+    let resp = t
       .http()
-      .get("/api/scopes/scope/packages/foo")
+      .get("/api/scopes/scope/packages/foo/versions/1.2.3/docs")
       .call()
       .await
       .unwrap();
-    let package: ApiPackage = resp.expect_ok().await;
-    assert!(!package.is_archived);
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+      resp.headers().get(hyper::header::CACHE_CONTROL).unwrap(),
+      "private, no-cache"
+    );
 
+    // The lb has the API serve the files of private packages.
     let mut resp = t
       .http()
-      .patch("/api/scopes/scope/packages/foo")
-      .body_json(json!({
-        "isArchived": true
-      }))
+      .get("/api/scopes/scope/packages/foo/module_file?path=1.2.3/mod.ts")
       .call()
       .await
       .unwrap();
-    let package: ApiPackage = resp.expect_ok().await;
-    assert!(package.is_archived);
-
-    let mut resp = t
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+      resp.headers().get(hyper::header::CONTENT_TYPE).unwrap(),
+      "text/typescript"
+    );
+    let body = hyper::body::to_bytes(resp.body_mut()).await.unwrap();
+    assert_eq!(
+      body,
+      std::fs::read("./testdata/tarballs/ok/mod.ts").unwrap()
+    );
+    let meta = t
       .http()
-      .patch("/api/scopes/scope/packages/foo")
-      .body_json(json!({
-        "description": "foo"
-      }))
+      .get("/api/scopes/scope/packages/foo/module_file?path=1.2.3_meta.json")
       .call()
       .await
-      .unwrap();
-    resp
-      .expect_err_code(StatusCode::BAD_REQUEST, "packageArchived")
+      .unwrap()
+      .expect_ok::<serde_json::Value>()
       .await;
-
-    let data = create_mock_tarball("ok");
-    let mut resp = t
-      .http()
-      .post("/api/scopes/scope/packages/foo/versions/1.2.3?config=/jsr.json")
-      .gzip()
-      .body(Body::from(data))
+    assert!(meta["manifest"]["/mod.ts"].is_object());
+    t.http()
+      .get("/api/scopes/scope/packages/foo/module_file?path=1.2.3/missing.ts")
       .call()
       .await
-      .unwrap();
-    resp
-      .expect_err_code(StatusCode::BAD_REQUEST, "packageArchived")
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "packagePathNotFound")
       .await;
 
     let mut resp = t
       .http()
       .patch("/api/scopes/scope/packages/foo")
-      .body_json(json!({
-        "isArchived": false
-      }))
+      .body_json(json!({ "visibility": "public" }))
       .call()
       .await
       .unwrap();
     let package: ApiPackage = resp.expect_ok().await;
-    assert!(!package.is_archived);
+    assert_eq!(package.visibility, ApiPackageVisibility::Public);
+    assert!(
+      t.buckets
+        .modules_bucket
+        .download(marker_path)
+        .await
+        .unwrap()
+        .is_none()
+    );
+    t.unauthed_http()
+      .get("/api/scopes/scope/packages/foo")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPackage>()
+      .await;
   }
 
   #[tokio::test]
@@ -4800,7 +9260,27 @@ ggHohNAjhbzDaY2iBW/m3NC5dehGUP4T2GBo/cwGhg==
     };
 
     assert_eq!(size, 124);
-    assert!(view.is_some());
+
+    // The view is rendered at publish time and keyed by the content hash.
+    let source = crate::module_files::download_file(
+      &t.buckets.modules_bucket,
+      &task.package_scope,
+      &task.package_name,
+      &task.package_version,
+      &PackagePath::try_from("/mod.ts").unwrap(),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    let hash = format!("sha256-{:x}", sha2::Sha256::digest(&source));
+    let stored_view = t
+      .buckets
+      .docs_bucket
+      .download(crate::s3_paths::source_view_path(&hash).into())
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(view.as_deref(), std::str::from_utf8(&stored_view).ok());
 
     let url = format!(
       "/api/scopes/{}/packages/{}/versions/{}/source?path=/bin.bin",
@@ -4968,4 +9448,259 @@ ggHohNAjhbzDaY2iBW/m3NC5dehGUP4T2GBo/cwGhg==
     .await;
     assert_eq!(task.status, PublishingTaskStatus::Failure, "{:?}", task);
   }
+
+  #[tokio::test]
+  async fn member_cooldown() {
+    let mut t = TestSetup::new().await;
+
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{:?}", task);
+
+    let scope = t.scope.scope.clone();
+    t.db()
+      .add_scope_invite(
+        &t.user1.user.id,
+        false,
+        NewScopeInvite {
+          target_user_id: t.user2.user.id,
+          requesting_user_id: t.user1.user.id,
+          scope: &scope,
+        },
+      )
+      .await
+      .unwrap();
+    let token = t.user2.token.clone();
+    let mut resp = t
+      .http()
+      .post("/api/user/invites/scope")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap();
+    resp.expect_ok::<ApiScopeMember>().await;
+
+    // the package is not popular, so the new member can update it
+    let mut resp = t
+      .http()
+      .patch("/api/scopes/scope/packages/foo")
+      .body_json(json!({ "description": "foo" }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap();
+    resp.expect_ok::<ApiPackage>().await;
+
+    t.db()
+      .insert_download_entries(vec![VersionDownloadCount {
+        scope: scope.clone(),
+        package: PackageName::try_from("foo").unwrap(),
+        version: Version::try_from("1.2.3").unwrap(),
+        time_bucket: Utc::now() - chrono::Duration::hours(1),
+        kind: DownloadKind::JsrMeta,
+        count: 1000,
+      }])
+      .await
+      .unwrap();
+
+    let mut resp = t
+      .http()
+      .patch("/api/scopes/scope/packages/foo")
+      .body_json(json!({ "description": "bar" }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::FORBIDDEN, "scopeMemberInCooldown")
+      .await;
+
+    // packages created after the member joined are not restricted
+    let name = PackageName::try_from("bar").unwrap();
+    let res = t.db().create_package(&scope, &name).await.unwrap();
+    assert!(matches!(res, CreatePackageResult::Ok(_)));
+    let mut resp = t
+      .http()
+      .patch("/api/scopes/scope/packages/bar")
+      .body_json(json!({ "description": "bar" }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap();
+    resp.expect_ok::<ApiPackage>().await;
+
+    // members can not lift their own cooldown
+    let user1_id = t.user1.user.id;
+    let user2_id = t.user2.user.id;
+    let mut resp = t
+      .http()
+      .post(format!(
+        "/api/scopes/scope/members/{user1_id}/lift_cooldown"
+      ))
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::FORBIDDEN, "cannotLiftOwnCooldown")
+      .await;
+    let mut resp = t
+      .http()
+      .post(format!(
+        "/api/scopes/scope/members/{user2_id}/lift_cooldown"
+      ))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::FORBIDDEN, "actorNotScopeAdmin")
+      .await;
+
+    let mut resp = t
+      .http()
+      .post(format!(
+        "/api/scopes/scope/members/{user2_id}/lift_cooldown"
+      ))
+      .call()
+      .await
+      .unwrap();
+    resp.expect_ok_no_content().await;
+
+    let mut resp = t
+      .http()
+      .patch("/api/scopes/scope/packages/foo")
+      .body_json(json!({ "description": "bar" }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap();
+    let package: ApiPackage = resp.expect_ok().await;
+    assert_eq!(package.description, "bar");
+  }
+
+  #[tokio::test]
+  async fn package_downloads() {
+    let mut t = TestSetup::new().await;
+
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{:?}", task);
+
+    let scope = t.scope.scope.clone();
+    let package = PackageName::try_from("foo").unwrap();
+    let version = Version::try_from("1.2.3").unwrap();
+    let time_bucket = Utc::now() - chrono::Duration::hours(1);
+    t.db()
+      .insert_download_entries(vec![VersionDownloadCount {
+        scope: scope.clone(),
+        package: package.clone(),
+        version: version.clone(),
+        time_bucket,
+        kind: DownloadKind::JsrMeta,
+        count: 10,
+      }])
+      .await
+      .unwrap();
+    t.db()
+      .insert_entrypoint_download_entries(vec![
+        ModuleDownloadCount {
+          scope: scope.clone(),
+          package: package.clone(),
+          version: version.clone(),
+          path: "/mod.ts".to_string(),
+          time_bucket,
+          count: 7,
+        },
+        // not an entrypoint, so it is dropped
+        ModuleDownloadCount {
+          scope: scope.clone(),
+          package: package.clone(),
+          version: version.clone(),
+          path: "/jsr.json".to_string(),
+          time_bucket,
+          count: 3,
+        },
+      ])
+      .await
+      .unwrap();
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/downloads?period=weekly")
+      .call()
+      .await
+      .unwrap();
+    let downloads: ApiPackageDownloads = resp.expect_ok().await;
+    assert_eq!(downloads.total.len(), 1);
+    assert_eq!(downloads.total[0].count, 10);
+    assert_eq!(downloads.recent_versions.len(), 1);
+    assert_eq!(downloads.entrypoints.len(), 1);
+    assert_eq!(downloads.entrypoints[0].entrypoint, ".");
+    assert_eq!(downloads.entrypoints[0].downloads.len(), 1);
+    assert_eq!(downloads.entrypoints[0].downloads[0].count, 7);
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/downloads?period=monthly")
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+
+    let mut resp = t
+      .http()
+      .get("/api/scopes/scope/packages/foo")
+      .call()
+      .await
+      .unwrap();
+    let package: ApiPackage = resp.expect_ok().await;
+    assert_eq!(package.downloads_last_30_days, 10);
+  }
+
+  #[test]
+  fn dependency_graph_cycles() {
+    let module = |path: &str, id: usize, children: &[usize]| {
+      (
+        super::DependencyKind::Root {
+          path: path.to_string(),
+        },
+        super::DependencyInfo {
+          id,
+          children: children.iter().copied().collect(),
+          size: None,
+          media_type: None,
+        },
+      )
+    };
+    // a.ts -> b.ts -> c.ts -> a.ts
+    let graph = super::DependencyGraph {
+      roots: IndexSet::from([0]),
+      dependencies: [
+        module("/c.ts", 2, &[0]),
+        module("/b.ts", 1, &[2]),
+        module("/a.ts", 0, &[1]),
+      ]
+      .into_iter()
+      .collect(),
+    };
+
+    let items = graph.to_api_items(None, true).unwrap();
+    assert_eq!(items.len(), 3);
+
+    let items = graph.to_api_items(Some(1), true).unwrap();
+    let ids = items.iter().map(|item| item.id).collect::<Vec<_>>();
+    assert_eq!(ids, vec![1, 0]);
+    assert!(items[0].children.is_empty());
+
+    // the import of a.ts from c.ts is listed, but not expanded again
+    let items = graph.to_api_items(None, false).unwrap();
+    assert_eq!(items.len(), 4);
+    assert_eq!(items[3].children, IndexSet::new());
+    assert_eq!(
+      items[3].dependency,
+      super::DependencyKind::Root {
+        path: "/a.ts".to_string()
+      }
+    );
+  }
 }
@@ -0,0 +1,575 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+//! Packages can be moved to another scope. An admin of the package requests
+//! the transfer, and an admin of the target scope accepts or declines it.
+//! Once accepted, the old name permanently redirects to the new one.
+//!
+//! The files of the published versions stay at the old location, so that
+//! existing `jsr:` specifiers and lockfiles keep working. They are copied to
+//! the new location in the background, after which the manifests and npm
+//! tarballs of the new name are generated.
+
+use hyper::Body;
+use hyper::Request;
+use hyper::Response;
+use hyper::StatusCode;
+use routerify::prelude::RequestExt;
+use tracing::Instrument;
+use tracing::Span;
+use tracing::error;
+use tracing::field;
+use tracing::instrument;
+use url::Url;
+
+use crate::NpmUrl;
+use crate::RegistryUrl;
+use crate::cache_purge::CachePurge;
+use crate::db::AcceptPackageTransferResult;
+use crate::db::Database;
+use crate::external::algolia::AlgoliaClient;
+use crate::iam::ReqIamExt;
+use crate::ids::PackageName;
+use crate::ids::ScopeName;
+use crate::npm::NpmSigner;
+use crate::npm::build_npm_tarball;
+use crate::npm::upload_npm_version_manifest;
+use crate::publish::upload_package_manifest;
+use crate::s3::Buckets;
+use crate::util::ApiResult;
+use crate::util::RequestIdExt;
+use crate::util::decode_json;
+
+use super::ApiCreatePackageTransferRequest;
+use super::ApiError;
+use super::ApiPackage;
+use super::ApiPackageTransfer;
+
+#[instrument(
+  name = "GET /api/scopes/:scope/packages/:package/transfer",
+  skip(req),
+  fields(scope, package)
+)]
+pub async fn get_handler(req: Request<Body>) -> ApiResult<ApiPackageTransfer> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+
+  let db = req.data::<Database>().unwrap();
+  let transfer = db
+    .get_package_transfer(&scope, &package)
+    .await?
+    .ok_or(ApiError::PackageTransferNotFound)?;
+
+  // Both sides of the transfer may see it.
+  let iam = req.iam();
+  if iam
+    .check_package_admin_access(&scope, &package)
+    .await
+    .is_err()
+  {
+    iam
+      .check_scope_admin_access(&transfer.target_scope)
+      .await
+      .map_err(|_| ApiError::PackageTransferNotFound)?;
+  }
+
+  Ok(transfer.into())
+}
+
+#[instrument(
+  name = "POST /api/scopes/:scope/packages/:package/transfer",
+  skip(req),
+  fields(scope, package, target_scope)
+)]
+pub async fn create_handler(
+  mut req: Request<Body>,
+) -> ApiResult<ApiPackageTransfer> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+
+  let ApiCreatePackageTransferRequest { target_scope } =
+    decode_json(&mut req).await?;
+  Span::current().record("target_scope", field::display(&target_scope));
+
+  let db = req.data::<Database>().unwrap();
+  db.get_package(&scope, &package)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+
+  let iam = req.iam();
+  let (user, sudo) = iam.check_package_admin_access(&scope, &package).await?;
+
+  if target_scope == scope {
+    return Err(ApiError::PackageTransferInvalid {
+      msg: "the package is already in this scope".into(),
+    });
+  }
+  db.get_scope(&target_scope)
+    .await?
+    .ok_or(ApiError::ScopeNotFound)?;
+  if db.get_package(&target_scope, &package).await?.is_some() {
+    return Err(ApiError::PackageAlreadyExists);
+  }
+
+  let transfer = db
+    .create_package_transfer(&user.id, sudo, &scope, &package, &target_scope)
+    .await?;
+
+  Ok(transfer.into())
+}
+
+/// Cancels the transfer when called by an admin of the package, or declines
+/// it when called by an admin of the target scope.
+#[instrument(
+  name = "DELETE /api/scopes/:scope/packages/:package/transfer",
+  skip(req),
+  fields(scope, package)
+)]
+pub async fn delete_handler(req: Request<Body>) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+
+  let db = req.data::<Database>().unwrap();
+  let transfer = db
+    .get_package_transfer(&scope, &package)
+    .await?
+    .ok_or(ApiError::PackageTransferNotFound)?;
+
+  let iam = req.iam();
+  let (user, sudo) =
+    match iam.check_package_admin_access(&scope, &package).await {
+      Ok(res) => res,
+      Err(err) => iam
+        .check_scope_admin_access(&transfer.target_scope)
+        .await
+        .map_err(|_| err)?,
+    };
+
+  db.delete_package_transfer(&user.id, sudo, &scope, &package)
+    .await?
+    .ok_or(ApiError::PackageTransferNotFound)?;
+
+  let resp = Response::builder()
+    .status(StatusCode::NO_CONTENT)
+    .body(Body::empty())
+    .unwrap();
+  Ok(resp)
+}
+
+#[instrument(
+  name = "POST /api/scopes/:scope/packages/:package/transfer/accept",
+  skip(req),
+  fields(scope, package, target_scope)
+)]
+pub async fn accept_handler(req: Request<Body>) -> ApiResult<ApiPackage> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+
+  let db = req.data::<Database>().unwrap();
+  let transfer = db
+    .get_package_transfer(&scope, &package)
+    .await?
+    .ok_or(ApiError::PackageTransferNotFound)?;
+  let target_scope = transfer.target_scope;
+  Span::current().record("target_scope", field::display(&target_scope));
+
+  let iam = req.iam();
+  let (user, sudo) = iam.check_scope_admin_access(&target_scope).await?;
+
+  let res = db
+    .accept_package_transfer(&user.id, sudo, &scope, &package, &target_scope)
+    .await?;
+  match res {
+    AcceptPackageTransferResult::Ok => {}
+    AcceptPackageTransferResult::NotFound => {
+      return Err(ApiError::PackageTransferNotFound);
+    }
+    AcceptPackageTransferResult::AlreadyExists => {
+      return Err(ApiError::PackageAlreadyExists);
+    }
+    AcceptPackageTransferResult::PublishInProgress => {
+      return Err(ApiError::PackageTransferInvalid {
+        msg: "a version of the package is currently being published".into(),
+      });
+    }
+    AcceptPackageTransferResult::PackageLimitExceeded(limit) => {
+      return Err(ApiError::PackageLimitExceeded { limit });
+    }
+  }
+
+  let (res_package, repo, meta) = db
+    .get_package(&target_scope, &package)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+
+  let algolia_client = req.data::<Option<AlgoliaClient>>().unwrap();
+  if let Some(algolia_client) = algolia_client {
+    algolia_client.delete_package(&scope, &package);
+    algolia_client.upsert_package(&res_package, &meta);
+  }
+
+  let registry_url = &req.data::<RegistryUrl>().unwrap().0;
+  let cache_purge = req.data::<CachePurge>().unwrap();
+  let mut purge_urls =
+    crate::s3_paths::package_api_cache_urls(registry_url, &scope, &package);
+  purge_urls.extend(crate::s3_paths::package_api_cache_urls(
+    registry_url,
+    &target_scope,
+    &package,
+  ));
+  cache_purge.purge(purge_urls);
+
+  spawn_move_files(&req, scope, target_scope, vec![package]);
+
+  Ok(ApiPackage::from((res_package, repo, meta)))
+}
+
+/// Moves the files of packages that were moved from `scope` to
+/// `target_scope` in the background, one package after the other.
+pub(super) fn spawn_move_files(
+  req: &Request<Body>,
+  scope: ScopeName,
+  target_scope: ScopeName,
+  packages: Vec<PackageName>,
+) {
+  let ctx = MoveContext {
+    db: req.data::<Database>().unwrap().clone(),
+    buckets: req.data::<Buckets>().unwrap().clone(),
+    registry_url: req.data::<RegistryUrl>().unwrap().0.clone(),
+    npm_url: req.data::<NpmUrl>().unwrap().0.clone(),
+    npm_signer: req.data::<NpmSigner>().unwrap().clone(),
+    cache_purge: req.data::<CachePurge>().unwrap().clone(),
+  };
+  let fut = async move {
+    for package in packages {
+      if let Err(err) = ctx.move_files(&scope, &target_scope, &package).await
+      {
+        error!(
+          "failed to move files of @{scope}/{package} to @{target_scope}/{package}: {err}"
+        );
+      }
+    }
+  }
+  .instrument(Span::current());
+  tokio::spawn(fut);
+}
+
+#[instrument(
+  name = "GET /api/scopes/:scope/incoming_transfers",
+  skip(req),
+  fields(scope)
+)]
+pub async fn list_incoming_handler(
+  req: Request<Body>,
+) -> ApiResult<Vec<ApiPackageTransfer>> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+
+  let iam = req.iam();
+  iam.check_scope_admin_access(&scope).await?;
+
+  let db = req.data::<Database>().unwrap();
+  let transfers = db.list_incoming_package_transfers(&scope).await?;
+  Ok(
+    transfers
+      .into_iter()
+      .map(ApiPackageTransfer::from)
+      .collect(),
+  )
+}
+
+struct MoveContext {
+  db: Database,
+  buckets: Buckets,
+  registry_url: Url,
+  npm_url: Url,
+  npm_signer: NpmSigner,
+  cache_purge: CachePurge,
+}
+
+impl MoveContext {
+  /// Copies the files of a moved package to its new location, and
+  /// generates the manifests and npm tarballs of the new name from them.
+  async fn move_files(
+    &self,
+    scope: &ScopeName,
+    target_scope: &ScopeName,
+    package: &PackageName,
+  ) -> Result<(), anyhow::Error> {
+    let from: std::sync::Arc<str> = format!("@{scope}/{package}/").into();
+    let to: std::sync::Arc<str> = format!("@{target_scope}/{package}/").into();
+    self
+      .buckets
+      .modules_bucket
+      .copy_directory(from.clone(), to.clone())
+      .await?;
+    self.buckets.docs_bucket.copy_directory(from, to).await?;
+
+    upload_package_manifest(
+      &self.db,
+      &self.buckets,
+      &self.registry_url,
+      &self.cache_purge,
+      target_scope,
+      package,
+    )
+    .await?;
+
+    let versions = self
+      .db
+      .list_package_versions_for_metadata(target_scope, package)
+      .await?;
+    for version in versions {
+      build_npm_tarball(
+        &self.db,
+        &self.buckets,
+        &self.registry_url,
+        &self.npm_url,
+        &self.npm_signer,
+        target_scope,
+        package,
+        &version.version,
+        false,
+      )
+      .await?;
+    }
+
+    upload_npm_version_manifest(
+      &self.db,
+      &self.buckets,
+      &self.npm_url,
+      &self.npm_signer,
+      &self.cache_purge,
+      target_scope,
+      package,
+    )
+    .await?;
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::StatusCode;
+  use serde_json::json;
+
+  use crate::api::ApiPackage;
+  use crate::api::ApiPackageTransfer;
+  use crate::db::PublishingTaskStatus;
+  use crate::db::ScopeDescription;
+  use crate::ids::PackageName;
+  use crate::ids::PackagePath;
+  use crate::ids::ScopeName;
+  use crate::ids::Version;
+  use crate::publish::tests::create_mock_tarball;
+  use crate::publish::tests::process_tarball_setup;
+  use crate::util::test::ApiResultExt;
+  use crate::util::test::TestSetup;
+
+  #[tokio::test]
+  async fn transfer_package() {
+    let mut t = TestSetup::new().await;
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:?}");
+
+    let target_scope = ScopeName::try_from("scope2").unwrap();
+    t.db()
+      .create_scope(
+        &t.user2.user.id,
+        false,
+        &target_scope,
+        t.user2.user.id,
+        &ScopeDescription::default(),
+      )
+      .await
+      .unwrap();
+
+    let token1 = t.user1.token.clone();
+    let token2 = t.user2.token.clone();
+
+    t.http()
+      .post("/api/scopes/scope/packages/foo/transfer")
+      .body_json(json!({ "targetScope": "scope" }))
+      .token(Some(&token1))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "packageTransferInvalid")
+      .await;
+    t.http()
+      .post("/api/scopes/scope/packages/foo/transfer")
+      .body_json(json!({ "targetScope": "scope2" }))
+      .token(Some(&token2))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::FORBIDDEN, "actorNotScopeMember")
+      .await;
+
+    let transfer = t
+      .http()
+      .post("/api/scopes/scope/packages/foo/transfer")
+      .body_json(json!({ "targetScope": "scope2" }))
+      .token(Some(&token1))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPackageTransfer>()
+      .await;
+    assert_eq!(transfer.target_scope, target_scope);
+    assert_eq!(transfer.requested_by, t.user1.user.id);
+
+    let incoming = t
+      .http()
+      .get("/api/scopes/scope2/incoming_transfers")
+      .token(Some(&token2))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<Vec<ApiPackageTransfer>>()
+      .await;
+    assert_eq!(incoming.len(), 1);
+    assert_eq!(incoming[0].package.to_string(), "foo");
+
+    // Only the target scope can accept the transfer.
+    t.http()
+      .post("/api/scopes/scope/packages/foo/transfer/accept")
+      .token(Some(&token1))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::FORBIDDEN, "actorNotScopeMember")
+      .await;
+
+    let package = t
+      .http()
+      .post("/api/scopes/scope/packages/foo/transfer/accept")
+      .token(Some(&token2))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPackage>()
+      .await;
+    assert_eq!(package.scope, target_scope);
+
+    t.http()
+      .get("/api/scopes/scope/packages/foo")
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::MOVED_PERMANENTLY, "packageMoved")
+      .await;
+    t.http()
+      .get("/api/scopes/scope2/packages/foo/versions/1.2.3")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<serde_json::Value>()
+      .await;
+    t.http()
+      .post("/api/scopes/scope/packages/foo/transfer/accept")
+      .token(Some(&token2))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "packageTransferNotFound")
+      .await;
+
+    let redirect = t
+      .db()
+      .get_package_redirect(
+        &ScopeName::try_from("scope").unwrap(),
+        &PackageName::try_from("foo").unwrap(),
+      )
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(redirect.target_scope, target_scope);
+
+    // Files published under the old name stay where they are.
+    crate::module_files::download_file(
+      &t.buckets.modules_bucket,
+      &ScopeName::try_from("scope").unwrap(),
+      &PackageName::try_from("foo").unwrap(),
+      &Version::try_from("1.2.3").unwrap(),
+      &PackagePath::try_from("/mod.ts").unwrap(),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+  }
+
+  #[tokio::test]
+  async fn decline_package_transfer() {
+    let mut t = TestSetup::new().await;
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:?}");
+
+    let target_scope = ScopeName::try_from("scope2").unwrap();
+    t.db()
+      .create_scope(
+        &t.user2.user.id,
+        false,
+        &target_scope,
+        t.user2.user.id,
+        &ScopeDescription::default(),
+      )
+      .await
+      .unwrap();
+
+    let token1 = t.user1.token.clone();
+    let token2 = t.user2.token.clone();
+    let token3 = t.user3.token.clone();
+
+    t.http()
+      .post("/api/scopes/scope/packages/foo/transfer")
+      .body_json(json!({ "targetScope": "scope2" }))
+      .token(Some(&token1))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPackageTransfer>()
+      .await;
+
+    t.http()
+      .get("/api/scopes/scope/packages/foo/transfer")
+      .token(Some(&token3))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "packageTransferNotFound")
+      .await;
+    t.http()
+      .get("/api/scopes/scope/packages/foo/transfer")
+      .token(Some(&token2))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPackageTransfer>()
+      .await;
+
+    t.http()
+      .delete("/api/scopes/scope/packages/foo/transfer")
+      .token(Some(&token2))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok_no_content()
+      .await;
+    t.http()
+      .post("/api/scopes/scope/packages/foo/transfer/accept")
+      .token(Some(&token2))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "packageTransferNotFound")
+      .await;
+  }
+}
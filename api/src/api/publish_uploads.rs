@@ -0,0 +1,484 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+//! Resumable publish uploads. Instead of uploading the tarball of a publish in
+//! a single request, a client can create an upload, append the tarball to it
+//! in chunks, and then commit it, which creates the publishing task just like
+//! a regular publish. If appending a chunk fails, the client gets the upload to
+//! find out how much of the tarball was received, and continues from there.
+
+use chrono::Duration;
+use chrono::Utc;
+use hyper::Body;
+use hyper::Request;
+use hyper::body::HttpBody;
+use routerify::prelude::RequestExt;
+use routerify_query::RequestQueryExt;
+use sha2::Digest;
+use tracing::Span;
+use tracing::error;
+use tracing::field;
+use tracing::instrument;
+
+use crate::db::Database;
+use crate::db::NewPublishUpload;
+use crate::db::NewPublishingTask;
+use crate::db::PublishUpload;
+use crate::iam::PublishAccessRestriction;
+use crate::iam::ReqIamExt;
+use crate::publish_limits::PublishLimits;
+use crate::s3::Buckets;
+use crate::s3::ContentEncoding;
+use crate::s3::S3UploadOptions;
+use crate::s3::UploadTaskBody;
+use crate::tarball::bucket_tarball_path;
+use crate::tarball::publish_upload_chunk_path;
+use crate::tarball::publish_upload_path;
+use crate::util::ApiResult;
+use crate::util::RequestIdExt;
+
+use super::ApiError;
+use super::ApiPublishUpload;
+use super::ApiPublishingTask;
+use super::package::check_package_publishable;
+use super::package::check_publish_tarball_hash;
+use super::package::config_file_query;
+use super::package::enqueue_publishing_task;
+use super::package::start_publishing_task;
+
+/// The largest chunk that can be appended to an upload at once.
+pub const MAX_PUBLISH_UPLOAD_CHUNK_SIZE: u64 = 5 * 1024 * 1024; // 5mb
+
+/// How long an upload can be appended to and committed after it was created.
+const PUBLISH_UPLOAD_TTL_HOURS: i64 = 24;
+
+/// Gets the upload in the path, and checks that the user may publish the
+/// version it is for, and created the upload. Uploads of other users are not
+/// found.
+async fn get_upload(
+  req: &Request<Body>,
+) -> Result<(PublishUpload, PublishAccessRestriction), ApiError> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  let version = req.param_version()?;
+  let id = req.param_uuid("upload")?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+  Span::current().record("version", field::display(&version));
+  Span::current().record("upload", field::display(&id));
+
+  let iam = req.iam();
+  let (access_restriction, user_id) =
+    iam.check_publish_access(&scope, &package, &version).await?;
+
+  let db = req.data::<Database>().unwrap();
+  let upload = db
+    .get_publish_upload(id)
+    .await?
+    .ok_or(ApiError::PublishUploadNotFound)?;
+  if upload.package_scope != scope
+    || upload.package_name != package
+    || upload.package_version != version
+    || upload.user_id != user_id
+  {
+    return Err(ApiError::PublishUploadNotFound);
+  }
+  Ok((upload, access_restriction))
+}
+
+#[instrument(
+  name = "POST /api/scopes/:scope/packages/:package/versions/:version/uploads",
+  skip(req),
+  err,
+  fields(scope, package, version)
+)]
+pub async fn create_handler(req: Request<Body>) -> ApiResult<ApiPublishUpload> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  let version = req.param_version()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+  Span::current().record("version", field::display(&version));
+  let config_file = config_file_query(&req)?;
+
+  let iam = req.iam();
+  let (_, user_id) =
+    iam.check_publish_access(&scope, &package, &version).await?;
+
+  let db = req.data::<Database>().unwrap();
+  check_package_publishable(db, &scope, &package).await?;
+
+  let upload = db
+    .create_publish_upload(NewPublishUpload {
+      user_id,
+      package_scope: &scope,
+      package_name: &package,
+      package_version: &version,
+      config_file: &config_file,
+      expires_at: Utc::now() + Duration::hours(PUBLISH_UPLOAD_TTL_HOURS),
+    })
+    .await?;
+
+  Ok(upload.into())
+}
+
+#[instrument(
+  name = "GET /api/scopes/:scope/packages/:package/versions/:version/uploads/:upload",
+  skip(req),
+  err,
+  fields(scope, package, version, upload)
+)]
+pub async fn get_handler(req: Request<Body>) -> ApiResult<ApiPublishUpload> {
+  let (upload, _) = get_upload(&req).await?;
+  Ok(upload.into())
+}
+
+/// Appends the body to the upload. The `offset` query parameter must be the
+/// number of bytes uploaded so far, so that a chunk that is sent again after
+/// its response was lost is not appended twice.
+#[instrument(
+  name = "POST /api/scopes/:scope/packages/:package/versions/:version/uploads/:upload/chunks",
+  skip(req),
+  err,
+  fields(scope, package, version, upload, offset)
+)]
+pub async fn append_chunk_handler(
+  mut req: Request<Body>,
+) -> ApiResult<ApiPublishUpload> {
+  let (upload, _) = get_upload(&req).await?;
+
+  let offset = req
+    .query("offset")
+    .ok_or_else(|| ApiError::MalformedRequest {
+      msg: "Missing query parameter 'offset'".into(),
+    })?
+    .parse::<i64>()
+    .map_err(|_| ApiError::MalformedRequest {
+      msg: "offset must be a number of bytes".into(),
+    })?;
+  Span::current().record("offset", offset);
+  if offset != upload.size {
+    return Err(ApiError::PublishUploadOffsetMismatch {
+      offset: upload.size,
+    });
+  }
+
+  let body = req.body_mut();
+  if let Some(size) = body.size_hint().upper()
+    && size > MAX_PUBLISH_UPLOAD_CHUNK_SIZE
+  {
+    return Err(ApiError::PublishUploadChunkTooLarge {
+      max_size: MAX_PUBLISH_UPLOAD_CHUNK_SIZE,
+    });
+  }
+  let mut chunk = Vec::new();
+  while let Some(data) = body.data().await {
+    chunk.extend_from_slice(&data.map_err(anyhow::Error::from)?);
+    if chunk.len() as u64 > MAX_PUBLISH_UPLOAD_CHUNK_SIZE {
+      return Err(ApiError::PublishUploadChunkTooLarge {
+        max_size: MAX_PUBLISH_UPLOAD_CHUNK_SIZE,
+      });
+    }
+  }
+  if chunk.is_empty() {
+    return Err(ApiError::MalformedRequest {
+      msg: "the chunk must not be empty".into(),
+    });
+  }
+
+  let db = req.data::<Database>().unwrap();
+  let limits = PublishLimits::for_scope(db, &upload.package_scope).await?;
+  let size = upload.size as u64 + chunk.len() as u64;
+  if size > limits.max_tarball_size {
+    return Err(ApiError::TarballSizeLimitExceeded {
+      size,
+      max_size: limits.max_tarball_size,
+    });
+  }
+
+  let hash = format!("{:x}", sha2::Sha256::digest(&chunk));
+  let chunk_size = chunk.len() as i64;
+  let buckets = req.data::<Buckets>().unwrap();
+  buckets
+    .publishing_bucket
+    .upload(
+      publish_upload_chunk_path(upload.id, &hash).into(),
+      UploadTaskBody::Bytes(chunk.into()),
+      S3UploadOptions {
+        content_type: Some("application/octet-stream".into()),
+        cache_control: None,
+        content_encoding: ContentEncoding::Identity,
+      },
+    )
+    .await?;
+
+  // Another request may have appended a chunk in the meantime.
+  let upload = match db
+    .append_publish_upload_chunk(upload.id, offset, &hash, chunk_size)
+    .await?
+  {
+    Some(upload) => upload,
+    None => {
+      let upload = db
+        .get_publish_upload(upload.id)
+        .await?
+        .ok_or(ApiError::PublishUploadNotFound)?;
+      return Err(ApiError::PublishUploadOffsetMismatch {
+        offset: upload.size,
+      });
+    }
+  };
+
+  Ok(upload.into())
+}
+
+/// Assembles the uploaded chunks into the tarball of the publish, and creates
+/// the publishing task for it.
+#[instrument(
+  name = "POST /api/scopes/:scope/packages/:package/versions/:version/uploads/:upload/commit",
+  skip(req),
+  err,
+  fields(scope, package, version, upload)
+)]
+pub async fn commit_handler(
+  req: Request<Body>,
+) -> ApiResult<ApiPublishingTask> {
+  let (upload, access_restriction) = get_upload(&req).await?;
+  if upload.chunks.is_empty() {
+    return Err(ApiError::PublishUploadEmpty);
+  }
+
+  let db = req.data::<Database>().unwrap();
+  let buckets = req.data::<Buckets>().unwrap();
+  let quota =
+    check_package_publishable(db, &upload.package_scope, &upload.package_name)
+      .await?;
+  quota.check(upload.size as u64)?;
+
+  let tarball = download_upload_tarball(buckets, &upload).await?;
+
+  let hash = format!("sha256-{:02x}", sha2::Sha256::digest(&tarball));
+  check_publish_tarball_hash(&access_restriction, &hash)?;
+
+  let (publishing_task, user) = start_publishing_task(
+    db,
+    NewPublishingTask {
+      user_id: upload.user_id,
+      package_scope: &upload.package_scope,
+      package_name: &upload.package_name,
+      package_version: &upload.package_version,
+      config_file: &upload.config_file,
+    },
+  )
+  .await?;
+
+  buckets
+    .publishing_bucket
+    .upload(
+      bucket_tarball_path(publishing_task.id).into(),
+      UploadTaskBody::Bytes(tarball.into()),
+      S3UploadOptions {
+        content_type: Some("application/x-tar".into()),
+        cache_control: None,
+        content_encoding: ContentEncoding::Gzip,
+      },
+    )
+    .await?;
+
+  enqueue_publishing_task(&req, publishing_task.id, &hash).await?;
+
+  delete_committed_upload(db, buckets, &upload).await?;
+
+  Ok((publishing_task, user).into())
+}
+
+/// Assembles the uploaded chunks of an upload into its tarball.
+pub(super) async fn download_upload_tarball(
+  buckets: &Buckets,
+  upload: &PublishUpload,
+) -> Result<Vec<u8>, ApiError> {
+  let mut tarball = Vec::with_capacity(upload.size as usize);
+  for hash in &upload.chunks {
+    let chunk = buckets
+      .publishing_bucket
+      .download(publish_upload_chunk_path(upload.id, hash).into())
+      .await?
+      .ok_or_else(|| {
+        error!("chunk {hash} of publish upload {} is missing", upload.id);
+        ApiError::InternalServerError
+      })?;
+    tarball.extend_from_slice(&chunk);
+  }
+  Ok(tarball)
+}
+
+/// Deletes an upload once its tarball was stored for a publishing task.
+pub(super) async fn delete_committed_upload(
+  db: &Database,
+  buckets: &Buckets,
+  upload: &PublishUpload,
+) -> Result<(), ApiError> {
+  // If the chunks can't be deleted now, the upload is left for the
+  // `clean_publish_uploads` task to delete once it expires.
+  match buckets
+    .publishing_bucket
+    .delete_directory(publish_upload_path(upload.id).into())
+    .await
+  {
+    Ok(()) => db.delete_publish_upload(upload.id).await?,
+    Err(err) => {
+      error!(
+        "failed to delete chunks of publish upload {}: {err}",
+        upload.id
+      )
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::Body;
+  use hyper::StatusCode;
+
+  use crate::api::ApiPublishUpload;
+  use crate::api::ApiPublishingTask;
+  use crate::db::NewScopeMember;
+  use crate::ids::PackageName;
+  use crate::publish::tests::create_mock_tarball;
+  use crate::tarball::bucket_tarball_path;
+  use crate::tarball::publish_upload_path;
+  use crate::util::test::ApiResultExt;
+  use crate::util::test::TestSetup;
+
+  #[tokio::test]
+  async fn chunked_upload() {
+    let mut t = TestSetup::new().await;
+    let name = PackageName::new("foo".to_owned()).unwrap();
+    t.db().create_package(&t.scope.scope, &name).await.unwrap();
+    let tarball = create_mock_tarball("ok");
+    let (first, second) = tarball.split_at(tarball.len() / 2);
+
+    let base = "/api/scopes/scope/packages/foo/versions/1.2.3/uploads";
+    let upload = t
+      .http()
+      .post(format!("{base}?config=/jsr.json"))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPublishUpload>()
+      .await;
+    assert_eq!(upload.offset, 0);
+    let base = format!("{base}/{}", upload.id);
+
+    t.http()
+      .post(format!("{base}/commit"))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "publishUploadEmpty")
+      .await;
+
+    let upload = t
+      .http()
+      .post(format!("{base}/chunks?offset=0"))
+      .body(Body::from(first.to_vec()))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPublishUpload>()
+      .await;
+    assert_eq!(upload.offset, first.len() as i64);
+
+    // Sending the first chunk again does not append it twice.
+    t.http()
+      .post(format!("{base}/chunks?offset=0"))
+      .body(Body::from(first.to_vec()))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::CONFLICT, "publishUploadOffsetMismatch")
+      .await;
+
+    let upload = t
+      .http()
+      .get(&base)
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPublishUpload>()
+      .await;
+    assert_eq!(upload.offset, first.len() as i64);
+
+    // Other members of the scope can not see or append to the upload.
+    t.db()
+      .add_user_to_scope(NewScopeMember {
+        scope: &t.scope.scope,
+        user_id: t.user2.user.id,
+        is_admin: false,
+      })
+      .await
+      .unwrap();
+    let token = t.user2.token.clone();
+    t.http()
+      .get(&base)
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "publishUploadNotFound")
+      .await;
+    t.http()
+      .post(format!("{base}/chunks?offset={}", upload.offset))
+      .body(Body::from(second.to_vec()))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "publishUploadNotFound")
+      .await;
+
+    t.http()
+      .post(format!("{base}/chunks?offset={}", upload.offset))
+      .body(Body::from(second.to_vec()))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPublishUpload>()
+      .await;
+
+    let task = t
+      .http()
+      .post(format!("{base}/commit"))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPublishingTask>()
+      .await;
+    assert_eq!(task.package_name, name);
+
+    let uploaded = t
+      .buckets
+      .publishing_bucket
+      .download(bucket_tarball_path(task.id).into())
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(uploaded, tarball);
+
+    // The upload is deleted once it is committed.
+    let chunks = t
+      .buckets
+      .publishing_bucket
+      .bucket
+      .list(&publish_upload_path(upload.id))
+      .await
+      .unwrap();
+    assert!(chunks.is_empty());
+    t.http()
+      .get(&base)
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "publishUploadNotFound")
+      .await;
+  }
+}
@@ -8,12 +8,19 @@ use tracing::field;
 use tracing::instrument;
 
 use crate::db::Database;
+use crate::db::PublishingTaskStatus;
+use crate::iam::ReqIamExt;
+use crate::s3::Buckets;
+use crate::tarball::bucket_tarball_path;
 use crate::util;
 use crate::util::ApiResult;
 use crate::util::RequestIdExt;
 
 use super::ApiError;
 use super::ApiPublishingTask;
+use super::package::check_package_publishable;
+use super::package::check_publish_tarball_hash;
+use super::package::dispatch_publishing_task;
 
 pub fn publishing_task_router() -> Router<Body, ApiError> {
   Router::builder()
@@ -23,6 +30,10 @@ pub fn publishing_task_router() -> Router<Body, ApiError> {
       "/:publishing_task_id",
       util::no_store(util::json(get_handler)),
     )
+    .post(
+      "/:publishing_task_id/retry",
+      util::auth(util::json(retry_handler)),
+    )
     .build()
     .unwrap()
 }
@@ -44,5 +55,197 @@ pub async fn get_handler(req: Request<Body>) -> ApiResult<ApiPublishingTask> {
     .await?
     .ok_or(ApiError::PublishNotFound)?;
 
-  Ok(publishing_task.into())
+  let mut publishing_task = ApiPublishingTask::from(publishing_task);
+  publishing_task.preview_diff = db
+    .get_publishing_task_preview_diff(publishing_task.id)
+    .await?;
+
+  Ok(publishing_task)
+}
+
+/// Processes a publishing task again from the tarball that was uploaded for
+/// it, so that a publish that failed for a transient reason does not need to
+/// be started over. The task resumes from the last stage it completed: a
+/// failed task is analyzed again, reusing the module files that were already
+/// stored, and a task whose version was already created only has its package
+/// manifests uploaded.
+#[instrument(
+  name = "POST /api/publishing_tasks/:publishing_task_id/retry",
+  skip(req),
+  fields(publishing_task_id)
+)]
+pub async fn retry_handler(req: Request<Body>) -> ApiResult<ApiPublishingTask> {
+  let publishing_task_id = req.param_uuid("publishing_task_id")?;
+  Span::current()
+    .record("publishing_task_id", field::display(&publishing_task_id));
+
+  let db = req.data::<Database>().unwrap().clone();
+  let buckets = req.data::<Buckets>().unwrap();
+
+  let (publishing_task, user) = db
+    .get_publishing_task(publishing_task_id)
+    .await?
+    .ok_or(ApiError::PublishNotFound)?;
+
+  let iam = req.iam();
+  let (access_restriction, _) = iam
+    .check_publish_access(
+      &publishing_task.package_scope,
+      &publishing_task.package_name,
+      &publishing_task.package_version,
+    )
+    .await?;
+  if access_restriction.tarball_hash.is_some() {
+    let hash = db
+      .get_publishing_task_tarball_hash(publishing_task.id)
+      .await?
+      .ok_or(ApiError::MissingPermission)?;
+    check_publish_tarball_hash(&access_restriction, &hash)?;
+  }
+  check_package_publishable(
+    &db,
+    &publishing_task.package_scope,
+    &publishing_task.package_name,
+  )
+  .await?;
+
+  let publishing_task = match publishing_task.status {
+    PublishingTaskStatus::Failure => {
+      let tarball_path = bucket_tarball_path(publishing_task.id);
+      let stored = buckets
+        .publishing_bucket
+        .list(tarball_path.as_str().into())
+        .await?;
+      if !stored.contains(&tarball_path) {
+        return Err(ApiError::PublishTarballNotFound);
+      }
+      db.retry_failed_publishing_task(publishing_task.id)
+        .await?
+        .ok_or(ApiError::PublishNotRetryable)?
+    }
+    PublishingTaskStatus::Processed => publishing_task,
+    PublishingTaskStatus::Pending
+    | PublishingTaskStatus::Processing
+    | PublishingTaskStatus::Success => {
+      return Err(ApiError::PublishNotRetryable);
+    }
+  };
+
+  dispatch_publishing_task(&req, publishing_task.id).await?;
+
+  Ok((publishing_task, user).into())
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::StatusCode;
+
+  use crate::api::ApiPublishingTask;
+  use crate::api::ApiPublishingTaskStatus;
+  use crate::db::PublishingTaskStatus;
+  use crate::ids::PackageName;
+  use crate::ids::Version;
+  use crate::publish::tests::create_mock_tarball;
+  use crate::publish::tests::process_tarball_setup;
+  use crate::publish::tests::process_tarball_setup2;
+  use crate::tarball::bucket_tarball_path;
+  use crate::util::test::ApiResultExt;
+  use crate::util::test::TestSetup;
+
+  #[tokio::test]
+  async fn retry() {
+    let mut t = TestSetup::new().await;
+    let failed =
+      process_tarball_setup(&t, create_mock_tarball("no_exports")).await;
+    assert_eq!(failed.status, PublishingTaskStatus::Failure);
+
+    let token = t.user1.token.clone();
+    let task = t
+      .http()
+      .post(format!("/api/publishing_tasks/{}/retry", failed.id))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPublishingTask>()
+      .await;
+    assert_eq!(task.id, failed.id);
+    assert_eq!(task.status, ApiPublishingTaskStatus::Pending);
+    assert!(task.error.is_none());
+
+    // The tarball is analyzed again, and fails the same way.
+    let mut task = task;
+    for _ in 0..50 {
+      if task.status == ApiPublishingTaskStatus::Failure {
+        break;
+      }
+      tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+      task = t
+        .http()
+        .get(format!("/api/publishing_tasks/{}", task.id))
+        .call()
+        .await
+        .unwrap()
+        .expect_ok::<ApiPublishingTask>()
+        .await;
+    }
+    assert_eq!(task.status, ApiPublishingTaskStatus::Failure);
+    assert_eq!(task.error.unwrap().code, "configFileExportsInvalid");
+
+    let other_token = t.user2.token.clone();
+    t.http()
+      .post(format!("/api/publishing_tasks/{}/retry", failed.id))
+      .token(Some(&other_token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err(StatusCode::FORBIDDEN)
+      .await;
+
+    // Once the version is published, neither task can be retried.
+    let published = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(published.status, PublishingTaskStatus::Success);
+    for id in [failed.id, published.id] {
+      t.http()
+        .post(format!("/api/publishing_tasks/{id}/retry"))
+        .token(Some(&token))
+        .call()
+        .await
+        .unwrap()
+        .expect_err_code(StatusCode::CONFLICT, "publishNotRetryable")
+        .await;
+    }
+
+    let failed = process_tarball_setup2(
+      &t,
+      create_mock_tarball("no_exports"),
+      &PackageName::try_from("foo").unwrap(),
+      &Version::try_from("1.2.4").unwrap(),
+      false,
+    )
+    .await;
+    assert_eq!(failed.status, PublishingTaskStatus::Failure);
+    t.buckets
+      .publishing_bucket
+      .delete_file(bucket_tarball_path(failed.id).into())
+      .await
+      .unwrap();
+    t.http()
+      .post(format!("/api/publishing_tasks/{}/retry", failed.id))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "publishTarballNotFound")
+      .await;
+
+    t.http()
+      .post(format!("/api/publishing_tasks/{}/retry", uuid::Uuid::nil()))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "publishNotFound")
+      .await;
+  }
 }
@@ -0,0 +1,160 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+use hyper::Body;
+use hyper::Request;
+use routerify::Router;
+use routerify::prelude::RequestExt;
+use routerify_query::RequestQueryExt;
+use tracing::instrument;
+
+use crate::db::Database;
+use crate::ids::ScopeName;
+use crate::publish_limits::PublishLimits;
+use crate::util;
+use crate::util::ApiResult;
+
+use super::ApiError;
+use super::ApiPublishLimits;
+
+pub fn registry_router() -> Router<Body, ApiError> {
+  Router::builder()
+    .get("/limits", util::json(limits_handler))
+    .build()
+    .unwrap()
+}
+
+/// The limits that publishes are checked against: the registry-wide defaults,
+/// or with the `scope` query parameter, the limits that apply to publishes to
+/// that scope.
+#[instrument(name = "GET /api/registry/limits", skip(req))]
+pub async fn limits_handler(req: Request<Body>) -> ApiResult<ApiPublishLimits> {
+  let Some(scope) = req.query("scope") else {
+    return Ok(PublishLimits::registry_defaults().into());
+  };
+  let scope = ScopeName::try_from(scope.as_str()).map_err(|err| {
+    ApiError::MalformedRequest {
+      msg: format!("invalid scope name: {err}").into(),
+    }
+  })?;
+
+  let db = req.data::<Database>().unwrap();
+  db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?;
+  let limits = PublishLimits::for_scope(db, &scope).await?;
+
+  Ok(limits.into())
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::StatusCode;
+  use serde_json::json;
+
+  use crate::api::ApiPublishLimits;
+  use crate::api::ApiScopePublishLimits;
+  use crate::db::PublishingTaskStatus;
+  use crate::publish::tests::create_mock_tarball;
+  use crate::publish::tests::process_tarball_setup;
+  use crate::publish_limits::PublishLimits;
+  use crate::util::test::ApiResultExt;
+  use crate::util::test::TestSetup;
+
+  #[tokio::test]
+  async fn scope_publish_limits() {
+    let mut t = TestSetup::new().await;
+    let staff_token = t.staff_user.token.clone();
+
+    let limits = t
+      .http()
+      .get("/api/registry/limits")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPublishLimits>()
+      .await;
+    let defaults = PublishLimits::default();
+    assert_eq!(limits.max_tarball_size, defaults.max_tarball_size);
+    assert_eq!(limits.max_file_count, defaults.max_file_count);
+    assert_eq!(limits.max_module_count, defaults.max_module_count);
+
+    // only staff can override limits
+    t.http()
+      .put("/api/admin/publish_limits/scopes/scope")
+      .body_json(json!({ "maxModuleCount": 1 }))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::FORBIDDEN, "actorNotAuthorized")
+      .await;
+    t.http()
+      .put("/api/admin/publish_limits/scopes/scope")
+      .body_json(json!({ "maxModuleCount": 0 }))
+      .token(Some(&staff_token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+
+    let overrides = t
+      .http()
+      .put("/api/admin/publish_limits/scopes/scope")
+      .body_json(json!({
+        "maxModuleCount": 1,
+        "maxFileCount": 20,
+        "reason": "testing",
+      }))
+      .token(Some(&staff_token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiScopePublishLimits>()
+      .await;
+    assert_eq!(overrides.max_module_count, Some(1));
+    assert_eq!(overrides.max_tarball_size, None);
+
+    let limits = t
+      .http()
+      .get("/api/registry/limits?scope=scope")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPublishLimits>()
+      .await;
+    assert_eq!(limits.max_module_count, 1);
+    assert_eq!(limits.max_file_count, 20);
+    assert_eq!(limits.max_tarball_size, defaults.max_tarball_size);
+
+    // the exports of the package import more than one module
+    let task =
+      process_tarball_setup(&t, create_mock_tarball("module_graph")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Failure, "{task:#?}");
+    assert_eq!(task.error.unwrap().code, "tooManyModules");
+
+    t.http()
+      .delete("/api/admin/publish_limits/scopes/scope")
+      .token(Some(&staff_token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok_no_content()
+      .await;
+    t.http()
+      .delete("/api/admin/publish_limits/scopes/scope")
+      .token(Some(&staff_token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "publishLimitsOverrideNotFound")
+      .await;
+
+    let limits = t
+      .http()
+      .get("/api/registry/limits?scope=scope")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPublishLimits>()
+      .await;
+    assert_eq!(limits.max_module_count, defaults.max_module_count);
+  }
+}
@@ -0,0 +1,155 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+use hyper::Body;
+use hyper::Request;
+use routerify::prelude::RequestExt;
+use tracing::Span;
+use tracing::field;
+use tracing::instrument;
+
+use crate::db::Database;
+use crate::docs::MarkdownRenderOptions;
+use crate::iam::ReqIamExt;
+use crate::util::ApiResult;
+use crate::util::decode_json;
+
+use super::ApiError;
+use super::ApiRenderMarkdownRequest;
+use super::ApiRenderMarkdownResponse;
+
+/// Markdown documents larger than this are rejected, as rendering happens on
+/// request.
+const MAX_RENDER_MARKDOWN_SIZE: usize = 1024 * 1024;
+
+/// Renders markdown the way the readme of a published package is rendered,
+/// so the publish flow can preview it. Raw HTML is escaped unless the markdown
+/// settings of the given scope allow it.
+#[instrument(name = "POST /api/render/markdown", skip(req), fields(scope))]
+pub async fn render_markdown_handler(
+  mut req: Request<Body>,
+) -> ApiResult<ApiRenderMarkdownResponse> {
+  let ApiRenderMarkdownRequest { markdown, scope } =
+    decode_json(&mut req).await?;
+
+  let iam = req.iam();
+  iam.check_current_user_access()?;
+
+  if markdown.len() > MAX_RENDER_MARKDOWN_SIZE {
+    return Err(ApiError::MalformedRequest {
+      msg: format!("markdown must be at most {MAX_RENDER_MARKDOWN_SIZE} bytes")
+        .into(),
+    });
+  }
+
+  let mut render_options = MarkdownRenderOptions::default();
+  if let Some(scope) = scope {
+    Span::current().record("scope", field::display(&scope));
+    let db = req.data::<Database>().unwrap();
+    db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?;
+    if let Some(settings) = db.get_scope_markdown_settings(&scope).await? {
+      render_options.allow_html = settings.allow_html;
+      render_options.admonitions = settings.admonitions;
+    }
+  }
+
+  let html = tokio::task::spawn_blocking(move || {
+    crate::docs::render_markdown(&markdown, &render_options)
+  })
+  .await
+  .map_err(|_| ApiError::InternalServerError)?;
+
+  Ok(ApiRenderMarkdownResponse { html })
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::StatusCode;
+  use serde_json::json;
+
+  use crate::api::ApiFullScope;
+  use crate::api::ApiRenderMarkdownResponse;
+  use crate::util::test::ApiResultExt;
+  use crate::util::test::TestSetup;
+
+  #[tokio::test]
+  async fn render_markdown() {
+    let mut t = TestSetup::new().await;
+    let token = t.user1.token.clone();
+
+    let markdown = "# Hello\n\n<b onclick=\"x()\">bold</b>\n\n\
+      > [!NOTE]\n> Note this.\n\n> [!DANGER]\n> Careful.\n";
+
+    let res = t
+      .http()
+      .post("/api/render/markdown")
+      .body_json(json!({ "markdown": markdown }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiRenderMarkdownResponse>()
+      .await;
+    assert!(res.html.contains("Hello</h1>"), "{}", res.html);
+    assert!(res.html.contains("&lt;b"), "{}", res.html);
+    assert!(!res.html.contains("<b>"), "{}", res.html);
+    assert!(
+      res.html.contains(r#"class="alert alert-note""#),
+      "{}",
+      res.html
+    );
+    assert!(!res.html.contains("alert-danger"), "{}", res.html);
+
+    // Scope admins can allow raw HTML and additional alerts.
+    t.http()
+      .patch("/api/scopes/scope")
+      .body_json(json!({
+        "markdownSettings": { "allowHtml": true, "admonitions": ["danger"] },
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiFullScope>()
+      .await;
+
+    let res = t
+      .http()
+      .post("/api/render/markdown")
+      .body_json(json!({ "markdown": markdown, "scope": "scope" }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiRenderMarkdownResponse>()
+      .await;
+    assert!(res.html.contains("<b>bold</b>"), "{}", res.html);
+    assert!(!res.html.contains("onclick"), "{}", res.html);
+    assert!(
+      res.html.contains(r#"class="alert alert-danger""#),
+      "{}",
+      res.html
+    );
+
+    let mut resp = t
+      .http()
+      .post("/api/render/markdown")
+      .body_json(json!({ "markdown": markdown, "scope": "missing" }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::NOT_FOUND, "scopeNotFound")
+      .await;
+
+    let mut resp = t
+      .http()
+      .post("/api/render/markdown")
+      .body_json(json!({ "markdown": markdown }))
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::UNAUTHORIZED, "missingAuthentication")
+      .await;
+  }
+}
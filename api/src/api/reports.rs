@@ -0,0 +1,234 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+use hyper::Body;
+use hyper::Request;
+use routerify::Router;
+use routerify::prelude::RequestExt;
+use tracing::instrument;
+
+use crate::db::Database;
+use crate::db::PackageVisibility;
+use crate::events;
+use crate::events::DomainEvent;
+use crate::iam::ReqIamExt;
+use crate::util;
+use crate::util::ApiResult;
+use crate::util::decode_json;
+
+use super::ApiAbuseReport;
+use super::ApiCreateAbuseReportRequest;
+use super::ApiError;
+
+/// The maximum length of the description of an abuse report, in characters.
+const MAX_ABUSE_REPORT_DESCRIPTION_LENGTH: usize = 5000;
+
+pub fn reports_router() -> Router<Body, ApiError> {
+  Router::builder()
+    .post("/", util::auth(util::json(post_handler)))
+    .build()
+    .unwrap()
+}
+
+#[instrument(name = "POST /api/reports", skip(req))]
+pub async fn post_handler(mut req: Request<Body>) -> ApiResult<ApiAbuseReport> {
+  let ApiCreateAbuseReportRequest {
+    scope,
+    package,
+    version,
+    reason,
+    description,
+  } = decode_json(&mut req).await?;
+
+  let iam = req.iam();
+  let user = iam.check_current_user_access()?;
+
+  let description = description.trim();
+  if description.is_empty() {
+    return Err(ApiError::MalformedRequest {
+      msg: "missing 'description' parameter".into(),
+    });
+  }
+  if description.chars().count() > MAX_ABUSE_REPORT_DESCRIPTION_LENGTH {
+    return Err(ApiError::MalformedRequest {
+      msg: format!(
+        "description must be at most {MAX_ABUSE_REPORT_DESCRIPTION_LENGTH} characters"
+      )
+      .into(),
+    });
+  }
+
+  let db = req.data::<Database>().unwrap();
+  let (existing, _, _) = db
+    .get_package(&scope, &package)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+  // Private packages can only be reported by those who can see them, so this
+  // route can't be used to discover them.
+  if existing.visibility == PackageVisibility::Private {
+    iam.check_private_package_read_access(&scope).await?;
+  }
+  if let Some(version) = &version {
+    db.get_package_version(&scope, &package, version)
+      .await?
+      .ok_or(ApiError::PackageVersionNotFound)?;
+  }
+
+  let report = db
+    .create_abuse_report(
+      user.id,
+      &scope,
+      &package,
+      version.as_ref(),
+      reason,
+      description,
+    )
+    .await?;
+
+  events::emit(
+    db,
+    DomainEvent::PackageReported {
+      report_id: report.id,
+      scope,
+      package,
+      version,
+      reason,
+      user_id: user.id,
+    },
+  )
+  .await;
+
+  Ok(report.into())
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::StatusCode;
+  use serde_json::json;
+
+  use crate::api::ApiAbuseReport;
+  use crate::api::ApiPackage;
+  use crate::db::AbuseReportReason;
+  use crate::db::AbuseReportStatus;
+  use crate::publish::tests::create_mock_tarball;
+  use crate::publish::tests::process_tarball_setup;
+  use crate::util::test::ApiResultExt;
+  use crate::util::test::TestSetup;
+
+  #[tokio::test]
+  async fn report_package() {
+    let mut t = TestSetup::new().await;
+    process_tarball_setup(&t, create_mock_tarball("ok")).await;
+
+    let token = t.user2.token.clone();
+    let report = t
+      .http()
+      .post("/api/reports")
+      .body_json(json!({
+        "scope": "scope",
+        "package": "foo",
+        "version": "1.2.3",
+        "reason": "malware",
+        "description": "  Sends environment variables to a remote server.  ",
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiAbuseReport>()
+      .await;
+    assert_eq!(report.reason, AbuseReportReason::Malware);
+    assert_eq!(report.status, AbuseReportStatus::Reported);
+    assert_eq!(report.reporter_id, Some(t.user2.user.id));
+    assert_eq!(
+      report.description,
+      "Sends environment variables to a remote server."
+    );
+
+    t.http()
+      .post("/api/reports")
+      .body_json(json!({
+        "scope": "scope",
+        "package": "foo",
+        "reason": "spam",
+        "description": "  ",
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+
+    t.http()
+      .post("/api/reports")
+      .body_json(json!({
+        "scope": "scope",
+        "package": "foo",
+        "version": "9.9.9",
+        "reason": "spam",
+        "description": "spam",
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "packageVersionNotFound")
+      .await;
+
+    t.http()
+      .post("/api/reports")
+      .body_json(json!({
+        "scope": "scope",
+        "package": "foo",
+        "reason": "spam",
+        "description": "spam",
+      }))
+      .token(None)
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::UNAUTHORIZED, "missingAuthentication")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn report_private_package() {
+    let mut t = TestSetup::new().await;
+    process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    t.http()
+      .patch("/api/scopes/scope/packages/foo")
+      .body_json(json!({ "visibility": "private" }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPackage>()
+      .await;
+
+    let body = json!({
+      "scope": "scope",
+      "package": "foo",
+      "reason": "spam",
+      "description": "spam",
+    });
+
+    let token = t.user2.token.clone();
+    t.http()
+      .post("/api/reports")
+      .body_json(body.clone())
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "packageNotFound")
+      .await;
+
+    t.http()
+      .post("/api/reports")
+      .body_json(body)
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiAbuseReport>()
+      .await;
+  }
+}
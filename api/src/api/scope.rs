@@ -1,12 +1,22 @@
 // Copyright 2024 the JSR authors. All rights reserved. MIT license.
 use std::borrow::Cow;
-use std::sync::OnceLock;
 
 use crate::RegistryUrl;
+use crate::api::feeds::scope_feed_handler;
 use crate::api::package::package_router;
+use crate::api::package_transfers;
+use crate::api::scope_renames;
+use crate::api::teams::teams_router;
+use crate::api::trusted_publishers;
+use crate::api::webhooks::webhooks_router;
+use crate::emails;
 use crate::emails::EmailArgs;
 use crate::emails::EmailSender;
+use crate::emails::NotificationKind;
+use crate::events;
+use crate::events::DomainEvent;
 use crate::iam::ReqIamExt;
+use crate::ids::PackageName;
 use hyper::Body;
 use hyper::Request;
 use hyper::Response;
@@ -17,6 +27,7 @@ use tracing::Span;
 use tracing::error;
 use tracing::field;
 use tracing::instrument;
+use uuid::Uuid;
 
 use super::errors::ApiError;
 use super::errors::map_unique_violation;
@@ -33,15 +44,26 @@ use crate::util::decode_json;
 pub fn scope_router() -> Router<Body, ApiError> {
   Router::builder()
     .scope("/:scope/packages", package_router())
+    .scope("/:scope/webhooks", webhooks_router())
+    .scope("/:scope/teams", teams_router())
     .post("/", util::auth(util::json(create_handler)))
     .get(
       // Cache-busted on package publish/create/delete via the scope aggregates
       // in `package_api_cache_urls` / `scope_api_cache_urls`.
       "/:scope",
-      util::cache(CacheDuration::ONE_DAY, util::json(get_handler)),
+      util::etag(util::cache(CacheDuration::ONE_DAY, util::json(get_handler))),
     )
     .patch("/:scope", util::auth(util::json(update_handler)))
+    .get("/:scope/usage", util::auth(util::json(get_usage_handler)))
     .delete("/:scope", util::auth(delete_handler))
+    .get(
+      "/:scope/feed.xml",
+      util::cache_shared(CacheDuration::FIVE_MINUTES, scope_feed_handler),
+    )
+    .get(
+      "/:scope/markdown_settings",
+      util::json(get_markdown_settings_handler),
+    )
     .get(
       "/:scope/members",
       util::cache(CacheDuration::ONE_HOUR, util::json(list_members_handler)),
@@ -55,6 +77,10 @@ pub fn scope_router() -> Router<Body, ApiError> {
       util::auth(util::json(update_member_handler)),
     )
     .delete("/:scope/members/:member", util::auth(delete_member_handler))
+    .post(
+      "/:scope/members/:member/lift_cooldown",
+      util::auth(lift_member_cooldown_handler),
+    )
     .get(
       "/:scope/invites",
       util::auth(util::json(list_invites_handler)),
@@ -63,13 +89,43 @@ pub fn scope_router() -> Router<Body, ApiError> {
       "/:scope/invites/:user_id",
       util::auth(delete_invite_handler),
     )
+    .get(
+      "/:scope/incoming_transfers",
+      util::auth(util::json(package_transfers::list_incoming_handler)),
+    )
+    .get(
+      "/:scope/rename",
+      util::auth(util::json(scope_renames::get_handler)),
+    )
+    .post(
+      "/:scope/rename",
+      util::auth(util::json(scope_renames::create_handler)),
+    )
+    .delete("/:scope/rename", util::auth(scope_renames::delete_handler))
+    .delete(
+      "/:scope/tokens",
+      util::auth(util::json(revoke_tokens_handler)),
+    )
+    .get(
+      "/:scope/audit-log",
+      util::auth(util::json(list_audit_log_handler)),
+    )
+    .get(
+      "/:scope/oidc_issuers",
+      util::auth(util::json(trusted_publishers::list_oidc_issuers_handler)),
+    )
+    .post(
+      "/:scope/oidc_issuers",
+      util::auth(util::json(trusted_publishers::create_oidc_issuer_handler)),
+    )
+    .delete(
+      "/:scope/oidc_issuers/:id",
+      util::auth(trusted_publishers::delete_oidc_issuer_handler),
+    )
     .build()
     .unwrap()
 }
 
-static RESERVED_SCOPES: OnceLock<std::collections::HashSet<String>> =
-  OnceLock::new();
-
 #[instrument(name = "POST /api/scopes", skip(req), fields(scope))]
 async fn create_handler(mut req: Request<Body>) -> ApiResult<ApiScope> {
   let ApiCreateScopeRequest { scope, description } =
@@ -95,15 +151,22 @@ async fn create_handler(mut req: Request<Body>) -> ApiResult<ApiScope> {
     return Err(ApiError::ScopeNameNotAllowed);
   }
 
-  let reserved_scopes = RESERVED_SCOPES.get_or_init(|| {
-    let reserved_scopes = include_str!("../reserved_scopes.json");
-    serde_json::from_str(reserved_scopes).unwrap()
-  });
-
-  if reserved_scopes.contains(&scope_without_hyphens) {
+  if db
+    .check_is_reserved_name(ReservedNameKind::Scope, &scope_without_hyphens)
+    .await?
+  {
     return Err(ApiError::ScopeNameReserved);
   }
 
+  if let Some(existing) = db.find_confusable_scope(&scope).await? {
+    return Err(ApiError::ScopeNameConfusable { existing });
+  }
+
+  // The old names of renamed scopes keep redirecting to the new names.
+  if db.get_scope_redirect(&scope).await?.is_some() {
+    return Err(ApiError::ScopeAlreadyExists);
+  }
+
   let scope = db
     .create_scope(&user.id, false, &scope, user.id, &description)
     .await
@@ -119,7 +182,7 @@ async fn get_handler(req: Request<Body>) -> ApiResult<ApiScopeOrFullScope> {
 
   let db = req.data::<Database>().unwrap();
   let scope = db
-    .get_scope(&scope_name)
+    .get_scope_cached(&scope_name)
     .await?
     .ok_or(ApiError::ScopeNotFound)?;
 
@@ -136,6 +199,24 @@ async fn get_handler(req: Request<Body>) -> ApiResult<ApiScopeOrFullScope> {
   }
 }
 
+#[instrument(name = "GET /api/scopes/:scope/usage", skip(req), fields(scope))]
+async fn get_usage_handler(req: Request<Body>) -> ApiResult<ApiScopeQuotas> {
+  let scope_name = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope_name));
+
+  let db = req.data::<Database>().unwrap();
+  let scope = db
+    .get_scope(&scope_name)
+    .await?
+    .ok_or(ApiError::ScopeNotFound)?;
+
+  let iam = req.iam();
+  iam.check_scope_write_access(&scope.scope).await?;
+
+  let usage = db.get_scope_usage(&scope.scope).await?;
+  Ok((&scope, &usage).into())
+}
+
 #[instrument(name = "PATCH /api/scopes/:scope", skip(req), fields(scope))]
 async fn update_handler(
   mut req: Request<Body>,
@@ -179,6 +260,22 @@ async fn update_handler(
       db.scope_set_description(&user.id, sudo, &scope, description)
         .await?
     }
+    ApiUpdateScopeRequest::MarkdownSettings(ApiScopeMarkdownSettings {
+      allow_html,
+      admonitions,
+    }) => {
+      let (user, sudo) = iam.check_scope_admin_access(&scope).await?;
+      validate_admonitions(&admonitions)?;
+      db.scope_set_markdown_settings(
+        &user.id,
+        sudo,
+        &scope,
+        allow_html,
+        &admonitions,
+      )
+      .await?;
+      db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?
+    }
   };
 
   let user = db
@@ -192,6 +289,55 @@ async fn update_handler(
   ))
 }
 
+const MAX_ADMONITIONS: usize = 16;
+
+/// Custom alert kinds are lowercase words, that are not one of the built-in
+/// alerts, as they are used in class names.
+fn validate_admonitions(admonitions: &[String]) -> Result<(), ApiError> {
+  if admonitions.len() > MAX_ADMONITIONS {
+    return Err(ApiError::MalformedRequest {
+      msg: format!("at most {MAX_ADMONITIONS} admonitions are allowed").into(),
+    });
+  }
+  for admonition in admonitions {
+    let valid = !admonition.is_empty()
+      && admonition.len() <= 32
+      && admonition
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+      && !["note", "tip", "important", "warning", "caution"]
+        .contains(&admonition.as_str());
+    if !valid {
+      return Err(ApiError::MalformedRequest {
+        msg: format!("invalid admonition '{admonition}'").into(),
+      });
+    }
+  }
+  Ok(())
+}
+
+#[instrument(
+  name = "GET /api/scopes/:scope/markdown_settings",
+  skip(req),
+  fields(scope)
+)]
+async fn get_markdown_settings_handler(
+  req: Request<Body>,
+) -> ApiResult<ApiScopeMarkdownSettings> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+
+  let db = req.data::<Database>().unwrap();
+  db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?;
+
+  let settings = db
+    .get_scope_markdown_settings(&scope)
+    .await?
+    .map(ApiScopeMarkdownSettings::from)
+    .unwrap_or_default();
+  Ok(settings)
+}
+
 #[instrument(name = "DELETE /api/scopes/:scope", skip(req), fields(scop))]
 pub async fn delete_handler(req: Request<Body>) -> ApiResult<Response<Body>> {
   let scope = req.param_scope()?;
@@ -303,10 +449,18 @@ async fn invite_member_handler(
     .await
     .map_err(|e| map_unique_violation(e, ApiError::AlreadyInvited))?;
 
-  if let Some(ref email) = new_user.email {
-    let email_sender = req.data::<Option<EmailSender>>().unwrap();
+  let email_sender = req.data::<Option<EmailSender>>().unwrap();
+  if let Some(ref email) = new_user.email
+    && let Some(email_sender) = email_sender
+  {
     let registry_url = req.data::<RegistryUrl>().unwrap();
-    if let Some(email_sender) = email_sender {
+    let preferences = db.get_notification_preferences(new_user.id).await?;
+    if preferences.scope_invites {
+      let unsubscribe_url = emails::unsubscribe_url(
+        &registry_url.0,
+        preferences.unsubscribe_token,
+        NotificationKind::ScopeInvites,
+      );
       let email_args = EmailArgs::ScopeInvite {
         name: Cow::Borrowed(&new_user.name),
         scope: Cow::Borrowed(&scope),
@@ -314,6 +468,7 @@ async fn invite_member_handler(
         registry_name: Cow::Borrowed(&email_sender.from_name),
         support_email: Cow::Borrowed(&email_sender.from),
         inviter_name: Cow::Borrowed(&current_user.name),
+        unsubscribe_url: Cow::Borrowed(unsubscribe_url.as_str()),
       };
       email_sender
         .send(email.clone(), email_args)
@@ -382,6 +537,17 @@ async fn update_member_handler(
         ApiError::InternalServerError
       })?;
 
+  events::emit(
+    db,
+    DomainEvent::ScopeMemberUpdated {
+      scope: scope.clone(),
+      user_id: scope_member.user_id,
+      user_name: user.name.clone(),
+      is_admin: scope_member.is_admin,
+    },
+  )
+  .await;
+
   Ok((scope_member, user).into())
 }
 
@@ -403,13 +569,15 @@ pub async fn delete_member_handler(
   db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?;
 
   let iam = req.iam();
-  iam
+  let (user, sudo) = iam
     .check_scope_member_delete_access(&scope, member_id)
     .await?;
 
-  let res = db.delete_scope_member(&scope, member_id).await?;
-  match res {
-    ScopeMemberUpdateResult::Ok(_) => {}
+  let res = db
+    .delete_scope_member(&user.id, sudo, &scope, member_id)
+    .await?;
+  let scope_member = match res {
+    ScopeMemberUpdateResult::Ok(scope_member) => scope_member,
     ScopeMemberUpdateResult::TargetIsLastTransferableAdmin => {
       return Err(ApiError::NoScopeOwnerAvailable);
     }
@@ -421,6 +589,67 @@ pub async fn delete_member_handler(
     }
   };
 
+  events::emit(
+    db,
+    DomainEvent::ScopeMemberRemoved {
+      scope: scope.clone(),
+      user_id: scope_member.user_id,
+      is_admin: scope_member.is_admin,
+    },
+  )
+  .await;
+
+  let resp = Response::builder()
+    .status(StatusCode::NO_CONTENT)
+    .body(Body::empty())
+    .unwrap();
+  Ok(resp)
+}
+
+#[instrument(
+  name = "POST /api/scopes/:scope/members/:member/lift_cooldown",
+  skip(req),
+  fields(scope, member)
+)]
+pub async fn lift_member_cooldown_handler(
+  req: Request<Body>,
+) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  let member_id = req.param_uuid("member")?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("member", field::display(&member_id));
+
+  let db = req.data::<Database>().unwrap();
+
+  db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?;
+
+  let iam = req.iam();
+  let (user, sudo) = iam.check_scope_admin_access(&scope).await?;
+
+  if !sudo {
+    if user.id == member_id {
+      return Err(ApiError::CannotLiftOwnCooldown);
+    }
+    // An admin that only just joined could have been added by the same
+    // compromised account, so they can not vouch for other new members.
+    let actor = db
+      .get_scope_member(&scope, user.id)
+      .await?
+      .ok_or(ApiError::ActorNotScopeMember)?;
+    if let Some(until) = iam.member_cooldown_until(&actor).await? {
+      return Err(ApiError::ScopeMemberInCooldown {
+        until: until.to_rfc3339(),
+      });
+    }
+  }
+
+  db.get_scope_member(&scope, member_id)
+    .await?
+    .ok_or(ApiError::ScopeMemberNotFound)?;
+
+  db.lift_scope_member_cooldown(&user.id, sudo, &scope, member_id)
+    .await?;
+
   let resp = Response::builder()
     .status(StatusCode::NO_CONTENT)
     .body(Body::empty())
@@ -452,6 +681,65 @@ pub async fn list_invites_handler(
   Ok(scope_invites)
 }
 
+#[instrument(
+  name = "GET /api/scopes/:scope/audit-log",
+  skip(req),
+  fields(scope)
+)]
+pub async fn list_audit_log_handler(
+  req: Request<Body>,
+) -> ApiResult<ApiList<ApiAuditLog>> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+
+  let db = req.data::<Database>().unwrap();
+
+  db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?;
+
+  let iam = req.iam();
+  iam.check_scope_admin_access(&scope).await?;
+
+  let (start, limit) = util::pagination(&req);
+  let action = req.query("action").map(|action| action.as_str());
+  let actor_id = req
+    .query("actor")
+    .map(|actor| {
+      Uuid::parse_str(actor).map_err(|err| {
+        let msg =
+          format!("failed to parse query parameter 'actor' as uuid: {err}")
+            .into();
+        ApiError::MalformedRequest { msg }
+      })
+    })
+    .transpose()?;
+  let package = req
+    .query("package")
+    .map(|package| {
+      PackageName::try_from(package.as_str()).map_err(|err| {
+        let msg =
+          format!("failed to parse query parameter 'package': {err}").into();
+        ApiError::MalformedRequest { msg }
+      })
+    })
+    .transpose()?;
+
+  let (total, audit_logs) = db
+    .list_scope_audit_logs(
+      &scope,
+      start,
+      limit,
+      action,
+      actor_id,
+      package.as_ref(),
+    )
+    .await?;
+
+  Ok(ApiList {
+    items: audit_logs.into_iter().map(ApiAuditLog::from).collect(),
+    total,
+  })
+}
+
 #[instrument(
   name = "DELETE /api/scopes/:scope/invites/:user_id",
   skip(req),
@@ -482,10 +770,35 @@ pub async fn delete_invite_handler(
   Ok(resp)
 }
 
+#[instrument(
+  name = "DELETE /api/scopes/:scope/tokens",
+  skip(req),
+  fields(scope)
+)]
+pub async fn revoke_tokens_handler(
+  req: Request<Body>,
+) -> ApiResult<ApiRevokedTokens> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+
+  let db = req.data::<Database>().unwrap();
+
+  db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?;
+
+  let iam = req.iam();
+  let (user, sudo) = iam.check_scope_admin_access(&scope).await?;
+
+  let token_ids = db.revoke_scope_tokens(&user.id, sudo, &scope).await?;
+
+  Ok(ApiRevokedTokens { token_ids })
+}
+
 #[cfg(test)]
 pub mod tests {
   use super::*;
   use crate::ids::{PackageName, ScopeDescription, ScopeName};
+  use crate::publish::tests::create_mock_tarball;
+  use crate::publish::tests::process_tarball_setup;
   use crate::util::test::ApiResultExt;
   use crate::util::test::TestSetup;
   use serde_json::json;
@@ -534,9 +847,21 @@ pub mod tests {
       .await
       .unwrap();
     resp
-      .expect_err_code(StatusCode::CONFLICT, "scopeAlreadyExists")
+      .expect_err_code(StatusCode::CONFLICT, "scopeNameConfusable")
       .await;
 
+    // lookalike scope name
+    let mut resp = t
+      .http()
+      .post("/api/scopes")
+      .body_json(json!({ "scope": "sc0pe1", "description": "" }))
+      .call()
+      .await
+      .unwrap();
+    let err = resp.expect_err(StatusCode::CONFLICT).await;
+    assert_eq!(err.code, "scopeNameConfusable");
+    assert_eq!(err.data["existing"], "scope1");
+
     // invalid name
     let mut resp = t
       .http()
@@ -722,6 +1047,107 @@ pub mod tests {
     assert!(!scope.require_publishing_from_ci);
   }
 
+  #[tokio::test]
+  async fn scope_usage() {
+    let mut t = TestSetup::new().await;
+
+    let path = format!("/api/scopes/{}/usage", t.scope.scope);
+    let quotas = t
+      .http()
+      .get(&path)
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiScopeQuotas>()
+      .await;
+    assert_eq!(quotas.storage_usage, 0);
+    assert_eq!(quotas.storage_limit, 5368709120);
+
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:?}");
+
+    let quotas = t
+      .http()
+      .get(&path)
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiScopeQuotas>()
+      .await;
+    assert!(quotas.storage_usage > 0);
+    assert_eq!(quotas.package_usage, 1);
+
+    let token = t.user2.token.clone();
+    t.http()
+      .get(&path)
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::FORBIDDEN, "actorNotScopeMember")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn scope_update_markdown_settings() {
+    let mut t = TestSetup::new().await;
+
+    let path = format!("/api/scopes/{}/markdown_settings", t.scope.scope);
+    let settings = t
+      .http()
+      .get(&path)
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiScopeMarkdownSettings>()
+      .await;
+    assert_eq!(settings, ApiScopeMarkdownSettings::default());
+
+    let scope_path = format!("/api/scopes/{}", t.scope.scope);
+    let token = t.user1.token.clone();
+    let mut resp = t
+      .http()
+      .patch(&scope_path)
+      .body_json(json!({
+        "markdownSettings": { "allowHtml": false, "admonitions": ["Danger"] },
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap();
+    resp
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+
+    let mut resp = t
+      .http()
+      .patch(&scope_path)
+      .body_json(json!({
+        "markdownSettings": { "allowHtml": true, "admonitions": ["danger"] },
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap();
+    resp.expect_ok::<ApiFullScope>().await;
+
+    let settings = t
+      .http()
+      .get(&path)
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiScopeMarkdownSettings>()
+      .await;
+    assert_eq!(
+      settings,
+      ApiScopeMarkdownSettings {
+        allow_html: true,
+        admonitions: vec!["danger".to_string()],
+      }
+    );
+  }
+
   async fn list_members(t: &mut TestSetup) -> Vec<ApiScopeMember> {
     // list
     let mut resp = t
@@ -2304,4 +2730,267 @@ pub mod tests {
       .expect_err_code(StatusCode::BAD_REQUEST, "noScopeOwnerAvailable")
       .await;
   }
+
+  #[tokio::test]
+  async fn scope_audit_log() {
+    let mut t = TestSetup::new().await;
+    let scope = ScopeName::try_from("scope").unwrap();
+
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:?}");
+
+    t.db()
+      .add_scope_invite(
+        &t.user1.user.id,
+        false,
+        NewScopeInvite {
+          target_user_id: t.user2.user.id,
+          requesting_user_id: t.user1.user.id,
+          scope: &scope,
+        },
+      )
+      .await
+      .unwrap();
+    t.db()
+      .accept_scope_invite(&t.user2.user.id, &scope)
+      .await
+      .unwrap();
+
+    t.http()
+      .post("/api/user/tokens")
+      .body_json(json!({
+        "description": "publish foo",
+        "expiresAt": null,
+        "permissions": [
+          { "permission": "package/publish", "scope": "scope", "package": "foo" }
+        ]
+      }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiCreatedToken>()
+      .await;
+
+    t.http()
+      .patch("/api/scopes/scope/packages/foo/versions/1.2.3")
+      .body_json(json!({ "yanked": true }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok_no_content()
+      .await;
+
+    t.http()
+      .delete(format!("/api/scopes/scope/members/{}", t.user2.user.id))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok_no_content()
+      .await;
+
+    let audit_log: ApiList<ApiAuditLog> = t
+      .http()
+      .get("/api/scopes/scope/audit-log")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    let actions = audit_log
+      .items
+      .iter()
+      .map(|item| item.action.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(
+      actions[..6],
+      [
+        "delete_scope_member",
+        "yank_package_version",
+        "create_token",
+        "add_scope_member",
+        "add_scope_invite",
+        "publish_package_version",
+      ]
+    );
+    // the test setup creates the scope and sets its limits
+    assert!(actions.contains(&"create_scope"));
+    assert!(actions.contains(&"scope_set_package_limit"));
+    let total = audit_log.total;
+    assert_eq!(total, actions.len());
+    assert_eq!(audit_log.items[3].actor.id, t.user2.user.id);
+
+    let audit_log: ApiList<ApiAuditLog> = t
+      .http()
+      .get("/api/scopes/scope/audit-log?action=yank_package_version")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    assert_eq!(audit_log.total, 1);
+    assert_eq!(audit_log.items[0].meta["version"], "1.2.3");
+
+    let audit_log: ApiList<ApiAuditLog> = t
+      .http()
+      .get("/api/scopes/scope/audit-log?package=foo")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    assert_eq!(audit_log.total, 2);
+
+    let audit_log: ApiList<ApiAuditLog> = t
+      .http()
+      .get(format!(
+        "/api/scopes/scope/audit-log?actor={}",
+        t.user2.user.id
+      ))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    assert_eq!(audit_log.total, 1);
+    assert_eq!(audit_log.items[0].action, "add_scope_member");
+
+    let audit_log: ApiList<ApiAuditLog> = t
+      .http()
+      .get("/api/scopes/scope/audit-log?limit=2&page=2")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    assert_eq!(audit_log.total, total);
+    assert_eq!(audit_log.items.len(), 2);
+    assert_eq!(audit_log.items[0].action, "create_token");
+
+    t.http()
+      .get("/api/scopes/scope/audit-log?actor=foo")
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+
+    let token = t.user3.token.clone();
+    t.http()
+      .get("/api/scopes/scope/audit-log")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::FORBIDDEN, "actorNotScopeMember")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn scope_revoke_tokens() {
+    let mut t = TestSetup::new().await;
+
+    let scope_token: ApiCreatedToken = t
+      .http()
+      .post("/api/user/tokens")
+      .body_json(json!({
+        "description": "publish foo",
+        "expiresAt": null,
+        "permissions": [
+          { "permission": "package/publish", "scope": "scope", "package": "foo" }
+        ]
+      }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    let full_token: ApiCreatedToken = t
+      .http()
+      .post("/api/user/tokens")
+      .body_json(json!({
+        "description": "full access",
+        "expiresAt": null,
+        "permissions": null
+      }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+
+    let token = t.user3.token.clone();
+    let outsider_token: ApiCreatedToken = t
+      .http()
+      .post("/api/user/tokens")
+      .token(Some(&token))
+      .body_json(json!({
+        "description": "full access",
+        "expiresAt": null,
+        "permissions": null
+      }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+
+    t.http()
+      .delete("/api/scopes/scope/tokens")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::FORBIDDEN, "actorNotScopeMember")
+      .await;
+
+    let revoked: ApiRevokedTokens = t
+      .http()
+      .delete("/api/scopes/scope/tokens")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    let mut token_ids = revoked.token_ids;
+    token_ids.sort();
+    let mut expected = vec![scope_token.token.id, full_token.token.id];
+    expected.sort();
+    assert_eq!(token_ids, expected);
+
+    for secret in [&scope_token.secret, &full_token.secret] {
+      t.http()
+        .get("/api/user")
+        .token(Some(secret.as_str()))
+        .call()
+        .await
+        .unwrap()
+        .expect_err_code(StatusCode::UNAUTHORIZED, "invalidBearerToken")
+        .await;
+    }
+    t.http()
+      .get("/api/user")
+      .token(Some(&outsider_token.secret))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiFullUser>()
+      .await;
+
+    let audit_log: ApiList<ApiAuditLog> = t
+      .http()
+      .get("/api/scopes/scope/audit-log?action=revoke_scope_tokens")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    assert_eq!(audit_log.total, 1);
+    assert_eq!(
+      audit_log.items[0].meta["token_ids"]
+        .as_array()
+        .unwrap()
+        .len(),
+      2
+    );
+  }
 }
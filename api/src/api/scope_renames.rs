@@ -0,0 +1,389 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+//! Scopes can be renamed. An admin of the scope requests the rename, and staff
+//! approve or reject it. Once approved, the scope and its packages, members
+//! and settings live at the new name, and the old name permanently redirects
+//! to it.
+//!
+//! Like for moved packages, the files of the published versions stay at the
+//! old location, so that existing `jsr:` specifiers and lockfiles keep
+//! working, and are copied to the new location in the background, see
+//! [super::package_transfers].
+
+use hyper::Body;
+use hyper::Request;
+use hyper::Response;
+use hyper::StatusCode;
+use routerify::prelude::RequestExt;
+use tracing::Span;
+use tracing::field;
+use tracing::instrument;
+
+use crate::RegistryUrl;
+use crate::cache_purge::CachePurge;
+use crate::db::Database;
+use crate::db::RenameScopeResult;
+use crate::db::ReservedNameKind;
+use crate::external::algolia::AlgoliaClient;
+use crate::iam::ReqIamExt;
+use crate::s3_paths;
+use crate::util::ApiResult;
+use crate::util::RequestIdExt;
+use crate::util::decode_json;
+use crate::util::pagination;
+
+use super::ApiCreateScopeRenameRequest;
+use super::ApiError;
+use super::ApiList;
+use super::ApiScope;
+use super::ApiScopeRename;
+use super::package_transfers::spawn_move_files;
+
+#[instrument(name = "GET /api/scopes/:scope/rename", skip(req), fields(scope))]
+pub async fn get_handler(req: Request<Body>) -> ApiResult<ApiScopeRename> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+
+  let iam = req.iam();
+  iam.check_scope_admin_access(&scope).await?;
+
+  let db = req.data::<Database>().unwrap();
+  let rename = db
+    .get_scope_rename(&scope)
+    .await?
+    .ok_or(ApiError::ScopeRenameNotFound)?;
+
+  Ok(rename.into())
+}
+
+#[instrument(
+  name = "POST /api/scopes/:scope/rename",
+  skip(req),
+  fields(scope, target_scope)
+)]
+pub async fn create_handler(
+  mut req: Request<Body>,
+) -> ApiResult<ApiScopeRename> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+
+  let ApiCreateScopeRenameRequest { target_scope } =
+    decode_json(&mut req).await?;
+  Span::current().record("target_scope", field::display(&target_scope));
+
+  let db = req.data::<Database>().unwrap();
+  db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?;
+
+  let iam = req.iam();
+  let (user, sudo) = iam.check_scope_admin_access(&scope).await?;
+
+  if target_scope == scope {
+    return Err(ApiError::ScopeRenameInvalid {
+      msg: "the scope already has this name".into(),
+    });
+  }
+
+  let target_scope_without_hyphens = target_scope.replace('-', "");
+  if db.check_is_bad_word(&target_scope_without_hyphens).await? {
+    return Err(ApiError::ScopeNameNotAllowed);
+  }
+  if db
+    .check_is_reserved_name(
+      ReservedNameKind::Scope,
+      &target_scope_without_hyphens,
+    )
+    .await?
+  {
+    return Err(ApiError::ScopeNameReserved);
+  }
+
+  // The scope may take back one of its own old names, but not the name, or a
+  // lookalike of the name, of another scope.
+  if db.get_scope(&target_scope).await?.is_some() {
+    return Err(ApiError::ScopeAlreadyExists);
+  }
+  if let Some(redirect) = db.get_scope_redirect(&target_scope).await?
+    && redirect.target_scope != scope
+  {
+    return Err(ApiError::ScopeAlreadyExists);
+  }
+  if let Some(existing) = db.find_confusable_scope(&target_scope).await?
+    && existing != scope
+  {
+    return Err(ApiError::ScopeNameConfusable { existing });
+  }
+
+  let rename = db
+    .create_scope_rename(&user.id, sudo, &scope, &target_scope)
+    .await?;
+
+  Ok(rename.into())
+}
+
+/// Cancels the rename when called by an admin of the scope, or rejects it
+/// when called by staff.
+#[instrument(
+  name = "DELETE /api/scopes/:scope/rename",
+  skip(req),
+  fields(scope)
+)]
+pub async fn delete_handler(req: Request<Body>) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+
+  let iam = req.iam();
+  let (user, sudo) = iam.check_scope_admin_access(&scope).await?;
+
+  let db = req.data::<Database>().unwrap();
+  db.delete_scope_rename(&user.id, sudo, &scope)
+    .await?
+    .ok_or(ApiError::ScopeRenameNotFound)?;
+
+  let resp = Response::builder()
+    .status(StatusCode::NO_CONTENT)
+    .body(Body::empty())
+    .unwrap();
+  Ok(resp)
+}
+
+#[instrument(name = "GET /api/admin/scope_renames", skip(req))]
+pub async fn list_handler(
+  req: Request<Body>,
+) -> ApiResult<ApiList<ApiScopeRename>> {
+  let iam = req.iam();
+  iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let (start, limit) = pagination(&req);
+  let (total, renames) = db.list_scope_renames(start, limit).await?;
+
+  Ok(ApiList {
+    items: renames.into_iter().map(ApiScopeRename::from).collect(),
+    total,
+  })
+}
+
+#[instrument(
+  name = "POST /api/admin/scope_renames/:scope/approve",
+  skip(req),
+  fields(scope, target_scope)
+)]
+pub async fn approve_handler(req: Request<Body>) -> ApiResult<ApiScope> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+
+  let iam = req.iam();
+  let staff = iam.check_admin_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let (renamed_scope, packages) = match db
+    .rename_scope(&staff.id, true, &scope)
+    .await?
+  {
+    RenameScopeResult::Ok(renamed_scope, packages) => (renamed_scope, packages),
+    RenameScopeResult::NotFound => {
+      return Err(ApiError::ScopeRenameNotFound);
+    }
+    RenameScopeResult::AlreadyExists => {
+      return Err(ApiError::ScopeAlreadyExists);
+    }
+    RenameScopeResult::PublishInProgress => {
+      return Err(ApiError::ScopeRenameInvalid {
+        msg: "a version of a package of the scope is currently being published"
+          .into(),
+      });
+    }
+  };
+  let target_scope = renamed_scope.scope.clone();
+  Span::current().record("target_scope", field::display(&target_scope));
+
+  let algolia_client = req.data::<Option<AlgoliaClient>>().unwrap();
+  if let Some(algolia_client) = algolia_client {
+    for package in &packages {
+      algolia_client.delete_package(&scope, package);
+      if let Some((res_package, _, meta)) =
+        db.get_package(&target_scope, package).await?
+      {
+        algolia_client.upsert_package(&res_package, &meta);
+      }
+    }
+  }
+
+  let registry_url = &req.data::<RegistryUrl>().unwrap().0;
+  let cache_purge = req.data::<CachePurge>().unwrap();
+  let mut purge_urls = s3_paths::scope_api_cache_urls(registry_url, &scope);
+  purge_urls
+    .extend(s3_paths::scope_api_cache_urls(registry_url, &target_scope));
+  for package in &packages {
+    purge_urls.extend(s3_paths::package_api_cache_urls(
+      registry_url,
+      &scope,
+      package,
+    ));
+    purge_urls.extend(s3_paths::package_api_cache_urls(
+      registry_url,
+      &target_scope,
+      package,
+    ));
+  }
+  cache_purge.purge(purge_urls);
+
+  spawn_move_files(&req, scope, target_scope, packages);
+
+  Ok(renamed_scope.into())
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::StatusCode;
+  use serde_json::json;
+
+  use crate::api::ApiList;
+  use crate::api::ApiPackage;
+  use crate::api::ApiScope;
+  use crate::api::ApiScopeRename;
+  use crate::db::PublishingTaskStatus;
+  use crate::ids::PackageName;
+  use crate::ids::ScopeName;
+  use crate::publish::tests::create_mock_tarball;
+  use crate::publish::tests::process_tarball_setup;
+  use crate::util::test::ApiResultExt;
+  use crate::util::test::TestSetup;
+
+  #[tokio::test]
+  async fn rename_scope() {
+    let mut t = TestSetup::new().await;
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:?}");
+
+    let token1 = t.user1.token.clone();
+    let token2 = t.user2.token.clone();
+    let staff_token = t.staff_user.token.clone();
+
+    t.http()
+      .post("/api/scopes/scope/rename")
+      .body_json(json!({ "targetScope": "renamed" }))
+      .token(Some(&token2))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::FORBIDDEN, "actorNotScopeMember")
+      .await;
+    t.http()
+      .post("/api/scopes/scope/rename")
+      .body_json(json!({ "targetScope": "scope" }))
+      .token(Some(&token1))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "scopeRenameInvalid")
+      .await;
+
+    let rename = t
+      .http()
+      .post("/api/scopes/scope/rename")
+      .body_json(json!({ "targetScope": "renamed" }))
+      .token(Some(&token1))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiScopeRename>()
+      .await;
+    assert_eq!(rename.target_scope, ScopeName::try_from("renamed").unwrap());
+    assert_eq!(rename.requested_by, t.user1.user.id);
+
+    // only staff can approve renames
+    t.http()
+      .post("/api/admin/scope_renames/scope/approve")
+      .token(Some(&token1))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::FORBIDDEN, "actorNotAuthorized")
+      .await;
+
+    let renames = t
+      .http()
+      .get("/api/admin/scope_renames")
+      .token(Some(&staff_token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiList<ApiScopeRename>>()
+      .await;
+    assert_eq!(renames.total, 1);
+    assert_eq!(
+      renames.items[0].scope,
+      ScopeName::try_from("scope").unwrap()
+    );
+
+    let scope = t
+      .http()
+      .post("/api/admin/scope_renames/scope/approve")
+      .token(Some(&staff_token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiScope>()
+      .await;
+    assert_eq!(scope.scope, ScopeName::try_from("renamed").unwrap());
+
+    // the package and its members moved with the scope
+    let package = t
+      .http()
+      .get("/api/scopes/renamed/packages/foo")
+      .token(Some(&token1))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPackage>()
+      .await;
+    assert_eq!(package.scope, ScopeName::try_from("renamed").unwrap());
+    assert_eq!(package.name, PackageName::try_from("foo").unwrap());
+
+    // the old name redirects to the new one
+    t.http()
+      .get("/api/scopes/scope")
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::MOVED_PERMANENTLY, "scopeMoved")
+      .await;
+    t.http()
+      .get("/api/scopes/scope/packages/foo/versions")
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::MOVED_PERMANENTLY, "packageMoved")
+      .await;
+    let redirect = t
+      .db()
+      .get_package_redirect(
+        &ScopeName::try_from("scope").unwrap(),
+        &PackageName::try_from("foo").unwrap(),
+      )
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(redirect.target_scope, scope.scope);
+
+    // the old name can not be taken by another scope
+    t.http()
+      .post("/api/scopes")
+      .body_json(json!({ "scope": "scope", "description": "" }))
+      .token(Some(&token2))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::CONFLICT, "scopeAlreadyExists")
+      .await;
+
+    t.http()
+      .post("/api/admin/scope_renames/renamed/approve")
+      .token(Some(&staff_token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "scopeRenameNotFound")
+      .await;
+  }
+}
@@ -1,17 +1,26 @@
 // Copyright 2024 the JSR authors. All rights reserved. MIT license.
+use chrono::DateTime;
+use chrono::Utc;
 use hyper::Body;
 use hyper::Request;
 use hyper::Response;
 use hyper::StatusCode;
+use hyper::header;
 use routerify::Router;
 use routerify::prelude::RequestExt;
+use routerify_query::RequestQueryExt;
 use tracing::Span;
 use tracing::field;
 use tracing::instrument;
+use uuid::Uuid;
 
 use std::borrow::Cow;
 
 use crate::RegistryUrl;
+use crate::data_export::DATA_EXPORT_URL_EXPIRY;
+use crate::data_export::DataExportJobPayload;
+use crate::db::DataExport;
+use crate::db::DataExportStatus;
 use crate::db::Database;
 use crate::db::PackagePublishPermission;
 use crate::db::Permission;
@@ -19,7 +28,12 @@ use crate::db::TokenType;
 use crate::db::UserPublic;
 use crate::emails::EmailArgs;
 use crate::emails::EmailSender;
+use crate::emails::NotificationKind;
+use crate::events;
+use crate::events::DomainEvent;
+use crate::iam::MemberCooldown;
 use crate::iam::ReqIamExt;
+use crate::s3::Buckets;
 use crate::util;
 use crate::util::ApiResult;
 use crate::util::RequestIdExt;
@@ -27,13 +41,19 @@ use crate::util::decode_json;
 
 use super::ApiCreateTokenRequest;
 use super::ApiCreatedToken;
+use super::ApiDataExport;
 use super::ApiError;
 use super::ApiFullUser;
+use super::ApiNotificationPreferences;
+use super::ApiRotateTokenRequest;
 use super::ApiScope;
 use super::ApiScopeInvite;
 use super::ApiScopeMember;
+use super::ApiSignedUrl;
 use super::ApiTicket;
 use super::ApiToken;
+use super::ApiUpdateNotificationPreferencesRequest;
+use super::ApiUpdateTokenRequest;
 
 pub fn self_user_router() -> Router<Body, ApiError> {
   Router::builder()
@@ -48,8 +68,26 @@ pub fn self_user_router() -> Router<Body, ApiError> {
     .delete("/invites/:scope", util::auth(decline_invite_handler))
     .get("/tokens", util::auth(util::json(list_tokens)))
     .post("/tokens", util::auth(util::json(create_token)))
+    .get("/tokens/:id", util::auth(util::json(get_token)))
+    .patch("/tokens/:id", util::auth(util::json(update_token)))
     .delete("/tokens/:id", util::auth(delete_token))
+    .post("/tokens/:id/rotate", util::auth(util::json(rotate_token)))
     .get("/tickets", util::auth(util::json(list_tickets)))
+    .get("/data_exports", util::auth(util::json(list_data_exports)))
+    .post("/data_exports", util::auth(util::json(create_data_export)))
+    .get("/data_exports/:id", util::auth(util::json(get_data_export)))
+    .get(
+      "/data_exports/:id/download",
+      util::auth(util::json(download_data_export)),
+    )
+    .get(
+      "/notification_preferences",
+      util::auth(util::json(get_notification_preferences)),
+    )
+    .patch(
+      "/notification_preferences",
+      util::auth(util::json(update_notification_preferences)),
+    )
     .build()
     .unwrap()
 }
@@ -130,6 +168,49 @@ pub async fn accept_invite_handler(
     .await?
     .ok_or(ApiError::ScopeInviteNotFound)?;
 
+  // While new members are in their cooldown, let the other admins of the
+  // scope know, so they can lift it early or react to an unexpected member.
+  let member_cooldown = req.data::<MemberCooldown>().unwrap();
+  let email_sender = req.data::<Option<EmailSender>>().unwrap();
+  if let Some(email_sender) = email_sender
+    && member_cooldown.hours > 0
+  {
+    let registry_url = req.data::<RegistryUrl>().unwrap();
+    for admin in db.list_scope_admins(&scope).await? {
+      if admin.id == current_user.id {
+        continue;
+      }
+      let Some(email) = admin.email else {
+        continue;
+      };
+      let email_args = EmailArgs::ScopeMemberAdded {
+        name: Cow::Borrowed(&admin.name),
+        member_name: Cow::Borrowed(&current_user.name),
+        scope: Cow::Borrowed(&scope),
+        cooldown_hours: member_cooldown.hours,
+        registry_url: Cow::Borrowed(registry_url.0.as_str()),
+        registry_name: Cow::Borrowed(&email_sender.from_name),
+        support_email: Cow::Borrowed(&email_sender.from),
+      };
+      // The member has already been added, so a failed notification should
+      // not fail the request.
+      if let Err(e) = email_sender.send(email, email_args).await {
+        tracing::error!("failed to send email: {:?}", e);
+      }
+    }
+  }
+
+  events::emit(
+    db,
+    DomainEvent::ScopeMemberAdded {
+      scope: scope.clone(),
+      user_id: member.user_id,
+      user_name: current_user.name.clone(),
+      is_admin: member.is_admin,
+    },
+  )
+  .await;
+
   Ok((member, UserPublic::from(current_user)).into())
 }
 
@@ -181,33 +262,47 @@ async fn create_token(
     permissions,
   } = decode_json(&mut req).await?;
 
-  let description = description.trim().replace('\n', " ").replace('\r', "");
-  if description.is_empty() {
-    return Err(ApiError::MalformedRequest {
-      msg: "description must not be empty".into(),
-    });
-  }
-  if description.len() > 250 {
-    return Err(ApiError::MalformedRequest {
-      msg: "description must not be longer than 250 characters".into(),
-    });
-  }
-  if description.contains(|c: char| c.is_control()) {
-    return Err(ApiError::MalformedRequest {
-      msg: "description must not contain control characters".into(),
-    });
-  }
+  let description = validate_token_description(&description)?;
+  validate_token_expiry(expires_at)?;
 
-  if let Some(permissions) = permissions.as_ref()
-    && permissions.0.len() != 1
-  {
-    return Err(ApiError::MalformedRequest {
-      msg: "permissions must contain exactly one element".into(),
-    });
+  if let Some(permissions) = permissions.as_ref() {
+    if permissions.0.is_empty() {
+      return Err(ApiError::MalformedRequest {
+        msg: "permissions must not be empty".into(),
+      });
+    }
+    if permissions.0.len() > MAX_TOKEN_PERMISSIONS {
+      return Err(ApiError::MalformedRequest {
+        msg: format!(
+          "permissions must not contain more than {MAX_TOKEN_PERMISSIONS} elements"
+        )
+        .into(),
+      });
+    }
+    if permissions.0.len() > 1
+      && permissions
+        .0
+        .iter()
+        .any(|permission| matches!(permission, Permission::Read))
+    {
+      return Err(ApiError::MalformedRequest {
+        msg: "the read permission can not be combined with other permissions"
+          .into(),
+      });
+    }
   }
 
   let iam = req.iam();
   let user = iam.check_authorization_approve_access()?;
+  for permission in permissions.iter().flat_map(|permissions| &permissions.0) {
+    if let Permission::PackagePublish(PackagePublishPermission::Package {
+      scope,
+      package,
+    }) = permission
+    {
+      iam.check_package_token_access(scope, package).await?;
+    }
+  }
 
   let db = req.data::<Database>().unwrap();
 
@@ -221,7 +316,7 @@ async fn create_token(
   )
   .await?;
 
-  let hash = crate::token::hash(&secret);
+  let hash = crate::token::hash(&secret).await;
   let token = db.get_token_by_hash(&hash).await?.unwrap();
 
   if let Some(ref email) = user.email {
@@ -229,30 +324,14 @@ async fn create_token(
     let registry_url = req.data::<RegistryUrl>().unwrap();
     if let Some(email_sender) = email_sender {
       let permissions = if let Some(permissions) = &token.permissions {
-        match &permissions.0[0] {
-          Permission::PackagePublish(PackagePublishPermission::Scope {
-            scope,
-          }) => Cow::Owned(format!(
-            "Publish new versions to any package in the @{} scope",
-            scope
-          )),
-          Permission::PackagePublish(PackagePublishPermission::Package {
-            scope,
-            package,
-          }) => Cow::Owned(format!(
-            "Publish new versions of the @{}/{} package",
-            scope, package
-          )),
-          Permission::PackagePublish(PackagePublishPermission::Version {
-            scope,
-            package,
-            version,
-            ..
-          }) => Cow::Owned(format!(
-            "Publish the {} version of the @{}/{} package",
-            version, scope, package
-          )),
-        }
+        Cow::Owned(
+          permissions
+            .0
+            .iter()
+            .map(describe_permission)
+            .collect::<Vec<_>>()
+            .join("; "),
+        )
       } else {
         Cow::Borrowed("Full account access")
       };
@@ -287,6 +366,63 @@ async fn create_token(
   })
 }
 
+#[instrument("GET /api/user/tokens/:id")]
+async fn get_token(req: Request<Body>) -> ApiResult<ApiToken> {
+  let id = req.param_uuid("id")?;
+
+  let iam = req.iam();
+  let user = iam.check_current_user_access()?;
+
+  let db = req.data::<Database>().unwrap();
+
+  let token = db
+    .get_token(user.id, id)
+    .await?
+    .ok_or(ApiError::TokenNotFound)?;
+
+  Ok(token.into())
+}
+
+#[instrument("PATCH /api/user/tokens/:id")]
+async fn update_token(mut req: Request<Body>) -> ApiResult<ApiToken> {
+  let id = req.param_uuid("id")?;
+
+  let ApiUpdateTokenRequest {
+    description,
+    expires_at,
+  } = decode_json(&mut req).await?;
+
+  let description = description
+    .as_deref()
+    .map(validate_token_description)
+    .transpose()?;
+  validate_token_expiry(expires_at)?;
+
+  let iam = req.iam();
+  let user = iam.check_authorization_approve_access()?;
+
+  let db = req.data::<Database>().unwrap();
+
+  // Sessions expire on their own schedule, only personal access tokens may
+  // have their expiry changed.
+  let token = db
+    .get_token(user.id, id)
+    .await?
+    .ok_or(ApiError::TokenNotFound)?;
+  if token.r#type != TokenType::Personal {
+    return Err(ApiError::MalformedRequest {
+      msg: "only personal access tokens can be updated".into(),
+    });
+  }
+
+  let token = db
+    .update_token(user.id, id, description.as_deref(), expires_at)
+    .await?
+    .ok_or(ApiError::TokenNotFound)?;
+
+  Ok(token.into())
+}
+
 #[instrument("DELETE /api/user/tokens/:id")]
 async fn delete_token(req: Request<Body>) -> Result<Response<Body>, ApiError> {
   let id = req.param_uuid("id")?;
@@ -307,6 +443,123 @@ async fn delete_token(req: Request<Body>) -> Result<Response<Body>, ApiError> {
   Ok(resp)
 }
 
+#[instrument("POST /api/user/tokens/:id/rotate")]
+async fn rotate_token(mut req: Request<Body>) -> ApiResult<ApiCreatedToken> {
+  let id = req.param_uuid("id")?;
+
+  let ApiRotateTokenRequest { grace_period_hours } =
+    decode_json(&mut req).await?;
+  let grace_period_hours =
+    grace_period_hours.unwrap_or(DEFAULT_TOKEN_ROTATION_GRACE_PERIOD_HOURS);
+  if grace_period_hours > MAX_TOKEN_ROTATION_GRACE_PERIOD_HOURS {
+    return Err(ApiError::MalformedRequest {
+      msg: format!(
+        "gracePeriodHours must not be more than {MAX_TOKEN_ROTATION_GRACE_PERIOD_HOURS}"
+      )
+      .into(),
+    });
+  }
+
+  let iam = req.iam();
+  let user = iam.check_authorization_approve_access()?;
+
+  let db = req.data::<Database>().unwrap();
+
+  let token = db
+    .get_token(user.id, id)
+    .await?
+    .ok_or(ApiError::TokenNotFound)?;
+  if token.r#type != TokenType::Personal {
+    return Err(ApiError::MalformedRequest {
+      msg: "only personal access tokens can be rotated".into(),
+    });
+  }
+
+  let secret = crate::token::generate_token(TokenType::Personal);
+  let hash = crate::token::hash(&secret).await;
+  let lookup_hash = crate::token::lookup_hash(&secret);
+  let previous_expires_at =
+    Utc::now() + chrono::Duration::hours(grace_period_hours.into());
+  let token = db
+    .rotate_token(user.id, id, &hash, &lookup_hash, previous_expires_at)
+    .await?
+    .ok_or(ApiError::TokenNotFound)?;
+
+  Ok(ApiCreatedToken {
+    token: token.into(),
+    secret,
+  })
+}
+
+/// The maximum number of permissions a personal access token can have, for
+/// example to allow publishing a list of packages.
+const MAX_TOKEN_PERMISSIONS: usize = 20;
+
+/// How long the previous secret of a rotated token keeps working, unless
+/// another grace period is requested.
+const DEFAULT_TOKEN_ROTATION_GRACE_PERIOD_HOURS: u32 = 24;
+
+/// The longest grace period of a rotated token, a week.
+const MAX_TOKEN_ROTATION_GRACE_PERIOD_HOURS: u32 = 7 * 24;
+
+fn validate_token_description(description: &str) -> Result<String, ApiError> {
+  let description = description.trim().replace('\n', " ").replace('\r', "");
+  if description.is_empty() {
+    return Err(ApiError::MalformedRequest {
+      msg: "description must not be empty".into(),
+    });
+  }
+  if description.len() > 250 {
+    return Err(ApiError::MalformedRequest {
+      msg: "description must not be longer than 250 characters".into(),
+    });
+  }
+  if description.contains(|c: char| c.is_control()) {
+    return Err(ApiError::MalformedRequest {
+      msg: "description must not contain control characters".into(),
+    });
+  }
+  Ok(description)
+}
+
+fn validate_token_expiry(
+  expires_at: Option<DateTime<Utc>>,
+) -> Result<(), ApiError> {
+  if let Some(expires_at) = expires_at
+    && expires_at <= Utc::now()
+  {
+    return Err(ApiError::MalformedRequest {
+      msg: "expiresAt must be in the future".into(),
+    });
+  }
+  Ok(())
+}
+
+fn describe_permission(permission: &Permission) -> String {
+  match permission {
+    Permission::PackagePublish(PackagePublishPermission::Scope { scope }) => {
+      format!(
+        "Publish new versions to any package in the @{} scope",
+        scope
+      )
+    }
+    Permission::PackagePublish(PackagePublishPermission::Package {
+      scope,
+      package,
+    }) => format!("Publish new versions of the @{}/{} package", scope, package),
+    Permission::PackagePublish(PackagePublishPermission::Version {
+      scope,
+      package,
+      version,
+      ..
+    }) => format!(
+      "Publish the {} version of the @{}/{} package",
+      version, scope, package
+    ),
+    Permission::Read => "Read-only account access".to_owned(),
+  }
+}
+
 #[instrument(name = "GET /api/user/tickets", skip(req))]
 pub async fn list_tickets(req: Request<Body>) -> ApiResult<Vec<ApiTicket>> {
   let iam = req.iam();
@@ -318,15 +571,249 @@ pub async fn list_tickets(req: Request<Body>) -> ApiResult<Vec<ApiTicket>> {
   Ok(tickets.into_iter().map(|scope| scope.into()).collect())
 }
 
+#[instrument(name = "GET /api/user/data_exports", skip(req))]
+pub async fn list_data_exports(
+  req: Request<Body>,
+) -> ApiResult<Vec<ApiDataExport>> {
+  let iam = req.iam();
+  let current_user = iam.check_current_user_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let data_exports = db.list_data_exports(current_user.id).await?;
+
+  Ok(data_exports.into_iter().map(ApiDataExport::from).collect())
+}
+
+/// Requests an export of all data about the current user. The export is built
+/// in the background, and can be downloaded once its status is `completed`.
+#[instrument(name = "POST /api/user/data_exports", skip(req))]
+pub async fn create_data_export(
+  req: Request<Body>,
+) -> ApiResult<ApiDataExport> {
+  let iam = req.iam();
+  let current_user = iam.check_authorization_approve_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let id = Uuid::new_v4();
+  let data_export = db
+    .create_data_export(current_user.id, id, DataExportJobPayload::job(id))
+    .await?
+    .ok_or(ApiError::DataExportInProgress)?;
+
+  Ok(data_export.into())
+}
+
+#[instrument(name = "GET /api/user/data_exports/:id", skip(req), fields(id))]
+pub async fn get_data_export(req: Request<Body>) -> ApiResult<ApiDataExport> {
+  let id = req.param_uuid("id")?;
+  Span::current().record("id", field::display(id));
+
+  let iam = req.iam();
+  let current_user = iam.check_current_user_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let data_export = get_own_data_export(db, current_user.id, id).await?;
+
+  Ok(data_export.into())
+}
+
+/// Returns a signed URL to download the archive of a completed data export.
+#[instrument(
+  name = "GET /api/user/data_exports/:id/download",
+  skip(req),
+  fields(id)
+)]
+pub async fn download_data_export(
+  req: Request<Body>,
+) -> ApiResult<ApiSignedUrl> {
+  let id = req.param_uuid("id")?;
+  Span::current().record("id", field::display(id));
+
+  let iam = req.iam();
+  let current_user = iam.check_authorization_approve_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let data_export = get_own_data_export(db, current_user.id, id).await?;
+  if data_export.status != DataExportStatus::Completed {
+    return Err(ApiError::DataExportNotReady);
+  }
+
+  let buckets = req.data::<Buckets>().unwrap();
+  let filename = format!("jsr-data-export-{id}.tar.gz");
+  let expires_at =
+    Utc::now() + chrono::Duration::from_std(DATA_EXPORT_URL_EXPIRY).unwrap();
+  let url = buckets
+    .publishing_bucket
+    .bucket
+    .signed_url(
+      &crate::data_export::data_export_path(id),
+      DATA_EXPORT_URL_EXPIRY,
+      Some(&filename),
+    )
+    .await?;
+
+  db.log_data_export_download(current_user.id, id).await?;
+
+  Ok(ApiSignedUrl { url, expires_at })
+}
+
+/// Gets a data export, as long as it belongs to `user_id`. Exports of other
+/// users are reported as not found.
+async fn get_own_data_export(
+  db: &Database,
+  user_id: Uuid,
+  id: Uuid,
+) -> Result<DataExport, ApiError> {
+  db.get_data_export(id)
+    .await?
+    .filter(|data_export| data_export.user_id == user_id)
+    .ok_or(ApiError::DataExportNotFound)
+}
+
+#[instrument(name = "GET /api/user/notification_preferences", skip(req))]
+pub async fn get_notification_preferences(
+  req: Request<Body>,
+) -> ApiResult<ApiNotificationPreferences> {
+  let iam = req.iam();
+  let current_user = iam.check_current_user_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let preferences = db.get_notification_preferences(current_user.id).await?;
+
+  Ok(preferences.into())
+}
+
+#[instrument(name = "PATCH /api/user/notification_preferences", skip(req))]
+pub async fn update_notification_preferences(
+  mut req: Request<Body>,
+) -> ApiResult<ApiNotificationPreferences> {
+  let ApiUpdateNotificationPreferencesRequest {
+    scope_invites,
+    publish_failures,
+    scope_digests,
+  } = decode_json(&mut req).await?;
+
+  let iam = req.iam();
+  let current_user = iam.check_current_user_access()?;
+
+  let db = req.data::<Database>().unwrap();
+  let preferences = db
+    .update_notification_preferences(
+      current_user.id,
+      scope_invites,
+      publish_failures,
+      scope_digests,
+    )
+    .await?;
+
+  Ok(preferences.into())
+}
+
+fn unsubscribe_params(
+  req: &Request<Body>,
+) -> Result<(Uuid, NotificationKind), ApiError> {
+  let token = req
+    .query("token")
+    .and_then(|token| Uuid::parse_str(token).ok())
+    .ok_or(ApiError::UnsubscribeTokenNotFound)?;
+  let kind = req
+    .query("notification")
+    .and_then(|kind| kind.parse::<NotificationKind>().ok())
+    .ok_or_else(|| ApiError::MalformedRequest {
+      msg: "invalid 'notification' query parameter, expected one of: \
+        scopeInvites, publishFailures, scopeDigests"
+        .into(),
+    })?;
+  Span::current().record("notification", field::debug(&kind));
+  Ok((token, kind))
+}
+
+/// Renders the page that the unsubscribe link in an email opens. Following the
+/// link does not change anything, as mail scanners and link previews follow
+/// links too: the page asks the user to confirm, which sends the `POST`.
+#[instrument(
+  name = "GET /api/unsubscribe",
+  skip(req),
+  err,
+  fields(notification)
+)]
+pub async fn unsubscribe_page_handler(
+  req: Request<Body>,
+) -> ApiResult<Response<Body>> {
+  let (token, kind) = unsubscribe_params(&req)?;
+
+  let db = req.data::<Database>().unwrap();
+  db.get_notification_preferences_by_unsubscribe_token(token)
+    .await?
+    .ok_or(ApiError::UnsubscribeTokenNotFound)?;
+
+  let description = kind.description();
+  let body = format!(
+    r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Unsubscribe</title>
+</head>
+<body>
+<form method="post" action="unsubscribe?token={token}&amp;notification={}">
+<p>Stop receiving {description} emails?</p>
+<button type="submit">Unsubscribe</button>
+</form>
+</body>
+</html>
+"#,
+    kind.as_str(),
+  );
+
+  let res = Response::builder()
+    .status(StatusCode::OK)
+    .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+    .header(header::CACHE_CONTROL, "no-store")
+    .body(Body::from(body))
+    .unwrap();
+  Ok(res)
+}
+
+/// Turns off a kind of notification email for the user that the `token` of an
+/// unsubscribe link belongs to. This does not require signing in, and serves
+/// both one-click unsubscribing from email clients (RFC 8058) and the
+/// confirmation on the unsubscribe page.
+#[instrument(name = "POST /api/unsubscribe", skip(req), fields(notification))]
+pub async fn unsubscribe_handler(
+  req: Request<Body>,
+) -> ApiResult<ApiNotificationPreferences> {
+  let (token, kind) = unsubscribe_params(&req)?;
+
+  let off = |k: NotificationKind| (k == kind).then_some(false);
+  let db = req.data::<Database>().unwrap();
+  let preferences = db
+    .unsubscribe_notifications(
+      token,
+      off(NotificationKind::ScopeInvites),
+      off(NotificationKind::PublishFailures),
+      off(NotificationKind::ScopeDigests),
+    )
+    .await?
+    .ok_or(ApiError::UnsubscribeTokenNotFound)?;
+
+  Ok(preferences.into())
+}
+
 #[cfg(test)]
 mod tests {
   use hyper::StatusCode;
   use serde_json::json;
 
   use crate::api::ApiCreatedToken;
+  use crate::api::ApiDataExport;
   use crate::api::ApiFullUser;
+  use crate::api::ApiNotificationPreferences;
+  use crate::api::ApiSignedUrl;
   use crate::api::ApiToken;
   use crate::api::ApiTokenType;
+  use crate::db::DataExportStatus;
   use crate::util::test::ApiResultExt;
   use crate::util::test::TestSetup;
 
@@ -468,4 +955,552 @@ mod tests {
       .expect_err_code(StatusCode::UNAUTHORIZED, "invalidBearerToken")
       .await;
   }
+
+  #[tokio::test]
+  async fn read_only_token() {
+    let mut t = TestSetup::new().await;
+
+    // the read permission can't be combined with others
+    t.http()
+      .post("/api/user/tokens")
+      .body_json(json!({
+        "description": "test token",
+        "expiresAt": null,
+        "permissions": [
+          { "permission": "read" },
+          { "permission": "package/publish", "scope": "scope" }
+        ]
+      }))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+
+    let token: ApiCreatedToken = t
+      .http()
+      .post("/api/user/tokens")
+      .body_json(json!({
+        "description": "test token",
+        "expiresAt": null,
+        "permissions": [{ "permission": "read" }]
+      }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    assert!(token.token.last_used_at.is_none());
+    let secret = token.secret;
+
+    let user: ApiFullUser = t
+      .http()
+      .get("/api/user")
+      .token(Some(&secret))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    assert_eq!(user.id, t.user1.user.id);
+
+    // can't change anything with this token
+    t.http()
+      .post("/api/scopes")
+      .token(Some(&secret))
+      .body_json(json!({ "scope": "scope2" }))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::FORBIDDEN, "missingPermission")
+      .await;
+    t.http()
+      .delete(format!("/api/user/tokens/{}", token.token.id))
+      .token(Some(&secret))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::FORBIDDEN, "missingPermission")
+      .await;
+
+    let token: ApiToken = t
+      .http()
+      .get(format!("/api/user/tokens/{}", token.token.id))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    assert!(token.last_used_at.is_some());
+  }
+
+  #[tokio::test]
+  async fn package_list_token() {
+    let mut t = TestSetup::new().await;
+
+    let token: ApiCreatedToken = t
+      .http()
+      .post("/api/user/tokens")
+      .body_json(json!({
+        "description": "test token",
+        "expiresAt": null,
+        "permissions": [
+          { "permission": "package/publish", "scope": "scope", "package": "foo" },
+          { "permission": "package/publish", "scope": "scope", "package": "bar" }
+        ]
+      }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    assert_eq!(token.token.permissions.unwrap().0.len(), 2);
+
+    // publish tokens can't read the account
+    t.http()
+      .get("/api/user")
+      .token(Some(&token.secret))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::FORBIDDEN, "missingPermission")
+      .await;
+
+    t.http()
+      .post("/api/user/tokens")
+      .body_json(json!({
+        "description": "test token",
+        "expiresAt": null,
+        "permissions": []
+      }))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn update_token() {
+    let mut t = TestSetup::new().await;
+
+    t.http()
+      .post("/api/user/tokens")
+      .body_json(json!({
+        "description": "test token",
+        "expiresAt": chrono::Utc::now() - chrono::Duration::days(1),
+        "permissions": null
+      }))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+
+    let token: ApiCreatedToken = t
+      .http()
+      .post("/api/user/tokens")
+      .body_json(json!({
+        "description": "test token",
+        "expiresAt": chrono::Utc::now() + chrono::Duration::days(1),
+        "permissions": null
+      }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    let id = token.token.id;
+
+    let expires_at = chrono::Utc::now() + chrono::Duration::days(30);
+    let token: ApiToken = t
+      .http()
+      .patch(format!("/api/user/tokens/{id}"))
+      .body_json(json!({ "description": "renamed", "expiresAt": expires_at }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    assert_eq!(token.description.as_deref(), Some("renamed"));
+    assert_eq!(
+      token.expires_at.unwrap().timestamp(),
+      expires_at.timestamp()
+    );
+
+    // leaving out a field keeps it as is
+    let token: ApiToken = t
+      .http()
+      .patch(format!("/api/user/tokens/{id}"))
+      .body_json(json!({ "description": "renamed again" }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    assert_eq!(token.description.as_deref(), Some("renamed again"));
+    assert!(token.expires_at.is_some());
+
+    t.http()
+      .patch(format!("/api/user/tokens/{id}"))
+      .body_json(json!({
+        "expiresAt": chrono::Utc::now() - chrono::Duration::days(1)
+      }))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+
+    // sessions can't be updated
+    let tokens: Vec<ApiToken> = t
+      .http()
+      .get("/api/user/tokens")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    let session = tokens
+      .iter()
+      .find(|token| matches!(token.r#type, ApiTokenType::Web))
+      .unwrap();
+    t.http()
+      .patch(format!("/api/user/tokens/{}", session.id))
+      .body_json(json!({ "description": "session" }))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+
+    t.http()
+      .get(format!("/api/user/tokens/{}", uuid::Uuid::new_v4()))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "tokenNotFound")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn rotate_token() {
+    let mut t = TestSetup::new().await;
+
+    let token: ApiCreatedToken = t
+      .http()
+      .post("/api/user/tokens")
+      .body_json(json!({
+        "description": "test token",
+        "expiresAt": null,
+        "permissions": null
+      }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    let id = token.token.id;
+    let old_secret = token.secret;
+    assert!(token.token.rotated_at.is_none());
+
+    t.http()
+      .post(format!("/api/user/tokens/{id}/rotate"))
+      .body_json(json!({ "gracePeriodHours": 1000 }))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+
+    let rotated: ApiCreatedToken = t
+      .http()
+      .post(format!("/api/user/tokens/{id}/rotate"))
+      .body_json(json!({}))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    assert_eq!(rotated.token.id, id);
+    assert!(rotated.token.rotated_at.is_some());
+    assert_ne!(rotated.secret, old_secret);
+
+    // both secrets work during the grace period
+    for secret in [&old_secret, &rotated.secret] {
+      let user: ApiFullUser = t
+        .http()
+        .get("/api/user")
+        .token(Some(secret))
+        .call()
+        .await
+        .unwrap()
+        .expect_ok()
+        .await;
+      assert_eq!(user.id, t.user1.user.id);
+    }
+
+    // rotating again without a grace period only keeps the new secret
+    let rotated_again: ApiCreatedToken = t
+      .http()
+      .post(format!("/api/user/tokens/{id}/rotate"))
+      .body_json(json!({ "gracePeriodHours": 0 }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    for secret in [&old_secret, &rotated.secret] {
+      t.http()
+        .get("/api/user")
+        .token(Some(secret))
+        .call()
+        .await
+        .unwrap()
+        .expect_err_code(StatusCode::UNAUTHORIZED, "invalidBearerToken")
+        .await;
+    }
+    t.http()
+      .get("/api/user")
+      .token(Some(&rotated_again.secret))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiFullUser>()
+      .await;
+
+    // sessions can't be rotated
+    let tokens: Vec<ApiToken> = t
+      .http()
+      .get("/api/user/tokens")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    let session = tokens
+      .iter()
+      .find(|token| matches!(token.r#type, ApiTokenType::Web))
+      .unwrap();
+    t.http()
+      .post(format!("/api/user/tokens/{}/rotate", session.id))
+      .body_json(json!({}))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn data_export() {
+    let mut t = TestSetup::new().await;
+
+    let data_export: ApiDataExport = t
+      .http()
+      .post("/api/user/data_exports")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    assert_eq!(data_export.status, DataExportStatus::Pending);
+
+    // Only one export can be pending at a time.
+    t.http()
+      .post("/api/user/data_exports")
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::CONFLICT, "dataExportInProgress")
+      .await;
+    t.http()
+      .get(format!(
+        "/api/user/data_exports/{}/download",
+        data_export.id
+      ))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::CONFLICT, "dataExportNotReady")
+      .await;
+
+    crate::data_export::build_data_export(
+      &t.db(),
+      &t.buckets(),
+      data_export.id,
+    )
+    .await
+    .unwrap();
+
+    let data_export: ApiDataExport = t
+      .http()
+      .get(format!("/api/user/data_exports/{}", data_export.id))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    assert_eq!(data_export.status, DataExportStatus::Completed);
+    assert!(data_export.size.unwrap() > 0);
+    assert!(data_export.expires_at.is_some());
+
+    let signed: ApiSignedUrl = t
+      .http()
+      .get(format!(
+        "/api/user/data_exports/{}/download",
+        data_export.id
+      ))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    let resp = crate::util::shared_http_client()
+      .get(&signed.url)
+      .send()
+      .await
+      .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bytes = resp.bytes().await.unwrap();
+    let mut archive =
+      tar::Archive::new(flate2::read::GzDecoder::new(&bytes[..]));
+    let mut paths = archive
+      .entries()
+      .unwrap()
+      .map(|entry| {
+        let entry = entry.unwrap();
+        entry.path().unwrap().file_name().unwrap().to_owned()
+      })
+      .collect::<Vec<_>>();
+    paths.sort();
+    assert_eq!(
+      paths,
+      [
+        "audit_logs.json",
+        "package_versions.json",
+        "profile.json",
+        "scope_memberships.json",
+        "tokens.json",
+      ]
+    );
+
+    // Exports of other users are not visible.
+    let token = t.user2.token.clone();
+    t.http()
+      .get(format!("/api/user/data_exports/{}", data_export.id))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "dataExportNotFound")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn notification_preferences() {
+    let mut t = TestSetup::new().await;
+
+    let preferences: ApiNotificationPreferences = t
+      .http()
+      .get("/api/user/notification_preferences")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    assert!(preferences.scope_invites);
+    assert!(preferences.publish_failures);
+    assert!(preferences.scope_digests);
+
+    let preferences: ApiNotificationPreferences = t
+      .http()
+      .patch("/api/user/notification_preferences")
+      .body_json(json!({ "publishFailures": false }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    assert!(preferences.scope_invites);
+    assert!(!preferences.publish_failures);
+    assert!(preferences.scope_digests);
+
+    // Unsubscribe links work without signing in.
+    let token = t
+      .db()
+      .get_notification_preferences(t.user1.user.id)
+      .await
+      .unwrap()
+      .unsubscribe_token;
+
+    // Following the link only asks to confirm.
+    let mut resp = t
+      .http()
+      .get(format!(
+        "/api/unsubscribe?token={token}&notification=scopeDigests"
+      ))
+      .token(None)
+      .call()
+      .await
+      .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(resp.body_mut()).await.unwrap();
+    let page = std::str::from_utf8(&body).unwrap();
+    assert!(page.contains(r#"<form method="post""#));
+    assert!(page.contains("scope digest emails"));
+    let preferences = t
+      .db()
+      .get_notification_preferences(t.user1.user.id)
+      .await
+      .unwrap();
+    assert!(preferences.scope_digests);
+
+    t.http()
+      .get(format!(
+        "/api/unsubscribe?token={}&notification=scopeDigests",
+        uuid::Uuid::new_v4()
+      ))
+      .token(None)
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "unsubscribeTokenNotFound")
+      .await;
+
+    let preferences: ApiNotificationPreferences = t
+      .http()
+      .post(format!(
+        "/api/unsubscribe?token={token}&notification=scopeDigests"
+      ))
+      .token(None)
+      .call()
+      .await
+      .unwrap()
+      .expect_ok()
+      .await;
+    assert!(preferences.scope_invites);
+    assert!(!preferences.publish_failures);
+    assert!(!preferences.scope_digests);
+
+    t.http()
+      .post(format!(
+        "/api/unsubscribe?token={}&notification=scopeDigests",
+        uuid::Uuid::new_v4()
+      ))
+      .token(None)
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "unsubscribeTokenNotFound")
+      .await;
+    t.http()
+      .post(format!("/api/unsubscribe?token={token}&notification=all"))
+      .token(None)
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+  }
 }
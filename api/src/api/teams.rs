@@ -0,0 +1,718 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+//! Teams group members of a scope, and grant them permissions on specific
+//! packages of the scope. How the permissions are enforced is described in
+//! [`crate::iam::IamHandler::check_package_permission`].
+
+use hyper::Body;
+use hyper::Request;
+use hyper::Response;
+use hyper::StatusCode;
+use routerify::Router;
+use routerify::ext::RequestExt;
+use tracing::Span;
+use tracing::field;
+use tracing::instrument;
+
+use crate::db::Database;
+use crate::db::PackageTeamAccess;
+use crate::iam::ReqIamExt;
+use crate::ids::ScopeName;
+use crate::util;
+use crate::util::ApiResult;
+use crate::util::RequestIdExt;
+use crate::util::decode_json;
+use crate::util::param;
+
+use super::ApiCreateScopeTeamRequest;
+use super::ApiError;
+use super::ApiScopeTeam;
+use super::ApiScopeTeamMember;
+use super::ApiScopeTeamPackage;
+use super::ApiSetScopeTeamPackageRequest;
+use super::ApiUpdateScopeTeamRequest;
+use super::map_unique_violation;
+
+const MAX_TEAM_NAME_LENGTH: usize = 32;
+const MAX_DESCRIPTION_LENGTH: usize = 250;
+
+pub fn teams_router() -> Router<Body, ApiError> {
+  Router::builder()
+    .get("/", util::auth(util::json(list_handler)))
+    .post("/", util::auth(util::json(create_handler)))
+    .get("/:team", util::auth(util::json(get_handler)))
+    .patch("/:team", util::auth(util::json(update_handler)))
+    .delete("/:team", util::auth(delete_handler))
+    .get(
+      "/:team/members",
+      util::auth(util::json(list_members_handler)),
+    )
+    .put(
+      "/:team/members/:user_id",
+      util::auth(util::json(add_member_handler)),
+    )
+    .delete("/:team/members/:user_id", util::auth(remove_member_handler))
+    .get(
+      "/:team/packages",
+      util::auth(util::json(list_packages_handler)),
+    )
+    .put(
+      "/:team/packages/:package",
+      util::auth(util::json(set_package_handler)),
+    )
+    .delete(
+      "/:team/packages/:package",
+      util::auth(delete_package_handler),
+    )
+    .build()
+    .unwrap()
+}
+
+fn validate_team_name(name: &str) -> Result<(), ApiError> {
+  let valid = !name.is_empty()
+    && name.len() <= MAX_TEAM_NAME_LENGTH
+    && !name.starts_with('-')
+    && name
+      .chars()
+      .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+  if !valid {
+    return Err(ApiError::ScopeTeamInvalid {
+      msg: format!(
+        "the name must be at most {MAX_TEAM_NAME_LENGTH} characters long, contain only lowercase letters, digits and hyphens, and not start with a hyphen"
+      )
+      .into(),
+    });
+  }
+  Ok(())
+}
+
+fn validate_description(description: &str) -> Result<(), ApiError> {
+  if description.len() > MAX_DESCRIPTION_LENGTH {
+    return Err(ApiError::ScopeTeamInvalid {
+      msg: format!(
+        "the description must be at most {MAX_DESCRIPTION_LENGTH} characters long"
+      )
+      .into(),
+    });
+  }
+  Ok(())
+}
+
+/// Returns the team from the `:team` parameter, if it exists in the scope.
+async fn param_team(
+  req: &Request<Body>,
+  scope: &ScopeName,
+) -> Result<String, ApiError> {
+  let team = param(req, "team")?;
+  Span::current().record("team", field::display(team));
+  let db = req.data::<Database>().unwrap();
+  db.get_scope_team(scope, team)
+    .await?
+    .ok_or(ApiError::ScopeTeamNotFound)?;
+  Ok(team.clone())
+}
+
+#[instrument(name = "GET /api/scopes/:scope/teams", skip(req), fields(scope))]
+async fn list_handler(req: Request<Body>) -> ApiResult<Vec<ApiScopeTeam>> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+
+  let db = req.data::<Database>().unwrap();
+  db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?;
+
+  let iam = req.iam();
+  iam.check_scope_write_access(&scope).await?;
+
+  let teams = db.list_scope_teams(&scope).await?;
+  Ok(teams.into_iter().map(ApiScopeTeam::from).collect())
+}
+
+#[instrument(
+  name = "POST /api/scopes/:scope/teams",
+  skip(req),
+  fields(scope, team)
+)]
+async fn create_handler(mut req: Request<Body>) -> ApiResult<ApiScopeTeam> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+
+  let ApiCreateScopeTeamRequest { name, description } =
+    decode_json(&mut req).await?;
+  Span::current().record("team", field::display(&name));
+  validate_team_name(&name)?;
+  validate_description(&description)?;
+
+  let db = req.data::<Database>().unwrap();
+  db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?;
+
+  let iam = req.iam();
+  let (user, sudo) = iam.check_scope_admin_access(&scope).await?;
+
+  let team = db
+    .create_scope_team(&user.id, sudo, &scope, &name, &description)
+    .await
+    .map_err(|e| map_unique_violation(e, ApiError::ScopeTeamAlreadyExists))?;
+
+  Ok(team.into())
+}
+
+#[instrument(
+  name = "GET /api/scopes/:scope/teams/:team",
+  skip(req),
+  fields(scope, team)
+)]
+async fn get_handler(req: Request<Body>) -> ApiResult<ApiScopeTeam> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+
+  let iam = req.iam();
+  iam.check_scope_write_access(&scope).await?;
+
+  let team = param(&req, "team")?;
+  Span::current().record("team", field::display(team));
+  let db = req.data::<Database>().unwrap();
+  let team = db
+    .get_scope_team(&scope, team)
+    .await?
+    .ok_or(ApiError::ScopeTeamNotFound)?;
+
+  Ok(team.into())
+}
+
+#[instrument(
+  name = "PATCH /api/scopes/:scope/teams/:team",
+  skip(req),
+  fields(scope, team)
+)]
+async fn update_handler(mut req: Request<Body>) -> ApiResult<ApiScopeTeam> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+
+  let ApiUpdateScopeTeamRequest { description } = decode_json(&mut req).await?;
+  validate_description(&description)?;
+
+  let iam = req.iam();
+  let (user, sudo) = iam.check_scope_admin_access(&scope).await?;
+
+  let team = param(&req, "team")?;
+  Span::current().record("team", field::display(team));
+  let db = req.data::<Database>().unwrap();
+  let team = db
+    .update_scope_team(&user.id, sudo, &scope, team, &description)
+    .await?
+    .ok_or(ApiError::ScopeTeamNotFound)?;
+
+  Ok(team.into())
+}
+
+#[instrument(
+  name = "DELETE /api/scopes/:scope/teams/:team",
+  skip(req),
+  fields(scope, team)
+)]
+async fn delete_handler(req: Request<Body>) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+
+  let iam = req.iam();
+  let (user, sudo) = iam.check_scope_admin_access(&scope).await?;
+
+  let team = param(&req, "team")?;
+  Span::current().record("team", field::display(team));
+  let db = req.data::<Database>().unwrap();
+  db.delete_scope_team(&user.id, sudo, &scope, team)
+    .await?
+    .ok_or(ApiError::ScopeTeamNotFound)?;
+
+  let resp = Response::builder()
+    .status(StatusCode::NO_CONTENT)
+    .body(Body::empty())
+    .unwrap();
+  Ok(resp)
+}
+
+#[instrument(
+  name = "GET /api/scopes/:scope/teams/:team/members",
+  skip(req),
+  fields(scope, team)
+)]
+async fn list_members_handler(
+  req: Request<Body>,
+) -> ApiResult<Vec<ApiScopeTeamMember>> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+
+  let iam = req.iam();
+  iam.check_scope_write_access(&scope).await?;
+
+  let team = param_team(&req, &scope).await?;
+  let db = req.data::<Database>().unwrap();
+  let members = db.list_scope_team_members(&scope, &team).await?;
+  Ok(members.into_iter().map(ApiScopeTeamMember::from).collect())
+}
+
+#[instrument(
+  name = "PUT /api/scopes/:scope/teams/:team/members/:user_id",
+  skip(req),
+  fields(scope, team, user_id)
+)]
+async fn add_member_handler(
+  req: Request<Body>,
+) -> ApiResult<ApiScopeTeamMember> {
+  let scope = req.param_scope()?;
+  let user_id = req.param_uuid("user_id")?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("user_id", field::display(&user_id));
+
+  let iam = req.iam();
+  let (user, sudo) = iam.check_scope_admin_access(&scope).await?;
+
+  let team = param_team(&req, &scope).await?;
+  let db = req.data::<Database>().unwrap();
+  // Only members of the scope can be in its teams.
+  db.get_scope_member(&scope, user_id)
+    .await?
+    .ok_or(ApiError::ScopeMemberNotFound)?;
+  let target_user = db
+    .get_user_public(user_id)
+    .await?
+    .ok_or(ApiError::UserNotFound)?;
+
+  let member = db
+    .add_scope_team_member(&user.id, sudo, &scope, &team, user_id)
+    .await?;
+
+  Ok((member, target_user).into())
+}
+
+#[instrument(
+  name = "DELETE /api/scopes/:scope/teams/:team/members/:user_id",
+  skip(req),
+  fields(scope, team, user_id)
+)]
+async fn remove_member_handler(
+  req: Request<Body>,
+) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  let user_id = req.param_uuid("user_id")?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("user_id", field::display(&user_id));
+
+  let iam = req.iam();
+  let (user, sudo) = iam.check_scope_admin_access(&scope).await?;
+
+  let team = param_team(&req, &scope).await?;
+  let db = req.data::<Database>().unwrap();
+  let removed = db
+    .remove_scope_team_member(&user.id, sudo, &scope, &team, user_id)
+    .await?;
+  if !removed {
+    return Err(ApiError::ScopeTeamMemberNotFound);
+  }
+
+  let resp = Response::builder()
+    .status(StatusCode::NO_CONTENT)
+    .body(Body::empty())
+    .unwrap();
+  Ok(resp)
+}
+
+#[instrument(
+  name = "GET /api/scopes/:scope/teams/:team/packages",
+  skip(req),
+  fields(scope, team)
+)]
+async fn list_packages_handler(
+  req: Request<Body>,
+) -> ApiResult<Vec<ApiScopeTeamPackage>> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+
+  let iam = req.iam();
+  iam.check_scope_write_access(&scope).await?;
+
+  let team = param_team(&req, &scope).await?;
+  let db = req.data::<Database>().unwrap();
+  let packages = db.list_scope_team_packages(&scope, &team).await?;
+  Ok(
+    packages
+      .into_iter()
+      .map(ApiScopeTeamPackage::from)
+      .collect(),
+  )
+}
+
+#[instrument(
+  name = "PUT /api/scopes/:scope/teams/:team/packages/:package",
+  skip(req),
+  fields(scope, team, package)
+)]
+async fn set_package_handler(
+  mut req: Request<Body>,
+) -> ApiResult<ApiScopeTeamPackage> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+
+  let ApiSetScopeTeamPackageRequest {
+    can_publish,
+    can_yank,
+    can_manage_tokens,
+  } = decode_json(&mut req).await?;
+
+  let iam = req.iam();
+  let (user, sudo) = iam.check_scope_admin_access(&scope).await?;
+
+  let team = param_team(&req, &scope).await?;
+  let db = req.data::<Database>().unwrap();
+  db.get_package(&scope, &package)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+
+  let access = PackageTeamAccess {
+    can_publish,
+    can_yank,
+    can_manage_tokens,
+  };
+  let team_package = db
+    .set_scope_team_package(&user.id, sudo, &scope, &team, &package, access)
+    .await?;
+
+  Ok(team_package.into())
+}
+
+#[instrument(
+  name = "DELETE /api/scopes/:scope/teams/:team/packages/:package",
+  skip(req),
+  fields(scope, team, package)
+)]
+async fn delete_package_handler(
+  req: Request<Body>,
+) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+
+  let iam = req.iam();
+  let (user, sudo) = iam.check_scope_admin_access(&scope).await?;
+
+  let team = param_team(&req, &scope).await?;
+  let db = req.data::<Database>().unwrap();
+  let deleted = db
+    .delete_scope_team_package(&user.id, sudo, &scope, &team, &package)
+    .await?;
+  if !deleted {
+    return Err(ApiError::ScopeTeamPackageNotFound);
+  }
+
+  let resp = Response::builder()
+    .status(StatusCode::NO_CONTENT)
+    .body(Body::empty())
+    .unwrap();
+  Ok(resp)
+}
+
+/// Lists the teams that were granted permissions on a package. A package that
+/// is not in any team may be published by all members of the scope.
+#[instrument(
+  name = "GET /api/scopes/:scope/packages/:package/teams",
+  skip(req),
+  fields(scope, package)
+)]
+pub async fn list_package_teams_handler(
+  req: Request<Body>,
+) -> ApiResult<Vec<ApiScopeTeamPackage>> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+
+  let db = req.data::<Database>().unwrap();
+  db.get_package(&scope, &package)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+
+  let iam = req.iam();
+  iam.check_scope_write_access(&scope).await?;
+
+  let teams = db.list_package_teams(&scope, &package).await?;
+  Ok(teams.into_iter().map(ApiScopeTeamPackage::from).collect())
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::StatusCode;
+  use serde_json::json;
+
+  use crate::api::ApiScopeTeam;
+  use crate::api::ApiScopeTeamMember;
+  use crate::api::ApiScopeTeamPackage;
+  use crate::db::NewScopeMember;
+  use crate::db::PublishingTaskStatus;
+  use crate::ids::ScopeName;
+  use crate::publish::tests::create_mock_tarball;
+  use crate::publish::tests::process_tarball_setup;
+  use crate::util::test::ApiResultExt;
+  use crate::util::test::TestSetup;
+
+  #[test]
+  fn validate_team_name() {
+    for name in ["core", "release-team", "a", "team2"] {
+      assert!(super::validate_team_name(name).is_ok(), "{name}");
+    }
+    for name in [
+      "",
+      "Core",
+      "-team",
+      "team_1",
+      "a.b",
+      "a".repeat(33).as_str(),
+    ] {
+      assert!(super::validate_team_name(name).is_err(), "{name}");
+    }
+  }
+
+  #[tokio::test]
+  async fn teams() {
+    let mut t = TestSetup::new().await;
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:?}");
+
+    let scope = ScopeName::try_from("scope").unwrap();
+    t.db()
+      .add_user_to_scope(NewScopeMember {
+        scope: &scope,
+        user_id: t.user2.user.id,
+        is_admin: false,
+      })
+      .await
+      .unwrap();
+
+    let token1 = t.user1.token.clone();
+    let token2 = t.user2.token.clone();
+    let user2_id = t.user2.user.id;
+
+    // Only admins can manage teams.
+    t.http()
+      .post("/api/scopes/scope/teams")
+      .body_json(json!({ "name": "release" }))
+      .token(Some(&token2))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::FORBIDDEN, "actorNotScopeAdmin")
+      .await;
+    t.http()
+      .post("/api/scopes/scope/teams")
+      .body_json(json!({ "name": "Release" }))
+      .token(Some(&token1))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "scopeTeamInvalid")
+      .await;
+
+    let team = t
+      .http()
+      .post("/api/scopes/scope/teams")
+      .body_json(json!({ "name": "release", "description": "Releasers" }))
+      .token(Some(&token1))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiScopeTeam>()
+      .await;
+    assert_eq!(team.name, "release");
+    t.http()
+      .post("/api/scopes/scope/teams")
+      .body_json(json!({ "name": "release" }))
+      .token(Some(&token1))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::CONFLICT, "scopeTeamAlreadyExists")
+      .await;
+    t.http()
+      .post("/api/scopes/scope/teams")
+      .body_json(json!({ "name": "docs" }))
+      .token(Some(&token1))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiScopeTeam>()
+      .await;
+
+    // Without any teams on the package, members can publish but not yank.
+    t.http()
+      .patch("/api/scopes/scope/packages/foo")
+      .body_json(json!({ "description": "before teams" }))
+      .token(Some(&token2))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<serde_json::Value>()
+      .await;
+    t.http()
+      .patch("/api/scopes/scope/packages/foo/versions/1.2.3")
+      .body_json(json!({ "yanked": true }))
+      .token(Some(&token2))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::FORBIDDEN, "actorNotScopeAdmin")
+      .await;
+
+    // Restrict the package to the docs team, which user2 is not in.
+    let team_package = t
+      .http()
+      .put("/api/scopes/scope/teams/docs/packages/foo")
+      .body_json(json!({ "canPublish": true }))
+      .token(Some(&token1))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiScopeTeamPackage>()
+      .await;
+    assert!(team_package.can_publish);
+    assert!(!team_package.can_yank);
+
+    let err = t
+      .http()
+      .patch("/api/scopes/scope/packages/foo")
+      .body_json(json!({ "description": "restricted" }))
+      .token(Some(&token2))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::FORBIDDEN, "actorMissingPackagePermission")
+      .await;
+    assert_eq!(err.data, json!({ "permission": "publish" }));
+    t.http()
+      .put("/api/scopes/scope/packages/foo/dist_tags/beta")
+      .body_json(json!({ "version": "1.2.3" }))
+      .token(Some(&token2))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::FORBIDDEN, "actorMissingPackagePermission")
+      .await;
+
+    // Admins are not restricted by teams.
+    t.http()
+      .patch("/api/scopes/scope/packages/foo")
+      .body_json(json!({ "description": "by an admin" }))
+      .token(Some(&token1))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<serde_json::Value>()
+      .await;
+
+    // Grant user2 publishing and yanking through the release team.
+    t.http()
+      .put("/api/scopes/scope/teams/release/packages/foo")
+      .body_json(json!({ "canPublish": true, "canYank": true }))
+      .token(Some(&token1))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiScopeTeamPackage>()
+      .await;
+    let member = t
+      .http()
+      .put(&format!(
+        "/api/scopes/scope/teams/release/members/{user2_id}"
+      ))
+      .token(Some(&token1))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiScopeTeamMember>()
+      .await;
+    assert_eq!(member.user.id, user2_id);
+    let user3_id = t.user3.user.id;
+    t.http()
+      .put(&format!(
+        "/api/scopes/scope/teams/release/members/{user3_id}"
+      ))
+      .token(Some(&token1))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "scopeMemberNotFound")
+      .await;
+
+    let members = t
+      .http()
+      .get("/api/scopes/scope/teams/release/members")
+      .token(Some(&token2))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<Vec<ApiScopeTeamMember>>()
+      .await;
+    assert_eq!(members.len(), 1);
+
+    t.http()
+      .patch("/api/scopes/scope/packages/foo/versions/1.2.3")
+      .body_json(json!({ "yanked": true }))
+      .token(Some(&token2))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok_no_content()
+      .await;
+    t.http()
+      .patch("/api/scopes/scope/packages/foo")
+      .body_json(json!({ "githubRepository": null }))
+      .token(Some(&token2))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::FORBIDDEN, "actorMissingPackagePermission")
+      .await;
+
+    let package_teams = t
+      .http()
+      .get("/api/scopes/scope/packages/foo/teams")
+      .token(Some(&token2))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<Vec<ApiScopeTeamPackage>>()
+      .await;
+    assert_eq!(package_teams.len(), 2);
+
+    // Deleting the team takes away its permissions.
+    t.http()
+      .delete("/api/scopes/scope/teams/release")
+      .token(Some(&token1))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok_no_content()
+      .await;
+    t.http()
+      .patch("/api/scopes/scope/packages/foo/versions/1.2.3")
+      .body_json(json!({ "yanked": false }))
+      .token(Some(&token2))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::FORBIDDEN, "actorMissingPackagePermission")
+      .await;
+
+    let teams = t
+      .http()
+      .get("/api/scopes/scope/teams")
+      .token(Some(&token2))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<Vec<ApiScopeTeam>>()
+      .await;
+    assert_eq!(teams.len(), 1);
+    assert_eq!(teams[0].name, "docs");
+  }
+}
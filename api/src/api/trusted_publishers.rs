@@ -0,0 +1,506 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+//! Trusted publishers are the CI projects that may publish a package with the
+//! OpenID Connect token of their job, instead of an access token. Besides the
+//! CI systems known to the registry, scope admins can trust the issuers of
+//! self-hosted CI systems. See [`crate::oidc`].
+
+use hyper::Body;
+use hyper::Request;
+use hyper::Response;
+use hyper::StatusCode;
+use routerify::prelude::RequestExt;
+use tracing::Span;
+use tracing::field;
+use tracing::instrument;
+
+use crate::db::Database;
+use crate::iam::ReqIamExt;
+use crate::oidc;
+use crate::util::ApiResult;
+use crate::util::RequestIdExt;
+use crate::util::decode_json;
+
+use super::ApiCreateScopeOidcIssuerRequest;
+use super::ApiCreateTrustedPublisherRequest;
+use super::ApiError;
+use super::ApiScopeOidcIssuer;
+use super::ApiTrustedPublisher;
+use super::map_unique_violation;
+
+const MAX_OIDC_ISSUERS_PER_SCOPE: usize = 10;
+const MAX_TRUSTED_PUBLISHERS_PER_PACKAGE: usize = 20;
+const MAX_SUBJECT_LENGTH: usize = 256;
+
+#[instrument(
+  name = "GET /api/scopes/:scope/oidc_issuers",
+  skip(req),
+  fields(scope)
+)]
+pub async fn list_oidc_issuers_handler(
+  req: Request<Body>,
+) -> ApiResult<Vec<ApiScopeOidcIssuer>> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+
+  let db = req.data::<Database>().unwrap();
+  db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?;
+
+  let iam = req.iam();
+  iam.check_scope_admin_access(&scope).await?;
+
+  let issuers = db.list_scope_oidc_issuers(&scope).await?;
+  Ok(issuers.into_iter().map(ApiScopeOidcIssuer::from).collect())
+}
+
+#[instrument(
+  name = "POST /api/scopes/:scope/oidc_issuers",
+  skip(req),
+  fields(scope, issuer)
+)]
+pub async fn create_oidc_issuer_handler(
+  mut req: Request<Body>,
+) -> ApiResult<ApiScopeOidcIssuer> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+
+  let ApiCreateScopeOidcIssuerRequest { issuer } =
+    decode_json(&mut req).await?;
+  Span::current().record("issuer", field::display(&issuer));
+  oidc::validate_custom_issuer(&issuer)?;
+
+  let db = req.data::<Database>().unwrap();
+  db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?;
+
+  let iam = req.iam();
+  let (user, sudo) = iam.check_scope_admin_access(&scope).await?;
+
+  if db.list_scope_oidc_issuers(&scope).await?.len()
+    >= MAX_OIDC_ISSUERS_PER_SCOPE
+  {
+    return Err(ApiError::OidcIssuerInvalid {
+      msg: format!(
+        "a scope can trust at most {MAX_OIDC_ISSUERS_PER_SCOPE} issuers"
+      )
+      .into(),
+    });
+  }
+
+  let oidc_issuer = db
+    .create_scope_oidc_issuer(&user.id, sudo, &scope, &issuer)
+    .await
+    .map_err(|e| map_unique_violation(e, ApiError::OidcIssuerAlreadyExists))?;
+
+  Ok(oidc_issuer.into())
+}
+
+#[instrument(
+  name = "DELETE /api/scopes/:scope/oidc_issuers/:id",
+  skip(req),
+  fields(scope, id)
+)]
+pub async fn delete_oidc_issuer_handler(
+  req: Request<Body>,
+) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  let id = req.param_uuid("id")?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("id", field::display(&id));
+
+  let db = req.data::<Database>().unwrap();
+  db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?;
+
+  let iam = req.iam();
+  let (user, sudo) = iam.check_scope_admin_access(&scope).await?;
+
+  db.delete_scope_oidc_issuer(&user.id, sudo, &scope, id)
+    .await?
+    .ok_or(ApiError::OidcIssuerNotFound)?;
+
+  let resp = Response::builder()
+    .status(StatusCode::NO_CONTENT)
+    .body(Body::empty())
+    .unwrap();
+  Ok(resp)
+}
+
+#[instrument(
+  name = "GET /api/scopes/:scope/packages/:package/trusted_publishers",
+  skip(req),
+  fields(scope, package)
+)]
+pub async fn list_handler(
+  req: Request<Body>,
+) -> ApiResult<Vec<ApiTrustedPublisher>> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+
+  let db = req.data::<Database>().unwrap();
+  db.get_package(&scope, &package)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+
+  let iam = req.iam();
+  iam.check_scope_write_access(&scope).await?;
+
+  let trusted_publishers =
+    db.list_package_trusted_publishers(&scope, &package).await?;
+  Ok(
+    trusted_publishers
+      .into_iter()
+      .map(ApiTrustedPublisher::from)
+      .collect(),
+  )
+}
+
+#[instrument(
+  name = "POST /api/scopes/:scope/packages/:package/trusted_publishers",
+  skip(req),
+  fields(scope, package, issuer, subject)
+)]
+pub async fn create_handler(
+  mut req: Request<Body>,
+) -> ApiResult<ApiTrustedPublisher> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+
+  let ApiCreateTrustedPublisherRequest { issuer, subject } =
+    decode_json(&mut req).await?;
+  Span::current().record("issuer", field::display(&issuer));
+  Span::current().record("subject", field::display(&subject));
+  if subject.is_empty() || subject.len() > MAX_SUBJECT_LENGTH {
+    return Err(ApiError::TrustedPublisherInvalid {
+      msg: format!(
+        "the subject must be between 1 and {MAX_SUBJECT_LENGTH} characters long"
+      )
+      .into(),
+    });
+  }
+  oidc::provider_for_issuer(&issuer)
+    .validate_subject(&subject)
+    .map_err(|msg| ApiError::TrustedPublisherInvalid { msg: msg.into() })?;
+
+  let db = req.data::<Database>().unwrap();
+  db.get_package(&scope, &package)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+
+  let iam = req.iam();
+  let (user, sudo) = iam.check_package_admin_access(&scope, &package).await?;
+
+  if !oidc::is_builtin_issuer(&issuer)
+    && !db
+      .list_scope_oidc_issuers(&scope)
+      .await?
+      .iter()
+      .any(|oidc_issuer| oidc_issuer.issuer == issuer)
+  {
+    return Err(ApiError::TrustedPublisherInvalid {
+      msg: "the issuer is not a known CI system, and not trusted by the scope"
+        .into(),
+    });
+  }
+
+  if db
+    .list_package_trusted_publishers(&scope, &package)
+    .await?
+    .len()
+    >= MAX_TRUSTED_PUBLISHERS_PER_PACKAGE
+  {
+    return Err(ApiError::TrustedPublisherInvalid {
+      msg: format!(
+        "a package can have at most {MAX_TRUSTED_PUBLISHERS_PER_PACKAGE} trusted publishers"
+      )
+      .into(),
+    });
+  }
+
+  let trusted_publisher = db
+    .create_package_trusted_publisher(
+      &user.id, sudo, &scope, &package, &issuer, &subject,
+    )
+    .await
+    .map_err(|e| {
+      map_unique_violation(e, ApiError::TrustedPublisherAlreadyExists)
+    })?;
+
+  Ok(trusted_publisher.into())
+}
+
+#[instrument(
+  name = "DELETE /api/scopes/:scope/packages/:package/trusted_publishers/:id",
+  skip(req),
+  fields(scope, package, id)
+)]
+pub async fn delete_handler(req: Request<Body>) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  let package = req.param_package()?;
+  let id = req.param_uuid("id")?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("package", field::display(&package));
+  Span::current().record("id", field::display(&id));
+
+  let db = req.data::<Database>().unwrap();
+  db.get_package(&scope, &package)
+    .await?
+    .ok_or(ApiError::PackageNotFound)?;
+
+  let iam = req.iam();
+  let (user, sudo) = iam.check_package_admin_access(&scope, &package).await?;
+
+  db.delete_package_trusted_publisher(&user.id, sudo, &scope, &package, id)
+    .await?
+    .ok_or(ApiError::TrustedPublisherNotFound)?;
+
+  let resp = Response::builder()
+    .status(StatusCode::NO_CONTENT)
+    .body(Body::empty())
+    .unwrap();
+  Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::StatusCode;
+  use hyper::header::AUTHORIZATION;
+  use serde_json::json;
+
+  use crate::api::ApiScopeOidcIssuer;
+  use crate::api::ApiTrustedPublisher;
+  use crate::db::NewScopeMember;
+  use crate::db::PublishingTaskStatus;
+  use crate::ids::ScopeName;
+  use crate::publish::tests::create_mock_tarball;
+  use crate::publish::tests::process_tarball_setup;
+  use crate::util::test::ApiResultExt;
+  use crate::util::test::TestSetup;
+
+  #[tokio::test]
+  async fn oidc_issuers() {
+    let mut t = TestSetup::new().await;
+
+    let scope = ScopeName::try_from("scope").unwrap();
+    t.db()
+      .add_user_to_scope(NewScopeMember {
+        scope: &scope,
+        user_id: t.user2.user.id,
+        is_admin: false,
+      })
+      .await
+      .unwrap();
+    let token2 = t.user2.token.clone();
+
+    t.http()
+      .post("/api/scopes/scope/oidc_issuers")
+      .body_json(json!({ "issuer": "https://gitlab.example.com" }))
+      .token(Some(&token2))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::FORBIDDEN, "actorNotScopeAdmin")
+      .await;
+    for issuer in ["http://gitlab.example.com", "https://gitlab.com"] {
+      t.http()
+        .post("/api/scopes/scope/oidc_issuers")
+        .body_json(json!({ "issuer": issuer }))
+        .call()
+        .await
+        .unwrap()
+        .expect_err_code(StatusCode::BAD_REQUEST, "oidcIssuerInvalid")
+        .await;
+    }
+
+    let issuer = t
+      .http()
+      .post("/api/scopes/scope/oidc_issuers")
+      .body_json(json!({ "issuer": "https://gitlab.example.com" }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiScopeOidcIssuer>()
+      .await;
+    assert_eq!(issuer.issuer, "https://gitlab.example.com");
+    t.http()
+      .post("/api/scopes/scope/oidc_issuers")
+      .body_json(json!({ "issuer": "https://gitlab.example.com" }))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::CONFLICT, "oidcIssuerAlreadyExists")
+      .await;
+
+    let issuers = t
+      .http()
+      .get("/api/scopes/scope/oidc_issuers")
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<Vec<ApiScopeOidcIssuer>>()
+      .await;
+    assert_eq!(issuers.len(), 1);
+
+    t.http()
+      .delete(format!("/api/scopes/scope/oidc_issuers/{}", issuer.id))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok_no_content()
+      .await;
+    t.http()
+      .delete(format!("/api/scopes/scope/oidc_issuers/{}", issuer.id))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "oidcIssuerNotFound")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn trusted_publishers() {
+    let mut t = TestSetup::new().await;
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:?}");
+
+    let path = "/api/scopes/scope/packages/foo/trusted_publishers";
+
+    let trusted_publisher = t
+      .http()
+      .post(path)
+      .body_json(json!({
+        "issuer": "https://agent.buildkite.com",
+        "subject": "acme/release",
+      }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiTrustedPublisher>()
+      .await;
+    assert_eq!(trusted_publisher.provider, "buildkite");
+    assert_eq!(trusted_publisher.subject, "acme/release");
+    t.http()
+      .post(path)
+      .body_json(json!({
+        "issuer": "https://agent.buildkite.com",
+        "subject": "acme/release",
+      }))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::CONFLICT, "trustedPublisherAlreadyExists")
+      .await;
+
+    // GitHub and GitLab projects are identified by their id, not their name.
+    let github = t
+      .http()
+      .post(path)
+      .body_json(json!({
+        "issuer": "https://token.actions.githubusercontent.com",
+        "subject": "123456",
+      }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiTrustedPublisher>()
+      .await;
+    assert_eq!(github.provider, "github");
+    assert_eq!(github.subject, "123456");
+    t.http()
+      .post(path)
+      .body_json(json!({
+        "issuer": "https://gitlab.com",
+        "subject": "acme/app",
+      }))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "trustedPublisherInvalid")
+      .await;
+
+    // Custom issuers must be trusted by the scope first.
+    t.http()
+      .post(path)
+      .body_json(json!({
+        "issuer": "https://gitlab.example.com",
+        "subject": "project_path:acme/app:ref_type:branch:ref:main",
+      }))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "trustedPublisherInvalid")
+      .await;
+    t.http()
+      .post("/api/scopes/scope/oidc_issuers")
+      .body_json(json!({ "issuer": "https://gitlab.example.com" }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiScopeOidcIssuer>()
+      .await;
+    let custom = t
+      .http()
+      .post(path)
+      .body_json(json!({
+        "issuer": "https://gitlab.example.com",
+        "subject": "project_path:acme/app:ref_type:branch:ref:main",
+      }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiTrustedPublisher>()
+      .await;
+    assert_eq!(custom.provider, "custom");
+
+    let trusted_publishers = t
+      .http()
+      .get(path)
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<Vec<ApiTrustedPublisher>>()
+      .await;
+    assert_eq!(trusted_publishers.len(), 3);
+
+    t.http()
+      .delete(format!("{path}/{}", trusted_publisher.id))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok_no_content()
+      .await;
+    t.http()
+      .delete(format!("{path}/{}", trusted_publisher.id))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "trustedPublisherNotFound")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn oidc_token_from_untrusted_issuer() {
+    let mut t = TestSetup::new().await;
+
+    let token = jsonwebtoken::encode(
+      &jsonwebtoken::Header::default(),
+      &json!({
+        "iss": "https://ci.example.com",
+        "sub": "acme/app",
+        "aud": "jsr.test",
+        "exp": chrono::Utc::now().timestamp() + 300,
+      }),
+      &jsonwebtoken::EncodingKey::from_secret(b"secret"),
+    )
+    .unwrap();
+    t.unauthed_http()
+      .get("/api/user")
+      .header(AUTHORIZATION, format!("oidc {token}").try_into().unwrap())
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::UNAUTHORIZED, "invalidOidcToken")
+      .await;
+  }
+}
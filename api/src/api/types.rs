@@ -1,5 +1,6 @@
 // Copyright 2024 the JSR authors. All rights reserved. MIT license.
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 
 use crate::db::*;
 use crate::docs::GeneratedDocsContent;
@@ -9,8 +10,11 @@ use crate::ids::ScopeDescription;
 use crate::ids::ScopeName;
 use crate::ids::Version;
 use crate::provenance::ProvenanceBundle;
+use crate::publish_limits::PublishLimits;
+use crate::tombstones::TombstoneRetention;
 use chrono::DateTime;
 use chrono::Utc;
+use indexmap::IndexMap;
 use serde::Deserialize;
 use serde::Serialize;
 use uuid::Uuid;
@@ -63,6 +67,9 @@ pub struct ApiPublishingTask {
   pub package_scope: ScopeName,
   pub package_name: PackageName,
   pub package_version: Version,
+  /// The [ApiPublishPreviewDiff] of the publish, once the tarball has been
+  /// analyzed. Only included when a single publishing task is fetched.
+  pub preview_diff: Option<serde_json::Value>,
   pub created_at: DateTime<Utc>,
   pub updated_at: DateTime<Utc>,
 }
@@ -77,12 +84,153 @@ impl From<(PublishingTask, Option<UserPublic>)> for ApiPublishingTask {
       package_scope: value.package_scope,
       package_name: value.package_name,
       package_version: value.package_version,
+      preview_diff: None,
       created_at: value.created_at,
       updated_at: value.updated_at,
     }
   }
 }
 
+/// A resumable upload of the tarball of a publish.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiPublishUpload {
+  pub id: Uuid,
+  pub package_scope: ScopeName,
+  pub package_name: PackageName,
+  pub package_version: Version,
+  /// The number of bytes uploaded so far, which is the offset at which the
+  /// next chunk must be appended.
+  pub offset: i64,
+  pub expires_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<PublishUpload> for ApiPublishUpload {
+  fn from(value: PublishUpload) -> Self {
+    Self {
+      id: value.id,
+      package_scope: value.package_scope,
+      package_name: value.package_name,
+      package_version: value.package_version,
+      offset: value.size,
+      expires_at: value.expires_at,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiCreateWorkspacePublishRequest {
+  /// The publish uploads of the packages to publish, each with its whole
+  /// tarball uploaded but not committed.
+  pub uploads: Vec<Uuid>,
+}
+
+/// A publish of several packages of a workspace that either all publish or
+/// none does.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiWorkspacePublish {
+  pub id: Uuid,
+  pub tasks: Vec<ApiPublishingTask>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<(WorkspacePublish, Vec<(PublishingTask, Option<UserPublic>)>)>
+  for ApiWorkspacePublish
+{
+  fn from(
+    (value, tasks): (
+      WorkspacePublish,
+      Vec<(PublishingTask, Option<UserPublic>)>,
+    ),
+  ) -> Self {
+    Self {
+      id: value.id,
+      tasks: tasks.into_iter().map(Into::into).collect(),
+      created_at: value.created_at,
+    }
+  }
+}
+
+/// A signed URL that allows downloading a private artifact straight from
+/// storage until it expires.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiSignedUrl {
+  pub url: String,
+  pub expires_at: DateTime<Utc>,
+}
+
+/// The outcome of a dry-run publish. Exactly one of `error` and `analysis` is
+/// set.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiPublishDryRun {
+  /// The error the publish would fail with.
+  pub error: Option<ApiPublishingTaskError>,
+  pub analysis: Option<ApiPublishDryRunAnalysis>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiPublishDryRunAnalysis {
+  pub license: String,
+  pub exports: IndexMap<String, String>,
+  pub files: Vec<ApiPublishDryRunFile>,
+  pub dependencies: Vec<ApiDependency>,
+  pub deprecations: Vec<ApiDeprecation>,
+  /// Problems that do not fail the publish, but lower the score of the
+  /// version or the quality of its docs.
+  pub diagnostics: Vec<ApiPublishDiagnostic>,
+  /// The score the package would have with this version as its latest one.
+  pub score: ApiPackageScore,
+  /// The `package.json` of the generated npm tarball.
+  pub npm_package_json: serde_json::Value,
+  pub npm_tarball_size: u64,
+  /// The changes relative to the latest version of the package, if there is
+  /// one.
+  pub preview_diff: Option<ApiPublishPreviewDiff>,
+}
+
+/// The changes a publish makes relative to the latest version of the package.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiPublishPreviewDiff {
+  /// The version the publish is compared against.
+  pub base_version: Version,
+  pub files: ApiPublishFilesDiff,
+  /// The modules and symbols of the exported API that were added, removed or
+  /// changed.
+  pub api: deno_doc::diff::DocDiff,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiPublishFilesDiff {
+  pub added: Vec<PackagePath>,
+  pub removed: Vec<PackagePath>,
+  /// Files whose contents changed.
+  pub modified: Vec<PackagePath>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiPublishDryRunFile {
+  pub path: PackagePath,
+  pub size: u64,
+  pub checksum: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiPublishDiagnostic {
+  pub code: String,
+  pub message: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ApiDependencyGraphItem {
@@ -115,6 +263,13 @@ impl
   }
 }
 
+/// An import map, or the `imports` block of a `deno.json`, depending on the
+/// requested format.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiImportMap {
+  pub imports: IndexMap<String, String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ApiUser {
@@ -224,6 +379,24 @@ pub struct ApiScopeQuotas {
   pub new_package_per_week_limit: i32,
   pub publish_attempts_per_week_usage: i32,
   pub publish_attempts_per_week_limit: i32,
+  /// Total size in bytes of all files stored for packages in the scope.
+  pub storage_usage: i64,
+  pub storage_limit: i64,
+}
+
+impl From<(&Scope, &ScopeUsage)> for ApiScopeQuotas {
+  fn from((scope, scope_usage): (&Scope, &ScopeUsage)) -> Self {
+    Self {
+      package_usage: scope_usage.package,
+      package_limit: scope.package_limit,
+      new_package_per_week_usage: scope_usage.new_package_per_week,
+      new_package_per_week_limit: scope.new_package_per_week_limit,
+      publish_attempts_per_week_usage: scope_usage.publish_attempts_per_week,
+      publish_attempts_per_week_limit: scope.publish_attempts_per_week_limit,
+      storage_usage: scope_usage.storage,
+      storage_limit: scope.storage_limit,
+    }
+  }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -243,20 +416,14 @@ pub struct ApiFullScope {
 impl From<(Scope, ScopeUsage, UserPublic)> for ApiFullScope {
   fn from((scope, scope_usage, user): (Scope, ScopeUsage, UserPublic)) -> Self {
     assert_eq!(scope.creator, user.id);
+    let quotas = (&scope, &scope_usage).into();
     Self {
       scope: scope.scope,
       description: scope.description,
       creator: user.into(),
       updated_at: scope.updated_at,
       created_at: scope.created_at,
-      quotas: ApiScopeQuotas {
-        package_usage: scope_usage.package,
-        package_limit: scope.package_limit,
-        new_package_per_week_usage: scope_usage.new_package_per_week,
-        new_package_per_week_limit: scope.new_package_per_week_limit,
-        publish_attempts_per_week_usage: scope_usage.publish_attempts_per_week,
-        publish_attempts_per_week_limit: scope.publish_attempts_per_week_limit,
-      },
+      quotas,
       gh_actions_verify_actor: scope.verify_oidc_actor,
       require_publishing_from_ci: scope.require_publishing_from_ci,
     }
@@ -344,6 +511,83 @@ impl From<(ScopeInvite, UserPublic, UserPublic)> for ApiScopeInvite {
   }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ApiScoreFactorKind {
+  HasReadme,
+  HasReadmeExamples,
+  AllEntrypointsDocs,
+  PercentageDocumentedSymbols,
+  AllFastCheck,
+  AllExamplesResolve,
+  HasTests,
+  HasProvenance,
+  HasDescription,
+  AtLeastOneRuntimeCompatible,
+  MultipleRuntimesCompatible,
+}
+
+impl ApiScoreFactorKind {
+  /// The maximum number of points this factor contributes to the total.
+  pub fn weight(self, weights: &ScoreWeights) -> u32 {
+    match self {
+      Self::HasReadme => weights.has_readme,
+      Self::HasReadmeExamples => weights.has_readme_examples,
+      Self::AllEntrypointsDocs => weights.all_entrypoints_docs,
+      Self::PercentageDocumentedSymbols => {
+        weights.percentage_documented_symbols
+      }
+      Self::AllFastCheck => weights.all_fast_check,
+      Self::AllExamplesResolve => weights.all_examples_resolve,
+      Self::HasTests => weights.has_tests,
+      Self::HasProvenance => weights.has_provenance,
+      Self::HasDescription => weights.has_description,
+      Self::AtLeastOneRuntimeCompatible => {
+        weights.at_least_one_runtime_compatible
+      }
+      Self::MultipleRuntimesCompatible => weights.multiple_runtimes_compatible,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ApiScoreFactorValue {
+  Bool(bool),
+  Percentage(f32),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiPackageScoreFactor {
+  pub factor: ApiScoreFactorKind,
+  pub weight: u32,
+  /// The number of points achieved for this factor, between 0 and `weight`.
+  pub points: u32,
+  pub value: ApiScoreFactorValue,
+  /// Actionable steps to achieve the full weight of this factor. Empty if all
+  /// points were achieved.
+  pub hints: Vec<String>,
+}
+
+impl ApiPackageScoreFactor {
+  fn from_bool(
+    weights: &ScoreWeights,
+    factor: ApiScoreFactorKind,
+    value: bool,
+    hints: impl FnOnce() -> Vec<String>,
+  ) -> Self {
+    let weight = factor.weight(weights);
+    Self {
+      factor,
+      weight,
+      points: if value { weight } else { 0 },
+      value: ApiScoreFactorValue::Bool(value),
+      hints: if value { vec![] } else { hints() },
+    }
+  }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ApiPackageScore {
@@ -352,6 +596,8 @@ pub struct ApiPackageScore {
   pub all_entrypoints_docs: bool,
   pub percentage_documented_symbols: f32,
   pub all_fast_check: bool,
+  pub all_examples_resolve: bool,
+  pub has_tests: bool,
   pub has_provenance: bool,
 
   // package wide
@@ -359,75 +605,262 @@ pub struct ApiPackageScore {
   pub at_least_one_runtime_compatible: bool,
   pub multiple_runtimes_compatible: bool,
 
+  pub factors: Vec<ApiPackageScoreFactor>,
   pub total: u32,
+  /// The total that maps to a score of 100%.
+  pub max_score: u32,
+  /// Number of test files in the scored version.
+  pub test_files: u32,
+  /// The coverage summary attached to the scored version, if any.
+  pub coverage: Option<CoverageSummary>,
+  /// Documentation coverage of each export of the scored version.
+  pub entrypoint_docs: Vec<EntrypointDocsCoverage>,
+  /// Structure of the readme of the scored version, if it has one.
+  pub readme: Option<ReadmeQuality>,
+  /// The version of the score schema whose weights were used to compute this
+  /// score.
+  pub schema_version: i32,
+  /// The version of the score schema that was active when the scored version
+  /// was published.
+  pub published_schema_version: i32,
 }
 
 impl ApiPackageScore {
-  pub const MAX_SCORE: u32 = 17;
+  /// You only need to document this fraction of your symbols to get all the
+  /// points for [ApiScoreFactorKind::PercentageDocumentedSymbols].
+  const DOCUMENTED_SYMBOLS_THRESHOLD: f32 = 0.8;
 
   pub fn score_percentage(&self) -> u32 {
-    u32::min((self.total * 100) / Self::MAX_SCORE, 100)
+    u32::min((self.total * 100) / self.max_score.max(1), 100)
   }
 }
 
 impl From<(&PackageVersionMeta, &Package)> for ApiPackageScore {
   fn from((meta, package): (&PackageVersionMeta, &Package)) -> Self {
-    let mut score = 0;
-
-    if meta.has_readme {
-      score += 2;
-    }
-
-    if meta.has_readme_examples {
-      score += 1;
-    }
-
-    if meta.all_entrypoints_docs {
-      score += 1;
-    }
-
-    if meta.has_provenance {
-      score += 1;
-    }
-
-    // You only need to document 80% of your symbols to get all the points.
-    score += ((meta.percentage_documented_symbols / 0.8).min(1.0) * 5.0).floor()
-      as u32;
-
-    if meta.all_fast_check {
-      score += 5;
-    }
+    use ApiScoreFactorKind::*;
+
+    let schema = crate::score::active_schema();
+    let weights = &schema.weights;
+    let details = &meta.score_details;
+    let mut factors = Vec::with_capacity(11);
+
+    factors.push(ApiPackageScoreFactor::from_bool(
+      weights,
+      HasReadme,
+      meta.has_readme,
+      || {
+        vec![
+          "Add a README.md file to the root of the package, or a module doc \
+           to the main entrypoint."
+            .to_string(),
+        ]
+      },
+    ));
+
+    factors.push(ApiPackageScoreFactor::from_bool(
+      weights,
+      HasReadmeExamples,
+      meta.has_readme_examples,
+      || {
+        vec![
+          "Add a code block with an example to the README, or an @example \
+           tag to the module doc of the main entrypoint."
+            .to_string(),
+        ]
+      },
+    ));
+
+    factors.push(ApiPackageScoreFactor::from_bool(
+      weights,
+      AllEntrypointsDocs,
+      meta.all_entrypoints_docs,
+      || {
+        if details.entrypoints_without_module_doc.is_empty() {
+          vec![
+            "Add a module doc with the @module tag to every entrypoint."
+              .to_string(),
+          ]
+        } else {
+          details
+            .entrypoints_without_module_doc
+            .iter()
+            .map(|path| {
+              format!("Add a module doc with the @module tag to '{path}'.")
+            })
+            .collect()
+        }
+      },
+    ));
+
+    let documented_symbols_weight = PercentageDocumentedSymbols.weight(weights);
+    let documented_symbols_points = ((meta.percentage_documented_symbols
+      / Self::DOCUMENTED_SYMBOLS_THRESHOLD)
+      .min(1.0)
+      * documented_symbols_weight as f32)
+      .floor() as u32;
+    let documented_symbols_hints =
+      if documented_symbols_points < documented_symbols_weight {
+        if details.total_symbols > 0 {
+          let required = (details.total_symbols as f32
+            * Self::DOCUMENTED_SYMBOLS_THRESHOLD)
+            .ceil() as u32;
+          let mut hints = vec![format!(
+            "{} of {} exported symbols are documented. Add JSDoc comments to \
+             at least {} more to get all points.",
+            details.documented_symbols,
+            details.total_symbols,
+            required.saturating_sub(details.documented_symbols),
+          )];
+          hints.extend(
+            details
+              .entrypoint_docs
+              .iter()
+              .filter(|entrypoint| {
+                entrypoint.documented_symbols < entrypoint.total_symbols
+              })
+              .map(|entrypoint| {
+                format!(
+                  "'{}' has {} of {} exported symbols documented.",
+                  entrypoint.export,
+                  entrypoint.documented_symbols,
+                  entrypoint.total_symbols,
+                )
+              }),
+          );
+          hints
+        } else {
+          vec![
+            "Add JSDoc comments to at least 80% of the exported symbols."
+              .to_string(),
+          ]
+        }
+      } else {
+        vec![]
+      };
+    factors.push(ApiPackageScoreFactor {
+      factor: PercentageDocumentedSymbols,
+      weight: documented_symbols_weight,
+      points: documented_symbols_points,
+      value: ApiScoreFactorValue::Percentage(
+        meta.percentage_documented_symbols,
+      ),
+      hints: documented_symbols_hints,
+    });
+
+    factors.push(ApiPackageScoreFactor::from_bool(
+      weights,
+      AllFastCheck,
+      meta.all_fast_check,
+      || {
+        let mut hints = details
+          .entrypoints_with_slow_types
+          .iter()
+          .map(|path| {
+            format!(
+              "Add explicit types to the exports reachable from '{path}'."
+            )
+          })
+          .collect::<Vec<_>>();
+        hints.push(
+          "Run `deno publish --dry-run` to list all slow types.".to_string(),
+        );
+        hints
+      },
+    ));
+
+    factors.push(ApiPackageScoreFactor::from_bool(
+      weights,
+      AllExamplesResolve,
+      meta.all_examples_resolve,
+      || {
+        if details.examples_checked == 0 {
+          vec![
+            "Add a TypeScript or JavaScript code block to the README, or an \
+             @example tag to an exported symbol."
+              .to_string(),
+          ]
+        } else {
+          details
+            .failing_examples
+            .iter()
+            .map(|failure| format!("Fix the {failure}"))
+            .collect()
+        }
+      },
+    ));
+
+    factors.push(ApiPackageScoreFactor::from_bool(
+      weights,
+      HasTests,
+      meta.has_tests,
+      || {
+        vec![
+          "Publish test files (e.g. `mod_test.ts`), or attach a coverage \
+           summary with the `coverage` field of the config file."
+            .to_string(),
+        ]
+      },
+    ));
+
+    factors.push(ApiPackageScoreFactor::from_bool(
+      weights,
+      HasProvenance,
+      meta.has_provenance,
+      || {
+        vec![
+          "Publish from GitHub Actions with the `id-token: write` permission \
+           to attach a provenance attestation."
+            .to_string(),
+        ]
+      },
+    ));
 
     // package wide
 
-    if !package.description.is_empty() {
-      score += 1;
-    }
-
-    let mut compatible_runtimes_count = 0;
-    if package.runtime_compat.deno.is_some_and(|compat| compat) {
-      compatible_runtimes_count += 1;
-    }
-    if package.runtime_compat.bun.is_some_and(|compat| compat) {
-      compatible_runtimes_count += 1;
-    }
-    if package.runtime_compat.node.is_some_and(|compat| compat) {
-      compatible_runtimes_count += 1;
-    }
-    if package.runtime_compat.browser.is_some_and(|compat| compat) {
-      compatible_runtimes_count += 1;
-    }
-    if package.runtime_compat.workerd.is_some_and(|compat| compat) {
-      compatible_runtimes_count += 1;
-    }
-
-    if compatible_runtimes_count >= 1 {
-      score += 1;
-    }
+    factors.push(ApiPackageScoreFactor::from_bool(
+      weights,
+      HasDescription,
+      !package.description.is_empty(),
+      || vec!["Add a description in the package settings.".to_string()],
+    ));
+
+    let compatible_runtimes_count = [
+      package.runtime_compat.deno,
+      package.runtime_compat.bun,
+      package.runtime_compat.node,
+      package.runtime_compat.browser,
+      package.runtime_compat.workerd,
+    ]
+    .into_iter()
+    .filter(|compat| compat.is_some_and(|compat| compat))
+    .count();
+
+    factors.push(ApiPackageScoreFactor::from_bool(
+      weights,
+      AtLeastOneRuntimeCompatible,
+      compatible_runtimes_count >= 1,
+      || {
+        vec![
+          "Mark at least one runtime as compatible in the package settings."
+            .to_string(),
+        ]
+      },
+    ));
+
+    factors.push(ApiPackageScoreFactor::from_bool(
+      weights,
+      MultipleRuntimesCompatible,
+      compatible_runtimes_count >= 2,
+      || {
+        vec![
+          "Mark all runtimes the package works with as compatible in the \
+           package settings."
+            .to_string(),
+        ]
+      },
+    ));
 
-    if compatible_runtimes_count >= 2 {
-      score += 1;
-    }
+    let total = factors.iter().map(|factor| factor.points).sum();
 
     Self {
       has_readme: meta.has_readme,
@@ -435,11 +868,25 @@ impl From<(&PackageVersionMeta, &Package)> for ApiPackageScore {
       all_entrypoints_docs: meta.all_entrypoints_docs,
       percentage_documented_symbols: meta.percentage_documented_symbols,
       all_fast_check: meta.all_fast_check,
+      all_examples_resolve: meta.all_examples_resolve,
+      has_tests: meta.has_tests,
       has_provenance: meta.has_provenance,
       has_description: !package.description.is_empty(),
       at_least_one_runtime_compatible: compatible_runtimes_count >= 1,
       multiple_runtimes_compatible: compatible_runtimes_count >= 2,
-      total: score,
+      factors,
+      total,
+      max_score: schema.max_score as u32,
+      test_files: details.test_files,
+      coverage: details.coverage.clone(),
+      entrypoint_docs: details.entrypoint_docs.clone(),
+      readme: details.readme.clone(),
+      schema_version: schema.version,
+      published_schema_version: if meta.score_schema_version == 0 {
+        1
+      } else {
+        meta.score_schema_version
+      },
     }
   }
 }
@@ -457,11 +904,36 @@ pub struct ApiPackage {
   pub version_count: u64,
   pub dependency_count: u64,
   pub dependent_count: u64,
+  /// Downloads of all versions over the last 30 days. Only set when getting
+  /// a single package.
+  pub downloads_last_30_days: u64,
   pub score: Option<u32>,
   pub latest_version: Option<String>,
   pub when_featured: Option<DateTime<Utc>>,
   pub is_archived: bool,
   pub readme_source: ApiReadmeSource,
+  pub visibility: ApiPackageVisibility,
+  /// The locale of `description`, if it was replaced by a translation
+  /// matching the caller's preferred locales. See [ApiPackage::localize].
+  pub description_locale: Option<String>,
+  pub localized_descriptions: IndexMap<String, String>,
+}
+
+impl ApiPackage {
+  /// Replaces `description` with the translation that best matches `locales`,
+  /// if there is one.
+  pub fn localize(mut self, locales: &[String]) -> Self {
+    let available = self
+      .localized_descriptions
+      .keys()
+      .map(|locale| locale.as_str())
+      .collect::<Vec<_>>();
+    if let Some(locale) = crate::util::best_locale_match(locales, &available) {
+      self.description = self.localized_descriptions[locale].clone();
+      self.description_locale = Some(locale.to_string());
+    }
+    self
+  }
 }
 
 impl From<PackageWithGitHubRepoAndMeta> for ApiPackage {
@@ -481,6 +953,7 @@ impl From<PackageWithGitHubRepoAndMeta> for ApiPackage {
       version_count: package.version_count as u64,
       dependency_count: 0,
       dependent_count: 0,
+      downloads_last_30_days: 0,
       score: package
         .latest_version
         .as_ref()
@@ -489,6 +962,9 @@ impl From<PackageWithGitHubRepoAndMeta> for ApiPackage {
       when_featured: package.when_featured,
       is_archived: package.is_archived,
       readme_source: package.readme_source.into(),
+      visibility: package.visibility.into(),
+      description_locale: None,
+      localized_descriptions: package.localized_descriptions.0,
     }
   }
 }
@@ -503,11 +979,22 @@ pub struct ApiCreatePackageRequest {
 #[serde(rename_all = "camelCase")]
 pub enum ApiUpdatePackageRequest {
   Description(String),
+  LocalizedDescription(ApiUpdatePackageLocalizedDescriptionRequest),
   GithubRepository(Option<ApiUpdatePackageGithubRepositoryRequest>),
   RuntimeCompat(ApiRuntimeCompat),
   ReadmeSource(ApiReadmeSource),
   IsFeatured(bool),
   IsArchived(bool),
+  Visibility(ApiPackageVisibility),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiUpdatePackageLocalizedDescriptionRequest {
+  pub locale: String,
+  /// The translated description. `None` or an empty string removes the
+  /// translation for `locale`.
+  pub description: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Eq, PartialEq)]
@@ -535,6 +1022,31 @@ impl From<ReadmeSource> for ApiReadmeSource {
   }
 }
 
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiPackageVisibility {
+  Public,
+  Private,
+}
+
+impl From<ApiPackageVisibility> for PackageVisibility {
+  fn from(value: ApiPackageVisibility) -> Self {
+    match value {
+      ApiPackageVisibility::Public => PackageVisibility::Public,
+      ApiPackageVisibility::Private => PackageVisibility::Private,
+    }
+  }
+}
+
+impl From<PackageVisibility> for ApiPackageVisibility {
+  fn from(value: PackageVisibility) -> Self {
+    match value {
+      PackageVisibility::Public => ApiPackageVisibility::Public,
+      PackageVisibility::Private => ApiPackageVisibility::Private,
+    }
+  }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ApiUpdatePackageGithubRepositoryRequest {
@@ -548,10 +1060,45 @@ pub struct ApiProvenanceStatementRequest {
   pub bundle: ProvenanceBundle,
 }
 
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiProvenance {
+  /// The index of the attestation in the Rekor transparency log.
+  pub log_index: u64,
+  pub integrated_time: Option<DateTime<Utc>>,
+  pub bundle: ProvenanceBundle,
+}
+
+impl From<ProvenanceBundle> for ApiProvenance {
+  fn from(bundle: ProvenanceBundle) -> Self {
+    let tlog_entry = bundle.tlog_entry();
+    Self {
+      log_index: tlog_entry.log_index,
+      integrated_time: tlog_entry
+        .integrated_time()
+        .and_then(|time| DateTime::from_timestamp(time, 0)),
+      bundle,
+    }
+  }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiProvenanceVerification {
+  pub verified: bool,
+  pub log_index: u64,
+  /// The GitHub repository, as `owner/name`, the attestation was built in.
+  pub repository: Option<String>,
+  /// Why verification failed.
+  pub error: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ApiUpdatePackageVersionRequest {
   pub yanked: bool,
+  /// Why the version is yanked. Only allowed when yanking.
+  pub yank_reason: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -615,6 +1162,54 @@ impl From<ApiRuntimeCompat> for RuntimeCompat {
   }
 }
 
+/// The metadata of a package as it was at a past point in time.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiPackageSnapshot {
+  pub scope: ScopeName,
+  pub package: PackageName,
+  pub as_of: DateTime<Utc>,
+  pub latest: Option<Version>,
+  /// The versions published at or before `as_of`, newest first.
+  pub versions: IndexMap<Version, ApiPackageSnapshotVersion>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiPackageSnapshotVersion {
+  pub yanked: bool,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<(crate::metadata::PackageMetadata, DateTime<Utc>)>
+  for ApiPackageSnapshot
+{
+  fn from(
+    (metadata, as_of): (crate::metadata::PackageMetadata, DateTime<Utc>),
+  ) -> Self {
+    let mut versions = metadata.versions.into_iter().collect::<Vec<_>>();
+    versions.sort_by(|(a, _), (b, _)| b.cmp(a));
+    Self {
+      scope: metadata.scope,
+      package: metadata.name,
+      as_of,
+      latest: metadata.latest,
+      versions: versions
+        .into_iter()
+        .map(|(version, meta)| {
+          (
+            version,
+            ApiPackageSnapshotVersion {
+              yanked: meta.yanked,
+              created_at: meta.created_at,
+            },
+          )
+        })
+        .collect(),
+    }
+  }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ApiPackageVersion {
@@ -622,6 +1217,8 @@ pub struct ApiPackageVersion {
   pub package: PackageName,
   pub version: Version,
   pub yanked: bool,
+  pub yanked_at: Option<DateTime<Utc>>,
+  pub yank_reason: Option<String>,
   pub uses_npm: bool,
   pub newer_versions_count: Option<u64>,
   pub rekor_log_id: Option<String>,
@@ -638,6 +1235,9 @@ pub enum ApiPackageVersionDocs {
   #[serde(rename_all = "camelCase")]
   Content {
     version: ApiPackageVersion,
+    /// Whether the package is archived, so the docs can be shown with a
+    /// notice that the package is read-only.
+    package_archived: bool,
     comrak_css: Cow<'static, str>,
     script: Cow<'static, str>,
     breadcrumbs: Option<deno_doc::html::util::BreadcrumbsCtx>,
@@ -649,6 +1249,14 @@ pub enum ApiPackageVersionDocs {
   },
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiPackageVersionDiff {
+  pub old_version: ApiPackageVersion,
+  pub new_version: ApiPackageVersion,
+  pub diff: deno_doc::diff::DocDiff,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase", tag = "kind", content = "value")]
 #[allow(clippy::large_enum_variant)]
@@ -677,6 +1285,8 @@ impl From<PackageVersion> for ApiPackageVersion {
       package: value.name,
       version: value.version,
       yanked: value.is_yanked,
+      yanked_at: value.yanked_at,
+      yank_reason: value.yank_reason,
       uses_npm: value.uses_npm,
       newer_versions_count: None,
       rekor_log_id: value.rekor_log_id,
@@ -695,6 +1305,8 @@ impl From<PackageVersionWithNewerVersionsCount> for ApiPackageVersion {
       package: value.name,
       version: value.version,
       yanked: value.is_yanked,
+      yanked_at: value.yanked_at,
+      yank_reason: value.yank_reason,
       uses_npm: value.uses_npm,
       newer_versions_count: Some(value.newer_versions_count as u64),
       rekor_log_id: value.rekor_log_id,
@@ -745,6 +1357,8 @@ pub struct ApiPackageVersionWithUser {
   pub version: Version,
   pub user: Option<ApiUser>,
   pub yanked: bool,
+  pub yanked_at: Option<DateTime<Utc>>,
+  pub yank_reason: Option<String>,
   pub uses_npm: bool,
   pub rekor_log_id: Option<String>,
   pub readme_path: Option<PackagePath>,
@@ -766,6 +1380,8 @@ impl From<(PackageVersion, Option<UserPublic>)> for ApiPackageVersionWithUser {
       version: package_version.version,
       user: user.map(|user| user.into()),
       yanked: package_version.is_yanked,
+      yanked_at: package_version.yanked_at,
+      yank_reason: package_version.yank_reason,
       uses_npm: package_version.uses_npm,
       rekor_log_id: package_version.rekor_log_id,
       readme_path: package_version.readme_path,
@@ -789,6 +1405,7 @@ pub struct ApiAdminUpdateScopeRequest {
   pub package_limit: Option<i32>,
   pub new_package_per_week_limit: Option<i32>,
   pub publish_attempts_per_week_limit: Option<i32>,
+  pub storage_limit: Option<i64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -800,6 +1417,27 @@ pub enum ApiUpdateScopeRequest {
   RequirePublishingFromCI(bool),
   #[serde(rename = "description")]
   Description(Option<String>),
+  #[serde(rename = "markdownSettings")]
+  MarkdownSettings(ApiScopeMarkdownSettings),
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiScopeMarkdownSettings {
+  /// Whether raw HTML is kept, cleaned to the HTML subset of package docs,
+  /// instead of being escaped.
+  pub allow_html: bool,
+  /// Additional alert kinds, e.g. `danger` for `> [!DANGER]`.
+  pub admonitions: Vec<String>,
+}
+
+impl From<ScopeMarkdownSettings> for ApiScopeMarkdownSettings {
+  fn from(settings: ScopeMarkdownSettings) -> Self {
+    Self {
+      allow_html: settings.allow_html,
+      admonitions: settings.admonitions,
+    }
+  }
 }
 
 // `ApiStats`, `ApiStatsPackage`, `ApiStatsPackageVersion`, and `ApiMetrics` now
@@ -809,6 +1447,7 @@ pub enum ApiUpdateScopeRequest {
 // working.
 pub use jsr_types::api::ApiMetrics;
 pub use jsr_types::api::ApiStats;
+pub use jsr_types::api::ApiStatsDownloadedPackage;
 pub use jsr_types::api::ApiStatsPackage;
 pub use jsr_types::api::ApiStatsPackageVersion;
 
@@ -882,6 +1521,10 @@ pub struct ApiDependency {
   pub name: String,
   pub constraint: String,
   pub path: String,
+  pub optional: bool,
+  /// Whether the dependency is a jsr package that has been archived by its
+  /// owners, and so will not receive any further updates.
+  pub archived: bool,
 }
 
 impl From<PackageVersionDependency> for ApiDependency {
@@ -891,6 +1534,159 @@ impl From<PackageVersionDependency> for ApiDependency {
       name: dep.dependency_name,
       constraint: dep.dependency_constraint,
       path: dep.dependency_path,
+      optional: dep.is_optional,
+      archived: false,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct ApiDeprecation {
+  /// The export key of the entrypoint the symbol is exported from.
+  pub export: String,
+  pub symbol: String,
+  pub message: Option<String>,
+}
+
+impl From<PackageVersionDeprecation> for ApiDeprecation {
+  fn from(deprecation: PackageVersionDeprecation) -> Self {
+    Self {
+      export: deprecation.export,
+      symbol: deprecation.symbol,
+      message: deprecation.message,
+    }
+  }
+}
+
+/// The digests of the artifacts stored for a version.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiVersionIntegrity {
+  pub files: Vec<ApiFileDigest>,
+  pub npm_tarballs: Vec<ApiNpmTarballDigest>,
+  pub docs: Vec<ApiDocArtifactDigest>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiFileDigest {
+  pub path: PackagePath,
+  pub checksum: Option<String>,
+  pub size: i32,
+}
+
+impl From<PackageFile> for ApiFileDigest {
+  fn from(value: PackageFile) -> Self {
+    Self {
+      path: value.path,
+      checksum: value.checksum,
+      size: value.size,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiNpmTarballDigest {
+  pub revision: i32,
+  pub sha1: String,
+  pub sha512: String,
+  /// `None` for tarballs built before digests were recorded.
+  pub sha256: Option<String>,
+  pub size: i32,
+}
+
+impl From<NpmTarball> for ApiNpmTarballDigest {
+  fn from(value: NpmTarball) -> Self {
+    Self {
+      revision: value.revision,
+      sha1: value.sha1,
+      sha512: value.sha512,
+      sha256: value.sha256,
+      size: value.size,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiDocArtifactDigest {
+  pub kind: DocArtifactKind,
+  pub checksum: String,
+  pub size: i64,
+}
+
+impl From<PackageVersionDocArtifact> for ApiDocArtifactDigest {
+  fn from(value: PackageVersionDocArtifact) -> Self {
+    Self {
+      kind: value.kind,
+      checksum: value.checksum,
+      size: value.size,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct ApiDocLink {
+  pub version: Version,
+  pub identifier: String,
+  /// The path of the documentation page of the symbol, relative to the
+  /// package.
+  pub path: String,
+}
+
+impl From<PackageVersionDocLink> for ApiDocLink {
+  fn from(link: PackageVersionDocLink) -> Self {
+    Self {
+      version: link.version,
+      identifier: link.identifier,
+      path: link.path,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiSymbolSearchResult {
+  pub scope: ScopeName,
+  pub package: PackageName,
+  /// The latest stable version of the package, which the symbol is exported
+  /// from.
+  pub version: Version,
+  /// The export key of the entrypoint the symbol is exported from.
+  pub export: String,
+  pub symbol: String,
+  pub kind: String,
+  /// The score of the package, in percent.
+  pub score: Option<u32>,
+  /// Downloads of the package over the last 30 days.
+  pub downloads: u64,
+}
+
+impl From<(PackageVersionSymbol, Package, PackageVersionMeta, i64)>
+  for ApiSymbolSearchResult
+{
+  fn from(
+    (symbol, package, meta, downloads): (
+      PackageVersionSymbol,
+      Package,
+      PackageVersionMeta,
+      i64,
+    ),
+  ) -> Self {
+    let score = package
+      .latest_version
+      .as_ref()
+      .map(|_| ApiPackageScore::from((&meta, &package)).score_percentage());
+    Self {
+      scope: symbol.scope,
+      package: symbol.name,
+      version: symbol.version,
+      export: symbol.export,
+      symbol: symbol.symbol,
+      kind: symbol.kind,
+      score,
+      downloads: downloads.max(0) as u64,
     }
   }
 }
@@ -943,7 +1739,7 @@ impl From<VersionDownloadDataPoint> for ApiDownloadDataPoint {
   }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ApiDownloadKind {
   JsrMeta,
@@ -967,10 +1763,69 @@ pub struct ApiList<T> {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "snake_case")]
-pub enum ApiTokenType {
-  Web,
-  Device,
+#[serde(rename_all = "camelCase")]
+pub struct ApiPackageSearchResult {
+  pub items: Vec<ApiPackage>,
+  pub total: usize,
+  pub facets: ApiPackageSearchFacets,
+}
+
+/// The number of packages matching a package search for each value of the
+/// search filters.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiPackageSearchFacets {
+  pub runtime_compat: ApiRuntimeCompatFacets,
+  pub has_provenance: u64,
+  /// The most common licenses, most common first.
+  pub licenses: Vec<ApiLicenseFacet>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiRuntimeCompatFacets {
+  pub browser: u64,
+  pub deno: u64,
+  pub node: u64,
+  pub workerd: u64,
+  pub bun: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiLicenseFacet {
+  pub license: String,
+  pub count: u64,
+}
+
+impl From<PackageSearchFacets> for ApiPackageSearchFacets {
+  fn from(value: PackageSearchFacets) -> Self {
+    Self {
+      runtime_compat: ApiRuntimeCompatFacets {
+        browser: value.browser as u64,
+        deno: value.deno as u64,
+        node: value.node as u64,
+        workerd: value.workerd as u64,
+        bun: value.bun as u64,
+      },
+      has_provenance: value.has_provenance as u64,
+      licenses: value
+        .licenses
+        .into_iter()
+        .map(|(license, count)| ApiLicenseFacet {
+          license,
+          count: count as u64,
+        })
+        .collect(),
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiTokenType {
+  Web,
+  Device,
   Personal,
 }
 
@@ -995,6 +1850,8 @@ pub struct ApiToken {
   pub updated_at: DateTime<Utc>,
   pub created_at: DateTime<Utc>,
   pub permissions: Option<Permissions>,
+  pub last_used_at: Option<DateTime<Utc>>,
+  pub rotated_at: Option<DateTime<Utc>>,
 }
 
 impl From<Token> for ApiToken {
@@ -1008,6 +1865,8 @@ impl From<Token> for ApiToken {
       updated_at: value.updated_at,
       created_at: value.created_at,
       permissions: value.permissions,
+      last_used_at: value.last_used_at,
+      rotated_at: value.rotated_at,
     }
   }
 }
@@ -1020,6 +1879,15 @@ pub struct ApiCreateTokenRequest {
   pub permissions: Option<Permissions>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiUpdateTokenRequest {
+  #[serde(default)]
+  pub description: Option<String>,
+  #[serde(default)]
+  pub expires_at: Option<DateTime<Utc>>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ApiCreatedToken {
@@ -1027,6 +1895,20 @@ pub struct ApiCreatedToken {
   pub token: ApiToken,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiRotateTokenRequest {
+  /// How long the previous secret keeps working, in hours.
+  #[serde(default)]
+  pub grace_period_hours: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiRevokedTokens {
+  pub token_ids: Vec<Uuid>,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ApiAssignScopeRequest {
@@ -1039,6 +1921,43 @@ pub struct ApiAssignScopeRequest {
 pub struct ApiPackageDownloads {
   pub total: Vec<ApiDownloadDataPoint>,
   pub recent_versions: Vec<ApiPackageDownloadsRecentVersion>,
+  /// Downloads of each entrypoint, summed over all versions.
+  pub entrypoints: Vec<ApiPackageDownloadsEntrypoint>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiPackageDownloadsEntrypoint {
+  /// The export key of the entrypoint, e.g. `.` or `./utils`.
+  pub entrypoint: String,
+  pub downloads: Vec<ApiEntrypointDownloadDataPoint>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiEntrypointDownloadDataPoint {
+  pub time_bucket: DateTime<Utc>,
+  pub count: u64,
+}
+
+/// The order of the dependents of a package.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiDependentsSort {
+  /// By scope and package name.
+  Name,
+  /// By the score of the latest version of each dependent, highest first.
+  Score,
+  /// By downloads over the last 30 days, most downloaded first.
+  Downloads,
+}
+
+/// The size of the time buckets of download statistics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiDownloadPeriod {
+  /// Daily buckets over the last 90 days.
+  Daily,
+  /// Weekly buckets, starting on Monday, over the last 52 weeks.
+  Weekly,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -1172,3 +2091,1321 @@ impl From<(AuditLog, UserPublic)> for ApiAuditLog {
     }
   }
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiScoreSchema {
+  pub version: i32,
+  pub weights: ScoreWeights,
+  pub max_score: i32,
+  pub created_by: Option<Uuid>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<ScoreSchema> for ApiScoreSchema {
+  fn from(value: ScoreSchema) -> Self {
+    Self {
+      version: value.version,
+      weights: value.weights,
+      max_score: value.max_score,
+      created_by: value.created_by,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiAdminCreateScoreSchemaRequest {
+  pub weights: ScoreWeights,
+  pub max_score: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiScoreRecomputeJob {
+  pub id: Uuid,
+  pub status: ScoreRecomputeJobStatus,
+  pub total_versions: i32,
+  pub processed_versions: i32,
+  pub failed_versions: i32,
+  pub error: Option<String>,
+  pub created_by: Option<Uuid>,
+  pub finished_at: Option<DateTime<Utc>>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<ScoreRecomputeJob> for ApiScoreRecomputeJob {
+  fn from(value: ScoreRecomputeJob) -> Self {
+    Self {
+      id: value.id,
+      status: value.status,
+      total_versions: value.total_versions,
+      processed_versions: value.processed_versions,
+      failed_versions: value.failed_versions,
+      error: value.error,
+      created_by: value.created_by,
+      finished_at: value.finished_at,
+      updated_at: value.updated_at,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiAdminCreateNpmTarballRebuildJobRequest {
+  pub scope: Option<ScopeName>,
+  pub package: Option<PackageName>,
+  #[serde(default)]
+  pub verify: bool,
+  pub concurrency: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiNpmTarballRebuildJob {
+  pub id: Uuid,
+  pub status: NpmTarballRebuildJobStatus,
+  pub scope: Option<ScopeName>,
+  pub package: Option<PackageName>,
+  pub verify: bool,
+  pub concurrency: i32,
+  pub total_versions: i32,
+  pub processed_versions: i32,
+  pub failed_versions: i32,
+  pub error: Option<String>,
+  pub created_by: Option<Uuid>,
+  pub finished_at: Option<DateTime<Utc>>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<NpmTarballRebuildJob> for ApiNpmTarballRebuildJob {
+  fn from(value: NpmTarballRebuildJob) -> Self {
+    Self {
+      id: value.id,
+      status: value.status,
+      scope: value.scope,
+      package: value.name,
+      verify: value.verify,
+      concurrency: value.concurrency,
+      total_versions: value.total_versions,
+      processed_versions: value.processed_versions,
+      failed_versions: value.failed_versions,
+      error: value.error,
+      created_by: value.created_by,
+      finished_at: value.finished_at,
+      updated_at: value.updated_at,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiAdminCreateOrphanGcJobRequest {
+  #[serde(default)]
+  pub dry_run: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiOrphanGcJob {
+  pub id: Uuid,
+  pub status: OrphanGcJobStatus,
+  pub dry_run: bool,
+  pub scanned_packages: i32,
+  pub scanned_objects: i32,
+  pub orphaned_objects: i32,
+  pub deleted_objects: i32,
+  pub error: Option<String>,
+  pub created_by: Option<Uuid>,
+  pub finished_at: Option<DateTime<Utc>>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<OrphanGcJob> for ApiOrphanGcJob {
+  fn from(value: OrphanGcJob) -> Self {
+    Self {
+      id: value.id,
+      status: value.status,
+      dry_run: value.dry_run,
+      scanned_packages: value.scanned_packages,
+      scanned_objects: value.scanned_objects,
+      orphaned_objects: value.orphaned_objects,
+      deleted_objects: value.deleted_objects,
+      error: value.error,
+      created_by: value.created_by,
+      finished_at: value.finished_at,
+      updated_at: value.updated_at,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiOrphanedObject {
+  pub bucket: String,
+  pub path: String,
+  pub deleted: bool,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<OrphanedObject> for ApiOrphanedObject {
+  fn from(value: OrphanedObject) -> Self {
+    Self {
+      bucket: value.bucket,
+      path: value.path,
+      deleted: value.deleted,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiAdminCreateIntegrityScrubJobRequest {
+  pub scope: Option<ScopeName>,
+  pub package: Option<PackageName>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiIntegrityScrubJob {
+  pub id: Uuid,
+  pub status: IntegrityScrubJobStatus,
+  pub scope: Option<ScopeName>,
+  pub package: Option<PackageName>,
+  pub total_versions: i32,
+  pub checked_versions: i32,
+  pub checked_objects: i32,
+  pub failed_objects: i32,
+  pub error: Option<String>,
+  pub created_by: Option<Uuid>,
+  pub finished_at: Option<DateTime<Utc>>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<IntegrityScrubJob> for ApiIntegrityScrubJob {
+  fn from(value: IntegrityScrubJob) -> Self {
+    Self {
+      id: value.id,
+      status: value.status,
+      scope: value.scope,
+      package: value.name,
+      total_versions: value.total_versions,
+      checked_versions: value.checked_versions,
+      checked_objects: value.checked_objects,
+      failed_objects: value.failed_objects,
+      error: value.error,
+      created_by: value.created_by,
+      finished_at: value.finished_at,
+      updated_at: value.updated_at,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiIntegrityScrubFailure {
+  pub bucket: String,
+  pub path: String,
+  pub expected: String,
+  /// The digest of the object, or `None` if it is missing.
+  pub actual: Option<String>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<IntegrityScrubFailure> for ApiIntegrityScrubFailure {
+  fn from(value: IntegrityScrubFailure) -> Self {
+    Self {
+      bucket: value.bucket,
+      path: value.path,
+      expected: value.expected,
+      actual: value.actual,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiDatabasePool {
+  /// The number of open connections, idle or in use.
+  pub size: u32,
+  pub idle: usize,
+  pub max_connections: u32,
+  pub closed: bool,
+}
+
+impl From<PoolHealth> for ApiDatabasePool {
+  fn from(value: PoolHealth) -> Self {
+    Self {
+      size: value.size,
+      idle: value.idle,
+      max_connections: value.max_connections,
+      closed: value.closed,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiDatabasePools {
+  pub primary: ApiDatabasePool,
+  /// The pool of the read replica, if one is configured.
+  pub replica: Option<ApiDatabasePool>,
+  /// Whether reads go to the primary because of a recent write.
+  pub sticky_to_primary: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiMetadataCacheCounts {
+  pub hits: u64,
+  pub misses: u64,
+}
+
+impl From<(u64, u64)> for ApiMetadataCacheCounts {
+  fn from((hits, misses): (u64, u64)) -> Self {
+    Self { hits, misses }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiMetadataCache {
+  /// Whether a metadata cache is configured.
+  pub enabled: bool,
+  pub package: ApiMetadataCacheCounts,
+  pub versions: ApiMetadataCacheCounts,
+  pub scope: ApiMetadataCacheCounts,
+  /// How often the cache could not be read or written, and the database was
+  /// used instead.
+  pub errors: u64,
+}
+
+impl From<Option<MetadataCacheStats>> for ApiMetadataCache {
+  fn from(value: Option<MetadataCacheStats>) -> Self {
+    let stats = value.unwrap_or_default();
+    Self {
+      enabled: value.is_some(),
+      package: stats.package.into(),
+      versions: stats.versions.into(),
+      scope: stats.scope.into(),
+      errors: stats.errors,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiAdminCreateBackgroundJobRequest {
+  pub kind: BackgroundJobKind,
+  pub scope: ScopeName,
+  pub package: PackageName,
+  pub version: Version,
+  #[serde(default)]
+  pub priority: i32,
+  pub max_attempts: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiBackgroundJob {
+  pub id: Uuid,
+  pub kind: BackgroundJobKind,
+  pub payload: serde_json::Value,
+  pub status: BackgroundJobStatus,
+  pub priority: i32,
+  pub attempts: i32,
+  pub max_attempts: i32,
+  /// When a pending job is due, or when the lease of a running job expires.
+  pub run_at: DateTime<Utc>,
+  pub last_error: Option<String>,
+  pub created_by: Option<Uuid>,
+  pub finished_at: Option<DateTime<Utc>>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<BackgroundJob> for ApiBackgroundJob {
+  fn from(value: BackgroundJob) -> Self {
+    Self {
+      id: value.id,
+      kind: value.kind,
+      payload: value.payload,
+      status: value.status,
+      priority: value.priority,
+      attempts: value.attempts,
+      max_attempts: value.max_attempts,
+      run_at: value.run_at,
+      last_error: value.last_error,
+      created_by: value.created_by,
+      finished_at: value.finished_at,
+      updated_at: value.updated_at,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiAnnouncement {
+  pub id: Uuid,
+  pub title: String,
+  pub body: String,
+  pub severity: AnnouncementSeverity,
+  pub features: Vec<String>,
+  pub expires_at: Option<DateTime<Utc>>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<Announcement> for ApiAnnouncement {
+  fn from(value: Announcement) -> Self {
+    Self {
+      id: value.id,
+      title: value.title,
+      body: value.body,
+      severity: value.severity,
+      features: value.features,
+      expires_at: value.expires_at,
+      updated_at: value.updated_at,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiRegistryChange {
+  pub seq: i64,
+  pub kind: RegistryChangeKind,
+  pub scope: ScopeName,
+  pub package: PackageName,
+  pub version: Option<Version>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<RegistryChange> for ApiRegistryChange {
+  fn from(value: RegistryChange) -> Self {
+    Self {
+      seq: value.seq,
+      kind: value.kind,
+      scope: value.scope,
+      package: value.name,
+      version: value.version,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiRegistryChanges {
+  pub changes: Vec<ApiRegistryChange>,
+  /// The cursor to pass as `since` to get the next page. Equal to the passed
+  /// cursor if there are no new changes.
+  pub next_cursor: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiVersionExport {
+  pub versions: Vec<ApiPackageVersion>,
+  /// The cursor to pass as `after` to get the next page, or null if this is
+  /// the last page.
+  pub next: Option<String>,
+  /// The change log cursor at the time this page was read. Following the
+  /// change log from the cursor of the first page picks up everything that
+  /// changed during the export.
+  pub changes_cursor: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiDistTag {
+  pub tag: String,
+  pub version: Version,
+  pub updated_at: DateTime<Utc>,
+}
+
+impl From<PackageDistTag> for ApiDistTag {
+  fn from(value: PackageDistTag) -> Self {
+    Self {
+      tag: value.tag,
+      version: value.version,
+      updated_at: value.updated_at,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiSetDistTagRequest {
+  pub version: Version,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiPackageTransfer {
+  pub scope: ScopeName,
+  pub package: PackageName,
+  pub target_scope: ScopeName,
+  pub requested_by: Uuid,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<PackageTransfer> for ApiPackageTransfer {
+  fn from(value: PackageTransfer) -> Self {
+    Self {
+      scope: value.scope,
+      package: value.name,
+      target_scope: value.target_scope,
+      requested_by: value.requested_by,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiCreatePackageTransferRequest {
+  pub target_scope: ScopeName,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiScopeRename {
+  pub scope: ScopeName,
+  pub target_scope: ScopeName,
+  pub requested_by: Uuid,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<ScopeRename> for ApiScopeRename {
+  fn from(value: ScopeRename) -> Self {
+    Self {
+      scope: value.scope,
+      target_scope: value.target_scope,
+      requested_by: value.requested_by,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiCreateScopeRenameRequest {
+  pub target_scope: ScopeName,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiScopeTeam {
+  pub scope: ScopeName,
+  pub name: String,
+  pub description: String,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<ScopeTeam> for ApiScopeTeam {
+  fn from(value: ScopeTeam) -> Self {
+    Self {
+      scope: value.scope,
+      name: value.name,
+      description: value.description,
+      updated_at: value.updated_at,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiCreateScopeTeamRequest {
+  pub name: String,
+  #[serde(default)]
+  pub description: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiUpdateScopeTeamRequest {
+  pub description: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiScopeTeamMember {
+  pub scope: ScopeName,
+  pub team: String,
+  pub user: ApiUser,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<(ScopeTeamMember, UserPublic)> for ApiScopeTeamMember {
+  fn from((member, user): (ScopeTeamMember, UserPublic)) -> Self {
+    assert_eq!(member.user_id, user.id);
+    Self {
+      scope: member.scope,
+      team: member.team,
+      user: user.into(),
+      created_at: member.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiScopeTeamPackage {
+  pub scope: ScopeName,
+  pub team: String,
+  pub package: PackageName,
+  pub can_publish: bool,
+  pub can_yank: bool,
+  pub can_manage_tokens: bool,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<ScopeTeamPackage> for ApiScopeTeamPackage {
+  fn from(value: ScopeTeamPackage) -> Self {
+    Self {
+      scope: value.scope,
+      team: value.team,
+      package: value.package,
+      can_publish: value.can_publish,
+      can_yank: value.can_yank,
+      can_manage_tokens: value.can_manage_tokens,
+      updated_at: value.updated_at,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiSetScopeTeamPackageRequest {
+  #[serde(default)]
+  pub can_publish: bool,
+  #[serde(default)]
+  pub can_yank: bool,
+  #[serde(default)]
+  pub can_manage_tokens: bool,
+}
+
+/// A scope webhook. The secret is write-only and never part of responses.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiWebhook {
+  pub id: Uuid,
+  pub scope: ScopeName,
+  pub url: String,
+  pub events: Vec<WebhookEvent>,
+  pub description: String,
+  pub is_active: bool,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<ScopeWebhook> for ApiWebhook {
+  fn from(value: ScopeWebhook) -> Self {
+    Self {
+      id: value.id,
+      scope: value.scope,
+      url: value.url,
+      events: value.events,
+      description: value.description,
+      is_active: value.is_active,
+      updated_at: value.updated_at,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiCreateWebhookRequest {
+  pub url: String,
+  pub secret: String,
+  pub events: Vec<WebhookEvent>,
+  #[serde(default)]
+  pub description: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiUpdateWebhookRequest {
+  pub url: Option<String>,
+  pub secret: Option<String>,
+  pub events: Option<Vec<WebhookEvent>>,
+  pub description: Option<String>,
+  pub is_active: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiWebhookDelivery {
+  pub id: Uuid,
+  pub webhook_id: Uuid,
+  pub event: WebhookEvent,
+  pub payload: serde_json::Value,
+  pub status: WebhookDeliveryStatus,
+  pub attempts: i32,
+  /// When the next attempt is made, if the delivery is still pending.
+  pub next_attempt_at: Option<DateTime<Utc>>,
+  pub last_response_status: Option<i32>,
+  pub last_error: Option<String>,
+  pub delivered_at: Option<DateTime<Utc>>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<WebhookDelivery> for ApiWebhookDelivery {
+  fn from(value: WebhookDelivery) -> Self {
+    Self {
+      id: value.id,
+      webhook_id: value.webhook_id,
+      event: value.event,
+      payload: value.payload,
+      status: value.status,
+      attempts: value.attempts,
+      next_attempt_at: (value.status == WebhookDeliveryStatus::Pending)
+        .then_some(value.next_attempt_at),
+      last_response_status: value.last_response_status,
+      last_error: value.last_error,
+      delivered_at: value.delivered_at,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiLockfileRequest {
+  /// `jsr:` specifiers, like `jsr:@std/path@^1`.
+  pub specifiers: Vec<String>,
+}
+
+/// A fragment of a `deno.lock` file (version 4) with the `jsr:` packages that
+/// the requested specifiers resolve to, including transitive dependencies.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiLockfile {
+  pub version: String,
+  /// Maps `jsr:` package requirements to the version they resolved to.
+  pub specifiers: BTreeMap<String, String>,
+  /// Keyed by `@scope/name@version`.
+  pub jsr: BTreeMap<String, ApiLockfileJsrPackage>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiLockfileJsrPackage {
+  /// The SHA-256 hash of the version's `_meta.json` file, hex encoded.
+  pub integrity: String,
+  /// The `jsr:` dependencies of the version. A dependency is written as
+  /// `jsr:@scope/name` if only one version of it is in the lockfile, and as
+  /// `jsr:@scope/name@version` otherwise.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub dependencies: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiValidateConfigRequest {
+  /// The path of the config file in the package, e.g. `/deno.json`. Only
+  /// used in messages.
+  pub path: Option<PackagePath>,
+  /// The contents of the `jsr.json` or `deno.json(c)` file.
+  pub contents: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ApiConfigIssueSeverity {
+  /// Publishing will fail until this is fixed.
+  Error,
+  /// Publishing may succeed, but likely not as intended.
+  Warning,
+  /// Nothing to fix, but worth knowing before publishing.
+  Info,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiConfigIssue {
+  pub severity: ApiConfigIssueSeverity,
+  /// The error code a publish would fail with, e.g. `configFileExportsInvalid`.
+  pub code: String,
+  /// The config file field the issue is about, if any.
+  pub field: Option<String>,
+  pub message: String,
+  /// How to fix the issue.
+  pub hint: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiValidateConfigResponse {
+  /// Whether no issue with [ApiConfigIssueSeverity::Error] was found.
+  pub valid: bool,
+  pub scope: Option<ScopeName>,
+  pub package: Option<PackageName>,
+  pub version: Option<Version>,
+  /// The normalized exports, if they are valid.
+  pub exports: Option<IndexMap<String, String>>,
+  /// Whether the package already exists. `None` if the name is invalid.
+  pub package_exists: Option<bool>,
+  pub issues: Vec<ApiConfigIssue>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiRenderMarkdownRequest {
+  pub markdown: String,
+  /// The scope whose markdown settings to render with. Without a scope, raw
+  /// HTML is escaped and only the built-in alerts are rendered.
+  pub scope: Option<ScopeName>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiRenderMarkdownResponse {
+  pub html: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiAdminAnnouncementRequest {
+  pub title: String,
+  pub body: String,
+  pub severity: AnnouncementSeverity,
+  #[serde(default)]
+  pub features: Vec<String>,
+  pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiReservedName {
+  pub kind: ReservedNameKind,
+  pub name: String,
+  pub reason: Option<String>,
+  pub expires_at: Option<DateTime<Utc>>,
+  pub created_by: Option<Uuid>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<ReservedName> for ApiReservedName {
+  fn from(value: ReservedName) -> Self {
+    Self {
+      kind: value.kind,
+      name: value.name,
+      reason: value.reason,
+      expires_at: value.expires_at,
+      created_by: value.created_by,
+      updated_at: value.updated_at,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiAdminReserveNameRequest {
+  pub kind: ReservedNameKind,
+  pub name: String,
+  pub reason: Option<String>,
+  pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiRateLimits {
+  pub tiers: Vec<ApiRateLimitTier>,
+  pub scopes: Vec<ApiScopeRateLimit>,
+  pub tokens: Vec<ApiTokenRateLimit>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiRateLimitTier {
+  pub tier: RateLimitTier,
+  pub requests_per_minute: u32,
+  pub updated_at: DateTime<Utc>,
+}
+
+impl From<RateLimitTierLimit> for ApiRateLimitTier {
+  fn from(value: RateLimitTierLimit) -> Self {
+    Self {
+      tier: value.tier,
+      requests_per_minute: value.requests_per_minute as u32,
+      updated_at: value.updated_at,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiScopeRateLimit {
+  pub scope: ScopeName,
+  pub requests_per_minute: u32,
+  pub reason: Option<String>,
+  pub created_by: Option<Uuid>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<ScopeRateLimit> for ApiScopeRateLimit {
+  fn from(value: ScopeRateLimit) -> Self {
+    Self {
+      scope: value.scope,
+      requests_per_minute: value.requests_per_minute as u32,
+      reason: value.reason,
+      created_by: value.created_by,
+      updated_at: value.updated_at,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiPublishLimits {
+  pub max_tarball_size: u64,
+  pub max_file_size: u64,
+  pub max_total_file_size: u64,
+  pub max_file_count: usize,
+  pub max_path_depth: usize,
+  pub max_path_length: usize,
+  pub max_module_count: usize,
+}
+
+impl From<PublishLimits> for ApiPublishLimits {
+  fn from(value: PublishLimits) -> Self {
+    Self {
+      max_tarball_size: value.max_tarball_size,
+      max_file_size: value.max_file_size,
+      max_total_file_size: value.max_total_file_size,
+      max_file_count: value.max_file_count,
+      max_path_depth: value.max_path_depth,
+      max_path_length: value.max_path_length,
+      max_module_count: value.max_module_count,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiScopePublishLimits {
+  pub scope: ScopeName,
+  pub max_tarball_size: Option<u64>,
+  pub max_file_size: Option<u64>,
+  pub max_total_file_size: Option<u64>,
+  pub max_file_count: Option<u32>,
+  pub max_path_depth: Option<u32>,
+  pub max_path_length: Option<u32>,
+  pub max_module_count: Option<u32>,
+  pub reason: Option<String>,
+  pub created_by: Option<Uuid>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<ScopePublishLimits> for ApiScopePublishLimits {
+  fn from(value: ScopePublishLimits) -> Self {
+    Self {
+      scope: value.scope,
+      max_tarball_size: value.max_tarball_size.map(|limit| limit as u64),
+      max_file_size: value.max_file_size.map(|limit| limit as u64),
+      max_total_file_size: value.max_total_file_size.map(|limit| limit as u64),
+      max_file_count: value.max_file_count.map(|limit| limit as u32),
+      max_path_depth: value.max_path_depth.map(|limit| limit as u32),
+      max_path_length: value.max_path_length.map(|limit| limit as u32),
+      max_module_count: value.max_module_count.map(|limit| limit as u32),
+      reason: value.reason,
+      created_by: value.created_by,
+      updated_at: value.updated_at,
+      created_at: value.created_at,
+    }
+  }
+}
+
+/// Limits that are left out are not overridden.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiAdminUpdatePublishLimitsRequest {
+  pub max_tarball_size: Option<u64>,
+  pub max_file_size: Option<u64>,
+  pub max_total_file_size: Option<u64>,
+  pub max_file_count: Option<u32>,
+  pub max_path_depth: Option<u32>,
+  pub max_path_length: Option<u32>,
+  pub max_module_count: Option<u32>,
+  pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiTokenRateLimit {
+  pub token_id: Uuid,
+  pub requests_per_minute: u32,
+  pub reason: Option<String>,
+  pub created_by: Option<Uuid>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<TokenRateLimit> for ApiTokenRateLimit {
+  fn from(value: TokenRateLimit) -> Self {
+    Self {
+      token_id: value.token_id,
+      requests_per_minute: value.requests_per_minute as u32,
+      reason: value.reason,
+      created_by: value.created_by,
+      updated_at: value.updated_at,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiAdminUpdateRateLimitRequest {
+  pub requests_per_minute: u32,
+  /// Ignored for tiers.
+  pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiFeatureFlag {
+  pub name: String,
+  pub description: String,
+  pub enabled: bool,
+  pub scopes: Vec<ScopeName>,
+  pub user_ids: Vec<Uuid>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<FeatureFlag> for ApiFeatureFlag {
+  fn from(value: FeatureFlag) -> Self {
+    Self {
+      name: value.name,
+      description: value.description,
+      enabled: value.enabled,
+      scopes: value.scopes,
+      user_ids: value.user_ids,
+      updated_at: value.updated_at,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiAdminFeatureFlagRequest {
+  #[serde(default)]
+  pub description: String,
+  pub enabled: bool,
+  #[serde(default)]
+  pub scopes: Vec<ScopeName>,
+  #[serde(default)]
+  pub user_ids: Vec<Uuid>,
+}
+
+/// A deleted package or package version. `version` is `None` if the package
+/// itself was deleted.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiTombstone {
+  pub scope: ScopeName,
+  pub package: PackageName,
+  pub version: Option<Version>,
+  pub deleted_at: DateTime<Utc>,
+  /// When the tombstone will be purged, after which it can not be restored.
+  pub purge_at: DateTime<Utc>,
+}
+
+impl From<(Tombstone, TombstoneRetention)> for ApiTombstone {
+  fn from((tombstone, retention): (Tombstone, TombstoneRetention)) -> Self {
+    Self {
+      scope: tombstone.scope,
+      package: tombstone.name,
+      version: tombstone.version,
+      deleted_at: tombstone.deleted_at,
+      purge_at: retention.purge_at(tombstone.deleted_at),
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiDataExport {
+  pub id: Uuid,
+  pub status: DataExportStatus,
+  /// The size of the archive in bytes, once it is completed.
+  pub size: Option<i64>,
+  pub error: Option<String>,
+  pub completed_at: Option<DateTime<Utc>>,
+  /// When the archive will be deleted, once it is completed.
+  pub expires_at: Option<DateTime<Utc>>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<DataExport> for ApiDataExport {
+  fn from(value: DataExport) -> Self {
+    let retention =
+      chrono::Duration::days(crate::data_export::DATA_EXPORT_RETENTION_DAYS);
+    Self {
+      id: value.id,
+      status: value.status,
+      size: value.size,
+      error: value.error,
+      completed_at: value.completed_at,
+      expires_at: value.completed_at.map(|at| at + retention),
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiNotificationPreferences {
+  pub scope_invites: bool,
+  pub publish_failures: bool,
+  pub scope_digests: bool,
+  pub updated_at: DateTime<Utc>,
+}
+
+impl From<NotificationPreferences> for ApiNotificationPreferences {
+  fn from(value: NotificationPreferences) -> Self {
+    Self {
+      scope_invites: value.scope_invites,
+      publish_failures: value.publish_failures,
+      scope_digests: value.scope_digests,
+      updated_at: value.updated_at,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiUpdateNotificationPreferencesRequest {
+  #[serde(default)]
+  pub scope_invites: Option<bool>,
+  #[serde(default)]
+  pub publish_failures: Option<bool>,
+  #[serde(default)]
+  pub scope_digests: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiScheduledJob {
+  pub name: String,
+  pub schedule: String,
+  pub next_run_at: DateTime<Utc>,
+  /// Whether a run of the job holds an unexpired lease on it.
+  pub running: bool,
+  pub running_since: Option<DateTime<Utc>>,
+  pub last_started_at: Option<DateTime<Utc>>,
+  pub last_finished_at: Option<DateTime<Utc>>,
+  pub last_status: Option<ScheduledJobStatus>,
+  pub last_error: Option<String>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<ScheduledJob> for ApiScheduledJob {
+  fn from(value: ScheduledJob) -> Self {
+    Self {
+      running: value
+        .lease_expires_at
+        .is_some_and(|lease_expires_at| lease_expires_at > Utc::now()),
+      name: value.name,
+      schedule: value.schedule,
+      next_run_at: value.next_run_at,
+      running_since: value.running_since,
+      last_started_at: value.last_started_at,
+      last_finished_at: value.last_finished_at,
+      last_status: value.last_status,
+      last_error: value.last_error,
+      updated_at: value.updated_at,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiScopeOidcIssuer {
+  pub id: Uuid,
+  pub issuer: String,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<ScopeOidcIssuer> for ApiScopeOidcIssuer {
+  fn from(value: ScopeOidcIssuer) -> Self {
+    Self {
+      id: value.id,
+      issuer: value.issuer,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiCreateScopeOidcIssuerRequest {
+  pub issuer: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiTrustedPublisher {
+  pub id: Uuid,
+  pub issuer: String,
+  /// The CI system of the issuer, or `custom` for issuers registered by the
+  /// scope.
+  pub provider: String,
+  pub subject: String,
+  pub created_by: Option<Uuid>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<PackageTrustedPublisher> for ApiTrustedPublisher {
+  fn from(value: PackageTrustedPublisher) -> Self {
+    Self {
+      id: value.id,
+      provider: crate::oidc::provider_for_issuer(&value.issuer)
+        .name
+        .to_string(),
+      issuer: value.issuer,
+      subject: value.subject,
+      created_by: value.created_by,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiCreateTrustedPublisherRequest {
+  pub issuer: String,
+  pub subject: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiBucketMetrics {
+  pub bucket: String,
+  /// Whether requests to the bucket currently fail right away because too
+  /// many of them failed recently.
+  pub circuit_open: bool,
+  pub operations: Vec<crate::s3_policy::StorageOperationMetrics>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiCreateAbuseReportRequest {
+  pub scope: ScopeName,
+  pub package: PackageName,
+  /// The version that is reported, if the report is not about the whole
+  /// package.
+  #[serde(default)]
+  pub version: Option<Version>,
+  pub reason: AbuseReportReason,
+  pub description: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiAbuseReport {
+  pub id: Uuid,
+  pub scope: ScopeName,
+  pub package: PackageName,
+  pub version: Option<Version>,
+  pub reason: AbuseReportReason,
+  pub description: String,
+  pub reporter_id: Option<Uuid>,
+  pub status: AbuseReportStatus,
+  pub reviewer_id: Option<Uuid>,
+  pub resolution: Option<String>,
+  pub resolved_at: Option<DateTime<Utc>>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<AbuseReport> for ApiAbuseReport {
+  fn from(value: AbuseReport) -> Self {
+    Self {
+      id: value.id,
+      scope: value.scope,
+      package: value.name,
+      version: value.version,
+      reason: value.reason,
+      description: value.description,
+      reporter_id: value.reporter_id,
+      status: value.status,
+      reviewer_id: value.reviewer_id,
+      resolution: value.resolution,
+      resolved_at: value.resolved_at,
+      updated_at: value.updated_at,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiAdminUpdateAbuseReportRequest {
+  pub status: AbuseReportStatus,
+  /// What was done about the report, shown to other staff.
+  #[serde(default)]
+  pub resolution: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiPackageVersionQuarantine {
+  pub scope: ScopeName,
+  pub package: PackageName,
+  pub version: Version,
+  pub reason: String,
+  pub report_id: Option<Uuid>,
+  pub quarantined_by: Option<Uuid>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<PackageVersionQuarantine> for ApiPackageVersionQuarantine {
+  fn from(value: PackageVersionQuarantine) -> Self {
+    Self {
+      scope: value.scope,
+      package: value.name,
+      version: value.version,
+      reason: value.reason,
+      report_id: value.report_id,
+      quarantined_by: value.quarantined_by,
+      created_at: value.created_at,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiAdminQuarantineVersionRequest {
+  pub reason: String,
+  /// The abuse report that led to the quarantine, if any.
+  #[serde(default)]
+  pub report_id: Option<Uuid>,
+}
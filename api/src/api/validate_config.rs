@@ -0,0 +1,505 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+use hyper::Body;
+use hyper::Request;
+use routerify::prelude::RequestExt;
+use tracing::instrument;
+
+use crate::RegistryUrl;
+use crate::db::Database;
+use crate::db::PackageVisibility;
+use crate::db::ReservedNameKind;
+use crate::iam::ReqIamExt;
+use crate::ids::PackagePath;
+use crate::tarball::PublishError;
+use crate::tarball::exports_map_from_json;
+use crate::tarball::parse_config_file;
+use crate::util::ApiResult;
+use crate::util::LicenseStore;
+use crate::util::decode_json;
+
+use super::ApiConfigIssue;
+use super::ApiConfigIssueSeverity;
+use super::ApiError;
+use super::ApiValidateConfigRequest;
+use super::ApiValidateConfigResponse;
+
+impl ApiConfigIssue {
+  fn new(
+    severity: ApiConfigIssueSeverity,
+    code: &str,
+    field: Option<&str>,
+    message: impl Into<String>,
+    hint: Option<String>,
+  ) -> Self {
+    Self {
+      severity,
+      code: code.to_string(),
+      field: field.map(|field| field.to_string()),
+      message: message.into(),
+      hint,
+    }
+  }
+
+  fn from_publish_error(
+    error: &PublishError,
+    field: Option<&str>,
+    hint: Option<String>,
+  ) -> Self {
+    Self::new(
+      ApiConfigIssueSeverity::Error,
+      error.user_error_code().unwrap_or("invalidConfigFile"),
+      field,
+      error.to_string(),
+      hint,
+    )
+  }
+
+  fn from_api_error(error: &ApiError, hint: Option<String>) -> Self {
+    Self::new(
+      ApiConfigIssueSeverity::Error,
+      error.code(),
+      Some("name"),
+      error.message(),
+      hint,
+    )
+  }
+}
+
+/// Runs the checks a publish would run on the config file alone, without a
+/// tarball, so tooling can validate a package setup before the first publish.
+/// Problems with the config file are reported as issues in a successful
+/// response, not as an error response.
+#[instrument(name = "POST /api/validate-config", skip(req))]
+pub async fn validate_config_handler(
+  mut req: Request<Body>,
+) -> ApiResult<ApiValidateConfigResponse> {
+  let ApiValidateConfigRequest { path, contents } =
+    decode_json(&mut req).await?;
+  let path =
+    path.unwrap_or_else(|| PackagePath::new("/jsr.json".to_string()).unwrap());
+
+  let db = req.data::<Database>().unwrap();
+  let license_store = req.data::<LicenseStore>().unwrap();
+  let registry_url = &req.data::<RegistryUrl>().unwrap().0;
+  let iam = req.iam();
+
+  let mut response = ApiValidateConfigResponse {
+    valid: false,
+    scope: None,
+    package: None,
+    version: None,
+    exports: None,
+    package_exists: None,
+    issues: vec![],
+  };
+
+  let config_file = match parse_config_file(&path, contents.as_bytes()) {
+    Ok(config_file) => config_file,
+    Err(err) => {
+      response.issues.push(ApiConfigIssue::from_publish_error(
+        &err,
+        None,
+        Some(
+          "The config file must be a JSON(C) object with a `name` field of \
+           the form `@scope/package`."
+            .to_string(),
+        ),
+      ));
+      return Ok(response);
+    }
+  };
+
+  match exports_map_from_json(config_file.exports) {
+    Ok(exports) if exports.is_empty() => {
+      response.issues.push(ApiConfigIssue::from_publish_error(
+        &PublishError::ConfigFileExportsInvalid {
+          path: Box::new(path.clone()),
+          invalid_exports: "exports config must have at least one entry"
+            .to_string(),
+        },
+        Some("exports"),
+        Some(
+          "Add at least one entrypoint, e.g. `\"exports\": \"./mod.ts\"`."
+            .to_string(),
+        ),
+      ));
+    }
+    Ok(exports) => response.exports = Some(exports.into_inner()),
+    Err(invalid_exports) => {
+      response.issues.push(ApiConfigIssue::from_publish_error(
+        &PublishError::ConfigFileExportsInvalid {
+          path: Box::new(path.clone()),
+          invalid_exports,
+        },
+        Some("exports"),
+        Some(format!(
+          "See {registry_url}docs/publishing-packages#package-config-file."
+        )),
+      ));
+    }
+  }
+
+  if config_file.version.is_none() {
+    response.issues.push(ApiConfigIssue::new(
+      ApiConfigIssueSeverity::Warning,
+      "missingVersion",
+      Some("version"),
+      "the config file has no 'version' field",
+      Some(
+        "Add a `version` field, or pass `--set-version` to `deno publish`."
+          .to_string(),
+      ),
+    ));
+  }
+
+  match &config_file.license {
+    Some(license) if !license_store.is_recognized(license) => {
+      response.issues.push(ApiConfigIssue::from_publish_error(
+        &PublishError::InvalidLicense,
+        Some("license"),
+        Some("Use a valid SPDX license identifier, e.g. `MIT`.".to_string()),
+      ));
+    }
+    Some(_) => {}
+    None => {
+      response.issues.push(ApiConfigIssue::new(
+        ApiConfigIssueSeverity::Warning,
+        "missingLicense",
+        Some("license"),
+        "the config file has no 'license' field",
+        Some(
+          "Add a `license` field, or include a LICENSE file in the package."
+            .to_string(),
+        ),
+      ));
+    }
+  }
+
+  let scope = config_file.name.scope;
+  let package = config_file.name.package;
+
+  if db.get_scope(&scope).await?.is_none() {
+    response.issues.push(ApiConfigIssue::from_api_error(
+      &ApiError::ScopeNotFound,
+      Some(format!("Create the scope @{scope} at {registry_url}new.")),
+    ));
+  } else {
+    let mut existing = db.get_package(&scope, &package).await?;
+    // Private packages are reported as not existing to anyone who can't read
+    // them, so this route can't be used to discover them.
+    if existing.as_ref().is_some_and(|(existing, _, _)| {
+      existing.visibility == PackageVisibility::Private
+    }) {
+      match iam.check_private_package_read_access(&scope).await {
+        Ok(()) => {}
+        Err(ApiError::PackageNotFound) => existing = None,
+        Err(err) => return Err(err),
+      }
+    }
+    response.package_exists = Some(existing.is_some());
+    match existing {
+      Some((existing, _, _)) => {
+        if existing.is_archived {
+          response.issues.push(ApiConfigIssue::from_api_error(
+            &ApiError::PackageArchived,
+            Some(format!(
+              "Unarchive the package at {registry_url}@{scope}/{package}/settings."
+            )),
+          ));
+        }
+        if let Some(version) = &config_file.version
+          && db
+            .get_package_version(&scope, &package, version)
+            .await?
+            .is_some()
+        {
+          response.issues.push(ApiConfigIssue::new(
+            ApiConfigIssueSeverity::Error,
+            "duplicateVersionPublish",
+            Some("version"),
+            format!(
+              "version {version} of @{scope}/{package} is already published"
+            ),
+            Some(
+              "Published versions are immutable. Bump the `version` field."
+                .to_string(),
+            ),
+          ));
+        }
+      }
+      None if db.check_is_bad_word(&package.to_string()).await? => {
+        response.issues.push(ApiConfigIssue::from_api_error(
+          &ApiError::PackageNameNotAllowed,
+          Some("Choose a different package name.".to_string()),
+        ));
+      }
+      None
+        if db
+          .check_is_reserved_name(ReservedNameKind::Package, &package)
+          .await? =>
+      {
+        response.issues.push(ApiConfigIssue::from_api_error(
+          &ApiError::PackageNameReserved,
+          Some("Choose a different package name.".to_string()),
+        ));
+      }
+      None => {
+        if let Some(existing) =
+          db.find_confusable_package(&scope, &package).await?
+        {
+          response.issues.push(ApiConfigIssue::from_api_error(
+            &ApiError::PackageNameConfusable { existing },
+            Some("Choose a different package name.".to_string()),
+          ));
+        } else {
+          response.issues.push(ApiConfigIssue::new(
+            ApiConfigIssueSeverity::Info,
+            "packageNotFound",
+            Some("name"),
+            format!("the package @{scope}/{package} does not exist yet"),
+            Some(format!(
+              "`deno publish` creates it on the first publish, or create it at \
+               {registry_url}new."
+            )),
+          ));
+        }
+      }
+    }
+
+    if iam.is_anonymous() {
+      response.issues.push(ApiConfigIssue::new(
+        ApiConfigIssueSeverity::Info,
+        "missingAuthentication",
+        Some("name"),
+        "publish permissions were not checked",
+        Some(
+          "Authenticate the request to also check whether you can publish \
+           to this scope."
+            .to_string(),
+        ),
+      ));
+    } else {
+      let access = match &config_file.version {
+        Some(version) => iam
+          .check_publish_access(&scope, &package, version)
+          .await
+          .map(|_| ()),
+        None => iam.check_scope_write_access(&scope).await.map(|_| ()),
+      };
+      match access {
+        Ok(()) => {}
+        // Already reported above.
+        Err(ApiError::PackageNotFound) => {}
+        Err(err) if err.status_code().is_server_error() => return Err(err),
+        Err(err) => {
+          let hint = match &err {
+            ApiError::ActorNotScopeMember => Some(format!(
+              "Ask an admin of @{scope} to invite you to the scope."
+            )),
+            ApiError::ScopeRequiresPublishingFromCI => Some(
+              "Publish from GitHub Actions with the `id-token: write` \
+               permission."
+                .to_string(),
+            ),
+            _ => None,
+          };
+          response
+            .issues
+            .push(ApiConfigIssue::from_api_error(&err, hint));
+        }
+      }
+    }
+  }
+
+  response.scope = Some(scope);
+  response.package = Some(package);
+  response.version = config_file.version;
+  response.valid = !response
+    .issues
+    .iter()
+    .any(|issue| issue.severity == ApiConfigIssueSeverity::Error);
+
+  Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::StatusCode;
+  use serde_json::json;
+
+  use crate::api::ApiConfigIssueSeverity;
+  use crate::api::ApiValidateConfigResponse;
+  use crate::db::PackageVisibility;
+  use crate::ids::PackageName;
+  use crate::util::test::ApiResultExt;
+  use crate::util::test::TestSetup;
+
+  #[tokio::test]
+  async fn validate_config() {
+    let mut t = TestSetup::new().await;
+    let token = t.user1.token.clone();
+
+    let res = t
+      .http()
+      .post("/api/validate-config")
+      .body_json(json!({
+        "contents": r#"{
+          // comments are allowed
+          "name": "@scope/foo",
+          "version": "1.0.0",
+          "license": "MIT",
+          "exports": { ".": "./mod.ts" },
+        }"#,
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiValidateConfigResponse>()
+      .await;
+    assert!(res.valid, "{res:#?}");
+    assert_eq!(res.package_exists, Some(false));
+    assert_eq!(res.exports.unwrap()["."], "./mod.ts");
+    assert_eq!(res.issues.len(), 1);
+    assert_eq!(res.issues[0].code, "packageNotFound");
+
+    let res = t
+      .http()
+      .post("/api/validate-config")
+      .body_json(json!({
+        "path": "/deno.json",
+        "contents": r#"{
+          "name": "@scope/foo",
+          "license": "NOT-A-LICENSE",
+          "exports": { "mod": "./mod.ts" }
+        }"#,
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiValidateConfigResponse>()
+      .await;
+    assert!(!res.valid);
+    let codes = res
+      .issues
+      .iter()
+      .map(|issue| (issue.severity, issue.code.as_str()))
+      .collect::<Vec<_>>();
+    assert!(
+      codes
+        .contains(&(ApiConfigIssueSeverity::Error, "configFileExportsInvalid"))
+    );
+    assert!(codes.contains(&(ApiConfigIssueSeverity::Error, "invalidLicense")));
+    assert!(
+      codes.contains(&(ApiConfigIssueSeverity::Warning, "missingVersion"))
+    );
+
+    // the scope does not exist, and user2 is not a member of @scope
+    let res = t
+      .http()
+      .post("/api/validate-config")
+      .body_json(json!({
+        "contents": r#"{ "name": "@doesnotexist/foo", "version": "1.0.0", "license": "MIT", "exports": "./mod.ts" }"#,
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiValidateConfigResponse>()
+      .await;
+    assert!(!res.valid);
+    assert_eq!(res.issues[0].code, "scopeNotFound");
+
+    let token = t.user2.token.clone();
+    let res = t
+      .http()
+      .post("/api/validate-config")
+      .body_json(json!({
+        "contents": r#"{ "name": "@scope/foo", "version": "1.0.0", "license": "MIT", "exports": "./mod.ts" }"#,
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiValidateConfigResponse>()
+      .await;
+    assert!(!res.valid);
+    assert!(
+      res
+        .issues
+        .iter()
+        .any(|issue| issue.code == "actorNotScopeMember")
+    );
+
+    // private packages are reported as not existing to non-members
+    let name = PackageName::new("foo".to_owned()).unwrap();
+    t.db().create_package(&t.scope.scope, &name).await.unwrap();
+    t.db()
+      .update_package_visibility(
+        &t.user1.user.id,
+        false,
+        &t.scope.scope,
+        &name,
+        PackageVisibility::Private,
+      )
+      .await
+      .unwrap();
+    for token in [Some(token), None] {
+      let res = t
+        .http()
+        .post("/api/validate-config")
+        .body_json(json!({
+          "contents": r#"{ "name": "@scope/foo", "version": "1.0.0", "license": "MIT", "exports": "./mod.ts" }"#,
+        }))
+        .token(token.as_deref())
+        .call()
+        .await
+        .unwrap()
+        .expect_ok::<ApiValidateConfigResponse>()
+        .await;
+      assert_eq!(res.package_exists, Some(false));
+      assert!(
+        res
+          .issues
+          .iter()
+          .any(|issue| issue.code == "packageNotFound")
+      );
+    }
+    let token = t.user1.token.clone();
+    let res = t
+      .http()
+      .post("/api/validate-config")
+      .body_json(json!({
+        "contents": r#"{ "name": "@scope/foo", "version": "1.0.0", "license": "MIT", "exports": "./mod.ts" }"#,
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiValidateConfigResponse>()
+      .await;
+    assert_eq!(res.package_exists, Some(true));
+
+    let res = t
+      .http()
+      .post("/api/validate-config")
+      .body_json(json!({ "contents": "not json" }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiValidateConfigResponse>()
+      .await;
+    assert!(!res.valid);
+    assert_eq!(res.issues[0].code, "invalidConfigFile");
+
+    t.http()
+      .post("/api/validate-config")
+      .body_json(json!({}))
+      .call()
+      .await
+      .unwrap()
+      .expect_err(StatusCode::BAD_REQUEST)
+      .await;
+  }
+}
@@ -0,0 +1,559 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+use hyper::Body;
+use hyper::Request;
+use hyper::Response;
+use hyper::StatusCode;
+use routerify::Router;
+use routerify::ext::RequestExt;
+use tracing::Span;
+use tracing::field;
+use tracing::instrument;
+
+use crate::db::Database;
+use crate::db::NewScopeWebhook;
+use crate::db::UpdateScopeWebhook;
+use crate::db::WebhookEvent;
+use crate::iam::ReqIamExt;
+use crate::util;
+use crate::util::ApiResult;
+use crate::util::RequestIdExt;
+use crate::util::decode_json;
+use crate::util::pagination;
+use crate::webhooks;
+
+use super::ApiCreateWebhookRequest;
+use super::ApiError;
+use super::ApiList;
+use super::ApiUpdateWebhookRequest;
+use super::ApiWebhook;
+use super::ApiWebhookDelivery;
+
+const MAX_WEBHOOKS_PER_SCOPE: usize = 10;
+const MIN_SECRET_LENGTH: usize = 16;
+const MAX_SECRET_LENGTH: usize = 256;
+const MAX_DESCRIPTION_LENGTH: usize = 250;
+
+pub fn webhooks_router() -> Router<Body, ApiError> {
+  Router::builder()
+    .get("/", util::auth(util::json(list_handler)))
+    .post("/", util::auth(util::json(create_handler)))
+    .get("/:webhook", util::auth(util::json(get_handler)))
+    .patch("/:webhook", util::auth(util::json(update_handler)))
+    .delete("/:webhook", util::auth(delete_handler))
+    .get(
+      "/:webhook/deliveries",
+      util::auth(util::json(list_deliveries_handler)),
+    )
+    .build()
+    .unwrap()
+}
+
+fn validate_url(url: &str) -> Result<(), ApiError> {
+  let parsed = url::Url::parse(url).map_err(|_| ApiError::WebhookInvalid {
+    msg: "the url is not a valid URL".into(),
+  })?;
+  if parsed.scheme() != "https" {
+    return Err(ApiError::WebhookInvalid {
+      msg: "the url must use https".into(),
+    });
+  }
+  if !parsed.username().is_empty() || parsed.password().is_some() {
+    return Err(ApiError::WebhookInvalid {
+      msg: "the url must not contain credentials".into(),
+    });
+  }
+  // Host names are only resolved when a delivery is sent, which only connects
+  // to public addresses. See [crate::webhooks].
+  let is_public = match parsed.host() {
+    Some(url::Host::Ipv4(ip)) => webhooks::is_public_address(ip.into()),
+    Some(url::Host::Ipv6(ip)) => webhooks::is_public_address(ip.into()),
+    Some(url::Host::Domain(domain)) => {
+      let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+      domain != "localhost" && !domain.ends_with(".localhost")
+    }
+    None => false,
+  };
+  if !is_public {
+    return Err(ApiError::WebhookInvalid {
+      msg: "the url must point at a public address".into(),
+    });
+  }
+  Ok(())
+}
+
+fn validate_secret(secret: &str) -> Result<(), ApiError> {
+  if secret.len() < MIN_SECRET_LENGTH || secret.len() > MAX_SECRET_LENGTH {
+    return Err(ApiError::WebhookInvalid {
+      msg: format!(
+        "the secret must be between {MIN_SECRET_LENGTH} and {MAX_SECRET_LENGTH} characters long"
+      )
+      .into(),
+    });
+  }
+  Ok(())
+}
+
+fn validate_events(events: &[WebhookEvent]) -> Result<(), ApiError> {
+  if events.is_empty() {
+    return Err(ApiError::WebhookInvalid {
+      msg: "at least one event must be selected".into(),
+    });
+  }
+  Ok(())
+}
+
+fn validate_description(description: &str) -> Result<(), ApiError> {
+  if description.len() > MAX_DESCRIPTION_LENGTH {
+    return Err(ApiError::WebhookInvalid {
+      msg: format!(
+        "the description must be at most {MAX_DESCRIPTION_LENGTH} characters long"
+      )
+      .into(),
+    });
+  }
+  Ok(())
+}
+
+#[instrument(
+  name = "GET /api/scopes/:scope/webhooks",
+  skip(req),
+  fields(scope)
+)]
+async fn list_handler(req: Request<Body>) -> ApiResult<Vec<ApiWebhook>> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+
+  let db = req.data::<Database>().unwrap();
+  db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?;
+
+  let iam = req.iam();
+  iam.check_scope_admin_access(&scope).await?;
+
+  let webhooks = db.list_scope_webhooks(&scope).await?;
+  Ok(webhooks.into_iter().map(ApiWebhook::from).collect())
+}
+
+#[instrument(
+  name = "POST /api/scopes/:scope/webhooks",
+  skip(req),
+  fields(scope)
+)]
+async fn create_handler(mut req: Request<Body>) -> ApiResult<ApiWebhook> {
+  let scope = req.param_scope()?;
+  Span::current().record("scope", field::display(&scope));
+
+  let ApiCreateWebhookRequest {
+    url,
+    secret,
+    mut events,
+    description,
+  } = decode_json(&mut req).await?;
+  validate_url(&url)?;
+  validate_secret(&secret)?;
+  validate_events(&events)?;
+  validate_description(&description)?;
+  events.sort_by_key(|event| *event as u8);
+  events.dedup();
+
+  let db = req.data::<Database>().unwrap();
+  db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?;
+
+  let iam = req.iam();
+  let (user, sudo) = iam.check_scope_admin_access(&scope).await?;
+
+  let existing = db.list_scope_webhooks(&scope).await?;
+  if existing.len() >= MAX_WEBHOOKS_PER_SCOPE {
+    return Err(ApiError::WebhookLimitReached {
+      max_webhooks: MAX_WEBHOOKS_PER_SCOPE,
+    });
+  }
+
+  let webhook = db
+    .create_scope_webhook(
+      &user.id,
+      sudo,
+      NewScopeWebhook {
+        scope: &scope,
+        url: &url,
+        secret: &secret,
+        events: &events,
+        description: &description,
+        is_active: true,
+      },
+    )
+    .await?;
+
+  Ok(webhook.into())
+}
+
+#[instrument(
+  name = "GET /api/scopes/:scope/webhooks/:webhook",
+  skip(req),
+  fields(scope, webhook)
+)]
+async fn get_handler(req: Request<Body>) -> ApiResult<ApiWebhook> {
+  let scope = req.param_scope()?;
+  let webhook_id = req.param_uuid("webhook")?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("webhook", field::display(&webhook_id));
+
+  let db = req.data::<Database>().unwrap();
+  db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?;
+
+  let iam = req.iam();
+  iam.check_scope_admin_access(&scope).await?;
+
+  let webhook = db
+    .get_scope_webhook(&scope, webhook_id)
+    .await?
+    .ok_or(ApiError::WebhookNotFound)?;
+
+  Ok(webhook.into())
+}
+
+#[instrument(
+  name = "PATCH /api/scopes/:scope/webhooks/:webhook",
+  skip(req),
+  fields(scope, webhook)
+)]
+async fn update_handler(mut req: Request<Body>) -> ApiResult<ApiWebhook> {
+  let scope = req.param_scope()?;
+  let webhook_id = req.param_uuid("webhook")?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("webhook", field::display(&webhook_id));
+
+  let ApiUpdateWebhookRequest {
+    url,
+    secret,
+    mut events,
+    description,
+    is_active,
+  } = decode_json(&mut req).await?;
+  if let Some(url) = &url {
+    validate_url(url)?;
+  }
+  if let Some(secret) = &secret {
+    validate_secret(secret)?;
+  }
+  if let Some(events) = &mut events {
+    validate_events(events)?;
+    events.sort_by_key(|event| *event as u8);
+    events.dedup();
+  }
+  if let Some(description) = &description {
+    validate_description(description)?;
+  }
+
+  let db = req.data::<Database>().unwrap();
+  db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?;
+
+  let iam = req.iam();
+  let (user, sudo) = iam.check_scope_admin_access(&scope).await?;
+
+  let webhook = db
+    .update_scope_webhook(
+      &user.id,
+      sudo,
+      &scope,
+      webhook_id,
+      UpdateScopeWebhook {
+        url: url.as_deref(),
+        secret: secret.as_deref(),
+        events: events.as_deref(),
+        description: description.as_deref(),
+        is_active,
+      },
+    )
+    .await?
+    .ok_or(ApiError::WebhookNotFound)?;
+
+  Ok(webhook.into())
+}
+
+#[instrument(
+  name = "DELETE /api/scopes/:scope/webhooks/:webhook",
+  skip(req),
+  fields(scope, webhook)
+)]
+async fn delete_handler(req: Request<Body>) -> ApiResult<Response<Body>> {
+  let scope = req.param_scope()?;
+  let webhook_id = req.param_uuid("webhook")?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("webhook", field::display(&webhook_id));
+
+  let db = req.data::<Database>().unwrap();
+  db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?;
+
+  let iam = req.iam();
+  let (user, sudo) = iam.check_scope_admin_access(&scope).await?;
+
+  db.delete_scope_webhook(&user.id, sudo, &scope, webhook_id)
+    .await?
+    .ok_or(ApiError::WebhookNotFound)?;
+
+  let resp = Response::builder()
+    .status(StatusCode::NO_CONTENT)
+    .body(Body::empty())
+    .unwrap();
+  Ok(resp)
+}
+
+#[instrument(
+  name = "GET /api/scopes/:scope/webhooks/:webhook/deliveries",
+  skip(req),
+  fields(scope, webhook)
+)]
+async fn list_deliveries_handler(
+  req: Request<Body>,
+) -> ApiResult<ApiList<ApiWebhookDelivery>> {
+  let scope = req.param_scope()?;
+  let webhook_id = req.param_uuid("webhook")?;
+  Span::current().record("scope", field::display(&scope));
+  Span::current().record("webhook", field::display(&webhook_id));
+  let (start, limit) = pagination(&req);
+
+  let db = req.data::<Database>().unwrap();
+  db.get_scope(&scope).await?.ok_or(ApiError::ScopeNotFound)?;
+
+  let iam = req.iam();
+  iam.check_scope_admin_access(&scope).await?;
+
+  let webhook = db
+    .get_scope_webhook(&scope, webhook_id)
+    .await?
+    .ok_or(ApiError::WebhookNotFound)?;
+
+  let (total, deliveries) =
+    db.list_webhook_deliveries(webhook.id, start, limit).await?;
+
+  Ok(ApiList {
+    items: deliveries
+      .into_iter()
+      .map(ApiWebhookDelivery::from)
+      .collect(),
+    total,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::StatusCode;
+  use serde_json::json;
+
+  use crate::api::ApiList;
+  use crate::api::ApiWebhook;
+  use crate::api::ApiWebhookDelivery;
+  use crate::db::NewScopeMember;
+  use crate::db::WebhookDeliveryStatus;
+  use crate::db::WebhookEvent;
+  use crate::util::test::ApiResultExt;
+  use crate::util::test::TestSetup;
+  use crate::webhooks;
+
+  #[tokio::test]
+  async fn scope_webhooks() {
+    let mut t = TestSetup::new().await;
+    let token = t.user1.token.clone();
+
+    let webhook = t
+      .http()
+      .post("/api/scopes/scope/webhooks")
+      .body_json(json!({
+        "url": "https://example.com/hooks/jsr",
+        "secret": "0123456789abcdef",
+        "events": ["version_yanked", "version_published", "version_yanked"],
+        "description": "CI",
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiWebhook>()
+      .await;
+    assert_eq!(
+      webhook.events,
+      vec![WebhookEvent::VersionPublished, WebhookEvent::VersionYanked]
+    );
+    assert!(webhook.is_active);
+
+    // The secret is never returned.
+    let mut resp = t
+      .http()
+      .get(format!("/api/scopes/scope/webhooks/{}", webhook.id))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap();
+    let value = resp.expect_ok::<serde_json::Value>().await;
+    assert!(value.get("secret").is_none());
+
+    t.http()
+      .post("/api/scopes/scope/webhooks")
+      .body_json(json!({
+        "url": "http://example.com/hooks/jsr",
+        "secret": "0123456789abcdef",
+        "events": ["version_published"],
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "webhookInvalid")
+      .await;
+    for url in [
+      "https://127.0.0.1/hooks/jsr",
+      "https://169.254.169.254/latest/meta-data",
+      "https://[::1]/hooks/jsr",
+      "https://[fd00::1]/hooks/jsr",
+      "https://localhost/hooks/jsr",
+      "https://metadata.localhost./hooks/jsr",
+    ] {
+      t.http()
+        .post("/api/scopes/scope/webhooks")
+        .body_json(json!({
+          "url": url,
+          "secret": "0123456789abcdef",
+          "events": ["version_published"],
+        }))
+        .token(Some(&token))
+        .call()
+        .await
+        .unwrap()
+        .expect_err_code(StatusCode::BAD_REQUEST, "webhookInvalid")
+        .await;
+    }
+    t.http()
+      .post("/api/scopes/scope/webhooks")
+      .body_json(json!({
+        "url": "https://example.com/hooks/jsr",
+        "secret": "short",
+        "events": ["version_published"],
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "webhookInvalid")
+      .await;
+
+    let updated = t
+      .http()
+      .patch(format!("/api/scopes/scope/webhooks/{}", webhook.id))
+      .body_json(json!({ "isActive": false, "events": ["new_dependent"] }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiWebhook>()
+      .await;
+    assert!(!updated.is_active);
+    assert_eq!(updated.events, vec![WebhookEvent::NewDependent]);
+    assert_eq!(updated.url, "https://example.com/hooks/jsr");
+
+    // Members that are not admins can not see the webhooks.
+    t.db()
+      .add_user_to_scope(NewScopeMember {
+        scope: &t.scope.scope,
+        user_id: t.user2.user.id,
+        is_admin: false,
+      })
+      .await
+      .unwrap();
+    let token2 = t.user2.token.clone();
+    t.http()
+      .get("/api/scopes/scope/webhooks")
+      .token(Some(&token2))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::FORBIDDEN, "actorNotScopeAdmin")
+      .await;
+
+    let webhooks = t
+      .http()
+      .get("/api/scopes/scope/webhooks")
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<Vec<ApiWebhook>>()
+      .await;
+    assert_eq!(webhooks.len(), 1);
+
+    t.http()
+      .delete(format!("/api/scopes/scope/webhooks/{}", webhook.id))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok_no_content()
+      .await;
+    t.http()
+      .get(format!("/api/scopes/scope/webhooks/{}", webhook.id))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "webhookNotFound")
+      .await;
+  }
+
+  #[tokio::test]
+  async fn webhook_deliveries() {
+    let mut t = TestSetup::new().await;
+    let token = t.user1.token.clone();
+
+    let webhook = t
+      .http()
+      .post("/api/scopes/scope/webhooks")
+      .body_json(json!({
+        "url": "https://example.com/hooks/jsr",
+        "secret": "0123456789abcdef",
+        "events": ["scope_member_removed"],
+      }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiWebhook>()
+      .await;
+
+    // Only subscribed events are queued.
+    let scope = t.scope.scope.clone();
+    webhooks::dispatch(
+      &t.db(),
+      &scope,
+      WebhookEvent::VersionPublished,
+      json!({ "package": "foo", "version": "1.0.0" }),
+    )
+    .await;
+    webhooks::dispatch(
+      &t.db(),
+      &scope,
+      WebhookEvent::ScopeMemberRemoved,
+      json!({ "userId": t.user2.user.id }),
+    )
+    .await;
+
+    let deliveries = t
+      .http()
+      .get(format!(
+        "/api/scopes/scope/webhooks/{}/deliveries",
+        webhook.id
+      ))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiList<ApiWebhookDelivery>>()
+      .await;
+    assert_eq!(deliveries.total, 1);
+    let delivery = &deliveries.items[0];
+    assert_eq!(delivery.event, WebhookEvent::ScopeMemberRemoved);
+    assert_eq!(delivery.status, WebhookDeliveryStatus::Pending);
+    assert_eq!(delivery.attempts, 0);
+    assert_eq!(delivery.payload["event"], "scope_member_removed");
+    assert_eq!(delivery.payload["scope"], "scope");
+    assert_eq!(delivery.payload["data"]["userId"], json!(t.user2.user.id));
+  }
+}
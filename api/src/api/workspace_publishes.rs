@@ -0,0 +1,372 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+//! Workspace publishes. A client that publishes several packages of a
+//! workspace at once, some of which depend on the new versions of others,
+//! uploads the tarball of each package as a publish upload, and then creates a
+//! workspace publish from the uploads instead of committing them one by one.
+//! The packages are then analyzed together, and either all of them are
+//! published, or none is.
+
+use std::collections::HashSet;
+
+use hyper::Body;
+use hyper::Request;
+use routerify::Router;
+use routerify::ext::RequestExt;
+use sha2::Digest;
+use tracing::Span;
+use tracing::field;
+use tracing::instrument;
+
+use crate::db::CreateWorkspacePublishResult;
+use crate::db::Database;
+use crate::db::NewPublishingTask;
+use crate::iam::ReqIamExt;
+use crate::s3::Buckets;
+use crate::s3::ContentEncoding;
+use crate::s3::S3UploadOptions;
+use crate::s3::UploadTaskBody;
+use crate::tarball::bucket_tarball_path;
+use crate::util;
+use crate::util::ApiResult;
+use crate::util::RequestIdExt;
+use crate::util::decode_json;
+
+use super::ApiCreateWorkspacePublishRequest;
+use super::ApiError;
+use super::ApiWorkspacePublish;
+use super::package::check_package_publishable;
+use super::package::check_publish_tarball_hash;
+use super::package::dispatch_publishing_task;
+use super::publish_uploads::delete_committed_upload;
+use super::publish_uploads::download_upload_tarball;
+
+/// The most packages that can be published together.
+const MAX_WORKSPACE_PUBLISH_PACKAGES: usize = 32;
+
+pub fn workspace_publishes_router() -> Router<Body, ApiError> {
+  Router::builder()
+    .post("/", util::auth(util::json(create_handler)))
+    // Never cache: clients poll this for live status.
+    .get(
+      "/:workspace_publish_id",
+      util::no_store(util::json(get_handler)),
+    )
+    .build()
+    .unwrap()
+}
+
+/// Commits the given publish uploads together, creating a publishing task for
+/// each of them that is processed as part of the workspace publish.
+#[instrument(name = "POST /api/workspace_publishes", skip(req), err)]
+pub async fn create_handler(
+  mut req: Request<Body>,
+) -> ApiResult<ApiWorkspacePublish> {
+  let ApiCreateWorkspacePublishRequest { uploads } =
+    decode_json(&mut req).await?;
+  if uploads.is_empty() || uploads.len() > MAX_WORKSPACE_PUBLISH_PACKAGES {
+    return Err(ApiError::MalformedRequest {
+      msg: format!(
+        "a workspace publish must contain between 1 and {MAX_WORKSPACE_PUBLISH_PACKAGES} uploads"
+      )
+      .into(),
+    });
+  }
+
+  let db = req.data::<Database>().unwrap();
+  let buckets = req.data::<Buckets>().unwrap();
+  let iam = req.iam();
+
+  let mut user_id = None;
+  let mut packages = HashSet::new();
+  let mut members = Vec::with_capacity(uploads.len());
+  for id in uploads {
+    let upload = db
+      .get_publish_upload(id)
+      .await?
+      .ok_or(ApiError::PublishUploadNotFound)?;
+    let (access_restriction, upload_user_id) = iam
+      .check_publish_access(
+        &upload.package_scope,
+        &upload.package_name,
+        &upload.package_version,
+      )
+      .await?;
+    // Uploads of other users are not found, like in the uploads API.
+    if upload.user_id != upload_user_id {
+      return Err(ApiError::PublishUploadNotFound);
+    }
+    user_id = user_id.or(upload_user_id);
+
+    if !packages
+      .insert((upload.package_scope.clone(), upload.package_name.clone()))
+    {
+      return Err(ApiError::MalformedRequest {
+        msg: format!(
+          "@{}/{} is published more than once",
+          upload.package_scope, upload.package_name
+        )
+        .into(),
+      });
+    }
+    if upload.chunks.is_empty() {
+      return Err(ApiError::PublishUploadEmpty);
+    }
+
+    let quota = check_package_publishable(
+      db,
+      &upload.package_scope,
+      &upload.package_name,
+    )
+    .await?;
+    quota.check(upload.size as u64)?;
+
+    let tarball = download_upload_tarball(buckets, &upload).await?;
+    let hash = format!("sha256-{:02x}", sha2::Sha256::digest(&tarball));
+    check_publish_tarball_hash(&access_restriction, &hash)?;
+
+    members.push((upload, tarball, hash));
+  }
+
+  let new_tasks = members
+    .iter()
+    .map(|(upload, _, _)| NewPublishingTask {
+      user_id: upload.user_id,
+      package_scope: &upload.package_scope,
+      package_name: &upload.package_name,
+      package_version: &upload.package_version,
+      config_file: &upload.config_file,
+    })
+    .collect::<Vec<_>>();
+  let (workspace_publish, tasks) = match db
+    .create_workspace_publish(user_id, &new_tasks)
+    .await?
+  {
+    CreateWorkspacePublishResult::Created(workspace_publish, tasks) => {
+      (workspace_publish, tasks)
+    }
+    CreateWorkspacePublishResult::Exists(task) => {
+      return Err(ApiError::DuplicateVersionPublish {
+        task: Box::new(task.into()),
+      });
+    }
+    CreateWorkspacePublishResult::WeeklyPublishAttemptsLimitExceeded(limit) => {
+      return Err(ApiError::WeeklyPublishAttemptsLimitExceeded { limit });
+    }
+  };
+
+  for ((publishing_task, _), (_, tarball, hash)) in
+    tasks.iter().zip(members.iter_mut())
+  {
+    buckets
+      .publishing_bucket
+      .upload(
+        bucket_tarball_path(publishing_task.id).into(),
+        UploadTaskBody::Bytes(std::mem::take(tarball).into()),
+        S3UploadOptions {
+          content_type: Some("application/x-tar".into()),
+          cache_control: None,
+          content_encoding: ContentEncoding::Gzip,
+        },
+      )
+      .await?;
+    db.set_publishing_task_tarball_hash(publishing_task.id, hash)
+      .await?;
+  }
+
+  // The first task processes the whole workspace publish.
+  dispatch_publishing_task(&req, tasks[0].0.id).await?;
+
+  for (upload, _, _) in &members {
+    delete_committed_upload(db, buckets, upload).await?;
+  }
+
+  Ok((workspace_publish, tasks).into())
+}
+
+#[instrument(
+  name = "GET /api/workspace_publishes/:workspace_publish_id",
+  skip(req),
+  err,
+  fields(workspace_publish_id)
+)]
+pub async fn get_handler(req: Request<Body>) -> ApiResult<ApiWorkspacePublish> {
+  let workspace_publish_id = req.param_uuid("workspace_publish_id")?;
+  Span::current().record(
+    "workspace_publish_id",
+    field::display(&workspace_publish_id),
+  );
+
+  let db = req.data::<Database>().unwrap();
+  let workspace_publish = db
+    .get_workspace_publish(workspace_publish_id)
+    .await?
+    .ok_or(ApiError::WorkspacePublishNotFound)?;
+  let tasks = db
+    .list_workspace_publish_tasks(workspace_publish.id)
+    .await?;
+
+  Ok((workspace_publish, tasks).into())
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::Body;
+  use hyper::StatusCode;
+  use serde_json::json;
+  use uuid::Uuid;
+
+  use crate::api::ApiPublishUpload;
+  use crate::api::ApiPublishingTaskStatus;
+  use crate::api::ApiWorkspacePublish;
+  use crate::db::NewScopeMember;
+  use crate::ids::PackageName;
+  use crate::publish::tests::create_mock_tarball;
+  use crate::util::test::ApiResultExt;
+  use crate::util::test::TestSetup;
+
+  async fn upload(t: &mut TestSetup, package: &str, tarball: &str) -> Uuid {
+    let name = PackageName::new(package.to_owned()).unwrap();
+    let scope = t.scope.scope.clone();
+    t.db().create_package(&scope, &name).await.unwrap();
+
+    let base =
+      format!("/api/scopes/scope/packages/{package}/versions/1.2.3/uploads");
+    let upload = t
+      .http()
+      .post(format!("{base}?config=/jsr.json"))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPublishUpload>()
+      .await;
+    t.http()
+      .post(format!("{base}/{}/chunks?offset=0", upload.id))
+      .body(Body::from(create_mock_tarball(tarball).to_vec()))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPublishUpload>()
+      .await;
+    upload.id
+  }
+
+  async fn publish(t: &mut TestSetup, uploads: &[Uuid]) -> ApiWorkspacePublish {
+    let mut workspace_publish = t
+      .http()
+      .post("/api/workspace_publishes")
+      .body_json(json!({ "uploads": uploads }))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiWorkspacePublish>()
+      .await;
+    assert_eq!(workspace_publish.tasks.len(), uploads.len());
+
+    for _ in 0..50 {
+      if workspace_publish.tasks.iter().all(|task| {
+        matches!(
+          task.status,
+          ApiPublishingTaskStatus::Success | ApiPublishingTaskStatus::Failure
+        )
+      }) {
+        break;
+      }
+      tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+      workspace_publish = t
+        .http()
+        .get(format!("/api/workspace_publishes/{}", workspace_publish.id))
+        .call()
+        .await
+        .unwrap()
+        .expect_ok::<ApiWorkspacePublish>()
+        .await;
+    }
+    workspace_publish
+  }
+
+  #[tokio::test]
+  async fn workspace_publish() {
+    let mut t = TestSetup::new().await;
+
+    t.http()
+      .post("/api/workspace_publishes")
+      .body_json(json!({ "uploads": [] }))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::BAD_REQUEST, "malformedRequest")
+      .await;
+
+    // Other members of the scope can not publish the uploads of a user.
+    let baz = upload(&mut t, "baz", "ok").await;
+    t.db()
+      .add_user_to_scope(NewScopeMember {
+        scope: &t.scope.scope,
+        user_id: t.user2.user.id,
+        is_admin: false,
+      })
+      .await
+      .unwrap();
+    let token = t.user2.token.clone();
+    t.http()
+      .post("/api/workspace_publishes")
+      .body_json(json!({ "uploads": [baz] }))
+      .token(Some(&token))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "publishUploadNotFound")
+      .await;
+
+    // @scope/bar depends on the version of @scope/foo that is published with
+    // it, which fails, so neither is published.
+    let bar = upload(&mut t, "bar", "depends_on_ok").await;
+    let foo = upload(&mut t, "foo", "no_exports").await;
+    let workspace_publish = publish(&mut t, &[bar, foo]).await;
+    for task in &workspace_publish.tasks {
+      assert_eq!(task.status, ApiPublishingTaskStatus::Failure, "{task:#?}");
+      let code = &task.error.as_ref().unwrap().code;
+      match task.package_name.as_str() {
+        "foo" => assert_eq!(code, "configFileExportsInvalid"),
+        _ => assert_eq!(code, "workspacePublishFailed"),
+      }
+    }
+    t.http()
+      .get("/api/scopes/scope/packages/bar/versions/1.2.3")
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "packageVersionNotFound")
+      .await;
+
+    // Published together, the dependency resolves to the new version.
+    let bar = upload(&mut t, "bar", "depends_on_ok").await;
+    let foo = upload(&mut t, "foo", "ok").await;
+    let workspace_publish = publish(&mut t, &[bar, foo]).await;
+    for task in &workspace_publish.tasks {
+      assert_eq!(task.status, ApiPublishingTaskStatus::Success, "{task:#?}");
+    }
+    let dependencies = t
+      .db()
+      .list_package_version_dependencies(
+        &t.scope.scope,
+        &PackageName::new("bar".to_owned()).unwrap(),
+        &"1.2.3".try_into().unwrap(),
+      )
+      .await
+      .unwrap();
+    assert!(
+      dependencies
+        .iter()
+        .any(|dependency| dependency.dependency_name == "@scope/foo")
+    );
+
+    t.http()
+      .get(format!("/api/workspace_publishes/{}", Uuid::new_v4()))
+      .call()
+      .await
+      .unwrap()
+      .expect_err_code(StatusCode::NOT_FOUND, "workspacePublishNotFound")
+      .await;
+  }
+}
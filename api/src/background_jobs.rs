@@ -0,0 +1,290 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+//! A queue of small units of background work, stored in the database.
+//!
+//! Jobs are added with [Database::enqueue_background_job]. [run_worker], run
+//! periodically by the `run_jobs` task, claims the jobs that are due, highest
+//! priority first, with `FOR UPDATE SKIP LOCKED`, so that concurrent workers
+//! never claim the same job. A claimed job is leased to its worker: if the
+//! worker dies, the job is claimed again once the lease expires. Failed jobs
+//! are retried with exponential backoff until they run out of attempts, at
+//! which point they are dead and wait for an admin to retry them.
+//!
+//! Admin jobs that walk the whole registry take much longer than a lease, so
+//! they are not queued. They run on the instance they were started on and
+//! hold a lease of their own instead, which [run_with_lease] renews while they
+//! run. A job whose lease expired, because its instance went away, is failed
+//! when the next job of its kind is started.
+
+use std::future::Future;
+use std::time::Duration;
+use std::time::Instant;
+
+use chrono::Utc;
+use futures::StreamExt;
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::error;
+use tracing::instrument;
+use url::Url;
+
+use crate::cache_purge::CachePurge;
+use crate::data_export::DataExportJobPayload;
+use crate::db::BackgroundJob;
+use crate::db::BackgroundJobKind;
+use crate::db::Database;
+use crate::db::NewBackgroundJob;
+use crate::emails::EmailSender;
+use crate::emails::PublishFailureEmailJobPayload;
+use crate::ids::PackageName;
+use crate::ids::ScopeName;
+use crate::ids::Version;
+use crate::npm::NpmSigner;
+use crate::s3::Buckets;
+
+/// How often a job is attempted, unless it is queued with a different number.
+pub const DEFAULT_MAX_ATTEMPTS: i32 = 5;
+pub const MAX_MAX_ATTEMPTS: i32 = 20;
+
+/// How many jobs a worker runs at the same time.
+const WORKER_CONCURRENCY: usize = 8;
+/// How long a claimed job is leased to its worker. Jobs must finish well
+/// within this.
+const JOB_LEASE_MINUTES: i64 = 15;
+
+/// How long a long running admin job holds its lease without renewing it.
+pub const ADMIN_JOB_LEASE_SECS: i64 = 5 * 60;
+/// How often a long running admin job renews its lease.
+const ADMIN_JOB_LEASE_RENEW_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Runs a long running admin job, calling `renew` to renew its lease until it
+/// finished. Returns `None` if the job lost its lease, in which case it was
+/// failed to let another job start, and is cancelled.
+pub async fn run_with_lease<T, R, RenewFut>(
+  job: impl Future<Output = T>,
+  renew: R,
+) -> Option<T>
+where
+  R: Fn(chrono::Duration) -> RenewFut,
+  RenewFut: Future<Output = Result<bool, sqlx::Error>>,
+{
+  tokio::pin!(job);
+  let lease = chrono::Duration::seconds(ADMIN_JOB_LEASE_SECS);
+  let mut interval = tokio::time::interval(ADMIN_JOB_LEASE_RENEW_INTERVAL);
+  // The first tick completes immediately.
+  interval.tick().await;
+  loop {
+    tokio::select! {
+      res = &mut job => return Some(res),
+      _ = interval.tick() => match renew(lease).await {
+        Ok(true) => {}
+        Ok(false) => return None,
+        Err(err) => error!("failed to renew the lease of an admin job: {err}"),
+      },
+    }
+  }
+}
+
+/// The payload of jobs that operate on a single package version.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionJobPayload {
+  pub scope: ScopeName,
+  pub package: PackageName,
+  pub version: Version,
+}
+
+impl VersionJobPayload {
+  /// A job of the given kind for a version, queued by the system rather than
+  /// an admin.
+  pub fn job(
+    kind: BackgroundJobKind,
+    scope: &ScopeName,
+    package: &PackageName,
+    version: &Version,
+  ) -> NewBackgroundJob<'static> {
+    let payload = VersionJobPayload {
+      scope: scope.clone(),
+      package: package.clone(),
+      version: version.clone(),
+    };
+    NewBackgroundJob {
+      kind,
+      payload: serde_json::to_value(payload).unwrap(),
+      priority: 0,
+      max_attempts: DEFAULT_MAX_ATTEMPTS,
+      created_by: None,
+    }
+  }
+}
+
+/// What jobs need to run.
+pub struct JobContext {
+  pub db: Database,
+  pub buckets: Buckets,
+  pub registry_url: Url,
+  pub npm_url: Url,
+  pub npm_signer: NpmSigner,
+  pub cache_purge: CachePurge,
+  pub email_sender: Option<EmailSender>,
+}
+
+/// Runs jobs that are due until there are none left, or until `budget` is
+/// used up. Returns the number of jobs that were run.
+#[instrument(name = "run_background_jobs", skip(ctx), err)]
+pub async fn run_worker(
+  ctx: &JobContext,
+  budget: Duration,
+) -> Result<usize, sqlx::Error> {
+  let deadline = Instant::now() + budget;
+  let mut ran = 0;
+  while Instant::now() < deadline {
+    let jobs = ctx
+      .db
+      .claim_background_jobs(
+        WORKER_CONCURRENCY as i64,
+        chrono::Duration::minutes(JOB_LEASE_MINUTES),
+      )
+      .await?;
+    if jobs.is_empty() {
+      break;
+    }
+    ran += jobs.len();
+
+    let mut results = futures::stream::iter(jobs)
+      .map(|job| run_job(ctx, job))
+      .buffer_unordered(WORKER_CONCURRENCY);
+    while let Some(res) = results.next().await {
+      res?;
+    }
+  }
+  Ok(ran)
+}
+
+async fn run_job(
+  ctx: &JobContext,
+  job: BackgroundJob,
+) -> Result<(), sqlx::Error> {
+  match execute(ctx, &job).await {
+    Ok(()) => ctx.db.complete_background_job(job.id).await,
+    Err(err) => {
+      error!(
+        "background job {} ({:?}) failed on attempt {} of {}: {err:#}",
+        job.id, job.kind, job.attempts, job.max_attempts
+      );
+      ctx
+        .db
+        .fail_background_job(
+          job.id,
+          &format!("{err:#}"),
+          Utc::now() + retry_delay(job.attempts),
+        )
+        .await
+    }
+  }
+}
+
+async fn execute(
+  ctx: &JobContext,
+  job: &BackgroundJob,
+) -> Result<(), anyhow::Error> {
+  match job.kind {
+    BackgroundJobKind::NpmTarballBuild => {
+      let VersionJobPayload {
+        scope,
+        package,
+        version,
+      } = serde_json::from_value(job.payload.clone())?;
+      crate::npm::build_npm_tarball(
+        &ctx.db,
+        &ctx.buckets,
+        &ctx.registry_url,
+        &ctx.npm_url,
+        &ctx.npm_signer,
+        &scope,
+        &package,
+        &version,
+        false,
+      )
+      .await?;
+      crate::npm::upload_npm_version_manifest(
+        &ctx.db,
+        &ctx.buckets,
+        &ctx.npm_url,
+        &ctx.npm_signer,
+        &ctx.cache_purge,
+        &scope,
+        &package,
+      )
+      .await
+    }
+    BackgroundJobKind::ScoreRecompute => {
+      let VersionJobPayload {
+        scope,
+        package,
+        version,
+      } = serde_json::from_value(job.payload.clone())?;
+      let package_version = ctx
+        .db
+        .get_package_version(&scope, &package, &version)
+        .await?
+        .ok_or_else(|| {
+          anyhow::anyhow!("@{scope}/{package}@{version} does not exist")
+        })?;
+      if !crate::score::recompute_version_score(
+        &ctx.db,
+        &ctx.buckets,
+        package_version,
+      )
+      .await?
+      {
+        anyhow::bail!("the doc nodes or readme could not be loaded");
+      }
+      Ok(())
+    }
+    BackgroundJobKind::DataExport => {
+      let DataExportJobPayload { id } =
+        serde_json::from_value(job.payload.clone())?;
+      let res =
+        crate::data_export::build_data_export(&ctx.db, &ctx.buckets, id).await;
+      // The user is waiting for the export, so tell them once it won't be
+      // retried anymore.
+      if let Err(err) = &res
+        && job.attempts >= job.max_attempts
+      {
+        ctx.db.fail_data_export(id, &format!("{err:#}")).await?;
+      }
+      res
+    }
+    BackgroundJobKind::PublishFailureEmail => {
+      let PublishFailureEmailJobPayload { task_id } =
+        serde_json::from_value(job.payload.clone())?;
+      crate::emails::send_publish_failure_email(
+        &ctx.db,
+        ctx.email_sender.as_ref(),
+        &ctx.registry_url,
+        task_id,
+      )
+      .await
+    }
+  }
+}
+
+/// How long to wait before retrying a job that failed `attempts` times: 30
+/// seconds, doubling with every attempt, up to an hour.
+fn retry_delay(attempts: i32) -> chrono::Duration {
+  let exponent = (attempts - 1).clamp(0, 16) as u32;
+  let seconds = 30i64 * 2i64.pow(exponent);
+  chrono::Duration::seconds(seconds.min(60 * 60))
+}
+
+#[cfg(test)]
+mod tests {
+  #[test]
+  fn retry_delay() {
+    assert_eq!(super::retry_delay(1), chrono::Duration::seconds(30));
+    assert_eq!(super::retry_delay(2), chrono::Duration::seconds(60));
+    assert_eq!(super::retry_delay(4), chrono::Duration::seconds(240));
+    assert_eq!(super::retry_delay(30), chrono::Duration::hours(1));
+  }
+}
@@ -0,0 +1,154 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+//! Purging responses that the CDN caches, when what they were generated from
+//! changes.
+//!
+//! Mutations like a publish, a yank, or an update of the metadata or readme
+//! of a package call [CachePurge::purge] with the URLs of the responses they
+//! affect, instead of waiting for them to expire. Purges are queued in the
+//! background so that they don't slow down the request, and failed purges are
+//! retried with backoff. A purge that still fails is logged: the responses
+//! then expire on their own.
+
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures::Future;
+use futures::FutureExt;
+use tracing::Instrument;
+use tracing::Span;
+use tracing::error;
+
+use crate::external::cloudflare::CloudflarePurgeClient;
+use crate::external::fastly::FastlyPurgeClient;
+use crate::s3_policy::retry_delay;
+use crate::task_queue::DynamicBackgroundTaskQueue;
+use crate::task_queue::RestartableTask;
+use crate::task_queue::RestartableTaskResult;
+
+/// How many URLs are purged together. Cloudflare accepts at most 30 URLs in
+/// a single purge request.
+const PURGE_BATCH_SIZE: usize = 30;
+/// How often a purge is attempted before it is given up on.
+const MAX_PURGE_ATTEMPTS: u32 = 5;
+
+/// The CDN that the registry is served through.
+pub enum CachePurgeClient {
+  Cloudflare(CloudflarePurgeClient),
+  Fastly(FastlyPurgeClient),
+}
+
+impl CachePurgeClient {
+  async fn purge_urls(&self, urls: &[String]) -> Result<(), anyhow::Error> {
+    match self {
+      CachePurgeClient::Cloudflare(client) => client.purge_urls(urls).await,
+      CachePurgeClient::Fastly(client) => client.purge_urls(urls).await,
+    }
+  }
+}
+
+/// Stored in the routerify data map alongside other shared services. Purging
+/// is a no-op if no [CachePurgeClient] is configured (e.g. local dev).
+#[derive(Clone)]
+pub struct CachePurge(Option<CachePurgeQueue>);
+
+#[derive(Clone)]
+struct CachePurgeQueue {
+  client: Arc<CachePurgeClient>,
+  queue: DynamicBackgroundTaskQueue<PurgeTask>,
+}
+
+impl CachePurge {
+  pub fn new(client: Option<CachePurgeClient>) -> Self {
+    Self(client.map(|client| CachePurgeQueue {
+      client: Arc::new(client),
+      queue: DynamicBackgroundTaskQueue::default(),
+    }))
+  }
+
+  /// Queues a purge of `urls`, and returns right away.
+  pub fn purge(&self, urls: Vec<String>) {
+    let Some(CachePurgeQueue { client, queue }) = &self.0 else {
+      return;
+    };
+    for urls in purge_batches(urls) {
+      let queue = queue.clone();
+      let task = PurgeTask {
+        client: client.clone(),
+        urls,
+        attempts: 0,
+      };
+      let fut = async move {
+        let count = task.urls.len();
+        if let Err(err) = queue.run(task).await {
+          error!("failed to purge {count} URLs from the CDN cache: {err}");
+        }
+      };
+      tokio::spawn(fut.instrument(Span::current()));
+    }
+  }
+}
+
+/// Deduplicates `urls` and splits them into batches that are purged together.
+fn purge_batches(mut urls: Vec<String>) -> Vec<Vec<String>> {
+  urls.sort();
+  urls.dedup();
+  urls
+    .chunks(PURGE_BATCH_SIZE)
+    .map(|batch| batch.to_vec())
+    .collect()
+}
+
+struct PurgeTask {
+  client: Arc<CachePurgeClient>,
+  urls: Vec<String>,
+  attempts: u32,
+}
+
+impl RestartableTask for PurgeTask {
+  type Ok = ();
+  type Err = anyhow::Error;
+  type Fut =
+    Pin<Box<dyn Future<Output = RestartableTaskResult<Self>> + Send + 'static>>;
+
+  fn run(mut self) -> Self::Fut {
+    async move {
+      match self.client.purge_urls(&self.urls).await {
+        Ok(()) => RestartableTaskResult::Ok(()),
+        Err(_) if self.attempts + 1 < MAX_PURGE_ATTEMPTS => {
+          tokio::time::sleep(retry_delay(self.attempts)).await;
+          self.attempts += 1;
+          RestartableTaskResult::Backoff(self)
+        }
+        Err(err) => RestartableTaskResult::Error(err),
+      }
+    }
+    .boxed()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::PURGE_BATCH_SIZE;
+  use super::purge_batches;
+
+  #[test]
+  fn batches() {
+    assert!(purge_batches(vec![]).is_empty());
+
+    let batches = purge_batches(vec![
+      "https://jsr.io/b".to_string(),
+      "https://jsr.io/a".to_string(),
+      "https://jsr.io/b".to_string(),
+    ]);
+    assert_eq!(batches, vec![vec!["https://jsr.io/a", "https://jsr.io/b"]]);
+
+    let urls = (0..PURGE_BATCH_SIZE + 1)
+      .map(|i| format!("https://jsr.io/{i}"))
+      .collect();
+    let batches = purge_batches(urls);
+    assert_eq!(batches.len(), 2);
+    assert_eq!(batches[0].len(), PURGE_BATCH_SIZE);
+    assert_eq!(batches[1].len(), 1);
+  }
+}
@@ -0,0 +1,167 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+//! Exports of all data that the registry holds about a user, which users can
+//! request to exercise their right of access.
+//!
+//! An export is requested through the API, which queues a background job that
+//! gathers the data into a `.tar.gz` archive of JSON files and stores it in
+//! the publishing bucket. The user then downloads it through a short lived
+//! signed URL. Requesting an export and handing out a download URL are both
+//! recorded in the audit log. Archives are deleted by the `clean_data_exports`
+//! task once they are [DATA_EXPORT_RETENTION_DAYS] days old.
+
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::api::ApiAuditLog;
+use crate::api::ApiFullUser;
+use crate::api::ApiPackageVersion;
+use crate::api::ApiScopeMember;
+use crate::api::ApiToken;
+use crate::background_jobs::DEFAULT_MAX_ATTEMPTS;
+use crate::db::BackgroundJobKind;
+use crate::db::Database;
+use crate::db::NewBackgroundJob;
+use crate::db::UserPublic;
+use crate::s3::Buckets;
+use crate::s3::ContentEncoding;
+use crate::s3::S3UploadOptions;
+use crate::s3::UploadTaskBody;
+
+/// How long a completed archive can be downloaded for.
+pub const DATA_EXPORT_RETENTION_DAYS: i64 = 7;
+
+/// How long a signed URL to download an archive is valid for.
+pub const DATA_EXPORT_URL_EXPIRY: std::time::Duration =
+  std::time::Duration::from_secs(15 * 60);
+
+/// The payload of [BackgroundJobKind::DataExport] jobs.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataExportJobPayload {
+  pub id: Uuid,
+}
+
+impl DataExportJobPayload {
+  pub fn job(id: Uuid) -> NewBackgroundJob<'static> {
+    NewBackgroundJob {
+      kind: BackgroundJobKind::DataExport,
+      payload: serde_json::to_value(DataExportJobPayload { id }).unwrap(),
+      priority: 0,
+      max_attempts: DEFAULT_MAX_ATTEMPTS,
+      created_by: None,
+    }
+  }
+}
+
+/// The path of the archive of a data export in the publishing bucket.
+pub fn data_export_path(id: Uuid) -> String {
+  format!("data_exports/{id}.tar.gz")
+}
+
+/// Gathers the data of the user that requested the export `id` into an
+/// archive, uploads it, and marks the export as completed.
+#[instrument(name = "build_data_export", skip(db, buckets), err)]
+pub async fn build_data_export(
+  db: &Database,
+  buckets: &Buckets,
+  id: Uuid,
+) -> Result<(), anyhow::Error> {
+  let data_export = db
+    .get_data_export(id)
+    .await?
+    .ok_or_else(|| anyhow::anyhow!("data export {id} does not exist"))?;
+  let user = db.get_user(data_export.user_id).await?.ok_or_else(|| {
+    anyhow::anyhow!("user {} does not exist", data_export.user_id)
+  })?;
+  let user_public = UserPublic::from(user.clone());
+
+  let memberships = db
+    .list_scope_memberships_by_user(user.id)
+    .await?
+    .into_iter()
+    .map(|member| ApiScopeMember::from((member, user_public.clone())))
+    .collect::<Vec<_>>();
+  let tokens = db
+    .list_tokens(user.id)
+    .await?
+    .into_iter()
+    .map(ApiToken::from)
+    .collect::<Vec<_>>();
+  let audit_logs = db
+    .list_audit_logs_by_actor(user.id)
+    .await?
+    .into_iter()
+    .map(|audit_log| ApiAuditLog::from((audit_log, user_public.clone())))
+    .collect::<Vec<_>>();
+  let package_versions = db
+    .list_package_versions_by_user(user.id)
+    .await?
+    .into_iter()
+    .map(ApiPackageVersion::from)
+    .collect::<Vec<_>>();
+
+  let files = [
+    (
+      "profile.json",
+      serde_json::to_vec_pretty(&ApiFullUser::from(user))?,
+    ),
+    (
+      "scope_memberships.json",
+      serde_json::to_vec_pretty(&memberships)?,
+    ),
+    ("tokens.json", serde_json::to_vec_pretty(&tokens)?),
+    ("audit_logs.json", serde_json::to_vec_pretty(&audit_logs)?),
+    (
+      "package_versions.json",
+      serde_json::to_vec_pretty(&package_versions)?,
+    ),
+  ];
+  let archive = create_archive(id, &files)?;
+  let size = archive.len() as i64;
+
+  buckets
+    .publishing_bucket
+    .upload(
+      data_export_path(id).into(),
+      UploadTaskBody::Bytes(archive.into()),
+      S3UploadOptions {
+        content_type: Some("application/gzip".into()),
+        cache_control: None,
+        content_encoding: ContentEncoding::Identity,
+      },
+    )
+    .await?;
+
+  db.complete_data_export(id, size).await?;
+
+  Ok(())
+}
+
+/// Creates a `.tar.gz` archive with the given files in a directory named
+/// after the export.
+fn create_archive(
+  id: Uuid,
+  files: &[(&str, Vec<u8>)],
+) -> Result<Vec<u8>, std::io::Error> {
+  let mtime = chrono::Utc::now().timestamp() as u64;
+  let mut encoder =
+    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+  {
+    let mut tar = tar::Builder::new(&mut encoder);
+    for (name, content) in files {
+      let mut header = tar::Header::new_ustar();
+      header.set_path(format!("jsr-data-export-{id}/{name}"))?;
+      header.set_entry_type(tar::EntryType::Regular);
+      header.set_size(content.len() as u64);
+      header.set_mode(0o644);
+      header.set_mtime(mtime);
+      header.set_cksum();
+      tar.append(&header, content.as_slice())?;
+    }
+    tar.finish()?;
+  }
+  encoder.finish()
+}
@@ -17,10 +17,23 @@ use sqlx::migrate;
 use sqlx::postgres::PgConnectOptions;
 use sqlx::postgres::PgPoolOptions;
 use sqlx::postgres::PgSslMode;
+use std::collections::HashSet;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::AtomicI64;
+use std::sync::atomic::Ordering;
 use tracing::instrument;
 use uuid::Uuid;
 
+use crate::events::Event;
+use crate::events::EventQueue;
+use crate::search::SearchIndexQueue;
+
+use super::metadata_cache::MetadataCache;
+use super::metadata_cache::MetadataCacheKind;
+use super::metadata_cache::MetadataCacheStats;
+use super::metadata_cache::package_generation_key;
+use super::metadata_cache::scope_generation_key;
 use super::models::*;
 
 macro_rules! sort_by {
@@ -54,18 +67,89 @@ macro_rules! sort_by {
 
 #[derive(Debug, Clone)]
 pub struct Database {
+  /// The pool of the primary, which all writes and most reads go to.
+  pool: sqlx::PgPool,
+  replica: Option<ReadReplica>,
+  cache: Option<MetadataCache>,
+  search_index: Option<SearchIndexQueue>,
+  events: Option<EventQueue>,
+}
+
+/// A read replica that read-only queries which tolerate replication lag, like
+/// search and package metadata, are routed to.
+///
+/// Right after a write, the replica may not have caught up yet. So that users
+/// read their own writes, reads stick to the primary for a while after this
+/// instance handles a request that writes, see [Database::stick_to_primary].
+#[derive(Debug, Clone)]
+struct ReadReplica {
   pool: sqlx::PgPool,
+  stickiness: chrono::Duration,
+  /// Until when reads go to the primary, in milliseconds since the epoch.
+  sticky_until: Arc<AtomicI64>,
+}
+
+/// The state of a connection pool, for monitoring.
+#[derive(Debug, Clone, Copy)]
+pub struct PoolHealth {
+  /// The number of open connections, idle or in use.
+  pub size: u32,
+  pub idle: usize,
+  pub max_connections: u32,
+  pub closed: bool,
+}
+
+impl PoolHealth {
+  fn of(pool: &sqlx::PgPool) -> Self {
+    Self {
+      size: pool.size(),
+      idle: pool.num_idle(),
+      max_connections: pool.options().get_max_connections(),
+      closed: pool.is_closed(),
+    }
+  }
 }
 
 /// Client-certificate TLS material for connecting to the database. Supplied
 /// when the database requires a client certificate (`ssl_mode =
 /// TRUSTED_CLIENT_CERTIFICATE_REQUIRED`); the same cert is also presented by
 /// the Hyperdrive-backed `api` Worker so both reach Cloud SQL over mTLS.
+#[derive(Clone)]
 pub struct DbTls {
   pub client_cert: String,
   pub client_key: String,
 }
 
+async fn connect_pool(
+  database_url: &str,
+  pool_size: u32,
+  acquire_timeout: std::time::Duration,
+  tls: Option<DbTls>,
+) -> anyhow::Result<sqlx::PgPool> {
+  let mut opts = PgConnectOptions::from_str(database_url)?;
+  if let Some(tls) = tls {
+    // Present our client cert (the DB requires one) and encrypt, but don't
+    // verify the server cert. We use `Require`, not `VerifyCa`: Cloud Run
+    // connects to Cloud SQL by private IP, yet the server cert is only valid
+    // for the instance's `*.sql.goog` DNS name. `VerifyCa` is meant to skip
+    // that hostname check, but sqlx 0.8's `NoHostnameTlsVerifier` only
+    // swallows rustls's legacy `NotValidForName` error, not 0.23's
+    // `NotValidForNameContext`, so verification fails and the connection is
+    // refused. The client certificate (mTLS) is the access boundary and the
+    // link stays inside the VPC.
+    opts = opts
+      .ssl_mode(PgSslMode::Require)
+      .ssl_client_cert_from_pem(tls.client_cert.into_bytes())
+      .ssl_client_key_from_pem(tls.client_key.into_bytes());
+  }
+  let pool = PgPoolOptions::new()
+    .max_connections(pool_size)
+    .acquire_timeout(acquire_timeout)
+    .connect_with(opts)
+    .await?;
+  Ok(pool)
+}
+
 impl Database {
   pub async fn connect(
     database_url: &str,
@@ -73,27 +157,8 @@ impl Database {
     acquire_timeout: std::time::Duration,
     tls: Option<DbTls>,
   ) -> anyhow::Result<Self> {
-    let mut opts = PgConnectOptions::from_str(database_url)?;
-    if let Some(tls) = tls {
-      // Present our client cert (the DB requires one) and encrypt, but don't
-      // verify the server cert. We use `Require`, not `VerifyCa`: Cloud Run
-      // connects to Cloud SQL by private IP, yet the server cert is only valid
-      // for the instance's `*.sql.goog` DNS name. `VerifyCa` is meant to skip
-      // that hostname check, but sqlx 0.8's `NoHostnameTlsVerifier` only
-      // swallows rustls's legacy `NotValidForName` error, not 0.23's
-      // `NotValidForNameContext`, so verification fails and the connection is
-      // refused. The client certificate (mTLS) is the access boundary and the
-      // link stays inside the VPC.
-      opts = opts
-        .ssl_mode(PgSslMode::Require)
-        .ssl_client_cert_from_pem(tls.client_cert.into_bytes())
-        .ssl_client_key_from_pem(tls.client_key.into_bytes());
-    }
-    let pool = PgPoolOptions::new()
-      .max_connections(pool_size)
-      .acquire_timeout(acquire_timeout)
-      .connect_with(opts)
-      .await?;
+    let pool =
+      connect_pool(database_url, pool_size, acquire_timeout, tls).await?;
     if std::env::var("DATABASE_DISABLE_MIGRATIONS").is_err() {
       migrate!("./migrations")
         .run(&pool)
@@ -101,7 +166,129 @@ impl Database {
         .expect("database schema error");
     }
     println!("Database ready");
-    Ok(Database { pool })
+    Ok(Database {
+      pool,
+      replica: None,
+      cache: None,
+      search_index: None,
+      events: None,
+    })
+  }
+
+  /// Routes read-only queries that tolerate replication lag to the read
+  /// replica at `database_url`, except for `stickiness` after a write.
+  pub async fn with_read_replica(
+    self,
+    database_url: &str,
+    pool_size: u32,
+    acquire_timeout: std::time::Duration,
+    tls: Option<DbTls>,
+    stickiness: chrono::Duration,
+  ) -> anyhow::Result<Self> {
+    let pool =
+      connect_pool(database_url, pool_size, acquire_timeout, tls).await?;
+    println!("Database read replica ready");
+    Ok(Database {
+      replica: Some(ReadReplica {
+        pool,
+        stickiness,
+        sticky_until: Arc::new(AtomicI64::new(0)),
+      }),
+      ..self
+    })
+  }
+
+  /// Caches packages, their version lists and scopes in `cache`, for the
+  /// reads that opt into it, see [Database::get_package_cached].
+  pub fn with_metadata_cache(self, cache: MetadataCache) -> Self {
+    Database {
+      cache: Some(cache),
+      ..self
+    }
+  }
+
+  /// The hit counts of the metadata cache, if there is one.
+  pub fn metadata_cache_stats(&self) -> Option<MetadataCacheStats> {
+    self.cache.as_ref().map(|cache| cache.stats())
+  }
+
+  /// Queues an update of the search documents of packages in the external
+  /// search engines after every change to a package, see
+  /// [crate::search::spawn_index_worker].
+  pub fn with_search_index(self, search_index: SearchIndexQueue) -> Self {
+    Database {
+      search_index: Some(search_index),
+      ..self
+    }
+  }
+
+  /// Puts the events emitted with [crate::events::emit] on `events`, see
+  /// [crate::events::spawn_event_worker].
+  pub fn with_event_queue(self, events: EventQueue) -> Self {
+    Database {
+      events: Some(events),
+      ..self
+    }
+  }
+
+  /// Puts `event` on the event bus, if there is one.
+  pub fn queue_event(&self, event: Event) {
+    if let Some(events) = &self.events {
+      events.push(event);
+    }
+  }
+
+  /// Called after a package, or one of its versions, changed: makes its
+  /// cached metadata and version list stale, and queues an update of its
+  /// search document.
+  async fn package_changed(&self, scope: &ScopeName, name: &PackageName) {
+    if let Some(cache) = &self.cache {
+      cache.invalidate(&package_generation_key(scope, name)).await;
+    }
+    if let Some(search_index) = &self.search_index {
+      search_index.package_changed(scope, name);
+    }
+  }
+
+  /// Makes the cached scope stale.
+  async fn invalidate_cached_scope(&self, scope: &ScopeName) {
+    if let Some(cache) = &self.cache {
+      cache.invalidate(&scope_generation_key(scope)).await;
+    }
+  }
+
+  /// Sends all reads to the primary for a while, because a write is about to
+  /// happen that the replica will only see with a delay.
+  pub fn stick_to_primary(&self) {
+    if let Some(replica) = &self.replica {
+      let until = (Utc::now() + replica.stickiness).timestamp_millis();
+      replica.sticky_until.fetch_max(until, Ordering::Relaxed);
+    }
+  }
+
+  /// Whether reads currently stick to the primary after a write.
+  pub fn is_sticky_to_primary(&self) -> bool {
+    self.replica.as_ref().is_some_and(|replica| {
+      replica.sticky_until.load(Ordering::Relaxed)
+        >= Utc::now().timestamp_millis()
+    })
+  }
+
+  /// The pool for a read-only query that tolerates replication lag.
+  fn reader(&self) -> &sqlx::PgPool {
+    match &self.replica {
+      Some(replica) if !self.is_sticky_to_primary() => &replica.pool,
+      _ => &self.pool,
+    }
+  }
+
+  /// The health of the pool of the primary, and of the read replica, if any.
+  pub fn pool_health(&self) -> (PoolHealth, Option<PoolHealth>) {
+    let replica = self.replica.as_ref();
+    (
+      PoolHealth::of(&self.pool),
+      replica.map(|replica| PoolHealth::of(&replica.pool)),
+    )
   }
 
   #[instrument(name = "Database::get_user", skip(self), err)]
@@ -458,46 +645,28 @@ impl Database {
     scope: &ScopeName,
     name: &PackageName,
   ) -> Result<Option<PackageWithGitHubRepoAndMeta>> {
-    query_concat!(
-      "SELECT ", PACKAGE_SELECT_JOINED, ", ", GITHUB_REPOSITORY_SELECT_JOINED, "
-      FROM packages
-      LEFT JOIN github_repositories ON packages.github_repository_id = github_repositories.id
-      WHERE packages.scope = $1 AND packages.name = $2";
-      scope as _,
-      name as _
-    )
-      .map(|r| {
-        let package = Package {
-          scope: r.package_scope,
-          name: r.package_name,
-          description: r.package_description,
-          github_repository_id: r.package_github_repository_id,
-          runtime_compat: r.package_runtime_compat,
-          created_at: r.package_created_at,
-          updated_at: r.package_updated_at,
-          version_count: r.package_version_count,
-          latest_version: r.package_latest_version,
-          when_featured: r.package_when_featured,
-          is_archived: r.package_is_archived,
-          readme_source: r.package_readme_source,
-        };
-        let github_repository = if r.package_github_repository_id.is_some() {
-          Some(GithubRepository {
-            id: r.github_repository_id.unwrap(),
-            owner: r.github_repository_owner.unwrap(),
-            name: r.github_repository_name.unwrap(),
-            created_at: r.github_repository_created_at.unwrap(),
-            updated_at: r.github_repository_updated_at.unwrap(),
-          })
-        } else {
-          None
-        };
-
-        let meta = r.package_version_meta.unwrap_or_default();
+    get_package(self.reader(), scope, name).await
+  }
 
-        (package, github_repository, meta)
-      })
-      .fetch_optional(&self.pool)
+  /// Like [Database::get_package], but served from the metadata cache if
+  /// there is one. Misses are read from the primary, so that a lagging
+  /// replica never fills the cache with a stale package.
+  #[instrument(name = "Database::get_package_cached", skip(self), err)]
+  pub async fn get_package_cached(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+  ) -> Result<Option<PackageWithGitHubRepoAndMeta>> {
+    let Some(cache) = &self.cache else {
+      return self.get_package(scope, name).await;
+    };
+    cache
+      .get_or_fetch(
+        MetadataCacheKind::Package,
+        &package_generation_key(scope, name),
+        "package",
+        get_package(&self.pool, scope, name),
+      )
       .await
   }
 
@@ -513,8 +682,8 @@ impl Database {
       "INSERT INTO packages (scope, name)
       VALUES ($1, $2)
       RETURNING ", PACKAGE_SELECT, r#",
-        (SELECT COUNT(created_at) FROM package_versions WHERE scope = packages.scope AND name = packages.name) as "version_count!",
-        (SELECT version FROM package_versions WHERE scope = packages.scope AND name = packages.name AND version NOT LIKE '%-%' AND is_yanked = false ORDER BY version DESC LIMIT 1) as "latest_version"
+        (SELECT COUNT(created_at) FROM package_versions WHERE scope = packages.scope AND name = packages.name AND deleted_at IS NULL) as "version_count!",
+        (SELECT version FROM package_versions WHERE scope = packages.scope AND name = packages.name AND deleted_at IS NULL AND version NOT LIKE '%-%' AND is_yanked = false ORDER BY version DESC LIMIT 1) as "latest_version"
       "#;
       scope as _,
       name as _
@@ -527,13 +696,36 @@ impl Database {
         if let Some(dberr) = err.as_database_error()
           && dberr.is_unique_violation()
         {
+          drop(tx);
+          let deleted = sqlx::query!(
+            r#"SELECT deleted_at IS NOT NULL as "deleted!" FROM packages WHERE scope = $1 AND name = $2"#,
+            scope as _,
+            name as _,
+          )
+          .fetch_optional(&self.pool)
+          .await?;
+          if deleted.is_some_and(|r| r.deleted) {
+            return Ok(CreatePackageResult::Deleted);
+          }
           return Ok(CreatePackageResult::AlreadyExists);
         }
         return Err(err);
       }
     };
 
-    if let Some(res) = finalize_package_creation(tx, scope).await? {
+    // A new package takes over the name of a package that was moved away.
+    sqlx::query!(
+      r#"DELETE FROM package_redirects WHERE scope = $1 AND name = $2"#,
+      scope as _,
+      name as _,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // A lookup of the package before it existed may have been cached.
+    let res = finalize_package_creation(tx, scope).await?;
+    self.package_changed(scope, name).await;
+    if let Some(res) = res {
       return Ok(res);
     };
 
@@ -551,12 +743,14 @@ impl Database {
     package_name: &PackageName,
     version: &Version,
     rekor_log_id: &str,
+    bundle: &serde_json::Value,
   ) -> Result<()> {
     sqlx::query!(
       r#"UPDATE package_versions
-      SET rekor_log_id = $1, meta = jsonb_set_lax(meta, '{hasProvenance}', 'true'::jsonb, true)
-      WHERE scope = $2 AND name = $3 AND version = $4 AND rekor_log_id IS NULL AND created_at > now() - '2 minute'::interval"#,
+      SET rekor_log_id = $1, provenance_bundle = $2, meta = jsonb_set_lax(meta, '{hasProvenance}', 'true'::jsonb, true)
+      WHERE scope = $3 AND name = $4 AND version = $5 AND rekor_log_id IS NULL AND created_at > now() - '2 minute'::interval"#,
       rekor_log_id,
+      bundle,
       package_scope as _,
       package_name as _,
       version as _
@@ -564,9 +758,30 @@ impl Database {
       .execute(&self.pool)
       .await?;
 
+    self.package_changed(package_scope, package_name).await;
+
     Ok(())
   }
 
+  #[instrument(name = "Database::get_provenance_bundle", skip(self), err)]
+  pub async fn get_provenance_bundle(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+    version: &Version,
+  ) -> Result<Option<serde_json::Value>> {
+    let row = sqlx::query!(
+      r#"SELECT provenance_bundle FROM package_versions
+      WHERE scope = $1 AND name = $2 AND version = $3 AND deleted_at IS NULL"#,
+      scope as _,
+      name as _,
+      version as _,
+    )
+    .fetch_optional(&self.pool)
+    .await?;
+    Ok(row.and_then(|r| r.provenance_bundle))
+  }
+
   #[instrument(name = "Database::update_package_description", skip(self), err)]
   pub async fn update_package_description(
     &self,
@@ -593,7 +808,7 @@ impl Database {
     let package = query_concat!(
       "UPDATE packages
       SET description = $3
-      WHERE scope = $1 AND name = $2
+      WHERE scope = $1 AND name = $2 AND deleted_at IS NULL
       RETURNING ", PACKAGE_SELECT_JOINED;
       scope as _,
       name as _,
@@ -613,6 +828,8 @@ impl Database {
         when_featured: r.package_when_featured,
         is_archived: r.package_is_archived,
         readme_source: r.package_readme_source,
+        localized_descriptions: r.package_localized_descriptions,
+        visibility: r.package_visibility,
       };
 
       (package, None, r.package_version_meta.unwrap_or_default())
@@ -622,6 +839,65 @@ impl Database {
 
     tx.commit().await?;
 
+    self.package_changed(scope, name).await;
+
+    Ok(package)
+  }
+
+  /// Sets the description for `locale`, or removes the translation if
+  /// `description` is `None`.
+  #[instrument(
+    name = "Database::update_package_localized_description",
+    skip(self),
+    err
+  )]
+  pub async fn update_package_localized_description(
+    &self,
+    actor_id: &Uuid,
+    is_sudo: bool,
+    scope: &ScopeName,
+    name: &PackageName,
+    locale: &str,
+    description: Option<&str>,
+  ) -> Result<Package> {
+    let mut tx = self.pool.begin().await?;
+
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "update_package_localized_description",
+      json!({
+          "scope": scope,
+          "name": name,
+          "locale": locale,
+      }),
+    )
+    .await?;
+
+    let package = query_concat_as!(
+      Package,
+      "UPDATE packages
+      SET localized_descriptions = CASE
+        WHEN $4::text IS NULL THEN localized_descriptions - $3::text
+        ELSE jsonb_set(localized_descriptions, ARRAY[$3::text], to_jsonb($4::text))
+      END
+      WHERE scope = $1 AND name = $2 AND deleted_at IS NULL
+      RETURNING ", PACKAGE_SELECT, r#",
+        (SELECT COUNT(created_at) FROM package_versions WHERE scope = scope AND name = name AND deleted_at IS NULL) as "version_count!",
+        (SELECT version FROM package_versions WHERE scope = scope AND name = name AND deleted_at IS NULL ORDER BY version DESC LIMIT 1) as "latest_version""#;
+      scope as _,
+      name as _,
+      locale,
+      description,
+    )
+      .fetch_one(&mut *tx)
+      .await?;
+
+    tx.commit().await?;
+
+    self.package_changed(scope, name).await;
+
     Ok(package)
   }
 
@@ -670,7 +946,7 @@ impl Database {
     let (package, meta) = query_concat!(
       "UPDATE packages
       SET github_repository_id = $3
-      WHERE scope = $1 AND name = $2
+      WHERE scope = $1 AND name = $2 AND deleted_at IS NULL
       RETURNING ", PACKAGE_SELECT_JOINED;
       scope as _,
       name as _,
@@ -690,6 +966,8 @@ impl Database {
         when_featured: r.package_when_featured,
         is_archived: r.package_is_archived,
         readme_source: r.package_readme_source,
+        localized_descriptions: r.package_localized_descriptions,
+        visibility: r.package_visibility,
       };
 
       (package, r.package_version_meta.unwrap_or_default())
@@ -699,6 +977,8 @@ impl Database {
 
     tx.commit().await?;
 
+    self.package_changed(scope, name).await;
+
     Ok((package, repo, meta))
   }
 
@@ -732,10 +1012,10 @@ impl Database {
       Package,
       "UPDATE packages
       SET github_repository_id = NULL
-      WHERE scope = $1 AND name = $2
+      WHERE scope = $1 AND name = $2 AND deleted_at IS NULL
       RETURNING ", PACKAGE_SELECT, r#",
-        (SELECT COUNT(created_at) FROM package_versions WHERE scope = scope AND name = name) as "version_count!",
-        (SELECT version FROM package_versions WHERE scope = scope AND name = name ORDER BY version DESC LIMIT 1) as "latest_version""#;
+        (SELECT COUNT(created_at) FROM package_versions WHERE scope = scope AND name = name AND deleted_at IS NULL) as "version_count!",
+        (SELECT version FROM package_versions WHERE scope = scope AND name = name AND deleted_at IS NULL ORDER BY version DESC LIMIT 1) as "latest_version""#;
       scope as _,
       name as _,
     )
@@ -744,6 +1024,8 @@ impl Database {
 
     tx.commit().await?;
 
+    self.package_changed(scope, name).await;
+
     Ok(package)
   }
 
@@ -779,10 +1061,10 @@ impl Database {
       Package,
       "UPDATE packages
       SET runtime_compat = $3
-      WHERE scope = $1 AND name = $2
+      WHERE scope = $1 AND name = $2 AND deleted_at IS NULL
       RETURNING ", PACKAGE_SELECT, r#",
-        (SELECT COUNT(created_at) FROM package_versions WHERE scope = scope AND name = name) as "version_count!",
-        (SELECT version FROM package_versions WHERE scope = scope AND name = name ORDER BY version DESC LIMIT 1) as "latest_version""#;
+        (SELECT COUNT(created_at) FROM package_versions WHERE scope = scope AND name = name AND deleted_at IS NULL) as "version_count!",
+        (SELECT version FROM package_versions WHERE scope = scope AND name = name AND deleted_at IS NULL ORDER BY version DESC LIMIT 1) as "latest_version""#;
       scope as _,
       name as _,
       runtime_compat as _
@@ -792,6 +1074,8 @@ impl Database {
 
     tx.commit().await?;
 
+    self.package_changed(scope, name).await;
+
     Ok(package)
   }
 
@@ -824,10 +1108,10 @@ impl Database {
       Package,
       "UPDATE packages
       SET when_featured = $3
-      WHERE scope = $1 AND name = $2
+      WHERE scope = $1 AND name = $2 AND deleted_at IS NULL
       RETURNING ", PACKAGE_SELECT, r#",
-        (SELECT COUNT(created_at) FROM package_versions WHERE scope = scope AND name = name) as "version_count!",
-        (SELECT version FROM package_versions WHERE scope = scope AND name = name ORDER BY version DESC LIMIT 1) as "latest_version""#;
+        (SELECT COUNT(created_at) FROM package_versions WHERE scope = scope AND name = name AND deleted_at IS NULL) as "version_count!",
+        (SELECT version FROM package_versions WHERE scope = scope AND name = name AND deleted_at IS NULL ORDER BY version DESC LIMIT 1) as "latest_version""#;
       scope as _,
       name as _,
       when_featured,
@@ -837,6 +1121,8 @@ impl Database {
 
     tx.commit().await?;
 
+    self.package_changed(scope, name).await;
+
     Ok(package)
   }
 
@@ -868,10 +1154,10 @@ impl Database {
       Package,
       "UPDATE packages
       SET is_archived = $3
-      WHERE scope = $1 AND name = $2
+      WHERE scope = $1 AND name = $2 AND deleted_at IS NULL
       RETURNING ", PACKAGE_SELECT, r#",
-        (SELECT COUNT(created_at) FROM package_versions WHERE scope = scope AND name = name) as "version_count!",
-        (SELECT version FROM package_versions WHERE scope = scope AND name = name ORDER BY version DESC LIMIT 1) as "latest_version""#;
+        (SELECT COUNT(created_at) FROM package_versions WHERE scope = scope AND name = name AND deleted_at IS NULL) as "version_count!",
+        (SELECT version FROM package_versions WHERE scope = scope AND name = name AND deleted_at IS NULL ORDER BY version DESC LIMIT 1) as "latest_version""#;
       scope as _,
       name as _,
       is_archived,
@@ -881,9 +1167,73 @@ impl Database {
 
     tx.commit().await?;
 
+    self.package_changed(scope, name).await;
+
+    Ok(package)
+  }
+
+  #[instrument(name = "Database::update_package_visibility", skip(self), err)]
+  pub async fn update_package_visibility(
+    &self,
+    actor_id: &Uuid,
+    is_sudo: bool,
+    scope: &ScopeName,
+    name: &PackageName,
+    visibility: PackageVisibility,
+  ) -> Result<Package> {
+    let mut tx = self.pool.begin().await?;
+
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "package_set_visibility",
+      json!({
+          "scope": scope,
+          "name": name,
+          "visibility": visibility,
+      }),
+    )
+    .await?;
+
+    let package = query_concat_as!(
+      Package,
+      "UPDATE packages
+      SET visibility = $3
+      WHERE scope = $1 AND name = $2 AND deleted_at IS NULL
+      RETURNING ", PACKAGE_SELECT, r#",
+        (SELECT COUNT(created_at) FROM package_versions WHERE scope = scope AND name = name AND deleted_at IS NULL) as "version_count!",
+        (SELECT version FROM package_versions WHERE scope = scope AND name = name AND deleted_at IS NULL ORDER BY version DESC LIMIT 1) as "latest_version""#;
+      scope as _,
+      name as _,
+      visibility as _,
+    )
+      .fetch_one(&mut *tx)
+      .await?;
+
+    tx.commit().await?;
+
+    self.package_changed(scope, name).await;
+
     Ok(package)
   }
 
+  #[instrument(name = "Database::get_package_visibility", skip(self), err)]
+  pub async fn get_package_visibility(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+  ) -> Result<Option<PackageVisibility>> {
+    sqlx::query!(
+      r#"SELECT visibility as "visibility: PackageVisibility" FROM packages WHERE scope = $1 AND name = $2 AND deleted_at IS NULL"#,
+      scope as _,
+      name as _,
+    )
+    .map(|r| r.visibility)
+    .fetch_optional(&self.pool)
+    .await
+  }
+
   #[instrument(name = "Database::update_package_source", skip(self), err)]
   pub async fn update_package_source(
     &self,
@@ -912,10 +1262,10 @@ impl Database {
       Package,
       "UPDATE packages
       SET readme_source = $3
-      WHERE scope = $1 AND name = $2
+      WHERE scope = $1 AND name = $2 AND deleted_at IS NULL
       RETURNING ", PACKAGE_SELECT, r#",
-        (SELECT COUNT(created_at) FROM package_versions WHERE scope = scope AND name = name) as "version_count!",
-        (SELECT version FROM package_versions WHERE scope = scope AND name = name ORDER BY version DESC LIMIT 1) as "latest_version""#;
+        (SELECT COUNT(created_at) FROM package_versions WHERE scope = scope AND name = name AND deleted_at IS NULL) as "version_count!",
+        (SELECT version FROM package_versions WHERE scope = scope AND name = name AND deleted_at IS NULL ORDER BY version DESC LIMIT 1) as "latest_version""#;
       scope as _,
       name as _,
       source as _,
@@ -925,6 +1275,8 @@ impl Database {
 
     tx.commit().await?;
 
+    self.package_changed(scope, name).await;
+
     Ok(package)
   }
 
@@ -959,7 +1311,7 @@ impl Database {
       Scope,
       "WITH ins_scope AS (
             INSERT INTO scopes (scope, creator) VALUES ($1, $2)
-            RETURNING scope, description, creator, package_limit, new_package_per_week_limit, publish_attempts_per_week_limit, verify_oidc_actor, require_publishing_from_ci, updated_at, created_at
+            RETURNING scope, description, creator, package_limit, new_package_per_week_limit, publish_attempts_per_week_limit, storage_limit, verify_oidc_actor, require_publishing_from_ci, updated_at, created_at
         ),
         ins_member AS (
             INSERT INTO scope_members (scope, user_id, is_admin)
@@ -974,6 +1326,8 @@ impl Database {
 
     tx.commit().await?;
 
+    self.invalidate_cached_scope(scope_name).await;
+
     Ok(scope)
   }
 
@@ -985,6 +1339,7 @@ impl Database {
     package_limit: Option<i32>,
     new_package_per_week_limit: Option<i32>,
     publish_attempts_per_week_limit: Option<i32>,
+    storage_limit: Option<i64>,
   ) -> Result<(Scope, ScopeUsage, UserPublic)> {
     let mut tx = self.pool.begin().await?;
 
@@ -1056,13 +1411,36 @@ impl Database {
         .await?;
     }
 
+    if let Some(storage_limit) = storage_limit {
+      audit_log(
+        &mut tx,
+        staff_id,
+        true,
+        "scope_set_storage_limit",
+        json!({
+          "scope": scope,
+          "storage_limit": storage_limit,
+        }),
+      )
+      .await?;
+
+      sqlx::query!(
+        r#"UPDATE scopes SET storage_limit = $1 WHERE scope = $2"#,
+        storage_limit,
+        scope as _
+      )
+      .execute(&mut *tx)
+      .await?;
+    }
+
     let res = sqlx::query!(
       r#"
       WITH usage AS (
         SELECT
-          (SELECT COUNT(created_at) FROM packages WHERE scope = $1) AS package,
-          (SELECT COUNT(created_at) FROM packages WHERE scope = $1 AND created_at > now() - '1 week'::interval) AS new_package_per_week,
-          (SELECT COUNT(created_at) FROM publishing_tasks WHERE package_scope = $1 AND created_at > now() - '1 week'::interval) AS publish_attempts_per_week
+          (SELECT COUNT(created_at) FROM packages WHERE scope = $1 AND deleted_at IS NULL) AS package,
+          (SELECT COUNT(created_at) FROM packages WHERE scope = $1 AND deleted_at IS NULL AND created_at > now() - '1 week'::interval) AS new_package_per_week,
+          (SELECT COUNT(created_at) FROM publishing_tasks WHERE package_scope = $1 AND created_at > now() - '1 week'::interval) AS publish_attempts_per_week,
+          (SELECT COALESCE(SUM(size), 0) FROM package_files WHERE scope = $1) AS storage
       )
       SELECT
       scopes.scope as "scope_scope: ScopeName",
@@ -1071,13 +1449,14 @@ impl Database {
       scopes.package_limit as "scope_package_limit",
       scopes.new_package_per_week_limit as "scope_new_package_per_week_limit",
       scopes.publish_attempts_per_week_limit as "scope_publish_attempts_per_week_limit",
+      scopes.storage_limit as "scope_storage_limit",
       scopes.verify_oidc_actor as "scope_verify_oidc_actor",
       scopes.require_publishing_from_ci as "scope_require_publishing_from_ci",
       scopes.updated_at as "scope_updated_at",
       scopes.created_at as "scope_created_at",
       users.id as "user_id", users.name as "user_name", users.avatar_url as "user_avatar_url", users.github_id as "user_github_id",
 users.gitlab_id as "user_gitlab_id", users.updated_at as "user_updated_at", users.created_at as "user_created_at",
-      usage.package as "usage_package", usage.new_package_per_week as "usage_new_package_per_week", usage.publish_attempts_per_week as "usage_publish_attempts_per_week"
+      usage.package as "usage_package", usage.new_package_per_week as "usage_new_package_per_week", usage.publish_attempts_per_week as "usage_publish_attempts_per_week", usage.storage as "usage_storage"
       FROM scopes
       LEFT JOIN users ON scopes.creator = users.id
       CROSS JOIN usage
@@ -1095,6 +1474,7 @@ users.gitlab_id as "user_gitlab_id", users.updated_at as "user_updated_at", user
           package_limit: r.scope_package_limit,
           new_package_per_week_limit: r.scope_new_package_per_week_limit,
           publish_attempts_per_week_limit: r.scope_publish_attempts_per_week_limit,
+          storage_limit: r.scope_storage_limit,
           verify_oidc_actor: r.scope_verify_oidc_actor,
           require_publishing_from_ci: r.scope_require_publishing_from_ci,
         };
@@ -1102,6 +1482,7 @@ users.gitlab_id as "user_gitlab_id", users.updated_at as "user_updated_at", user
           package: r.usage_package.unwrap().try_into().unwrap(),
           new_package_per_week: r.usage_new_package_per_week.unwrap().try_into().unwrap(),
           publish_attempts_per_week: r.usage_publish_attempts_per_week.unwrap().try_into().unwrap(),
+          storage: r.usage_storage.unwrap(),
         };
         let user = UserPublic {
           id: r.user_id,
@@ -1119,6 +1500,8 @@ gitlab_id: r.user_gitlab_id,
 
     tx.commit().await?;
 
+    self.invalidate_cached_scope(scope).await;
+
     Ok(res)
   }
 
@@ -1141,6 +1524,7 @@ gitlab_id: r.user_gitlab_id,
       "package_limit" => "scopes.package_limit",
       "new_package_per_week_limit" => "scopes.new_package_per_week_limit",
       "publish_attempts_per_week_limit" => "scopes.publish_attempts_per_week_limit",
+      "storage_limit" => "scopes.storage_limit",
       "created_at" => "scopes.created_at",
     } || "scopes.created_at DESC");
 
@@ -1208,13 +1592,34 @@ gitlab_id: r.user_gitlab_id,
     .await
   }
 
+  /// Like [Database::get_scope], but served from the metadata cache if there
+  /// is one.
+  #[instrument(name = "Database::get_scope_cached", skip(self), err)]
+  pub async fn get_scope_cached(
+    &self,
+    scope: &ScopeName,
+  ) -> Result<Option<Scope>> {
+    let Some(cache) = &self.cache else {
+      return self.get_scope(scope).await;
+    };
+    cache
+      .get_or_fetch(
+        MetadataCacheKind::Scope,
+        &scope_generation_key(scope),
+        "scope",
+        self.get_scope(scope),
+      )
+      .await
+  }
+
   #[instrument(name = "Database::get_scope_usage", skip(self), err)]
   pub async fn get_scope_usage(&self, scope: &ScopeName) -> Result<ScopeUsage> {
     sqlx::query!(
       r#"SELECT
-      (SELECT COUNT(created_at) FROM packages WHERE scope = $1 AND created_at > now() - '1 week'::interval) AS new_package_per_week,
-      (SELECT COUNT(created_at) FROM packages WHERE scope = $1) AS package,
-      (SELECT COUNT(created_at) FROM publishing_tasks WHERE package_scope = $1 AND created_at > now() - '1 week'::interval) AS publish_attempts_per_week;"#,
+      (SELECT COUNT(created_at) FROM packages WHERE scope = $1 AND deleted_at IS NULL AND created_at > now() - '1 week'::interval) AS new_package_per_week,
+      (SELECT COUNT(created_at) FROM packages WHERE scope = $1 AND deleted_at IS NULL) AS package,
+      (SELECT COUNT(created_at) FROM publishing_tasks WHERE package_scope = $1 AND created_at > now() - '1 week'::interval) AS publish_attempts_per_week,
+      (SELECT COALESCE(SUM(size), 0) FROM package_files WHERE scope = $1) AS storage;"#,
     scope as _,
     )
       .map(|r| {
@@ -1222,6 +1627,7 @@ gitlab_id: r.user_gitlab_id,
           package: r.package.unwrap().try_into().unwrap(),
           new_package_per_week: r.new_package_per_week.unwrap().try_into().unwrap(),
           publish_attempts_per_week: r.publish_attempts_per_week.unwrap().try_into().unwrap(),
+          storage: r.storage.unwrap(),
         }
       })
       .fetch_one(&self.pool)
@@ -1262,6 +1668,8 @@ gitlab_id: r.user_gitlab_id,
 
     tx.commit().await?;
 
+    self.invalidate_cached_scope(scope).await;
+
     Ok(scope)
   }
 
@@ -1303,6 +1711,8 @@ gitlab_id: r.user_gitlab_id,
 
     tx.commit().await?;
 
+    self.invalidate_cached_scope(scope).await;
+
     Ok(scope)
   }
 
@@ -1340,14 +1750,76 @@ gitlab_id: r.user_gitlab_id,
 
     tx.commit().await?;
 
+    self.invalidate_cached_scope(scope).await;
+
     Ok(scope)
   }
 
+  #[instrument(name = "Database::get_scope_markdown_settings", skip(self), err)]
+  pub async fn get_scope_markdown_settings(
+    &self,
+    scope: &ScopeName,
+  ) -> Result<Option<ScopeMarkdownSettings>> {
+    query_concat_as!(
+      ScopeMarkdownSettings,
+      "SELECT ", SCOPE_MARKDOWN_SETTINGS_SELECT, "
+      FROM scope_markdown_settings
+      WHERE scope = $1";
+      scope as _
+    )
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::scope_set_markdown_settings", skip(self), err)]
+  pub async fn scope_set_markdown_settings(
+    &self,
+    actor_id: &Uuid,
+    is_sudo: bool,
+    scope: &ScopeName,
+    allow_html: bool,
+    admonitions: &[String],
+  ) -> Result<ScopeMarkdownSettings> {
+    let mut tx = self.pool.begin().await?;
+
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "scope_set_markdown_settings",
+      json!({
+        "scope": scope,
+        "allow_html": allow_html,
+        "admonitions": admonitions,
+      }),
+    )
+    .await?;
+
+    let settings = query_concat_as!(
+      ScopeMarkdownSettings,
+      "INSERT INTO scope_markdown_settings (scope, allow_html, admonitions)
+      VALUES ($1, $2, $3)
+      ON CONFLICT (scope) DO UPDATE
+      SET allow_html = $2, admonitions = $3
+      RETURNING ", SCOPE_MARKDOWN_SETTINGS_SELECT;
+      scope as _,
+      allow_html,
+      admonitions
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(settings)
+  }
+
   #[instrument(name = "Database::list_packages_by_scope", skip(self), err)]
   pub async fn list_packages_by_scope(
     &self,
     scope: &ScopeName,
     show_archived: bool,
+    show_private: bool,
     start: i64,
     limit: i64,
   ) -> Result<(usize, Vec<PackageWithGitHubRepoAndMeta>)> {
@@ -1360,13 +1832,14 @@ gitlab_id: r.user_gitlab_id,
       FROM packages
       LEFT JOIN github_repositories ON packages.github_repository_id = github_repositories.id
       ", PACKAGE_VERSION_LATERAL_JOINS, "
-      WHERE packages.scope = $1 AND ($2 = true OR packages.is_archived = false)
+      WHERE packages.scope = $1 AND packages.deleted_at IS NULL AND ($2 = true OR packages.is_archived = false) AND ($5 = true OR packages.visibility = 'public')
       ORDER BY packages.is_archived ASC, packages.name
       OFFSET $3 LIMIT $4";
       scope as _,
       show_archived,
       start,
-      limit
+      limit,
+      show_private
     )
       .map(|r| {
         let package = Package {
@@ -1382,6 +1855,8 @@ gitlab_id: r.user_gitlab_id,
           when_featured: r.package_when_featured,
           is_archived: r.package_is_archived,
           readme_source: r.package_readme_source,
+          localized_descriptions: r.package_localized_descriptions,
+          visibility: r.package_visibility,
         };
         let github_repository = if r.package_github_repository_id.is_some() {
           Some(GithubRepository {
@@ -1403,9 +1878,10 @@ gitlab_id: r.user_gitlab_id,
       .await?;
 
     let total_packages = sqlx::query!(
-      r#"SELECT COUNT(created_at) FROM packages WHERE scope = $1 AND ($2 = true OR packages.is_archived = false);"#,
+      r#"SELECT COUNT(created_at) FROM packages WHERE scope = $1 AND deleted_at IS NULL AND ($2 = true OR packages.is_archived = false) AND ($3 = true OR packages.visibility = 'public');"#,
       scope as _,
       show_archived,
+      show_private,
     )
       .map(|r| r.count.unwrap())
       .fetch_one(&mut *tx)
@@ -1416,6 +1892,7 @@ gitlab_id: r.user_gitlab_id,
     Ok((total_packages as usize, packages))
   }
 
+  #[allow(clippy::too_many_arguments)]
   #[instrument(name = "Database::list_packages", skip(self), err)]
   pub async fn list_packages(
     &self,
@@ -1424,52 +1901,20 @@ gitlab_id: r.user_gitlab_id,
     maybe_search_query: Option<&str>,
     maybe_github_repo_id: Option<i64>,
     maybe_sort: Option<&str>,
+    filters: &PackageSearchFilters,
   ) -> Result<(usize, Vec<PackageWithGitHubRepoAndMeta>)> {
-    let mut tx = self.pool.begin().await?;
+    let mut tx = self.reader().begin().await?;
+    let schema = crate::score::active_schema();
 
     let (
       scope_ilike_query,
       scope_exact_query,
       package_ilike_query,
       package_exact_query,
-    ) = if let Some(search_query) = maybe_search_query {
-      // 1. Strip leading `@`.
-      let search_query = search_query.strip_prefix('@').unwrap_or(search_query);
-
-      // 2. If there's a space in the search query, we're gonna split it
-      // and use the first term for scope search and the reminder for package
-      // search.
-      let (scope_query, package_query) = if let Some((
-        scope_query,
-        package_query,
-      )) = search_query.split_once(' ')
-      {
-        (scope_query, package_query)
-      } else {
-        // 3. If there's no space in the search query, we're gonna split it
-        // at `/` and use the first term for scope search and the reminder for package
-        // search.
-        search_query
-          .split_once('/')
-          .unwrap_or((search_query, search_query))
-      };
-
-      (
-        format!("%{}%", scope_query),
-        scope_query.to_string(),
-        format!("%{}%", package_query),
-        package_query.to_string(),
-      )
-    } else {
-      (
-        "%%".to_string(),
-        "".to_string(),
-        "%%".to_string(),
-        "".to_string(),
-      )
-    };
+    ) = package_search_terms(maybe_search_query);
+    // Like timestamps, downloads sort the highest first unless inverted.
     let sort = sort_by!(maybe_sort => {
-      @timestamps "when_featured", "updated_at", "created_at";
+      @timestamps "when_featured", "updated_at", "created_at", "weekly_downloads", "monthly_downloads";
       "scope" => "packages.scope",
       "name" => "packages.name",
       // "repository",
@@ -1477,33 +1922,43 @@ gitlab_id: r.user_gitlab_id,
       "when_featured" => "packages.when_featured",
       "updated_at" => "packages.updated_at",
       "created_at" => "packages.created_at",
+      "weekly_downloads" => "COALESCE(package_download_rollups.weekly_downloads, 0)",
+      "monthly_downloads" => "COALESCE(package_download_rollups.monthly_downloads, 0)",
     } || "packages.name ASC, packages.scope ASC");
 
     let packages = sqlx::query(
       &format!(r#"SELECT {}, {}, {}
        FROM packages
        LEFT JOIN github_repositories ON packages.github_repository_id = github_repositories.id
+       LEFT JOIN package_download_rollups ON package_download_rollups.scope = packages.scope AND package_download_rollups.package = packages.name
        {}
-       WHERE (packages.scope ILIKE $1 OR packages.name ILIKE $2) AND (packages.github_repository_id = $5 OR $5 IS NULL) AND NOT packages.is_archived
+       WHERE {}
        ORDER BY
          CASE
-           WHEN packages.name ILIKE $3 THEN 1 -- Exact match for package name
-           WHEN packages.scope ILIKE $4 THEN 2 -- Exact match for scope name
+           WHEN packages.name ILIKE $10 THEN 1 -- Exact match for package name
+           WHEN packages.scope ILIKE $11 THEN 2 -- Exact match for scope name
            ELSE 3 -- Fuzzy matches will be ordered by package name and then scope name below
         END,
         {sort}
-       OFFSET $6 LIMIT $7"#,
+       OFFSET $12 LIMIT $13"#,
         crate::db::sql_fragments::PACKAGE_BASE_SELECT_JOINED_RT,
         crate::db::sql_fragments::PACKAGE_VERSION_AGG_SELECT_RT,
         crate::db::sql_fragments::GITHUB_REPOSITORY_SELECT_JOINED_RT,
         crate::db::sql_fragments::PACKAGE_VERSION_LATERAL_JOINS_RT,
+        crate::db::sql_fragments::PACKAGE_SEARCH_WHERE_RT,
       ),
     )
       .bind(&scope_ilike_query)
       .bind(&package_ilike_query)
+      .bind(maybe_github_repo_id)
+      .bind(&filters.runtime_compat)
+      .bind(filters.has_provenance)
+      .bind(filters.license.as_deref())
+      .bind(filters.min_score.map(|min_score| min_score as i32))
+      .bind(sqlx::types::Json(schema.weights))
+      .bind(schema.max_score)
       .bind(package_exact_query)
       .bind(scope_exact_query)
-      .bind(maybe_github_repo_id)
       .bind(start)
       .bind(limit)
       .try_map(|r| {
@@ -1521,21 +1976,102 @@ gitlab_id: r.user_gitlab_id,
       .fetch_all(&mut *tx)
       .await?;
 
-    let total_packages = sqlx::query!(
-      r#"SELECT COUNT(created_at) FROM packages WHERE (packages.scope ILIKE $1 OR packages.name ILIKE $2) AND (packages.github_repository_id = $3 OR $3 IS NULL);"#,
-      scope_ilike_query,
-      package_ilike_query,
-      maybe_github_repo_id,
-    )
-      .map(|r| r.count.unwrap())
-      .fetch_one(&mut *tx)
-      .await?;
+    let total_packages: i64 = sqlx::query_scalar(&format!(
+      r#"SELECT COUNT(packages.created_at) FROM packages {} WHERE {}"#,
+      crate::db::sql_fragments::PACKAGE_VERSION_LATERAL_JOINS_RT,
+      crate::db::sql_fragments::PACKAGE_SEARCH_WHERE_RT,
+    ))
+    .bind(&scope_ilike_query)
+    .bind(&package_ilike_query)
+    .bind(maybe_github_repo_id)
+    .bind(&filters.runtime_compat)
+    .bind(filters.has_provenance)
+    .bind(filters.license.as_deref())
+    .bind(filters.min_score.map(|min_score| min_score as i32))
+    .bind(sqlx::types::Json(schema.weights))
+    .bind(schema.max_score)
+    .fetch_one(&mut *tx)
+    .await?;
 
     tx.commit().await?;
 
     Ok((total_packages as usize, packages))
   }
 
+  /// Counts the packages matching a package search for each runtime, for
+  /// provenance and for the most common licenses. The counts honor the
+  /// search query and all `filters`, so they describe the current result set.
+  #[instrument(name = "Database::package_search_facets", skip(self), err)]
+  pub async fn package_search_facets(
+    &self,
+    maybe_search_query: Option<&str>,
+    maybe_github_repo_id: Option<i64>,
+    filters: &PackageSearchFilters,
+  ) -> Result<PackageSearchFacets> {
+    let (scope_ilike_query, _, package_ilike_query, _) =
+      package_search_terms(maybe_search_query);
+    let schema = crate::score::active_schema();
+
+    let counts_fut = sqlx::query(&format!(
+      r#"SELECT
+        COUNT(*) FILTER (WHERE packages.runtime_compat->>'browser' = 'true') AS "browser",
+        COUNT(*) FILTER (WHERE packages.runtime_compat->>'deno' = 'true') AS "deno",
+        COUNT(*) FILTER (WHERE packages.runtime_compat->>'node' = 'true') AS "node",
+        COUNT(*) FILTER (WHERE packages.runtime_compat->>'workerd' = 'true') AS "workerd",
+        COUNT(*) FILTER (WHERE packages.runtime_compat->>'bun' = 'true') AS "bun",
+        COUNT(*) FILTER (WHERE (pv_latest.meta->>'hasProvenance')::boolean) AS "has_provenance"
+      FROM packages {} WHERE {}"#,
+      crate::db::sql_fragments::PACKAGE_VERSION_LATERAL_JOINS_RT,
+      crate::db::sql_fragments::PACKAGE_SEARCH_WHERE_RT,
+    ))
+    .bind(&scope_ilike_query)
+    .bind(&package_ilike_query)
+    .bind(maybe_github_repo_id)
+    .bind(&filters.runtime_compat)
+    .bind(filters.has_provenance)
+    .bind(filters.license.as_deref())
+    .bind(filters.min_score.map(|min_score| min_score as i32))
+    .bind(sqlx::types::Json(schema.weights))
+    .bind(schema.max_score)
+    .try_map(|r| {
+      Ok(PackageSearchFacets {
+        browser: r.try_get("browser")?,
+        deno: r.try_get("deno")?,
+        node: r.try_get("node")?,
+        workerd: r.try_get("workerd")?,
+        bun: r.try_get("bun")?,
+        has_provenance: r.try_get("has_provenance")?,
+        licenses: vec![],
+      })
+    })
+    .fetch_one(&self.pool);
+
+    let licenses_fut = sqlx::query_as::<_, (String, i64)>(&format!(
+      r#"SELECT pv_latest.license, COUNT(*)
+      FROM packages {} WHERE {} AND pv_latest.license IS NOT NULL
+      GROUP BY pv_latest.license
+      ORDER BY COUNT(*) DESC, pv_latest.license ASC
+      LIMIT 20"#,
+      crate::db::sql_fragments::PACKAGE_VERSION_LATERAL_JOINS_RT,
+      crate::db::sql_fragments::PACKAGE_SEARCH_WHERE_RT,
+    ))
+    .bind(&scope_ilike_query)
+    .bind(&package_ilike_query)
+    .bind(maybe_github_repo_id)
+    .bind(&filters.runtime_compat)
+    .bind(filters.has_provenance)
+    .bind(filters.license.as_deref())
+    .bind(filters.min_score.map(|min_score| min_score as i32))
+    .bind(sqlx::types::Json(schema.weights))
+    .bind(schema.max_score)
+    .fetch_all(&self.pool);
+
+    let (mut facets, licenses) = tokio::try_join!(counts_fut, licenses_fut)?;
+    facets.licenses = licenses;
+
+    Ok(facets)
+  }
+
   #[instrument(name = "Database::package_stats", skip(self), err)]
   pub async fn package_stats(
     &self,
@@ -1549,8 +2085,8 @@ gitlab_id: r.user_gitlab_id,
       FROM packages
       WHERE EXISTS (
         SELECT 1 FROM package_versions
-        WHERE scope = packages.scope AND name = packages.name AND is_yanked = false
-      ) AND NOT packages.is_archived
+        WHERE scope = packages.scope AND name = packages.name AND is_yanked = false AND deleted_at IS NULL
+      ) AND packages.deleted_at IS NULL AND NOT packages.is_archived AND packages.visibility = 'public'
       ORDER BY packages.created_at DESC
       LIMIT 10"#,
     )
@@ -1564,7 +2100,7 @@ gitlab_id: r.user_gitlab_id,
       r#"SELECT package_versions.scope as "scope: ScopeName", package_versions.name as "name: PackageName", package_versions.version as "version: Version"
       FROM package_versions
       JOIN packages ON packages.scope = package_versions.scope AND packages.name = package_versions.name
-      WHERE NOT packages.is_archived
+      WHERE package_versions.deleted_at IS NULL AND packages.deleted_at IS NULL AND NOT packages.is_archived AND packages.visibility = 'public'
       ORDER BY package_versions.created_at DESC
       LIMIT 10"#,
     )
@@ -1578,7 +2114,7 @@ gitlab_id: r.user_gitlab_id,
     let featured_fut = sqlx::query!(
       r#"SELECT packages.scope as "scope: ScopeName", packages.name as "name: PackageName"
       FROM packages
-      WHERE packages.when_featured IS NOT NULL AND NOT packages.is_archived
+      WHERE packages.when_featured IS NOT NULL AND packages.deleted_at IS NULL AND NOT packages.is_archived AND packages.visibility = 'public'
       ORDER BY packages.when_featured DESC
       LIMIT 10"#,
     )
@@ -1591,6 +2127,45 @@ gitlab_id: r.user_gitlab_id,
     Ok(tokio::try_join!(newest_fut, updated_fut, featured_fut)?)
   }
 
+  /// The public packages with the most downloads over the last week, and over
+  /// the last month, according to the download rollups.
+  #[instrument(name = "Database::most_downloaded_packages", skip(self), err)]
+  pub async fn most_downloaded_packages(
+    &self,
+  ) -> Result<(Vec<StatsDownloadedPackage>, Vec<StatsDownloadedPackage>)> {
+    let weekly_fut = sqlx::query!(
+      r#"SELECT packages.scope as "scope: ScopeName", packages.name as "name: PackageName", package_download_rollups.weekly_downloads as "downloads"
+      FROM package_download_rollups
+      JOIN packages ON packages.scope = package_download_rollups.scope AND packages.name = package_download_rollups.package
+      WHERE package_download_rollups.weekly_downloads > 0 AND packages.deleted_at IS NULL AND NOT packages.is_archived AND packages.visibility = 'public'
+      ORDER BY package_download_rollups.weekly_downloads DESC, packages.scope ASC, packages.name ASC
+      LIMIT 10"#,
+    )
+      .map(|r| StatsDownloadedPackage {
+        scope: r.scope,
+        name: r.name,
+        downloads: r.downloads,
+      })
+      .fetch_all(self.reader());
+
+    let monthly_fut = sqlx::query!(
+      r#"SELECT packages.scope as "scope: ScopeName", packages.name as "name: PackageName", package_download_rollups.monthly_downloads as "downloads"
+      FROM package_download_rollups
+      JOIN packages ON packages.scope = package_download_rollups.scope AND packages.name = package_download_rollups.package
+      WHERE package_download_rollups.monthly_downloads > 0 AND packages.deleted_at IS NULL AND NOT packages.is_archived AND packages.visibility = 'public'
+      ORDER BY package_download_rollups.monthly_downloads DESC, packages.scope ASC, packages.name ASC
+      LIMIT 10"#,
+    )
+      .map(|r| StatsDownloadedPackage {
+        scope: r.scope,
+        name: r.name,
+        downloads: r.downloads,
+      })
+      .fetch_all(self.reader());
+
+    Ok(tokio::try_join!(weekly_fut, monthly_fut)?)
+  }
+
   #[instrument(name = "Database::metrics", skip(self), err)]
   pub async fn metrics(&self) -> Result<ApiMetrics> {
     let packages = sqlx::query!(r#"
@@ -1603,9 +2178,9 @@ gitlab_id: r.user_gitlab_id,
       LEFT JOIN
         package_versions ON packages.name = package_versions.name AND packages.scope = package_versions.scope
       WHERE
-        package_versions.name IS NOT NULL
+        package_versions.name IS NOT NULL AND packages.deleted_at IS NULL AND package_versions.deleted_at IS NULL
     "#)
-      .fetch_one(&self.pool)
+      .fetch_one(self.reader())
       .await?;
 
     let users = sqlx::query!(r#"
@@ -1617,7 +2192,7 @@ gitlab_id: r.user_gitlab_id,
       FROM
         users;
       "#)
-      .fetch_one(&self.pool)
+      .fetch_one(self.reader())
       .await?;
 
     let package_versions =
@@ -1628,9 +2203,11 @@ gitlab_id: r.user_gitlab_id,
         COUNT(CASE WHEN created_at >= NOW() - INTERVAL '7 DAY' THEN 1 END) AS count_7d,
         COUNT(CASE WHEN created_at >= NOW() - INTERVAL '30 DAY' THEN 1 END) AS count_30d
       FROM
-        package_versions;
+        package_versions
+      WHERE
+        deleted_at IS NULL;
       "#)
-        .fetch_one(&self.pool)
+        .fetch_one(self.reader())
         .await?;
 
     Ok(ApiMetrics {
@@ -1681,10 +2258,63 @@ gitlab_id: r.user_gitlab_id,
       PackageVersionForMetadata,
       r#"SELECT version as "version: Version", is_yanked, created_at
       FROM package_versions
-      WHERE scope = $1 AND name = $2
+      WHERE scope = $1 AND name = $2 AND deleted_at IS NULL
+        AND NOT EXISTS (
+          SELECT 1 FROM package_version_quarantines q
+          WHERE q.scope = package_versions.scope AND q.name = package_versions.name AND q.version = package_versions.version
+        )
+      ORDER BY version DESC"#,
+      scope as _,
+      name as _,
+    )
+    .fetch_all(self.reader())
+    .await
+  }
+
+  /// Lists the versions of a package that were published at or before
+  /// `as_of`, with their yank state at that time. Yank state is reconstructed
+  /// from the audit log. Versions without any yank audit entries keep their
+  /// current state. Versions that were deleted since are not included. The
+  /// yank entries of a version are found through
+  /// `audit_logs_yank_package_version_idx`.
+  #[instrument(name = "Database::list_package_versions_as_of", skip(self), err)]
+  pub async fn list_package_versions_as_of(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+    as_of: DateTime<Utc>,
+  ) -> Result<Vec<PackageVersionForMetadata>> {
+    sqlx::query_as!(
+      PackageVersionForMetadata,
+      r#"SELECT version as "version: Version",
+        COALESCE(
+          (SELECT (audit_logs.meta->>'yank')::boolean
+           FROM audit_logs
+           WHERE audit_logs.action = 'yank_package_version'
+             AND audit_logs.meta->>'scope' = package_versions.scope
+             AND audit_logs.meta->>'name' = package_versions.name
+             AND audit_logs.meta->>'version' = package_versions.version
+             AND audit_logs.created_at <= $3
+           ORDER BY audit_logs.created_at DESC
+           LIMIT 1),
+          (SELECT NOT (audit_logs.meta->>'yank')::boolean
+           FROM audit_logs
+           WHERE audit_logs.action = 'yank_package_version'
+             AND audit_logs.meta->>'scope' = package_versions.scope
+             AND audit_logs.meta->>'name' = package_versions.name
+             AND audit_logs.meta->>'version' = package_versions.version
+             AND audit_logs.created_at > $3
+           ORDER BY audit_logs.created_at ASC
+           LIMIT 1),
+          is_yanked
+        ) as "is_yanked!",
+        created_at
+      FROM package_versions
+      WHERE scope = $1 AND name = $2 AND created_at <= $3 AND deleted_at IS NULL
       ORDER BY version DESC"#,
       scope as _,
       name as _,
+      as_of,
     )
     .fetch_all(&self.pool)
     .await
@@ -1700,74 +2330,89 @@ gitlab_id: r.user_gitlab_id,
     &self,
     scope: &ScopeName,
     name: &PackageName,
+    prereleases: bool,
     start: i64,
     limit: i64,
   ) -> Result<(usize, Vec<(PackageVersion, Option<UserPublic>)>)> {
-    let mut tx = self.pool.begin().await?;
-
-    let versions = query_concat!(
-      "SELECT ", PACKAGE_VERSION_SELECT_JOINED, ",
-      ", USER_PUBLIC_SELECT_JOINED, "
-      FROM package_versions
-      LEFT JOIN users ON package_versions.user_id = users.id
-      WHERE package_versions.scope = $1 AND package_versions.name = $2
-      ORDER BY package_versions.version DESC
-      OFFSET $3 LIMIT $4";
-      scope as _,
-      name as _,
+    list_package_versions_paginated(
+      self.reader(),
+      scope,
+      name,
+      prereleases,
       start,
       limit,
     )
-    .map(|r| {
-      let package_version = PackageVersion {
-        scope: r.package_version_scope,
-        name: r.package_version_name,
-        version: r.package_version_version,
-        user_id: r.package_version_user_id,
-        exports: r.package_version_exports,
-        is_yanked: r.package_version_is_yanked,
-        readme_path: r.package_version_readme_path,
-        uses_npm: r.package_version_uses_npm,
-        meta: r.package_version_meta,
-        updated_at: r.package_version_updated_at,
-        created_at: r.package_version_created_at,
-        rekor_log_id: r.package_version_rekor_log_id,
-        license: r.package_version_license,
-      };
-
-      let user = if r.package_version_user_id.is_some() {
-        let user = UserPublic {
-          id: r.user_id.unwrap(),
-          name: r.user_name.unwrap(),
-          avatar_url: r.user_avatar_url.unwrap(),
-          github_id: r.user_github_id,
-          gitlab_id: r.user_gitlab_id,
-          updated_at: r.user_updated_at.unwrap(),
-          created_at: r.user_created_at.unwrap(),
-        };
-
-        Some(user)
-      } else {
-        None
-      };
+    .await
+  }
 
-      (package_version, user)
-    })
-    .fetch_all(&mut *tx)
-    .await?;
+  /// Like [Database::list_package_versions_paginated], but served from the
+  /// metadata cache if there is one. Misses are read from the primary.
+  #[instrument(
+    name = "Database::list_package_versions_paginated_cached",
+    skip(self),
+    err
+  )]
+  pub async fn list_package_versions_paginated_cached(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+    prereleases: bool,
+    start: i64,
+    limit: i64,
+  ) -> Result<(usize, Vec<(PackageVersion, Option<UserPublic>)>)> {
+    let Some(cache) = &self.cache else {
+      return self
+        .list_package_versions_paginated(scope, name, prereleases, start, limit)
+        .await;
+    };
+    cache
+      .get_or_fetch(
+        MetadataCacheKind::Versions,
+        &package_generation_key(scope, name),
+        &format!("versions:{prereleases}:{start}:{limit}"),
+        list_package_versions_paginated(
+          &self.pool,
+          scope,
+          name,
+          prereleases,
+          start,
+          limit,
+        ),
+      )
+      .await
+  }
 
-    let total = sqlx::query!(
-      r#"SELECT COUNT(*) FROM package_versions WHERE scope = $1 AND name = $2"#,
+  /// Lists the most recently published unyanked versions of a scope, or of a
+  /// single package of the scope if `name` is set, newest first. Listing a
+  /// whole scope leaves out its private packages.
+  #[instrument(
+    name = "Database::list_recently_published_versions",
+    skip(self),
+    err
+  )]
+  pub async fn list_recently_published_versions(
+    &self,
+    scope: &ScopeName,
+    name: Option<&PackageName>,
+    limit: i64,
+  ) -> Result<Vec<PackageVersion>> {
+    query_concat_as!(
+      PackageVersion,
+      "SELECT ", PACKAGE_VERSION_SELECT, "
+      FROM package_versions
+      WHERE scope = $1 AND ($2::text IS NULL OR name = $2) AND is_yanked = false AND deleted_at IS NULL
+        AND ($2::text IS NOT NULL OR NOT EXISTS (
+          SELECT 1 FROM packages
+          WHERE packages.scope = package_versions.scope AND packages.name = package_versions.name AND packages.visibility = 'private'
+        ))
+      ORDER BY created_at DESC
+      LIMIT $3";
       scope as _,
       name as _,
+      limit,
     )
-    .map(|r| r.count.unwrap())
-    .fetch_one(&mut *tx)
-    .await?;
-
-    tx.commit().await?;
-
-    Ok((total as usize, versions))
+    .fetch_all(&self.pool)
+    .await
   }
 
   #[instrument(
@@ -1782,9 +2427,13 @@ gitlab_id: r.user_gitlab_id,
   ) -> Result<Vec<PackageVersionForResolution>> {
     sqlx::query_as!(
       PackageVersionForResolution,
-      r#"SELECT package_versions.version as "version: Version", package_versions.exports as "exports: ExportsMap"
+      r#"SELECT package_versions.version as "version: Version", package_versions.exports as "exports: ExportsMap", package_versions.is_yanked
       FROM package_versions
-      WHERE package_versions.scope = $1 AND package_versions.name = $2
+      WHERE package_versions.scope = $1 AND package_versions.name = $2 AND package_versions.deleted_at IS NULL
+        AND NOT EXISTS (
+          SELECT 1 FROM package_version_quarantines q
+          WHERE q.scope = package_versions.scope AND q.name = package_versions.name AND q.version = package_versions.version
+        )
       ORDER BY package_versions.version DESC"#,
       scope as _,
       name as _,
@@ -1805,11 +2454,11 @@ gitlab_id: r.user_gitlab_id,
   ) -> Result<Vec<PackageVersionForNpmVersionManifest>> {
     sqlx::query_as!(
       PackageVersionForNpmVersionManifest,
-      r#"SELECT package_versions.version as "version: Version", package_versions.is_yanked as "is_yanked", package_versions.created_at as "created_at",
-      npm_tarballs.revision as "npm_tarball_revision", npm_tarballs.sha1 as "npm_tarball_sha1", npm_tarballs.sha512 as "npm_tarball_sha512"
+      r#"SELECT package_versions.version as "version: Version", package_versions.is_yanked as "is_yanked", package_versions.yank_reason, package_versions.created_at as "created_at", package_versions.meta as "meta: PackageVersionMeta",
+      npm_tarballs.revision as "npm_tarball_revision", npm_tarballs.sha1 as "npm_tarball_sha1", npm_tarballs.sha512 as "npm_tarball_sha512", npm_tarballs.rekor_log_id as "npm_tarball_rekor_log_id"
       FROM package_versions
       INNER JOIN LATERAL (
-        SELECT revision, sha1, sha512
+        SELECT revision, sha1, sha512, rekor_log_id
         FROM npm_tarballs
         WHERE npm_tarballs.scope = package_versions.scope
         AND npm_tarballs.name = package_versions.name
@@ -1817,7 +2466,11 @@ gitlab_id: r.user_gitlab_id,
         ORDER BY revision DESC
         LIMIT 1
       ) npm_tarballs ON true
-      WHERE package_versions.scope = $1 AND package_versions.name = $2
+      WHERE package_versions.scope = $1 AND package_versions.name = $2 AND package_versions.deleted_at IS NULL
+        AND NOT EXISTS (
+          SELECT 1 FROM package_version_quarantines q
+          WHERE q.scope = package_versions.scope AND q.name = package_versions.name AND q.version = package_versions.version
+        )
       ORDER BY package_versions.version DESC"#,
       scope as _,
       name as _,
@@ -1840,7 +2493,11 @@ gitlab_id: r.user_gitlab_id,
       PackageVersion,
       "SELECT ", PACKAGE_VERSION_SELECT, "
       FROM package_versions
-      WHERE scope = $1 AND name = $2 AND version NOT LIKE '%-%' AND is_yanked = false
+      WHERE scope = $1 AND name = $2 AND version NOT LIKE '%-%' AND is_yanked = false AND deleted_at IS NULL
+        AND NOT EXISTS (
+          SELECT 1 FROM package_version_quarantines q
+          WHERE q.scope = package_versions.scope AND q.name = package_versions.name AND q.version = package_versions.version
+        )
       ORDER BY version DESC
       LIMIT 1";
       scope as _,
@@ -1870,7 +2527,11 @@ gitlab_id: r.user_gitlab_id,
       PackageVersion,
       "SELECT ", PACKAGE_VERSION_SELECT, "
       FROM package_versions
-      WHERE scope = $1 AND name = $2 AND is_yanked = false
+      WHERE scope = $1 AND name = $2 AND is_yanked = false AND deleted_at IS NULL
+        AND NOT EXISTS (
+          SELECT 1 FROM package_version_quarantines q
+          WHERE q.scope = package_versions.scope AND q.name = package_versions.name AND q.version = package_versions.version
+        )
       ORDER BY (version NOT LIKE '%-%') DESC, version DESC
       LIMIT 1";
       scope as _,
@@ -1895,7 +2556,11 @@ gitlab_id: r.user_gitlab_id,
       "SELECT ", PACKAGE_VERSION_SELECT, ",
       ", NEWER_VERSIONS_COUNT_SUBQUERY, "
       FROM package_versions
-      WHERE scope = $1 AND name = $2 AND version NOT LIKE '%-%' AND is_yanked = false
+      WHERE scope = $1 AND name = $2 AND version NOT LIKE '%-%' AND is_yanked = false AND deleted_at IS NULL
+        AND NOT EXISTS (
+          SELECT 1 FROM package_version_quarantines q
+          WHERE q.scope = package_versions.scope AND q.name = package_versions.name AND q.version = package_versions.version
+        )
       ORDER BY version DESC
       LIMIT 1";
       scope as _,
@@ -1920,7 +2585,7 @@ gitlab_id: r.user_gitlab_id,
       r#"
       SELECT version as "version: Version"
       FROM package_versions
-      WHERE scope = $1 AND name = $2 AND version NOT LIKE '%-%' AND is_yanked = false
+      WHERE scope = $1 AND name = $2 AND version NOT LIKE '%-%' AND is_yanked = false AND deleted_at IS NULL
       ORDER BY version DESC
       LIMIT $3
       "#,
@@ -1944,7 +2609,7 @@ gitlab_id: r.user_gitlab_id,
       PackageVersion,
       "SELECT ", PACKAGE_VERSION_SELECT, "
       FROM package_versions
-      WHERE scope = $1 AND name = $2 AND version = $3";
+      WHERE scope = $1 AND name = $2 AND version = $3 AND deleted_at IS NULL";
       scope as _,
       name as _,
       version as _
@@ -1969,7 +2634,7 @@ gitlab_id: r.user_gitlab_id,
       "SELECT ", PACKAGE_VERSION_SELECT, ",
       ", NEWER_VERSIONS_COUNT_SUBQUERY, "
       FROM package_versions
-      WHERE scope = $1 AND name = $2 AND version = $3";
+      WHERE scope = $1 AND name = $2 AND version = $3 AND deleted_at IS NULL";
       scope as _,
       name as _,
       version as _
@@ -1985,91 +2650,71 @@ gitlab_id: r.user_gitlab_id,
     fields(package_version.scope = %new_package_version.scope, package_version.name = %new_package_version.name, package_version.version = %new_package_version.version, package_version.exports = ?new_package_version.exports, package_files = new_package_files.len()
     )
   )]
+  #[allow(clippy::too_many_arguments)]
   pub async fn create_package_version_and_npm_tarball_and_finalize_publishing_task(
     &self,
     publishing_task_id: Uuid,
     new_package_version: NewPackageVersion<'_>,
     new_package_files: &[NewPackageFile<'_>],
     new_package_version_dependencies: &[NewPackageVersionDependency<'_>],
+    new_package_version_deprecations: &[NewPackageVersionDeprecation<'_>],
+    new_package_version_symbols: &[NewPackageVersionSymbol<'_>],
+    new_package_version_doc_links: &[NewPackageVersionDocLink<'_>],
+    new_package_version_doc_artifacts: &[NewPackageVersionDocArtifact<'_>],
     new_npm_tarball: NewNpmTarball<'_>,
+    malware_scan_findings: Option<&str>,
   ) -> Result<PublishingTask> {
-    let mut tx = self.pool.begin().await?;
-
-    sqlx::query!(
-      r#"INSERT INTO package_versions (scope, name, version, user_id, readme_path, exports, uses_npm, meta, license)
-      VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"#,
-      new_package_version.scope as _,
-      new_package_version.name as _,
-      new_package_version.version as _,
-      new_package_version.user_id as _,
-      new_package_version.readme_path as _,
-      new_package_version.exports as _,
-      new_package_version.uses_npm as _,
-      new_package_version.meta as _,
-      new_package_version.license as _,
-    )
-      .execute(&mut *tx)
+    let mut tasks = self
+      .create_package_versions_and_finalize_publishing_tasks(&[
+        NewPublishedVersion {
+          publishing_task_id,
+          package_version: new_package_version,
+          files: new_package_files,
+          dependencies: new_package_version_dependencies,
+          deprecations: new_package_version_deprecations,
+          symbols: new_package_version_symbols,
+          doc_links: new_package_version_doc_links,
+          doc_artifacts: new_package_version_doc_artifacts,
+          npm_tarball: new_npm_tarball,
+          malware_scan_findings,
+        },
+      ])
       .await?;
+    Ok(tasks.remove(0))
+  }
 
-    for new_package_file in new_package_files {
-      sqlx::query!(
-        r#"INSERT INTO package_files (scope, name, version, path, size, checksum)
-        VALUES ($1, $2, $3, $4, $5, $6)"#,
-        new_package_file.scope as _,
-        new_package_file.name as _,
-        new_package_file.version as _,
-        new_package_file.path as _,
-        new_package_file.size,
-        new_package_file.checksum,
-      )
-        .execute(&mut *tx)
-        .await?;
-    }
+  /// Creates the package versions of processed publishing tasks, and marks the
+  /// tasks as processed. Either all versions are created or none is, which is
+  /// what makes workspace publishes atomic.
+  #[instrument(
+    name = "Database::create_package_versions_and_finalize_publishing_tasks",
+    skip(self, versions),
+    err,
+    fields(versions = versions.len())
+  )]
+  pub async fn create_package_versions_and_finalize_publishing_tasks(
+    &self,
+    versions: &[NewPublishedVersion<'_>],
+  ) -> Result<Vec<PublishingTask>> {
+    let mut tx = self.pool.begin().await?;
 
-    for new_package_version_dependency in new_package_version_dependencies {
-      sqlx::query!(
-        r#"INSERT INTO package_version_dependencies (package_scope, package_name, package_version, dependency_kind, dependency_name, dependency_constraint, dependency_path)
-        VALUES ($1, $2, $3, $4, $5, $6, $7)"#,
-        new_package_version_dependency.package_scope as _,
-        new_package_version_dependency.package_name as _,
-        new_package_version_dependency.package_version as _,
-        new_package_version_dependency.dependency_kind as _,
-        new_package_version_dependency.dependency_name as _,
-        new_package_version_dependency.dependency_constraint as _,
-        new_package_version_dependency.dependency_path as _,
-      )
-        .execute(&mut *tx)
-        .await?;
+    let mut tasks = Vec::with_capacity(versions.len());
+    for version in versions {
+      tasks.push(insert_published_version(&mut tx, version).await?);
     }
 
-    sqlx::query!(
-      r#"INSERT INTO npm_tarballs (scope, name, version, revision, sha1, sha512, size)
-      VALUES ($1, $2, $3, $4, $5, $6, $7)"#,
-      new_npm_tarball.scope as _,
-      new_npm_tarball.name as _,
-      new_npm_tarball.version as _,
-      new_npm_tarball.revision,
-      new_npm_tarball.sha1,
-      new_npm_tarball.sha512,
-      new_npm_tarball.size,
-    )
-      .execute(&mut *tx)
-      .await?;
-
-    let task = query_concat_as!(
-      PublishingTask,
-      "UPDATE publishing_tasks
-      SET status = 'processed'
-      WHERE id = $1 AND status = 'processing'
-      RETURNING ", PUBLISHING_TASK_SELECT;
-      publishing_task_id,
-    )
-    .fetch_one(&mut *tx)
-    .await?;
-
     tx.commit().await?;
 
-    Ok(task)
+    for version in versions {
+      self
+        .package_changed(
+          version.package_version.scope,
+          version.package_version.name,
+        )
+        .await;
+    }
+
+    Ok(tasks)
   }
 
   #[cfg(test)]
@@ -2110,6 +2755,7 @@ gitlab_id: r.user_gitlab_id,
     name: &PackageName,
     version: &Version,
     yank: bool,
+    reason: Option<&str>,
   ) -> Result<PackageVersion> {
     let mut tx = self.pool.begin().await?;
 
@@ -2123,43 +2769,169 @@ gitlab_id: r.user_gitlab_id,
         "name": name,
         "version": version,
         "yank": yank,
+        "reason": reason,
       }),
     )
     .await?;
 
+    // Yanking an already yanked version only updates the reason, the version
+    // keeps the time it was first yanked at.
     let package_version = query_concat_as!(
       PackageVersion,
       "UPDATE package_versions
-      SET is_yanked = $4
-      WHERE scope = $1 AND name = $2 AND version = $3
+      SET is_yanked = $4,
+        yanked_at = CASE WHEN $4 THEN COALESCE(yanked_at, now()) END,
+        yank_reason = CASE WHEN $4 THEN $5 END
+      WHERE scope = $1 AND name = $2 AND version = $3 AND deleted_at IS NULL
       RETURNING ", PACKAGE_VERSION_SELECT;
       scope as _,
       name as _,
       version as _,
-      yank
+      yank,
+      reason
     )
     .fetch_one(&mut *tx)
     .await?;
 
     tx.commit().await?;
 
+    self.package_changed(scope, name).await;
+
     Ok(package_version)
   }
 
-  #[instrument(name = "Database::delete_package_version", skip(self), err)]
-  pub async fn delete_package_version(
+  #[instrument(name = "Database::list_package_dist_tags", skip(self), err)]
+  pub async fn list_package_dist_tags(
     &self,
-    staff_id: &Uuid,
     scope: &ScopeName,
     name: &PackageName,
+  ) -> Result<Vec<PackageDistTag>> {
+    query_concat_as!(
+      PackageDistTag,
+      "SELECT ", PACKAGE_DIST_TAG_SELECT, " FROM package_dist_tags
+      WHERE scope = $1 AND name = $2 AND EXISTS (
+        SELECT 1 FROM package_versions
+        WHERE package_versions.scope = package_dist_tags.scope
+          AND package_versions.name = package_dist_tags.name
+          AND package_versions.version = package_dist_tags.version
+          AND package_versions.deleted_at IS NULL
+      )
+      ORDER BY tag ASC";
+      scope as _,
+      name as _,
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  /// Points `tag` at `version`, creating the tag if it does not exist yet.
+  #[instrument(name = "Database::set_package_dist_tag", skip(self), err)]
+  pub async fn set_package_dist_tag(
+    &self,
+    actor_id: &Uuid,
+    is_sudo: bool,
+    scope: &ScopeName,
+    name: &PackageName,
+    tag: &str,
     version: &Version,
-  ) -> Result<()> {
+  ) -> Result<PackageDistTag> {
     let mut tx = self.pool.begin().await?;
 
-    audit_log(
-      &mut tx,
-      staff_id,
-      true,
+    let dist_tag = query_concat_as!(
+      PackageDistTag,
+      "INSERT INTO package_dist_tags (scope, name, tag, version)
+      VALUES ($1, $2, $3, $4)
+      ON CONFLICT (scope, name, tag) DO UPDATE SET version = EXCLUDED.version
+      RETURNING ", PACKAGE_DIST_TAG_SELECT;
+      scope as _,
+      name as _,
+      tag,
+      version as _,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "set_package_dist_tag",
+      json!({
+        "scope": scope,
+        "name": name,
+        "tag": tag,
+        "version": version,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(dist_tag)
+  }
+
+  #[instrument(name = "Database::delete_package_dist_tag", skip(self), err)]
+  pub async fn delete_package_dist_tag(
+    &self,
+    actor_id: &Uuid,
+    is_sudo: bool,
+    scope: &ScopeName,
+    name: &PackageName,
+    tag: &str,
+  ) -> Result<Option<PackageDistTag>> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some(dist_tag) = query_concat_as!(
+      PackageDistTag,
+      "DELETE FROM package_dist_tags
+      WHERE scope = $1 AND name = $2 AND tag = $3
+      RETURNING ", PACKAGE_DIST_TAG_SELECT;
+      scope as _,
+      name as _,
+      tag,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "delete_package_dist_tag",
+      json!({
+        "scope": scope,
+        "name": name,
+        "tag": tag,
+        "version": dist_tag.version,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(dist_tag))
+  }
+
+  /// Deletes a package version by turning it into a tombstone, which all
+  /// other queries leave out. It can be restored until it is purged. Returns
+  /// whether the version existed.
+  #[instrument(name = "Database::delete_package_version", skip(self), err)]
+  pub async fn delete_package_version(
+    &self,
+    staff_id: &Uuid,
+    scope: &ScopeName,
+    name: &PackageName,
+    version: &Version,
+  ) -> Result<bool> {
+    let mut tx = self.pool.begin().await?;
+
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
       "delete_package_version",
       json!({
       "scope": scope,
@@ -2169,19 +2941,74 @@ gitlab_id: r.user_gitlab_id,
     )
     .await?;
 
-    sqlx::query_as!(
+    let res = sqlx::query!(
+      r#"UPDATE package_versions SET deleted_at = now()
+      WHERE scope = $1 AND name = $2 AND version = $3 AND deleted_at IS NULL"#,
+      scope as _,
+      name as _,
+      version as _
+    )
+    .execute(&mut *tx)
+    .await?;
+    if res.rows_affected() == 0 {
+      return Ok(false);
+    }
+
+    tx.commit().await?;
+
+    self.package_changed(scope, name).await;
+
+    Ok(true)
+  }
+
+  /// Restores a deleted package version. The package must not be deleted.
+  #[instrument(name = "Database::restore_package_version", skip(self), err)]
+  pub async fn restore_package_version(
+    &self,
+    staff_id: &Uuid,
+    scope: &ScopeName,
+    name: &PackageName,
+    version: &Version,
+  ) -> Result<Option<PackageVersion>> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some(package_version) = query_concat_as!(
       PackageVersion,
-      r#"DELETE FROM package_versions WHERE scope = $1 AND name = $2 AND version = $3"#,
+      "UPDATE package_versions SET deleted_at = NULL
+      WHERE scope = $1 AND name = $2 AND version = $3 AND deleted_at IS NOT NULL
+        AND EXISTS (
+          SELECT 1 FROM packages
+          WHERE packages.scope = $1 AND packages.name = $2 AND packages.deleted_at IS NULL
+        )
+      RETURNING ", PACKAGE_VERSION_SELECT;
       scope as _,
       name as _,
       version as _
     )
-      .execute(&mut *tx)
-      .await?;
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "restore_package_version",
+      json!({
+        "scope": scope,
+        "name": name,
+        "version": version,
+      }),
+    )
+    .await?;
 
     tx.commit().await?;
 
-    Ok(())
+    self.package_changed(scope, name).await;
+
+    Ok(Some(package_version))
   }
 
   #[instrument(name = "Database::list_package_files", skip(self), err)]
@@ -2204,6 +3031,83 @@ gitlab_id: r.user_gitlab_id,
     .await
   }
 
+  #[instrument(
+    name = "Database::list_package_version_doc_artifacts",
+    skip(self),
+    err
+  )]
+  pub async fn list_package_version_doc_artifacts(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+    version: &Version,
+  ) -> Result<Vec<PackageVersionDocArtifact>> {
+    query_concat_as!(
+      PackageVersionDocArtifact,
+      "SELECT ", PACKAGE_VERSION_DOC_ARTIFACT_SELECT, "
+      FROM package_version_doc_artifacts
+      WHERE scope = $1 AND name = $2 AND version = $3
+      ORDER BY kind";
+      scope as _,
+      name as _,
+      version as _
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  /// Record the digest of a doc artifact that was written again, like doc
+  /// nodes that are no longer stored as a delta.
+  #[instrument(
+    name = "Database::upsert_package_version_doc_artifact",
+    skip(self),
+    err
+  )]
+  pub async fn upsert_package_version_doc_artifact(
+    &self,
+    new_doc_artifact: NewPackageVersionDocArtifact<'_>,
+  ) -> Result<()> {
+    sqlx::query!(
+      r#"INSERT INTO package_version_doc_artifacts (scope, name, version, kind, checksum, size)
+      VALUES ($1, $2, $3, $4, $5, $6)
+      ON CONFLICT (scope, name, version, kind)
+      DO UPDATE SET checksum = EXCLUDED.checksum, size = EXCLUDED.size"#,
+      new_doc_artifact.scope as _,
+      new_doc_artifact.name as _,
+      new_doc_artifact.version as _,
+      new_doc_artifact.kind as _,
+      new_doc_artifact.checksum,
+      new_doc_artifact.size,
+    )
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  #[instrument(
+    name = "Database::list_npm_tarballs_for_version",
+    skip(self),
+    err
+  )]
+  pub async fn list_npm_tarballs_for_version(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+    version: &Version,
+  ) -> Result<Vec<NpmTarball>> {
+    query_concat_as!(
+      NpmTarball,
+      "SELECT ", NPM_TARBALL_SELECT, " FROM npm_tarballs
+      WHERE scope = $1 AND name = $2 AND version = $3
+      ORDER BY revision DESC";
+      scope as _,
+      name as _,
+      version as _,
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
   #[cfg(test)]
   #[instrument(name = "Database::create_package_file_for_test", skip(
     self,
@@ -2241,8 +3145,8 @@ gitlab_id: r.user_gitlab_id,
   ) -> Result<NpmTarball> {
     query_concat_as!(
       NpmTarball,
-      "INSERT INTO npm_tarballs (scope, name, version, revision, sha1, sha512, size)
-      VALUES ($1, $2, $3, $4, $5, $6, $7)
+      "INSERT INTO npm_tarballs (scope, name, version, revision, sha1, sha512, sha256, size)
+      VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
       RETURNING ", NPM_TARBALL_SELECT;
       new_npm_tarball.scope as _,
       new_npm_tarball.name as _,
@@ -2250,12 +3154,42 @@ gitlab_id: r.user_gitlab_id,
       new_npm_tarball.revision,
       new_npm_tarball.sha1,
       new_npm_tarball.sha512,
+      new_npm_tarball.sha256,
       new_npm_tarball.size
     )
       .fetch_one(&self.pool)
       .await
   }
 
+  #[instrument(
+    name = "Database::set_npm_tarball_rekor_log_id",
+    skip(self),
+    err
+  )]
+  pub async fn set_npm_tarball_rekor_log_id(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+    version: &Version,
+    revision: i32,
+    rekor_log_id: &str,
+  ) -> Result<()> {
+    sqlx::query!(
+      r#"UPDATE npm_tarballs
+      SET rekor_log_id = $1
+      WHERE scope = $2 AND name = $3 AND version = $4 AND revision = $5"#,
+      rekor_log_id,
+      scope as _,
+      name as _,
+      version as _,
+      revision,
+    )
+    .execute(&self.pool)
+    .await?;
+
+    Ok(())
+  }
+
   #[instrument(name = "Database::get_scope_member", skip(self), err)]
   pub async fn get_scope_member(
     &self,
@@ -2309,1463 +3243,7534 @@ gitlab_id: r.user_gitlab_id,
     .await
   }
 
-  #[instrument(name = "Database::get_member_scopes_by_user", skip(self), err)]
-  pub async fn get_member_scopes_by_user(
+  #[instrument(name = "Database::list_scope_admins", skip(self), err)]
+  pub async fn list_scope_admins(
     &self,
-    id: &Uuid,
-  ) -> Result<Vec<Scope>> {
-    sqlx::query_as!(
-      Scope,
-      r#"SELECT
-      scopes.scope as "scope: ScopeName",
-      scopes.description as "description: ScopeDescription",
-      scopes.creator,
-      scopes.package_limit,
-      scopes.new_package_per_week_limit,
-      scopes.publish_attempts_per_week_limit,
-      scopes.verify_oidc_actor,
-      scopes.require_publishing_from_ci,
-      scopes.updated_at,
-      scopes.created_at
-      FROM scopes
-      LEFT JOIN scope_members ON scope_members.scope = scopes.scope
-      WHERE user_id = $1"#,
-      id
+    scope: &ScopeName,
+  ) -> Result<Vec<User>> {
+    query_concat_as!(
+      User,
+      "SELECT ", USER_SELECT_FULL, " FROM users WHERE id IN (
+        SELECT user_id FROM scope_members WHERE scope = $1 AND is_admin = true
+      ) ORDER BY name ASC";
+      scope as _
     )
     .fetch_all(&self.pool)
     .await
   }
 
-  #[instrument(name = "Database::add_scope_invite", skip(self), err)]
-  pub async fn add_scope_invite(
+  #[instrument(
+    name = "Database::is_scope_member_cooldown_lifted",
+    skip(self),
+    err
+  )]
+  pub async fn is_scope_member_cooldown_lifted(
+    &self,
+    scope: &ScopeName,
+    user_id: Uuid,
+  ) -> Result<bool> {
+    let row = sqlx::query!(
+      r#"SELECT lifted_by FROM scope_member_cooldown_overrides
+      WHERE scope = $1 AND user_id = $2"#,
+      scope as _,
+      user_id,
+    )
+    .fetch_optional(&self.pool)
+    .await?;
+    Ok(row.is_some())
+  }
+
+  #[instrument(name = "Database::lift_scope_member_cooldown", skip(self), err)]
+  pub async fn lift_scope_member_cooldown(
     &self,
     actor_id: &Uuid,
     is_sudo: bool,
-    new_scope_invite: NewScopeInvite<'_>,
-  ) -> Result<ScopeInvite> {
+    scope: &ScopeName,
+    user_id: Uuid,
+  ) -> Result<()> {
     let mut tx = self.pool.begin().await?;
 
     audit_log(
       &mut tx,
       actor_id,
       is_sudo,
-      "add_scope_invite",
+      "lift_scope_member_cooldown",
       json!({
-          "scope": new_scope_invite.scope,
-          "target_user_id": new_scope_invite.target_user_id,
+        "scope": scope,
+        "target_user_id": user_id,
       }),
     )
     .await?;
 
-    let scope_invite = query_concat_as!(
-      ScopeInvite,
-      "INSERT INTO scope_invites (scope, target_user_id, requesting_user_id)
+    sqlx::query!(
+      r#"INSERT INTO scope_member_cooldown_overrides (scope, user_id, lifted_by)
       VALUES ($1, $2, $3)
-      RETURNING ", SCOPE_INVITE_SELECT;
-      new_scope_invite.scope as _,
-      new_scope_invite.target_user_id,
-      new_scope_invite.requesting_user_id,
-    )
-    .fetch_one(&mut *tx)
+      ON CONFLICT (scope, user_id) DO NOTHING"#,
+      scope as _,
+      user_id,
+      actor_id,
+    )
+    .execute(&mut *tx)
     .await?;
 
     tx.commit().await?;
 
-    Ok(scope_invite)
+    Ok(())
   }
 
-  #[cfg(test)]
-  #[instrument(name = "Database::add_user_to_scope", skip(
-    self,
-    new_scope_member
-  ), err, fields(scope_member.scope = %new_scope_member.scope, scope_member.user_id = %new_scope_member.user_id, scope_member.is_admin = new_scope_member.is_admin
-  ))]
-  pub async fn add_user_to_scope(
+  #[instrument(name = "Database::list_scope_teams", skip(self), err)]
+  pub async fn list_scope_teams(
     &self,
-    new_scope_member: NewScopeMember<'_>,
-  ) -> Result<ScopeMember> {
+    scope: &ScopeName,
+  ) -> Result<Vec<ScopeTeam>> {
     query_concat_as!(
-      ScopeMember,
-      "INSERT INTO scope_members (scope, user_id, is_admin)
-      VALUES ($1, $2, $3)
-      RETURNING ", SCOPE_MEMBER_SELECT;
-      new_scope_member.scope as _,
-      new_scope_member.user_id,
-      new_scope_member.is_admin,
+      ScopeTeam,
+      "SELECT ", SCOPE_TEAM_SELECT, " FROM scope_teams
+      WHERE scope = $1
+      ORDER BY name ASC";
+      scope as _,
     )
-    .fetch_one(&self.pool)
+    .fetch_all(&self.pool)
     .await
   }
 
-  #[instrument(name = "Database::get_scope_invites_by_user", skip(self), err)]
-  pub async fn get_scope_invites_by_user(
-    &self,
-    target_user: &Uuid,
-  ) -> Result<Vec<(ScopeInvite, UserPublic, UserPublic)>> {
-    query_concat!(
-      "SELECT ", SCOPE_INVITE_SELECT_JOINED, "
-      FROM scope_invites
-      LEFT JOIN users AS target_user ON scope_invites.target_user_id = target_user.id
-      LEFT JOIN users AS requesting_user ON scope_invites.requesting_user_id = requesting_user.id
-      WHERE target_user_id = $1";
-      target_user
-    )
-      .map(|r| {
-        let scope_invite = ScopeInvite {
-          target_user_id: r.scope_invite_target_user_id,
-          requesting_user_id: r.scope_invite_requesting_user_id,
-          scope: r.scope_invite_scope,
-          created_at: r.scope_invite_created_at,
-          updated_at: r.scope_invite_updated_at,
-        };
-        let target_user = UserPublic {
-          id: r.target_user_id,
-          name: r.target_user_name,
-          avatar_url: r.target_user_avatar_url,
-          github_id: r.target_user_github_id,
-          gitlab_id: r.target_user_gitlab_id,
-          updated_at: r.target_user_updated_at,
-          created_at: r.target_user_created_at,
-        };
-        let requesting_user = UserPublic {
-          id: r.requesting_user_id,
-          name: r.requesting_user_name,
-          avatar_url: r.requesting_user_avatar_url,
-          github_id: r.requesting_user_github_id,
-          gitlab_id: r.requesting_user_gitlab_id,
-          updated_at: r.requesting_user_updated_at,
-          created_at: r.requesting_user_created_at,
-        };
-        (scope_invite, target_user, requesting_user)
-      })
-      .fetch_all(&self.pool)
-      .await
-  }
-
-  #[instrument(name = "Database::get_scope_invites_by_scope", skip(self), err)]
-  pub async fn get_scope_invites_by_scope(
+  #[instrument(name = "Database::get_scope_team", skip(self), err)]
+  pub async fn get_scope_team(
     &self,
     scope: &ScopeName,
-  ) -> Result<Vec<(ScopeInvite, UserPublic, UserPublic)>> {
-    query_concat!(
-      "SELECT ", SCOPE_INVITE_SELECT_JOINED, "
-      FROM scope_invites
-      LEFT JOIN users AS target_user ON scope_invites.target_user_id = target_user.id
-      LEFT JOIN users AS requesting_user ON scope_invites.requesting_user_id = requesting_user.id
-      WHERE scope = $1";
-      scope as _
+    name: &str,
+  ) -> Result<Option<ScopeTeam>> {
+    query_concat_as!(
+      ScopeTeam,
+      "SELECT ", SCOPE_TEAM_SELECT, " FROM scope_teams
+      WHERE scope = $1 AND name = $2";
+      scope as _,
+      name,
     )
-      .map(|r| {
-        let scope_invite = ScopeInvite {
-          target_user_id: r.scope_invite_target_user_id,
-          requesting_user_id: r.scope_invite_requesting_user_id,
-          scope: r.scope_invite_scope,
-          created_at: r.scope_invite_created_at,
-          updated_at: r.scope_invite_updated_at,
-        };
-        let target_user = UserPublic {
-          id: r.target_user_id,
-          name: r.target_user_name,
-          avatar_url: r.target_user_avatar_url,
-          github_id: r.target_user_github_id,
-          gitlab_id: r.target_user_gitlab_id,
-          updated_at: r.target_user_updated_at,
-          created_at: r.target_user_created_at,
-        };
-        let requesting_user = UserPublic {
-          id: r.requesting_user_id,
-          name: r.requesting_user_name,
-          avatar_url: r.requesting_user_avatar_url,
-          github_id: r.requesting_user_github_id,
-          gitlab_id: r.requesting_user_gitlab_id,
-          updated_at: r.requesting_user_updated_at,
-          created_at: r.requesting_user_created_at,
-        };
-        (scope_invite, target_user, requesting_user)
-      })
-      .fetch_all(&self.pool)
-      .await
+    .fetch_optional(&self.pool)
+    .await
   }
 
-  #[instrument(name = "Database::accept_scope_invite", skip(self), err)]
-  pub async fn accept_scope_invite(
+  #[instrument(name = "Database::create_scope_team", skip(self), err)]
+  pub async fn create_scope_team(
     &self,
-    target_user_id: &Uuid,
+    actor_id: &Uuid,
+    is_sudo: bool,
     scope: &ScopeName,
-  ) -> Result<Option<ScopeMember>> {
+    name: &str,
+    description: &str,
+  ) -> Result<ScopeTeam> {
     let mut tx = self.pool.begin().await?;
 
-    let res = sqlx::query!(
-      r#"DELETE FROM scope_invites WHERE target_user_id = $1 AND scope = $2"#,
-      target_user_id,
-      scope as _,
-    )
-    .execute(&mut *tx)
-    .await?;
-    if res.rows_affected() != 1 {
-      return Ok(None);
-    }
-
-    let member = query_concat_as!(
-      ScopeMember,
-      "INSERT INTO scope_members (scope, user_id) VALUES ($1, $2)
-      RETURNING ", SCOPE_MEMBER_SELECT;
+    let team = query_concat_as!(
+      ScopeTeam,
+      "INSERT INTO scope_teams (scope, name, description)
+      VALUES ($1, $2, $3)
+      RETURNING ", SCOPE_TEAM_SELECT;
       scope as _,
-      target_user_id,
+      name,
+      description,
     )
     .fetch_one(&mut *tx)
     .await?;
 
-    tx.commit().await?;
-
-    Ok(Some(member))
-  }
-
-  #[instrument(name = "Database::delete_scope_invite", skip(self), err)]
-  pub async fn delete_scope_invite(
-    &self,
-    actor_id: &Uuid,
-    is_sudo: bool,
-    target_user_id: &Uuid,
-    scope: &ScopeName,
-  ) -> Result<()> {
-    let mut tx = self.pool.begin().await?;
-
     audit_log(
       &mut tx,
       actor_id,
       is_sudo,
-      "delete_scope_invite",
+      "create_scope_team",
       json!({
-          "scope": scope,
-          "target_user_id": target_user_id,
+        "scope": scope,
+        "team": name,
       }),
     )
     .await?;
 
-    sqlx::query!(
-      r#"DELETE FROM scope_invites WHERE target_user_id = $1 AND scope = $2"#,
-      target_user_id,
-      scope as _,
-    )
-    .execute(&mut *tx)
-    .await?;
-
     tx.commit().await?;
 
-    Ok(())
+    Ok(team)
   }
 
-  #[instrument(name = "Database::delete_package", skip(self), err)]
-  pub async fn delete_package(
+  #[instrument(name = "Database::update_scope_team", skip(self), err)]
+  pub async fn update_scope_team(
     &self,
     actor_id: &Uuid,
     is_sudo: bool,
     scope: &ScopeName,
-    name: &PackageName,
-  ) -> Result<bool> {
+    name: &str,
+    description: &str,
+  ) -> Result<Option<ScopeTeam>> {
     let mut tx = self.pool.begin().await?;
 
+    let Some(team) = query_concat_as!(
+      ScopeTeam,
+      "UPDATE scope_teams SET description = $3
+      WHERE scope = $1 AND name = $2
+      RETURNING ", SCOPE_TEAM_SELECT;
+      scope as _,
+      name,
+      description,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
     audit_log(
       &mut tx,
       actor_id,
       is_sudo,
-      "delete_package",
+      "update_scope_team",
       json!({
-          "scope": scope,
+        "scope": scope,
+        "team": name,
+        "description": description,
       }),
     )
     .await?;
 
-    let status = sqlx::query!(
-      r#"SELECT count(*) FROM publishing_tasks WHERE package_scope = $1 AND package_name = $2 AND status != 'failure'"#,
-      scope as _,
-      name as _,
-    )
-      .fetch_one(&mut *tx)
-      .await?;
-    if status.count.unwrap() > 0 {
-      return Ok(false);
-    }
-
-    let res = sqlx::query!(
-      r#"DELETE FROM packages WHERE scope = $1 AND name = $2"#,
-      scope as _,
-      name as _,
-    )
-    .execute(&mut *tx)
-    .await;
+    tx.commit().await?;
 
-    match res {
-      Ok(res) => {
-        let success = res.rows_affected() > 0;
-        if success {
-          tx.commit().await?;
-        }
-        Ok(success)
-      }
-      Err(err) => {
-        if let Some(dberr) = err.as_database_error()
-          && dberr.is_foreign_key_violation()
-        {
-          return Ok(false);
-        }
-        Err(err)
-      }
-    }
+    Ok(Some(team))
   }
 
-  #[instrument(name = "Database::delete_scope", skip(self), err)]
-  pub async fn delete_scope(
+  /// Deletes a team, along with its memberships and package permissions.
+  #[instrument(name = "Database::delete_scope_team", skip(self), err)]
+  pub async fn delete_scope_team(
     &self,
     actor_id: &Uuid,
     is_sudo: bool,
     scope: &ScopeName,
-  ) -> Result<bool> {
+    name: &str,
+  ) -> Result<Option<ScopeTeam>> {
     let mut tx = self.pool.begin().await?;
 
+    let Some(team) = query_concat_as!(
+      ScopeTeam,
+      "DELETE FROM scope_teams WHERE scope = $1 AND name = $2
+      RETURNING ", SCOPE_TEAM_SELECT;
+      scope as _,
+      name,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
     audit_log(
       &mut tx,
       actor_id,
       is_sudo,
-      "delete_scope",
+      "delete_scope_team",
       json!({
         "scope": scope,
+        "team": name,
       }),
     )
     .await?;
 
-    sqlx::query!(r#"DELETE FROM scope_members WHERE scope = $1"#, scope as _,)
-      .execute(&mut *tx)
-      .await?;
+    tx.commit().await?;
 
-    sqlx::query!(r#"DELETE FROM scope_invites WHERE scope = $1"#, scope as _,)
-      .execute(&mut *tx)
-      .await?;
+    Ok(Some(team))
+  }
 
-    let res =
-      sqlx::query!(r#"DELETE FROM scopes WHERE scope = $1"#, scope as _,)
-        .execute(&mut *tx)
-        .await;
-    match res {
-      Ok(res) => {
-        let success = res.rows_affected() > 0;
-        if success {
-          tx.commit().await?;
-        }
-        Ok(success)
-      }
-      Err(err) => {
-        if let Some(dberr) = err.as_database_error()
-          && dberr.is_foreign_key_violation()
-        {
-          return Ok(false);
-        }
-        Err(err)
-      }
-    }
+  #[instrument(name = "Database::list_scope_team_members", skip(self), err)]
+  pub async fn list_scope_team_members(
+    &self,
+    scope: &ScopeName,
+    team: &str,
+  ) -> Result<Vec<(ScopeTeamMember, UserPublic)>> {
+    query_concat!(
+      "SELECT ", SCOPE_TEAM_MEMBER_SELECT_JOINED, ",
+        ", USER_PUBLIC_SELECT_JOINED_RT, "
+      FROM scope_team_members
+      LEFT JOIN users ON scope_team_members.user_id = users.id
+      WHERE scope = $1 AND team = $2
+      ORDER BY users.name ASC";
+      scope as _,
+      team,
+    )
+    .map(|r| {
+      let member = ScopeTeamMember {
+        scope: r.scope_team_member_scope,
+        team: r.scope_team_member_team,
+        user_id: r.scope_team_member_user_id,
+        created_at: r.scope_team_member_created_at,
+      };
+      let user = UserPublic {
+        id: r.user_id,
+        name: r.user_name,
+        avatar_url: r.user_avatar_url,
+        github_id: r.user_github_id,
+        gitlab_id: r.user_gitlab_id,
+        updated_at: r.user_updated_at,
+        created_at: r.user_created_at,
+      };
+      (member, user)
+    })
+    .fetch_all(&self.pool)
+    .await
   }
 
-  pub async fn transfer_scope<'a>(
+  /// Adds a member of the scope to a team. Adding a user that already is in
+  /// the team is a no-op.
+  #[instrument(name = "Database::add_scope_team_member", skip(self), err)]
+  pub async fn add_scope_team_member(
     &self,
+    actor_id: &Uuid,
+    is_sudo: bool,
     scope: &ScopeName,
-    is_creator: bool,
-    tx: &mut sqlx::Transaction<'a, sqlx::Postgres>,
-  ) -> Result<Option<ScopeMemberUpdateResult>> {
-    let admins_n = sqlx::query!(
-      r#"SELECT COUNT(user_id) FROM scope_members WHERE scope = $1 AND is_admin = true"#,
+    team: &str,
+    user_id: Uuid,
+  ) -> Result<ScopeTeamMember> {
+    let mut tx = self.pool.begin().await?;
+
+    let member = query_concat_as!(
+      ScopeTeamMember,
+      "INSERT INTO scope_team_members (scope, team, user_id)
+      VALUES ($1, $2, $3)
+      ON CONFLICT (scope, team, user_id) DO UPDATE
+      SET created_at = scope_team_members.created_at
+      RETURNING ", SCOPE_TEAM_MEMBER_SELECT;
       scope as _,
+      team,
+      user_id,
     )
-      .map(|r| r.count.unwrap())
-      .fetch_one(&mut **tx)
-      .await?;
-    if admins_n == 0 {
-      return Ok(Some(ScopeMemberUpdateResult::TargetIsLastAdmin));
-    }
+    .fetch_one(&mut *tx)
+    .await?;
 
-    let maybe_available_admin_id = sqlx::query!(
-      r#"
-      SELECT user_id
-      FROM (
-        SELECT scope_members.user_id as "user_id", users.scope_limit as "scope_limit", scope_members.created_at as "created_at", (SELECT COUNT(created_at) FROM scopes WHERE creator = users.id) as "scope_usage"
-        FROM scope_members
-        LEFT JOIN users ON scope_members.user_id = users.id
-        WHERE scope_members.scope = $1 AND scope_members.is_admin = true
-      ) AS subquery
-      WHERE "scope_usage" < scope_limit
-      ORDER BY created_at LIMIT 1;
-      "#,
-      scope as _,
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "add_scope_team_member",
+      json!({
+        "scope": scope,
+        "team": team,
+        "user_id": user_id,
+      }),
     )
-      .map(|r| r.user_id)
-      .fetch_optional(&mut **tx)
-      .await?;
-    let Some(new_creator_id) = maybe_available_admin_id else {
-      return Ok(Some(ScopeMemberUpdateResult::TargetIsLastTransferableAdmin));
-    };
+    .await?;
 
-    if is_creator {
-      let _ = sqlx::query!(
-        r#"UPDATE scopes SET creator = $1 WHERE scope = $2"#,
-        new_creator_id,
-        scope as _,
-      )
-      .execute(&mut **tx)
-      .await?;
-    }
+    tx.commit().await?;
 
-    Ok(None)
+    Ok(member)
   }
 
-  #[instrument(name = "Database::delete_scope_member", skip(self), err)]
-  pub async fn update_scope_member_role(
+  #[instrument(name = "Database::remove_scope_team_member", skip(self), err)]
+  pub async fn remove_scope_team_member(
     &self,
     actor_id: &Uuid,
     is_sudo: bool,
     scope: &ScopeName,
+    team: &str,
     user_id: Uuid,
-    is_admin: bool,
-  ) -> Result<ScopeMemberUpdateResult> {
+  ) -> Result<bool> {
     let mut tx = self.pool.begin().await?;
 
+    let res = sqlx::query!(
+      r#"DELETE FROM scope_team_members
+      WHERE scope = $1 AND team = $2 AND user_id = $3"#,
+      scope as _,
+      team,
+      user_id,
+    )
+    .execute(&mut *tx)
+    .await?;
+    if res.rows_affected() == 0 {
+      return Ok(false);
+    }
+
     audit_log(
       &mut tx,
       actor_id,
       is_sudo,
-      "scope_update_scope_member_role",
+      "remove_scope_team_member",
       json!({
         "scope": scope,
+        "team": team,
         "user_id": user_id,
-        "is_admin": is_admin,
       }),
     )
     .await?;
 
-    let maybe_scope_member = sqlx::query!(
-      r#"UPDATE scope_members
-      SET is_admin = $1
-      WHERE scope = $2 AND user_id = $3
-      RETURNING scope as "scope: ScopeName", user_id, is_admin, updated_at, created_at,
-      (SELECT creator FROM scopes WHERE scope = $2) AS "scope_creator!""#,
-      is_admin,
+    tx.commit().await?;
+
+    Ok(true)
+  }
+
+  #[instrument(name = "Database::list_scope_team_packages", skip(self), err)]
+  pub async fn list_scope_team_packages(
+    &self,
+    scope: &ScopeName,
+    team: &str,
+  ) -> Result<Vec<ScopeTeamPackage>> {
+    query_concat_as!(
+      ScopeTeamPackage,
+      "SELECT ", SCOPE_TEAM_PACKAGE_SELECT, " FROM scope_team_packages
+      WHERE scope = $1 AND team = $2
+      ORDER BY package ASC";
       scope as _,
-      user_id,
+      team,
     )
-      .map(|r| {
-        (
-          ScopeMember {
-            scope: r.scope,
-            user_id: r.user_id,
-            is_admin: r.is_admin,
-            updated_at: r.updated_at,
-            created_at: r.created_at,
-          },
-          r.user_id == r.scope_creator
-        )
-      })
-      .fetch_optional(&mut *tx)
-      .await?;
+    .fetch_all(&self.pool)
+    .await
+  }
 
-    let Some((scope_member, is_creator)) = maybe_scope_member else {
-      return Ok(ScopeMemberUpdateResult::TargetNotMember);
-    };
+  /// Lists the teams that were granted permissions on a package.
+  #[instrument(name = "Database::list_package_teams", skip(self), err)]
+  pub async fn list_package_teams(
+    &self,
+    scope: &ScopeName,
+    package: &PackageName,
+  ) -> Result<Vec<ScopeTeamPackage>> {
+    query_concat_as!(
+      ScopeTeamPackage,
+      "SELECT ", SCOPE_TEAM_PACKAGE_SELECT, " FROM scope_team_packages
+      WHERE scope = $1 AND package = $2
+      ORDER BY team ASC";
+      scope as _,
+      package as _,
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
 
-    if !scope_member.is_admin
-      && let Some(result) =
-        self.transfer_scope(scope, is_creator, &mut tx).await?
-    {
-      return Ok(result);
-    }
+  /// Sets the permissions a team grants on a package. This restricts the
+  /// package to the teams that were granted permissions on it.
+  #[instrument(name = "Database::set_scope_team_package", skip(self), err)]
+  pub async fn set_scope_team_package(
+    &self,
+    actor_id: &Uuid,
+    is_sudo: bool,
+    scope: &ScopeName,
+    team: &str,
+    package: &PackageName,
+    access: PackageTeamAccess,
+  ) -> Result<ScopeTeamPackage> {
+    let mut tx = self.pool.begin().await?;
+
+    let team_package = query_concat_as!(
+      ScopeTeamPackage,
+      "INSERT INTO scope_team_packages (scope, team, package, can_publish, can_yank, can_manage_tokens)
+      VALUES ($1, $2, $3, $4, $5, $6)
+      ON CONFLICT (scope, team, package) DO UPDATE
+      SET can_publish = EXCLUDED.can_publish, can_yank = EXCLUDED.can_yank,
+        can_manage_tokens = EXCLUDED.can_manage_tokens
+      RETURNING ", SCOPE_TEAM_PACKAGE_SELECT;
+      scope as _,
+      team,
+      package as _,
+      access.can_publish,
+      access.can_yank,
+      access.can_manage_tokens,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "set_scope_team_package",
+      json!({
+        "scope": scope,
+        "team": team,
+        "package": package,
+        "can_publish": access.can_publish,
+        "can_yank": access.can_yank,
+        "can_manage_tokens": access.can_manage_tokens,
+      }),
+    )
+    .await?;
 
     tx.commit().await?;
 
-    Ok(ScopeMemberUpdateResult::Ok(scope_member))
+    Ok(team_package)
   }
 
-  #[instrument(name = "Database::delete_scope_member", skip(self), err)]
-  pub async fn delete_scope_member(
+  #[instrument(name = "Database::delete_scope_team_package", skip(self), err)]
+  pub async fn delete_scope_team_package(
     &self,
+    actor_id: &Uuid,
+    is_sudo: bool,
     scope: &ScopeName,
-    user_id: Uuid,
-  ) -> Result<ScopeMemberUpdateResult> {
+    team: &str,
+    package: &PackageName,
+  ) -> Result<bool> {
     let mut tx = self.pool.begin().await?;
 
-    let maybe_scope_member = sqlx::query!(
-      r#"DELETE FROM scope_members WHERE scope = $1 AND user_id = $2
-      RETURNING scope as "scope: ScopeName", user_id, is_admin, updated_at, created_at,
-      (SELECT creator FROM scopes WHERE scope = $1) AS "scope_creator!""#,
+    let res = sqlx::query!(
+      r#"DELETE FROM scope_team_packages
+      WHERE scope = $1 AND team = $2 AND package = $3"#,
       scope as _,
-      user_id,
+      team,
+      package as _,
     )
-      .map(|r| {
-        (
-          ScopeMember {
-            scope: r.scope,
-            user_id: r.user_id,
-            is_admin: r.is_admin,
-            updated_at: r.updated_at,
-            created_at: r.created_at,
-          },
-          r.user_id == r.scope_creator
-        )
-      })
-      .fetch_optional(&mut *tx)
-      .await?;
-    let Some((scope_member, is_creator)) = maybe_scope_member else {
-      return Ok(ScopeMemberUpdateResult::TargetNotMember);
-    };
-
-    if let Some(result) =
-      self.transfer_scope(scope, is_creator, &mut tx).await?
-    {
-      return Ok(result);
+    .execute(&mut *tx)
+    .await?;
+    if res.rows_affected() == 0 {
+      return Ok(false);
     }
 
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "delete_scope_team_package",
+      json!({
+        "scope": scope,
+        "team": team,
+        "package": package,
+      }),
+    )
+    .await?;
+
     tx.commit().await?;
 
-    Ok(ScopeMemberUpdateResult::Ok(scope_member))
+    Ok(true)
   }
 
-  #[instrument(
-    name = "Database::create_publishing_task",
-    skip(self, task),
-    err,
-    fields(publishing_task.package_scope = %task.package_scope, publishing_task.package_name = %task.package_name, publishing_task.package_version = %task.package_version
+  /// Returns the permissions the teams of a user grant on a package, or
+  /// `None` if no team was granted permissions on the package.
+  #[instrument(name = "Database::get_package_team_access", skip(self), err)]
+  pub async fn get_package_team_access(
+    &self,
+    scope: &ScopeName,
+    package: &PackageName,
+    user_id: Uuid,
+  ) -> Result<Option<PackageTeamAccess>> {
+    let row = sqlx::query!(
+      r#"SELECT count(*) as "count!",
+        bool_or(scope_team_members.user_id IS NOT NULL AND can_publish) as can_publish,
+        bool_or(scope_team_members.user_id IS NOT NULL AND can_yank) as can_yank,
+        bool_or(scope_team_members.user_id IS NOT NULL AND can_manage_tokens) as can_manage_tokens
+      FROM scope_team_packages
+      LEFT JOIN scope_team_members ON scope_team_members.scope = scope_team_packages.scope
+        AND scope_team_members.team = scope_team_packages.team
+        AND scope_team_members.user_id = $3
+      WHERE scope_team_packages.scope = $1 AND scope_team_packages.package = $2"#,
+      scope as _,
+      package as _,
+      user_id,
     )
+    .fetch_one(&self.pool)
+    .await?;
+    if row.count == 0 {
+      return Ok(None);
+    }
+    Ok(Some(PackageTeamAccess {
+      can_publish: row.can_publish.unwrap_or(false),
+      can_yank: row.can_yank.unwrap_or(false),
+      can_manage_tokens: row.can_manage_tokens.unwrap_or(false),
+    }))
+  }
+
+  /// Lists the scope memberships of a user.
+  #[instrument(
+    name = "Database::list_scope_memberships_by_user",
+    skip(self),
+    err
   )]
-  pub async fn create_publishing_task(
+  pub async fn list_scope_memberships_by_user(
     &self,
-    task: NewPublishingTask<'_>,
+    user_id: Uuid,
+  ) -> Result<Vec<ScopeMember>> {
+    query_concat_as!(
+      ScopeMember,
+      "SELECT ", SCOPE_MEMBER_SELECT, " FROM scope_members
+      WHERE user_id = $1
+      ORDER BY scope ASC";
+      user_id,
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::get_member_scopes_by_user", skip(self), err)]
+  pub async fn get_member_scopes_by_user(
+    &self,
+    id: &Uuid,
+  ) -> Result<Vec<Scope>> {
+    sqlx::query_as!(
+      Scope,
+      r#"SELECT
+      scopes.scope as "scope: ScopeName",
+      scopes.description as "description: ScopeDescription",
+      scopes.creator,
+      scopes.package_limit,
+      scopes.new_package_per_week_limit,
+      scopes.publish_attempts_per_week_limit,
+      scopes.storage_limit,
+      scopes.verify_oidc_actor,
+      scopes.require_publishing_from_ci,
+      scopes.updated_at,
+      scopes.created_at
+      FROM scopes
+      LEFT JOIN scope_members ON scope_members.scope = scopes.scope
+      WHERE user_id = $1"#,
+      id
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::add_scope_invite", skip(self), err)]
+  pub async fn add_scope_invite(
+    &self,
+    actor_id: &Uuid,
+    is_sudo: bool,
+    new_scope_invite: NewScopeInvite<'_>,
+  ) -> Result<ScopeInvite> {
+    let mut tx = self.pool.begin().await?;
+
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "add_scope_invite",
+      json!({
+          "scope": new_scope_invite.scope,
+          "target_user_id": new_scope_invite.target_user_id,
+      }),
+    )
+    .await?;
+
+    let scope_invite = query_concat_as!(
+      ScopeInvite,
+      "INSERT INTO scope_invites (scope, target_user_id, requesting_user_id)
+      VALUES ($1, $2, $3)
+      RETURNING ", SCOPE_INVITE_SELECT;
+      new_scope_invite.scope as _,
+      new_scope_invite.target_user_id,
+      new_scope_invite.requesting_user_id,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(scope_invite)
+  }
+
+  #[cfg(test)]
+  #[instrument(name = "Database::add_user_to_scope", skip(
+    self,
+    new_scope_member
+  ), err, fields(scope_member.scope = %new_scope_member.scope, scope_member.user_id = %new_scope_member.user_id, scope_member.is_admin = new_scope_member.is_admin
+  ))]
+  pub async fn add_user_to_scope(
+    &self,
+    new_scope_member: NewScopeMember<'_>,
+  ) -> Result<ScopeMember> {
+    query_concat_as!(
+      ScopeMember,
+      "INSERT INTO scope_members (scope, user_id, is_admin)
+      VALUES ($1, $2, $3)
+      RETURNING ", SCOPE_MEMBER_SELECT;
+      new_scope_member.scope as _,
+      new_scope_member.user_id,
+      new_scope_member.is_admin,
+    )
+    .fetch_one(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::get_scope_invites_by_user", skip(self), err)]
+  pub async fn get_scope_invites_by_user(
+    &self,
+    target_user: &Uuid,
+  ) -> Result<Vec<(ScopeInvite, UserPublic, UserPublic)>> {
+    query_concat!(
+      "SELECT ", SCOPE_INVITE_SELECT_JOINED, "
+      FROM scope_invites
+      LEFT JOIN users AS target_user ON scope_invites.target_user_id = target_user.id
+      LEFT JOIN users AS requesting_user ON scope_invites.requesting_user_id = requesting_user.id
+      WHERE target_user_id = $1";
+      target_user
+    )
+      .map(|r| {
+        let scope_invite = ScopeInvite {
+          target_user_id: r.scope_invite_target_user_id,
+          requesting_user_id: r.scope_invite_requesting_user_id,
+          scope: r.scope_invite_scope,
+          created_at: r.scope_invite_created_at,
+          updated_at: r.scope_invite_updated_at,
+        };
+        let target_user = UserPublic {
+          id: r.target_user_id,
+          name: r.target_user_name,
+          avatar_url: r.target_user_avatar_url,
+          github_id: r.target_user_github_id,
+          gitlab_id: r.target_user_gitlab_id,
+          updated_at: r.target_user_updated_at,
+          created_at: r.target_user_created_at,
+        };
+        let requesting_user = UserPublic {
+          id: r.requesting_user_id,
+          name: r.requesting_user_name,
+          avatar_url: r.requesting_user_avatar_url,
+          github_id: r.requesting_user_github_id,
+          gitlab_id: r.requesting_user_gitlab_id,
+          updated_at: r.requesting_user_updated_at,
+          created_at: r.requesting_user_created_at,
+        };
+        (scope_invite, target_user, requesting_user)
+      })
+      .fetch_all(&self.pool)
+      .await
+  }
+
+  #[instrument(name = "Database::get_scope_invites_by_scope", skip(self), err)]
+  pub async fn get_scope_invites_by_scope(
+    &self,
+    scope: &ScopeName,
+  ) -> Result<Vec<(ScopeInvite, UserPublic, UserPublic)>> {
+    query_concat!(
+      "SELECT ", SCOPE_INVITE_SELECT_JOINED, "
+      FROM scope_invites
+      LEFT JOIN users AS target_user ON scope_invites.target_user_id = target_user.id
+      LEFT JOIN users AS requesting_user ON scope_invites.requesting_user_id = requesting_user.id
+      WHERE scope = $1";
+      scope as _
+    )
+      .map(|r| {
+        let scope_invite = ScopeInvite {
+          target_user_id: r.scope_invite_target_user_id,
+          requesting_user_id: r.scope_invite_requesting_user_id,
+          scope: r.scope_invite_scope,
+          created_at: r.scope_invite_created_at,
+          updated_at: r.scope_invite_updated_at,
+        };
+        let target_user = UserPublic {
+          id: r.target_user_id,
+          name: r.target_user_name,
+          avatar_url: r.target_user_avatar_url,
+          github_id: r.target_user_github_id,
+          gitlab_id: r.target_user_gitlab_id,
+          updated_at: r.target_user_updated_at,
+          created_at: r.target_user_created_at,
+        };
+        let requesting_user = UserPublic {
+          id: r.requesting_user_id,
+          name: r.requesting_user_name,
+          avatar_url: r.requesting_user_avatar_url,
+          github_id: r.requesting_user_github_id,
+          gitlab_id: r.requesting_user_gitlab_id,
+          updated_at: r.requesting_user_updated_at,
+          created_at: r.requesting_user_created_at,
+        };
+        (scope_invite, target_user, requesting_user)
+      })
+      .fetch_all(&self.pool)
+      .await
+  }
+
+  #[instrument(name = "Database::accept_scope_invite", skip(self), err)]
+  pub async fn accept_scope_invite(
+    &self,
+    target_user_id: &Uuid,
+    scope: &ScopeName,
+  ) -> Result<Option<ScopeMember>> {
+    let mut tx = self.pool.begin().await?;
+
+    let res = sqlx::query!(
+      r#"DELETE FROM scope_invites WHERE target_user_id = $1 AND scope = $2"#,
+      target_user_id,
+      scope as _,
+    )
+    .execute(&mut *tx)
+    .await?;
+    if res.rows_affected() != 1 {
+      return Ok(None);
+    }
+
+    let member = query_concat_as!(
+      ScopeMember,
+      "INSERT INTO scope_members (scope, user_id) VALUES ($1, $2)
+      RETURNING ", SCOPE_MEMBER_SELECT;
+      scope as _,
+      target_user_id,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit_log(
+      &mut tx,
+      target_user_id,
+      false,
+      "add_scope_member",
+      json!({
+        "scope": scope,
+        "user_id": target_user_id,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(member))
+  }
+
+  #[instrument(name = "Database::delete_scope_invite", skip(self), err)]
+  pub async fn delete_scope_invite(
+    &self,
+    actor_id: &Uuid,
+    is_sudo: bool,
+    target_user_id: &Uuid,
+    scope: &ScopeName,
+  ) -> Result<()> {
+    let mut tx = self.pool.begin().await?;
+
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "delete_scope_invite",
+      json!({
+          "scope": scope,
+          "target_user_id": target_user_id,
+      }),
+    )
+    .await?;
+
+    sqlx::query!(
+      r#"DELETE FROM scope_invites WHERE target_user_id = $1 AND scope = $2"#,
+      target_user_id,
+      scope as _,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+  }
+
+  #[instrument(name = "Database::delete_package", skip(self), err)]
+  pub async fn delete_package(
+    &self,
+    actor_id: &Uuid,
+    is_sudo: bool,
+    scope: &ScopeName,
+    name: &PackageName,
+  ) -> Result<bool> {
+    let mut tx = self.pool.begin().await?;
+
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "delete_package",
+      json!({
+          "scope": scope,
+      }),
+    )
+    .await?;
+
+    let status = sqlx::query!(
+      r#"SELECT count(*) FROM publishing_tasks WHERE package_scope = $1 AND package_name = $2 AND status IN ('pending', 'processing', 'processed')"#,
+      scope as _,
+      name as _,
+    )
+      .fetch_one(&mut *tx)
+      .await?;
+    if status.count.unwrap() > 0 {
+      return Ok(false);
+    }
+
+    let versions = sqlx::query!(
+      r#"SELECT count(*) FROM package_versions WHERE scope = $1 AND name = $2 AND deleted_at IS NULL"#,
+      scope as _,
+      name as _,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if versions.count.unwrap() > 0 {
+      return Ok(false);
+    }
+
+    // The package becomes a tombstone, which all other queries leave out. It
+    // can be restored until it is purged.
+    let res = sqlx::query!(
+      r#"UPDATE packages SET deleted_at = now() WHERE scope = $1 AND name = $2 AND deleted_at IS NULL"#,
+      scope as _,
+      name as _,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let success = res.rows_affected() > 0;
+    if success {
+      tx.commit().await?;
+      self.package_changed(scope, name).await;
+    }
+    Ok(success)
+  }
+
+  /// Restores a deleted package. Its deleted versions stay deleted.
+  #[instrument(name = "Database::restore_package", skip(self), err)]
+  pub async fn restore_package(
+    &self,
+    staff_id: &Uuid,
+    scope: &ScopeName,
+    name: &PackageName,
+  ) -> Result<Option<Package>> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some(package) = query_concat_as!(
+      Package,
+      "UPDATE packages SET deleted_at = NULL
+      WHERE scope = $1 AND name = $2 AND deleted_at IS NOT NULL
+      RETURNING ", PACKAGE_SELECT;
+      scope as _,
+      name as _,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "restore_package",
+      json!({
+        "scope": scope,
+        "name": name,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    self.package_changed(scope, name).await;
+
+    Ok(Some(package))
+  }
+
+  /// Lists deleted packages and package versions that have not been purged
+  /// yet, most recently deleted first.
+  #[instrument(name = "Database::list_tombstones", skip(self), err)]
+  pub async fn list_tombstones(&self) -> Result<Vec<Tombstone>> {
+    sqlx::query_as!(
+      Tombstone,
+      r#"SELECT scope as "scope!: ScopeName", name as "name!: PackageName", NULL::text as "version: Version", deleted_at as "deleted_at!"
+      FROM packages WHERE deleted_at IS NOT NULL
+      UNION ALL
+      SELECT scope, name, version, deleted_at
+      FROM package_versions WHERE deleted_at IS NOT NULL
+      ORDER BY 4 DESC"#
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  /// Lists package versions that were deleted before `cutoff`, oldest first.
+  #[instrument(
+    name = "Database::list_expired_package_version_tombstones",
+    skip(self),
+    err
+  )]
+  pub async fn list_expired_package_version_tombstones(
+    &self,
+    cutoff: DateTime<Utc>,
+    limit: i64,
+  ) -> Result<Vec<PackageVersion>> {
+    query_concat_as!(
+      PackageVersion,
+      "SELECT ", PACKAGE_VERSION_SELECT, " FROM package_versions
+      WHERE deleted_at IS NOT NULL AND deleted_at < $1
+      ORDER BY deleted_at ASC
+      LIMIT $2";
+      cutoff,
+      limit,
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  /// Lists all versions of a package, including deleted ones.
+  #[instrument(
+    name = "Database::list_package_versions_including_deleted",
+    skip(self),
+    err
+  )]
+  pub async fn list_package_versions_including_deleted(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+  ) -> Result<Vec<PackageVersion>> {
+    query_concat_as!(
+      PackageVersion,
+      "SELECT ", PACKAGE_VERSION_SELECT, " FROM package_versions
+      WHERE scope = $1 AND name = $2
+      ORDER BY version DESC";
+      scope as _,
+      name as _,
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  /// Permanently deletes a deleted package version. Returns whether it was
+  /// still there.
+  #[instrument(name = "Database::purge_package_version", skip(self), err)]
+  pub async fn purge_package_version(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+    version: &Version,
+  ) -> Result<bool> {
+    let res = sqlx::query!(
+      r#"DELETE FROM package_versions WHERE scope = $1 AND name = $2 AND version = $3 AND deleted_at IS NOT NULL"#,
+      scope as _,
+      name as _,
+      version as _,
+    )
+    .execute(&self.pool)
+    .await?;
+
+    Ok(res.rows_affected() > 0)
+  }
+
+  /// Permanently deletes the packages that were deleted before `cutoff` and
+  /// have no versions left. Returns the number of purged packages.
+  #[instrument(name = "Database::purge_expired_packages", skip(self), err)]
+  pub async fn purge_expired_packages(
+    &self,
+    cutoff: DateTime<Utc>,
+  ) -> Result<u64> {
+    let res = sqlx::query!(
+      r#"DELETE FROM packages
+      WHERE deleted_at IS NOT NULL AND deleted_at < $1
+        AND NOT EXISTS (
+          SELECT 1 FROM package_versions
+          WHERE package_versions.scope = packages.scope AND package_versions.name = packages.name
+        )"#,
+      cutoff,
+    )
+    .execute(&self.pool)
+    .await?;
+
+    Ok(res.rows_affected())
+  }
+
+  #[instrument(name = "Database::get_package_transfer", skip(self), err)]
+  pub async fn get_package_transfer(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+  ) -> Result<Option<PackageTransfer>> {
+    query_concat_as!(
+      PackageTransfer,
+      "SELECT ", PACKAGE_TRANSFER_SELECT, " FROM package_transfers
+      WHERE scope = $1 AND name = $2";
+      scope as _,
+      name as _,
+    )
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  /// Lists the pending transfers of packages into `target_scope`.
+  #[instrument(
+    name = "Database::list_incoming_package_transfers",
+    skip(self),
+    err
+  )]
+  pub async fn list_incoming_package_transfers(
+    &self,
+    target_scope: &ScopeName,
+  ) -> Result<Vec<PackageTransfer>> {
+    query_concat_as!(
+      PackageTransfer,
+      "SELECT ", PACKAGE_TRANSFER_SELECT, " FROM package_transfers
+      WHERE target_scope = $1
+      ORDER BY created_at DESC";
+      target_scope as _,
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  /// Requests to move a package to `target_scope`, replacing any pending
+  /// transfer of the package.
+  #[instrument(name = "Database::create_package_transfer", skip(self), err)]
+  pub async fn create_package_transfer(
+    &self,
+    actor_id: &Uuid,
+    is_sudo: bool,
+    scope: &ScopeName,
+    name: &PackageName,
+    target_scope: &ScopeName,
+  ) -> Result<PackageTransfer> {
+    let mut tx = self.pool.begin().await?;
+
+    let transfer = query_concat_as!(
+      PackageTransfer,
+      "INSERT INTO package_transfers (scope, name, target_scope, requested_by)
+      VALUES ($1, $2, $3, $4)
+      ON CONFLICT (scope, name) DO UPDATE
+      SET target_scope = EXCLUDED.target_scope,
+        requested_by = EXCLUDED.requested_by,
+        created_at = now()
+      RETURNING ", PACKAGE_TRANSFER_SELECT;
+      scope as _,
+      name as _,
+      target_scope as _,
+      actor_id,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "create_package_transfer",
+      json!({
+        "scope": scope,
+        "name": name,
+        "target_scope": target_scope,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(transfer)
+  }
+
+  /// Cancels or declines a pending transfer.
+  #[instrument(name = "Database::delete_package_transfer", skip(self), err)]
+  pub async fn delete_package_transfer(
+    &self,
+    actor_id: &Uuid,
+    is_sudo: bool,
+    scope: &ScopeName,
+    name: &PackageName,
+  ) -> Result<Option<PackageTransfer>> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some(transfer) = query_concat_as!(
+      PackageTransfer,
+      "DELETE FROM package_transfers
+      WHERE scope = $1 AND name = $2
+      RETURNING ", PACKAGE_TRANSFER_SELECT;
+      scope as _,
+      name as _,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "delete_package_transfer",
+      json!({
+        "scope": scope,
+        "name": name,
+        "target_scope": transfer.target_scope,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(transfer))
+  }
+
+  /// Moves a package to the target scope of its pending transfer. All rows of
+  /// the package follow through `ON UPDATE CASCADE`. The old name redirects
+  /// to the new one, as do names that redirected to the old one, and `jsr:`
+  /// dependencies on the old name are rewritten so that the package keeps its
+  /// dependents.
+  #[instrument(name = "Database::accept_package_transfer", skip(self), err)]
+  pub async fn accept_package_transfer(
+    &self,
+    actor_id: &Uuid,
+    is_sudo: bool,
+    scope: &ScopeName,
+    name: &PackageName,
+    target_scope: &ScopeName,
+  ) -> Result<AcceptPackageTransferResult> {
+    let mut tx = self.pool.begin().await?;
+
+    let deleted = sqlx::query!(
+      r#"DELETE FROM package_transfers
+      WHERE scope = $1 AND name = $2 AND target_scope = $3"#,
+      scope as _,
+      name as _,
+      target_scope as _,
+    )
+    .execute(&mut *tx)
+    .await?;
+    if deleted.rows_affected() == 0 {
+      return Ok(AcceptPackageTransferResult::NotFound);
+    }
+
+    let pending_tasks = sqlx::query!(
+      r#"SELECT count(*) as "count!" FROM publishing_tasks
+      WHERE package_scope = $1 AND package_name = $2
+        AND status NOT IN ('success', 'failure')"#,
+      scope as _,
+      name as _,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if pending_tasks.count > 0 {
+      return Ok(AcceptPackageTransferResult::PublishInProgress);
+    }
+
+    // Teams belong to the old scope, so their permissions on the package do
+    // not carry over.
+    sqlx::query!(
+      r#"DELETE FROM scope_team_packages WHERE scope = $1 AND package = $2"#,
+      scope as _,
+      name as _,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let res = sqlx::query!(
+      r#"UPDATE packages SET scope = $3 WHERE scope = $1 AND name = $2 AND deleted_at IS NULL"#,
+      scope as _,
+      name as _,
+      target_scope as _,
+    )
+    .execute(&mut *tx)
+    .await;
+    if let Err(err) = &res
+      && let Some(dberr) = err.as_database_error()
+      && dberr.is_unique_violation()
+    {
+      return Ok(AcceptPackageTransferResult::AlreadyExists);
+    }
+    res?;
+
+    let package_limit = sqlx::query!(
+      r#"SELECT package_limit,
+        (SELECT count(*) FROM packages WHERE scope = $1 AND deleted_at IS NULL) as "package_count!"
+      FROM scopes WHERE scope = $1"#,
+      target_scope as _,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if package_limit.package_count > package_limit.package_limit as i64 {
+      return Ok(AcceptPackageTransferResult::PackageLimitExceeded(
+        package_limit.package_limit,
+      ));
+    }
+
+    sqlx::query!(
+      r#"UPDATE publishing_tasks SET package_scope = $3
+      WHERE package_scope = $1 AND package_name = $2"#,
+      scope as _,
+      name as _,
+      target_scope as _,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // npm tarballs contain the npm name of the package, which changes with
+    // the scope, so they have to be built again for the new name.
+    sqlx::query!(
+      r#"DELETE FROM npm_tarballs WHERE scope = $1 AND name = $2"#,
+      target_scope as _,
+      name as _,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+      r#"UPDATE package_version_dependencies SET dependency_name = $2
+      WHERE dependency_kind = 'jsr' AND dependency_name = $1"#,
+      format!("@{scope}/{name}"),
+      format!("@{target_scope}/{name}"),
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // A package lives at the new name now, so it must not redirect anymore.
+    sqlx::query!(
+      r#"DELETE FROM package_redirects WHERE scope = $1 AND name = $2"#,
+      target_scope as _,
+      name as _,
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+      r#"UPDATE package_redirects SET target_scope = $3
+      WHERE target_scope = $1 AND target_name = $2"#,
+      scope as _,
+      name as _,
+      target_scope as _,
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+      r#"INSERT INTO package_redirects (scope, name, target_scope, target_name)
+      VALUES ($1, $2, $3, $2)
+      ON CONFLICT (scope, name) DO UPDATE
+      SET target_scope = EXCLUDED.target_scope,
+        target_name = EXCLUDED.target_name,
+        created_at = now()"#,
+      scope as _,
+      name as _,
+      target_scope as _,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "accept_package_transfer",
+      json!({
+        "scope": scope,
+        "name": name,
+        "target_scope": target_scope,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    self.package_changed(scope, name).await;
+    self.package_changed(target_scope, name).await;
+
+    Ok(AcceptPackageTransferResult::Ok)
+  }
+
+  /// Gets where a package that was moved to another scope lives now. The
+  /// packages of a renamed scope keep their names in the new scope.
+  #[instrument(name = "Database::get_package_redirect", skip(self), err)]
+  pub async fn get_package_redirect(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+  ) -> Result<Option<PackageRedirect>> {
+    let redirect = query_concat_as!(
+      PackageRedirect,
+      "SELECT ", PACKAGE_REDIRECT_SELECT, " FROM package_redirects
+      WHERE scope = $1 AND name = $2";
+      scope as _,
+      name as _,
+    )
+    .fetch_optional(&self.pool)
+    .await?;
+    if redirect.is_some() {
+      return Ok(redirect);
+    }
+
+    let redirect = self.get_scope_redirect(scope).await?;
+    Ok(redirect.map(|redirect| PackageRedirect {
+      scope: redirect.scope,
+      name: name.clone(),
+      target_scope: redirect.target_scope,
+      target_name: name.clone(),
+      created_at: redirect.created_at,
+    }))
+  }
+
+  #[instrument(name = "Database::get_scope_redirect", skip(self), err)]
+  pub async fn get_scope_redirect(
+    &self,
+    scope: &ScopeName,
+  ) -> Result<Option<ScopeRedirect>> {
+    query_concat_as!(
+      ScopeRedirect,
+      "SELECT ", SCOPE_REDIRECT_SELECT, " FROM scope_redirects
+      WHERE scope = $1";
+      scope as _,
+    )
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::get_scope_rename", skip(self), err)]
+  pub async fn get_scope_rename(
+    &self,
+    scope: &ScopeName,
+  ) -> Result<Option<ScopeRename>> {
+    query_concat_as!(
+      ScopeRename,
+      "SELECT ", SCOPE_RENAME_SELECT, " FROM scope_renames
+      WHERE scope = $1";
+      scope as _,
+    )
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  /// Lists the pending scope renames, oldest first.
+  #[instrument(name = "Database::list_scope_renames", skip(self), err)]
+  pub async fn list_scope_renames(
+    &self,
+    start: i64,
+    limit: i64,
+  ) -> Result<(usize, Vec<ScopeRename>)> {
+    let mut tx = self.pool.begin().await?;
+
+    let renames = query_concat_as!(
+      ScopeRename,
+      "SELECT ", SCOPE_RENAME_SELECT, " FROM scope_renames
+      ORDER BY created_at ASC
+      OFFSET $1 LIMIT $2";
+      start,
+      limit,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let total = sqlx::query!(r#"SELECT COUNT(created_at) FROM scope_renames"#)
+      .map(|r| r.count.unwrap())
+      .fetch_one(&mut *tx)
+      .await?;
+
+    tx.commit().await?;
+
+    Ok((total as usize, renames))
+  }
+
+  /// Requests to rename a scope to `target_scope`, replacing any pending
+  /// rename of the scope.
+  #[instrument(name = "Database::create_scope_rename", skip(self), err)]
+  pub async fn create_scope_rename(
+    &self,
+    actor_id: &Uuid,
+    is_sudo: bool,
+    scope: &ScopeName,
+    target_scope: &ScopeName,
+  ) -> Result<ScopeRename> {
+    let mut tx = self.pool.begin().await?;
+
+    let rename = query_concat_as!(
+      ScopeRename,
+      "INSERT INTO scope_renames (scope, target_scope, requested_by)
+      VALUES ($1, $2, $3)
+      ON CONFLICT (scope) DO UPDATE
+      SET target_scope = EXCLUDED.target_scope,
+        requested_by = EXCLUDED.requested_by,
+        created_at = now()
+      RETURNING ", SCOPE_RENAME_SELECT;
+      scope as _,
+      target_scope as _,
+      actor_id,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "create_scope_rename",
+      json!({
+        "scope": scope,
+        "target_scope": target_scope,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(rename)
+  }
+
+  /// Cancels or rejects a pending scope rename.
+  #[instrument(name = "Database::delete_scope_rename", skip(self), err)]
+  pub async fn delete_scope_rename(
+    &self,
+    actor_id: &Uuid,
+    is_sudo: bool,
+    scope: &ScopeName,
+  ) -> Result<Option<ScopeRename>> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some(rename) = query_concat_as!(
+      ScopeRename,
+      "DELETE FROM scope_renames
+      WHERE scope = $1
+      RETURNING ", SCOPE_RENAME_SELECT;
+      scope as _,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "delete_scope_rename",
+      json!({
+        "scope": scope,
+        "target_scope": rename.target_scope,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(rename))
+  }
+
+  /// Renames a scope to the target scope of its pending rename. All rows of
+  /// the scope and its packages follow through `ON UPDATE CASCADE`. The old
+  /// name redirects to the new one, as do older names of the scope, and
+  /// `jsr:` dependencies on its packages are rewritten so that they keep
+  /// their dependents.
+  #[instrument(name = "Database::rename_scope", skip(self), err)]
+  pub async fn rename_scope(
+    &self,
+    actor_id: &Uuid,
+    is_sudo: bool,
+    scope: &ScopeName,
+  ) -> Result<RenameScopeResult> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some(rename) = query_concat_as!(
+      ScopeRename,
+      "DELETE FROM scope_renames
+      WHERE scope = $1
+      RETURNING ", SCOPE_RENAME_SELECT;
+      scope as _,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(RenameScopeResult::NotFound);
+    };
+    let target_scope = rename.target_scope;
+
+    let pending_tasks = sqlx::query!(
+      r#"SELECT count(*) as "count!" FROM publishing_tasks
+      WHERE package_scope = $1 AND status NOT IN ('success', 'failure')"#,
+      scope as _,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if pending_tasks.count > 0 {
+      return Ok(RenameScopeResult::PublishInProgress);
+    }
+
+    // A scope may take back one of its old names, but not that of another
+    // scope.
+    sqlx::query!(
+      r#"DELETE FROM scope_redirects WHERE scope = $1 AND target_scope = $2"#,
+      target_scope as _,
+      scope as _,
+    )
+    .execute(&mut *tx)
+    .await?;
+    let taken = sqlx::query!(
+      r#"SELECT EXISTS (SELECT 1 FROM scope_redirects WHERE scope = $1) as "taken!""#,
+      target_scope as _,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if taken.taken {
+      return Ok(RenameScopeResult::AlreadyExists);
+    }
+
+    let res = query_concat_as!(
+      Scope,
+      "UPDATE scopes SET scope = $2 WHERE scope = $1
+      RETURNING ", SCOPE_SELECT;
+      scope as _,
+      target_scope as _,
+    )
+    .fetch_one(&mut *tx)
+    .await;
+    if let Err(err) = &res
+      && let Some(dberr) = err.as_database_error()
+      && dberr.is_unique_violation()
+    {
+      return Ok(RenameScopeResult::AlreadyExists);
+    }
+    let renamed_scope = res?;
+
+    sqlx::query!(
+      r#"UPDATE publishing_tasks SET package_scope = $2 WHERE package_scope = $1"#,
+      scope as _,
+      target_scope as _,
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+      r#"UPDATE publish_uploads SET package_scope = $2 WHERE package_scope = $1"#,
+      scope as _,
+      target_scope as _,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // npm tarballs contain the npm names of the packages, which change with
+    // the scope, so they have to be built again for the new name.
+    sqlx::query!(
+      r#"DELETE FROM npm_tarballs WHERE scope = $1"#,
+      target_scope as _,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+      r#"UPDATE package_version_dependencies
+      SET dependency_name = '@' || $2::text || substring(dependency_name FROM length($1::text) + 2)
+      WHERE dependency_kind = 'jsr' AND starts_with(dependency_name, '@' || $1::text || '/')"#,
+      scope as _,
+      target_scope as _,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+      r#"UPDATE package_redirects SET target_scope = $2 WHERE target_scope = $1"#,
+      scope as _,
+      target_scope as _,
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+      r#"UPDATE feature_flags SET scopes = array_replace(scopes, $1::text, $2::text)
+      WHERE $1::text = ANY(scopes)"#,
+      scope as _,
+      target_scope as _,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+      r#"INSERT INTO scope_redirects (scope, target_scope) VALUES ($1, $2)"#,
+      scope as _,
+      target_scope as _,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let packages = sqlx::query!(
+      r#"SELECT name as "name: PackageName" FROM packages WHERE scope = $1"#,
+      target_scope as _,
+    )
+    .map(|r| r.name)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "rename_scope",
+      json!({
+        "scope": scope,
+        "target_scope": target_scope,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    self.invalidate_cached_scope(scope).await;
+    self.invalidate_cached_scope(&target_scope).await;
+    for package in &packages {
+      self.package_changed(scope, package).await;
+      self.package_changed(&target_scope, package).await;
+    }
+
+    Ok(RenameScopeResult::Ok(renamed_scope, packages))
+  }
+
+  #[instrument(name = "Database::delete_scope", skip(self), err)]
+  pub async fn delete_scope(
+    &self,
+    actor_id: &Uuid,
+    is_sudo: bool,
+    scope: &ScopeName,
+  ) -> Result<bool> {
+    let mut tx = self.pool.begin().await?;
+
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "delete_scope",
+      json!({
+        "scope": scope,
+      }),
+    )
+    .await?;
+
+    sqlx::query!(r#"DELETE FROM scope_members WHERE scope = $1"#, scope as _,)
+      .execute(&mut *tx)
+      .await?;
+
+    sqlx::query!(r#"DELETE FROM scope_invites WHERE scope = $1"#, scope as _,)
+      .execute(&mut *tx)
+      .await?;
+
+    let res =
+      sqlx::query!(r#"DELETE FROM scopes WHERE scope = $1"#, scope as _,)
+        .execute(&mut *tx)
+        .await;
+    match res {
+      Ok(res) => {
+        let success = res.rows_affected() > 0;
+        if success {
+          tx.commit().await?;
+          self.invalidate_cached_scope(scope).await;
+        }
+        Ok(success)
+      }
+      Err(err) => {
+        if let Some(dberr) = err.as_database_error()
+          && dberr.is_foreign_key_violation()
+        {
+          return Ok(false);
+        }
+        Err(err)
+      }
+    }
+  }
+
+  pub async fn transfer_scope<'a>(
+    &self,
+    scope: &ScopeName,
+    is_creator: bool,
+    tx: &mut sqlx::Transaction<'a, sqlx::Postgres>,
+  ) -> Result<Option<ScopeMemberUpdateResult>> {
+    let admins_n = sqlx::query!(
+      r#"SELECT COUNT(user_id) FROM scope_members WHERE scope = $1 AND is_admin = true"#,
+      scope as _,
+    )
+      .map(|r| r.count.unwrap())
+      .fetch_one(&mut **tx)
+      .await?;
+    if admins_n == 0 {
+      return Ok(Some(ScopeMemberUpdateResult::TargetIsLastAdmin));
+    }
+
+    let maybe_available_admin_id = sqlx::query!(
+      r#"
+      SELECT user_id
+      FROM (
+        SELECT scope_members.user_id as "user_id", users.scope_limit as "scope_limit", scope_members.created_at as "created_at", (SELECT COUNT(created_at) FROM scopes WHERE creator = users.id) as "scope_usage"
+        FROM scope_members
+        LEFT JOIN users ON scope_members.user_id = users.id
+        WHERE scope_members.scope = $1 AND scope_members.is_admin = true
+      ) AS subquery
+      WHERE "scope_usage" < scope_limit
+      ORDER BY created_at LIMIT 1;
+      "#,
+      scope as _,
+    )
+      .map(|r| r.user_id)
+      .fetch_optional(&mut **tx)
+      .await?;
+    let Some(new_creator_id) = maybe_available_admin_id else {
+      return Ok(Some(ScopeMemberUpdateResult::TargetIsLastTransferableAdmin));
+    };
+
+    if is_creator {
+      let _ = sqlx::query!(
+        r#"UPDATE scopes SET creator = $1 WHERE scope = $2"#,
+        new_creator_id,
+        scope as _,
+      )
+      .execute(&mut **tx)
+      .await?;
+    }
+
+    Ok(None)
+  }
+
+  #[instrument(name = "Database::delete_scope_member", skip(self), err)]
+  pub async fn update_scope_member_role(
+    &self,
+    actor_id: &Uuid,
+    is_sudo: bool,
+    scope: &ScopeName,
+    user_id: Uuid,
+    is_admin: bool,
+  ) -> Result<ScopeMemberUpdateResult> {
+    let mut tx = self.pool.begin().await?;
+
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "scope_update_scope_member_role",
+      json!({
+        "scope": scope,
+        "user_id": user_id,
+        "is_admin": is_admin,
+      }),
+    )
+    .await?;
+
+    let maybe_scope_member = sqlx::query!(
+      r#"UPDATE scope_members
+      SET is_admin = $1
+      WHERE scope = $2 AND user_id = $3
+      RETURNING scope as "scope: ScopeName", user_id, is_admin, updated_at, created_at,
+      (SELECT creator FROM scopes WHERE scope = $2) AS "scope_creator!""#,
+      is_admin,
+      scope as _,
+      user_id,
+    )
+      .map(|r| {
+        (
+          ScopeMember {
+            scope: r.scope,
+            user_id: r.user_id,
+            is_admin: r.is_admin,
+            updated_at: r.updated_at,
+            created_at: r.created_at,
+          },
+          r.user_id == r.scope_creator
+        )
+      })
+      .fetch_optional(&mut *tx)
+      .await?;
+
+    let Some((scope_member, is_creator)) = maybe_scope_member else {
+      return Ok(ScopeMemberUpdateResult::TargetNotMember);
+    };
+
+    if !scope_member.is_admin
+      && let Some(result) =
+        self.transfer_scope(scope, is_creator, &mut tx).await?
+    {
+      return Ok(result);
+    }
+
+    tx.commit().await?;
+
+    self.invalidate_cached_scope(scope).await;
+
+    Ok(ScopeMemberUpdateResult::Ok(scope_member))
+  }
+
+  #[instrument(name = "Database::delete_scope_member", skip(self), err)]
+  pub async fn delete_scope_member(
+    &self,
+    actor_id: &Uuid,
+    is_sudo: bool,
+    scope: &ScopeName,
+    user_id: Uuid,
+  ) -> Result<ScopeMemberUpdateResult> {
+    let mut tx = self.pool.begin().await?;
+
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "delete_scope_member",
+      json!({
+        "scope": scope,
+        "user_id": user_id,
+      }),
+    )
+    .await?;
+
+    let maybe_scope_member = sqlx::query!(
+      r#"DELETE FROM scope_members WHERE scope = $1 AND user_id = $2
+      RETURNING scope as "scope: ScopeName", user_id, is_admin, updated_at, created_at,
+      (SELECT creator FROM scopes WHERE scope = $1) AS "scope_creator!""#,
+      scope as _,
+      user_id,
+    )
+      .map(|r| {
+        (
+          ScopeMember {
+            scope: r.scope,
+            user_id: r.user_id,
+            is_admin: r.is_admin,
+            updated_at: r.updated_at,
+            created_at: r.created_at,
+          },
+          r.user_id == r.scope_creator
+        )
+      })
+      .fetch_optional(&mut *tx)
+      .await?;
+    let Some((scope_member, is_creator)) = maybe_scope_member else {
+      return Ok(ScopeMemberUpdateResult::TargetNotMember);
+    };
+
+    if let Some(result) =
+      self.transfer_scope(scope, is_creator, &mut tx).await?
+    {
+      return Ok(result);
+    }
+
+    tx.commit().await?;
+
+    self.invalidate_cached_scope(scope).await;
+
+    Ok(ScopeMemberUpdateResult::Ok(scope_member))
+  }
+
+  #[instrument(
+    name = "Database::create_publishing_task",
+    skip(self, task),
+    err,
+    fields(publishing_task.package_scope = %task.package_scope, publishing_task.package_name = %task.package_name, publishing_task.package_version = %task.package_version
+    )
+  )]
+  pub async fn create_publishing_task(
+    &self,
+    task: NewPublishingTask<'_>,
   ) -> Result<CreatePublishingTaskResult> {
     let mut tx = self.pool.begin().await?;
 
-    // only allow insert if no non status==failure tasks exist
-    let already_processing = query_concat!(
-      "SELECT
-        ", PUBLISHING_TASK_SELECT_JOINED, ",
-        ", USER_PUBLIC_SELECT_JOINED_OPTIONAL, "
-      FROM publishing_tasks
-      LEFT JOIN users on publishing_tasks.user_id = users.id
-      WHERE package_scope = $1 AND package_name = $2 AND package_version = $3 AND status != 'failure'
-      LIMIT 1";
-      task.package_scope as _,
-      task.package_name as _,
-      task.package_version as _
-    ).map(|r| {
-      let task = PublishingTask {
-        id: r.task_id,
-        status: r.task_status,
-        error: r.task_error,
-        package_scope: r.task_package_scope,
-        package_name: r.task_package_name,
-        package_version: r.task_package_version,
-        config_file: r.task_config_file,
-        user_id: r.task_user_id,
-        created_at: r.task_created_at,
-        updated_at: r.task_updated_at,
-      };
+    let res = insert_publishing_task(&mut tx, &task, None).await?;
+    if matches!(res, CreatePublishingTaskResult::Created(_)) {
+      tx.commit().await?;
+    }
+
+    Ok(res)
+  }
+
+  /// Creates a workspace publish with a publishing task for each of the
+  /// packages. Either all of the tasks are created, or none is.
+  #[instrument(
+    name = "Database::create_workspace_publish",
+    skip(self, tasks),
+    err
+  )]
+  pub async fn create_workspace_publish(
+    &self,
+    user_id: Option<Uuid>,
+    tasks: &[NewPublishingTask<'_>],
+  ) -> Result<CreateWorkspacePublishResult> {
+    let mut tx = self.pool.begin().await?;
+
+    let workspace_publish = sqlx::query_as!(
+      WorkspacePublish,
+      r#"INSERT INTO workspace_publishes (user_id)
+      VALUES ($1)
+      RETURNING id, user_id, created_at"#,
+      user_id,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let mut created = Vec::with_capacity(tasks.len());
+    for task in tasks {
+      match insert_publishing_task(&mut tx, task, Some(workspace_publish.id))
+        .await?
+      {
+        CreatePublishingTaskResult::Created(task) => created.push(task),
+        CreatePublishingTaskResult::Exists(task) => {
+          return Ok(CreateWorkspacePublishResult::Exists(task));
+        }
+        CreatePublishingTaskResult::WeeklyPublishAttemptsLimitExceeded(
+          limit,
+        ) => {
+          return Ok(
+            CreateWorkspacePublishResult::WeeklyPublishAttemptsLimitExceeded(
+              limit,
+            ),
+          );
+        }
+      }
+    }
+
+    tx.commit().await?;
+
+    Ok(CreateWorkspacePublishResult::Created(
+      workspace_publish,
+      created,
+    ))
+  }
+
+  #[instrument(name = "Database::get_workspace_publish", skip(self), err)]
+  pub async fn get_workspace_publish(
+    &self,
+    id: Uuid,
+  ) -> Result<Option<WorkspacePublish>> {
+    sqlx::query_as!(
+      WorkspacePublish,
+      r#"SELECT id, user_id, created_at FROM workspace_publishes WHERE id = $1"#,
+      id,
+    )
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  /// The workspace publish that a publishing task is part of, if any.
+  #[instrument(
+    name = "Database::get_publishing_task_workspace_publish_id",
+    skip(self),
+    err
+  )]
+  pub async fn get_publishing_task_workspace_publish_id(
+    &self,
+    task_id: Uuid,
+  ) -> Result<Option<Uuid>> {
+    sqlx::query!(
+      r#"SELECT workspace_publish_id FROM publishing_tasks WHERE id = $1"#,
+      task_id,
+    )
+    .map(|r| r.workspace_publish_id)
+    .fetch_optional(&self.pool)
+    .await
+    .map(Option::flatten)
+  }
+
+  #[instrument(
+    name = "Database::list_workspace_publish_tasks",
+    skip(self),
+    err
+  )]
+  pub async fn list_workspace_publish_tasks(
+    &self,
+    id: Uuid,
+  ) -> Result<Vec<(PublishingTask, Option<UserPublic>)>> {
+    query_concat!(
+      "SELECT
+        ", PUBLISHING_TASK_SELECT_JOINED, ",
+        ", USER_PUBLIC_SELECT_JOINED_OPTIONAL, "
+      FROM publishing_tasks
+      LEFT JOIN users on publishing_tasks.user_id = users.id
+      WHERE publishing_tasks.workspace_publish_id = $1
+      ORDER BY publishing_tasks.package_scope, publishing_tasks.package_name";
+      id
+    )
+    .map(|r| {
+      let task = PublishingTask {
+        id: r.task_id,
+        status: r.task_status,
+        error: r.task_error,
+        package_scope: r.task_package_scope,
+        package_name: r.task_package_name,
+        package_version: r.task_package_version,
+        config_file: r.task_config_file,
+        user_id: r.task_user_id,
+        created_at: r.task_created_at,
+        updated_at: r.task_updated_at,
+      };
+
+      let user = task.user_id.map(|_| UserPublic {
+        id: r.user_id.unwrap(),
+        name: r.user_name.unwrap(),
+        avatar_url: r.user_avatar_url.unwrap(),
+        github_id: r.user_github_id,
+        gitlab_id: r.user_gitlab_id,
+        updated_at: r.user_updated_at.unwrap(),
+        created_at: r.user_created_at.unwrap(),
+      });
+
+      (task, user)
+    })
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  /// Moves all publishing tasks of a workspace publish from `pending` to
+  /// `processing`. `None`, without changing any of them, if not all of them
+  /// are pending, for example because another worker is processing them.
+  #[instrument(name = "Database::start_workspace_publish", skip(self), err)]
+  pub async fn start_workspace_publish(
+    &self,
+    id: Uuid,
+  ) -> Result<Option<Vec<PublishingTask>>> {
+    let mut tx = self.pool.begin().await?;
+
+    let tasks = query_concat_as!(
+      PublishingTask,
+      "UPDATE publishing_tasks
+      SET status = 'processing'
+      WHERE workspace_publish_id = $1 AND status = 'pending'
+      RETURNING ", PUBLISHING_TASK_SELECT;
+      id,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let total = sqlx::query!(
+      r#"SELECT COUNT(*) as "count!" FROM publishing_tasks WHERE workspace_publish_id = $1"#,
+      id,
+    )
+    .map(|r| r.count)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if tasks.is_empty() || tasks.len() as i64 != total {
+      tx.rollback().await?;
+      return Ok(None);
+    }
+
+    tx.commit().await?;
+
+    Ok(Some(tasks))
+  }
+
+  /// Moves the publishing tasks of a workspace publish that are `processing`
+  /// back to `pending`, after processing them failed with a retryable error.
+  #[instrument(name = "Database::reset_workspace_publish", skip(self), err)]
+  pub async fn reset_workspace_publish(&self, id: Uuid) -> Result<()> {
+    sqlx::query!(
+      r#"UPDATE publishing_tasks
+      SET status = 'pending'
+      WHERE workspace_publish_id = $1 AND status = 'processing'"#,
+      id,
+    )
+    .execute(&self.pool)
+    .await?;
+
+    Ok(())
+  }
+
+  #[instrument(name = "Database::get_publishing_task", skip(self), err)]
+  pub async fn get_publishing_task(
+    &self,
+    id: Uuid,
+  ) -> Result<Option<(PublishingTask, Option<UserPublic>)>> {
+    query_concat!(
+      "SELECT
+        ", PUBLISHING_TASK_SELECT_JOINED, ",
+        ", USER_PUBLIC_SELECT_JOINED_OPTIONAL, "
+      FROM publishing_tasks
+      LEFT JOIN users on publishing_tasks.user_id = users.id
+      WHERE publishing_tasks.id = $1";
+      id
+    )
+    .map(|r| {
+      let task = PublishingTask {
+        id: r.task_id,
+        status: r.task_status,
+        error: r.task_error,
+        package_scope: r.task_package_scope,
+        package_name: r.task_package_name,
+        package_version: r.task_package_version,
+        config_file: r.task_config_file,
+        user_id: r.task_user_id,
+        created_at: r.task_created_at,
+        updated_at: r.task_updated_at,
+      };
+
+      let user = task.user_id.map(|_| UserPublic {
+        id: r.user_id.unwrap(),
+        name: r.user_name.unwrap(),
+        avatar_url: r.user_avatar_url.unwrap(),
+        github_id: r.user_github_id,
+        gitlab_id: r.user_gitlab_id,
+        updated_at: r.user_updated_at.unwrap(),
+        created_at: r.user_created_at.unwrap(),
+      });
+
+      (task, user)
+    })
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  /// Record the SHA-256 (`sha256-<hex>`) of the uploaded gzipped tarball on the
+  /// publishing task. This is the artifact that SLSA provenance attests over, so
+  /// it is later used to bind an attestation to the actual published bytes.
+  #[instrument(
+    name = "Database::set_publishing_task_tarball_hash",
+    skip(self),
+    err
+  )]
+  pub async fn set_publishing_task_tarball_hash(
+    &self,
+    id: Uuid,
+    tarball_hash: &str,
+  ) -> Result<()> {
+    sqlx::query!(
+      "UPDATE publishing_tasks SET tarball_hash = $1 WHERE id = $2",
+      tarball_hash,
+      id,
+    )
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  #[instrument(
+    name = "Database::get_publishing_task_tarball_hash",
+    skip(self),
+    err
+  )]
+  pub async fn get_publishing_task_tarball_hash(
+    &self,
+    id: Uuid,
+  ) -> Result<Option<String>> {
+    let row = sqlx::query!(
+      "SELECT tarball_hash FROM publishing_tasks WHERE id = $1",
+      id,
+    )
+    .fetch_optional(&self.pool)
+    .await?;
+    Ok(row.and_then(|r| r.tarball_hash))
+  }
+
+  /// Move a failed publishing task back to `pending`, so that it is processed
+  /// again from its stored tarball. Like `create_publishing_task`, this is only
+  /// allowed if every other task for the version failed too. Returns `None` if
+  /// the task did not fail, another task for the version did not, or the task
+  /// is part of a workspace publish, whose tasks only succeed together.
+  #[instrument(
+    name = "Database::retry_failed_publishing_task",
+    skip(self),
+    err
+  )]
+  pub async fn retry_failed_publishing_task(
+    &self,
+    id: Uuid,
+  ) -> Result<Option<PublishingTask>> {
+    query_concat_as!(
+      PublishingTask,
+      "UPDATE publishing_tasks
+      SET status = 'pending', error = NULL
+      WHERE id = $1 AND status = 'failure' AND workspace_publish_id IS NULL
+      AND NOT EXISTS (
+        SELECT 1 FROM publishing_tasks other
+        WHERE other.package_scope = publishing_tasks.package_scope
+          AND other.package_name = publishing_tasks.package_name
+          AND other.package_version = publishing_tasks.package_version
+          AND other.status != 'failure'
+      )
+      RETURNING ", PUBLISHING_TASK_SELECT;
+      id,
+    )
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  /// Record the changes the publish makes relative to the latest version of
+  /// the package on the publishing task.
+  #[instrument(
+    name = "Database::set_publishing_task_preview_diff",
+    skip(self, preview_diff),
+    err
+  )]
+  pub async fn set_publishing_task_preview_diff(
+    &self,
+    id: Uuid,
+    preview_diff: &serde_json::Value,
+  ) -> Result<()> {
+    sqlx::query!(
+      "UPDATE publishing_tasks SET preview_diff = $1 WHERE id = $2",
+      preview_diff,
+      id,
+    )
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  #[instrument(name = "Database::create_publish_upload", skip(self), err)]
+  pub async fn create_publish_upload(
+    &self,
+    new_upload: NewPublishUpload<'_>,
+  ) -> Result<PublishUpload> {
+    query_concat_as!(
+      PublishUpload,
+      "INSERT INTO publish_uploads (user_id, package_scope, package_name, package_version, config_file, expires_at)
+      VALUES ($1, $2, $3, $4, $5, $6)
+      RETURNING ", PUBLISH_UPLOAD_SELECT;
+      new_upload.user_id,
+      new_upload.package_scope as _,
+      new_upload.package_name as _,
+      new_upload.package_version as _,
+      new_upload.config_file as _,
+      new_upload.expires_at,
+    )
+    .fetch_one(&self.pool)
+    .await
+  }
+
+  /// Get a publish upload, unless it has expired.
+  #[instrument(name = "Database::get_publish_upload", skip(self), err)]
+  pub async fn get_publish_upload(
+    &self,
+    id: Uuid,
+  ) -> Result<Option<PublishUpload>> {
+    query_concat_as!(
+      PublishUpload,
+      "SELECT ", PUBLISH_UPLOAD_SELECT, " FROM publish_uploads
+      WHERE id = $1 AND expires_at > now()";
+      id,
+    )
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  /// Append a chunk to a publish upload, if `offset` is the number of bytes
+  /// uploaded so far. Returns `None` if it is not, or if the upload does not
+  /// exist or has expired.
+  #[instrument(name = "Database::append_publish_upload_chunk", skip(self), err)]
+  pub async fn append_publish_upload_chunk(
+    &self,
+    id: Uuid,
+    offset: i64,
+    hash: &str,
+    size: i64,
+  ) -> Result<Option<PublishUpload>> {
+    query_concat_as!(
+      PublishUpload,
+      "UPDATE publish_uploads
+      SET chunks = array_append(chunks, $3), size = size + $4
+      WHERE id = $1 AND size = $2 AND expires_at > now()
+      RETURNING ", PUBLISH_UPLOAD_SELECT;
+      id,
+      offset,
+      hash,
+      size,
+    )
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::delete_publish_upload", skip(self), err)]
+  pub async fn delete_publish_upload(&self, id: Uuid) -> Result<()> {
+    sqlx::query!("DELETE FROM publish_uploads WHERE id = $1", id)
+      .execute(&self.pool)
+      .await?;
+    Ok(())
+  }
+
+  #[instrument(
+    name = "Database::list_expired_publish_uploads",
+    skip(self),
+    err
+  )]
+  pub async fn list_expired_publish_uploads(
+    &self,
+    limit: i64,
+  ) -> Result<Vec<Uuid>> {
+    sqlx::query!(
+      "SELECT id FROM publish_uploads WHERE expires_at <= now()
+      ORDER BY expires_at
+      LIMIT $1",
+      limit,
+    )
+    .map(|r| r.id)
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  #[instrument(
+    name = "Database::get_publishing_task_preview_diff",
+    skip(self),
+    err
+  )]
+  pub async fn get_publishing_task_preview_diff(
+    &self,
+    id: Uuid,
+  ) -> Result<Option<serde_json::Value>> {
+    let row = sqlx::query!(
+      "SELECT preview_diff FROM publishing_tasks WHERE id = $1",
+      id,
+    )
+    .fetch_optional(&self.pool)
+    .await?;
+    Ok(row.and_then(|r| r.preview_diff))
+  }
+
+  /// The recorded tarball hash (`sha256-<hex>`) for the most recent publishing
+  /// task of a version, if one exists and recorded a hash. Used to verify that a
+  /// provenance attestation's `subject.digest.sha256` matches the published
+  /// bytes.
+  #[instrument(
+    name = "Database::get_publishing_task_tarball_hash_for_version",
+    skip(self),
+    err
+  )]
+  pub async fn get_publishing_task_tarball_hash_for_version(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+    version: &Version,
+  ) -> Result<Option<String>> {
+    let row = sqlx::query!(
+      r#"SELECT tarball_hash FROM publishing_tasks
+      WHERE package_scope = $1 AND package_name = $2 AND package_version = $3
+        AND tarball_hash IS NOT NULL
+      ORDER BY created_at DESC
+      LIMIT 1"#,
+      scope as _,
+      name as _,
+      version as _,
+    )
+    .fetch_optional(&self.pool)
+    .await?;
+    Ok(row.and_then(|r| r.tarball_hash))
+  }
+
+  #[allow(clippy::type_complexity)]
+  #[instrument(name = "Database::list_publishing_tasks", skip(self), err)]
+  pub async fn list_publishing_tasks(
+    &self,
+    start: i64,
+    limit: i64,
+    maybe_search_query: Option<&str>,
+    maybe_sort: Option<&str>,
+  ) -> Result<(usize, Vec<(PublishingTask, Option<UserPublic>)>)> {
+    let mut tx = self.pool.begin().await?;
+
+    let search = format!("%{}%", maybe_search_query.unwrap_or(""));
+    let sort = sort_by!(maybe_sort => {
+      @timestamps "updated_at", "created_at";
+      "status" => "publishing_tasks.status",
+      "user" => "users.name",
+      "scope" => "publishing_tasks.package_scope",
+      "name" => "publishing_tasks.package_name",
+      "version" => "publishing_tasks.package_version",
+      "updated_at" => "publishing_tasks.updated_at",
+      "created_at" => "publishing_tasks.created_at",
+    } || "publishing_tasks.created_at DESC");
+
+    let publishing_tasks = sqlx::query(&format!(
+      r#"SELECT
+        {}, {}
+      FROM publishing_tasks
+      LEFT JOIN users on publishing_tasks.user_id = users.id
+      WHERE publishing_tasks.package_scope ILIKE $1
+         OR publishing_tasks.package_name ILIKE $1
+         OR publishing_tasks.package_version ILIKE $1
+      ORDER BY {sort} OFFSET $2 LIMIT $3"#,
+      crate::db::sql_fragments::PUBLISHING_TASK_SELECT_JOINED_RT,
+      crate::db::sql_fragments::USER_PUBLIC_SELECT_JOINED_RT,
+    ))
+    .bind(&search)
+    .bind(start)
+    .bind(limit)
+    .try_map(|r| {
+      let task = PublishingTask::from_row(&r)?;
+
+      let user = if r.try_get::<Option<Uuid>, &str>("user_id")?.is_some() {
+        Some(UserPublic::from_row(&r)?)
+      } else {
+        None
+      };
+
+      Ok((task, user))
+    })
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let total_publishing_tasks = sqlx::query!(
+      r#"SELECT COUNT(created_at) FROM publishing_tasks WHERE package_scope ILIKE $1 OR package_name ILIKE $1 OR package_version ILIKE $1;"#,
+      search,
+    )
+      .map(|r| r.count.unwrap())
+      .fetch_one(&mut *tx)
+      .await?;
+
+    tx.commit().await?;
+
+    Ok((total_publishing_tasks as usize, publishing_tasks))
+  }
+
+  #[instrument(
+    name = "Database::list_publishing_tasks_for_package",
+    skip(self),
+    err
+  )]
+  pub async fn list_publishing_tasks_for_package(
+    &self,
+    scope_name: &ScopeName,
+    package_name: &PackageName,
+  ) -> Result<Vec<(PublishingTask, Option<UserPublic>)>> {
+    query_concat!(
+      "SELECT
+        ", PUBLISHING_TASK_SELECT_JOINED, ",
+        ", USER_PUBLIC_SELECT_JOINED_OPTIONAL, "
+      FROM publishing_tasks
+      LEFT JOIN users on publishing_tasks.user_id = users.id
+      JOIN packages ON publishing_tasks.package_scope = packages.scope AND publishing_tasks.package_name = packages.name
+      WHERE publishing_tasks.package_scope = $1 AND publishing_tasks.package_name = $2 AND publishing_tasks.created_at >= packages.created_at AND packages.deleted_at IS NULL
+      ORDER BY publishing_tasks.package_version DESC";
+      scope_name as _,
+      package_name as _,
+    )
+      .map(|r| {
+        let task = PublishingTask {
+          id: r.task_id,
+          status: r.task_status,
+          error: r.task_error,
+          package_scope: r.task_package_scope,
+          package_name: r.task_package_name,
+          package_version: r.task_package_version,
+          config_file: r.task_config_file,
+          user_id: r.task_user_id,
+          created_at: r.task_created_at,
+          updated_at: r.task_updated_at,
+        };
+
+        let user = task.user_id.map(|_| {
+          UserPublic {
+            id: r.user_id.unwrap(),
+            name: r.user_name.unwrap(),
+            avatar_url: r.user_avatar_url.unwrap(),
+            github_id: r.user_github_id,
+gitlab_id: r.user_gitlab_id,
+            updated_at: r.user_updated_at.unwrap(),
+            created_at: r.user_created_at.unwrap(),
+          }
+        });
+
+        (task, user)
+      })
+      .fetch_all(&self.pool)
+      .await
+  }
+
+  #[instrument(
+    name = "Database::get_publishing_task_for_version",
+    skip(self),
+    err
+  )]
+  pub async fn get_publishing_task_for_version(
+    &self,
+    scope_name: &ScopeName,
+    package_name: &PackageName,
+    version: &Version,
+  ) -> Result<(PublishingTask, Option<UserPublic>)> {
+    query_concat!(
+      "SELECT
+        ", PUBLISHING_TASK_SELECT_JOINED, ",
+        ", USER_PUBLIC_SELECT_JOINED_OPTIONAL, "
+      FROM publishing_tasks
+      LEFT JOIN users on publishing_tasks.user_id = users.id
+      JOIN packages ON publishing_tasks.package_scope = packages.scope AND publishing_tasks.package_name = packages.name
+      WHERE publishing_tasks.package_scope = $1 AND publishing_tasks.package_name = $2 AND publishing_tasks.package_version = $3 AND publishing_tasks.created_at >= packages.created_at AND packages.deleted_at IS NULL
+      ORDER BY publishing_tasks.created_at DESC
+      LIMIT 1";
+      scope_name as _,
+      package_name as _,
+      version as _,
+    )
+      .map(|r| {
+        let task = PublishingTask {
+          id: r.task_id,
+          status: r.task_status,
+          error: r.task_error,
+          package_scope: r.task_package_scope,
+          package_name: r.task_package_name,
+          package_version: r.task_package_version,
+          config_file: r.task_config_file,
+          user_id: r.task_user_id,
+          created_at: r.task_created_at,
+          updated_at: r.task_updated_at,
+        };
+
+        let user = task.user_id.map(|_| {
+          UserPublic {
+            id: r.user_id.unwrap(),
+            name: r.user_name.unwrap(),
+            avatar_url: r.user_avatar_url.unwrap(),
+            github_id: r.user_github_id,
+            gitlab_id: r.user_gitlab_id,
+            updated_at: r.user_updated_at.unwrap(),
+            created_at: r.user_created_at.unwrap(),
+          }
+        });
+
+        (task, user)
+      })
+      .fetch_one(&self.pool)
+      .await
+  }
+
+  #[instrument(
+    name = "Database::update_publishing_task_status",
+    skip(self),
+    err
+  )]
+  pub async fn update_publishing_task_status(
+    &self,
+    staff_id: Option<&Uuid>,
+    id: Uuid,
+    prev_status: PublishingTaskStatus,
+    new_status: PublishingTaskStatus,
+    new_error: Option<PublishingTaskError>,
+  ) -> Result<PublishingTask> {
+    assert_eq!(
+      new_error.is_some(),
+      new_status == PublishingTaskStatus::Failure,
+      "error must be set if and only if status is failure"
+    );
+
+    let mut tx = self.pool.begin().await?;
+
+    if let Some(staff_id) = staff_id {
+      audit_log(
+        &mut tx,
+        staff_id,
+        true,
+        "requeue_publishing_task",
+        json!({
+          "id": id,
+        }),
+      )
+      .await?;
+    }
+
+    let task = query_concat_as!(
+      PublishingTask,
+      "UPDATE publishing_tasks
+      SET status = $1, error = $2
+      WHERE id = $3 AND status = $4
+      RETURNING ", PUBLISHING_TASK_SELECT;
+      new_status as _,
+      new_error as _,
+      id,
+      prev_status as _,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(task)
+  }
+
+  /// List publishing tasks that have been stuck in a non-terminal state
+  /// (`processing` or `processed`) for longer than `stale_after_seconds`.
+  ///
+  /// A task is normally driven from `pending` to `success` within seconds by
+  /// the publish queue. If the queue worker is killed mid-flight (e.g. the
+  /// Cloud Run request times out, or a publish is cancelled) a task can be
+  /// stranded: `processing` means the version row was never committed, while
+  /// `processed` means the version exists but its package-level `meta.json`
+  /// was never regenerated, leaving the version invisible to the resolver.
+  /// Either state also blocks re-publishing that exact version (see the
+  /// `status != 'failure'` guard in `create_publishing_task`). The reaper at
+  /// `POST /tasks/requeue_stuck_publishing_tasks` re-drives these.
+  #[instrument(name = "Database::list_stale_publishing_tasks", skip(self), err)]
+  pub async fn list_stale_publishing_tasks(
+    &self,
+    stale_after_seconds: i64,
+  ) -> Result<Vec<(Uuid, PublishingTaskStatus)>> {
+    sqlx::query!(
+      r#"SELECT id, status as "status: PublishingTaskStatus"
+      FROM publishing_tasks
+      WHERE status IN ('processing', 'processed')
+        AND updated_at < now() - ($1::bigint * interval '1 second')
+      ORDER BY updated_at ASC
+      LIMIT 1000"#,
+      stale_after_seconds,
+    )
+    .map(|r| (r.id, r.status))
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  /// List publishing tasks that were created more than
+  /// `abandoned_after_seconds` ago and still have not committed their version
+  /// row (`pending` or `processing`).
+  ///
+  /// Unlike `list_stale_publishing_tasks`, this keys off `created_at`: the
+  /// requeue reaper resets `updated_at` every time it re-drives a task, so a
+  /// task that fails on every attempt would otherwise never age out. `processed`
+  /// tasks are excluded because their version already exists and only needs
+  /// its metadata re-uploaded.
+  #[instrument(
+    name = "Database::list_abandoned_publishing_tasks",
+    skip(self),
+    err
+  )]
+  pub async fn list_abandoned_publishing_tasks(
+    &self,
+    abandoned_after_seconds: i64,
+  ) -> Result<Vec<PublishingTask>> {
+    query_concat_as!(
+      PublishingTask,
+      "SELECT ", PUBLISHING_TASK_SELECT, "
+      FROM publishing_tasks
+      WHERE status IN ('pending', 'processing')
+        AND created_at < now() - ($1::bigint * interval '1 second')
+      ORDER BY created_at ASC
+      LIMIT 1000";
+      abandoned_after_seconds,
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::get_oauth_state", skip(self), err)]
+  pub async fn get_oauth_state(
+    &self,
+    csrf_token: &str,
+  ) -> Result<Option<OauthState>> {
+    query_concat_as!(
+      OauthState,
+      "SELECT ", OAUTH_STATE_SELECT, " FROM oauth_states WHERE csrf_token = $1";
+      csrf_token
+    )
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::delete_expired_oauth_states", skip(self), err)]
+  pub async fn delete_expired_oauth_states(
+    &self,
+    older_than: DateTime<Utc>,
+  ) -> Result<u64> {
+    let result = sqlx::query!(
+      "DELETE FROM oauth_states WHERE created_at < $1",
+      older_than
+    )
+    .execute(&self.pool)
+    .await?;
+    Ok(result.rows_affected())
+  }
+
+  /// Marks the module blobs with the given hashes as referenced by a publish,
+  /// and returns the hashes of those that are already stored.
+  #[instrument(
+    name = "Database::reference_module_blobs",
+    skip(self, hashes),
+    err,
+    fields(hashes = hashes.len())
+  )]
+  pub async fn reference_module_blobs(
+    &self,
+    hashes: &[String],
+  ) -> Result<Vec<String>> {
+    sqlx::query!(
+      "UPDATE module_blobs SET last_referenced_at = now()
+      WHERE hash = ANY($1)
+      RETURNING hash",
+      hashes,
+    )
+    .map(|r| r.hash)
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  /// Records module blobs that were just uploaded, as `(hash, size)` pairs.
+  #[instrument(
+    name = "Database::insert_module_blobs",
+    skip(self, blobs),
+    err,
+    fields(blobs = blobs.len())
+  )]
+  pub async fn insert_module_blobs(
+    &self,
+    blobs: &[(String, i64)],
+  ) -> Result<()> {
+    let (hashes, sizes): (Vec<_>, Vec<_>) = blobs.iter().cloned().unzip();
+    sqlx::query!(
+      "INSERT INTO module_blobs (hash, size)
+      SELECT * FROM UNNEST($1::TEXT[], $2::BIGINT[])
+      ON CONFLICT (hash) DO UPDATE SET last_referenced_at = now()",
+      &hashes,
+      &sizes,
+    )
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  /// Deletes up to `limit` module blobs that no package file refers to, and
+  /// that were last referenced by a publish before `older_than`.
+  ///
+  /// `delete_objects` is called with their hashes and returns those whose
+  /// stored objects it deleted, and only their rows are removed. The rows stay
+  /// locked until then, so a concurrent publish that wants to reuse one of the
+  /// blobs waits for the deletion and then uploads it again.
+  #[instrument(
+    name = "Database::delete_unreferenced_module_blobs",
+    skip(self, delete_objects),
+    err
+  )]
+  pub async fn delete_unreferenced_module_blobs<F, Fut>(
+    &self,
+    older_than: DateTime<Utc>,
+    limit: i64,
+    delete_objects: F,
+  ) -> Result<Vec<String>>
+  where
+    F: FnOnce(Vec<String>) -> Fut,
+    Fut: std::future::Future<Output = Vec<String>>,
+  {
+    let mut tx = self.pool.begin().await?;
+    let hashes = sqlx::query!(
+      "SELECT hash FROM module_blobs
+      WHERE last_referenced_at < $1
+        AND NOT EXISTS (
+          SELECT 1 FROM package_files WHERE package_files.checksum = module_blobs.hash
+        )
+      ORDER BY last_referenced_at
+      LIMIT $2
+      FOR UPDATE SKIP LOCKED",
+      older_than,
+      limit,
+    )
+    .map(|r| r.hash)
+    .fetch_all(&mut *tx)
+    .await?;
+    if hashes.is_empty() {
+      return Ok(vec![]);
+    }
+
+    let deleted = delete_objects(hashes).await;
+    sqlx::query!("DELETE FROM module_blobs WHERE hash = ANY($1)", &deleted)
+      .execute(&mut *tx)
+      .await?;
+    tx.commit().await?;
+    Ok(deleted)
+  }
+
+  #[instrument(name = "Database::cleanup_download_counts_4h", skip(self), err)]
+  pub async fn cleanup_download_counts_4h(
+    &self,
+    older_than: DateTime<Utc>,
+  ) -> Result<u64> {
+    let mut tx = self.pool.begin().await?;
+    let versions = sqlx::query!(
+      "DELETE FROM version_download_counts_4h WHERE time_bucket < $1",
+      older_than
+    )
+    .execute(&mut *tx)
+    .await?;
+    let entrypoints = sqlx::query!(
+      "DELETE FROM entrypoint_download_counts_4h WHERE time_bucket < $1",
+      older_than
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(versions.rows_affected() + entrypoints.rows_affected())
+  }
+
+  /// Recomputes the downloads of every package over the last week and month
+  /// from the daily download counts, and drops the rollups of packages that
+  /// were not downloaded in the last month. Returns the number of packages
+  /// that were.
+  #[instrument(
+    name = "Database::refresh_package_download_rollups",
+    skip(self),
+    err
+  )]
+  pub async fn refresh_package_download_rollups(&self) -> Result<u64> {
+    let mut tx = self.pool.begin().await?;
+    // `now()` is the start of the transaction, so every rollup that is not
+    // refreshed here is older than it.
+    let refreshed = sqlx::query!(
+      r#"INSERT INTO package_download_rollups (scope, package, weekly_downloads, monthly_downloads)
+      SELECT scope, package,
+        COALESCE(SUM(count) FILTER (WHERE time_bucket >= now() - '7 days'::interval), 0),
+        SUM(count)
+      FROM package_download_counts_24h
+      WHERE time_bucket >= now() - '30 days'::interval
+      GROUP BY scope, package
+      ON CONFLICT (scope, package) DO UPDATE
+      SET weekly_downloads = EXCLUDED.weekly_downloads,
+        monthly_downloads = EXCLUDED.monthly_downloads,
+        updated_at = now()"#,
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+      "DELETE FROM package_download_rollups WHERE updated_at < now()"
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(refreshed.rows_affected())
+  }
+
+  #[instrument(name = "Database::insert_oauth_state", skip(
+    self,
+    new_oauth_state
+  ), err, fields(oauth_state.csrf_token = %new_oauth_state.csrf_token, oauth_state.redirect_url = %new_oauth_state.redirect_url
+  ))]
+  pub async fn insert_oauth_state<'a>(
+    &self,
+    new_oauth_state: NewOauthState<'a>,
+  ) -> Result<OauthState> {
+    query_concat_as!(
+      OauthState,
+      "INSERT INTO oauth_states (csrf_token, pkce_code_verifier, redirect_url, user_id)
+      VALUES ($1, $2, $3, $4)
+      RETURNING ", OAUTH_STATE_SELECT;
+      new_oauth_state.csrf_token,
+      new_oauth_state.pkce_code_verifier,
+      new_oauth_state.redirect_url,
+      new_oauth_state.user_id,
+    )
+    .fetch_one(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::delete_oauth_state", skip(self), err)]
+  pub async fn delete_oauth_state(
+    &self,
+    csrf_token: &str,
+  ) -> Result<Option<OauthState>> {
+    query_concat_as!(
+      OauthState,
+      "DELETE FROM oauth_states WHERE csrf_token = $1
+      RETURNING ", OAUTH_STATE_SELECT;
+      csrf_token
+    )
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::insert_github_identity", skip(
+    self,
+    new_github_identity
+  ), err, fields(github_identity.github_id = new_github_identity.github_id))]
+  pub async fn upsert_github_identity(
+    &self,
+    new_github_identity: NewGithubIdentity,
+  ) -> Result<GithubIdentity> {
+    query_concat_as!(
+      GithubIdentity,
+      "INSERT INTO github_identities (github_id, access_token, access_token_expires_at, refresh_token, refresh_token_expires_at) VALUES ($1, $2, $3, $4, $5)
+      ON CONFLICT (github_id) DO
+      UPDATE SET access_token = $2, access_token_expires_at = $3, refresh_token = $4, refresh_token_expires_at = $5
+      RETURNING ", GITHUB_IDENTITY_SELECT;
+      new_github_identity.github_id,
+      new_github_identity.access_token,
+      new_github_identity.access_token_expires_at,
+      new_github_identity.refresh_token,
+      new_github_identity.refresh_token_expires_at,
+    )
+      .fetch_one(&self.pool)
+      .await
+  }
+
+  #[instrument(name = "Database::get_github_identity", skip(self), err)]
+  pub async fn get_github_identity(
+    &self,
+    github_id: i64,
+  ) -> Result<GithubIdentity> {
+    query_concat_as!(
+      GithubIdentity,
+      "SELECT ", GITHUB_IDENTITY_SELECT, " FROM github_identities WHERE github_id = $1";
+      github_id
+    )
+      .fetch_one(&self.pool)
+      .await
+  }
+
+  #[instrument(name = "Database::delete_github_identity", skip(self), err)]
+  pub async fn delete_github_identity(
+    &self,
+    github_id: i64,
+  ) -> Result<GithubIdentity> {
+    sqlx::query_as!(
+      GithubIdentity,
+      "DELETE FROM github_identities WHERE github_id = $1
+      RETURNING github_id, access_token, access_token_expires_at, refresh_token, refresh_token_expires_at, updated_at, created_at",
+      github_id
+    )
+      .fetch_one(&self.pool)
+      .await
+  }
+
+  #[instrument(name = "Database::insert_gitlab_identity", skip(
+    self,
+    new_gitlab_identity
+  ), err, fields(gitlab_identity.gitlab_id = new_gitlab_identity.gitlab_id))]
+  pub async fn upsert_gitlab_identity(
+    &self,
+    new_gitlab_identity: NewGitlabIdentity,
+  ) -> Result<GitlabIdentity> {
+    sqlx::query_as!(
+      GitlabIdentity,
+      "INSERT INTO gitlab_identities (gitlab_id, access_token, access_token_expires_at, refresh_token) VALUES ($1, $2, $3, $4)
+      ON CONFLICT (gitlab_id) DO
+      UPDATE SET access_token = $2, access_token_expires_at = $3, refresh_token = $4
+      RETURNING gitlab_id, access_token, access_token_expires_at, refresh_token, updated_at, created_at",
+      new_gitlab_identity.gitlab_id,
+      new_gitlab_identity.access_token,
+      new_gitlab_identity.access_token_expires_at,
+      new_gitlab_identity.refresh_token,
+    )
+      .fetch_one(&self.pool)
+      .await
+  }
+
+  #[cfg(not(test))]
+  #[instrument(name = "Database::get_gitlab_identity", skip(self), err)]
+  pub async fn get_gitlab_identity(
+    &self,
+    gitlab_id: i64,
+  ) -> Result<GitlabIdentity> {
+    sqlx::query_as!(
+      GitlabIdentity,
+      "SELECT gitlab_id, access_token, access_token_expires_at, refresh_token, updated_at, created_at
+      FROM gitlab_identities
+      WHERE gitlab_id = $1",
+      gitlab_id
+    )
+      .fetch_one(&self.pool)
+      .await
+  }
+
+  #[instrument(name = "Database::delete_gitlab_identity", skip(self), err)]
+  pub async fn delete_gitlab_identity(
+    &self,
+    gitlab_id: i64,
+  ) -> Result<GitlabIdentity> {
+    sqlx::query_as!(
+      GitlabIdentity,
+      "DELETE FROM gitlab_identities WHERE gitlab_id = $1
+      RETURNING gitlab_id, access_token, access_token_expires_at, refresh_token, updated_at, created_at",
+      gitlab_id
+    )
+      .fetch_one(&self.pool)
+      .await
+  }
+
+  #[instrument(
+    name = "Database::insert_token",
+    skip(self, new_token),
+    err,
+    fields(token.r#type = ?new_token.r#type)
+  )]
+  pub async fn insert_token(&self, new_token: NewToken) -> Result<Token> {
+    let mut tx = self.pool.begin().await?;
+
+    let token = query_concat_as!(
+      Token,
+      "INSERT INTO tokens (hash, lookup_hash, user_id, type, description, expires_at, permissions)
+      VALUES ($1, $2, $3, $4, $5, $6, $7)
+      RETURNING ", TOKEN_SELECT;
+      new_token.hash,
+      new_token.lookup_hash,
+      new_token.user_id,
+      new_token.r#type as _,
+      new_token.description,
+      new_token.expires_at,
+      new_token.permissions as _,
+    )
+      .fetch_one(&mut *tx)
+      .await?;
+
+    // Sessions are created on every login, so only personal access tokens are
+    // recorded. Tokens that are restricted to scopes show up in the audit log
+    // of each of those scopes.
+    if token.r#type == TokenType::Personal {
+      let mut scopes = Vec::<&ScopeName>::new();
+      for permission in token.permissions.iter().flat_map(|p| &p.0) {
+        if let Permission::PackagePublish(
+          PackagePublishPermission::Scope { scope }
+          | PackagePublishPermission::Package { scope, .. }
+          | PackagePublishPermission::Version { scope, .. },
+        ) = permission
+          && !scopes.contains(&scope)
+        {
+          scopes.push(scope);
+        }
+      }
+
+      if scopes.is_empty() {
+        audit_log(
+          &mut tx,
+          &token.user_id,
+          false,
+          "create_token",
+          json!({
+            "token_id": token.id,
+            "permissions": token.permissions,
+          }),
+        )
+        .await?;
+      }
+      for scope in scopes {
+        audit_log(
+          &mut tx,
+          &token.user_id,
+          false,
+          "create_token",
+          json!({
+            "scope": scope,
+            "token_id": token.id,
+            "permissions": token.permissions,
+          }),
+        )
+        .await?;
+      }
+    }
+
+    tx.commit().await?;
+
+    Ok(token)
+  }
+
+  /// Gets the token with the given hash, or the token whose previous hash it
+  /// is, if the grace period of its rotation has not ended yet.
+  #[instrument(name = "Database::get_token_by_hash", skip(self), err)]
+  pub async fn get_token_by_hash(&self, hash: &str) -> Result<Option<Token>> {
+    query_concat_as!(
+      Token,
+      "SELECT ", TOKEN_SELECT, " FROM tokens
+      WHERE hash = $1 OR (previous_hash = $1 AND previous_hash_expires_at > now())";
+      hash
+    )
+      .fetch_optional(&self.pool)
+      .await
+  }
+
+  /// Whether a token, or the previous secret of a token that is still in the
+  /// grace period of its rotation, has the given lookup hash.
+  #[instrument(
+    name = "Database::token_lookup_hash_exists",
+    skip(self, lookup_hash),
+    err
+  )]
+  pub async fn token_lookup_hash_exists(
+    &self,
+    lookup_hash: &str,
+  ) -> Result<bool> {
+    let res = sqlx::query_scalar!(
+      r#"SELECT EXISTS (
+        SELECT 1 FROM tokens
+        WHERE lookup_hash = $1 OR (previous_lookup_hash = $1 AND previous_hash_expires_at > now())
+      ) "exists!""#,
+      lookup_hash,
+    )
+    .fetch_one(&self.pool)
+    .await?;
+    Ok(res)
+  }
+
+  /// Replaces the hash of a token that is still stored in an older format.
+  /// A no-op if the token was rotated or rehashed in the meantime.
+  #[instrument(
+    name = "Database::rehash_token",
+    skip(self, old_hash, new_hash),
+    err
+  )]
+  pub async fn rehash_token(
+    &self,
+    id: Uuid,
+    old_hash: &str,
+    new_hash: &str,
+  ) -> Result<()> {
+    sqlx::query!(
+      "UPDATE tokens SET hash = $3 WHERE id = $1 AND hash = $2",
+      id,
+      old_hash,
+      new_hash,
+    )
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  /// Replaces the secret of a personal access token. The previous secret
+  /// keeps working until `previous_expires_at`. Returns `None` if the user
+  /// has no such personal access token.
+  #[instrument(
+    name = "Database::rotate_token",
+    skip(self, hash, lookup_hash),
+    err
+  )]
+  pub async fn rotate_token(
+    &self,
+    user_id: Uuid,
+    id: Uuid,
+    hash: &str,
+    lookup_hash: &str,
+    previous_expires_at: DateTime<Utc>,
+  ) -> Result<Option<Token>> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some(token) = query_concat_as!(
+      Token,
+      "UPDATE tokens
+      SET previous_hash = hash, previous_lookup_hash = lookup_hash, previous_hash_expires_at = $4, hash = $3, lookup_hash = $5, rotated_at = now()
+      WHERE user_id = $1 AND id = $2 AND type = 'personal'
+      RETURNING ", TOKEN_SELECT;
+      user_id,
+      id,
+      hash,
+      previous_expires_at,
+      lookup_hash,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
+    audit_log(
+      &mut tx,
+      &user_id,
+      false,
+      "rotate_token",
+      json!({
+        "token_id": token.id,
+        "previous_expires_at": previous_expires_at,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(token))
+  }
+
+  /// Deletes the personal access tokens that have permissions for a scope, of
+  /// all users, and the unrestricted personal access tokens of the members of
+  /// the scope, which can publish to it too. Returns the IDs of the deleted
+  /// tokens.
+  #[instrument(name = "Database::revoke_scope_tokens", skip(self), err)]
+  pub async fn revoke_scope_tokens(
+    &self,
+    actor_id: &Uuid,
+    is_sudo: bool,
+    scope: &ScopeName,
+  ) -> Result<Vec<Uuid>> {
+    let mut tx = self.pool.begin().await?;
+
+    let token_ids = sqlx::query!(
+      r#"DELETE FROM tokens
+      WHERE type = 'personal'
+        AND (
+          permissions @> jsonb_build_array(jsonb_build_object('permission', 'package/publish', 'scope', $1::text))
+          OR (
+            permissions IS NULL
+            AND user_id IN (SELECT user_id FROM scope_members WHERE scope = $1)
+          )
+        )
+      RETURNING id"#,
+      scope as _,
+    )
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|row| row.id)
+    .collect::<Vec<_>>();
+
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "revoke_scope_tokens",
+      json!({
+        "scope": scope,
+        "token_ids": token_ids,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(token_ids)
+  }
+
+  #[instrument(name = "Database::get_token", skip(self), err)]
+  pub async fn get_token(
+    &self,
+    user_id: Uuid,
+    id: Uuid,
+  ) -> Result<Option<Token>> {
+    query_concat_as!(
+      Token,
+      "SELECT ", TOKEN_SELECT, " FROM tokens WHERE user_id = $1 AND id = $2";
+      user_id,
+      id
+    )
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::update_token", skip(self), err)]
+  pub async fn update_token(
+    &self,
+    user_id: Uuid,
+    id: Uuid,
+    description: Option<&str>,
+    expires_at: Option<DateTime<Utc>>,
+  ) -> Result<Option<Token>> {
+    query_concat_as!(
+      Token,
+      "UPDATE tokens
+      SET description = COALESCE($3, description), expires_at = COALESCE($4, expires_at)
+      WHERE user_id = $1 AND id = $2
+      RETURNING ", TOKEN_SELECT;
+      user_id,
+      id,
+      description,
+      expires_at,
+    )
+      .fetch_optional(&self.pool)
+      .await
+  }
+
+  /// Records that a token was just used. To avoid a write on every request,
+  /// this is a no-op if the token was already used within the last minute.
+  /// Returns whether the token was touched.
+  #[instrument(name = "Database::touch_token", skip(self), err)]
+  pub async fn touch_token(&self, id: Uuid) -> Result<bool> {
+    let res = sqlx::query!(
+      r#"UPDATE tokens SET last_used_at = now()
+      WHERE id = $1 AND (last_used_at IS NULL OR last_used_at < now() - interval '1 minute')"#,
+      id
+    )
+    .execute(&self.pool)
+    .await?;
+    Ok(res.rows_affected() > 0)
+  }
+
+  #[instrument(name = "Database::list_token", skip(self), err)]
+  pub async fn list_tokens(&self, user_id: Uuid) -> Result<Vec<Token>> {
+    // list a user's tokens where the expiration date is at most 1 day in the past
+    query_concat_as!(
+      Token,
+      "SELECT ", TOKEN_SELECT, "
+      FROM tokens
+      WHERE user_id = $1 AND (expires_at > now() - interval '1 day' OR expires_at IS NULL)
+      ORDER BY expires_at DESC NULLS FIRST, created_at DESC";
+      user_id
+    )
+      .fetch_all(&self.pool)
+      .await
+  }
+
+  #[instrument(name = "Database::delete_token", skip(self), err)]
+  pub async fn delete_token(&self, user_id: Uuid, id: Uuid) -> Result<bool> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some(token) = sqlx::query!(
+      r#"DELETE FROM tokens WHERE user_id = $1 ANd id = $2
+      RETURNING type "type: TokenType""#,
+      user_id,
+      id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(false);
+    };
+
+    // Like their creation, only the deletion of personal access tokens is
+    // recorded.
+    if token.r#type == TokenType::Personal {
+      audit_log(
+        &mut tx,
+        &user_id,
+        false,
+        "delete_token",
+        json!({ "token_id": id }),
+      )
+      .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(true)
+  }
+
+  #[instrument(
+    name = "Database::create_authorization",
+    skip(self, new_authorization),
+    err
+  )]
+  pub async fn create_authorization(
+    &self,
+    new_authorization: NewAuthorization<'_>,
+  ) -> Result<Authorization> {
+    query_concat_as!(
+      Authorization,
+      "INSERT INTO authorizations (exchange_token, code, challenge, permissions, expires_at)
+      VALUES ($1, $2, $3, $4, $5)
+      RETURNING ", AUTHORIZATION_SELECT;
+      new_authorization.exchange_token,
+      new_authorization.code,
+      new_authorization.challenge,
+      new_authorization.permissions as _,
+      new_authorization.expires_at,
+    )
+      .fetch_one(&self.pool)
+      .await
+  }
+
+  #[instrument(name = "Database::get_authorization_by_code", skip(self), err)]
+  pub async fn get_authorization_by_code(
+    &self,
+    code: &str,
+  ) -> Result<Option<Authorization>> {
+    query_concat_as!(
+      Authorization,
+      "SELECT ", AUTHORIZATION_SELECT, " FROM authorizations WHERE code = $1";
+      code
+    )
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  #[instrument(
+    name = "Database::get_authorization_by_exchange_token",
+    skip(self, exchange_token),
+    err
+  )]
+  pub async fn get_authorization_by_exchange_token_and_remove_if_complete(
+    &self,
+    exchange_token: &str,
+  ) -> Result<Option<Authorization>> {
+    let mut tx = self.pool.begin().await?;
+
+    let maybe_authorization = query_concat_as!(
+      Authorization,
+      "DELETE FROM authorizations WHERE exchange_token = $1
+      RETURNING ", AUTHORIZATION_SELECT;
+      exchange_token
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(authorization) = &maybe_authorization
+      && authorization.user_id.is_some()
+    {
+      tx.commit().await?;
+    }
+
+    Ok(maybe_authorization)
+  }
+
+  #[instrument(name = "Database::update_authorization", skip(self), err)]
+  pub async fn update_authorization(
+    &self,
+    code: &str,
+    approved: bool,
+    user_id: Uuid,
+  ) -> Result<bool> {
+    let res = sqlx::query!(
+      r#"UPDATE authorizations
+      SET approved = $1, user_id = $2
+      WHERE code = $3 AND approved IS NULL"#,
+      approved,
+      user_id,
+      code
+    )
+    .execute(&self.pool)
+    .await?;
+    Ok(res.rows_affected() > 0)
+  }
+
+  #[instrument(name = "Database::list_package_dependencies", skip(self), err)]
+  pub async fn list_package_dependencies(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+  ) -> Result<Vec<PackageVersionDependency>> {
+    query_concat_as!(
+      PackageVersionDependency,
+      "SELECT ", PACKAGE_VERSION_DEPENDENCY_SELECT, "
+      FROM package_version_dependencies
+      WHERE package_scope = $1 AND package_name = $2
+      ORDER BY dependency_kind ASC, dependency_name ASC, dependency_constraint ASC, dependency_path ASC";
+      scope as _,
+      name as _,
+    )
+      .fetch_all(&self.pool)
+      .await
+  }
+
+  #[instrument(
+    name = "Database::list_package_version_dependencies",
+    skip(self),
+    err
+  )]
+  pub async fn list_package_version_dependencies(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+    version: &Version,
+  ) -> Result<Vec<PackageVersionDependency>> {
+    query_concat_as!(
+      PackageVersionDependency,
+      "SELECT ", PACKAGE_VERSION_DEPENDENCY_SELECT, "
+      FROM package_version_dependencies
+      WHERE package_scope = $1 AND package_name = $2 AND package_version = $3
+      ORDER BY dependency_kind ASC, dependency_name ASC, dependency_constraint ASC, dependency_path ASC";
+      scope as _,
+      name as _,
+      version as _
+    )
+      .fetch_all(&self.pool)
+      .await
+  }
+
+  #[instrument(
+    name = "Database::list_package_version_deprecations",
+    skip(self),
+    err
+  )]
+  pub async fn list_package_version_deprecations(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+    version: &Version,
+  ) -> Result<Vec<PackageVersionDeprecation>> {
+    query_concat_as!(
+      PackageVersionDeprecation,
+      "SELECT ", PACKAGE_VERSION_DEPRECATION_SELECT, "
+      FROM package_version_deprecations
+      WHERE scope = $1 AND name = $2 AND version = $3
+      ORDER BY export ASC, symbol ASC";
+      scope as _,
+      name as _,
+      version as _
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  #[instrument(
+    name = "Database::get_package_version_doc_link",
+    skip(self),
+    err
+  )]
+  pub async fn get_package_version_doc_link(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+    version: &Version,
+    identifier: &str,
+  ) -> Result<Option<PackageVersionDocLink>> {
+    query_concat_as!(
+      PackageVersionDocLink,
+      "SELECT ", PACKAGE_VERSION_DOC_LINK_SELECT, "
+      FROM package_version_doc_links
+      WHERE scope = $1 AND name = $2 AND version = $3 AND identifier = $4";
+      scope as _,
+      name as _,
+      version as _,
+      identifier
+    )
+    .fetch_optional(self.reader())
+    .await
+  }
+
+  /// Finds the symbols exported by the latest stable version of each package
+  /// whose name starts with `prefix`, case insensitively, along with their
+  /// package and its downloads over the last 30 days. Exact matches and the
+  /// most downloaded packages come first, up to `limit` results.
+  #[instrument(name = "Database::search_package_symbols", skip(self), err)]
+  pub async fn search_package_symbols(
+    &self,
+    prefix: &str,
+    maybe_kind: Option<&str>,
+    limit: i64,
+  ) -> Result<Vec<(PackageVersionSymbol, Package, PackageVersionMeta, i64)>> {
+    let prefix = prefix.to_lowercase();
+    let like_query = format!(
+      "{}%",
+      prefix
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+    );
+
+    sqlx::query(
+      &format!(r#"SELECT {}, {}, {},
+        package_version_symbols.version "symbol_version", package_version_symbols.export "symbol_export", package_version_symbols.symbol "symbol_symbol", package_version_symbols.kind "symbol_kind", package_version_symbols.updated_at "symbol_updated_at", package_version_symbols.created_at "symbol_created_at",
+        COALESCE(downloads.total, 0) "downloads"
+       FROM package_version_symbols
+       JOIN packages ON packages.scope = package_version_symbols.scope AND packages.name = package_version_symbols.name
+       LEFT JOIN github_repositories ON packages.github_repository_id = github_repositories.id
+       {}
+       LEFT JOIN LATERAL (SELECT SUM(count) as total FROM package_download_counts_24h WHERE scope = packages.scope AND package = packages.name AND time_bucket >= now() - interval '30 days') downloads ON true
+       WHERE lower(package_version_symbols.symbol) LIKE $1 AND ($2::text IS NULL OR package_version_symbols.kind = $2)
+         AND package_version_symbols.version = pv_latest.version AND NOT packages.is_archived AND packages.visibility = 'public' AND packages.deleted_at IS NULL
+       ORDER BY lower(package_version_symbols.symbol) = $3 DESC, downloads DESC, packages.scope ASC, packages.name ASC
+       LIMIT $4"#,
+        crate::db::sql_fragments::PACKAGE_BASE_SELECT_JOINED_RT,
+        crate::db::sql_fragments::PACKAGE_VERSION_AGG_SELECT_RT,
+        crate::db::sql_fragments::GITHUB_REPOSITORY_SELECT_JOINED_RT,
+        crate::db::sql_fragments::PACKAGE_VERSION_LATERAL_JOINS_RT,
+      ),
+    )
+      .bind(like_query)
+      .bind(maybe_kind)
+      .bind(prefix)
+      .bind(limit)
+      .try_map(|r| {
+        let package = Package::from_row(&r)?;
+        let symbol = PackageVersionSymbol {
+          scope: package.scope.clone(),
+          name: package.name.clone(),
+          version: r.try_get("symbol_version")?,
+          export: r.try_get("symbol_export")?,
+          symbol: r.try_get("symbol_symbol")?,
+          kind: r.try_get("symbol_kind")?,
+          updated_at: r.try_get("symbol_updated_at")?,
+          created_at: r.try_get("symbol_created_at")?,
+        };
+        let meta: Option<PackageVersionMeta> = r.try_get("package_version_meta")?;
+        let downloads: i64 = r.try_get("downloads")?;
+        Ok((symbol, package, meta.unwrap_or_default(), downloads))
+      })
+      .fetch_all(self.reader())
+      .await
+  }
+
+  /// Lists the packages that depend on `name`, along with each dependent
+  /// package, the meta of its latest version and its downloads over the last
+  /// 30 days. If `maybe_constraints` is set, only dependencies through one of
+  /// these constraints are considered. Dependents are ordered by name, or by
+  /// downloads if `sort_by_downloads` is set. A `limit` of `None` lists all
+  /// dependents.
+  #[instrument(name = "Database::list_package_dependents", skip(self), err)]
+  #[allow(clippy::too_many_arguments)]
+  pub async fn list_package_dependents(
+    &self,
+    kind: DependencyKind,
+    name: &str,
+    maybe_constraints: Option<&[String]>,
+    sort_by_downloads: bool,
+    start: i64,
+    limit: Option<i64>,
+    versions_per_package_limit: i64,
+  ) -> Result<(usize, Vec<(Dependent, Package, PackageVersionMeta, i64)>)> {
+    let mut tx = self.pool.begin().await?;
+
+    let sort = if sort_by_downloads {
+      "downloads DESC, packages.scope ASC, packages.name ASC"
+    } else {
+      "packages.scope ASC, packages.name ASC"
+    };
+
+    let dependents = sqlx::query(
+      &format!(r#"SELECT {}, {}, {},
+        dependents.versions "dependent_versions", dependents.total_versions "dependent_total_versions",
+        COALESCE(downloads.total, 0) "downloads"
+       FROM (
+         SELECT package_scope, package_name, (ARRAY_AGG(DISTINCT package_version))[:$3] versions, COUNT(DISTINCT package_version) total_versions
+         FROM package_version_dependencies
+         WHERE dependency_kind = $1 AND dependency_name = $2 AND ($4::text[] IS NULL OR dependency_constraint = ANY($4))
+           AND NOT EXISTS (
+             SELECT 1 FROM package_versions
+             WHERE package_versions.scope = package_scope AND package_versions.name = package_name AND package_versions.version = package_version AND package_versions.deleted_at IS NOT NULL
+           )
+         GROUP BY package_scope, package_name
+       ) dependents
+       JOIN packages ON packages.scope = dependents.package_scope AND packages.name = dependents.package_name
+       LEFT JOIN github_repositories ON packages.github_repository_id = github_repositories.id
+       {}
+       LEFT JOIN LATERAL (SELECT SUM(count) as total FROM package_download_counts_24h WHERE scope = packages.scope AND package = packages.name AND time_bucket >= now() - interval '30 days') downloads ON true
+       WHERE packages.visibility = 'public' AND packages.deleted_at IS NULL
+       ORDER BY {sort}
+       OFFSET $5 LIMIT $6"#,
+        crate::db::sql_fragments::PACKAGE_BASE_SELECT_JOINED_RT,
+        crate::db::sql_fragments::PACKAGE_VERSION_AGG_SELECT_RT,
+        crate::db::sql_fragments::GITHUB_REPOSITORY_SELECT_JOINED_RT,
+        crate::db::sql_fragments::PACKAGE_VERSION_LATERAL_JOINS_RT,
+      ),
+    )
+      .bind(kind)
+      .bind(name)
+      .bind(versions_per_package_limit as i32)
+      .bind(maybe_constraints)
+      .bind(start)
+      .bind(limit)
+      .try_map(|r| {
+        let package = Package::from_row(&r)?;
+        let dependent = Dependent {
+          scope: package.scope.clone(),
+          name: package.name.clone(),
+          versions: r.try_get("dependent_versions")?,
+          total_versions: r.try_get("dependent_total_versions")?,
+        };
+        let meta: Option<PackageVersionMeta> = r.try_get("package_version_meta")?;
+        let downloads: i64 = r.try_get("downloads")?;
+        Ok((dependent, package, meta.unwrap_or_default(), downloads))
+      })
+      .fetch_all(&mut *tx)
+      .await?;
+
+    let total_unique_package_dependents = sqlx::query!(
+      r#"SELECT COUNT(*) FROM (
+        SELECT DISTINCT package_scope, package_name
+        FROM package_version_dependencies
+        WHERE dependency_kind = $1 AND dependency_name = $2 AND ($3::text[] IS NULL OR dependency_constraint = ANY($3))
+          AND NOT EXISTS (
+            SELECT 1 FROM packages
+            WHERE packages.scope = package_scope AND packages.name = package_name AND (packages.visibility = 'private' OR packages.deleted_at IS NOT NULL)
+          )
+          AND NOT EXISTS (
+            SELECT 1 FROM package_versions
+            WHERE package_versions.scope = package_scope AND package_versions.name = package_name AND package_versions.version = package_version AND package_versions.deleted_at IS NOT NULL
+          )
+      ) t;"#,
+      kind as _,
+      name,
+      maybe_constraints,
+    )
+    .map(|r| r.count.unwrap())
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok((total_unique_package_dependents as usize, dependents))
+  }
+
+  /// The distinct constraints other packages depend on `name` with.
+  #[instrument(
+    name = "Database::list_package_dependent_constraints",
+    skip(self),
+    err
+  )]
+  pub async fn list_package_dependent_constraints(
+    &self,
+    kind: DependencyKind,
+    name: &str,
+  ) -> Result<Vec<String>> {
+    sqlx::query!(
+      r#"SELECT DISTINCT dependency_constraint
+      FROM package_version_dependencies
+      WHERE dependency_kind = $1 AND dependency_name = $2"#,
+      kind as _,
+      name,
+    )
+    .map(|r| r.dependency_constraint)
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::count_package_dependents", skip(self), err)]
+  pub async fn count_package_dependents(
+    &self,
+    kind: DependencyKind,
+    name: &str,
+  ) -> Result<usize> {
+    let total_unique_package_dependents = sqlx::query!(
+      r#"SELECT COUNT(*) FROM (
+        SELECT DISTINCT package_scope, package_name
+        FROM package_version_dependencies
+        WHERE dependency_kind = $1 AND dependency_name = $2
+      ) t;"#,
+      kind as _,
+      name,
+    )
+    .map(|r| r.count.unwrap())
+    .fetch_one(&self.pool)
+    .await?;
+
+    Ok(total_unique_package_dependents as usize)
+  }
+
+  #[instrument(name = "Database::count_package_dependencies", skip(self), err)]
+  pub async fn count_package_dependencies(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+    version: &Version,
+  ) -> Result<usize> {
+    let total_package_dependencies = sqlx::query!(
+      r#"SELECT COUNT(DISTINCT dependency_name)
+      FROM package_version_dependencies
+      WHERE package_scope = $1 AND package_name = $2 AND package_version = $3"#,
+      scope as _,
+      name as _,
+      version as _
+    )
+    .fetch_one(&self.pool)
+    .await?
+    .count
+    .unwrap();
+
+    Ok(total_package_dependencies as usize)
+  }
+
+  #[instrument(name = "Database::check_bad_word", skip(self), err)]
+  pub async fn check_is_bad_word(&self, word: &str) -> Result<bool> {
+    let res = sqlx::query!("SELECT * FROM bad_words WHERE word = $1", word)
+      .fetch_optional(&self.pool)
+      .await?;
+    Ok(res.is_some())
+  }
+
+  #[cfg(test)]
+  #[instrument(name = "Database::add_bad_word_for_test", skip(self), err)]
+  pub async fn add_bad_word_for_test(&self, word: &str) -> Result<()> {
+    sqlx::query!("INSERT INTO bad_words (word) VALUES ($1)", word)
+      .execute(&self.pool)
+      .await?;
+
+    Ok(())
+  }
+
+  /// Whether `name` is reserved and the reservation has not expired yet. For
+  /// scopes, `name` must have its hyphens removed.
+  #[instrument(name = "Database::check_is_reserved_name", skip(self), err)]
+  pub async fn check_is_reserved_name(
+    &self,
+    kind: ReservedNameKind,
+    name: &str,
+  ) -> Result<bool> {
+    let res = sqlx::query!(
+      "SELECT name FROM reserved_names WHERE kind = $1 AND name = $2 AND (expires_at IS NULL OR expires_at > now())",
+      kind as _,
+      name,
+    )
+    .fetch_optional(&self.pool)
+    .await?;
+    Ok(res.is_some())
+  }
+
+  /// Finds another scope whose name looks like `scope`, see
+  /// [ScopeName::confusable_skeleton].
+  #[instrument(name = "Database::find_confusable_scope", skip(self), err)]
+  pub async fn find_confusable_scope(
+    &self,
+    scope: &ScopeName,
+  ) -> Result<Option<ScopeName>> {
+    let res = sqlx::query!(
+      r#"SELECT scope as "scope: ScopeName" FROM scopes WHERE confusable_skeleton(scope) = $1 AND scope != $2 LIMIT 1"#,
+      scope.confusable_skeleton(),
+      scope as _,
+    )
+    .fetch_optional(&self.pool)
+    .await?;
+    Ok(res.map(|r| r.scope))
+  }
+
+  /// Finds another package in `scope` whose name looks like `name`, see
+  /// [PackageName::confusable_skeleton].
+  #[instrument(name = "Database::find_confusable_package", skip(self), err)]
+  pub async fn find_confusable_package(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+  ) -> Result<Option<PackageName>> {
+    let res = sqlx::query!(
+      r#"SELECT name as "name: PackageName" FROM packages WHERE scope = $1 AND confusable_skeleton(name) = $2 AND name != $3 LIMIT 1"#,
+      scope as _,
+      name.confusable_skeleton(),
+      name as _,
+    )
+    .fetch_optional(&self.pool)
+    .await?;
+    Ok(res.map(|r| r.name))
+  }
+
+  #[instrument(name = "Database::list_reserved_names", skip(self), err)]
+  pub async fn list_reserved_names(
+    &self,
+    start: i64,
+    limit: i64,
+    maybe_kind: Option<ReservedNameKind>,
+    maybe_search_query: Option<&str>,
+  ) -> Result<(usize, Vec<ReservedName>)> {
+    let mut tx = self.pool.begin().await?;
+
+    let search = format!("%{}%", maybe_search_query.unwrap_or(""));
+
+    let reserved_names = query_concat_as!(
+      ReservedName,
+      "SELECT ", RESERVED_NAME_SELECT, " FROM reserved_names
+      WHERE ($1::reserved_name_kind IS NULL OR kind = $1) AND name ILIKE $2
+      ORDER BY kind ASC, name ASC
+      OFFSET $3 LIMIT $4";
+      maybe_kind as _,
+      search,
+      start,
+      limit,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let total = sqlx::query!(
+      r#"SELECT COUNT(created_at) FROM reserved_names WHERE ($1::reserved_name_kind IS NULL OR kind = $1) AND name ILIKE $2;"#,
+      maybe_kind as _,
+      search,
+    )
+    .map(|r| r.count.unwrap())
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok((total as usize, reserved_names))
+  }
+
+  /// Reserve a name, or replace the reason and expiry of an existing
+  /// reservation.
+  #[instrument(name = "Database::upsert_reserved_name", skip(self), err)]
+  pub async fn upsert_reserved_name(
+    &self,
+    staff_id: &Uuid,
+    new_reserved_name: NewReservedName<'_>,
+  ) -> Result<ReservedName> {
+    let mut tx = self.pool.begin().await?;
+
+    let reserved_name = query_concat_as!(
+      ReservedName,
+      "INSERT INTO reserved_names (kind, name, reason, expires_at, created_by)
+      VALUES ($1, $2, $3, $4, $5)
+      ON CONFLICT (kind, name) DO UPDATE SET reason = $3, expires_at = $4
+      RETURNING ", RESERVED_NAME_SELECT;
+      new_reserved_name.kind as _,
+      new_reserved_name.name,
+      new_reserved_name.reason,
+      new_reserved_name.expires_at,
+      staff_id,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "reserve_name",
+      json!({
+        "kind": reserved_name.kind,
+        "name": reserved_name.name,
+        "reason": reserved_name.reason,
+        "expires_at": reserved_name.expires_at,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(reserved_name)
+  }
+
+  #[instrument(name = "Database::delete_reserved_name", skip(self), err)]
+  pub async fn delete_reserved_name(
+    &self,
+    staff_id: &Uuid,
+    kind: ReservedNameKind,
+    name: &str,
+  ) -> Result<Option<ReservedName>> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some(reserved_name) = query_concat_as!(
+      ReservedName,
+      "DELETE FROM reserved_names WHERE kind = $1 AND name = $2 RETURNING ", RESERVED_NAME_SELECT;
+      kind as _,
+      name,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "unreserve_name",
+      json!({
+        "kind": reserved_name.kind,
+        "name": reserved_name.name,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(reserved_name))
+  }
+
+  /// All rate limit tiers and overrides.
+  #[instrument(name = "Database::list_rate_limits", skip(self), err)]
+  pub async fn list_rate_limits(
+    &self,
+  ) -> Result<(
+    Vec<RateLimitTierLimit>,
+    Vec<ScopeRateLimit>,
+    Vec<TokenRateLimit>,
+  )> {
+    let tiers_fut = query_concat_as!(
+      RateLimitTierLimit,
+      "SELECT ", RATE_LIMIT_TIER_SELECT, " FROM rate_limit_tiers ORDER BY tier";
+    )
+    .fetch_all(&self.pool);
+    let scopes_fut = query_concat_as!(
+      ScopeRateLimit,
+      "SELECT ", SCOPE_RATE_LIMIT_SELECT, " FROM scope_rate_limits ORDER BY scope";
+    )
+    .fetch_all(&self.pool);
+    let tokens_fut = query_concat_as!(
+      TokenRateLimit,
+      "SELECT ", TOKEN_RATE_LIMIT_SELECT, " FROM token_rate_limits ORDER BY created_at";
+    )
+    .fetch_all(&self.pool);
+
+    tokio::try_join!(tiers_fut, scopes_fut, tokens_fut)
+  }
+
+  #[instrument(name = "Database::update_rate_limit_tier", skip(self), err)]
+  pub async fn update_rate_limit_tier(
+    &self,
+    staff_id: &Uuid,
+    tier: RateLimitTier,
+    requests_per_minute: i32,
+  ) -> Result<RateLimitTierLimit> {
+    let mut tx = self.pool.begin().await?;
+
+    let tier_limit = query_concat_as!(
+      RateLimitTierLimit,
+      "INSERT INTO rate_limit_tiers (tier, requests_per_minute) VALUES ($1, $2)
+      ON CONFLICT (tier) DO UPDATE SET requests_per_minute = $2
+      RETURNING ", RATE_LIMIT_TIER_SELECT;
+      tier as _,
+      requests_per_minute,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "update_rate_limit_tier",
+      json!({
+        "tier": tier_limit.tier,
+        "requests_per_minute": tier_limit.requests_per_minute,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(tier_limit)
+  }
+
+  #[instrument(name = "Database::upsert_scope_rate_limit", skip(self), err)]
+  pub async fn upsert_scope_rate_limit(
+    &self,
+    staff_id: &Uuid,
+    scope: &ScopeName,
+    requests_per_minute: i32,
+    reason: Option<&str>,
+  ) -> Result<ScopeRateLimit> {
+    let mut tx = self.pool.begin().await?;
+
+    let rate_limit = query_concat_as!(
+      ScopeRateLimit,
+      "INSERT INTO scope_rate_limits (scope, requests_per_minute, reason, created_by)
+      VALUES ($1, $2, $3, $4)
+      ON CONFLICT (scope) DO UPDATE SET requests_per_minute = $2, reason = $3
+      RETURNING ", SCOPE_RATE_LIMIT_SELECT;
+      scope as _,
+      requests_per_minute,
+      reason,
+      staff_id,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "update_scope_rate_limit",
+      json!({
+        "scope": rate_limit.scope,
+        "requests_per_minute": rate_limit.requests_per_minute,
+        "reason": rate_limit.reason,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(rate_limit)
+  }
+
+  #[instrument(name = "Database::get_scope_publish_limits", skip(self), err)]
+  pub async fn get_scope_publish_limits(
+    &self,
+    scope: &ScopeName,
+  ) -> Result<Option<ScopePublishLimits>> {
+    query_concat_as!(
+      ScopePublishLimits,
+      "SELECT ", SCOPE_PUBLISH_LIMITS_SELECT, " FROM scope_publish_limits
+      WHERE scope = $1";
+      scope as _,
+    )
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  /// Replaces the publish limit overrides of a scope.
+  #[instrument(name = "Database::upsert_scope_publish_limits", skip(self), err)]
+  pub async fn upsert_scope_publish_limits(
+    &self,
+    staff_id: &Uuid,
+    scope: &ScopeName,
+    new_limits: NewScopePublishLimits<'_>,
+  ) -> Result<ScopePublishLimits> {
+    let mut tx = self.pool.begin().await?;
+
+    let limits = query_concat_as!(
+      ScopePublishLimits,
+      "INSERT INTO scope_publish_limits (scope, max_tarball_size, max_file_size, max_total_file_size, max_file_count, max_path_depth, max_path_length, max_module_count, reason, created_by)
+      VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+      ON CONFLICT (scope) DO UPDATE SET max_tarball_size = $2,
+        max_file_size = $3, max_total_file_size = $4, max_file_count = $5,
+        max_path_depth = $6, max_path_length = $7, max_module_count = $8,
+        reason = $9
+      RETURNING ", SCOPE_PUBLISH_LIMITS_SELECT;
+      scope as _,
+      new_limits.max_tarball_size,
+      new_limits.max_file_size,
+      new_limits.max_total_file_size,
+      new_limits.max_file_count,
+      new_limits.max_path_depth,
+      new_limits.max_path_length,
+      new_limits.max_module_count,
+      new_limits.reason,
+      staff_id,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "update_scope_publish_limits",
+      json!({
+        "scope": limits.scope,
+        "max_tarball_size": limits.max_tarball_size,
+        "max_file_size": limits.max_file_size,
+        "max_total_file_size": limits.max_total_file_size,
+        "max_file_count": limits.max_file_count,
+        "max_path_depth": limits.max_path_depth,
+        "max_path_length": limits.max_path_length,
+        "max_module_count": limits.max_module_count,
+        "reason": limits.reason,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(limits)
+  }
+
+  #[instrument(name = "Database::delete_scope_publish_limits", skip(self), err)]
+  pub async fn delete_scope_publish_limits(
+    &self,
+    staff_id: &Uuid,
+    scope: &ScopeName,
+  ) -> Result<Option<ScopePublishLimits>> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some(limits) = query_concat_as!(
+      ScopePublishLimits,
+      "DELETE FROM scope_publish_limits WHERE scope = $1 RETURNING ", SCOPE_PUBLISH_LIMITS_SELECT;
+      scope as _,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "delete_scope_publish_limits",
+      json!({ "scope": limits.scope }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(limits))
+  }
+
+  #[instrument(name = "Database::delete_scope_rate_limit", skip(self), err)]
+  pub async fn delete_scope_rate_limit(
+    &self,
+    staff_id: &Uuid,
+    scope: &ScopeName,
+  ) -> Result<Option<ScopeRateLimit>> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some(rate_limit) = query_concat_as!(
+      ScopeRateLimit,
+      "DELETE FROM scope_rate_limits WHERE scope = $1 RETURNING ", SCOPE_RATE_LIMIT_SELECT;
+      scope as _,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "delete_scope_rate_limit",
+      json!({ "scope": rate_limit.scope }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(rate_limit))
+  }
+
+  /// Returns `None` if the token does not exist.
+  #[instrument(name = "Database::upsert_token_rate_limit", skip(self), err)]
+  pub async fn upsert_token_rate_limit(
+    &self,
+    staff_id: &Uuid,
+    token_id: Uuid,
+    requests_per_minute: i32,
+    reason: Option<&str>,
+  ) -> Result<Option<TokenRateLimit>> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some(rate_limit) = query_concat_as!(
+      TokenRateLimit,
+      "INSERT INTO token_rate_limits (token_id, requests_per_minute, reason, created_by)
+      SELECT id, $2, $3, $4 FROM tokens WHERE id = $1
+      ON CONFLICT (token_id) DO UPDATE SET requests_per_minute = $2, reason = $3
+      RETURNING ", TOKEN_RATE_LIMIT_SELECT;
+      token_id,
+      requests_per_minute,
+      reason,
+      staff_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "update_token_rate_limit",
+      json!({
+        "token_id": rate_limit.token_id,
+        "requests_per_minute": rate_limit.requests_per_minute,
+        "reason": rate_limit.reason,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(rate_limit))
+  }
+
+  #[instrument(name = "Database::delete_token_rate_limit", skip(self), err)]
+  pub async fn delete_token_rate_limit(
+    &self,
+    staff_id: &Uuid,
+    token_id: Uuid,
+  ) -> Result<Option<TokenRateLimit>> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some(rate_limit) = query_concat_as!(
+      TokenRateLimit,
+      "DELETE FROM token_rate_limits WHERE token_id = $1 RETURNING ", TOKEN_RATE_LIMIT_SELECT;
+      token_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "delete_token_rate_limit",
+      json!({ "token_id": rate_limit.token_id }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(rate_limit))
+  }
+
+  #[instrument(name = "Database::get_npm_tarball", skip(self), err)]
+  pub async fn get_npm_tarball(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+    version: &Version,
+    revision: i32,
+  ) -> Result<Option<NpmTarball>> {
+    query_concat_as!(
+      NpmTarball,
+      "SELECT ", NPM_TARBALL_SELECT, " FROM npm_tarballs
+      WHERE scope = $1 AND name = $2 AND version = $3 AND revision = $4
+      LIMIT 1";
+      scope as _,
+      name as _,
+      version as _,
+      revision,
+    )
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  #[cfg(test)]
+  #[instrument(
+    name = "Database::delete_npm_tarballs_for_version",
+    skip(self),
+    err
+  )]
+  pub async fn delete_npm_tarballs_for_version(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+    version: &Version,
+  ) -> Result<()> {
+    sqlx::query!(
+      "DELETE FROM npm_tarballs WHERE scope = $1 AND name = $2 AND version = $3",
+      scope as _,
+      name as _,
+      version as _,
+    )
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  #[instrument(name = "Database::list_missing_npm_tarballs", skip(self), err)]
+  pub async fn list_missing_npm_tarballs(
+    &self,
+    current_revision: i32,
+  ) -> Result<Vec<(ScopeName, PackageName, Version)>> {
+    // List all package versions (scope, name, version) that do not have a npm_tarball with the current_revision revision
+    sqlx::query!(
+      r#"SELECT scope as "scope: ScopeName", name as "name: PackageName", version as "version: Version"
+      FROM package_versions
+      WHERE deleted_at IS NULL AND NOT EXISTS (
+        SELECT 1
+        FROM npm_tarballs
+        WHERE npm_tarballs.scope = package_versions.scope AND npm_tarballs.name = package_versions.name AND npm_tarballs.version = package_versions.version AND npm_tarballs.revision = $1
+      )
+      ORDER BY created_at ASC
+      LIMIT 1000
+      "#,
+      current_revision,
+    )
+      .map(|r| {
+        (r.scope, r.name, r.version)
+      })
+      .fetch_all(&self.pool)
+      .await
+  }
+
+  #[instrument(name = "Database::list_all_scopes_for_sitemap", skip(self), err)]
+  #[allow(clippy::type_complexity)]
+  pub async fn list_all_scopes_for_sitemap(
+    &self,
+  ) -> Result<Vec<(ScopeName, DateTime<Utc>, Option<DateTime<Utc>>)>> {
+    sqlx::query!(
+      r#"
+        SELECT
+          scope as "scope: ScopeName",
+          updated_at,
+          (SELECT updated_at FROM packages WHERE scope = scope AND deleted_at IS NULL ORDER BY updated_At DESC LIMIT 1) as "latest_package_created_at"
+        FROM scopes
+        ORDER BY scope ASC
+        LIMIT 50000
+      "#
+    )
+      .map(|r| (r.scope, r.updated_at, r.latest_package_created_at))
+      .fetch_all(&self.pool)
+      .await
+  }
+
+  #[instrument(
+    name = "Database::list_all_packages_for_sitemap",
+    skip(self),
+    err
+  )]
+  #[allow(clippy::type_complexity)]
+  pub async fn list_all_packages_for_sitemap(
+    &self,
+  ) -> Result<Vec<(ScopeName, PackageName, DateTime<Utc>, DateTime<Utc>)>> {
+    sqlx::query!(
+      r#"SELECT
+        scope as "scope: ScopeName", name as "name: PackageName", updated_at,
+        (SELECT created_at FROM package_versions WHERE scope = scope AND name = name AND deleted_at IS NULL ORDER BY version DESC LIMIT 1) as "latest_version_updated_at!"
+      FROM packages
+      WHERE (SELECT version FROM package_versions WHERE scope = scope AND name = name AND deleted_at IS NULL ORDER BY version DESC LIMIT 1) IS NOT NULL
+        AND visibility = 'public'
+      ORDER BY scope ASC, name ASC
+      LIMIT 50000"#
+    )
+      .map(|r| {
+        (
+          r.scope,
+          r.name,
+          r.updated_at,
+          r.latest_version_updated_at,
+        )
+      })
+      .fetch_all(&self.pool)
+      .await
+  }
+
+  #[instrument(
+    name = "Database::insert_download_entries",
+    skip(self, entries),
+    err
+  )]
+  pub async fn insert_download_entries(
+    &self,
+    entries: Vec<VersionDownloadCount>,
+  ) -> Result<()> {
+    if entries.is_empty() {
+      return Ok(());
+    }
+
+    let mut tx = self.pool.begin().await?;
+
+    let mut scopes = Vec::with_capacity(entries.len());
+    let mut packages = Vec::with_capacity(entries.len());
+    let mut versions = Vec::with_capacity(entries.len());
+    let mut time_buckets = Vec::with_capacity(entries.len());
+    let mut kinds = Vec::with_capacity(entries.len());
+    let mut counts = Vec::with_capacity(entries.len());
+
+    let mut smallest_time_bucket = Utc::now();
+    let mut largest_time_bucket = DateTime::from_timestamp_nanos(0);
+
+    for entry in entries {
+      scopes.push(entry.scope);
+      packages.push(entry.package);
+      versions.push(entry.version);
+      time_buckets.push(entry.time_bucket);
+      kinds.push(entry.kind);
+      counts.push(entry.count);
+
+      if entry.time_bucket < smallest_time_bucket {
+        smallest_time_bucket = entry.time_bucket;
+      }
+      if entry.time_bucket > largest_time_bucket {
+        largest_time_bucket = entry.time_bucket;
+      }
+    }
+
+    // Upsert data into version_download_counts_4h
+    sqlx::query!(
+      r#"
+      INSERT INTO version_download_counts_4h (scope, package, version, time_bucket, kind, count)
+      SELECT * FROM UNNEST($1::TEXT[], $2::TEXT[], $3::TEXT[], $4::TIMESTAMPTZ[], $5::download_kind[], $6::INT[]) as temp(scope, package, version, time_bucket, kind, count)
+      WHERE (SELECT COUNT(*) FROM package_versions WHERE package_versions.scope = temp.scope AND package_versions.name = temp.package AND version = temp.version) > 0
+      ON CONFLICT (scope, package, version, time_bucket, kind) DO UPDATE SET count = EXCLUDED.count
+      "#,
+      &scopes as _,
+      &packages as _,
+      &versions as _,
+      &time_buckets,
+      &kinds as _,
+      &counts as _,
+    )
+      .execute(&mut *tx)
+      .await?;
+
+    // Compute data in version_download_counts_24h from version_download_counts_4h between smallest_timestamp and largest_timestamp.
+    // smallest_timestamp must be truncated down to the nearest day and largest_timestamp must be truncated up to the nearest day.
+    sqlx::query!(
+      r#"
+      INSERT INTO version_download_counts_24h (scope, package, version, time_bucket, kind, count)
+      SELECT scope, package, version, date_trunc('day', time_bucket), kind, SUM(count)
+      FROM version_download_counts_4h
+      WHERE time_bucket >= date_trunc('day', $1::timestamptz) AND time_bucket < date_trunc('day', $2::timestamptz) + interval '1 day'
+      GROUP BY scope, package, version, date_trunc('day', time_bucket), kind
+      ON CONFLICT (scope, package, version, time_bucket, kind) DO UPDATE SET count = EXCLUDED.count
+      "#,
+      smallest_time_bucket,
+      largest_time_bucket,
+    )
+      .execute(&mut *tx)
+      .await?;
+
+    // Aggregate version-level 24h counts into package-level 24h counts.
+    // Derived from version_download_counts_24h (not 4h) so that the
+    // package-level rollup stays correct even after 4h rows are pruned.
+    sqlx::query!(
+      r#"
+      INSERT INTO package_download_counts_24h (scope, package, time_bucket, kind, count)
+      SELECT scope, package, time_bucket, kind, SUM(count)
+      FROM version_download_counts_24h
+      WHERE time_bucket >= date_trunc('day', $1::timestamptz) AND time_bucket < date_trunc('day', $2::timestamptz) + interval '1 day'
+      GROUP BY scope, package, time_bucket, kind
+      ON CONFLICT (scope, package, time_bucket, kind) DO UPDATE SET count = EXCLUDED.count
+      "#,
+      smallest_time_bucket,
+      largest_time_bucket,
+    )
+      .execute(&mut *tx)
+      .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+  }
+
+  #[instrument(
+    name = "Database::get_package_version_downloads_24h",
+    skip(self),
+    err
+  )]
+  pub async fn get_package_versions_downloads_24h(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+    versions: &[Version],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+  ) -> Result<Vec<VersionDownloadDataPoint>> {
+    sqlx::query_as!(
+      VersionDownloadDataPoint,
+      r#"
+      SELECT version as "version: Version", time_bucket, kind as "kind: DownloadKind", count
+      FROM version_download_counts_24h
+      WHERE scope = $1 AND package = $2 AND version = ANY($3) AND time_bucket >= $4 AND time_bucket < $5
+      ORDER BY time_bucket ASC, version DESC
+      "#,
+      scope as _,
+      name as _,
+      versions as _,
+      start,
+      end,
+    )
+      .fetch_all(&self.pool)
+      .await
+  }
+
+  #[instrument(name = "Database::get_package_downloads_24h", skip(self), err)]
+  pub async fn get_package_downloads_24h(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+  ) -> Result<Vec<DownloadDataPoint>> {
+    sqlx::query_as!(
+      DownloadDataPoint,
+      r#"
+    SELECT time_bucket, kind as "kind: DownloadKind", count as "count!"
+    FROM package_download_counts_24h
+    WHERE scope = $1 AND package = $2 AND time_bucket >= $3 AND time_bucket < $4
+    ORDER BY time_bucket ASC
+    "#,
+      scope as _,
+      name as _,
+      start,
+      end,
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  /// Upserts module fetches into the 4h entrypoint download counts, keeping
+  /// only fetches of the entrypoints of each version, and recomputes the
+  /// affected 24h buckets.
+  #[instrument(
+    name = "Database::insert_entrypoint_download_entries",
+    skip(self, entries),
+    err
+  )]
+  pub async fn insert_entrypoint_download_entries(
+    &self,
+    entries: Vec<ModuleDownloadCount>,
+  ) -> Result<()> {
+    if entries.is_empty() {
+      return Ok(());
+    }
+
+    let mut tx = self.pool.begin().await?;
+
+    let mut scopes = Vec::with_capacity(entries.len());
+    let mut packages = Vec::with_capacity(entries.len());
+    let mut versions = Vec::with_capacity(entries.len());
+    let mut paths = Vec::with_capacity(entries.len());
+    let mut time_buckets = Vec::with_capacity(entries.len());
+    let mut counts = Vec::with_capacity(entries.len());
+
+    let mut smallest_time_bucket = Utc::now();
+    let mut largest_time_bucket = DateTime::from_timestamp_nanos(0);
+
+    for entry in entries {
+      scopes.push(entry.scope);
+      packages.push(entry.package);
+      versions.push(entry.version);
+      paths.push(entry.path);
+      time_buckets.push(entry.time_bucket);
+      counts.push(entry.count);
+
+      if entry.time_bucket < smallest_time_bucket {
+        smallest_time_bucket = entry.time_bucket;
+      }
+      if entry.time_bucket > largest_time_bucket {
+        largest_time_bucket = entry.time_bucket;
+      }
+    }
+
+    // A module is an entrypoint if an export of its version points to it.
+    sqlx::query!(
+      r#"
+      INSERT INTO entrypoint_download_counts_4h (scope, package, version, entrypoint, time_bucket, count)
+      SELECT temp.scope, temp.package, temp.version, exports.key, temp.time_bucket, SUM(temp.count)::INT
+      FROM UNNEST($1::TEXT[], $2::TEXT[], $3::TEXT[], $4::TEXT[], $5::TIMESTAMPTZ[], $6::INT[]) as temp(scope, package, version, path, time_bucket, count)
+      JOIN package_versions ON package_versions.scope = temp.scope AND package_versions.name = temp.package AND package_versions.version = temp.version
+      JOIN LATERAL jsonb_each_text(package_versions.exports) exports ON exports.value = '.' || temp.path
+      GROUP BY temp.scope, temp.package, temp.version, exports.key, temp.time_bucket
+      ON CONFLICT (scope, package, version, entrypoint, time_bucket) DO UPDATE SET count = EXCLUDED.count
+      "#,
+      &scopes as _,
+      &packages as _,
+      &versions as _,
+      &paths,
+      &time_buckets,
+      &counts as _,
+    )
+      .execute(&mut *tx)
+      .await?;
+
+    sqlx::query!(
+      r#"
+      INSERT INTO entrypoint_download_counts_24h (scope, package, version, entrypoint, time_bucket, count)
+      SELECT scope, package, version, entrypoint, date_trunc('day', time_bucket), SUM(count)
+      FROM entrypoint_download_counts_4h
+      WHERE time_bucket >= date_trunc('day', $1::timestamptz) AND time_bucket < date_trunc('day', $2::timestamptz) + interval '1 day'
+      GROUP BY scope, package, version, entrypoint, date_trunc('day', time_bucket)
+      ON CONFLICT (scope, package, version, entrypoint, time_bucket) DO UPDATE SET count = EXCLUDED.count
+      "#,
+      smallest_time_bucket,
+      largest_time_bucket,
+    )
+      .execute(&mut *tx)
+      .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+  }
+
+  /// Daily downloads of each entrypoint of a package, summed over all of its
+  /// versions.
+  #[instrument(
+    name = "Database::get_package_entrypoint_downloads_24h",
+    skip(self),
+    err
+  )]
+  pub async fn get_package_entrypoint_downloads_24h(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+  ) -> Result<Vec<EntrypointDownloadDataPoint>> {
+    sqlx::query_as!(
+      EntrypointDownloadDataPoint,
+      r#"
+      SELECT entrypoint, time_bucket, SUM(count) as "count!"
+      FROM entrypoint_download_counts_24h
+      WHERE scope = $1 AND package = $2 AND time_bucket >= $3 AND time_bucket < $4
+      GROUP BY entrypoint, time_bucket
+      ORDER BY time_bucket ASC, entrypoint ASC
+      "#,
+      scope as _,
+      name as _,
+      start,
+      end,
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::get_package_downloads_total", skip(self), err)]
+  pub async fn get_package_downloads_total(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+    start: DateTime<Utc>,
+  ) -> Result<i64> {
+    let row = sqlx::query!(
+      r#"
+      SELECT COALESCE(SUM(count), 0) as "total!"
+      FROM package_download_counts_24h
+      WHERE scope = $1 AND package = $2 AND time_bucket >= $3
+      "#,
+      scope as _,
+      name as _,
+      start,
+    )
+    .fetch_one(&self.pool)
+    .await?;
+    Ok(row.total)
+  }
+
+  #[instrument(name = "Database::create_ticket", skip(self), err)]
+  pub async fn create_ticket(
+    &self,
+    user_id: Uuid,
+    new_ticket: NewTicket,
+  ) -> Result<(Ticket, User, TicketMessage)> {
+    let mut tx = self.pool.begin().await?;
+
+    let (ticket, user) = query_concat!(
+      "WITH ticket AS (
+          INSERT INTO tickets (kind, creator, meta)
+          VALUES ($1, $2, $3)
+          RETURNING id, kind, creator, meta, closed, updated_at, created_at
+        )
+        SELECT
+            ticket.id as \"ticket_id\",
+            ticket.kind as \"ticket_kind: TicketKind\",
+            ticket.creator as \"ticket_creator\",
+            ticket.meta as \"ticket_meta\",
+            ticket.closed as \"ticket_closed\",
+            ticket.updated_at as \"ticket_updated_at\",
+            ticket.created_at as \"ticket_created_at\",
+            ", USER_SELECT_FULL_JOINED, "
+        FROM ticket
+        INNER JOIN users ON users.id = ticket.creator
+    ";
+      new_ticket.kind as _,
+      user_id as _,
+      new_ticket.meta as _,
+    )
+    .map(|r| {
+      let ticket = Ticket {
+        id: r.ticket_id,
+        kind: r.ticket_kind,
+        creator: r.ticket_creator,
+        meta: r.ticket_meta,
+        closed: r.ticket_closed,
+        updated_at: r.ticket_updated_at,
+        created_at: r.ticket_created_at,
+      };
+
+      let user = User {
+        id: r.user_id,
+        name: r.user_name,
+        email: r.user_email,
+        avatar_url: r.user_avatar_url,
+        github_id: r.user_github_id,
+        gitlab_id: r.user_gitlab_id,
+        is_blocked: r.user_is_blocked,
+        is_staff: r.user_is_staff,
+        scope_usage: r.user_scope_usage,
+        scope_limit: r.user_scope_limit,
+        invite_count: r.user_invite_count,
+        newer_ticket_messages_count: r.user_newer_ticket_messages_count,
+        updated_at: r.user_updated_at,
+        created_at: r.user_created_at,
+      };
+
+      (ticket, user)
+    })
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let message = sqlx::query!(
+      r#"INSERT INTO ticket_messages (ticket_id, author, message)
+          VALUES ($1, $2, $3)
+          RETURNING ticket_id, author, message, updated_at, created_at
+    "#,
+      ticket.id as _,
+      user_id as _,
+      new_ticket.message as _,
+    )
+    .map(|r| TicketMessage {
+      ticket_id: r.ticket_id,
+      author: r.author,
+      message: r.message,
+      updated_at: r.updated_at,
+      created_at: r.created_at,
+    })
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok((ticket, user, message))
+  }
+
+  #[instrument(name = "Database::list_tickets", skip(self), err)]
+  pub async fn list_tickets(
+    &self,
+    start: i64,
+    limit: i64,
+    maybe_search_query: Option<&str>,
+    maybe_sort: Option<&str>,
+  ) -> Result<(usize, Vec<FullTicket>)> {
+    let mut tx = self.pool.begin().await?;
+
+    let search = format!("%{}%", maybe_search_query.unwrap_or(""));
+    let sort = sort_by!(maybe_sort => {
+      @timestamps "updated_at", "created_at";
+      "kind" => "tickets.kind",
+      "creator" => "users.name",
+      "closed" => "tickets.closed",
+      "updated_at" => "tickets.updated_at",
+      "created_at" => "tickets.created_at",
+    } || "tickets.closed ASC, tickets.created_at DESC");
+
+    let tickets = sqlx::query(&format!(
+      r#"SELECT {}, {} FROM tickets
+    INNER JOIN users ON users.id = tickets.creator
+    WHERE users.name ILIKE $1
+       OR EXISTS (
+         SELECT 1
+         FROM ticket_messages
+         WHERE ticket_messages.ticket_id = tickets.id
+           AND ticket_messages.message ILIKE $1
+       )
+    ORDER BY {sort} OFFSET $2 LIMIT $3
+"#,
+      crate::db::sql_fragments::TICKET_SELECT_JOINED_RT,
+      crate::db::sql_fragments::USER_SELECT_FULL_JOINED_RT,
+    ))
+    .bind(&search)
+    .bind(start)
+    .bind(limit)
+    .try_map(|r| {
+      let ticket = Ticket::from_row(&r)?;
+      let user = User::from_row(&r)?;
+
+      Ok((ticket, user))
+    })
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut out = Vec::with_capacity(tickets.len());
+    for (ticket, user) in tickets {
+      let messages = query_concat!(
+      "SELECT ", TICKET_MESSAGE_SELECT_JOINED, ", ", USER_PUBLIC_SELECT_JOINED_RT, "
+        FROM ticket_messages
+        LEFT JOIN users ON users.id = ticket_messages.author
+        WHERE ticket_messages.ticket_id = $1 ORDER BY ticket_messages.created_at";
+      ticket.id as _,
+    )
+        .map(|r| {
+          let message = TicketMessage {
+            ticket_id: r.message_ticket_id,
+            author: r.message_author,
+            message: r.message_message,
+            updated_at: r.message_updated_at,
+            created_at: r.message_created_at,
+          };
+
+          let user = UserPublic {
+            id: r.user_id,
+            name: r.user_name,
+            avatar_url: r.user_avatar_url,
+            github_id: r.user_github_id,
+gitlab_id: r.user_gitlab_id,
+            updated_at: r.user_updated_at,
+            created_at: r.user_created_at,
+          };
+
+          (message, user)
+        })
+        .fetch_all(&mut *tx)
+        .await?;
+
+      out.push((ticket, user, messages));
+    }
+
+    let total_tickets = sqlx::query!(
+      r#"SELECT COUNT(tickets.created_at) FROM tickets
+    LEFT JOIN users ON users.id = tickets.creator
+    WHERE users.name ILIKE $1
+       OR EXISTS (
+         SELECT 1
+         FROM ticket_messages
+         WHERE ticket_messages.ticket_id = tickets.id
+           AND ticket_messages.message ILIKE $1
+       )"#,
+      search,
+    )
+    .map(|r| r.count.unwrap())
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok((total_tickets as usize, out))
+  }
+
+  #[instrument(name = "Database::list_tickets_for_user", skip(self), err)]
+  pub async fn list_tickets_for_user(
+    &self,
+    user_id: Uuid,
+  ) -> Result<Vec<FullTicket>> {
+    let mut tx = self.pool.begin().await?;
+
+    let tickets = query_concat!(
+      "SELECT ", TICKET_SELECT_JOINED, ", ", USER_SELECT_FULL_JOINED, "
+    FROM tickets
+    INNER JOIN users ON users.id = tickets.creator
+    WHERE tickets.creator = $1
+    ORDER BY tickets.closed ASC, tickets.created_at DESC
+";
+      user_id as _,
+    )
+    .map(|r| {
+      let ticket = Ticket {
+        id: r.ticket_id,
+        kind: r.ticket_kind,
+        creator: r.ticket_creator,
+        meta: r.ticket_meta,
+        closed: r.ticket_closed,
+        updated_at: r.ticket_updated_at,
+        created_at: r.ticket_created_at,
+      };
+
+      let user = User {
+        id: r.user_id,
+        name: r.user_name,
+        email: r.user_email,
+        avatar_url: r.user_avatar_url,
+        github_id: r.user_github_id,
+        gitlab_id: r.user_gitlab_id,
+        is_blocked: r.user_is_blocked,
+        is_staff: r.user_is_staff,
+        scope_usage: r.user_scope_usage,
+        scope_limit: r.user_scope_limit,
+        invite_count: r.user_invite_count,
+        newer_ticket_messages_count: r.user_newer_ticket_messages_count,
+        updated_at: r.user_updated_at,
+        created_at: r.user_created_at,
+      };
+
+      (ticket, user)
+    })
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut out = Vec::with_capacity(tickets.len());
+    for (ticket, user) in tickets {
+      let messages = query_concat!(
+      "SELECT
+            ", TICKET_MESSAGE_SELECT_JOINED, ",
+            ", USER_PUBLIC_SELECT_JOINED_RT, "
+        FROM ticket_messages
+        INNER JOIN users ON users.id = ticket_messages.author
+        WHERE ticket_messages.ticket_id = $1 ORDER BY ticket_messages.created_at";
+      ticket.id as _,
+    )
+        .map(|r| {
+          let message = TicketMessage {
+            ticket_id: r.message_ticket_id,
+            author: r.message_author,
+            message: r.message_message,
+            updated_at: r.message_updated_at,
+            created_at: r.message_created_at,
+          };
+
+          let user = UserPublic {
+            id: r.user_id,
+            name: r.user_name,
+            avatar_url: r.user_avatar_url,
+            github_id: r.user_github_id,
+gitlab_id: r.user_gitlab_id,
+            updated_at: r.user_updated_at,
+            created_at: r.user_created_at,
+          };
+
+          (message, user)
+        })
+        .fetch_all(&mut *tx)
+        .await?;
+
+      out.push((ticket, user, messages));
+    }
+
+    tx.commit().await?;
+
+    Ok(out)
+  }
+
+  #[instrument(name = "Database::get_ticket", skip(self), err)]
+  pub async fn get_ticket(
+    &self,
+    ticket_id: Uuid,
+  ) -> Result<Option<FullTicket>> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some((ticket, user)) = query_concat!(
+      "SELECT
+            ", TICKET_SELECT_JOINED, ",
+            ", USER_SELECT_FULL_JOINED, "
+        FROM tickets
+        INNER JOIN users ON users.id = tickets.creator
+        WHERE tickets.id = $1";
+      ticket_id as _,
+    )
+    .map(|r| {
+      let ticket = Ticket {
+        id: r.ticket_id,
+        kind: r.ticket_kind,
+        creator: r.ticket_creator,
+        meta: r.ticket_meta,
+        closed: r.ticket_closed,
+        updated_at: r.ticket_updated_at,
+        created_at: r.ticket_created_at,
+      };
+
+      let user = User {
+        id: r.user_id,
+        name: r.user_name,
+        email: r.user_email,
+        avatar_url: r.user_avatar_url,
+        github_id: r.user_github_id,
+        gitlab_id: r.user_gitlab_id,
+        is_blocked: r.user_is_blocked,
+        is_staff: r.user_is_staff,
+        scope_usage: r.user_scope_usage,
+        scope_limit: r.user_scope_limit,
+        invite_count: r.user_invite_count,
+        newer_ticket_messages_count: r.user_newer_ticket_messages_count,
+        updated_at: r.user_updated_at,
+        created_at: r.user_created_at,
+      };
+
+      (ticket, user)
+    })
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
+    let messages = query_concat!(
+      "SELECT
+            ", TICKET_MESSAGE_SELECT_JOINED, ",
+            ", USER_PUBLIC_SELECT_JOINED_RT, "
+        FROM ticket_messages
+        INNER JOIN users ON users.id = ticket_messages.author
+        WHERE ticket_messages.ticket_id = $1 ORDER BY ticket_messages.created_at";
+      ticket_id as _,
+    )
+      .map(|r| {
+        let message = TicketMessage {
+          ticket_id: r.message_ticket_id,
+          author: r.message_author,
+          message: r.message_message,
+          updated_at: r.message_updated_at,
+          created_at: r.message_created_at,
+        };
+
+        let user = UserPublic {
+          id: r.user_id,
+          name: r.user_name,
+          avatar_url: r.user_avatar_url,
+          github_id: r.user_github_id,
+gitlab_id: r.user_gitlab_id,
+          updated_at: r.user_updated_at,
+          created_at: r.user_created_at,
+        };
+
+        (message, user)
+      })
+      .fetch_all(&mut *tx)
+      .await?;
+
+    tx.commit().await?;
+
+    Ok(Some((ticket, user, messages)))
+  }
+
+  #[instrument(name = "Database::get_ticket_audit_logs", skip(self), err)]
+  pub async fn get_ticket_audit_logs(
+    &self,
+    ticket_id: Uuid,
+  ) -> Result<Vec<(AuditLog, UserPublic)>> {
+    let mut tx = self.pool.begin().await?;
+
+    let audit_logs = query_concat!(
+      "SELECT
+          ", AUDIT_LOG_SELECT_JOINED, ",
+          ", USER_PUBLIC_SELECT_JOINED_RT, "
+        FROM
+          audit_logs
+        LEFT JOIN
+          users ON audit_logs.actor_id = users.id
+        WHERE
+          audit_logs.meta::text LIKE $1
+        ORDER BY audit_logs.created_at DESC;
+        ";
+      format!("%\"ticket_id\": \"{}\"%", ticket_id),
+    )
+    .map(|r| {
+      let audit_log = AuditLog {
+        actor_id: r.audit_log_actor_id,
+        is_sudo: r.audit_log_is_sudo,
+        action: r.audit_log_action,
+        meta: r.audit_log_meta,
+        created_at: r.audit_log_created_at,
+      };
+
+      let user = UserPublic {
+        id: r.user_id,
+        name: r.user_name,
+        avatar_url: r.user_avatar_url,
+        github_id: r.user_github_id,
+        gitlab_id: r.user_gitlab_id,
+        updated_at: r.user_updated_at,
+        created_at: r.user_created_at,
+      };
+
+      (audit_log, user)
+    })
+    .fetch_all(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(audit_logs)
+  }
+
+  #[instrument(name = "Database::ticket_add_message", skip(self), err)]
+  pub async fn ticket_add_message(
+    &self,
+    id: Uuid,
+    author: Uuid,
+    message: NewTicketMessage,
+  ) -> Result<(TicketMessage, UserPublic)> {
+    let mut tx = self.pool.begin().await?;
+
+    let message = query_concat!(
+      "WITH message AS (
+          INSERT INTO ticket_messages (ticket_id, author, message)
+          VALUES ($1, $2, $3)
+          RETURNING ticket_id, author, message, updated_at, created_at
+        )
+        SELECT
+            message.ticket_id as \"message_ticket_id\",
+            message.author as \"message_author\",
+            message.message as \"message_message\",
+            message.updated_at as \"message_updated_at\",
+            message.created_at as \"message_created_at\",
+            ", USER_PUBLIC_SELECT_JOINED_RT, "
+        FROM message
+        INNER JOIN users ON users.id = message.author
+    ";
+      id as _,
+      author as _,
+      message.message as _,
+    )
+    .map(|r| {
+      let message = TicketMessage {
+        ticket_id: r.message_ticket_id,
+        author: r.message_author,
+        message: r.message_message,
+        updated_at: r.message_updated_at,
+        created_at: r.message_created_at,
+      };
+
+      let user = UserPublic {
+        id: r.user_id,
+        name: r.user_name,
+        avatar_url: r.user_avatar_url,
+        github_id: r.user_github_id,
+        gitlab_id: r.user_gitlab_id,
+        updated_at: r.user_updated_at,
+        created_at: r.user_created_at,
+      };
+
+      (message, user)
+    })
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+      r#"UPDATE tickets SET updated_at = now() WHERE id = $1"#,
+      id as _
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(message)
+  }
+
+  #[instrument(name = "Database::update_ticket", skip(self), err)]
+  pub async fn update_ticket_closed(
+    &self,
+    staff_id: &Uuid,
+    ticket_id: Uuid,
+    closed: bool,
+  ) -> Result<FullTicket> {
+    let mut tx = self.pool.begin().await?;
+
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "update_ticket_status",
+      json!({
+      "ticket_id": ticket_id,
+      "closed": closed,
+      }),
+    )
+    .await?;
+
+    let (ticket, user) = query_concat!(
+      "WITH ticket AS (
+          UPDATE tickets SET closed = $1 WHERE id = $2
+          RETURNING id, kind, creator, meta, closed, updated_at, created_at
+        )
+        SELECT
+            ticket.id as \"ticket_id\",
+            ticket.kind as \"ticket_kind: TicketKind\",
+            ticket.creator as \"ticket_creator\",
+            ticket.meta as \"ticket_meta\",
+            ticket.closed as \"ticket_closed\",
+            ticket.updated_at as \"ticket_updated_at\",
+            ticket.created_at as \"ticket_created_at\",
+            ", USER_SELECT_FULL_JOINED, "
+        FROM ticket
+        INNER JOIN users ON users.id = ticket.creator
+    ";
+      closed as _,
+      ticket_id as _,
+    )
+    .map(|r| {
+      let ticket = Ticket {
+        id: r.ticket_id,
+        kind: r.ticket_kind,
+        creator: r.ticket_creator,
+        meta: r.ticket_meta,
+        closed: r.ticket_closed,
+        updated_at: r.ticket_updated_at,
+        created_at: r.ticket_created_at,
+      };
+
+      let user = User {
+        id: r.user_id,
+        name: r.user_name,
+        email: r.user_email,
+        avatar_url: r.user_avatar_url,
+        github_id: r.user_github_id,
+        gitlab_id: r.user_gitlab_id,
+        is_blocked: r.user_is_blocked,
+        is_staff: r.user_is_staff,
+        scope_usage: r.user_scope_usage,
+        scope_limit: r.user_scope_limit,
+        invite_count: r.user_invite_count,
+        newer_ticket_messages_count: r.user_newer_ticket_messages_count,
+        updated_at: r.user_updated_at,
+        created_at: r.user_created_at,
+      };
+
+      (ticket, user)
+    })
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let messages = query_concat!(
+      "SELECT
+            ", TICKET_MESSAGE_SELECT_JOINED, ",
+            ", USER_PUBLIC_SELECT_JOINED_RT, "
+        FROM ticket_messages
+        INNER JOIN users ON users.id = ticket_messages.author
+        WHERE ticket_messages.ticket_id = $1 ORDER BY ticket_messages.created_at";
+      ticket_id as _,
+    )
+      .map(|r| {
+        let message = TicketMessage {
+          ticket_id: r.message_ticket_id,
+          author: r.message_author,
+          message: r.message_message,
+          updated_at: r.message_updated_at,
+          created_at: r.message_created_at,
+        };
+
+        let user = UserPublic {
+          id: r.user_id,
+          name: r.user_name,
+          avatar_url: r.user_avatar_url,
+          github_id: r.user_github_id,
+gitlab_id: r.user_gitlab_id,
+          updated_at: r.user_updated_at,
+          created_at: r.user_created_at,
+        };
+
+        (message, user)
+      })
+      .fetch_all(&mut *tx)
+      .await?;
+
+    tx.commit().await?;
+
+    Ok((ticket, user, messages))
+  }
+
+  #[allow(clippy::type_complexity)]
+  #[instrument(name = "Database::list_audit_logs", skip(self), err)]
+  pub async fn list_audit_logs(
+    &self,
+    start: i64,
+    limit: i64,
+    maybe_search_query: Option<&str>,
+    maybe_sort: Option<&str>,
+    sudo_only: bool,
+  ) -> Result<(usize, Vec<(AuditLog, UserPublic)>)> {
+    let mut tx = self.pool.begin().await?;
+
+    let search = format!("%{}%", maybe_search_query.unwrap_or(""));
+    let sort = sort_by!(maybe_sort => {
+      @timestamps "created_at";
+      "action" => "audit_logs.action",
+      "user" => "users.name",
+      "created_at" => "audit_logs.created_at",
+    } || "audit_logs.created_at DESC");
+
+    let scopes = sqlx::query(&format!(
+      r#"SELECT
+      {}, {}
+      FROM audit_logs
+      LEFT JOIN users ON audit_logs.actor_id = users.id
+      WHERE (audit_logs.action ILIKE $1
+         OR users.name ILIKE $1
+         OR audit_logs.meta::text ILIKE $1)
+         AND ($2 IS NOT TRUE OR audit_logs.is_sudo = TRUE)
+      ORDER BY {sort} OFFSET $3 LIMIT $4
+      "#,
+      crate::db::sql_fragments::AUDIT_LOG_SELECT_JOINED,
+      crate::db::sql_fragments::USER_PUBLIC_SELECT_JOINED_RT,
+    ))
+    .bind(&search)
+    .bind(sudo_only)
+    .bind(start)
+    .bind(limit)
+    .try_map(|r| {
+      let audit_log = AuditLog::from_row(&r)?;
+      let user = UserPublic::from_row(&r)?;
+
+      Ok((audit_log, user))
+    })
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let total_scopes = sqlx::query!(
+      r#"SELECT COUNT(audit_logs.created_at) FROM audit_logs LEFT JOIN users ON audit_logs.actor_id = users.id WHERE audit_logs.action ILIKE $1 OR users.name ILIKE $2 AND ($3 IS NOT TRUE OR audit_logs.is_sudo = TRUE);"#,
+      search,
+      search,
+      sudo_only,
+    )
+      .map(|r| r.count.unwrap())
+      .fetch_one(&mut *tx)
+      .await?;
+
+    tx.commit().await?;
+
+    Ok((total_scopes as usize, scopes))
+  }
+
+  /// Lists the audit logs of actions taken within a scope, newest first. This
+  /// includes packages that were moved into the scope.
+  #[instrument(name = "Database::list_scope_audit_logs", skip(self), err)]
+  pub async fn list_scope_audit_logs(
+    &self,
+    scope: &ScopeName,
+    start: i64,
+    limit: i64,
+    action: Option<&str>,
+    actor_id: Option<Uuid>,
+    package: Option<&PackageName>,
+  ) -> Result<(usize, Vec<(AuditLog, UserPublic)>)> {
+    let mut tx = self.pool.begin().await?;
+
+    let audit_logs = query_concat!(
+      "SELECT ", AUDIT_LOG_SELECT_JOINED, ", ", USER_PUBLIC_SELECT_JOINED_RT, "
+      FROM audit_logs
+      JOIN users ON audit_logs.actor_id = users.id
+      WHERE (audit_logs.meta->>'scope' = $1 OR audit_logs.meta->>'target_scope' = $1)
+        AND ($2::text IS NULL OR audit_logs.action = $2)
+        AND ($3::uuid IS NULL OR audit_logs.actor_id = $3)
+        AND ($4::text IS NULL OR audit_logs.meta->>'name' = $4)
+      ORDER BY audit_logs.created_at DESC
+      OFFSET $5 LIMIT $6";
+      scope as _,
+      action,
+      actor_id,
+      package as _,
+      start,
+      limit,
+    )
+    .map(|r| {
+      let audit_log = AuditLog {
+        actor_id: r.audit_log_actor_id,
+        is_sudo: r.audit_log_is_sudo,
+        action: r.audit_log_action,
+        meta: r.audit_log_meta,
+        created_at: r.audit_log_created_at,
+      };
+
+      let user = UserPublic {
+        id: r.user_id,
+        name: r.user_name,
+        avatar_url: r.user_avatar_url,
+        github_id: r.user_github_id,
+        gitlab_id: r.user_gitlab_id,
+        updated_at: r.user_updated_at,
+        created_at: r.user_created_at,
+      };
+
+      (audit_log, user)
+    })
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let total = sqlx::query!(
+      r#"SELECT COUNT(created_at) as "count!" FROM audit_logs
+      WHERE (meta->>'scope' = $1 OR meta->>'target_scope' = $1)
+        AND ($2::text IS NULL OR action = $2)
+        AND ($3::uuid IS NULL OR actor_id = $3)
+        AND ($4::text IS NULL OR meta->>'name' = $4)"#,
+      scope as _,
+      action,
+      actor_id,
+      package as _,
+    )
+    .map(|r| r.count)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok((total as usize, audit_logs))
+  }
+
+  #[instrument(name = "Database::get_latest_score_schema", skip(self), err)]
+  pub async fn get_latest_score_schema(&self) -> Result<Option<ScoreSchema>> {
+    sqlx::query_as!(
+      ScoreSchema,
+      r#"SELECT version, weights as "weights: ScoreWeights", max_score, created_by, created_at
+      FROM score_schemas ORDER BY version DESC LIMIT 1"#
+    )
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::list_score_schemas", skip(self), err)]
+  pub async fn list_score_schemas(&self) -> Result<Vec<ScoreSchema>> {
+    sqlx::query_as!(
+      ScoreSchema,
+      r#"SELECT version, weights as "weights: ScoreWeights", max_score, created_by, created_at
+      FROM score_schemas ORDER BY version DESC"#
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::create_score_schema", skip(self), err)]
+  pub async fn create_score_schema(
+    &self,
+    staff_id: &Uuid,
+    weights: &ScoreWeights,
+    max_score: i32,
+  ) -> Result<ScoreSchema> {
+    let mut tx = self.pool.begin().await?;
+
+    let schema = sqlx::query_as!(
+      ScoreSchema,
+      r#"INSERT INTO score_schemas (version, weights, max_score, created_by)
+      VALUES ((SELECT COALESCE(MAX(version), 0) + 1 FROM score_schemas), $1, $2, $3)
+      RETURNING version, weights as "weights: ScoreWeights", max_score, created_by, created_at"#,
+      weights as _,
+      max_score,
+      staff_id,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "create_score_schema",
+      json!({
+        "version": schema.version,
+        "weights": weights,
+        "max_score": max_score,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(schema)
+  }
+
+  /// Start a score recompute job covering every package version. `staff_id`
+  /// is `None` for the scheduled job. Returns `None` if another job is still
+  /// running. A running job whose lease expired is failed first.
+  #[instrument(name = "Database::create_score_recompute_job", skip(self), err)]
+  pub async fn create_score_recompute_job(
+    &self,
+    staff_id: Option<&Uuid>,
+    lease: chrono::Duration,
+  ) -> Result<Option<ScoreRecomputeJob>> {
+    let mut tx = self.pool.begin().await?;
+
+    sqlx::query!(
+      "UPDATE score_recompute_jobs
+      SET status = 'failed', error = 'the job stopped before it finished', finished_at = now()
+      WHERE status = 'running' AND lease_expires_at <= now()",
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let Some(job) = query_concat_as!(
+      ScoreRecomputeJob,
+      "INSERT INTO score_recompute_jobs (total_versions, created_by, lease_expires_at)
+      SELECT (SELECT COUNT(*) FROM package_versions WHERE deleted_at IS NULL)::int, $1, $2
+      WHERE NOT EXISTS (SELECT 1 FROM score_recompute_jobs WHERE status = 'running')
+      RETURNING ", SCORE_RECOMPUTE_JOB_SELECT;
+      staff_id,
+      Utc::now() + lease,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
+    if let Some(staff_id) = staff_id {
+      audit_log(
+        &mut tx,
+        staff_id,
+        true,
+        "create_score_recompute_job",
+        json!({
+          "id": job.id,
+          "total_versions": job.total_versions,
+        }),
+      )
+      .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(Some(job))
+  }
+
+  #[instrument(name = "Database::get_score_recompute_job", skip(self), err)]
+  pub async fn get_score_recompute_job(
+    &self,
+    id: Uuid,
+  ) -> Result<Option<ScoreRecomputeJob>> {
+    query_concat_as!(
+      ScoreRecomputeJob,
+      "SELECT ", SCORE_RECOMPUTE_JOB_SELECT, " FROM score_recompute_jobs WHERE id = $1";
+      id,
+    )
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::list_score_recompute_jobs", skip(self), err)]
+  pub async fn list_score_recompute_jobs(
+    &self,
+  ) -> Result<Vec<ScoreRecomputeJob>> {
+    query_concat_as!(
+      ScoreRecomputeJob,
+      "SELECT ", SCORE_RECOMPUTE_JOB_SELECT, " FROM score_recompute_jobs ORDER BY created_at DESC LIMIT 20";
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  /// Extends the lease of a running score recompute job. Returns `false` if
+  /// the job is not running anymore.
+  #[instrument(
+    name = "Database::renew_score_recompute_job_lease",
+    skip(self),
+    err
+  )]
+  pub async fn renew_score_recompute_job_lease(
+    &self,
+    id: Uuid,
+    lease: chrono::Duration,
+  ) -> Result<bool> {
+    let res = sqlx::query!(
+      "UPDATE score_recompute_jobs SET lease_expires_at = $2
+      WHERE id = $1 AND status = 'running'",
+      id,
+      Utc::now() + lease,
+    )
+    .execute(&self.pool)
+    .await?;
+    Ok(res.rows_affected() > 0)
+  }
+
+  #[instrument(
+    name = "Database::update_score_recompute_job_progress",
+    skip(self),
+    err
+  )]
+  pub async fn update_score_recompute_job_progress(
+    &self,
+    id: Uuid,
+    processed_versions: i32,
+    failed_versions: i32,
+  ) -> Result<()> {
+    sqlx::query!(
+      "UPDATE score_recompute_jobs
+      SET processed_versions = $2, failed_versions = $3
+      WHERE id = $1",
+      id,
+      processed_versions,
+      failed_versions,
+    )
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  #[instrument(name = "Database::finish_score_recompute_job", skip(self), err)]
+  pub async fn finish_score_recompute_job(
+    &self,
+    id: Uuid,
+    status: ScoreRecomputeJobStatus,
+    error: Option<&str>,
+  ) -> Result<()> {
+    sqlx::query!(
+      "UPDATE score_recompute_jobs
+      SET status = $2, error = $3, finished_at = now()
+      WHERE id = $1 AND status = 'running'",
+      id,
+      status as _,
+      error,
+    )
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  /// List package versions ordered by scope, name and version, starting after
+  /// `after`. Used to walk all versions in batches.
+  #[instrument(name = "Database::list_package_versions_after", skip(self), err)]
+  pub async fn list_package_versions_after(
+    &self,
+    after: Option<(&ScopeName, &PackageName, &Version)>,
+    limit: i64,
+  ) -> Result<Vec<PackageVersion>> {
+    let (scope, name, version) = match after {
+      Some((scope, name, version)) => (Some(scope), Some(name), Some(version)),
+      None => (None, None, None),
+    };
+    query_concat_as!(
+      PackageVersion,
+      "SELECT ", PACKAGE_VERSION_SELECT, "
+      FROM package_versions
+      WHERE deleted_at IS NULL AND ($1::text IS NULL OR (scope, name, version) > ($1, $2, $3))
+      ORDER BY scope, name, version
+      LIMIT $4";
+      scope as _,
+      name as _,
+      version as _,
+      limit,
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  /// Start an npm tarball rebuild job covering every version in `scope` (or
+  /// every version of the `scope`/`name` package, or the whole registry if
+  /// both are `None`). Returns `None` if another job is still running. A
+  /// running job whose lease expired is failed first.
+  #[instrument(
+    name = "Database::create_npm_tarball_rebuild_job",
+    skip(self),
+    err
+  )]
+  pub async fn create_npm_tarball_rebuild_job(
+    &self,
+    staff_id: &Uuid,
+    scope: Option<&ScopeName>,
+    name: Option<&PackageName>,
+    verify: bool,
+    concurrency: i32,
+    lease: chrono::Duration,
+  ) -> Result<Option<NpmTarballRebuildJob>> {
+    let mut tx = self.pool.begin().await?;
+
+    sqlx::query!(
+      "UPDATE npm_tarball_rebuild_jobs
+      SET status = 'failed', error = 'the job stopped before it finished', finished_at = now()
+      WHERE status = 'running' AND lease_expires_at <= now()",
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let Some(job) = query_concat_as!(
+      NpmTarballRebuildJob,
+      "INSERT INTO npm_tarball_rebuild_jobs (scope, name, verify, concurrency, total_versions, created_by, lease_expires_at)
+      SELECT $1, $2, $3, $4, (
+        SELECT COUNT(*) FROM package_versions
+        WHERE deleted_at IS NULL AND ($1::text IS NULL OR scope = $1) AND ($2::text IS NULL OR name = $2)
+          AND NOT EXISTS (
+            SELECT 1 FROM package_version_quarantines q
+            WHERE q.scope = package_versions.scope AND q.name = package_versions.name AND q.version = package_versions.version
+          )
+      )::int, $5, $6
+      WHERE NOT EXISTS (SELECT 1 FROM npm_tarball_rebuild_jobs WHERE status = 'running')
+      RETURNING ", NPM_TARBALL_REBUILD_JOB_SELECT;
+      scope as _,
+      name as _,
+      verify,
+      concurrency,
+      staff_id,
+      Utc::now() + lease,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "create_npm_tarball_rebuild_job",
+      json!({
+        "id": job.id,
+        "scope": job.scope,
+        "name": job.name,
+        "verify": job.verify,
+        "concurrency": job.concurrency,
+        "total_versions": job.total_versions,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(job))
+  }
+
+  #[instrument(name = "Database::get_npm_tarball_rebuild_job", skip(self), err)]
+  pub async fn get_npm_tarball_rebuild_job(
+    &self,
+    id: Uuid,
+  ) -> Result<Option<NpmTarballRebuildJob>> {
+    query_concat_as!(
+      NpmTarballRebuildJob,
+      "SELECT ", NPM_TARBALL_REBUILD_JOB_SELECT, " FROM npm_tarball_rebuild_jobs WHERE id = $1";
+      id,
+    )
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  #[instrument(
+    name = "Database::list_npm_tarball_rebuild_jobs",
+    skip(self),
+    err
+  )]
+  pub async fn list_npm_tarball_rebuild_jobs(
+    &self,
+  ) -> Result<Vec<NpmTarballRebuildJob>> {
+    query_concat_as!(
+      NpmTarballRebuildJob,
+      "SELECT ", NPM_TARBALL_REBUILD_JOB_SELECT, " FROM npm_tarball_rebuild_jobs ORDER BY created_at DESC LIMIT 20";
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  /// Extends the lease of a running npm tarball rebuild job. Returns `false`
+  /// if the job is not running anymore.
+  #[instrument(
+    name = "Database::renew_npm_tarball_rebuild_job_lease",
+    skip(self),
+    err
+  )]
+  pub async fn renew_npm_tarball_rebuild_job_lease(
+    &self,
+    id: Uuid,
+    lease: chrono::Duration,
+  ) -> Result<bool> {
+    let res = sqlx::query!(
+      "UPDATE npm_tarball_rebuild_jobs SET lease_expires_at = $2
+      WHERE id = $1 AND status = 'running'",
+      id,
+      Utc::now() + lease,
+    )
+    .execute(&self.pool)
+    .await?;
+    Ok(res.rows_affected() > 0)
+  }
+
+  #[instrument(
+    name = "Database::update_npm_tarball_rebuild_job_progress",
+    skip(self),
+    err
+  )]
+  pub async fn update_npm_tarball_rebuild_job_progress(
+    &self,
+    id: Uuid,
+    processed_versions: i32,
+    failed_versions: i32,
+  ) -> Result<()> {
+    sqlx::query!(
+      "UPDATE npm_tarball_rebuild_jobs
+      SET processed_versions = $2, failed_versions = $3
+      WHERE id = $1",
+      id,
+      processed_versions,
+      failed_versions,
+    )
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  #[instrument(
+    name = "Database::finish_npm_tarball_rebuild_job",
+    skip(self),
+    err
+  )]
+  pub async fn finish_npm_tarball_rebuild_job(
+    &self,
+    id: Uuid,
+    status: NpmTarballRebuildJobStatus,
+    error: Option<&str>,
+  ) -> Result<()> {
+    sqlx::query!(
+      "UPDATE npm_tarball_rebuild_jobs
+      SET status = $2, error = $3, finished_at = now()
+      WHERE id = $1 AND status = 'running'",
+      id,
+      status as _,
+      error,
+    )
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  /// List the versions of a package, a scope, or the whole registry, like
+  /// those covered by an npm tarball rebuild or integrity scrub job, ordered
+  /// by scope, name and version, starting after `after`. Quarantined versions
+  /// are left out, as their artifacts are not where the jobs expect them.
+  #[instrument(
+    name = "Database::list_filtered_package_versions_after",
+    skip(self),
+    err
+  )]
+  pub async fn list_filtered_package_versions_after(
+    &self,
+    scope_filter: Option<&ScopeName>,
+    name_filter: Option<&PackageName>,
+    after: Option<(&ScopeName, &PackageName, &Version)>,
+    limit: i64,
+  ) -> Result<Vec<(ScopeName, PackageName, Version)>> {
+    let (scope, name, version) = match after {
+      Some((scope, name, version)) => (Some(scope), Some(name), Some(version)),
+      None => (None, None, None),
+    };
+    sqlx::query!(
+      r#"SELECT scope as "scope: ScopeName", name as "name: PackageName", version as "version: Version"
+      FROM package_versions
+      WHERE deleted_at IS NULL AND ($1::text IS NULL OR scope = $1) AND ($2::text IS NULL OR name = $2)
+        AND ($3::text IS NULL OR (scope, name, version) > ($3, $4, $5))
+        AND NOT EXISTS (
+          SELECT 1 FROM package_version_quarantines q
+          WHERE q.scope = package_versions.scope AND q.name = package_versions.name AND q.version = package_versions.version
+        )
+      ORDER BY scope, name, version
+      LIMIT $6"#,
+      scope_filter as _,
+      name_filter as _,
+      scope as _,
+      name as _,
+      version as _,
+      limit,
+    )
+    .map(|r| (r.scope, r.name, r.version))
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::create_integrity_scrub_job", skip(self), err)]
+  pub async fn create_integrity_scrub_job(
+    &self,
+    staff_id: &Uuid,
+    scope: Option<&ScopeName>,
+    name: Option<&PackageName>,
+  ) -> Result<Option<IntegrityScrubJob>> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some(job) = query_concat_as!(
+      IntegrityScrubJob,
+      "INSERT INTO integrity_scrub_jobs (scope, name, total_versions, created_by)
+      SELECT $1, $2, (
+        SELECT COUNT(*) FROM package_versions
+        WHERE deleted_at IS NULL AND ($1::text IS NULL OR scope = $1) AND ($2::text IS NULL OR name = $2)
+          AND NOT EXISTS (
+            SELECT 1 FROM package_version_quarantines q
+            WHERE q.scope = package_versions.scope AND q.name = package_versions.name AND q.version = package_versions.version
+          )
+      )::int, $3
+      WHERE NOT EXISTS (SELECT 1 FROM integrity_scrub_jobs WHERE status = 'running')
+      RETURNING ", INTEGRITY_SCRUB_JOB_SELECT;
+      scope as _,
+      name as _,
+      staff_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "create_integrity_scrub_job",
+      json!({
+        "id": job.id,
+        "scope": job.scope,
+        "name": job.name,
+        "total_versions": job.total_versions,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(job))
+  }
+
+  #[instrument(name = "Database::get_integrity_scrub_job", skip(self), err)]
+  pub async fn get_integrity_scrub_job(
+    &self,
+    id: Uuid,
+  ) -> Result<Option<IntegrityScrubJob>> {
+    query_concat_as!(
+      IntegrityScrubJob,
+      "SELECT ", INTEGRITY_SCRUB_JOB_SELECT, " FROM integrity_scrub_jobs WHERE id = $1";
+      id,
+    )
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::list_integrity_scrub_jobs", skip(self), err)]
+  pub async fn list_integrity_scrub_jobs(
+    &self,
+  ) -> Result<Vec<IntegrityScrubJob>> {
+    query_concat_as!(
+      IntegrityScrubJob,
+      "SELECT ", INTEGRITY_SCRUB_JOB_SELECT, " FROM integrity_scrub_jobs ORDER BY created_at DESC LIMIT 20";
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  /// Record the objects a job found to be missing or corrupted, and add them
+  /// to its progress. Each failure is a `(bucket, path, expected, actual)`
+  /// tuple.
+  #[instrument(
+    name = "Database::record_integrity_scrub_job_progress",
+    skip(self, failures),
+    err,
+    fields(failures = failures.len())
+  )]
+  pub async fn record_integrity_scrub_job_progress(
+    &self,
+    id: Uuid,
+    checked_versions: i32,
+    checked_objects: i32,
+    failures: &[(String, String, String, Option<String>)],
+  ) -> Result<()> {
+    let mut tx = self.pool.begin().await?;
+
+    let mut buckets = Vec::with_capacity(failures.len());
+    let mut paths = Vec::with_capacity(failures.len());
+    let mut expected = Vec::with_capacity(failures.len());
+    let mut actual = Vec::with_capacity(failures.len());
+    for (bucket, path, expected_digest, actual_digest) in failures {
+      buckets.push(bucket.clone());
+      paths.push(path.clone());
+      expected.push(expected_digest.clone());
+      actual.push(actual_digest.clone());
+    }
+    // Versions can share module blobs, so the same object can be reported
+    // more than once.
+    let failed_objects = sqlx::query!(
+      "INSERT INTO integrity_scrub_failures (job_id, bucket, path, expected, actual)
+      SELECT $1, * FROM UNNEST($2::text[], $3::text[], $4::text[], $5::text[])
+      ON CONFLICT DO NOTHING",
+      id,
+      &buckets,
+      &paths,
+      &expected,
+      &actual as &[Option<String>],
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    sqlx::query!(
+      "UPDATE integrity_scrub_jobs
+      SET checked_versions = checked_versions + $2,
+        checked_objects = checked_objects + $3,
+        failed_objects = failed_objects + $4
+      WHERE id = $1",
+      id,
+      checked_versions,
+      checked_objects,
+      failed_objects as i32,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+  }
+
+  #[instrument(name = "Database::finish_integrity_scrub_job", skip(self), err)]
+  pub async fn finish_integrity_scrub_job(
+    &self,
+    id: Uuid,
+    status: IntegrityScrubJobStatus,
+    error: Option<&str>,
+  ) -> Result<()> {
+    sqlx::query!(
+      "UPDATE integrity_scrub_jobs
+      SET status = $2, error = $3, finished_at = now()
+      WHERE id = $1 AND status = 'running'",
+      id,
+      status as _,
+      error,
+    )
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  #[instrument(
+    name = "Database::list_integrity_scrub_failures",
+    skip(self),
+    err
+  )]
+  pub async fn list_integrity_scrub_failures(
+    &self,
+    job_id: Uuid,
+    start: i64,
+    limit: i64,
+  ) -> Result<(usize, Vec<IntegrityScrubFailure>)> {
+    let mut tx = self.pool.begin().await?;
+
+    let failures = sqlx::query_as!(
+      IntegrityScrubFailure,
+      "SELECT job_id, bucket, path, expected, actual, created_at FROM integrity_scrub_failures
+      WHERE job_id = $1
+      ORDER BY bucket, path
+      OFFSET $2 LIMIT $3",
+      job_id,
+      start,
+      limit,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let total = sqlx::query!(
+      r#"SELECT COUNT(created_at) FROM integrity_scrub_failures WHERE job_id = $1"#,
+      job_id,
+    )
+    .map(|r| r.count.unwrap())
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok((total as usize, failures))
+  }
+
+  /// Start an orphan GC job. `staff_id` is `None` for the scheduled task.
+  /// Returns `None` if another job is still running.
+  #[instrument(name = "Database::create_orphan_gc_job", skip(self), err)]
+  pub async fn create_orphan_gc_job(
+    &self,
+    staff_id: Option<&Uuid>,
+    dry_run: bool,
+  ) -> Result<Option<OrphanGcJob>> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some(job) = query_concat_as!(
+      OrphanGcJob,
+      "INSERT INTO orphan_gc_jobs (dry_run, created_by)
+      SELECT $1, $2
+      WHERE NOT EXISTS (SELECT 1 FROM orphan_gc_jobs WHERE status = 'running')
+      RETURNING ", ORPHAN_GC_JOB_SELECT;
+      dry_run,
+      staff_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
+    if let Some(staff_id) = staff_id {
+      audit_log(
+        &mut tx,
+        staff_id,
+        true,
+        "create_orphan_gc_job",
+        json!({
+          "id": job.id,
+          "dry_run": job.dry_run,
+        }),
+      )
+      .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(Some(job))
+  }
+
+  #[instrument(name = "Database::get_orphan_gc_job", skip(self), err)]
+  pub async fn get_orphan_gc_job(
+    &self,
+    id: Uuid,
+  ) -> Result<Option<OrphanGcJob>> {
+    query_concat_as!(
+      OrphanGcJob,
+      "SELECT ", ORPHAN_GC_JOB_SELECT, " FROM orphan_gc_jobs WHERE id = $1";
+      id,
+    )
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::list_orphan_gc_jobs", skip(self), err)]
+  pub async fn list_orphan_gc_jobs(&self) -> Result<Vec<OrphanGcJob>> {
+    query_concat_as!(
+      OrphanGcJob,
+      "SELECT ", ORPHAN_GC_JOB_SELECT, " FROM orphan_gc_jobs ORDER BY created_at DESC LIMIT 20";
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  /// Record the orphaned objects a job found, and add them to its progress.
+  #[instrument(
+    name = "Database::record_orphan_gc_job_progress",
+    skip(self, objects),
+    err,
+    fields(objects = objects.len())
+  )]
+  pub async fn record_orphan_gc_job_progress(
+    &self,
+    id: Uuid,
+    scanned_packages: i32,
+    scanned_objects: i32,
+    objects: &[(String, String, bool)],
+  ) -> Result<()> {
+    let mut tx = self.pool.begin().await?;
+
+    let (buckets, (paths, deleted)): (Vec<_>, (Vec<_>, Vec<_>)) = objects
+      .iter()
+      .map(|(bucket, path, deleted)| (bucket.clone(), (path.clone(), *deleted)))
+      .unzip();
+    sqlx::query!(
+      "INSERT INTO orphaned_objects (job_id, bucket, path, deleted)
+      SELECT $1, * FROM UNNEST($2::text[], $3::text[], $4::bool[])
+      ON CONFLICT DO NOTHING",
+      id,
+      &buckets,
+      &paths,
+      &deleted,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let deleted_objects = deleted.iter().filter(|deleted| **deleted).count();
+    sqlx::query!(
+      "UPDATE orphan_gc_jobs
+      SET scanned_packages = scanned_packages + $2,
+        scanned_objects = scanned_objects + $3,
+        orphaned_objects = orphaned_objects + $4,
+        deleted_objects = deleted_objects + $5
+      WHERE id = $1",
+      id,
+      scanned_packages,
+      scanned_objects,
+      objects.len() as i32,
+      deleted_objects as i32,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+  }
+
+  #[instrument(name = "Database::finish_orphan_gc_job", skip(self), err)]
+  pub async fn finish_orphan_gc_job(
+    &self,
+    id: Uuid,
+    status: OrphanGcJobStatus,
+    error: Option<&str>,
+  ) -> Result<()> {
+    sqlx::query!(
+      "UPDATE orphan_gc_jobs
+      SET status = $2, error = $3, finished_at = now()
+      WHERE id = $1 AND status = 'running'",
+      id,
+      status as _,
+      error,
+    )
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  #[instrument(name = "Database::list_orphaned_objects", skip(self), err)]
+  pub async fn list_orphaned_objects(
+    &self,
+    job_id: Uuid,
+    start: i64,
+    limit: i64,
+  ) -> Result<(usize, Vec<OrphanedObject>)> {
+    let mut tx = self.pool.begin().await?;
+
+    let objects = sqlx::query_as!(
+      OrphanedObject,
+      "SELECT job_id, bucket, path, deleted, created_at FROM orphaned_objects
+      WHERE job_id = $1
+      ORDER BY bucket, path
+      OFFSET $2 LIMIT $3",
+      job_id,
+      start,
+      limit,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
 
-      let user = task.user_id.map(|_| {
-        UserPublic {
-          id: r.user_id.unwrap(),
-          name: r.user_name.unwrap(),
-          avatar_url: r.user_avatar_url.unwrap(),
-          github_id: r.user_github_id,
-          gitlab_id: r.user_gitlab_id,
-          updated_at: r.user_updated_at.unwrap(),
-          created_at: r.user_created_at.unwrap(),
-        }
-      });
+    let total = sqlx::query!(
+      r#"SELECT COUNT(created_at) FROM orphaned_objects WHERE job_id = $1"#,
+      job_id,
+    )
+    .map(|r| r.count.unwrap())
+    .fetch_one(&mut *tx)
+    .await?;
 
-      (task, user)
-    })
+    tx.commit().await?;
 
-      .fetch_optional(&mut *tx)
+    Ok((total as usize, objects))
+  }
+
+  /// Add a job to the background job queue. It is due right away.
+  #[instrument(name = "Database::enqueue_background_job", skip(self), err)]
+  pub async fn enqueue_background_job(
+    &self,
+    new_job: NewBackgroundJob<'_>,
+  ) -> Result<BackgroundJob> {
+    let mut tx = self.pool.begin().await?;
+
+    let job = query_concat_as!(
+      BackgroundJob,
+      "INSERT INTO background_jobs (kind, payload, priority, max_attempts, created_by)
+      VALUES ($1, $2, $3, $4, $5)
+      RETURNING ", BACKGROUND_JOB_SELECT;
+      new_job.kind as _,
+      new_job.payload,
+      new_job.priority,
+      new_job.max_attempts,
+      new_job.created_by,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if let Some(staff_id) = new_job.created_by {
+      audit_log(
+        &mut tx,
+        staff_id,
+        true,
+        "enqueue_background_job",
+        json!({
+          "id": job.id,
+          "kind": job.kind,
+          "payload": job.payload,
+          "priority": job.priority,
+          "max_attempts": job.max_attempts,
+        }),
+      )
       .await?;
-    if let Some(already_processing) = already_processing {
-      return Ok(CreatePublishingTaskResult::Exists(already_processing));
     }
 
-    let task = query_concat!(
-      "WITH task AS (
-          INSERT INTO publishing_tasks (user_id, package_scope, package_name, package_version, config_file)
-          VALUES ($1, $2, $3, $4, $5)
-          RETURNING
-            id,
-            status,
-            error,
-            user_id,
-            package_scope,
-            package_name,
-            package_version,
-            config_file,
-            created_at,
-            updated_at
-        )
-        SELECT
-          task.id as \"task_id\",
-          task.status as \"task_status: PublishingTaskStatus\",
-          task.error as \"task_error: PublishingTaskError\",
-          task.user_id as \"task_user_id\",
-          task.package_scope as \"task_package_scope: ScopeName\",
-          task.package_name as \"task_package_name: PackageName\",
-          task.package_version as \"task_package_version: Version\",
-          task.config_file as \"task_config_file: PackagePath\",
-          task.created_at as \"task_created_at\",
-          task.updated_at as \"task_updated_at\",
-        ", USER_PUBLIC_SELECT_JOINED_OPTIONAL, "
-        FROM task
-        LEFT JOIN users ON task.user_id = users.id";
-      task.user_id,
-      task.package_scope as _,
-      task.package_name as _,
-      task.package_version as _,
-      task.config_file as _,
+    tx.commit().await?;
+
+    Ok(job)
+  }
+
+  /// Claims up to `limit` jobs that are due, highest priority first, and
+  /// leases them to the caller until `lease` from now. Running jobs whose
+  /// lease expired are claimed again, unless they ran out of attempts, in
+  /// which case they are dead.
+  #[instrument(name = "Database::claim_background_jobs", skip(self), err)]
+  pub async fn claim_background_jobs(
+    &self,
+    limit: i64,
+    lease: chrono::Duration,
+  ) -> Result<Vec<BackgroundJob>> {
+    let mut tx = self.pool.begin().await?;
+
+    sqlx::query!(
+      "UPDATE background_jobs
+      SET status = 'dead', last_error = 'the job did not finish before its lease expired', finished_at = now()
+      WHERE status = 'running' AND run_at <= now() AND attempts >= max_attempts",
     )
-      .map(|r| {
-        let task = PublishingTask {
-          id: r.task_id,
-          status: r.task_status,
-          error: r.task_error,
-          package_scope: r.task_package_scope,
-          package_name: r.task_package_name,
-          package_version: r.task_package_version,
-          config_file: r.task_config_file,
-          user_id: r.task_user_id,
-          created_at: r.task_created_at,
-          updated_at: r.task_updated_at,
-        };
+    .execute(&mut *tx)
+    .await?;
 
-        let user = task.user_id.map(|_| {
-          UserPublic {
-            id: r.user_id.unwrap(),
-            name: r.user_name.unwrap(),
-            avatar_url: r.user_avatar_url.unwrap(),
-            github_id: r.user_github_id,
-gitlab_id: r.user_gitlab_id,
-            updated_at: r.user_updated_at.unwrap(),
-            created_at: r.user_created_at.unwrap(),
-          }
-        });
+    let jobs = query_concat_as!(
+      BackgroundJob,
+      "UPDATE background_jobs
+      SET status = 'running', attempts = attempts + 1, run_at = $2
+      WHERE id IN (
+        SELECT id FROM background_jobs
+        WHERE status IN ('pending', 'running') AND run_at <= now()
+        ORDER BY priority DESC, run_at ASC
+        LIMIT $1
+        FOR UPDATE SKIP LOCKED
+      )
+      RETURNING ", BACKGROUND_JOB_SELECT;
+      limit,
+      Utc::now() + lease,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
 
-        (task, user)
-      })
+    tx.commit().await?;
 
-      .fetch_one(&mut *tx)
-      .await?;
+    Ok(jobs)
+  }
 
-    let publish_attempts_per_week_limit = sqlx::query!(
-      r#"
-      SELECT publish_attempts_per_week_limit FROM scopes WHERE scope = $1;
-      "#,
-      task.0.package_scope as _,
+  /// Marks a claimed job as completed.
+  #[instrument(name = "Database::complete_background_job", skip(self), err)]
+  pub async fn complete_background_job(&self, id: Uuid) -> Result<()> {
+    sqlx::query!(
+      "UPDATE background_jobs
+      SET status = 'completed', last_error = NULL, finished_at = now()
+      WHERE id = $1 AND status = 'running'",
+      id,
     )
-    .map(|r| r.publish_attempts_per_week_limit)
-    .fetch_one(&mut *tx)
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  /// Records that an attempt to run a claimed job failed. The job is retried
+  /// at `retry_at`, or is dead if it ran out of attempts.
+  #[instrument(name = "Database::fail_background_job", skip(self, error), err)]
+  pub async fn fail_background_job(
+    &self,
+    id: Uuid,
+    error: &str,
+    retry_at: DateTime<Utc>,
+  ) -> Result<()> {
+    sqlx::query!(
+      "UPDATE background_jobs
+      SET status = CASE WHEN attempts >= max_attempts THEN 'dead'::background_job_status ELSE 'pending'::background_job_status END,
+        run_at = $3,
+        last_error = $2,
+        finished_at = CASE WHEN attempts >= max_attempts THEN now() ELSE NULL END
+      WHERE id = $1 AND status = 'running'",
+      id,
+      error,
+      retry_at,
+    )
+    .execute(&self.pool)
     .await?;
+    Ok(())
+  }
 
-    let publish_attempts_from_last_week = sqlx::query!(
-      r#"
-      SELECT COUNT(created_at) FROM publishing_tasks WHERE package_scope = $1 AND created_at > now() - '1 week'::interval;
-      "#,
-      task.0.package_scope as _,
+  #[instrument(name = "Database::get_background_job", skip(self), err)]
+  pub async fn get_background_job(
+    &self,
+    id: Uuid,
+  ) -> Result<Option<BackgroundJob>> {
+    query_concat_as!(
+      BackgroundJob,
+      "SELECT ", BACKGROUND_JOB_SELECT, " FROM background_jobs WHERE id = $1";
+      id,
     )
-      .map(|r| {
-        r.count.unwrap()
-      })
-      .fetch_one(&mut *tx)
-      .await?;
+    .fetch_optional(&self.pool)
+    .await
+  }
 
-    if publish_attempts_from_last_week > publish_attempts_per_week_limit as i64
-    {
-      tx.rollback().await?;
-      return Ok(
-        CreatePublishingTaskResult::WeeklyPublishAttemptsLimitExceeded(
-          publish_attempts_per_week_limit,
-        ),
-      );
-    }
+  /// List background jobs, newest first, optionally only those with the given
+  /// status or kind.
+  #[instrument(name = "Database::list_background_jobs", skip(self), err)]
+  pub async fn list_background_jobs(
+    &self,
+    status: Option<BackgroundJobStatus>,
+    kind: Option<BackgroundJobKind>,
+    start: i64,
+    limit: i64,
+  ) -> Result<(usize, Vec<BackgroundJob>)> {
+    let mut tx = self.pool.begin().await?;
+
+    let jobs = query_concat_as!(
+      BackgroundJob,
+      "SELECT ", BACKGROUND_JOB_SELECT, " FROM background_jobs
+      WHERE ($1::background_job_status IS NULL OR status = $1)
+        AND ($2::background_job_kind IS NULL OR kind = $2)
+      ORDER BY created_at DESC
+      OFFSET $3 LIMIT $4";
+      status as _,
+      kind as _,
+      start,
+      limit,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let total = sqlx::query!(
+      r#"SELECT COUNT(created_at) FROM background_jobs
+      WHERE ($1::background_job_status IS NULL OR status = $1)
+        AND ($2::background_job_kind IS NULL OR kind = $2)"#,
+      status as _,
+      kind as _,
+    )
+    .map(|r| r.count.unwrap())
+    .fetch_one(&mut *tx)
+    .await?;
 
     tx.commit().await?;
 
-    Ok(CreatePublishingTaskResult::Created(task))
+    Ok((total as usize, jobs))
   }
 
-  #[instrument(name = "Database::get_publishing_task", skip(self), err)]
-  pub async fn get_publishing_task(
+  /// Requeue a dead job with a fresh set of attempts. Returns `None` if the
+  /// job is not dead.
+  #[instrument(name = "Database::retry_background_job", skip(self), err)]
+  pub async fn retry_background_job(
     &self,
+    staff_id: &Uuid,
     id: Uuid,
-  ) -> Result<Option<(PublishingTask, Option<UserPublic>)>> {
-    query_concat!(
-      "SELECT
-        ", PUBLISHING_TASK_SELECT_JOINED, ",
-        ", USER_PUBLIC_SELECT_JOINED_OPTIONAL, "
-      FROM publishing_tasks
-      LEFT JOIN users on publishing_tasks.user_id = users.id
-      WHERE publishing_tasks.id = $1";
-      id
+  ) -> Result<Option<BackgroundJob>> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some(job) = query_concat_as!(
+      BackgroundJob,
+      "UPDATE background_jobs
+      SET status = 'pending', attempts = 0, run_at = now(), finished_at = NULL
+      WHERE id = $1 AND status = 'dead'
+      RETURNING ", BACKGROUND_JOB_SELECT;
+      id,
     )
-    .map(|r| {
-      let task = PublishingTask {
-        id: r.task_id,
-        status: r.task_status,
-        error: r.task_error,
-        package_scope: r.task_package_scope,
-        package_name: r.task_package_name,
-        package_version: r.task_package_version,
-        config_file: r.task_config_file,
-        user_id: r.task_user_id,
-        created_at: r.task_created_at,
-        updated_at: r.task_updated_at,
-      };
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
 
-      let user = task.user_id.map(|_| UserPublic {
-        id: r.user_id.unwrap(),
-        name: r.user_name.unwrap(),
-        avatar_url: r.user_avatar_url.unwrap(),
-        github_id: r.user_github_id,
-        gitlab_id: r.user_gitlab_id,
-        updated_at: r.user_updated_at.unwrap(),
-        created_at: r.user_created_at.unwrap(),
-      });
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "retry_background_job",
+      json!({ "id": job.id }),
+    )
+    .await?;
 
-      (task, user)
-    })
+    tx.commit().await?;
+
+    Ok(Some(job))
+  }
+
+  /// List packages ordered by scope and name, starting after `after`.
+  /// Includes deleted packages, whose objects are kept until they are purged.
+  #[instrument(name = "Database::list_packages_after", skip(self), err)]
+  pub async fn list_packages_after(
+    &self,
+    after: Option<(&ScopeName, &PackageName)>,
+    limit: i64,
+  ) -> Result<Vec<(ScopeName, PackageName)>> {
+    let (scope, name) = after.unzip();
+    sqlx::query!(
+      r#"SELECT scope as "scope: ScopeName", name as "name: PackageName"
+      FROM packages
+      WHERE $1::text IS NULL OR (scope, name) > ($1, $2)
+      ORDER BY scope, name
+      LIMIT $3"#,
+      scope as _,
+      name as _,
+      limit,
+    )
+    .map(|r| (r.scope, r.name))
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  /// Packages with the meta and the license of their latest version, ordered
+  /// by scope and name, starting after `after`. What the search index is
+  /// rebuilt from.
+  #[instrument(
+    name = "Database::list_packages_for_search_index",
+    skip(self),
+    err
+  )]
+  pub async fn list_packages_for_search_index(
+    &self,
+    after: Option<(&ScopeName, &PackageName)>,
+    limit: i64,
+  ) -> Result<Vec<(Package, PackageVersionMeta, Option<String>)>> {
+    let (scope, name) = after.unzip();
+    sqlx::query(&format!(
+      r#"SELECT {}, {}, pv_latest.license AS "package_version_license"
+      FROM packages {}
+      WHERE packages.deleted_at IS NULL AND ($1::text IS NULL OR (packages.scope, packages.name) > ($1, $2))
+      ORDER BY packages.scope, packages.name
+      LIMIT $3"#,
+      crate::db::sql_fragments::PACKAGE_BASE_SELECT_JOINED_RT,
+      crate::db::sql_fragments::PACKAGE_VERSION_AGG_SELECT_RT,
+      crate::db::sql_fragments::PACKAGE_VERSION_LATERAL_JOINS_RT,
+    ))
+    .bind(scope.map(|scope| scope.to_string()))
+    .bind(name.map(|name| name.to_string()))
+    .bind(limit)
+    .try_map(|r| package_for_search_index_from_row(&r))
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  /// A package with the meta and the license of its latest version, to update
+  /// its search document with.
+  #[instrument(
+    name = "Database::get_package_for_search_index",
+    skip(self),
+    err
+  )]
+  pub async fn get_package_for_search_index(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+  ) -> Result<Option<(Package, PackageVersionMeta, Option<String>)>> {
+    sqlx::query(&format!(
+      r#"SELECT {}, {}, pv_latest.license AS "package_version_license"
+      FROM packages {}
+      WHERE packages.scope = $1 AND packages.name = $2 AND packages.deleted_at IS NULL"#,
+      crate::db::sql_fragments::PACKAGE_BASE_SELECT_JOINED_RT,
+      crate::db::sql_fragments::PACKAGE_VERSION_AGG_SELECT_RT,
+      crate::db::sql_fragments::PACKAGE_VERSION_LATERAL_JOINS_RT,
+    ))
+    .bind(scope.to_string())
+    .bind(name.to_string())
+    .try_map(|r| package_for_search_index_from_row(&r))
     .fetch_optional(&self.pool)
     .await
   }
 
-  /// Record the SHA-256 (`sha256-<hex>`) of the uploaded gzipped tarball on the
-  /// publishing task. This is the artifact that SLSA provenance attests over, so
-  /// it is later used to bind an attestation to the actual published bytes.
+  /// The given packages, in the given order, leaving out those that don't
+  /// exist or are not listed in search (anymore). Used to load the results of
+  /// a search that an external search engine answered.
+  #[instrument(name = "Database::list_packages_by_name", skip(self), err)]
+  pub async fn list_packages_by_name(
+    &self,
+    packages: &[(ScopeName, PackageName)],
+  ) -> Result<Vec<PackageWithGitHubRepoAndMeta>> {
+    let (scopes, names): (Vec<String>, Vec<String>) = packages
+      .iter()
+      .map(|(scope, name)| (scope.to_string(), name.to_string()))
+      .unzip();
+    sqlx::query(&format!(
+      r#"SELECT {}, {}, {}
+      FROM unnest($1::text[], $2::text[]) WITH ORDINALITY AS hits(scope, name, position)
+      JOIN packages ON packages.scope = hits.scope AND packages.name = hits.name
+      LEFT JOIN github_repositories ON packages.github_repository_id = github_repositories.id
+      {}
+      WHERE NOT packages.is_archived AND packages.visibility = 'public' AND packages.deleted_at IS NULL
+      ORDER BY hits.position"#,
+      crate::db::sql_fragments::PACKAGE_BASE_SELECT_JOINED_RT,
+      crate::db::sql_fragments::PACKAGE_VERSION_AGG_SELECT_RT,
+      crate::db::sql_fragments::GITHUB_REPOSITORY_SELECT_JOINED_RT,
+      crate::db::sql_fragments::PACKAGE_VERSION_LATERAL_JOINS_RT,
+    ))
+    .bind(scopes)
+    .bind(names)
+    .try_map(|r| {
+      let package = Package::from_row(&r)?;
+
+      let github_repository = if r
+        .try_get::<Option<i64>, &str>("github_repository_id")?
+        .is_some()
+      {
+        Some(GithubRepository::from_row(&r)?)
+      } else {
+        None
+      };
+
+      let meta: Option<PackageVersionMeta> =
+        r.try_get("package_version_meta")?;
+      Ok((package, github_repository, meta.unwrap_or_default()))
+    })
+    .fetch_all(self.reader())
+    .await
+  }
+
+  /// The versions of a package whose objects must be kept: the versions that
+  /// exist, including deleted ones that were not purged yet, and those of
+  /// publishing tasks that may still create them. A
+  /// single query, so that a publish that finishes concurrently is always
+  /// seen in one or the other.
+  #[instrument(name = "Database::list_live_package_versions", skip(self), err)]
+  pub async fn list_live_package_versions(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+  ) -> Result<Vec<Version>> {
+    sqlx::query!(
+      r#"SELECT version as "version!: Version" FROM package_versions
+        WHERE scope = $1 AND name = $2
+      UNION
+      SELECT package_version FROM publishing_tasks
+        WHERE package_scope = $1 AND package_name = $2
+          AND status IN ('pending', 'processing', 'processed')"#,
+      scope as _,
+      name as _,
+    )
+    .map(|r| r.version)
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  /// Of the given publishing task ids, those whose task exists.
   #[instrument(
-    name = "Database::set_publishing_task_tarball_hash",
-    skip(self),
+    name = "Database::list_existing_publishing_task_ids",
+    skip(self, ids),
     err
   )]
-  pub async fn set_publishing_task_tarball_hash(
+  pub async fn list_existing_publishing_task_ids(
     &self,
-    id: Uuid,
-    tarball_hash: &str,
-  ) -> Result<()> {
-    sqlx::query!(
-      "UPDATE publishing_tasks SET tarball_hash = $1 WHERE id = $2",
-      tarball_hash,
-      id,
-    )
-    .execute(&self.pool)
-    .await?;
-    Ok(())
+    ids: &[Uuid],
+  ) -> Result<Vec<Uuid>> {
+    sqlx::query!("SELECT id FROM publishing_tasks WHERE id = ANY($1)", ids)
+      .map(|r| r.id)
+      .fetch_all(&self.pool)
+      .await
   }
 
-  /// The recorded tarball hash (`sha256-<hex>`) for the most recent publishing
-  /// task of a version, if one exists and recorded a hash. Used to verify that a
-  /// provenance attestation's `subject.digest.sha256` matches the published
-  /// bytes.
-  #[instrument(
-    name = "Database::get_publishing_task_tarball_hash_for_version",
-    skip(self),
-    err
-  )]
-  pub async fn get_publishing_task_tarball_hash_for_version(
+  #[instrument(name = "Database::update_package_version_meta", skip(self), err)]
+  pub async fn update_package_version_meta(
     &self,
     scope: &ScopeName,
     name: &PackageName,
     version: &Version,
-  ) -> Result<Option<String>> {
-    let row = sqlx::query!(
-      r#"SELECT tarball_hash FROM publishing_tasks
-      WHERE package_scope = $1 AND package_name = $2 AND package_version = $3
-        AND tarball_hash IS NOT NULL
-      ORDER BY created_at DESC
-      LIMIT 1"#,
+    meta: &PackageVersionMeta,
+  ) -> Result<()> {
+    sqlx::query!(
+      "UPDATE package_versions SET meta = $4
+      WHERE scope = $1 AND name = $2 AND version = $3",
       scope as _,
       name as _,
       version as _,
+      meta as _,
     )
-    .fetch_optional(&self.pool)
+    .execute(&self.pool)
     .await?;
-    Ok(row.and_then(|r| r.tarball_hash))
+    self.package_changed(scope, name).await;
+    Ok(())
   }
 
-  #[allow(clippy::type_complexity)]
-  #[instrument(name = "Database::list_publishing_tasks", skip(self), err)]
-  pub async fn list_publishing_tasks(
+  /// List announcements, newest first. If `feature` is set, only announcements
+  /// tagged with that feature or with no features at all are returned. If
+  /// `active_only` is set, expired announcements are left out.
+  #[instrument(name = "Database::list_announcements", skip(self), err)]
+  pub async fn list_announcements(
     &self,
-    start: i64,
+    feature: Option<&str>,
+    active_only: bool,
     limit: i64,
-    maybe_search_query: Option<&str>,
-    maybe_sort: Option<&str>,
-  ) -> Result<(usize, Vec<(PublishingTask, Option<UserPublic>)>)> {
+  ) -> Result<Vec<Announcement>> {
+    query_concat_as!(
+      Announcement,
+      "SELECT ", ANNOUNCEMENT_SELECT, " FROM announcements
+      WHERE ($1::text IS NULL OR cardinality(features) = 0 OR $1 = ANY(features))
+        AND (NOT $2 OR expires_at IS NULL OR expires_at > now())
+      ORDER BY created_at DESC
+      LIMIT $3";
+      feature,
+      active_only,
+      limit,
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::create_announcement", skip(self), err)]
+  pub async fn create_announcement(
+    &self,
+    staff_id: &Uuid,
+    new_announcement: NewAnnouncement<'_>,
+  ) -> Result<Announcement> {
     let mut tx = self.pool.begin().await?;
 
-    let search = format!("%{}%", maybe_search_query.unwrap_or(""));
-    let sort = sort_by!(maybe_sort => {
-      @timestamps "updated_at", "created_at";
-      "status" => "publishing_tasks.status",
-      "user" => "users.name",
-      "scope" => "publishing_tasks.package_scope",
-      "name" => "publishing_tasks.package_name",
-      "version" => "publishing_tasks.package_version",
-      "updated_at" => "publishing_tasks.updated_at",
-      "created_at" => "publishing_tasks.created_at",
-    } || "publishing_tasks.created_at DESC");
+    let announcement = query_concat_as!(
+      Announcement,
+      "INSERT INTO announcements (title, body, severity, features, expires_at, created_by)
+      VALUES ($1, $2, $3, $4, $5, $6)
+      RETURNING ", ANNOUNCEMENT_SELECT;
+      new_announcement.title,
+      new_announcement.body,
+      new_announcement.severity as _,
+      new_announcement.features,
+      new_announcement.expires_at,
+      staff_id,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
 
-    let publishing_tasks = sqlx::query(&format!(
-      r#"SELECT
-        {}, {}
-      FROM publishing_tasks
-      LEFT JOIN users on publishing_tasks.user_id = users.id
-      WHERE publishing_tasks.package_scope ILIKE $1
-         OR publishing_tasks.package_name ILIKE $1
-         OR publishing_tasks.package_version ILIKE $1
-      ORDER BY {sort} OFFSET $2 LIMIT $3"#,
-      crate::db::sql_fragments::PUBLISHING_TASK_SELECT_JOINED_RT,
-      crate::db::sql_fragments::USER_PUBLIC_SELECT_JOINED_RT,
-    ))
-    .bind(&search)
-    .bind(start)
-    .bind(limit)
-    .try_map(|r| {
-      let task = PublishingTask::from_row(&r)?;
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "create_announcement",
+      json!({
+        "id": announcement.id,
+        "title": announcement.title,
+        "severity": announcement.severity,
+        "features": announcement.features,
+      }),
+    )
+    .await?;
 
-      let user = if r.try_get::<Option<Uuid>, &str>("user_id")?.is_some() {
-        Some(UserPublic::from_row(&r)?)
-      } else {
-        None
-      };
+    tx.commit().await?;
 
-      Ok((task, user))
-    })
-    .fetch_all(&mut *tx)
+    Ok(announcement)
+  }
+
+  /// Replace the contents of an announcement.
+  #[instrument(name = "Database::update_announcement", skip(self), err)]
+  pub async fn update_announcement(
+    &self,
+    staff_id: &Uuid,
+    id: Uuid,
+    announcement: NewAnnouncement<'_>,
+  ) -> Result<Option<Announcement>> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some(announcement) = query_concat_as!(
+      Announcement,
+      "UPDATE announcements
+      SET title = $2, body = $3, severity = $4, features = $5, expires_at = $6
+      WHERE id = $1
+      RETURNING ", ANNOUNCEMENT_SELECT;
+      id,
+      announcement.title,
+      announcement.body,
+      announcement.severity as _,
+      announcement.features,
+      announcement.expires_at,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "update_announcement",
+      json!({
+        "id": announcement.id,
+        "title": announcement.title,
+        "severity": announcement.severity,
+        "features": announcement.features,
+        "expires_at": announcement.expires_at,
+      }),
+    )
     .await?;
 
-    let total_publishing_tasks = sqlx::query!(
-      r#"SELECT COUNT(created_at) FROM publishing_tasks WHERE package_scope ILIKE $1 OR package_name ILIKE $1 OR package_version ILIKE $1;"#,
-      search,
+    tx.commit().await?;
+
+    Ok(Some(announcement))
+  }
+
+  #[instrument(name = "Database::delete_announcement", skip(self), err)]
+  pub async fn delete_announcement(
+    &self,
+    staff_id: &Uuid,
+    id: Uuid,
+  ) -> Result<Option<Announcement>> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some(announcement) = query_concat_as!(
+      Announcement,
+      "DELETE FROM announcements WHERE id = $1 RETURNING ", ANNOUNCEMENT_SELECT;
+      id,
     )
-      .map(|r| r.count.unwrap())
-      .fetch_one(&mut *tx)
-      .await?;
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "delete_announcement",
+      json!({
+        "id": announcement.id,
+        "title": announcement.title,
+      }),
+    )
+    .await?;
 
     tx.commit().await?;
 
-    Ok((total_publishing_tasks as usize, publishing_tasks))
+    Ok(Some(announcement))
   }
 
-  #[instrument(
-    name = "Database::list_publishing_tasks_for_package",
-    skip(self),
-    err
-  )]
-  pub async fn list_publishing_tasks_for_package(
+  #[instrument(name = "Database::list_feature_flags", skip(self), err)]
+  pub async fn list_feature_flags(&self) -> Result<Vec<FeatureFlag>> {
+    query_concat_as!(
+      FeatureFlag,
+      "SELECT ", FEATURE_FLAG_SELECT, " FROM feature_flags ORDER BY name";
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::get_feature_flag", skip(self), err)]
+  pub async fn get_feature_flag(
     &self,
-    scope_name: &ScopeName,
-    package_name: &PackageName,
-  ) -> Result<Vec<(PublishingTask, Option<UserPublic>)>> {
-    query_concat!(
-      "SELECT
-        ", PUBLISHING_TASK_SELECT_JOINED, ",
-        ", USER_PUBLIC_SELECT_JOINED_OPTIONAL, "
-      FROM publishing_tasks
-      LEFT JOIN users on publishing_tasks.user_id = users.id
-      JOIN packages ON publishing_tasks.package_scope = packages.scope AND publishing_tasks.package_name = packages.name
-      WHERE publishing_tasks.package_scope = $1 AND publishing_tasks.package_name = $2 AND publishing_tasks.created_at >= packages.created_at
-      ORDER BY publishing_tasks.package_version DESC";
-      scope_name as _,
-      package_name as _,
+    name: &str,
+  ) -> Result<Option<FeatureFlag>> {
+    query_concat_as!(
+      FeatureFlag,
+      "SELECT ", FEATURE_FLAG_SELECT, " FROM feature_flags WHERE name = $1";
+      name,
     )
-      .map(|r| {
-        let task = PublishingTask {
-          id: r.task_id,
-          status: r.task_status,
-          error: r.task_error,
-          package_scope: r.task_package_scope,
-          package_name: r.task_package_name,
-          package_version: r.task_package_version,
-          config_file: r.task_config_file,
-          user_id: r.task_user_id,
-          created_at: r.task_created_at,
-          updated_at: r.task_updated_at,
-        };
+    .fetch_optional(&self.pool)
+    .await
+  }
 
-        let user = task.user_id.map(|_| {
-          UserPublic {
-            id: r.user_id.unwrap(),
-            name: r.user_name.unwrap(),
-            avatar_url: r.user_avatar_url.unwrap(),
-            github_id: r.user_github_id,
-gitlab_id: r.user_gitlab_id,
-            updated_at: r.user_updated_at.unwrap(),
-            created_at: r.user_created_at.unwrap(),
-          }
-        });
+  /// Create a feature flag, or replace it if it exists.
+  #[instrument(name = "Database::upsert_feature_flag", skip(self), err)]
+  pub async fn upsert_feature_flag(
+    &self,
+    staff_id: &Uuid,
+    flag: NewFeatureFlag<'_>,
+  ) -> Result<FeatureFlag> {
+    let mut tx = self.pool.begin().await?;
 
-        (task, user)
-      })
-      .fetch_all(&self.pool)
-      .await
+    let feature_flag = query_concat_as!(
+      FeatureFlag,
+      "INSERT INTO feature_flags (name, description, enabled, scopes, user_ids)
+      VALUES ($1, $2, $3, $4, $5)
+      ON CONFLICT (name) DO UPDATE
+      SET description = $2, enabled = $3, scopes = $4, user_ids = $5
+      RETURNING ", FEATURE_FLAG_SELECT;
+      flag.name,
+      flag.description,
+      flag.enabled,
+      flag.scopes as _,
+      flag.user_ids,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "upsert_feature_flag",
+      json!({
+        "name": feature_flag.name,
+        "enabled": feature_flag.enabled,
+        "scopes": feature_flag.scopes,
+        "user_ids": feature_flag.user_ids,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(feature_flag)
   }
 
-  #[instrument(
-    name = "Database::get_publishing_task_for_version",
-    skip(self),
-    err
-  )]
-  pub async fn get_publishing_task_for_version(
-    &self,
-    scope_name: &ScopeName,
-    package_name: &PackageName,
-    version: &Version,
-  ) -> Result<(PublishingTask, Option<UserPublic>)> {
-    query_concat!(
-      "SELECT
-        ", PUBLISHING_TASK_SELECT_JOINED, ",
-        ", USER_PUBLIC_SELECT_JOINED_OPTIONAL, "
-      FROM publishing_tasks
-      LEFT JOIN users on publishing_tasks.user_id = users.id
-      JOIN packages ON publishing_tasks.package_scope = packages.scope AND publishing_tasks.package_name = packages.name
-      WHERE publishing_tasks.package_scope = $1 AND publishing_tasks.package_name = $2 AND publishing_tasks.package_version = $3 AND publishing_tasks.created_at >= packages.created_at
-      ORDER BY publishing_tasks.created_at DESC
-      LIMIT 1";
-      scope_name as _,
-      package_name as _,
-      version as _,
+  #[instrument(name = "Database::delete_feature_flag", skip(self), err)]
+  pub async fn delete_feature_flag(
+    &self,
+    staff_id: &Uuid,
+    name: &str,
+  ) -> Result<Option<FeatureFlag>> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some(feature_flag) = query_concat_as!(
+      FeatureFlag,
+      "DELETE FROM feature_flags WHERE name = $1 RETURNING ", FEATURE_FLAG_SELECT;
+      name,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "delete_feature_flag",
+      json!({ "name": feature_flag.name }),
     )
-      .map(|r| {
-        let task = PublishingTask {
-          id: r.task_id,
-          status: r.task_status,
-          error: r.task_error,
-          package_scope: r.task_package_scope,
-          package_name: r.task_package_name,
-          package_version: r.task_package_version,
-          config_file: r.task_config_file,
-          user_id: r.task_user_id,
-          created_at: r.task_created_at,
-          updated_at: r.task_updated_at,
-        };
+    .await?;
 
-        let user = task.user_id.map(|_| {
-          UserPublic {
-            id: r.user_id.unwrap(),
-            name: r.user_name.unwrap(),
-            avatar_url: r.user_avatar_url.unwrap(),
-            github_id: r.user_github_id,
-            gitlab_id: r.user_gitlab_id,
-            updated_at: r.user_updated_at.unwrap(),
-            created_at: r.user_created_at.unwrap(),
-          }
-        });
+    tx.commit().await?;
 
-        (task, user)
-      })
-      .fetch_one(&self.pool)
-      .await
+    Ok(Some(feature_flag))
   }
 
-  #[instrument(
-    name = "Database::update_publishing_task_status",
-    skip(self),
-    err
-  )]
-  pub async fn update_publishing_task_status(
+  /// Creates a data export for a user, and queues `job` to build it. Returns
+  /// `None` if the user requested an export in the last day that is still
+  /// pending.
+  #[instrument(name = "Database::create_data_export", skip(self, job), err)]
+  pub async fn create_data_export(
     &self,
-    staff_id: Option<&Uuid>,
+    user_id: Uuid,
     id: Uuid,
-    prev_status: PublishingTaskStatus,
-    new_status: PublishingTaskStatus,
-    new_error: Option<PublishingTaskError>,
-  ) -> Result<PublishingTask> {
-    assert_eq!(
-      new_error.is_some(),
-      new_status == PublishingTaskStatus::Failure,
-      "error must be set if and only if status is failure"
-    );
-
+    job: NewBackgroundJob<'_>,
+  ) -> Result<Option<DataExport>> {
     let mut tx = self.pool.begin().await?;
 
-    if let Some(staff_id) = staff_id {
-      audit_log(
-        &mut tx,
-        staff_id,
-        true,
-        "requeue_publishing_task",
-        json!({
-          "id": id,
-        }),
+    let Some(data_export) = query_concat_as!(
+      DataExport,
+      "INSERT INTO data_exports (id, user_id)
+      SELECT $1, $2
+      WHERE NOT EXISTS (
+        SELECT 1 FROM data_exports
+        WHERE user_id = $2 AND status = 'pending' AND created_at > now() - '1 day'::interval
       )
-      .await?;
-    }
-
-    let task = query_concat_as!(
-      PublishingTask,
-      "UPDATE publishing_tasks
-      SET status = $1, error = $2
-      WHERE id = $3 AND status = $4
-      RETURNING ", PUBLISHING_TASK_SELECT;
-      new_status as _,
-      new_error as _,
+      RETURNING ", DATA_EXPORT_SELECT;
       id,
-      prev_status as _,
+      user_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
+    sqlx::query!(
+      "INSERT INTO background_jobs (kind, payload, priority, max_attempts, created_by)
+      VALUES ($1, $2, $3, $4, $5)",
+      job.kind as _,
+      job.payload,
+      job.priority,
+      job.max_attempts,
+      job.created_by,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    audit_log(
+      &mut tx,
+      &user_id,
+      false,
+      "create_data_export",
+      json!({ "id": data_export.id }),
     )
-    .fetch_one(&mut *tx)
     .await?;
 
     tx.commit().await?;
 
-    Ok(task)
+    Ok(Some(data_export))
   }
 
-  /// List publishing tasks that have been stuck in a non-terminal state
-  /// (`processing` or `processed`) for longer than `stale_after_seconds`.
-  ///
-  /// A task is normally driven from `pending` to `success` within seconds by
-  /// the publish queue. If the queue worker is killed mid-flight (e.g. the
-  /// Cloud Run request times out, or a publish is cancelled) a task can be
-  /// stranded: `processing` means the version row was never committed, while
-  /// `processed` means the version exists but its package-level `meta.json`
-  /// was never regenerated, leaving the version invisible to the resolver.
-  /// Either state also blocks re-publishing that exact version (see the
-  /// `status != 'failure'` guard in `create_publishing_task`). The reaper at
-  /// `POST /tasks/requeue_stuck_publishing_tasks` re-drives these.
-  #[instrument(name = "Database::list_stale_publishing_tasks", skip(self), err)]
-  pub async fn list_stale_publishing_tasks(
+  /// Lists the data exports of a user, newest first.
+  #[instrument(name = "Database::list_data_exports", skip(self), err)]
+  pub async fn list_data_exports(
     &self,
-    stale_after_seconds: i64,
-  ) -> Result<Vec<(Uuid, PublishingTaskStatus)>> {
-    sqlx::query!(
-      r#"SELECT id, status as "status: PublishingTaskStatus"
-      FROM publishing_tasks
-      WHERE status IN ('processing', 'processed')
-        AND updated_at < now() - ($1::bigint * interval '1 second')
-      ORDER BY updated_at ASC
-      LIMIT 1000"#,
-      stale_after_seconds,
+    user_id: Uuid,
+  ) -> Result<Vec<DataExport>> {
+    query_concat_as!(
+      DataExport,
+      "SELECT ", DATA_EXPORT_SELECT, " FROM data_exports
+      WHERE user_id = $1
+      ORDER BY created_at DESC";
+      user_id,
     )
-    .map(|r| (r.id, r.status))
     .fetch_all(&self.pool)
     .await
   }
 
-  #[instrument(name = "Database::get_oauth_state", skip(self), err)]
-  pub async fn get_oauth_state(
-    &self,
-    csrf_token: &str,
-  ) -> Result<Option<OauthState>> {
+  #[instrument(name = "Database::get_data_export", skip(self), err)]
+  pub async fn get_data_export(&self, id: Uuid) -> Result<Option<DataExport>> {
     query_concat_as!(
-      OauthState,
-      "SELECT ", OAUTH_STATE_SELECT, " FROM oauth_states WHERE csrf_token = $1";
-      csrf_token
+      DataExport,
+      "SELECT ", DATA_EXPORT_SELECT, " FROM data_exports WHERE id = $1";
+      id,
     )
     .fetch_optional(&self.pool)
     .await
   }
 
-  #[instrument(name = "Database::delete_expired_oauth_states", skip(self), err)]
-  pub async fn delete_expired_oauth_states(
+  /// Records in the audit log that a user was handed a URL to download their
+  /// data export.
+  #[instrument(name = "Database::log_data_export_download", skip(self), err)]
+  pub async fn log_data_export_download(
     &self,
-    older_than: DateTime<Utc>,
-  ) -> Result<u64> {
-    let result = sqlx::query!(
-      "DELETE FROM oauth_states WHERE created_at < $1",
-      older_than
+    user_id: Uuid,
+    id: Uuid,
+  ) -> Result<()> {
+    let mut tx = self.pool.begin().await?;
+
+    audit_log(
+      &mut tx,
+      &user_id,
+      false,
+      "download_data_export",
+      json!({ "id": id }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+  }
+
+  #[instrument(name = "Database::complete_data_export", skip(self), err)]
+  pub async fn complete_data_export(&self, id: Uuid, size: i64) -> Result<()> {
+    sqlx::query!(
+      "UPDATE data_exports
+      SET status = 'completed', size = $2, error = NULL, completed_at = now()
+      WHERE id = $1 AND status = 'pending'",
+      id,
+      size,
     )
     .execute(&self.pool)
     .await?;
-    Ok(result.rows_affected())
+    Ok(())
   }
 
-  #[instrument(name = "Database::cleanup_download_counts_4h", skip(self), err)]
-  pub async fn cleanup_download_counts_4h(
-    &self,
-    older_than: DateTime<Utc>,
-  ) -> Result<u64> {
-    let result = sqlx::query!(
-      "DELETE FROM version_download_counts_4h WHERE time_bucket < $1",
-      older_than
+  #[instrument(name = "Database::fail_data_export", skip(self, error), err)]
+  pub async fn fail_data_export(&self, id: Uuid, error: &str) -> Result<()> {
+    sqlx::query!(
+      "UPDATE data_exports SET status = 'failed', error = $2
+      WHERE id = $1 AND status = 'pending'",
+      id,
+      error,
     )
     .execute(&self.pool)
     .await?;
-    Ok(result.rows_affected())
+    Ok(())
   }
 
-  #[instrument(name = "Database::insert_oauth_state", skip(
-    self,
-    new_oauth_state
-  ), err, fields(oauth_state.csrf_token = %new_oauth_state.csrf_token, oauth_state.redirect_url = %new_oauth_state.redirect_url
-  ))]
-  pub async fn insert_oauth_state<'a>(
+  /// Lists the completed data exports that were completed before `cutoff`.
+  #[instrument(name = "Database::list_expired_data_exports", skip(self), err)]
+  pub async fn list_expired_data_exports(
     &self,
-    new_oauth_state: NewOauthState<'a>,
-  ) -> Result<OauthState> {
-    query_concat_as!(
-      OauthState,
-      "INSERT INTO oauth_states (csrf_token, pkce_code_verifier, redirect_url, user_id)
-      VALUES ($1, $2, $3, $4)
-      RETURNING ", OAUTH_STATE_SELECT;
-      new_oauth_state.csrf_token,
-      new_oauth_state.pkce_code_verifier,
-      new_oauth_state.redirect_url,
-      new_oauth_state.user_id,
+    cutoff: DateTime<Utc>,
+    limit: i64,
+  ) -> Result<Vec<Uuid>> {
+    sqlx::query!(
+      "SELECT id FROM data_exports
+      WHERE status = 'completed' AND completed_at < $1
+      ORDER BY completed_at ASC
+      LIMIT $2",
+      cutoff,
+      limit,
     )
-    .fetch_one(&self.pool)
+    .map(|r| r.id)
+    .fetch_all(&self.pool)
     .await
   }
 
-  #[instrument(name = "Database::delete_oauth_state", skip(self), err)]
-  pub async fn delete_oauth_state(
-    &self,
-    csrf_token: &str,
-  ) -> Result<Option<OauthState>> {
-    query_concat_as!(
-      OauthState,
-      "DELETE FROM oauth_states WHERE csrf_token = $1
-      RETURNING ", OAUTH_STATE_SELECT;
-      csrf_token
+  /// Marks a data export whose archive was deleted as expired.
+  #[instrument(name = "Database::expire_data_export", skip(self), err)]
+  pub async fn expire_data_export(&self, id: Uuid) -> Result<()> {
+    sqlx::query!(
+      "UPDATE data_exports SET status = 'expired' WHERE id = $1",
+      id,
     )
-    .fetch_optional(&self.pool)
-    .await
+    .execute(&self.pool)
+    .await?;
+    Ok(())
   }
 
-  #[instrument(name = "Database::insert_github_identity", skip(
-    self,
-    new_github_identity
-  ), err, fields(github_identity.github_id = new_github_identity.github_id))]
-  pub async fn upsert_github_identity(
+  /// Gets the notification preferences of a user, creating them with
+  /// everything enabled if the user has none yet.
+  #[instrument(
+    name = "Database::get_notification_preferences",
+    skip(self),
+    err
+  )]
+  pub async fn get_notification_preferences(
     &self,
-    new_github_identity: NewGithubIdentity,
-  ) -> Result<GithubIdentity> {
+    user_id: Uuid,
+  ) -> Result<NotificationPreferences> {
+    sqlx::query!(
+      "INSERT INTO notification_preferences (user_id) VALUES ($1)
+      ON CONFLICT (user_id) DO NOTHING",
+      user_id,
+    )
+    .execute(&self.pool)
+    .await?;
+
     query_concat_as!(
-      GithubIdentity,
-      "INSERT INTO github_identities (github_id, access_token, access_token_expires_at, refresh_token, refresh_token_expires_at) VALUES ($1, $2, $3, $4, $5)
-      ON CONFLICT (github_id) DO
-      UPDATE SET access_token = $2, access_token_expires_at = $3, refresh_token = $4, refresh_token_expires_at = $5
-      RETURNING ", GITHUB_IDENTITY_SELECT;
-      new_github_identity.github_id,
-      new_github_identity.access_token,
-      new_github_identity.access_token_expires_at,
-      new_github_identity.refresh_token,
-      new_github_identity.refresh_token_expires_at,
+      NotificationPreferences,
+      "SELECT ", NOTIFICATION_PREFERENCES_SELECT, " FROM notification_preferences WHERE user_id = $1";
+      user_id,
     )
-      .fetch_one(&self.pool)
-      .await
+    .fetch_one(&self.pool)
+    .await
   }
 
-  #[instrument(name = "Database::get_github_identity", skip(self), err)]
-  pub async fn get_github_identity(
+  /// Gets the notification preferences that the unsubscribe token belongs to.
+  #[instrument(
+    name = "Database::get_notification_preferences_by_unsubscribe_token",
+    skip(self, unsubscribe_token),
+    err
+  )]
+  pub async fn get_notification_preferences_by_unsubscribe_token(
     &self,
-    github_id: i64,
-  ) -> Result<GithubIdentity> {
+    unsubscribe_token: Uuid,
+  ) -> Result<Option<NotificationPreferences>> {
     query_concat_as!(
-      GithubIdentity,
-      "SELECT ", GITHUB_IDENTITY_SELECT, " FROM github_identities WHERE github_id = $1";
-      github_id
+      NotificationPreferences,
+      "SELECT ", NOTIFICATION_PREFERENCES_SELECT, " FROM notification_preferences WHERE unsubscribe_token = $1";
+      unsubscribe_token,
     )
-      .fetch_one(&self.pool)
-      .await
+    .fetch_optional(&self.pool)
+    .await
   }
 
-  #[instrument(name = "Database::delete_github_identity", skip(self), err)]
-  pub async fn delete_github_identity(
+  /// Updates the notification preferences of a user. Preferences that are
+  /// `None` are left as they are.
+  #[instrument(
+    name = "Database::update_notification_preferences",
+    skip(self),
+    err
+  )]
+  pub async fn update_notification_preferences(
     &self,
-    github_id: i64,
-  ) -> Result<GithubIdentity> {
-    sqlx::query_as!(
-      GithubIdentity,
-      "DELETE FROM github_identities WHERE github_id = $1
-      RETURNING github_id, access_token, access_token_expires_at, refresh_token, refresh_token_expires_at, updated_at, created_at",
-      github_id
+    user_id: Uuid,
+    scope_invites: Option<bool>,
+    publish_failures: Option<bool>,
+    scope_digests: Option<bool>,
+  ) -> Result<NotificationPreferences> {
+    let mut tx = self.pool.begin().await?;
+
+    let preferences = query_concat_as!(
+      NotificationPreferences,
+      "INSERT INTO notification_preferences (user_id, scope_invites, publish_failures, scope_digests)
+      VALUES ($1, COALESCE($2, true), COALESCE($3, true), COALESCE($4, true))
+      ON CONFLICT (user_id) DO UPDATE SET
+        scope_invites = COALESCE($2, notification_preferences.scope_invites),
+        publish_failures = COALESCE($3, notification_preferences.publish_failures),
+        scope_digests = COALESCE($4, notification_preferences.scope_digests)
+      RETURNING ", NOTIFICATION_PREFERENCES_SELECT;
+      user_id,
+      scope_invites,
+      publish_failures,
+      scope_digests,
     )
-      .fetch_one(&self.pool)
-      .await
-  }
+    .fetch_one(&mut *tx)
+    .await?;
 
-  #[instrument(name = "Database::insert_gitlab_identity", skip(
-    self,
-    new_gitlab_identity
-  ), err, fields(gitlab_identity.gitlab_id = new_gitlab_identity.gitlab_id))]
-  pub async fn upsert_gitlab_identity(
-    &self,
-    new_gitlab_identity: NewGitlabIdentity,
-  ) -> Result<GitlabIdentity> {
-    sqlx::query_as!(
-      GitlabIdentity,
-      "INSERT INTO gitlab_identities (gitlab_id, access_token, access_token_expires_at, refresh_token) VALUES ($1, $2, $3, $4)
-      ON CONFLICT (gitlab_id) DO
-      UPDATE SET access_token = $2, access_token_expires_at = $3, refresh_token = $4
-      RETURNING gitlab_id, access_token, access_token_expires_at, refresh_token, updated_at, created_at",
-      new_gitlab_identity.gitlab_id,
-      new_gitlab_identity.access_token,
-      new_gitlab_identity.access_token_expires_at,
-      new_gitlab_identity.refresh_token,
+    audit_log(
+      &mut tx,
+      &user_id,
+      false,
+      "update_notification_preferences",
+      json!({
+        "scope_invites": scope_invites,
+        "publish_failures": publish_failures,
+        "scope_digests": scope_digests,
+      }),
     )
-      .fetch_one(&self.pool)
-      .await
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(preferences)
   }
 
-  #[cfg(not(test))]
-  #[instrument(name = "Database::get_gitlab_identity", skip(self), err)]
-  pub async fn get_gitlab_identity(
+  /// Turns off notifications for the user the unsubscribe token belongs to.
+  /// Preferences that are `None` are left as they are. Returns `None` if the
+  /// token belongs to no user.
+  #[instrument(
+    name = "Database::unsubscribe_notifications",
+    skip(self, unsubscribe_token),
+    err
+  )]
+  pub async fn unsubscribe_notifications(
     &self,
-    gitlab_id: i64,
-  ) -> Result<GitlabIdentity> {
-    sqlx::query_as!(
-      GitlabIdentity,
-      "SELECT gitlab_id, access_token, access_token_expires_at, refresh_token, updated_at, created_at
-      FROM gitlab_identities
-      WHERE gitlab_id = $1",
-      gitlab_id
+    unsubscribe_token: Uuid,
+    scope_invites: Option<bool>,
+    publish_failures: Option<bool>,
+    scope_digests: Option<bool>,
+  ) -> Result<Option<NotificationPreferences>> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some(preferences) = query_concat_as!(
+      NotificationPreferences,
+      "UPDATE notification_preferences SET
+        scope_invites = COALESCE($2, scope_invites),
+        publish_failures = COALESCE($3, publish_failures),
+        scope_digests = COALESCE($4, scope_digests)
+      WHERE unsubscribe_token = $1
+      RETURNING ", NOTIFICATION_PREFERENCES_SELECT;
+      unsubscribe_token,
+      scope_invites,
+      publish_failures,
+      scope_digests,
     )
-      .fetch_one(&self.pool)
-      .await
-  }
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
 
-  #[instrument(name = "Database::delete_gitlab_identity", skip(self), err)]
-  pub async fn delete_gitlab_identity(
-    &self,
-    gitlab_id: i64,
-  ) -> Result<GitlabIdentity> {
-    sqlx::query_as!(
-      GitlabIdentity,
-      "DELETE FROM gitlab_identities WHERE gitlab_id = $1
-      RETURNING gitlab_id, access_token, access_token_expires_at, refresh_token, updated_at, created_at",
-      gitlab_id
+    audit_log(
+      &mut tx,
+      &preferences.user_id,
+      false,
+      "unsubscribe_notifications",
+      json!({
+        "scope_invites": scope_invites,
+        "publish_failures": publish_failures,
+        "scope_digests": scope_digests,
+      }),
     )
-      .fetch_one(&self.pool)
-      .await
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(preferences))
   }
 
+  /// Lists users that are members of a scope, have an email address, want
+  /// scope digests, and did not get one since `sent_before`.
   #[instrument(
-    name = "Database::insert_token",
-    skip(self, new_token),
-    err,
-    fields(token.r#type = ?new_token.r#type)
+    name = "Database::list_scope_digest_recipients",
+    skip(self),
+    err
   )]
-  pub async fn insert_token(&self, new_token: NewToken) -> Result<Token> {
-    query_concat_as!(
-      Token,
-      "INSERT INTO tokens (hash, user_id, type, description, expires_at, permissions)
-      VALUES ($1, $2, $3, $4, $5, $6)
-      RETURNING ", TOKEN_SELECT;
-      new_token.hash,
-      new_token.user_id,
-      new_token.r#type as _,
-      new_token.description,
-      new_token.expires_at,
-      new_token.permissions as _,
+  pub async fn list_scope_digest_recipients(
+    &self,
+    sent_before: DateTime<Utc>,
+    limit: i64,
+  ) -> Result<Vec<ScopeDigestRecipient>> {
+    let mut tx = self.pool.begin().await?;
+
+    sqlx::query!(
+      "INSERT INTO notification_preferences (user_id)
+      SELECT DISTINCT user_id FROM scope_members
+      ON CONFLICT (user_id) DO NOTHING",
     )
-      .fetch_one(&self.pool)
-      .await
-  }
+    .execute(&mut *tx)
+    .await?;
 
-  #[instrument(name = "Database::get_token_by_hash", skip(self), err)]
-  pub async fn get_token_by_hash(&self, hash: &str) -> Result<Option<Token>> {
-    query_concat_as!(Token, "SELECT ", TOKEN_SELECT, " FROM tokens WHERE hash = $1"; hash)
-      .fetch_optional(&self.pool)
-      .await
+    let recipients = sqlx::query_as!(
+      ScopeDigestRecipient,
+      r#"SELECT users.id as "user_id", users.name, users.email as "email!",
+        notification_preferences.unsubscribe_token,
+        notification_preferences.last_digest_sent_at
+      FROM users
+      JOIN notification_preferences ON notification_preferences.user_id = users.id
+      WHERE users.email IS NOT NULL
+        AND users.is_blocked = false
+        AND notification_preferences.scope_digests
+        AND (notification_preferences.last_digest_sent_at IS NULL OR notification_preferences.last_digest_sent_at < $1)
+        AND EXISTS (SELECT 1 FROM scope_members WHERE scope_members.user_id = users.id)
+      ORDER BY users.id
+      LIMIT $2"#,
+      sent_before,
+      limit,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(recipients)
   }
 
-  #[instrument(name = "Database::list_token", skip(self), err)]
-  pub async fn list_tokens(&self, user_id: Uuid) -> Result<Vec<Token>> {
-    // list a user's tokens where the expiration date is at most 1 day in the past
-    query_concat_as!(
-      Token,
-      "SELECT ", TOKEN_SELECT, "
-      FROM tokens
-      WHERE user_id = $1 AND (expires_at > now() - interval '1 day' OR expires_at IS NULL)
-      ORDER BY expires_at DESC NULLS FIRST, created_at DESC";
-      user_id
+  /// Lists the versions published since `since` to the scopes that the user
+  /// is a member of, leaving out yanked and deleted versions.
+  #[instrument(name = "Database::list_scope_digest_versions", skip(self), err)]
+  pub async fn list_scope_digest_versions(
+    &self,
+    user_id: Uuid,
+    since: DateTime<Utc>,
+    limit: i64,
+  ) -> Result<Vec<ScopeDigestVersion>> {
+    sqlx::query_as!(
+      ScopeDigestVersion,
+      r#"SELECT package_versions.scope as "scope: ScopeName", package_versions.name as "name: PackageName", package_versions.version as "version: Version", package_versions.created_at
+      FROM package_versions
+      JOIN scope_members ON scope_members.scope = package_versions.scope
+      WHERE scope_members.user_id = $1
+        AND package_versions.created_at > $2
+        AND package_versions.is_yanked = false
+        AND package_versions.deleted_at IS NULL
+      ORDER BY package_versions.scope, package_versions.name, package_versions.created_at DESC
+      LIMIT $3"#,
+      user_id,
+      since,
+      limit,
     )
-      .fetch_all(&self.pool)
-      .await
+    .fetch_all(&self.pool)
+    .await
   }
 
-  #[instrument(name = "Database::delete_token", skip(self), err)]
-  pub async fn delete_token(&self, user_id: Uuid, id: Uuid) -> Result<bool> {
-    let res = sqlx::query!(
-      r#"DELETE FROM tokens WHERE user_id = $1 ANd id = $2"#,
+  #[instrument(name = "Database::mark_scope_digest_sent", skip(self), err)]
+  pub async fn mark_scope_digest_sent(
+    &self,
+    user_id: Uuid,
+    sent_at: DateTime<Utc>,
+  ) -> Result<()> {
+    sqlx::query!(
+      "UPDATE notification_preferences SET last_digest_sent_at = $2
+      WHERE user_id = $1",
       user_id,
-      id
+      sent_at,
     )
     .execute(&self.pool)
     .await?;
-    Ok(res.rows_affected() > 0)
+    Ok(())
   }
 
-  #[instrument(
-    name = "Database::create_authorization",
-    skip(self, new_authorization),
-    err
-  )]
-  pub async fn create_authorization(
+  #[instrument(name = "Database::create_abuse_report", skip(self), err)]
+  pub async fn create_abuse_report(
     &self,
-    new_authorization: NewAuthorization<'_>,
-  ) -> Result<Authorization> {
-    query_concat_as!(
-      Authorization,
-      "INSERT INTO authorizations (exchange_token, code, challenge, permissions, expires_at)
-      VALUES ($1, $2, $3, $4, $5)
-      RETURNING ", AUTHORIZATION_SELECT;
-      new_authorization.exchange_token,
-      new_authorization.code,
-      new_authorization.challenge,
-      new_authorization.permissions as _,
-      new_authorization.expires_at,
+    reporter_id: Uuid,
+    scope: &ScopeName,
+    name: &PackageName,
+    version: Option<&Version>,
+    reason: AbuseReportReason,
+    description: &str,
+  ) -> Result<AbuseReport> {
+    let mut tx = self.pool.begin().await?;
+
+    let report = query_concat_as!(
+      AbuseReport,
+      "INSERT INTO abuse_reports (scope, name, version, reason, description, reporter_id)
+      VALUES ($1, $2, $3, $4, $5, $6)
+      RETURNING ", ABUSE_REPORT_SELECT;
+      scope as _,
+      name as _,
+      version as _,
+      reason as _,
+      description,
+      reporter_id,
     )
-      .fetch_one(&self.pool)
-      .await
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit_log(
+      &mut tx,
+      &reporter_id,
+      false,
+      "create_abuse_report",
+      json!({
+        "id": report.id,
+        "scope": scope,
+        "name": name,
+        "version": version,
+        "reason": reason,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(report)
   }
 
-  #[instrument(name = "Database::get_authorization_by_code", skip(self), err)]
-  pub async fn get_authorization_by_code(
+  #[instrument(name = "Database::get_abuse_report", skip(self), err)]
+  pub async fn get_abuse_report(
     &self,
-    code: &str,
-  ) -> Result<Option<Authorization>> {
+    id: Uuid,
+  ) -> Result<Option<AbuseReport>> {
     query_concat_as!(
-      Authorization,
-      "SELECT ", AUTHORIZATION_SELECT, " FROM authorizations WHERE code = $1";
-      code
+      AbuseReport,
+      "SELECT ", ABUSE_REPORT_SELECT, " FROM abuse_reports WHERE id = $1";
+      id,
     )
     .fetch_optional(&self.pool)
     .await
   }
 
-  #[instrument(
-    name = "Database::get_authorization_by_exchange_token",
-    skip(self, exchange_token),
-    err
-  )]
-  pub async fn get_authorization_by_exchange_token_and_remove_if_complete(
+  /// List abuse reports, oldest first so that the moderation queue is worked
+  /// through in order, optionally only those with the given status.
+  #[instrument(name = "Database::list_abuse_reports", skip(self), err)]
+  pub async fn list_abuse_reports(
     &self,
-    exchange_token: &str,
-  ) -> Result<Option<Authorization>> {
+    status: Option<AbuseReportStatus>,
+    start: i64,
+    limit: i64,
+  ) -> Result<(usize, Vec<AbuseReport>)> {
     let mut tx = self.pool.begin().await?;
 
-    let maybe_authorization = query_concat_as!(
-      Authorization,
-      "DELETE FROM authorizations WHERE exchange_token = $1
-      RETURNING ", AUTHORIZATION_SELECT;
-      exchange_token
+    let reports = query_concat_as!(
+      AbuseReport,
+      "SELECT ", ABUSE_REPORT_SELECT, " FROM abuse_reports
+      WHERE ($1::abuse_report_status IS NULL OR status = $1)
+      ORDER BY created_at ASC
+      OFFSET $2 LIMIT $3";
+      status as _,
+      start,
+      limit,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let total = sqlx::query!(
+      r#"SELECT COUNT(created_at) FROM abuse_reports
+      WHERE ($1::abuse_report_status IS NULL OR status = $1)"#,
+      status as _,
     )
-    .fetch_optional(&mut *tx)
+    .map(|r| r.count.unwrap())
+    .fetch_one(&mut *tx)
     .await?;
 
-    if let Some(authorization) = &maybe_authorization
-      && authorization.user_id.is_some()
-    {
-      tx.commit().await?;
-    }
+    tx.commit().await?;
 
-    Ok(maybe_authorization)
+    Ok((total as usize, reports))
   }
 
-  #[instrument(name = "Database::update_authorization", skip(self), err)]
-  pub async fn update_authorization(
+  /// Moves an abuse report from status `from` to status `to`. Returns `None`
+  /// if the report does not exist or is no longer in status `from`, because
+  /// another staff member moved it in the meantime.
+  #[instrument(name = "Database::update_abuse_report_status", skip(self), err)]
+  pub async fn update_abuse_report_status(
     &self,
-    code: &str,
-    approved: bool,
-    user_id: Uuid,
-  ) -> Result<bool> {
-    let res = sqlx::query!(
-      r#"UPDATE authorizations
-      SET approved = $1, user_id = $2
-      WHERE code = $3 AND approved IS NULL"#,
-      approved,
-      user_id,
-      code
+    staff_id: &Uuid,
+    id: Uuid,
+    from: AbuseReportStatus,
+    to: AbuseReportStatus,
+    resolution: Option<&str>,
+  ) -> Result<Option<AbuseReport>> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some(report) = query_concat_as!(
+      AbuseReport,
+      "UPDATE abuse_reports SET status = $3, reviewer_id = $4,
+        resolution = COALESCE($5, resolution),
+        resolved_at = CASE WHEN $3 IN ('actioned'::abuse_report_status, 'dismissed'::abuse_report_status) THEN NOW() ELSE NULL END
+      WHERE id = $1 AND status = $2
+      RETURNING ", ABUSE_REPORT_SELECT;
+      id,
+      from as _,
+      to as _,
+      staff_id,
+      resolution,
     )
-    .execute(&self.pool)
-    .await?;
-    Ok(res.rows_affected() > 0)
-  }
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
 
-  #[instrument(name = "Database::list_package_dependencies", skip(self), err)]
-  pub async fn list_package_dependencies(
-    &self,
-    scope: &ScopeName,
-    name: &PackageName,
-  ) -> Result<Vec<PackageVersionDependency>> {
-    query_concat_as!(
-      PackageVersionDependency,
-      "SELECT ", PACKAGE_VERSION_DEPENDENCY_SELECT, "
-      FROM package_version_dependencies
-      WHERE package_scope = $1 AND package_name = $2
-      ORDER BY dependency_kind ASC, dependency_name ASC, dependency_constraint ASC, dependency_path ASC";
-      scope as _,
-      name as _,
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "update_abuse_report",
+      json!({
+        "id": id,
+        "from": from,
+        "to": to,
+        "resolution": resolution,
+      }),
     )
-      .fetch_all(&self.pool)
-      .await
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(report))
   }
 
   #[instrument(
-    name = "Database::list_package_version_dependencies",
+    name = "Database::get_package_version_quarantine",
     skip(self),
     err
   )]
-  pub async fn list_package_version_dependencies(
+  pub async fn get_package_version_quarantine(
     &self,
     scope: &ScopeName,
     name: &PackageName,
     version: &Version,
-  ) -> Result<Vec<PackageVersionDependency>> {
+  ) -> Result<Option<PackageVersionQuarantine>> {
     query_concat_as!(
-      PackageVersionDependency,
-      "SELECT ", PACKAGE_VERSION_DEPENDENCY_SELECT, "
-      FROM package_version_dependencies
-      WHERE package_scope = $1 AND package_name = $2 AND package_version = $3
-      ORDER BY dependency_kind ASC, dependency_name ASC, dependency_constraint ASC, dependency_path ASC";
+      PackageVersionQuarantine,
+      "SELECT ", PACKAGE_VERSION_QUARANTINE_SELECT, " FROM package_version_quarantines
+      WHERE scope = $1 AND name = $2 AND version = $3";
       scope as _,
       name as _,
-      version as _
+      version as _,
     )
-      .fetch_all(&self.pool)
-      .await
+    .fetch_optional(&self.pool)
+    .await
   }
 
-  #[instrument(name = "Database::list_package_dependents", skip(self), err)]
-  pub async fn list_package_dependents(
+  /// List quarantined package versions, most recently quarantined first.
+  #[instrument(
+    name = "Database::list_package_version_quarantines",
+    skip(self),
+    err
+  )]
+  pub async fn list_package_version_quarantines(
     &self,
-    kind: DependencyKind,
-    name: &str,
     start: i64,
     limit: i64,
-    versions_per_package_limit: i64,
-  ) -> Result<(usize, Vec<Dependent>)> {
+  ) -> Result<(usize, Vec<PackageVersionQuarantine>)> {
     let mut tx = self.pool.begin().await?;
-    let dependents = sqlx::query_as!(
-      Dependent,
-      r#"
-      SELECT
-        package_scope as "scope: ScopeName",
-        package_name as "name: PackageName",
-        (ARRAY_AGG(DISTINCT package_version))[:$5] as "versions!: Vec<Version>",
-        COUNT(DISTINCT package_version) as "total_versions!"
-      FROM
-        package_version_dependencies
-      WHERE
-        dependency_kind = $1 AND dependency_name = $2
-      GROUP BY package_scope, package_name
-      ORDER BY package_scope ASC, package_name ASC OFFSET $3 LIMIT $4;
-      "#,
-      kind as _,
-      name,
+
+    let quarantines = query_concat_as!(
+      PackageVersionQuarantine,
+      "SELECT ", PACKAGE_VERSION_QUARANTINE_SELECT, " FROM package_version_quarantines
+      ORDER BY created_at DESC
+      OFFSET $1 LIMIT $2";
       start,
       limit,
-      versions_per_package_limit as i32,
     )
     .fetch_all(&mut *tx)
     .await?;
 
-    let total_unique_package_dependents = sqlx::query!(
-      r#"SELECT COUNT(*) FROM (
-        SELECT DISTINCT package_scope, package_name
-        FROM package_version_dependencies
-        WHERE dependency_kind = $1 AND dependency_name = $2
-      ) t;"#,
-      kind as _,
-      name,
+    let total = sqlx::query!(
+      r#"SELECT COUNT(created_at) FROM package_version_quarantines"#
     )
     .map(|r| r.count.unwrap())
     .fetch_one(&mut *tx)
@@ -3773,987 +10778,1148 @@ gitlab_id: r.user_gitlab_id,
 
     tx.commit().await?;
 
-    Ok((total_unique_package_dependents as usize, dependents))
+    Ok((total as usize, quarantines))
   }
 
-  #[instrument(name = "Database::count_package_dependents", skip(self), err)]
-  pub async fn count_package_dependents(
+  /// Quarantines a package version. Fails with a unique violation if the
+  /// version is already quarantined.
+  #[instrument(name = "Database::quarantine_package_version", skip(self), err)]
+  pub async fn quarantine_package_version(
     &self,
-    kind: DependencyKind,
-    name: &str,
-  ) -> Result<usize> {
-    let total_unique_package_dependents = sqlx::query!(
-      r#"SELECT COUNT(*) FROM (
-        SELECT DISTINCT package_scope, package_name
-        FROM package_version_dependencies
-        WHERE dependency_kind = $1 AND dependency_name = $2
-      ) t;"#,
-      kind as _,
-      name,
+    staff_id: &Uuid,
+    scope: &ScopeName,
+    name: &PackageName,
+    version: &Version,
+    reason: &str,
+    report_id: Option<Uuid>,
+  ) -> Result<PackageVersionQuarantine> {
+    let mut tx = self.pool.begin().await?;
+
+    let quarantine = query_concat_as!(
+      PackageVersionQuarantine,
+      "INSERT INTO package_version_quarantines (scope, name, version, reason, report_id, quarantined_by)
+      VALUES ($1, $2, $3, $4, $5, $6)
+      RETURNING ", PACKAGE_VERSION_QUARANTINE_SELECT;
+      scope as _,
+      name as _,
+      version as _,
+      reason,
+      report_id,
+      staff_id,
     )
-    .map(|r| r.count.unwrap())
-    .fetch_one(&self.pool)
+    .fetch_one(&mut *tx)
     .await?;
 
-    Ok(total_unique_package_dependents as usize)
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "quarantine_package_version",
+      json!({
+        "scope": scope,
+        "name": name,
+        "version": version,
+        "reason": reason,
+        "report_id": report_id,
+      }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    self.package_changed(scope, name).await;
+
+    Ok(quarantine)
   }
 
-  #[instrument(name = "Database::count_package_dependencies", skip(self), err)]
-  pub async fn count_package_dependencies(
+  /// Releases a package version from quarantine. Returns `None` if the
+  /// version is not quarantined.
+  #[instrument(
+    name = "Database::release_package_version_quarantine",
+    skip(self),
+    err
+  )]
+  pub async fn release_package_version_quarantine(
     &self,
+    staff_id: &Uuid,
     scope: &ScopeName,
     name: &PackageName,
     version: &Version,
-  ) -> Result<usize> {
-    let total_package_dependencies = sqlx::query!(
-      r#"SELECT COUNT(DISTINCT dependency_name)
-      FROM package_version_dependencies
-      WHERE package_scope = $1 AND package_name = $2 AND package_version = $3"#,
+  ) -> Result<Option<PackageVersionQuarantine>> {
+    let mut tx = self.pool.begin().await?;
+
+    let Some(quarantine) = query_concat_as!(
+      PackageVersionQuarantine,
+      "DELETE FROM package_version_quarantines
+      WHERE scope = $1 AND name = $2 AND version = $3
+      RETURNING ", PACKAGE_VERSION_QUARANTINE_SELECT;
       scope as _,
       name as _,
-      version as _
+      version as _,
     )
-    .fetch_one(&self.pool)
+    .fetch_optional(&mut *tx)
     .await?
-    .count
-    .unwrap();
+    else {
+      return Ok(None);
+    };
 
-    Ok(total_package_dependencies as usize)
-  }
+    audit_log(
+      &mut tx,
+      staff_id,
+      true,
+      "release_package_version_quarantine",
+      json!({
+        "scope": scope,
+        "name": name,
+        "version": version,
+      }),
+    )
+    .await?;
 
-  #[instrument(name = "Database::check_bad_word", skip(self), err)]
-  pub async fn check_is_bad_word(&self, word: &str) -> Result<bool> {
-    let res = sqlx::query!("SELECT * FROM bad_words WHERE word = $1", word)
-      .fetch_optional(&self.pool)
-      .await?;
-    Ok(res.is_some())
+    tx.commit().await?;
+
+    self.package_changed(scope, name).await;
+
+    Ok(Some(quarantine))
   }
 
-  #[cfg(test)]
-  #[instrument(name = "Database::add_bad_word_for_test", skip(self), err)]
-  pub async fn add_bad_word_for_test(&self, word: &str) -> Result<()> {
-    sqlx::query!("INSERT INTO bad_words (word) VALUES ($1)", word)
-      .execute(&self.pool)
-      .await?;
+  /// Takes or renews the lease of `holder` on running the scheduler, unless
+  /// another instance holds an unexpired lease. Returns whether `holder` is
+  /// the leader.
+  #[instrument(
+    name = "Database::acquire_scheduler_leadership",
+    skip(self),
+    err
+  )]
+  pub async fn acquire_scheduler_leadership(
+    &self,
+    holder: Uuid,
+    lease: chrono::Duration,
+  ) -> Result<bool> {
+    let res = sqlx::query!(
+      "INSERT INTO scheduler_leader (id, holder, expires_at)
+      VALUES (true, $1, $2)
+      ON CONFLICT (id) DO UPDATE SET
+        holder = EXCLUDED.holder,
+        expires_at = EXCLUDED.expires_at,
+        acquired_at = CASE
+          WHEN scheduler_leader.holder = EXCLUDED.holder
+          THEN scheduler_leader.acquired_at
+          ELSE now()
+        END
+      WHERE scheduler_leader.holder = EXCLUDED.holder
+        OR scheduler_leader.expires_at < now()",
+      holder,
+      Utc::now() + lease,
+    )
+    .execute(&self.pool)
+    .await?;
+    Ok(res.rows_affected() > 0)
+  }
 
+  /// Creates the state of a scheduled job, or updates its schedule. The next
+  /// run is only moved to `next_run_at` if the schedule changed.
+  #[instrument(name = "Database::register_scheduled_job", skip(self), err)]
+  pub async fn register_scheduled_job(
+    &self,
+    name: &str,
+    schedule: &str,
+    next_run_at: DateTime<Utc>,
+  ) -> Result<()> {
+    sqlx::query!(
+      "INSERT INTO scheduled_jobs (name, schedule, next_run_at)
+      VALUES ($1, $2, $3)
+      ON CONFLICT (name) DO UPDATE SET
+        schedule = EXCLUDED.schedule,
+        next_run_at = EXCLUDED.next_run_at
+      WHERE scheduled_jobs.schedule <> EXCLUDED.schedule",
+      name,
+      schedule,
+      next_run_at,
+    )
+    .execute(&self.pool)
+    .await?;
     Ok(())
   }
 
-  #[instrument(name = "Database::get_npm_tarball", skip(self), err)]
-  pub async fn get_npm_tarball(
+  /// Starts the run `run_id` of a scheduled job, if it is due and its
+  /// previous run is not running anymore. The run holds a lease on the job
+  /// until `lease` from now, see [Database::renew_scheduled_job_lease].
+  #[instrument(name = "Database::claim_scheduled_job", skip(self), err)]
+  pub async fn claim_scheduled_job(
     &self,
-    scope: &ScopeName,
-    name: &PackageName,
-    version: &Version,
-    revision: i32,
-  ) -> Result<Option<NpmTarball>> {
-    query_concat_as!(
-      NpmTarball,
-      "SELECT ", NPM_TARBALL_SELECT, " FROM npm_tarballs
-      WHERE scope = $1 AND name = $2 AND version = $3 AND revision = $4
-      LIMIT 1";
-      scope as _,
-      name as _,
-      version as _,
-      revision,
+    name: &str,
+    run_id: Uuid,
+    next_run_at: DateTime<Utc>,
+    lease: chrono::Duration,
+  ) -> Result<bool> {
+    let res = sqlx::query!(
+      "UPDATE scheduled_jobs SET
+        run_id = $2,
+        running_since = now(),
+        lease_expires_at = $4,
+        last_started_at = now(),
+        next_run_at = $3
+      WHERE name = $1 AND next_run_at <= now()
+        AND (lease_expires_at IS NULL OR lease_expires_at < now())",
+      name,
+      run_id,
+      next_run_at,
+      Utc::now() + lease,
+    )
+    .execute(&self.pool)
+    .await?;
+    Ok(res.rows_affected() > 0)
+  }
+
+  /// Extends the lease of the run `run_id` of a scheduled job. Returns `false`
+  /// if the run lost its lease.
+  #[instrument(name = "Database::renew_scheduled_job_lease", skip(self), err)]
+  pub async fn renew_scheduled_job_lease(
+    &self,
+    name: &str,
+    run_id: Uuid,
+    lease: chrono::Duration,
+  ) -> Result<bool> {
+    let res = sqlx::query!(
+      "UPDATE scheduled_jobs SET lease_expires_at = $3
+      WHERE name = $1 AND run_id = $2",
+      name,
+      run_id,
+      Utc::now() + lease,
     )
-    .fetch_optional(&self.pool)
-    .await
+    .execute(&self.pool)
+    .await?;
+    Ok(res.rows_affected() > 0)
   }
 
-  #[instrument(name = "Database::list_missing_npm_tarballs", skip(self), err)]
-  pub async fn list_missing_npm_tarballs(
+  /// Records the outcome of the run `run_id` of a scheduled job. Runs that
+  /// came due while it was running are skipped: the next run is at
+  /// `next_run_at` at the earliest.
+  #[instrument(name = "Database::finish_scheduled_job", skip(self, error), err)]
+  pub async fn finish_scheduled_job(
     &self,
-    current_revision: i32,
-  ) -> Result<Vec<(ScopeName, PackageName, Version)>> {
-    // List all package versions (scope, name, version) that do not have a npm_tarball with the current_revision revision
+    name: &str,
+    run_id: Uuid,
+    status: ScheduledJobStatus,
+    error: Option<&str>,
+    next_run_at: DateTime<Utc>,
+  ) -> Result<()> {
     sqlx::query!(
-      r#"SELECT scope as "scope: ScopeName", name as "name: PackageName", version as "version: Version"
-      FROM package_versions
-      WHERE NOT EXISTS (
-        SELECT 1
-        FROM npm_tarballs
-        WHERE npm_tarballs.scope = package_versions.scope AND npm_tarballs.name = package_versions.name AND npm_tarballs.version = package_versions.version AND npm_tarballs.revision = $1
-      )
-      ORDER BY created_at ASC
-      LIMIT 1000
-      "#,
-      current_revision,
+      "UPDATE scheduled_jobs SET
+        run_id = NULL,
+        running_since = NULL,
+        lease_expires_at = NULL,
+        last_finished_at = now(),
+        last_status = $3,
+        last_error = $4,
+        next_run_at = GREATEST(next_run_at, $5)
+      WHERE name = $1 AND run_id = $2",
+      name,
+      run_id,
+      status as _,
+      error,
+      next_run_at,
     )
-      .map(|r| {
-        (r.scope, r.name, r.version)
-      })
-      .fetch_all(&self.pool)
-      .await
+    .execute(&self.pool)
+    .await?;
+    Ok(())
   }
 
-  #[instrument(name = "Database::list_all_scopes_for_sitemap", skip(self), err)]
-  #[allow(clippy::type_complexity)]
-  pub async fn list_all_scopes_for_sitemap(
+  #[instrument(name = "Database::list_scheduled_jobs", skip(self), err)]
+  pub async fn list_scheduled_jobs(&self) -> Result<Vec<ScheduledJob>> {
+    query_concat_as!(
+      ScheduledJob,
+      "SELECT ", SCHEDULED_JOB_SELECT, " FROM scheduled_jobs ORDER BY name";
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  /// Lists the actions a user took, oldest first.
+  #[instrument(name = "Database::list_audit_logs_by_actor", skip(self), err)]
+  pub async fn list_audit_logs_by_actor(
     &self,
-  ) -> Result<Vec<(ScopeName, DateTime<Utc>, Option<DateTime<Utc>>)>> {
-    sqlx::query!(
-      r#"
-        SELECT
-          scope as "scope: ScopeName",
-          updated_at,
-          (SELECT updated_at FROM packages WHERE scope = scope ORDER BY updated_At DESC LIMIT 1) as "latest_package_created_at"
-        FROM scopes
-        ORDER BY scope ASC
-        LIMIT 50000
-      "#
+    actor_id: Uuid,
+  ) -> Result<Vec<AuditLog>> {
+    sqlx::query_as!(
+      AuditLog,
+      r#"SELECT actor_id, is_sudo, action, meta, created_at
+      FROM audit_logs
+      WHERE actor_id = $1
+      ORDER BY created_at ASC"#,
+      actor_id,
     )
-      .map(|r| (r.scope, r.updated_at, r.latest_package_created_at))
-      .fetch_all(&self.pool)
-      .await
+    .fetch_all(&self.pool)
+    .await
   }
 
+  /// Lists the package versions a user published, oldest first.
   #[instrument(
-    name = "Database::list_all_packages_for_sitemap",
+    name = "Database::list_package_versions_by_user",
     skip(self),
     err
   )]
-  #[allow(clippy::type_complexity)]
-  pub async fn list_all_packages_for_sitemap(
+  pub async fn list_package_versions_by_user(
     &self,
-  ) -> Result<Vec<(ScopeName, PackageName, DateTime<Utc>, DateTime<Utc>)>> {
-    sqlx::query!(
-      r#"SELECT
-        scope as "scope: ScopeName", name as "name: PackageName", updated_at,
-        (SELECT created_at FROM package_versions WHERE scope = scope AND name = name ORDER BY version DESC LIMIT 1) as "latest_version_updated_at!"
-      FROM packages
-      WHERE (SELECT version FROM package_versions WHERE scope = scope AND name = name ORDER BY version DESC LIMIT 1) IS NOT NULL
-      ORDER BY scope ASC, name ASC
-      LIMIT 50000"#
+    user_id: Uuid,
+  ) -> Result<Vec<PackageVersion>> {
+    query_concat_as!(
+      PackageVersion,
+      "SELECT ", PACKAGE_VERSION_SELECT, " FROM package_versions
+      WHERE user_id = $1 AND deleted_at IS NULL
+      ORDER BY created_at ASC";
+      user_id,
     )
-      .map(|r| {
-        (
-          r.scope,
-          r.name,
-          r.updated_at,
-          r.latest_version_updated_at,
-        )
-      })
-      .fetch_all(&self.pool)
-      .await
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::list_scope_webhooks", skip(self), err)]
+  pub async fn list_scope_webhooks(
+    &self,
+    scope: &ScopeName,
+  ) -> Result<Vec<ScopeWebhook>> {
+    query_concat_as!(
+      ScopeWebhook,
+      "SELECT ", SCOPE_WEBHOOK_SELECT, " FROM scope_webhooks
+      WHERE scope = $1
+      ORDER BY created_at ASC";
+      scope as _,
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  #[instrument(name = "Database::get_scope_webhook", skip(self), err)]
+  pub async fn get_scope_webhook(
+    &self,
+    scope: &ScopeName,
+    id: Uuid,
+  ) -> Result<Option<ScopeWebhook>> {
+    query_concat_as!(
+      ScopeWebhook,
+      "SELECT ", SCOPE_WEBHOOK_SELECT, " FROM scope_webhooks
+      WHERE scope = $1 AND id = $2";
+      scope as _,
+      id,
+    )
+    .fetch_optional(&self.pool)
+    .await
   }
 
   #[instrument(
-    name = "Database::insert_download_entries",
-    skip(self, entries),
+    name = "Database::create_scope_webhook",
+    skip(self, new_webhook),
     err
   )]
-  pub async fn insert_download_entries(
+  pub async fn create_scope_webhook(
     &self,
-    entries: Vec<VersionDownloadCount>,
-  ) -> Result<()> {
-    if entries.is_empty() {
-      return Ok(());
-    }
-
+    actor_id: &Uuid,
+    is_sudo: bool,
+    new_webhook: NewScopeWebhook<'_>,
+  ) -> Result<ScopeWebhook> {
     let mut tx = self.pool.begin().await?;
 
-    let mut scopes = Vec::with_capacity(entries.len());
-    let mut packages = Vec::with_capacity(entries.len());
-    let mut versions = Vec::with_capacity(entries.len());
-    let mut time_buckets = Vec::with_capacity(entries.len());
-    let mut kinds = Vec::with_capacity(entries.len());
-    let mut counts = Vec::with_capacity(entries.len());
-
-    let mut smallest_time_bucket = Utc::now();
-    let mut largest_time_bucket = DateTime::from_timestamp_nanos(0);
-
-    for entry in entries {
-      scopes.push(entry.scope);
-      packages.push(entry.package);
-      versions.push(entry.version);
-      time_buckets.push(entry.time_bucket);
-      kinds.push(entry.kind);
-      counts.push(entry.count);
-
-      if entry.time_bucket < smallest_time_bucket {
-        smallest_time_bucket = entry.time_bucket;
-      }
-      if entry.time_bucket > largest_time_bucket {
-        largest_time_bucket = entry.time_bucket;
-      }
-    }
-
-    // Upsert data into version_download_counts_4h
-    sqlx::query!(
-      r#"
-      INSERT INTO version_download_counts_4h (scope, package, version, time_bucket, kind, count)
-      SELECT * FROM UNNEST($1::TEXT[], $2::TEXT[], $3::TEXT[], $4::TIMESTAMPTZ[], $5::download_kind[], $6::INT[]) as temp(scope, package, version, time_bucket, kind, count)
-      WHERE (SELECT COUNT(*) FROM package_versions WHERE package_versions.scope = temp.scope AND package_versions.name = temp.package AND version = temp.version) > 0
-      ON CONFLICT (scope, package, version, time_bucket, kind) DO UPDATE SET count = EXCLUDED.count
-      "#,
-      &scopes as _,
-      &packages as _,
-      &versions as _,
-      &time_buckets,
-      &kinds as _,
-      &counts as _,
-    )
-      .execute(&mut *tx)
-      .await?;
-
-    // Compute data in version_download_counts_24h from version_download_counts_4h between smallest_timestamp and largest_timestamp.
-    // smallest_timestamp must be truncated down to the nearest day and largest_timestamp must be truncated up to the nearest day.
-    sqlx::query!(
-      r#"
-      INSERT INTO version_download_counts_24h (scope, package, version, time_bucket, kind, count)
-      SELECT scope, package, version, date_trunc('day', time_bucket), kind, SUM(count)
-      FROM version_download_counts_4h
-      WHERE time_bucket >= date_trunc('day', $1::timestamptz) AND time_bucket < date_trunc('day', $2::timestamptz) + interval '1 day'
-      GROUP BY scope, package, version, date_trunc('day', time_bucket), kind
-      ON CONFLICT (scope, package, version, time_bucket, kind) DO UPDATE SET count = EXCLUDED.count
-      "#,
-      smallest_time_bucket,
-      largest_time_bucket,
+    let webhook = query_concat_as!(
+      ScopeWebhook,
+      "INSERT INTO scope_webhooks (scope, url, secret, events, description, is_active, created_by)
+      VALUES ($1, $2, $3, $4, $5, $6, $7)
+      RETURNING ", SCOPE_WEBHOOK_SELECT;
+      new_webhook.scope as _,
+      new_webhook.url,
+      new_webhook.secret,
+      new_webhook.events as _,
+      new_webhook.description,
+      new_webhook.is_active,
+      actor_id,
     )
-      .execute(&mut *tx)
-      .await?;
+    .fetch_one(&mut *tx)
+    .await?;
 
-    // Aggregate version-level 24h counts into package-level 24h counts.
-    // Derived from version_download_counts_24h (not 4h) so that the
-    // package-level rollup stays correct even after 4h rows are pruned.
-    sqlx::query!(
-      r#"
-      INSERT INTO package_download_counts_24h (scope, package, time_bucket, kind, count)
-      SELECT scope, package, time_bucket, kind, SUM(count)
-      FROM version_download_counts_24h
-      WHERE time_bucket >= date_trunc('day', $1::timestamptz) AND time_bucket < date_trunc('day', $2::timestamptz) + interval '1 day'
-      GROUP BY scope, package, time_bucket, kind
-      ON CONFLICT (scope, package, time_bucket, kind) DO UPDATE SET count = EXCLUDED.count
-      "#,
-      smallest_time_bucket,
-      largest_time_bucket,
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "create_scope_webhook",
+      json!({
+        "scope": webhook.scope,
+        "id": webhook.id,
+        "url": webhook.url,
+        "events": webhook.events,
+      }),
     )
-      .execute(&mut *tx)
-      .await?;
+    .await?;
 
     tx.commit().await?;
 
-    Ok(())
+    Ok(webhook)
   }
 
   #[instrument(
-    name = "Database::get_package_version_downloads_24h",
-    skip(self),
+    name = "Database::update_scope_webhook",
+    skip(self, update),
     err
   )]
-  pub async fn get_package_versions_downloads_24h(
+  pub async fn update_scope_webhook(
     &self,
+    actor_id: &Uuid,
+    is_sudo: bool,
     scope: &ScopeName,
-    name: &PackageName,
-    versions: &[Version],
-    start: DateTime<Utc>,
-    end: DateTime<Utc>,
-  ) -> Result<Vec<VersionDownloadDataPoint>> {
-    sqlx::query_as!(
-      VersionDownloadDataPoint,
-      r#"
-      SELECT version as "version: Version", time_bucket, kind as "kind: DownloadKind", count
-      FROM version_download_counts_24h
-      WHERE scope = $1 AND package = $2 AND version = ANY($3) AND time_bucket >= $4 AND time_bucket < $5
-      ORDER BY time_bucket ASC, version DESC
-      "#,
-      scope as _,
-      name as _,
-      versions as _,
-      start,
-      end,
-    )
-      .fetch_all(&self.pool)
-      .await
-  }
+    id: Uuid,
+    update: UpdateScopeWebhook<'_>,
+  ) -> Result<Option<ScopeWebhook>> {
+    let mut tx = self.pool.begin().await?;
 
-  #[instrument(name = "Database::get_package_downloads_24h", skip(self), err)]
-  pub async fn get_package_downloads_24h(
-    &self,
-    scope: &ScopeName,
-    name: &PackageName,
-    start: DateTime<Utc>,
-    end: DateTime<Utc>,
-  ) -> Result<Vec<DownloadDataPoint>> {
-    sqlx::query_as!(
-      DownloadDataPoint,
-      r#"
-    SELECT time_bucket, kind as "kind: DownloadKind", count as "count!"
-    FROM package_download_counts_24h
-    WHERE scope = $1 AND package = $2 AND time_bucket >= $3 AND time_bucket < $4
-    ORDER BY time_bucket ASC
-    "#,
+    let Some(webhook) = query_concat_as!(
+      ScopeWebhook,
+      "UPDATE scope_webhooks
+      SET url = COALESCE($3, url), secret = COALESCE($4, secret), events = COALESCE($5, events),
+        description = COALESCE($6, description), is_active = COALESCE($7, is_active)
+      WHERE scope = $1 AND id = $2
+      RETURNING ", SCOPE_WEBHOOK_SELECT;
       scope as _,
-      name as _,
-      start,
-      end,
+      id,
+      update.url,
+      update.secret,
+      update.events as _,
+      update.description,
+      update.is_active,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "update_scope_webhook",
+      json!({
+        "scope": webhook.scope,
+        "id": webhook.id,
+        "url": webhook.url,
+        "events": webhook.events,
+        "is_active": webhook.is_active,
+        "secret_changed": update.secret.is_some(),
+      }),
     )
-    .fetch_all(&self.pool)
-    .await
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(webhook))
   }
 
-  #[instrument(name = "Database::create_ticket", skip(self), err)]
-  pub async fn create_ticket(
+  #[instrument(name = "Database::delete_scope_webhook", skip(self), err)]
+  pub async fn delete_scope_webhook(
     &self,
-    user_id: Uuid,
-    new_ticket: NewTicket,
-  ) -> Result<(Ticket, User, TicketMessage)> {
+    actor_id: &Uuid,
+    is_sudo: bool,
+    scope: &ScopeName,
+    id: Uuid,
+  ) -> Result<Option<ScopeWebhook>> {
     let mut tx = self.pool.begin().await?;
 
-    let (ticket, user) = query_concat!(
-      "WITH ticket AS (
-          INSERT INTO tickets (kind, creator, meta)
-          VALUES ($1, $2, $3)
-          RETURNING id, kind, creator, meta, closed, updated_at, created_at
-        )
-        SELECT
-            ticket.id as \"ticket_id\",
-            ticket.kind as \"ticket_kind: TicketKind\",
-            ticket.creator as \"ticket_creator\",
-            ticket.meta as \"ticket_meta\",
-            ticket.closed as \"ticket_closed\",
-            ticket.updated_at as \"ticket_updated_at\",
-            ticket.created_at as \"ticket_created_at\",
-            ", USER_SELECT_FULL_JOINED, "
-        FROM ticket
-        INNER JOIN users ON users.id = ticket.creator
-    ";
-      new_ticket.kind as _,
-      user_id as _,
-      new_ticket.meta as _,
+    let Some(webhook) = query_concat_as!(
+      ScopeWebhook,
+      "DELETE FROM scope_webhooks WHERE scope = $1 AND id = $2 RETURNING ", SCOPE_WEBHOOK_SELECT;
+      scope as _,
+      id,
     )
-    .map(|r| {
-      let ticket = Ticket {
-        id: r.ticket_id,
-        kind: r.ticket_kind,
-        creator: r.ticket_creator,
-        meta: r.ticket_meta,
-        closed: r.ticket_closed,
-        updated_at: r.ticket_updated_at,
-        created_at: r.ticket_created_at,
-      };
-
-      let user = User {
-        id: r.user_id,
-        name: r.user_name,
-        email: r.user_email,
-        avatar_url: r.user_avatar_url,
-        github_id: r.user_github_id,
-        gitlab_id: r.user_gitlab_id,
-        is_blocked: r.user_is_blocked,
-        is_staff: r.user_is_staff,
-        scope_usage: r.user_scope_usage,
-        scope_limit: r.user_scope_limit,
-        invite_count: r.user_invite_count,
-        newer_ticket_messages_count: r.user_newer_ticket_messages_count,
-        updated_at: r.user_updated_at,
-        created_at: r.user_created_at,
-      };
-
-      (ticket, user)
-    })
-    .fetch_one(&mut *tx)
-    .await?;
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
 
-    let message = sqlx::query!(
-      r#"INSERT INTO ticket_messages (ticket_id, author, message)
-          VALUES ($1, $2, $3)
-          RETURNING ticket_id, author, message, updated_at, created_at
-    "#,
-      ticket.id as _,
-      user_id as _,
-      new_ticket.message as _,
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "delete_scope_webhook",
+      json!({
+        "scope": webhook.scope,
+        "id": webhook.id,
+        "url": webhook.url,
+      }),
     )
-    .map(|r| TicketMessage {
-      ticket_id: r.ticket_id,
-      author: r.author,
-      message: r.message,
-      updated_at: r.updated_at,
-      created_at: r.created_at,
-    })
-    .fetch_one(&mut *tx)
     .await?;
 
     tx.commit().await?;
 
-    Ok((ticket, user, message))
+    Ok(Some(webhook))
   }
 
-  #[instrument(name = "Database::list_tickets", skip(self), err)]
-  pub async fn list_tickets(
+  /// Lists the deliveries of a webhook, newest first.
+  #[instrument(name = "Database::list_webhook_deliveries", skip(self), err)]
+  pub async fn list_webhook_deliveries(
     &self,
+    webhook_id: Uuid,
     start: i64,
     limit: i64,
-    maybe_search_query: Option<&str>,
-    maybe_sort: Option<&str>,
-  ) -> Result<(usize, Vec<FullTicket>)> {
+  ) -> Result<(usize, Vec<WebhookDelivery>)> {
     let mut tx = self.pool.begin().await?;
 
-    let search = format!("%{}%", maybe_search_query.unwrap_or(""));
-    let sort = sort_by!(maybe_sort => {
-      @timestamps "updated_at", "created_at";
-      "kind" => "tickets.kind",
-      "creator" => "users.name",
-      "closed" => "tickets.closed",
-      "updated_at" => "tickets.updated_at",
-      "created_at" => "tickets.created_at",
-    } || "tickets.closed ASC, tickets.created_at DESC");
-
-    let tickets = sqlx::query(&format!(
-      r#"SELECT {}, {} FROM tickets
-    INNER JOIN users ON users.id = tickets.creator
-    WHERE users.name ILIKE $1
-       OR EXISTS (
-         SELECT 1
-         FROM ticket_messages
-         WHERE ticket_messages.ticket_id = tickets.id
-           AND ticket_messages.message ILIKE $1
-       )
-    ORDER BY {sort} OFFSET $2 LIMIT $3
-"#,
-      crate::db::sql_fragments::TICKET_SELECT_JOINED_RT,
-      crate::db::sql_fragments::USER_SELECT_FULL_JOINED_RT,
-    ))
-    .bind(&search)
-    .bind(start)
-    .bind(limit)
-    .try_map(|r| {
-      let ticket = Ticket::from_row(&r)?;
-      let user = User::from_row(&r)?;
-
-      Ok((ticket, user))
-    })
+    let deliveries = query_concat_as!(
+      WebhookDelivery,
+      "SELECT ", WEBHOOK_DELIVERY_SELECT, " FROM webhook_deliveries
+      WHERE webhook_id = $1
+      ORDER BY created_at DESC
+      OFFSET $2 LIMIT $3";
+      webhook_id,
+      start,
+      limit,
+    )
     .fetch_all(&mut *tx)
     .await?;
 
-    let mut out = Vec::with_capacity(tickets.len());
-    for (ticket, user) in tickets {
-      let messages = query_concat!(
-      "SELECT ", TICKET_MESSAGE_SELECT_JOINED, ", ", USER_PUBLIC_SELECT_JOINED_RT, "
-        FROM ticket_messages
-        LEFT JOIN users ON users.id = ticket_messages.author
-        WHERE ticket_messages.ticket_id = $1 ORDER BY ticket_messages.created_at";
-      ticket.id as _,
+    let total = sqlx::query!(
+      r#"SELECT COUNT(*) as "count!" FROM webhook_deliveries WHERE webhook_id = $1"#,
+      webhook_id,
     )
-        .map(|r| {
-          let message = TicketMessage {
-            ticket_id: r.message_ticket_id,
-            author: r.message_author,
-            message: r.message_message,
-            updated_at: r.message_updated_at,
-            created_at: r.message_created_at,
-          };
+    .map(|r| r.count)
+    .fetch_one(&mut *tx)
+    .await?;
 
-          let user = UserPublic {
-            id: r.user_id,
-            name: r.user_name,
-            avatar_url: r.user_avatar_url,
-            github_id: r.user_github_id,
-gitlab_id: r.user_gitlab_id,
-            updated_at: r.user_updated_at,
-            created_at: r.user_created_at,
-          };
+    tx.commit().await?;
 
-          (message, user)
-        })
-        .fetch_all(&mut *tx)
-        .await?;
+    Ok((total as usize, deliveries))
+  }
 
-      out.push((ticket, user, messages));
+  /// Inserts events published to the Postgres event sink.
+  #[instrument(
+    name = "Database::insert_events",
+    skip(self, events),
+    fields(count = events.len()),
+    err
+  )]
+  pub async fn insert_events(&self, events: &[Event]) -> Result<()> {
+    let mut ids = Vec::with_capacity(events.len());
+    let mut types = Vec::with_capacity(events.len());
+    let mut payloads = Vec::with_capacity(events.len());
+    let mut created_ats = Vec::with_capacity(events.len());
+    for event in events {
+      ids.push(event.id);
+      types.push(event.event.name());
+      payloads.push(serde_json::to_value(event).unwrap());
+      created_ats.push(event.created_at);
     }
+    sqlx::query!(
+      r#"INSERT INTO events (id, type, payload, created_at)
+      SELECT * FROM UNNEST($1::uuid[], $2::text[], $3::jsonb[], $4::timestamptz[])
+      ON CONFLICT (id) DO NOTHING"#,
+      &ids,
+      &types as _,
+      &payloads,
+      &created_ats,
+    )
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
 
-    let total_tickets = sqlx::query!(
-      r#"SELECT COUNT(tickets.created_at) FROM tickets
-    LEFT JOIN users ON users.id = tickets.creator
-    WHERE users.name ILIKE $1
-       OR EXISTS (
-         SELECT 1
-         FROM ticket_messages
-         WHERE ticket_messages.ticket_id = tickets.id
-           AND ticket_messages.message ILIKE $1
-       )"#,
-      search,
+  /// Queues a delivery of `payload` for every active webhook of `scope` that
+  /// subscribes to `event`. Returns the number of deliveries queued.
+  #[instrument(
+    name = "Database::enqueue_webhook_deliveries",
+    skip(self, payload),
+    err
+  )]
+  pub async fn enqueue_webhook_deliveries(
+    &self,
+    scope: &ScopeName,
+    event: WebhookEvent,
+    payload: serde_json::Value,
+  ) -> Result<u64> {
+    let result = sqlx::query!(
+      r#"INSERT INTO webhook_deliveries (webhook_id, event, payload)
+      SELECT id, $2, $3 FROM scope_webhooks
+      WHERE scope = $1 AND is_active AND $2 = ANY(events)"#,
+      scope as _,
+      event as _,
+      payload,
     )
-    .map(|r| r.count.unwrap())
-    .fetch_one(&mut *tx)
+    .execute(&self.pool)
+    .await?;
+    Ok(result.rows_affected())
+  }
+
+  /// Claims up to `limit` pending deliveries that are due, oldest first. The
+  /// claimed deliveries are not due again for `lease`, so that concurrent
+  /// runs of the delivery task do not send them twice.
+  #[instrument(
+    name = "Database::claim_pending_webhook_deliveries",
+    skip(self),
+    err
+  )]
+  pub async fn claim_pending_webhook_deliveries(
+    &self,
+    limit: i64,
+    lease: chrono::Duration,
+  ) -> Result<Vec<PendingWebhookDelivery>> {
+    sqlx::query_as!(
+      PendingWebhookDelivery,
+      r#"WITH claimed AS (
+        UPDATE webhook_deliveries SET next_attempt_at = $2
+        WHERE id IN (
+          SELECT id FROM webhook_deliveries
+          WHERE status = 'pending' AND next_attempt_at <= now()
+          ORDER BY next_attempt_at ASC
+          LIMIT $1
+          FOR UPDATE SKIP LOCKED
+        )
+        RETURNING id, webhook_id, event, payload, attempts
+      )
+      SELECT claimed.id as "id!", claimed.webhook_id as "webhook_id!", claimed.event as "event!: WebhookEvent", claimed.payload as "payload!", claimed.attempts as "attempts!", scope_webhooks.url, scope_webhooks.secret
+      FROM claimed
+      JOIN scope_webhooks ON scope_webhooks.id = claimed.webhook_id"#,
+      limit,
+      Utc::now() + lease,
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  /// Records the outcome of an attempt to send a delivery. A `pending`
+  /// delivery is retried at `next_attempt_at`.
+  #[instrument(
+    name = "Database::finish_webhook_delivery_attempt",
+    skip(self, error),
+    err
+  )]
+  pub async fn finish_webhook_delivery_attempt(
+    &self,
+    id: Uuid,
+    status: WebhookDeliveryStatus,
+    response_status: Option<i32>,
+    error: Option<&str>,
+    next_attempt_at: DateTime<Utc>,
+  ) -> Result<()> {
+    sqlx::query!(
+      r#"UPDATE webhook_deliveries
+      SET status = $2, attempts = attempts + 1, last_response_status = $3, last_error = $4, next_attempt_at = $5,
+        delivered_at = CASE WHEN $2 = 'success'::webhook_delivery_status THEN now() ELSE delivered_at END
+      WHERE id = $1"#,
+      id,
+      status as _,
+      response_status,
+      error,
+      next_attempt_at,
+    )
+    .execute(&self.pool)
     .await?;
+    Ok(())
+  }
+
+  /// Of the given `@scope/name` package names, the ones that are archived.
+  #[instrument(name = "Database::list_archived_package_names", skip(self), err)]
+  pub async fn list_archived_package_names(
+    &self,
+    names: &[String],
+  ) -> Result<HashSet<String>> {
+    sqlx::query!(
+      r#"SELECT '@' || scope || '/' || name AS "name!"
+      FROM packages
+      WHERE is_archived AND deleted_at IS NULL AND '@' || scope || '/' || name = ANY($1)"#,
+      names,
+    )
+    .map(|r| r.name)
+    .fetch_all(&self.pool)
+    .await
+    .map(|names| names.into_iter().collect())
+  }
 
-    tx.commit().await?;
+  /// The jsr packages a version depends on that no other version of its
+  /// package depends on, with the constraint they are depended on with.
+  #[instrument(name = "Database::list_new_jsr_dependencies", skip(self), err)]
+  pub async fn list_new_jsr_dependencies(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+    version: &Version,
+  ) -> Result<Vec<(String, String)>> {
+    sqlx::query!(
+      r#"SELECT DISTINCT ON (dependency_name) dependency_name, dependency_constraint
+      FROM package_version_dependencies deps
+      WHERE package_scope = $1 AND package_name = $2 AND package_version = $3 AND dependency_kind = 'jsr'
+        AND NOT EXISTS (
+          SELECT 1 FROM package_version_dependencies other
+          WHERE other.package_scope = $1 AND other.package_name = $2 AND other.package_version != $3
+            AND other.dependency_kind = 'jsr' AND other.dependency_name = deps.dependency_name
+        )
+      ORDER BY dependency_name ASC, dependency_constraint ASC"#,
+      scope as _,
+      name as _,
+      version as _,
+    )
+    .map(|r| (r.dependency_name, r.dependency_constraint))
+    .fetch_all(&self.pool)
+    .await
+  }
 
-    Ok((total_tickets as usize, out))
+  #[instrument(name = "Database::list_scope_oidc_issuers", skip(self), err)]
+  pub async fn list_scope_oidc_issuers(
+    &self,
+    scope: &ScopeName,
+  ) -> Result<Vec<ScopeOidcIssuer>> {
+    query_concat_as!(
+      ScopeOidcIssuer,
+      "SELECT ", SCOPE_OIDC_ISSUER_SELECT, " FROM scope_oidc_issuers WHERE scope = $1 ORDER BY created_at ASC";
+      scope as _,
+    )
+    .fetch_all(&self.pool)
+    .await
   }
 
-  #[instrument(name = "Database::list_tickets_for_user", skip(self), err)]
-  pub async fn list_tickets_for_user(
+  #[instrument(name = "Database::create_scope_oidc_issuer", skip(self), err)]
+  pub async fn create_scope_oidc_issuer(
     &self,
-    user_id: Uuid,
-  ) -> Result<Vec<FullTicket>> {
+    actor_id: &Uuid,
+    is_sudo: bool,
+    scope: &ScopeName,
+    issuer: &str,
+  ) -> Result<ScopeOidcIssuer> {
     let mut tx = self.pool.begin().await?;
 
-    let tickets = query_concat!(
-      "SELECT ", TICKET_SELECT_JOINED, ", ", USER_SELECT_FULL_JOINED, "
-    FROM tickets
-    INNER JOIN users ON users.id = tickets.creator
-    WHERE tickets.creator = $1
-    ORDER BY tickets.closed ASC, tickets.created_at DESC
-";
-      user_id as _,
+    let oidc_issuer = query_concat_as!(
+      ScopeOidcIssuer,
+      "INSERT INTO scope_oidc_issuers (scope, issuer) VALUES ($1, $2)
+      RETURNING ", SCOPE_OIDC_ISSUER_SELECT;
+      scope as _,
+      issuer,
     )
-    .map(|r| {
-      let ticket = Ticket {
-        id: r.ticket_id,
-        kind: r.ticket_kind,
-        creator: r.ticket_creator,
-        meta: r.ticket_meta,
-        closed: r.ticket_closed,
-        updated_at: r.ticket_updated_at,
-        created_at: r.ticket_created_at,
-      };
-
-      let user = User {
-        id: r.user_id,
-        name: r.user_name,
-        email: r.user_email,
-        avatar_url: r.user_avatar_url,
-        github_id: r.user_github_id,
-        gitlab_id: r.user_gitlab_id,
-        is_blocked: r.user_is_blocked,
-        is_staff: r.user_is_staff,
-        scope_usage: r.user_scope_usage,
-        scope_limit: r.user_scope_limit,
-        invite_count: r.user_invite_count,
-        newer_ticket_messages_count: r.user_newer_ticket_messages_count,
-        updated_at: r.user_updated_at,
-        created_at: r.user_created_at,
-      };
-
-      (ticket, user)
-    })
-    .fetch_all(&mut *tx)
+    .fetch_one(&mut *tx)
     .await?;
 
-    let mut out = Vec::with_capacity(tickets.len());
-    for (ticket, user) in tickets {
-      let messages = query_concat!(
-      "SELECT
-            ", TICKET_MESSAGE_SELECT_JOINED, ",
-            ", USER_PUBLIC_SELECT_JOINED_RT, "
-        FROM ticket_messages
-        INNER JOIN users ON users.id = ticket_messages.author
-        WHERE ticket_messages.ticket_id = $1 ORDER BY ticket_messages.created_at";
-      ticket.id as _,
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "create_scope_oidc_issuer",
+      json!({
+        "scope": scope,
+        "issuer": issuer,
+      }),
     )
-        .map(|r| {
-          let message = TicketMessage {
-            ticket_id: r.message_ticket_id,
-            author: r.message_author,
-            message: r.message_message,
-            updated_at: r.message_updated_at,
-            created_at: r.message_created_at,
-          };
-
-          let user = UserPublic {
-            id: r.user_id,
-            name: r.user_name,
-            avatar_url: r.user_avatar_url,
-            github_id: r.user_github_id,
-gitlab_id: r.user_gitlab_id,
-            updated_at: r.user_updated_at,
-            created_at: r.user_created_at,
-          };
-
-          (message, user)
-        })
-        .fetch_all(&mut *tx)
-        .await?;
-
-      out.push((ticket, user, messages));
-    }
+    .await?;
 
     tx.commit().await?;
 
-    Ok(out)
+    Ok(oidc_issuer)
   }
 
-  #[instrument(name = "Database::get_ticket", skip(self), err)]
-  pub async fn get_ticket(
+  #[instrument(name = "Database::delete_scope_oidc_issuer", skip(self), err)]
+  pub async fn delete_scope_oidc_issuer(
     &self,
-    ticket_id: Uuid,
-  ) -> Result<Option<FullTicket>> {
+    actor_id: &Uuid,
+    is_sudo: bool,
+    scope: &ScopeName,
+    id: Uuid,
+  ) -> Result<Option<ScopeOidcIssuer>> {
     let mut tx = self.pool.begin().await?;
 
-    let Some((ticket, user)) = query_concat!(
-      "SELECT
-            ", TICKET_SELECT_JOINED, ",
-            ", USER_SELECT_FULL_JOINED, "
-        FROM tickets
-        INNER JOIN users ON users.id = tickets.creator
-        WHERE tickets.id = $1";
-      ticket_id as _,
+    let Some(oidc_issuer) = query_concat_as!(
+      ScopeOidcIssuer,
+      "DELETE FROM scope_oidc_issuers WHERE scope = $1 AND id = $2
+      RETURNING ", SCOPE_OIDC_ISSUER_SELECT;
+      scope as _,
+      id,
     )
-    .map(|r| {
-      let ticket = Ticket {
-        id: r.ticket_id,
-        kind: r.ticket_kind,
-        creator: r.ticket_creator,
-        meta: r.ticket_meta,
-        closed: r.ticket_closed,
-        updated_at: r.ticket_updated_at,
-        created_at: r.ticket_created_at,
-      };
-
-      let user = User {
-        id: r.user_id,
-        name: r.user_name,
-        email: r.user_email,
-        avatar_url: r.user_avatar_url,
-        github_id: r.user_github_id,
-        gitlab_id: r.user_gitlab_id,
-        is_blocked: r.user_is_blocked,
-        is_staff: r.user_is_staff,
-        scope_usage: r.user_scope_usage,
-        scope_limit: r.user_scope_limit,
-        invite_count: r.user_invite_count,
-        newer_ticket_messages_count: r.user_newer_ticket_messages_count,
-        updated_at: r.user_updated_at,
-        created_at: r.user_created_at,
-      };
-
-      (ticket, user)
-    })
     .fetch_optional(&mut *tx)
     .await?
     else {
       return Ok(None);
     };
 
-    let messages = query_concat!(
-      "SELECT
-            ", TICKET_MESSAGE_SELECT_JOINED, ",
-            ", USER_PUBLIC_SELECT_JOINED_RT, "
-        FROM ticket_messages
-        INNER JOIN users ON users.id = ticket_messages.author
-        WHERE ticket_messages.ticket_id = $1 ORDER BY ticket_messages.created_at";
-      ticket_id as _,
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "delete_scope_oidc_issuer",
+      json!({
+        "scope": scope,
+        "issuer": oidc_issuer.issuer,
+      }),
     )
-      .map(|r| {
-        let message = TicketMessage {
-          ticket_id: r.message_ticket_id,
-          author: r.message_author,
-          message: r.message_message,
-          updated_at: r.message_updated_at,
-          created_at: r.message_created_at,
-        };
-
-        let user = UserPublic {
-          id: r.user_id,
-          name: r.user_name,
-          avatar_url: r.user_avatar_url,
-          github_id: r.user_github_id,
-gitlab_id: r.user_gitlab_id,
-          updated_at: r.user_updated_at,
-          created_at: r.user_created_at,
-        };
-
-        (message, user)
-      })
-      .fetch_all(&mut *tx)
-      .await?;
+    .await?;
 
     tx.commit().await?;
 
-    Ok(Some((ticket, user, messages)))
+    Ok(Some(oidc_issuer))
   }
 
-  #[instrument(name = "Database::get_ticket_audit_logs", skip(self), err)]
-  pub async fn get_ticket_audit_logs(
-    &self,
-    ticket_id: Uuid,
-  ) -> Result<Vec<(AuditLog, UserPublic)>> {
-    let mut tx = self.pool.begin().await?;
-
-    let audit_logs = query_concat!(
-      "SELECT
-          ", AUDIT_LOG_SELECT_JOINED, ",
-          ", USER_PUBLIC_SELECT_JOINED_RT, "
-        FROM
-          audit_logs
-        LEFT JOIN
-          users ON audit_logs.actor_id = users.id
-        WHERE
-          audit_logs.meta::text LIKE $1
-        ORDER BY audit_logs.created_at DESC;
-        ";
-      format!("%\"ticket_id\": \"{}\"%", ticket_id),
+  /// Whether any scope trusts tokens from `issuer`. Tokens from other issuers
+  /// that are not built-in are rejected without fetching their keys.
+  #[instrument(name = "Database::is_oidc_issuer_registered", skip(self), err)]
+  pub async fn is_oidc_issuer_registered(&self, issuer: &str) -> Result<bool> {
+    let row = sqlx::query!(
+      r#"SELECT scope FROM scope_oidc_issuers WHERE issuer = $1 LIMIT 1"#,
+      issuer,
     )
-    .map(|r| {
-      let audit_log = AuditLog {
-        actor_id: r.audit_log_actor_id,
-        is_sudo: r.audit_log_is_sudo,
-        action: r.audit_log_action,
-        meta: r.audit_log_meta,
-        created_at: r.audit_log_created_at,
-      };
-
-      let user = UserPublic {
-        id: r.user_id,
-        name: r.user_name,
-        avatar_url: r.user_avatar_url,
-        github_id: r.user_github_id,
-        gitlab_id: r.user_gitlab_id,
-        updated_at: r.user_updated_at,
-        created_at: r.user_created_at,
-      };
-
-      (audit_log, user)
-    })
-    .fetch_all(&mut *tx)
+    .fetch_optional(&self.pool)
     .await?;
+    Ok(row.is_some())
+  }
 
-    tx.commit().await?;
-
-    Ok(audit_logs)
+  #[instrument(
+    name = "Database::list_package_trusted_publishers",
+    skip(self),
+    err
+  )]
+  pub async fn list_package_trusted_publishers(
+    &self,
+    scope: &ScopeName,
+    package: &PackageName,
+  ) -> Result<Vec<PackageTrustedPublisher>> {
+    query_concat_as!(
+      PackageTrustedPublisher,
+      "SELECT ", PACKAGE_TRUSTED_PUBLISHER_SELECT, " FROM package_trusted_publishers
+      WHERE scope = $1 AND package = $2 ORDER BY created_at ASC";
+      scope as _,
+      package as _,
+    )
+    .fetch_all(&self.pool)
+    .await
   }
 
-  #[instrument(name = "Database::ticket_add_message", skip(self), err)]
-  pub async fn ticket_add_message(
+  #[instrument(
+    name = "Database::create_package_trusted_publisher",
+    skip(self),
+    err
+  )]
+  pub async fn create_package_trusted_publisher(
     &self,
-    id: Uuid,
-    author: Uuid,
-    message: NewTicketMessage,
-  ) -> Result<(TicketMessage, UserPublic)> {
+    actor_id: &Uuid,
+    is_sudo: bool,
+    scope: &ScopeName,
+    package: &PackageName,
+    issuer: &str,
+    subject: &str,
+  ) -> Result<PackageTrustedPublisher> {
     let mut tx = self.pool.begin().await?;
 
-    let message = query_concat!(
-      "WITH message AS (
-          INSERT INTO ticket_messages (ticket_id, author, message)
-          VALUES ($1, $2, $3)
-          RETURNING ticket_id, author, message, updated_at, created_at
-        )
-        SELECT
-            message.ticket_id as \"message_ticket_id\",
-            message.author as \"message_author\",
-            message.message as \"message_message\",
-            message.updated_at as \"message_updated_at\",
-            message.created_at as \"message_created_at\",
-            ", USER_PUBLIC_SELECT_JOINED_RT, "
-        FROM message
-        INNER JOIN users ON users.id = message.author
-    ";
-      id as _,
-      author as _,
-      message.message as _,
+    let trusted_publisher = query_concat_as!(
+      PackageTrustedPublisher,
+      "INSERT INTO package_trusted_publishers (scope, package, issuer, subject, created_by)
+      VALUES ($1, $2, $3, $4, $5)
+      RETURNING ", PACKAGE_TRUSTED_PUBLISHER_SELECT;
+      scope as _,
+      package as _,
+      issuer,
+      subject,
+      actor_id,
     )
-    .map(|r| {
-      let message = TicketMessage {
-        ticket_id: r.message_ticket_id,
-        author: r.message_author,
-        message: r.message_message,
-        updated_at: r.message_updated_at,
-        created_at: r.message_created_at,
-      };
-
-      let user = UserPublic {
-        id: r.user_id,
-        name: r.user_name,
-        avatar_url: r.user_avatar_url,
-        github_id: r.user_github_id,
-        gitlab_id: r.user_gitlab_id,
-        updated_at: r.user_updated_at,
-        created_at: r.user_created_at,
-      };
-
-      (message, user)
-    })
     .fetch_one(&mut *tx)
     .await?;
 
-    sqlx::query!(
-      r#"UPDATE tickets SET updated_at = now() WHERE id = $1"#,
-      id as _
+    audit_log(
+      &mut tx,
+      actor_id,
+      is_sudo,
+      "create_package_trusted_publisher",
+      json!({
+        "scope": scope,
+        "package": package,
+        "issuer": issuer,
+        "subject": subject,
+      }),
     )
-    .execute(&mut *tx)
     .await?;
 
     tx.commit().await?;
 
-    Ok(message)
+    Ok(trusted_publisher)
   }
 
-  #[instrument(name = "Database::update_ticket", skip(self), err)]
-  pub async fn update_ticket_closed(
+  #[instrument(
+    name = "Database::delete_package_trusted_publisher",
+    skip(self),
+    err
+  )]
+  pub async fn delete_package_trusted_publisher(
     &self,
-    staff_id: &Uuid,
-    ticket_id: Uuid,
-    closed: bool,
-  ) -> Result<FullTicket> {
+    actor_id: &Uuid,
+    is_sudo: bool,
+    scope: &ScopeName,
+    package: &PackageName,
+    id: Uuid,
+  ) -> Result<Option<PackageTrustedPublisher>> {
     let mut tx = self.pool.begin().await?;
 
+    let Some(trusted_publisher) = query_concat_as!(
+      PackageTrustedPublisher,
+      "DELETE FROM package_trusted_publishers WHERE scope = $1 AND package = $2 AND id = $3
+      RETURNING ", PACKAGE_TRUSTED_PUBLISHER_SELECT;
+      scope as _,
+      package as _,
+      id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(None);
+    };
+
     audit_log(
       &mut tx,
-      staff_id,
-      true,
-      "update_ticket_status",
+      actor_id,
+      is_sudo,
+      "delete_package_trusted_publisher",
       json!({
-      "ticket_id": ticket_id,
-      "closed": closed,
+        "scope": scope,
+        "package": package,
+        "issuer": trusted_publisher.issuer,
+        "subject": trusted_publisher.subject,
       }),
     )
     .await?;
 
-    let (ticket, user) = query_concat!(
-      "WITH ticket AS (
-          UPDATE tickets SET closed = $1 WHERE id = $2
-          RETURNING id, kind, creator, meta, closed, updated_at, created_at
-        )
-        SELECT
-            ticket.id as \"ticket_id\",
-            ticket.kind as \"ticket_kind: TicketKind\",
-            ticket.creator as \"ticket_creator\",
-            ticket.meta as \"ticket_meta\",
-            ticket.closed as \"ticket_closed\",
-            ticket.updated_at as \"ticket_updated_at\",
-            ticket.created_at as \"ticket_created_at\",
-            ", USER_SELECT_FULL_JOINED, "
-        FROM ticket
-        INNER JOIN users ON users.id = ticket.creator
-    ";
-      closed as _,
-      ticket_id as _,
-    )
-    .map(|r| {
-      let ticket = Ticket {
-        id: r.ticket_id,
-        kind: r.ticket_kind,
-        creator: r.ticket_creator,
-        meta: r.ticket_meta,
-        closed: r.ticket_closed,
-        updated_at: r.ticket_updated_at,
-        created_at: r.ticket_created_at,
-      };
+    tx.commit().await?;
 
-      let user = User {
-        id: r.user_id,
-        name: r.user_name,
-        email: r.user_email,
-        avatar_url: r.user_avatar_url,
-        github_id: r.user_github_id,
-        gitlab_id: r.user_gitlab_id,
-        is_blocked: r.user_is_blocked,
-        is_staff: r.user_is_staff,
-        scope_usage: r.user_scope_usage,
-        scope_limit: r.user_scope_limit,
-        invite_count: r.user_invite_count,
-        newer_ticket_messages_count: r.user_newer_ticket_messages_count,
-        updated_at: r.user_updated_at,
-        created_at: r.user_created_at,
-      };
+    Ok(Some(trusted_publisher))
+  }
 
-      (ticket, user)
-    })
-    .fetch_one(&mut *tx)
+  /// Whether the CI project `subject` of `issuer` may publish the package.
+  /// Custom issuers are only trusted while they are registered in the scope of
+  /// the package.
+  #[instrument(
+    name = "Database::has_package_trusted_publisher",
+    skip(self),
+    err
+  )]
+  pub async fn has_package_trusted_publisher(
+    &self,
+    scope: &ScopeName,
+    package: &PackageName,
+    issuer: &str,
+    subject: &str,
+    builtin_issuer: bool,
+  ) -> Result<bool> {
+    let row = sqlx::query!(
+      r#"SELECT id FROM package_trusted_publishers
+      WHERE scope = $1 AND package = $2 AND issuer = $3 AND subject = $4
+        AND ($5 OR EXISTS (SELECT 1 FROM scope_oidc_issuers WHERE scope = $1 AND issuer = $3))"#,
+      scope as _,
+      package as _,
+      issuer,
+      subject,
+      builtin_issuer,
+    )
+    .fetch_optional(&self.pool)
     .await?;
+    Ok(row.is_some())
+  }
 
-    let messages = query_concat!(
-      "SELECT
-            ", TICKET_MESSAGE_SELECT_JOINED, ",
-            ", USER_PUBLIC_SELECT_JOINED_RT, "
-        FROM ticket_messages
-        INNER JOIN users ON users.id = ticket_messages.author
-        WHERE ticket_messages.ticket_id = $1 ORDER BY ticket_messages.created_at";
-      ticket_id as _,
+  #[instrument(name = "Database::list_registry_changes", skip(self), err)]
+  pub async fn list_registry_changes(
+    &self,
+    since: i64,
+    limit: i64,
+  ) -> Result<Vec<RegistryChange>> {
+    query_concat_as!(
+      RegistryChange,
+      "SELECT ", REGISTRY_CHANGE_SELECT, " FROM registry_changes
+      WHERE seq > $1
+      ORDER BY seq
+      LIMIT $2";
+      since,
+      limit,
     )
-      .map(|r| {
-        let message = TicketMessage {
-          ticket_id: r.message_ticket_id,
-          author: r.message_author,
-          message: r.message_message,
-          updated_at: r.message_updated_at,
-          created_at: r.message_created_at,
-        };
+    .fetch_all(&self.pool)
+    .await
+  }
 
-        let user = UserPublic {
-          id: r.user_id,
-          name: r.user_name,
-          avatar_url: r.user_avatar_url,
-          github_id: r.user_github_id,
-gitlab_id: r.user_gitlab_id,
-          updated_at: r.user_updated_at,
-          created_at: r.user_created_at,
-        };
+  /// The cursor of the most recent change, or 0 if there are none yet.
+  #[instrument(
+    name = "Database::get_latest_registry_change_seq",
+    skip(self),
+    err
+  )]
+  pub async fn get_latest_registry_change_seq(&self) -> Result<i64> {
+    sqlx::query!(
+      r#"SELECT COALESCE(MAX(seq), 0) as "seq!" FROM registry_changes"#
+    )
+    .map(|r| r.seq)
+    .fetch_one(&self.pool)
+    .await
+  }
 
-        (message, user)
-      })
-      .fetch_all(&mut *tx)
-      .await?;
+  /// Lists the versions of all public packages, ordered by scope, name and
+  /// version, starting after the given version.
+  #[instrument(
+    name = "Database::list_package_versions_for_export",
+    skip(self),
+    err
+  )]
+  pub async fn list_package_versions_for_export(
+    &self,
+    after: Option<(&ScopeName, &PackageName, &Version)>,
+    limit: i64,
+  ) -> Result<Vec<PackageVersion>> {
+    let scope = after.map(|(scope, _, _)| scope);
+    let name = after.map(|(_, name, _)| name);
+    let version = after.map(|(_, _, version)| version);
+    query_concat_as!(
+      PackageVersion,
+      "SELECT ", PACKAGE_VERSION_SELECT, "
+      FROM package_versions
+      WHERE ($1::text IS NULL OR (scope, name, version) > ($1, $2, $3))
+        AND deleted_at IS NULL
+        AND EXISTS (
+          SELECT 1 FROM packages
+          WHERE packages.scope = package_versions.scope
+            AND packages.name = package_versions.name
+            AND packages.visibility = 'public'
+            AND packages.deleted_at IS NULL
+        )
+      ORDER BY scope, name, version
+      LIMIT $4";
+      scope as _,
+      name as _,
+      version as _,
+      limit,
+    )
+    .fetch_all(&self.pool)
+    .await
+  }
+}
+
+async fn get_package(
+  pool: &sqlx::PgPool,
+  scope: &ScopeName,
+  name: &PackageName,
+) -> Result<Option<PackageWithGitHubRepoAndMeta>> {
+  query_concat!(
+    "SELECT ", PACKAGE_SELECT_JOINED, ", ", GITHUB_REPOSITORY_SELECT_JOINED, "
+    FROM packages
+    LEFT JOIN github_repositories ON packages.github_repository_id = github_repositories.id
+    WHERE packages.scope = $1 AND packages.name = $2 AND packages.deleted_at IS NULL";
+    scope as _,
+    name as _
+  )
+    .map(|r| {
+      let package = Package {
+        scope: r.package_scope,
+        name: r.package_name,
+        description: r.package_description,
+        github_repository_id: r.package_github_repository_id,
+        runtime_compat: r.package_runtime_compat,
+        created_at: r.package_created_at,
+        updated_at: r.package_updated_at,
+        version_count: r.package_version_count,
+        latest_version: r.package_latest_version,
+        when_featured: r.package_when_featured,
+        is_archived: r.package_is_archived,
+        readme_source: r.package_readme_source,
+        localized_descriptions: r.package_localized_descriptions,
+        visibility: r.package_visibility,
+      };
+      let github_repository = if r.package_github_repository_id.is_some() {
+        Some(GithubRepository {
+          id: r.github_repository_id.unwrap(),
+          owner: r.github_repository_owner.unwrap(),
+          name: r.github_repository_name.unwrap(),
+          created_at: r.github_repository_created_at.unwrap(),
+          updated_at: r.github_repository_updated_at.unwrap(),
+        })
+      } else {
+        None
+      };
 
-    tx.commit().await?;
+      let meta = r.package_version_meta.unwrap_or_default();
 
-    Ok((ticket, user, messages))
-  }
+      (package, github_repository, meta)
+    })
+    .fetch_optional(pool)
+    .await
+}
 
-  #[allow(clippy::type_complexity)]
-  #[instrument(name = "Database::list_audit_logs", skip(self), err)]
-  pub async fn list_audit_logs(
-    &self,
-    start: i64,
-    limit: i64,
-    maybe_search_query: Option<&str>,
-    maybe_sort: Option<&str>,
-    sudo_only: bool,
-  ) -> Result<(usize, Vec<(AuditLog, UserPublic)>)> {
-    let mut tx = self.pool.begin().await?;
+/// Versions are sorted by semver precedence, newest first, with the
+/// `version_sort_key` column, as the collation of the version column does not
+/// follow it for pre-releases and build metadata.
+async fn list_package_versions_paginated(
+  pool: &sqlx::PgPool,
+  scope: &ScopeName,
+  name: &PackageName,
+  prereleases: bool,
+  start: i64,
+  limit: i64,
+) -> Result<(usize, Vec<(PackageVersion, Option<UserPublic>)>)> {
+  let mut tx = pool.begin().await?;
+
+  let versions = query_concat!(
+    "SELECT ", PACKAGE_VERSION_SELECT_JOINED, ",
+    ", USER_PUBLIC_SELECT_JOINED, "
+    FROM package_versions
+    LEFT JOIN users ON package_versions.user_id = users.id
+    WHERE package_versions.scope = $1 AND package_versions.name = $2 AND package_versions.deleted_at IS NULL
+      AND ($3 OR split_part(package_versions.version, '+', 1) NOT LIKE '%-%')
+    ORDER BY package_versions.version_sort_key DESC
+    OFFSET $4 LIMIT $5";
+    scope as _,
+    name as _,
+    prereleases,
+    start,
+    limit,
+  )
+  .map(|r| {
+    let package_version = PackageVersion {
+      scope: r.package_version_scope,
+      name: r.package_version_name,
+      version: r.package_version_version,
+      user_id: r.package_version_user_id,
+      exports: r.package_version_exports,
+      is_yanked: r.package_version_is_yanked,
+      yanked_at: r.package_version_yanked_at,
+      yank_reason: r.package_version_yank_reason,
+      readme_path: r.package_version_readme_path,
+      uses_npm: r.package_version_uses_npm,
+      meta: r.package_version_meta,
+      updated_at: r.package_version_updated_at,
+      created_at: r.package_version_created_at,
+      rekor_log_id: r.package_version_rekor_log_id,
+      license: r.package_version_license,
+    };
 
-    let search = format!("%{}%", maybe_search_query.unwrap_or(""));
-    let sort = sort_by!(maybe_sort => {
-      @timestamps "created_at";
-      "action" => "audit_logs.action",
-      "user" => "users.name",
-      "created_at" => "audit_logs.created_at",
-    } || "audit_logs.created_at DESC");
+    let user = if r.package_version_user_id.is_some() {
+      let user = UserPublic {
+        id: r.user_id.unwrap(),
+        name: r.user_name.unwrap(),
+        avatar_url: r.user_avatar_url.unwrap(),
+        github_id: r.user_github_id,
+        gitlab_id: r.user_gitlab_id,
+        updated_at: r.user_updated_at.unwrap(),
+        created_at: r.user_created_at.unwrap(),
+      };
 
-    let scopes = sqlx::query(&format!(
-      r#"SELECT
-      {}, {}
-      FROM audit_logs
-      LEFT JOIN users ON audit_logs.actor_id = users.id
-      WHERE (audit_logs.action ILIKE $1
-         OR users.name ILIKE $1
-         OR audit_logs.meta::text ILIKE $1)
-         AND ($2 IS NOT TRUE OR audit_logs.is_sudo = TRUE)
-      ORDER BY {sort} OFFSET $3 LIMIT $4
-      "#,
-      crate::db::sql_fragments::AUDIT_LOG_SELECT_JOINED,
-      crate::db::sql_fragments::USER_PUBLIC_SELECT_JOINED_RT,
-    ))
-    .bind(&search)
-    .bind(sudo_only)
-    .bind(start)
-    .bind(limit)
-    .try_map(|r| {
-      let audit_log = AuditLog::from_row(&r)?;
-      let user = UserPublic::from_row(&r)?;
+      Some(user)
+    } else {
+      None
+    };
 
-      Ok((audit_log, user))
-    })
-    .fetch_all(&mut *tx)
-    .await?;
+    (package_version, user)
+  })
+  .fetch_all(&mut *tx)
+  .await?;
 
-    let total_scopes = sqlx::query!(
-      r#"SELECT COUNT(audit_logs.created_at) FROM audit_logs LEFT JOIN users ON audit_logs.actor_id = users.id WHERE audit_logs.action ILIKE $1 OR users.name ILIKE $2 AND ($3 IS NOT TRUE OR audit_logs.is_sudo = TRUE);"#,
-      search,
-      search,
-      sudo_only,
-    )
-      .map(|r| r.count.unwrap())
-      .fetch_one(&mut *tx)
-      .await?;
+  let total = sqlx::query!(
+    r#"SELECT COUNT(*) FROM package_versions
+    WHERE scope = $1 AND name = $2 AND deleted_at IS NULL
+      AND ($3 OR split_part(version, '+', 1) NOT LIKE '%-%')"#,
+    scope as _,
+    name as _,
+    prereleases,
+  )
+  .map(|r| r.count.unwrap())
+  .fetch_one(&mut *tx)
+  .await?;
 
-    tx.commit().await?;
+  tx.commit().await?;
 
-    Ok((total_scopes as usize, scopes))
-  }
+  Ok((total as usize, versions))
 }
 
 async fn finalize_package_creation(
@@ -4774,7 +11940,7 @@ async fn finalize_package_creation(
 
   let packages_from_last_week = sqlx::query!(
     r#"
-    SELECT COUNT(created_at) FROM packages WHERE scope = $1 AND created_at > now() - '1 week'::interval;
+    SELECT COUNT(created_at) FROM packages WHERE scope = $1 AND deleted_at IS NULL AND created_at > now() - '1 week'::interval;
     "#,
     scope as _,
   )
@@ -4793,7 +11959,7 @@ async fn finalize_package_creation(
 
   let total_packages = sqlx::query!(
     r#"
-    SELECT COUNT(created_at) FROM packages WHERE scope = $1;
+    SELECT COUNT(created_at) FROM packages WHERE scope = $1 AND deleted_at IS NULL;
     "#,
     scope as _,
   )
@@ -4812,6 +11978,350 @@ async fn finalize_package_creation(
   Ok(None)
 }
 
+/// Creates a publishing task in the transaction of the caller, which must only
+/// commit it if the task was created.
+async fn insert_publishing_task(
+  tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+  task: &NewPublishingTask<'_>,
+  workspace_publish_id: Option<Uuid>,
+) -> Result<CreatePublishingTaskResult> {
+  // only allow insert if no non status==failure tasks exist
+  let already_processing = query_concat!(
+    "SELECT
+      ", PUBLISHING_TASK_SELECT_JOINED, ",
+      ", USER_PUBLIC_SELECT_JOINED_OPTIONAL, "
+    FROM publishing_tasks
+    LEFT JOIN users on publishing_tasks.user_id = users.id
+    WHERE package_scope = $1 AND package_name = $2 AND package_version = $3 AND status != 'failure'
+    LIMIT 1";
+    task.package_scope as _,
+    task.package_name as _,
+    task.package_version as _
+  ).map(|r| {
+    let task = PublishingTask {
+      id: r.task_id,
+      status: r.task_status,
+      error: r.task_error,
+      package_scope: r.task_package_scope,
+      package_name: r.task_package_name,
+      package_version: r.task_package_version,
+      config_file: r.task_config_file,
+      user_id: r.task_user_id,
+      created_at: r.task_created_at,
+      updated_at: r.task_updated_at,
+    };
+
+    let user = task.user_id.map(|_| {
+      UserPublic {
+        id: r.user_id.unwrap(),
+        name: r.user_name.unwrap(),
+        avatar_url: r.user_avatar_url.unwrap(),
+        github_id: r.user_github_id,
+        gitlab_id: r.user_gitlab_id,
+        updated_at: r.user_updated_at.unwrap(),
+        created_at: r.user_created_at.unwrap(),
+      }
+    });
+
+    (task, user)
+  })
+
+    .fetch_optional(&mut **tx)
+    .await?;
+  if let Some(already_processing) = already_processing {
+    return Ok(CreatePublishingTaskResult::Exists(already_processing));
+  }
+
+  let task = query_concat!(
+    "WITH task AS (
+        INSERT INTO publishing_tasks (user_id, package_scope, package_name, package_version, config_file, workspace_publish_id)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING
+          id,
+          status,
+          error,
+          user_id,
+          package_scope,
+          package_name,
+          package_version,
+          config_file,
+          created_at,
+          updated_at
+      )
+      SELECT
+        task.id as \"task_id\",
+        task.status as \"task_status: PublishingTaskStatus\",
+        task.error as \"task_error: PublishingTaskError\",
+        task.user_id as \"task_user_id\",
+        task.package_scope as \"task_package_scope: ScopeName\",
+        task.package_name as \"task_package_name: PackageName\",
+        task.package_version as \"task_package_version: Version\",
+        task.config_file as \"task_config_file: PackagePath\",
+        task.created_at as \"task_created_at\",
+        task.updated_at as \"task_updated_at\",
+      ", USER_PUBLIC_SELECT_JOINED_OPTIONAL, "
+      FROM task
+      LEFT JOIN users ON task.user_id = users.id";
+    task.user_id,
+    task.package_scope as _,
+    task.package_name as _,
+    task.package_version as _,
+    task.config_file as _,
+    workspace_publish_id,
+  )
+    .map(|r| {
+      let task = PublishingTask {
+        id: r.task_id,
+        status: r.task_status,
+        error: r.task_error,
+        package_scope: r.task_package_scope,
+        package_name: r.task_package_name,
+        package_version: r.task_package_version,
+        config_file: r.task_config_file,
+        user_id: r.task_user_id,
+        created_at: r.task_created_at,
+        updated_at: r.task_updated_at,
+      };
+
+      let user = task.user_id.map(|_| {
+        UserPublic {
+          id: r.user_id.unwrap(),
+          name: r.user_name.unwrap(),
+          avatar_url: r.user_avatar_url.unwrap(),
+          github_id: r.user_github_id,
+gitlab_id: r.user_gitlab_id,
+          updated_at: r.user_updated_at.unwrap(),
+          created_at: r.user_created_at.unwrap(),
+        }
+      });
+
+      (task, user)
+    })
+
+    .fetch_one(&mut **tx)
+    .await?;
+
+  let publish_attempts_per_week_limit = sqlx::query!(
+    r#"
+    SELECT publish_attempts_per_week_limit FROM scopes WHERE scope = $1;
+    "#,
+    task.0.package_scope as _,
+  )
+  .map(|r| r.publish_attempts_per_week_limit)
+  .fetch_one(&mut **tx)
+  .await?;
+
+  let publish_attempts_from_last_week = sqlx::query!(
+    r#"
+    SELECT COUNT(created_at) FROM publishing_tasks WHERE package_scope = $1 AND created_at > now() - '1 week'::interval;
+    "#,
+    task.0.package_scope as _,
+  )
+    .map(|r| {
+      r.count.unwrap()
+    })
+    .fetch_one(&mut **tx)
+    .await?;
+
+  if publish_attempts_from_last_week > publish_attempts_per_week_limit as i64 {
+    return Ok(
+      CreatePublishingTaskResult::WeeklyPublishAttemptsLimitExceeded(
+        publish_attempts_per_week_limit,
+      ),
+    );
+  }
+
+  Ok(CreatePublishingTaskResult::Created(task))
+}
+
+/// Creates the package version of a processed publishing task, and marks the
+/// task as processed, in the transaction of the caller.
+async fn insert_published_version(
+  tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+  version: &NewPublishedVersion<'_>,
+) -> Result<PublishingTask> {
+  sqlx::query!(
+    r#"INSERT INTO package_versions (scope, name, version, user_id, readme_path, exports, uses_npm, meta, license)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"#,
+    version.package_version.scope as _,
+    version.package_version.name as _,
+    version.package_version.version as _,
+    version.package_version.user_id as _,
+    version.package_version.readme_path as _,
+    version.package_version.exports as _,
+    version.package_version.uses_npm as _,
+    version.package_version.meta as _,
+    version.package_version.license as _,
+  )
+    .execute(&mut **tx)
+    .await?;
+
+  for new_package_file in version.files {
+    sqlx::query!(
+      r#"INSERT INTO package_files (scope, name, version, path, size, checksum)
+      VALUES ($1, $2, $3, $4, $5, $6)"#,
+      new_package_file.scope as _,
+      new_package_file.name as _,
+      new_package_file.version as _,
+      new_package_file.path as _,
+      new_package_file.size,
+      new_package_file.checksum,
+    )
+    .execute(&mut **tx)
+    .await?;
+  }
+
+  for new_package_version_dependency in version.dependencies {
+    sqlx::query!(
+      r#"INSERT INTO package_version_dependencies (package_scope, package_name, package_version, dependency_kind, dependency_name, dependency_constraint, dependency_path, is_optional)
+      VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"#,
+      new_package_version_dependency.package_scope as _,
+      new_package_version_dependency.package_name as _,
+      new_package_version_dependency.package_version as _,
+      new_package_version_dependency.dependency_kind as _,
+      new_package_version_dependency.dependency_name as _,
+      new_package_version_dependency.dependency_constraint as _,
+      new_package_version_dependency.dependency_path as _,
+      new_package_version_dependency.is_optional,
+    )
+      .execute(&mut **tx)
+      .await?;
+  }
+
+  for new_package_version_deprecation in version.deprecations {
+    sqlx::query!(
+      r#"INSERT INTO package_version_deprecations (scope, name, version, export, symbol, message)
+      VALUES ($1, $2, $3, $4, $5, $6)"#,
+      new_package_version_deprecation.scope as _,
+      new_package_version_deprecation.name as _,
+      new_package_version_deprecation.version as _,
+      new_package_version_deprecation.export,
+      new_package_version_deprecation.symbol,
+      new_package_version_deprecation.message,
+    )
+      .execute(&mut **tx)
+      .await?;
+  }
+
+  for new_package_version_symbol in version.symbols {
+    sqlx::query!(
+      r#"INSERT INTO package_version_symbols (scope, name, version, export, symbol, kind)
+      VALUES ($1, $2, $3, $4, $5, $6)"#,
+      new_package_version_symbol.scope as _,
+      new_package_version_symbol.name as _,
+      new_package_version_symbol.version as _,
+      new_package_version_symbol.export,
+      new_package_version_symbol.symbol,
+      new_package_version_symbol.kind,
+    )
+      .execute(&mut **tx)
+      .await?;
+  }
+
+  for new_package_version_doc_link in version.doc_links {
+    sqlx::query!(
+      r#"INSERT INTO package_version_doc_links (scope, name, version, identifier, path)
+      VALUES ($1, $2, $3, $4, $5)"#,
+      new_package_version_doc_link.scope as _,
+      new_package_version_doc_link.name as _,
+      new_package_version_doc_link.version as _,
+      new_package_version_doc_link.identifier,
+      new_package_version_doc_link.path,
+    )
+      .execute(&mut **tx)
+      .await?;
+  }
+
+  for new_package_version_doc_artifact in version.doc_artifacts {
+    sqlx::query!(
+      r#"INSERT INTO package_version_doc_artifacts (scope, name, version, kind, checksum, size)
+      VALUES ($1, $2, $3, $4, $5, $6)"#,
+      new_package_version_doc_artifact.scope as _,
+      new_package_version_doc_artifact.name as _,
+      new_package_version_doc_artifact.version as _,
+      new_package_version_doc_artifact.kind as _,
+      new_package_version_doc_artifact.checksum,
+      new_package_version_doc_artifact.size,
+    )
+      .execute(&mut **tx)
+      .await?;
+  }
+
+  sqlx::query!(
+    r#"INSERT INTO npm_tarballs (scope, name, version, revision, sha1, sha512, sha256, size)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"#,
+    version.npm_tarball.scope as _,
+    version.npm_tarball.name as _,
+    version.npm_tarball.version as _,
+    version.npm_tarball.revision,
+    version.npm_tarball.sha1,
+    version.npm_tarball.sha512,
+    version.npm_tarball.sha256,
+    version.npm_tarball.size,
+  )
+    .execute(&mut **tx)
+    .await?;
+
+  // A version that the malware scan flagged is reported and quarantined in
+  // the same transaction that creates it, so that it never shows up in the
+  // manifests before staff reviewed it.
+  if let Some(findings) = version.malware_scan_findings {
+    let report = sqlx::query!(
+      r#"INSERT INTO abuse_reports (scope, name, version, reason, description)
+      VALUES ($1, $2, $3, 'malware', $4)
+      RETURNING id"#,
+      version.package_version.scope as _,
+      version.package_version.name as _,
+      version.package_version.version as _,
+      findings,
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    sqlx::query!(
+      r#"INSERT INTO package_version_quarantines (scope, name, version, reason, report_id)
+      VALUES ($1, $2, $3, 'Flagged by the malware scan, pending review', $4)"#,
+      version.package_version.scope as _,
+      version.package_version.name as _,
+      version.package_version.version as _,
+      report.id,
+    )
+    .execute(&mut **tx)
+    .await?;
+  }
+
+  let task = query_concat_as!(
+    PublishingTask,
+    "UPDATE publishing_tasks
+    SET status = 'processed'
+    WHERE id = $1 AND status = 'processing'
+    RETURNING ", PUBLISHING_TASK_SELECT;
+    version.publishing_task_id,
+  )
+  .fetch_one(&mut **tx)
+  .await?;
+
+  // Publishes from GitHub Actions that are not linked to a user have no
+  // actor, so they only show up in the publishing tasks of the package.
+  if let Some(user_id) = version.package_version.user_id {
+    audit_log(
+      tx,
+      user_id,
+      false,
+      "publish_package_version",
+      json!({
+        "scope": version.package_version.scope,
+        "name": version.package_version.name,
+        "version": version.package_version.version,
+        "publishing_task_id": version.publishing_task_id,
+      }),
+    )
+    .await?;
+  }
+
+  Ok(task)
+}
+
 async fn audit_log(
   tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
   actor_id: &Uuid,
@@ -4832,6 +12342,75 @@ async fn audit_log(
   Ok(())
 }
 
+/// Maps a row of [Database::list_packages_for_search_index] or
+/// [Database::get_package_for_search_index].
+fn package_for_search_index_from_row(
+  r: &sqlx::postgres::PgRow,
+) -> Result<(Package, PackageVersionMeta, Option<String>)> {
+  let package = Package::from_row(r)?;
+  let meta: Option<PackageVersionMeta> = r.try_get("package_version_meta")?;
+  let license: Option<String> = r.try_get("package_version_license")?;
+  Ok((package, meta.unwrap_or_default(), license))
+}
+
+/// Splits a package search query into the scope ILIKE pattern, the exact scope,
+/// the package ILIKE pattern and the exact package name to match against.
+fn package_search_terms(
+  maybe_search_query: Option<&str>,
+) -> (String, String, String, String) {
+  let Some(search_query) = maybe_search_query else {
+    return (
+      "%%".to_string(),
+      "".to_string(),
+      "%%".to_string(),
+      "".to_string(),
+    );
+  };
+
+  // 1. Strip leading `@`.
+  let search_query = search_query.strip_prefix('@').unwrap_or(search_query);
+
+  // 2. If there's a space in the search query, we're gonna split it
+  // and use the first term for scope search and the reminder for package
+  // search.
+  let (scope_query, package_query) =
+    if let Some((scope_query, package_query)) = search_query.split_once(' ') {
+      (scope_query, package_query)
+    } else {
+      // 3. If there's no space in the search query, we're gonna split it
+      // at `/` and use the first term for scope search and the reminder for package
+      // search.
+      search_query
+        .split_once('/')
+        .unwrap_or((search_query, search_query))
+    };
+
+  (
+    format!("%{}%", scope_query),
+    scope_query.to_string(),
+    format!("%{}%", package_query),
+    package_query.to_string(),
+  )
+}
+
+#[derive(Debug)]
+pub enum AcceptPackageTransferResult {
+  Ok,
+  NotFound,
+  AlreadyExists,
+  PublishInProgress,
+  PackageLimitExceeded(i32),
+}
+
+#[derive(Debug)]
+pub enum RenameScopeResult {
+  /// The renamed scope, and the names of its packages.
+  Ok(Scope, Vec<PackageName>),
+  NotFound,
+  AlreadyExists,
+  PublishInProgress,
+}
+
 #[derive(Debug)]
 pub enum ScopeMemberUpdateResult {
   Ok(ScopeMember),
@@ -4844,6 +12423,9 @@ pub enum ScopeMemberUpdateResult {
 pub enum CreatePackageResult {
   Ok(Package),
   AlreadyExists,
+  /// The package was deleted, and can only be restored by staff until it is
+  /// purged.
+  Deleted,
   WeeklyPackageLimitExceeded(i32),
   PackageLimitExceeded(i32),
 }
@@ -4855,6 +12437,15 @@ pub enum CreatePublishingTaskResult {
   WeeklyPublishAttemptsLimitExceeded(i32),
 }
 
+#[derive(Debug)]
+pub enum CreateWorkspacePublishResult {
+  Created(WorkspacePublish, Vec<(PublishingTask, Option<UserPublic>)>),
+  /// A publish of the version of one of the packages is already in progress
+  /// or succeeded, so no task was created.
+  Exists((PublishingTask, Option<UserPublic>)),
+  WeeklyPublishAttemptsLimitExceeded(i32),
+}
+
 /// In-memory cache for `count_package_dependents` results. The dependent count
 /// for a package only changes when someone publishes a new version of a
 /// depending package, so a 15-minute TTL is safe and drastically reduces
@@ -0,0 +1,203 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+//! An optional Redis cache in front of the most requested metadata: packages,
+//! the version lists of packages, and scopes.
+//!
+//! Entries are stored as JSON under a key that contains the generation of the
+//! package or scope they belong to. Writes through [Database] that change a
+//! package or scope bump its generation, which makes all of its entries
+//! unreachable at once, including every page of its version list. A read that
+//! races with a write stores its result under the old generation, where it is
+//! never read again. Entries that are no longer reachable expire on their own.
+//!
+//! Redis is never required for correctness: if it is unreachable, reads go to
+//! the database and the error is counted. An invalidation that fails is
+//! logged, and the stale entries expire after their TTL.
+//!
+//! [Database]: super::Database
+
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tracing::error;
+
+use crate::ids::PackageName;
+use crate::ids::ScopeName;
+
+/// How long a generation counter is kept after it was last bumped. Must
+/// exceed the TTL of all entries, so that an expired counter never makes
+/// stale entries of an earlier generation reachable again.
+const GENERATION_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long the entries of each kind are cached for.
+#[derive(Debug, Clone, Copy)]
+pub struct MetadataCacheTtls {
+  pub package: Duration,
+  pub versions: Duration,
+  pub scope: Duration,
+}
+
+#[derive(Clone)]
+pub struct MetadataCache {
+  conn: ConnectionManager,
+  ttls: MetadataCacheTtls,
+  stats: Arc<Stats>,
+}
+
+impl std::fmt::Debug for MetadataCache {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("MetadataCache")
+      .field("ttls", &self.ttls)
+      .finish_non_exhaustive()
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum MetadataCacheKind {
+  Package,
+  Versions,
+  Scope,
+}
+
+#[derive(Default)]
+struct Stats {
+  hits: [AtomicU64; 3],
+  misses: [AtomicU64; 3],
+  errors: AtomicU64,
+}
+
+/// Hits and misses of the cache on this instance since it started.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MetadataCacheStats {
+  pub package: (u64, u64),
+  pub versions: (u64, u64),
+  pub scope: (u64, u64),
+  pub errors: u64,
+}
+
+impl MetadataCache {
+  pub async fn connect(
+    redis_url: &str,
+    ttls: MetadataCacheTtls,
+  ) -> anyhow::Result<Self> {
+    let client = redis::Client::open(redis_url)?;
+    let conn = ConnectionManager::new(client).await?;
+    Ok(Self {
+      conn,
+      ttls,
+      stats: Default::default(),
+    })
+  }
+
+  pub fn stats(&self) -> MetadataCacheStats {
+    let get = |kind: MetadataCacheKind| {
+      let i = kind as usize;
+      (
+        self.stats.hits[i].load(Ordering::Relaxed),
+        self.stats.misses[i].load(Ordering::Relaxed),
+      )
+    };
+    MetadataCacheStats {
+      package: get(MetadataCacheKind::Package),
+      versions: get(MetadataCacheKind::Versions),
+      scope: get(MetadataCacheKind::Scope),
+      errors: self.stats.errors.load(Ordering::Relaxed),
+    }
+  }
+
+  /// Returns the cached value of `entry` of the package or scope with the
+  /// generation key `generation_key`, or computes it with `fetch` and caches
+  /// it.
+  pub(super) async fn get_or_fetch<T, F>(
+    &self,
+    kind: MetadataCacheKind,
+    generation_key: &str,
+    entry: &str,
+    fetch: F,
+  ) -> sqlx::Result<T>
+  where
+    T: Serialize + DeserializeOwned,
+    F: Future<Output = sqlx::Result<T>>,
+  {
+    let mut conn = self.conn.clone();
+    let generation: Option<u64> = match conn.get(generation_key).await {
+      Ok(generation) => generation,
+      Err(err) => {
+        self.record_error("read", generation_key, err);
+        return fetch.await;
+      }
+    };
+    let key = format!("{generation_key}:{}:{entry}", generation.unwrap_or(0));
+
+    match conn.get::<_, Option<String>>(&key).await {
+      Ok(Some(cached)) => match serde_json::from_str(&cached) {
+        Ok(value) => {
+          self.stats.hits[kind as usize].fetch_add(1, Ordering::Relaxed);
+          return Ok(value);
+        }
+        Err(err) => self.record_error("deserialize", &key, err),
+      },
+      Ok(None) => {}
+      Err(err) => {
+        self.record_error("read", &key, err);
+        return fetch.await;
+      }
+    }
+    self.stats.misses[kind as usize].fetch_add(1, Ordering::Relaxed);
+
+    let value = fetch.await?;
+    let ttl = match kind {
+      MetadataCacheKind::Package => self.ttls.package,
+      MetadataCacheKind::Versions => self.ttls.versions,
+      MetadataCacheKind::Scope => self.ttls.scope,
+    };
+    let ttl = ttl.min(GENERATION_TTL).as_secs().max(1);
+    let serialized = serde_json::to_string(&value).unwrap();
+    if let Err(err) = conn.set_ex::<_, _, ()>(&key, serialized, ttl).await {
+      self.record_error("write", &key, err);
+    }
+    Ok(value)
+  }
+
+  /// Makes all cached entries of the package or scope with the generation key
+  /// `generation_key` unreachable.
+  pub(super) async fn invalidate(&self, generation_key: &str) {
+    let mut conn = self.conn.clone();
+    let res = redis::pipe()
+      .atomic()
+      .incr(generation_key, 1)
+      .ignore()
+      .expire(generation_key, GENERATION_TTL.as_secs() as i64)
+      .ignore()
+      .query_async::<()>(&mut conn)
+      .await;
+    if let Err(err) = res {
+      self.record_error("invalidate", generation_key, err);
+    }
+  }
+
+  fn record_error(&self, action: &str, key: &str, err: impl std::fmt::Display) {
+    self.stats.errors.fetch_add(1, Ordering::Relaxed);
+    error!("failed to {action} metadata cache entry {key}: {err}");
+  }
+}
+
+/// The generation key of the entries of a package: its metadata and its
+/// version list.
+pub(super) fn package_generation_key(
+  scope: &ScopeName,
+  name: &PackageName,
+) -> String {
+  format!("metadata:package:@{scope}/{name}")
+}
+
+/// The generation key of the entries of a scope.
+pub(super) fn scope_generation_key(scope: &ScopeName) -> String {
+  format!("metadata:scope:{scope}")
+}
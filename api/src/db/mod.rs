@@ -2,6 +2,7 @@
 mod database;
 #[cfg(test)]
 mod ephemeral_database;
+mod metadata_cache;
 pub(crate) mod models;
 pub(crate) mod sql_fragments;
 #[cfg(test)]
@@ -10,4 +11,7 @@ mod tests;
 pub use database::*;
 #[cfg(test)]
 pub use ephemeral_database::EphemeralDatabase;
+pub use metadata_cache::MetadataCache;
+pub use metadata_cache::MetadataCacheStats;
+pub use metadata_cache::MetadataCacheTtls;
 pub use models::*;
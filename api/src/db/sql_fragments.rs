@@ -37,44 +37,68 @@ pub const USER_SELECT_FULL_RT: &str = r#"id, name, email, avatar_url, updated_at
   )
 ) END) as "newer_ticket_messages_count" "#;
 
-pub const SCOPE_SELECT: &str = r#"scope as "scope: ScopeName", description as "description: ScopeDescription", creator, package_limit, new_package_per_week_limit, publish_attempts_per_week_limit, verify_oidc_actor, require_publishing_from_ci, updated_at, created_at"#;
+pub const SCOPE_SELECT: &str = r#"scope as "scope: ScopeName", description as "description: ScopeDescription", creator, package_limit, new_package_per_week_limit, publish_attempts_per_week_limit, storage_limit, verify_oidc_actor, require_publishing_from_ci, updated_at, created_at"#;
 
-pub const PACKAGE_SELECT: &str = r#"scope as "scope: ScopeName", name as "name: PackageName", description, github_repository_id, runtime_compat as "runtime_compat: RuntimeCompat", readme_source as "readme_source: ReadmeSource", when_featured, is_archived, updated_at, created_at"#;
+pub const PACKAGE_SELECT: &str = r#"scope as "scope: ScopeName", name as "name: PackageName", description, github_repository_id, runtime_compat as "runtime_compat: RuntimeCompat", readme_source as "readme_source: ReadmeSource", localized_descriptions as "localized_descriptions: LocalizedDescriptions", when_featured, is_archived, visibility as "visibility: PackageVisibility", updated_at, created_at"#;
 
-pub const PACKAGE_SELECT_JOINED: &str = r#"packages.scope "package_scope: ScopeName", packages.name "package_name: PackageName", packages.description "package_description", packages.github_repository_id "package_github_repository_id", packages.runtime_compat "package_runtime_compat: RuntimeCompat", packages.readme_source "package_readme_source: ReadmeSource", packages.when_featured "package_when_featured", packages.is_archived "package_is_archived", packages.updated_at "package_updated_at", packages.created_at "package_created_at",
-(SELECT COUNT(created_at) FROM package_versions WHERE scope = packages.scope AND name = packages.name) as "package_version_count!",
-(SELECT version FROM package_versions WHERE scope = packages.scope AND name = packages.name AND version NOT LIKE '%-%' AND is_yanked = false ORDER BY version DESC LIMIT 1) as "package_latest_version",
-(SELECT meta FROM package_versions WHERE scope = packages.scope AND name = packages.name AND version NOT LIKE '%-%' AND is_yanked = false ORDER BY version DESC LIMIT 1) as "package_version_meta: PackageVersionMeta""#;
+pub const PACKAGE_SELECT_JOINED: &str = r#"packages.scope "package_scope: ScopeName", packages.name "package_name: PackageName", packages.description "package_description", packages.github_repository_id "package_github_repository_id", packages.runtime_compat "package_runtime_compat: RuntimeCompat", packages.readme_source "package_readme_source: ReadmeSource", packages.localized_descriptions "package_localized_descriptions: LocalizedDescriptions", packages.when_featured "package_when_featured", packages.is_archived "package_is_archived", packages.visibility "package_visibility: PackageVisibility", packages.updated_at "package_updated_at", packages.created_at "package_created_at",
+(SELECT COUNT(created_at) FROM package_versions WHERE scope = packages.scope AND name = packages.name AND deleted_at IS NULL) as "package_version_count!",
+(SELECT version FROM package_versions WHERE scope = packages.scope AND name = packages.name AND deleted_at IS NULL AND version NOT LIKE '%-%' AND is_yanked = false ORDER BY version DESC LIMIT 1) as "package_latest_version",
+(SELECT meta FROM package_versions WHERE scope = packages.scope AND name = packages.name AND deleted_at IS NULL AND version NOT LIKE '%-%' AND is_yanked = false ORDER BY version DESC LIMIT 1) as "package_version_meta: PackageVersionMeta""#;
 
 // Base package columns without version aggregates (for use with lateral joins in list queries)
-pub const PACKAGE_BASE_SELECT_JOINED: &str = r#"packages.scope "package_scope: ScopeName", packages.name "package_name: PackageName", packages.description "package_description", packages.github_repository_id "package_github_repository_id", packages.runtime_compat "package_runtime_compat: RuntimeCompat", packages.readme_source "package_readme_source: ReadmeSource", packages.when_featured "package_when_featured", packages.is_archived "package_is_archived", packages.updated_at "package_updated_at", packages.created_at "package_created_at""#;
+pub const PACKAGE_BASE_SELECT_JOINED: &str = r#"packages.scope "package_scope: ScopeName", packages.name "package_name: PackageName", packages.description "package_description", packages.github_repository_id "package_github_repository_id", packages.runtime_compat "package_runtime_compat: RuntimeCompat", packages.readme_source "package_readme_source: ReadmeSource", packages.localized_descriptions "package_localized_descriptions: LocalizedDescriptions", packages.when_featured "package_when_featured", packages.is_archived "package_is_archived", packages.visibility "package_visibility: PackageVisibility", packages.updated_at "package_updated_at", packages.created_at "package_created_at""#;
 
 // Version aggregate columns from lateral join aliases (SELECT clause)
 pub const PACKAGE_VERSION_AGG_SELECT: &str = r#"COALESCE(pv_count.cnt, 0) as "package_version_count!", pv_latest.version as "package_latest_version?", pv_latest.meta as "package_version_meta?: PackageVersionMeta""#;
 
 // Lateral joins replacing correlated subqueries — combines latest version + meta into a single lookup
-pub const PACKAGE_VERSION_LATERAL_JOINS: &str = r#"LEFT JOIN LATERAL (SELECT COUNT(*) as cnt FROM package_versions WHERE scope = packages.scope AND name = packages.name) pv_count ON true LEFT JOIN LATERAL (SELECT version, meta FROM package_versions WHERE scope = packages.scope AND name = packages.name AND version NOT LIKE '%-%' AND is_yanked = false ORDER BY version DESC LIMIT 1) pv_latest ON true"#;
+pub const PACKAGE_VERSION_LATERAL_JOINS: &str = r#"LEFT JOIN LATERAL (SELECT COUNT(*) as cnt FROM package_versions WHERE scope = packages.scope AND name = packages.name AND deleted_at IS NULL) pv_count ON true LEFT JOIN LATERAL (SELECT version, meta, license FROM package_versions WHERE scope = packages.scope AND name = packages.name AND deleted_at IS NULL AND version NOT LIKE '%-%' AND is_yanked = false ORDER BY version DESC LIMIT 1) pv_latest ON true"#;
 
 pub const GITHUB_REPOSITORY_SELECT_JOINED: &str = r#"github_repositories.id "github_repository_id?", github_repositories.owner "github_repository_owner?", github_repositories.name "github_repository_name?", github_repositories.updated_at "github_repository_updated_at?", github_repositories.created_at "github_repository_created_at?""#;
 
-pub const SCOPE_SELECT_JOINED_RT: &str = r#"scopes.scope as "scope_scope", scopes.description as "scope_description", scopes.creator as "scope_creator", scopes.package_limit as "scope_package_limit", scopes.new_package_per_week_limit as "scope_new_package_per_week_limit", scopes.publish_attempts_per_week_limit as "scope_publish_attempts_per_week_limit", scopes.verify_oidc_actor as "scope_verify_oidc_actor", scopes.require_publishing_from_ci as "scope_require_publishing_from_ci", scopes.updated_at as "scope_updated_at", scopes.created_at as "scope_created_at""#;
+pub const SCOPE_SELECT_JOINED_RT: &str = r#"scopes.scope as "scope_scope", scopes.description as "scope_description", scopes.creator as "scope_creator", scopes.package_limit as "scope_package_limit", scopes.new_package_per_week_limit as "scope_new_package_per_week_limit", scopes.publish_attempts_per_week_limit as "scope_publish_attempts_per_week_limit", scopes.storage_limit as "scope_storage_limit", scopes.verify_oidc_actor as "scope_verify_oidc_actor", scopes.require_publishing_from_ci as "scope_require_publishing_from_ci", scopes.updated_at as "scope_updated_at", scopes.created_at as "scope_created_at""#;
 
 pub const USER_PUBLIC_SELECT_JOINED_RT: &str = r#"users.id as "user_id", users.name as "user_name", users.avatar_url as "user_avatar_url", users.github_id as "user_github_id", users.gitlab_id as "user_gitlab_id", users.updated_at as "user_updated_at", users.created_at as "user_created_at""#;
 
-pub const SCOPE_USAGE_SELECT_RT: &str = r#"(SELECT COUNT(created_at) FROM packages WHERE packages.scope = scopes.scope) AS "usage_package",
-(SELECT COUNT(created_at) FROM packages WHERE packages.scope = scopes.scope AND created_at > now() - '1 week'::interval) AS "usage_new_package_per_week",
-(SELECT COUNT(created_at) FROM publishing_tasks WHERE publishing_tasks.package_scope = scopes.scope AND created_at > now() - '1 week'::interval) AS "usage_publish_attempts_per_week""#;
+pub const SCOPE_USAGE_SELECT_RT: &str = r#"(SELECT COUNT(created_at) FROM packages WHERE packages.scope = scopes.scope AND packages.deleted_at IS NULL) AS "usage_package",
+(SELECT COUNT(created_at) FROM packages WHERE packages.scope = scopes.scope AND packages.deleted_at IS NULL AND created_at > now() - '1 week'::interval) AS "usage_new_package_per_week",
+(SELECT COUNT(created_at) FROM publishing_tasks WHERE publishing_tasks.package_scope = scopes.scope AND created_at > now() - '1 week'::interval) AS "usage_publish_attempts_per_week",
+(SELECT COALESCE(SUM(size), 0) FROM package_files WHERE package_files.scope = scopes.scope) AS "usage_storage""#;
 
 pub const GITHUB_REPOSITORY_SELECT_JOINED_RT: &str = r#"github_repositories.id "github_repository_id", github_repositories.owner "github_repository_owner", github_repositories.name "github_repository_name", github_repositories.updated_at "github_repository_updated_at", github_repositories.created_at "github_repository_created_at""#;
 
 // Runtime lateral join variants
-pub const PACKAGE_BASE_SELECT_JOINED_RT: &str = r#"packages.scope "package_scope", packages.name "package_name", packages.description "package_description", packages.github_repository_id "package_github_repository_id", packages.runtime_compat as "package_runtime_compat", packages.readme_source "package_readme_source", packages.when_featured "package_when_featured", packages.is_archived "package_is_archived", packages.updated_at "package_updated_at", packages.created_at "package_created_at""#;
+pub const PACKAGE_BASE_SELECT_JOINED_RT: &str = r#"packages.scope "package_scope", packages.name "package_name", packages.description "package_description", packages.github_repository_id "package_github_repository_id", packages.runtime_compat as "package_runtime_compat", packages.readme_source "package_readme_source", packages.localized_descriptions "package_localized_descriptions", packages.when_featured "package_when_featured", packages.is_archived "package_is_archived", packages.visibility "package_visibility", packages.updated_at "package_updated_at", packages.created_at "package_created_at""#;
 
 pub const PACKAGE_VERSION_AGG_SELECT_RT: &str = r#"COALESCE(pv_count.cnt, 0) as "package_version_count", pv_latest.version as "package_latest_version", pv_latest.meta as "package_version_meta""#;
 
-pub const PACKAGE_VERSION_LATERAL_JOINS_RT: &str = r#"LEFT JOIN LATERAL (SELECT COUNT(*) as cnt FROM package_versions WHERE scope = packages.scope AND name = packages.name) pv_count ON true LEFT JOIN LATERAL (SELECT version, meta FROM package_versions WHERE scope = packages.scope AND name = packages.name AND version NOT LIKE '%-%' AND is_yanked = false ORDER BY version DESC LIMIT 1) pv_latest ON true"#;
-
-pub const PACKAGE_VERSION_SELECT: &str = r#"scope as "scope: ScopeName", name as "name: PackageName", version as "version: Version", user_id, readme_path as "readme_path: PackagePath", exports as "exports: ExportsMap", is_yanked, uses_npm, meta as "meta: PackageVersionMeta", updated_at, created_at, rekor_log_id, license"#;
+pub const PACKAGE_VERSION_LATERAL_JOINS_RT: &str = r#"LEFT JOIN LATERAL (SELECT COUNT(*) as cnt FROM package_versions WHERE scope = packages.scope AND name = packages.name AND deleted_at IS NULL) pv_count ON true LEFT JOIN LATERAL (SELECT version, meta, license FROM package_versions WHERE scope = packages.scope AND name = packages.name AND deleted_at IS NULL AND version NOT LIKE '%-%' AND is_yanked = false ORDER BY version DESC LIMIT 1) pv_latest ON true"#;
+
+/// The conditions shared by the package search listing, count and facets. Binds
+/// the scope and package ILIKE patterns, the GitHub repository id, the runtime
+/// compat filter, the provenance filter, the license filter and the minimum
+/// score filter to $1 - $7, and the weights and max score of the active score
+/// schema to $8 and $9. Expects the `pv_latest` lateral join to be present.
+///
+/// The score is computed like `ApiPackageScore` does, and packages without a
+/// latest version have none.
+pub const PACKAGE_SEARCH_WHERE_RT: &str = r#"(packages.scope ILIKE $1 OR packages.name ILIKE $2) AND (packages.github_repository_id = $3 OR $3 IS NULL) AND packages.deleted_at IS NULL AND NOT packages.is_archived AND packages.visibility = 'public' AND packages.runtime_compat @> $4 AND ($5::boolean IS NULL OR COALESCE((pv_latest.meta->>'hasProvenance')::boolean, false) = $5) AND ($6::text IS NULL OR lower(pv_latest.license) = lower($6))
+AND ($7::integer IS NULL OR (pv_latest.version IS NOT NULL AND LEAST((
+  ($8::jsonb->>'hasReadme')::integer * COALESCE((pv_latest.meta->>'hasReadme')::boolean, false)::integer
+  + ($8::jsonb->>'hasReadmeExamples')::integer * COALESCE((pv_latest.meta->>'hasReadmeExamples')::boolean, false)::integer
+  + ($8::jsonb->>'allEntrypointsDocs')::integer * COALESCE((pv_latest.meta->>'allEntrypointsDocs')::boolean, false)::integer
+  + floor(LEAST(COALESCE((pv_latest.meta->>'percentageDocumentedSymbols')::real, 0) / 0.8::real, 1::real) * ($8::jsonb->>'percentageDocumentedSymbols')::real)::integer
+  + ($8::jsonb->>'allFastCheck')::integer * COALESCE((pv_latest.meta->>'allFastCheck')::boolean, false)::integer
+  + ($8::jsonb->>'allExamplesResolve')::integer * COALESCE((pv_latest.meta->>'allExamplesResolve')::boolean, false)::integer
+  + ($8::jsonb->>'hasTests')::integer * COALESCE((pv_latest.meta->>'hasTests')::boolean, false)::integer
+  + ($8::jsonb->>'hasProvenance')::integer * COALESCE((pv_latest.meta->>'hasProvenance')::boolean, false)::integer
+  + ($8::jsonb->>'hasDescription')::integer * (packages.description <> '')::integer
+  + ($8::jsonb->>'atLeastOneRuntimeCompatible')::integer * ((COALESCE((packages.runtime_compat->>'browser')::boolean, false)::integer + COALESCE((packages.runtime_compat->>'deno')::boolean, false)::integer + COALESCE((packages.runtime_compat->>'node')::boolean, false)::integer + COALESCE((packages.runtime_compat->>'workerd')::boolean, false)::integer + COALESCE((packages.runtime_compat->>'bun')::boolean, false)::integer) >= 1)::integer
+  + ($8::jsonb->>'multipleRuntimesCompatible')::integer * ((COALESCE((packages.runtime_compat->>'browser')::boolean, false)::integer + COALESCE((packages.runtime_compat->>'deno')::boolean, false)::integer + COALESCE((packages.runtime_compat->>'node')::boolean, false)::integer + COALESCE((packages.runtime_compat->>'workerd')::boolean, false)::integer + COALESCE((packages.runtime_compat->>'bun')::boolean, false)::integer) >= 2)::integer
+) * 100 / GREATEST($9::integer, 1), 100) >= $7))"#;
+
+pub const PACKAGE_VERSION_SELECT: &str = r#"scope as "scope: ScopeName", name as "name: PackageName", version as "version: Version", user_id, readme_path as "readme_path: PackagePath", exports as "exports: ExportsMap", is_yanked, yanked_at, yank_reason, uses_npm, meta as "meta: PackageVersionMeta", updated_at, created_at, rekor_log_id, license"#;
 
 pub const NEWER_VERSIONS_COUNT_SUBQUERY: &str = r#"(SELECT COUNT(*)
         FROM package_versions AS pv
@@ -82,9 +106,10 @@ pub const NEWER_VERSIONS_COUNT_SUBQUERY: &str = r#"(SELECT COUNT(*)
         AND pv.name = package_versions.name
         AND pv.version > package_versions.version
         AND pv.version NOT LIKE '%-%'
-        AND pv.is_yanked = false) as "newer_versions_count!""#;
+        AND pv.is_yanked = false
+        AND pv.deleted_at IS NULL) as "newer_versions_count!""#;
 
-pub const PACKAGE_VERSION_SELECT_JOINED: &str = r#"package_versions.scope as "package_version_scope: ScopeName", package_versions.name as "package_version_name: PackageName", package_versions.version as "package_version_version: Version", package_versions.user_id as "package_version_user_id", package_versions.readme_path as "package_version_readme_path: PackagePath", package_versions.exports as "package_version_exports: ExportsMap", package_versions.is_yanked as "package_version_is_yanked", package_versions.uses_npm as "package_version_uses_npm", package_versions.meta as "package_version_meta: PackageVersionMeta", package_versions.updated_at as "package_version_updated_at", package_versions.created_at as "package_version_created_at", package_versions.rekor_log_id as "package_version_rekor_log_id", package_versions.license as "package_version_license""#;
+pub const PACKAGE_VERSION_SELECT_JOINED: &str = r#"package_versions.scope as "package_version_scope: ScopeName", package_versions.name as "package_version_name: PackageName", package_versions.version as "package_version_version: Version", package_versions.user_id as "package_version_user_id", package_versions.readme_path as "package_version_readme_path: PackagePath", package_versions.exports as "package_version_exports: ExportsMap", package_versions.is_yanked as "package_version_is_yanked", package_versions.yanked_at as "package_version_yanked_at", package_versions.yank_reason as "package_version_yank_reason", package_versions.uses_npm as "package_version_uses_npm", package_versions.meta as "package_version_meta: PackageVersionMeta", package_versions.updated_at as "package_version_updated_at", package_versions.created_at as "package_version_created_at", package_versions.rekor_log_id as "package_version_rekor_log_id", package_versions.license as "package_version_license""#;
 
 pub const USER_PUBLIC_SELECT_JOINED: &str = r#"users.id as "user_id?", users.name as "user_name?", users.avatar_url as "user_avatar_url?", users.github_id as "user_github_id", users.gitlab_id as "user_gitlab_id", users.updated_at as "user_updated_at?", users.created_at as "user_created_at?""#;
 
@@ -93,7 +118,7 @@ pub const SCOPE_MEMBER_SELECT: &str =
 
 pub const SCOPE_INVITE_SELECT: &str = r#"scope as "scope: ScopeName", target_user_id, requesting_user_id, updated_at, created_at"#;
 
-pub const TOKEN_SELECT: &str = r#"id, hash, user_id, type "type: _", description, expires_at, permissions "permissions: _", updated_at, created_at"#;
+pub const TOKEN_SELECT: &str = r#"id, hash, user_id, type "type: _", description, expires_at, permissions "permissions: _", last_used_at, rotated_at, updated_at, created_at"#;
 
 pub const PUBLISHING_TASK_SELECT: &str = r#"id, status as "status: PublishingTaskStatus", error as "error: PublishingTaskError", user_id, package_scope as "package_scope: ScopeName", package_name as "package_name: PackageName", package_version as "package_version: Version", config_file as "config_file: PackagePath", created_at, updated_at"#;
 
@@ -105,9 +130,17 @@ pub const GITHUB_IDENTITY_SELECT: &str = "github_id, access_token, access_token_
 
 pub const PACKAGE_FILE_SELECT: &str = r#"scope as "scope: ScopeName", name as "name: PackageName", version as "version: Version", path as "path: PackagePath", size, checksum, updated_at, created_at"#;
 
-pub const NPM_TARBALL_SELECT: &str = r#"scope as "scope: ScopeName", name as "name: PackageName", version as "version: Version", revision, sha1, sha512, size, updated_at, created_at"#;
+pub const PACKAGE_VERSION_DOC_ARTIFACT_SELECT: &str = r#"scope as "scope: ScopeName", name as "name: PackageName", version as "version: Version", kind as "kind: DocArtifactKind", checksum, size, updated_at, created_at"#;
+
+pub const NPM_TARBALL_SELECT: &str = r#"scope as "scope: ScopeName", name as "name: PackageName", version as "version: Version", revision, sha1, sha512, sha256, size, rekor_log_id, updated_at, created_at"#;
+
+pub const PACKAGE_VERSION_DEPENDENCY_SELECT: &str = r#"package_scope as "package_scope: ScopeName", package_name as "package_name: PackageName", package_version as "package_version: Version", dependency_kind as "dependency_kind: DependencyKind", dependency_name, dependency_constraint, dependency_path, is_optional, updated_at, created_at"#;
+
+pub const PACKAGE_VERSION_DEPRECATION_SELECT: &str = r#"scope as "scope: ScopeName", name as "name: PackageName", version as "version: Version", export, symbol, message, updated_at, created_at"#;
+
+pub const PACKAGE_VERSION_DOC_LINK_SELECT: &str = r#"scope as "scope: ScopeName", name as "name: PackageName", version as "version: Version", identifier, path, updated_at, created_at"#;
 
-pub const PACKAGE_VERSION_DEPENDENCY_SELECT: &str = r#"package_scope as "package_scope: ScopeName", package_name as "package_name: PackageName", package_version as "package_version: Version", dependency_kind as "dependency_kind: DependencyKind", dependency_name, dependency_constraint, dependency_path, updated_at, created_at"#;
+pub const SCOPE_MARKDOWN_SETTINGS_SELECT: &str = r#"scope as "scope: ScopeName", allow_html, admonitions, updated_at, created_at"#;
 
 pub const PUBLISHING_TASK_SELECT_JOINED: &str = r#"publishing_tasks.id as "task_id", publishing_tasks.status as "task_status: PublishingTaskStatus", publishing_tasks.error as "task_error: PublishingTaskError", publishing_tasks.user_id as "task_user_id", publishing_tasks.package_scope as "task_package_scope: ScopeName", publishing_tasks.package_name as "task_package_name: PackageName", publishing_tasks.package_version as "task_package_version: Version", publishing_tasks.config_file as "task_config_file: PackagePath", publishing_tasks.created_at as "task_created_at", publishing_tasks.updated_at as "task_updated_at""#;
 
@@ -162,3 +195,70 @@ pub const USER_SELECT_FULL_JOINED_RT: &str = r#"users.id as "user_id", users.nam
 pub const TICKET_MESSAGE_SELECT_JOINED: &str = r#"ticket_messages.ticket_id as "message_ticket_id", ticket_messages.author as "message_author", ticket_messages.message as "message_message", ticket_messages.updated_at as "message_updated_at", ticket_messages.created_at as "message_created_at""#;
 
 pub const AUDIT_LOG_SELECT_JOINED: &str = r#"audit_logs.actor_id as "audit_log_actor_id", audit_logs.is_sudo as "audit_log_is_sudo", audit_logs.action as "audit_log_action", audit_logs.meta as "audit_log_meta", audit_logs.created_at as "audit_log_created_at""#;
+
+pub const SCORE_RECOMPUTE_JOB_SELECT: &str = r#"id, status as "status: ScoreRecomputeJobStatus", total_versions, processed_versions, failed_versions, error, created_by, finished_at, updated_at, created_at"#;
+
+pub const NPM_TARBALL_REBUILD_JOB_SELECT: &str = r#"id, status as "status: NpmTarballRebuildJobStatus", scope as "scope: ScopeName", name as "name: PackageName", verify, concurrency, total_versions, processed_versions, failed_versions, error, created_by, finished_at, updated_at, created_at"#;
+
+pub const ORPHAN_GC_JOB_SELECT: &str = r#"id, status as "status: OrphanGcJobStatus", dry_run, scanned_packages, scanned_objects, orphaned_objects, deleted_objects, error, created_by, finished_at, updated_at, created_at"#;
+
+pub const INTEGRITY_SCRUB_JOB_SELECT: &str = r#"id, status as "status: IntegrityScrubJobStatus", scope as "scope: ScopeName", name as "name: PackageName", total_versions, checked_versions, checked_objects, failed_objects, error, created_by, finished_at, updated_at, created_at"#;
+
+pub const BACKGROUND_JOB_SELECT: &str = r#"id, kind as "kind: BackgroundJobKind", payload, status as "status: BackgroundJobStatus", priority, attempts, max_attempts, run_at, last_error, created_by, finished_at, updated_at, created_at"#;
+
+pub const ANNOUNCEMENT_SELECT: &str = r#"id, title, body, severity as "severity: AnnouncementSeverity", features, expires_at, created_by, updated_at, created_at"#;
+
+pub const FEATURE_FLAG_SELECT: &str = r#"name, description, enabled, scopes as "scopes: Vec<ScopeName>", user_ids, updated_at, created_at"#;
+
+pub const DATA_EXPORT_SELECT: &str = r#"id, user_id, status as "status: DataExportStatus", size, error, completed_at, updated_at, created_at"#;
+
+pub const SCHEDULED_JOB_SELECT: &str = r#"name, schedule, next_run_at, run_id, running_since, lease_expires_at, last_started_at, last_finished_at, last_status as "last_status: ScheduledJobStatus", last_error, updated_at, created_at"#;
+
+pub const NOTIFICATION_PREFERENCES_SELECT: &str = r#"user_id, scope_invites, publish_failures, scope_digests, unsubscribe_token, last_digest_sent_at, updated_at, created_at"#;
+
+pub const RESERVED_NAME_SELECT: &str = r#"kind as "kind: ReservedNameKind", name, reason, expires_at, created_by, updated_at, created_at"#;
+
+pub const RATE_LIMIT_TIER_SELECT: &str = r#"tier as "tier: RateLimitTier", requests_per_minute, updated_at, created_at"#;
+
+pub const SCOPE_RATE_LIMIT_SELECT: &str = r#"scope as "scope: ScopeName", requests_per_minute, reason, created_by, updated_at, created_at"#;
+
+pub const SCOPE_PUBLISH_LIMITS_SELECT: &str = r#"scope as "scope: ScopeName", max_tarball_size, max_file_size, max_total_file_size, max_file_count, max_path_depth, max_path_length, max_module_count, reason, created_by, updated_at, created_at"#;
+
+pub const TOKEN_RATE_LIMIT_SELECT: &str = r#"token_id, requests_per_minute, reason, created_by, updated_at, created_at"#;
+
+pub const SCOPE_OIDC_ISSUER_SELECT: &str =
+  r#"id, scope as "scope: ScopeName", issuer, created_at"#;
+
+pub const PACKAGE_TRUSTED_PUBLISHER_SELECT: &str = r#"id, scope as "scope: ScopeName", package as "package: PackageName", issuer, subject, created_by, created_at"#;
+
+pub const SCOPE_WEBHOOK_SELECT: &str = r#"id, scope as "scope: ScopeName", url, secret, events as "events: Vec<WebhookEvent>", description, is_active, created_by, updated_at, created_at"#;
+
+pub const WEBHOOK_DELIVERY_SELECT: &str = r#"id, webhook_id, event as "event: WebhookEvent", payload, status as "status: WebhookDeliveryStatus", attempts, next_attempt_at, last_response_status, last_error, delivered_at, updated_at, created_at"#;
+
+pub const PACKAGE_DIST_TAG_SELECT: &str = r#"scope as "scope: ScopeName", name as "name: PackageName", tag, version as "version: Version", updated_at, created_at"#;
+
+pub const PACKAGE_TRANSFER_SELECT: &str = r#"scope as "scope: ScopeName", name as "name: PackageName", target_scope as "target_scope: ScopeName", requested_by, created_at"#;
+
+pub const PACKAGE_REDIRECT_SELECT: &str = r#"scope as "scope: ScopeName", name as "name: PackageName", target_scope as "target_scope: ScopeName", target_name as "target_name: PackageName", created_at"#;
+
+pub const SCOPE_RENAME_SELECT: &str = r#"scope as "scope: ScopeName", target_scope as "target_scope: ScopeName", requested_by, created_at"#;
+
+pub const SCOPE_REDIRECT_SELECT: &str = r#"scope as "scope: ScopeName", target_scope as "target_scope: ScopeName", created_at"#;
+
+pub const SCOPE_TEAM_SELECT: &str =
+  r#"scope as "scope: ScopeName", name, description, updated_at, created_at"#;
+
+pub const SCOPE_TEAM_MEMBER_SELECT: &str =
+  r#"scope as "scope: ScopeName", team, user_id, created_at"#;
+
+pub const SCOPE_TEAM_PACKAGE_SELECT: &str = r#"scope as "scope: ScopeName", team, package as "package: PackageName", can_publish, can_yank, can_manage_tokens, updated_at, created_at"#;
+
+pub const SCOPE_TEAM_MEMBER_SELECT_JOINED: &str = r#"scope_team_members.scope as "scope_team_member_scope: ScopeName", scope_team_members.team as "scope_team_member_team", scope_team_members.user_id as "scope_team_member_user_id", scope_team_members.created_at as "scope_team_member_created_at""#;
+
+pub const REGISTRY_CHANGE_SELECT: &str = r#"seq, kind as "kind: RegistryChangeKind", scope as "scope: ScopeName", name as "name: PackageName", version as "version: Version", created_at"#;
+
+pub const PUBLISH_UPLOAD_SELECT: &str = r#"id, user_id, package_scope as "package_scope: ScopeName", package_name as "package_name: PackageName", package_version as "package_version: Version", config_file as "config_file: PackagePath", chunks, size, expires_at, updated_at, created_at"#;
+
+pub const ABUSE_REPORT_SELECT: &str = r#"id, scope as "scope: ScopeName", name as "name: PackageName", version as "version: Version", reason as "reason: AbuseReportReason", description, reporter_id, status as "status: AbuseReportStatus", reviewer_id, resolution, resolved_at, updated_at, created_at"#;
+
+pub const PACKAGE_VERSION_QUARANTINE_SELECT: &str = r#"scope as "scope: ScopeName", name as "name: PackageName", version as "version: Version", reason, report_id, quarantined_by, created_at"#;
@@ -218,6 +218,106 @@ async fn list_stale_publishing_tasks() {
   assert!(none_stale.is_empty(), "{none_stale:?}");
 }
 
+#[tokio::test]
+async fn list_abandoned_publishing_tasks() {
+  let db = EphemeralDatabase::create().await;
+
+  let user_id = uuid::Uuid::default();
+  let scope_name: ScopeName = "scope".try_into().unwrap();
+  let package_name: PackageName = "package".try_into().unwrap();
+  let config_file: PackagePath = "/jsr.json".try_into().unwrap();
+
+  db.create_scope(
+    &user_id,
+    false,
+    &scope_name,
+    user_id,
+    &ScopeDescription::default(),
+  )
+  .await
+  .unwrap();
+  db.create_package(&scope_name, &package_name).await.unwrap();
+
+  let mut ids = std::collections::HashMap::new();
+  for (version_str, path) in [
+    ("1.0.0", &[][..]),
+    ("2.0.0", &[PublishingTaskStatus::Processing][..]),
+    (
+      "3.0.0",
+      &[
+        PublishingTaskStatus::Processing,
+        PublishingTaskStatus::Processed,
+      ][..],
+    ),
+  ] {
+    let version: Version = version_str.try_into().unwrap();
+    let CreatePublishingTaskResult::Created((pt, _)) = db
+      .create_publishing_task(NewPublishingTask {
+        user_id: Some(user_id),
+        package_scope: &scope_name,
+        package_name: &package_name,
+        package_version: &version,
+        config_file: &config_file,
+      })
+      .await
+      .unwrap()
+    else {
+      unreachable!()
+    };
+    let mut prev = PublishingTaskStatus::Pending;
+    for next in path {
+      db.update_publishing_task_status(None, pt.id, prev, next.clone(), None)
+        .await
+        .unwrap();
+      prev = next.clone();
+    }
+    ids.insert(version_str, pt.id);
+  }
+
+  // Only tasks that have not committed their version row can be abandoned.
+  let abandoned = db.list_abandoned_publishing_tasks(0).await.unwrap();
+  let abandoned_ids: std::collections::HashSet<_> =
+    abandoned.iter().map(|t| t.id).collect();
+  assert_eq!(abandoned.len(), 2, "{abandoned:?}");
+  assert!(
+    abandoned_ids.contains(&ids["1.0.0"]),
+    "pending must be listed"
+  );
+  assert!(
+    abandoned_ids.contains(&ids["2.0.0"]),
+    "processing must be listed"
+  );
+
+  let none = db.list_abandoned_publishing_tasks(3600).await.unwrap();
+  assert!(none.is_empty(), "{none:?}");
+
+  // Once expired, the version is free to be published again.
+  db.update_publishing_task_status(
+    None,
+    ids["2.0.0"],
+    PublishingTaskStatus::Processing,
+    PublishingTaskStatus::Failure,
+    Some(PublishingTaskError {
+      code: "publishTimedOut".to_string(),
+      message: "x".to_string(),
+    }),
+  )
+  .await
+  .unwrap();
+  let version: Version = "2.0.0".try_into().unwrap();
+  let res = db
+    .create_publishing_task(NewPublishingTask {
+      user_id: Some(user_id),
+      package_scope: &scope_name,
+      package_name: &package_name,
+      package_version: &version,
+      config_file: &config_file,
+    })
+    .await
+    .unwrap();
+  assert!(matches!(res, CreatePublishingTaskResult::Created(_)));
+}
+
 #[tokio::test]
 async fn users() {
   let db = EphemeralDatabase::create().await;
@@ -375,7 +475,7 @@ async fn packages() {
   assert!(no_package.is_none());
 
   let (total, packages) = db
-    .list_packages_by_scope(&scope_name, false, 0, 100)
+    .list_packages_by_scope(&scope_name, false, false, 0, 100)
     .await
     .unwrap();
   assert_eq!(total, 1);
@@ -384,6 +484,69 @@ async fn packages() {
   assert!(packages[0].1.is_none());
 }
 
+#[tokio::test]
+async fn confusable_names() {
+  let db = EphemeralDatabase::create().await;
+
+  let alice = db
+    .insert_user(NewUser {
+      name: "Alice",
+      email: None,
+      avatar_url: "https://example.com/alice.png",
+      github_id: None,
+      gitlab_id: None,
+      is_blocked: false,
+      is_staff: false,
+    })
+    .await
+    .unwrap();
+
+  let scope_name: ScopeName = "modern-cl1".try_into().unwrap();
+  db.create_scope(
+    &alice.id,
+    false,
+    &scope_name,
+    alice.id,
+    &ScopeDescription::default(),
+  )
+  .await
+  .unwrap();
+  let package_name: PackageName = "vvorld-0".try_into().unwrap();
+  db.create_package(&scope_name, &package_name).await.unwrap();
+
+  // the skeletons that the database computes match those of the names
+  for lookalike in ["modem-dl", "rnodern-cll", "m0dern-d1"] {
+    let lookalike = ScopeName::try_from(lookalike).unwrap();
+    let existing = db.find_confusable_scope(&lookalike).await.unwrap();
+    assert_eq!(existing.as_ref(), Some(&scope_name), "{lookalike}");
+  }
+  for lookalike in ["world-o", "vv-orldo"] {
+    let lookalike = PackageName::try_from(lookalike).unwrap();
+    let existing = db
+      .find_confusable_package(&scope_name, &lookalike)
+      .await
+      .unwrap();
+    assert_eq!(existing.as_ref(), Some(&package_name), "{lookalike}");
+  }
+
+  // a name does not look like itself, or like different names
+  assert!(
+    db.find_confusable_scope(&scope_name)
+      .await
+      .unwrap()
+      .is_none()
+  );
+  let other = ScopeName::try_from("modern").unwrap();
+  assert!(db.find_confusable_scope(&other).await.unwrap().is_none());
+  let other = PackageName::try_from("word").unwrap();
+  assert!(
+    db.find_confusable_package(&scope_name, &other)
+      .await
+      .unwrap()
+      .is_none()
+  );
+}
+
 #[tokio::test]
 async fn scope_members() {
   let db = EphemeralDatabase::create().await;
@@ -450,7 +613,9 @@ async fn scope_members() {
   assert_eq!(members[1].0.user_id, bob.id);
   assert_eq!(members[1].1.id, bob.id);
 
-  db.delete_scope_member(&scope_name, alice.id).await.unwrap();
+  db.delete_scope_member(&bob.id, false, &scope_name, alice.id)
+    .await
+    .unwrap();
   let members = db.list_scope_members(&scope_name).await.unwrap();
   assert_eq!(members.len(), 1);
   assert_eq!(members[0].0.user_id, bob.id);
@@ -521,6 +686,7 @@ async fn create_package_version_and_finalize_publishing_task() {
     revision: NPM_TARBALL_REVISION as i32,
     sha1: "",
     sha512: "",
+    sha256: "",
     size: 0,
   };
 
@@ -540,7 +706,12 @@ async fn create_package_version_and_finalize_publishing_task() {
       },
       &package_files,
       &package_version_dependencies,
+      &[],
+      &[],
+      &[],
+      &[],
       npm_tarball,
+      None,
     )
     .await
     .unwrap();
@@ -658,6 +829,112 @@ async fn package_files() {
   assert_eq!(package_files.len(), 0);
 }
 
+#[tokio::test]
+async fn module_blobs() {
+  let db = EphemeralDatabase::create().await;
+
+  let user = db
+    .insert_user(NewUser {
+      name: "Alice",
+      email: None,
+      avatar_url: "https://example.com/alice.png",
+      github_id: None,
+      gitlab_id: None,
+      is_blocked: false,
+      is_staff: false,
+    })
+    .await
+    .unwrap();
+
+  let scope_name = "scope".try_into().unwrap();
+  let package_name = "testpkg".try_into().unwrap();
+  let version = "1.2.3".try_into().unwrap();
+
+  db.create_scope(
+    &user.id,
+    false,
+    &scope_name,
+    user.id,
+    &ScopeDescription::default(),
+  )
+  .await
+  .unwrap();
+  db.create_package(&scope_name, &package_name).await.unwrap();
+  db.create_package_version_for_test(NewPackageVersion {
+    scope: &scope_name,
+    name: &package_name,
+    version: &version,
+    user_id: None,
+    readme_path: None,
+    exports: &ExportsMap::mock(),
+    uses_npm: false,
+    meta: Default::default(),
+    license: "MIT".to_string(),
+  })
+  .await
+  .unwrap();
+
+  let used = "sha256-used".to_string();
+  let unused = "sha256-unused".to_string();
+  db.create_package_file_for_test(NewPackageFile {
+    scope: &scope_name,
+    name: &package_name,
+    version: &version,
+    path: &PackagePath::try_from("/mod.ts").unwrap(),
+    size: 10,
+    checksum: Some(used.as_str()),
+  })
+  .await
+  .unwrap();
+  db.insert_module_blobs(&[(used.clone(), 10), (unused.clone(), 20)])
+    .await
+    .unwrap();
+
+  let stored = db
+    .reference_module_blobs(&[used.clone(), "sha256-missing".to_string()])
+    .await
+    .unwrap();
+  assert_eq!(stored, vec![used.clone()]);
+
+  // Blobs that were referenced recently are kept.
+  let deleted = db
+    .delete_unreferenced_module_blobs(
+      Utc::now() - chrono::Duration::hours(1),
+      10,
+      |hashes| async move { hashes },
+    )
+    .await
+    .unwrap();
+  assert!(deleted.is_empty());
+
+  // Blobs whose objects could not be deleted are kept too.
+  let deleted = db
+    .delete_unreferenced_module_blobs(
+      Utc::now() + chrono::Duration::hours(1),
+      10,
+      |_| async move { vec![] },
+    )
+    .await
+    .unwrap();
+  assert!(deleted.is_empty());
+
+  let deleted = db
+    .delete_unreferenced_module_blobs(
+      Utc::now() + chrono::Duration::hours(1),
+      10,
+      |hashes| async move { hashes },
+    )
+    .await
+    .unwrap();
+  assert_eq!(deleted, vec![unused.clone()]);
+
+  let stored = db
+    .reference_module_blobs(&[used.clone(), unused])
+    .await
+    .unwrap();
+  assert_eq!(stored, vec![used]);
+}
+
 #[tokio::test]
 async fn oauth_state() {
   let db = EphemeralDatabase::create().await;
@@ -737,6 +1014,7 @@ async fn tokens() {
   let time = DateTime::<Utc>::default();
   let new_token = NewToken {
     hash: "0".to_string(),
+    lookup_hash: "0".to_string(),
     user_id: user.id,
     r#type: TokenType::Web,
     description: None,
@@ -756,3 +1034,136 @@ async fn tokens() {
   let no_token = db.get_token_by_hash("1").await.unwrap();
   assert!(no_token.is_none());
 }
+
+#[tokio::test]
+async fn publish_uploads() {
+  let db = EphemeralDatabase::create().await;
+
+  let scope_name = "scope".try_into().unwrap();
+  let package_name = "package".try_into().unwrap();
+  let version = "1.0.0".try_into().unwrap();
+  let config_file = "/jsr.json".try_into().unwrap();
+  let new_upload = |expires_at| NewPublishUpload {
+    user_id: None,
+    package_scope: &scope_name,
+    package_name: &package_name,
+    package_version: &version,
+    config_file: &config_file,
+    expires_at,
+  };
+
+  let upload = db
+    .create_publish_upload(new_upload(Utc::now() + chrono::Duration::hours(1)))
+    .await
+    .unwrap();
+  assert!(upload.chunks.is_empty());
+  assert_eq!(upload.size, 0);
+
+  let upload = db
+    .append_publish_upload_chunk(upload.id, 0, "a", 10)
+    .await
+    .unwrap()
+    .unwrap();
+  assert_eq!(upload.chunks, vec!["a".to_string()]);
+  assert_eq!(upload.size, 10);
+  // The offset of the chunk must be the size of the upload.
+  assert!(
+    db.append_publish_upload_chunk(upload.id, 0, "b", 10)
+      .await
+      .unwrap()
+      .is_none()
+  );
+  let upload = db
+    .append_publish_upload_chunk(upload.id, 10, "a", 10)
+    .await
+    .unwrap()
+    .unwrap();
+  assert_eq!(upload.chunks, vec!["a".to_string(), "a".to_string()]);
+  assert_eq!(upload.size, 20);
+
+  let expired = db
+    .create_publish_upload(new_upload(Utc::now() - chrono::Duration::hours(1)))
+    .await
+    .unwrap();
+  assert!(db.get_publish_upload(expired.id).await.unwrap().is_none());
+  assert!(
+    db.append_publish_upload_chunk(expired.id, 0, "a", 10)
+      .await
+      .unwrap()
+      .is_none()
+  );
+  assert_eq!(
+    db.list_expired_publish_uploads(10).await.unwrap(),
+    vec![expired.id]
+  );
+
+  db.delete_publish_upload(expired.id).await.unwrap();
+  assert!(
+    db.list_expired_publish_uploads(10)
+      .await
+      .unwrap()
+      .is_empty()
+  );
+  assert!(db.get_publish_upload(upload.id).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn read_replica_stickiness() {
+  let db = EphemeralDatabase::create().await;
+  let (primary, replica) = db.pool_health();
+  assert_eq!(primary.max_connections, 1);
+  assert!(replica.is_none());
+  // Without a replica, there is nothing to stick to the primary for.
+  db.stick_to_primary();
+  assert!(!db.is_sticky_to_primary());
+
+  // The database itself stands in for its replica.
+  let database = db
+    .database
+    .clone()
+    .unwrap()
+    .with_read_replica(
+      &db.database_url,
+      2,
+      std::time::Duration::from_secs(5),
+      None,
+      chrono::Duration::minutes(1),
+    )
+    .await
+    .unwrap();
+  let (_, replica) = database.pool_health();
+  let replica = replica.unwrap();
+  assert_eq!(replica.max_connections, 2);
+  assert!(!replica.closed);
+
+  assert!(!database.is_sticky_to_primary());
+  database.stick_to_primary();
+  assert!(database.is_sticky_to_primary());
+  // Clones share the stickiness.
+  assert!(database.clone().is_sticky_to_primary());
+
+  let scope_name = "scope".try_into().unwrap();
+  let package_name = "package".try_into().unwrap();
+  let user_id = uuid::Uuid::default();
+  database
+    .create_scope(
+      &user_id,
+      false,
+      &scope_name,
+      user_id,
+      &ScopeDescription::default(),
+    )
+    .await
+    .unwrap();
+  database
+    .create_package(&scope_name, &package_name)
+    .await
+    .unwrap();
+  assert!(
+    database
+      .get_package(&scope_name, &package_name)
+      .await
+      .unwrap()
+      .is_some()
+  );
+}
@@ -37,6 +37,9 @@ use std::io::Read;
 use std::io::Write;
 use std::sync::Arc;
 use std::sync::OnceLock;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+use std::time::Instant;
 use tracing::instrument;
 use url::Url;
 
@@ -61,20 +64,87 @@ pub async fn acquire_doc_render_permit() -> tokio::sync::OwnedSemaphorePermit {
     .expect("doc render semaphore closed")
 }
 
-/// Current doc nodes storage format version.
+/// Current doc nodes storage format version for full snapshots.
 const DOC_NODES_VERSION: u32 = 2;
 
+/// Storage format version for doc nodes that are stored as a delta against the
+/// doc nodes of an earlier version of the same package.
+const DOC_NODES_DELTA_VERSION: u32 = 3;
+
+/// Maximum number of deltas between a version's doc nodes and the nearest full
+/// snapshot. Once reached, the next version is stored as a full snapshot again,
+/// which bounds the number of downloads needed to reconstruct any version.
+const MAX_DOC_NODES_DELTA_DEPTH: u32 = 8;
+
+/// Version of the shape of the doc nodes themselves, independent of the
+/// storage format above. Bump this whenever a deno_doc upgrade changes how
+/// doc nodes serialize, and add the conversion from the previous shape to
+/// [DOC_NODES_SCHEMA_MIGRATIONS]. Also update `docs/doc_nodes.schema.json`.
+pub const DOC_NODES_SCHEMA_VERSION: u32 = 1;
+
+/// Conversions between doc node schema versions, applied to each stored
+/// document in order. The entry at index `n` converts a document of schema
+/// version `n + 1` to version `n + 2`.
+const DOC_NODES_SCHEMA_MIGRATIONS: &[fn(&mut serde_json::Value)] = &[];
+
+/// The JSON schema of doc nodes of the current [DOC_NODES_SCHEMA_VERSION].
+pub const DOC_NODES_SCHEMA: &str = include_str!("./docs/doc_nodes.schema.json");
+
+/// Blobs written before schema versioning was introduced all have the shape
+/// of schema version 1.
+fn legacy_doc_nodes_schema_version() -> u32 {
+  1
+}
+
 /// Versioned wrapper for stored doc nodes.
 #[derive(Serialize, Deserialize)]
-struct StoredDocNodes {
+struct StoredDocNodes<T = ParseOutput> {
+  version: u32,
+  #[serde(default = "legacy_doc_nodes_schema_version")]
+  schema_version: u32,
+  doc_nodes: T,
+}
+
+/// Doc nodes stored as the documents that changed since the doc nodes of
+/// `base`. Documents that are identical to the base are not stored.
+#[derive(Serialize, Deserialize)]
+struct StoredDocNodesDelta<T = ParseOutput> {
   version: u32,
-  doc_nodes: ParseOutput,
+  #[serde(default = "legacy_doc_nodes_schema_version")]
+  schema_version: u32,
+  /// The version of the same package this delta applies to.
+  base: Version,
+  /// The number of deltas up to and including this one until the nearest full
+  /// snapshot.
+  depth: u32,
+  /// The specifiers of all documents, in order. Documents of the base that
+  /// are not listed here were removed.
+  specifiers: Vec<ModuleSpecifier>,
+  /// Documents that are new or differ from the base.
+  changed: T,
+}
+
+#[derive(Deserialize)]
+struct StoredDocNodesHeader {
+  version: u32,
+  #[serde(default = "legacy_doc_nodes_schema_version")]
+  schema_version: u32,
+}
+
+/// Documents of an older schema version, before conversion.
+type UnconvertedDocNodes = IndexMap<ModuleSpecifier, serde_json::Value>;
+
+enum StoredDocNodesKind {
+  Full(ParseOutput),
+  Delta(StoredDocNodesDelta),
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum DocNodeCacheError {
   #[error(transparent)]
   S3(#[from] crate::s3::S3Error),
+  #[error(transparent)]
+  Database(#[from] sqlx::Error),
   #[error("failed to decompress doc nodes: {0}")]
   Decompress(std::io::Error),
   #[error("failed to deserialize doc nodes: {0}")]
@@ -83,37 +153,171 @@ pub enum DocNodeCacheError {
   InvalidSpecifier(url::ParseError),
   #[error("unexpected doc nodes JSON shape")]
   UnexpectedJsonShape,
-  #[error("unsupported doc nodes version: {0} (expected {DOC_NODES_VERSION})")]
+  #[error(
+    "unsupported doc nodes version: {0} (expected {DOC_NODES_VERSION} or {DOC_NODES_DELTA_VERSION})"
+  )]
   UnsupportedVersion(u32),
+  #[error(
+    "unsupported doc nodes schema version: {0} (expected at most {DOC_NODES_SCHEMA_VERSION})"
+  )]
+  UnsupportedSchemaVersion(u32),
+  #[error("failed to convert doc nodes from schema version {0}: {1}")]
+  SchemaMigration(u32, String),
+  #[error("doc nodes delta base {0} is missing")]
+  MissingDeltaBase(Version),
+  #[error("doc nodes delta chain is longer than {MAX_DOC_NODES_DELTA_DEPTH}")]
+  DeltaChainTooLong,
+  #[error("document {0} is missing from the doc nodes delta and its base")]
+  MissingDeltaDocument(ModuleSpecifier),
+}
+
+fn gzip_msgpack(value: &impl Serialize) -> Bytes {
+  let msgpack = rmp_serde::to_vec_named(value).unwrap();
+  let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+  encoder.write_all(&msgpack).unwrap();
+  encoder.finish().unwrap().into()
 }
 
 /// Serialize doc nodes to gzip-compressed MessagePack with a version field.
 pub fn serialize_doc_nodes(doc_nodes: &ParseOutput) -> Bytes {
-  let stored = StoredDocNodes {
+  gzip_msgpack(&StoredDocNodes {
     version: DOC_NODES_VERSION,
+    schema_version: DOC_NODES_SCHEMA_VERSION,
     doc_nodes: doc_nodes.clone(),
+  })
+}
+
+/// Serialize doc nodes for storage, as a delta against the doc nodes of
+/// `base` if that is smaller than a full snapshot. Falls back to a full
+/// snapshot if there is no base, the base can not be loaded, or the delta
+/// chain of the base is already [MAX_DOC_NODES_DELTA_DEPTH] long.
+pub async fn serialize_doc_nodes_for_storage(
+  scope: &ScopeName,
+  package: &PackageName,
+  base: Option<&Version>,
+  doc_nodes: &ParseOutput,
+  bucket: &crate::s3::Buckets,
+) -> Bytes {
+  let Some(base) = base else {
+    return serialize_doc_nodes(doc_nodes);
   };
-  let msgpack = rmp_serde::to_vec_named(&stored).unwrap();
-  let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-  encoder.write_all(&msgpack).unwrap();
-  encoder.finish().unwrap().into()
+
+  let (base_doc_nodes, base_depth) = match load_doc_nodes(
+    scope, package, base, bucket,
+  )
+  .await
+  {
+    Ok(Some(loaded)) => loaded,
+    Ok(None) => return serialize_doc_nodes(doc_nodes),
+    Err(err) => {
+      tracing::warn!(
+        "failed to load doc nodes of {base} as delta base, storing a full snapshot: {err}"
+      );
+      return serialize_doc_nodes(doc_nodes);
+    }
+  };
+  if base_depth >= MAX_DOC_NODES_DELTA_DEPTH {
+    return serialize_doc_nodes(doc_nodes);
+  }
+
+  let changed = doc_nodes
+    .iter()
+    .filter(|(specifier, document)| {
+      base_doc_nodes.get(*specifier).is_none_or(|base_document| {
+        rmp_serde::to_vec_named(base_document).unwrap()
+          != rmp_serde::to_vec_named(document).unwrap()
+      })
+    })
+    .map(|(specifier, document)| (specifier.clone(), document.clone()))
+    .collect::<ParseOutput>();
+  if changed.len() == doc_nodes.len() {
+    return serialize_doc_nodes(doc_nodes);
+  }
+
+  gzip_msgpack(&StoredDocNodesDelta {
+    version: DOC_NODES_DELTA_VERSION,
+    schema_version: DOC_NODES_SCHEMA_VERSION,
+    base: base.clone(),
+    depth: base_depth + 1,
+    specifiers: doc_nodes.keys().cloned().collect(),
+    changed,
+  })
 }
 
-/// Deserialize doc nodes from gzip-compressed MessagePack (v2 format).
-fn deserialize_doc_nodes_v2(
-  bytes: &[u8],
+/// Convert documents of an older schema version to the current one.
+fn migrate_doc_nodes_schema(
+  schema_version: u32,
+  doc_nodes: UnconvertedDocNodes,
 ) -> Result<ParseOutput, DocNodeCacheError> {
+  doc_nodes
+    .into_iter()
+    .map(|(specifier, mut document)| {
+      for migrate in &DOC_NODES_SCHEMA_MIGRATIONS[schema_version as usize - 1..]
+      {
+        migrate(&mut document);
+      }
+      let document = serde_json::from_value(document).map_err(|e| {
+        DocNodeCacheError::SchemaMigration(schema_version, e.to_string())
+      })?;
+      Ok((specifier, document))
+    })
+    .collect()
+}
+
+/// Deserialize doc nodes from gzip-compressed MessagePack (v2 full snapshots
+/// and v3 deltas), converting them to the current schema version if they were
+/// written with an older one.
+fn deserialize_stored_doc_nodes(
+  bytes: &[u8],
+) -> Result<StoredDocNodesKind, DocNodeCacheError> {
   let mut decoder = GzDecoder::new(bytes);
   let mut decompressed = Vec::new();
   decoder
     .read_to_end(&mut decompressed)
     .map_err(DocNodeCacheError::Decompress)?;
-  let stored: StoredDocNodes = rmp_serde::from_slice(&decompressed)
+  let header: StoredDocNodesHeader = rmp_serde::from_slice(&decompressed)
     .map_err(|e| DocNodeCacheError::Deserialize(e.to_string()))?;
-  if stored.version != DOC_NODES_VERSION {
-    return Err(DocNodeCacheError::UnsupportedVersion(stored.version));
+  let schema_version = header.schema_version;
+  if schema_version == 0 || schema_version > DOC_NODES_SCHEMA_VERSION {
+    return Err(DocNodeCacheError::UnsupportedSchemaVersion(schema_version));
+  }
+  let is_current_schema = schema_version == DOC_NODES_SCHEMA_VERSION;
+
+  match header.version {
+    DOC_NODES_VERSION if is_current_schema => {
+      let stored: StoredDocNodes = rmp_serde::from_slice(&decompressed)
+        .map_err(|e| DocNodeCacheError::Deserialize(e.to_string()))?;
+      Ok(StoredDocNodesKind::Full(stored.doc_nodes))
+    }
+    DOC_NODES_VERSION => {
+      let stored: StoredDocNodes<UnconvertedDocNodes> =
+        rmp_serde::from_slice(&decompressed)
+          .map_err(|e| DocNodeCacheError::Deserialize(e.to_string()))?;
+      Ok(StoredDocNodesKind::Full(migrate_doc_nodes_schema(
+        schema_version,
+        stored.doc_nodes,
+      )?))
+    }
+    DOC_NODES_DELTA_VERSION if is_current_schema => {
+      let stored: StoredDocNodesDelta = rmp_serde::from_slice(&decompressed)
+        .map_err(|e| DocNodeCacheError::Deserialize(e.to_string()))?;
+      Ok(StoredDocNodesKind::Delta(stored))
+    }
+    DOC_NODES_DELTA_VERSION => {
+      let stored: StoredDocNodesDelta<UnconvertedDocNodes> =
+        rmp_serde::from_slice(&decompressed)
+          .map_err(|e| DocNodeCacheError::Deserialize(e.to_string()))?;
+      Ok(StoredDocNodesKind::Delta(StoredDocNodesDelta {
+        version: stored.version,
+        schema_version: DOC_NODES_SCHEMA_VERSION,
+        base: stored.base,
+        depth: stored.depth,
+        specifiers: stored.specifiers,
+        changed: migrate_doc_nodes_schema(schema_version, stored.changed)?,
+      }))
+    }
+    version => Err(DocNodeCacheError::UnsupportedVersion(version)),
   }
-  Ok(stored.doc_nodes)
 }
 
 /// Deserialize doc nodes from legacy JSON (v1 format), migrating to v2.
@@ -137,15 +341,15 @@ fn deserialize_doc_nodes_v1(
   }
 }
 
-/// Download doc nodes from GCS for a package version, trying v2
-/// (msgpack+gzip) first and falling back to v1 (JSON) with migration.
-/// Used by the diff endpoint which needs raw ParseOutput.
-pub async fn download_doc_nodes(
+/// Download the stored doc nodes of a single version without resolving
+/// deltas, trying v2/v3 (msgpack+gzip) first and falling back to v1 (JSON)
+/// with migration.
+async fn download_stored_doc_nodes(
   scope: &ScopeName,
   package: &PackageName,
   version: &Version,
   bucket: &crate::s3::Buckets,
-) -> Result<Option<ParseOutput>, DocNodeCacheError> {
+) -> Result<Option<StoredDocNodesKind>, DocNodeCacheError> {
   let v2_path = crate::s3_paths::docs_v2_path(scope, package, version);
   let v2_result = bucket
     .docs_bucket
@@ -153,7 +357,7 @@ pub async fn download_doc_nodes(
     .await?;
 
   if let Some(bytes) = v2_result {
-    return Ok(Some(deserialize_doc_nodes_v2(&bytes)?));
+    return Ok(Some(deserialize_stored_doc_nodes(&bytes)?));
   }
 
   let v1_path = crate::s3_paths::docs_v1_path(scope, package, version);
@@ -171,19 +375,7 @@ pub async fn download_doc_nodes(
   // Best-effort migration: re-upload as v2 and delete v1. Failures are
   // logged but not propagated — the doc nodes were already read successfully.
   let v2_bytes = serialize_doc_nodes(&doc_nodes);
-  match bucket
-    .docs_bucket
-    .upload(
-      Arc::from(v2_path.as_str()),
-      crate::s3::UploadTaskBody::Bytes(v2_bytes),
-      crate::s3::S3UploadOptions {
-        content_type: Some("application/x-msgpack".into()),
-        cache_control: Some(crate::s3::CACHE_CONTROL_IMMUTABLE.into()),
-        gzip_encoded: true,
-      },
-    )
-    .await
-  {
+  match upload_doc_nodes(&v2_path, v2_bytes, bucket).await {
     Ok(()) => {
       if let Err(err) = bucket
         .docs_bucket
@@ -198,7 +390,140 @@ pub async fn download_doc_nodes(
     }
   }
 
-  Ok(Some(doc_nodes))
+  Ok(Some(StoredDocNodesKind::Full(doc_nodes)))
+}
+
+async fn upload_doc_nodes(
+  path: &str,
+  bytes: Bytes,
+  bucket: &crate::s3::Buckets,
+) -> Result<(), crate::s3::S3Error> {
+  bucket
+    .docs_bucket
+    .upload(
+      Arc::from(path),
+      crate::s3::UploadTaskBody::Bytes(bytes),
+      crate::s3::S3UploadOptions {
+        content_type: Some("application/x-msgpack".into()),
+        cache_control: Some(crate::s3::CACHE_CONTROL_IMMUTABLE.into()),
+        content_encoding: crate::s3::ContentEncoding::Gzip,
+      },
+    )
+    .await
+}
+
+/// Download the doc nodes of a version, reconstructing them from the nearest
+/// full snapshot if they are stored as a delta. Also returns the depth of the
+/// delta chain, `0` for a full snapshot.
+async fn load_doc_nodes(
+  scope: &ScopeName,
+  package: &PackageName,
+  version: &Version,
+  bucket: &crate::s3::Buckets,
+) -> Result<Option<(ParseOutput, u32)>, DocNodeCacheError> {
+  let mut deltas = vec![];
+  let mut doc_nodes = loop {
+    let current = deltas
+      .last()
+      .map(|delta: &StoredDocNodesDelta| &delta.base)
+      .unwrap_or(version);
+    match download_stored_doc_nodes(scope, package, current, bucket).await? {
+      Some(StoredDocNodesKind::Full(doc_nodes)) => break doc_nodes,
+      Some(StoredDocNodesKind::Delta(delta)) => {
+        if deltas.len() as u32 >= MAX_DOC_NODES_DELTA_DEPTH {
+          return Err(DocNodeCacheError::DeltaChainTooLong);
+        }
+        deltas.push(delta);
+      }
+      None if deltas.is_empty() => return Ok(None),
+      None => {
+        return Err(DocNodeCacheError::MissingDeltaBase(current.clone()));
+      }
+    }
+  };
+
+  let depth = deltas.len() as u32;
+  for delta in deltas.into_iter().rev() {
+    let StoredDocNodesDelta {
+      specifiers,
+      mut changed,
+      ..
+    } = delta;
+    let mut next = ParseOutput::with_capacity(specifiers.len());
+    for specifier in specifiers {
+      let document = match changed.swap_remove(&specifier) {
+        Some(document) => document,
+        None => doc_nodes.swap_remove(&specifier).ok_or_else(|| {
+          DocNodeCacheError::MissingDeltaDocument(specifier.clone())
+        })?,
+      };
+      next.insert(specifier, document);
+    }
+    doc_nodes = next;
+  }
+
+  Ok(Some((doc_nodes, depth)))
+}
+
+/// Download doc nodes from GCS for a package version, reconstructing them from
+/// deltas if needed.
+pub async fn download_doc_nodes(
+  scope: &ScopeName,
+  package: &PackageName,
+  version: &Version,
+  bucket: &crate::s3::Buckets,
+) -> Result<Option<ParseOutput>, DocNodeCacheError> {
+  Ok(
+    load_doc_nodes(scope, package, version, bucket)
+      .await?
+      .map(|(doc_nodes, _)| doc_nodes),
+  )
+}
+
+/// Rewrite the doc nodes of every version in `versions` that are stored as a
+/// delta against `deleted` as a full snapshot, so they stay readable once the
+/// doc nodes of `deleted` are removed. The digests of the rewritten doc nodes
+/// are recorded.
+pub async fn detach_doc_nodes_deltas(
+  db: &crate::db::Database,
+  scope: &ScopeName,
+  package: &PackageName,
+  deleted: &Version,
+  versions: &[Version],
+  bucket: &crate::s3::Buckets,
+) -> Result<(), DocNodeCacheError> {
+  for version in versions {
+    let Some(StoredDocNodesKind::Delta(delta)) =
+      download_stored_doc_nodes(scope, package, version, bucket).await?
+    else {
+      continue;
+    };
+    if delta.base != *deleted {
+      continue;
+    }
+    let Some(doc_nodes) =
+      download_doc_nodes(scope, package, version, bucket).await?
+    else {
+      continue;
+    };
+    let path = crate::s3_paths::docs_v2_path(scope, package, version);
+    let bytes = serialize_doc_nodes(&doc_nodes);
+    let checksum = crate::integrity::sha256_digest(&bytes);
+    let size = bytes.len() as i64;
+    upload_doc_nodes(&path, bytes, bucket).await?;
+    db.upsert_package_version_doc_artifact(
+      crate::db::NewPackageVersionDocArtifact {
+        scope,
+        name: package,
+        version,
+        kind: crate::db::DocArtifactKind::DocNodes,
+        checksum: &checksum,
+        size,
+      },
+    )
+    .await?;
+  }
+  Ok(())
 }
 
 /// Cache for fully-built GenerateCtx. Keyed by
@@ -277,65 +602,70 @@ thread_local! {
 }
 
 lazy_static::lazy_static! {
-  static ref AMMONIA: ammonia::Builder<'static> = {
-    let mut ammonia_builder = ammonia::Builder::default();
-
-    ammonia_builder
-      .add_tags(["video", "button", "svg", "path", "rect"])
-      .add_generic_attributes(["id", "align"])
-      .add_tag_attributes("button", ["data-copy"])
-      .add_tag_attributes(
-        "svg",
-        [
-          "class",
-          "width",
-          "height",
-          "viewBox",
-          "fill",
-          "xmlns",
-          "stroke",
-          "stroke-width",
-          "stroke-linecap",
-          "stroke-linejoin",
-        ],
-      )
-      .add_tag_attributes(
-        "path",
-        [
-          "d",
-          "fill",
-          "fill-rule",
-          "clip-rule",
-          "stroke",
-          "stroke-width",
-          "stroke-linecap",
-          "stroke-linejoin",
-        ],
-      )
-      .add_tag_attributes("rect", ["x", "y", "width", "height", "fill"])
-      .add_tag_attributes("video", ["src", "controls"])
-      .add_allowed_classes("pre", ["highlight"])
-      .add_allowed_classes("button", ["copyButton"])
-      .add_allowed_classes(
-        "div",
-        [
-          "alert",
-          "alert-note",
-          "alert-tip",
-          "alert-important",
-          "alert-warning",
-          "alert-caution",
-          "or-bar"
-        ],
-      )
-      .link_rel(Some("nofollow"))
-      .url_relative(ammonia::UrlRelative::Custom(Box::new(
-        AmmoniaRelativeUrlEvaluator(),
-      )))
-      .add_allowed_classes("span", crate::tree_sitter::CLASSES);
+  static ref AMMONIA: ammonia::Builder<'static> = ammonia_builder();
+}
 
-    ammonia_builder
-  };
+fn ammonia_builder<'a>() -> ammonia::Builder<'a> {
+  let mut ammonia_builder = ammonia::Builder::default();
+
+  ammonia_builder
+    .add_tags(["video", "button", "svg", "path", "rect"])
+    .add_generic_attributes(["id", "align"])
+    .add_tag_attributes("button", ["data-copy"])
+    .add_tag_attributes(
+      "svg",
+      [
+        "class",
+        "width",
+        "height",
+        "viewBox",
+        "fill",
+        "xmlns",
+        "stroke",
+        "stroke-width",
+        "stroke-linecap",
+        "stroke-linejoin",
+      ],
+    )
+    .add_tag_attributes(
+      "path",
+      [
+        "d",
+        "fill",
+        "fill-rule",
+        "clip-rule",
+        "stroke",
+        "stroke-width",
+        "stroke-linecap",
+        "stroke-linejoin",
+      ],
+    )
+    .add_tag_attributes("rect", ["x", "y", "width", "height", "fill"])
+    .add_tag_attributes("video", ["src", "controls"])
+    .add_allowed_classes("pre", ["highlight", "mermaid"])
+    .add_allowed_classes("button", ["copyButton"])
+    .add_allowed_classes(
+      "div",
+      [
+        "alert",
+        "alert-note",
+        "alert-tip",
+        "alert-important",
+        "alert-warning",
+        "alert-caution",
+        "or-bar",
+        "math",
+        "math-display",
+      ],
+    )
+    .link_rel(Some("nofollow"))
+    .url_relative(ammonia::UrlRelative::Custom(Box::new(
+      AmmoniaRelativeUrlEvaluator(),
+    )))
+    .add_allowed_classes("span", crate::tree_sitter::CLASSES)
+    .add_allowed_classes("span", ["math", "math-inline"]);
+
+  ammonia_builder
 }
 
 struct AmmoniaRelativeUrlEvaluator();
@@ -355,119 +685,216 @@ impl ammonia::UrlRelativeEvaluate<'_> for AmmoniaRelativeUrlEvaluator {
   }
 }
 
-enum Alert {
+enum Alert<'a> {
   Note,
   Tip,
   Important,
   Warning,
   Caution,
+  /// An alert kind allowed by the markdown settings of a scope.
+  Custom(&'a str),
 }
 
-fn match_node_value<'a>(
+/// Renders a block quote starting with `[!NOTE]` and the like as a GitHub
+/// style alert. `admonitions` are additional lowercase alert kinds to render.
+fn render_alert<'a>(
   arena: &'a comrak::Arena<AstNode<'a>>,
   node: &'a AstNode<'a>,
   options: &comrak::Options,
   plugins: &comrak::Plugins,
+  admonitions: &[String],
 ) {
-  match &node.data.borrow().value {
-    NodeValue::BlockQuote => {
-      if let Some(paragraph_child) = node.first_child()
-        && paragraph_child.data.borrow().value == NodeValue::Paragraph
+  let Some(paragraph_child) = node.first_child() else {
+    return;
+  };
+  if paragraph_child.data.borrow().value != NodeValue::Paragraph {
+    return;
+  }
+
+  let alert = paragraph_child.first_child().and_then(|text_child| {
+    if let NodeValue::Text(text) = &text_child.data.borrow().value {
+      match text
+        .split_once(' ')
+        .map_or((text.as_str(), None), |(kind, title)| (kind, Some(title)))
       {
-        let alert = paragraph_child.first_child().and_then(|text_child| {
-          if let NodeValue::Text(text) = &text_child.data.borrow().value {
-            match text
-              .split_once(' ')
-              .map_or((text.as_str(), None), |(kind, title)| {
-                (kind, Some(title))
-              }) {
-              ("[!NOTE]", title) => {
-                Some((Alert::Note, title.unwrap_or("Note").to_string()))
-              }
-              ("[!TIP]", title) => {
-                Some((Alert::Tip, title.unwrap_or("Tip").to_string()))
-              }
-              ("[!IMPORTANT]", title) => Some((
-                Alert::Important,
-                title.unwrap_or("Important").to_string(),
-              )),
-              ("[!WARNING]", title) => {
-                Some((Alert::Warning, title.unwrap_or("Warning").to_string()))
-              }
-              ("[!CAUTION]", title) => {
-                Some((Alert::Caution, title.unwrap_or("Caution").to_string()))
-              }
-              _ => None,
-            }
-          } else {
-            None
-          }
-        });
+        ("[!NOTE]", title) => {
+          Some((Alert::Note, title.unwrap_or("Note").to_string()))
+        }
+        ("[!TIP]", title) => {
+          Some((Alert::Tip, title.unwrap_or("Tip").to_string()))
+        }
+        ("[!IMPORTANT]", title) => {
+          Some((Alert::Important, title.unwrap_or("Important").to_string()))
+        }
+        ("[!WARNING]", title) => {
+          Some((Alert::Warning, title.unwrap_or("Warning").to_string()))
+        }
+        ("[!CAUTION]", title) => {
+          Some((Alert::Caution, title.unwrap_or("Caution").to_string()))
+        }
+        (kind, title) => {
+          let kind = kind.strip_prefix("[!")?.strip_suffix(']')?;
+          let admonition = admonitions
+            .iter()
+            .find(|admonition| admonition.eq_ignore_ascii_case(kind))?;
+          let title = title.map(str::to_string).unwrap_or_else(|| {
+            let mut chars = admonition.chars();
+            chars
+              .next()
+              .map(|first| first.to_uppercase().chain(chars).collect())
+              .unwrap_or_default()
+          });
+          Some((Alert::Custom(admonition.as_str()), title))
+        }
+      }
+    } else {
+      None
+    }
+  });
 
-        if let Some((alert, title)) = alert {
-          let start_col = node.data.borrow().sourcepos.start;
+  let Some((alert, title)) = alert else {
+    return;
+  };
 
-          let document = arena.alloc(AstNode::new(RefCell::new(Ast::new(
-            NodeValue::Document,
-            start_col,
-          ))));
+  let start_col = node.data.borrow().sourcepos.start;
 
-          let node_without_alert = arena.alloc(AstNode::new(RefCell::new(
-            Ast::new(NodeValue::Paragraph, start_col),
-          )));
+  let document = arena.alloc(AstNode::new(RefCell::new(Ast::new(
+    NodeValue::Document,
+    start_col,
+  ))));
 
-          for child_node in paragraph_child.children().skip(1) {
-            node_without_alert.append(child_node);
-          }
-          for child_node in node.children().skip(1) {
-            node_without_alert.append(child_node);
-          }
+  let node_without_alert = arena.alloc(AstNode::new(RefCell::new(Ast::new(
+    NodeValue::Paragraph,
+    start_col,
+  ))));
 
-          document.append(node_without_alert);
+  for child_node in paragraph_child.children().skip(1) {
+    node_without_alert.append(child_node);
+  }
+  for child_node in node.children().skip(1) {
+    node_without_alert.append(child_node);
+  }
 
-          let html =
-            deno_doc::html::comrak::render_node(document, options, plugins);
+  document.append(node_without_alert);
 
-          let alert_title = match alert {
-            Alert::Note => {
-              format!("{}{title}", include_str!("./docs/info-circle.svg"))
-            }
-            Alert::Tip => {
-              format!("{}{title}", include_str!("./docs/bulb.svg"))
-            }
-            Alert::Important => {
-              format!("{}{title}", include_str!("./docs/warning-message.svg"))
-            }
-            Alert::Warning => {
-              format!("{}{title}", include_str!("./docs/warning-triangle.svg"))
-            }
-            Alert::Caution => {
-              format!("{}{title}", include_str!("./docs/warning-octagon.svg"))
-            }
-          };
-
-          let html = format!(
-            r#"<div class="alert alert-{}"><div>{alert_title}</div><div>{html}</div></div>"#,
-            match alert {
-              Alert::Note => "note",
-              Alert::Tip => "tip",
-              Alert::Important => "important",
-              Alert::Warning => "warning",
-              Alert::Caution => "caution",
-            }
-          );
+  let html = deno_doc::html::comrak::render_node(document, options, plugins);
 
-          let alert_node = arena.alloc(AstNode::new(RefCell::new(Ast::new(
-            NodeValue::HtmlBlock(comrak::nodes::NodeHtmlBlock {
-              block_type: 6,
-              literal: html,
-            }),
-            start_col,
-          ))));
-          node.insert_before(alert_node);
-          node.detach();
-        }
+  let alert_title = match alert {
+    Alert::Note => {
+      format!("{}{title}", include_str!("./docs/info-circle.svg"))
+    }
+    Alert::Tip => {
+      format!("{}{title}", include_str!("./docs/bulb.svg"))
+    }
+    Alert::Important => {
+      format!("{}{title}", include_str!("./docs/warning-message.svg"))
+    }
+    Alert::Warning => {
+      format!("{}{title}", include_str!("./docs/warning-triangle.svg"))
+    }
+    Alert::Caution => {
+      format!("{}{title}", include_str!("./docs/warning-octagon.svg"))
+    }
+    Alert::Custom(_) => {
+      format!(
+        "{}{}",
+        include_str!("./docs/info-circle.svg"),
+        escape_html(&title)
+      )
+    }
+  };
+
+  let html = format!(
+    r#"<div class="alert alert-{}"><div>{alert_title}</div><div>{html}</div></div>"#,
+    match alert {
+      Alert::Note => "note",
+      Alert::Tip => "tip",
+      Alert::Important => "important",
+      Alert::Warning => "warning",
+      Alert::Caution => "caution",
+      Alert::Custom(kind) => kind,
+    }
+  );
+
+  let alert_node = arena.alloc(AstNode::new(RefCell::new(Ast::new(
+    NodeValue::HtmlBlock(comrak::nodes::NodeHtmlBlock {
+      block_type: 6,
+      literal: html,
+    }),
+    start_col,
+  ))));
+  node.insert_before(alert_node);
+  node.detach();
+}
+
+fn match_node_value<'a>(
+  arena: &'a comrak::Arena<AstNode<'a>>,
+  node: &'a AstNode<'a>,
+  options: &comrak::Options,
+  plugins: &comrak::Plugins,
+) {
+  match &node.data.borrow().value {
+    NodeValue::BlockQuote => {
+      render_alert(arena, node, options, plugins, &[]);
+    }
+    // Mermaid diagrams and math are rendered client-side, so the source is
+    // only escaped here and wrapped in an element the frontend picks up.
+    NodeValue::CodeBlock(block) if block.fenced => {
+      let html = match block.info.split_whitespace().next() {
+        Some("mermaid") => format!(
+          r#"<pre class="mermaid">{}</pre>"#,
+          escape_html(&block.literal)
+        ),
+        Some("math") => format!(
+          r#"<div class="math math-display">{}</div>"#,
+          escape_html(block.literal.trim_end())
+        ),
+        _ => return,
+      };
+
+      let start_col = node.data.borrow().sourcepos.start;
+      let html_node = arena.alloc(AstNode::new(RefCell::new(Ast::new(
+        NodeValue::HtmlBlock(comrak::nodes::NodeHtmlBlock {
+          block_type: 6,
+          literal: html,
+        }),
+        start_col,
+      ))));
+      node.insert_before(html_node);
+      node.detach();
+    }
+    // Inline math uses the $`...`$ syntax, so that it stays readable as code
+    // wherever the markdown is not rendered.
+    NodeValue::Code(code) => {
+      let (Some(prev), Some(next)) =
+        (node.previous_sibling(), node.next_sibling())
+      else {
+        return;
+      };
+      let is_math = matches!(&prev.data.borrow().value, NodeValue::Text(text) if text.ends_with('$'))
+        && matches!(&next.data.borrow().value, NodeValue::Text(text) if text.starts_with('$'));
+      if !is_math {
+        return;
+      }
+
+      if let NodeValue::Text(text) = &mut prev.data.borrow_mut().value {
+        text.pop();
+      }
+      if let NodeValue::Text(text) = &mut next.data.borrow_mut().value {
+        text.remove(0);
       }
+
+      let html = format!(
+        r#"<span class="math math-inline">{}</span>"#,
+        escape_html(&code.literal)
+      );
+      let start_col = node.data.borrow().sourcepos.start;
+      let html_node = arena.alloc(AstNode::new(RefCell::new(Ast::new(
+        NodeValue::HtmlInline(html),
+        start_col,
+      ))));
+      node.insert_before(html_node);
+      node.detach();
     }
     NodeValue::Link(link) => {
       if link.url.ends_with(".mov") || link.url.ends_with(".mp4") {
@@ -490,6 +917,86 @@ fn match_node_value<'a>(
   }
 }
 
+fn escape_html(text: &str) -> String {
+  let mut out = Vec::with_capacity(text.len());
+  comrak::html::escape(&mut out, text.as_bytes()).unwrap();
+  String::from_utf8(out).unwrap()
+}
+
+/// Markdown extensions allowed on top of the strict defaults of
+/// [render_markdown], as configured in the markdown settings of a scope.
+#[derive(Debug, Clone, Default)]
+pub struct MarkdownRenderOptions {
+  /// Keep raw HTML, cleaned to the HTML subset of package docs, instead of
+  /// escaping it.
+  pub allow_html: bool,
+  /// Additional lowercase alert kinds, e.g. `danger` for `> [!DANGER]`.
+  pub admonitions: Vec<String>,
+}
+
+/// Renders a standalone markdown document, such as a readme that is not
+/// published yet, to sanitized HTML. Raw HTML is escaped unless allowed.
+pub fn render_markdown(
+  markdown: &str,
+  render_options: &MarkdownRenderOptions,
+) -> String {
+  let mut options = deno_doc::html::comrak::default_options();
+  options.extension.header_ids = Some(String::new());
+  // Raw HTML from the markdown is escaped below, and the output is cleaned.
+  options.render.unsafe_ = true;
+
+  let highlight_adapter = deno_doc::html::comrak::ComrakHighlightWrapperAdapter(
+    Some(Arc::new(super::tree_sitter::ComrakAdapter {
+      show_line_numbers: false,
+    })),
+  );
+  let mut plugins = comrak::Plugins::default();
+  plugins.render.codefence_syntax_highlighter = Some(&highlight_adapter);
+
+  let arena = comrak::Arena::new();
+  let root = comrak::parse_document(&arena, markdown, &options);
+  let nodes = root.descendants().collect::<Vec<_>>();
+
+  if !render_options.allow_html {
+    for node in &nodes {
+      let mut ast = node.data.borrow_mut();
+      let literal = match &ast.value {
+        NodeValue::HtmlBlock(block) => block.literal.clone(),
+        NodeValue::HtmlInline(html) => html.clone(),
+        _ => continue,
+      };
+      ast.value = NodeValue::Text(literal);
+    }
+  }
+
+  for node in nodes {
+    if matches!(node.data.borrow().value, NodeValue::BlockQuote) {
+      render_alert(
+        &arena,
+        node,
+        &options,
+        &plugins,
+        &render_options.admonitions,
+      );
+    } else {
+      match_node_value(&arena, node, &options, &plugins);
+    }
+  }
+
+  let html = deno_doc::html::comrak::render_node(root, &options, &plugins);
+
+  let admonition_classes = render_options
+    .admonitions
+    .iter()
+    .map(|admonition| format!("alert-{admonition}"))
+    .collect::<Vec<_>>();
+  let mut ammonia_builder = ammonia_builder();
+  ammonia_builder
+    .url_relative(ammonia::UrlRelative::PassThrough)
+    .add_allowed_classes("div", admonition_classes.iter().map(String::as_str));
+  ammonia_builder.clean(&html).to_string()
+}
+
 static DENO_TYPES: OnceLock<Arc<std::collections::HashSet<Vec<String>>>> =
   OnceLock::new();
 static WEB_TYPES: OnceLock<
@@ -503,13 +1010,30 @@ struct WebType {
 }
 
 #[instrument(name = "generate_docs", skip(source_files, graph, analyzer), err)]
+/// How long generating the docs of a single entrypoint may take at publish
+/// time. Entrypoints that take longer are left out of the docs instead of
+/// failing the publish.
+pub const DOC_GENERATION_ENTRYPOINT_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub struct GeneratedDocNodes {
+  pub doc_nodes: ParseOutput,
+  /// Entrypoints left out of `doc_nodes` because generating their docs timed
+  /// out.
+  pub degraded_entrypoints: Vec<ModuleSpecifier>,
+}
+
 pub fn generate_docs(
   mut source_files: Vec<ModuleSpecifier>,
   graph: &deno_graph::ModuleGraph,
   analyzer: &deno_graph::ast::CapturingModuleAnalyzer,
-) -> Result<ParseOutput, anyhow::Error> {
+  entrypoint_timeout: Duration,
+) -> Result<GeneratedDocNodes, anyhow::Error> {
   source_files.sort();
 
+  let degraded_entrypoints =
+    find_slow_entrypoints(&source_files, graph, entrypoint_timeout);
+  source_files.retain(|specifier| !degraded_entrypoints.contains(specifier));
+
   let parser = deno_doc::DocParser::new(
     graph,
     analyzer,
@@ -520,9 +1044,65 @@ pub fn generate_docs(
     },
   )?;
 
-  let doc_nodes_by_url = parser.parse()?;
+  let doc_nodes = parser.parse()?;
+
+  Ok(GeneratedDocNodes {
+    doc_nodes,
+    degraded_entrypoints,
+  })
+}
+
+/// Generates the docs of each entrypoint on its own thread, and returns the
+/// entrypoints that did not finish within `timeout`. The docs of all other
+/// entrypoints are then generated together, so that references between
+/// entrypoints resolve as before.
+///
+/// Threads can not be cancelled, so the thread of a slow entrypoint is
+/// detached and left to finish in the background.
+fn find_slow_entrypoints(
+  source_files: &[ModuleSpecifier],
+  graph: &deno_graph::ModuleGraph,
+  timeout: Duration,
+) -> Vec<ModuleSpecifier> {
+  let graph = Arc::new(graph.clone());
+  let parallelism = std::thread::available_parallelism()
+    .map_or(1, |parallelism| parallelism.get());
+
+  let mut slow = vec![];
+  for chunk in source_files.chunks(parallelism) {
+    let deadline = Instant::now() + timeout;
+    let receivers = chunk
+      .iter()
+      .map(|specifier| {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let graph = graph.clone();
+        let specifiers = [specifier.clone()];
+        std::thread::spawn(move || {
+          // Errors are reported by the parse of all entrypoints.
+          let _ = deno_doc::DocParser::new(
+            &graph,
+            &deno_graph::ast::DefaultEsParser,
+            &specifiers,
+            deno_doc::DocParserOptions {
+              diagnostics: false,
+              private: false,
+            },
+          )
+          .and_then(|parser| Ok(parser.parse()?));
+          let _ = tx.send(());
+        });
+        rx
+      })
+      .collect::<Vec<_>>();
 
-  Ok(doc_nodes_by_url)
+    for (specifier, rx) in chunk.iter().zip(receivers) {
+      let remaining = deadline.saturating_duration_since(Instant::now());
+      if let Err(RecvTimeoutError::Timeout) = rx.recv_timeout(remaining) {
+        slow.push(specifier.clone());
+      }
+    }
+  }
+  slow
 }
 
 #[derive(Debug)]
@@ -692,6 +1272,10 @@ pub fn get_generate_ctx(
   registry_url: String,
   diff: Option<(deno_doc::diff::DocDiff, bool)>,
 ) -> GenerateCtx {
+  // The main entrypoint has no doc nodes if generating its docs timed out at
+  // publish time.
+  let main_entrypoint = main_entrypoint
+    .filter(|main_entrypoint| documents_by_url.contains_key(main_entrypoint));
   let package_name = format!("@{scope}/{package}");
   let url_rewriter_base = format!("/{package_name}/{version}");
 
@@ -1379,6 +1963,207 @@ impl HrefResolver for DocResolver {
   }
 }
 
+/// Resolves links for the offline documentation archive. Links between pages
+/// are relative file paths, so that the pages can be browsed from the file
+/// system, and links to anything else on JSR are absolute.
+struct OfflineDocResolver {
+  inner: Arc<dyn HrefResolver>,
+  registry_url: Url,
+}
+
+impl OfflineDocResolver {
+  fn absolute(&self, href: String) -> String {
+    match href.strip_prefix('/') {
+      Some(path) => self.registry_url.join(path).unwrap().to_string(),
+      None => href,
+    }
+  }
+}
+
+impl HrefResolver for OfflineDocResolver {
+  fn resolve_path(
+    &self,
+    current: UrlResolveKind,
+    target: UrlResolveKind,
+  ) -> String {
+    deno_doc::html::util::href_path_resolve(current, target)
+  }
+
+  fn resolve_global_symbol(&self, symbol: &[String]) -> Option<String> {
+    self.inner.resolve_global_symbol(symbol)
+  }
+
+  fn resolve_import_href(
+    &self,
+    symbol: &[String],
+    src: &str,
+  ) -> Option<String> {
+    self
+      .inner
+      .resolve_import_href(symbol, src)
+      .map(|href| self.absolute(href))
+  }
+
+  fn resolve_source(&self, location: &Location) -> Option<String> {
+    self
+      .inner
+      .resolve_source(location)
+      .map(|href| self.absolute(href))
+  }
+
+  fn resolve_external_jsdoc_module(
+    &self,
+    module: &str,
+    symbol: Option<&str>,
+  ) -> Option<(String, String)> {
+    self.inner.resolve_external_jsdoc_module(module, symbol)
+  }
+}
+
+/// Renders every page of the documentation in `ctx` as a static site, and
+/// returns it as a gzipped tarball with all files in the `root` directory.
+#[instrument(name = "generate_docs_archive", skip(ctx, registry_url), err)]
+pub fn generate_docs_archive(
+  mut ctx: GenerateCtx,
+  registry_url: &Url,
+  root: &str,
+) -> Result<Vec<u8>, anyhow::Error> {
+  ctx.href_resolver = Arc::new(OfflineDocResolver {
+    inner: ctx.href_resolver.clone(),
+    registry_url: registry_url.clone(),
+  });
+
+  let mut files = deno_doc::html::generate(ctx)?
+    .into_iter()
+    .collect::<Vec<_>>();
+  files.sort_by(|a, b| a.0.cmp(&b.0));
+
+  let mut tar_gz_bytes = Vec::new();
+  let mut gz_encoder =
+    GzEncoder::new(&mut tar_gz_bytes, Compression::default());
+  let mut tarball = tar::Builder::new(&mut gz_encoder);
+  for (path, content) in files {
+    // GNU headers, as symbol pages can have paths longer than ustar allows.
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    tarball.append_data(
+      &mut header,
+      format!("{root}/{path}"),
+      content.as_bytes(),
+    )?;
+  }
+  tarball.into_inner()?;
+  gz_encoder.finish()?;
+
+  Ok(tar_gz_bytes)
+}
+
+/// Bounds on the doc search index of a package version, so that very large
+/// packages produce a truncated index instead of exhausting memory.
+#[derive(Debug, Clone, Copy)]
+pub struct SearchIndexLimits {
+  /// Symbols of one entrypoint beyond this many are left out of the index.
+  pub max_symbols_per_entrypoint: usize,
+  /// Symbols beyond this many across all entrypoints are left out of the
+  /// index.
+  pub max_symbols: usize,
+  /// The doc summary of a symbol is cut off after this many characters.
+  pub max_doc_length: usize,
+}
+
+impl Default for SearchIndexLimits {
+  fn default() -> Self {
+    Self {
+      max_symbols_per_entrypoint: 5_000,
+      max_symbols: 20_000,
+      max_doc_length: 500,
+    }
+  }
+}
+
+static SEARCH_INDEX_LIMITS: OnceLock<SearchIndexLimits> = OnceLock::new();
+
+/// Sets the limits used by [generate_search_index]. Only the first call has
+/// an effect; without one, the defaults are used.
+pub fn set_search_index_limits(limits: SearchIndexLimits) {
+  let _ = SEARCH_INDEX_LIMITS.set(limits);
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchIndex {
+  pub kind: &'static str,
+  pub nodes: Vec<deno_doc::html::search::SearchIndexNode>,
+  /// Whether symbols were left out, or their docs cut off, to stay within the
+  /// [SearchIndexLimits].
+  pub truncated: bool,
+}
+
+/// Builds the doc search index one entrypoint at a time, so the limits apply
+/// to each entrypoint separately as well as to the whole package. With
+/// `entrypoint`, only the shard of the entrypoint whose short path matches is
+/// built.
+#[instrument(name = "generate_search_index", skip(ctx), fields(truncated))]
+pub fn generate_search_index(
+  ctx: &GenerateCtx,
+  entrypoint: Option<&str>,
+) -> SearchIndex {
+  let limits = SEARCH_INDEX_LIMITS.get().copied().unwrap_or_default();
+  let render_ctx = RenderContext::new(ctx, &[], UrlResolveKind::AllSymbols);
+
+  let mut nodes = vec![];
+  let mut truncated = false;
+  for (short_path, doc_nodes) in &ctx.doc_nodes {
+    if entrypoint.is_some_and(|entrypoint| entrypoint != short_path.path) {
+      continue;
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    let mut shard = vec![];
+    let flattened = deno_doc::html::partition::flatten_namespace(
+      ctx,
+      Box::new(doc_nodes.iter().map(Cow::Borrowed)),
+    );
+    'nodes: for node in &flattened {
+      for mut search_node in
+        deno_doc::html::search::doc_nodes_into_search_index_node(
+          &render_ctx,
+          node,
+          None,
+        )
+      {
+        if !seen.insert((search_node.name.clone(), search_node.file.clone())) {
+          continue;
+        }
+        if shard.len() >= limits.max_symbols_per_entrypoint
+          || nodes.len() + shard.len() >= limits.max_symbols
+        {
+          truncated = true;
+          break 'nodes;
+        }
+        if let Some((end, _)) =
+          search_node.doc.char_indices().nth(limits.max_doc_length)
+        {
+          search_node.doc = format!("{}…", &search_node.doc[..end]).into();
+          truncated = true;
+        }
+        shard.push(search_node);
+      }
+    }
+    nodes.extend(shard);
+  }
+  nodes.sort_by(|a, b| a.file.cmp(&b.file));
+
+  tracing::Span::current().record("truncated", truncated);
+  SearchIndex {
+    kind: "search",
+    nodes,
+    truncated,
+  }
+}
+
 struct DocUsageComposer {
   runtime_compat: RuntimeCompat,
   scope: ScopeName,
@@ -1662,6 +2447,88 @@ mod tests {
     }
   }
 
+  #[test]
+  fn test_doc_nodes_schema_version() {
+    #[derive(Serialize)]
+    struct LegacyStoredDocNodes {
+      version: u32,
+      doc_nodes: ParseOutput,
+    }
+
+    let specifier = ModuleSpecifier::parse("file:///mod.ts").unwrap();
+    let doc_nodes =
+      ParseOutput::from([(specifier.clone(), deno_doc::Document::default())]);
+
+    let legacy = gzip_msgpack(&LegacyStoredDocNodes {
+      version: DOC_NODES_VERSION,
+      doc_nodes: doc_nodes.clone(),
+    });
+    let Ok(StoredDocNodesKind::Full(stored)) =
+      deserialize_stored_doc_nodes(&legacy)
+    else {
+      panic!("failed to deserialize legacy doc nodes");
+    };
+    assert!(stored.contains_key(&specifier));
+
+    let current = serialize_doc_nodes(&doc_nodes);
+    assert!(matches!(
+      deserialize_stored_doc_nodes(&current),
+      Ok(StoredDocNodesKind::Full(_))
+    ));
+
+    let future = gzip_msgpack(&StoredDocNodes {
+      version: DOC_NODES_VERSION,
+      schema_version: DOC_NODES_SCHEMA_VERSION + 1,
+      doc_nodes,
+    });
+    assert!(matches!(
+      deserialize_stored_doc_nodes(&future),
+      Err(DocNodeCacheError::UnsupportedSchemaVersion(_))
+    ));
+
+    let schema: serde_json::Value =
+      serde_json::from_str(DOC_NODES_SCHEMA).unwrap();
+    assert_eq!(
+      schema["$id"],
+      format!(
+        "https://jsr.io/schemas/doc-nodes.v{DOC_NODES_SCHEMA_VERSION}.json"
+      )
+    );
+  }
+
+  #[test]
+  fn test_mermaid_and_math() {
+    let markdown = "```mermaid\ngraph TD;\n  A-->B<script>;\n```\n\n\
+      Euler: $`e^{i\\pi} < 0`$ and `code`.\n\n\
+      ```math\n\\sum_{n=1}^\\infty n\n```\n";
+
+    let arena = comrak::Arena::new();
+    let options = comrak::Options::default();
+    let plugins = comrak::Plugins::default();
+    let root = comrak::parse_document(&arena, markdown, &options);
+    for node in root.descendants().collect::<Vec<_>>() {
+      match_node_value(&arena, node, &options, &plugins);
+    }
+    let mut html = vec![];
+    comrak::format_html_with_plugins(root, &options, &mut html, &plugins)
+      .unwrap();
+    let html = AMMONIA.clean(&String::from_utf8(html).unwrap()).to_string();
+
+    assert!(html.contains(
+      r#"<pre class="mermaid">graph TD;
+  A--&gt;B&lt;script&gt;;
+</pre>"#
+    ));
+    assert!(html.contains(
+      r#"Euler: <span class="math math-inline">e^{i\pi} &lt; 0</span> and <code>code</code>."#
+    ));
+    assert!(
+      html.contains(
+        r#"<div class="math math-display">\sum_{n=1}^\infty n</div>"#
+      )
+    );
+  }
+
   #[test]
   fn test_url_rewriter() {
     let base = String::from("/@foo/bar/1.2.3");
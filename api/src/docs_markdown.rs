@@ -0,0 +1,328 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+//! Renders the documentation of a package version as a single markdown
+//! document, for AI tooling and offline readers that can not make use of the
+//! HTML documentation.
+
+use crate::db::ExportsMap;
+use crate::ids::PackageName;
+use crate::ids::ScopeName;
+use crate::ids::Version;
+use deno_ast::swc::ast::VarDeclKind;
+use deno_doc::DeclarationDef;
+use deno_doc::ParseOutput;
+use deno_doc::Symbol;
+use deno_doc::js_doc::JsDoc;
+use deno_doc::js_doc::JsDocTag;
+use deno_doc::node::DeclarationKind;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::fmt::Display;
+use std::fmt::Write;
+use url::Url;
+
+/// The `Display` implementations of deno_doc may color their output for
+/// terminals, which has no place in a markdown document.
+static ANSI_ESCAPE_RE: Lazy<Regex> =
+  Lazy::new(|| Regex::new(r"\x1b\[[0-9;]*m").unwrap());
+
+fn plain(value: impl Display) -> String {
+  ANSI_ESCAPE_RE
+    .replace_all(&value.to_string(), "")
+    .into_owned()
+}
+
+fn join(values: &[impl Display]) -> String {
+  values.iter().map(plain).collect::<Vec<_>>().join(", ")
+}
+
+fn type_params(params: &[impl Display]) -> String {
+  if params.is_empty() {
+    String::new()
+  } else {
+    format!("<{}>", join(params))
+  }
+}
+
+/// Renders the public symbols of every export of a package version, with
+/// their signatures and JSDoc, as one markdown document.
+pub fn generate_docs_markdown(
+  scope: &ScopeName,
+  package: &PackageName,
+  version: &Version,
+  exports: &ExportsMap,
+  doc_nodes: &ParseOutput,
+) -> String {
+  let mut out = String::new();
+  writeln!(out, "# @{scope}/{package}@{version}\n").unwrap();
+
+  for (export, path) in exports.iter() {
+    let Some(path) = path.strip_prefix('.') else {
+      continue;
+    };
+    let Ok(specifier) = Url::parse(&format!("file://{path}")) else {
+      continue;
+    };
+    let Some(document) = doc_nodes.get(&specifier) else {
+      continue;
+    };
+
+    let import = match export.strip_prefix("./") {
+      Some(subpath) => format!("@{scope}/{package}/{subpath}"),
+      None => format!("@{scope}/{package}"),
+    };
+    writeln!(out, "## {import}\n").unwrap();
+    write_js_doc(&mut out, &document.module_doc);
+
+    for symbol in &document.symbols {
+      write_symbol(&mut out, "", symbol);
+    }
+  }
+
+  out
+}
+
+fn write_symbol(out: &mut String, prefix: &str, symbol: &Symbol) {
+  let declarations = symbol
+    .declarations
+    .iter()
+    .filter(|decl| {
+      decl.declaration_kind != DeclarationKind::Private
+        && !matches!(decl.def, DeclarationDef::Reference(_))
+    })
+    .collect::<Vec<_>>();
+  if declarations.is_empty() {
+    return;
+  }
+
+  let name = format!("{prefix}{}", symbol.name);
+  writeln!(out, "### {name}\n").unwrap();
+
+  for decl in declarations {
+    writeln!(out, "```ts").unwrap();
+    match &decl.def {
+      DeclarationDef::Function(def) => {
+        write!(
+          out,
+          "{}function{} {}{}({})",
+          if def.is_async { "async " } else { "" },
+          if def.is_generator { "*" } else { "" },
+          symbol.name,
+          type_params(&def.type_params),
+          join(&def.params),
+        )
+        .unwrap();
+        if let Some(return_type) = &def.return_type {
+          write!(out, ": {}", plain(return_type)).unwrap();
+        }
+        writeln!(out).unwrap();
+      }
+      DeclarationDef::Variable(def) => {
+        let kind = match def.kind {
+          VarDeclKind::Var => "var",
+          VarDeclKind::Let => "let",
+          VarDeclKind::Const => "const",
+        };
+        write!(out, "{kind} {}", symbol.name).unwrap();
+        if let Some(ts_type) = &def.ts_type {
+          write!(out, ": {}", plain(ts_type)).unwrap();
+        }
+        writeln!(out).unwrap();
+      }
+      DeclarationDef::Class(def) => {
+        write!(
+          out,
+          "{}class {}{}",
+          if def.is_abstract { "abstract " } else { "" },
+          symbol.name,
+          type_params(&def.type_params),
+        )
+        .unwrap();
+        if let Some(extends) = &def.extends {
+          write!(
+            out,
+            " extends {extends}{}",
+            type_params(&def.super_type_params)
+          )
+          .unwrap();
+        }
+        if !def.implements.is_empty() {
+          write!(out, " implements {}", join(&def.implements)).unwrap();
+        }
+        writeln!(out, " {{").unwrap();
+        for constructor in &def.constructors {
+          writeln!(out, "  {};", plain(constructor)).unwrap();
+        }
+        for property in &def.properties {
+          writeln!(out, "  {};", plain(property)).unwrap();
+        }
+        for signature in &def.index_signatures {
+          writeln!(out, "  {};", plain(signature)).unwrap();
+        }
+        for method in &def.methods {
+          writeln!(out, "  {};", plain(method)).unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+      }
+      DeclarationDef::Interface(def) => {
+        write!(
+          out,
+          "interface {}{}",
+          symbol.name,
+          type_params(&def.type_params)
+        )
+        .unwrap();
+        if !def.extends.is_empty() {
+          write!(out, " extends {}", join(&def.extends)).unwrap();
+        }
+        writeln!(out, " {{").unwrap();
+        for constructor in &def.constructors {
+          writeln!(out, "  {};", plain(constructor)).unwrap();
+        }
+        for signature in &def.call_signatures {
+          writeln!(out, "  {};", plain(signature)).unwrap();
+        }
+        for signature in &def.index_signatures {
+          writeln!(out, "  {};", plain(signature)).unwrap();
+        }
+        for property in &def.properties {
+          writeln!(out, "  {};", plain(property)).unwrap();
+        }
+        for method in &def.methods {
+          writeln!(out, "  {};", plain(method)).unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+      }
+      DeclarationDef::TypeAlias(def) => {
+        writeln!(
+          out,
+          "type {}{} = {}",
+          symbol.name,
+          type_params(&def.type_params),
+          plain(&def.ts_type)
+        )
+        .unwrap();
+      }
+      DeclarationDef::Enum(def) => {
+        writeln!(out, "enum {} {{", symbol.name).unwrap();
+        for member in &def.members {
+          match &member.init {
+            Some(init) => {
+              writeln!(out, "  {} = {},", member.name, plain(init)).unwrap()
+            }
+            None => writeln!(out, "  {},", member.name).unwrap(),
+          }
+        }
+        writeln!(out, "}}").unwrap();
+      }
+      DeclarationDef::Namespace(_) => {
+        writeln!(out, "namespace {}", symbol.name).unwrap();
+      }
+      DeclarationDef::Reference(_) => unreachable!(),
+    }
+    writeln!(out, "```\n").unwrap();
+
+    write_js_doc(out, &decl.js_doc);
+
+    match &decl.def {
+      DeclarationDef::Class(def) => {
+        for property in &def.properties {
+          write_member_doc(out, &name, &property.name, &property.js_doc);
+        }
+        for method in &def.methods {
+          write_member_doc(out, &name, &method.name, &method.js_doc);
+        }
+      }
+      DeclarationDef::Interface(def) => {
+        for property in &def.properties {
+          write_member_doc(out, &name, &property.name, &property.js_doc);
+        }
+        for method in &def.methods {
+          write_member_doc(out, &name, &method.name, &method.js_doc);
+        }
+      }
+      DeclarationDef::Enum(def) => {
+        for member in &def.members {
+          write_member_doc(out, &name, &member.name, &member.js_doc);
+        }
+      }
+      DeclarationDef::Namespace(def) => {
+        for element in &def.elements {
+          write_symbol(out, &format!("{name}."), element);
+        }
+      }
+      _ => {}
+    }
+  }
+}
+
+fn write_member_doc(
+  out: &mut String,
+  parent: &str,
+  name: &str,
+  js_doc: &JsDoc,
+) {
+  if js_doc.is_empty() {
+    return;
+  }
+  writeln!(out, "#### {parent}.{name}\n").unwrap();
+  write_js_doc(out, js_doc);
+}
+
+fn write_js_doc(out: &mut String, js_doc: &JsDoc) {
+  if let Some(doc) = js_doc.doc.as_deref().map(str::trim)
+    && !doc.is_empty()
+  {
+    writeln!(out, "{doc}\n").unwrap();
+  }
+
+  for tag in &js_doc.tags {
+    match tag {
+      JsDocTag::Deprecated { doc } => match doc.as_deref().map(str::trim) {
+        Some(doc) if !doc.is_empty() => {
+          writeln!(out, "**Deprecated:** {doc}\n").unwrap()
+        }
+        _ => writeln!(out, "**Deprecated.**\n").unwrap(),
+      },
+      JsDocTag::Param { name, doc, .. } => {
+        match doc.as_deref().map(str::trim) {
+          Some(doc) if !doc.is_empty() => {
+            writeln!(out, "- `{name}`: {doc}").unwrap()
+          }
+          _ => writeln!(out, "- `{name}`").unwrap(),
+        }
+      }
+      JsDocTag::Return { doc, .. } => {
+        if let Some(doc) = doc.as_deref().map(str::trim)
+          && !doc.is_empty()
+        {
+          writeln!(out, "\n**Returns:** {doc}\n").unwrap();
+        }
+      }
+      JsDocTag::Example { doc } => {
+        writeln!(out, "\n**Example:**\n\n{}\n", doc.trim()).unwrap();
+      }
+      _ => {}
+    }
+  }
+
+  if js_doc
+    .tags
+    .iter()
+    .any(|tag| matches!(tag, JsDocTag::Param { .. }))
+  {
+    writeln!(out).unwrap();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn strips_ansi_escapes() {
+    assert_eq!(
+      plain("\x1b[1mfoo\x1b[22m: \x1b[36mstring\x1b[39m"),
+      "foo: string"
+    );
+  }
+}
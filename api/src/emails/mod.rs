@@ -1,25 +1,110 @@
 // Copyright 2024 the JSR authors. All rights reserved. MIT license.
+mod notifications;
+mod providers;
+
 use std::borrow::Cow;
+use std::str::FromStr;
+use std::sync::Arc;
 use std::sync::OnceLock;
 
 use handlebars::Handlebars;
 use handlebars::RenderError;
-use postmark::Query;
-use postmark::reqwest::PostmarkClient;
 use serde::Serialize;
+use url::Url;
+use uuid::Uuid;
 
+use crate::ids::PackageName;
 use crate::ids::ScopeName;
+use crate::ids::Version;
+use providers::EmailMessage;
+
+pub use notifications::PublishFailureEmailJobPayload;
+pub use notifications::send_publish_failure_email;
+pub use notifications::send_scope_digests;
+pub use providers::EmailProvider;
+pub use providers::EmailProviderKind;
+pub use providers::PostmarkProvider;
+pub use providers::SesProvider;
+pub use providers::SmtpProvider;
 
 const BASE_TXT: &str = "base.txt";
 const BASE_HTML: &str = "base.html";
 const SCOPE_INVITE_TXT: &str = "scope_invite.txt";
 const SCOPE_INVITE_HTML: &str = "scope_invite.html";
+const SCOPE_MEMBER_ADDED_TXT: &str = "scope_member_added.txt";
+const SCOPE_MEMBER_ADDED_HTML: &str = "scope_member_added.html";
 const PERSONAL_ACCESS_TOKEN_TXT: &str = "personal_access_token.txt";
 const PERSONAL_ACCESS_TOKEN_HTML: &str = "personal_access_token.html";
 const SUPPORT_TICKET_CREATED_TXT: &str = "support_ticket_created.txt";
 const SUPPORT_TICKET_CREATED_HTML: &str = "support_ticket_created.html";
 const SUPPORT_TICKET_MESSAGE_TXT: &str = "support_ticket_message.txt";
 const SUPPORT_TICKET_MESSAGE_HTML: &str = "support_ticket_message.html";
+const PUBLISH_FAILED_TXT: &str = "publish_failed.txt";
+const PUBLISH_FAILED_HTML: &str = "publish_failed.html";
+const SCOPE_DIGEST_TXT: &str = "scope_digest.txt";
+const SCOPE_DIGEST_HTML: &str = "scope_digest.html";
+
+/// The kinds of notification emails that users can turn off.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NotificationKind {
+  ScopeInvites,
+  PublishFailures,
+  ScopeDigests,
+}
+
+impl NotificationKind {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      NotificationKind::ScopeInvites => "scopeInvites",
+      NotificationKind::PublishFailures => "publishFailures",
+      NotificationKind::ScopeDigests => "scopeDigests",
+    }
+  }
+
+  /// A description of the emails of this kind, for the unsubscribe page.
+  pub fn description(&self) -> &'static str {
+    match self {
+      NotificationKind::ScopeInvites => "scope invitation",
+      NotificationKind::PublishFailures => "publish failure",
+      NotificationKind::ScopeDigests => "scope digest",
+    }
+  }
+}
+
+impl FromStr for NotificationKind {
+  type Err = anyhow::Error;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "scopeInvites" => Ok(Self::ScopeInvites),
+      "publishFailures" => Ok(Self::PublishFailures),
+      "scopeDigests" => Ok(Self::ScopeDigests),
+      _ => Err(anyhow::anyhow!("Invalid notification kind '{}'", s)),
+    }
+  }
+}
+
+/// The link that unsubscribes the owner of `token` from a kind of
+/// notification, without signing in.
+pub fn unsubscribe_url(
+  registry_url: &Url,
+  token: Uuid,
+  kind: NotificationKind,
+) -> Url {
+  let mut url = registry_url.join("api/unsubscribe").unwrap();
+  url
+    .query_pairs_mut()
+    .append_pair("token", &token.to_string())
+    .append_pair("notification", kind.as_str());
+  url
+}
+
+/// A version listed in a [EmailArgs::ScopeDigest].
+#[derive(Debug, Serialize)]
+pub struct DigestVersion {
+  pub scope: ScopeName,
+  pub package: PackageName,
+  pub version: Version,
+}
 
 #[derive(Debug, Serialize)]
 #[serde(untagged)]
@@ -31,6 +116,16 @@ pub enum EmailArgs<'a> {
     registry_url: Cow<'a, str>,
     registry_name: Cow<'a, str>,
     support_email: Cow<'a, str>,
+    unsubscribe_url: Cow<'a, str>,
+  },
+  ScopeMemberAdded {
+    name: Cow<'a, str>,
+    member_name: Cow<'a, str>,
+    scope: Cow<'a, ScopeName>,
+    cooldown_hours: u32,
+    registry_url: Cow<'a, str>,
+    registry_name: Cow<'a, str>,
+    support_email: Cow<'a, str>,
   },
   PersonalAccessToken {
     token_description: Cow<'a, str>,
@@ -56,6 +151,27 @@ pub enum EmailArgs<'a> {
     registry_name: Cow<'a, str>,
     support_email: Cow<'a, str>,
   },
+  PublishFailed {
+    name: Cow<'a, str>,
+    scope: Cow<'a, ScopeName>,
+    package: Cow<'a, PackageName>,
+    version: Cow<'a, Version>,
+    task_id: Uuid,
+    error_code: Cow<'a, str>,
+    error_message: Cow<'a, str>,
+    registry_url: Cow<'a, str>,
+    registry_name: Cow<'a, str>,
+    support_email: Cow<'a, str>,
+    unsubscribe_url: Cow<'a, str>,
+  },
+  ScopeDigest {
+    name: Cow<'a, str>,
+    versions: Vec<DigestVersion>,
+    registry_url: Cow<'a, str>,
+    registry_name: Cow<'a, str>,
+    support_email: Cow<'a, str>,
+    unsubscribe_url: Cow<'a, str>,
+  },
 }
 
 impl EmailArgs<'_> {
@@ -68,6 +184,11 @@ impl EmailArgs<'_> {
       } => {
         format!("You've been invited to @{scope} on {registry_name}")
       }
+      EmailArgs::ScopeMemberAdded {
+        member_name, scope, ..
+      } => {
+        format!("{member_name} joined @{scope}")
+      }
       EmailArgs::PersonalAccessToken { registry_name, .. } => {
         format!("A new personal access token was created on {registry_name}")
       }
@@ -75,24 +196,61 @@ impl EmailArgs<'_> {
       | EmailArgs::SupportTicketMessage { ticket_id, .. } => {
         format!("Support request {ticket_id}")
       }
+      EmailArgs::PublishFailed {
+        scope,
+        package,
+        version,
+        ..
+      } => {
+        format!("Publishing @{scope}/{package}@{version} failed")
+      }
+      EmailArgs::ScopeDigest { registry_name, .. } => {
+        format!("Your weekly {registry_name} digest")
+      }
+    }
+  }
+
+  /// The link to unsubscribe from this email, for emails that users can turn
+  /// off.
+  pub fn unsubscribe_url(&self) -> Option<&str> {
+    match self {
+      EmailArgs::ScopeInvite {
+        unsubscribe_url, ..
+      }
+      | EmailArgs::PublishFailed {
+        unsubscribe_url, ..
+      }
+      | EmailArgs::ScopeDigest {
+        unsubscribe_url, ..
+      } => Some(unsubscribe_url),
+      EmailArgs::ScopeMemberAdded { .. }
+      | EmailArgs::PersonalAccessToken { .. }
+      | EmailArgs::SupportTicketCreated { .. }
+      | EmailArgs::SupportTicketMessage { .. } => None,
     }
   }
 
   pub fn text_template_filename(&self) -> &'static str {
     match self {
       EmailArgs::ScopeInvite { .. } => SCOPE_INVITE_TXT,
+      EmailArgs::ScopeMemberAdded { .. } => SCOPE_MEMBER_ADDED_TXT,
       EmailArgs::PersonalAccessToken { .. } => PERSONAL_ACCESS_TOKEN_TXT,
       EmailArgs::SupportTicketCreated { .. } => SUPPORT_TICKET_CREATED_TXT,
       EmailArgs::SupportTicketMessage { .. } => SUPPORT_TICKET_MESSAGE_TXT,
+      EmailArgs::PublishFailed { .. } => PUBLISH_FAILED_TXT,
+      EmailArgs::ScopeDigest { .. } => SCOPE_DIGEST_TXT,
     }
   }
 
   pub fn html_template_filename(&self) -> &'static str {
     match self {
       EmailArgs::ScopeInvite { .. } => SCOPE_INVITE_HTML,
+      EmailArgs::ScopeMemberAdded { .. } => SCOPE_MEMBER_ADDED_HTML,
       EmailArgs::PersonalAccessToken { .. } => PERSONAL_ACCESS_TOKEN_HTML,
       EmailArgs::SupportTicketCreated { .. } => SUPPORT_TICKET_CREATED_HTML,
       EmailArgs::SupportTicketMessage { .. } => SUPPORT_TICKET_MESSAGE_HTML,
+      EmailArgs::PublishFailed { .. } => PUBLISH_FAILED_HTML,
+      EmailArgs::ScopeDigest { .. } => SCOPE_DIGEST_HTML,
     }
   }
 }
@@ -117,6 +275,14 @@ fn init_handlebars()
     SCOPE_INVITE_HTML,
     include_str!("./templates/scope_invite.html.hbs"),
   )?;
+  t.register_template_string(
+    SCOPE_MEMBER_ADDED_TXT,
+    include_str!("./templates/scope_member_added.txt.hbs"),
+  )?;
+  t.register_template_string(
+    SCOPE_MEMBER_ADDED_HTML,
+    include_str!("./templates/scope_member_added.html.hbs"),
+  )?;
   t.register_template_string(
     PERSONAL_ACCESS_TOKEN_TXT,
     include_str!("./templates/personal_access_token.txt.hbs"),
@@ -141,6 +307,22 @@ fn init_handlebars()
     SUPPORT_TICKET_MESSAGE_HTML,
     include_str!("./templates/support_ticket_message.html.hbs"),
   )?;
+  t.register_template_string(
+    PUBLISH_FAILED_TXT,
+    include_str!("./templates/publish_failed.txt.hbs"),
+  )?;
+  t.register_template_string(
+    PUBLISH_FAILED_HTML,
+    include_str!("./templates/publish_failed.html.hbs"),
+  )?;
+  t.register_template_string(
+    SCOPE_DIGEST_TXT,
+    include_str!("./templates/scope_digest.txt.hbs"),
+  )?;
+  t.register_template_string(
+    SCOPE_DIGEST_HTML,
+    include_str!("./templates/scope_digest.html.hbs"),
+  )?;
 
   t.set_strict_mode(true);
 
@@ -166,21 +348,22 @@ pub fn email_content(args: EmailArgs) -> Result<EmailContent, RenderError> {
   Ok(EmailContent { text, html })
 }
 
-#[derive(Debug)]
+/// Renders emails and sends them through an [EmailProvider].
+#[derive(Clone)]
 pub struct EmailSender {
-  postmark: PostmarkClient,
+  provider: Arc<dyn EmailProvider>,
   pub from: String,
   pub from_name: String,
 }
 
 impl EmailSender {
   pub fn new(
-    postmark: PostmarkClient,
+    provider: Arc<dyn EmailProvider>,
     from: String,
     from_name: String,
   ) -> Self {
     Self {
-      postmark,
+      provider,
       from,
       from_name,
     }
@@ -192,25 +375,97 @@ impl EmailSender {
     args: EmailArgs<'_>,
   ) -> Result<(), anyhow::Error> {
     let subject = args.subject();
+    // RFC 8058 one-click unsubscribe. Only the POST request unsubscribes, the
+    // GET request for the same link renders a confirmation page.
+    let headers = match args.unsubscribe_url() {
+      Some(url) => vec![
+        ("List-Unsubscribe".to_owned(), format!("<{url}>")),
+        (
+          "List-Unsubscribe-Post".to_owned(),
+          "List-Unsubscribe=One-Click".to_owned(),
+        ),
+      ],
+      None => vec![],
+    };
     let content = email_content(args)?;
-    let req = postmark::api::email::SendEmailRequest::builder()
-      .from(format!("{} <{}>", self.from_name, self.from))
-      .to(to)
-      .subject(subject)
-      .body(postmark::api::Body::HtmlAndText {
-        html: content.html,
+    self
+      .provider
+      .send(EmailMessage {
+        from: format!("{} <{}>", self.from_name, self.from),
+        to,
+        subject,
         text: content.text,
+        html: content.html,
+        headers,
       })
-      .build();
-    let resp = req.execute(&self.postmark).await?;
-    if resp.error_code != 0 {
-      Err(anyhow::anyhow!(
-        "Postmark error {}: {}",
-        resp.error_code,
-        resp.message
-      ))
-    } else {
-      Ok(())
-    }
+      .await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::borrow::Cow;
+
+  use super::DigestVersion;
+  use super::EmailArgs;
+  use super::NotificationKind;
+  use super::email_content;
+  use super::unsubscribe_url;
+
+  #[test]
+  fn notification_emails() {
+    let registry_url = url::Url::parse("https://jsr.io/").unwrap();
+    let token = uuid::Uuid::new_v4();
+    let unsubscribe =
+      unsubscribe_url(&registry_url, token, NotificationKind::ScopeDigests);
+    assert_eq!(
+      unsubscribe.as_str(),
+      format!(
+        "https://jsr.io/api/unsubscribe?token={token}&notification=scopeDigests"
+      )
+    );
+
+    let scope = "std".try_into().unwrap();
+    let package = "fs".try_into().unwrap();
+    let version = "1.0.0".try_into().unwrap();
+    let args = EmailArgs::PublishFailed {
+      name: Cow::Borrowed("Alice"),
+      scope: Cow::Borrowed(&scope),
+      package: Cow::Borrowed(&package),
+      version: Cow::Borrowed(&version),
+      task_id: token,
+      error_code: Cow::Borrowed("invalidPath"),
+      error_message: Cow::Borrowed("the path is invalid"),
+      registry_url: Cow::Borrowed(registry_url.as_str()),
+      registry_name: Cow::Borrowed("JSR"),
+      support_email: Cow::Borrowed("help@jsr.io"),
+      unsubscribe_url: Cow::Borrowed(unsubscribe.as_str()),
+    };
+    assert_eq!(args.subject(), "Publishing @std/fs@1.0.0 failed");
+    assert_eq!(args.unsubscribe_url(), Some(unsubscribe.as_str()));
+    let content = email_content(args).unwrap();
+    assert!(content.text.contains("invalidPath: the path is invalid"));
+    assert!(
+      content
+        .text
+        .contains(&format!("https://jsr.io/status/{token}"))
+    );
+
+    let args = EmailArgs::ScopeDigest {
+      name: Cow::Borrowed("Alice"),
+      versions: vec![DigestVersion {
+        scope,
+        package,
+        version,
+      }],
+      registry_url: Cow::Borrowed(registry_url.as_str()),
+      registry_name: Cow::Borrowed("JSR"),
+      support_email: Cow::Borrowed("help@jsr.io"),
+      unsubscribe_url: Cow::Borrowed(unsubscribe.as_str()),
+    };
+    let content = email_content(args).unwrap();
+    assert!(content.text.contains("https://jsr.io/@std/fs@1.0.0"));
+    assert!(content.text.contains(unsubscribe.as_str()));
+    assert!(content.html.contains("@std/fs@1.0.0"));
   }
 }
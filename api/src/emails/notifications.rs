@@ -0,0 +1,196 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+//! Notification emails that are not sent in response to a request: failed
+//! publishes, sent by a background job, and the weekly scope digest, sent by
+//! the `send_scope_digests` task. Both respect the notification preferences
+//! of the recipient.
+
+use std::borrow::Cow;
+
+use chrono::Duration;
+use chrono::Utc;
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::instrument;
+use url::Url;
+use uuid::Uuid;
+
+use crate::background_jobs::DEFAULT_MAX_ATTEMPTS;
+use crate::db::BackgroundJobKind;
+use crate::db::Database;
+use crate::db::NewBackgroundJob;
+use crate::db::PublishingTaskStatus;
+
+use super::DigestVersion;
+use super::EmailArgs;
+use super::EmailSender;
+use super::NotificationKind;
+use super::unsubscribe_url;
+
+/// How many recipients of the scope digest are loaded at a time.
+const SCOPE_DIGEST_BATCH_SIZE: i64 = 100;
+
+/// The most versions listed in one scope digest.
+const SCOPE_DIGEST_MAX_VERSIONS: i64 = 50;
+
+/// The payload of [BackgroundJobKind::PublishFailureEmail] jobs.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublishFailureEmailJobPayload {
+  pub task_id: Uuid,
+}
+
+impl PublishFailureEmailJobPayload {
+  pub fn job(task_id: Uuid) -> NewBackgroundJob<'static> {
+    NewBackgroundJob {
+      kind: BackgroundJobKind::PublishFailureEmail,
+      payload: serde_json::to_value(PublishFailureEmailJobPayload { task_id })
+        .unwrap(),
+      priority: 0,
+      max_attempts: DEFAULT_MAX_ATTEMPTS,
+      created_by: None,
+    }
+  }
+}
+
+/// Tells the user that started the publishing task `task_id` that it failed,
+/// unless they turned these emails off or have no email address.
+#[instrument(
+  name = "send_publish_failure_email",
+  skip(db, email_sender, registry_url),
+  err
+)]
+pub async fn send_publish_failure_email(
+  db: &Database,
+  email_sender: Option<&EmailSender>,
+  registry_url: &Url,
+  task_id: Uuid,
+) -> Result<(), anyhow::Error> {
+  let Some(email_sender) = email_sender else {
+    return Ok(());
+  };
+  let (task, _) = db.get_publishing_task(task_id).await?.ok_or_else(|| {
+    anyhow::anyhow!("publishing task {task_id} does not exist")
+  })?;
+  if task.status != PublishingTaskStatus::Failure {
+    return Ok(());
+  }
+  // Publishes from GitHub Actions without a linked user have no one to tell.
+  let Some(user_id) = task.user_id else {
+    return Ok(());
+  };
+  let user = db
+    .get_user(user_id)
+    .await?
+    .ok_or_else(|| anyhow::anyhow!("user {user_id} does not exist"))?;
+  let Some(email) = user.email else {
+    return Ok(());
+  };
+  let preferences = db.get_notification_preferences(user.id).await?;
+  if !preferences.publish_failures {
+    return Ok(());
+  }
+
+  let (error_code, error_message) = match &task.error {
+    Some(error) => (error.code.as_str(), error.message.as_str()),
+    None => ("unknown", "The publish failed for an unknown reason."),
+  };
+  let unsubscribe_url = unsubscribe_url(
+    registry_url,
+    preferences.unsubscribe_token,
+    NotificationKind::PublishFailures,
+  );
+  let args = EmailArgs::PublishFailed {
+    name: Cow::Borrowed(&user.name),
+    scope: Cow::Borrowed(&task.package_scope),
+    package: Cow::Borrowed(&task.package_name),
+    version: Cow::Borrowed(&task.package_version),
+    task_id: task.id,
+    error_code: Cow::Borrowed(error_code),
+    error_message: Cow::Borrowed(error_message),
+    registry_url: Cow::Borrowed(registry_url.as_str()),
+    registry_name: Cow::Borrowed(&email_sender.from_name),
+    support_email: Cow::Borrowed(&email_sender.from),
+    unsubscribe_url: Cow::Borrowed(unsubscribe_url.as_str()),
+  };
+  email_sender.send(email, args).await
+}
+
+/// Sends every scope member that wants them a digest of the versions that
+/// were published to their scopes in the last week. Members that did not get
+/// a digest in the last six days are due, so that reruns of the task within
+/// the same week don't send a second digest. Returns the number of digests
+/// sent.
+#[instrument(
+  name = "send_scope_digests",
+  skip(db, email_sender, registry_url),
+  err
+)]
+pub async fn send_scope_digests(
+  db: &Database,
+  email_sender: &EmailSender,
+  registry_url: &Url,
+) -> Result<usize, anyhow::Error> {
+  let now = Utc::now();
+  let week_ago = now - Duration::days(7);
+  let mut sent = 0;
+  loop {
+    let recipients = db
+      .list_scope_digest_recipients(
+        now - Duration::days(6),
+        SCOPE_DIGEST_BATCH_SIZE,
+      )
+      .await?;
+    if recipients.is_empty() {
+      break;
+    }
+
+    for recipient in recipients {
+      let since = recipient
+        .last_digest_sent_at
+        .map_or(week_ago, |sent_at| sent_at.max(week_ago));
+      let versions = db
+        .list_scope_digest_versions(
+          recipient.user_id,
+          since,
+          SCOPE_DIGEST_MAX_VERSIONS,
+        )
+        .await?;
+
+      if !versions.is_empty() {
+        let unsubscribe_url = unsubscribe_url(
+          registry_url,
+          recipient.unsubscribe_token,
+          NotificationKind::ScopeDigests,
+        );
+        let args = EmailArgs::ScopeDigest {
+          name: Cow::Borrowed(&recipient.name),
+          versions: versions
+            .into_iter()
+            .map(|version| DigestVersion {
+              scope: version.scope,
+              package: version.name,
+              version: version.version,
+            })
+            .collect(),
+          registry_url: Cow::Borrowed(registry_url.as_str()),
+          registry_name: Cow::Borrowed(&email_sender.from_name),
+          support_email: Cow::Borrowed(&email_sender.from),
+          unsubscribe_url: Cow::Borrowed(unsubscribe_url.as_str()),
+        };
+        // One failed email should not hold up the digests of everyone else,
+        // and is not retried, like a week without new versions.
+        match email_sender.send(recipient.email, args).await {
+          Ok(()) => sent += 1,
+          Err(err) => tracing::error!(
+            user_id = %recipient.user_id,
+            "failed to send scope digest: {err:#}"
+          ),
+        }
+      }
+
+      db.mark_scope_digest_sent(recipient.user_id, now).await?;
+    }
+  }
+  Ok(sent)
+}
@@ -0,0 +1,534 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+//! Typed domain events, like a version being published or a token being used,
+//! so that downstream analytics and webhooks are fed from one source.
+//!
+//! [emit] queues the webhook deliveries of an event, if it has any, and puts
+//! the event on the internal event bus of [Database]. The worker started by
+//! [spawn_event_worker] takes events off the bus in batches and publishes
+//! them to every configured [EventSink]: the `events` table
+//! ([PostgresEventSink]), a Pub/Sub topic ([PubSubEventSink]), or a Kafka
+//! topic through a Kafka REST proxy ([KafkaRestEventSink]).
+//!
+//! Events are delivered at most once: a batch that a sink fails to publish is
+//! logged and dropped, as are the events still on the bus when the process
+//! stops.
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use base64::Engine as _;
+use base64::prelude::BASE64_STANDARD;
+use chrono::DateTime;
+use chrono::Utc;
+use hyper::StatusCode;
+use serde::Serialize;
+use serde_json::json;
+use tokio::sync::mpsc;
+use tracing::Instrument;
+use tracing::error;
+use tracing::info_span;
+use url::Url;
+use uuid::Uuid;
+
+use crate::db::AbuseReportReason;
+use crate::db::AbuseReportStatus;
+use crate::db::Database;
+use crate::db::WebhookEvent;
+use crate::gcp;
+use crate::ids::PackageName;
+use crate::ids::ScopeName;
+use crate::ids::Version;
+use crate::webhooks;
+
+/// How many events are published to the sinks at most at a time.
+const EVENT_BATCH_SIZE: usize = 100;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EventSinkKind {
+  Postgres,
+  PubSub,
+  Kafka,
+}
+
+impl FromStr for EventSinkKind {
+  type Err = anyhow::Error;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "postgres" => Ok(Self::Postgres),
+      "pubsub" => Ok(Self::PubSub),
+      "kafka" => Ok(Self::Kafka),
+      _ => Err(anyhow::anyhow!("Invalid event sink '{}'", s)),
+    }
+  }
+}
+
+/// Something that happened in the registry.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(
+  tag = "type",
+  rename_all = "camelCase",
+  rename_all_fields = "camelCase"
+)]
+pub enum DomainEvent {
+  VersionPublished {
+    scope: ScopeName,
+    package: PackageName,
+    version: Version,
+    user_id: Option<Uuid>,
+  },
+  VersionYanked {
+    scope: ScopeName,
+    package: PackageName,
+    version: Version,
+    user_id: Uuid,
+  },
+  /// A version of `dependent` is the first to depend on `package`, a package
+  /// of another scope.
+  NewDependent {
+    scope: ScopeName,
+    /// The name of the dependency, as `@scope/name`.
+    package: String,
+    constraint: String,
+    /// The name of the dependent package, as `@scope/name`.
+    dependent: String,
+    dependent_version: Version,
+  },
+  PackageCreated {
+    scope: ScopeName,
+    package: PackageName,
+    user_id: Uuid,
+  },
+  /// A token was used to authenticate a request. Emitted at most once a
+  /// minute per token, see [Database::touch_token].
+  TokenUsed { token_id: Uuid, user_id: Uuid },
+  ScopeMemberAdded {
+    scope: ScopeName,
+    user_id: Uuid,
+    user_name: String,
+    is_admin: bool,
+  },
+  ScopeMemberUpdated {
+    scope: ScopeName,
+    user_id: Uuid,
+    user_name: String,
+    is_admin: bool,
+  },
+  ScopeMemberRemoved {
+    scope: ScopeName,
+    user_id: Uuid,
+    is_admin: bool,
+  },
+  /// A user reported a package, or a version of it, as malicious or
+  /// infringing. Not sent to webhooks, so that reports stay confidential.
+  PackageReported {
+    report_id: Uuid,
+    scope: ScopeName,
+    package: PackageName,
+    version: Option<Version>,
+    reason: AbuseReportReason,
+    user_id: Uuid,
+  },
+  /// Staff moved an abuse report to another moderation status.
+  AbuseReportUpdated {
+    report_id: Uuid,
+    status: AbuseReportStatus,
+    user_id: Uuid,
+  },
+  /// Staff, or the malware scan when the version was published, quarantined
+  /// a version.
+  VersionQuarantined {
+    scope: ScopeName,
+    package: PackageName,
+    version: Version,
+    report_id: Option<Uuid>,
+    /// `None` if the malware scan quarantined the version.
+    user_id: Option<Uuid>,
+  },
+  VersionReleased {
+    scope: ScopeName,
+    package: PackageName,
+    version: Version,
+    user_id: Uuid,
+  },
+}
+
+impl DomainEvent {
+  /// The value of the `type` field of the event.
+  pub fn name(&self) -> &'static str {
+    match self {
+      DomainEvent::VersionPublished { .. } => "versionPublished",
+      DomainEvent::VersionYanked { .. } => "versionYanked",
+      DomainEvent::NewDependent { .. } => "newDependent",
+      DomainEvent::PackageCreated { .. } => "packageCreated",
+      DomainEvent::TokenUsed { .. } => "tokenUsed",
+      DomainEvent::ScopeMemberAdded { .. } => "scopeMemberAdded",
+      DomainEvent::ScopeMemberUpdated { .. } => "scopeMemberUpdated",
+      DomainEvent::ScopeMemberRemoved { .. } => "scopeMemberRemoved",
+      DomainEvent::PackageReported { .. } => "packageReported",
+      DomainEvent::AbuseReportUpdated { .. } => "abuseReportUpdated",
+      DomainEvent::VersionQuarantined { .. } => "versionQuarantined",
+      DomainEvent::VersionReleased { .. } => "versionReleased",
+    }
+  }
+
+  /// The scope whose webhooks are notified of the event, the webhook event
+  /// and its data, if the event is one that webhooks can subscribe to.
+  pub fn webhook(
+    &self,
+  ) -> Option<(&ScopeName, WebhookEvent, serde_json::Value)> {
+    match self {
+      DomainEvent::VersionPublished {
+        scope,
+        package,
+        version,
+        user_id,
+      } => Some((
+        scope,
+        WebhookEvent::VersionPublished,
+        json!({
+          "package": format!("@{scope}/{package}"),
+          "version": version,
+          "userId": user_id,
+        }),
+      )),
+      DomainEvent::VersionYanked {
+        scope,
+        package,
+        version,
+        user_id,
+      } => Some((
+        scope,
+        WebhookEvent::VersionYanked,
+        json!({
+          "package": format!("@{scope}/{package}"),
+          "version": version,
+          "userId": user_id,
+        }),
+      )),
+      DomainEvent::NewDependent {
+        scope,
+        package,
+        constraint,
+        dependent,
+        dependent_version,
+      } => Some((
+        scope,
+        WebhookEvent::NewDependent,
+        json!({
+          "package": package,
+          "constraint": constraint,
+          "dependent": dependent,
+          "dependentVersion": dependent_version,
+        }),
+      )),
+      DomainEvent::ScopeMemberAdded {
+        scope,
+        user_id,
+        user_name,
+        is_admin,
+      } => Some((
+        scope,
+        WebhookEvent::ScopeMemberAdded,
+        json!({
+          "userId": user_id,
+          "userName": user_name,
+          "isAdmin": is_admin,
+        }),
+      )),
+      DomainEvent::ScopeMemberUpdated {
+        scope,
+        user_id,
+        user_name,
+        is_admin,
+      } => Some((
+        scope,
+        WebhookEvent::ScopeMemberUpdated,
+        json!({
+          "userId": user_id,
+          "userName": user_name,
+          "isAdmin": is_admin,
+        }),
+      )),
+      DomainEvent::ScopeMemberRemoved {
+        scope,
+        user_id,
+        is_admin,
+      } => Some((
+        scope,
+        WebhookEvent::ScopeMemberRemoved,
+        json!({
+          "userId": user_id,
+          "isAdmin": is_admin,
+        }),
+      )),
+      DomainEvent::VersionQuarantined {
+        scope,
+        package,
+        version,
+        ..
+      } => Some((
+        scope,
+        WebhookEvent::VersionQuarantined,
+        json!({
+          "package": format!("@{scope}/{package}"),
+          "version": version,
+        }),
+      )),
+      DomainEvent::VersionReleased {
+        scope,
+        package,
+        version,
+        ..
+      } => Some((
+        scope,
+        WebhookEvent::VersionReleased,
+        json!({
+          "package": format!("@{scope}/{package}"),
+          "version": version,
+        }),
+      )),
+      DomainEvent::PackageCreated { .. }
+      | DomainEvent::TokenUsed { .. }
+      | DomainEvent::PackageReported { .. }
+      | DomainEvent::AbuseReportUpdated { .. } => None,
+    }
+  }
+}
+
+/// A [DomainEvent] as it is published to the sinks.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Event {
+  pub id: Uuid,
+  pub created_at: DateTime<Utc>,
+  #[serde(flatten)]
+  pub event: DomainEvent,
+}
+
+impl Event {
+  pub fn new(event: DomainEvent) -> Self {
+    Event {
+      id: Uuid::new_v4(),
+      created_at: Utc::now(),
+      event,
+    }
+  }
+}
+
+/// Emits `event`: queues its webhook deliveries and puts it on the event bus.
+/// Failures are logged rather than returned, so that the action that caused
+/// the event is not failed by it.
+pub async fn emit(db: &Database, event: DomainEvent) {
+  if let Some((scope, webhook_event, data)) = event.webhook() {
+    webhooks::dispatch(db, scope, webhook_event, data).await;
+  }
+  db.queue_event(Event::new(event));
+}
+
+/// Where events are published to.
+#[async_trait::async_trait]
+pub trait EventSink: Send + Sync {
+  fn name(&self) -> &'static str;
+
+  async fn publish(&self, events: &[Event]) -> Result<(), anyhow::Error>;
+}
+
+/// Inserts events into the `events` table.
+pub struct PostgresEventSink {
+  db: Database,
+}
+
+impl PostgresEventSink {
+  pub fn new(db: Database) -> Self {
+    Self { db }
+  }
+}
+
+#[async_trait::async_trait]
+impl EventSink for PostgresEventSink {
+  fn name(&self) -> &'static str {
+    "postgres"
+  }
+
+  async fn publish(&self, events: &[Event]) -> Result<(), anyhow::Error> {
+    self.db.insert_events(events).await?;
+    Ok(())
+  }
+}
+
+/// Publishes events to a Pub/Sub topic, with the type of the event as the
+/// `type` attribute of the message.
+pub struct PubSubEventSink {
+  topic: gcp::Topic,
+}
+
+impl PubSubEventSink {
+  pub fn new(topic: gcp::Topic) -> Self {
+    Self { topic }
+  }
+}
+
+#[async_trait::async_trait]
+impl EventSink for PubSubEventSink {
+  fn name(&self) -> &'static str {
+    "pubsub"
+  }
+
+  async fn publish(&self, events: &[Event]) -> Result<(), anyhow::Error> {
+    let messages = events
+      .iter()
+      .map(|event| {
+        Ok(json!({
+          "data": BASE64_STANDARD.encode(serde_json::to_vec(event)?),
+          "attributes": { "type": event.event.name() },
+        }))
+      })
+      .collect::<Result<Vec<_>, serde_json::Error>>()?;
+    self.topic.publish(messages).await
+  }
+}
+
+/// Produces events to a Kafka topic through the REST API of a Kafka REST
+/// proxy, keyed by the id of the event.
+pub struct KafkaRestEventSink {
+  url: Url,
+  topic: String,
+}
+
+impl KafkaRestEventSink {
+  pub fn new(url: Url, topic: String) -> Self {
+    Self { url, topic }
+  }
+}
+
+#[async_trait::async_trait]
+impl EventSink for KafkaRestEventSink {
+  fn name(&self) -> &'static str {
+    "kafka"
+  }
+
+  async fn publish(&self, events: &[Event]) -> Result<(), anyhow::Error> {
+    let url = self.url.join(&format!("topics/{}", self.topic))?;
+    let records = events
+      .iter()
+      .map(|event| json!({ "key": event.id, "value": event }))
+      .collect::<Vec<_>>();
+    let resp = crate::util::shared_http_client()
+      .post(url)
+      .header("content-type", "application/vnd.kafka.json.v2+json")
+      .json(&json!({ "records": records }))
+      .send()
+      .await?;
+    let status = resp.status();
+    if status != StatusCode::OK {
+      let body = resp.text().await?;
+      return Err(anyhow::anyhow!(
+        "Failed to produce events (status={status}): {body}"
+      ));
+    }
+    Ok(())
+  }
+}
+
+/// The event bus of [Database], see [spawn_event_worker].
+#[derive(Debug, Clone)]
+pub struct EventQueue(mpsc::UnboundedSender<Event>);
+
+impl EventQueue {
+  pub fn new() -> (Self, EventReceiver) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    (Self(tx), EventReceiver(rx))
+  }
+
+  pub fn push(&self, event: Event) {
+    // The worker only stops when the process does.
+    let _ = self.0.send(event);
+  }
+}
+
+pub struct EventReceiver(mpsc::UnboundedReceiver<Event>);
+
+/// Publishes the events that are put on the [EventQueue] of `rx` to every
+/// sink, in batches of up to [EVENT_BATCH_SIZE] events.
+pub fn spawn_event_worker(
+  sinks: Vec<Arc<dyn EventSink>>,
+  mut rx: EventReceiver,
+) {
+  tokio::spawn(async move {
+    let mut events = Vec::with_capacity(EVENT_BATCH_SIZE);
+    while rx.0.recv_many(&mut events, EVENT_BATCH_SIZE).await > 0 {
+      for sink in &sinks {
+        let span = info_span!(
+          "publish_events",
+          sink = sink.name(),
+          count = events.len()
+        );
+        let res = sink.publish(&events).instrument(span).await;
+        if let Err(err) = res {
+          error!(
+            "failed to publish {} events to the {} event sink: {err:#}",
+            events.len(),
+            sink.name()
+          );
+        }
+      }
+      events.clear();
+    }
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::DomainEvent;
+  use super::Event;
+  use crate::db::WebhookEvent;
+  use crate::ids::PackageName;
+  use crate::ids::ScopeName;
+  use crate::ids::Version;
+  use serde_json::json;
+
+  #[test]
+  fn serialize() {
+    let user_id = uuid::Uuid::new_v4();
+    let event = Event::new(DomainEvent::VersionPublished {
+      scope: ScopeName::try_from("scope").unwrap(),
+      package: PackageName::try_from("foo").unwrap(),
+      version: Version::try_from("1.2.3").unwrap(),
+      user_id: Some(user_id),
+    });
+    let value = serde_json::to_value(&event).unwrap();
+    assert_eq!(
+      value,
+      json!({
+        "id": event.id,
+        "createdAt": event.created_at,
+        "type": "versionPublished",
+        "scope": "scope",
+        "package": "foo",
+        "version": "1.2.3",
+        "userId": user_id,
+      })
+    );
+    assert_eq!(value["type"], event.event.name());
+
+    let (scope, webhook_event, data) = event.event.webhook().unwrap();
+    assert_eq!(scope.to_string(), "scope");
+    assert_eq!(webhook_event, WebhookEvent::VersionPublished);
+    assert_eq!(
+      data,
+      json!({
+        "package": "@scope/foo",
+        "version": "1.2.3",
+        "userId": user_id,
+      })
+    );
+
+    let event = DomainEvent::TokenUsed {
+      token_id: uuid::Uuid::new_v4(),
+      user_id,
+    };
+    assert_eq!(serde_json::to_value(&event).unwrap()["type"], event.name());
+    assert!(event.webhook().is_none());
+  }
+}
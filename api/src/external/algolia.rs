@@ -5,6 +5,7 @@ use std::sync::Arc;
 use crate::api::ApiPackageScore;
 use crate::db::Package;
 use crate::db::PackageVersionMeta;
+use crate::db::PackageVisibility;
 use crate::ids::PackageName;
 use crate::ids::ScopeName;
 use percent_encoding::NON_ALPHANUMERIC;
@@ -64,6 +65,11 @@ impl AlgoliaClient {
 
   #[instrument(name = "AlgoliaClient::upsert_package", skip(self))]
   pub fn upsert_package(&self, package: &Package, meta: &PackageVersionMeta) {
+    if package.visibility == PackageVisibility::Private {
+      self.delete_package(&package.scope, &package.name);
+      return;
+    }
+
     if package.version_count == 0 || package.is_archived {
       return;
     }
@@ -83,6 +89,7 @@ impl AlgoliaClient {
       "scope": &package.scope,
       "name": &package.name,
       "description": &package.description,
+      "localizedDescriptions": &package.localized_descriptions,
       "runtimeCompat": &package.runtime_compat,
       "score": score,
     });
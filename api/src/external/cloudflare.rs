@@ -118,53 +118,23 @@ impl TurnstileClient {
   }
 }
 
-/// Client for the Cloudflare zone cache-purge endpoint, used to invalidate
-/// cached package and npm version manifests after a publish or mutation.
-///
-/// Construction requires both a zone ID and an API token; if either is
-/// missing the API server simply does not build a client and all purge
-/// calls become no-ops.
+/// Client for the Cloudflare zone cache-purge endpoint, used by
+/// [CachePurge](crate::cache_purge::CachePurge) when the registry is served
+/// through Cloudflare.
 #[derive(Clone)]
-pub struct CachePurgeClient {
+pub struct CloudflarePurgeClient {
   zone_id: String,
   api_token: String,
 }
 
-/// Wrapper around an optional `CachePurgeClient` so it can be stored in
-/// the routerify data map alongside other shared services. A `None`
-/// value means cache purging is disabled (e.g. local dev), and call
-/// sites should treat it as a no-op.
-#[derive(Clone)]
-pub struct CachePurge(pub Option<CachePurgeClient>);
-
-impl CachePurge {
-  /// Purge `urls` if a client is configured. Errors are logged inside
-  /// `purge_urls` and converted into `Ok(())` here, since callers want
-  /// best-effort behaviour (the manifests have `stale-while-revalidate`
-  /// as their durability net).
-  pub async fn purge(&self, urls: Vec<String>) {
-    let Some(client) = &self.0 else {
-      return;
-    };
-    let _ = client.purge_urls(urls).await;
-  }
-}
-
-impl CachePurgeClient {
+impl CloudflarePurgeClient {
   pub fn new(zone_id: String, api_token: String) -> Self {
     Self { zone_id, api_token }
   }
 
   /// Purge a set of fully-qualified URLs from the Cloudflare zone cache.
-  ///
-  /// Errors are logged and returned — callers should treat purge as
-  /// best-effort and not fail the publish on a purge failure (the
-  /// `stale-while-revalidate` window on the manifests is the safety net).
   #[instrument(name = "cloudflare.purge_cache", skip(self, urls), err)]
-  pub async fn purge_urls(
-    &self,
-    urls: Vec<String>,
-  ) -> Result<(), anyhow::Error> {
+  pub async fn purge_urls(&self, urls: &[String]) -> Result<(), anyhow::Error> {
     if urls.is_empty() {
       return Ok(());
     }
@@ -211,6 +181,9 @@ pub struct DownloadRecord {
   pub package: String,
   // because 'version' is reserved in cloudflare analytics engine
   pub ver: String,
+  /// The path of the fetched file, only selected for module downloads.
+  #[serde(default)]
+  pub path: Option<String>,
   pub count: String,
 }
 
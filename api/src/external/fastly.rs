@@ -0,0 +1,73 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+use tracing::error;
+use tracing::instrument;
+
+/// Client for the Fastly purge API, used by
+/// [CachePurge](crate::cache_purge::CachePurge) when the registry is served
+/// through Fastly.
+#[derive(Clone)]
+pub struct FastlyPurgeClient {
+  api_token: String,
+}
+
+impl FastlyPurgeClient {
+  pub fn new(api_token: String) -> Self {
+    Self { api_token }
+  }
+
+  /// Purge a set of fully-qualified URLs from the Fastly cache. Fastly purges
+  /// a single URL per request, so one request is sent for each URL.
+  #[instrument(name = "fastly.purge_cache", skip(self, urls), err)]
+  pub async fn purge_urls(&self, urls: &[String]) -> Result<(), anyhow::Error> {
+    for url in urls {
+      let response = crate::util::shared_http_client()
+        .post(purge_endpoint(url))
+        .header("Fastly-Key", &self.api_token)
+        .header("Accept", "application/json")
+        .send()
+        .await?;
+
+      if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        error!(
+          "Fastly cache purge of {} failed (status={}): {}",
+          url, status, body
+        );
+        return Err(anyhow::anyhow!(
+          "Fastly cache purge of {} failed (status={}): {}",
+          url,
+          status,
+          body,
+        ));
+      }
+    }
+
+    Ok(())
+  }
+}
+
+/// The endpoint that purges `url`, which takes the URL without its scheme.
+fn purge_endpoint(url: &str) -> String {
+  let url = url
+    .strip_prefix("https://")
+    .or_else(|| url.strip_prefix("http://"))
+    .unwrap_or(url);
+  format!("https://api.fastly.com/purge/{url}")
+}
+
+#[cfg(test)]
+mod tests {
+  #[test]
+  fn purge_endpoint() {
+    assert_eq!(
+      super::purge_endpoint("https://jsr.io/api/scopes/std/packages/fs"),
+      "https://api.fastly.com/purge/jsr.io/api/scopes/std/packages/fs"
+    );
+    assert_eq!(
+      super::purge_endpoint("https://npm.jsr.io/@jsr/std__fs"),
+      "https://api.fastly.com/purge/npm.jsr.io/@jsr/std__fs"
+    );
+  }
+}
@@ -3,14 +3,10 @@
 use std::fmt::Display;
 use std::str::FromStr;
 
-use crate::api::ApiError;
-use crate::util::ApiResult;
 use crate::util::shared_http_client;
-use anyhow::Context;
 use hyper::StatusCode;
 use serde::Deserialize;
 use serde::Deserializer;
-use tracing::error;
 use tracing::instrument;
 
 pub struct GitHubUserClient {
@@ -203,11 +199,6 @@ where
   }
 }
 
-#[derive(Deserialize)]
-struct GitHubActionKeys {
-  keys: Vec<jsonwebkey::JsonWebKey>,
-}
-
 pub static GITHUB_OIDC_ISSUER: &str =
   "https://token.actions.githubusercontent.com";
 
@@ -219,58 +210,3 @@ pub struct GitHubClaims {
   pub actor_id: i64,
   pub aud: String,
 }
-
-#[instrument(name = "github::verify_oidc_token", err, skip(token))]
-pub async fn verify_oidc_token(token: &str) -> ApiResult<GitHubClaims> {
-  let url = format!("{GITHUB_OIDC_ISSUER}/.well-known/jwks");
-  let res = shared_http_client()
-    .get(url)
-    .header("Accept", "application/json")
-    .send()
-    .await
-    .context("failed to download github jwks")?;
-  let status = res.status();
-  if !status.is_success() {
-    let body = res.text().await.unwrap_or_default();
-    error!("failed to download github jwks: {body} (status: {status}) ");
-    return Err(ApiError::InternalServerError);
-  }
-  let GitHubActionKeys { keys } =
-    res.json().await.context("failed to parse github jwks")?;
-
-  let header = jsonwebtoken::decode_header(token).map_err(|err| {
-    ApiError::InvalidOidcToken {
-      msg: err.to_string().into(),
-    }
-  })?;
-  let kid = header.kid.ok_or(ApiError::InvalidOidcToken {
-    msg: "missing kid".into(),
-  })?;
-
-  let jwk = keys
-    .iter()
-    .find(|k| k.key_id.as_deref() == Some(&*kid))
-    .ok_or_else(|| ApiError::InvalidOidcToken {
-      msg: format!("invalid kid: {kid}").into(),
-    })?;
-
-  let alg: jsonwebtoken::Algorithm = jwk
-    .algorithm
-    .ok_or_else(|| {
-      error!("jwk {jwk:?} missing algorithm");
-      ApiError::InternalServerError
-    })?
-    .into();
-  let mut validation = jsonwebtoken::Validation::new(alg);
-  validation.set_issuer(&[GITHUB_OIDC_ISSUER]);
-  let decoded = jsonwebtoken::decode::<GitHubClaims>(
-    token,
-    &jwk.key.to_decoding_key(),
-    &validation,
-  )
-  .map_err(|err| ApiError::InvalidOidcToken {
-    msg: err.to_string().into(),
-  })?;
-
-  Ok(decoded.claims)
-}
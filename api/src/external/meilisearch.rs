@@ -0,0 +1,206 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+use std::time::Duration;
+use std::time::Instant;
+
+use reqwest::Method;
+use serde::Deserialize;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tracing::instrument;
+use url::Url;
+
+/// How often the status of a task is polled while waiting for it.
+const TASK_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A minimal client for the Meilisearch REST API, used by
+/// [MeilisearchBackend](crate::search::MeilisearchBackend). It only implements
+/// the operations that searching and indexing packages need.
+///
+/// Meilisearch applies all writes asynchronously: they return a task, which
+/// can be waited for with [MeilisearchClient::wait_for_task].
+#[derive(Clone)]
+pub struct MeilisearchClient {
+  url: Url,
+  api_key: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchRequest<'a> {
+  pub q: &'a str,
+  pub offset: i64,
+  pub limit: i64,
+  /// Conditions that all matches must fulfill.
+  pub filter: Vec<String>,
+  pub attributes_to_retrieve: &'a [&'a str],
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResponse<T> {
+  pub hits: Vec<T>,
+  pub estimated_total_hits: usize,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TaskInfo {
+  task_uid: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Task {
+  status: String,
+  error: Option<TaskError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TaskError {
+  message: String,
+}
+
+impl MeilisearchClient {
+  pub fn new(url: Url, api_key: Option<String>) -> Self {
+    Self { url, api_key }
+  }
+
+  fn request(&self, method: Method, path: &str) -> reqwest::RequestBuilder {
+    let url = format!("{}{path}", self.url.as_str().trim_end_matches('/'));
+    let req = crate::util::shared_http_client().request(method, url);
+    match &self.api_key {
+      Some(api_key) => req.bearer_auth(api_key),
+      None => req,
+    }
+  }
+
+  async fn send<T: DeserializeOwned>(
+    &self,
+    req: reqwest::RequestBuilder,
+  ) -> Result<T, anyhow::Error> {
+    let res = req.send().await?;
+    if !res.status().is_success() {
+      let status = res.status();
+      let body = res.text().await.unwrap_or_default();
+      anyhow::bail!("Meilisearch request failed (status={status}): {body}");
+    }
+    Ok(res.json().await?)
+  }
+
+  async fn send_task(
+    &self,
+    req: reqwest::RequestBuilder,
+  ) -> Result<u64, anyhow::Error> {
+    let info: TaskInfo = self.send(req).await?;
+    Ok(info.task_uid)
+  }
+
+  #[instrument(name = "meilisearch.search", skip(self, search), err)]
+  pub async fn search<T: DeserializeOwned>(
+    &self,
+    index: &str,
+    search: &SearchRequest<'_>,
+  ) -> Result<SearchResponse<T>, anyhow::Error> {
+    let path = format!("/indexes/{index}/search");
+    self
+      .send(self.request(Method::POST, &path).json(search))
+      .await
+  }
+
+  /// Adds documents to `index`, replacing those with the same primary key.
+  #[instrument(name = "meilisearch.add_documents", skip(self, documents), err)]
+  pub async fn add_documents<T: Serialize>(
+    &self,
+    index: &str,
+    documents: &[T],
+  ) -> Result<u64, anyhow::Error> {
+    let path = format!("/indexes/{index}/documents");
+    self
+      .send_task(self.request(Method::POST, &path).json(documents))
+      .await
+  }
+
+  #[instrument(name = "meilisearch.delete_document", skip(self), err)]
+  pub async fn delete_document(
+    &self,
+    index: &str,
+    id: &str,
+  ) -> Result<u64, anyhow::Error> {
+    let path = format!("/indexes/{index}/documents/{id}");
+    self.send_task(self.request(Method::DELETE, &path)).await
+  }
+
+  /// Creates an index. The task fails if the index already exists.
+  #[instrument(name = "meilisearch.create_index", skip(self), err)]
+  pub async fn create_index(
+    &self,
+    index: &str,
+    primary_key: &str,
+  ) -> Result<u64, anyhow::Error> {
+    let body = serde_json::json!({ "uid": index, "primaryKey": primary_key });
+    self
+      .send_task(self.request(Method::POST, "/indexes").json(&body))
+      .await
+  }
+
+  /// Deletes an index. The task fails if the index does not exist.
+  #[instrument(name = "meilisearch.delete_index", skip(self), err)]
+  pub async fn delete_index(&self, index: &str) -> Result<u64, anyhow::Error> {
+    let path = format!("/indexes/{index}");
+    self.send_task(self.request(Method::DELETE, &path)).await
+  }
+
+  #[instrument(name = "meilisearch.update_settings", skip(self, settings), err)]
+  pub async fn update_settings(
+    &self,
+    index: &str,
+    settings: &serde_json::Value,
+  ) -> Result<u64, anyhow::Error> {
+    let path = format!("/indexes/{index}/settings");
+    self
+      .send_task(self.request(Method::PATCH, &path).json(settings))
+      .await
+  }
+
+  /// Swaps the documents and settings of two indexes, atomically.
+  #[instrument(name = "meilisearch.swap_indexes", skip(self), err)]
+  pub async fn swap_indexes(
+    &self,
+    a: &str,
+    b: &str,
+  ) -> Result<u64, anyhow::Error> {
+    let body = serde_json::json!([{ "indexes": [a, b] }]);
+    self
+      .send_task(self.request(Method::POST, "/swap-indexes").json(&body))
+      .await
+  }
+
+  /// Waits until a task is processed, for at most `timeout`, and returns an
+  /// error if it failed.
+  #[instrument(name = "meilisearch.wait_for_task", skip(self), err)]
+  pub async fn wait_for_task(
+    &self,
+    task_uid: u64,
+    timeout: Duration,
+  ) -> Result<(), anyhow::Error> {
+    let deadline = Instant::now() + timeout;
+    let path = format!("/tasks/{task_uid}");
+    loop {
+      let task: Task = self.send(self.request(Method::GET, &path)).await?;
+      match task.status.as_str() {
+        "succeeded" => return Ok(()),
+        "failed" | "canceled" => {
+          let message = task.error.map(|err| err.message).unwrap_or_default();
+          anyhow::bail!(
+            "Meilisearch task {task_uid} {}: {message}",
+            task.status
+          );
+        }
+        _ if Instant::now() >= deadline => {
+          anyhow::bail!("timed out waiting for Meilisearch task {task_uid}");
+        }
+        _ => tokio::time::sleep(TASK_POLL_INTERVAL).await,
+      }
+    }
+  }
+}
@@ -4,8 +4,11 @@ use percent_encoding::CONTROLS;
 
 pub mod algolia;
 pub mod cloudflare;
+pub mod fastly;
 pub mod github;
 pub mod gitlab;
+pub mod meilisearch;
+pub mod rekor;
 
 /// https://url.spec.whatwg.org/#fragment-percent-encode-set
 const FRAGMENT: &AsciiSet =
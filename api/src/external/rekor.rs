@@ -0,0 +1,188 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+use std::collections::HashMap;
+
+use base64::Engine as _;
+use base64::prelude::BASE64_STANDARD;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::json;
+use tracing::instrument;
+
+use crate::util::shared_http_client;
+
+/// The public Sigstore transparency log.
+const REKOR_URL: &str = "https://rekor.sigstore.dev";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LogEntry {
+  body: String,
+  integrated_time: u64,
+  #[serde(rename = "logID")]
+  log_id: String,
+  log_index: u64,
+  verification: LogEntryVerification,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LogEntryVerification {
+  inclusion_proof: InclusionProof,
+  signed_entry_timestamp: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InclusionProof {
+  checkpoint: String,
+  hashes: Vec<String>,
+  log_index: u64,
+  root_hash: String,
+  tree_size: u64,
+}
+
+/// A transparency log entry, in the form Sigstore bundles (version 0.2)
+/// embed it.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TlogEntry {
+  pub log_index: String,
+  pub log_id: TlogEntryLogId,
+  pub kind_version: TlogEntryKindVersion,
+  pub integrated_time: String,
+  pub inclusion_promise: TlogEntryInclusionPromise,
+  pub inclusion_proof: TlogEntryInclusionProof,
+  pub canonicalized_body: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TlogEntryLogId {
+  pub key_id: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TlogEntryKindVersion {
+  pub kind: String,
+  pub version: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TlogEntryInclusionPromise {
+  pub signed_entry_timestamp: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TlogEntryInclusionProof {
+  pub log_index: String,
+  pub root_hash: String,
+  pub tree_size: String,
+  pub hashes: Vec<String>,
+  pub checkpoint: TlogEntryCheckpoint,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TlogEntryCheckpoint {
+  pub envelope: String,
+}
+
+/// Rekor returns hashes and log IDs hex encoded, while bundles carry them
+/// base64 encoded.
+fn hex_to_base64(hex: &str) -> Result<String, anyhow::Error> {
+  if hex.len() % 2 != 0 || !hex.is_ascii() {
+    anyhow::bail!("invalid hex string from Rekor: {hex}");
+  }
+  let bytes = (0..hex.len())
+    .step_by(2)
+    .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+    .collect::<Result<Vec<_>, _>>()?;
+  Ok(BASE64_STANDARD.encode(bytes))
+}
+
+/// Records a DSSE envelope, signed by `public_key_pem`, in the transparency
+/// log and returns the resulting entry.
+#[instrument(name = "rekor.create_dsse_entry", skip_all, err)]
+pub async fn create_dsse_entry(
+  envelope: &str,
+  public_key_pem: &str,
+) -> Result<TlogEntry, anyhow::Error> {
+  let body = json!({
+    "apiVersion": "0.0.1",
+    "kind": "dsse",
+    "spec": {
+      "proposedContent": {
+        "envelope": envelope,
+        "verifiers": [BASE64_STANDARD.encode(public_key_pem)],
+      },
+    },
+  });
+
+  let response = shared_http_client()
+    .post(format!("{REKOR_URL}/api/v1/log/entries"))
+    .json(&body)
+    .send()
+    .await?;
+
+  let status = response.status();
+  if !status.is_success() {
+    let body = response.text().await.unwrap_or_default();
+    return Err(anyhow::anyhow!(
+      "failed to create Rekor entry (status {status}): {body}"
+    ));
+  }
+
+  // The response maps the UUID of the new entry to the entry itself.
+  let entries: HashMap<String, LogEntry> = response.json().await?;
+  let entry = entries
+    .into_values()
+    .next()
+    .ok_or_else(|| anyhow::anyhow!("Rekor returned no entry"))?;
+
+  let proof = entry.verification.inclusion_proof;
+  Ok(TlogEntry {
+    log_index: entry.log_index.to_string(),
+    log_id: TlogEntryLogId {
+      key_id: hex_to_base64(&entry.log_id)?,
+    },
+    kind_version: TlogEntryKindVersion {
+      kind: "dsse".to_string(),
+      version: "0.0.1".to_string(),
+    },
+    integrated_time: entry.integrated_time.to_string(),
+    inclusion_promise: TlogEntryInclusionPromise {
+      signed_entry_timestamp: entry.verification.signed_entry_timestamp,
+    },
+    inclusion_proof: TlogEntryInclusionProof {
+      log_index: proof.log_index.to_string(),
+      root_hash: hex_to_base64(&proof.root_hash)?,
+      tree_size: proof.tree_size.to_string(),
+      hashes: proof
+        .hashes
+        .iter()
+        .map(|hash| hex_to_base64(hash))
+        .collect::<Result<_, _>>()?,
+      checkpoint: TlogEntryCheckpoint {
+        envelope: proof.checkpoint,
+      },
+    },
+    canonicalized_body: entry.body,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::hex_to_base64;
+
+  #[test]
+  fn hex_to_base64_converts_rekor_hashes() {
+    assert_eq!(hex_to_base64("fbff").unwrap(), "+/8=");
+    assert_eq!(hex_to_base64("").unwrap(), "");
+    assert!(hex_to_base64("abc").is_err());
+    assert!(hex_to_base64("zz").is_err());
+  }
+}
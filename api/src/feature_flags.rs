@@ -0,0 +1,71 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+//! Feature flags, to roll out features gradually.
+//!
+//! A flag is on for everyone once it is enabled. Until then, it can be turned
+//! on for individual scopes and users. Flags that don't exist are off.
+//!
+//! The flags are stored in the database and managed by staff through the admin
+//! API. Each API instance caches them for up to a minute.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use uuid::Uuid;
+
+use crate::db::Database;
+use crate::db::FeatureFlag;
+use crate::ids::ScopeName;
+
+/// Allows published modules to import files as bytes.
+pub const UNSTABLE_BYTES_IMPORTS: &str = "unstable-bytes-imports";
+
+#[derive(Clone)]
+pub struct FeatureFlags {
+  flags: moka::future::Cache<(), Arc<HashMap<String, FeatureFlag>>>,
+}
+
+impl FeatureFlags {
+  pub fn new() -> Self {
+    Self {
+      flags: moka::future::Cache::builder()
+        .max_capacity(1)
+        .time_to_live(Duration::from_secs(60))
+        .build(),
+    }
+  }
+
+  /// Whether the flag `name` is on for an action on `scope` by `user_id`.
+  pub async fn is_enabled(
+    &self,
+    db: &Database,
+    name: &str,
+    scope: Option<&ScopeName>,
+    user_id: Option<Uuid>,
+  ) -> Result<bool, anyhow::Error> {
+    let flags = self
+      .flags
+      .try_get_with((), async {
+        let flags = db.list_feature_flags().await?;
+        Ok::<_, sqlx::Error>(Arc::new(
+          flags
+            .into_iter()
+            .map(|flag| (flag.name.clone(), flag))
+            .collect(),
+        ))
+      })
+      .await
+      .map_err(|err| anyhow::anyhow!(err))?;
+    Ok(
+      flags
+        .get(name)
+        .is_some_and(|flag| flag.is_enabled_for(scope, user_id)),
+    )
+  }
+
+  /// Makes the next check reload the flags. Other instances pick up the
+  /// change once their cache expires.
+  pub async fn invalidate(&self) {
+    self.flags.invalidate(&()).await;
+  }
+}
@@ -176,3 +176,53 @@ impl Queue {
     Ok(())
   }
 }
+
+/// A Pub/Sub topic, as `projects/{project}/topics/{topic}`.
+#[derive(Clone)]
+pub struct Topic {
+  pub(crate) client: Client,
+  pub(crate) id: String,
+  pub(crate) endpoint: String,
+}
+
+impl Topic {
+  pub fn new(client: Client, id: String, endpoint: Option<String>) -> Self {
+    Self {
+      client,
+      id,
+      endpoint: endpoint
+        .unwrap_or_else(|| "https://pubsub.googleapis.com".into()),
+    }
+  }
+
+  /// Publishes `messages`, `PubsubMessage` objects with base64 encoded data.
+  #[instrument(
+    "gcp::Topic::publish",
+    skip(self, messages),
+    err,
+    fields(topic_id = self.id)
+  )]
+  pub async fn publish(
+    &self,
+    messages: Vec<serde_json::Value>,
+  ) -> Result<(), anyhow::Error> {
+    let url = format!("{}/v1/{}:publish", self.endpoint, self.id);
+    let token = self.client.get_access_token().await?;
+    let resp = self
+      .client
+      .http()
+      .post(url)
+      .bearer_auth(token)
+      .json(&serde_json::json!({ "messages": messages }))
+      .send()
+      .await?;
+    let status = resp.status();
+    if status != StatusCode::OK {
+      let body = resp.text().await?;
+      return Err(anyhow::anyhow!(
+        "Failed to publish messages (status={status}): {body}"
+      ));
+    }
+    Ok(())
+  }
+}
@@ -1,4 +1,7 @@
 // Copyright 2024 the JSR authors. All rights reserved. MIT license.
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::Utc;
 use hyper::Body;
 use hyper::Request;
 use routerify::prelude::RequestExt;
@@ -6,15 +9,19 @@ use uuid::Uuid;
 
 use crate::api::ApiError;
 use crate::db::Database;
+use crate::db::PackagePermission;
 use crate::db::PackagePublishPermission;
 use crate::db::Permission;
 use crate::db::Permissions;
+use crate::db::ScopeMember;
 use crate::db::Token;
 use crate::db::TokenType;
 use crate::db::User;
 use crate::ids::PackageName;
 use crate::ids::ScopeName;
 use crate::ids::Version;
+use crate::oidc;
+use crate::oidc::OidcPublisher;
 use crate::util::GithubOidcTokenAud;
 
 pub struct IamHandler<'s> {
@@ -23,6 +30,7 @@ pub struct IamHandler<'s> {
   permissions: Option<Permissions>,
   interactive: bool,
   sudo: bool,
+  member_cooldown: &'s MemberCooldown,
 }
 
 impl<'s> IamHandler<'s> {
@@ -30,11 +38,21 @@ impl<'s> IamHandler<'s> {
     matches!(self.principal, Principal::Anonymous)
   }
 
+  /// Whether the permissions restrict the request to specific actions.
+  /// Read-only permissions don't count, as the auth middleware already
+  /// rejects every request made with them that could change anything.
+  fn is_restricted(&self) -> bool {
+    self
+      .permissions
+      .as_ref()
+      .is_some_and(|permissions| !permissions.is_read_only())
+  }
+
   pub async fn check_scope_write_access(
     &self,
     scope: &ScopeName,
   ) -> Result<(&User, bool), ApiError> {
-    if self.permissions.is_some() {
+    if self.is_restricted() {
       // There is no specific permission that allows scope write access, so if
       // the permissions are restricted, this action is also restricted.
       return Err(ApiError::MissingPermission);
@@ -50,7 +68,9 @@ impl<'s> IamHandler<'s> {
           Err(ApiError::ActorNotScopeMember)
         }
       }
-      Principal::GitHubActions { .. } => Err(ApiError::ActorNotAuthorized),
+      Principal::GitHubActions { .. } | Principal::Oidc(_) => {
+        Err(ApiError::ActorNotAuthorized)
+      }
       Principal::Anonymous => Err(ApiError::MissingAuthentication),
     }
   }
@@ -61,7 +81,7 @@ impl<'s> IamHandler<'s> {
     &self,
     scope: &ScopeName,
   ) -> Result<(&User, bool), ApiError> {
-    if self.permissions.is_some() {
+    if self.is_restricted() {
       // There is no specific permission that allows scope admin access, so if
       // the permissions are restricted, this action is also restricted.
       return Err(ApiError::MissingPermission);
@@ -91,16 +111,56 @@ impl<'s> IamHandler<'s> {
           Ok((user, false))
         }
       }
-      Principal::GitHubActions { .. } => Err(ApiError::ActorNotAuthorized),
+      Principal::GitHubActions { .. } | Principal::Oidc(_) => {
+        Err(ApiError::ActorNotAuthorized)
+      }
       Principal::Anonymous => Err(ApiError::MissingAuthentication),
     }
   }
 
+  /// Checks if the principal may read the private packages of a scope, which
+  /// members of the scope and staff using sudo can. Tokens with restricted
+  /// permissions must either be read-only or grant publishing to something in
+  /// the scope. Everyone else is told that the package does not exist, so that
+  /// private packages can't be discovered.
+  pub async fn check_private_package_read_access(
+    &self,
+    scope_: &ScopeName,
+  ) -> Result<(), ApiError> {
+    if let Some(permissions) = &self.permissions {
+      let permitted = permissions.0.iter().any(|permission| match permission {
+        Permission::Read => true,
+        Permission::PackagePublish(
+          PackagePublishPermission::Version { scope, .. }
+          | PackagePublishPermission::Package { scope, .. }
+          | PackagePublishPermission::Scope { scope },
+        ) => scope == scope_,
+      });
+      if !permitted {
+        return Err(ApiError::PackageNotFound);
+      }
+    }
+
+    match &self.principal {
+      Principal::User(user) if user.is_staff && self.sudo => Ok(()),
+      Principal::User(user) => {
+        if self.db.get_scope_member(scope_, user.id).await?.is_some() {
+          Ok(())
+        } else {
+          Err(ApiError::PackageNotFound)
+        }
+      }
+      Principal::GitHubActions { .. }
+      | Principal::Oidc(_)
+      | Principal::Anonymous => Err(ApiError::PackageNotFound),
+    }
+  }
+
   pub async fn check_scope_member_delete_access(
     &self,
     scope: &ScopeName,
     member_id: Uuid,
-  ) -> Result<(), ApiError> {
+  ) -> Result<(&User, bool), ApiError> {
     if self.permissions.is_some() {
       // There is no specific permission that allows scope admin access, so if
       // the permissions are restricted, this action is also restricted.
@@ -108,7 +168,7 @@ impl<'s> IamHandler<'s> {
     }
 
     match &self.principal {
-      Principal::User(user) if user.is_staff && self.sudo => Ok(()),
+      Principal::User(user) if user.is_staff && self.sudo => Ok((user, true)),
       Principal::User(user) => {
         let scope_member = self
           .db
@@ -118,13 +178,208 @@ impl<'s> IamHandler<'s> {
         if user.id != member_id && !scope_member.is_admin {
           return Err(ApiError::ActorNotScopeAdmin);
         }
-        Ok(())
+        Ok((user, false))
+      }
+      Principal::GitHubActions { .. } | Principal::Oidc(_) => {
+        Err(ApiError::ActorNotAuthorized)
       }
-      Principal::GitHubActions { .. } => Err(ApiError::ActorNotAuthorized),
       Principal::Anonymous => Err(ApiError::MissingAuthentication),
     }
   }
 
+  /// Checks if the user may perform an action on a package that requires
+  /// the given permission, as granted by the teams of the scope. This also
+  /// rejects scope members that are still in their new member cooldown for
+  /// the package.
+  pub async fn check_package_permission(
+    &self,
+    scope: &ScopeName,
+    package: &PackageName,
+    permission: PackagePermission,
+  ) -> Result<(&User, bool), ApiError> {
+    if self.is_restricted() {
+      // Publish permissions are only checked when publishing, so if the
+      // permissions are restricted, this action is also restricted.
+      return Err(ApiError::MissingPermission);
+    }
+
+    match &self.principal {
+      Principal::User(user) => {
+        let res = match self.db.get_scope_member(scope, user.id).await? {
+          Some(member) => {
+            self
+              .check_member_package_permission(&member, package, permission)
+              .await
+          }
+          None => Err(ApiError::ActorNotScopeMember),
+        };
+        match res {
+          Ok(()) => {
+            self
+              .check_user_member_cooldown(scope, package, user)
+              .await?;
+            Ok((user, false))
+          }
+          Err(_) if user.is_staff && self.sudo => Ok((user, true)),
+          Err(err) => Err(err),
+        }
+      }
+      Principal::GitHubActions { .. } | Principal::Oidc(_) => {
+        Err(ApiError::ActorNotAuthorized)
+      }
+      Principal::Anonymous => Err(ApiError::MissingAuthentication),
+    }
+  }
+
+  /// Scope admins may do anything with the packages of the scope. Other
+  /// members may publish packages, unless teams were granted permissions on
+  /// the package: then they may only do what one of their teams grants them.
+  async fn check_member_package_permission(
+    &self,
+    member: &ScopeMember,
+    package: &PackageName,
+    permission: PackagePermission,
+  ) -> Result<(), ApiError> {
+    if member.is_admin {
+      return Ok(());
+    }
+    let access = self
+      .db
+      .get_package_team_access(&member.scope, package, member.user_id)
+      .await?;
+    match access {
+      Some(access) if access.allows(permission) => Ok(()),
+      Some(_) => Err(ApiError::ActorMissingPackagePermission { permission }),
+      None if permission == PackagePermission::Publish => Ok(()),
+      None => Err(ApiError::ActorNotScopeAdmin),
+    }
+  }
+
+  /// Checks if the user may create an access token that publishes a package.
+  /// This is only restricted for packages that teams were granted
+  /// permissions on, to members of the scope that are not in a team that
+  /// grants managing tokens.
+  pub async fn check_package_token_access(
+    &self,
+    scope: &ScopeName,
+    package: &PackageName,
+  ) -> Result<(), ApiError> {
+    let Principal::User(user) = &self.principal else {
+      return Err(ApiError::ActorNotUser);
+    };
+    let Some(member) = self.db.get_scope_member(scope, user.id).await? else {
+      return Ok(());
+    };
+    if member.is_admin {
+      return Ok(());
+    }
+    let access = self
+      .db
+      .get_package_team_access(scope, package, user.id)
+      .await?;
+    match access {
+      Some(access) if !access.can_manage_tokens => {
+        Err(ApiError::ActorMissingPackagePermission {
+          permission: PackagePermission::ManageTokens,
+        })
+      }
+      _ => Ok(()),
+    }
+  }
+
+  /// Like [`Self::check_scope_admin_access`], but additionally rejects scope
+  /// admins that are still in their new member cooldown for the package.
+  pub async fn check_package_admin_access(
+    &self,
+    scope: &ScopeName,
+    package: &PackageName,
+  ) -> Result<(&User, bool), ApiError> {
+    let (user, sudo) = self.check_scope_admin_access(scope).await?;
+    if !sudo {
+      self
+        .check_user_member_cooldown(scope, package, user)
+        .await?;
+    }
+    Ok((user, sudo))
+  }
+
+  async fn check_user_member_cooldown(
+    &self,
+    scope: &ScopeName,
+    package: &PackageName,
+    user: &User,
+  ) -> Result<(), ApiError> {
+    let member = self
+      .db
+      .get_scope_member(scope, user.id)
+      .await?
+      .ok_or(ApiError::ActorNotScopeMember)?;
+    self.check_member_cooldown(&member, package).await
+  }
+
+  /// Returns when the new member cooldown of the scope member ends, or `None`
+  /// if the cooldown is disabled, already over, or was lifted by an admin.
+  pub async fn member_cooldown_until(
+    &self,
+    member: &ScopeMember,
+  ) -> Result<Option<DateTime<Utc>>, ApiError> {
+    if self.member_cooldown.hours == 0 {
+      return Ok(None);
+    }
+    let until =
+      member.created_at + Duration::hours(self.member_cooldown.hours as i64);
+    if until <= Utc::now() {
+      return Ok(None);
+    }
+    if self
+      .db
+      .is_scope_member_cooldown_lifted(&member.scope, member.user_id)
+      .await?
+    {
+      return Ok(None);
+    }
+    Ok(Some(until))
+  }
+
+  /// Members in their cooldown may only touch packages that were created after
+  /// they joined, or that are not popular enough to be worth taking over.
+  async fn check_member_cooldown(
+    &self,
+    member: &ScopeMember,
+    package: &PackageName,
+  ) -> Result<(), ApiError> {
+    let Some(until) = self.member_cooldown_until(member).await? else {
+      return Ok(());
+    };
+    let Some((package, _, _)) =
+      self.db.get_package(&member.scope, package).await?
+    else {
+      return Ok(());
+    };
+    if package.created_at >= member.created_at {
+      return Ok(());
+    }
+    let now = Utc::now();
+    let weekly_downloads: i64 = self
+      .db
+      .get_package_downloads_24h(
+        &member.scope,
+        &package.name,
+        now - Duration::days(7),
+        now,
+      )
+      .await?
+      .iter()
+      .map(|point| point.count)
+      .sum();
+    if weekly_downloads < self.member_cooldown.min_weekly_downloads {
+      return Ok(());
+    }
+    Err(ApiError::ScopeMemberInCooldown {
+      until: until.to_rfc3339(),
+    })
+  }
+
   pub async fn check_publish_access(
     &self,
     scope_: &ScopeName,
@@ -180,11 +435,19 @@ impl<'s> IamHandler<'s> {
         if scope.require_publishing_from_ci {
           return Err(ApiError::ScopeRequiresPublishingFromCI);
         }
-        self
+        let member = self
           .db
           .get_scope_member(scope_, user.id)
           .await?
           .ok_or(ApiError::ActorNotScopeMember)?;
+        self
+          .check_member_package_permission(
+            &member,
+            package_,
+            PackagePermission::Publish,
+          )
+          .await?;
+        self.check_member_cooldown(&member, package_).await?;
         Ok((access_restriction, Some(user.id)))
       }
       Principal::GitHubActions { repo_id, user } => {
@@ -195,11 +458,19 @@ impl<'s> IamHandler<'s> {
           .ok_or(ApiError::ScopeNotFound)?;
         if scope.verify_oidc_actor {
           let user = user.as_ref().ok_or(ApiError::ActorNotScopeMember)?;
-          self
+          let member = self
             .db
             .get_scope_member(scope_, user.id)
             .await?
             .ok_or(ApiError::ActorNotScopeMember)?;
+          self
+            .check_member_package_permission(
+              &member,
+              package_,
+              PackagePermission::Publish,
+            )
+            .await?;
+          self.check_member_cooldown(&member, package_).await?;
         }
         let (package, _, _) = self
           .db
@@ -211,19 +482,119 @@ impl<'s> IamHandler<'s> {
         }
         Ok((access_restriction, user.as_ref().map(|user| user.id)))
       }
+      Principal::Oidc(publisher) => {
+        let scope = self
+          .db
+          .get_scope(scope_)
+          .await?
+          .ok_or(ApiError::ScopeNotFound)?;
+        // Trusted publishers are not linked to a user that could be verified
+        // to be a scope member.
+        if scope.verify_oidc_actor {
+          return Err(ApiError::ActorNotScopeMember);
+        }
+        let trusted = self
+          .db
+          .has_package_trusted_publisher(
+            scope_,
+            package_,
+            &publisher.issuer,
+            &publisher.subject,
+            oidc::is_builtin_issuer(&publisher.issuer),
+          )
+          .await?;
+        if !trusted {
+          return Err(ApiError::ActorNotAuthorized);
+        }
+        Ok((access_restriction, None))
+      }
+      Principal::Anonymous => Err(ApiError::MissingAuthentication),
+    }
+  }
+
+  /// Checks if the dist tags of a package may be moved. This is allowed for
+  /// everyone who may publish any version of the package, including tokens
+  /// with a publish permission for the package or its scope. GitHub Actions
+  /// must be linked to a user, who the change is attributed to.
+  pub async fn check_dist_tag_access(
+    &self,
+    scope_: &ScopeName,
+    package_: &PackageName,
+  ) -> Result<(&User, bool), ApiError> {
+    if let Some(permissions) = &self.permissions {
+      let permitted = permissions.0.iter().any(|permission| match permission {
+        Permission::PackagePublish(PackagePublishPermission::Package {
+          scope,
+          package,
+        }) => scope == scope_ && package == package_,
+        Permission::PackagePublish(PackagePublishPermission::Scope {
+          scope,
+        }) => scope == scope_,
+        _ => false,
+      });
+      if !permitted {
+        return Err(ApiError::MissingPermission);
+      }
+    }
+    match &self.principal {
+      Principal::User(user) if user.is_staff && self.sudo => Ok((user, true)),
+      Principal::User(user) => {
+        let member = self
+          .db
+          .get_scope_member(scope_, user.id)
+          .await?
+          .ok_or(ApiError::ActorNotScopeMember)?;
+        self
+          .check_member_package_permission(
+            &member,
+            package_,
+            PackagePermission::Publish,
+          )
+          .await?;
+        self.check_member_cooldown(&member, package_).await?;
+        Ok((user, false))
+      }
+      Principal::GitHubActions { repo_id, user } => {
+        let user = user.as_ref().ok_or(ApiError::ActorNotAuthorized)?;
+        let member = self
+          .db
+          .get_scope_member(scope_, user.id)
+          .await?
+          .ok_or(ApiError::ActorNotScopeMember)?;
+        self
+          .check_member_package_permission(
+            &member,
+            package_,
+            PackagePermission::Publish,
+          )
+          .await?;
+        self.check_member_cooldown(&member, package_).await?;
+        let (package, _, _) = self
+          .db
+          .get_package(scope_, package_)
+          .await?
+          .ok_or(ApiError::PackageNotFound)?;
+        if package.github_repository_id != Some(*repo_id) {
+          return Err(ApiError::ActorNotAuthorized);
+        }
+        Ok((user, false))
+      }
+      Principal::Oidc(_) => Err(ApiError::ActorNotAuthorized),
       Principal::Anonymous => Err(ApiError::MissingAuthentication),
     }
   }
 
   pub fn check_current_user_access(&self) -> Result<&User, ApiError> {
-    if self.permissions.is_some() {
+    if self.is_restricted() {
       // There is no specific permission that allows access to current user, so
       // if the permissions are restricted, this action is also restricted.
       return Err(ApiError::MissingPermission);
     }
     match &self.principal {
       Principal::User(user) => Ok(user),
-      Principal::GitHubActions { .. } => Err(ApiError::ActorNotUser),
+      Principal::GitHubActions { .. } | Principal::Oidc(_) => {
+        Err(ApiError::ActorNotUser)
+      }
       Principal::Anonymous => Err(ApiError::MissingAuthentication),
     }
   }
@@ -238,7 +609,9 @@ impl<'s> IamHandler<'s> {
     match &self.principal {
       Principal::User(user) if self.interactive => Ok(user),
       Principal::User(_) => Err(ApiError::CredentialNotInteractive),
-      Principal::GitHubActions { .. } => Err(ApiError::ActorNotUser),
+      Principal::GitHubActions { .. } | Principal::Oidc(_) => {
+        Err(ApiError::ActorNotUser)
+      }
       Principal::Anonymous => Err(ApiError::MissingAuthentication),
     }
   }
@@ -247,12 +620,25 @@ impl<'s> IamHandler<'s> {
     match &self.principal {
       Principal::User(user) if user.is_staff => Ok(user),
       Principal::User(_) => Err(ApiError::ActorNotAuthorized),
-      Principal::GitHubActions { .. } => Err(ApiError::ActorNotAuthorized),
+      Principal::GitHubActions { .. } | Principal::Oidc(_) => {
+        Err(ApiError::ActorNotAuthorized)
+      }
       Principal::Anonymous => Err(ApiError::MissingAuthentication),
     }
   }
 }
 
+/// Restricts what newly added scope members can do with popular packages that
+/// already existed when they joined, limiting the damage a compromised admin
+/// account can do by inviting new members.
+pub struct MemberCooldown {
+  /// The length of the cooldown in hours. Disabled if 0.
+  pub hours: u32,
+  /// The number of downloads in the last 7 days at which a package is
+  /// considered popular.
+  pub min_weekly_downloads: i64,
+}
+
 pub struct PublishAccessRestriction {
   pub tarball_hash: Option<String>,
 }
@@ -260,7 +646,13 @@ pub struct PublishAccessRestriction {
 #[derive(Clone)]
 pub enum Principal {
   User(User),
-  GitHubActions { repo_id: i64, user: Option<User> },
+  GitHubActions {
+    repo_id: i64,
+    user: Option<User>,
+  },
+  /// A CI job authenticated with an OpenID Connect token, which may only
+  /// publish packages that list it as a trusted publisher.
+  Oidc(OidcPublisher),
   Anonymous,
 }
 
@@ -281,6 +673,8 @@ pub struct IamInfo {
   /// Whether the request is being made with sudo privileges, which allows
   /// staff users to bypass some access restrictions.
   pub sudo: bool,
+  /// The token the request was authenticated with, if any.
+  pub token_id: Option<Uuid>,
 }
 
 impl IamInfo {
@@ -290,6 +684,7 @@ impl IamInfo {
       permissions: None,
       interactive: false,
       sudo: false,
+      token_id: None,
     }
   }
 }
@@ -302,6 +697,7 @@ impl From<(Token, User, bool)> for IamInfo {
       permissions: token.permissions,
       interactive: token.r#type == TokenType::Web,
       sudo,
+      token_id: Some(token.id),
     }
   }
 }
@@ -315,6 +711,19 @@ impl From<(i64, GithubOidcTokenAud, Option<User>)> for IamInfo {
       permissions: Some(aud.permissions),
       interactive: false,
       sudo: false,
+      token_id: None,
+    }
+  }
+}
+
+impl From<OidcPublisher> for IamInfo {
+  fn from(publisher: OidcPublisher) -> Self {
+    IamInfo {
+      principal: Principal::Oidc(publisher),
+      permissions: None,
+      interactive: false,
+      sudo: false,
+      token_id: None,
     }
   }
 }
@@ -326,11 +735,13 @@ pub trait ReqIamExt {
 impl ReqIamExt for Request<Body> {
   fn iam(&'_ self) -> IamHandler<'_> {
     let db = self.data().unwrap();
+    let member_cooldown = self.data().unwrap();
     let IamInfo {
       principal,
       permissions,
       interactive,
       sudo,
+      ..
     } = self.context().unwrap();
     IamHandler {
       db,
@@ -338,6 +749,7 @@ impl ReqIamExt for Request<Body> {
       permissions,
       interactive,
       sudo,
+      member_cooldown,
     }
   }
 }
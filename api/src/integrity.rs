@@ -0,0 +1,276 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+//! SHA-256 digests of the artifacts stored for package versions.
+//!
+//! Digests are recorded when an artifact is written: the checksums of the
+//! files of a version in `package_files` (which module blobs are also keyed
+//! by), and the digests of npm tarballs and doc artifacts. Module files are
+//! verified when they are read, see [crate::module_files::VersionFiles].
+//! [scrub_integrity] downloads all artifacts of a set of versions and records
+//! those that are missing or don't match their digest.
+
+use futures::StreamExt;
+use sha2::Digest;
+use sha2::Sha256;
+use thiserror::Error;
+use tracing::error;
+
+use crate::db::Database;
+use crate::db::DocArtifactKind;
+use crate::db::IntegrityScrubJob;
+use crate::db::IntegrityScrubJobStatus;
+use crate::ids::PackageName;
+use crate::ids::ScopeName;
+use crate::ids::Version;
+use crate::module_files::ModuleFilesError;
+use crate::module_files::VersionFiles;
+use crate::s3::BucketWithQueue;
+use crate::s3::Buckets;
+use crate::s3::S3Error;
+use crate::s3_paths;
+
+/// The digest of the given bytes, as `sha256-<hex>`.
+pub fn sha256_digest(bytes: &[u8]) -> String {
+  format!("sha256-{:x}", Sha256::digest(bytes))
+}
+
+#[derive(Debug, Error)]
+#[error("expected digest {expected}, got {actual}")]
+pub struct DigestMismatch {
+  pub expected: String,
+  pub actual: String,
+}
+
+pub fn verify_digest(
+  expected: &str,
+  bytes: &[u8],
+) -> Result<(), DigestMismatch> {
+  let actual = sha256_digest(bytes);
+  if actual != expected {
+    return Err(DigestMismatch {
+      expected: expected.to_owned(),
+      actual,
+    });
+  }
+  Ok(())
+}
+
+/// The path of a doc artifact in the docs bucket.
+pub fn doc_artifact_path(
+  kind: DocArtifactKind,
+  scope: &ScopeName,
+  package: &PackageName,
+  version: &Version,
+) -> String {
+  match kind {
+    DocArtifactKind::DocNodes => {
+      s3_paths::docs_v2_path(scope, package, version)
+    }
+    DocArtifactKind::Markdown => {
+      s3_paths::docs_markdown_path(scope, package, version)
+    }
+    DocArtifactKind::TypeGraph => {
+      s3_paths::docs_type_graph_path(scope, package, version)
+    }
+  }
+}
+
+const SCRUB_BATCH_SIZE: i64 = 100;
+const SCRUB_CONCURRENCY: usize = 8;
+const SCRUB_DOWNLOAD_CONCURRENCY: usize = 16;
+
+/// Runs an integrity scrub job to completion and records whether it
+/// succeeded.
+pub async fn scrub_integrity(
+  db: Database,
+  buckets: Buckets,
+  job: IntegrityScrubJob,
+) {
+  let (status, error) = match scrub_integrity_inner(&db, &buckets, &job).await {
+    Ok(()) => (IntegrityScrubJobStatus::Completed, None),
+    Err(err) => {
+      error!("integrity scrub job {} failed: {err}", job.id);
+      (IntegrityScrubJobStatus::Failed, Some(err.to_string()))
+    }
+  };
+
+  if let Err(err) = db
+    .finish_integrity_scrub_job(job.id, status, error.as_deref())
+    .await
+  {
+    error!("failed to finish integrity scrub job {}: {err}", job.id);
+  }
+}
+
+#[derive(Debug, Error)]
+enum ScrubError {
+  #[error(transparent)]
+  Database(#[from] sqlx::Error),
+  #[error(transparent)]
+  Storage(#[from] S3Error),
+  #[error(transparent)]
+  ModuleFiles(#[from] ModuleFilesError),
+}
+
+async fn scrub_integrity_inner(
+  db: &Database,
+  buckets: &Buckets,
+  job: &IntegrityScrubJob,
+) -> Result<(), ScrubError> {
+  let mut last = None;
+  loop {
+    let versions = db
+      .list_filtered_package_versions_after(
+        job.scope.as_ref(),
+        job.name.as_ref(),
+        last
+          .as_ref()
+          .map(|(scope, name, version)| (scope, name, version)),
+        SCRUB_BATCH_SIZE,
+      )
+      .await?;
+    let Some(last_in_batch) = versions.last().cloned() else {
+      break;
+    };
+
+    let mut checked_objects = 0;
+    let mut failures = vec![];
+    let mut results = futures::stream::iter(&versions)
+      .map(|(scope, name, version)| {
+        scrub_version(db, buckets, scope, name, version)
+      })
+      .buffer_unordered(SCRUB_CONCURRENCY);
+    while let Some(res) = results.next().await {
+      let (checked, version_failures) = res?;
+      checked_objects += checked;
+      failures.extend(version_failures);
+    }
+    drop(results);
+
+    db.record_integrity_scrub_job_progress(
+      job.id,
+      versions.len() as i32,
+      checked_objects as i32,
+      &failures,
+    )
+    .await?;
+
+    last = Some(last_in_batch);
+  }
+
+  Ok(())
+}
+
+/// An object to check: the bucket it is in, its path and its digest.
+struct ExpectedObject<'a> {
+  bucket_name: &'static str,
+  bucket: &'a BucketWithQueue,
+  path: String,
+  digest: String,
+}
+
+/// Checks the artifacts of a version that have a digest. Returns how many
+/// were checked, and the `(bucket, path, expected, actual)` of those that are
+/// missing or corrupted.
+async fn scrub_version(
+  db: &Database,
+  buckets: &Buckets,
+  scope: &ScopeName,
+  name: &PackageName,
+  version: &Version,
+) -> Result<(usize, Vec<(String, String, String, Option<String>)>), ScrubError>
+{
+  let mut objects = vec![];
+
+  let files =
+    VersionFiles::load(&buckets.modules_bucket, scope, name, version).await?;
+  for file in db.list_package_files(scope, name, version).await? {
+    let (Some(checksum), Some(path)) =
+      (file.checksum, files.object_path(&file.path))
+    else {
+      continue;
+    };
+    objects.push(ExpectedObject {
+      bucket_name: "modules",
+      bucket: &buckets.modules_bucket,
+      path,
+      digest: checksum,
+    });
+  }
+
+  for npm_tarball in db
+    .list_npm_tarballs_for_version(scope, name, version)
+    .await?
+  {
+    let Some(sha256) = npm_tarball.sha256 else {
+      continue;
+    };
+    objects.push(ExpectedObject {
+      bucket_name: "npm",
+      bucket: &buckets.npm_bucket,
+      path: s3_paths::npm_tarball_path(
+        scope,
+        name,
+        version,
+        npm_tarball.revision as u32,
+      ),
+      digest: sha256,
+    });
+  }
+
+  for artifact in db
+    .list_package_version_doc_artifacts(scope, name, version)
+    .await?
+  {
+    objects.push(ExpectedObject {
+      bucket_name: "docs",
+      bucket: &buckets.docs_bucket,
+      path: doc_artifact_path(artifact.kind, scope, name, version),
+      digest: artifact.checksum,
+    });
+  }
+
+  let checked = objects.len();
+  let mut failures = vec![];
+  let mut results = futures::stream::iter(objects)
+    .map(|object| async move {
+      let bytes = object.bucket.download(object.path.as_str().into()).await?;
+      let actual = bytes.map(|bytes| sha256_digest(&bytes));
+      Ok::<_, S3Error>((object, actual))
+    })
+    .buffer_unordered(SCRUB_DOWNLOAD_CONCURRENCY);
+  while let Some(res) = results.next().await {
+    let (object, actual) = res?;
+    if actual.as_ref() != Some(&object.digest) {
+      error!(
+        "{} in the {} bucket does not match its digest {}: {actual:?}",
+        object.path, object.bucket_name, object.digest
+      );
+      failures.push((
+        object.bucket_name.to_string(),
+        object.path,
+        object.digest,
+        actual,
+      ));
+    }
+  }
+
+  Ok((checked, failures))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::sha256_digest;
+  use super::verify_digest;
+
+  #[test]
+  fn digests() {
+    assert_eq!(
+      sha256_digest(b"hello"),
+      "sha256-2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+    );
+    assert!(verify_digest(&sha256_digest(b"hello"), b"hello").is_ok());
+    let err = verify_digest(&sha256_digest(b"hello"), b"world").unwrap_err();
+    assert_eq!(err.actual, sha256_digest(b"world"));
+  }
+}
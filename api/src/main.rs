@@ -6,50 +6,92 @@ static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 mod analysis;
 mod api;
 mod auth;
+mod background_jobs;
+mod cache_purge;
 mod config;
+mod data_export;
 mod db;
 mod docs;
+mod docs_markdown;
 mod emails;
 mod errors_internal;
+mod events;
 mod external;
+mod feature_flags;
 mod gcp;
 mod iam;
 mod ids;
+mod integrity;
 mod jemalloc_profiling;
+mod malware_scan;
 mod metadata;
+mod metrics;
+mod module_files;
 mod npm;
+mod oidc;
+mod orphan_gc;
 mod provenance;
 mod publish;
+mod publish_limits;
+mod quarantine;
+mod rate_limit;
 mod s3;
 mod s3_paths;
+mod s3_policy;
+mod sbom;
+mod scheduler;
+mod score;
+mod search;
 mod sitemap;
+mod spilled_files;
 mod tarball;
 mod task_queue;
 mod tasks;
 mod token;
+mod tombstones;
 mod traced_router;
 mod tracing;
 mod tree_sitter;
+mod type_graph;
 mod util;
+mod webhooks;
 
 use crate::api::ApiError;
 use crate::api::PublishQueue;
 use crate::api::api_router;
+use crate::cache_purge::CachePurge;
+use crate::cache_purge::CachePurgeClient;
 use crate::config::Config;
 use crate::db::Database;
+use crate::emails::EmailProvider;
+use crate::emails::EmailProviderKind;
 use crate::emails::EmailSender;
+use crate::emails::PostmarkProvider;
+use crate::emails::SesProvider;
+use crate::emails::SmtpProvider;
 use crate::errors_internal::error_handler;
+use crate::events::EventSink;
+use crate::events::EventSinkKind;
 use crate::external::algolia::AlgoliaClient;
-use crate::external::cloudflare::CachePurge;
 use crate::external::cloudflare::Turnstile;
 use crate::external::cloudflare::TurnstileClient;
 use crate::gcp::Queue;
+use crate::iam::MemberCooldown;
+use crate::malware_scan::ClamAvScanner;
+use crate::malware_scan::MalwareScanner;
+use crate::malware_scan::MalwareScannerKind;
+use crate::malware_scan::MalwareScanners;
+use crate::malware_scan::RulesScanner;
 use crate::s3::Buckets;
+use crate::search::PackageSearch;
+use crate::search::SearchBackend;
+use crate::search::SearchBackendKind;
 use crate::sitemap::packages_sitemap_handler;
 use crate::sitemap::scopes_sitemap_handler;
 use crate::sitemap::sitemap_index_handler;
 use crate::tasks::NpmTarballBuildQueue;
 use crate::tasks::tasks_router;
+use crate::tombstones::TombstoneRetention;
 use crate::traced_router::TracedRouterService;
 use crate::tracing::TracingExportTarget;
 use crate::tracing::setup_tracing;
@@ -57,8 +99,10 @@ use crate::tracing::setup_tracing;
 use clap::Parser;
 use hyper::Body;
 use hyper::Server;
+use routerify::Middleware;
 use routerify::Router;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Duration;
 use tasks::AnalyticsEngineConfig;
 use url::Url;
@@ -70,20 +114,28 @@ pub struct MainRouterOptions {
   github_client: auth::github::Oauth2Client,
   gitlab_client: auth::gitlab::Oauth2Client,
   algolia_client: Option<AlgoliaClient>,
+  package_search: PackageSearch,
   email_sender: Option<EmailSender>,
   license_store: util::LicenseStore,
+  malware_scanners: MalwareScanners,
   registry_url: Url,
   npm_url: Url,
+  npm_signer: npm::NpmSigner,
   publish_queue: Option<Queue>,
   npm_tarball_build_queue: Option<Queue>,
   analytics_engine_config: Option<(
     external::cloudflare::AnalyticsEngineClient,
     /* dataset_name */ String,
   )>,
-  cache_purge_client: Option<external::cloudflare::CachePurgeClient>,
+  cache_purge_client: Option<CachePurgeClient>,
   turnstile: Turnstile,
+  member_cooldown: MemberCooldown,
+  tombstone_retention: TombstoneRetention,
   expose_api: bool,
   expose_tasks: bool,
+  metrics_token: Option<String>,
+  origin_secret: Option<String>,
+  trusted_proxy_hops: usize,
 }
 
 pub struct RegistryUrl(pub Url);
@@ -97,17 +149,25 @@ pub(crate) fn main_router(
     github_client,
     gitlab_client,
     algolia_client,
+    package_search,
     license_store,
+    malware_scanners,
     email_sender,
     registry_url,
     npm_url,
+    npm_signer,
     publish_queue,
     npm_tarball_build_queue,
     analytics_engine_config,
     cache_purge_client,
     turnstile,
+    member_cooldown,
+    tombstone_retention,
     expose_api,
     expose_tasks,
+    metrics_token,
+    origin_secret,
+    trusted_proxy_hops,
   }: MainRouterOptions,
 ) -> Router<Body, ApiError> {
   let builder = Router::builder()
@@ -117,19 +177,38 @@ pub(crate) fn main_router(
     .data(github_client)
     .data(gitlab_client)
     .data(algolia_client)
+    .data(package_search)
     .data(email_sender)
     .data(license_store)
+    .data(malware_scanners)
     .data(RegistryUrl(registry_url))
     .data(NpmUrl(npm_url))
+    .data(npm_signer)
     .data(PublishQueue(publish_queue))
     .data(NpmTarballBuildQueue(npm_tarball_build_queue))
     .data(AnalyticsEngineConfig(analytics_engine_config))
-    .data(CachePurge(cache_purge_client))
+    .data(CachePurge::new(cache_purge_client))
     .data(turnstile)
+    .data(member_cooldown)
+    .data(tombstone_retention)
     .data(db::DependentCountCache::new())
+    .data(api::package::DependencyGraphCache::new())
+    .data(rate_limit::RateLimiter::new(trusted_proxy_hops))
+    .data(feature_flags::FeatureFlags::new())
+    .data(oidc::OidcVerifier::new())
     .middleware(routerify_query::query_parser())
+    .middleware(Middleware::pre(util::db_stickiness_middleware))
     .err_handler_with_info(error_handler);
 
+  // Before the routes, so that the middlewares of the API, like the rate
+  // limit, never see requests that did not come through the load balancer.
+  let builder = match origin_secret {
+    Some(secret) => builder
+      .data(util::OriginSecret(secret))
+      .middleware(Middleware::pre(util::origin_secret_middleware)),
+    None => builder,
+  };
+
   let builder = if expose_api {
     builder
       .scope("/api", api_router())
@@ -162,6 +241,13 @@ pub(crate) fn main_router(
     builder
   };
 
+  let builder = match metrics_token {
+    Some(token) => builder
+      .data(metrics::MetricsToken(token))
+      .get("/metrics", metrics::metrics_handler),
+    None => builder,
+  };
+
   builder.build().unwrap()
 }
 
@@ -189,6 +275,17 @@ async fn main() {
   };
   setup_tracing("api", export_target, config.deployment_environment).await;
 
+  docs::set_search_index_limits(docs::SearchIndexLimits {
+    max_symbols_per_entrypoint: config.docs_search_max_symbols_per_entrypoint,
+    max_symbols: config.docs_search_max_symbols,
+    max_doc_length: config.docs_search_max_doc_length,
+  });
+  publish_limits::set_default_publish_limits(publish_limits::PublishLimits {
+    max_path_depth: config.max_path_depth,
+    max_path_length: config.max_path_length,
+    ..Default::default()
+  });
+
   let db_tls = match (config.db_client_cert, config.db_client_key) {
     (Some(client_cert), Some(client_key)) => Some(crate::db::DbTls {
       client_cert,
@@ -201,51 +298,136 @@ async fn main() {
     &config.database_url,
     config.database_pool_size,
     Duration::from_secs(15),
-    db_tls,
+    db_tls.clone(),
   )
   .await
   .unwrap();
+  let database = match &config.database_replica_url {
+    Some(database_replica_url) => database
+      .with_read_replica(
+        database_replica_url,
+        config.database_replica_pool_size,
+        Duration::from_secs(15),
+        db_tls,
+        chrono::Duration::seconds(config.database_replica_stickiness_secs),
+      )
+      .await
+      .unwrap(),
+    None => database,
+  };
+  let database = match &config.redis_url {
+    Some(redis_url) => {
+      let ttls = crate::db::MetadataCacheTtls {
+        package: Duration::from_secs(config.metadata_cache_package_ttl_secs),
+        versions: Duration::from_secs(config.metadata_cache_versions_ttl_secs),
+        scope: Duration::from_secs(config.metadata_cache_scope_ttl_secs),
+      };
+      let cache = crate::db::MetadataCache::connect(redis_url, ttls)
+        .await
+        .unwrap();
+      println!("Metadata cache ready");
+      database.with_metadata_cache(cache)
+    }
+    None => database,
+  };
 
-  let s3_region = ::s3::Region::Custom {
-    region: config.s3_region,
-    endpoint: config.s3_endpoint,
+  let search_backend = |kind: SearchBackendKind| -> Arc<dyn SearchBackend> {
+    match kind {
+      SearchBackendKind::Postgres => Arc::new(search::PostgresSearchBackend),
+      SearchBackendKind::Meilisearch => {
+        Arc::new(search::MeilisearchBackend::new(
+          external::meilisearch::MeilisearchClient::new(
+            config
+              .meilisearch_url
+              .clone()
+              .expect("meilisearch is a search backend but no meilisearch_url"),
+            config.meilisearch_api_key.clone(),
+          ),
+          config.meilisearch_packages_index.clone(),
+        ))
+      }
+    }
   };
-  let s3_credentials = ::s3::creds::Credentials {
-    access_key: Some(config.s3_access_key),
-    secret_key: Some(config.s3_secret_key),
-    security_token: None,
-    session_token: None,
-    expiration: None,
+  let package_search = PackageSearch::new(
+    search_backend(config.search_backend),
+    config.search_dual_write.map(search_backend),
+  );
+  let database = if package_search.has_indexes() {
+    let (queue, rx) = search::SearchIndexQueue::new();
+    let database = database.with_search_index(queue);
+    search::spawn_index_worker(database.clone(), package_search.clone(), rx);
+    database
+  } else {
+    database
+  };
+
+  score::spawn_refresh_loop(database.clone());
+
+  let new_bucket = |name: String| match &config.storage_dir {
+    Some(storage_dir) => {
+      let root = storage_dir.join(&name);
+      s3::Bucket::new_fs(name, root)
+    }
+    None => {
+      let s3_region = ::s3::Region::Custom {
+        region: config.s3_region.clone().unwrap(),
+        endpoint: config.s3_endpoint.clone().unwrap(),
+      };
+      let s3_credentials = ::s3::creds::Credentials {
+        access_key: config.s3_access_key.clone(),
+        secret_key: config.s3_secret_key.clone(),
+        security_token: None,
+        session_token: None,
+        expiration: None,
+      };
+      s3::Bucket::new(name, s3_region, s3_credentials).unwrap()
+    }
   };
 
   let gcp_client = gcp::Client::new(config.metadata_strategy);
-  let publishing_bucket = s3::BucketWithQueue::new(
-    s3::Bucket::new(
-      config.publishing_bucket,
-      s3_region.clone(),
-      s3_credentials.clone(),
-    )
-    .unwrap(),
-  );
-  let modules_bucket = s3::BucketWithQueue::new(
-    s3::Bucket::new(
-      config.modules_bucket,
-      s3_region.clone(),
-      s3_credentials.clone(),
-    )
-    .unwrap(),
-  );
-  let docs_bucket = s3::BucketWithQueue::new(
-    s3::Bucket::new(
-      config.docs_bucket,
-      s3_region.clone(),
-      s3_credentials.clone(),
-    )
-    .unwrap(),
-  );
-  let npm_bucket = s3::BucketWithQueue::new(
-    s3::Bucket::new(config.npm_bucket, s3_region, s3_credentials).unwrap(),
-  );
+
+  let event_sinks = config
+    .event_sinks
+    .iter()
+    .map(|kind| -> Arc<dyn EventSink> {
+      match kind {
+        EventSinkKind::Postgres => {
+          Arc::new(events::PostgresEventSink::new(database.clone()))
+        }
+        EventSinkKind::PubSub => {
+          Arc::new(events::PubSubEventSink::new(gcp::Topic::new(
+            gcp_client.clone(),
+            config
+              .pubsub_events_topic
+              .clone()
+              .expect("pubsub is an event sink but no pubsub_events_topic"),
+            None,
+          )))
+        }
+        EventSinkKind::Kafka => Arc::new(events::KafkaRestEventSink::new(
+          config
+            .kafka_rest_url
+            .clone()
+            .expect("kafka is an event sink but no kafka_rest_url"),
+          config.kafka_events_topic.clone(),
+        )),
+      }
+    })
+    .collect::<Vec<_>>();
+  let database = if event_sinks.is_empty() {
+    database
+  } else {
+    let (queue, rx) = events::EventQueue::new();
+    events::spawn_event_worker(event_sinks, rx);
+    database.with_event_queue(queue)
+  };
+
+  let publishing_bucket =
+    s3::BucketWithQueue::new(new_bucket(config.publishing_bucket));
+  let modules_bucket =
+    s3::BucketWithQueue::new(new_bucket(config.modules_bucket));
+  let docs_bucket = s3::BucketWithQueue::new(new_bucket(config.docs_bucket));
+  let npm_bucket = s3::BucketWithQueue::new(new_bucket(config.npm_bucket));
   let buckets = Buckets {
     publishing_bucket,
     modules_bucket,
@@ -261,13 +443,28 @@ async fn main() {
     .npm_tarball_build_queue_id
     .map(|id: String| Queue::new(gcp_client.clone(), id, None));
 
+  let npm_signer = npm::NpmSigner(config.npm_signing_key.map(|pem| {
+    Arc::new(
+      npm::NpmSigningKey::from_pem(&pem).expect("invalid npm_signing_key"),
+    )
+  }));
+  if let Some(signing_key) = &npm_signer.0 {
+    npm::upload_npm_keys(&buckets.npm_bucket, signing_key)
+      .await
+      .expect("failed to upload npm signing keys");
+  }
+
   let cache_purge_client = match (
     config.cloudflare_zone_id.clone(),
     config.cloudflare_api_token.clone(),
+    config.fastly_api_token,
   ) {
-    (Some(zone_id), Some(api_token)) => Some(
-      external::cloudflare::CachePurgeClient::new(zone_id, api_token),
-    ),
+    (Some(zone_id), Some(api_token), _) => Some(CachePurgeClient::Cloudflare(
+      external::cloudflare::CloudflarePurgeClient::new(zone_id, api_token),
+    )),
+    (_, _, Some(api_token)) => Some(CachePurgeClient::Fastly(
+      external::fastly::FastlyPurgeClient::new(api_token),
+    )),
     _ => None,
   };
 
@@ -315,24 +512,88 @@ async fn main() {
     None
   };
 
-  let email_sender = config.postmark_token.map(|token| {
+  let email_provider: Option<Arc<dyn EmailProvider>> =
+    match config.email_provider {
+      EmailProviderKind::Postmark => config.postmark_token.map(|token| {
+        Arc::new(PostmarkProvider::new(
+          postmark::reqwest::PostmarkClient::builder()
+            .token(token)
+            .build(),
+        )) as Arc<dyn EmailProvider>
+      }),
+      EmailProviderKind::Smtp => Some(Arc::new(
+        SmtpProvider::new(
+          config
+            .smtp_url
+            .as_deref()
+            .expect("smtp is the email provider but no smtp_url"),
+        )
+        .expect("invalid smtp_url"),
+      )),
+      EmailProviderKind::Ses => Some(Arc::new(SesProvider::new(
+        config
+          .ses_region
+          .expect("ses is the email provider but no ses_region"),
+        config
+          .ses_access_key_id
+          .expect("ses is the email provider but no ses_access_key_id"),
+        config
+          .ses_secret_access_key
+          .expect("ses is the email provider but no ses_secret_access_key"),
+      ))),
+    };
+  let email_sender = email_provider.map(|provider| {
     EmailSender::new(
-      postmark::reqwest::PostmarkClient::builder()
-        .token(token)
-        .build(),
+      provider,
       config
         .email_from
-        .expect("email_from must be set when postmark_token is set"),
+        .expect("email_from must be set when sending emails"),
       config
         .email_from_name
-        .expect("email_from_name must be set when postmark_token is set"),
+        .expect("email_from_name must be set when sending emails"),
     )
   });
 
   let license_store = util::license_store();
 
+  let malware_scanners = MalwareScanners::new(
+    config
+      .malware_scanners
+      .iter()
+      .map(|kind| -> Arc<dyn MalwareScanner> {
+        match kind {
+          MalwareScannerKind::ClamAv => Arc::new(ClamAvScanner::new(
+            config
+              .clamd_address
+              .clone()
+              .expect("clamav is a malware scanner but no clamd_address"),
+          )),
+          MalwareScannerKind::Rules => Arc::new(RulesScanner),
+        }
+      })
+      .collect(),
+  );
+
   let generate_ctx_cache = crate::docs::GenerateCtxCache::new();
 
+  let tombstone_retention = TombstoneRetention {
+    days: config.tombstone_retention_days,
+  };
+
+  if !config.scheduled_jobs.is_empty() {
+    scheduler::spawn_scheduler(
+      scheduler::SchedulerContext {
+        db: database.clone(),
+        buckets: buckets.clone(),
+        package_search: package_search.clone(),
+        tombstone_retention,
+        email_sender: email_sender.clone(),
+        registry_url: config.registry_url.clone(),
+      },
+      config.scheduled_jobs,
+    );
+  }
+
   let router = main_router(MainRouterOptions {
     database,
     buckets,
@@ -340,17 +601,30 @@ async fn main() {
     github_client,
     gitlab_client,
     algolia_client,
+    package_search,
     email_sender,
     license_store,
+    malware_scanners,
     registry_url: config.registry_url,
     npm_url: config.npm_url,
+    npm_signer,
     publish_queue,
     npm_tarball_build_queue,
     analytics_engine_config,
     cache_purge_client,
     turnstile,
+    member_cooldown: MemberCooldown {
+      hours: config.member_cooldown_hours,
+      min_weekly_downloads: config.member_cooldown_min_weekly_downloads,
+    },
+    tombstone_retention,
     expose_api: config.api,
     expose_tasks: config.tasks,
+    // An empty token would let anyone scrape the metrics.
+    metrics_token: config.metrics_token.filter(|s| !s.trim().is_empty()),
+    // An empty secret would be sent by anyone who sends no secret at all.
+    origin_secret: config.origin_secret.filter(|s| !s.trim().is_empty()),
+    trusted_proxy_hops: config.trusted_proxy_hops,
   });
 
   // Create a Service from the router above to handle incoming requests.
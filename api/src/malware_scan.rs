@@ -0,0 +1,537 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+//! Scanning of the files of a publish for malware, after it is analyzed.
+//!
+//! Every configured [MalwareScanner] is passed every file of the publish:
+//! [ClamAvScanner] sends them to a clamd daemon, and [RulesScanner] looks for
+//! patterns in the syntax trees of JavaScript and TypeScript modules that are
+//! common in malicious code, but rare otherwise. A publish that a scanner
+//! flags is still published, but its version is reported and quarantined
+//! (see [crate::quarantine]) right away, so that it is not served before staff
+//! reviewed it.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use deno_ast::LineAndColumnDisplay;
+use deno_ast::MediaType;
+use deno_ast::ModuleSpecifier;
+use deno_ast::ParsedSource;
+use deno_ast::SourceRange;
+use deno_ast::SourceRangedForSpanned;
+use deno_ast::swc::ast;
+use deno_ast::swc::ecma_visit::Visit;
+use deno_ast::swc::ecma_visit::VisitWith;
+use futures::StreamExt;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+use crate::ids::PackagePath;
+use crate::spilled_files::SpilledFiles;
+
+/// How many files are scanned at once.
+const MAX_CONCURRENT_SCANS: usize = 8;
+
+/// How long clamd may take to scan one file.
+const CLAMD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The size of the chunks files are streamed to clamd in.
+const CLAMD_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MalwareScannerKind {
+  ClamAv,
+  Rules,
+}
+
+impl FromStr for MalwareScannerKind {
+  type Err = anyhow::Error;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "clamav" => Ok(Self::ClamAv),
+      "rules" => Ok(Self::Rules),
+      _ => Err(anyhow::anyhow!("Invalid malware scanner '{}'", s)),
+    }
+  }
+}
+
+/// Something a scanner found in a file of a publish.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanFinding {
+  /// The name of the scanner that reported the finding.
+  pub scanner: &'static str,
+  pub path: PackagePath,
+  pub message: String,
+}
+
+impl std::fmt::Display for ScanFinding {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}: {} ({})", self.path, self.message, self.scanner)
+  }
+}
+
+/// Looks for malware in the files of a publish.
+#[async_trait::async_trait]
+pub trait MalwareScanner: Send + Sync {
+  fn name(&self) -> &'static str;
+
+  /// Scans one file. Errors fail the publish with a retryable error, so that
+  /// a scanner that is down does not let a publish through unscanned.
+  async fn scan(
+    &self,
+    path: &PackagePath,
+    bytes: &[u8],
+  ) -> Result<Vec<ScanFinding>, anyhow::Error>;
+}
+
+/// The scanners that publishes are passed to. Publishes are not scanned if
+/// there are none.
+#[derive(Clone, Default)]
+pub struct MalwareScanners(Arc<Vec<Arc<dyn MalwareScanner>>>);
+
+impl MalwareScanners {
+  pub fn new(scanners: Vec<Arc<dyn MalwareScanner>>) -> Self {
+    Self(Arc::new(scanners))
+  }
+
+  /// Passes the files of a publish, both those in memory and those spilled to
+  /// temporary storage, to every scanner. Returns the findings of all of
+  /// them.
+  pub async fn scan(
+    &self,
+    files: &HashMap<PackagePath, Vec<u8>>,
+    spilled_files: &SpilledFiles,
+  ) -> Result<Vec<ScanFinding>, anyhow::Error> {
+    if self.0.is_empty() {
+      return Ok(vec![]);
+    }
+
+    let mut findings = vec![];
+    let mut scans = futures::stream::iter(files)
+      .map(|(path, bytes)| async move { self.scan_file(path, bytes).await })
+      .buffer_unordered(MAX_CONCURRENT_SCANS);
+    while let Some(res) = scans.next().await {
+      findings.extend(res?);
+    }
+    drop(scans);
+
+    // Spilled files are read back one at a time, like when they are stored.
+    for path in spilled_files.paths() {
+      let Some(bytes) = spilled_files.read_async(path).await? else {
+        continue;
+      };
+      findings.extend(self.scan_file(path, &bytes).await?);
+    }
+
+    findings.sort_by(|a, b| (*a.path).cmp(&*b.path));
+    Ok(findings)
+  }
+
+  async fn scan_file(
+    &self,
+    path: &PackagePath,
+    bytes: &[u8],
+  ) -> Result<Vec<ScanFinding>, anyhow::Error> {
+    let mut findings = vec![];
+    for scanner in self.0.iter() {
+      findings.extend(scanner.scan(path, bytes).await.map_err(|err| {
+        err.context(format!("{} scanner failed on {path}", scanner.name()))
+      })?);
+    }
+    Ok(findings)
+  }
+}
+
+/// Streams files to clamd with the `INSTREAM` command.
+pub struct ClamAvScanner {
+  /// The `host:port` clamd listens on.
+  address: String,
+}
+
+impl ClamAvScanner {
+  pub fn new(address: String) -> Self {
+    Self { address }
+  }
+
+  async fn instream(&self, bytes: &[u8]) -> Result<String, anyhow::Error> {
+    let mut stream = TcpStream::connect(&self.address).await?;
+    stream.write_all(b"zINSTREAM\0").await?;
+    for chunk in bytes.chunks(CLAMD_CHUNK_SIZE) {
+      stream
+        .write_all(&(chunk.len() as u32).to_be_bytes())
+        .await?;
+      stream.write_all(chunk).await?;
+    }
+    stream.write_all(&0u32.to_be_bytes()).await?;
+    stream.flush().await?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    let response = String::from_utf8_lossy(&response);
+    Ok(response.trim_end_matches(['\0', '\n']).to_owned())
+  }
+}
+
+#[async_trait::async_trait]
+impl MalwareScanner for ClamAvScanner {
+  fn name(&self) -> &'static str {
+    "clamav"
+  }
+
+  async fn scan(
+    &self,
+    path: &PackagePath,
+    bytes: &[u8],
+  ) -> Result<Vec<ScanFinding>, anyhow::Error> {
+    let response = tokio::time::timeout(CLAMD_TIMEOUT, self.instream(bytes))
+      .await
+      .map_err(|_| anyhow::anyhow!("clamd timed out"))??;
+    parse_clamd_response(&response).map(|signature| {
+      signature
+        .map(|signature| ScanFinding {
+          scanner: self.name(),
+          path: path.clone(),
+          message: format!("matches the {signature} signature"),
+        })
+        .into_iter()
+        .collect()
+    })
+  }
+}
+
+/// Parses the response of clamd to `INSTREAM`, like `stream: OK` or
+/// `stream: Eicar-Test-Signature FOUND`, into the signature that matched, if
+/// any.
+fn parse_clamd_response(
+  response: &str,
+) -> Result<Option<String>, anyhow::Error> {
+  let result = response.strip_prefix("stream: ").unwrap_or(response);
+  if result == "OK" {
+    Ok(None)
+  } else if let Some(signature) = result.strip_suffix(" FOUND") {
+    Ok(Some(signature.to_owned()))
+  } else {
+    Err(anyhow::anyhow!("unexpected clamd response: {response}"))
+  }
+}
+
+/// Looks for obfuscated code execution and exfiltration of the environment in
+/// the syntax trees of JavaScript and TypeScript modules, see
+/// [SuspiciousCodeFinder]. Files that do not parse are left to the analysis.
+pub struct RulesScanner;
+
+#[async_trait::async_trait]
+impl MalwareScanner for RulesScanner {
+  fn name(&self) -> &'static str {
+    "rules"
+  }
+
+  async fn scan(
+    &self,
+    path: &PackagePath,
+    bytes: &[u8],
+  ) -> Result<Vec<ScanFinding>, anyhow::Error> {
+    let media_type = MediaType::from_str(path);
+    if !matches!(
+      media_type,
+      MediaType::JavaScript
+        | MediaType::Jsx
+        | MediaType::Mjs
+        | MediaType::Cjs
+        | MediaType::TypeScript
+        | MediaType::Mts
+        | MediaType::Cts
+        | MediaType::Tsx
+    ) {
+      return Ok(vec![]);
+    }
+    let Ok(text) = std::str::from_utf8(bytes) else {
+      return Ok(vec![]);
+    };
+    let text: Arc<str> = text.into();
+    let path = path.clone();
+    let scanner = self.name();
+    let findings = tokio::task::spawn_blocking(move || {
+      let Ok(specifier) = ModuleSpecifier::parse(&format!("file://{path}"))
+      else {
+        return vec![];
+      };
+      let Ok(parsed_source) = deno_ast::parse_program(deno_ast::ParseParams {
+        specifier,
+        text,
+        media_type,
+        capture_tokens: false,
+        scope_analysis: false,
+        maybe_syntax: None,
+      }) else {
+        return vec![];
+      };
+      find_suspicious_code(&parsed_source)
+        .into_iter()
+        .map(|message| ScanFinding {
+          scanner,
+          path: path.clone(),
+          message,
+        })
+        .collect()
+    })
+    .await?;
+    Ok(findings)
+  }
+}
+
+/// Runs [SuspiciousCodeFinder] on a module, and describes what it found.
+fn find_suspicious_code(parsed_source: &ParsedSource) -> Vec<String> {
+  let mut finder = SuspiciousCodeFinder::default();
+  match parsed_source.program_ref() {
+    deno_ast::ProgramRef::Module(module) => module.visit_with(&mut finder),
+    deno_ast::ProgramRef::Script(script) => script.visit_with(&mut finder),
+  }
+  finder
+    .found
+    .into_iter()
+    .map(|(message, range)| {
+      let LineAndColumnDisplay {
+        line_number,
+        column_number,
+      } = parsed_source
+        .text_info_lazy()
+        .line_and_column_display(range.start);
+      format!("{message} ({line_number}:{column_number})")
+    })
+    .collect()
+}
+
+/// Functions that turn encoded data back into text, as used to hide code from
+/// readers.
+const DECODERS: &[(&str, Option<&str>)] = &[
+  ("atob", None),
+  ("unescape", None),
+  ("decodeURIComponent", None),
+  ("String", Some("fromCharCode")),
+  ("Buffer", Some("from")),
+];
+
+/// Functions that send data over the network.
+const NETWORK_CALLS: &[(&str, Option<&str>)] = &[
+  ("fetch", None),
+  ("navigator", Some("sendBeacon")),
+  ("http", Some("request")),
+  ("http", Some("get")),
+  ("https", Some("request")),
+  ("https", Some("get")),
+];
+
+/// Finds code that executes decoded data, like `eval(atob("..."))` or
+/// `new Function(Buffer.from("...", "base64").toString())`, and network calls
+/// that send all environment variables, like
+/// `fetch(url, { body: JSON.stringify(process.env) })`.
+#[derive(Default)]
+struct SuspiciousCodeFinder {
+  /// How many `eval` or `Function` calls the visitor is in the arguments of.
+  in_code_execution: usize,
+  /// How many network calls the visitor is in the arguments of.
+  in_network_call: usize,
+  found: Vec<(&'static str, SourceRange)>,
+}
+
+impl SuspiciousCodeFinder {
+  fn visit_args_of(
+    &mut self,
+    callee_is_code_execution: bool,
+    callee_is_network_call: bool,
+    args: &[ast::ExprOrSpread],
+  ) {
+    self.in_code_execution += callee_is_code_execution as usize;
+    self.in_network_call += callee_is_network_call as usize;
+    for arg in args {
+      arg.visit_with(self);
+    }
+    self.in_code_execution -= callee_is_code_execution as usize;
+    self.in_network_call -= callee_is_network_call as usize;
+  }
+}
+
+impl Visit for SuspiciousCodeFinder {
+  fn visit_call_expr(&mut self, node: &ast::CallExpr) {
+    let ast::Callee::Expr(callee) = &node.callee else {
+      node.visit_children_with(self);
+      return;
+    };
+    callee.visit_with(self);
+
+    if self.in_code_execution > 0 && matches_any(callee, DECODERS) {
+      self.found.push((
+        "executes code decoded at runtime, which hides what it does",
+        node.range(),
+      ));
+    }
+    if self.in_network_call > 0 && is_environment_dump(node) {
+      self.found.push((
+        "sends all environment variables over the network",
+        node.range(),
+      ));
+    }
+
+    self.visit_args_of(
+      is_global(callee, "eval") || is_global(callee, "Function"),
+      matches_any(callee, NETWORK_CALLS),
+      &node.args,
+    );
+  }
+
+  fn visit_new_expr(&mut self, node: &ast::NewExpr) {
+    node.callee.visit_with(self);
+    if let Some(args) = &node.args {
+      self.visit_args_of(
+        is_global(&node.callee, "Function"),
+        is_global(&node.callee, "WebSocket"),
+        args,
+      );
+    }
+  }
+
+  fn visit_spread_element(&mut self, node: &ast::SpreadElement) {
+    node.visit_children_with(self);
+    if self.in_network_call > 0 && is_member(&node.expr, "process", "env") {
+      self.found.push((
+        "sends all environment variables over the network",
+        node.range(),
+      ));
+    }
+  }
+}
+
+/// Whether `call` reads all environment variables at once, like
+/// `JSON.stringify(process.env)` or `Deno.env.toObject()`.
+fn is_environment_dump(call: &ast::CallExpr) -> bool {
+  let ast::Callee::Expr(callee) = &call.callee else {
+    return false;
+  };
+  if let ast::Expr::Member(member) = &**callee
+    && is_member(&member.obj, "Deno", "env")
+    && member_prop(member) == Some("toObject")
+  {
+    return true;
+  }
+  (is_member(callee, "JSON", "stringify")
+    || is_member(callee, "Object", "entries")
+    || is_member(callee, "Object", "keys")
+    || is_member(callee, "Object", "values")
+    || is_member(callee, "Object", "assign"))
+    && call
+      .args
+      .iter()
+      .any(|arg| is_member(&arg.expr, "process", "env"))
+}
+
+fn matches_any(expr: &ast::Expr, names: &[(&str, Option<&str>)]) -> bool {
+  names.iter().any(|(obj, prop)| match prop {
+    Some(prop) => is_member(expr, obj, prop),
+    None => is_global(expr, obj),
+  })
+}
+
+fn is_global(expr: &ast::Expr, name: &str) -> bool {
+  match expr {
+    ast::Expr::Ident(ident) => ident.sym.as_ref() == name,
+    ast::Expr::Member(member) => {
+      is_global(&member.obj, "globalThis") && member_prop(member) == Some(name)
+    }
+    ast::Expr::Paren(paren) => is_global(&paren.expr, name),
+    _ => false,
+  }
+}
+
+/// Whether `expr` is `obj.prop`, or `obj["prop"]`.
+fn is_member(expr: &ast::Expr, obj: &str, prop: &str) -> bool {
+  match expr {
+    ast::Expr::Member(member) => {
+      is_global(&member.obj, obj) && member_prop(member) == Some(prop)
+    }
+    ast::Expr::Paren(paren) => is_member(&paren.expr, obj, prop),
+    _ => false,
+  }
+}
+
+fn member_prop(member: &ast::MemberExpr) -> Option<&str> {
+  match &member.prop {
+    ast::MemberProp::Ident(ident) => Some(&*ident.sym),
+    ast::MemberProp::Computed(computed) => match &*computed.expr {
+      ast::Expr::Lit(ast::Lit::Str(lit_str)) => lit_str.value.as_str(),
+      _ => None,
+    },
+    ast::MemberProp::PrivateName(_) => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::find_suspicious_code;
+  use super::parse_clamd_response;
+
+  fn parse(source: &str) -> deno_ast::ParsedSource {
+    deno_ast::parse_program(deno_ast::ParseParams {
+      specifier: deno_ast::ModuleSpecifier::parse("file:///mod.js").unwrap(),
+      text: source.into(),
+      media_type: deno_ast::MediaType::JavaScript,
+      capture_tokens: false,
+      scope_analysis: false,
+      maybe_syntax: None,
+    })
+    .unwrap()
+  }
+
+  #[test]
+  fn suspicious_code() {
+    let cases: &[(&str, &[&str])] = &[
+      ("eval('1 + 1');", &[]),
+      ("const s = atob(data);", &[]),
+      (
+        "eval(atob('Y29uc29sZS5sb2coMSk='));",
+        &["executes code decoded at runtime, which hides what it does (1:6)"],
+      ),
+      (
+        "new Function(Buffer.from(code, 'base64').toString())();",
+        &["executes code decoded at runtime, which hides what it does (1:14)"],
+      ),
+      (
+        "globalThis['eval'](String.fromCharCode(97, 98));",
+        &["executes code decoded at runtime, which hides what it does (1:20)"],
+      ),
+      ("fetch(url, { body: JSON.stringify({ a: 1 }) });", &[]),
+      ("fetch(url + process.env.API_KEY);", &[]),
+      (
+        "fetch(url, { method: 'POST', body: JSON.stringify(process.env) });",
+        &["sends all environment variables over the network (1:36)"],
+      ),
+      (
+        "navigator.sendBeacon(url, { ...process.env });",
+        &["sends all environment variables over the network (1:29)"],
+      ),
+      (
+        "new WebSocket(`${url}?${Deno.env.toObject()}`);",
+        &["sends all environment variables over the network (1:25)"],
+      ),
+    ];
+    for (source, expected) in cases {
+      let found = find_suspicious_code(&parse(source));
+      assert_eq!(&found, expected, "{source}");
+    }
+  }
+
+  #[test]
+  fn clamd_response() {
+    assert_eq!(parse_clamd_response("stream: OK").unwrap(), None);
+    assert_eq!(
+      parse_clamd_response("stream: Eicar-Test-Signature FOUND").unwrap(),
+      Some("Eicar-Test-Signature".to_owned())
+    );
+    assert!(
+      parse_clamd_response("INSTREAM size limit exceeded. ERROR").is_err()
+    );
+  }
+}
@@ -1,6 +1,8 @@
 // Copyright 2024 the JSR authors. All rights reserved. MIT license.
 // https://www.notion.so/denolandinc/Deno-2-Roadmap-7301003f57754ccea043388d3cc15d8c
 use crate::db::Database;
+use crate::db::PackageDistTag;
+use crate::db::PackageVersionForMetadata;
 use crate::ids::PackageName;
 use crate::ids::PackagePath;
 use crate::ids::ScopeName;
@@ -10,11 +12,19 @@ use serde::Deserialize;
 use serde::Serialize;
 use std::collections::HashMap;
 
+/// The dist tag that overrides which version is the latest one.
+pub const LATEST_DIST_TAG: &str = "latest";
+
 /// Looks like this:
 /// ```json
 /// {
 ///   "scope": "ry",
 ///   "name": "foo",
+///   "latest": "0.1.3",
+///   "archived": true,
+///   "tags": {
+///     "beta": "0.2.0-beta.1"
+///   },
 ///   "versions": {
 ///     "0.1.2": {
 ///       "yanked": true,
@@ -32,6 +42,13 @@ pub struct PackageMetadata {
   pub scope: ScopeName,
   pub name: PackageName,
   pub latest: Option<Version>,
+  /// Whether the package is archived, meaning it is read-only and no longer
+  /// receives new versions.
+  #[serde(skip_serializing_if = "is_false", default)]
+  pub archived: bool,
+  /// The dist tags of the package that point at unyanked versions.
+  #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
+  pub tags: IndexMap<String, Version>,
   pub versions: HashMap<Version, PackageMetadataVersion>,
 }
 
@@ -41,18 +58,50 @@ impl PackageMetadata {
     scope: &ScopeName,
     package_name: &PackageName,
   ) -> anyhow::Result<Self> {
-    let mut versions = db
+    let versions = db
       .list_package_versions_for_metadata(scope, package_name)
       .await?;
+    let dist_tags = db.list_package_dist_tags(scope, package_name).await?;
+    let archived = db
+      .get_package(scope, package_name)
+      .await?
+      .is_some_and(|(package, _, _)| package.is_archived);
+    let mut metadata =
+      Self::from_versions(scope, package_name, versions, &dist_tags);
+    metadata.archived = archived;
+    Ok(metadata)
+  }
+
+  /// A `latest` dist tag takes precedence over the newest unyanked stable
+  /// version. Tags pointing at yanked versions are left out.
+  pub fn from_versions(
+    scope: &ScopeName,
+    package_name: &PackageName,
+    mut versions: Vec<PackageVersionForMetadata>,
+    dist_tags: &[PackageDistTag],
+  ) -> Self {
     versions.sort_by(|a, b| b.version.cmp(&a.version));
-    let latest = versions
+    let tags = dist_tags
       .iter()
-      .find(|v| !v.is_yanked && v.version.0.pre.is_empty())
-      .map(|v| v.version.clone());
+      .filter(|dist_tag| {
+        versions
+          .iter()
+          .any(|v| v.version == dist_tag.version && !v.is_yanked)
+      })
+      .map(|dist_tag| (dist_tag.tag.clone(), dist_tag.version.clone()))
+      .collect::<IndexMap<_, _>>();
+    let latest = tags.get(LATEST_DIST_TAG).cloned().or_else(|| {
+      versions
+        .iter()
+        .find(|v| !v.is_yanked && v.version.0.pre.is_empty())
+        .map(|v| v.version.clone())
+    });
     let mut out = Self {
       scope: scope.to_owned(),
       name: package_name.to_owned(),
       latest,
+      archived: false,
+      tags,
       versions: HashMap::new(),
     };
     for version in versions {
@@ -64,7 +113,7 @@ impl PackageMetadata {
         },
       );
     }
-    Ok(out)
+    out
   }
 }
 
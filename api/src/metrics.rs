@@ -0,0 +1,385 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+//! Metrics of this API instance, served in the Prometheus text format at
+//! `/metrics`.
+//!
+//! Counters and histograms are recorded into a process wide registry as
+//! publishes, storage requests and webhook deliveries happen, and count from
+//! when the instance started. The state of the database pools is read when the
+//! metrics are scraped. The endpoint is only served if a metrics token is
+//! configured, and scrapers must present it as a bearer token.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use hyper::Body;
+use hyper::Request;
+use hyper::Response;
+use hyper::header;
+use routerify::prelude::RequestExt;
+use tracing::instrument;
+
+use crate::api::ApiError;
+use crate::db::Database;
+use crate::db::PoolHealth;
+use crate::s3_policy::StorageOperation;
+
+/// The bearer token that scrapers of `/metrics` must present.
+pub struct MetricsToken(pub String);
+
+/// A stage of processing a publish. Graph building is part of
+/// [PublishStage::Analysis], and is also recorded on its own.
+#[derive(Debug, Clone, Copy)]
+pub enum PublishStage {
+  /// Reading the files out of the tarball.
+  Unpack,
+  /// Building and checking the module graph and the fast check type graph.
+  Analysis,
+  /// Generating the docs of the exports.
+  Docs,
+  /// Checking the code examples in the readme and docs.
+  Examples,
+  /// Creating the npm compatibility tarball.
+  NpmTarball,
+  /// Scanning the files for malware.
+  MalwareScan,
+  /// Uploading the files and docs of the version.
+  Upload,
+  /// Writing the version to the database.
+  CreateVersion,
+}
+
+impl PublishStage {
+  fn as_str(self) -> &'static str {
+    match self {
+      PublishStage::Unpack => "unpack",
+      PublishStage::Analysis => "analysis",
+      PublishStage::Docs => "docs",
+      PublishStage::Examples => "examples",
+      PublishStage::NpmTarball => "npm_tarball",
+      PublishStage::MalwareScan => "malware_scan",
+      PublishStage::Upload => "upload",
+      PublishStage::CreateVersion => "create_version",
+    }
+  }
+}
+
+struct HistogramDesc {
+  name: &'static str,
+  help: &'static str,
+  buckets: &'static [f64],
+}
+
+struct CounterDesc {
+  name: &'static str,
+  help: &'static str,
+}
+
+const DURATION_BUCKETS: &[f64] = &[
+  0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+  120.0,
+];
+
+const SIZE_BUCKETS: &[f64] = &[
+  1024.0, 10240.0, 102400.0, 1048576.0, 5242880.0, 10485760.0, 20971520.0,
+  52428800.0,
+];
+
+const PUBLISH_STAGE_DURATION: HistogramDesc = HistogramDesc {
+  name: "jsr_publish_stage_duration_seconds",
+  help: "How long the stages of processing a publish took.",
+  buckets: DURATION_BUCKETS,
+};
+
+const GRAPH_BUILD_DURATION: HistogramDesc = HistogramDesc {
+  name: "jsr_graph_build_duration_seconds",
+  help: "How long building the module graph of a published version took.",
+  buckets: DURATION_BUCKETS,
+};
+
+const TARBALL_SIZE: HistogramDesc = HistogramDesc {
+  name: "jsr_publish_tarball_size_bytes",
+  help: "The total size of the files in published tarballs, uncompressed.",
+  buckets: SIZE_BUCKETS,
+};
+
+const STORAGE_REQUEST_DURATION: HistogramDesc = HistogramDesc {
+  name: "jsr_storage_request_duration_seconds",
+  help: "How long requests to the buckets took, including retries.",
+  buckets: DURATION_BUCKETS,
+};
+
+const HISTOGRAMS: [&HistogramDesc; 4] = [
+  &PUBLISH_STAGE_DURATION,
+  &GRAPH_BUILD_DURATION,
+  &TARBALL_SIZE,
+  &STORAGE_REQUEST_DURATION,
+];
+
+const WEBHOOK_DELIVERIES: CounterDesc = CounterDesc {
+  name: "jsr_webhook_deliveries_total",
+  help: "Attempts to deliver webhooks, by outcome.",
+};
+
+const COUNTERS: [&CounterDesc; 1] = [&WEBHOOK_DELIVERIES];
+
+type Labels = Vec<(&'static str, String)>;
+
+struct HistogramValues {
+  /// The number of observations in each bucket, not cumulative.
+  buckets: Vec<u64>,
+  sum: f64,
+  count: u64,
+}
+
+#[derive(Default)]
+struct Registry {
+  histograms: Mutex<BTreeMap<(&'static str, Labels), HistogramValues>>,
+  counters: Mutex<BTreeMap<(&'static str, Labels), u64>>,
+}
+
+static REGISTRY: LazyLock<Registry> = LazyLock::new(Registry::default);
+
+impl Registry {
+  fn observe(&self, desc: &HistogramDesc, labels: Labels, value: f64) {
+    let mut histograms = self.histograms.lock().unwrap();
+    let values = histograms.entry((desc.name, labels)).or_insert_with(|| {
+      HistogramValues {
+        buckets: vec![0; desc.buckets.len()],
+        sum: 0.0,
+        count: 0,
+      }
+    });
+    if let Some(i) = desc.buckets.iter().position(|le| value <= *le) {
+      values.buckets[i] += 1;
+    }
+    values.sum += value;
+    values.count += 1;
+  }
+
+  fn increment(&self, desc: &CounterDesc, labels: Labels) {
+    let mut counters = self.counters.lock().unwrap();
+    *counters.entry((desc.name, labels)).or_default() += 1;
+  }
+
+  fn render(&self, out: &mut String) {
+    let histograms = self.histograms.lock().unwrap();
+    for desc in HISTOGRAMS {
+      writeln!(out, "# HELP {} {}", desc.name, desc.help).unwrap();
+      writeln!(out, "# TYPE {} histogram", desc.name).unwrap();
+      for ((_, labels), values) in histograms
+        .iter()
+        .filter(|((name, _), _)| *name == desc.name)
+      {
+        let mut cumulative = 0;
+        for (le, count) in desc.buckets.iter().zip(&values.buckets) {
+          cumulative += count;
+          let labels = format_labels(labels, Some(&le.to_string()));
+          writeln!(out, "{}_bucket{labels} {cumulative}", desc.name).unwrap();
+        }
+        let bucket_labels = format_labels(labels, Some("+Inf"));
+        writeln!(out, "{}_bucket{bucket_labels} {}", desc.name, values.count)
+          .unwrap();
+        let labels = format_labels(labels, None);
+        writeln!(out, "{}_sum{labels} {}", desc.name, values.sum).unwrap();
+        writeln!(out, "{}_count{labels} {}", desc.name, values.count).unwrap();
+      }
+    }
+
+    let counters = self.counters.lock().unwrap();
+    for desc in COUNTERS {
+      writeln!(out, "# HELP {} {}", desc.name, desc.help).unwrap();
+      writeln!(out, "# TYPE {} counter", desc.name).unwrap();
+      for ((_, labels), value) in
+        counters.iter().filter(|((name, _), _)| *name == desc.name)
+      {
+        let labels = format_labels(labels, None);
+        writeln!(out, "{}{labels} {value}", desc.name).unwrap();
+      }
+    }
+  }
+}
+
+/// Formats labels as `{a="1",b="2"}`, with `le` last if it is set.
+fn format_labels(labels: &Labels, le: Option<&str>) -> String {
+  let mut pairs = labels
+    .iter()
+    .map(|(name, value)| format!("{name}=\"{}\"", escape_label_value(value)))
+    .collect::<Vec<_>>();
+  if let Some(le) = le {
+    pairs.push(format!("le=\"{le}\""));
+  }
+  if pairs.is_empty() {
+    String::new()
+  } else {
+    format!("{{{}}}", pairs.join(","))
+  }
+}
+
+fn escape_label_value(value: &str) -> String {
+  value
+    .replace('\\', "\\\\")
+    .replace('"', "\\\"")
+    .replace('\n', "\\n")
+}
+
+pub fn observe_publish_stage(stage: PublishStage, duration: Duration) {
+  REGISTRY.observe(
+    &PUBLISH_STAGE_DURATION,
+    vec![("stage", stage.as_str().to_owned())],
+    duration.as_secs_f64(),
+  );
+}
+
+pub fn observe_graph_build(duration: Duration) {
+  REGISTRY.observe(&GRAPH_BUILD_DURATION, vec![], duration.as_secs_f64());
+}
+
+pub fn observe_tarball_size(size: u64) {
+  REGISTRY.observe(&TARBALL_SIZE, vec![], size as f64);
+}
+
+pub fn observe_storage_request(
+  bucket: &str,
+  operation: StorageOperation,
+  duration: Duration,
+  success: bool,
+) {
+  REGISTRY.observe(
+    &STORAGE_REQUEST_DURATION,
+    vec![
+      ("bucket", bucket.to_owned()),
+      ("operation", operation.as_str().to_owned()),
+      ("outcome", outcome(success).to_owned()),
+    ],
+    duration.as_secs_f64(),
+  );
+}
+
+/// Records an attempt to deliver a webhook. `retrying` is whether a failed
+/// delivery will be attempted again.
+pub fn record_webhook_delivery(success: bool, retrying: bool) {
+  let outcome = match (success, retrying) {
+    (true, _) => "success",
+    (false, true) => "retry",
+    (false, false) => "failure",
+  };
+  REGISTRY.increment(&WEBHOOK_DELIVERIES, vec![("outcome", outcome.into())]);
+}
+
+fn outcome(success: bool) -> &'static str {
+  if success { "success" } else { "failure" }
+}
+
+/// Renders the gauges of the database pools, `pool` being `primary` or
+/// `replica`.
+fn render_pools(out: &mut String, pools: &[(&str, PoolHealth)]) {
+  let gauges: [(&str, &str, fn(&PoolHealth) -> f64); 4] = [
+    (
+      "jsr_db_pool_connections",
+      "The number of open connections, idle or in use.",
+      |pool| pool.size as f64,
+    ),
+    (
+      "jsr_db_pool_idle_connections",
+      "The number of idle connections.",
+      |pool| pool.idle as f64,
+    ),
+    (
+      "jsr_db_pool_max_connections",
+      "The maximum number of connections.",
+      |pool| pool.max_connections as f64,
+    ),
+    (
+      "jsr_db_pool_saturation_ratio",
+      "The share of the maximum number of connections that are in use.",
+      |pool| {
+        let in_use = pool.size as f64 - pool.idle as f64;
+        in_use / pool.max_connections.max(1) as f64
+      },
+    ),
+  ];
+  for (name, help, value) in gauges {
+    writeln!(out, "# HELP {name} {help}").unwrap();
+    writeln!(out, "# TYPE {name} gauge").unwrap();
+    for (pool, health) in pools {
+      writeln!(out, "{name}{{pool=\"{pool}\"}} {}", value(health)).unwrap();
+    }
+  }
+}
+
+/// Renders all metrics in the Prometheus text format.
+pub fn render(db: &Database) -> String {
+  let mut out = String::new();
+  REGISTRY.render(&mut out);
+  let (primary, replica) = db.pool_health();
+  let mut pools = vec![("primary", primary)];
+  if let Some(replica) = replica {
+    pools.push(("replica", replica));
+  }
+  render_pools(&mut out, &pools);
+  out
+}
+
+#[instrument(name = "GET /metrics", skip(req), err)]
+pub async fn metrics_handler(
+  req: Request<Body>,
+) -> Result<Response<Body>, ApiError> {
+  let MetricsToken(token) = req.data::<MetricsToken>().unwrap();
+  let authorized = req
+    .headers()
+    .get(header::AUTHORIZATION)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.strip_prefix("Bearer "))
+    .is_some_and(|value| value == token.as_str());
+  if !authorized {
+    return Err(ApiError::InvalidBearerToken);
+  }
+
+  let db = req.data::<Database>().unwrap();
+  let response = Response::builder()
+    .header("Content-Type", "text/plain; version=0.0.4; charset=utf-8")
+    .header("Cache-Control", "no-store")
+    .body(Body::from(render(db)))
+    .unwrap();
+  Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Registry;
+  use super::STORAGE_REQUEST_DURATION;
+  use super::WEBHOOK_DELIVERIES;
+
+  #[test]
+  fn render() {
+    let registry = Registry::default();
+    let labels = vec![("bucket", "modules".to_owned())];
+    registry.observe(&STORAGE_REQUEST_DURATION, labels.clone(), 0.015625);
+    registry.observe(&STORAGE_REQUEST_DURATION, labels, 512.0);
+    registry.increment(
+      &WEBHOOK_DELIVERIES,
+      vec![("outcome", "quote\"d".to_owned())],
+    );
+
+    let mut out = String::new();
+    registry.render(&mut out);
+    let lines = out.lines().collect::<Vec<_>>();
+    for line in [
+      "# TYPE jsr_storage_request_duration_seconds histogram",
+      "jsr_storage_request_duration_seconds_bucket{bucket=\"modules\",le=\"0.01\"} 0",
+      "jsr_storage_request_duration_seconds_bucket{bucket=\"modules\",le=\"0.025\"} 1",
+      "jsr_storage_request_duration_seconds_bucket{bucket=\"modules\",le=\"120\"} 1",
+      "jsr_storage_request_duration_seconds_bucket{bucket=\"modules\",le=\"+Inf\"} 2",
+      "jsr_storage_request_duration_seconds_sum{bucket=\"modules\"} 512.015625",
+      "jsr_storage_request_duration_seconds_count{bucket=\"modules\"} 2",
+      "# TYPE jsr_webhook_deliveries_total counter",
+      "jsr_webhook_deliveries_total{outcome=\"quote\\\"d\"} 1",
+    ] {
+      assert!(lines.contains(&line), "missing {line:?} in:\n{out}");
+    }
+  }
+}
@@ -0,0 +1,205 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+//! Module files are stored once per distinct content, under the hash of it
+//! (see [s3_paths::module_blob_path]), so that versions that share most of
+//! their files don't store them again. Every version has a manifest that maps
+//! the paths of its files to those hashes. Versions published before files
+//! were deduplicated have no manifest, and their files are stored under their
+//! paths instead.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::StreamExt;
+use serde::Deserialize;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::ids::PackageName;
+use crate::ids::PackagePath;
+use crate::ids::ScopeName;
+use crate::ids::Version;
+use crate::integrity::DigestMismatch;
+use crate::integrity::verify_digest;
+use crate::s3::BucketWithQueue;
+use crate::s3::S3Error;
+use crate::s3_paths;
+
+#[derive(Debug, Error, deno_error::JsError)]
+#[class(generic)]
+pub enum ModuleFilesError {
+  #[error(transparent)]
+  S3(#[from] S3Error),
+  #[error("invalid files manifest: {0}")]
+  Manifest(#[from] serde_json::Error),
+  #[error("stored file {path} is corrupted: {source}")]
+  Integrity {
+    path: PackagePath,
+    source: DigestMismatch,
+  },
+}
+
+/// The manifest of a package version, stored at
+/// [s3_paths::version_files_manifest]. The lb reads it to serve the files.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FilesManifest {
+  pub files: HashMap<PackagePath, ManifestFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestFile {
+  /// The hash of the content of the file, like `sha256-...`.
+  pub hash: String,
+  /// A blob can be shared by files of different types, so the content type is
+  /// stored with the path rather than with the blob.
+  pub content_type: Option<String>,
+}
+
+/// The files of a package version, wherever they are stored.
+#[derive(Clone)]
+pub struct VersionFiles {
+  bucket: BucketWithQueue,
+  scope: ScopeName,
+  package: PackageName,
+  version: Version,
+  manifest: Option<Arc<FilesManifest>>,
+}
+
+impl VersionFiles {
+  pub async fn load(
+    bucket: &BucketWithQueue,
+    scope: &ScopeName,
+    package: &PackageName,
+    version: &Version,
+  ) -> Result<Self, ModuleFilesError> {
+    let manifest_path =
+      s3_paths::version_files_manifest(scope, package, version);
+    let manifest = match bucket.download(manifest_path.into()).await? {
+      Some(bytes) => Some(Arc::new(serde_json::from_slice(&bytes)?)),
+      None => None,
+    };
+    Ok(Self {
+      bucket: bucket.clone(),
+      scope: scope.clone(),
+      package: package.clone(),
+      version: version.clone(),
+      manifest,
+    })
+  }
+
+  /// The path of the object that stores the file, or `None` if the version
+  /// has no such file.
+  pub fn object_path(&self, path: &PackagePath) -> Option<String> {
+    match &self.manifest {
+      Some(manifest) => manifest
+        .files
+        .get(path)
+        .map(|file| s3_paths::module_blob_path(&file.hash)),
+      None => Some(s3_paths::file_path(
+        &self.scope,
+        &self.package,
+        &self.version,
+        path,
+      )),
+    }
+  }
+
+  /// Downloads a file. Files stored by the hash of their content are verified
+  /// against it.
+  pub async fn download(
+    &self,
+    path: &PackagePath,
+  ) -> Result<Option<Bytes>, ModuleFilesError> {
+    let Some(object_path) = self.object_path(path) else {
+      return Ok(None);
+    };
+    let Some(bytes) = self.bucket.download(object_path.into()).await? else {
+      return Ok(None);
+    };
+    if let Some(file) = self
+      .manifest
+      .as_ref()
+      .and_then(|manifest| manifest.files.get(path))
+    {
+      verify_digest(&file.hash, &bytes).map_err(|source| {
+        ModuleFilesError::Integrity {
+          path: path.clone(),
+          source,
+        }
+      })?;
+    }
+    Ok(Some(bytes))
+  }
+}
+
+/// The total size of the files a [CachedVersionFiles] keeps in memory. Files
+/// that don't fit are downloaded again when they are needed.
+const MAX_CACHED_FILES_SIZE: u64 = 64 * 1024 * 1024;
+
+/// How many files [CachedVersionFiles::prefetch] downloads at a time.
+const PREFETCH_CONCURRENCY: usize = 16;
+
+/// The files of a package version, kept in memory once downloaded. Building a
+/// module graph downloads modules one import at a time, so the files can be
+/// prefetched while the graph is built.
+#[derive(Clone)]
+pub struct CachedVersionFiles {
+  files: VersionFiles,
+  cache: moka::future::Cache<PackagePath, Option<Bytes>>,
+}
+
+impl CachedVersionFiles {
+  pub fn new(files: VersionFiles) -> Self {
+    Self {
+      files,
+      cache: moka::future::Cache::builder()
+        .max_capacity(MAX_CACHED_FILES_SIZE)
+        .weigher(|_, bytes: &Option<Bytes>| {
+          bytes
+            .as_ref()
+            .map_or(0, |bytes| bytes.len().try_into().unwrap_or(u32::MAX))
+        })
+        .build(),
+    }
+  }
+
+  /// Downloads a file, or returns it from memory. Concurrent downloads of
+  /// the same file are only sent to the bucket once.
+  pub async fn download(
+    &self,
+    path: &PackagePath,
+  ) -> Result<Option<Bytes>, Arc<ModuleFilesError>> {
+    self
+      .cache
+      .try_get_with_by_ref(path, self.files.download(path))
+      .await
+  }
+
+  /// Downloads the given files into memory, a bounded number at a time.
+  /// Errors are ignored, the file is downloaded again when it is needed.
+  pub async fn prefetch(&self, paths: impl Iterator<Item = &PackagePath>) {
+    futures::stream::iter(paths)
+      .for_each_concurrent(PREFETCH_CONCURRENCY, |path| async move {
+        if let Err(err) = self.download(path).await {
+          tracing::warn!("failed to prefetch {path}: {err}");
+        }
+      })
+      .await;
+  }
+}
+
+/// Downloads a single file of a package version. Use [VersionFiles] to
+/// download several.
+pub async fn download_file(
+  bucket: &BucketWithQueue,
+  scope: &ScopeName,
+  package: &PackageName,
+  version: &Version,
+  path: &PackagePath,
+) -> Result<Option<Bytes>, ModuleFilesError> {
+  VersionFiles::load(bucket, scope, package, version)
+    .await?
+    .download(path)
+    .await
+}
@@ -0,0 +1,624 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ops::Range;
+
+use deno_ast::MediaType;
+use deno_ast::ParseParams;
+use deno_ast::ParsedSource;
+use deno_ast::ProgramRef;
+use deno_ast::SourceRanged;
+use deno_ast::TextChange;
+use deno_ast::apply_text_changes;
+use deno_ast::swc::ast::Decl;
+use deno_ast::swc::ast::DefaultDecl;
+use deno_ast::swc::ast::ExportSpecifier;
+use deno_ast::swc::ast::Expr;
+use deno_ast::swc::ast::ImportSpecifier;
+use deno_ast::swc::ast::ModuleDecl;
+use deno_ast::swc::ast::ModuleExportName;
+use deno_ast::swc::ast::ModuleItem;
+use deno_ast::swc::ast::Pat;
+use deno_ast::swc::ast::Stmt;
+use deno_ast::swc::ast::TsImportType;
+use deno_ast::swc::ast::TsModuleName;
+use deno_ast::swc::ecma_visit::Visit;
+use deno_ast::swc::ecma_visit::VisitWith;
+use indexmap::IndexMap;
+use url::Url;
+
+/// Bundles the declaration file at `entry` and every declaration file it
+/// imports with a relative specifier into a single declaration file, so that
+/// TypeScript only has to load one file for the entrypoint.
+///
+/// Declarations are not renamed, so this fails if two of the files declare
+/// the same name, or if the files use a construct that can not be bundled
+/// without renaming, like namespace imports of other files in the package.
+pub fn rollup_dts(
+  entry: &str,
+  package_files: &IndexMap<String, Vec<u8>>,
+) -> Result<String, anyhow::Error> {
+  let mut rollup = DtsRollup {
+    package_files,
+    modules: HashMap::new(),
+    in_progress: HashSet::new(),
+    declared: HashMap::new(),
+    external_imports: IndexMap::new(),
+    bodies: vec![],
+  };
+  rollup.add_module(entry, true)?;
+
+  let mut text = String::new();
+  for (local, import) in &rollup.external_imports {
+    text.push_str(&import.to_statement(local));
+    text.push('\n');
+  }
+  for body in &rollup.bodies {
+    let body = body.trim();
+    if !body.is_empty() {
+      text.push_str(body);
+      text.push('\n');
+    }
+  }
+
+  let exports = rollup.all_exports(entry)?;
+  let specifiers = exports
+    .iter()
+    .map(|(exported, local)| {
+      if exported == local {
+        local.clone()
+      } else {
+        format!("{local} as {exported}")
+      }
+    })
+    .collect::<Vec<_>>();
+  text.push_str(&format!("export {{ {} }};\n", specifiers.join(", ")));
+
+  Ok(text)
+}
+
+#[derive(Clone, PartialEq, Eq)]
+enum ExternalImport {
+  Named { src: String, imported: String },
+  Default { src: String },
+  Namespace { src: String },
+}
+
+impl ExternalImport {
+  fn to_statement(&self, local: &str) -> String {
+    match self {
+      ExternalImport::Named { src, imported } if imported == local => {
+        format!("import {{ {local} }} from \"{src}\";")
+      }
+      ExternalImport::Named { src, imported } => {
+        format!("import {{ {imported} as {local} }} from \"{src}\";")
+      }
+      ExternalImport::Default { src } => {
+        format!("import {local} from \"{src}\";")
+      }
+      ExternalImport::Namespace { src } => {
+        format!("import * as {local} from \"{src}\";")
+      }
+    }
+  }
+}
+
+#[derive(Default)]
+struct DtsModuleExports {
+  /// Exported names, mapped to the name of the top level declaration they
+  /// refer to.
+  named: IndexMap<String, String>,
+  /// Paths of the modules that are re-exported with `export *`.
+  stars: Vec<String>,
+}
+
+struct DtsRollup<'a> {
+  package_files: &'a IndexMap<String, Vec<u8>>,
+  modules: HashMap<String, DtsModuleExports>,
+  in_progress: HashSet<String>,
+  /// Top level names, mapped to the path of the module that declares them,
+  /// or an empty string for imports of other packages.
+  declared: HashMap<String, String>,
+  external_imports: IndexMap<String, ExternalImport>,
+  bodies: Vec<String>,
+}
+
+impl DtsRollup<'_> {
+  /// Adds the module at `path`, after all the modules it imports.
+  fn add_module(
+    &mut self,
+    path: &str,
+    is_entry: bool,
+  ) -> Result<(), anyhow::Error> {
+    if self.modules.contains_key(path) {
+      return Ok(());
+    }
+    if !self.in_progress.insert(path.to_string()) {
+      anyhow::bail!("'{path}' is part of an import cycle");
+    }
+
+    let Some(bytes) = self.package_files.get(path) else {
+      anyhow::bail!("'{path}' is not in the tarball");
+    };
+    let text = std::str::from_utf8(bytes)?;
+    // Source maps and file references do not apply to the rolled up file.
+    let text = text
+      .lines()
+      .filter(|line| !line.starts_with("//# sourceMappingURL="))
+      .collect::<Vec<_>>()
+      .join("\n");
+    if text.contains("/// <reference path=") {
+      anyhow::bail!("'{path}' references another file");
+    }
+
+    let specifier = Url::parse(&format!("file://{path}")).unwrap();
+    let parsed = deno_ast::parse_module(ParseParams {
+      specifier: specifier.clone(),
+      text: text.into(),
+      media_type: MediaType::from_path(std::path::Path::new(path)),
+      capture_tokens: false,
+      scope_analysis: false,
+      maybe_syntax: None,
+    })?;
+    let ProgramRef::Module(module) = parsed.program_ref() else {
+      anyhow::bail!("'{path}' is not a module");
+    };
+
+    let mut import_types = RelativeImportTypeFinder { found: false };
+    module.visit_with(&mut import_types);
+    if import_types.found {
+      anyhow::bail!("'{path}' has an import type of another file");
+    }
+
+    let mut exports = DtsModuleExports::default();
+    let mut text_changes = vec![];
+    let remove = |text_changes: &mut Vec<TextChange>, range: Range<usize>| {
+      text_changes.push(TextChange {
+        new_text: String::new(),
+        range,
+      });
+    };
+
+    for item in &module.body {
+      match item {
+        ModuleItem::ModuleDecl(ModuleDecl::Import(import)) => {
+          let src = str_value(&import.src.value)?;
+          match self.resolve(&specifier, src)? {
+            Some(target) => {
+              self.add_module(&target, false)?;
+              for import_specifier in &import.specifiers {
+                let (local, imported) = match import_specifier {
+                  ImportSpecifier::Named(named) => (
+                    named.local.sym.to_string(),
+                    match &named.imported {
+                      Some(imported) => export_name(imported)?,
+                      None => named.local.sym.to_string(),
+                    },
+                  ),
+                  ImportSpecifier::Default(default) => {
+                    (default.local.sym.to_string(), "default".to_string())
+                  }
+                  ImportSpecifier::Namespace(_) => {
+                    anyhow::bail!("'{path}' has a namespace import of '{src}'")
+                  }
+                };
+                let resolved = self.resolve_export(&target, &imported)?;
+                if resolved != local {
+                  anyhow::bail!(
+                    "'{path}' imports '{resolved}' from '{src}' as '{local}'"
+                  );
+                }
+              }
+            }
+            None => {
+              if import.with.is_some() {
+                anyhow::bail!("'{path}' has an import with attributes");
+              }
+              for import_specifier in &import.specifiers {
+                let (local, external_import) = match import_specifier {
+                  ImportSpecifier::Named(named) => (
+                    named.local.sym.to_string(),
+                    ExternalImport::Named {
+                      src: src.to_string(),
+                      imported: match &named.imported {
+                        Some(imported) => export_name(imported)?,
+                        None => named.local.sym.to_string(),
+                      },
+                    },
+                  ),
+                  ImportSpecifier::Default(default) => (
+                    default.local.sym.to_string(),
+                    ExternalImport::Default {
+                      src: src.to_string(),
+                    },
+                  ),
+                  ImportSpecifier::Namespace(namespace) => (
+                    namespace.local.sym.to_string(),
+                    ExternalImport::Namespace {
+                      src: src.to_string(),
+                    },
+                  ),
+                };
+                match self.external_imports.get(&local) {
+                  Some(existing) if *existing == external_import => {}
+                  Some(_) => anyhow::bail!("'{local}' is declared twice"),
+                  None => {
+                    self.declare("", &local)?;
+                    self.external_imports.insert(local, external_import);
+                  }
+                }
+              }
+            }
+          }
+          remove(&mut text_changes, byte_range(&parsed, import));
+        }
+        ModuleItem::ModuleDecl(ModuleDecl::ExportDecl(export_decl)) => {
+          for name in self.declare_decl(path, &export_decl.decl)? {
+            exports.named.insert(name.clone(), name);
+          }
+          let range = byte_range(&parsed, export_decl).start
+            ..byte_range(&parsed, &export_decl.decl).start;
+          text_changes.push(TextChange {
+            new_text: strip_export_keywords(
+              parsed.text(),
+              range.clone(),
+              needs_declare(&export_decl.decl),
+            ),
+            range,
+          });
+        }
+        ModuleItem::ModuleDecl(ModuleDecl::ExportNamed(named_export)) => {
+          let target = match &named_export.src {
+            Some(src) => {
+              let src = str_value(&src.value)?;
+              match self.resolve(&specifier, src)? {
+                Some(target) => Some(target),
+                // Re-exports of other packages stay as they are.
+                None if is_entry => continue,
+                None => {
+                  anyhow::bail!("'{path}' re-exports '{src}'")
+                }
+              }
+            }
+            None => None,
+          };
+          if let Some(target) = &target {
+            self.add_module(target, false)?;
+          }
+          for export_specifier in &named_export.specifiers {
+            let ExportSpecifier::Named(named) = export_specifier else {
+              anyhow::bail!("'{path}' has a namespace re-export");
+            };
+            let orig = export_name(&named.orig)?;
+            let exported = match &named.exported {
+              Some(exported) => export_name(exported)?,
+              None => orig.clone(),
+            };
+            let local = match &target {
+              Some(target) => self.resolve_export(target, &orig)?,
+              None => orig,
+            };
+            exports.named.insert(exported, local);
+          }
+          remove(&mut text_changes, byte_range(&parsed, named_export));
+        }
+        ModuleItem::ModuleDecl(ModuleDecl::ExportAll(export_all)) => {
+          let src = str_value(&export_all.src.value)?;
+          match self.resolve(&specifier, src)? {
+            Some(target) => {
+              self.add_module(&target, false)?;
+              exports.stars.push(target);
+              remove(&mut text_changes, byte_range(&parsed, export_all));
+            }
+            None if is_entry => {}
+            None => anyhow::bail!("'{path}' re-exports '{src}'"),
+          }
+        }
+        ModuleItem::ModuleDecl(ModuleDecl::ExportDefaultDecl(default)) => {
+          let (ident, declare) = match &default.decl {
+            DefaultDecl::Class(class) => (class.ident.as_ref(), true),
+            DefaultDecl::Fn(function) => (function.ident.as_ref(), true),
+            DefaultDecl::TsInterfaceDecl(interface) => {
+              (Some(&interface.id), false)
+            }
+            #[allow(unreachable_patterns)]
+            _ => (None, false),
+          };
+          let Some(ident) = ident else {
+            anyhow::bail!("'{path}' has an anonymous default export");
+          };
+          let name = ident.sym.to_string();
+          self.declare(path, &name)?;
+          exports.named.insert("default".to_string(), name);
+          let range = byte_range(&parsed, default).start
+            ..byte_range(&parsed, &default.decl).start;
+          text_changes.push(TextChange {
+            new_text: strip_export_keywords(
+              parsed.text(),
+              range.clone(),
+              declare,
+            ),
+            range,
+          });
+        }
+        ModuleItem::ModuleDecl(ModuleDecl::ExportDefaultExpr(default)) => {
+          let Expr::Ident(ident) = &*default.expr else {
+            anyhow::bail!("'{path}' has a default export of an expression");
+          };
+          exports
+            .named
+            .insert("default".to_string(), ident.sym.to_string());
+          remove(&mut text_changes, byte_range(&parsed, default));
+        }
+        ModuleItem::ModuleDecl(_) => {
+          anyhow::bail!("'{path}' has an unsupported module declaration");
+        }
+        ModuleItem::Stmt(Stmt::Decl(decl)) => {
+          self.declare_decl(path, decl)?;
+        }
+        ModuleItem::Stmt(_) => {}
+      }
+    }
+
+    self
+      .bodies
+      .push(apply_text_changes(parsed.text().as_ref(), text_changes));
+    self.in_progress.remove(path);
+    self.modules.insert(path.to_string(), exports);
+    Ok(())
+  }
+
+  /// The path of the declaration file that a relative `specifier` in the
+  /// module at `referrer` points to, or `None` for other packages.
+  fn resolve(
+    &self,
+    referrer: &Url,
+    specifier: &str,
+  ) -> Result<Option<String>, anyhow::Error> {
+    if !is_relative(specifier) {
+      return Ok(None);
+    }
+    let resolved = referrer.join(specifier)?;
+    let path = resolved.path();
+    let path = if path.ends_with(".d.ts") || path.ends_with(".d.mts") {
+      path.to_string()
+    } else if let Some(stem) = path.strip_suffix(".js") {
+      format!("{stem}.d.ts")
+    } else if let Some(stem) = path.strip_suffix(".mjs") {
+      format!("{stem}.d.mts")
+    } else {
+      anyhow::bail!("'{specifier}' is not a declaration file");
+    };
+    if !self.package_files.contains_key(&path) {
+      anyhow::bail!("'{path}' is not in the tarball");
+    }
+    Ok(Some(path))
+  }
+
+  /// The top level name that `name` refers to when imported from the
+  /// module at `path`.
+  fn resolve_export(
+    &self,
+    path: &str,
+    name: &str,
+  ) -> Result<String, anyhow::Error> {
+    let module = &self.modules[path];
+    if let Some(local) = module.named.get(name) {
+      return Ok(local.clone());
+    }
+    if name != "default" {
+      let mut found = None;
+      for star in &module.stars {
+        if let Ok(local) = self.resolve_export(star, name) {
+          if found.as_ref().is_some_and(|found| *found != local) {
+            anyhow::bail!("'{name}' is exported by '{path}' more than once");
+          }
+          found = Some(local);
+        }
+      }
+      if let Some(local) = found {
+        return Ok(local);
+      }
+    }
+    anyhow::bail!("'{path}' does not export '{name}'")
+  }
+
+  /// Every name exported by the module at `path`, including those of the
+  /// modules it re-exports with `export *`.
+  fn all_exports(
+    &self,
+    path: &str,
+  ) -> Result<IndexMap<String, String>, anyhow::Error> {
+    let module = &self.modules[path];
+    let mut exports = module.named.clone();
+    for star in &module.stars {
+      for (name, _) in self.all_exports(star)? {
+        if name != "default" && !exports.contains_key(&name) {
+          let local = self.resolve_export(path, &name)?;
+          exports.insert(name, local);
+        }
+      }
+    }
+    Ok(exports)
+  }
+
+  /// Records that the module at `path` declares `name`. A module may declare
+  /// the same name more than once, like overloads of a function, which
+  /// TypeScript merges, but two modules may not.
+  fn declare(&mut self, path: &str, name: &str) -> Result<(), anyhow::Error> {
+    match self.declared.get(name) {
+      Some(declared_by) if declared_by != path || path.is_empty() => {
+        anyhow::bail!("'{name}' is declared in more than one file")
+      }
+      Some(_) => {}
+      None => {
+        self.declared.insert(name.to_string(), path.to_string());
+      }
+    }
+    Ok(())
+  }
+
+  /// Records the names declared by `decl` in the module at `path`, and
+  /// returns them.
+  fn declare_decl(
+    &mut self,
+    path: &str,
+    decl: &Decl,
+  ) -> Result<Vec<String>, anyhow::Error> {
+    let names = match decl {
+      Decl::Class(class) => vec![class.ident.sym.to_string()],
+      Decl::Fn(function) => vec![function.ident.sym.to_string()],
+      Decl::Var(var) => var
+        .decls
+        .iter()
+        .map(|declarator| match &declarator.name {
+          Pat::Ident(ident) => Ok(ident.id.sym.to_string()),
+          _ => Err(anyhow::anyhow!("destructuring declarations")),
+        })
+        .collect::<Result<_, _>>()?,
+      Decl::TsInterface(interface) => vec![interface.id.sym.to_string()],
+      Decl::TsTypeAlias(alias) => vec![alias.id.sym.to_string()],
+      Decl::TsEnum(ts_enum) => vec![ts_enum.id.sym.to_string()],
+      Decl::TsModule(ts_module) => match &ts_module.id {
+        TsModuleName::Ident(ident) => vec![ident.sym.to_string()],
+        TsModuleName::Str(name) if is_relative(str_value(&name.value)?) => {
+          anyhow::bail!("module augmentations of other files")
+        }
+        // `declare global` and `declare module "<package>"` do not declare
+        // anything at the top level.
+        TsModuleName::Str(_) => vec![],
+        #[allow(unreachable_patterns)]
+        _ => anyhow::bail!("unsupported module declaration"),
+      },
+      _ => anyhow::bail!("unsupported declaration"),
+    };
+    for name in &names {
+      self.declare(path, name)?;
+    }
+    Ok(names)
+  }
+}
+
+/// Whether a top level declaration of this kind in a declaration file needs
+/// the `declare` keyword once it is no longer exported.
+fn needs_declare(decl: &Decl) -> bool {
+  matches!(
+    decl,
+    Decl::Class(_) | Decl::Fn(_) | Decl::Var(_) | Decl::TsEnum(_)
+  ) || matches!(decl, Decl::TsModule(ts_module) if !ts_module.global)
+}
+
+/// Removes `export` and `default` from `range` of `text`, which spans from
+/// the start of an export declaration to the start of what it declares. The
+/// `declare` keyword may be on either side of the end of the range.
+fn strip_export_keywords(
+  text: &str,
+  range: Range<usize>,
+  needs_declare: bool,
+) -> String {
+  let rest = text[range.clone()]
+    .trim_start_matches("export")
+    .trim_start();
+  let rest = rest.strip_prefix("default").unwrap_or(rest).trim_start();
+  let is_declared =
+    rest.starts_with("declare") || text[range.end..].starts_with("declare");
+  if needs_declare && !is_declared {
+    format!("declare {rest}")
+  } else {
+    rest.to_string()
+  }
+}
+
+fn is_relative(specifier: &str) -> bool {
+  specifier.starts_with("./")
+    || specifier.starts_with("../")
+    || specifier.starts_with('/')
+}
+
+fn str_value(
+  value: &deno_ast::swc::atoms::Wtf8Atom,
+) -> Result<&str, anyhow::Error> {
+  value
+    .as_str()
+    .ok_or_else(|| anyhow::anyhow!("invalid string literal"))
+}
+
+fn export_name(name: &ModuleExportName) -> Result<String, anyhow::Error> {
+  match name {
+    ModuleExportName::Ident(ident) => Ok(ident.sym.to_string()),
+    _ => anyhow::bail!("string export names"),
+  }
+}
+
+fn byte_range(parsed: &ParsedSource, node: &impl SourceRanged) -> Range<usize> {
+  let start = parsed.text_info_lazy().range().start;
+  node.start().as_byte_index(start)..node.end().as_byte_index(start)
+}
+
+struct RelativeImportTypeFinder {
+  found: bool,
+}
+
+impl Visit for RelativeImportTypeFinder {
+  fn visit_ts_import_type(&mut self, node: &TsImportType) {
+    node.visit_children_with(self);
+    if node.arg.value.as_str().is_none_or(is_relative) {
+      self.found = true;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use indexmap::IndexMap;
+
+  fn files(files: &[(&str, &str)]) -> IndexMap<String, Vec<u8>> {
+    files
+      .iter()
+      .map(|(path, text)| (path.to_string(), text.as_bytes().to_vec()))
+      .collect()
+  }
+
+  #[test]
+  fn rollup_dts() {
+    let package_files = files(&[
+      (
+        "/_dist/mod.d.ts",
+        "import { Options } from \"@jsr/std__cli\";\nimport { Point } from \"./point.js\";\nexport declare function distance(a: Point, b: Point, options?: Options): number;\nexport { Point as Coordinate } from \"./point.js\";\n//# sourceMappingURL=mod.d.ts.map",
+      ),
+      (
+        "/_dist/point.d.ts",
+        "import { Options } from \"@jsr/std__cli\";\nexport interface Point {\n  x: number;\n  y: number;\n  options: Options;\n}\n//# sourceMappingURL=point.d.ts.map",
+      ),
+    ]);
+    let rollup = super::rollup_dts("/_dist/mod.d.ts", &package_files).unwrap();
+    assert_eq!(
+      rollup,
+      "import { Options } from \"@jsr/std__cli\";\ninterface Point {\n  x: number;\n  y: number;\n  options: Options;\n}\ndeclare function distance(a: Point, b: Point, options?: Options): number;\nexport { distance, Point as Coordinate };\n"
+    );
+
+    let package_files = files(&[
+      (
+        "/_dist/mod.d.ts",
+        "export * from \"./a.js\";\nexport * from \"./b.js\";\n",
+      ),
+      ("/_dist/a.d.ts", "export declare const value: number;\n"),
+      (
+        "/_dist/b.d.ts",
+        "declare const value: string;\nexport {};\n",
+      ),
+    ]);
+    let err = super::rollup_dts("/_dist/mod.d.ts", &package_files).unwrap_err();
+    assert!(err.to_string().contains("'value'"), "{err}");
+
+    let package_files = files(&[
+      (
+        "/_dist/mod.d.ts",
+        "import * as point from \"./point.js\";\nexport declare const origin: point.Point;\n",
+      ),
+      ("/_dist/point.d.ts", "export interface Point {}\n"),
+    ]);
+    assert!(super::rollup_dts("/_dist/mod.d.ts", &package_files).is_err());
+  }
+}
@@ -1,8 +1,12 @@
 // Copyright 2024 the JSR authors. All rights reserved. MIT license.
 
+use deno_ast::MediaType;
+use deno_ast::ModuleKind;
+use deno_ast::ParseParams;
 use deno_ast::ParsedSource;
 use deno_ast::SourceMap;
 use deno_ast::SourceMapOption;
+use deno_ast::TranspileModuleOptions;
 use deno_ast::TranspileOptions;
 use deno_ast::emit;
 use deno_ast::fold_program;
@@ -81,6 +85,54 @@ pub fn transpile_to_js(
   })
 }
 
+/// Converts an ES module that was already emitted into the tarball to
+/// CommonJS. Its specifiers have already been rewritten, so they are kept as
+/// is.
+pub fn transpile_to_cjs(
+  specifier: &Url,
+  esm: &[u8],
+) -> Result<Vec<u8>, anyhow::Error> {
+  let text = std::str::from_utf8(esm)?;
+  // The source map of the ES module does not apply to the CommonJS output.
+  let text = match text.rfind("//# sourceMappingURL=") {
+    Some(index) => &text[..index],
+    None => text,
+  };
+
+  let parsed_source = deno_ast::parse_module(ParseParams {
+    specifier: specifier.clone(),
+    text: text.into(),
+    media_type: MediaType::JavaScript,
+    capture_tokens: false,
+    scope_analysis: false,
+    maybe_syntax: None,
+  })?;
+
+  let emit_options = deno_ast::EmitOptions {
+    source_map: SourceMapOption::None,
+    source_map_file: None,
+    source_map_base: None,
+    inline_sources: false,
+    remove_comments: false,
+  };
+  let transpiled = parsed_source.transpile(
+    &TranspileOptions::default(),
+    &TranspileModuleOptions {
+      module_kind: Some(ModuleKind::Cjs),
+    },
+    &emit_options,
+  )?;
+
+  let mut source = transpiled.into_source().text.into_bytes();
+  if let Some(last) = source.last()
+    && *last != b'\n'
+  {
+    source.push(b'\n');
+  }
+
+  Ok(source)
+}
+
 pub fn transpile_to_dts(
   source: &ParsedSource,
   fast_check_module: &FastCheckTypeModule,
@@ -126,3 +178,22 @@ pub fn transpile_to_dts(
 
   Ok((source, source_map.unwrap().into_bytes()))
 }
+
+#[cfg(test)]
+mod tests {
+  use url::Url;
+
+  #[test]
+  fn transpile_to_cjs() {
+    let specifier = Url::parse("file:///mod.js").unwrap();
+    let esm = b"import { b } from \"./b.js\";\nexport const a = b + 1;\nexport default a;\n//# sourceMappingURL=mod.js.map";
+    let cjs =
+      String::from_utf8(super::transpile_to_cjs(&specifier, esm).unwrap())
+        .unwrap();
+    assert!(cjs.contains("require(\"./b.js\")"), "{cjs}");
+    assert!(cjs.contains("exports."), "{cjs}");
+    assert!(!cjs.contains("import "), "{cjs}");
+    assert!(!cjs.contains("export "), "{cjs}");
+    assert!(!cjs.contains("sourceMappingURL"), "{cjs}");
+  }
+}
@@ -1,12 +1,17 @@
 // Copyright 2024 the JSR authors. All rights reserved. MIT license.
+mod dts_rollup;
 mod emit;
 mod import_transform;
+mod rebuild;
+mod resolution;
+mod signing;
 mod specifiers;
 mod tarball;
 #[cfg(test)]
 mod tests;
 mod types;
 
+use bytes::Bytes;
 use chrono::SecondsFormat;
 use deno_semver::StackString;
 use deno_semver::VersionReq;
@@ -17,27 +22,47 @@ use std::borrow::Cow;
 use std::collections::HashMap;
 use url::Url;
 
+use crate::analysis::parse_peer_dependency;
 use crate::db::Database;
 use crate::db::PackageVersionDependency;
 use crate::ids::PackageName;
 use crate::ids::ScopeName;
 use crate::ids::Version;
+use crate::metadata::LATEST_DIST_TAG;
+use crate::npm::tarball::create_npm_bin;
 use crate::npm::tarball::create_npm_dependencies;
+use crate::npm::tarball::create_npm_engines;
+use crate::npm::types::NpmDistAttestations;
 use crate::npm::types::NpmDistInfo;
 use crate::npm::types::NpmPackageInfo;
+use crate::s3::BucketWithQueue;
+use crate::s3::CACHE_CONTROL_MANIFEST;
+use crate::s3::ContentEncoding;
+use crate::s3::S3UploadOptions;
+use crate::s3::UploadTaskBody;
 
+pub use self::rebuild::build_npm_tarball;
+pub use self::rebuild::rebuild_npm_tarballs;
+pub use self::rebuild::upload_npm_version_manifest;
+pub use self::signing::NpmSigner;
+pub use self::signing::NpmSigningKey;
 pub use self::tarball::NpmTarball;
 pub use self::tarball::NpmTarballFiles;
 pub use self::tarball::NpmTarballOptions;
 pub use self::tarball::create_npm_tarball;
+pub use self::tarball::npm_exclude_match;
+pub use self::types::NpmAbbreviatedPackageInfo;
+pub use self::types::NpmKeys;
 pub use self::types::NpmMappedJsrPackageName;
 use self::types::NpmVersionInfo;
+pub use self::types::parse_npm_mapped_jsr_package_name;
 
-pub const NPM_TARBALL_REVISION: u32 = 11;
+pub const NPM_TARBALL_REVISION: u32 = 12;
 
 pub async fn generate_npm_version_manifest<'a>(
   db: &Database,
   npm_url: &Url,
+  npm_signer: &NpmSigner,
   scope: &'a ScopeName,
   name: &'a PackageName,
 ) -> Result<NpmPackageInfo<'a>, anyhow::Error> {
@@ -89,16 +114,13 @@ pub async fn generate_npm_version_manifest<'a>(
   );
 
   for version in versions {
-    // We don't publish yanked versions in the NPM manifest.
-    if version.is_yanked {
-      continue;
-    }
-
     let dependencies = dependencies_per_version
       .remove(&version.version)
       .unwrap_or_default();
 
-    let dependencies = dependencies.into_iter().map(|dep| {
+    let (optional_dependencies, dependencies): (Vec<_>, Vec<_>) =
+      dependencies.into_iter().partition(|dep| dep.is_optional);
+    let to_req = |dep: PackageVersionDependency| {
       let sub_path = if dep.dependency_path.is_empty() {
         None
       } else {
@@ -113,8 +135,23 @@ pub async fn generate_npm_version_manifest<'a>(
         version_req,
       };
       Cow::Owned((dep.dependency_kind, PackageReqReference { req, sub_path }))
+    };
+    let peer_dependencies = version
+      .meta
+      .npm_peer_dependencies
+      .iter()
+      .map(|specifier| Cow::Owned(parse_peer_dependency(specifier).unwrap()));
+    let npm_peer_dependencies = create_npm_dependencies(peer_dependencies)?;
+    let mut npm_dependencies =
+      create_npm_dependencies(dependencies.into_iter().map(to_req))?;
+    npm_dependencies
+      .retain(|name, _| !npm_peer_dependencies.contains_key(name));
+    let mut npm_optional_dependencies =
+      create_npm_dependencies(optional_dependencies.into_iter().map(to_req))?;
+    npm_optional_dependencies.retain(|name, _| {
+      !npm_dependencies.contains_key(name)
+        && !npm_peer_dependencies.contains_key(name)
     });
-    let npm_dependencies = create_npm_dependencies(dependencies)?;
 
     let tarball = Url::options()
       .base_url(Some(npm_url))
@@ -129,19 +166,60 @@ pub async fn generate_npm_version_manifest<'a>(
       ))
       .unwrap();
 
+    let npm_name = NpmMappedJsrPackageName {
+      scope,
+      package: name,
+    };
+    let integrity = format!("sha512-{}", version.npm_tarball_sha512);
+    let signatures = match &npm_signer.0 {
+      Some(signing_key) => vec![signing_key.registry_signature(
+        &npm_name,
+        &version.version,
+        &integrity,
+      )?],
+      None => vec![],
+    };
+    let attestations =
+      version
+        .npm_tarball_rekor_log_id
+        .as_ref()
+        .map(|_| NpmDistAttestations {
+          url: Url::options()
+            .base_url(Some(npm_url))
+            .parse(&format!(
+              "./{}",
+              crate::s3_paths::npm_attestations_path(
+                scope,
+                name,
+                &version.version
+              )
+            ))
+            .unwrap()
+            .to_string(),
+        });
+
     let npm_version_info = NpmVersionInfo {
-      name: NpmMappedJsrPackageName {
-        scope,
-        package: name,
-      },
+      name: npm_name,
       version: version.version.clone(),
       description: package.description.clone(),
       dist: NpmDistInfo {
         tarball: tarball.to_string(),
         shasum: version.npm_tarball_sha1,
-        integrity: format!("sha512-{}", version.npm_tarball_sha512),
+        integrity,
+        signatures,
+        attestations,
       },
+      engines: create_npm_engines(&package.runtime_compat),
       dependencies: npm_dependencies,
+      optional_dependencies: npm_optional_dependencies,
+      peer_dependencies: npm_peer_dependencies,
+      bin: create_npm_bin(&version.meta.npm_bin),
+      // Yanked versions stay installable by exact version, so they are part
+      // of the manifest, but marked as deprecated to keep them out of ranges.
+      deprecated: version.is_yanked.then(|| match &version.yank_reason {
+        Some(reason) => format!("This version has been yanked: {reason}"),
+        None => "This version has been yanked".to_string(),
+      }),
     };
 
     out
@@ -155,9 +233,116 @@ pub async fn generate_npm_version_manifest<'a>(
     );
   }
 
-  if let Some((version, _)) = out.versions.first() {
-    out.dist_tags.insert("latest".to_string(), version.clone());
+  if let Some((version, _)) = out
+    .versions
+    .iter()
+    .find(|(_, info)| info.deprecated.is_none())
+  {
+    out
+      .dist_tags
+      .insert(LATEST_DIST_TAG.to_string(), version.clone());
+  }
+  // Tags pointing at yanked versions are left out.
+  for dist_tag in db.list_package_dist_tags(scope, name).await? {
+    if out
+      .versions
+      .get(&dist_tag.version)
+      .is_some_and(|info| info.deprecated.is_none())
+    {
+      out.dist_tags.insert(dist_tag.tag, dist_tag.version);
+    }
   }
 
   Ok(out)
 }
+
+/// Uploads the public key npm tarballs are signed with to `/-/npm/v1/keys`,
+/// where `npm audit signatures` looks for it.
+pub async fn upload_npm_keys(
+  npm_bucket: &BucketWithQueue,
+  signing_key: &NpmSigningKey,
+) -> Result<(), anyhow::Error> {
+  let content = serde_json::to_vec_pretty(&signing_key.keys())?;
+  npm_bucket
+    .upload(
+      crate::s3_paths::npm_keys_path().into(),
+      UploadTaskBody::Bytes(Bytes::from(content)),
+      S3UploadOptions {
+        content_type: Some("application/json".into()),
+        cache_control: Some(CACHE_CONTROL_MANIFEST.into()),
+        content_encoding: ContentEncoding::Identity,
+      },
+    )
+    .await?;
+  Ok(())
+}
+
+/// Signs a publish attestation for the current npm tarball of a version that
+/// was published with provenance, and uploads it to the npm bucket. The
+/// tarball is marked as attested, so that the next npm version manifest
+/// links to the attestation.
+///
+/// Does nothing if the tarball for the current revision has not been built
+/// yet: the npm tarball build job attests it once it is.
+pub async fn attest_npm_tarball(
+  db: &Database,
+  npm_bucket: &BucketWithQueue,
+  npm_url: &Url,
+  signing_key: &NpmSigningKey,
+  scope: &ScopeName,
+  name: &PackageName,
+  version: &Version,
+) -> Result<(), anyhow::Error> {
+  let Some(npm_tarball) = db
+    .get_npm_tarball(scope, name, version, NPM_TARBALL_REVISION as i32)
+    .await?
+  else {
+    return Ok(());
+  };
+  if npm_tarball.rekor_log_id.is_some() {
+    return Ok(());
+  }
+
+  let npm_name = NpmMappedJsrPackageName {
+    scope,
+    package: name,
+  };
+  let attestations = signing_key
+    .create_publish_attestation(
+      npm_url,
+      &npm_name,
+      version,
+      &npm_tarball.sha512,
+    )
+    .await?;
+  let rekor_log_id = attestations.attestations[0]
+    .bundle
+    .verification_material
+    .tlog_entries[0]
+    .log_index
+    .clone();
+
+  let content = serde_json::to_vec_pretty(&attestations)?;
+  npm_bucket
+    .upload(
+      crate::s3_paths::npm_attestations_path(scope, name, version).into(),
+      UploadTaskBody::Bytes(Bytes::from(content)),
+      S3UploadOptions {
+        content_type: Some("application/json".into()),
+        cache_control: Some(CACHE_CONTROL_MANIFEST.into()),
+        content_encoding: ContentEncoding::Identity,
+      },
+    )
+    .await?;
+
+  db.set_npm_tarball_rekor_log_id(
+    scope,
+    name,
+    version,
+    NPM_TARBALL_REVISION as i32,
+    &rekor_log_id,
+  )
+  .await?;
+
+  Ok(())
+}
@@ -0,0 +1,441 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+//! Building npm tarballs from the files stored for a published version.
+//!
+//! [build_npm_tarball] builds the tarball for the current
+//! [NPM_TARBALL_REVISION] of a single version. It backs the npm tarball build
+//! queue, which catches up on missing tarballs after a revision bump, and
+//! [rebuild_npm_tarballs], which admins can run for a package, a scope or the
+//! whole registry without waiting for the queue.
+
+use std::collections::HashSet;
+
+use bytes::Bytes;
+use deno_semver::StackString;
+use deno_semver::VersionReq;
+use deno_semver::package::PackageReq;
+use deno_semver::package::PackageReqReference;
+use deno_semver::package::PackageSubPath;
+use futures::StreamExt;
+use tracing::Span;
+use tracing::error;
+use tracing::info;
+use tracing::instrument;
+use url::Url;
+
+use crate::analysis::RebuildNpmTarballData;
+use crate::analysis::parse_peer_dependency;
+use crate::analysis::rebuild_npm_tarball;
+use crate::background_jobs::VersionJobPayload;
+use crate::background_jobs::run_with_lease;
+use crate::cache_purge::CachePurge;
+use crate::db::BackgroundJobKind;
+use crate::db::Database;
+use crate::db::NewNpmTarball;
+use crate::db::NpmTarballRebuildJob;
+use crate::db::NpmTarballRebuildJobStatus;
+use crate::ids::PackageName;
+use crate::ids::ScopeName;
+use crate::ids::Version;
+use crate::npm::NPM_TARBALL_REVISION;
+use crate::npm::NpmSigner;
+use crate::npm::attest_npm_tarball;
+use crate::npm::generate_npm_version_manifest;
+use crate::s3::Buckets;
+use crate::s3::CACHE_CONTROL_IMMUTABLE;
+use crate::s3::CACHE_CONTROL_MANIFEST;
+use crate::s3::ContentEncoding;
+use crate::s3::S3UploadOptions;
+use crate::s3::UploadTaskBody;
+use crate::s3_paths;
+
+/// Builds and uploads the npm tarball for the current revision of a version,
+/// unless it already exists. With `verify`, an existing tarball is rebuilt
+/// too, and the build fails if the result is not byte-identical to it.
+///
+/// Tarballs of versions published with provenance are attested once they
+/// exist. The npm version manifest is not updated.
+#[allow(clippy::too_many_arguments)]
+pub async fn build_npm_tarball(
+  db: &Database,
+  buckets: &Buckets,
+  registry_url: &Url,
+  npm_url: &Url,
+  npm_signer: &NpmSigner,
+  scope: &ScopeName,
+  name: &PackageName,
+  version: &Version,
+  verify: bool,
+) -> Result<(), anyhow::Error> {
+  let existing_npm_tarball = db
+    .get_npm_tarball(scope, name, version, NPM_TARBALL_REVISION as i32)
+    .await?;
+
+  if existing_npm_tarball.is_none() || verify {
+    let package_version = db
+      .get_package_version(scope, name, version)
+      .await?
+      .ok_or_else(|| {
+      anyhow::anyhow!("package version not found: @{scope}/{name}@{version}")
+    })?;
+    let (package, _, _) = db
+      .get_package(scope, name)
+      .await?
+      .ok_or_else(|| anyhow::anyhow!("package not found: @{scope}/{name}"))?;
+    let dependencies = db
+      .list_package_version_dependencies(scope, name, version)
+      .await?;
+    let files: HashSet<_> = db
+      .list_package_files(scope, name, version)
+      .await?
+      .into_iter()
+      .map(|f| f.path)
+      .collect();
+
+    let dependencies = dependencies
+      .into_iter()
+      .map(|dep| {
+        let sub_path = if dep.dependency_path.is_empty() {
+          None
+        } else {
+          Some(PackageSubPath::from_string(dep.dependency_path))
+        };
+        let version_req =
+          VersionReq::parse_from_specifier(&dep.dependency_constraint).unwrap();
+        let req = PackageReq {
+          name: StackString::from_string(dep.dependency_name),
+          version_req,
+        };
+        let dependency =
+          (dep.dependency_kind, PackageReqReference { req, sub_path });
+        (dep.is_optional, dependency)
+      })
+      .collect::<Vec<_>>();
+    let optional_dependencies = dependencies
+      .iter()
+      .filter(|(is_optional, _)| *is_optional)
+      .map(|(_, dependency)| dependency.clone())
+      .collect();
+    let dependencies = dependencies
+      .into_iter()
+      .map(|(_, dependency)| dependency)
+      .collect();
+
+    let span = Span::current();
+    let data = RebuildNpmTarballData {
+      files,
+      scope: package_version.scope,
+      name: package_version.name,
+      version: package_version.version,
+      dependencies,
+      optional_dependencies,
+      exports: package_version.exports,
+      peer_dependencies: package_version
+        .meta
+        .npm_peer_dependencies
+        .iter()
+        .map(|specifier| parse_peer_dependency(specifier).unwrap())
+        .collect(),
+      runtime_compat: package.runtime_compat,
+      bin: package_version.meta.npm_bin.clone(),
+      export_patterns: package_version.meta.export_patterns.clone(),
+      npm_exclude: package_version.meta.npm_exclude.clone(),
+      cjs: package_version.meta.npm_cjs,
+      dts_rollup: package_version.meta.npm_dts_rollup,
+      expected_sha512: existing_npm_tarball
+        .as_ref()
+        .map(|npm_tarball| npm_tarball.sha512.clone()),
+    };
+    let registry_url = registry_url.clone();
+    let modules_bucket = buckets.modules_bucket.clone();
+    let npm_tarball = tokio::task::spawn_blocking(|| {
+      rebuild_npm_tarball(span, registry_url, modules_bucket, data)
+    })
+    .await
+    .unwrap()?;
+
+    // When verifying, the rebuilt tarball matched the one that was already
+    // uploaded, so there is nothing left to do.
+    if existing_npm_tarball.is_none() {
+      let new_npm_tarball = NewNpmTarball {
+        scope,
+        name,
+        version,
+        revision: NPM_TARBALL_REVISION as i32,
+        size: npm_tarball.tarball.len() as i32,
+        sha1: &npm_tarball.sha1,
+        sha512: &npm_tarball.sha512,
+        sha256: &npm_tarball.sha256,
+      };
+
+      let npm_tarball_path =
+        s3_paths::npm_tarball_path(scope, name, version, NPM_TARBALL_REVISION);
+      buckets
+        .npm_bucket
+        .upload(
+          npm_tarball_path.into(),
+          UploadTaskBody::Bytes(Bytes::from(npm_tarball.tarball)),
+          S3UploadOptions {
+            content_type: Some("application/octet-stream".into()),
+            cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
+            content_encoding: ContentEncoding::Identity,
+          },
+        )
+        .await?;
+
+      db.create_npm_tarball(new_npm_tarball).await?;
+    }
+  }
+
+  // Every revision is a new tarball, which needs its own attestation. This is
+  // a no-op if the tarball was already attested, so a retried build picks up
+  // where it left off.
+  if let Some(signing_key) = &npm_signer.0 {
+    let package_version = db
+      .get_package_version(scope, name, version)
+      .await?
+      .ok_or_else(|| {
+      anyhow::anyhow!("package version not found: @{scope}/{name}@{version}")
+    })?;
+    if package_version.meta.has_provenance {
+      attest_npm_tarball(
+        db,
+        &buckets.npm_bucket,
+        npm_url,
+        signing_key,
+        scope,
+        name,
+        version,
+      )
+      .await?;
+    }
+  }
+
+  Ok(())
+}
+
+/// Regenerates the npm version manifest of a package, uploads it and purges
+/// the cached copy.
+pub async fn upload_npm_version_manifest(
+  db: &Database,
+  buckets: &Buckets,
+  npm_url: &Url,
+  npm_signer: &NpmSigner,
+  cache_purge: &CachePurge,
+  scope: &ScopeName,
+  name: &PackageName,
+) -> Result<(), anyhow::Error> {
+  let npm_version_manifest_path =
+    s3_paths::npm_version_manifest_path(scope, name);
+  let npm_version_manifest =
+    generate_npm_version_manifest(db, npm_url, npm_signer, scope, name).await?;
+  let content = serde_json::to_vec_pretty(&npm_version_manifest)?;
+  buckets
+    .npm_bucket
+    .upload(
+      npm_version_manifest_path.into(),
+      UploadTaskBody::Bytes(content.into()),
+      S3UploadOptions {
+        content_type: Some("application/json".into()),
+        cache_control: Some(CACHE_CONTROL_MANIFEST.into()),
+        content_encoding: ContentEncoding::Identity,
+      },
+    )
+    .await?;
+
+  cache_purge.purge(vec![s3_paths::npm_version_manifest_url(
+    npm_url, scope, name,
+  )]);
+
+  Ok(())
+}
+
+/// Number of versions loaded from the database at once. Progress is written
+/// back, and the npm version manifests of the packages in the batch are
+/// updated, after every batch.
+const REBUILD_BATCH_SIZE: i64 = 100;
+
+/// Builds the npm tarballs for all versions covered by the given rebuild job,
+/// updating its progress as it goes.
+///
+/// Versions whose tarball can not be built are counted as failed and keep
+/// their existing tarball, if any. A database error while recording progress
+/// aborts the job and marks it as failed. The job holds a lease while it runs,
+/// see [run_with_lease].
+#[instrument(
+  name = "rebuild_npm_tarballs",
+  skip(db, buckets, registry_url, npm_url, npm_signer, cache_purge, job),
+  fields(job_id = %job.id)
+)]
+pub async fn rebuild_npm_tarballs(
+  db: Database,
+  buckets: Buckets,
+  registry_url: Url,
+  npm_url: Url,
+  npm_signer: NpmSigner,
+  cache_purge: CachePurge,
+  job: NpmTarballRebuildJob,
+) {
+  let ctx = RebuildContext {
+    db: &db,
+    buckets: &buckets,
+    registry_url: &registry_url,
+    npm_url: &npm_url,
+    npm_signer: &npm_signer,
+    cache_purge: &cache_purge,
+  };
+  let res = run_with_lease(rebuild_npm_tarballs_inner(&ctx, &job), |lease| {
+    db.renew_npm_tarball_rebuild_job_lease(job.id, lease)
+  })
+  .await;
+  let (status, error) = match res {
+    Some(Ok(())) => (NpmTarballRebuildJobStatus::Completed, None),
+    Some(Err(err)) => {
+      error!("npm tarball rebuild job {} failed: {err}", job.id);
+      (NpmTarballRebuildJobStatus::Failed, Some(err.to_string()))
+    }
+    None => {
+      error!("npm tarball rebuild job {} lost its lease", job.id);
+      return;
+    }
+  };
+
+  if let Err(err) = db
+    .finish_npm_tarball_rebuild_job(job.id, status, error.as_deref())
+    .await
+  {
+    error!("failed to finish npm tarball rebuild job {}: {err}", job.id);
+  }
+}
+
+struct RebuildContext<'a> {
+  db: &'a Database,
+  buckets: &'a Buckets,
+  registry_url: &'a Url,
+  npm_url: &'a Url,
+  npm_signer: &'a NpmSigner,
+  cache_purge: &'a CachePurge,
+}
+
+async fn rebuild_npm_tarballs_inner(
+  ctx: &RebuildContext<'_>,
+  job: &NpmTarballRebuildJob,
+) -> Result<(), sqlx::Error> {
+  let concurrency = job.concurrency.max(1) as usize;
+  let mut processed = 0;
+  let mut failed = 0;
+  let mut last = None;
+
+  loop {
+    let versions = ctx
+      .db
+      .list_filtered_package_versions_after(
+        job.scope.as_ref(),
+        job.name.as_ref(),
+        last
+          .as_ref()
+          .map(|(scope, name, version)| (scope, name, version)),
+        REBUILD_BATCH_SIZE,
+      )
+      .await?;
+    let Some(last_in_batch) = versions.last().cloned() else {
+      break;
+    };
+
+    let packages = versions
+      .iter()
+      .map(|(scope, name, _)| (scope.clone(), name.clone()))
+      .collect::<HashSet<_>>();
+
+    let mut results = futures::stream::iter(&versions)
+      .map(|(scope, name, version)| {
+        rebuild_version(ctx, job.verify, scope, name, version)
+      })
+      .buffer_unordered(concurrency);
+    while let Some(ok) = results.next().await {
+      processed += 1;
+      if !ok {
+        failed += 1;
+      }
+    }
+    drop(results);
+
+    let mut results = futures::stream::iter(&packages)
+      .map(|(scope, name)| async move {
+        let res = upload_npm_version_manifest(
+          ctx.db,
+          ctx.buckets,
+          ctx.npm_url,
+          ctx.npm_signer,
+          ctx.cache_purge,
+          scope,
+          name,
+        )
+        .await;
+        if let Err(err) = res {
+          error!(
+            "failed to upload npm version manifest for @{scope}/{name}: {err}"
+          );
+        }
+      })
+      .buffer_unordered(concurrency);
+    while results.next().await.is_some() {}
+    drop(results);
+
+    ctx
+      .db
+      .update_npm_tarball_rebuild_job_progress(job.id, processed, failed)
+      .await?;
+    info!(processed, failed, "npm tarball rebuild progress");
+
+    last = Some(last_in_batch);
+  }
+
+  Ok(())
+}
+
+/// Returns `false` if the tarball could not be built.
+async fn rebuild_version(
+  ctx: &RebuildContext<'_>,
+  verify: bool,
+  scope: &ScopeName,
+  name: &PackageName,
+  version: &Version,
+) -> bool {
+  let res = build_npm_tarball(
+    ctx.db,
+    ctx.buckets,
+    ctx.registry_url,
+    ctx.npm_url,
+    ctx.npm_signer,
+    scope,
+    name,
+    version,
+    verify,
+  )
+  .await;
+  match res {
+    Ok(()) => true,
+    Err(err) => {
+      error!(
+        "failed to build npm tarball for @{scope}/{name}@{version}: {err}"
+      );
+      // A failed verification is a mismatch that needs a look, but a failed
+      // build is usually transient, so it is retried in the background.
+      if !verify {
+        let job = VersionJobPayload::job(
+          BackgroundJobKind::NpmTarballBuild,
+          scope,
+          name,
+          version,
+        );
+        if let Err(err) = ctx.db.enqueue_background_job(job).await {
+          error!(
+            "failed to queue npm tarball build for @{scope}/{name}@{version}: {err}"
+          );
+        }
+      }
+      false
+    }
+  }
+}
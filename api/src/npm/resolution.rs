@@ -0,0 +1,341 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+//! A simulation of how Node.js, and TypeScript with `"moduleResolution":
+//! "node16"` or `"nodenext"`, resolve a subpath of a package through the
+//! `exports` field of its `package.json`. It follows the
+//! `PACKAGE_EXPORTS_RESOLVE` algorithm of the Node.js ESM resolver, and is used
+//! to check that every entrypoint of a package can be imported from the npm
+//! tarball that is generated for it.
+
+use indexmap::IndexMap;
+
+use crate::db::ExportsMap;
+
+use super::types::NpmExportConditions;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NpmResolutionMode {
+  /// An `import` in Node.js.
+  Import,
+  /// A `require()` in Node.js.
+  Require,
+  /// An `import` type checked by TypeScript.
+  Types,
+}
+
+impl NpmResolutionMode {
+  /// The conditions that are active in this mode, besides `default`.
+  fn conditions(self) -> &'static [&'static str] {
+    match self {
+      NpmResolutionMode::Import => &["node", "import"],
+      NpmResolutionMode::Require => &["node", "require"],
+      NpmResolutionMode::Types => &["types", "node", "import"],
+    }
+  }
+}
+
+impl std::fmt::Display for NpmResolutionMode {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      NpmResolutionMode::Import => write!(f, "import"),
+      NpmResolutionMode::Require => write!(f, "require"),
+      NpmResolutionMode::Types => write!(f, "types"),
+    }
+  }
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum NpmResolutionError {
+  #[error(
+    "ERR_PACKAGE_PATH_NOT_EXPORTED: subpath '{subpath}' is not exported for {mode}"
+  )]
+  PathNotExported {
+    subpath: String,
+    mode: NpmResolutionMode,
+  },
+
+  #[error(
+    "ERR_INVALID_PACKAGE_TARGET: target '{target}' of subpath '{subpath}' is invalid for {mode}"
+  )]
+  InvalidPackageTarget {
+    subpath: String,
+    target: String,
+    mode: NpmResolutionMode,
+  },
+
+  #[error(
+    "ERR_MODULE_NOT_FOUND: target '{target}' of subpath '{subpath}' is not in the tarball for {mode}"
+  )]
+  ModuleNotFound {
+    subpath: String,
+    target: String,
+    mode: NpmResolutionMode,
+  },
+}
+
+/// Checks that every entrypoint in `exports` resolves to a file in the tarball
+/// through the `exports` of the generated `package.json`, with `import`, for
+/// types, and with `require()` if a CommonJS build was emitted.
+pub fn validate_npm_exports(
+  exports: &ExportsMap,
+  npm_exports: &IndexMap<String, NpmExportConditions>,
+  package_files: &IndexMap<String, Vec<u8>>,
+  cjs: bool,
+) -> Result<(), NpmResolutionError> {
+  let mut modes = vec![NpmResolutionMode::Import, NpmResolutionMode::Types];
+  if cjs {
+    modes.push(NpmResolutionMode::Require);
+  }
+  for (subpath, _) in exports.iter() {
+    for mode in &modes {
+      resolve_npm_export(npm_exports, package_files, subpath, *mode)?;
+    }
+  }
+  Ok(())
+}
+
+/// Resolves `subpath` (`.` or `./<path>`) through `npm_exports`, returning
+/// the path of the file in the tarball it resolves to.
+fn resolve_npm_export(
+  npm_exports: &IndexMap<String, NpmExportConditions>,
+  package_files: &IndexMap<String, Vec<u8>>,
+  subpath: &str,
+  mode: NpmResolutionMode,
+) -> Result<String, NpmResolutionError> {
+  let not_exported = || NpmResolutionError::PathNotExported {
+    subpath: subpath.to_string(),
+    mode,
+  };
+
+  let (conditions, capture) = match npm_exports.get(subpath) {
+    Some(conditions) if !subpath.contains('*') => (conditions, None),
+    _ => {
+      let (conditions, capture) =
+        match_npm_export_pattern(npm_exports, subpath)
+          .ok_or_else(not_exported)?;
+      (conditions, Some(capture))
+    }
+  };
+
+  let target = npm_export_target(conditions, mode).ok_or_else(not_exported)?;
+
+  let invalid_target = || NpmResolutionError::InvalidPackageTarget {
+    subpath: subpath.to_string(),
+    target: target.to_string(),
+    mode,
+  };
+  let Some(rest) = target.strip_prefix("./") else {
+    return Err(invalid_target());
+  };
+  if !is_valid_target_path(rest) {
+    return Err(invalid_target());
+  }
+  let resolved = match capture {
+    Some(capture) => {
+      if !is_valid_target_path(capture) {
+        return Err(invalid_target());
+      }
+      target.replace('*', capture)
+    }
+    None => target.to_string(),
+  };
+
+  let path = resolved.trim_start_matches('.').to_string();
+  if !package_files.contains_key(&path) {
+    return Err(NpmResolutionError::ModuleNotFound {
+      subpath: subpath.to_string(),
+      target: resolved,
+      mode,
+    });
+  }
+  Ok(path)
+}
+
+/// Finds the pattern entry that `subpath` matches, preferring the one with
+/// the longest prefix before the `*`, and then the longest key, like
+/// `PATTERN_KEY_COMPARE` does.
+fn match_npm_export_pattern<'a, 'b>(
+  npm_exports: &'a IndexMap<String, NpmExportConditions>,
+  subpath: &'b str,
+) -> Option<(&'a NpmExportConditions, &'b str)> {
+  let mut best: Option<(&str, &NpmExportConditions, &str)> = None;
+  for (key, conditions) in npm_exports {
+    let Some((prefix, suffix)) = key.split_once('*') else {
+      continue;
+    };
+    if suffix.contains('*')
+      || subpath == prefix
+      || subpath.len() < key.len()
+      || !subpath.starts_with(prefix)
+      || !subpath.ends_with(suffix)
+    {
+      continue;
+    }
+    let is_better = best.is_none_or(|(best_key, _, _)| {
+      let best_prefix_len = best_key.find('*').unwrap();
+      prefix.len() > best_prefix_len
+        || (prefix.len() == best_prefix_len && key.len() > best_key.len())
+    });
+    if is_better {
+      let capture = &subpath[prefix.len()..subpath.len() - suffix.len()];
+      best = Some((key, conditions, capture));
+    }
+  }
+  best.map(|(_, conditions, capture)| (conditions, capture))
+}
+
+/// The target of the first condition, in the order they appear in
+/// `package.json`, that is active in `mode`.
+fn npm_export_target(
+  conditions: &NpmExportConditions,
+  mode: NpmResolutionMode,
+) -> Option<&str> {
+  let entries = [
+    ("types", &conditions.types),
+    ("browser", &conditions.browser),
+    ("workerd", &conditions.workerd),
+    ("bun", &conditions.bun),
+    ("require", &conditions.require),
+    ("default", &conditions.default),
+  ];
+  entries
+    .into_iter()
+    .filter(|(condition, _)| {
+      *condition == "default" || mode.conditions().contains(condition)
+    })
+    .find_map(|(_, target)| target.as_deref())
+}
+
+/// Node.js rejects targets with empty, `.`, `..` or `node_modules` segments.
+fn is_valid_target_path(path: &str) -> bool {
+  path.split(['/', '\\']).all(|segment| {
+    !segment.is_empty()
+      && segment != "."
+      && segment != ".."
+      && !segment.eq_ignore_ascii_case("node_modules")
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn conditions(
+    types: Option<&str>,
+    require: Option<&str>,
+    default: Option<&str>,
+  ) -> NpmExportConditions {
+    NpmExportConditions {
+      types: types.map(str::to_string),
+      browser: None,
+      workerd: None,
+      bun: None,
+      require: require.map(str::to_string),
+      default: default.map(str::to_string),
+    }
+  }
+
+  fn files(paths: &[&str]) -> IndexMap<String, Vec<u8>> {
+    paths
+      .iter()
+      .map(|path| (path.to_string(), vec![]))
+      .collect()
+  }
+
+  #[test]
+  fn test_resolve_npm_export() {
+    let npm_exports = IndexMap::from([
+      (
+        ".".to_string(),
+        conditions(
+          Some("./_dist/mod.d.ts"),
+          Some("./_cjs/mod.js"),
+          Some("./mod.js"),
+        ),
+      ),
+      (
+        "./features/*".to_string(),
+        conditions(None, None, Some("./features/*.js")),
+      ),
+      (
+        "./features/nested/*".to_string(),
+        conditions(None, None, Some("./nested/*.js")),
+      ),
+      (
+        "./types".to_string(),
+        conditions(Some("./types.d.ts"), None, None),
+      ),
+    ]);
+    let package_files = files(&[
+      "/mod.js",
+      "/_cjs/mod.js",
+      "/_dist/mod.d.ts",
+      "/features/a.js",
+      "/nested/b.js",
+      "/types.d.ts",
+    ]);
+    let resolve = |subpath, mode| {
+      resolve_npm_export(&npm_exports, &package_files, subpath, mode)
+    };
+
+    assert_eq!(resolve(".", NpmResolutionMode::Import).unwrap(), "/mod.js");
+    assert_eq!(
+      resolve(".", NpmResolutionMode::Require).unwrap(),
+      "/_cjs/mod.js"
+    );
+    assert_eq!(
+      resolve(".", NpmResolutionMode::Types).unwrap(),
+      "/_dist/mod.d.ts"
+    );
+    assert_eq!(
+      resolve("./features/a", NpmResolutionMode::Import).unwrap(),
+      "/features/a.js"
+    );
+    assert_eq!(
+      resolve("./features/nested/b", NpmResolutionMode::Import).unwrap(),
+      "/nested/b.js"
+    );
+    assert_eq!(
+      resolve("./types", NpmResolutionMode::Types).unwrap(),
+      "/types.d.ts"
+    );
+
+    assert_eq!(
+      resolve("./types", NpmResolutionMode::Import),
+      Err(NpmResolutionError::PathNotExported {
+        subpath: "./types".to_string(),
+        mode: NpmResolutionMode::Import,
+      })
+    );
+    assert_eq!(
+      resolve("./mod", NpmResolutionMode::Import),
+      Err(NpmResolutionError::PathNotExported {
+        subpath: "./mod".to_string(),
+        mode: NpmResolutionMode::Import,
+      })
+    );
+    assert_eq!(
+      resolve("./features/", NpmResolutionMode::Import),
+      Err(NpmResolutionError::PathNotExported {
+        subpath: "./features/".to_string(),
+        mode: NpmResolutionMode::Import,
+      })
+    );
+    assert_eq!(
+      resolve("./features/c", NpmResolutionMode::Import),
+      Err(NpmResolutionError::ModuleNotFound {
+        subpath: "./features/c".to_string(),
+        target: "./features/c.js".to_string(),
+        mode: NpmResolutionMode::Import,
+      })
+    );
+    assert_eq!(
+      resolve("./features/../mod", NpmResolutionMode::Import),
+      Err(NpmResolutionError::InvalidPackageTarget {
+        subpath: "./features/../mod".to_string(),
+        target: "./features/*.js".to_string(),
+        mode: NpmResolutionMode::Import,
+      })
+    );
+  }
+}
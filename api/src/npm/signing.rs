@@ -0,0 +1,384 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+use std::sync::Arc;
+
+use base64::Engine as _;
+use base64::prelude::BASE64_STANDARD;
+use base64::prelude::BASE64_STANDARD_NO_PAD;
+use ring::rand::SystemRandom;
+use ring::signature::ECDSA_P256_SHA256_ASN1_SIGNING;
+use ring::signature::EcdsaKeyPair;
+use ring::signature::KeyPair;
+use serde::Serialize;
+use sha2::Digest;
+use url::Url;
+use x509_parser::pem::parse_x509_pem;
+
+use crate::external::rekor;
+use crate::external::rekor::TlogEntry;
+use crate::ids::Version;
+use crate::provenance::dsse_pae;
+
+use super::types::NpmAttestation;
+use super::types::NpmAttestations;
+use super::types::NpmKey;
+use super::types::NpmKeys;
+use super::types::NpmMappedJsrPackageName;
+use super::types::NpmSignature;
+
+/// The DER prefix of a SubjectPublicKeyInfo for an uncompressed P-256 point,
+/// which ring only gives us as the raw point.
+const P256_SPKI_PREFIX: &[u8] = &[
+  0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01,
+  0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+];
+
+const NPM_KEY_TYPE: &str = "ecdsa-sha2-nistp256";
+
+const IN_TOTO_PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+const IN_TOTO_STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+const SIGSTORE_BUNDLE_MEDIA_TYPE: &str =
+  "application/vnd.dev.sigstore.bundle+json;version=0.2";
+
+/// The predicate type npm uses for the attestations its registry signs when
+/// a package is published.
+pub const NPM_PUBLISH_PREDICATE_TYPE: &str =
+  "https://github.com/npm/attestation/tree/main/specs/publish/v0.1";
+
+/// The key the registry signs npm tarballs with. Its public half is served at
+/// `/-/npm/v1/keys`, where npm looks it up by `keyid`.
+pub struct NpmSigningKey {
+  key_pair: EcdsaKeyPair,
+  spki: Vec<u8>,
+  keyid: String,
+}
+
+/// Wrapper around an optional `NpmSigningKey` so it can be stored in the
+/// routerify data map. A `None` value means no key was configured, and npm
+/// tarballs are not signed.
+#[derive(Clone)]
+pub struct NpmSigner(pub Option<Arc<NpmSigningKey>>);
+
+impl NpmSigningKey {
+  /// Parse a PEM encoded PKCS#8 ECDSA P-256 private key.
+  pub fn from_pem(pem: &str) -> Result<Self, anyhow::Error> {
+    let (_, pem) = parse_x509_pem(pem.as_bytes())?;
+    if pem.label != "PRIVATE KEY" {
+      anyhow::bail!("expected a PKCS#8 private key, got '{}'", pem.label);
+    }
+    Self::from_pkcs8(&pem.contents)
+  }
+
+  fn from_pkcs8(pkcs8: &[u8]) -> Result<Self, anyhow::Error> {
+    let key_pair = EcdsaKeyPair::from_pkcs8(
+      &ECDSA_P256_SHA256_ASN1_SIGNING,
+      pkcs8,
+      &SystemRandom::new(),
+    )
+    .map_err(|err| anyhow::anyhow!("invalid npm signing key: {err}"))?;
+    let mut spki = P256_SPKI_PREFIX.to_vec();
+    spki.extend_from_slice(key_pair.public_key().as_ref());
+    // Same key ID format as the npm registry uses.
+    let keyid = format!(
+      "SHA256:{}",
+      BASE64_STANDARD_NO_PAD.encode(sha2::Sha256::digest(&spki))
+    );
+    Ok(Self {
+      key_pair,
+      spki,
+      keyid,
+    })
+  }
+
+  fn sign(&self, message: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    let signature = self
+      .key_pair
+      .sign(&SystemRandom::new(), message)
+      .map_err(|err| anyhow::anyhow!("failed to sign: {err}"))?;
+    Ok(signature.as_ref().to_vec())
+  }
+
+  fn public_key_pem(&self) -> String {
+    let encoded = BASE64_STANDARD.encode(&self.spki);
+    let mut pem = String::from("-----BEGIN PUBLIC KEY-----\n");
+    for line in encoded.as_bytes().chunks(64) {
+      pem.push_str(std::str::from_utf8(line).unwrap());
+      pem.push('\n');
+    }
+    pem.push_str("-----END PUBLIC KEY-----\n");
+    pem
+  }
+
+  /// The content of `/-/npm/v1/keys`.
+  pub fn keys(&self) -> NpmKeys {
+    NpmKeys {
+      keys: vec![NpmKey {
+        expires: None,
+        keyid: self.keyid.clone(),
+        keytype: NPM_KEY_TYPE.to_string(),
+        scheme: NPM_KEY_TYPE.to_string(),
+        key: BASE64_STANDARD.encode(&self.spki),
+      }],
+    }
+  }
+
+  /// Sign a version in the npm version manifest, given the `integrity` of its
+  /// tarball.
+  pub fn registry_signature(
+    &self,
+    name: &NpmMappedJsrPackageName,
+    version: &Version,
+    integrity: &str,
+  ) -> Result<NpmSignature, anyhow::Error> {
+    let message = format!("{name}@{version}:{integrity}");
+    Ok(NpmSignature {
+      keyid: self.keyid.clone(),
+      sig: BASE64_STANDARD.encode(self.sign(message.as_bytes())?),
+    })
+  }
+
+  /// Sign the publish attestation for an npm tarball, given the base64
+  /// encoded sha512 hash of the tarball, and record it in the transparency
+  /// log.
+  pub async fn create_publish_attestation(
+    &self,
+    npm_url: &Url,
+    name: &NpmMappedJsrPackageName<'_>,
+    version: &Version,
+    sha512: &str,
+  ) -> Result<NpmAttestations, anyhow::Error> {
+    let envelope =
+      self.publish_attestation_envelope(npm_url, name, version, sha512)?;
+    let tlog_entry = rekor::create_dsse_entry(
+      &serde_json::to_string(&envelope)?,
+      &self.public_key_pem(),
+    )
+    .await?;
+    Ok(NpmAttestations {
+      attestations: vec![NpmAttestation {
+        predicate_type: NPM_PUBLISH_PREDICATE_TYPE.to_string(),
+        bundle: SigstoreBundle {
+          media_type: SIGSTORE_BUNDLE_MEDIA_TYPE.to_string(),
+          verification_material: SigstoreVerificationMaterial {
+            public_key: SigstorePublicKey {
+              hint: self.keyid.clone(),
+            },
+            tlog_entries: vec![tlog_entry],
+          },
+          dsse_envelope: envelope,
+        },
+      }],
+    })
+  }
+
+  fn publish_attestation_envelope(
+    &self,
+    npm_url: &Url,
+    name: &NpmMappedJsrPackageName,
+    version: &Version,
+    sha512: &str,
+  ) -> Result<DsseEnvelope, anyhow::Error> {
+    let statement = InTotoStatement {
+      type_: IN_TOTO_STATEMENT_TYPE,
+      subject: vec![InTotoSubject {
+        name: npm_purl(name, version),
+        digest: InTotoDigest {
+          sha512: BASE64_STANDARD
+            .decode(sha512)?
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect(),
+        },
+      }],
+      predicate_type: NPM_PUBLISH_PREDICATE_TYPE,
+      predicate: NpmPublishPredicate {
+        name: name.to_string(),
+        version: version.to_string(),
+        registry: npm_url.as_str().trim_end_matches('/').to_string(),
+      },
+    };
+    let payload = serde_json::to_vec(&statement)?;
+    let signature = self.sign(&dsse_pae(IN_TOTO_PAYLOAD_TYPE, &payload))?;
+    Ok(DsseEnvelope {
+      payload: BASE64_STANDARD.encode(&payload),
+      payload_type: IN_TOTO_PAYLOAD_TYPE.to_string(),
+      signatures: vec![DsseSignature {
+        sig: BASE64_STANDARD.encode(signature),
+        keyid: self.keyid.clone(),
+      }],
+    })
+  }
+}
+
+/// The package URL npm expects as the subject of an attestation, with the
+/// `@` of the scope percent encoded.
+fn npm_purl(name: &NpmMappedJsrPackageName, version: &Version) -> String {
+  format!(
+    "pkg:npm/%40{}@{version}",
+    name.to_string().trim_start_matches('@')
+  )
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SigstoreBundle {
+  pub media_type: String,
+  pub verification_material: SigstoreVerificationMaterial,
+  pub dsse_envelope: DsseEnvelope,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SigstoreVerificationMaterial {
+  pub public_key: SigstorePublicKey,
+  pub tlog_entries: Vec<TlogEntry>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SigstorePublicKey {
+  pub hint: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DsseEnvelope {
+  pub payload: String,
+  pub payload_type: String,
+  pub signatures: Vec<DsseSignature>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DsseSignature {
+  pub sig: String,
+  pub keyid: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InTotoStatement {
+  #[serde(rename = "_type")]
+  type_: &'static str,
+  subject: Vec<InTotoSubject>,
+  predicate_type: &'static str,
+  predicate: NpmPublishPredicate,
+}
+
+#[derive(Debug, Serialize)]
+struct InTotoSubject {
+  name: String,
+  digest: InTotoDigest,
+}
+
+#[derive(Debug, Serialize)]
+struct InTotoDigest {
+  sha512: String,
+}
+
+#[derive(Debug, Serialize)]
+struct NpmPublishPredicate {
+  name: String,
+  version: String,
+  registry: String,
+}
+
+#[cfg(test)]
+mod tests {
+  use base64::Engine as _;
+  use base64::prelude::BASE64_STANDARD;
+  use ring::rand::SystemRandom;
+  use ring::signature::ECDSA_P256_SHA256_ASN1;
+  use ring::signature::ECDSA_P256_SHA256_ASN1_SIGNING;
+  use ring::signature::EcdsaKeyPair;
+  use ring::signature::UnparsedPublicKey;
+  use url::Url;
+
+  use super::NPM_PUBLISH_PREDICATE_TYPE;
+  use super::NpmSigningKey;
+  use crate::ids::PackageName;
+  use crate::ids::ScopeName;
+  use crate::ids::Version;
+  use crate::npm::types::NpmMappedJsrPackageName;
+  use crate::provenance::dsse_pae;
+
+  fn signing_key() -> NpmSigningKey {
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(
+      &ECDSA_P256_SHA256_ASN1_SIGNING,
+      &SystemRandom::new(),
+    )
+    .unwrap();
+    NpmSigningKey::from_pkcs8(pkcs8.as_ref()).unwrap()
+  }
+
+  fn verify(key: &NpmSigningKey, message: &[u8], sig: &str) {
+    // The SPKI ends with the raw point, which is what ring verifies against.
+    let point = &key.spki[super::P256_SPKI_PREFIX.len()..];
+    UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, point)
+      .verify(message, &BASE64_STANDARD.decode(sig).unwrap())
+      .unwrap();
+  }
+
+  #[test]
+  fn keys() {
+    let key = signing_key();
+    let keys = key.keys();
+    assert_eq!(keys.keys.len(), 1);
+    assert!(keys.keys[0].keyid.starts_with("SHA256:"));
+    assert_eq!(keys.keys[0].keytype, "ecdsa-sha2-nistp256");
+    assert_eq!(BASE64_STANDARD.decode(&keys.keys[0].key).unwrap().len(), 91);
+
+    let pem = key.public_key_pem();
+    assert!(pem.starts_with("-----BEGIN PUBLIC KEY-----\n"));
+    assert!(pem.ends_with("-----END PUBLIC KEY-----\n"));
+  }
+
+  #[test]
+  fn registry_signature() {
+    let key = signing_key();
+    let scope = ScopeName::try_from("luca").unwrap();
+    let package = PackageName::try_from("flag").unwrap();
+    let name = NpmMappedJsrPackageName {
+      scope: &scope,
+      package: &package,
+    };
+    let version = Version::new("1.0.0").unwrap();
+
+    let signature = key
+      .registry_signature(&name, &version, "sha512-abc")
+      .unwrap();
+    assert_eq!(signature.keyid, key.keyid);
+    verify(&key, b"@jsr/luca__flag@1.0.0:sha512-abc", &signature.sig);
+  }
+
+  #[test]
+  fn publish_attestation_envelope() {
+    let key = signing_key();
+    let scope = ScopeName::try_from("luca").unwrap();
+    let package = PackageName::try_from("flag").unwrap();
+    let name = NpmMappedJsrPackageName {
+      scope: &scope,
+      package: &package,
+    };
+    let version = Version::new("1.0.0").unwrap();
+    let npm_url = Url::parse("https://npm.jsr.io/").unwrap();
+
+    let envelope = key
+      .publish_attestation_envelope(&npm_url, &name, &version, "+/8=")
+      .unwrap();
+    let payload = BASE64_STANDARD.decode(&envelope.payload).unwrap();
+    verify(
+      &key,
+      &dsse_pae(&envelope.payload_type, &payload),
+      &envelope.signatures[0].sig,
+    );
+
+    let statement: serde_json::Value =
+      serde_json::from_slice(&payload).unwrap();
+    assert_eq!(statement["predicateType"], NPM_PUBLISH_PREDICATE_TYPE);
+    assert_eq!(
+      statement["subject"][0]["name"],
+      "pkg:npm/%40jsr/luca__flag@1.0.0"
+    );
+    assert_eq!(statement["subject"][0]["digest"]["sha512"], "fbff");
+    assert_eq!(statement["predicate"]["registry"], "https://npm.jsr.io");
+  }
+}
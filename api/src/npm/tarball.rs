@@ -21,22 +21,29 @@ use futures::StreamExt;
 use futures::TryStreamExt;
 use indexmap::IndexMap;
 use sha2::Digest;
+use tar::EntryType;
 use tar::Header;
+use tracing::debug;
 use tracing::error;
 use url::Url;
 
 use crate::db::DependencyKind;
 use crate::db::ExportsMap;
+use crate::db::RuntimeCompat;
 use crate::ids::PackageName;
 use crate::ids::PackagePath;
 use crate::ids::ScopeName;
 use crate::ids::ScopedPackageName;
 use crate::ids::Version;
-use crate::s3::BucketWithQueue;
+use crate::module_files::CachedVersionFiles;
+use crate::spilled_files::SpilledFiles;
 
 use super::NPM_TARBALL_REVISION;
+use super::dts_rollup::rollup_dts;
+use super::emit::transpile_to_cjs;
 use super::emit::transpile_to_dts;
 use super::emit::transpile_to_js;
+use super::resolution::validate_npm_exports;
 use super::specifiers::Extension;
 use super::specifiers::RewriteKind;
 use super::specifiers::SpecifierRewriter;
@@ -54,16 +61,30 @@ pub struct NpmTarball {
   pub sha1: String,
   /// The base64 encoded sha512 hash of the gzipped tarball.
   pub sha512: String,
+  /// The SHA-256 digest of the gzipped tarball, as `sha256-<hex>`.
+  pub sha256: String,
+  /// The `package.json` included in the tarball.
+  pub package_json: String,
 }
 
 pub enum NpmTarballFiles<'a> {
-  WithBytes(&'a HashMap<PackagePath, Vec<u8>>),
+  WithBytes {
+    files: &'a HashMap<PackagePath, Vec<u8>>,
+    spilled_files: &'a SpilledFiles,
+  },
   FromBucket {
     files: &'a HashSet<PackagePath>,
-    modules_bucket: &'a BucketWithQueue,
+    version_files: &'a CachedVersionFiles,
   },
 }
 
+/// The modification time of every file in the tarball: 1985-10-26T08:15:00Z,
+/// the same fixed time that `npm pack` uses.
+const NPM_TARBALL_MTIME: u64 = 499162500;
+
+/// The "unknown" operating system in a gzip header.
+const GZIP_OS_UNKNOWN: u8 = 255;
+
 pub struct NpmTarballOptions<
   'a,
   Deps: Iterator<Item = &'a (DependencyKind, PackageReqReference)>,
@@ -77,6 +98,29 @@ pub struct NpmTarballOptions<
   pub exports: &'a ExportsMap,
   pub files: NpmTarballFiles<'a>,
   pub dependencies: Deps,
+  /// The subset of `dependencies` that is listed in `optionalDependencies`.
+  pub optional_dependencies: &'a [(DependencyKind, PackageReqReference)],
+  /// Dependencies that are listed in `peerDependencies` instead of
+  /// `dependencies`, whether the package imports them or not.
+  pub peer_dependencies: &'a [(DependencyKind, PackageReqReference)],
+  /// The runtime compatibility of the package, used for the `engines` field
+  /// and the runtime specific export conditions.
+  pub runtime_compat: &'a RuntimeCompat,
+  /// Executables to list in the `bin` field, mapped to the entrypoint they
+  /// run.
+  pub bin: &'a IndexMap<String, String>,
+  /// The subpath pattern entries the exports were expanded from, which are
+  /// exported as patterns in `package.json` where possible.
+  pub export_patterns: &'a IndexMap<String, String>,
+  /// Patterns of files that are left out of the tarball, see
+  /// [npm_exclude_match]. None of them may be part of the module graph.
+  pub exclude: &'a [String],
+  /// Whether to also emit a CommonJS build of every module to `/_cjs`, and
+  /// reference it from the `require` export condition.
+  pub cjs: bool,
+  /// Whether to roll up the declarations of every export into a single file
+  /// in `/_types`, and reference it from the `types` export condition.
+  pub dts_rollup: bool,
 }
 
 pub async fn create_npm_tarball<'a>(
@@ -95,12 +139,32 @@ pub async fn create_npm_tarball<'a>(
     exports,
     files,
     dependencies,
+    optional_dependencies,
+    peer_dependencies,
+    runtime_compat,
+    bin,
+    export_patterns,
+    exclude,
+    cjs,
+    dts_rollup,
   } = opts;
 
   let npm_package_id = NpmMappedJsrPackageName { scope, package };
 
-  let npm_dependencies =
-    create_npm_dependencies(dependencies.map(Cow::Borrowed))?;
+  let npm_peer_dependencies =
+    create_npm_dependencies(peer_dependencies.iter().map(Cow::Borrowed))?;
+  let mut npm_dependencies = create_npm_dependencies(
+    dependencies
+      .filter(|dependency| !optional_dependencies.contains(dependency))
+      .map(Cow::Borrowed),
+  )?;
+  npm_dependencies.retain(|name, _| !npm_peer_dependencies.contains_key(name));
+  let mut npm_optional_dependencies =
+    create_npm_dependencies(optional_dependencies.iter().map(Cow::Borrowed))?;
+  npm_optional_dependencies.retain(|name, _| {
+    !npm_dependencies.contains_key(name)
+      && !npm_peer_dependencies.contains_key(name)
+  });
 
   let homepage = Url::options()
     .base_url(Some(registry_url))
@@ -110,6 +174,9 @@ pub async fn create_npm_tarball<'a>(
 
   let mut package_files = IndexMap::new();
   let mut to_be_rewritten = vec![];
+  // Paths of the emitted ES modules, which get a CommonJS counterpart if
+  // `cjs` is set.
+  let mut esm_paths = vec![];
 
   // Mapping of original specifiers in the module graph to where one can find
   // the source code or declarations for that module in the tarball, if it
@@ -200,6 +267,7 @@ pub async fn create_npm_tarball<'a>(
         );
         package_files
           .insert(js.specifier.path().to_owned(), rewritten.into_bytes());
+        esm_paths.push(js.specifier.path().to_owned());
       }
       deno_ast::MediaType::Dts | deno_ast::MediaType::Dmts => {
         let parsed_source = sources.get_parsed_source(&js.specifier).unwrap();
@@ -237,6 +305,7 @@ pub async fn create_npm_tarball<'a>(
         package_files.insert(source_target.path().to_owned(), source);
         package_files
           .insert(format!("{}.map", source_target.path()), source_map);
+        esm_paths.push(source_target.path().to_owned());
       }
       deno_ast::MediaType::TypeScript | deno_ast::MediaType::Mts => {
         let parsed_source = sources.get_parsed_source(&js.specifier).unwrap();
@@ -273,6 +342,7 @@ pub async fn create_npm_tarball<'a>(
         package_files.insert(source_target.path().to_owned(), source);
         package_files
           .insert(format!("{}.map", source_target.path()), source_map);
+        esm_paths.push(source_target.path().to_owned());
 
         if let Some(fast_check_module) = js.fast_check_module() {
           let declaration_target =
@@ -301,36 +371,109 @@ pub async fn create_npm_tarball<'a>(
     }
   }
 
+  // Paths of the files generated for npm that are not derived from a file of
+  // the package with the same path. A file of the package must not use one of
+  // them, as it would silently replace the other.
+  let mut generated_paths = HashSet::new();
+
+  if cjs {
+    for path in esm_paths {
+      // Modules keep their path inside `/_cjs`, so relative imports between
+      // them resolve without rewriting. A `.mjs` file is always an ES module
+      // though, whatever the closest package.json says.
+      if !path.ends_with(".js") {
+        anyhow::bail!(
+          "a CommonJS build can not be created for '{path}', only .js, .jsx, .ts and .tsx modules are supported"
+        );
+      }
+      let specifier = Url::parse(&format!("file://{path}")).unwrap();
+      let source = transpile_to_cjs(&specifier, &package_files[&path])?;
+      let cjs_path = format!("/_cjs{path}");
+      generated_paths.insert(cjs_path.clone());
+      package_files.insert(cjs_path, source);
+    }
+    generated_paths.insert("/_cjs/package.json".to_string());
+    package_files.insert(
+      "/_cjs/package.json".to_string(),
+      b"{\n  \"type\": \"commonjs\"\n}\n".to_vec(),
+    );
+  }
+
+  for (name, path) in bin.iter() {
+    let specifier = ModuleSpecifier::parse(&format!(
+      "file:///{}",
+      path.trim_start_matches('.').trim_start_matches('/')
+    ))
+    .unwrap();
+    let Some(source_specifier) = follow_specifier(&specifier, source_rewrites)
+    else {
+      anyhow::bail!("bin entrypoint '{path}' is not part of the package");
+    };
+    // npm marks the file it links as executable, so the stub needs a shebang.
+    // The entrypoint itself is kept as is, so it can also be imported.
+    let stub_path = npm_bin_stub_path(name);
+    let stub_specifier =
+      ModuleSpecifier::parse(&format!("file://{stub_path}")).unwrap();
+    let import_specifier =
+      relative_import_specifier(&stub_specifier, source_specifier);
+    generated_paths.insert(stub_path.clone());
+    package_files.insert(
+      stub_path.clone(),
+      format!("#!/usr/bin/env node\nimport \"{import_specifier}\";\n")
+        .into_bytes(),
+    );
+  }
+
   match files {
-    NpmTarballFiles::WithBytes(files) => {
+    NpmTarballFiles::WithBytes {
+      files,
+      spilled_files,
+    } => {
       for (path, content) in files.iter() {
+        if npm_exclude_match(path, exclude).is_some() {
+          continue;
+        }
+        reject_generated_path(&generated_paths, path)?;
         if !package_files.contains_key(&**path) {
           package_files.insert(path.to_string(), content.clone());
         }
       }
+      for path in spilled_files.paths() {
+        if npm_exclude_match(path, exclude).is_some() {
+          continue;
+        }
+        reject_generated_path(&generated_paths, path)?;
+        if !package_files.contains_key(&**path) {
+          let content = spilled_files
+            .read_async(path)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("spilled file missing: {path}"))?;
+          package_files.insert(path.to_string(), content.to_vec());
+        }
+      }
     }
     NpmTarballFiles::FromBucket {
       files,
-      modules_bucket,
+      version_files,
     } => {
       let mut paths_to_download = vec![];
       for path in files.iter() {
+        if npm_exclude_match(path, exclude).is_some() {
+          continue;
+        }
+        reject_generated_path(&generated_paths, path)?;
         if !package_files.contains_key(&**path) {
           paths_to_download.push(path);
         }
       }
 
       let downloads = futures::stream::iter(paths_to_download.into_iter())
-        .map(|path| {
-          let s3_path =
-            crate::s3_paths::file_path(scope, package, version, path).into();
-          async move {
-            let bytes = modules_bucket
-              .download(s3_path)
-              .await?
-              .ok_or_else(|| anyhow::anyhow!("file missing on S3: {path}"))?;
-            Ok::<_, anyhow::Error>((path, bytes))
-          }
+        .map(|path| async move {
+          let bytes = version_files
+            .download(path)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("file missing on S3: {path}"))?;
+          Ok::<_, anyhow::Error>((path, bytes))
         })
         .buffer_unordered(64);
 
@@ -341,38 +484,95 @@ pub async fn create_npm_tarball<'a>(
     }
   }
 
+  // Mapping of declaration file paths of exports to the path of the file
+  // their declarations were rolled up into. Rolling up is best effort: if it
+  // fails, the export keeps pointing at its own declaration file.
+  let mut dts_rollups = HashMap::<String, String>::new();
+  if dts_rollup {
+    for (_, path) in exports.iter() {
+      let specifier = ModuleSpecifier::parse(&format!(
+        "file:///{}",
+        path.trim_start_matches('.').trim_start_matches('/')
+      ))
+      .unwrap();
+      let Some(types_specifier) =
+        follow_specifier(&specifier, &declaration_rewrites)
+      else {
+        continue;
+      };
+      let types_path = types_specifier.path();
+      if types_specifier.scheme() != "file"
+        || !(types_path.ends_with(".d.ts") || types_path.ends_with(".d.mts"))
+        || !package_files.contains_key(types_path)
+        || dts_rollups.contains_key(types_path)
+      {
+        continue;
+      }
+      let rollup_path = format!(
+        "/_types{}",
+        types_path.strip_prefix("/_dist").unwrap_or(types_path)
+      );
+      if package_files.contains_key(&rollup_path) {
+        continue;
+      }
+      match rollup_dts(types_path, &package_files) {
+        Ok(rollup) => {
+          package_files.insert(rollup_path.clone(), rollup.into_bytes());
+          dts_rollups.insert(types_path.to_string(), rollup_path);
+        }
+        Err(err) => {
+          debug!("failed to roll up declarations of {types_path}: {err}");
+        }
+      }
+    }
+  }
+
   let npm_exports = create_npm_exports(
     exports,
+    export_patterns,
     &package_files,
     &source_rewrites,
     &declaration_rewrites,
+    &dts_rollups,
+    runtime_compat,
   );
 
+  // Catch exports that can not be imported from the tarball before anyone
+  // installs it, rather than when they do.
+  validate_npm_exports(exports, &npm_exports, &package_files, cjs).map_err(
+    |err| anyhow::anyhow!("exports do not resolve in Node.js: {err}"),
+  )?;
+
   let pkg_json = NpmPackageJson {
     name: npm_package_id,
     version: version.clone(),
     module_type: "module".to_string(),
+    engines: create_npm_engines(runtime_compat),
     exports: npm_exports,
+    bin: create_npm_bin(bin),
     dependencies: npm_dependencies,
+    optional_dependencies: npm_optional_dependencies,
+    peer_dependencies: npm_peer_dependencies,
     homepage,
     revision: NPM_TARBALL_REVISION,
   };
 
   let pkg_json_str = serde_json::to_string_pretty(&pkg_json)?;
-  package_files.insert("/package.json".to_string(), pkg_json_str.into());
+  package_files
+    .insert("/package.json".to_string(), pkg_json_str.clone().into());
 
   package_files.sort_keys();
 
+  // The tarball must only depend on its inputs, so that rebuilding it yields
+  // the same bytes, and so the same hashes. Files are sorted by path above,
+  // and the gzip header has no timestamp, file name or OS.
   let mut tar_gz_bytes = Vec::new();
-  let mut gz_encoder = flate2::write::GzEncoder::new(
-    &mut tar_gz_bytes,
-    flate2::Compression::default(),
-  );
+  let mut gz_encoder = flate2::GzBuilder::new()
+    .mtime(0)
+    .operating_system(GZIP_OS_UNKNOWN)
+    .write(&mut tar_gz_bytes, flate2::Compression::default());
   let mut tarball = tar::Builder::new(&mut gz_encoder);
 
-  let now = std::time::SystemTime::now();
-  let mtime = now.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
-
   for (path, content) in package_files.iter() {
     let mut header = Header::new_ustar();
     header.set_path(format!("./package{path}")).map_err(|e| {
@@ -386,9 +586,12 @@ pub async fn create_npm_tarball<'a>(
         error: crate::ids::PackagePathValidationError::TooLong(path.len()),
       }
     })?;
+    header.set_entry_type(EntryType::Regular);
     header.set_size(content.len() as u64);
     header.set_mode(0o644);
-    header.set_mtime(mtime);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mtime(NPM_TARBALL_MTIME);
     header.set_cksum();
     tarball.append(&header, content.as_slice()).unwrap();
   }
@@ -403,11 +606,14 @@ pub async fn create_npm_tarball<'a>(
   let sha1 = format!("{sha1_digest:X}");
   let sha512_digest = sha2::Sha512::digest(&tar_gz_bytes);
   let sha512 = base64::prelude::BASE64_STANDARD.encode(sha512_digest);
+  let sha256 = crate::integrity::sha256_digest(&tar_gz_bytes);
 
   Ok(NpmTarball {
     tarball: tar_gz_bytes,
     sha1,
     sha512,
+    sha256,
+    package_json: pkg_json_str,
   })
 }
 
@@ -561,11 +767,109 @@ pub fn create_npm_dependencies<'a>(
   Ok(npm_dependencies)
 }
 
+/// The `engines` field for a package marked as compatible with Node.js. The
+/// tarball is made up of ES modules with `exports`, which Node.js supports
+/// since 18, the oldest release line this is declared for.
+const NPM_NODE_ENGINE: &str = ">=18";
+
+pub fn create_npm_engines(
+  runtime_compat: &RuntimeCompat,
+) -> IndexMap<String, String> {
+  let mut engines = IndexMap::new();
+  if runtime_compat.node == Some(true) {
+    engines.insert("node".to_string(), NPM_NODE_ENGINE.to_string());
+  }
+  engines
+}
+
+/// Returns the first pattern of the `npm.exclude` field of the config file
+/// that matches `path`, an absolute path in the package.
+///
+/// Patterns are relative to the root of the package. `*` matches any part of
+/// a path segment, and `**` any number of segments. A pattern that matches a
+/// directory excludes everything in it.
+pub fn npm_exclude_match<'a>(
+  path: &str,
+  exclude: &'a [String],
+) -> Option<&'a str> {
+  let path_segments =
+    path.trim_start_matches('/').split('/').collect::<Vec<_>>();
+  exclude
+    .iter()
+    .find(|pattern| {
+      let pattern_segments = pattern
+        .trim_start_matches("./")
+        .trim_start_matches('/')
+        .trim_end_matches('/')
+        .split('/')
+        .collect::<Vec<_>>();
+      (1..=path_segments.len()).any(|len| {
+        glob_segments_match(&pattern_segments, &path_segments[..len])
+      })
+    })
+    .map(|pattern| pattern.as_str())
+}
+
+fn glob_segments_match(pattern: &[&str], path: &[&str]) -> bool {
+  match pattern.split_first() {
+    None => path.is_empty(),
+    Some((&"**", rest)) => {
+      (0..=path.len()).any(|skip| glob_segments_match(rest, &path[skip..]))
+    }
+    Some((segment, rest)) => path.split_first().is_some_and(|(first, path)| {
+      glob_segment_match(segment.as_bytes(), first.as_bytes())
+        && glob_segments_match(rest, path)
+    }),
+  }
+}
+
+fn glob_segment_match(pattern: &[u8], text: &[u8]) -> bool {
+  match pattern.split_first() {
+    None => text.is_empty(),
+    Some((b'*', rest)) => {
+      (0..=text.len()).any(|skip| glob_segment_match(rest, &text[skip..]))
+    }
+    Some((c, rest)) => text
+      .split_first()
+      .is_some_and(|(t, text)| c == t && glob_segment_match(rest, text)),
+  }
+}
+
+fn reject_generated_path(
+  generated_paths: &HashSet<String>,
+  path: &str,
+) -> Result<(), anyhow::Error> {
+  if generated_paths.contains(path) {
+    anyhow::bail!(
+      "'{path}' conflicts with a file generated for the npm tarball, rename it or leave it out of the npm tarball with `npm.exclude`"
+    );
+  }
+  Ok(())
+}
+
+fn npm_bin_stub_path(name: &str) -> String {
+  format!("/_bin/{name}.js")
+}
+
+/// The `bin` field, pointing every command at the stub that runs its
+/// entrypoint.
+pub fn create_npm_bin(
+  bin: &IndexMap<String, String>,
+) -> IndexMap<String, String> {
+  bin
+    .keys()
+    .map(|name| (name.clone(), format!(".{}", npm_bin_stub_path(name))))
+    .collect()
+}
+
 pub fn create_npm_exports(
   exports: &ExportsMap,
+  export_patterns: &IndexMap<String, String>,
   package_files: &IndexMap<String, Vec<u8>>,
   source_rewrites: &HashMap<&ModuleSpecifier, ModuleSpecifier>,
   declaration_rewrites: &HashMap<&ModuleSpecifier, ModuleSpecifier>,
+  dts_rollups: &HashMap<String, String>,
+  runtime_compat: &RuntimeCompat,
 ) -> IndexMap<String, NpmExportConditions> {
   let package_json_specifier =
     ModuleSpecifier::parse("file:///package.json").unwrap();
@@ -574,6 +878,10 @@ pub fn create_npm_exports(
   for (key, path) in exports.iter() {
     let mut conditions = NpmExportConditions {
       types: None,
+      browser: None,
+      workerd: None,
+      bun: None,
+      require: None,
       default: None,
     };
 
@@ -590,7 +898,29 @@ pub fn create_npm_exports(
     {
       let new_specifier =
         relative_import_specifier(&package_json_specifier, source_specifier);
+
+      // The same module is used in every runtime, but the conditions let
+      // tools pick up which runtimes the package is marked compatible with.
+      if runtime_compat.browser == Some(true) {
+        conditions.browser = Some(new_specifier.clone());
+      }
+      if runtime_compat.workerd == Some(true) {
+        conditions.workerd = Some(new_specifier.clone());
+      }
+      if runtime_compat.bun == Some(true) {
+        conditions.bun = Some(new_specifier.clone());
+      }
       conditions.default = Some(new_specifier);
+
+      let cjs_path = format!("/_cjs{}", source_specifier.path());
+      if package_files.contains_key(&cjs_path) {
+        let cjs_specifier =
+          ModuleSpecifier::parse(&format!("file://{cjs_path}")).unwrap();
+        conditions.require = Some(relative_import_specifier(
+          &package_json_specifier,
+          &cjs_specifier,
+        ));
+      }
     }
 
     if let Some(types_specifier) =
@@ -598,6 +928,12 @@ pub fn create_npm_exports(
       && types_specifier.scheme() == "file"
       && package_files.contains_key(types_specifier.path())
     {
+      let rollup_specifier =
+        dts_rollups.get(types_specifier.path()).map(|rollup_path| {
+          ModuleSpecifier::parse(&format!("file://{rollup_path}")).unwrap()
+        });
+      let types_specifier =
+        rollup_specifier.as_ref().unwrap_or(types_specifier);
       let new_specifier =
         relative_import_specifier(&package_json_specifier, types_specifier);
       if conditions.default.as_ref() != Some(&new_specifier) {
@@ -607,9 +943,133 @@ pub fn create_npm_exports(
 
     npm_exports.insert(key.clone(), conditions);
   }
+
+  for (pattern_key, pattern_path) in export_patterns {
+    collapse_npm_export_pattern(
+      &mut npm_exports,
+      exports,
+      pattern_key,
+      pattern_path,
+      package_files,
+    );
+  }
+
   npm_exports
 }
 
+/// Replaces the entries of `npm_exports` that a subpath pattern expanded to
+/// with a single pattern entry, if every export condition of every entry
+/// follows the same pattern, and that pattern does not match any other file
+/// in the tarball. Otherwise the entries are kept as they are, which is
+/// equivalent, only longer.
+fn collapse_npm_export_pattern(
+  npm_exports: &mut IndexMap<String, NpmExportConditions>,
+  exports: &ExportsMap,
+  pattern_key: &str,
+  pattern_path: &str,
+  package_files: &IndexMap<String, Vec<u8>>,
+) {
+  let (key_prefix, key_suffix) = pattern_key.split_once('*').unwrap();
+  let (path_prefix, path_suffix) = pattern_path.split_once('*').unwrap();
+
+  // The expanded entries, with the part of the subpath matched by the `*`.
+  let members = exports
+    .iter()
+    .filter_map(|(key, path)| {
+      let capture = key.strip_prefix(key_prefix)?.strip_suffix(key_suffix)?;
+      let expected_path = format!("{path_prefix}{capture}{path_suffix}");
+      (!capture.is_empty() && *path == expected_path)
+        .then_some((key.as_str(), capture))
+    })
+    .collect::<Vec<_>>();
+  if members.is_empty() {
+    return;
+  }
+
+  let Some(conditions) =
+    collapse_npm_export_conditions(&members, npm_exports, package_files)
+  else {
+    return;
+  };
+
+  let first = npm_exports.get_index_of(members[0].0).unwrap();
+  for (key, _) in &members {
+    npm_exports.shift_remove(*key);
+  }
+  npm_exports.shift_insert(
+    first.min(npm_exports.len()),
+    pattern_key.to_string(),
+    conditions,
+  );
+}
+
+fn collapse_npm_export_conditions(
+  members: &[(&str, &str)],
+  npm_exports: &IndexMap<String, NpmExportConditions>,
+  package_files: &IndexMap<String, Vec<u8>>,
+) -> Option<NpmExportConditions> {
+  let members = members
+    .iter()
+    .map(|(key, capture)| Some((*capture, npm_exports.get(*key)?)))
+    .collect::<Option<Vec<_>>>()?;
+  let field = |get: fn(&NpmExportConditions) -> Option<&str>| {
+    let values = members
+      .iter()
+      .map(|(capture, conditions)| (*capture, get(conditions)))
+      .collect::<Vec<_>>();
+    if values.iter().all(|(_, value)| value.is_none()) {
+      return Some(None);
+    }
+    let values = values
+      .into_iter()
+      .map(|(capture, value)| Some((capture, value?)))
+      .collect::<Option<Vec<_>>>()?;
+    npm_export_pattern(&values, package_files).map(Some)
+  };
+  Some(NpmExportConditions {
+    types: field(|c| c.types.as_deref())?,
+    browser: field(|c| c.browser.as_deref())?,
+    workerd: field(|c| c.workerd.as_deref())?,
+    bun: field(|c| c.bun.as_deref())?,
+    require: field(|c| c.require.as_deref())?,
+    default: field(|c| c.default.as_deref())?,
+  })
+}
+
+/// Finds the pattern (`./prefix*suffix`) that every specifier in `values`
+/// follows, with the `*` replaced by the captured part of the subpath. The
+/// pattern must not match any file in the tarball besides these.
+fn npm_export_pattern(
+  values: &[(&str, &str)],
+  package_files: &IndexMap<String, Vec<u8>>,
+) -> Option<String> {
+  let (first_capture, first_value) = values[0];
+  first_value
+    .match_indices(first_capture)
+    .map(|(index, _)| {
+      (
+        &first_value[..index],
+        &first_value[index + first_capture.len()..],
+      )
+    })
+    .find(|(prefix, suffix)| {
+      let follows_pattern = values
+        .iter()
+        .all(|(capture, value)| *value == format!("{prefix}{capture}{suffix}"));
+      let matched_files = package_files
+        .keys()
+        .filter(|path| {
+          format!(".{path}")
+            .strip_prefix(prefix)
+            .and_then(|rest| rest.strip_suffix(suffix))
+            .is_some_and(|capture| !capture.is_empty())
+        })
+        .count();
+      follows_pattern && matched_files == values.len()
+    })
+    .map(|(prefix, suffix)| format!("{prefix}*{suffix}"))
+}
+
 #[cfg(test)]
 mod tests {
   use std::collections::HashMap;
@@ -635,16 +1095,22 @@ mod tests {
   use crate::analysis::JsrResolver;
   use crate::analysis::ModuleAnalyzer;
   use crate::analysis::PassthroughJsrUrlProvider;
+  use crate::analysis::parse_peer_dependency;
   use crate::db::DependencyKind;
+  use crate::db::RuntimeCompat;
   use crate::ids::PackagePath;
   use crate::npm::NPM_TARBALL_REVISION;
   use crate::npm::tests::helpers;
   use crate::npm::tests::helpers::Spec;
+  use crate::spilled_files::SpilledFiles;
+  use crate::tarball::expand_export_patterns;
   use crate::tarball::exports_map_from_json;
 
   use super::NpmTarballFiles;
   use super::NpmTarballOptions;
+  use super::create_npm_engines;
   use super::create_npm_tarball;
+  use super::npm_exclude_match;
 
   async fn test_npm_tarball(
     spec_path: &Path,
@@ -688,6 +1154,14 @@ mod tests {
       }
     }
 
+    let (exports, export_patterns) =
+      match expand_export_patterns(exports, files.keys()) {
+        Ok(exports) => exports,
+        Err(e) => {
+          return Err(anyhow::anyhow!("failed to expand exports: {}", e));
+        }
+      };
+
     let loader = MemoryLoader::new(memory_files, vec![]);
     let mut graph = ModuleGraph::new(GraphKind::All);
     let workspace_member = WorkspaceMember {
@@ -716,7 +1190,7 @@ mod tests {
           module_analyzer: &module_analyzer,
           file_system: &NullFileSystem,
           resolver: Some(&JsrResolver {
-            member: workspace_member,
+            members: vec![workspace_member],
           }),
           npm_resolver: None,
           reporter: None,
@@ -739,19 +1213,49 @@ mod tests {
     });
 
     let deps: Vec<(DependencyKind, PackageReqReference)> = vec![];
-
-    let npm_tarball = create_npm_tarball(NpmTarballOptions {
+    let peer_deps = spec
+      .jsr_json
+      .peer_dependencies
+      .iter()
+      .map(|specifier| parse_peer_dependency(specifier).unwrap())
+      .collect::<Vec<_>>();
+
+    let registry_url = Url::parse("http://jsr.test").unwrap();
+    let runtime_compat = RuntimeCompat {
+      browser: None,
+      deno: None,
+      node: None,
+      workerd: None,
+      bun: None,
+    };
+    let options = || NpmTarballOptions {
       exports: &exports,
       package: &package,
-      registry_url: &Url::parse("http://jsr.test").unwrap(),
+      registry_url: &registry_url,
       scope: &scope,
       version: &version,
       graph: &graph,
       analyzer: &module_analyzer.analyzer,
-      files: NpmTarballFiles::WithBytes(&files),
+      files: NpmTarballFiles::WithBytes {
+        files: &files,
+        spilled_files: &SpilledFiles::default(),
+      },
       dependencies: deps.iter(),
-    })
-    .await?;
+      optional_dependencies: &[],
+      peer_dependencies: &peer_deps,
+      runtime_compat: &runtime_compat,
+      bin: &spec.jsr_json.bin,
+      export_patterns: &export_patterns,
+      exclude: &spec.jsr_json.npm.exclude,
+      cjs: spec.jsr_json.npm.cjs,
+      dts_rollup: spec.jsr_json.npm.dts_rollup,
+    };
+    let npm_tarball = create_npm_tarball(options()).await?;
+    let npm_tarball_again = create_npm_tarball(options()).await?;
+    assert_eq!(
+      npm_tarball.tarball, npm_tarball_again.tarball,
+      "npm tarball for {spec_path:?} is not reproducible",
+    );
 
     let mut transpiled_files: Vec<(String, Vec<u8>)> = Vec::new();
 
@@ -823,4 +1327,43 @@ mod tests {
         .unwrap_or_else(|e| panic!("failed to test npm tarball {path:?}: {e}"));
     }
   }
+
+  #[test]
+  fn npm_engines() {
+    let mut runtime_compat = RuntimeCompat {
+      browser: Some(true),
+      deno: Some(true),
+      node: None,
+      workerd: None,
+      bun: None,
+    };
+    assert!(create_npm_engines(&runtime_compat).is_empty());
+
+    runtime_compat.node = Some(false);
+    assert!(create_npm_engines(&runtime_compat).is_empty());
+
+    runtime_compat.node = Some(true);
+    let engines = create_npm_engines(&runtime_compat);
+    assert_eq!(engines.len(), 1);
+    assert_eq!(engines["node"], ">=18");
+  }
+
+  #[test]
+  fn npm_exclude() {
+    let exclude = [
+      "./tests".to_string(),
+      "**/*_bench.ts".to_string(),
+      "src/*.fixture.*".to_string(),
+    ];
+    let matches = |path: &str| npm_exclude_match(path, &exclude);
+    assert_eq!(matches("/tests/foo.ts"), Some("./tests"));
+    assert_eq!(matches("/tests/nested/foo.ts"), Some("./tests"));
+    assert_eq!(matches("/tests.ts"), None);
+    assert_eq!(matches("/mod_bench.ts"), Some("**/*_bench.ts"));
+    assert_eq!(matches("/src/deep/mod_bench.ts"), Some("**/*_bench.ts"));
+    assert_eq!(matches("/src/mod_bench.js"), None);
+    assert_eq!(matches("/src/a.fixture.json"), Some("src/*.fixture.*"));
+    assert_eq!(matches("/src/nested/a.fixture.json"), None);
+    assert_eq!(matches("/src/mod.ts"), None);
+  }
 }
@@ -5,6 +5,7 @@ use serde::Serialize;
 use crate::ids::PackageName;
 use crate::ids::ScopeName;
 use crate::ids::Version;
+use crate::npm::signing::SigstoreBundle;
 
 // TODO: We don't have the @jsr scope on npm
 pub const NPM_SCOPE: &str = "jsr";
@@ -26,6 +27,21 @@ impl std::fmt::Debug for NpmMappedJsrPackageName<'_> {
   }
 }
 
+/// Parses the name of a JSR package on npm (`@jsr/<scope>__<package>`) back
+/// into its JSR scope and package name.
+pub fn parse_npm_mapped_jsr_package_name(
+  name: &str,
+) -> Option<(ScopeName, PackageName)> {
+  let (scope, package) = name
+    .strip_prefix('@')?
+    .strip_prefix(NPM_SCOPE)?
+    .strip_prefix('/')?
+    .split_once("__")?;
+  let scope = ScopeName::try_from(scope).ok()?;
+  let package = PackageName::try_from(package).ok()?;
+  Some((scope, package))
+}
+
 impl serde::Serialize for NpmMappedJsrPackageName<'_> {
   fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
   where
@@ -41,6 +57,53 @@ pub struct NpmDistInfo {
   pub tarball: String,
   pub shasum: String,
   pub integrity: String,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub signatures: Vec<NpmSignature>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub attestations: Option<NpmDistAttestations>,
+}
+
+/// A registry signature over `<name>@<version>:<integrity>`, checked by
+/// `npm audit signatures` against the keys at `/-/npm/v1/keys`.
+#[derive(Debug, Serialize)]
+pub struct NpmSignature {
+  pub keyid: String,
+  pub sig: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NpmDistAttestations {
+  pub url: String,
+}
+
+/// The response of `/-/npm/v1/keys`.
+#[derive(Debug, Serialize)]
+pub struct NpmKeys {
+  pub keys: Vec<NpmKey>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NpmKey {
+  pub expires: Option<String>,
+  pub keyid: String,
+  pub keytype: String,
+  pub scheme: String,
+  /// The base64 encoded DER SubjectPublicKeyInfo of the key.
+  pub key: String,
+}
+
+/// The response of `/-/npm/v1/attestations/<name>@<version>`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NpmAttestations {
+  pub attestations: Vec<NpmAttestation>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NpmAttestation {
+  pub predicate_type: String,
+  pub bundle: SigstoreBundle,
 }
 
 #[derive(Debug, Serialize)]
@@ -50,7 +113,19 @@ pub struct NpmVersionInfo<'a> {
   pub version: Version,
   pub description: String,
   pub dist: NpmDistInfo,
+  #[serde(skip_serializing_if = "IndexMap::is_empty")]
+  pub engines: IndexMap<String, String>,
   pub dependencies: IndexMap<String, String>,
+  #[serde(skip_serializing_if = "IndexMap::is_empty")]
+  pub optional_dependencies: IndexMap<String, String>,
+  #[serde(skip_serializing_if = "IndexMap::is_empty")]
+  pub peer_dependencies: IndexMap<String, String>,
+  #[serde(skip_serializing_if = "IndexMap::is_empty")]
+  pub bin: IndexMap<String, String>,
+  /// Set for yanked versions. npm only picks deprecated versions for a range
+  /// if no other version matches, but still installs them when pinned.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub deprecated: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -64,11 +139,80 @@ pub struct NpmPackageInfo<'a> {
   pub time: IndexMap<String, String>,
 }
 
+/// The abbreviated package metadata that package managers request with
+/// `Accept: application/vnd.npm.install-v1+json`, which only contains the
+/// fields needed to install the package.
+#[derive(Debug, Serialize)]
+pub struct NpmAbbreviatedPackageInfo<'a> {
+  pub name: NpmMappedJsrPackageName<'a>,
+  pub modified: String,
+  #[serde(rename = "dist-tags")]
+  pub dist_tags: IndexMap<String, Version>,
+  pub versions: IndexMap<Version, NpmAbbreviatedVersionInfo<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NpmAbbreviatedVersionInfo<'a> {
+  pub name: NpmMappedJsrPackageName<'a>,
+  pub version: Version,
+  pub dist: NpmDistInfo,
+  #[serde(skip_serializing_if = "IndexMap::is_empty")]
+  pub engines: IndexMap<String, String>,
+  pub dependencies: IndexMap<String, String>,
+  #[serde(skip_serializing_if = "IndexMap::is_empty")]
+  pub optional_dependencies: IndexMap<String, String>,
+  #[serde(skip_serializing_if = "IndexMap::is_empty")]
+  pub peer_dependencies: IndexMap<String, String>,
+  #[serde(skip_serializing_if = "IndexMap::is_empty")]
+  pub bin: IndexMap<String, String>,
+  /// Set for yanked versions. npm only picks deprecated versions for a range
+  /// if no other version matches, but still installs them when pinned.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub deprecated: Option<String>,
+}
+
+impl<'a> From<NpmPackageInfo<'a>> for NpmAbbreviatedPackageInfo<'a> {
+  fn from(mut info: NpmPackageInfo<'a>) -> Self {
+    NpmAbbreviatedPackageInfo {
+      name: info.name,
+      modified: info.time.shift_remove("modified").unwrap_or_default(),
+      dist_tags: info.dist_tags,
+      versions: info
+        .versions
+        .into_iter()
+        .map(|(version, info)| {
+          let info = NpmAbbreviatedVersionInfo {
+            name: info.name,
+            version: info.version,
+            dist: info.dist,
+            engines: info.engines,
+            dependencies: info.dependencies,
+            optional_dependencies: info.optional_dependencies,
+            peer_dependencies: info.peer_dependencies,
+            bin: info.bin,
+            deprecated: info.deprecated,
+          };
+          (version, info)
+        })
+        .collect(),
+    }
+  }
+}
+
 #[derive(Debug, Serialize)]
 pub struct NpmExportConditions {
   #[serde(skip_serializing_if = "Option::is_none")]
   pub types: Option<String>,
   #[serde(skip_serializing_if = "Option::is_none")]
+  pub browser: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub workerd: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub bun: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub require: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
   pub default: Option<String>,
 }
 
@@ -80,8 +224,22 @@ pub struct NpmPackageJson<'a> {
 
   #[serde(rename = "type")]
   pub module_type: String,
+  #[serde(skip_serializing_if = "IndexMap::is_empty")]
+  pub engines: IndexMap<String, String>,
   pub dependencies: IndexMap<String, String>,
+  #[serde(
+    rename = "optionalDependencies",
+    skip_serializing_if = "IndexMap::is_empty"
+  )]
+  pub optional_dependencies: IndexMap<String, String>,
+  #[serde(
+    rename = "peerDependencies",
+    skip_serializing_if = "IndexMap::is_empty"
+  )]
+  pub peer_dependencies: IndexMap<String, String>,
   pub exports: IndexMap<String, NpmExportConditions>,
+  #[serde(skip_serializing_if = "IndexMap::is_empty")]
+  pub bin: IndexMap<String, String>,
 
   #[serde(rename = "_jsr_revision")]
   pub revision: u32,
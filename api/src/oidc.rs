@@ -0,0 +1,495 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+//! Verification of the OpenID Connect tokens that CI systems issue to their
+//! jobs, which allow publishing without an access token.
+//!
+//! Each built-in provider knows the issuer of its tokens, and the claims that
+//! identify the CI project a token was issued to, its subject. Packages list
+//! the subjects that may publish them as trusted publishers. Scopes may trust
+//! additional issuers, like self-hosted GitLab instances, whose tokens are
+//! identified by their `sub` claim.
+//!
+//! Tokens of GitHub Actions can also be exchanged through the `githuboidc`
+//! authorization scheme, which checks the repository linked to the package
+//! instead of the trusted publishers.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use tracing::error;
+use tracing::instrument;
+use url::Url;
+
+use crate::api::ApiError;
+use crate::db::Database;
+use crate::external::github::GITHUB_OIDC_ISSUER;
+use crate::util::ApiResult;
+use crate::util::shared_http_client;
+
+pub struct OidcProvider {
+  pub name: &'static str,
+  /// The claims that together identify the CI project, joined with `/`.
+  /// Where the CI system has them, these are ids rather than names, as a
+  /// name can be taken by someone else once its project was renamed.
+  subject_claims: &'static [&'static str],
+  /// Whether the subject is a single numeric id.
+  numeric_subject: bool,
+}
+
+enum IssuerMatch {
+  Exact(&'static str),
+  /// Issuers that include an account specific suffix, like the organization
+  /// id of CircleCI.
+  Prefix(&'static str),
+}
+
+impl IssuerMatch {
+  fn matches(&self, issuer: &str) -> bool {
+    match self {
+      IssuerMatch::Exact(expected) => issuer == *expected,
+      IssuerMatch::Prefix(prefix) => issuer
+        .strip_prefix(prefix)
+        .is_some_and(|rest| !rest.is_empty() && !rest.contains('/')),
+    }
+  }
+}
+
+static PROVIDERS: &[(IssuerMatch, OidcProvider)] = &[
+  (
+    IssuerMatch::Exact(GITHUB_OIDC_ISSUER),
+    OidcProvider {
+      name: "github",
+      subject_claims: &["repository_id"],
+      numeric_subject: true,
+    },
+  ),
+  (
+    IssuerMatch::Exact("https://gitlab.com"),
+    OidcProvider {
+      name: "gitlab",
+      subject_claims: &["project_id"],
+      numeric_subject: true,
+    },
+  ),
+  (
+    IssuerMatch::Exact("https://agent.buildkite.com"),
+    OidcProvider {
+      name: "buildkite",
+      subject_claims: &["organization_slug", "pipeline_slug"],
+      numeric_subject: false,
+    },
+  ),
+  (
+    IssuerMatch::Prefix("https://oidc.circleci.com/org/"),
+    OidcProvider {
+      name: "circleci",
+      subject_claims: &["oidc.circleci.com/project-id"],
+      numeric_subject: false,
+    },
+  ),
+];
+
+/// The provider of tokens from issuers registered by a scope.
+static CUSTOM_PROVIDER: OidcProvider = OidcProvider {
+  name: "custom",
+  subject_claims: &["sub"],
+  numeric_subject: false,
+};
+
+pub fn is_builtin_issuer(issuer: &str) -> bool {
+  PROVIDERS
+    .iter()
+    .any(|(issuer_match, _)| issuer_match.matches(issuer))
+}
+
+pub fn provider_for_issuer(issuer: &str) -> &'static OidcProvider {
+  PROVIDERS
+    .iter()
+    .find(|(issuer_match, _)| issuer_match.matches(issuer))
+    .map(|(_, provider)| provider)
+    .unwrap_or(&CUSTOM_PROVIDER)
+}
+
+impl OidcProvider {
+  /// Checks that a trusted publisher's subject can match the subject of a
+  /// token of this provider.
+  pub fn validate_subject(&self, subject: &str) -> Result<(), String> {
+    if self.numeric_subject && !subject.bytes().all(|b| b.is_ascii_digit()) {
+      return Err(format!(
+        "the subject of {} trusted publishers is the numeric '{}' claim, not \
+         the name of the project",
+        self.name,
+        self.subject_claims.join(", ")
+      ));
+    }
+    Ok(())
+  }
+
+  pub fn subject(
+    &self,
+    claims: &serde_json::Map<String, serde_json::Value>,
+  ) -> Option<String> {
+    let mut parts = Vec::with_capacity(self.subject_claims.len());
+    for claim in self.subject_claims {
+      match claims.get(*claim)? {
+        serde_json::Value::String(value) if !value.is_empty() => {
+          parts.push(value.clone())
+        }
+        serde_json::Value::Number(value) => parts.push(value.to_string()),
+        _ => return None,
+      }
+    }
+    Some(parts.join("/"))
+  }
+}
+
+/// Checks that an issuer registered by a scope can be used to fetch keys from:
+/// an https URL of a public host, which is how it appears in the `iss` claim.
+pub fn validate_custom_issuer(issuer: &str) -> Result<(), ApiError> {
+  let invalid =
+    |msg: &'static str| ApiError::OidcIssuerInvalid { msg: msg.into() };
+  if issuer.len() > 256 {
+    return Err(invalid("must be at most 256 characters long"));
+  }
+  let url = Url::parse(issuer).map_err(|_| invalid("must be a URL"))?;
+  if url.scheme() != "https" {
+    return Err(invalid("must be an https URL"));
+  }
+  let Some(domain) = url.domain() else {
+    return Err(invalid("must have a domain name"));
+  };
+  if domain == "localhost" || !domain.contains('.') {
+    return Err(invalid("must have a public domain name"));
+  }
+  if url.query().is_some()
+    || url.fragment().is_some()
+    || !url.username().is_empty()
+    || url.password().is_some()
+  {
+    return Err(invalid("must not have credentials, a query or a fragment"));
+  }
+  if is_builtin_issuer(issuer) {
+    return Err(invalid("is already trusted by every scope"));
+  }
+  Ok(())
+}
+
+/// The audience that tokens used through the `oidc` authorization scheme must
+/// be issued for, the host of the registry.
+pub fn audience(registry_url: &Url) -> String {
+  registry_url.host_str().unwrap_or_default().to_string()
+}
+
+/// The CI project a verified token was issued to.
+#[derive(Clone, Debug)]
+pub struct OidcPublisher {
+  pub issuer: String,
+  pub subject: String,
+}
+
+#[derive(Deserialize)]
+struct OpenIdConfiguration {
+  jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct JsonWebKeySet {
+  keys: Vec<serde_json::Value>,
+}
+
+pub struct OidcVerifier {
+  /// The signing keys of each issuer.
+  jwks: moka::future::Cache<String, Arc<Vec<jsonwebkey::JsonWebKey>>>,
+}
+
+impl OidcVerifier {
+  pub fn new() -> Self {
+    Self {
+      jwks: moka::future::Cache::builder()
+        .max_capacity(1024)
+        .time_to_live(Duration::from_secs(600))
+        .build(),
+    }
+  }
+
+  async fn keys(
+    &self,
+    issuer: &str,
+  ) -> ApiResult<Arc<Vec<jsonwebkey::JsonWebKey>>> {
+    self
+      .jwks
+      .try_get_with(issuer.to_string(), fetch_jwks(issuer))
+      .await
+      .map_err(|err| {
+        error!("failed to fetch the keys of OIDC issuer {issuer}: {err:#}");
+        ApiError::InvalidOidcToken {
+          msg: format!("failed to fetch the keys of issuer {issuer}").into(),
+        }
+      })
+  }
+
+  /// Verifies the signature, issuer, expiry and, if given, the audience of a
+  /// token, and returns its claims.
+  #[instrument(name = "OidcVerifier::verify", skip(self, token), err)]
+  pub async fn verify<T: DeserializeOwned>(
+    &self,
+    token: &str,
+    issuer: &str,
+    audience: Option<&str>,
+  ) -> ApiResult<T> {
+    let header = jsonwebtoken::decode_header(token).map_err(|err| {
+      ApiError::InvalidOidcToken {
+        msg: err.to_string().into(),
+      }
+    })?;
+    let kid = header.kid.ok_or(ApiError::InvalidOidcToken {
+      msg: "missing kid".into(),
+    })?;
+
+    let has_kid =
+      |k: &&jsonwebkey::JsonWebKey| k.key_id.as_deref() == Some(&*kid);
+    let mut keys = self.keys(issuer).await?;
+    if !keys.iter().any(|k| has_kid(&k)) {
+      // The issuer may have rotated its keys since they were cached.
+      self.jwks.invalidate(issuer).await;
+      keys = self.keys(issuer).await?;
+    }
+    let jwk =
+      keys
+        .iter()
+        .find(has_kid)
+        .ok_or_else(|| ApiError::InvalidOidcToken {
+          msg: format!("invalid kid: {kid}").into(),
+        })?;
+
+    // Only public keys can prove that the issuer signed the token.
+    if matches!(*jwk.key, jsonwebkey::Key::Symmetric { .. })
+      || matches!(
+        header.alg,
+        jsonwebtoken::Algorithm::HS256
+          | jsonwebtoken::Algorithm::HS384
+          | jsonwebtoken::Algorithm::HS512
+      )
+    {
+      return Err(ApiError::InvalidOidcToken {
+        msg: "unsupported algorithm".into(),
+      });
+    }
+    let alg = jwk.algorithm.map(Into::into).unwrap_or(header.alg);
+
+    let mut validation = jsonwebtoken::Validation::new(alg);
+    validation.set_issuer(&[issuer]);
+    if let Some(audience) = audience {
+      validation.set_audience(&[audience]);
+    }
+    let decoded =
+      jsonwebtoken::decode::<T>(token, &jwk.key.to_decoding_key(), &validation)
+        .map_err(|err| ApiError::InvalidOidcToken {
+          msg: err.to_string().into(),
+        })?;
+
+    Ok(decoded.claims)
+  }
+
+  /// Verifies a token used through the `oidc` authorization scheme. Only
+  /// built-in issuers and issuers registered by any scope are considered,
+  /// whether the subject may publish a package is checked later.
+  #[instrument(
+    name = "OidcVerifier::verify_publisher",
+    skip(self, db, token),
+    err
+  )]
+  pub async fn verify_publisher(
+    &self,
+    db: &Database,
+    audience: &str,
+    token: &str,
+  ) -> ApiResult<OidcPublisher> {
+    let issuer = unverified_issuer(token)?;
+    if !is_builtin_issuer(&issuer)
+      && !db.is_oidc_issuer_registered(&issuer).await?
+    {
+      return Err(ApiError::InvalidOidcToken {
+        msg: format!("untrusted issuer: {issuer}").into(),
+      });
+    }
+
+    let claims: serde_json::Map<String, serde_json::Value> =
+      self.verify(token, &issuer, Some(audience)).await?;
+    let provider = provider_for_issuer(&issuer);
+    let subject =
+      provider
+        .subject(&claims)
+        .ok_or_else(|| ApiError::InvalidOidcToken {
+          msg: format!(
+            "missing claims: {}",
+            provider.subject_claims.join(", ")
+          )
+          .into(),
+        })?;
+
+    Ok(OidcPublisher { issuer, subject })
+  }
+}
+
+/// The issuer a token claims to be from, used to pick the keys to verify it
+/// with. Must not be trusted before the token is verified.
+fn unverified_issuer(token: &str) -> ApiResult<String> {
+  #[derive(Deserialize)]
+  struct Claims {
+    iss: String,
+  }
+
+  let mut validation = jsonwebtoken::Validation::default();
+  validation.insecure_disable_signature_validation();
+  validation.validate_exp = false;
+  validation.required_spec_claims.clear();
+  let decoded = jsonwebtoken::decode::<Claims>(
+    token,
+    &jsonwebtoken::DecodingKey::from_secret(&[]),
+    &validation,
+  )
+  .map_err(|err| ApiError::InvalidOidcToken {
+    msg: err.to_string().into(),
+  })?;
+  Ok(decoded.claims.iss)
+}
+
+async fn fetch_jwks(
+  issuer: &str,
+) -> Result<Arc<Vec<jsonwebkey::JsonWebKey>>, anyhow::Error> {
+  let url = format!(
+    "{}/.well-known/openid-configuration",
+    issuer.trim_end_matches('/')
+  );
+  let configuration: OpenIdConfiguration = shared_http_client()
+    .get(url)
+    .header("Accept", "application/json")
+    .send()
+    .await?
+    .error_for_status()?
+    .json()
+    .await
+    .context("failed to parse openid configuration")?;
+  if !configuration.jwks_uri.starts_with("https://") {
+    anyhow::bail!("jwks_uri is not an https URL");
+  }
+
+  let JsonWebKeySet { keys } = shared_http_client()
+    .get(configuration.jwks_uri)
+    .header("Accept", "application/json")
+    .send()
+    .await?
+    .error_for_status()?
+    .json()
+    .await
+    .context("failed to parse jwks")?;
+  // Skip the keys of types or algorithms that can't be used, instead of
+  // rejecting the whole set.
+  let keys = keys
+    .into_iter()
+    .filter_map(|key| serde_json::from_value(key).ok())
+    .collect();
+
+  Ok(Arc::new(keys))
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+
+  #[test]
+  fn providers_by_issuer() {
+    assert_eq!(provider_for_issuer(GITHUB_OIDC_ISSUER).name, "github");
+    assert_eq!(provider_for_issuer("https://gitlab.com").name, "gitlab");
+    assert_eq!(
+      provider_for_issuer("https://agent.buildkite.com").name,
+      "buildkite"
+    );
+    assert_eq!(
+      provider_for_issuer("https://oidc.circleci.com/org/1234-abcd").name,
+      "circleci"
+    );
+    assert_eq!(
+      provider_for_issuer("https://oidc.circleci.com/org/").name,
+      "custom"
+    );
+    assert_eq!(
+      provider_for_issuer("https://oidc.circleci.com/org/1/2").name,
+      "custom"
+    );
+    assert_eq!(
+      provider_for_issuer("https://gitlab.example.com").name,
+      "custom"
+    );
+    assert!(is_builtin_issuer("https://gitlab.com"));
+    assert!(!is_builtin_issuer("https://gitlab.com/"));
+  }
+
+  #[test]
+  fn subject_from_claims() {
+    let claims = |value: serde_json::Value| value.as_object().unwrap().clone();
+
+    let buildkite = provider_for_issuer("https://agent.buildkite.com");
+    assert_eq!(
+      buildkite.subject(&claims(json!({
+        "organization_slug": "acme",
+        "pipeline_slug": "release",
+      }))),
+      Some("acme/release".to_string())
+    );
+    assert_eq!(
+      buildkite.subject(&claims(json!({ "organization_slug": "acme" }))),
+      None
+    );
+
+    let github = provider_for_issuer(GITHUB_OIDC_ISSUER);
+    assert_eq!(
+      github.subject(&claims(json!({
+        "repository": "acme/app",
+        "repository_id": "123456",
+      }))),
+      Some("123456".to_string())
+    );
+    assert_eq!(
+      github.subject(&claims(json!({ "repository": "acme/app" }))),
+      None
+    );
+    assert!(github.validate_subject("123456").is_ok());
+    assert!(github.validate_subject("acme/app").is_err());
+
+    let gitlab = provider_for_issuer("https://gitlab.com");
+    assert_eq!(
+      gitlab.subject(&claims(json!({
+        "project_path": "group/project",
+        "project_id": "42",
+      }))),
+      Some("42".to_string())
+    );
+    assert_eq!(gitlab.subject(&claims(json!({ "project_id": "" }))), None);
+    assert!(gitlab.validate_subject("group/project").is_err());
+
+    let custom = provider_for_issuer("https://ci.example.com");
+    assert_eq!(
+      custom.subject(&claims(json!({ "sub": "repo:acme/app" }))),
+      Some("repo:acme/app".to_string())
+    );
+  }
+
+  #[test]
+  fn custom_issuers() {
+    assert!(validate_custom_issuer("https://gitlab.example.com").is_ok());
+    assert!(validate_custom_issuer("https://ci.example.com/oidc").is_ok());
+    assert!(validate_custom_issuer("http://gitlab.example.com").is_err());
+    assert!(validate_custom_issuer("https://localhost").is_err());
+    assert!(validate_custom_issuer("https://127.0.0.1").is_err());
+    assert!(validate_custom_issuer("https://intranet").is_err());
+    assert!(validate_custom_issuer("https://ci.example.com/?a=b").is_err());
+    assert!(validate_custom_issuer("https://gitlab.com").is_err());
+    assert!(validate_custom_issuer("gitlab.example.com").is_err());
+  }
+}
@@ -0,0 +1,300 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+//! Finding objects in the buckets that nothing in the database refers to.
+//!
+//! A publish that fails part way, or a version that is deleted, can leave
+//! behind module files, docs, npm tarballs and attestations of a version that
+//! does not exist, and the tarball of a publishing task can outlive the task.
+//! [gc_orphaned_objects] lists the objects of every package and deletes those
+//! of versions that neither exist nor are being published. In a dry run, the
+//! orphaned objects are only recorded, so admins can review them first.
+//!
+//! Module blobs are collected by the `gc_module_blobs` task, the chunks of
+//! publish uploads by the `clean_publish_uploads` task, and the archives of
+//! data exports by the `clean_data_exports` task.
+
+use std::collections::HashSet;
+
+use futures::StreamExt;
+use thiserror::Error;
+use tracing::error;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::db::Database;
+use crate::db::OrphanGcJob;
+use crate::db::OrphanGcJobStatus;
+use crate::ids::PackageName;
+use crate::ids::ScopeName;
+use crate::ids::Version;
+use crate::npm::NPM_TARBALL_REVISION;
+use crate::npm::NpmMappedJsrPackageName;
+use crate::s3::BucketWithQueue;
+use crate::s3::Buckets;
+use crate::s3::S3Error;
+
+const PACKAGE_BATCH_SIZE: i64 = 100;
+const PACKAGE_CONCURRENCY: usize = 8;
+const PUBLISHING_TASK_BATCH_SIZE: usize = 1000;
+
+const PUBLISHING_TASKS_PREFIX: &str = "publishing_tasks/";
+
+/// Runs an orphan GC job to completion and records whether it succeeded.
+pub async fn gc_orphaned_objects(
+  db: Database,
+  buckets: Buckets,
+  job: OrphanGcJob,
+) {
+  let (status, error) =
+    match gc_orphaned_objects_inner(&db, &buckets, &job).await {
+      Ok(()) => (OrphanGcJobStatus::Completed, None),
+      Err(err) => {
+        error!("orphan GC job {} failed: {err}", job.id);
+        (OrphanGcJobStatus::Failed, Some(err.to_string()))
+      }
+    };
+
+  if let Err(err) = db
+    .finish_orphan_gc_job(job.id, status, error.as_deref())
+    .await
+  {
+    error!("failed to finish orphan GC job {}: {err}", job.id);
+  }
+}
+
+#[derive(Debug, Error)]
+enum OrphanGcError {
+  #[error(transparent)]
+  Database(#[from] sqlx::Error),
+  #[error(transparent)]
+  Storage(#[from] S3Error),
+}
+
+async fn gc_orphaned_objects_inner(
+  db: &Database,
+  buckets: &Buckets,
+  job: &OrphanGcJob,
+) -> Result<(), OrphanGcError> {
+  let mut last = None;
+  loop {
+    let packages = db
+      .list_packages_after(
+        last.as_ref().map(|(scope, name)| (scope, name)),
+        PACKAGE_BATCH_SIZE,
+      )
+      .await?;
+    let Some(last_in_batch) = packages.last().cloned() else {
+      break;
+    };
+
+    let mut scanned_objects = 0;
+    let mut orphans = vec![];
+    let mut results = futures::stream::iter(&packages)
+      .map(|(scope, name)| find_package_orphans(db, buckets, scope, name))
+      .buffer_unordered(PACKAGE_CONCURRENCY);
+    while let Some(res) = results.next().await {
+      let (scanned, package_orphans) = res?;
+      scanned_objects += scanned;
+      orphans.extend(package_orphans);
+    }
+    drop(results);
+
+    let objects = delete_orphans(buckets, job.dry_run, orphans).await;
+    db.record_orphan_gc_job_progress(
+      job.id,
+      packages.len() as i32,
+      scanned_objects as i32,
+      &objects,
+    )
+    .await?;
+
+    last = Some(last_in_batch);
+  }
+
+  let tarballs = buckets
+    .publishing_bucket
+    .list(PUBLISHING_TASKS_PREFIX.into())
+    .await?;
+  for batch in tarballs.chunks(PUBLISHING_TASK_BATCH_SIZE) {
+    let tasks = batch
+      .iter()
+      .filter_map(|key| Some((publishing_task_id(key)?, key)))
+      .collect::<Vec<_>>();
+    let ids = tasks.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+    let existing = db
+      .list_existing_publishing_task_ids(&ids)
+      .await?
+      .into_iter()
+      .collect::<HashSet<_>>();
+    let orphans = tasks
+      .into_iter()
+      .filter(|(id, _)| !existing.contains(id))
+      .map(|(_, key)| (OrphanBucket::Publishing, key.clone()))
+      .collect();
+
+    let objects = delete_orphans(buckets, job.dry_run, orphans).await;
+    db.record_orphan_gc_job_progress(job.id, 0, batch.len() as i32, &objects)
+      .await?;
+  }
+
+  info!("orphan GC job {} completed", job.id);
+  Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OrphanBucket {
+  Publishing,
+  Modules,
+  Docs,
+  Npm,
+}
+
+impl OrphanBucket {
+  fn name(self) -> &'static str {
+    match self {
+      OrphanBucket::Publishing => "publishing",
+      OrphanBucket::Modules => "modules",
+      OrphanBucket::Docs => "docs",
+      OrphanBucket::Npm => "npm",
+    }
+  }
+
+  fn get(self, buckets: &Buckets) -> &BucketWithQueue {
+    match self {
+      OrphanBucket::Publishing => &buckets.publishing_bucket,
+      OrphanBucket::Modules => &buckets.modules_bucket,
+      OrphanBucket::Docs => &buckets.docs_bucket,
+      OrphanBucket::Npm => &buckets.npm_bucket,
+    }
+  }
+}
+
+/// Lists the objects of a package and returns how many there are, and those
+/// that belong to a version that is neither published nor being published.
+async fn find_package_orphans(
+  db: &Database,
+  buckets: &Buckets,
+  scope: &ScopeName,
+  name: &PackageName,
+) -> Result<(usize, Vec<(OrphanBucket, String)>), OrphanGcError> {
+  let npm_name = NpmMappedJsrPackageName {
+    scope,
+    package: name,
+  };
+  let prefixes = [
+    (OrphanBucket::Modules, format!("@{scope}/{name}/")),
+    (OrphanBucket::Docs, format!("@{scope}/{name}/")),
+    (
+      OrphanBucket::Npm,
+      format!("~/{NPM_TARBALL_REVISION}/{npm_name}/"),
+    ),
+    (
+      OrphanBucket::Npm,
+      format!("-/npm/v1/attestations/{npm_name}@"),
+    ),
+  ];
+
+  // The objects are listed before the versions are looked up. A publish
+  // creates its publishing task before it uploads anything, so the version of
+  // every listed object is live at that point unless its publish failed.
+  let mut objects = vec![];
+  for (bucket, prefix) in &prefixes {
+    let keys = bucket.get(buckets).list(prefix.as_str().into()).await?;
+    objects.extend(keys.into_iter().map(|key| (*bucket, prefix.len(), key)));
+  }
+
+  let live_versions = db
+    .list_live_package_versions(scope, name)
+    .await?
+    .into_iter()
+    .collect::<HashSet<_>>();
+
+  let scanned = objects.len();
+  let orphans = objects
+    .into_iter()
+    .filter(|(_, prefix_len, key)| {
+      object_version(&key[*prefix_len..])
+        .is_some_and(|version| !live_versions.contains(&version))
+    })
+    .map(|(bucket, _, key)| (bucket, key))
+    .collect();
+  Ok((scanned, orphans))
+}
+
+/// The version that an object belongs to, given its path below the package.
+/// Objects of the package as a whole, like `meta.json`, have none.
+fn object_version(path: &str) -> Option<Version> {
+  let end = path.find(['/', '_']).unwrap_or(path.len());
+  let segment = &path[..end];
+  let segment = segment.strip_suffix(".tgz").unwrap_or(segment);
+  Version::new(segment).ok()
+}
+
+fn publishing_task_id(key: &str) -> Option<Uuid> {
+  let id = key
+    .strip_prefix(PUBLISHING_TASKS_PREFIX)?
+    .strip_suffix(".tar.gz")?;
+  Uuid::parse_str(id).ok()
+}
+
+/// Deletes the given objects, unless this is a dry run, and returns them in
+/// the form they are recorded in.
+async fn delete_orphans(
+  buckets: &Buckets,
+  dry_run: bool,
+  orphans: Vec<(OrphanBucket, String)>,
+) -> Vec<(String, String, bool)> {
+  futures::stream::iter(orphans)
+    .map(|(bucket, key)| async move {
+      let deleted = if dry_run {
+        false
+      } else {
+        match bucket.get(buckets).delete_file(key.as_str().into()).await {
+          Ok(_) => true,
+          Err(err) => {
+            error!(
+              "failed to delete orphaned object {key} in the {} bucket: {err}",
+              bucket.name()
+            );
+            false
+          }
+        }
+      };
+      (bucket.name().to_string(), key, deleted)
+    })
+    .buffer_unordered(32)
+    .collect()
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+  use super::object_version;
+  use super::publishing_task_id;
+  use crate::ids::Version;
+
+  #[test]
+  fn object_versions() {
+    let v = |s| Some(Version::new(s).unwrap());
+    assert_eq!(object_version("1.2.3/mod.ts"), v("1.2.3"));
+    assert_eq!(object_version("1.2.3_meta.json"), v("1.2.3"));
+    assert_eq!(object_version("1.2.3_meta.json.br"), v("1.2.3"));
+    assert_eq!(object_version("1.0.0-beta.1/raw.rmp.gz"), v("1.0.0-beta.1"));
+    assert_eq!(object_version("1.0.0-rc.2.tgz"), v("1.0.0-rc.2"));
+    assert_eq!(object_version("2.0.0"), v("2.0.0"));
+    assert_eq!(object_version("meta.json"), None);
+    assert_eq!(object_version("meta.json.zst"), None);
+    assert_eq!(object_version("v1.0.0/mod.ts"), None);
+  }
+
+  #[test]
+  fn publishing_task_ids() {
+    let id = uuid::Uuid::new_v4();
+    assert_eq!(
+      publishing_task_id(&format!("publishing_tasks/{id}.tar.gz")),
+      Some(id)
+    );
+    assert_eq!(publishing_task_id("publishing_tasks/foo.tar.gz"), None);
+    assert_eq!(publishing_task_id(&format!("publishing_tasks/{id}")), None);
+  }
+}
@@ -5,10 +5,12 @@ use base64::prelude::BASE64_STANDARD;
 use base64::prelude::BASE64_URL_SAFE;
 use serde::Deserialize;
 use serde::Serialize;
+use serde_json::Value;
 use x509_parser::parse_x509_certificate;
 use x509_parser::pem::parse_x509_pem;
 use x509_parser::prelude::GeneralName;
 use x509_parser::public_key::PublicKey;
+use x509_parser::time::ASN1Time;
 
 /// The OIDC issuer that GitHub Actions uses when requesting a Fulcio signing
 /// certificate. JSR provenance is only ever produced by GitHub Actions, so the
@@ -65,10 +67,37 @@ pub struct VerificationMaterialContent {
   pub x509_certificate_chain: X509CertificateChain,
 }
 
+/// A Rekor transparency log entry. Only the log index is required; the rest is
+/// kept as sent so that the stored bundle stays complete.
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TlogEntry {
   pub log_index: u64,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub log_id: Option<Value>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub kind_version: Option<Value>,
+  /// Unix timestamp of when the entry was added to the log. Sigstore clients
+  /// send it either as a number or, following the protobuf JSON mapping of
+  /// int64, as a string.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub integrated_time: Option<Value>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub inclusion_promise: Option<Value>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub inclusion_proof: Option<Value>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub canonicalized_body: Option<String>,
+}
+
+impl TlogEntry {
+  pub fn integrated_time(&self) -> Option<i64> {
+    match self.integrated_time.as_ref()? {
+      Value::Number(time) => time.as_i64(),
+      Value::String(time) => time.parse().ok(),
+      _ => None,
+    }
+  }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -76,6 +105,8 @@ pub struct TlogEntry {
 pub struct VerificationMaterial {
   pub content: VerificationMaterialContent,
   pub tlog_entries: [TlogEntry; 1],
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub timestamp_verification_data: Option<Value>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -86,6 +117,12 @@ pub struct ProvenanceBundle {
   pub verification_material: VerificationMaterial,
 }
 
+impl ProvenanceBundle {
+  pub fn tlog_entry(&self) -> &TlogEntry {
+    &self.verification_material.tlog_entries[0]
+  }
+}
+
 // Fulcio root cert
 const FULCIO_CERT: &[u8] = b"-----BEGIN CERTIFICATE-----
 MIICGjCCAaGgAwIBAgIUALnViVfnU0brJasmRkHrn/UnfaQwCgYIKoZIzj0EAwMw
@@ -146,7 +183,7 @@ fn decode_base64(value: &str) -> Result<Vec<u8>> {
 /// `"DSSEv1" SP LEN(type) SP type SP LEN(payload) SP payload`, where `LEN` is
 /// the ASCII-decimal byte length and `SP` is a single space. This is the exact
 /// byte string that the signature is computed over.
-fn dsse_pae(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+pub(crate) fn dsse_pae(payload_type: &str, payload: &[u8]) -> Vec<u8> {
   let mut pae = Vec::new();
   pae.extend_from_slice(b"DSSEv1 ");
   pae.extend_from_slice(payload_type.len().to_string().as_bytes());
@@ -182,12 +219,14 @@ struct RepoIdentity {
 }
 
 /// Validate that `cert` is a GitHub Actions Fulcio signing certificate: it must
-/// be within its validity window, carry the GitHub Actions OIDC issuer, and
-/// have a `github.com/<owner>/<repo>/...` SAN. Returns the repository identity.
+/// have been within its validity window at `signed_at`, carry the GitHub Actions
+/// OIDC issuer, and have a `github.com/<owner>/<repo>/...` SAN. Returns the
+/// repository identity.
 fn verify_certificate_identity(
   cert: &x509_parser::certificate::X509Certificate,
+  signed_at: ASN1Time,
 ) -> Result<RepoIdentity> {
-  if !cert.validity().is_valid() {
+  if !cert.validity().is_valid_at(signed_at) {
     bail!("provenance certificate is expired or not yet valid");
   }
 
@@ -243,7 +282,16 @@ fn digest_matches(expected_tarball_hash: &str, subject_sha256: &str) -> bool {
   !subject_sha256.is_empty() && subject_sha256.eq_ignore_ascii_case(expected)
 }
 
-/// Verify a provenance bundle and return the Rekor transparency-log index.
+/// A provenance bundle that passed verification.
+#[derive(Debug)]
+pub struct VerifiedProvenance {
+  /// The Rekor transparency log index of the attestation.
+  pub log_index: u64,
+  /// The GitHub repository, as `owner/name`, the attestation was built in.
+  pub repository: String,
+}
+
+/// Verify a provenance bundle.
 ///
 /// `subject_name` is the package coordinate (`pkg:jsr/@scope/name@version`) the
 /// attestation must be for. `expected_tarball_hash` is the `sha256-<hex>` digest
@@ -256,6 +304,9 @@ fn digest_matches(expected_tarball_hash: &str, subject_sha256: &str) -> bool {
 ///  2. The leaf certificate is a valid GitHub Actions identity (within validity
 ///     window, GitHub Actions OIDC issuer, `github.com/<owner>/<repo>` SAN) and,
 ///     if the package is linked to a repository, that the repository matches.
+///     Fulcio certificates are short lived, so the validity window is checked
+///     at the time the transparency log integrated the entry, which allows a
+///     stored bundle to be verified again later.
 ///  3. The DSSE envelope signature is valid for the leaf certificate's key. This
 ///     is what binds the (otherwise attacker-supplied) payload to the
 ///     certificate: without the certificate's private key the signature cannot
@@ -274,8 +325,8 @@ pub fn verify(
   subject_name: String,
   expected_repo: Option<(String, String)>,
   expected_tarball_hash: &str,
-  bundle: ProvenanceBundle,
-) -> Result<String> {
+  bundle: &ProvenanceBundle,
+) -> Result<VerifiedProvenance> {
   let key = &bundle
     .verification_material
     .content
@@ -293,7 +344,11 @@ pub fn verify(
 
   // 2. The signing certificate must be a GitHub Actions identity, optionally
   //    matching the repository linked to the package.
-  let repo = verify_certificate_identity(&x509)?;
+  let signed_at = match bundle.tlog_entry().integrated_time() {
+    Some(time) => ASN1Time::from_timestamp(time)?,
+    None => ASN1Time::now(),
+  };
+  let repo = verify_certificate_identity(&x509, signed_at)?;
   if let Some((owner, name)) = expected_repo
     && (!owner.eq_ignore_ascii_case(&repo.owner)
       || !name.eq_ignore_ascii_case(&repo.name))
@@ -341,12 +396,15 @@ pub fn verify(
     bail!("Invalid subject digest");
   }
 
-  let tls = &bundle.verification_material.tlog_entries[0];
-  Ok(tls.log_index.to_string())
+  Ok(VerifiedProvenance {
+    log_index: bundle.tlog_entry().log_index,
+    repository: format!("{}/{}", repo.owner, repo.name),
+  })
 }
 
 #[cfg(test)]
 mod tests {
+  use super::TlogEntry;
   use super::decode_base64;
   use super::digest_matches;
   use super::dsse_pae;
@@ -420,4 +478,27 @@ mod tests {
     // subject digest does not match a bare-hex recorded one.
     assert!(!digest_matches(hex, &recorded));
   }
+
+  #[test]
+  fn tlog_entry_integrated_time() {
+    let entry: TlogEntry =
+      serde_json::from_str(r#"{"logIndex":1,"integratedTime":"1708689214"}"#)
+        .unwrap();
+    assert_eq!(entry.integrated_time(), Some(1708689214));
+    let entry: TlogEntry =
+      serde_json::from_str(r#"{"logIndex":1,"integratedTime":1708689214}"#)
+        .unwrap();
+    assert_eq!(entry.integrated_time(), Some(1708689214));
+    let entry: TlogEntry = serde_json::from_str(r#"{"logIndex":1}"#).unwrap();
+    assert_eq!(entry.integrated_time(), None);
+    // Fields that aren't needed for verification are kept as sent.
+    let entry: TlogEntry = serde_json::from_str(
+      r#"{"logIndex":1,"logId":{"keyId":"abc"},"canonicalizedBody":"e30="}"#,
+    )
+    .unwrap();
+    assert_eq!(
+      serde_json::to_string(&entry).unwrap(),
+      r#"{"logIndex":1,"logId":{"keyId":"abc"},"canonicalizedBody":"e30="}"#
+    );
+  }
 }
@@ -4,38 +4,59 @@ use std::collections::HashSet;
 
 use crate::NpmUrl;
 use crate::RegistryUrl;
+use crate::analysis::WorkspacePackage;
+use crate::analysis::analyze_workspace;
 use crate::api::ApiError;
+use crate::api::ApiPublishFilesDiff;
+use crate::api::ApiPublishPreviewDiff;
+use crate::cache_purge::CachePurge;
 use crate::db::Database;
 use crate::db::DependencyKind;
-use crate::db::ExportsMap;
 use crate::db::NewNpmTarball;
 use crate::db::NewPackageFile;
 use crate::db::NewPackageVersion;
 use crate::db::NewPackageVersionDependency;
-use crate::db::PackageVersionMeta;
+use crate::db::NewPackageVersionDeprecation;
+use crate::db::NewPackageVersionDocArtifact;
+use crate::db::NewPackageVersionDocLink;
+use crate::db::NewPackageVersionSymbol;
+use crate::db::NewPublishedVersion;
+use crate::db::PackageVisibility;
 use crate::db::PublishingTask;
 use crate::db::PublishingTaskError;
 use crate::db::PublishingTaskStatus;
+use crate::emails::PublishFailureEmailJobPayload;
+use crate::events;
+use crate::events::DomainEvent;
 use crate::external::algolia::AlgoliaClient;
-use crate::external::cloudflare::CachePurge;
-use crate::ids::PackagePath;
+use crate::feature_flags::FeatureFlags;
+use crate::feature_flags::UNSTABLE_BYTES_IMPORTS;
+use crate::ids::PackageName;
+use crate::ids::ScopeName;
+use crate::malware_scan::MalwareScanners;
 use crate::metadata::ManifestEntry;
 use crate::metadata::PackageMetadata;
 use crate::metadata::VersionMetadata;
 use crate::npm::NPM_TARBALL_REVISION;
-use crate::npm::generate_npm_version_manifest;
+use crate::npm::NpmSigner;
+use crate::npm::upload_npm_version_manifest;
+use crate::quarantine;
 use crate::s3::Buckets;
 use crate::s3::CACHE_CONTROL_IMMUTABLE;
 use crate::s3::CACHE_CONTROL_MANIFEST;
+use crate::s3::ContentEncoding;
 use crate::s3::S3UploadOptions;
 use crate::s3::UploadTaskBody;
-use crate::tarball::NpmTarballInfo;
+use crate::tarball::FileInfo;
 use crate::tarball::ProcessTarballOutput;
+use crate::tarball::download_and_analyze_tarball;
 use crate::tarball::process_tarball;
+use crate::tarball::store_analyzed_tarball;
 use crate::util::ApiResult;
 use crate::util::LicenseStore;
 use crate::util::decode_json;
-use deno_semver::package::PackageReqReference;
+use deno_semver::StackString;
+use deno_semver::package::PackageNv;
 use hyper::Body;
 use hyper::Request;
 use indexmap::IndexMap;
@@ -57,20 +78,26 @@ pub async fn publish_handler(mut req: Request<Body>) -> ApiResult<()> {
   let db = req.data::<Database>().unwrap().clone();
   let buckets = req.data::<Buckets>().unwrap().clone();
   let license_store = req.data::<LicenseStore>().unwrap().clone();
+  let malware_scanners = req.data::<MalwareScanners>().unwrap().clone();
   let algolia_client = req.data::<Option<AlgoliaClient>>().unwrap().clone();
   let registry_url = req.data::<RegistryUrl>().unwrap().0.clone();
   let npm_url = req.data::<NpmUrl>().unwrap().0.clone();
+  let npm_signer = req.data::<NpmSigner>().unwrap().clone();
   let cache_purge = req.data::<CachePurge>().unwrap().clone();
+  let feature_flags = req.data::<FeatureFlags>().unwrap().clone();
 
   publish_task(
     publishing_task_id,
     buckets,
     license_store,
+    malware_scanners,
     registry_url,
     npm_url,
+    npm_signer,
     db,
     algolia_client,
     cache_purge,
+    feature_flags,
   )
   .await?;
 
@@ -80,23 +107,116 @@ pub async fn publish_handler(mut req: Request<Body>) -> ApiResult<()> {
 #[allow(clippy::too_many_arguments)]
 #[instrument(
   name = "publish_task",
-  skip(buckets, db, license_store, registry_url, algolia_client, cache_purge),
+  skip(
+    buckets,
+    db,
+    license_store,
+    malware_scanners,
+    registry_url,
+    npm_signer,
+    algolia_client,
+    cache_purge,
+    feature_flags
+  ),
   err
 )]
 pub async fn publish_task(
   publish_id: Uuid,
   buckets: Buckets,
   license_store: LicenseStore,
+  malware_scanners: MalwareScanners,
   registry_url: Url,
   npm_url: Url,
+  npm_signer: NpmSigner,
   db: Database,
   algolia_client: Option<AlgoliaClient>,
   cache_purge: CachePurge,
+  feature_flags: FeatureFlags,
 ) -> Result<(), ApiError> {
-  let (mut publishing_task, _) = db
+  let (publishing_task, _) = db
     .get_publishing_task(publish_id)
     .await?
     .ok_or(ApiError::PublishNotFound)?;
+
+  // The packages of a workspace publish are processed together, when any of
+  // their tasks is dispatched.
+  if publishing_task.status == PublishingTaskStatus::Pending
+    && let Some(workspace_publish_id) = db
+      .get_publishing_task_workspace_publish_id(publishing_task.id)
+      .await?
+  {
+    let Some(mut publishing_tasks) =
+      db.start_workspace_publish(workspace_publish_id).await?
+    else {
+      error!("workspace publish already processing");
+      return Err(ApiError::InternalServerError);
+    };
+    let res = process_workspace_publish(
+      &db,
+      &buckets,
+      &license_store,
+      &malware_scanners,
+      &feature_flags,
+      registry_url.clone(),
+      &mut publishing_tasks,
+    )
+    .await;
+    if let Err(err) = res {
+      // retryable errors
+      db.reset_workspace_publish(workspace_publish_id).await?;
+      return Err(err.into());
+    }
+    for publishing_task in publishing_tasks {
+      run_publishing_task(
+        &db,
+        &buckets,
+        &license_store,
+        &malware_scanners,
+        &registry_url,
+        &npm_url,
+        &npm_signer,
+        &algolia_client,
+        &cache_purge,
+        &feature_flags,
+        publishing_task,
+      )
+      .await?;
+    }
+    return Ok(());
+  }
+
+  run_publishing_task(
+    &db,
+    &buckets,
+    &license_store,
+    &malware_scanners,
+    &registry_url,
+    &npm_url,
+    &npm_signer,
+    &algolia_client,
+    &cache_purge,
+    &feature_flags,
+    publishing_task,
+  )
+  .await
+}
+
+/// Drives a publishing task through its states until it has succeeded or
+/// failed.
+#[allow(clippy::too_many_arguments)]
+async fn run_publishing_task(
+  db: &Database,
+  buckets: &Buckets,
+  license_store: &LicenseStore,
+  malware_scanners: &MalwareScanners,
+  registry_url: &Url,
+  npm_url: &Url,
+  npm_signer: &NpmSigner,
+  algolia_client: &Option<AlgoliaClient>,
+  cache_purge: &CachePurge,
+  feature_flags: &FeatureFlags,
+  mut publishing_task: PublishingTask,
+) -> Result<(), ApiError> {
   loop {
     // If the task is pending, we can start processing it. If the task is
     // already processing, don't do anything. If the task is already
@@ -106,10 +226,12 @@ pub async fn publish_task(
     match publishing_task.status {
       PublishingTaskStatus::Pending => {
         let res = process_publishing_task(
-          &db,
-          &buckets,
-          &license_store,
-          &algolia_client,
+          db,
+          buckets,
+          license_store,
+          malware_scanners,
+          algolia_client,
+          feature_flags,
           registry_url.clone(),
           &mut publishing_task,
         )
@@ -132,22 +254,45 @@ pub async fn publish_task(
         return Err(ApiError::InternalServerError);
       }
       PublishingTaskStatus::Processed => {
+        let quarantine = db
+          .get_package_version_quarantine(
+            &publishing_task.package_scope,
+            &publishing_task.package_name,
+            &publishing_task.package_version,
+          )
+          .await?;
         upload_package_manifest(
-          &db,
-          &buckets,
-          &registry_url,
-          &cache_purge,
-          &publishing_task,
+          db,
+          buckets,
+          registry_url,
+          cache_purge,
+          &publishing_task.package_scope,
+          &publishing_task.package_name,
         )
         .await?;
         upload_npm_version_manifest(
-          &db,
-          &buckets,
-          &npm_url,
-          &cache_purge,
-          &publishing_task,
+          db,
+          buckets,
+          npm_url,
+          npm_signer,
+          cache_purge,
+          &publishing_task.package_scope,
+          &publishing_task.package_name,
         )
         .await?;
+        // A version that the malware scan flagged was quarantined when it was
+        // created, after its artifacts were uploaded, so they are moved out
+        // of the public buckets here.
+        if quarantine.is_some() {
+          quarantine::quarantine_artifacts(
+            db,
+            buckets,
+            &publishing_task.package_scope,
+            &publishing_task.package_name,
+            &publishing_task.package_version,
+          )
+          .await?;
+        }
         publishing_task = db
           .update_publishing_task_status(
             None,
@@ -157,6 +302,22 @@ pub async fn publish_task(
             None,
           )
           .await?;
+        match quarantine {
+          Some(quarantine) => {
+            events::emit(
+              db,
+              DomainEvent::VersionQuarantined {
+                scope: quarantine.scope,
+                package: quarantine.name,
+                version: quarantine.version,
+                report_id: quarantine.report_id,
+                user_id: None,
+              },
+            )
+            .await;
+          }
+          None => dispatch_publish_webhooks(db, &publishing_task).await,
+        }
       }
       PublishingTaskStatus::Failure => return Ok(()),
       PublishingTaskStatus::Success => {
@@ -182,6 +343,102 @@ pub async fn publish_task(
   }
 }
 
+/// Notifies the webhooks of the scope of the new version, and the webhooks of
+/// the scopes of its JSR dependencies that were not depended on by an earlier
+/// version of the package, unless the package is private.
+async fn dispatch_publish_webhooks(
+  db: &Database,
+  publishing_task: &PublishingTask,
+) {
+  let package = format!(
+    "@{}/{}",
+    publishing_task.package_scope, publishing_task.package_name
+  );
+  events::emit(
+    db,
+    DomainEvent::VersionPublished {
+      scope: publishing_task.package_scope.clone(),
+      package: publishing_task.package_name.clone(),
+      version: publishing_task.package_version.clone(),
+      user_id: publishing_task.user_id,
+    },
+  )
+  .await;
+
+  // Other scopes must not learn about private packages, or their versions.
+  match db
+    .get_package_visibility(
+      &publishing_task.package_scope,
+      &publishing_task.package_name,
+    )
+    .await
+  {
+    Ok(Some(PackageVisibility::Public)) => {}
+    Ok(_) => return,
+    Err(err) => {
+      error!("failed to get the visibility of {package}: {err}");
+      return;
+    }
+  }
+
+  let new_dependencies = match db
+    .list_new_jsr_dependencies(
+      &publishing_task.package_scope,
+      &publishing_task.package_name,
+      &publishing_task.package_version,
+    )
+    .await
+  {
+    Ok(new_dependencies) => new_dependencies,
+    Err(err) => {
+      error!("failed to list new dependencies of {package}: {err}");
+      return;
+    }
+  };
+  for (dependency_name, constraint) in new_dependencies {
+    let Some(scope) = dependency_name
+      .strip_prefix('@')
+      .and_then(|name| name.split_once('/'))
+      .and_then(|(scope, _)| ScopeName::try_from(scope).ok())
+    else {
+      continue;
+    };
+    // Depending on a package of the same scope is not news to its admins.
+    if scope == publishing_task.package_scope {
+      continue;
+    }
+    events::emit(
+      db,
+      DomainEvent::NewDependent {
+        scope,
+        package: dependency_name,
+        constraint,
+        dependent: package.clone(),
+        dependent_version: publishing_task.package_version.clone(),
+      },
+    )
+    .await;
+  }
+}
+
+/// Queues the email that tells the user who started a publishing task that it
+/// failed. Failing to queue it does not fail the task.
+pub async fn queue_publish_failure_email(
+  db: &Database,
+  publishing_task: &PublishingTask,
+) {
+  if publishing_task.user_id.is_none() {
+    return;
+  }
+  let job = PublishFailureEmailJobPayload::job(publishing_task.id);
+  if let Err(err) = db.enqueue_background_job(job).await {
+    error!(
+      "failed to queue publish failure email for task {}: {err}",
+      publishing_task.id
+    );
+  }
+}
+
 // `algolia_client`/`doc_search_json` are unused while symbol indexing is
 // disabled; keep them so re-enabling is just uncommenting the block below.
 #[allow(unused_variables)]
@@ -189,7 +446,9 @@ async fn process_publishing_task(
   db: &Database,
   buckets: &Buckets,
   license_store: &LicenseStore,
+  malware_scanners: &MalwareScanners,
   algolia_client: &Option<AlgoliaClient>,
+  feature_flags: &FeatureFlags,
   registry_url: Url,
   publishing_task: &mut PublishingTask,
 ) -> Result<(), anyhow::Error> {
@@ -203,12 +462,23 @@ async fn process_publishing_task(
     )
     .await?;
 
-  let output = match process_tarball(
+  let unstable_bytes_imports = feature_flags
+    .is_enabled(
+      db,
+      UNSTABLE_BYTES_IMPORTS,
+      Some(&publishing_task.package_scope),
+      publishing_task.user_id,
+    )
+    .await?;
+
+  let mut output = match process_tarball(
     db,
     buckets,
     license_store,
+    malware_scanners,
     registry_url,
     publishing_task,
+    unstable_bytes_imports,
   )
   .await
   {
@@ -229,6 +499,7 @@ async fn process_publishing_task(
             }),
           )
           .await?;
+        queue_publish_failure_email(db, publishing_task).await;
         return Ok(());
       }
       None => {
@@ -238,51 +509,265 @@ async fn process_publishing_task(
     },
   };
 
-  let ProcessTarballOutput {
-    file_infos,
-    module_graph_2,
-    exports,
-    dependencies,
-    npm_tarball_info,
-    readme_path,
-    meta,
-    doc_search_json,
-    license,
-  } = output;
-
   upload_version_manifest(
     buckets,
     publishing_task,
-    &file_infos,
-    exports.clone().into_inner(),
-    module_graph_2,
+    &output.file_infos,
+    output.exports.clone().into_inner(),
+    std::mem::take(&mut output.module_graph_2),
   )
   .await?;
 
-  create_package_version_and_npm_tarball_and_update_publishing_task(
-    db,
-    publishing_task,
-    &file_infos,
-    exports,
-    dependencies,
-    &npm_tarball_info,
-    readme_path,
-    meta,
-    license,
-  )
-  .await?;
+  let create_version_start = std::time::Instant::now();
+  let rows = PublishedVersionRows::new(publishing_task, &output);
+  let mut tasks = db
+    .create_package_versions_and_finalize_publishing_tasks(&[
+      rows.published_version()
+    ])
+    .await?;
+  *publishing_task = tasks.remove(0);
+  crate::metrics::observe_publish_stage(
+    crate::metrics::PublishStage::CreateVersion,
+    create_version_start.elapsed(),
+  );
 
   /*if let Some(algolia_client) = algolia_client {
     algolia_client.upsert_symbols(
       &publishing_task.package_scope,
       &publishing_task.package_name,
-      doc_search_json,
+      output.doc_search_json,
     );
   }*/
 
   Ok(())
 }
 
+/// Processes the publishing tasks of a workspace publish, which are already
+/// `processing`. Each package is analyzed on its own first, with its
+/// dependencies on the other packages of the publish resolved to their new
+/// versions, and then all packages are analyzed together. Only once all of
+/// them are stored, the package versions are created, in one transaction.
+/// If any of the packages can not be published, all tasks fail.
+#[allow(clippy::too_many_arguments)]
+async fn process_workspace_publish(
+  db: &Database,
+  buckets: &Buckets,
+  license_store: &LicenseStore,
+  malware_scanners: &MalwareScanners,
+  feature_flags: &FeatureFlags,
+  registry_url: Url,
+  publishing_tasks: &mut [PublishingTask],
+) -> Result<(), anyhow::Error> {
+  let workspace_versions = publishing_tasks
+    .iter()
+    .map(|task| PackageNv {
+      name: StackString::from_string(format!(
+        "@{}/{}",
+        task.package_scope, task.package_name
+      )),
+      version: task.package_version.0.clone(),
+    })
+    .collect::<Vec<_>>();
+
+  let mut analyzed_tarballs = Vec::with_capacity(publishing_tasks.len());
+  let mut any_unstable_bytes_imports = false;
+  let mut failure = None;
+  for (i, publishing_task) in publishing_tasks.iter().enumerate() {
+    let unstable_bytes_imports = feature_flags
+      .is_enabled(
+        db,
+        UNSTABLE_BYTES_IMPORTS,
+        Some(&publishing_task.package_scope),
+        publishing_task.user_id,
+      )
+      .await?;
+    any_unstable_bytes_imports |= unstable_bytes_imports;
+
+    let other_versions = workspace_versions
+      .iter()
+      .enumerate()
+      .filter(|(j, _)| *j != i)
+      .map(|(_, nv)| nv.clone())
+      .collect::<Vec<_>>();
+    match download_and_analyze_tarball(
+      db,
+      buckets,
+      license_store,
+      registry_url.clone(),
+      publishing_task,
+      unstable_bytes_imports,
+      &other_versions,
+    )
+    .await
+    {
+      Ok(analyzed) => analyzed_tarballs.push(analyzed),
+      Err(err) => match err.user_error_code() {
+        Some(code) => {
+          // non retryable, fatal error
+          error!("Error processing tarball, fatal: {}", err);
+          failure = Some((
+            publishing_task.id,
+            PublishingTaskError {
+              code: code.to_owned(),
+              message: err.to_string(),
+            },
+          ));
+          break;
+        }
+        None => return Err(anyhow::Error::from(err)),
+      },
+    }
+  }
+  if let Some((failed_task_id, error)) = failure {
+    fail_workspace_publish(db, publishing_tasks, Some(failed_task_id), error)
+      .await?;
+    return Ok(());
+  }
+
+  // Every package of a publish could be imported from every other one, so
+  // all files that the analysis reads are needed.
+  let packages = publishing_tasks
+    .iter()
+    .zip(&analyzed_tarballs)
+    .map(|(task, analyzed)| WorkspacePackage {
+      scope: task.package_scope.clone(),
+      name: task.package_name.clone(),
+      version: task.package_version.clone(),
+      exports: analyzed.exports.clone(),
+      files: analyzed.files.clone(),
+      workspace_dependencies: analyzed.workspace_dependencies.clone(),
+    })
+    .collect::<Vec<_>>();
+  let span = tracing::Span::current();
+  let res = tokio::task::spawn_blocking(move || {
+    analyze_workspace(span, packages, any_unstable_bytes_imports)
+  })
+  .await?;
+  if let Err(err) = res {
+    match err.user_error_code() {
+      Some(code) => {
+        error!("Error analyzing workspace, fatal: {}", err);
+        fail_workspace_publish(
+          db,
+          publishing_tasks,
+          None,
+          PublishingTaskError {
+            code: code.to_owned(),
+            message: err.to_string(),
+          },
+        )
+        .await?;
+        return Ok(());
+      }
+      None => return Err(anyhow::Error::from(err)),
+    }
+  }
+
+  // All errors from here on are retryable, so no package fails on its own.
+  let mut outputs = Vec::with_capacity(analyzed_tarballs.len());
+  for (publishing_task, analyzed) in
+    publishing_tasks.iter().zip(analyzed_tarballs)
+  {
+    let mut output = store_analyzed_tarball(
+      db,
+      buckets,
+      malware_scanners,
+      registry_url.clone(),
+      publishing_task,
+      analyzed,
+    )
+    .await?;
+    upload_version_manifest(
+      buckets,
+      publishing_task,
+      &output.file_infos,
+      output.exports.clone().into_inner(),
+      std::mem::take(&mut output.module_graph_2),
+    )
+    .await?;
+    outputs.push(output);
+  }
+
+  let create_version_start = std::time::Instant::now();
+  let rows = publishing_tasks
+    .iter()
+    .zip(&outputs)
+    .map(|(publishing_task, output)| {
+      PublishedVersionRows::new(publishing_task, output)
+    })
+    .collect::<Vec<_>>();
+  let published_versions = rows
+    .iter()
+    .map(|rows| rows.published_version())
+    .collect::<Vec<_>>();
+  let tasks = db
+    .create_package_versions_and_finalize_publishing_tasks(&published_versions)
+    .await?;
+  for (publishing_task, task) in publishing_tasks.iter_mut().zip(tasks) {
+    *publishing_task = task;
+  }
+  crate::metrics::observe_publish_stage(
+    crate::metrics::PublishStage::CreateVersion,
+    create_version_start.elapsed(),
+  );
+
+  Ok(())
+}
+
+/// Fails all publishing tasks of a workspace publish. `failed_task_id` is the
+/// task of the package that could not be published, if the problem is with a
+/// single package. The other tasks fail because of it.
+async fn fail_workspace_publish(
+  db: &Database,
+  publishing_tasks: &mut [PublishingTask],
+  failed_task_id: Option<Uuid>,
+  error: PublishingTaskError,
+) -> Result<(), anyhow::Error> {
+  let failed_package = publishing_tasks
+    .iter()
+    .find(|task| Some(task.id) == failed_task_id)
+    .map(|task| {
+      format!(
+        "@{}/{}@{}",
+        task.package_scope, task.package_name, task.package_version
+      )
+    });
+
+  for publishing_task in publishing_tasks.iter_mut() {
+    let error = match &failed_package {
+      Some(package) if Some(publishing_task.id) != failed_task_id => {
+        PublishingTaskError {
+          code: "workspacePublishFailed".to_owned(),
+          message: format!(
+            "No package of the workspace publish was published, because {package} failed to publish: {}",
+            error.message
+          ),
+        }
+      }
+      _ => error.clone(),
+    };
+    *publishing_task = db
+      .update_publishing_task_status(
+        None,
+        publishing_task.id,
+        PublishingTaskStatus::Processing,
+        PublishingTaskStatus::Failure,
+        Some(error),
+      )
+      .await?;
+  }
+
+  // One email is enough for the whole publish.
+  if let Some(publishing_task) = publishing_tasks
+    .iter()
+    .find(|task| failed_task_id.is_none_or(|id| id == task.id))
+  {
+    queue_publish_failure_email(db, publishing_task).await;
+  }
+
+  Ok(())
+}
+
 async fn upload_version_manifest(
   buckets: &Buckets,
   publishing_task: &PublishingTask,
@@ -321,7 +806,7 @@ async fn upload_version_manifest(
       S3UploadOptions {
         content_type: Some("application/json".into()),
         cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
-        gzip_encoded: false,
+        content_encoding: ContentEncoding::Identity,
       },
     )
     .await?;
@@ -329,99 +814,244 @@ async fn upload_version_manifest(
   Ok(())
 }
 
-#[allow(clippy::too_many_arguments)]
-async fn create_package_version_and_npm_tarball_and_update_publishing_task(
-  db: &Database,
-  publishing_task: &mut PublishingTask,
-  file_infos: &[crate::tarball::FileInfo],
-  exports: ExportsMap,
-  dependencies: HashSet<(DependencyKind, PackageReqReference)>,
-  npm_tarball_info: &NpmTarballInfo,
-  readme_path: Option<PackagePath>,
-  meta: PackageVersionMeta,
-  license: String,
-) -> Result<(), anyhow::Error> {
-  let uses_npm = dependencies
-    .iter()
-    .any(|(kind, _)| kind == &DependencyKind::Npm);
-
-  let new_package_version = NewPackageVersion {
-    scope: &publishing_task.package_scope,
-    name: &publishing_task.package_name,
-    version: &publishing_task.package_version,
-    user_id: publishing_task.user_id.as_ref(),
-    readme_path: readme_path.as_ref(),
-    uses_npm,
-    exports: &exports,
-    meta,
-    license,
-  };
+/// The rows that are inserted for the package version of a processed
+/// publishing task.
+struct PublishedVersionRows<'a> {
+  publishing_task: &'a PublishingTask,
+  output: &'a ProcessTarballOutput,
+  files: Vec<NewPackageFile<'a>>,
+  dependencies: Vec<NewPackageVersionDependency<'a>>,
+  deprecations: Vec<NewPackageVersionDeprecation<'a>>,
+  symbols: Vec<NewPackageVersionSymbol<'a>>,
+  doc_links: Vec<NewPackageVersionDocLink<'a>>,
+  doc_artifacts: Vec<NewPackageVersionDocArtifact<'a>>,
+  malware_scan_findings: Option<String>,
+}
 
-  let new_package_files = file_infos
-    .iter()
-    .map(|file| NewPackageFile {
-      scope: &publishing_task.package_scope,
-      name: &publishing_task.package_name,
-      version: &publishing_task.package_version,
-      path: &file.path,
-      size: file.size as i32,
-      checksum: Some(&file.hash),
-    })
-    .collect::<Vec<_>>();
+impl<'a> PublishedVersionRows<'a> {
+  fn new(
+    publishing_task: &'a PublishingTask,
+    output: &'a ProcessTarballOutput,
+  ) -> Self {
+    let files = output
+      .file_infos
+      .iter()
+      .map(|file| NewPackageFile {
+        scope: &publishing_task.package_scope,
+        name: &publishing_task.package_name,
+        version: &publishing_task.package_version,
+        path: &file.path,
+        size: file.size as i32,
+        checksum: Some(&file.hash),
+      })
+      .collect::<Vec<_>>();
+
+    let dependencies = output
+      .dependencies
+      .iter()
+      .map(|(kind, req)| NewPackageVersionDependency {
+        package_scope: &publishing_task.package_scope,
+        package_name: &publishing_task.package_name,
+        package_version: &publishing_task.package_version,
+        dependency_kind: *kind,
+        dependency_name: &req.req.name,
+        dependency_constraint: req.req.version_req.version_text(),
+        dependency_path: req.sub_path.as_deref().unwrap_or(""),
+        is_optional: output
+          .optional_dependencies
+          .contains(&(*kind, req.clone())),
+      })
+      .collect::<Vec<_>>();
+
+    let deprecations = output
+      .deprecations
+      .iter()
+      .map(|deprecation| NewPackageVersionDeprecation {
+        scope: &publishing_task.package_scope,
+        name: &publishing_task.package_name,
+        version: &publishing_task.package_version,
+        export: &deprecation.export,
+        symbol: &deprecation.symbol,
+        message: deprecation.message.as_deref(),
+      })
+      .collect::<Vec<_>>();
+
+    let symbols = output
+      .symbols
+      .iter()
+      .map(|symbol| NewPackageVersionSymbol {
+        scope: &publishing_task.package_scope,
+        name: &publishing_task.package_name,
+        version: &publishing_task.package_version,
+        export: &symbol.export,
+        symbol: &symbol.symbol,
+        kind: symbol.kind,
+      })
+      .collect::<Vec<_>>();
+
+    let doc_links = output
+      .doc_links
+      .iter()
+      .map(|link| NewPackageVersionDocLink {
+        scope: &publishing_task.package_scope,
+        name: &publishing_task.package_name,
+        version: &publishing_task.package_version,
+        identifier: &link.identifier,
+        path: &link.path,
+      })
+      .collect::<Vec<_>>();
+
+    let doc_artifacts = output
+      .doc_artifacts
+      .iter()
+      .map(|artifact| NewPackageVersionDocArtifact {
+        scope: &publishing_task.package_scope,
+        name: &publishing_task.package_name,
+        version: &publishing_task.package_version,
+        kind: artifact.kind,
+        checksum: &artifact.checksum,
+        size: artifact.size,
+      })
+      .collect::<Vec<_>>();
+
+    let malware_scan_findings = (!output.scan_findings.is_empty()).then(|| {
+      let findings = output
+        .scan_findings
+        .iter()
+        .map(|finding| format!("- {finding}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+      format!("Flagged by the malware scan:\n{findings}")
+    });
 
-  let new_package_version_dependencies = dependencies
-    .iter()
-    .map(|(kind, req)| NewPackageVersionDependency {
-      package_scope: &publishing_task.package_scope,
-      package_name: &publishing_task.package_name,
-      package_version: &publishing_task.package_version,
-      dependency_kind: *kind,
-      dependency_name: &req.req.name,
-      dependency_constraint: req.req.version_req.version_text(),
-      dependency_path: req.sub_path.as_deref().unwrap_or(""),
-    })
-    .collect::<Vec<_>>();
+    Self {
+      publishing_task,
+      output,
+      files,
+      dependencies,
+      deprecations,
+      symbols,
+      doc_links,
+      doc_artifacts,
+      malware_scan_findings,
+    }
+  }
+
+  fn published_version(&self) -> NewPublishedVersion<'_> {
+    let publishing_task = self.publishing_task;
+    let output = self.output;
+
+    let uses_npm = output
+      .dependencies
+      .iter()
+      .any(|(kind, _)| kind == &DependencyKind::Npm);
+
+    NewPublishedVersion {
+      publishing_task_id: publishing_task.id,
+      package_version: NewPackageVersion {
+        scope: &publishing_task.package_scope,
+        name: &publishing_task.package_name,
+        version: &publishing_task.package_version,
+        user_id: publishing_task.user_id.as_ref(),
+        readme_path: output.readme_path.as_ref(),
+        uses_npm,
+        exports: &output.exports,
+        meta: output.meta.clone(),
+        license: output.license.clone(),
+      },
+      files: &self.files,
+      dependencies: &self.dependencies,
+      deprecations: &self.deprecations,
+      symbols: &self.symbols,
+      doc_links: &self.doc_links,
+      doc_artifacts: &self.doc_artifacts,
+      npm_tarball: NewNpmTarball {
+        scope: &publishing_task.package_scope,
+        name: &publishing_task.package_name,
+        version: &publishing_task.package_version,
+        revision: NPM_TARBALL_REVISION as i32,
+        sha1: &output.npm_tarball_info.sha1,
+        sha512: &output.npm_tarball_info.sha512,
+        sha256: &output.npm_tarball_info.sha256,
+        size: output.npm_tarball_info.size as i32,
+      },
+      malware_scan_findings: self.malware_scan_findings.as_deref(),
+    }
+  }
+}
 
-  let new_npm_tarball = NewNpmTarball {
-    scope: &publishing_task.package_scope,
-    name: &publishing_task.package_name,
-    version: &publishing_task.package_version,
-    revision: NPM_TARBALL_REVISION as i32,
-    sha1: &npm_tarball_info.sha1,
-    sha512: &npm_tarball_info.sha512,
-    size: npm_tarball_info.size as i32,
+/// Compares a publish with the latest version of the package: the files that
+/// were added, removed or modified, and the doc diff of the exported API.
+/// `None` if the package has no versions yet.
+pub async fn publish_preview_diff(
+  db: &Database,
+  buckets: &Buckets,
+  scope: &ScopeName,
+  name: &PackageName,
+  file_infos: &[FileInfo],
+  doc_nodes: &deno_doc::ParseOutput,
+) -> Result<Option<ApiPublishPreviewDiff>, anyhow::Error> {
+  let Some(base) = db
+    .get_latest_unyanked_version_for_package_for_docs(scope, name)
+    .await?
+  else {
+    return Ok(None);
   };
 
-  *publishing_task = db
-    .create_package_version_and_npm_tarball_and_finalize_publishing_task(
-      publishing_task.id,
-      new_package_version,
-      &new_package_files,
-      &new_package_version_dependencies,
-      new_npm_tarball,
-    )
-    .await?;
+  let base_checksums = db
+    .list_package_files(scope, name, &base.version)
+    .await?
+    .into_iter()
+    .map(|file| (file.path, file.checksum))
+    .collect::<HashMap<_, _>>();
+
+  let mut files = ApiPublishFilesDiff::default();
+  for file in file_infos {
+    match base_checksums.get(&file.path) {
+      None => files.added.push(file.path.clone()),
+      Some(checksum) if checksum.as_deref() != Some(file.hash.as_str()) => {
+        files.modified.push(file.path.clone())
+      }
+      Some(_) => {}
+    }
+  }
+  let paths = file_infos
+    .iter()
+    .map(|file| &file.path)
+    .collect::<HashSet<_>>();
+  files.removed = base_checksums
+    .into_keys()
+    .filter(|path| !paths.contains(path))
+    .collect();
+  for paths in [&mut files.added, &mut files.removed, &mut files.modified] {
+    paths.sort_by(|a, b| (**a).cmp(&**b));
+  }
 
-  Ok(())
+  let base_doc_nodes =
+    crate::docs::download_doc_nodes(scope, name, &base.version, buckets)
+      .await?
+      .ok_or_else(|| {
+        anyhow::anyhow!("docs not found for @{scope}/{name}/{}", base.version)
+      })?;
+  let api = deno_doc::diff::DocDiff::diff(&base_doc_nodes, doc_nodes);
+
+  Ok(Some(ApiPublishPreviewDiff {
+    base_version: base.version,
+    files,
+    api,
+  }))
 }
 
-async fn upload_package_manifest(
+pub async fn upload_package_manifest(
   db: &Database,
   buckets: &Buckets,
   registry_url: &Url,
   cache_purge: &CachePurge,
-  publishing_task: &PublishingTask,
+  scope: &ScopeName,
+  name: &PackageName,
 ) -> Result<(), anyhow::Error> {
-  let package_metadata_s3_path = crate::s3_paths::package_metadata(
-    &publishing_task.package_scope,
-    &publishing_task.package_name,
-  );
-  let package_metadata = PackageMetadata::create(
-    db,
-    &publishing_task.package_scope,
-    &publishing_task.package_name,
-  )
-  .await?;
+  let package_metadata_s3_path = crate::s3_paths::package_metadata(scope, name);
+  let package_metadata = PackageMetadata::create(db, scope, name).await?;
   let content = serde_json::to_vec(&package_metadata)?;
   buckets
     .modules_bucket
@@ -431,66 +1061,22 @@ async fn upload_package_manifest(
       S3UploadOptions {
         content_type: Some("application/json".into()),
         cache_control: Some(CACHE_CONTROL_MANIFEST.into()),
-        gzip_encoded: false,
+        content_encoding: ContentEncoding::Identity,
       },
     )
     .await?;
 
   let mut purge_urls = vec![crate::s3_paths::package_metadata_url(
     registry_url,
-    &publishing_task.package_scope,
-    &publishing_task.package_name,
+    scope,
+    name,
   )];
   purge_urls.extend(crate::s3_paths::package_api_cache_urls(
     registry_url,
-    &publishing_task.package_scope,
-    &publishing_task.package_name,
+    scope,
+    name,
   ));
-  cache_purge.purge(purge_urls).await;
-
-  Ok(())
-}
-
-async fn upload_npm_version_manifest(
-  db: &Database,
-  buckets: &Buckets,
-  npm_url: &Url,
-  cache_purge: &CachePurge,
-  publishing_task: &PublishingTask,
-) -> Result<(), anyhow::Error> {
-  let npm_version_manifest_path_s3_path =
-    crate::s3_paths::npm_version_manifest_path(
-      &publishing_task.package_scope,
-      &publishing_task.package_name,
-    );
-  let npm_version_manifest = generate_npm_version_manifest(
-    db,
-    npm_url,
-    &publishing_task.package_scope,
-    &publishing_task.package_name,
-  )
-  .await?;
-  let content = serde_json::to_vec_pretty(&npm_version_manifest)?;
-  buckets
-    .npm_bucket
-    .upload(
-      npm_version_manifest_path_s3_path.into(),
-      crate::s3::UploadTaskBody::Bytes(content.into()),
-      S3UploadOptions {
-        content_type: Some("application/json".into()),
-        cache_control: Some(CACHE_CONTROL_MANIFEST.into()),
-        gzip_encoded: false,
-      },
-    )
-    .await?;
-
-  cache_purge
-    .purge(vec![crate::s3_paths::npm_version_manifest_url(
-      npm_url,
-      &publishing_task.package_scope,
-      &publishing_task.package_name,
-    )])
-    .await;
+  cache_purge.purge(purge_urls);
 
   Ok(())
 }
@@ -499,14 +1085,17 @@ async fn upload_npm_version_manifest(
 pub mod tests {
   use super::*;
   use crate::api::ApiPublishingTask;
-  use crate::api::package::MAX_PUBLISH_TARBALL_SIZE;
   use crate::db::CreatePackageResult;
   use crate::db::CreatePublishingTaskResult;
+  use crate::db::EntrypointDocsCoverage;
   use crate::db::NewPublishingTask;
   use crate::ids::ScopeName;
   use crate::ids::Version;
   use crate::ids::{PackageName, PackagePath};
+  use crate::malware_scan::RulesScanner;
   use crate::metadata::VersionMetadata;
+  use crate::module_files::FilesManifest;
+  use crate::publish_limits::PublishLimits;
   use crate::tarball::ConfigFile;
   use crate::tarball::bucket_tarball_path;
   use crate::util::test::ApiResultExt;
@@ -517,8 +1106,10 @@ pub mod tests {
   use flate2::write::GzEncoder;
   use hyper::StatusCode;
   use serde_json::json;
+  use sha2::Digest;
   use std::collections::HashMap;
   use std::io::Write;
+  use std::sync::Arc;
 
   pub async fn process_tarball_setup(
     t: &TestSetup,
@@ -577,7 +1168,7 @@ pub mod tests {
         S3UploadOptions {
           content_type: Some("application/x-tar".into()),
           cache_control: None,
-          gzip_encoded: true,
+          content_encoding: ContentEncoding::Gzip,
         },
       )
       .await
@@ -587,11 +1178,14 @@ pub mod tests {
       task.0.id,
       t.buckets(),
       t.license_store(),
+      MalwareScanners::new(vec![Arc::new(RulesScanner)]),
       t.registry_url(),
       t.npm_url(),
+      NpmSigner(None),
       t.db(),
       None,
-      CachePurge(None),
+      CachePurge::new(None),
+      FeatureFlags::new(),
     )
     .await
     .unwrap();
@@ -641,7 +1235,40 @@ pub mod tests {
     Bytes::from(gz_bytes)
   }
 
-  pub fn create_invalid_path_mock_tarball() -> Bytes {
+  pub fn create_path_issues_mock_tarball(with_duplicates: bool) -> Bytes {
+    let mut tar_bytes = Vec::new();
+    let mut tar = tar::Builder::new(&mut tar_bytes);
+    tar.append_dir_all("./", "./testdata/tarballs/ok/").unwrap();
+    if with_duplicates {
+      let mut file =
+        std::fs::File::open("./testdata/tarballs/ok/mod.ts").unwrap();
+      tar.append_file("./Mod.ts", &mut file).unwrap();
+      let mut file =
+        std::fs::File::open("./testdata/tarballs/ok/mod.ts").unwrap();
+      tar.append_file("./MOD.ts", &mut file).unwrap();
+    }
+    let mut file =
+      std::fs::File::open("./testdata/tarballs/ok/mod.ts").unwrap();
+    tar
+      .append_file(format!(".{}mod.ts", "/a".repeat(33)), &mut file)
+      .unwrap();
+    let mut file =
+      std::fs::File::open("./testdata/tarballs/ok/mod.ts").unwrap();
+    tar
+      .append_file(format!(".{}/mod.ts", "/abcdefghi".repeat(16)), &mut file)
+      .unwrap();
+    tar.finish().unwrap();
+    drop(tar);
+
+    let mut gz_bytes = Vec::new();
+    let mut encoder = GzEncoder::new(&mut gz_bytes, Compression::default());
+    encoder.write_all(&tar_bytes).unwrap();
+    encoder.finish().unwrap();
+
+    gz_bytes.into()
+  }
+
+  pub fn create_invalid_path_mock_tarball() -> Bytes {
     let mut tar_bytes = Vec::new();
     let mut tar = tar::Builder::new(&mut tar_bytes);
     tar.append_dir_all("./", "./testdata/tarballs/ok/").unwrap();
@@ -661,7 +1288,8 @@ pub mod tests {
 
   #[tokio::test]
   async fn payload_too_large() {
-    let body = Body::from(vec![0; MAX_PUBLISH_TARBALL_SIZE as usize + 10]);
+    let max_size = PublishLimits::default().max_tarball_size;
+    let body = Body::from(vec![0; max_size as usize + 10]);
 
     let mut t = TestSetup::new().await;
     let mut resp = t
@@ -684,7 +1312,7 @@ pub mod tests {
   async fn payload_too_large_stream() {
     // Convert the Vec<u8> into a hyper Body with chunked transfer encoding
     let body = Body::wrap_stream(tokio_stream::once(Ok::<_, std::io::Error>(
-      vec![0; MAX_PUBLISH_TARBALL_SIZE as usize + 10],
+      vec![0; PublishLimits::default().max_tarball_size as usize + 10],
     )));
 
     let mut t = TestSetup::new().await;
@@ -735,36 +1363,127 @@ pub mod tests {
     let t = TestSetup::new().await;
     let task = process_tarball_setup(&t, create_mock_tarball("with_svg")).await;
     assert_eq!(task.status, PublishingTaskStatus::Success);
-    let response = t
+    let manifest = t
       .buckets
       .modules_bucket
-      .bucket
-      .bucket
-      .get_object("@scope/foo/1.2.3/jsr.json")
+      .download("@scope/foo/1.2.3_files.json".into())
       .await
+      .unwrap()
       .unwrap();
-    assert_eq!(response.status_code(), 200);
-    assert_eq!(response.headers()["content-type"], "application/json");
-    let response = t
+    let manifest: FilesManifest = serde_json::from_slice(&manifest).unwrap();
+    let content_type = |path: &str| {
+      manifest.files[&PackagePath::try_from(path).unwrap()]
+        .content_type
+        .clone()
+    };
+    assert_eq!(content_type("/jsr.json").unwrap(), "application/json");
+    assert_eq!(content_type("/mod.ts").unwrap(), "text/typescript");
+    assert_eq!(content_type("/logo.svg").unwrap(), "image/svg+xml");
+  }
+
+  #[tokio::test]
+  async fn files_deduplicated() {
+    let t = TestSetup::new().await;
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success);
+    let task = process_tarball_setup2(
+      &t,
+      create_mock_tarball("ok_prerelease"),
+      &PackageName::try_from("foo").unwrap(),
+      &Version::try_from("1.2.3-alpha.1").unwrap(),
+      false,
+    )
+    .await;
+    assert_eq!(task.status, PublishingTaskStatus::Success);
+
+    let manifest = |version: &'static str| {
+      let bucket = t.buckets.modules_bucket.clone();
+      async move {
+        let manifest = bucket
+          .download(format!("@scope/foo/{version}_files.json").into())
+          .await
+          .unwrap()
+          .unwrap();
+        serde_json::from_slice::<FilesManifest>(&manifest).unwrap()
+      }
+    };
+    let manifest1 = manifest("1.2.3").await;
+    let manifest2 = manifest("1.2.3-alpha.1").await;
+
+    // The unchanged module is stored once, the config file twice.
+    let mod_ts = PackagePath::try_from("/mod.ts").unwrap();
+    let jsr_json = PackagePath::try_from("/jsr.json").unwrap();
+    assert_eq!(manifest1.files[&mod_ts].hash, manifest2.files[&mod_ts].hash);
+    assert_ne!(
+      manifest1.files[&jsr_json].hash,
+      manifest2.files[&jsr_json].hash
+    );
+    let mut hashes = manifest1
+      .files
+      .values()
+      .chain(manifest2.files.values())
+      .map(|file| file.hash.clone())
+      .collect::<Vec<_>>();
+    hashes.sort();
+    hashes.dedup();
+    assert_eq!(hashes.len(), 3);
+    let stored = t.db().reference_module_blobs(&hashes).await.unwrap();
+    assert_eq!(stored.len(), 3);
+    for hash in hashes {
+      t.buckets
+        .modules_bucket
+        .download(crate::s3_paths::module_blob_path(&hash).into())
+        .await
+        .unwrap()
+        .unwrap();
+    }
+  }
+
+  #[tokio::test]
+  async fn large_file_spilled() {
+    // A file larger than the spill threshold that the analysis does not read.
+    let data = (0..2 * 1024 * 1024)
+      .map(|i| (i % 251) as u8)
+      .collect::<Vec<_>>();
+    let mut tar_bytes = Vec::new();
+    let mut tar = tar::Builder::new(&mut tar_bytes);
+    tar.append_dir_all("./", "./testdata/tarballs/ok/").unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    tar
+      .append_data(&mut header, "./data.bin", data.as_slice())
+      .unwrap();
+    tar.finish().unwrap();
+    drop(tar);
+    let mut gz_bytes = Vec::new();
+    let mut encoder = GzEncoder::new(&mut gz_bytes, Compression::default());
+    encoder.write_all(&tar_bytes).unwrap();
+    encoder.finish().unwrap();
+
+    let t = TestSetup::new().await;
+    let task = process_tarball_setup(&t, gz_bytes.into()).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success);
+
+    let manifest = t
       .buckets
       .modules_bucket
-      .bucket
-      .bucket
-      .get_object("@scope/foo/1.2.3/mod.ts")
+      .download("@scope/foo/1.2.3_files.json".into())
       .await
+      .unwrap()
       .unwrap();
-    assert_eq!(response.status_code(), 200);
-    assert_eq!(response.headers()["content-type"], "text/typescript");
-    let response = t
+    let manifest: FilesManifest = serde_json::from_slice(&manifest).unwrap();
+    let file = &manifest.files[&PackagePath::try_from("/data.bin").unwrap()];
+    assert_eq!(file.content_type, None);
+    let blob = t
       .buckets
       .modules_bucket
-      .bucket
-      .bucket
-      .get_object("@scope/foo/1.2.3/logo.svg")
+      .download(crate::s3_paths::module_blob_path(&file.hash).into())
       .await
+      .unwrap()
       .unwrap();
-    assert_eq!(response.status_code(), 200);
-    assert_eq!(response.headers()["content-type"], "image/svg+xml");
+    assert_eq!(blob.as_ref(), data.as_slice());
   }
 
   #[tokio::test]
@@ -869,16 +1588,41 @@ pub mod tests {
     let t = TestSetup::new().await;
     let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
     assert_eq!(task.status, PublishingTaskStatus::Success);
-    let json = t
-      .buckets
-      .modules_bucket
-      .download("@scope/foo/1.2.3/jsr.json".into())
-      .await
-      .unwrap()
-      .unwrap();
+    let json = crate::module_files::download_file(
+      &t.buckets.modules_bucket,
+      &task.package_scope,
+      &task.package_name,
+      &task.package_version,
+      &PackagePath::try_from("/jsr.json").unwrap(),
+    )
+    .await
+    .unwrap()
+    .unwrap();
     let deno_json: ConfigFile = serde_json::from_slice(&json).unwrap();
     assert_eq!(deno_json.name.to_string(), "@scope/foo");
     assert_eq!(deno_json.version.unwrap().to_string(), "1.2.3");
+    {
+      let hash = format!("sha256-{:x}", sha2::Sha256::digest(&json));
+      let blob_path = crate::s3_paths::module_blob_path(&hash);
+      let br = t
+        .buckets
+        .modules_bucket
+        .download(format!("{blob_path}.br").into())
+        .await
+        .unwrap()
+        .unwrap();
+      let mut decompressed = vec![];
+      brotli::BrotliDecompress(&mut &br[..], &mut decompressed).unwrap();
+      assert_eq!(decompressed, json);
+      let zst = t
+        .buckets
+        .modules_bucket
+        .download(format!("{blob_path}.zst").into())
+        .await
+        .unwrap()
+        .unwrap();
+      assert_eq!(zstd::decode_all(&zst[..]).unwrap(), json);
+    }
     {
       let metadata_json = t
         .buckets
@@ -1082,6 +1826,61 @@ pub mod tests {
     assert_eq!(error.code, "caseInsensitiveDuplicatePath");
   }
 
+  #[tokio::test]
+  async fn path_issues_reported_together() {
+    let t = TestSetup::new().await;
+    let bytes = create_path_issues_mock_tarball(true);
+    let task = process_tarball_setup(&t, bytes).await;
+    assert_eq!(task.status, PublishingTaskStatus::Failure, "{task:#?}");
+    let error = task.error.unwrap();
+    assert_eq!(error.code, "caseInsensitiveDuplicatePath");
+    assert!(
+      error
+        .message
+        .contains("case-insensitive duplicate path '/Mod.ts' and '/mod.ts'"),
+      "{}",
+      error.message
+    );
+    assert!(
+      error
+        .message
+        .contains("case-insensitive duplicate path '/MOD.ts' and '/mod.ts'"),
+      "{}",
+      error.message
+    );
+    assert!(
+      error
+        .message
+        .contains("is nested in 33 directories, the maximum is 32"),
+      "{}",
+      error.message
+    );
+    assert!(
+      error
+        .message
+        .contains("is 167 characters long, the maximum is 155"),
+      "{}",
+      error.message
+    );
+  }
+
+  #[tokio::test]
+  async fn path_issues_without_duplicates() {
+    let t = TestSetup::new().await;
+    let bytes = create_path_issues_mock_tarball(false);
+    let task = process_tarball_setup(&t, bytes).await;
+    assert_eq!(task.status, PublishingTaskStatus::Failure, "{task:#?}");
+    let error = task.error.unwrap();
+    assert_eq!(error.code, "invalidPaths");
+    assert!(
+      error
+        .message
+        .contains("is 167 characters long, the maximum is 155"),
+      "{}",
+      error.message
+    );
+  }
+
   #[tokio::test]
   async fn case_insensitive_exports_reference() {
     let t = TestSetup::new().await;
@@ -1231,6 +2030,49 @@ pub mod tests {
     );
   }
 
+  #[tokio::test]
+  async fn preview_diff() {
+    let mut t = TestSetup::new().await;
+
+    let task = process_tarball_setup(&t, create_mock_tarball("ok")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:#?}");
+    let first = t
+      .http()
+      .get(format!("/api/publishing_tasks/{}", task.id))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPublishingTask>()
+      .await;
+    assert!(first.preview_diff.is_none());
+
+    let task = process_tarball_setup2(
+      &t,
+      create_mock_tarball("diff"),
+      &PackageName::try_from("foo").unwrap(),
+      &Version::try_from("1.3.0").unwrap(),
+      false,
+    )
+    .await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:#?}");
+    let second = t
+      .http()
+      .get(format!("/api/publishing_tasks/{}", task.id))
+      .call()
+      .await
+      .unwrap()
+      .expect_ok::<ApiPublishingTask>()
+      .await;
+    let diff = second.preview_diff.unwrap();
+    assert_eq!(diff["baseVersion"], "1.2.3");
+    assert_eq!(
+      diff["files"],
+      json!({ "added": [], "removed": [], "modified": ["/jsr.json", "/mod.ts"] })
+    );
+    let module = &diff["api"]["modifiedModules"]["file:///mod.ts"];
+    assert_eq!(module["added"][0]["name"], "goodbye");
+  }
+
   #[tokio::test]
   async fn https_import() {
     let t = TestSetup::new().await;
@@ -1258,6 +2100,82 @@ pub mod tests {
       .uses_npm
   }
 
+  #[tokio::test]
+  async fn coverage() {
+    let t = TestSetup::new().await;
+    let bytes = create_mock_tarball("coverage");
+    let task = process_tarball_setup(&t, bytes).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:#?}");
+
+    let meta = t
+      .db()
+      .get_package_version(
+        &task.package_scope,
+        &task.package_name,
+        &task.package_version,
+      )
+      .await
+      .unwrap()
+      .unwrap()
+      .meta;
+    assert!(meta.has_tests);
+    assert_eq!(meta.score_details.test_files, 1);
+    let coverage = meta.score_details.coverage.unwrap();
+    assert_eq!(coverage.lines.total, 3);
+    assert_eq!(coverage.lines.covered, 3);
+    assert_eq!(coverage.functions.unwrap().covered, 1);
+  }
+
+  #[tokio::test]
+  async fn coverage_invalid() {
+    let t = TestSetup::new().await;
+    let bytes = create_mock_tarball("coverage_invalid");
+    let task = process_tarball_setup(&t, bytes).await;
+    assert_eq!(task.status, PublishingTaskStatus::Failure, "{task:#?}");
+    let error = task.error.unwrap();
+    assert_eq!(error.code, "configFileCoverageInvalid");
+  }
+
+  #[tokio::test]
+  async fn entrypoint_docs() {
+    let t = TestSetup::new().await;
+    let bytes = create_mock_tarball("entrypoint_docs");
+    let task = process_tarball_setup(&t, bytes).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:#?}");
+
+    let meta = t
+      .db()
+      .get_package_version(
+        &task.package_scope,
+        &task.package_name,
+        &task.package_version,
+      )
+      .await
+      .unwrap()
+      .unwrap()
+      .meta;
+    assert!(!meta.all_entrypoints_docs);
+    assert_eq!(
+      meta.score_details.entrypoint_docs,
+      vec![
+        EntrypointDocsCoverage {
+          export: ".".to_string(),
+          path: "/mod.ts".to_string(),
+          has_module_doc: true,
+          documented_symbols: 2,
+          total_symbols: 2,
+        },
+        EntrypointDocsCoverage {
+          export: "./utils".to_string(),
+          path: "/utils.ts".to_string(),
+          has_module_doc: false,
+          documented_symbols: 1,
+          total_symbols: 2,
+        },
+      ]
+    );
+  }
+
   #[tokio::test]
   async fn npm_import() {
     let t = TestSetup::new().await;
@@ -1267,6 +2185,188 @@ pub mod tests {
     assert!(uses_npm(&t, &task).await);
   }
 
+  #[tokio::test]
+  async fn peer_dependencies() {
+    let t = TestSetup::new().await;
+    let bytes = create_mock_tarball("peer_dependencies");
+    let task = process_tarball_setup(&t, bytes).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:#?}");
+
+    let meta = t
+      .db()
+      .get_package_version(
+        &task.package_scope,
+        &task.package_name,
+        &task.package_version,
+      )
+      .await
+      .unwrap()
+      .unwrap()
+      .meta;
+    assert_eq!(meta.npm_peer_dependencies, vec!["npm:chalk@^5.0.0"]);
+  }
+
+  #[tokio::test]
+  async fn peer_dependencies_invalid() {
+    let t = TestSetup::new().await;
+    let bytes = create_mock_tarball("peer_dependencies_invalid");
+    let task = process_tarball_setup(&t, bytes).await;
+    assert_eq!(task.status, PublishingTaskStatus::Failure, "{task:#?}");
+    let error = task.error.unwrap();
+    assert_eq!(error.code, "configFilePeerDependenciesInvalid");
+  }
+
+  #[tokio::test]
+  async fn bin() {
+    let t = TestSetup::new().await;
+    let bytes = create_mock_tarball("bin");
+    let task = process_tarball_setup(&t, bytes).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:#?}");
+
+    let meta = t
+      .db()
+      .get_package_version(
+        &task.package_scope,
+        &task.package_name,
+        &task.package_version,
+      )
+      .await
+      .unwrap()
+      .unwrap()
+      .meta;
+    assert_eq!(meta.npm_bin.len(), 1);
+    assert_eq!(meta.npm_bin["foo"], "./cli.ts");
+  }
+
+  #[tokio::test]
+  async fn bin_invalid() {
+    let t = TestSetup::new().await;
+    let bytes = create_mock_tarball("bin_invalid");
+    let task = process_tarball_setup(&t, bytes).await;
+    assert_eq!(task.status, PublishingTaskStatus::Failure, "{task:#?}");
+    let error = task.error.unwrap();
+    assert_eq!(error.code, "configFileBinInvalid");
+  }
+
+  #[tokio::test]
+  async fn bin_conflict() {
+    let t = TestSetup::new().await;
+    let bytes = create_mock_tarball("bin_conflict");
+    let task = process_tarball_setup(&t, bytes).await;
+    assert_eq!(task.status, PublishingTaskStatus::Failure, "{task:#?}");
+    let error = task.error.unwrap();
+    assert_eq!(error.code, "npmTarballError");
+    assert!(error.message.contains("/_bin/foo.js"), "{error:?}");
+  }
+
+  #[tokio::test]
+  async fn export_patterns() {
+    let t = TestSetup::new().await;
+    let bytes = create_mock_tarball("export_patterns");
+    let task = process_tarball_setup(&t, bytes).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:#?}");
+
+    let version = t
+      .db()
+      .get_package_version(
+        &task.package_scope,
+        &task.package_name,
+        &task.package_version,
+      )
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(
+      version.exports.into_inner().into_iter().collect::<Vec<_>>(),
+      vec![
+        (".".to_string(), "./mod.ts".to_string()),
+        ("./features/a".to_string(), "./features/a.ts".to_string()),
+        (
+          "./features/nested/b".to_string(),
+          "./features/nested/b.ts".to_string()
+        ),
+      ]
+    );
+    assert_eq!(version.meta.export_patterns.len(), 1);
+    assert_eq!(
+      version.meta.export_patterns["./features/*"],
+      "./features/*.ts"
+    );
+  }
+
+  #[tokio::test]
+  async fn export_patterns_no_match() {
+    let t = TestSetup::new().await;
+    let bytes = create_mock_tarball("export_patterns_no_match");
+    let task = process_tarball_setup(&t, bytes).await;
+    assert_eq!(task.status, PublishingTaskStatus::Failure, "{task:#?}");
+    let error = task.error.unwrap();
+    assert_eq!(error.code, "configFileExportsInvalid");
+  }
+
+  #[tokio::test]
+  async fn npm_exclude() {
+    let t = TestSetup::new().await;
+    let bytes = create_mock_tarball("npm_exclude");
+    let task = process_tarball_setup(&t, bytes).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:#?}");
+
+    let meta = t
+      .db()
+      .get_package_version(
+        &task.package_scope,
+        &task.package_name,
+        &task.package_version,
+      )
+      .await
+      .unwrap()
+      .unwrap()
+      .meta;
+    assert_eq!(meta.npm_exclude, vec!["./bench", "**/*_test.ts"]);
+    // Excluded files are still published to JSR, and count as tests.
+    assert!(meta.has_tests);
+  }
+
+  #[tokio::test]
+  async fn npm_exclude_reachable() {
+    let t = TestSetup::new().await;
+    let bytes = create_mock_tarball("npm_exclude_reachable");
+    let task = process_tarball_setup(&t, bytes).await;
+    assert_eq!(task.status, PublishingTaskStatus::Failure, "{task:#?}");
+    let error = task.error.unwrap();
+    assert_eq!(error.code, "configFileNpmExcludeInvalid");
+  }
+
+  #[tokio::test]
+  async fn optional_import() {
+    let t = TestSetup::new().await;
+    let bytes = create_mock_tarball("optional_import");
+    let task = process_tarball_setup(&t, bytes).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:#?}");
+
+    let deps = t
+      .db()
+      .list_package_version_dependencies(
+        &task.package_scope,
+        &task.package_name,
+        &task.package_version,
+      )
+      .await
+      .unwrap();
+    let deps = deps
+      .into_iter()
+      .map(|dep| (dep.dependency_name, dep.is_optional))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      deps,
+      vec![
+        ("@scope/missing".to_string(), true),
+        ("chalk".to_string(), true),
+        ("express".to_string(), false),
+      ]
+    );
+  }
+
   #[tokio::test]
   async fn bun_import() {
     let t = TestSetup::new().await;
@@ -1315,6 +2415,52 @@ pub mod tests {
     assert!(!uses_npm(&t, &task).await);
   }
 
+  #[tokio::test]
+  async fn jsr_import_yanked_dependency() {
+    let t = TestSetup::new().await;
+
+    let bytes = create_mock_tarball("ok");
+    let task = process_tarball_setup(&t, bytes).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:#?}");
+    t.db()
+      .yank_package_version(
+        &t.user1.user.id,
+        false,
+        &task.package_scope,
+        &task.package_name,
+        &task.package_version,
+        true,
+        Some("broken"),
+      )
+      .await
+      .unwrap();
+
+    // A yanked version does not satisfy a range...
+    let bytes = create_mock_tarball("jsr_import");
+    let task = process_tarball_setup2(
+      &t,
+      bytes,
+      &PackageName::try_from("bar").unwrap(),
+      &Version::try_from("1.2.3").unwrap(),
+      false,
+    )
+    .await;
+    assert_eq!(task.status, PublishingTaskStatus::Failure, "{task:#?}");
+    assert_eq!(task.error.unwrap().code, "unresolvableJsrDependency");
+
+    // ...but it does satisfy an exact pin.
+    let bytes = create_mock_tarball("jsr_import_exact");
+    let task = process_tarball_setup2(
+      &t,
+      bytes,
+      &PackageName::try_from("baz").unwrap(),
+      &Version::try_from("1.2.3").unwrap(),
+      false,
+    )
+    .await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:#?}");
+  }
+
   #[tokio::test]
   async fn jsr_import_missing_dependency() {
     let t = TestSetup::new().await;
@@ -1481,6 +2627,63 @@ pub mod tests {
     assert_eq!(error.code, "commonJs");
   }
 
+  #[tokio::test]
+  async fn npm_cjs_conflict() {
+    let t = TestSetup::new().await;
+    let bytes = create_mock_tarball("npm_cjs_conflict");
+    let task = process_tarball_setup(&t, bytes).await;
+    assert_eq!(task.status, PublishingTaskStatus::Failure, "{task:#?}");
+    let error = task.error.unwrap();
+    assert_eq!(error.code, "npmTarballError");
+    assert!(error.message.contains("/_cjs/mod.js"), "{error:?}");
+  }
+
+  #[tokio::test]
+  async fn malware_scan() {
+    let t = TestSetup::new().await;
+    let task = process_tarball_setup(&t, create_mock_tarball("malware")).await;
+    assert_eq!(task.status, PublishingTaskStatus::Success, "{task:#?}");
+
+    // The version is published, but quarantined pending review.
+    let scope = ScopeName::try_from("scope").unwrap();
+    let package = PackageName::try_from("foo").unwrap();
+    let version = Version::try_from("1.2.3").unwrap();
+    let quarantine = t
+      .db()
+      .get_package_version_quarantine(&scope, &package, &version)
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(quarantine.quarantined_by, None);
+    let report = t
+      .db()
+      .get_abuse_report(quarantine.report_id.unwrap())
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(report.reason, crate::db::AbuseReportReason::Malware);
+    assert_eq!(report.reporter_id, None);
+    assert_eq!(
+      report.description,
+      "Flagged by the malware scan:\n- /mod.ts: sends all environment variables over the network (9:24) (rules)"
+    );
+
+    let metadata = PackageMetadata::create(&t.db(), &scope, &package)
+      .await
+      .unwrap();
+    assert!(metadata.versions.is_empty());
+    let meta_path =
+      crate::s3_paths::version_metadata(&scope, &package, &version);
+    assert!(
+      t.buckets
+        .modules_bucket
+        .download(meta_path.into())
+        .await
+        .unwrap()
+        .is_none()
+    );
+  }
+
   #[tokio::test]
   async fn npm_tarball() {
     let t = TestSetup::new().await;
@@ -1491,7 +2694,7 @@ pub mod tests {
       .buckets
       .npm_bucket
       .bucket
-      .bucket
+      .s3()
       .get_object("@jsr/scope__foo")
       .await
       .unwrap();
@@ -1547,7 +2750,7 @@ pub mod tests {
       .buckets
       .npm_bucket
       .bucket
-      .bucket
+      .s3()
       .get_object(res_url.as_str())
       .await
       .unwrap();
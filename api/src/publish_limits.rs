@@ -0,0 +1,106 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+//! The limits that publishes are checked against.
+//!
+//! Every limit has a registry-wide default. Staff can override any of them for
+//! a scope through the admin API, for example to let a scope publish a package
+//! with unusually large files. Overrides are read from the database whenever
+//! a publish is checked, so they apply to the next publish.
+
+use std::sync::OnceLock;
+
+use crate::db::Database;
+use crate::db::ScopePublishLimits;
+use crate::ids::ScopeName;
+
+/// The most characters any package path may be long, regardless of
+/// [PublishLimits::max_path_length], see [crate::ids::PackagePath::new].
+pub const MAX_PACKAGE_PATH_LENGTH: usize = 155;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublishLimits {
+  /// The most bytes the gzipped tarball of a publish may take up.
+  pub max_tarball_size: u64,
+  /// The most bytes a single file of a package version may take up.
+  pub max_file_size: u64,
+  /// The most bytes all files of a package version together may take up.
+  pub max_total_file_size: u64,
+  /// The most files a package version may contain.
+  pub max_file_count: usize,
+  /// The most directories a file of a package version may be nested in.
+  pub max_path_depth: usize,
+  /// The most characters the path of a file of a package version may be
+  /// long. Paths longer than [MAX_PACKAGE_PATH_LENGTH] are always rejected,
+  /// so a higher limit has no effect. Either way, the paths that are too long
+  /// are reported together with the other path issues of the publish.
+  pub max_path_length: usize,
+  /// The most modules the module graph of the exports of a package version
+  /// may contain.
+  pub max_module_count: usize,
+}
+
+impl Default for PublishLimits {
+  fn default() -> Self {
+    Self {
+      max_tarball_size: 20 * 1024 * 1024,    // 20 MB
+      max_file_size: 20 * 1024 * 1024,       // 20 MB
+      max_total_file_size: 20 * 1024 * 1024, // 20 MB
+      max_file_count: 10_000,
+      max_path_depth: 32,
+      max_path_length: MAX_PACKAGE_PATH_LENGTH,
+      max_module_count: 5_000,
+    }
+  }
+}
+
+static DEFAULT_PUBLISH_LIMITS: OnceLock<PublishLimits> = OnceLock::new();
+
+/// Sets the registry-wide defaults. Only the first call has an effect;
+/// without one, [PublishLimits::default] is used.
+pub fn set_default_publish_limits(limits: PublishLimits) {
+  let _ = DEFAULT_PUBLISH_LIMITS.set(limits);
+}
+
+impl PublishLimits {
+  /// The registry-wide defaults, that apply to scopes without overrides.
+  pub fn registry_defaults() -> Self {
+    DEFAULT_PUBLISH_LIMITS.get().copied().unwrap_or_default()
+  }
+
+  /// The limits that apply to publishes to `scope`.
+  pub async fn for_scope(
+    db: &Database,
+    scope: &ScopeName,
+  ) -> Result<Self, sqlx::Error> {
+    let limits = Self::registry_defaults();
+    Ok(match db.get_scope_publish_limits(scope).await? {
+      Some(overrides) => limits.with_overrides(&overrides),
+      None => limits,
+    })
+  }
+
+  pub fn with_overrides(self, overrides: &ScopePublishLimits) -> Self {
+    Self {
+      max_tarball_size: overrides
+        .max_tarball_size
+        .map_or(self.max_tarball_size, |limit| limit as u64),
+      max_file_size: overrides
+        .max_file_size
+        .map_or(self.max_file_size, |limit| limit as u64),
+      max_total_file_size: overrides
+        .max_total_file_size
+        .map_or(self.max_total_file_size, |limit| limit as u64),
+      max_file_count: overrides
+        .max_file_count
+        .map_or(self.max_file_count, |limit| limit as usize),
+      max_path_depth: overrides
+        .max_path_depth
+        .map_or(self.max_path_depth, |limit| limit as usize),
+      max_path_length: overrides
+        .max_path_length
+        .map_or(self.max_path_length, |limit| limit as usize),
+      max_module_count: overrides
+        .max_module_count
+        .map_or(self.max_module_count, |limit| limit as usize),
+    }
+  }
+}
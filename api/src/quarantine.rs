@@ -0,0 +1,210 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+//! Quarantined package versions are blocked from being downloaded, but kept so
+//! that staff can review them, and release them if a report turns out to be
+//! wrong.
+//!
+//! A quarantined version is left out of the package and npm manifests, and
+//! the API refuses to serve its tarball and source. Its version metadata,
+//! files manifest and npm tarballs are moved from the modules and npm buckets
+//! into the publishing bucket, below [s3_paths::quarantine_directory], so that
+//! the lb can not serve it either. Releasing the version moves them back.
+//!
+//! The artifacts of deleted versions are moved the same way, until the
+//! version is restored or purged.
+//!
+//! The files of versions published before files were deduplicated (see
+//! [crate::module_files]) are stored under their paths rather than listed in a
+//! files manifest, and are left in place.
+
+use url::Url;
+
+use crate::cache_purge::CachePurge;
+use crate::db::Database;
+use crate::ids::PackageName;
+use crate::ids::ScopeName;
+use crate::ids::Version;
+use crate::s3::BucketWithQueue;
+use crate::s3::Buckets;
+use crate::s3::CACHE_CONTROL_IMMUTABLE;
+use crate::s3::ContentEncoding;
+use crate::s3::S3UploadOptions;
+use crate::s3::UploadTaskBody;
+use crate::s3_paths;
+
+/// An object of a package version that is moved into quarantine.
+struct Artifact<'a> {
+  bucket: &'a BucketWithQueue,
+  /// The name of the directory below the quarantine directory that the
+  /// objects of `bucket` are kept in.
+  directory: &'static str,
+  path: String,
+  content_type: &'static str,
+}
+
+async fn artifacts<'a>(
+  db: &Database,
+  buckets: &'a Buckets,
+  scope: &ScopeName,
+  package: &PackageName,
+  version: &Version,
+) -> Result<Vec<Artifact<'a>>, anyhow::Error> {
+  let mut artifacts = vec![
+    Artifact {
+      bucket: &buckets.modules_bucket,
+      directory: "modules",
+      path: s3_paths::version_metadata(scope, package, version),
+      content_type: "application/json",
+    },
+    Artifact {
+      bucket: &buckets.modules_bucket,
+      directory: "modules",
+      path: s3_paths::version_files_manifest(scope, package, version),
+      content_type: "application/json",
+    },
+  ];
+  for tarball in db
+    .list_npm_tarballs_for_version(scope, package, version)
+    .await?
+  {
+    artifacts.push(Artifact {
+      bucket: &buckets.npm_bucket,
+      directory: "npm",
+      path: s3_paths::npm_tarball_path(
+        scope,
+        package,
+        version,
+        tarball.revision as u32,
+      ),
+      content_type: "application/octet-stream",
+    });
+  }
+  Ok(artifacts)
+}
+
+/// Moves the artifacts of a version into quarantine. Artifacts that are not
+/// in the public buckets, like those moved by an earlier attempt, are
+/// skipped.
+pub async fn quarantine_artifacts(
+  db: &Database,
+  buckets: &Buckets,
+  scope: &ScopeName,
+  package: &PackageName,
+  version: &Version,
+) -> Result<(), anyhow::Error> {
+  let directory = s3_paths::quarantine_directory(scope, package, version);
+  for artifact in artifacts(db, buckets, scope, package, version).await? {
+    let Some(bytes) = artifact
+      .bucket
+      .download(artifact.path.clone().into())
+      .await?
+    else {
+      continue;
+    };
+    buckets
+      .publishing_bucket
+      .upload(
+        format!("{directory}{}/{}", artifact.directory, artifact.path).into(),
+        UploadTaskBody::Bytes(bytes),
+        S3UploadOptions {
+          content_type: Some(artifact.content_type.into()),
+          cache_control: None,
+          content_encoding: ContentEncoding::Identity,
+        },
+      )
+      .await?;
+    artifact.bucket.delete_file(artifact.path.into()).await?;
+  }
+  Ok(())
+}
+
+/// Moves the artifacts of a version out of quarantine, back to where they
+/// were published to.
+pub async fn release_artifacts(
+  db: &Database,
+  buckets: &Buckets,
+  scope: &ScopeName,
+  package: &PackageName,
+  version: &Version,
+) -> Result<(), anyhow::Error> {
+  let directory = s3_paths::quarantine_directory(scope, package, version);
+  for artifact in artifacts(db, buckets, scope, package, version).await? {
+    let quarantine_path =
+      format!("{directory}{}/{}", artifact.directory, artifact.path);
+    let Some(bytes) = buckets
+      .publishing_bucket
+      .download(quarantine_path.clone().into())
+      .await?
+    else {
+      continue;
+    };
+    artifact
+      .bucket
+      .upload(
+        artifact.path.into(),
+        UploadTaskBody::Bytes(bytes),
+        S3UploadOptions {
+          content_type: Some(artifact.content_type.into()),
+          cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
+          content_encoding: ContentEncoding::Identity,
+        },
+      )
+      .await?;
+    buckets
+      .publishing_bucket
+      .delete_file(quarantine_path.into())
+      .await?;
+  }
+  Ok(())
+}
+
+/// Purges the artifacts of a version that the lb serves, and the responses of
+/// the API that return its contents, from the CDN cache.
+pub async fn purge_version_caches(
+  db: &Database,
+  cache_purge: &CachePurge,
+  registry_url: &Url,
+  npm_url: &Url,
+  scope: &ScopeName,
+  package: &PackageName,
+  version: &Version,
+) -> Result<(), anyhow::Error> {
+  let mut urls = vec![format!(
+    "{registry_url}{}",
+    s3_paths::version_metadata(scope, package, version)
+  )];
+  for file in db.list_package_files(scope, package, version).await? {
+    urls.push(format!(
+      "{registry_url}{}",
+      s3_paths::file_path(scope, package, version, &file.path)
+    ));
+  }
+  for tarball in db
+    .list_npm_tarballs_for_version(scope, package, version)
+    .await?
+  {
+    urls.push(format!(
+      "{npm_url}{}",
+      s3_paths::npm_tarball_path(
+        scope,
+        package,
+        version,
+        tarball.revision as u32
+      )
+    ));
+  }
+  urls.extend(s3_paths::package_version_api_cache_urls(
+    registry_url,
+    scope,
+    package,
+    version,
+  ));
+  urls.extend(s3_paths::package_version_download_api_cache_urls(
+    registry_url,
+    scope,
+    package,
+    version,
+  ));
+  cache_purge.purge(urls);
+  Ok(())
+}
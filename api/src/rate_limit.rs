@@ -0,0 +1,277 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+//! Request rate limiting of the API.
+//!
+//! Every request is counted against a limit per minute, picked by the
+//! following policies, first match wins:
+//! 1. the override of the token the request was made with,
+//! 2. the override of the scope the request targets, counted separately from
+//!    the principal's other requests,
+//! 3. the tier of the principal: anonymous, user or GitHub Actions, which also
+//!    covers other CI systems authenticated with OpenID Connect.
+//!
+//! The policies are stored in the database and adjusted by staff through the
+//! admin API. Each API instance caches them for up to a minute. The counters
+//! are kept in memory, so each instance also enforces the limits on its own.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use hyper::Body;
+use hyper::Request;
+use hyper::Response;
+use hyper::header::HeaderName;
+use hyper::header::HeaderValue;
+use hyper::header::RETRY_AFTER;
+use routerify::RequestInfo;
+use routerify::prelude::RequestExt;
+use uuid::Uuid;
+
+use crate::api::ApiError;
+use crate::db::Database;
+use crate::db::RateLimitTier;
+use crate::iam::IamInfo;
+use crate::iam::Principal;
+use crate::ids::ScopeName;
+use crate::util::ApiResult;
+use crate::util::target_scope;
+
+const WINDOW_SECS: u64 = 60;
+
+static RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+static RATELIMIT_REMAINING: HeaderName =
+  HeaderName::from_static("ratelimit-remaining");
+static RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+/// The rate limits in effect, as loaded from the database.
+struct RateLimitPolicies {
+  tiers: HashMap<RateLimitTier, u32>,
+  scopes: HashMap<ScopeName, u32>,
+  tokens: HashMap<Uuid, u32>,
+}
+
+pub struct RateLimiter {
+  policies: moka::future::Cache<(), Arc<RateLimitPolicies>>,
+  /// Request counts per bucket and window.
+  windows: moka::future::Cache<String, Arc<AtomicU32>>,
+  /// See [`client_ip`].
+  trusted_proxy_hops: usize,
+}
+
+impl RateLimiter {
+  pub fn new(trusted_proxy_hops: usize) -> Self {
+    Self {
+      policies: moka::future::Cache::builder()
+        .max_capacity(1)
+        .time_to_live(Duration::from_secs(60))
+        .build(),
+      windows: moka::future::Cache::builder()
+        .max_capacity(1_000_000)
+        .time_to_live(Duration::from_secs(WINDOW_SECS * 2))
+        .build(),
+      trusted_proxy_hops,
+    }
+  }
+
+  async fn policies(
+    &self,
+    db: &Database,
+  ) -> Result<Arc<RateLimitPolicies>, ApiError> {
+    self
+      .policies
+      .try_get_with((), async {
+        let (tiers, scopes, tokens) = db.list_rate_limits().await?;
+        Ok::<_, sqlx::Error>(Arc::new(RateLimitPolicies {
+          tiers: tiers
+            .into_iter()
+            .map(|tier| (tier.tier, tier.requests_per_minute as u32))
+            .collect(),
+          scopes: scopes
+            .into_iter()
+            .map(|scope| (scope.scope, scope.requests_per_minute as u32))
+            .collect(),
+          tokens: tokens
+            .into_iter()
+            .map(|token| (token.token_id, token.requests_per_minute as u32))
+            .collect(),
+        }))
+      })
+      .await
+      .map_err(|err| ApiError::from(anyhow::anyhow!(err)))
+  }
+
+  /// Makes the next request reload the policies. Other instances pick up the
+  /// change once their cache expires.
+  pub async fn invalidate_policies(&self) {
+    self.policies.invalidate(&()).await;
+  }
+
+  /// Counts a request against `bucket`.
+  async fn hit(&self, bucket: &str, limit: u32) -> RateLimitStatus {
+    let now = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap()
+      .as_secs();
+    let window = now / WINDOW_SECS;
+    let counter = self
+      .windows
+      .get_with(format!("{bucket}:{window}"), async {
+        Arc::new(AtomicU32::new(0))
+      })
+      .await;
+    let count = counter.fetch_add(1, Ordering::Relaxed) + 1;
+
+    RateLimitStatus {
+      limit,
+      remaining: limit.saturating_sub(count),
+      reset: (window + 1) * WINDOW_SECS - now,
+      exceeded: count > limit,
+    }
+  }
+}
+
+/// The state of the rate limit a request was counted against, reported in the
+/// `RateLimit-*` headers of the response.
+#[derive(Clone, Debug)]
+pub struct RateLimitStatus {
+  pub limit: u32,
+  pub remaining: u32,
+  /// Seconds until the limit resets.
+  pub reset: u64,
+  pub exceeded: bool,
+}
+
+/// The client IP as seen by the load balancer, for keying anonymous requests.
+/// The load balancer replaces `x-forwarded-for` with the client IP, and each
+/// of the `trusted_proxy_hops` proxies between it and the API appends the
+/// address it received the request from, like Cloud Run does with the address
+/// of the load balancer. Entries further left can't be trusted.
+fn client_ip(req: &Request<Body>, trusted_proxy_hops: usize) -> &str {
+  req
+    .headers()
+    .get("x-forwarded-for")
+    .and_then(|ips| ips.to_str().ok())
+    .and_then(|ips| ips.rsplit(',').nth(trusted_proxy_hops))
+    .map(str::trim)
+    .filter(|ip| !ip.is_empty())
+    .unwrap_or("unknown")
+}
+
+/// Must run after `auth_middleware`, as the limits depend on the principal.
+pub async fn rate_limit_middleware(
+  req: Request<Body>,
+) -> ApiResult<Request<Body>> {
+  let limiter = req.data::<RateLimiter>().unwrap();
+  let db = req.data::<Database>().unwrap();
+  let iam_info = req.context::<IamInfo>().unwrap();
+
+  let (tier, principal) = match &iam_info.principal {
+    Principal::User(user) => (
+      RateLimitTier::User,
+      match iam_info.token_id {
+        Some(token_id) => format!("token:{token_id}"),
+        None => format!("user:{}", user.id),
+      },
+    ),
+    Principal::GitHubActions { repo_id, .. } => {
+      (RateLimitTier::GithubActions, format!("repo:{repo_id}"))
+    }
+    // Other CI systems share the tier of GitHub Actions.
+    Principal::Oidc(publisher) => (
+      RateLimitTier::GithubActions,
+      format!("oidc:{}:{}", publisher.issuer, publisher.subject),
+    ),
+    Principal::Anonymous => (
+      RateLimitTier::Anonymous,
+      format!("ip:{}", client_ip(&req, limiter.trusted_proxy_hops)),
+    ),
+  };
+
+  let policies = limiter.policies(db).await?;
+  let token_limit = iam_info
+    .token_id
+    .and_then(|token_id| policies.tokens.get(&token_id));
+  let scope = target_scope(req.uri().path());
+  let scope_limit = scope
+    .as_ref()
+    .and_then(|scope| policies.scopes.get(scope).map(|limit| (scope, limit)));
+  let (bucket, limit) = if let Some(limit) = token_limit {
+    (principal, *limit)
+  } else if let Some((scope, limit)) = scope_limit {
+    (format!("{principal}:scope:{scope}"), *limit)
+  } else {
+    // Tiers missing from the database are not limited.
+    let Some(limit) = policies.tiers.get(&tier) else {
+      return Ok(req);
+    };
+    (principal, *limit)
+  };
+
+  let status = limiter.hit(&bucket, limit).await;
+  let exceeded = status.exceeded;
+  req.set_context(status);
+  if exceeded {
+    return Err(ApiError::RateLimitExceeded { limit });
+  }
+
+  Ok(req)
+}
+
+/// Adds the `RateLimit-*` headers to every response, including errors.
+pub async fn rate_limit_headers_middleware(
+  mut res: Response<Body>,
+  req_info: RequestInfo,
+) -> ApiResult<Response<Body>> {
+  if let Some(status) = req_info.context::<RateLimitStatus>() {
+    let headers = res.headers_mut();
+    headers.insert(RATELIMIT_LIMIT.clone(), HeaderValue::from(status.limit));
+    headers.insert(
+      RATELIMIT_REMAINING.clone(),
+      HeaderValue::from(status.remaining),
+    );
+    headers.insert(RATELIMIT_RESET.clone(), HeaderValue::from(status.reset));
+    if status.exceeded {
+      headers.insert(RETRY_AFTER, HeaderValue::from(status.reset));
+    }
+  }
+  Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::Body;
+  use hyper::Request;
+
+  use super::client_ip;
+
+  fn request(forwarded_for: Option<&str>) -> Request<Body> {
+    let mut req = Request::builder();
+    if let Some(forwarded_for) = forwarded_for {
+      req = req.header("x-forwarded-for", forwarded_for);
+    }
+    req.body(Body::empty()).unwrap()
+  }
+
+  #[test]
+  fn client_ip_skips_trusted_proxies() {
+    // The load balancer set the client IP, and Cloud Run appended the address
+    // of the load balancer.
+    let req = request(Some("1.2.3.4, 172.64.0.1"));
+    assert_eq!(client_ip(&req, 1), "1.2.3.4");
+    assert_eq!(client_ip(&req, 0), "172.64.0.1");
+
+    // A value the client sent is never picked, however long it is.
+    let req = request(Some("6.6.6.6, 7.7.7.7, 1.2.3.4, 172.64.0.1"));
+    assert_eq!(client_ip(&req, 1), "1.2.3.4");
+
+    // The load balancer did not know the client IP.
+    let req = request(Some("172.64.0.1"));
+    assert_eq!(client_ip(&req, 1), "unknown");
+    let req = request(None);
+    assert_eq!(client_ip(&req, 0), "unknown");
+  }
+}
@@ -1,4 +1,10 @@
 // Copyright 2024 the JSR authors. All rights reserved. MIT license.
+use crate::s3_policy::BucketMetrics;
+use crate::s3_policy::CircuitBreaker;
+use crate::s3_policy::MAX_ATTEMPTS;
+use crate::s3_policy::StorageOperation;
+use crate::s3_policy::StorageOperationMetrics;
+use crate::s3_policy::retry_delay;
 use crate::task_queue::DynamicBackgroundTaskQueue;
 use crate::task_queue::RestartableTask;
 use crate::task_queue::RestartableTaskResult;
@@ -12,9 +18,14 @@ use futures::join;
 use hyper::StatusCode;
 use s3::serde_types::ListBucketResult;
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
 use thiserror::Error;
 use tracing::instrument;
 
@@ -42,6 +53,11 @@ pub const CACHE_CONTROL_MANIFEST: &str = "public, max-age=60, s-maxage=60";
 
 const HTTP_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// The longest a signed URL can be valid for. This is the limit of SigV4
+/// presigned URLs.
+pub const MAX_SIGNED_URL_EXPIRY: Duration =
+  Duration::from_secs(60 * 60 * 24 * 7);
+
 #[derive(Clone)]
 pub struct Buckets {
   pub publishing_bucket: BucketWithQueue,
@@ -65,32 +81,76 @@ pub enum S3Error {
   S3(#[from] s3::error::S3Error),
   #[error("stream failed: {0}")]
   Stream(anyhow::Error),
+  #[error("compression failed: {0}")]
+  Compression(std::io::Error),
+  #[error("file system error: {0}")]
+  Fs(std::io::Error),
+  #[error("too many requests to S3 failed recently, try again later")]
+  CircuitOpen,
 }
 
 impl S3Error {
-  /// 408, 429, and 5xx errors are retryable.
+  /// 408, 429, and 5xx errors are retryable, and so are requests that failed
+  /// to connect or timed out.
   /// https://cloud.google.com/storage/docs/retry-strategy
   pub fn is_retryable(&self) -> bool {
-    matches!(
-      self,
-      Self::RequestTimeout | Self::TooManyRequests | Self::Server(_)
-    )
+    match self {
+      Self::RequestTimeout | Self::TooManyRequests | Self::Server(_) => true,
+      Self::S3(s3::error::S3Error::Reqwest(err)) => {
+        err.is_timeout() || err.is_connect() || err.is_body()
+      }
+      Self::S3(s3::error::S3Error::Io(_)) => true,
+      _ => false,
+    }
   }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct S3UploadOptions<'a> {
   pub content_type: Option<Cow<'a, str>>,
   pub cache_control: Option<Cow<'a, str>>,
-  pub gzip_encoded: bool,
+  pub content_encoding: ContentEncoding,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncoding {
+  Identity,
+  Gzip,
+  Brotli,
+  Zstd,
+}
+
+impl ContentEncoding {
+  pub fn as_str(self) -> &'static str {
+    match self {
+      ContentEncoding::Identity => "identity",
+      ContentEncoding::Gzip => "gzip",
+      ContentEncoding::Brotli => "br",
+      ContentEncoding::Zstd => "zstd",
+    }
+  }
 }
 
+/// Suffix of the path of the brotli compressed copy of a file uploaded with
+/// [BucketWithQueue::upload_precompressed].
+pub const PRECOMPRESSED_BROTLI_SUFFIX: &str = ".br";
+/// Suffix of the path of the zstd compressed copy of a file uploaded with
+/// [BucketWithQueue::upload_precompressed].
+pub const PRECOMPRESSED_ZSTD_SUFFIX: &str = ".zst";
+
 #[derive(Clone)]
 pub struct Bucket {
-  pub(crate) bucket: Box<s3::Bucket>,
+  backend: BucketBackend,
   pub(crate) name: String,
 }
 
+#[derive(Clone)]
+enum BucketBackend {
+  S3(Box<s3::Bucket>),
+  /// Files are stored below this directory on the local file system.
+  Fs(PathBuf),
+}
+
 impl Bucket {
   pub fn new(
     name: String,
@@ -101,7 +161,48 @@ impl Bucket {
       .with_path_style()
       .with_request_timeout(HTTP_CONNECT_TIMEOUT)?;
 
-    Ok(Self { bucket, name })
+    Ok(Self {
+      backend: BucketBackend::S3(bucket),
+      name,
+    })
+  }
+
+  /// Creates a bucket that stores files below `root` on the local file
+  /// system instead of in S3, so that the API can run end-to-end in
+  /// development and tests without an S3 emulator. Content types, cache
+  /// control and content encodings are not stored.
+  pub fn new_fs(name: String, root: PathBuf) -> Self {
+    Self {
+      backend: BucketBackend::Fs(root),
+      name,
+    }
+  }
+
+  /// The location of the file at `path` below `root`. Paths that would
+  /// escape `root` are rejected.
+  fn fs_path(root: &Path, path: &str) -> Result<PathBuf, S3Error> {
+    let relative = Path::new(path.trim_start_matches('/'));
+    if !relative
+      .components()
+      .all(|component| matches!(component, Component::Normal(_)))
+    {
+      return Err(S3Error::Client(StatusCode::BAD_REQUEST));
+    }
+    Ok(root.join(relative))
+  }
+
+  async fn fs_write(
+    root: &Path,
+    path: &str,
+    data: &[u8],
+  ) -> Result<(), S3Error> {
+    let path = Bucket::fs_path(root, path)?;
+    if let Some(parent) = path.parent() {
+      tokio::fs::create_dir_all(parent)
+        .await
+        .map_err(S3Error::Fs)?;
+    }
+    tokio::fs::write(path, data).await.map_err(S3Error::Fs)
   }
 
   fn check_status(status_code: u16) -> Result<(), S3Error> {
@@ -135,14 +236,35 @@ impl Bucket {
     .await?;
 
     Ok(Self {
-      bucket: bucket.bucket,
+      backend: BucketBackend::S3(bucket.bucket),
       name,
     })
   }
 
+  /// The underlying S3 bucket, for tests that inspect object metadata.
+  #[cfg(test)]
+  pub fn s3(&self) -> &s3::Bucket {
+    match &self.backend {
+      BucketBackend::S3(bucket) => bucket,
+      BucketBackend::Fs(_) => {
+        panic!("bucket {} is not backed by S3", self.name)
+      }
+    }
+  }
+
   #[instrument(name = "s3::Bucket::download", skip(self), err, fields(bucket = %self.name))]
   pub async fn download(&self, path: &str) -> Result<Option<Bytes>, S3Error> {
-    let resp = self.bucket.get_object(path).await?;
+    let bucket = match &self.backend {
+      BucketBackend::S3(bucket) => bucket,
+      BucketBackend::Fs(root) => {
+        return match tokio::fs::read(Bucket::fs_path(root, path)?).await {
+          Ok(data) => Ok(Some(data.into())),
+          Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+          Err(err) => Err(S3Error::Fs(err)),
+        };
+      }
+    };
+    let resp = bucket.get_object(path).await?;
 
     if resp.status_code() == 404 {
       return Ok(None);
@@ -159,11 +281,24 @@ impl Bucket {
     offset: Option<usize>,
   ) -> Result<Option<impl Stream<Item = Result<Bytes, S3Error>> + use<>>, S3Error>
   {
+    let bucket = match &self.backend {
+      BucketBackend::S3(bucket) => bucket,
+      BucketBackend::Fs(_) => {
+        let Some(data) = self.download(path).await? else {
+          return Ok(None);
+        };
+        let offset = offset.unwrap_or(0);
+        if offset > data.len() {
+          return Ok(None);
+        }
+        let data = data.slice(offset..);
+        let stream =
+          futures::stream::once(async move { Ok::<_, S3Error>(data) });
+        return Ok(Some(stream.boxed()));
+      }
+    };
     if let Some(offset) = offset {
-      let resp = self
-        .bucket
-        .get_object_range(path, offset as _, None)
-        .await?;
+      let resp = bucket.get_object_range(path, offset as _, None).await?;
       if resp.status_code() == 404 || resp.status_code() == 416 {
         return Ok(None);
       }
@@ -172,7 +307,7 @@ impl Bucket {
         futures::stream::once(async { Ok(resp.into_bytes()) }).boxed(),
       ))
     } else {
-      let resp = self.bucket.get_object_stream(path).await?;
+      let resp = bucket.get_object_stream(path).await?;
       if resp.status_code == 404 || resp.status_code == 416 {
         return Ok(None);
       }
@@ -188,14 +323,15 @@ impl Bucket {
     data: Bytes,
     options: &S3UploadOptions<'_>,
   ) -> Result<(), S3Error> {
-    let mut builder = self
-      .bucket
+    let bucket = match &self.backend {
+      BucketBackend::S3(bucket) => bucket,
+      BucketBackend::Fs(root) => {
+        return Bucket::fs_write(root, path, &data).await;
+      }
+    };
+    let mut builder = bucket
       .put_object_builder(path, data.as_ref())
-      .with_content_encoding(if options.gzip_encoded {
-        "gzip"
-      } else {
-        "identity"
-      })?;
+      .with_content_encoding(options.content_encoding.as_str())?;
 
     if let Some(content_type) = &options.content_type {
       builder = builder.with_content_type(content_type);
@@ -222,14 +358,19 @@ impl Bucket {
     stream: &mut (impl tokio::io::AsyncRead + Unpin + Send),
     options: &S3UploadOptions<'_>,
   ) -> Result<(), S3Error> {
-    let mut builder = self
-      .bucket
+    let bucket = match &self.backend {
+      BucketBackend::S3(bucket) => bucket,
+      BucketBackend::Fs(root) => {
+        let mut data = Vec::new();
+        tokio::io::AsyncReadExt::read_to_end(stream, &mut data)
+          .await
+          .map_err(S3Error::Fs)?;
+        return Bucket::fs_write(root, path, &data).await;
+      }
+    };
+    let mut builder = bucket
       .put_object_stream_builder(path)
-      .with_content_encoding(if options.gzip_encoded {
-        "gzip"
-      } else {
-        "identity"
-      })?;
+      .with_content_encoding(options.content_encoding.as_str())?;
 
     if let Some(content_type) = &options.content_type {
       builder = builder.with_content_type(content_type);
@@ -245,17 +386,120 @@ impl Bucket {
   }
 
   #[instrument(name = "s3::Bucket::list", skip(self), err, fields(bucket = %self.name))]
-  pub async fn list(
+  /// Lists the paths of all files whose path starts with `prefix`.
+  pub async fn list(&self, prefix: &str) -> Result<Vec<String>, S3Error> {
+    let bucket = match &self.backend {
+      BucketBackend::S3(bucket) => bucket,
+      BucketBackend::Fs(root) => return Bucket::fs_list(root, prefix).await,
+    };
+    let list: Vec<ListBucketResult> =
+      bucket.list(prefix.to_string(), None).await?;
+    Ok(
+      list
+        .into_iter()
+        .flat_map(|page| page.contents)
+        .map(|object| object.key)
+        .collect(),
+    )
+  }
+
+  async fn fs_list(root: &Path, prefix: &str) -> Result<Vec<String>, S3Error> {
+    // Only walk the deepest directory that contains all matching files.
+    let dir = prefix.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+    let mut pending = vec![Bucket::fs_path(root, dir)?];
+    let mut paths = vec![];
+    while let Some(dir) = pending.pop() {
+      let mut entries = match tokio::fs::read_dir(&dir).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+        Err(err) => return Err(S3Error::Fs(err)),
+      };
+      while let Some(entry) = entries.next_entry().await.map_err(S3Error::Fs)? {
+        let file_type = entry.file_type().await.map_err(S3Error::Fs)?;
+        if file_type.is_dir() {
+          pending.push(entry.path());
+          continue;
+        }
+        let path = entry.path();
+        let Some(path) =
+          path.strip_prefix(root).ok().and_then(|path| path.to_str())
+        else {
+          continue;
+        };
+        let path = path.replace(std::path::MAIN_SEPARATOR, "/");
+        if path.starts_with(prefix) {
+          paths.push(path);
+        }
+      }
+    }
+    paths.sort();
+    Ok(paths)
+  }
+
+  #[instrument(name = "s3::Bucket::copy", skip(self), err, fields(bucket = %self.name))]
+  pub async fn copy(&self, from: &str, to: &str) -> Result<(), S3Error> {
+    let bucket = match &self.backend {
+      BucketBackend::S3(bucket) => bucket,
+      BucketBackend::Fs(root) => {
+        let data = tokio::fs::read(Bucket::fs_path(root, from)?)
+          .await
+          .map_err(S3Error::Fs)?;
+        return Bucket::fs_write(root, to, &data).await;
+      }
+    };
+    let status_code = bucket.copy_object_internal(from, to).await?;
+    Bucket::check_status(status_code)?;
+    Ok(())
+  }
+
+  /// Create a URL that allows downloading the file at `path` straight from the
+  /// bucket until it expires, so that private files can be handed out without
+  /// proxying their bytes through the API. Anyone holding the URL can use it,
+  /// so only return it to callers that are allowed to read the file. If
+  /// `filename` is set, browsers save the download under that name.
+  #[instrument(name = "s3::Bucket::signed_url", skip(self), err, fields(bucket = %self.name))]
+  pub async fn signed_url(
     &self,
     path: &str,
-  ) -> Result<Vec<ListBucketResult>, S3Error> {
-    let list = self.bucket.list(path.to_string(), None).await?;
-    Ok(list)
+    expires_in: Duration,
+    filename: Option<&str>,
+  ) -> Result<String, S3Error> {
+    let bucket = match &self.backend {
+      BucketBackend::S3(bucket) => bucket,
+      // There is nothing to sign locally, so point at the file itself.
+      BucketBackend::Fs(root) => {
+        let path = std::path::absolute(Bucket::fs_path(root, path)?)
+          .map_err(S3Error::Fs)?;
+        return Ok(format!("file://{}", path.display()));
+      }
+    };
+    let expires_in = expires_in.min(MAX_SIGNED_URL_EXPIRY);
+    let queries = filename.map(|filename| {
+      HashMap::from([(
+        "response-content-disposition".to_string(),
+        format!("attachment; filename=\"{filename}\""),
+      )])
+    });
+    let url = bucket
+      .presign_get(path, expires_in.as_secs() as u32, queries)
+      .await?;
+    Ok(url)
   }
 
   #[instrument(name = "s3::Bucket::delete", skip(self), err, fields(bucket = %self.name))]
   pub async fn delete_file(&self, path: &str) -> Result<bool, S3Error> {
-    let resp = self.bucket.delete_object(path).await?;
+    let bucket = match &self.backend {
+      BucketBackend::S3(bucket) => bucket,
+      BucketBackend::Fs(root) => {
+        return match tokio::fs::remove_file(Bucket::fs_path(root, path)?).await
+        {
+          Ok(()) => Ok(false),
+          Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(true),
+          Err(err) => Err(S3Error::Fs(err)),
+        };
+      }
+    };
+    let resp = bucket.delete_object(path).await?;
 
     if resp.status_code() == 404 {
       return Ok(true);
@@ -273,6 +517,8 @@ pub struct BucketWithQueue {
   download_queue: DynamicBackgroundTaskQueue<DownloadTask>,
   delete_queue: DynamicBackgroundTaskQueue<DeleteFileTask>,
   list_queue: DynamicBackgroundTaskQueue<ListDirectoryTask>,
+  circuit_breaker: Arc<CircuitBreaker>,
+  metrics: Arc<BucketMetrics>,
 }
 
 impl BucketWithQueue {
@@ -283,7 +529,58 @@ impl BucketWithQueue {
       download_queue: DynamicBackgroundTaskQueue::default(),
       delete_queue: DynamicBackgroundTaskQueue::default(),
       list_queue: DynamicBackgroundTaskQueue::default(),
+      circuit_breaker: Arc::new(CircuitBreaker::default()),
+      metrics: Arc::new(BucketMetrics::default()),
+    }
+  }
+
+  pub fn metrics(&self) -> Vec<StorageOperationMetrics> {
+    self.metrics.snapshot()
+  }
+
+  pub fn is_circuit_open(&self) -> bool {
+    self.circuit_breaker.is_open()
+  }
+
+  fn retries(&self) -> TaskRetries {
+    TaskRetries {
+      retries: 0,
+      metrics: self.metrics.clone(),
+    }
+  }
+
+  /// Sends a request, unless the circuit is open, and records its outcome.
+  async fn run<T>(
+    &self,
+    operation: StorageOperation,
+    request: impl Future<Output = Result<T, S3Error>>,
+  ) -> Result<T, S3Error> {
+    if !self.circuit_breaker.allow() {
+      self.metrics.record_rejected(operation);
+      return Err(S3Error::CircuitOpen);
+    }
+    let start = Instant::now();
+    let res = request.await;
+    let elapsed = start.elapsed();
+    self.metrics.record(operation, elapsed, res.is_ok());
+    crate::metrics::observe_storage_request(
+      &self.bucket.name,
+      operation,
+      elapsed,
+      res.is_ok(),
+    );
+    match &res {
+      Err(err) if err.is_retryable() => {
+        if self.circuit_breaker.record_failure() {
+          tracing::error!(
+            bucket = %self.bucket.name,
+            "requests to bucket keep failing, opening the circuit: {err}"
+          );
+        }
+      }
+      _ => self.circuit_breaker.record_success(),
     }
+    res
   }
 
   #[instrument(
@@ -298,13 +595,71 @@ impl BucketWithQueue {
     options: S3UploadOptions<'static>,
   ) -> Result<(), S3Error> {
     self
-      .upload_queue
-      .run(UploadTask {
-        bucket: self.bucket.clone(),
-        path,
-        body,
-        options,
-      })
+      .run(
+        StorageOperation::Upload,
+        self.upload_queue.run(UploadTask {
+          bucket: self.bucket.clone(),
+          path,
+          body,
+          options,
+          retries: self.retries(),
+        }),
+      )
+      .await
+  }
+
+  /// Uploads `data`, along with brotli and zstd compressed copies of it at the
+  /// same path with [PRECOMPRESSED_BROTLI_SUFFIX] and
+  /// [PRECOMPRESSED_ZSTD_SUFFIX] appended. The lb serves the copies to clients
+  /// that accept those encodings, so that the CDN does not have to compress
+  /// the file on every cache miss.
+  #[instrument(
+    name = "BucketWithQueue::upload_precompressed",
+    skip(self, data, options),
+    err
+  )]
+  pub async fn upload_precompressed(
+    &self,
+    path: Arc<str>,
+    data: Bytes,
+    options: S3UploadOptions<'static>,
+  ) -> Result<(), S3Error> {
+    let uncompressed = data.clone();
+    let (brotli, zstd) = tokio::task::spawn_blocking(move || {
+      Ok::<_, std::io::Error>((
+        compress_brotli(&uncompressed)?,
+        zstd::bulk::compress(&uncompressed, PRECOMPRESSED_ZSTD_LEVEL)?,
+      ))
+    })
+    .await
+    .unwrap()
+    .map_err(S3Error::Compression)?;
+
+    let brotli_options = S3UploadOptions {
+      content_encoding: ContentEncoding::Brotli,
+      ..options.clone()
+    };
+    let zstd_options = S3UploadOptions {
+      content_encoding: ContentEncoding::Zstd,
+      ..options.clone()
+    };
+    futures::try_join!(
+      self.upload(
+        format!("{path}{PRECOMPRESSED_BROTLI_SUFFIX}").into(),
+        UploadTaskBody::Bytes(brotli.into()),
+        brotli_options,
+      ),
+      self.upload(
+        format!("{path}{PRECOMPRESSED_ZSTD_SUFFIX}").into(),
+        UploadTaskBody::Bytes(zstd.into()),
+        zstd_options,
+      ),
+    )?;
+
+    // The original goes last, so that a file that exists always has its
+    // precompressed copies too.
+    self
+      .upload(path, UploadTaskBody::Bytes(data), options)
       .await
   }
 
@@ -315,11 +670,14 @@ impl BucketWithQueue {
     path: Arc<str>,
   ) -> Result<Option<Bytes>, S3Error> {
     self
-      .download_queue
-      .run(DownloadTask {
-        bucket: self.bucket.clone(),
-        path,
-      })
+      .run(
+        StorageOperation::Download,
+        self.download_queue.run(DownloadTask {
+          bucket: self.bucket.clone(),
+          path,
+          retries: self.retries(),
+        }),
+      )
       .await
   }
 
@@ -327,28 +685,63 @@ impl BucketWithQueue {
   #[instrument(name = "BucketWithQueue::delete_file", skip(self), err)]
   pub async fn delete_file(&self, path: Arc<str>) -> Result<bool, S3Error> {
     self
-      .delete_queue
-      .run(DeleteFileTask {
-        bucket: self.bucket.clone(),
-        path,
-      })
+      .run(
+        StorageOperation::Delete,
+        self.delete_queue.run(DeleteFileTask {
+          bucket: self.bucket.clone(),
+          path,
+          retries: self.retries(),
+        }),
+      )
       .await
   }
 
+  /// Copies all files below the `from` prefix to the `to` prefix.
+  #[instrument(name = "BucketWithQueue::copy_directory", skip(self), err)]
+  pub async fn copy_directory(
+    &self,
+    from: Arc<str>,
+    to: Arc<str>,
+  ) -> Result<(), S3Error> {
+    let list = self.list(from.clone()).await?;
+
+    let stream = futures::stream::iter(list)
+      .map(|key| {
+        let target = format!("{to}{}", &key[from.len()..]);
+        async move {
+          self
+            .run(StorageOperation::Copy, async {
+              // Copies don't go through a queue, so they are retried here.
+              let mut retries = self.retries();
+              loop {
+                let res = self.bucket.copy(&key, &target).await;
+                match &res {
+                  Err(err) if err.is_retryable() => {
+                    if !retries.retry(StorageOperation::Copy, err).await {
+                      break res;
+                    }
+                  }
+                  _ => break res,
+                }
+              }
+            })
+            .await
+        }
+      })
+      .buffer_unordered(64);
+    let _ = stream.try_collect::<Vec<_>>().await?;
+
+    Ok(())
+  }
+
   #[allow(dead_code)]
   #[instrument(name = "BucketWithQueue::delete_directory", skip(self), err)]
   pub async fn delete_directory(&self, path: Arc<str>) -> Result<(), S3Error> {
-    let list = self
-      .list_queue
-      .run(ListDirectoryTask {
-        bucket: self.bucket.clone(),
-        path,
-      })
-      .await?;
+    let list = self.list(path).await?;
 
     if !list.is_empty() {
       let stream = futures::stream::iter(list)
-        .map(|item| self.delete_file(item.name.into()))
+        .map(|key| self.delete_file(key.into()))
         .buffer_unordered(64);
 
       let _ = stream.try_collect::<Vec<_>>().await?;
@@ -356,6 +749,63 @@ impl BucketWithQueue {
 
     Ok(())
   }
+
+  /// Lists the paths of all files whose path starts with `path`.
+  #[instrument(name = "BucketWithQueue::list", skip(self), err)]
+  pub async fn list(&self, path: Arc<str>) -> Result<Vec<String>, S3Error> {
+    self
+      .run(
+        StorageOperation::List,
+        self.list_queue.run(ListDirectoryTask {
+          bucket: self.bucket.clone(),
+          path,
+          retries: self.retries(),
+        }),
+      )
+      .await
+  }
+}
+
+/// Counts how often a request was retried. The [DynamicBackgroundTaskQueue]
+/// slows down when a task backs off, and the delay before each retry spreads
+/// out the retries of requests that failed together.
+struct TaskRetries {
+  retries: u32,
+  metrics: Arc<BucketMetrics>,
+}
+
+impl TaskRetries {
+  /// Whether a request that failed with `err` should be sent again. If so,
+  /// waits before returning.
+  async fn retry(
+    &mut self,
+    operation: StorageOperation,
+    err: &S3Error,
+  ) -> bool {
+    if !err.is_retryable() || self.retries + 1 >= MAX_ATTEMPTS {
+      return false;
+    }
+    self.metrics.record_retry(operation);
+    tokio::time::sleep(retry_delay(self.retries)).await;
+    self.retries += 1;
+    true
+  }
+}
+
+/// Files are compressed once and served many times, so they are compressed
+/// with high levels. Brotli's highest quality, 11, is an order of magnitude
+/// slower than 9 for only a little gain, which adds up for large packages.
+const PRECOMPRESSED_BROTLI_QUALITY: i32 = 9;
+const PRECOMPRESSED_ZSTD_LEVEL: i32 = 19;
+
+fn compress_brotli(data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+  let params = brotli::enc::BrotliEncoderParams {
+    quality: PRECOMPRESSED_BROTLI_QUALITY,
+    ..Default::default()
+  };
+  let mut compressed = Vec::new();
+  brotli::BrotliCompress(&mut &data[..], &mut compressed, &params)?;
+  Ok(compressed)
 }
 
 struct UploadTask {
@@ -363,6 +813,7 @@ struct UploadTask {
   path: Arc<str>,
   body: UploadTaskBody,
   options: S3UploadOptions<'static>,
+  retries: TaskRetries,
 }
 
 pub enum UploadTaskBody {
@@ -382,21 +833,26 @@ impl RestartableTask for UploadTask {
 
   fn run(self) -> Self::Fut {
     async move {
+      let mut retries = self.retries;
       match self.body {
         UploadTaskBody::Bytes(data) => {
           let bytes = data.clone();
           let res = self.bucket.upload(&self.path, data, &self.options).await;
           match res {
             Ok(()) => RestartableTaskResult::Ok(()),
-            Err(e) if e.is_retryable() => {
-              RestartableTaskResult::Backoff(UploadTask {
-                bucket: self.bucket,
-                path: self.path,
-                body: UploadTaskBody::Bytes(bytes),
-                options: self.options,
-              })
+            Err(e) => {
+              if retries.retry(StorageOperation::Upload, &e).await {
+                RestartableTaskResult::Backoff(UploadTask {
+                  bucket: self.bucket,
+                  path: self.path,
+                  body: UploadTaskBody::Bytes(bytes),
+                  options: self.options,
+                  retries,
+                })
+              } else {
+                RestartableTaskResult::Error(e)
+              }
             }
-            Err(e) => RestartableTaskResult::Error(e),
           }
         }
         UploadTaskBody::Stream(mut stream) => {
@@ -423,13 +879,18 @@ impl RestartableTask for UploadTask {
           let (stream_res, upload_res) = join!(stream_fut, upload_fut);
           match (stream_res, upload_res) {
             (Ok(_), Ok(())) => RestartableTaskResult::Ok(()),
-            (Ok(retry_buffer), Err(e)) if e.is_retryable() => {
-              RestartableTaskResult::Backoff(UploadTask {
-                bucket: self.bucket,
-                path: self.path,
-                body: UploadTaskBody::Bytes(Bytes::from(retry_buffer)),
-                options: self.options,
-              })
+            (Ok(retry_buffer), Err(e)) => {
+              if retries.retry(StorageOperation::Upload, &e).await {
+                RestartableTaskResult::Backoff(UploadTask {
+                  bucket: self.bucket,
+                  path: self.path,
+                  body: UploadTaskBody::Bytes(Bytes::from(retry_buffer)),
+                  options: self.options,
+                  retries,
+                })
+              } else {
+                RestartableTaskResult::Error(e)
+              }
             }
             (_, Err(e)) => RestartableTaskResult::Error(e),
             (Err(e), _) => RestartableTaskResult::Error(S3Error::Stream(e)),
@@ -444,6 +905,7 @@ impl RestartableTask for UploadTask {
 struct DownloadTask {
   bucket: Bucket,
   path: Arc<str>,
+  retries: TaskRetries,
 }
 
 impl RestartableTask for DownloadTask {
@@ -452,18 +914,18 @@ impl RestartableTask for DownloadTask {
   type Fut =
     Pin<Box<dyn Future<Output = RestartableTaskResult<Self>> + Send + 'static>>;
 
-  fn run(self) -> Self::Fut {
+  fn run(mut self) -> Self::Fut {
     async move {
       let res = self.bucket.download(&self.path).await;
       match res {
         Ok(data) => RestartableTaskResult::Ok(data),
-        Err(e) if e.is_retryable() => {
-          RestartableTaskResult::Backoff(DownloadTask {
-            bucket: self.bucket,
-            path: self.path,
-          })
+        Err(e) => {
+          if self.retries.retry(StorageOperation::Download, &e).await {
+            RestartableTaskResult::Backoff(self)
+          } else {
+            RestartableTaskResult::Error(e)
+          }
         }
-        Err(e) => RestartableTaskResult::Error(e),
       }
     }
     .boxed()
@@ -473,6 +935,7 @@ impl RestartableTask for DownloadTask {
 struct DeleteFileTask {
   bucket: Bucket,
   path: Arc<str>,
+  retries: TaskRetries,
 }
 
 impl RestartableTask for DeleteFileTask {
@@ -481,18 +944,18 @@ impl RestartableTask for DeleteFileTask {
   type Fut =
     Pin<Box<dyn Future<Output = RestartableTaskResult<Self>> + Send + 'static>>;
 
-  fn run(self) -> Self::Fut {
+  fn run(mut self) -> Self::Fut {
     async move {
       let res = self.bucket.delete_file(&self.path).await;
       match res {
         Ok(data) => RestartableTaskResult::Ok(data),
-        Err(e) if e.is_retryable() => {
-          RestartableTaskResult::Backoff(DeleteFileTask {
-            bucket: self.bucket,
-            path: self.path,
-          })
+        Err(e) => {
+          if self.retries.retry(StorageOperation::Delete, &e).await {
+            RestartableTaskResult::Backoff(self)
+          } else {
+            RestartableTaskResult::Error(e)
+          }
         }
-        Err(e) => RestartableTaskResult::Error(e),
       }
     }
     .boxed()
@@ -502,26 +965,27 @@ impl RestartableTask for DeleteFileTask {
 struct ListDirectoryTask {
   bucket: Bucket,
   path: Arc<str>,
+  retries: TaskRetries,
 }
 
 impl RestartableTask for ListDirectoryTask {
-  type Ok = Vec<ListBucketResult>;
+  type Ok = Vec<String>;
   type Err = S3Error;
   type Fut =
     Pin<Box<dyn Future<Output = RestartableTaskResult<Self>> + Send + 'static>>;
 
-  fn run(self) -> Self::Fut {
+  fn run(mut self) -> Self::Fut {
     async move {
       let res = self.bucket.list(&self.path).await;
       match res {
         Ok(data) => RestartableTaskResult::Ok(data),
-        Err(e) if e.is_retryable() => {
-          RestartableTaskResult::Backoff(ListDirectoryTask {
-            bucket: self.bucket,
-            path: self.path,
-          })
+        Err(e) => {
+          if self.retries.retry(StorageOperation::List, &e).await {
+            RestartableTaskResult::Backoff(self)
+          } else {
+            RestartableTaskResult::Error(e)
+          }
         }
-        Err(e) => RestartableTaskResult::Error(e),
       }
     }
     .boxed()
@@ -691,7 +1155,7 @@ mod tests {
         &S3UploadOptions {
           content_type: None,
           cache_control: None,
-          gzip_encoded: false,
+          content_encoding: ContentEncoding::Identity,
         },
       )
       .await
@@ -704,4 +1168,55 @@ mod tests {
     let response = bucket.download("does_not_exist.txt").await.unwrap();
     assert!(response.is_none());
   }
+
+  #[tokio::test]
+  async fn fs_upload_download() {
+    let mut root = std::env::temp_dir();
+    root.push(format!("jsr-fs-bucket-{}", uuid::Uuid::new_v4()));
+    let bucket = Bucket::new_fs("testbucket".to_string(), root.clone());
+    let options = S3UploadOptions {
+      content_type: None,
+      cache_control: None,
+      content_encoding: ContentEncoding::Identity,
+    };
+
+    bucket
+      .upload(
+        "a/b/c.txt",
+        "hello world".as_bytes().to_vec().into(),
+        &options,
+      )
+      .await
+      .unwrap();
+    bucket
+      .upload("a/d.txt", "hi".as_bytes().to_vec().into(), &options)
+      .await
+      .unwrap();
+
+    let response = bucket.download("a/b/c.txt").await.unwrap();
+    assert_eq!(response.unwrap().len(), 11);
+    let response = bucket.download("does_not_exist.txt").await.unwrap();
+    assert!(response.is_none());
+
+    assert_eq!(
+      bucket.list("a/").await.unwrap(),
+      vec!["a/b/c.txt", "a/d.txt"]
+    );
+    assert_eq!(bucket.list("a/b").await.unwrap(), vec!["a/b/c.txt"]);
+    assert!(bucket.list("e/").await.unwrap().is_empty());
+
+    bucket.copy("a/d.txt", "e/d.txt").await.unwrap();
+    let response = bucket.download("e/d.txt").await.unwrap();
+    assert_eq!(response.unwrap().len(), 2);
+
+    assert!(!bucket.delete_file("a/d.txt").await.unwrap());
+    assert!(bucket.delete_file("a/d.txt").await.unwrap());
+    assert!(bucket.download("a/d.txt").await.unwrap().is_none());
+
+    // Paths can not escape the bucket directory.
+    let err = bucket.download("../secret.txt").await.unwrap_err();
+    assert!(matches!(err, S3Error::Client(StatusCode::BAD_REQUEST)));
+
+    std::fs::remove_dir_all(root).unwrap();
+  }
 }
@@ -23,6 +23,22 @@ pub fn file_path_root_directory(
   format!("@{scope}/{package_name}/{version}/")
 }
 
+/// A module file, keyed by the hash of its content (`sha256-...`) so that it
+/// is stored once for all the versions that contain it.
+pub fn module_blob_path(hash: &str) -> String {
+  format!("blobs/{hash}")
+}
+
+/// The manifest of a package version that maps the paths of its files to the
+/// hashes of their content. See [crate::module_files].
+pub fn version_files_manifest(
+  scope: &ScopeName,
+  package_name: &PackageName,
+  version: &Version,
+) -> String {
+  format!("@{scope}/{package_name}/{version}_files.json")
+}
+
 pub fn docs_v1_path(
   scope: &ScopeName,
   package_name: &PackageName,
@@ -39,6 +55,25 @@ pub fn docs_v2_path(
   format!("@{scope}/{package_name}/{version}/raw.rmp.gz")
 }
 
+/// The offline documentation archive of a package version, generated on the
+/// first request for it.
+pub fn docs_archive_path(
+  scope: &ScopeName,
+  package_name: &PackageName,
+  version: &Version,
+) -> String {
+  format!("@{scope}/{package_name}/{version}/archive.tar.gz")
+}
+
+/// The documentation of a package version as a single markdown document.
+pub fn docs_markdown_path(
+  scope: &ScopeName,
+  package_name: &PackageName,
+  version: &Version,
+) -> String {
+  format!("@{scope}/{package_name}/{version}/docs.md")
+}
+
 pub fn package_metadata(
   scope: &ScopeName,
   package_name: &PackageName,
@@ -46,6 +81,16 @@ pub fn package_metadata(
   format!("@{scope}/{package_name}/meta.json")
 }
 
+/// An empty object that marks a package as private, so that the lb does not
+/// serve its files from the buckets. See `lb/private_packages.ts`. It is kept
+/// below the directory of the package, so that it moves along with it.
+pub fn private_package_marker(
+  scope: &ScopeName,
+  package_name: &PackageName,
+) -> String {
+  format!("@{scope}/{package_name}/_private")
+}
+
 #[allow(dead_code)]
 pub fn top_level_package_metadata(package_name: &PackageName) -> String {
   format!("{package_name}/meta.json")
@@ -140,6 +185,7 @@ pub fn package_api_cache_urls(
     format!("{pkg}/versions/latest/docs"),
     format!("{pkg}/versions/latest/source"),
     format!("{pkg}/versions/latest/dependencies"),
+    format!("{pkg}/dist_tags"),
     // Scope-level aggregates that surface this package and its latest version.
     format!("api/scopes/{scope}"),
     format!("api/scopes/{scope}/packages"),
@@ -147,6 +193,36 @@ pub fn package_api_cache_urls(
   api_cache_urls(registry_url, &paths)
 }
 
+/// API endpoint URLs of a specific version of `@scope/name`, whose cached
+/// responses change when the version is yanked or unyanked, or when the readme
+/// source of the package changes. They are cached for much longer than the
+/// `latest` ones, as published versions are otherwise immutable.
+pub fn package_version_api_cache_urls(
+  registry_url: &url::Url,
+  scope: &ScopeName,
+  package_name: &PackageName,
+  version: &Version,
+) -> Vec<String> {
+  let version =
+    format!("api/scopes/{scope}/packages/{package_name}/versions/{version}");
+  let paths = [version.clone(), format!("{version}/docs")];
+  api_cache_urls(registry_url, &paths)
+}
+
+/// API endpoint URLs of a specific version of `@scope/name` that return its
+/// contents, which are cached forever unless the version is quarantined.
+pub fn package_version_download_api_cache_urls(
+  registry_url: &url::Url,
+  scope: &ScopeName,
+  package_name: &PackageName,
+  version: &Version,
+) -> Vec<String> {
+  let version =
+    format!("api/scopes/{scope}/packages/{package_name}/versions/{version}");
+  let paths = [format!("{version}/tarball"), format!("{version}/source")];
+  api_cache_urls(registry_url, &paths)
+}
+
 /// API endpoint URLs whose cached responses change when a package is created or
 /// deleted within `scope`. Pass `registry_url` as `https://jsr.io/`.
 pub fn scope_api_cache_urls(
@@ -173,6 +249,33 @@ pub fn npm_tarball_path(
   format!("~/{revision}/{npm_mapped_package_name}/{version}.tgz")
 }
 
+/// The npm registry's public signing keys, as served at `/-/npm/v1/keys`.
+pub fn npm_keys_path() -> String {
+  "-/npm/v1/keys".to_string()
+}
+
+pub fn npm_attestations_path(
+  scope: &ScopeName,
+  package_name: &PackageName,
+  version: &Version,
+) -> String {
+  let npm_mapped_package_name = NpmMappedJsrPackageName {
+    scope,
+    package: package_name,
+  };
+  format!("-/npm/v1/attestations/{npm_mapped_package_name}@{version}")
+}
+
+/// Where the artifacts of a quarantined or deleted package version are kept, in
+/// the publishing bucket. See [crate::quarantine].
+pub fn quarantine_directory(
+  scope: &ScopeName,
+  package_name: &PackageName,
+  version: &Version,
+) -> String {
+  format!("quarantine/@{scope}/{package_name}/{version}/")
+}
+
 #[cfg(test)]
 mod tests {
   use crate::ids::PackageName;
@@ -197,6 +300,26 @@ mod tests {
     assert!(urls.contains(&"https://api.jsr.io/api/scopes/std".into()));
   }
 
+  #[test]
+  fn package_version_api_cache_urls_include_docs() {
+    let registry_url = url::Url::parse("https://jsr.io/").unwrap();
+    let urls = super::package_version_api_cache_urls(
+      &registry_url,
+      &ScopeName::try_from("std").unwrap(),
+      &PackageName::try_from("fs").unwrap(),
+      &crate::ids::Version::new("1.0.0").unwrap(),
+    );
+    assert_eq!(
+      urls,
+      vec![
+        "https://jsr.io/api/scopes/std/packages/fs/versions/1.0.0",
+        "https://api.jsr.io/api/scopes/std/packages/fs/versions/1.0.0",
+        "https://jsr.io/api/scopes/std/packages/fs/versions/1.0.0/docs",
+        "https://api.jsr.io/api/scopes/std/packages/fs/versions/1.0.0/docs",
+      ]
+    );
+  }
+
   #[test]
   fn version_metadata_is_correct() {
     let crazy = "= v 1.2.3-pre.other+build.test";
@@ -213,3 +336,35 @@ mod tests {
     // Therefore /r/:scope/:package/:version_meta.json is ok.
   }
 }
+
+/// The type dependency graph of the exported symbols of a package version.
+pub fn docs_type_graph_path(
+  scope: &ScopeName,
+  package_name: &PackageName,
+  version: &Version,
+) -> String {
+  format!("@{scope}/{package_name}/{version}/type_graph.json")
+}
+
+/// A source file rendered with syntax highlighting, keyed by the hash of its
+/// content so that files that do not change between versions share a view.
+pub fn source_view_path(hash: &str) -> String {
+  format!(
+    "source_views/v{}/{hash}.html",
+    crate::tree_sitter::SOURCE_VIEW_VERSION
+  )
+}
+
+/// The software bill of materials of a package version, in the given format.
+pub fn sbom_path(
+  scope: &ScopeName,
+  package_name: &PackageName,
+  version: &Version,
+  format: crate::sbom::SbomFormat,
+) -> String {
+  let extension = match format {
+    crate::sbom::SbomFormat::CycloneDx => "cdx.json",
+    crate::sbom::SbomFormat::Spdx => "spdx.json",
+  };
+  format!("@{scope}/{package_name}/{version}/sbom.{extension}")
+}
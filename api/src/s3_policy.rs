@@ -0,0 +1,306 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+//! How [BucketWithQueue](crate::s3::BucketWithQueue) deals with a storage
+//! backend that fails. Requests that fail with a transient error are retried a
+//! few times, with jittered exponential backoff in between. When requests keep
+//! failing even after they were retried, the circuit breaker opens and requests
+//! fail right away for a while, instead of piling up behind an outage.
+
+use std::sync::Mutex;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+use rand::Rng;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// How often a request is sent before its error is returned.
+pub const MAX_ATTEMPTS: u32 = 5;
+const BASE_RETRY_DELAY: Duration = Duration::from_millis(100);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// How many requests in a row must fail, after retries, to open the circuit.
+const FAILURE_THRESHOLD: u32 = 5;
+/// How long the circuit stays open before a request is let through to probe
+/// whether the backend recovered.
+const OPEN_DURATION: Duration = Duration::from_secs(30);
+
+/// The delay before the given retry, counting from 0. The delay is random up
+/// to an exponentially growing cap ("full jitter"), so that requests that
+/// failed together don't all retry together.
+pub fn retry_delay(retry: u32) -> Duration {
+  let cap = BASE_RETRY_DELAY
+    .saturating_mul(2u32.saturating_pow(retry))
+    .min(MAX_RETRY_DELAY);
+  rand::thread_rng().gen_range(Duration::ZERO..=cap)
+}
+
+pub struct CircuitBreaker {
+  failure_threshold: u32,
+  open_duration: Duration,
+  state: Mutex<CircuitState>,
+}
+
+enum CircuitState {
+  /// Requests are sent. Counts the requests that failed in a row.
+  Closed { failures: u32 },
+  /// Requests fail right away until the given time.
+  Open { until: Instant },
+  /// A single request was let through to probe the backend. If it never
+  /// finishes, another one is let through after the open duration.
+  HalfOpen { since: Instant },
+}
+
+impl CircuitBreaker {
+  pub fn new(failure_threshold: u32, open_duration: Duration) -> Self {
+    Self {
+      failure_threshold,
+      open_duration,
+      state: Mutex::new(CircuitState::Closed { failures: 0 }),
+    }
+  }
+
+  /// Whether a request may be sent.
+  pub fn allow(&self) -> bool {
+    let mut state = self.state.lock().unwrap();
+    let now = Instant::now();
+    match *state {
+      CircuitState::Closed { .. } => true,
+      CircuitState::Open { until } if now >= until => {
+        *state = CircuitState::HalfOpen { since: now };
+        true
+      }
+      CircuitState::HalfOpen { since }
+        if now.duration_since(since) >= self.open_duration =>
+      {
+        *state = CircuitState::HalfOpen { since: now };
+        true
+      }
+      CircuitState::Open { .. } | CircuitState::HalfOpen { .. } => false,
+    }
+  }
+
+  pub fn is_open(&self) -> bool {
+    !matches!(*self.state.lock().unwrap(), CircuitState::Closed { .. })
+  }
+
+  pub fn record_success(&self) {
+    *self.state.lock().unwrap() = CircuitState::Closed { failures: 0 };
+  }
+
+  /// Records a request that failed with a transient error even after it was
+  /// retried. Returns whether this opened the circuit.
+  pub fn record_failure(&self) -> bool {
+    let mut state = self.state.lock().unwrap();
+    match *state {
+      CircuitState::Closed { failures }
+        if failures + 1 < self.failure_threshold =>
+      {
+        *state = CircuitState::Closed {
+          failures: failures + 1,
+        };
+        false
+      }
+      _ => {
+        *state = CircuitState::Open {
+          until: Instant::now() + self.open_duration,
+        };
+        true
+      }
+    }
+  }
+}
+
+impl Default for CircuitBreaker {
+  fn default() -> Self {
+    Self::new(FAILURE_THRESHOLD, OPEN_DURATION)
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StorageOperation {
+  Upload,
+  Download,
+  Delete,
+  List,
+  Copy,
+}
+
+impl StorageOperation {
+  const ALL: [StorageOperation; 5] = [
+    StorageOperation::Upload,
+    StorageOperation::Download,
+    StorageOperation::Delete,
+    StorageOperation::List,
+    StorageOperation::Copy,
+  ];
+
+  pub fn as_str(self) -> &'static str {
+    match self {
+      StorageOperation::Upload => "upload",
+      StorageOperation::Download => "download",
+      StorageOperation::Delete => "delete",
+      StorageOperation::List => "list",
+      StorageOperation::Copy => "copy",
+    }
+  }
+}
+
+/// Counters of the requests of a bucket, since the API instance started.
+#[derive(Default)]
+pub struct BucketMetrics {
+  operations: [OperationCounters; StorageOperation::ALL.len()],
+}
+
+#[derive(Default)]
+struct OperationCounters {
+  requests: AtomicU64,
+  failures: AtomicU64,
+  retries: AtomicU64,
+  rejected: AtomicU64,
+  duration_ms: AtomicU64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageOperationMetrics {
+  pub operation: StorageOperation,
+  /// Requests that were sent, counting retries of a request once.
+  pub requests: u64,
+  /// Requests that failed, after they were retried.
+  pub failures: u64,
+  pub retries: u64,
+  /// Requests that failed right away because the circuit was open.
+  pub rejected: u64,
+  /// The total time requests took, including retries.
+  pub total_duration_ms: u64,
+}
+
+impl BucketMetrics {
+  fn counters(&self, operation: StorageOperation) -> &OperationCounters {
+    &self.operations[operation as usize]
+  }
+
+  pub fn record(
+    &self,
+    operation: StorageOperation,
+    duration: Duration,
+    success: bool,
+  ) {
+    let counters = self.counters(operation);
+    counters.requests.fetch_add(1, Ordering::Relaxed);
+    if !success {
+      counters.failures.fetch_add(1, Ordering::Relaxed);
+    }
+    counters
+      .duration_ms
+      .fetch_add(duration.as_millis() as u64, Ordering::Relaxed);
+  }
+
+  pub fn record_retry(&self, operation: StorageOperation) {
+    self
+      .counters(operation)
+      .retries
+      .fetch_add(1, Ordering::Relaxed);
+  }
+
+  pub fn record_rejected(&self, operation: StorageOperation) {
+    self
+      .counters(operation)
+      .rejected
+      .fetch_add(1, Ordering::Relaxed);
+  }
+
+  pub fn snapshot(&self) -> Vec<StorageOperationMetrics> {
+    StorageOperation::ALL
+      .into_iter()
+      .map(|operation| {
+        let counters = self.counters(operation);
+        StorageOperationMetrics {
+          operation,
+          requests: counters.requests.load(Ordering::Relaxed),
+          failures: counters.failures.load(Ordering::Relaxed),
+          retries: counters.retries.load(Ordering::Relaxed),
+          rejected: counters.rejected.load(Ordering::Relaxed),
+          total_duration_ms: counters.duration_ms.load(Ordering::Relaxed),
+        }
+      })
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::BucketMetrics;
+  use super::CircuitBreaker;
+  use super::MAX_RETRY_DELAY;
+  use super::StorageOperation;
+  use super::retry_delay;
+
+  #[test]
+  fn retry_delay_is_capped() {
+    for retry in 0..10 {
+      assert!(retry_delay(retry) <= Duration::from_millis(100) * (1 << retry));
+    }
+    assert!(retry_delay(u32::MAX) <= MAX_RETRY_DELAY);
+  }
+
+  #[test]
+  fn circuit_breaker() {
+    let breaker = CircuitBreaker::new(3, Duration::from_millis(50));
+    assert!(breaker.allow());
+
+    // A success resets the count of failures in a row.
+    assert!(!breaker.record_failure());
+    assert!(!breaker.record_failure());
+    breaker.record_success();
+    assert!(!breaker.record_failure());
+    assert!(!breaker.record_failure());
+    assert!(!breaker.is_open());
+    assert!(breaker.record_failure());
+    assert!(breaker.is_open());
+    assert!(!breaker.allow());
+
+    // After the open duration, a single probe is let through.
+    std::thread::sleep(Duration::from_millis(60));
+    assert!(breaker.allow());
+    assert!(!breaker.allow());
+    // A failed probe opens the circuit again right away.
+    assert!(breaker.record_failure());
+    assert!(!breaker.allow());
+
+    std::thread::sleep(Duration::from_millis(60));
+    assert!(breaker.allow());
+    breaker.record_success();
+    assert!(!breaker.is_open());
+    assert!(breaker.allow());
+    assert!(breaker.allow());
+  }
+
+  #[test]
+  fn metrics() {
+    let metrics = BucketMetrics::default();
+    metrics.record(StorageOperation::Upload, Duration::from_millis(10), true);
+    metrics.record(StorageOperation::Upload, Duration::from_millis(20), false);
+    metrics.record_retry(StorageOperation::Upload);
+    metrics.record_rejected(StorageOperation::Download);
+
+    let snapshot = metrics.snapshot();
+    assert_eq!(snapshot.len(), 5);
+    let upload = &snapshot[0];
+    assert_eq!(upload.operation, StorageOperation::Upload);
+    assert_eq!(upload.requests, 2);
+    assert_eq!(upload.failures, 1);
+    assert_eq!(upload.retries, 1);
+    assert_eq!(upload.rejected, 0);
+    assert_eq!(upload.total_duration_ms, 30);
+    let download = &snapshot[1];
+    assert_eq!(download.operation, StorageOperation::Download);
+    assert_eq!(download.requests, 0);
+    assert_eq!(download.rejected, 1);
+  }
+}
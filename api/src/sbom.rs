@@ -0,0 +1,456 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+//! Software bills of materials of package versions, in the CycloneDX and SPDX
+//! JSON formats, for supply-chain tooling.
+//!
+//! They are built from the files of the version with their SHA-256 hashes, and
+//! the packages it depends on. Dependencies are listed with the version
+//! constraint they are imported with, as the versions they resolve to depend
+//! on the lockfile of the consumer.
+
+use std::str::FromStr;
+
+use chrono::DateTime;
+use chrono::SecondsFormat;
+use chrono::Utc;
+use indexmap::IndexMap;
+use serde_json::Value;
+use serde_json::json;
+use url::Url;
+use uuid::Uuid;
+
+use crate::api::ApiError;
+use crate::db::DependencyKind;
+use crate::db::PackageFile;
+use crate::db::PackageVersionDependency;
+use crate::ids::PackageName;
+use crate::ids::PackagePath;
+use crate::ids::ScopeName;
+use crate::ids::Version;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbomFormat {
+  CycloneDx,
+  Spdx,
+}
+
+impl SbomFormat {
+  pub fn content_type(self) -> &'static str {
+    match self {
+      SbomFormat::CycloneDx => "application/vnd.cyclonedx+json",
+      SbomFormat::Spdx => "application/spdx+json",
+    }
+  }
+}
+
+impl FromStr for SbomFormat {
+  type Err = ApiError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "cyclonedx" => Ok(SbomFormat::CycloneDx),
+      "spdx" => Ok(SbomFormat::Spdx),
+      _ => Err(ApiError::MalformedRequest {
+        msg: "the format must be 'cyclonedx' or 'spdx'".into(),
+      }),
+    }
+  }
+}
+
+pub struct SbomFile<'a> {
+  pub path: &'a PackagePath,
+  pub size: u64,
+  /// As stored in the manifest, `sha256-<hex>`.
+  pub checksum: Option<&'a str>,
+}
+
+pub struct SbomDependency<'a> {
+  pub kind: DependencyKind,
+  pub name: &'a str,
+  pub constraint: &'a str,
+  pub is_optional: bool,
+}
+
+pub struct SbomInput<'a> {
+  pub registry_url: &'a Url,
+  pub scope: &'a ScopeName,
+  pub package: &'a PackageName,
+  pub version: &'a Version,
+  pub license: Option<&'a str>,
+  pub created_at: DateTime<Utc>,
+  pub files: Vec<SbomFile<'a>>,
+  /// May contain the same dependency once per imported sub path.
+  pub dependencies: Vec<SbomDependency<'a>>,
+}
+
+impl<'a> SbomInput<'a> {
+  /// The input for a version that is already stored in the database.
+  #[allow(clippy::too_many_arguments)]
+  pub fn from_stored(
+    registry_url: &'a Url,
+    scope: &'a ScopeName,
+    package: &'a PackageName,
+    version: &'a Version,
+    license: Option<&'a str>,
+    created_at: DateTime<Utc>,
+    files: &'a [PackageFile],
+    dependencies: &'a [PackageVersionDependency],
+  ) -> Self {
+    SbomInput {
+      registry_url,
+      scope,
+      package,
+      version,
+      license,
+      created_at,
+      files: files
+        .iter()
+        .map(|file| SbomFile {
+          path: &file.path,
+          size: file.size as u64,
+          checksum: file.checksum.as_deref(),
+        })
+        .collect(),
+      dependencies: dependencies
+        .iter()
+        .map(|dep| SbomDependency {
+          kind: dep.dependency_kind,
+          name: &dep.dependency_name,
+          constraint: &dep.dependency_constraint,
+          is_optional: dep.is_optional,
+        })
+        .collect(),
+    }
+  }
+
+  pub fn generate(&self, format: SbomFormat) -> Value {
+    match format {
+      SbomFormat::CycloneDx => self.cyclonedx(),
+      SbomFormat::Spdx => self.spdx(),
+    }
+  }
+
+  fn purl(&self) -> String {
+    format!(
+      "pkg:jsr/%40{}/{}@{}",
+      self.scope,
+      self.package,
+      encode_purl_version(&self.version.to_string())
+    )
+  }
+
+  fn download_location(&self) -> String {
+    format!(
+      "{}@{}/{}/{}",
+      self.registry_url, self.scope, self.package, self.version
+    )
+  }
+
+  /// One entry per dependency, with the sub paths merged. A dependency is only
+  /// optional if it is optional for every sub path.
+  fn dependencies(&self) -> Vec<(&SbomDependency<'a>, bool)> {
+    let mut deps = IndexMap::new();
+    for dep in &self.dependencies {
+      deps
+        .entry((dep.kind, dep.name, dep.constraint))
+        .and_modify(|(_, optional)| *optional &= dep.is_optional)
+        .or_insert((dep, dep.is_optional));
+    }
+    let mut deps = deps.into_values().collect::<Vec<_>>();
+    deps.sort_by_key(|(dep, _)| (dep.kind as u8, dep.name, dep.constraint));
+    deps
+  }
+
+  fn files(&self) -> Vec<(&SbomFile<'a>, Option<&'a str>)> {
+    let mut files = self
+      .files
+      .iter()
+      .map(|file| {
+        let sha256 = file
+          .checksum
+          .and_then(|checksum| checksum.strip_prefix("sha256-"));
+        (file, sha256)
+      })
+      .collect::<Vec<_>>();
+    files.sort_by(|(a, _), (b, _)| a.path.cmp(b.path));
+    files
+  }
+
+  fn cyclonedx(&self) -> Value {
+    let purl = self.purl();
+
+    let files = self
+      .files()
+      .into_iter()
+      .map(|(file, sha256)| {
+        let mut component = json!({
+          "type": "file",
+          "bom-ref": format!("file:{}", file.path),
+          "name": file.path.to_string(),
+          "properties": [{ "name": "jsr:size", "value": file.size.to_string() }],
+        });
+        if let Some(sha256) = sha256 {
+          component["hashes"] = json!([{ "alg": "SHA-256", "content": sha256 }]);
+        }
+        component
+      })
+      .collect::<Vec<_>>();
+
+    let mut root = json!({
+      "type": "library",
+      "bom-ref": purl,
+      "name": self.package.to_string(),
+      "group": format!("@{}", self.scope),
+      "version": self.version.to_string(),
+      "purl": purl,
+      "externalReferences": [
+        { "type": "distribution", "url": self.download_location() },
+      ],
+      "components": files,
+    });
+    if let Some(license) = self.license {
+      root["licenses"] = json!([{ "expression": license }]);
+    }
+
+    let dependencies = self.dependencies();
+    let components = dependencies
+      .iter()
+      .map(|(dep, is_optional)| {
+        json!({
+          "type": "library",
+          "bom-ref": dependency_ref(dep),
+          "name": dep.name,
+          "purl": dependency_purl(dep),
+          "scope": if *is_optional { "optional" } else { "required" },
+          "properties": [
+            { "name": "jsr:versionConstraint", "value": dep.constraint },
+          ],
+        })
+      })
+      .collect::<Vec<_>>();
+    let depends_on = dependencies
+      .iter()
+      .map(|(dep, _)| dependency_ref(dep))
+      .collect::<Vec<_>>();
+
+    json!({
+      "bomFormat": "CycloneDX",
+      "specVersion": "1.5",
+      "serialNumber": format!("urn:uuid:{}", Uuid::new_v4()),
+      "version": 1,
+      "metadata": {
+        "timestamp": self.created_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+        "tools": {
+          "components": [{ "type": "application", "name": "jsr" }],
+        },
+        "component": root,
+      },
+      "components": components,
+      "dependencies": [{ "ref": purl, "dependsOn": depends_on }],
+    })
+  }
+
+  fn spdx(&self) -> Value {
+    let license = self.license.unwrap_or("NOASSERTION");
+
+    let mut packages = vec![json!({
+      "SPDXID": "SPDXRef-Package",
+      "name": format!("@{}/{}", self.scope, self.package),
+      "versionInfo": self.version.to_string(),
+      "downloadLocation": self.download_location(),
+      "filesAnalyzed": false,
+      "licenseConcluded": license,
+      "licenseDeclared": license,
+      "copyrightText": "NOASSERTION",
+      "externalRefs": [{
+        "referenceCategory": "PACKAGE-MANAGER",
+        "referenceType": "purl",
+        "referenceLocator": self.purl(),
+      }],
+    })];
+    let mut relationships = vec![json!({
+      "spdxElementId": "SPDXRef-DOCUMENT",
+      "relationshipType": "DESCRIBES",
+      "relatedSpdxElement": "SPDXRef-Package",
+    })];
+
+    for (i, (dep, is_optional)) in self.dependencies().into_iter().enumerate() {
+      let id = format!("SPDXRef-Dependency-{i}");
+      packages.push(json!({
+        "SPDXID": id,
+        "name": dep.name,
+        "versionInfo": dep.constraint,
+        "downloadLocation": "NOASSERTION",
+        "filesAnalyzed": false,
+        "licenseConcluded": "NOASSERTION",
+        "licenseDeclared": "NOASSERTION",
+        "copyrightText": "NOASSERTION",
+        "externalRefs": [{
+          "referenceCategory": "PACKAGE-MANAGER",
+          "referenceType": "purl",
+          "referenceLocator": dependency_purl(dep),
+        }],
+      }));
+      relationships.push(if is_optional {
+        json!({
+          "spdxElementId": id,
+          "relationshipType": "OPTIONAL_DEPENDENCY_OF",
+          "relatedSpdxElement": "SPDXRef-Package",
+        })
+      } else {
+        json!({
+          "spdxElementId": "SPDXRef-Package",
+          "relationshipType": "DEPENDS_ON",
+          "relatedSpdxElement": id,
+        })
+      });
+    }
+
+    let mut files = vec![];
+    for (i, (file, sha256)) in self.files().into_iter().enumerate() {
+      let id = format!("SPDXRef-File-{i}");
+      let checksums = sha256
+        .map(
+          |sha256| json!([{ "algorithm": "SHA256", "checksumValue": sha256 }]),
+        )
+        .unwrap_or_else(|| json!([]));
+      files.push(json!({
+        "SPDXID": id,
+        "fileName": format!(".{}", file.path),
+        "checksums": checksums,
+        "licenseConcluded": "NOASSERTION",
+        "copyrightText": "NOASSERTION",
+      }));
+      relationships.push(json!({
+        "spdxElementId": "SPDXRef-Package",
+        "relationshipType": "CONTAINS",
+        "relatedSpdxElement": id,
+      }));
+    }
+
+    json!({
+      "spdxVersion": "SPDX-2.3",
+      "dataLicense": "CC0-1.0",
+      "SPDXID": "SPDXRef-DOCUMENT",
+      "name": format!("@{}/{}@{}", self.scope, self.package, self.version),
+      "documentNamespace": format!("{}/sbom.spdx.json", self.download_location()),
+      "creationInfo": {
+        "created": self.created_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+        "creators": ["Tool: jsr"],
+      },
+      "packages": packages,
+      "files": files,
+      "relationships": relationships,
+    })
+  }
+}
+
+/// Build metadata (`+`) must be percent encoded in a purl version.
+fn encode_purl_version(version: &str) -> String {
+  version.replace('+', "%2B")
+}
+
+fn dependency_purl(dep: &SbomDependency) -> String {
+  let (ty, name) = match dep.kind {
+    DependencyKind::Jsr => ("jsr", dep.name),
+    DependencyKind::Npm => ("npm", dep.name),
+  };
+  format!("pkg:{ty}/{}", name.replacen('@', "%40", 1))
+}
+
+fn dependency_ref(dep: &SbomDependency) -> String {
+  format!("{}@{}", dependency_purl(dep), dep.constraint)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn sbom_documents() {
+    let registry_url = Url::parse("https://jsr.io/").unwrap();
+    let scope = ScopeName::try_from("std").unwrap();
+    let package = PackageName::try_from("fs").unwrap();
+    let version = Version::new("1.0.0").unwrap();
+    let mod_ts = PackagePath::try_from("/mod.ts").unwrap();
+    let input = SbomInput {
+      registry_url: &registry_url,
+      scope: &scope,
+      package: &package,
+      version: &version,
+      license: Some("MIT"),
+      created_at: DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z")
+        .unwrap()
+        .into(),
+      files: vec![SbomFile {
+        path: &mod_ts,
+        size: 12,
+        checksum: Some("sha256-abcd"),
+      }],
+      dependencies: vec![
+        SbomDependency {
+          kind: DependencyKind::Npm,
+          name: "@types/node",
+          constraint: "^20.0.0",
+          is_optional: true,
+        },
+        SbomDependency {
+          kind: DependencyKind::Jsr,
+          name: "@std/path",
+          constraint: "^1.0.0",
+          is_optional: false,
+        },
+        SbomDependency {
+          kind: DependencyKind::Jsr,
+          name: "@std/path",
+          constraint: "^1.0.0",
+          is_optional: true,
+        },
+      ],
+    };
+
+    let cyclonedx = input.generate(SbomFormat::CycloneDx);
+    assert_eq!(cyclonedx["bomFormat"], "CycloneDX");
+    assert_eq!(
+      cyclonedx["metadata"]["component"]["purl"],
+      "pkg:jsr/%40std/fs@1.0.0"
+    );
+    assert_eq!(
+      cyclonedx["metadata"]["component"]["licenses"][0]["expression"],
+      "MIT"
+    );
+    assert_eq!(
+      cyclonedx["metadata"]["component"]["components"][0]["hashes"][0]["content"],
+      "abcd"
+    );
+    let components = cyclonedx["components"].as_array().unwrap();
+    assert_eq!(components.len(), 2);
+    assert_eq!(components[0]["purl"], "pkg:jsr/%40std/path");
+    assert_eq!(components[0]["scope"], "required");
+    assert_eq!(components[1]["purl"], "pkg:npm/%40types/node");
+    assert_eq!(components[1]["scope"], "optional");
+    assert_eq!(
+      cyclonedx["dependencies"][0]["dependsOn"],
+      json!([
+        "pkg:jsr/%40std/path@^1.0.0",
+        "pkg:npm/%40types/node@^20.0.0"
+      ])
+    );
+
+    let spdx = input.generate(SbomFormat::Spdx);
+    assert_eq!(spdx["spdxVersion"], "SPDX-2.3");
+    assert_eq!(spdx["creationInfo"]["created"], "2024-01-01T00:00:00Z");
+    assert_eq!(spdx["packages"].as_array().unwrap().len(), 3);
+    assert_eq!(spdx["packages"][0]["licenseDeclared"], "MIT");
+    assert_eq!(spdx["files"][0]["fileName"], "./mod.ts");
+    assert_eq!(
+      spdx["files"][0]["checksums"][0],
+      json!({ "algorithm": "SHA256", "checksumValue": "abcd" })
+    );
+    let relationships = spdx["relationships"].as_array().unwrap();
+    assert!(relationships.contains(&json!({
+      "spdxElementId": "SPDXRef-Dependency-1",
+      "relationshipType": "OPTIONAL_DEPENDENCY_OF",
+      "relatedSpdxElement": "SPDXRef-Package",
+    })));
+  }
+}
@@ -0,0 +1,563 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+//! A scheduler that runs periodic tasks inside the API, as an alternative to
+//! triggering the `/tasks/*` routes from Cloud Scheduler.
+//!
+//! The jobs to run and their schedules are configured with `scheduled_jobs`,
+//! as `name=<cron expression>` pairs, for example
+//! `gc_module_blobs=30 * * * *`. Schedules are in UTC. Every instance that has
+//! jobs configured runs the scheduler, but only the one that holds the lease
+//! in the `scheduler_leader` table starts runs, so that a job runs once per
+//! schedule regardless of how many instances there are.
+//!
+//! Runs never overlap: a run holds a lease on its job, which it renews while
+//! it is running, and the job is not started again until the run finished or
+//! its lease expired. Runs that come due in the meantime are skipped. The
+//! state and the last run of every job are shown by
+//! `GET /api/admin/scheduled_jobs`.
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::DateTime;
+use chrono::Datelike;
+use chrono::TimeZone;
+use chrono::Timelike;
+use chrono::Utc;
+use tracing::Instrument;
+use tracing::error;
+use tracing::info_span;
+use url::Url;
+use uuid::Uuid;
+
+use crate::db::Database;
+use crate::db::ScheduledJobStatus;
+use crate::emails::EmailSender;
+use crate::s3::Buckets;
+use crate::search::PackageSearch;
+use crate::tasks;
+use crate::tombstones;
+use crate::tombstones::TombstoneRetention;
+
+/// How often the scheduler checks for jobs that are due.
+const TICK_INTERVAL: Duration = Duration::from_secs(15);
+/// How long the leader holds its lease without renewing it. Another instance
+/// takes over at most this long after the leader stopped.
+const LEADER_LEASE_SECS: i64 = 60;
+/// How long a run holds its lease on the job without renewing it.
+const RUN_LEASE_SECS: i64 = 5 * 60;
+/// How often a run renews its lease on the job.
+const RUN_LEASE_RENEW_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The tasks that can be scheduled.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ScheduledJobKind {
+  CleanOauthStates,
+  CleanDownloadCounts4h,
+  RefreshDownloadRollups,
+  RebuildSearchIndex,
+  DeliverWebhooks,
+  GcModuleBlobs,
+  GcOrphanedObjects,
+  PurgeTombstones,
+  CleanPublishUploads,
+  CleanDataExports,
+  RecomputeScores,
+  SendScopeDigests,
+}
+
+impl ScheduledJobKind {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Self::CleanOauthStates => "clean_oauth_states",
+      Self::CleanDownloadCounts4h => "clean_download_counts_4h",
+      Self::RefreshDownloadRollups => "refresh_download_rollups",
+      Self::RebuildSearchIndex => "rebuild_search_index",
+      Self::DeliverWebhooks => "deliver_webhooks",
+      Self::GcModuleBlobs => "gc_module_blobs",
+      Self::GcOrphanedObjects => "gc_orphaned_objects",
+      Self::PurgeTombstones => "purge_tombstones",
+      Self::CleanPublishUploads => "clean_publish_uploads",
+      Self::CleanDataExports => "clean_data_exports",
+      Self::RecomputeScores => "recompute_scores",
+      Self::SendScopeDigests => "send_scope_digests",
+    }
+  }
+}
+
+impl FromStr for ScheduledJobKind {
+  type Err = anyhow::Error;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "clean_oauth_states" => Ok(Self::CleanOauthStates),
+      "clean_download_counts_4h" => Ok(Self::CleanDownloadCounts4h),
+      "refresh_download_rollups" => Ok(Self::RefreshDownloadRollups),
+      "rebuild_search_index" => Ok(Self::RebuildSearchIndex),
+      "deliver_webhooks" => Ok(Self::DeliverWebhooks),
+      "gc_module_blobs" => Ok(Self::GcModuleBlobs),
+      "gc_orphaned_objects" => Ok(Self::GcOrphanedObjects),
+      "purge_tombstones" => Ok(Self::PurgeTombstones),
+      "clean_publish_uploads" => Ok(Self::CleanPublishUploads),
+      "clean_data_exports" => Ok(Self::CleanDataExports),
+      "recompute_scores" => Ok(Self::RecomputeScores),
+      "send_scope_digests" => Ok(Self::SendScopeDigests),
+      _ => Err(anyhow::anyhow!("Invalid scheduled job '{}'", s)),
+    }
+  }
+}
+
+/// A cron expression with the five fields minute, hour, day of month, month
+/// and day of week. Fields are `*`, values, ranges (`1-5`) and steps (`*/15`,
+/// `0-30/10`), separated by commas. Like in cron, a time matches if either
+/// the day of month or the day of week matches, if both are restricted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+  source: String,
+  minutes: u64,
+  hours: u64,
+  days_of_month: u64,
+  months: u64,
+  days_of_week: u64,
+  days_of_month_restricted: bool,
+  days_of_week_restricted: bool,
+}
+
+impl CronSchedule {
+  pub fn as_str(&self) -> &str {
+    &self.source
+  }
+
+  /// The first time after `after` that matches the schedule, at the start of
+  /// a minute.
+  pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let mut time = after
+      .with_second(0)?
+      .with_nanosecond(0)?
+      .checked_add_signed(chrono::Duration::minutes(1))?;
+    // Every schedule that matches at all matches within 4 years, because of
+    // the 29th of February.
+    let end = time + chrono::Duration::days(4 * 366);
+    while time < end {
+      if !contains(self.months, time.month()) {
+        let (year, month) = if time.month() == 12 {
+          (time.year() + 1, 1)
+        } else {
+          (time.year(), time.month() + 1)
+        };
+        time = Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single()?;
+      } else if !self.matches_day(time) {
+        time = (time.date_naive() + chrono::Duration::days(1))
+          .and_hms_opt(0, 0, 0)?
+          .and_utc();
+      } else if !contains(self.hours, time.hour()) {
+        time = time.with_minute(0)? + chrono::Duration::hours(1);
+      } else if !contains(self.minutes, time.minute()) {
+        time += chrono::Duration::minutes(1);
+      } else {
+        return Some(time);
+      }
+    }
+    None
+  }
+
+  fn matches_day(&self, time: DateTime<Utc>) -> bool {
+    let day_of_month = contains(self.days_of_month, time.day());
+    let day_of_week =
+      contains(self.days_of_week, time.weekday().num_days_from_sunday());
+    if self.days_of_month_restricted && self.days_of_week_restricted {
+      day_of_month || day_of_week
+    } else {
+      day_of_month && day_of_week
+    }
+  }
+}
+
+impl FromStr for CronSchedule {
+  type Err = anyhow::Error;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let fields = s.split_whitespace().collect::<Vec<_>>();
+    let [minutes, hours, days_of_month, months, days_of_week] = fields[..]
+    else {
+      return Err(anyhow::anyhow!(
+        "Invalid cron expression '{}', expected 5 fields",
+        s
+      ));
+    };
+    let mut schedule = CronSchedule {
+      source: fields.join(" "),
+      minutes: parse_field(minutes, 0, 59)?,
+      hours: parse_field(hours, 0, 23)?,
+      days_of_month: parse_field(days_of_month, 1, 31)?,
+      months: parse_field(months, 1, 12)?,
+      // Both 0 and 7 are Sunday.
+      days_of_week: parse_field(days_of_week, 0, 7)?,
+      days_of_month_restricted: !days_of_month.starts_with('*'),
+      days_of_week_restricted: !days_of_week.starts_with('*'),
+    };
+    if contains(schedule.days_of_week, 7) {
+      schedule.days_of_week |= 1;
+    }
+    Ok(schedule)
+  }
+}
+
+fn contains(set: u64, value: u32) -> bool {
+  set & (1 << value) != 0
+}
+
+/// Parses a field of a cron expression into the set of values it matches, as
+/// a bit set.
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, anyhow::Error> {
+  let mut set = 0;
+  for part in field.split(',') {
+    let (range, step) = match part.split_once('/') {
+      Some((range, step)) => (range, Some(step.parse::<u32>()?)),
+      None => (part, None),
+    };
+    let (start, end) = if range == "*" {
+      (min, max)
+    } else if let Some((start, end)) = range.split_once('-') {
+      (start.parse()?, end.parse()?)
+    } else {
+      let value = range.parse()?;
+      // `5/10` means from 5 to the end, in steps of 10.
+      (value, if step.is_some() { max } else { value })
+    };
+    if start < min || end > max || start > end || step == Some(0) {
+      return Err(anyhow::anyhow!(
+        "Invalid cron field '{}', values must be between {} and {}",
+        field,
+        min,
+        max
+      ));
+    }
+    for value in (start..=end).step_by(step.unwrap_or(1) as usize) {
+      set |= 1 << value;
+    }
+  }
+  Ok(set)
+}
+
+/// A job and the schedule it runs on, configured as `name=<cron expression>`.
+#[derive(Debug, Clone)]
+pub struct ScheduledJobConfig {
+  pub kind: ScheduledJobKind,
+  pub schedule: CronSchedule,
+}
+
+impl FromStr for ScheduledJobConfig {
+  type Err = anyhow::Error;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (kind, schedule) = s.split_once('=').ok_or_else(|| {
+      anyhow::anyhow!("Invalid scheduled job '{}', expected name=schedule", s)
+    })?;
+    Ok(ScheduledJobConfig {
+      kind: kind.trim().parse()?,
+      schedule: schedule.parse()?,
+    })
+  }
+}
+
+/// What scheduled jobs need to run.
+#[derive(Clone)]
+pub struct SchedulerContext {
+  pub db: Database,
+  pub buckets: Buckets,
+  pub package_search: PackageSearch,
+  pub tombstone_retention: TombstoneRetention,
+  pub email_sender: Option<EmailSender>,
+  pub registry_url: Url,
+}
+
+async fn run_job(
+  ctx: &SchedulerContext,
+  kind: ScheduledJobKind,
+) -> Result<(), anyhow::Error> {
+  let db = &ctx.db;
+  let buckets = &ctx.buckets;
+  match kind {
+    ScheduledJobKind::CleanOauthStates => tasks::clean_oauth_states(db).await,
+    ScheduledJobKind::CleanDownloadCounts4h => {
+      tasks::clean_download_counts_4h(db).await
+    }
+    ScheduledJobKind::RefreshDownloadRollups => {
+      let packages = db.refresh_package_download_rollups().await?;
+      tracing::info!(packages, "refreshed download rollups");
+      Ok(())
+    }
+    ScheduledJobKind::RebuildSearchIndex => {
+      for (backend, documents) in ctx.package_search.rebuild_indexes(db).await?
+      {
+        tracing::info!(backend, documents, "rebuilt search index");
+      }
+      Ok(())
+    }
+    ScheduledJobKind::DeliverWebhooks => {
+      let attempted = crate::webhooks::deliver_pending_webhooks(db).await?;
+      tracing::info!(attempted, "delivered pending webhooks");
+      Ok(())
+    }
+    ScheduledJobKind::GcModuleBlobs => {
+      tasks::gc_module_blobs(db, &buckets.modules_bucket).await
+    }
+    ScheduledJobKind::GcOrphanedObjects => {
+      tasks::gc_orphaned_objects(db, buckets, false).await
+    }
+    ScheduledJobKind::PurgeTombstones => {
+      let cutoff = ctx.tombstone_retention.cutoff();
+      let (versions, packages) =
+        tombstones::purge_tombstones(db, buckets, cutoff).await?;
+      tracing::info!(versions, packages, "purged tombstones");
+      Ok(())
+    }
+    ScheduledJobKind::CleanPublishUploads => {
+      tasks::clean_publish_uploads(db, buckets).await
+    }
+    ScheduledJobKind::CleanDataExports => {
+      tasks::clean_data_exports(db, buckets).await
+    }
+    ScheduledJobKind::RecomputeScores => {
+      let lease =
+        chrono::Duration::seconds(crate::background_jobs::ADMIN_JOB_LEASE_SECS);
+      let Some(job) = db.create_score_recompute_job(None, lease).await? else {
+        tracing::info!("skipping score recompute, another job is running");
+        return Ok(());
+      };
+      crate::score::recompute_scores(db.clone(), buckets.clone(), job.id).await;
+      Ok(())
+    }
+    ScheduledJobKind::SendScopeDigests => {
+      tasks::send_scope_digests(
+        db,
+        ctx.email_sender.as_ref(),
+        &ctx.registry_url,
+      )
+      .await
+    }
+  }
+}
+
+/// Runs `kind` as the run `run_id`, renewing the lease of the run until it
+/// finished, and records its outcome.
+async fn run_scheduled_job(
+  ctx: SchedulerContext,
+  job: ScheduledJobConfig,
+  run_id: Uuid,
+) {
+  let name = job.kind.as_str();
+  let lease = chrono::Duration::seconds(RUN_LEASE_SECS);
+  let run = run_job(&ctx, job.kind);
+  tokio::pin!(run);
+  let mut renew = tokio::time::interval(RUN_LEASE_RENEW_INTERVAL);
+  // The first tick completes immediately.
+  renew.tick().await;
+  let res = loop {
+    tokio::select! {
+      res = &mut run => break res,
+      _ = renew.tick() => {
+        match ctx.db.renew_scheduled_job_lease(name, run_id, lease).await {
+          Ok(true) => {}
+          Ok(false) => error!("scheduled job {name} lost its lease"),
+          Err(err) => {
+            error!("failed to renew the lease of scheduled job {name}: {err}");
+          }
+        }
+      }
+    }
+  };
+
+  let (status, error) = match res {
+    Ok(()) => (ScheduledJobStatus::Succeeded, None),
+    Err(err) => {
+      error!("scheduled job {name} failed: {err:#}");
+      (ScheduledJobStatus::Failed, Some(format!("{err:#}")))
+    }
+  };
+  let Some(next_run_at) = job.schedule.next_after(Utc::now()) else {
+    return;
+  };
+  if let Err(err) = ctx
+    .db
+    .finish_scheduled_job(name, run_id, status, error.as_deref(), next_run_at)
+    .await
+  {
+    error!("failed to record the run of scheduled job {name}: {err}");
+  }
+}
+
+/// Registers `jobs` and starts runs of the jobs that are due, while this
+/// instance is the leader.
+async fn tick(
+  ctx: &SchedulerContext,
+  jobs: &[ScheduledJobConfig],
+  instance_id: Uuid,
+) -> Result<(), anyhow::Error> {
+  let leader = ctx
+    .db
+    .acquire_scheduler_leadership(
+      instance_id,
+      chrono::Duration::seconds(LEADER_LEASE_SECS),
+    )
+    .await?;
+  if !leader {
+    return Ok(());
+  }
+
+  let now = Utc::now();
+  for job in jobs {
+    let name = job.kind.as_str();
+    let Some(next_run_at) = job.schedule.next_after(now) else {
+      continue;
+    };
+    let run_id = Uuid::new_v4();
+    let claimed = ctx
+      .db
+      .claim_scheduled_job(
+        name,
+        run_id,
+        next_run_at,
+        chrono::Duration::seconds(RUN_LEASE_SECS),
+      )
+      .await?;
+    if claimed {
+      let span = info_span!("scheduled_job", job = name, %run_id);
+      tokio::spawn(
+        run_scheduled_job(ctx.clone(), job.clone(), run_id).instrument(span),
+      );
+    }
+  }
+  Ok(())
+}
+
+/// Runs `jobs` on their schedules, see the module documentation.
+pub fn spawn_scheduler(ctx: SchedulerContext, jobs: Vec<ScheduledJobConfig>) {
+  tokio::spawn(async move {
+    let instance_id = Uuid::new_v4();
+    for job in &jobs {
+      let Some(next_run_at) = job.schedule.next_after(Utc::now()) else {
+        error!(
+          "scheduled job {} never runs on '{}'",
+          job.kind.as_str(),
+          job.schedule.as_str()
+        );
+        continue;
+      };
+      if let Err(err) = ctx
+        .db
+        .register_scheduled_job(
+          job.kind.as_str(),
+          job.schedule.as_str(),
+          next_run_at,
+        )
+        .await
+      {
+        error!("failed to register scheduled job: {err}");
+      }
+    }
+
+    let mut interval = tokio::time::interval(TICK_INTERVAL);
+    loop {
+      interval.tick().await;
+      if let Err(err) = tick(&ctx, &jobs, instance_id).await {
+        error!("failed to run the scheduler: {err:#}");
+      }
+    }
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::CronSchedule;
+  use super::ScheduledJobConfig;
+  use super::ScheduledJobKind;
+  use chrono::TimeZone;
+  use chrono::Utc;
+
+  fn next(schedule: &str, after: (i32, u32, u32, u32, u32)) -> String {
+    let (year, month, day, hour, minute) = after;
+    let after = Utc
+      .with_ymd_and_hms(year, month, day, hour, minute, 30)
+      .unwrap();
+    let schedule = schedule.parse::<CronSchedule>().unwrap();
+    schedule
+      .next_after(after)
+      .unwrap()
+      .format("%Y-%m-%d %H:%M %a")
+      .to_string()
+  }
+
+  #[test]
+  fn cron_schedule() {
+    assert_eq!(
+      next("* * * * *", (2026, 1, 1, 0, 0)),
+      "2026-01-01 00:01 Thu"
+    );
+    assert_eq!(
+      next("30 * * * *", (2026, 1, 1, 0, 30)),
+      "2026-01-01 01:30 Thu"
+    );
+    assert_eq!(
+      next("*/15 * * * *", (2026, 1, 1, 0, 31)),
+      "2026-01-01 00:45 Thu"
+    );
+    assert_eq!(
+      next("0 3 * * *", (2026, 12, 31, 4, 0)),
+      "2027-01-01 03:00 Fri"
+    );
+    // Sunday, as 0 and as 7.
+    assert_eq!(
+      next("0 4 * * 0", (2026, 1, 1, 0, 0)),
+      "2026-01-04 04:00 Sun"
+    );
+    assert_eq!(
+      next("0 4 * * 7", (2026, 1, 1, 0, 0)),
+      "2026-01-04 04:00 Sun"
+    );
+    // Either the day of month or the day of week.
+    assert_eq!(
+      next("0 0 15 * 1", (2026, 1, 1, 0, 0)),
+      "2026-01-05 00:00 Mon"
+    );
+    assert_eq!(
+      next("0 0 29 2 *", (2026, 3, 1, 0, 0)),
+      "2028-02-29 00:00 Tue"
+    );
+    assert_eq!(
+      next("10-20/5,50 9-17 * 6 1-5", (2026, 1, 1, 0, 0)),
+      "2026-06-01 09:10 Mon"
+    );
+
+    for invalid in [
+      "* * * *",
+      "60 * * * *",
+      "*/0 * * * *",
+      "5-1 * * * *",
+      "a * * * *",
+    ] {
+      assert!(invalid.parse::<CronSchedule>().is_err(), "{invalid}");
+    }
+    // February never has 30 days.
+    assert!(
+      "0 0 30 2 *"
+        .parse::<CronSchedule>()
+        .unwrap()
+        .next_after(Utc::now())
+        .is_none()
+    );
+  }
+
+  #[test]
+  fn scheduled_job_config() {
+    let job = "gc_module_blobs=30  *  * * *"
+      .parse::<ScheduledJobConfig>()
+      .unwrap();
+    assert_eq!(job.kind, ScheduledJobKind::GcModuleBlobs);
+    assert_eq!(job.schedule.as_str(), "30 * * * *");
+    assert!("gc_module_blobs".parse::<ScheduledJobConfig>().is_err());
+    assert!(
+      "run_everything=* * * * *"
+        .parse::<ScheduledJobConfig>()
+        .is_err()
+    );
+  }
+}
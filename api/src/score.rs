@@ -0,0 +1,230 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+//! The active [ScoreSchema] used to compute package scores.
+//!
+//! Score weights live in the `score_schemas` table. The schema with the
+//! highest version is loaded at startup and refreshed periodically, so the
+//! weights can be tuned by creating a new schema through the admin API without
+//! a redeploy. Until the first load completes, [ScoreSchema::builtin] is used.
+//!
+//! When the scoring logic itself changes, [recompute_scores] re-runs score
+//! generation for every published version from its stored doc nodes and
+//! readme.
+
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use futures::StreamExt;
+use once_cell::sync::Lazy;
+use tracing::error;
+use tracing::info;
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::analysis::regenerate_score;
+use crate::background_jobs::VersionJobPayload;
+use crate::background_jobs::run_with_lease;
+use crate::db::BackgroundJobKind;
+use crate::db::Database;
+use crate::db::PackageVersion;
+use crate::db::ScoreRecomputeJobStatus;
+use crate::db::ScoreSchema;
+use crate::s3::Buckets;
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+static ACTIVE_SCHEMA: Lazy<RwLock<Arc<ScoreSchema>>> =
+  Lazy::new(|| RwLock::new(Arc::new(ScoreSchema::builtin())));
+
+pub fn active_schema() -> Arc<ScoreSchema> {
+  ACTIVE_SCHEMA.read().unwrap().clone()
+}
+
+async fn refresh_active_schema(db: &Database) -> Result<(), sqlx::Error> {
+  if let Some(schema) = db.get_latest_score_schema().await? {
+    *ACTIVE_SCHEMA.write().unwrap() = Arc::new(schema);
+  }
+  Ok(())
+}
+
+/// Loads the latest schema from the database and keeps it up to date. New
+/// schemas created on any instance become active everywhere within
+/// [REFRESH_INTERVAL].
+pub fn spawn_refresh_loop(db: Database) {
+  tokio::spawn(async move {
+    let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+    loop {
+      interval.tick().await;
+      if let Err(err) = refresh_active_schema(&db).await {
+        error!("failed to refresh score schema: {err}");
+      }
+    }
+  });
+}
+
+/// Number of versions loaded from the database at once. Progress is written
+/// back after every batch.
+const RECOMPUTE_BATCH_SIZE: i64 = 100;
+/// Number of versions whose doc nodes are downloaded and scored concurrently.
+const RECOMPUTE_PARALLELISM: usize = 8;
+
+/// Re-runs score generation for every package version and stores the result,
+/// updating the progress of the given score recompute job as it goes.
+///
+/// Versions whose doc nodes or readme can not be loaded are counted as failed
+/// and keep their stored score. A database error aborts the job and marks it
+/// as failed. The job holds a lease while it runs, see [run_with_lease].
+#[instrument(name = "recompute_scores", skip(db, buckets))]
+pub async fn recompute_scores(db: Database, buckets: Buckets, job_id: Uuid) {
+  let res =
+    run_with_lease(recompute_scores_inner(&db, &buckets, job_id), |lease| {
+      db.renew_score_recompute_job_lease(job_id, lease)
+    })
+    .await;
+  let (status, error) = match res {
+    Some(Ok(())) => (ScoreRecomputeJobStatus::Completed, None),
+    Some(Err(err)) => {
+      error!("score recompute job {job_id} failed: {err}");
+      (ScoreRecomputeJobStatus::Failed, Some(err.to_string()))
+    }
+    None => {
+      error!("score recompute job {job_id} lost its lease");
+      return;
+    }
+  };
+
+  if let Err(err) = db
+    .finish_score_recompute_job(job_id, status, error.as_deref())
+    .await
+  {
+    error!("failed to finish score recompute job {job_id}: {err}");
+  }
+}
+
+async fn recompute_scores_inner(
+  db: &Database,
+  buckets: &Buckets,
+  job_id: Uuid,
+) -> Result<(), sqlx::Error> {
+  let mut processed = 0;
+  let mut failed = 0;
+  let mut last = None;
+
+  loop {
+    let versions = db
+      .list_package_versions_after(
+        last
+          .as_ref()
+          .map(|(scope, name, version)| (scope, name, version)),
+        RECOMPUTE_BATCH_SIZE,
+      )
+      .await?;
+    let Some(last_in_batch) = versions
+      .last()
+      .map(|v| (v.scope.clone(), v.name.clone(), v.version.clone()))
+    else {
+      break;
+    };
+
+    let mut results = futures::stream::iter(versions)
+      .map(|version| async move {
+        let key = (
+          version.scope.clone(),
+          version.name.clone(),
+          version.version.clone(),
+        );
+        (key, recompute_version_score(db, buckets, version).await)
+      })
+      .buffer_unordered(RECOMPUTE_PARALLELISM);
+    while let Some(((scope, name, version), res)) = results.next().await {
+      processed += 1;
+      if !res? {
+        failed += 1;
+        // The inputs are usually missing because of a transient storage
+        // error, so the version is retried in the background.
+        let job = VersionJobPayload::job(
+          BackgroundJobKind::ScoreRecompute,
+          &scope,
+          &name,
+          &version,
+        );
+        db.enqueue_background_job(job).await?;
+      }
+    }
+    drop(results);
+
+    db.update_score_recompute_job_progress(job_id, processed, failed)
+      .await?;
+    info!(processed, failed, "score recompute progress");
+
+    last = Some(last_in_batch);
+  }
+
+  Ok(())
+}
+
+/// Returns `false` if the inputs for the score could not be loaded.
+pub async fn recompute_version_score(
+  db: &Database,
+  buckets: &Buckets,
+  version: PackageVersion,
+) -> Result<bool, sqlx::Error> {
+  let PackageVersion {
+    scope,
+    name,
+    version,
+    exports,
+    readme_path,
+    meta,
+    ..
+  } = version;
+
+  let doc_nodes =
+    match crate::docs::download_doc_nodes(&scope, &name, &version, buckets)
+      .await
+    {
+      Ok(Some(doc_nodes)) => doc_nodes,
+      Ok(None) => {
+        error!("no doc nodes stored for @{scope}/{name}@{version}");
+        return Ok(false);
+      }
+      Err(err) => {
+        error!("failed to load doc nodes for @{scope}/{name}@{version}: {err}");
+        return Ok(false);
+      }
+    };
+
+  let readme = match &readme_path {
+    Some(path) => {
+      match crate::module_files::download_file(
+        &buckets.modules_bucket,
+        &scope,
+        &name,
+        &version,
+        path,
+      )
+      .await
+      {
+        Ok(Some(bytes)) => Some((path, bytes.to_vec())),
+        Ok(None) => None,
+        Err(err) => {
+          error!("failed to load readme for @{scope}/{name}@{version}: {err}");
+          return Ok(false);
+        }
+      }
+    }
+    None => None,
+  };
+
+  let new_meta = regenerate_score(
+    &exports,
+    &doc_nodes,
+    readme.as_ref().map(|(path, bytes)| (*path, bytes)),
+    &meta,
+  );
+  db.update_package_version_meta(&scope, &name, &version, &new_meta)
+    .await?;
+
+  Ok(true)
+}
@@ -0,0 +1,595 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+//! Package search, behind a [SearchBackend], so that a registry whose search
+//! outgrows the database can serve it from a search engine instead.
+//!
+//! The default backend, [PostgresSearchBackend], searches the `packages`
+//! table directly. External engines, like [MeilisearchBackend], keep their own
+//! index of search documents: after every change to a package, [Database]
+//! queues an update of its document, which the worker started by
+//! [spawn_index_worker] applies to every backend that keeps an index. Updates
+//! that get lost, for example because an instance stopped before it applied
+//! them, are caught up on by the `rebuild_search_index` task, which rebuilds
+//! the indexes from scratch.
+//!
+//! To migrate to another backend, configure it as `search_dual_write`, so that
+//! it is written to but not yet searched, and run `rebuild_search_index`.
+//! Once the rebuild has completed, configure it as `search_backend`.
+
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::StreamExt;
+use futures::stream::BoxStream;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tracing::Instrument;
+use tracing::error;
+use tracing::info_span;
+use tracing::instrument;
+
+use crate::api::ApiPackageScore;
+use crate::db::Database;
+use crate::db::Package;
+use crate::db::PackageSearchFilters;
+use crate::db::PackageVersionMeta;
+use crate::db::PackageVisibility;
+use crate::db::PackageWithGitHubRepoAndMeta;
+use crate::db::RuntimeCompat;
+use crate::external::meilisearch::MeilisearchClient;
+use crate::external::meilisearch::SearchRequest;
+use crate::ids::PackageName;
+use crate::ids::ScopeName;
+
+/// How many packages are loaded and indexed at a time during a rebuild.
+const REBUILD_BATCH_SIZE: i64 = 1000;
+/// How long to wait for a Meilisearch task during a rebuild.
+const MEILISEARCH_TASK_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SearchBackendKind {
+  Postgres,
+  Meilisearch,
+}
+
+impl FromStr for SearchBackendKind {
+  type Err = anyhow::Error;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "postgres" => Ok(Self::Postgres),
+      "meilisearch" => Ok(Self::Meilisearch),
+      _ => Err(anyhow::anyhow!("Invalid search backend '{}'", s)),
+    }
+  }
+}
+
+/// A search for packages by text.
+#[derive(Debug)]
+pub struct PackageSearchQuery<'a> {
+  pub query: &'a str,
+  pub filters: &'a PackageSearchFilters,
+  pub start: i64,
+  pub limit: i64,
+}
+
+/// What search engines index of a package.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageDocument {
+  /// `<scope>_<name>`, which is unique because neither contains `_`.
+  pub id: String,
+  pub scope: ScopeName,
+  pub name: PackageName,
+  pub description: String,
+  pub runtime_compat: RuntimeCompat,
+  /// Whether the latest version has provenance.
+  pub has_provenance: bool,
+  /// The license of the latest version, in lowercase.
+  pub license: Option<String>,
+  pub score: Option<u32>,
+}
+
+impl PackageDocument {
+  /// The document of a package, or `None` if the package must not be found by
+  /// searches.
+  pub fn new(
+    package: &Package,
+    meta: &PackageVersionMeta,
+    license: Option<&str>,
+  ) -> Option<Self> {
+    if package.visibility != PackageVisibility::Public || package.is_archived {
+      return None;
+    }
+    let score = package
+      .latest_version
+      .as_ref()
+      .map(|_| ApiPackageScore::from((meta, package)).score_percentage());
+    Some(Self {
+      id: document_id(&package.scope, &package.name),
+      scope: package.scope.clone(),
+      name: package.name.clone(),
+      description: package.description.clone(),
+      runtime_compat: package.runtime_compat.clone(),
+      has_provenance: meta.has_provenance,
+      license: license.map(str::to_lowercase),
+      score,
+    })
+  }
+}
+
+fn document_id(scope: &ScopeName, name: &PackageName) -> String {
+  format!("{scope}_{name}")
+}
+
+#[async_trait::async_trait]
+pub trait SearchBackend: Send + Sync {
+  fn name(&self) -> &'static str;
+
+  /// Whether the backend keeps an index that has to be kept up to date with
+  /// the packages.
+  fn keeps_index(&self) -> bool {
+    true
+  }
+
+  /// Returns the total number of matches, and the matches from `query.start`
+  /// on, best first.
+  async fn search_packages(
+    &self,
+    db: &Database,
+    query: &PackageSearchQuery<'_>,
+  ) -> Result<(usize, Vec<PackageWithGitHubRepoAndMeta>), anyhow::Error>;
+
+  /// Adds the document of a package to the index, or replaces it.
+  async fn upsert_package(
+    &self,
+    document: &PackageDocument,
+  ) -> Result<(), anyhow::Error>;
+
+  async fn delete_package(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+  ) -> Result<(), anyhow::Error>;
+
+  /// Replaces the index with one of the documents in `batches`, and returns
+  /// how many documents it has. Searches are answered from the old index until
+  /// the new one is complete.
+  async fn rebuild(
+    &self,
+    batches: BoxStream<'_, Result<Vec<PackageDocument>, sqlx::Error>>,
+  ) -> Result<usize, anyhow::Error>;
+}
+
+/// Searches the `packages` table, which needs no separate index.
+pub struct PostgresSearchBackend;
+
+#[async_trait::async_trait]
+impl SearchBackend for PostgresSearchBackend {
+  fn name(&self) -> &'static str {
+    "postgres"
+  }
+
+  fn keeps_index(&self) -> bool {
+    false
+  }
+
+  async fn search_packages(
+    &self,
+    db: &Database,
+    query: &PackageSearchQuery<'_>,
+  ) -> Result<(usize, Vec<PackageWithGitHubRepoAndMeta>), anyhow::Error> {
+    let res = db
+      .list_packages(
+        query.start,
+        query.limit,
+        Some(query.query),
+        None,
+        None,
+        query.filters,
+      )
+      .await?;
+    Ok(res)
+  }
+
+  async fn upsert_package(
+    &self,
+    _document: &PackageDocument,
+  ) -> Result<(), anyhow::Error> {
+    Ok(())
+  }
+
+  async fn delete_package(
+    &self,
+    _scope: &ScopeName,
+    _name: &PackageName,
+  ) -> Result<(), anyhow::Error> {
+    Ok(())
+  }
+
+  async fn rebuild(
+    &self,
+    _batches: BoxStream<'_, Result<Vec<PackageDocument>, sqlx::Error>>,
+  ) -> Result<usize, anyhow::Error> {
+    Ok(0)
+  }
+}
+
+/// Searches a Meilisearch index of [PackageDocument]s. The matches are then
+/// loaded from the database, which also leaves out packages that have become
+/// private or archived since they were indexed.
+pub struct MeilisearchBackend {
+  client: MeilisearchClient,
+  index: String,
+}
+
+#[derive(Deserialize)]
+struct MeilisearchHit {
+  scope: ScopeName,
+  name: PackageName,
+}
+
+impl MeilisearchBackend {
+  pub fn new(client: MeilisearchClient, index: String) -> Self {
+    Self { client, index }
+  }
+
+  async fn run_task(&self, task_uid: u64) -> Result<(), anyhow::Error> {
+    self
+      .client
+      .wait_for_task(task_uid, MEILISEARCH_TASK_TIMEOUT)
+      .await
+  }
+}
+
+#[async_trait::async_trait]
+impl SearchBackend for MeilisearchBackend {
+  fn name(&self) -> &'static str {
+    "meilisearch"
+  }
+
+  async fn search_packages(
+    &self,
+    db: &Database,
+    query: &PackageSearchQuery<'_>,
+  ) -> Result<(usize, Vec<PackageWithGitHubRepoAndMeta>), anyhow::Error> {
+    let res = self
+      .client
+      .search::<MeilisearchHit>(
+        &self.index,
+        &SearchRequest {
+          q: query.query,
+          offset: query.start,
+          limit: query.limit,
+          filter: meilisearch_filter(query.filters),
+          attributes_to_retrieve: &["scope", "name"],
+        },
+      )
+      .await?;
+    let hits = res
+      .hits
+      .into_iter()
+      .map(|hit| (hit.scope, hit.name))
+      .collect::<Vec<_>>();
+    let packages = db.list_packages_by_name(&hits).await?;
+    Ok((res.estimated_total_hits, packages))
+  }
+
+  async fn upsert_package(
+    &self,
+    document: &PackageDocument,
+  ) -> Result<(), anyhow::Error> {
+    self
+      .client
+      .add_documents(&self.index, std::slice::from_ref(document))
+      .await?;
+    Ok(())
+  }
+
+  async fn delete_package(
+    &self,
+    scope: &ScopeName,
+    name: &PackageName,
+  ) -> Result<(), anyhow::Error> {
+    self
+      .client
+      .delete_document(&self.index, &document_id(scope, name))
+      .await?;
+    Ok(())
+  }
+
+  async fn rebuild(
+    &self,
+    mut batches: BoxStream<'_, Result<Vec<PackageDocument>, sqlx::Error>>,
+  ) -> Result<usize, anyhow::Error> {
+    let rebuild_index = format!("{}_rebuild", self.index);
+
+    // Left over from a rebuild that did not complete, if it exists.
+    let task = self.client.delete_index(&rebuild_index).await?;
+    let _ = self.run_task(task).await;
+    let task = self.client.create_index(&rebuild_index, "id").await?;
+    self.run_task(task).await?;
+    let task = self
+      .client
+      .update_settings(&rebuild_index, &meilisearch_settings())
+      .await?;
+    self.run_task(task).await?;
+
+    let mut count = 0;
+    while let Some(batch) = batches.next().await {
+      let batch = batch?;
+      count += batch.len();
+      let task = self.client.add_documents(&rebuild_index, &batch).await?;
+      self.run_task(task).await?;
+    }
+
+    // The index does not exist yet on the first rebuild, and indexes can only
+    // be swapped if both exist.
+    let task = self.client.create_index(&self.index, "id").await?;
+    let _ = self.run_task(task).await;
+    let task = self
+      .client
+      .swap_indexes(&self.index, &rebuild_index)
+      .await?;
+    self.run_task(task).await?;
+    let task = self.client.delete_index(&rebuild_index).await?;
+    self.run_task(task).await?;
+
+    Ok(count)
+  }
+}
+
+fn meilisearch_settings() -> serde_json::Value {
+  serde_json::json!({
+    "searchableAttributes": ["name", "scope", "description"],
+    "filterableAttributes": [
+      "runtimeCompat.browser",
+      "runtimeCompat.deno",
+      "runtimeCompat.node",
+      "runtimeCompat.workerd",
+      "runtimeCompat.bun",
+      "hasProvenance",
+      "license",
+    ],
+    // The default ranking rules, with ties broken by the package score.
+    "rankingRules": [
+      "words",
+      "typo",
+      "proximity",
+      "attribute",
+      "sort",
+      "exactness",
+      "score:desc",
+    ],
+  })
+}
+
+/// The Meilisearch filter expressions, which all have to match, that narrow a
+/// search down like [PackageSearchFilters] narrow down a Postgres search.
+fn meilisearch_filter(filters: &PackageSearchFilters) -> Vec<String> {
+  let compat = &filters.runtime_compat;
+  let mut filter = vec![];
+  for (runtime, compatible) in [
+    ("browser", compat.browser),
+    ("deno", compat.deno),
+    ("node", compat.node),
+    ("workerd", compat.workerd),
+    ("bun", compat.bun),
+  ] {
+    if compatible == Some(true) {
+      filter.push(format!("runtimeCompat.{runtime} = true"));
+    }
+  }
+  if let Some(has_provenance) = filters.has_provenance {
+    filter.push(format!("hasProvenance = {has_provenance}"));
+  }
+  if let Some(license) = &filters.license {
+    // A JSON string literal is a valid Meilisearch string literal.
+    let license = serde_json::to_string(&license.to_lowercase()).unwrap();
+    filter.push(format!("license = {license}"));
+  }
+  filter
+}
+
+/// Stored in the routerify data map. Searches go to `backend`, and the index
+/// updates go to both `backend` and `dual_write`.
+#[derive(Clone)]
+pub struct PackageSearch {
+  backend: Arc<dyn SearchBackend>,
+  dual_write: Option<Arc<dyn SearchBackend>>,
+}
+
+impl PackageSearch {
+  pub fn new(
+    backend: Arc<dyn SearchBackend>,
+    dual_write: Option<Arc<dyn SearchBackend>>,
+  ) -> Self {
+    Self {
+      backend,
+      dual_write,
+    }
+  }
+
+  /// Searches the `packages` table only.
+  pub fn postgres() -> Self {
+    Self::new(Arc::new(PostgresSearchBackend), None)
+  }
+
+  /// The backends whose index is kept up to date.
+  fn indexes(&self) -> impl Iterator<Item = &Arc<dyn SearchBackend>> {
+    std::iter::once(&self.backend)
+      .chain(&self.dual_write)
+      .filter(|backend| backend.keeps_index())
+  }
+
+  pub fn has_indexes(&self) -> bool {
+    self.indexes().next().is_some()
+  }
+
+  /// Searches packages by text. If an external search engine fails, the
+  /// search is answered from Postgres instead.
+  #[instrument(name = "PackageSearch::search_packages", skip(self, db), err)]
+  pub async fn search_packages(
+    &self,
+    db: &Database,
+    query: &PackageSearchQuery<'_>,
+  ) -> Result<(usize, Vec<PackageWithGitHubRepoAndMeta>), anyhow::Error> {
+    match self.backend.search_packages(db, query).await {
+      Ok(res) => Ok(res),
+      Err(err) if self.backend.keeps_index() => {
+        error!(
+          "package search with {} failed, using postgres: {err:#}",
+          self.backend.name()
+        );
+        PostgresSearchBackend.search_packages(db, query).await
+      }
+      Err(err) => Err(err),
+    }
+  }
+
+  /// Brings the document of a package up to date in all indexes, or deletes
+  /// it if the package is gone or must not be found anymore.
+  async fn update_package(
+    &self,
+    db: &Database,
+    scope: &ScopeName,
+    name: &PackageName,
+  ) -> Result<(), anyhow::Error> {
+    let document = db
+      .get_package_for_search_index(scope, name)
+      .await?
+      .and_then(|(package, meta, license)| {
+        PackageDocument::new(&package, &meta, license.as_deref())
+      });
+    for backend in self.indexes() {
+      let res = match &document {
+        Some(document) => backend.upsert_package(document).await,
+        None => backend.delete_package(scope, name).await,
+      };
+      if let Err(err) = res {
+        error!(
+          "failed to update @{scope}/{name} in the {} search index: {err:#}",
+          backend.name()
+        );
+      }
+    }
+    Ok(())
+  }
+
+  /// Rebuilds all indexes from the database. Returns the name of each backend
+  /// that keeps an index, with the number of documents in it.
+  pub async fn rebuild_indexes(
+    &self,
+    db: &Database,
+  ) -> Result<Vec<(&'static str, usize)>, anyhow::Error> {
+    let mut counts = vec![];
+    for backend in self.indexes() {
+      let count = backend.rebuild(document_batches(db)).await?;
+      counts.push((backend.name(), count));
+    }
+    Ok(counts)
+  }
+}
+
+/// The documents of all packages that can be found by searches, in batches.
+fn document_batches(
+  db: &Database,
+) -> BoxStream<'_, Result<Vec<PackageDocument>, sqlx::Error>> {
+  let start: Option<Option<(ScopeName, PackageName)>> = Some(None);
+  futures::stream::try_unfold(start, move |after| async move {
+    let Some(after) = after else {
+      return Ok(None);
+    };
+    let packages = db
+      .list_packages_for_search_index(
+        after.as_ref().map(|(scope, name)| (scope, name)),
+        REBUILD_BATCH_SIZE,
+      )
+      .await?;
+    let next = match packages.last() {
+      Some((package, _, _)) if packages.len() as i64 == REBUILD_BATCH_SIZE => {
+        Some(Some((package.scope.clone(), package.name.clone())))
+      }
+      _ => None,
+    };
+    let documents = packages
+      .iter()
+      .filter_map(|(package, meta, license)| {
+        PackageDocument::new(package, meta, license.as_deref())
+      })
+      .collect::<Vec<_>>();
+    Ok::<_, sqlx::Error>(Some((documents, next)))
+  })
+  .boxed()
+}
+
+/// Where [Database] queues the packages whose search documents have to be
+/// updated, see [spawn_index_worker].
+#[derive(Debug, Clone)]
+pub struct SearchIndexQueue(mpsc::UnboundedSender<(ScopeName, PackageName)>);
+
+impl SearchIndexQueue {
+  pub fn new() -> (Self, SearchIndexReceiver) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    (Self(tx), SearchIndexReceiver(rx))
+  }
+
+  pub fn package_changed(&self, scope: &ScopeName, name: &PackageName) {
+    // The worker only stops when the process does.
+    let _ = self.0.send((scope.clone(), name.clone()));
+  }
+}
+
+pub struct SearchIndexReceiver(
+  mpsc::UnboundedReceiver<(ScopeName, PackageName)>,
+);
+
+/// Updates the search documents of the packages that are queued on the
+/// [SearchIndexQueue] of `rx`, one at a time.
+pub fn spawn_index_worker(
+  db: Database,
+  search: PackageSearch,
+  mut rx: SearchIndexReceiver,
+) {
+  tokio::spawn(async move {
+    while let Some((scope, name)) = rx.0.recv().await {
+      let span = info_span!("search_index_update", %scope, %name);
+      let res = search
+        .update_package(&db, &scope, &name)
+        .instrument(span)
+        .await;
+      if let Err(err) = res {
+        error!(
+          "failed to update the search document of @{scope}/{name}: {err:#}"
+        );
+      }
+    }
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::meilisearch_filter;
+  use crate::db::PackageSearchFilters;
+
+  #[test]
+  fn filter() {
+    assert!(meilisearch_filter(&PackageSearchFilters::default()).is_empty());
+
+    let mut filters = PackageSearchFilters::default();
+    filters.runtime_compat.deno = Some(true);
+    filters.runtime_compat.node = Some(true);
+    filters.has_provenance = Some(false);
+    filters.license = Some("Apache-2.0 \"OR\" MIT".to_string());
+    assert_eq!(
+      meilisearch_filter(&filters),
+      vec![
+        "runtimeCompat.deno = true",
+        "runtimeCompat.node = true",
+        "hasProvenance = false",
+        r#"license = "apache-2.0 \"or\" mit""#,
+      ]
+    );
+  }
+}
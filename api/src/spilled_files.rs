@@ -0,0 +1,118 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+//! Large files of a package that is being published are written to a
+//! temporary directory while the tarball is extracted, instead of being kept
+//! in memory until the publish completes. Only files that the analysis of the
+//! package never reads are spilled, so they are only read back to be stored
+//! and to be included in the npm tarball.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+
+use bytes::Bytes;
+use uuid::Uuid;
+
+use crate::ids::PackagePath;
+
+#[derive(Default)]
+pub struct SpilledFiles {
+  /// Created when the first file is spilled.
+  dir: Option<PathBuf>,
+  files: HashMap<PackagePath, PathBuf>,
+}
+
+impl SpilledFiles {
+  /// Creates the file that the given package file is spilled to. The caller
+  /// writes the contents to it.
+  pub async fn create(
+    &mut self,
+    path: &PackagePath,
+  ) -> io::Result<tokio::fs::File> {
+    let dir = match &self.dir {
+      Some(dir) => dir.clone(),
+      None => {
+        let dir =
+          std::env::temp_dir().join(format!("jsr-publish-{}", Uuid::new_v4()));
+        tokio::fs::create_dir(&dir).await?;
+        self.dir = Some(dir.clone());
+        dir
+      }
+    };
+    // Package paths can't be used as file names as is, so the files are
+    // numbered instead.
+    let file_path = dir.join(self.files.len().to_string());
+    let file = tokio::fs::File::create(&file_path).await?;
+    self.files.insert(path.clone(), file_path);
+    Ok(file)
+  }
+
+  pub fn contains(&self, path: &PackagePath) -> bool {
+    self.files.contains_key(path)
+  }
+
+  pub fn paths(&self) -> impl Iterator<Item = &PackagePath> {
+    self.files.keys()
+  }
+
+  /// Reads a spilled file, from synchronous code like the analysis.
+  pub fn read(&self, path: &PackagePath) -> io::Result<Option<Vec<u8>>> {
+    match self.files.get(path) {
+      Some(file_path) => std::fs::read(file_path).map(Some),
+      None => Ok(None),
+    }
+  }
+
+  pub async fn read_async(
+    &self,
+    path: &PackagePath,
+  ) -> io::Result<Option<Bytes>> {
+    match self.files.get(path) {
+      Some(file_path) => {
+        tokio::fs::read(file_path).await.map(|b| Some(b.into()))
+      }
+      None => Ok(None),
+    }
+  }
+}
+
+impl Drop for SpilledFiles {
+  fn drop(&mut self) {
+    if let Some(dir) = &self.dir
+      && let Err(err) = std::fs::remove_dir_all(dir)
+    {
+      tracing::error!("failed to remove {}: {err}", dir.display());
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use tokio::io::AsyncWriteExt;
+
+  use super::SpilledFiles;
+  use crate::ids::PackagePath;
+
+  #[tokio::test]
+  async fn spill_and_read() {
+    let path = PackagePath::try_from("/data/large.bin").unwrap();
+    let other = PackagePath::try_from("/mod.ts").unwrap();
+
+    let mut spilled = SpilledFiles::default();
+    let mut file = spilled.create(&path).await.unwrap();
+    file.write_all(b"hello").await.unwrap();
+    file.flush().await.unwrap();
+    drop(file);
+
+    assert!(spilled.contains(&path));
+    assert!(!spilled.contains(&other));
+    assert_eq!(spilled.paths().collect::<Vec<_>>(), vec![&path]);
+    assert_eq!(spilled.read(&path).unwrap().unwrap(), b"hello");
+    assert_eq!(spilled.read_async(&path).await.unwrap().unwrap(), "hello");
+    assert!(spilled.read(&other).unwrap().is_none());
+
+    let dir = spilled.dir.clone().unwrap();
+    assert!(dir.exists());
+    drop(spilled);
+    assert!(!dir.exists());
+  }
+}
@@ -3,6 +3,7 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::io;
 use std::sync::OnceLock;
+use std::time::Instant;
 
 use async_tar::EntryType;
 use bytes::Bytes;
@@ -10,6 +11,7 @@ use deno_ast::MediaType;
 use deno_graph::ModuleGraphError;
 use deno_semver::jsr::JsrPackageReqReference;
 use deno_semver::npm::NpmPackageReqReference;
+use deno_semver::package::PackageNv;
 use deno_semver::package::PackageReq;
 use deno_semver::package::PackageReqReference;
 use deno_semver::package::PackageReqReferenceParseError;
@@ -22,39 +24,66 @@ use serde::Deserialize;
 use serde::Serialize;
 use sha2::Digest;
 use thiserror::Error;
+use tokio::io::AsyncWriteExt;
 use tracing::Span;
+use tracing::error;
 use tracing::instrument;
 use url::Url;
 use uuid::Uuid;
 
+use crate::analysis::DeprecatedSymbol;
+use crate::analysis::DocLink;
+use crate::analysis::ExportedSymbol;
 use crate::analysis::PackageAnalysisData;
 use crate::analysis::PackageAnalysisOutput;
+use crate::analysis::SourceView;
 use crate::analysis::analyze_package;
 use crate::db::Database;
+use crate::db::DocArtifactKind;
 use crate::db::ExportsMap;
 use crate::db::PublishingTask;
 use crate::db::{DependencyKind, PackageVersionMeta};
 use crate::ids::CaseInsensitivePackagePath;
+use crate::ids::PackageName;
 use crate::ids::PackagePath;
 use crate::ids::PackagePathValidationError;
+use crate::ids::ScopeName;
 use crate::ids::ScopedPackageName;
 use crate::ids::ScopedPackageNameValidateError;
 use crate::ids::Version;
+use crate::integrity::sha256_digest;
+use crate::malware_scan::MalwareScanners;
+use crate::malware_scan::ScanFinding;
+use crate::metrics::PublishStage;
+use crate::module_files::FilesManifest;
+use crate::module_files::ManifestFile;
 use crate::npm::NPM_TARBALL_REVISION;
+use crate::npm::NpmTarball;
+use crate::publish_limits::MAX_PACKAGE_PATH_LENGTH;
+use crate::publish_limits::PublishLimits;
 use crate::s3::Buckets;
 use crate::s3::CACHE_CONTROL_IMMUTABLE;
+use crate::s3::ContentEncoding;
 use crate::s3::S3Error;
 use crate::s3::S3UploadOptions;
-use crate::s3::UploadTaskBody;
-use crate::s3_paths::file_path;
+use crate::s3_paths::module_blob_path;
 use crate::s3_paths::npm_tarball_path;
+use crate::s3_paths::version_files_manifest;
+use crate::sbom::SbomDependency;
+use crate::sbom::SbomFile;
+use crate::sbom::SbomFormat;
+use crate::sbom::SbomInput;
+use crate::spilled_files::SpilledFiles;
+use crate::type_graph::TypeGraph;
 use crate::util::LicenseStore;
 
-const MAX_FILE_SIZE: u64 = 20 * 1024 * 1024; // 20 MB
-const MAX_TOTAL_FILE_SIZE: u64 = 20 * 1024 * 1024; // 20 MB
-const HIGH_MAX_FILE_SIZE: u64 = 20 * 1024 * 1024; // 40 MB
-const HIGH_MAX_TOTAL_FILE_SIZE: u64 = 20 * 1024 * 1024; // 40 MB
 const MAX_CONCURRENT_UPLOADS: usize = 64;
+/// Files larger than this that the analysis does not read are spilled to
+/// temporary storage while publishing, see [SpilledFiles].
+const SPILL_FILE_SIZE: u64 = 1024 * 1024; // 1 MB
+/// Spilled files are read back into memory to be stored, so fewer of them are
+/// uploaded at once.
+const MAX_CONCURRENT_SPILLED_UPLOADS: usize = 4;
 
 static MEDIA_INFER: OnceLock<infer::Infer> = OnceLock::new();
 
@@ -63,11 +92,46 @@ pub struct ProcessTarballOutput {
   pub module_graph_2: HashMap<String, deno_graph::analysis::ModuleInfo>,
   pub exports: ExportsMap,
   pub dependencies: HashSet<(DependencyKind, PackageReqReference)>,
+  pub optional_dependencies: HashSet<(DependencyKind, PackageReqReference)>,
+  pub deprecations: Vec<DeprecatedSymbol>,
+  pub symbols: Vec<ExportedSymbol>,
+  pub doc_links: Vec<DocLink>,
   pub npm_tarball_info: NpmTarballInfo,
+  pub doc_artifacts: Vec<DocArtifactInfo>,
   pub readme_path: Option<PackagePath>,
   pub meta: PackageVersionMeta,
   pub doc_search_json: serde_json::Value,
   pub license: String,
+  /// What the malware scanners found in the files of the publish.
+  pub scan_findings: Vec<ScanFinding>,
+}
+
+/// The outcome of [analyze_tarball]: everything that is uploaded and stored
+/// when the package version is published.
+pub struct AnalyzedTarball {
+  pub file_infos: Vec<FileInfo>,
+  pub files: HashMap<PackagePath, Vec<u8>>,
+  pub spilled_files: SpilledFiles,
+  pub exports: ExportsMap,
+  pub module_graph_2: HashMap<String, deno_graph::analysis::ModuleInfo>,
+  pub doc_nodes: deno_doc::ParseOutput,
+  pub doc_search_json: serde_json::Value,
+  pub docs_markdown: String,
+  pub type_graph: TypeGraph,
+  pub dependencies: HashSet<(DependencyKind, PackageReqReference)>,
+  pub optional_dependencies: HashSet<(DependencyKind, PackageReqReference)>,
+  pub deprecations: Vec<DeprecatedSymbol>,
+  pub symbols: Vec<ExportedSymbol>,
+  pub doc_links: Vec<DocLink>,
+  pub source_views: Vec<SourceView>,
+  pub npm_tarball: NpmTarball,
+  pub readme_path: Option<PackagePath>,
+  pub meta: PackageVersionMeta,
+  pub license: String,
+  /// The JSR dependencies on the version of another package of the same
+  /// workspace publish. They are not checked against the published versions,
+  /// but by [crate::analysis::analyze_workspace].
+  pub workspace_dependencies: Vec<PackageReqReference>,
 }
 
 pub struct NpmTarballInfo {
@@ -77,6 +141,25 @@ pub struct NpmTarballInfo {
   pub sha512: String,
   /// The size of the tarball in bytes.
   pub size: u64,
+  /// The SHA-256 digest of the gzipped tarball, as `sha256-<hex>`.
+  pub sha256: String,
+}
+
+/// The digest of a doc artifact that was uploaded, recorded with the version.
+pub struct DocArtifactInfo {
+  pub kind: DocArtifactKind,
+  pub checksum: String,
+  pub size: i64,
+}
+
+impl DocArtifactInfo {
+  fn new(kind: DocArtifactKind, bytes: &[u8]) -> Self {
+    Self {
+      kind,
+      checksum: sha256_digest(bytes),
+      size: bytes.len() as i64,
+    }
+  }
 }
 
 static SUPPORTED_LICENSE_FILE_NAMES: [&str; 12] = [
@@ -96,16 +179,57 @@ static SUPPORTED_LICENSE_FILE_NAMES: [&str; 12] = [
 
 #[instrument(
   name = "process_tarball",
-  skip(buckets, license_store, registry_url, publishing_task),
+  skip(
+    buckets,
+    license_store,
+    malware_scanners,
+    registry_url,
+    publishing_task
+  ),
   err
 )]
 pub async fn process_tarball(
   db: &Database,
   buckets: &Buckets,
   license_store: &LicenseStore,
+  malware_scanners: &MalwareScanners,
   registry_url: Url,
   publishing_task: &PublishingTask,
+  unstable_bytes_imports: bool,
 ) -> Result<ProcessTarballOutput, PublishError> {
+  let analyzed = download_and_analyze_tarball(
+    db,
+    buckets,
+    license_store,
+    registry_url.clone(),
+    publishing_task,
+    unstable_bytes_imports,
+    &[],
+  )
+  .await?;
+  store_analyzed_tarball(
+    db,
+    buckets,
+    malware_scanners,
+    registry_url,
+    publishing_task,
+    analyzed,
+  )
+  .await
+}
+
+/// Downloads and analyzes the tarball of a publishing task. Nothing is stored
+/// yet. `workspace_versions` are the versions of the other packages of a
+/// workspace publish, which dependencies may resolve to.
+pub async fn download_and_analyze_tarball(
+  db: &Database,
+  buckets: &Buckets,
+  license_store: &LicenseStore,
+  registry_url: Url,
+  publishing_task: &PublishingTask,
+  unstable_bytes_imports: bool,
+  workspace_versions: &[PackageNv],
+) -> Result<AnalyzedTarball, PublishError> {
   let tarball_path = bucket_tarball_path(publishing_task.id);
   let stream = buckets
     .publishing_bucket
@@ -116,34 +240,519 @@ pub async fn process_tarball(
     .ok_or(PublishError::MissingTarball)?
     .map_err(io::Error::other);
 
-  let async_read = stream.into_async_read();
+  analyze_tarball(
+    db,
+    license_store,
+    registry_url,
+    &publishing_task.package_scope,
+    &publishing_task.package_name,
+    &publishing_task.package_version,
+    &publishing_task.config_file,
+    unstable_bytes_imports,
+    workspace_versions,
+    stream.into_async_read(),
+  )
+  .await
+}
+
+/// Scans an analyzed tarball for malware and stores its files, docs and npm
+/// tarball. The package version itself is created by the caller.
+#[instrument(
+  name = "store_analyzed_tarball",
+  skip(buckets, malware_scanners, registry_url, publishing_task, analyzed),
+  err
+)]
+pub async fn store_analyzed_tarball(
+  db: &Database,
+  buckets: &Buckets,
+  malware_scanners: &MalwareScanners,
+  registry_url: Url,
+  publishing_task: &PublishingTask,
+  analyzed: AnalyzedTarball,
+) -> Result<ProcessTarballOutput, PublishError> {
+  let AnalyzedTarball {
+    file_infos,
+    files,
+    spilled_files,
+    exports,
+    module_graph_2,
+    doc_nodes,
+    doc_search_json,
+    docs_markdown,
+    type_graph,
+    dependencies,
+    optional_dependencies,
+    deprecations,
+    symbols,
+    doc_links,
+    source_views,
+    npm_tarball,
+    readme_path,
+    meta,
+    license,
+    workspace_dependencies: _,
+  } = analyzed;
+
+  let scan_start = Instant::now();
+  let scan_findings = malware_scanners
+    .scan(&files, &spilled_files)
+    .await
+    .map_err(PublishError::MalwareScanError)?;
+  crate::metrics::observe_publish_stage(
+    PublishStage::MalwareScan,
+    scan_start.elapsed(),
+  );
+
+  // The diff is only for the author to review, so failing to compute it does
+  // not fail the publish.
+  match crate::publish::publish_preview_diff(
+    db,
+    buckets,
+    &publishing_task.package_scope,
+    &publishing_task.package_name,
+    &file_infos,
+    &doc_nodes,
+  )
+  .await
+  {
+    Ok(Some(preview_diff)) => {
+      db.set_publishing_task_preview_diff(
+        publishing_task.id,
+        &serde_json::to_value(preview_diff).unwrap(),
+      )
+      .await?;
+    }
+    Ok(None) => {}
+    Err(err) => error!("failed to compute the preview diff: {err:#}"),
+  }
+
+  // Store the doc nodes as a delta against the most recently published
+  // version, which is usually nearly identical.
+  let delta_base = db
+    .list_package_versions_for_metadata(
+      &publishing_task.package_scope,
+      &publishing_task.package_name,
+    )
+    .await?
+    .into_iter()
+    .max_by_key(|version| version.created_at)
+    .map(|version| version.version);
+  let doc_nodes_bytes = crate::docs::serialize_doc_nodes_for_storage(
+    &publishing_task.package_scope,
+    &publishing_task.package_name,
+    delta_base.as_ref(),
+    &doc_nodes,
+    buckets,
+  )
+  .await;
+
+  // TO ENSURE CONSISTENCY OF FILES IN S3, ALL ERRORS RETURNED AFTER THIS POINT MUST BE RETRYABLE
+
+  let upload_start = Instant::now();
+
+  let mut doc_artifacts = vec![DocArtifactInfo::new(
+    DocArtifactKind::DocNodes,
+    &doc_nodes_bytes,
+  )];
+
+  buckets
+    .docs_bucket
+    .upload(
+      crate::s3_paths::docs_v2_path(
+        &publishing_task.package_scope,
+        &publishing_task.package_name,
+        &publishing_task.package_version,
+      )
+      .into(),
+      crate::s3::UploadTaskBody::Bytes(doc_nodes_bytes),
+      S3UploadOptions {
+        content_type: Some("application/x-msgpack".into()),
+        cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
+        content_encoding: ContentEncoding::Gzip,
+      },
+    )
+    .await
+    .map_err(PublishError::S3UploadError)?;
+
+  let docs_markdown = Bytes::from(docs_markdown);
+  doc_artifacts.push(DocArtifactInfo::new(
+    DocArtifactKind::Markdown,
+    &docs_markdown,
+  ));
+  buckets
+    .docs_bucket
+    .upload(
+      crate::s3_paths::docs_markdown_path(
+        &publishing_task.package_scope,
+        &publishing_task.package_name,
+        &publishing_task.package_version,
+      )
+      .into(),
+      crate::s3::UploadTaskBody::Bytes(docs_markdown),
+      S3UploadOptions {
+        content_type: Some("text/markdown; charset=utf-8".into()),
+        cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
+        content_encoding: ContentEncoding::Identity,
+      },
+    )
+    .await
+    .map_err(PublishError::S3UploadError)?;
+
+  // Source views are keyed by content hash and shared between versions, so
+  // they are not removed together with a version.
+  let mut source_view_uploads = futures::stream::iter(source_views)
+    .map(|view| async move {
+      buckets
+        .docs_bucket
+        .upload(
+          crate::s3_paths::source_view_path(&view.hash).into(),
+          crate::s3::UploadTaskBody::Bytes(Bytes::from(view.html)),
+          S3UploadOptions {
+            content_type: Some("text/html; charset=utf-8".into()),
+            cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
+            content_encoding: ContentEncoding::Identity,
+          },
+        )
+        .await
+        .map_err(PublishError::S3UploadError)
+    })
+    .buffer_unordered(MAX_CONCURRENT_UPLOADS);
+  while let Some(res) = source_view_uploads.next().await {
+    res?;
+  }
+
+  let type_graph = Bytes::from(serde_json::to_vec(&type_graph).unwrap());
+  doc_artifacts.push(DocArtifactInfo::new(
+    DocArtifactKind::TypeGraph,
+    &type_graph,
+  ));
+  buckets
+    .docs_bucket
+    .upload(
+      crate::s3_paths::docs_type_graph_path(
+        &publishing_task.package_scope,
+        &publishing_task.package_name,
+        &publishing_task.package_version,
+      )
+      .into(),
+      crate::s3::UploadTaskBody::Bytes(type_graph),
+      S3UploadOptions {
+        content_type: Some("application/json".into()),
+        cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
+        content_encoding: ContentEncoding::Identity,
+      },
+    )
+    .await
+    .map_err(PublishError::S3UploadError)?;
+
+  let sbom_input = SbomInput {
+    registry_url: &registry_url,
+    scope: &publishing_task.package_scope,
+    package: &publishing_task.package_name,
+    version: &publishing_task.package_version,
+    license: Some(license.as_str()),
+    created_at: chrono::Utc::now(),
+    files: file_infos
+      .iter()
+      .map(|file| SbomFile {
+        path: &file.path,
+        size: file.size,
+        checksum: Some(&file.hash),
+      })
+      .collect(),
+    dependencies: dependencies
+      .iter()
+      .map(|(kind, req)| SbomDependency {
+        kind: *kind,
+        name: &req.req.name,
+        constraint: req.req.version_req.version_text(),
+        is_optional: optional_dependencies.contains(&(*kind, req.clone())),
+      })
+      .collect(),
+  };
+  for format in [SbomFormat::CycloneDx, SbomFormat::Spdx] {
+    buckets
+      .docs_bucket
+      .upload(
+        crate::s3_paths::sbom_path(
+          &publishing_task.package_scope,
+          &publishing_task.package_name,
+          &publishing_task.package_version,
+          format,
+        )
+        .into(),
+        crate::s3::UploadTaskBody::Bytes(Bytes::from(
+          serde_json::to_vec(&sbom_input.generate(format)).unwrap(),
+        )),
+        S3UploadOptions {
+          content_type: Some(format.content_type().into()),
+          cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
+          content_encoding: ContentEncoding::Identity,
+        },
+      )
+      .await
+      .map_err(PublishError::S3UploadError)?;
+  }
+
+  let npm_tarball_info = NpmTarballInfo {
+    sha1: npm_tarball.sha1,
+    sha512: npm_tarball.sha512,
+    size: npm_tarball.tarball.len() as u64,
+    sha256: npm_tarball.sha256,
+  };
+
+  let npm_tarball_path = npm_tarball_path(
+    &publishing_task.package_scope,
+    &publishing_task.package_name,
+    &publishing_task.package_version,
+    NPM_TARBALL_REVISION,
+  );
+  buckets
+    .npm_bucket
+    .upload(
+      npm_tarball_path.into(),
+      crate::s3::UploadTaskBody::Bytes(Bytes::from(npm_tarball.tarball)),
+      S3UploadOptions {
+        content_type: Some("application/octet-stream".into()),
+        cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
+        content_encoding: ContentEncoding::Identity,
+      },
+    )
+    .await
+    .map_err(PublishError::S3UploadError)?;
+
+  // Files are stored by the hash of their content, and only the ones that
+  // are not stored yet, usually because no earlier version contains them,
+  // are uploaded.
+  let file_infos_by_path = file_infos
+    .iter()
+    .map(|file| (&file.path, file))
+    .collect::<HashMap<_, _>>();
+  let mut hashes = file_infos
+    .iter()
+    .map(|file| file.hash.clone())
+    .collect::<Vec<_>>();
+  hashes.sort();
+  hashes.dedup();
+  let stored_hashes = db
+    .reference_module_blobs(&hashes)
+    .await?
+    .into_iter()
+    .collect::<HashSet<_>>();
+
+  let mut files_manifest = FilesManifest::default();
+  let mut new_blobs = HashMap::new();
+  for (path, data) in files {
+    let bytes = Bytes::from(data);
+    let hash = file_infos_by_path[&path].hash.clone();
+    files_manifest.files.insert(
+      path.clone(),
+      ManifestFile {
+        hash: hash.clone(),
+        content_type: file_content_type(&path, &bytes),
+      },
+    );
+    if !stored_hashes.contains(&hash) {
+      new_blobs.entry(hash).or_insert(bytes);
+    }
+  }
+
+  // Each new blob is uploaded once, even if several spilled files contain it.
+  let mut new_spilled_blobs = HashMap::new();
+  for path in spilled_files.paths() {
+    let file = file_infos_by_path[path];
+    if !stored_hashes.contains(&file.hash)
+      && !new_blobs.contains_key(&file.hash)
+    {
+      new_spilled_blobs.entry(&file.hash).or_insert(file);
+    }
+  }
+
+  let new_blob_sizes = new_blobs
+    .iter()
+    .map(|(hash, bytes)| (hash.clone(), bytes.len() as i64))
+    .chain(
+      new_spilled_blobs
+        .values()
+        .map(|file| (file.hash.clone(), file.size as i64)),
+    )
+    .collect::<Vec<_>>();
+  let mut uploads = futures::stream::iter(new_blobs)
+    .map(|(hash, bytes)| upload_module_blob(buckets, hash, bytes))
+    .buffer_unordered(MAX_CONCURRENT_UPLOADS);
+
+  while let Some(res) = uploads.next().await {
+    res?;
+  }
+
+  drop(uploads);
+
+  // Spilled files are read back from temporary storage, both to be uploaded
+  // and to infer their content type, so only a few are in memory at once.
+  let spilled_files = &spilled_files;
+  let mut spilled_uploads = futures::stream::iter(spilled_files.paths())
+    .map(|path| {
+      let file = file_infos_by_path[path];
+      let upload = new_spilled_blobs
+        .get(&file.hash)
+        .is_some_and(|new_blob| new_blob.path == *path);
+      async move {
+        let bytes = spilled_files
+          .read_async(path)
+          .await
+          .map_err(PublishError::SpillError)?
+          .unwrap();
+        let content_type = file_content_type(path, &bytes);
+        if upload {
+          upload_module_blob(buckets, file.hash.clone(), bytes).await?;
+        }
+        Ok::<_, PublishError>((
+          path.clone(),
+          ManifestFile {
+            hash: file.hash.clone(),
+            content_type,
+          },
+        ))
+      }
+    })
+    .buffer_unordered(MAX_CONCURRENT_SPILLED_UPLOADS);
+
+  while let Some(res) = spilled_uploads.next().await {
+    let (path, file) = res?;
+    files_manifest.files.insert(path, file);
+  }
+
+  drop(spilled_uploads);
+
+  db.insert_module_blobs(&new_blob_sizes).await?;
+
+  // The manifest goes last, so that every file it lists is stored.
+  buckets
+    .modules_bucket
+    .upload(
+      version_files_manifest(
+        &publishing_task.package_scope,
+        &publishing_task.package_name,
+        &publishing_task.package_version,
+      )
+      .into(),
+      crate::s3::UploadTaskBody::Bytes(Bytes::from(
+        serde_json::to_vec(&files_manifest).unwrap(),
+      )),
+      S3UploadOptions {
+        content_type: Some("application/json".into()),
+        cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
+        content_encoding: ContentEncoding::Identity,
+      },
+    )
+    .await
+    .map_err(PublishError::S3UploadError)?;
+  crate::metrics::observe_publish_stage(
+    PublishStage::Upload,
+    upload_start.elapsed(),
+  );
+
+  Ok(ProcessTarballOutput {
+    file_infos,
+    module_graph_2,
+    exports,
+    dependencies,
+    optional_dependencies,
+    deprecations,
+    symbols,
+    doc_links,
+    npm_tarball_info,
+    doc_artifacts,
+    readme_path,
+    meta,
+    doc_search_json,
+    license,
+    scan_findings,
+  })
+}
+
+/// The content type a file is served with, from its extension or, failing
+/// that, from its contents.
+pub fn file_content_type(path: &PackagePath, bytes: &[u8]) -> Option<String> {
+  MediaType::from_str(path)
+    .as_content_type()
+    .map(|str| str.to_string())
+    .or_else(|| {
+      MEDIA_INFER
+        .get_or_init(|| {
+          let mut media_infer = infer::Infer::new();
+          media_infer.add("image/svg+xml", "svg", |content_bytes| {
+            (content_bytes.starts_with(b"<svg")
+              || content_bytes.starts_with(b"<?xml"))
+              && content_bytes.ends_with(b"</svg>")
+          });
+          media_infer
+        })
+        .get(bytes)
+        .map(|mimetype| mimetype.mime_type().to_string())
+    })
+}
+
+async fn upload_module_blob(
+  buckets: &Buckets,
+  hash: String,
+  bytes: Bytes,
+) -> Result<(), PublishError> {
+  buckets
+    .modules_bucket
+    .upload_precompressed(
+      module_blob_path(&hash).into(),
+      bytes,
+      S3UploadOptions {
+        content_type: None,
+        cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
+        content_encoding: ContentEncoding::Identity,
+      },
+    )
+    .await
+    .map_err(PublishError::S3UploadError)
+}
+
+/// Whether the analysis of a package may read the contents of the file, which
+/// is the case for modules, the readme and the license file. The config file
+/// and the coverage summary are JSON, so they are modules too.
+fn is_read_by_analysis(path: &PackagePath) -> bool {
+  MediaType::from_str(path) != MediaType::Unknown
+    || path.case_insensitive().is_readme()
+    || SUPPORTED_LICENSE_FILE_NAMES.contains(&&**path)
+}
+
+/// Reads, validates and analyzes a gzipped package tarball the same way a
+/// publish does, without storing anything. The package must exist.
+#[allow(clippy::too_many_arguments)]
+pub async fn analyze_tarball(
+  db: &Database,
+  license_store: &LicenseStore,
+  registry_url: Url,
+  scope: &ScopeName,
+  package_name: &PackageName,
+  version: &Version,
+  config_file_path: &PackagePath,
+  unstable_bytes_imports: bool,
+  workspace_versions: &[PackageNv],
+  tarball: impl futures::AsyncBufRead + Unpin + Send,
+) -> Result<AnalyzedTarball, PublishError> {
   let decompressed =
-    async_compression::futures::bufread::GzipDecoder::new(async_read);
+    async_compression::futures::bufread::GzipDecoder::new(tarball);
   let mut tar = async_tar::Archive::new(decompressed)
     .entries()
     .map_err(from_tarball_io_error)?;
 
   let mut files = HashMap::new();
+  let mut spilled_files = SpilledFiles::default();
   let mut case_insensitive_paths = HashSet::<CaseInsensitivePackagePath>::new();
+  let mut path_issues = Vec::new();
   let mut file_infos = Vec::new();
   let mut total_file_size = 0;
 
-  // TODO: make these configurable through quota fields on the package
-  let max_file_size = if *publishing_task.package_scope == "llamaindex"
-    && *publishing_task.package_name == "core"
-  {
-    HIGH_MAX_FILE_SIZE
-  } else {
-    MAX_FILE_SIZE
-  };
-  let max_total_file_size = if *publishing_task.package_scope == "llamaindex"
-    && *publishing_task.package_name == "core"
-  {
-    HIGH_MAX_TOTAL_FILE_SIZE
-  } else {
-    MAX_TOTAL_FILE_SIZE
-  };
+  let limits = PublishLimits::for_scope(db, scope).await?;
 
+  let unpack_start = Instant::now();
   while let Some(res) = tar.next().await {
     let mut entry = res.map_err(from_tarball_io_error)?;
 
@@ -168,8 +777,19 @@ pub async fn process_tarball(
       }
     }
 
-    let path = PackagePath::new(path.clone())
-      .map_err(|error| PublishError::InvalidPath { path, error })?;
+    let path = match PackagePath::new(path.clone()) {
+      Ok(path) => path,
+      // Collected like the path issues below, to report all of them at once.
+      Err(PackagePathValidationError::TooLong(length)) => {
+        path_issues.push(PathIssue::TooLong {
+          path,
+          length,
+          max_length: limits.max_path_length.min(MAX_PACKAGE_PATH_LENGTH),
+        });
+        continue;
+      }
+      Err(error) => return Err(PublishError::InvalidPath { path, error }),
+    };
 
     if path.starts_with("/.git/") {
       return Err(PublishError::InvalidGitPath {
@@ -177,121 +797,155 @@ pub async fn process_tarball(
       });
     }
 
+    if file_infos.len() >= limits.max_file_count {
+      return Err(PublishError::TooManyFiles {
+        max_count: limits.max_file_count,
+      });
+    }
+
     let size = header.size().map_err(from_tarball_io_error)?;
-    if size > max_file_size {
+    if size > limits.max_file_size {
       return Err(PublishError::FileTooLarge {
         path,
-        max_size: max_file_size,
+        max_size: limits.max_file_size,
         size,
       });
     }
     total_file_size += size;
 
-    if total_file_size > max_total_file_size {
+    if total_file_size > limits.max_total_file_size {
       return Err(PublishError::PackageTooLarge {
         path,
-        max_size: max_total_file_size,
+        max_size: limits.max_total_file_size,
         size: total_file_size,
       });
     }
 
-    let mut bytes = Vec::new();
-    entry
-      .read_to_end(&mut bytes)
-      .await
-      .map_err(from_tarball_io_error)?;
-
-    // sha256 hash the bytes
-    let hash = sha2::Sha256::digest(&bytes);
-    let hash = format!("sha256-{:x}", hash);
-
-    // check for case-insensitive duplicate paths
+    // Check for case-insensitive duplicate paths, which can not be checked
+    // out side by side on macOS and Windows. Package paths are ASCII, so they
+    // can not differ in their Unicode normalization form. Problems with paths
+    // are collected, to report all of them once the tarball is unpacked.
     let case_insensitive_path = path.case_insensitive();
     if let Some(existing) = case_insensitive_paths.get(&case_insensitive_path) {
-      return Err(PublishError::CaseInsensitiveDuplicatePath {
+      path_issues.push(PathIssue::CaseInsensitiveDuplicate {
         a: path.clone(),
         b: existing.clone().into_inner().into_owned(),
       });
+    } else {
+      case_insensitive_paths.insert(case_insensitive_path.to_owned());
     }
-    case_insensitive_paths.insert(case_insensitive_path.to_owned());
 
-    if files.insert(path.clone(), bytes).is_some() {
-      unreachable!("duplicate path: {:?}", path);
+    let depth = path.matches('/').count() - 1;
+    if depth > limits.max_path_depth {
+      path_issues.push(PathIssue::TooDeep {
+        path: path.clone(),
+        depth,
+        max_depth: limits.max_path_depth,
+      });
+    }
+    let length = path.chars().count();
+    if length > limits.max_path_length {
+      path_issues.push(PathIssue::TooLong {
+        path: path.to_string(),
+        length,
+        max_length: limits.max_path_length,
+      });
     }
 
+    let mut hasher = sha2::Sha256::new();
+    if size > SPILL_FILE_SIZE && !is_read_by_analysis(&path) {
+      let mut file = spilled_files
+        .create(&path)
+        .await
+        .map_err(PublishError::SpillError)?;
+      let mut buf = vec![0; 64 * 1024];
+      loop {
+        let n = entry.read(&mut buf).await.map_err(from_tarball_io_error)?;
+        if n == 0 {
+          break;
+        }
+        hasher.update(&buf[..n]);
+        file
+          .write_all(&buf[..n])
+          .await
+          .map_err(PublishError::SpillError)?;
+      }
+      file.flush().await.map_err(PublishError::SpillError)?;
+    } else {
+      let mut bytes = Vec::new();
+      entry
+        .read_to_end(&mut bytes)
+        .await
+        .map_err(from_tarball_io_error)?;
+      hasher.update(&bytes);
+      if files.insert(path.clone(), bytes).is_some() {
+        unreachable!("duplicate path: {:?}", path);
+      }
+    }
+    let hash = format!("sha256-{:x}", hasher.finalize());
+
     let file_info = FileInfo { path, hash, size };
     file_infos.push(file_info);
   }
+  crate::metrics::observe_publish_stage(
+    PublishStage::Unpack,
+    unpack_start.elapsed(),
+  );
+  crate::metrics::observe_tarball_size(total_file_size);
 
-  let config_file_bytes =
-    files.get(&publishing_task.config_file).ok_or_else(|| {
-      PublishError::MissingConfigFile(Box::new(
-        publishing_task.config_file.clone(),
-      ))
-    })?;
-  let config_file_str =
-    std::str::from_utf8(config_file_bytes).map_err(|e| {
-      PublishError::InvalidConfigFile {
-        path: Box::new(publishing_task.config_file.clone()),
-        error: e.into(),
-      }
-    })?;
-  let config_file_value: serde_json::Value =
-    jsonc_parser::parse_to_serde_value(
-      config_file_str,
-      &ParseOptions::default(),
-    )
-    .map_err(|e| PublishError::InvalidConfigFile {
-      path: Box::new(publishing_task.config_file.clone()),
-      error: e.into(),
-    })?
-    .ok_or(PublishError::InvalidConfigFile {
-      path: Box::new(publishing_task.config_file.clone()),
-      error: anyhow::anyhow!("config file must not be empty"),
-    })?;
-  let config_file: ConfigFile = serde_json::from_value(config_file_value)
-    .map_err(|e| PublishError::InvalidConfigFile {
-      path: Box::new(publishing_task.config_file.clone()),
-      error: e.into(),
-    })?;
-
-  let publishing_task_scoped_package_name = ScopedPackageName {
-    scope: publishing_task.package_scope.clone(),
-    package: publishing_task.package_name.clone(),
+  if !path_issues.is_empty() {
+    return Err(PublishError::InvalidPaths(path_issues));
+  }
+
+  let config_file_bytes = files.get(config_file_path).ok_or_else(|| {
+    PublishError::MissingConfigFile(Box::new(config_file_path.clone()))
+  })?;
+  let config_file = parse_config_file(config_file_path, config_file_bytes)?;
+
+  let scoped_package_name = ScopedPackageName {
+    scope: scope.clone(),
+    package: package_name.clone(),
   };
-  if config_file.name != publishing_task_scoped_package_name {
+  if config_file.name != scoped_package_name {
     return Err(PublishError::ConfigFileNameMismatch {
-      path: Box::new(publishing_task.config_file.clone()),
+      path: Box::new(config_file_path.clone()),
       deno_json_name: config_file.name,
-      publish_task_name: publishing_task_scoped_package_name,
+      publish_task_name: scoped_package_name,
     });
   }
   if let Some(config_file_version) = config_file.version
-    && config_file_version != publishing_task.package_version
+    && config_file_version != *version
   {
     return Err(PublishError::ConfigFileVersionMismatch {
-      path: Box::new(publishing_task.config_file.clone()),
+      path: Box::new(config_file_path.clone()),
       deno_json_version: Box::new(config_file_version),
-      publish_task_version: Box::new(publishing_task.package_version.clone()),
+      publish_task_version: Box::new(version.clone()),
     });
   }
 
   let exports =
     exports_map_from_json(config_file.exports).map_err(|invalid_exports| {
       PublishError::ConfigFileExportsInvalid {
-        path: Box::new(publishing_task.config_file.clone()),
+        path: Box::new(config_file_path.clone()),
         invalid_exports,
       }
     })?;
 
   if exports.is_empty() {
     return Err(PublishError::ConfigFileExportsInvalid {
-      path: Box::new(publishing_task.config_file.clone()),
+      path: Box::new(config_file_path.clone()),
       invalid_exports: "exports config must have at least one entry"
         .to_string(),
     });
   }
 
+  let (exports, export_patterns) =
+    expand_export_patterns(exports, files.keys().chain(spilled_files.paths()))
+      .map_err(|invalid_exports| PublishError::ConfigFileExportsInvalid {
+        path: Box::new(config_file_path.clone()),
+        invalid_exports,
+      })?;
+
   let license = if let Some(license) = config_file.license {
     if !license_store.is_recognized(&license) {
       return Err(PublishError::InvalidLicense);
@@ -321,18 +975,131 @@ pub async fn process_tarball(
     license.ok_or_else(|| PublishError::MissingLicense)?
   };
 
+  // Glob patterns are not supported, and are skipped.
+  let test_include = config_file
+    .test
+    .include
+    .iter()
+    .filter(|path| !path.contains('*'))
+    .filter_map(|path| resolve_config_relative_path(config_file_path, path))
+    .collect::<Vec<_>>();
+
+  let coverage = if let Some(coverage_path) = config_file.coverage {
+    let invalid_coverage =
+      |error: String| PublishError::ConfigFileCoverageInvalid {
+        path: Box::new(config_file_path.clone()),
+        error,
+      };
+    let path = resolve_config_relative_path(config_file_path, &coverage_path)
+      .ok_or_else(|| {
+      invalid_coverage(format!(
+        "the coverage summary '{coverage_path}' is outside of the package"
+      ))
+    })?;
+    let path = PackagePath::new(path)
+      .map_err(|err| invalid_coverage(err.to_string()))?;
+    let bytes = files.get(&path).ok_or_else(|| {
+      invalid_coverage(format!(
+        "the coverage summary '{path}' is not included in the package"
+      ))
+    })?;
+    Some(
+      crate::analysis::parse_coverage_summary(bytes)
+        .map_err(invalid_coverage)?,
+    )
+  } else {
+    None
+  };
+
+  let npm_cjs = config_file.npm.cjs;
+  let npm_dts_rollup = config_file.npm.dts_rollup;
+
+  for pattern in &config_file.npm.exclude {
+    if !is_valid_npm_exclude_pattern(pattern) {
+      return Err(PublishError::ConfigFileNpmExcludeInvalid {
+        path: Box::new(config_file_path.clone()),
+        error: format!("'{pattern}' is not a valid path pattern"),
+      });
+    }
+  }
+  let npm_exclude = config_file.npm.exclude;
+
+  let peer_dependencies = config_file
+    .peer_dependencies
+    .iter()
+    .map(|specifier| crate::analysis::parse_peer_dependency(specifier))
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|error| PublishError::ConfigFilePeerDependenciesInvalid {
+      path: Box::new(config_file_path.clone()),
+      error,
+    })?;
+
+  for (name, path) in config_file.bin.iter() {
+    let invalid_bin = |error: String| PublishError::ConfigFileBinInvalid {
+      path: Box::new(config_file_path.clone()),
+      error,
+    };
+    if !is_valid_bin_name(name) {
+      return Err(invalid_bin(format!("'{name}' is not a valid command name")));
+    }
+    if !exports.iter().any(|(_, export)| export == path) {
+      return Err(invalid_bin(format!(
+        "'{name}' runs '{path}', which is not an entrypoint in 'exports'"
+      )));
+    }
+  }
+  let bin = config_file.bin;
+
+  let (package, _, _) =
+    db.get_package(scope, package_name).await?.ok_or_else(|| {
+      PublishError::UnexpectedError(format!(
+        "package not found: @{scope}/{package_name}"
+      ))
+    })?;
+
+  let runtime_compat = package.runtime_compat;
+
   let span = Span::current();
-  let scope = publishing_task.package_scope.clone();
-  let package = publishing_task.package_name.clone();
-  let version = publishing_task.package_version.clone();
-  let config_file = publishing_task.config_file.clone();
-  let analysis_data = PackageAnalysisData { exports, files };
+  let scope = scope.clone();
+  let package = package_name.clone();
+  let version = version.clone();
+  let config_file = config_file_path.clone();
+  let analysis_data = PackageAnalysisData {
+    exports,
+    files,
+    spilled_files,
+    test_include,
+    coverage,
+    npm_cjs,
+    npm_dts_rollup,
+    peer_dependencies,
+    runtime_compat,
+    bin,
+    export_patterns,
+    npm_exclude,
+    unstable_bytes_imports,
+    max_module_count: limits.max_module_count,
+  };
   let PackageAnalysisOutput {
-    data: PackageAnalysisData { exports, files },
+    data:
+      PackageAnalysisData {
+        exports,
+        files,
+        spilled_files,
+        peer_dependencies,
+        ..
+      },
     module_graph_2,
-    doc_nodes_bytes,
+    doc_nodes,
     doc_search_json,
+    docs_markdown,
+    type_graph,
     dependencies,
+    optional_dependencies,
+    deprecations,
+    symbols,
+    doc_links,
+    source_views,
     npm_tarball,
     readme_path,
     meta,
@@ -350,9 +1117,22 @@ pub async fn process_tarball(
   .await
   .map_err(|e| PublishError::UnexpectedError(format!("{:?}", e)))??;
 
-  // ensure all of the JSR dependencies are resolvable
-  for (kind, req) in dependencies.iter() {
+  // ensure all of the JSR dependencies are resolvable, except for optional
+  // ones, which the package is expected to handle being unavailable
+  let mut workspace_dependencies = Vec::new();
+  for (kind, req) in dependencies
+    .iter()
+    .filter(|dependency| !optional_dependencies.contains(dependency))
+    .chain(peer_dependencies.iter())
+  {
     if kind == &DependencyKind::Jsr {
+      if workspace_versions.iter().any(|nv| {
+        nv.name == req.req.name && req.req.version_req.matches(&nv.version)
+      }) {
+        workspace_dependencies.push(req.clone());
+        continue;
+      }
+
       let package_scope = ScopedPackageName::new(req.req.name.to_string())
         .map_err(|e| {
           PublishError::InvalidJsrScopedPackageName(req.req.name.clone(), e)
@@ -364,21 +1144,35 @@ pub async fn process_tarball(
           &package_scope.package,
         )
         .await?;
+      // Dependencies on a package that was transferred to another scope are
+      // resolved against the package at its new name.
+      if versions.is_empty()
+        && let Some(redirect) = db
+          .get_package_redirect(&package_scope.scope, &package_scope.package)
+          .await?
+      {
+        versions = db
+          .list_package_versions_for_resolution(
+            &redirect.target_scope,
+            &redirect.target_name,
+          )
+          .await?;
+      }
       versions.sort_by(|a, b| b.version.cmp(&a.version));
 
+      // Yanked versions only satisfy exact pins, not ranges.
+      let is_exact_pin = deno_semver::Version::parse_standard(
+        req.req.version_req.version_text().trim_start_matches('='),
+      )
+      .is_ok();
+
       let mut found = false;
       for version in versions.iter().rev() {
+        if version.is_yanked && !is_exact_pin {
+          continue;
+        }
         if req.req.version_req.matches(&version.version.0) {
-          let exports_key = if let Some(sub_path) = &req.sub_path {
-            if sub_path.is_empty() {
-              ".".to_owned()
-            } else {
-              format!("./{}", sub_path)
-            }
-          } else {
-            ".".to_owned()
-          };
-
+          let exports_key = dependency_exports_key(req);
           if !version.exports.contains_key(&exports_key) {
             return Err(PublishError::InvalidJsrDependencySubPath {
               req: Box::new(req.clone()),
@@ -398,125 +1192,53 @@ pub async fn process_tarball(
     }
   }
 
-  // TO ENSURE CONSISTENCY OF FILES IN S3, ALL ERRORS RETURNED AFTER THIS POINT MUST BE RETRYABLE
-
-  buckets
-    .docs_bucket
-    .upload(
-      crate::s3_paths::docs_v2_path(
-        &publishing_task.package_scope,
-        &publishing_task.package_name,
-        &publishing_task.package_version,
-      )
-      .into(),
-      crate::s3::UploadTaskBody::Bytes(doc_nodes_bytes),
-      S3UploadOptions {
-        content_type: Some("application/x-msgpack".into()),
-        cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
-        gzip_encoded: true,
-      },
-    )
-    .await
-    .map_err(PublishError::S3UploadError)?;
-
-  let npm_tarball_info = NpmTarballInfo {
-    sha1: npm_tarball.sha1,
-    sha512: npm_tarball.sha512,
-    size: npm_tarball.tarball.len() as u64,
-  };
-
-  let npm_tarball_path = npm_tarball_path(
-    &publishing_task.package_scope,
-    &publishing_task.package_name,
-    &publishing_task.package_version,
-    NPM_TARBALL_REVISION,
-  );
-  buckets
-    .npm_bucket
-    .upload(
-      npm_tarball_path.into(),
-      crate::s3::UploadTaskBody::Bytes(Bytes::from(npm_tarball.tarball)),
-      S3UploadOptions {
-        content_type: Some("application/octet-stream".into()),
-        cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
-        gzip_encoded: false,
-      },
-    )
-    .await
-    .map_err(PublishError::S3UploadError)?;
-
-  let mut uploads = futures::stream::iter(files)
-    .map(|(path, data)| {
-      let bytes = Bytes::from(data);
-      let media_type = MediaType::from_str(&path);
-      let maybe_content_type = media_type
-        .as_content_type()
-        .map(|str| str.to_string())
-        .or_else(|| {
-          MEDIA_INFER
-            .get_or_init(|| {
-              let mut media_infer = infer::Infer::new();
-              media_infer.add("image/svg+xml", "svg", |content_bytes| {
-                (content_bytes.starts_with(b"<svg")
-                  || content_bytes.starts_with(b"<?xml"))
-                  && content_bytes.ends_with(b"</svg>")
-              });
-              media_infer
-            })
-            .get(&bytes)
-            .map(|mimetype| mimetype.mime_type().to_string())
-        });
-      (path, bytes, maybe_content_type)
-    })
-    .map(|(path, bytes, maybe_content_type)| {
-      let s3_path = file_path(
-        &publishing_task.package_scope,
-        &publishing_task.package_name,
-        &publishing_task.package_version,
-        &path,
-      );
-
-      async move {
-        buckets
-          .modules_bucket
-          .upload(
-            s3_path.into(),
-            UploadTaskBody::Bytes(bytes),
-            S3UploadOptions {
-              content_type: maybe_content_type.map(Into::into),
-              cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
-              gzip_encoded: false,
-            },
-          )
-          .await
-          .map_err(PublishError::S3UploadError)
-      }
-    })
-    .buffer_unordered(MAX_CONCURRENT_UPLOADS);
-
-  while let Some(res) = uploads.next().await {
-    res?;
-  }
-
-  drop(uploads);
-
-  Ok(ProcessTarballOutput {
+  Ok(AnalyzedTarball {
     file_infos,
-    module_graph_2,
+    files,
+    spilled_files,
     exports,
+    module_graph_2,
+    doc_nodes,
+    doc_search_json,
+    docs_markdown,
+    type_graph,
     dependencies,
-    npm_tarball_info,
+    optional_dependencies,
+    deprecations,
+    symbols,
+    doc_links,
+    source_views,
+    npm_tarball,
     readme_path,
     meta,
-    doc_search_json,
     license,
+    workspace_dependencies,
   })
 }
 
+/// The key in the exports of a package that a dependency on it imports.
+pub fn dependency_exports_key(req: &PackageReqReference) -> String {
+  match &req.sub_path {
+    Some(sub_path) if !sub_path.is_empty() => format!("./{}", sub_path),
+    _ => ".".to_owned(),
+  }
+}
+
 pub fn bucket_tarball_path(id: Uuid) -> String {
   format!("publishing_tasks/{}.tar.gz", id)
 }
 
+/// The directory of the chunks of a publish upload.
+pub fn publish_upload_path(id: Uuid) -> String {
+  format!("publish_uploads/{}/", id)
+}
+
+/// Chunks are stored under their hash, so that retrying the upload of a chunk
+/// never overwrites a chunk that was already appended with other content.
+pub fn publish_upload_chunk_path(id: Uuid, hash: &str) -> String {
+  format!("publish_uploads/{}/{}", id, hash)
+}
+
 #[derive(Debug, Error)]
 pub enum PublishError {
   #[error("s3 download error: {0}")]
@@ -528,6 +1250,12 @@ pub enum PublishError {
   #[error("s3 upload error: {0}")]
   S3UploadError(S3Error),
 
+  #[error("failed to spill a file to temporary storage: {0}")]
+  SpillError(io::Error),
+
+  #[error("malware scan failed: {0:#}")]
+  MalwareScanError(anyhow::Error),
+
   #[error("invalid tarball: {0}")]
   InvalidTarball(io::Error),
 
@@ -606,8 +1334,19 @@ pub enum PublishError {
     size: u64,
   },
 
-  #[error("case-insensitive duplicate path '{a}' and '{b}'")]
-  CaseInsensitiveDuplicatePath { a: PackagePath, b: PackagePath },
+  #[error("package has too many files, the maximum is {max_count}")]
+  TooManyFiles { max_count: usize },
+
+  #[error(
+    "the exports of the package import {count} modules, the maximum is {max_count}"
+  )]
+  TooManyModules { count: usize, max_count: usize },
+
+  /// Has the `caseInsensitiveDuplicatePath` code if any of the issues is a
+  /// case-insensitive duplicate, which was the only path issue that was
+  /// checked before, and `invalidPaths` otherwise.
+  #[error("invalid paths: {}", join_path_issues(.0))]
+  InvalidPaths(Vec<PathIssue>),
 
   #[error("missing config file '{0}', is it perhaps excluded from publishing?")]
   MissingConfigFile(Box<PackagePath>),
@@ -640,6 +1379,30 @@ pub enum PublishError {
     invalid_exports: String,
   },
 
+  #[error("invalid 'coverage' field in config file '{path}': {error}")]
+  ConfigFileCoverageInvalid {
+    path: Box<PackagePath>,
+    error: String,
+  },
+
+  #[error("invalid 'bin' field in config file '{path}': {error}")]
+  ConfigFileBinInvalid {
+    path: Box<PackagePath>,
+    error: String,
+  },
+
+  #[error("invalid 'npm.exclude' field in config file '{path}': {error}")]
+  ConfigFileNpmExcludeInvalid {
+    path: Box<PackagePath>,
+    error: String,
+  },
+
+  #[error("invalid 'peerDependencies' field in config file '{path}': {error}")]
+  ConfigFilePeerDependenciesInvalid {
+    path: Box<PackagePath>,
+    error: String,
+  },
+
   #[error("failed to build module graph: {}", .0.to_string_with_range())]
   GraphError(Box<ModuleGraphError>),
 
@@ -702,6 +1465,8 @@ impl PublishError {
     match self {
       PublishError::S3DownloadError(_) => None,
       PublishError::S3UploadError(_) => None,
+      PublishError::SpillError(_) => None,
+      PublishError::MalwareScanError(_) => None,
       PublishError::MissingTarball => None,
       PublishError::DatabaseError(_) => None,
       PublishError::UnexpectedError(_) => None,
@@ -725,8 +1490,16 @@ impl PublishError {
       }
       PublishError::FileTooLarge { .. } => Some("fileTooLarge"),
       PublishError::PackageTooLarge { .. } => Some("packageTooLarge"),
-      PublishError::CaseInsensitiveDuplicatePath { .. } => {
-        Some("caseInsensitiveDuplicatePath")
+      PublishError::TooManyFiles { .. } => Some("tooManyFiles"),
+      PublishError::TooManyModules { .. } => Some("tooManyModules"),
+      PublishError::InvalidPaths(issues) => {
+        if issues.iter().any(|issue| {
+          matches!(issue, PathIssue::CaseInsensitiveDuplicate { .. })
+        }) {
+          Some("caseInsensitiveDuplicatePath")
+        } else {
+          Some("invalidPaths")
+        }
       }
       PublishError::MissingConfigFile(_) => Some("missingConfigFile"),
       PublishError::InvalidConfigFile { .. } => Some("invalidConfigFile"),
@@ -739,6 +1512,16 @@ impl PublishError {
       PublishError::ConfigFileExportsInvalid { .. } => {
         Some("configFileExportsInvalid")
       }
+      PublishError::ConfigFileCoverageInvalid { .. } => {
+        Some("configFileCoverageInvalid")
+      }
+      PublishError::ConfigFileBinInvalid { .. } => Some("configFileBinInvalid"),
+      PublishError::ConfigFileNpmExcludeInvalid { .. } => {
+        Some("configFileNpmExcludeInvalid")
+      }
+      PublishError::ConfigFilePeerDependenciesInvalid { .. } => {
+        Some("configFilePeerDependenciesInvalid")
+      }
       PublishError::GraphError(_) => Some("graphError"),
       PublishError::DocError(_) => Some("docError"),
       PublishError::NpmTarballError(_) => Some("npmTarballError"),
@@ -761,6 +1544,40 @@ impl PublishError {
   }
 }
 
+/// A problem with the path of a file in a package, see
+/// [PublishError::InvalidPaths].
+#[derive(Debug, Error)]
+pub enum PathIssue {
+  #[error("case-insensitive duplicate path '{a}' and '{b}'")]
+  CaseInsensitiveDuplicate { a: PackagePath, b: PackagePath },
+
+  #[error(
+    "path '{path}' is nested in {depth} directories, the maximum is {max_depth}"
+  )]
+  TooDeep {
+    path: PackagePath,
+    depth: usize,
+    max_depth: usize,
+  },
+
+  #[error(
+    "path '{path}' is {length} characters long, the maximum is {max_length}"
+  )]
+  TooLong {
+    path: String,
+    length: usize,
+    max_length: usize,
+  },
+}
+
+fn join_path_issues(issues: &[PathIssue]) -> String {
+  issues
+    .iter()
+    .map(|issue| issue.to_string())
+    .collect::<Vec<_>>()
+    .join("; ")
+}
+
 fn from_tarball_io_error(err: io::Error) -> PublishError {
   match err.downcast::<s3::error::S3Error>() {
     Ok(err) => PublishError::S3DownloadError(S3Error::S3(err)),
@@ -780,6 +1597,163 @@ pub struct ConfigFile {
   pub version: Option<Version>,
   pub license: Option<String>,
   pub exports: Option<serde_json::Value>,
+  #[serde(default)]
+  pub test: ConfigFileTest,
+  /// Path to an lcov or Istanbul `json-summary` coverage report, relative to
+  /// the config file.
+  pub coverage: Option<String>,
+  #[serde(default)]
+  pub npm: ConfigFileNpm,
+  /// `jsr:` and `npm:` specifiers of packages that the user of this package
+  /// is expected to provide, such as a framework the package plugs into.
+  #[serde(default, rename = "peerDependencies")]
+  pub peer_dependencies: Vec<String>,
+  /// Names of executables, mapped to the path of the entrypoint they run.
+  #[serde(default)]
+  pub bin: IndexMap<String, String>,
+}
+
+/// The `test` field of a `deno.json`. Only `include` is used, to find test
+/// files that do not follow the `deno test` naming convention.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ConfigFileTest {
+  #[serde(default)]
+  pub include: Vec<String>,
+}
+
+/// Options for the npm compatibility tarball of the package.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ConfigFileNpm {
+  /// Whether to also include a CommonJS build, used through the `require`
+  /// export condition.
+  #[serde(default)]
+  pub cjs: bool,
+  /// Whether to roll up the declarations of every entrypoint into a single
+  /// file, which TypeScript loads faster than many small ones.
+  #[serde(default, rename = "dtsRollup")]
+  pub dts_rollup: bool,
+  /// Patterns of files to leave out of the npm tarball, like tests and
+  /// benchmarks. They are still published to JSR.
+  #[serde(default)]
+  pub exclude: Vec<String>,
+}
+
+/// Resolves a path from the config file, which is relative to the directory of
+/// the config file, to a path from the root of the package. Returns `None` if
+/// the path points outside of the package.
+fn resolve_config_relative_path(
+  config_file_path: &PackagePath,
+  path: &str,
+) -> Option<String> {
+  let (dir, _) = config_file_path.rsplit_once('/').unwrap();
+  let mut segments = dir
+    .split('/')
+    .filter(|segment| !segment.is_empty())
+    .collect::<Vec<_>>();
+  for segment in path.split('/') {
+    match segment {
+      "" | "." => {}
+      ".." => {
+        segments.pop()?;
+      }
+      segment => segments.push(segment),
+    }
+  }
+  Some(format!("/{}", segments.join("/")))
+}
+
+/// Patterns in `npm.exclude` are paths relative to the root of the package,
+/// which may contain `*` and `**` wildcards.
+fn is_valid_npm_exclude_pattern(pattern: &str) -> bool {
+  let pattern = pattern
+    .trim_start_matches("./")
+    .trim_start_matches('/')
+    .trim_end_matches('/');
+  !pattern.is_empty()
+    && pattern
+      .split('/')
+      .all(|segment| !segment.is_empty() && segment != "." && segment != "..")
+}
+
+/// Command names of `bin` entries end up as file names in the npm tarball and
+/// in the `bin` directory of `node_modules`.
+fn is_valid_bin_name(name: &str) -> bool {
+  !name.is_empty()
+    && !name.starts_with('.')
+    && name
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Parses a `jsr.json` or `deno.json(c)` config file. `path` is only used for
+/// error messages.
+pub fn parse_config_file(
+  path: &PackagePath,
+  bytes: &[u8],
+) -> Result<ConfigFile, PublishError> {
+  let invalid_config_file =
+    |error: anyhow::Error| PublishError::InvalidConfigFile {
+      path: Box::new(path.clone()),
+      error,
+    };
+  let config_file_str =
+    std::str::from_utf8(bytes).map_err(|e| invalid_config_file(e.into()))?;
+  let config_file_value: serde_json::Value =
+    jsonc_parser::parse_to_serde_value(
+      config_file_str,
+      &ParseOptions::default(),
+    )
+    .map_err(|e| invalid_config_file(e.into()))?
+    .ok_or_else(|| {
+      invalid_config_file(anyhow::anyhow!("config file must not be empty"))
+    })?;
+  serde_json::from_value(config_file_value)
+    .map_err(|e| invalid_config_file(e.into()))
+}
+
+fn validate_exports_key(key: &str) -> Result<(), String> {
+  if key == "." {
+    return Ok(());
+  }
+  if !key.starts_with("./") {
+    let suggestion = if key.starts_with('/') {
+      format!(".{}", key)
+    } else {
+      format!("./{}", key)
+    };
+    return Err(format!(
+      "the key '{key}' must start with a ./, did you mean '{suggestion}'?"
+    ));
+  }
+  if key.ends_with('/') {
+    let suggestion = key.trim_end_matches('/');
+    return Err(format!(
+      "the key '{key}' must not end with '/', did you mean '{suggestion}'?",
+    ));
+  }
+  if key.matches('*').count() > 1 {
+    return Err(format!(
+      "the key '{key}' must not contain more than one '*' subpath pattern"
+    ));
+  }
+  // ban anything that is not [a-zA-Z0-9_-./], apart from the `*` of a subpath
+  // pattern
+  if !key.chars().all(|c| {
+    matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '/' | '.' | '*')
+  }) {
+    return Err(format!(
+      "the key '{key}' contains invalid characters, only [a-z][A-Z][0-9]-_/. are allowed",
+    ));
+  }
+  // ban parts consisting of only dots, and empty parts (e.g. `./foo//bar`)
+  for part in key.split('/').skip(1) {
+    if part.is_empty() || part.chars().all(|c| c == '.') {
+      return Err(format!(
+        "the key '{key}' must not contain double slashes (//) or parts entirely of dots (.).",
+      ));
+    }
+  }
+  Ok(())
 }
 
 pub fn exports_map_from_json(
@@ -790,46 +1764,11 @@ pub fn exports_map_from_json(
     search_text.contains('.')
   }
 
-  fn validate_key(key: &str) -> Result<(), String> {
-    if key == "." {
-      return Ok(());
-    }
-    if !key.starts_with("./") {
-      let suggestion = if key.starts_with('/') {
-        format!(".{}", key)
-      } else {
-        format!("./{}", key)
-      };
-      return Err(format!(
-        "the key '{key}' must start with a ./, did you mean '{suggestion}'?"
-      ));
-    }
-    if key.ends_with('/') {
-      let suggestion = key.trim_end_matches('/');
-      return Err(format!(
-        "the key '{key}' must not end with '/', did you mean '{suggestion}'?",
-      ));
-    }
-    // ban anything that is not [a-zA-Z0-9_-./]
-    if !key.chars().all(|c| {
-      matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '/' | '.')
-    }) {
-      return Err(format!(
-        "the key '{key}' contains invalid characters, only [a-z][A-Z][0-9]-_/. are allowed",
-      ));
-    }
-    // ban parts consisting of only dots, and empty parts (e.g. `./foo//bar`)
-    for part in key.split('/').skip(1) {
-      if part.is_empty() || part.chars().all(|c| c == '.') {
-        return Err(format!(
-          "the key '{key}' must not contain double slashes (//) or parts entirely of dots (.).",
-        ));
-      }
-    }
-    Ok(())
-  }
-
-  fn validate_value(key: &str, value: &str) -> Result<(), String> {
+  fn validate_value(
+    key: &str,
+    value: &str,
+    is_pattern: bool,
+  ) -> Result<(), String> {
     if value.is_empty() {
       return Err(format!(
         "the path for {key} must be a non-empty relative path"
@@ -845,6 +1784,16 @@ pub fn exports_map_from_json(
         "the path '{value}' for {key} must not end in / and must have a file extension"
       ));
     }
+    if is_pattern && value.matches('*').count() != 1 {
+      return Err(format!(
+        "the path '{value}' for {key} must contain exactly one '*', which is replaced by the part of the subpath matched by the pattern"
+      ));
+    }
+    if !is_pattern && value.contains('*') {
+      return Err(format!(
+        "the path '{value}' for {key} must not contain '*', unless {key} is a subpath pattern"
+      ));
+    }
     Ok(())
   }
 
@@ -853,7 +1802,7 @@ pub fn exports_map_from_json(
       return Ok(ExportsMap::new(IndexMap::new()));
     }
     Some(serde_json::Value::String(val)) => {
-      validate_value("the root export", &val)?;
+      validate_value("the root export", &val, false)?;
       return Ok(ExportsMap::new(IndexMap::from([(".".to_string(), val)])));
     }
     Some(serde_json::Value::Object(map)) => map,
@@ -868,7 +1817,7 @@ pub fn exports_map_from_json(
   let mut result = IndexMap::new();
 
   for (key, value) in exports {
-    validate_key(&key)?;
+    validate_exports_key(&key)?;
     let value = match value {
       serde_json::Value::String(value) => value,
       _ => {
@@ -877,13 +1826,92 @@ pub fn exports_map_from_json(
         ));
       }
     };
-    validate_value(&format!("export '{key}'"), &value)?;
+    validate_value(&format!("export '{key}'"), &value, key.contains('*'))?;
     result.insert(key, value);
   }
 
   Ok(ExportsMap::new(result))
 }
 
+/// Expands the subpath pattern entries of `exports` (like `./features/*`) to
+/// one entry per file of the package they match, in place of the pattern.
+/// Returns the expanded exports, and the pattern entries themselves.
+///
+/// Like in Node.js, the `*` matches any part of the subpath, including `/`.
+/// Explicit entries take precedence over patterns, and patterns with a longer
+/// prefix over those with a shorter one.
+pub fn expand_export_patterns<'a>(
+  exports: ExportsMap,
+  files: impl Iterator<Item = &'a PackagePath>,
+) -> Result<(ExportsMap, IndexMap<String, String>), String> {
+  let exports = exports.into_inner();
+  if !exports.keys().any(|key| key.contains('*')) {
+    return Ok((ExportsMap::new(exports), IndexMap::new()));
+  }
+
+  let mut paths = files.map(|path| format!(".{path}")).collect::<Vec<_>>();
+  paths.sort();
+
+  let mut claimed = exports
+    .keys()
+    .filter(|key| !key.contains('*'))
+    .cloned()
+    .collect::<HashSet<_>>();
+  let mut patterns = exports
+    .iter()
+    .filter(|(key, _)| key.contains('*'))
+    .collect::<Vec<_>>();
+  patterns.sort_by_key(|(key, _)| std::cmp::Reverse(key.find('*').unwrap()));
+
+  let mut expanded = HashMap::new();
+  for (key, value) in patterns {
+    let (key_prefix, key_suffix) = key.split_once('*').unwrap();
+    let (value_prefix, value_suffix) = value.split_once('*').unwrap();
+    let mut matched_any = false;
+    let mut entries = IndexMap::new();
+    for path in &paths {
+      let Some(capture) = path
+        .strip_prefix(value_prefix)
+        .and_then(|rest| rest.strip_suffix(value_suffix))
+        .filter(|capture| !capture.is_empty())
+      else {
+        continue;
+      };
+      matched_any = true;
+      let expanded_key = format!("{key_prefix}{capture}{key_suffix}");
+      if claimed.contains(&expanded_key) {
+        continue;
+      }
+      validate_exports_key(&expanded_key).map_err(|_| {
+        format!(
+          "export '{key}' matches '{path}', which can not be exported as '{expanded_key}'"
+        )
+      })?;
+      claimed.insert(expanded_key.clone());
+      entries.insert(expanded_key, path.clone());
+    }
+    if !matched_any {
+      return Err(format!(
+        "export '{key}' references '{value}', which does not match any file"
+      ));
+    }
+    expanded.insert(key.clone(), entries);
+  }
+
+  let mut result = IndexMap::new();
+  let mut export_patterns = IndexMap::new();
+  for (key, value) in exports {
+    if let Some(entries) = expanded.remove(&key) {
+      result.extend(entries);
+      export_patterns.insert(key, value);
+    } else {
+      result.insert(key, value);
+    }
+  }
+
+  Ok((ExportsMap::new(result), export_patterns))
+}
+
 #[cfg(test)]
 mod tests {
   macro_rules! exports_map_from_json_error {
@@ -957,4 +1985,145 @@ mod tests {
     { "./foo": 1 },
     "export './foo' must be a string, invalid value: '1'"
   );
+  exports_map_from_json_error!(
+    invalid_pattern_key,
+    { "./foo/*/*": "./foo/*.ts" },
+    "the key './foo/*/*' must not contain more than one '*' subpath pattern"
+  );
+  exports_map_from_json_error!(
+    invalid_pattern_value_1,
+    { "./foo/*": "./foo/bar.ts" },
+    "the path './foo/bar.ts' for export './foo/*' must contain exactly one '*', which is replaced by the part of the subpath matched by the pattern"
+  );
+  exports_map_from_json_error!(
+    invalid_pattern_value_2,
+    { "./foo": "./foo/*.ts" },
+    "the path './foo/*.ts' for export './foo' must not contain '*', unless export './foo' is a subpath pattern"
+  );
+
+  fn expand(
+    exports: serde_json::Value,
+    files: &[&str],
+  ) -> Result<
+    (
+      indexmap::IndexMap<String, String>,
+      indexmap::IndexMap<String, String>,
+    ),
+    String,
+  > {
+    let exports = super::exports_map_from_json(Some(exports)).unwrap();
+    let files = files
+      .iter()
+      .map(|path| crate::ids::PackagePath::new(path.to_string()).unwrap())
+      .collect::<Vec<_>>();
+    let (exports, patterns) =
+      super::expand_export_patterns(exports, files.iter())?;
+    Ok((exports.into_inner(), patterns))
+  }
+
+  #[test]
+  fn expand_export_patterns() {
+    let (exports, patterns) = expand(
+      serde_json::json!({
+        ".": "./mod.ts",
+        "./features/*": "./src/features/*.ts",
+        "./features/special/*": "./src/special/*.ts",
+        "./features/b": "./src/b.ts",
+      }),
+      &[
+        "/mod.ts",
+        "/src/b.ts",
+        "/src/features/a.ts",
+        "/src/features/b.ts",
+        "/src/features/nested/c.ts",
+        "/src/features/README.md",
+        "/src/special/d.ts",
+      ],
+    )
+    .unwrap();
+    assert_eq!(
+      exports.into_iter().collect::<Vec<_>>(),
+      vec![
+        (".".to_string(), "./mod.ts".to_string()),
+        (
+          "./features/a".to_string(),
+          "./src/features/a.ts".to_string()
+        ),
+        (
+          "./features/nested/c".to_string(),
+          "./src/features/nested/c.ts".to_string()
+        ),
+        (
+          "./features/special/d".to_string(),
+          "./src/special/d.ts".to_string()
+        ),
+        ("./features/b".to_string(), "./src/b.ts".to_string()),
+      ]
+    );
+    assert_eq!(
+      patterns.into_iter().collect::<Vec<_>>(),
+      vec![
+        (
+          "./features/*".to_string(),
+          "./src/features/*.ts".to_string()
+        ),
+        (
+          "./features/special/*".to_string(),
+          "./src/special/*.ts".to_string()
+        ),
+      ]
+    );
+
+    let (exports, patterns) =
+      expand(serde_json::json!("./mod.ts"), &["/mod.ts"]).unwrap();
+    assert_eq!(exports.len(), 1);
+    assert!(patterns.is_empty());
+
+    assert_eq!(
+      expand(
+        serde_json::json!({ "./features/*": "./src/features/*.ts" }),
+        &["/mod.ts"],
+      )
+      .unwrap_err(),
+      "export './features/*' references './src/features/*.ts', which does not match any file"
+    );
+    assert_eq!(
+      expand(
+        serde_json::json!({ "./features/*": "./src/*.ts" }),
+        &["/src/a~b.ts"],
+      )
+      .unwrap_err(),
+      "export './features/*' matches './src/a~b.ts', which can not be exported as './features/a~b'"
+    );
+  }
+
+  #[test]
+  fn resolve_config_relative_path() {
+    let resolve = |config_file: &str, path: &str| {
+      let config_file =
+        crate::ids::PackagePath::new(config_file.to_string()).unwrap();
+      super::resolve_config_relative_path(&config_file, path)
+    };
+
+    assert_eq!(
+      resolve("/jsr.json", "./coverage.lcov").as_deref(),
+      Some("/coverage.lcov")
+    );
+    assert_eq!(
+      resolve("/jsr.json", "coverage/lcov.info").as_deref(),
+      Some("/coverage/lcov.info")
+    );
+    assert_eq!(
+      resolve("/packages/foo/jsr.json", "./coverage.lcov").as_deref(),
+      Some("/packages/foo/coverage.lcov")
+    );
+    assert_eq!(
+      resolve("/packages/foo/deno.json", "../../coverage/foo.lcov").as_deref(),
+      Some("/coverage/foo.lcov")
+    );
+    assert_eq!(
+      resolve("/packages/foo/jsr.json", "../../../coverage.lcov"),
+      None
+    );
+  }
 }
@@ -1,12 +1,6 @@
 // Copyright 2024 the JSR authors. All rights reserved. MIT license.
-use bytes::Bytes;
 use chrono::Duration;
 use chrono::Utc;
-use deno_semver::StackString;
-use deno_semver::VersionReq;
-use deno_semver::package::PackageReq;
-use deno_semver::package::PackageReqReference;
-use deno_semver::package::PackageSubPath;
 use futures::StreamExt;
 use futures::stream;
 use hyper::Body;
@@ -16,42 +10,51 @@ use routerify::ext::RequestExt;
 use routerify_query::RequestQueryExt;
 use serde::Deserialize;
 use serde::Serialize;
-use std::collections::HashSet;
 use std::str::FromStr;
 use tracing::Span;
 use tracing::error;
 use tracing::field;
 use tracing::instrument;
+use url::Url;
 
 use crate::NpmUrl;
 use crate::RegistryUrl;
-use crate::analysis::RebuildNpmTarballData;
-use crate::analysis::rebuild_npm_tarball;
 use crate::api::ApiError;
 use crate::api::PublishQueue;
+use crate::background_jobs;
+use crate::background_jobs::JobContext;
+use crate::cache_purge::CachePurge;
 use crate::db::Database;
 use crate::db::DownloadKind;
-use crate::db::NewNpmTarball;
+use crate::db::ModuleDownloadCount;
+use crate::db::PublishingTask;
+use crate::db::PublishingTaskError;
 use crate::db::PublishingTaskStatus;
 use crate::db::VersionDownloadCount;
+use crate::emails;
+use crate::emails::EmailSender;
 use crate::external::cloudflare;
-use crate::external::cloudflare::CachePurge;
 use crate::gcp;
 use crate::ids::PackageName;
 use crate::ids::ScopeName;
 use crate::ids::Version;
 use crate::npm::NPM_TARBALL_REVISION;
-use crate::npm::generate_npm_version_manifest;
+use crate::npm::NpmSigner;
+use crate::npm::build_npm_tarball;
+use crate::npm::upload_npm_version_manifest;
 use crate::publish;
+use crate::s3::BucketWithQueue;
 use crate::s3::Buckets;
-use crate::s3::CACHE_CONTROL_IMMUTABLE;
-use crate::s3::CACHE_CONTROL_MANIFEST;
-use crate::s3::S3UploadOptions;
-use crate::s3::UploadTaskBody;
+use crate::s3::PRECOMPRESSED_BROTLI_SUFFIX;
+use crate::s3::PRECOMPRESSED_ZSTD_SUFFIX;
 use crate::s3_paths;
+use crate::search::PackageSearch;
+use crate::tombstones;
+use crate::tombstones::TombstoneRetention;
 use crate::util;
 use crate::util::ApiResult;
 use crate::util::decode_json;
+use crate::webhooks;
 
 pub struct NpmTarballBuildQueue(pub Option<gcp::Queue>);
 pub struct AnalyticsEngineConfig(
@@ -81,10 +84,42 @@ pub fn tasks_router() -> Router<Body, ApiError> {
       "/clean_download_counts_4h",
       util::json(clean_download_counts_4h_handler),
     )
+    .post(
+      "/refresh_download_rollups",
+      util::json(refresh_download_rollups_handler),
+    )
+    .post(
+      "/rebuild_search_index",
+      util::json(rebuild_search_index_handler),
+    )
     .post(
       "/requeue_stuck_publishing_tasks",
       util::json(requeue_stuck_publishing_tasks_handler),
     )
+    .post(
+      "/expire_abandoned_publishing_tasks",
+      util::json(expire_abandoned_publishing_tasks_handler),
+    )
+    .post("/deliver_webhooks", util::json(deliver_webhooks_handler))
+    .post("/run_jobs", util::json(run_jobs_handler))
+    .post("/gc_module_blobs", util::json(gc_module_blobs_handler))
+    .post(
+      "/clean_publish_uploads",
+      util::json(clean_publish_uploads_handler),
+    )
+    .post(
+      "/gc_orphaned_objects",
+      util::json(gc_orphaned_objects_handler),
+    )
+    .post("/purge_tombstones", util::json(purge_tombstones_handler))
+    .post(
+      "/clean_data_exports",
+      util::json(clean_data_exports_handler),
+    )
+    .post(
+      "/send_scope_digests",
+      util::json(send_scope_digests_handler),
+    )
     .build()
     .unwrap()
 }
@@ -154,11 +189,129 @@ pub async fn requeue_stuck_publishing_tasks_handler(
   Ok(())
 }
 
+/// How long after creation a publishing task that still has not committed its
+/// version (`pending`/`processing`) is considered abandoned. This is well past
+/// the point where the requeue reaper has had many chances to re-drive it, so
+/// a task this old is failing on every attempt.
+const ABANDONED_PUBLISHING_TASK_SECS: i64 = 6 * 60 * 60;
+
+/// Expire publishing tasks that never managed to commit their version.
+///
+/// `requeue_stuck_publishing_tasks` keeps re-driving stranded tasks, but a
+/// task that fails with a retryable error on every attempt would stay
+/// `pending`/`processing` forever. That blocks the author from publishing the
+/// same version again, because `create_publishing_task` only allows a new task
+/// once every previous one for the version is `failure`. This handler, run
+/// periodically by Cloud Scheduler, removes the files such a task uploaded
+/// before it got stuck and marks it as failed, so the status page shows why and
+/// the version can be re-published.
+#[instrument(
+  name = "POST /tasks/expire_abandoned_publishing_tasks",
+  skip(req),
+  err
+)]
+pub async fn expire_abandoned_publishing_tasks_handler(
+  req: Request<Body>,
+) -> ApiResult<()> {
+  let db = req.data::<Database>().unwrap().clone();
+  let buckets = req.data::<Buckets>().unwrap().clone();
+
+  let abandoned = db
+    .list_abandoned_publishing_tasks(ABANDONED_PUBLISHING_TASK_SECS)
+    .await?;
+
+  let mut expired = 0;
+  for task in abandoned {
+    // Remove the partial uploads first: once the task is marked as failed a
+    // new publish of the same version may start writing to the same paths.
+    if let Err(err) = delete_partial_publish_uploads(&buckets, &task).await {
+      error!(
+        "failed to clean uploads of abandoned task {}: {err}",
+        task.id
+      );
+      continue;
+    }
+
+    let task = match db
+      .update_publishing_task_status(
+        None,
+        task.id,
+        task.status,
+        PublishingTaskStatus::Failure,
+        Some(PublishingTaskError {
+          code: "publishTimedOut".into(),
+          message: format!(
+            "Publishing did not complete within {} hours and was abandoned. No version was created; please publish this version again.",
+            ABANDONED_PUBLISHING_TASK_SECS / 3600
+          ),
+        }),
+      )
+      .await
+    {
+      Ok(task) => task,
+      Err(err) => {
+        // Lost a race (a worker picked the task up concurrently) or a
+        // transient DB error. Skip it — a later run will pick it up again if
+        // still stuck.
+        error!("failed to expire abandoned publishing task {}: {err}", task.id);
+        continue;
+      }
+    };
+    publish::queue_publish_failure_email(&db, &task).await;
+    expired += 1;
+  }
+
+  tracing::info!(expired, "expired abandoned publishing tasks");
+  Ok(())
+}
+
+/// Delete everything `process_tarball` may have written for a task's version
+/// before the version row was committed.
+async fn delete_partial_publish_uploads(
+  buckets: &Buckets,
+  task: &PublishingTask,
+) -> Result<(), ApiError> {
+  let scope = &task.package_scope;
+  let package = &task.package_name;
+  let version = &task.package_version;
+
+  let path = s3_paths::docs_v2_path(scope, package, version);
+  buckets.docs_bucket.delete_file(path.into()).await?;
+
+  let path = s3_paths::docs_archive_path(scope, package, version);
+  buckets.docs_bucket.delete_file(path.into()).await?;
+
+  let path = s3_paths::docs_markdown_path(scope, package, version);
+  buckets.docs_bucket.delete_file(path.into()).await?;
+
+  let path = s3_paths::docs_type_graph_path(scope, package, version);
+  buckets.docs_bucket.delete_file(path.into()).await?;
+
+  let path =
+    s3_paths::npm_tarball_path(scope, package, version, NPM_TARBALL_REVISION);
+  buckets.npm_bucket.delete_file(path.into()).await?;
+
+  let path = s3_paths::version_metadata(scope, package, version);
+  buckets.modules_bucket.delete_file(path.into()).await?;
+
+  let path = s3_paths::version_files_manifest(scope, package, version);
+  buckets.modules_bucket.delete_file(path.into()).await?;
+
+  let path = s3_paths::file_path_root_directory(scope, package, version);
+  buckets.modules_bucket.delete_directory(path.into()).await?;
+
+  Ok(())
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct NpmTarballBuildJob {
   pub scope: ScopeName,
   pub name: PackageName,
   pub version: Version,
+  /// Rebuild the tarball even if it was already built for the current
+  /// revision, and check that the result is byte-identical to it.
+  #[serde(default)]
+  pub verify: bool,
 }
 
 #[instrument(
@@ -177,121 +330,32 @@ pub async fn npm_tarball_build_handler(
   let buckets = req.data::<Buckets>().unwrap().clone();
   let registry_url = req.data::<RegistryUrl>().unwrap().0.clone();
   let npm_url = req.data::<NpmUrl>().unwrap().0.clone();
+  let npm_signer = req.data::<NpmSigner>().unwrap().clone();
   let cache_purge = req.data::<CachePurge>().unwrap().clone();
 
-  let is_already_built = db
-    .get_npm_tarball(
-      &job.scope,
-      &job.name,
-      &job.version,
-      NPM_TARBALL_REVISION as i32,
-    )
-    .await?
-    .is_some();
-
-  if !is_already_built {
-    let version = db
-      .get_package_version(&job.scope, &job.name, &job.version)
-      .await?
-      .ok_or(ApiError::PackageVersionNotFound)?;
-    let dependencies = db
-      .list_package_version_dependencies(&job.scope, &job.name, &job.version)
-      .await?;
-    let files: HashSet<_> = db
-      .list_package_files(&job.scope, &job.name, &job.version)
-      .await?
-      .into_iter()
-      .map(|f| f.path)
-      .collect();
-
-    let dependencies = dependencies
-      .into_iter()
-      .map(|dep| {
-        let sub_path = if dep.dependency_path.is_empty() {
-          None
-        } else {
-          Some(PackageSubPath::from_string(dep.dependency_path))
-        };
-        let version_req =
-          VersionReq::parse_from_specifier(&dep.dependency_constraint).unwrap();
-        let req = PackageReq {
-          name: StackString::from_string(dep.dependency_name),
-          version_req,
-        };
-        (dep.dependency_kind, PackageReqReference { req, sub_path })
-      })
-      .collect();
-
-    let span = Span::current();
-    let data = RebuildNpmTarballData {
-      files,
-      scope: version.scope,
-      name: version.name,
-      version: version.version,
-      dependencies,
-      exports: version.exports,
-    };
-    let npm_tarball = tokio::task::spawn_blocking(|| {
-      rebuild_npm_tarball(span, registry_url, buckets.modules_bucket, data)
-    })
-    .await
-    .unwrap()?;
-
-    let new_npm_tarball = NewNpmTarball {
-      scope: &job.scope,
-      name: &job.name,
-      version: &job.version,
-      revision: NPM_TARBALL_REVISION as i32,
-      size: npm_tarball.tarball.len() as i32,
-      sha1: &npm_tarball.sha1,
-      sha512: &npm_tarball.sha512,
-    };
-
-    let npm_tarball_path = s3_paths::npm_tarball_path(
-      &job.scope,
-      &job.name,
-      &job.version,
-      NPM_TARBALL_REVISION,
-    );
-    buckets
-      .npm_bucket
-      .upload(
-        npm_tarball_path.into(),
-        UploadTaskBody::Bytes(Bytes::from(npm_tarball.tarball)),
-        S3UploadOptions {
-          content_type: Some("application/octet-stream".into()),
-          cache_control: Some(CACHE_CONTROL_IMMUTABLE.into()),
-          gzip_encoded: false,
-        },
-      )
-      .await?;
-
-    db.create_npm_tarball(new_npm_tarball).await?;
-  }
-
-  let npm_version_manifest_path =
-    crate::s3_paths::npm_version_manifest_path(&job.scope, &job.name);
-  let npm_version_manifest =
-    generate_npm_version_manifest(&db, &npm_url, &job.scope, &job.name).await?;
-  let content = serde_json::to_vec_pretty(&npm_version_manifest)?;
-  buckets
-    .npm_bucket
-    .upload(
-      npm_version_manifest_path.into(),
-      UploadTaskBody::Bytes(content.into()),
-      S3UploadOptions {
-        content_type: Some("application/json".into()),
-        cache_control: Some(CACHE_CONTROL_MANIFEST.into()),
-        gzip_encoded: false,
-      },
-    )
-    .await?;
-
-  cache_purge
-    .purge(vec![crate::s3_paths::npm_version_manifest_url(
-      &npm_url, &job.scope, &job.name,
-    )])
-    .await;
+  build_npm_tarball(
+    &db,
+    &buckets,
+    &registry_url,
+    &npm_url,
+    &npm_signer,
+    &job.scope,
+    &job.name,
+    &job.version,
+    job.verify,
+  )
+  .await?;
+
+  upload_npm_version_manifest(
+    &db,
+    &buckets,
+    &npm_url,
+    &npm_signer,
+    &cache_purge,
+    &job.scope,
+    &job.name,
+  )
+  .await?;
 
   Ok(())
 }
@@ -315,6 +379,7 @@ pub async fn npm_tarball_enqueue_handler(req: Request<Body>) -> ApiResult<()> {
         scope: missing_tarball.0,
         name: missing_tarball.1,
         version: missing_tarball.2,
+        verify: false,
       };
       let body = serde_json::to_vec(&job).unwrap();
       queue.task_buffer(None, Some(body.into()))
@@ -413,6 +478,46 @@ ORDER BY
 
     insert_analytics_download_entries(&db, npm_downloads, DownloadKind::NpmTgz)
       .await?;
+
+    let module_downloads = analytics_client
+      .query_downloads(format!(
+        r#"
+SELECT
+  toStartOfInterval(timestamp, INTERVAL '4' HOUR) as time_bucket,
+  blob2 as scope,
+  blob3 as package,
+  blob4 as ver,
+  blob6 as path,
+  intDiv(sum(_sample_interval), 1) as count
+FROM
+  '{dataset_name}'
+WHERE
+  timestamp >= NOW() - INTERVAL '{time_window}' HOUR
+  AND blob1 = 'jsr_module'
+GROUP BY
+  time_bucket,
+  scope,
+  package,
+  ver,
+  path
+ORDER BY
+  time_bucket DESC
+      "#
+      ))
+      .await
+      .map_err(|e| {
+        error!(
+          "Failed to query JSR module downloads from Analytics Engine: {}",
+          e
+        );
+        ApiError::InternalServerError
+      })?;
+
+    let entries = module_downloads
+      .into_iter()
+      .filter_map(deserialize_module_download_count_from_analytics)
+      .collect::<Vec<_>>();
+    db.insert_entrypoint_download_entries(entries).await?;
   };
 
   Ok(())
@@ -420,24 +525,298 @@ ORDER BY
 
 #[instrument(name = "POST /tasks/clean_oauth_states", skip(req), err)]
 pub async fn clean_oauth_states_handler(req: Request<Body>) -> ApiResult<()> {
-  let db = req.data::<Database>().unwrap().clone();
+  let db = req.data::<Database>().unwrap();
+  clean_oauth_states(db).await?;
+  Ok(())
+}
+
+pub async fn clean_oauth_states(db: &Database) -> Result<(), anyhow::Error> {
   let cutoff = Utc::now() - Duration::hours(1);
   let deleted = db.delete_expired_oauth_states(cutoff).await?;
   tracing::info!(deleted, "cleaned up expired oauth states");
   Ok(())
 }
 
+#[instrument(name = "POST /tasks/deliver_webhooks", skip(req), err)]
+pub async fn deliver_webhooks_handler(req: Request<Body>) -> ApiResult<()> {
+  let db = req.data::<Database>().unwrap().clone();
+  let attempted = webhooks::deliver_pending_webhooks(&db).await?;
+  tracing::info!(attempted, "delivered pending webhooks");
+  Ok(())
+}
+
+/// How long a single run of the background job worker keeps claiming jobs.
+/// Runs are scheduled every minute, so runs only overlap when jobs pile up.
+const BACKGROUND_JOB_WORKER_BUDGET_SECS: u64 = 4 * 60;
+
+/// Runs the background jobs that are due, see [background_jobs].
+#[instrument(name = "POST /tasks/run_jobs", skip(req), err)]
+pub async fn run_jobs_handler(req: Request<Body>) -> ApiResult<()> {
+  let ctx = JobContext {
+    db: req.data::<Database>().unwrap().clone(),
+    buckets: req.data::<Buckets>().unwrap().clone(),
+    registry_url: req.data::<RegistryUrl>().unwrap().0.clone(),
+    npm_url: req.data::<NpmUrl>().unwrap().0.clone(),
+    npm_signer: req.data::<NpmSigner>().unwrap().clone(),
+    cache_purge: req.data::<CachePurge>().unwrap().clone(),
+    email_sender: req.data::<Option<EmailSender>>().unwrap().clone(),
+  };
+  let ran = background_jobs::run_worker(
+    &ctx,
+    std::time::Duration::from_secs(BACKGROUND_JOB_WORKER_BUDGET_SECS),
+  )
+  .await?;
+  tracing::info!(ran, "ran background jobs");
+  Ok(())
+}
+
+/// How long a module blob that no package file refers to is kept after a
+/// publish last referred to it. A publish refers to the blobs it reuses before
+/// it stores the files of the version, which never takes this long.
+const MODULE_BLOB_GC_GRACE_HOURS: i64 = 24;
+
+/// The maximum number of module blobs deleted in one run.
+const MODULE_BLOB_GC_BATCH_SIZE: i64 = 1000;
+
+/// Deletes module blobs that no package version refers to anymore, because the
+/// versions were deleted or their publish was abandoned.
+#[instrument(name = "POST /tasks/gc_module_blobs", skip(req), err)]
+pub async fn gc_module_blobs_handler(req: Request<Body>) -> ApiResult<()> {
+  let db = req.data::<Database>().unwrap();
+  let bucket = &req.data::<Buckets>().unwrap().modules_bucket;
+  gc_module_blobs(db, bucket).await?;
+  Ok(())
+}
+
+pub async fn gc_module_blobs(
+  db: &Database,
+  bucket: &BucketWithQueue,
+) -> Result<(), anyhow::Error> {
+  let cutoff = Utc::now() - Duration::hours(MODULE_BLOB_GC_GRACE_HOURS);
+  let deleted = db
+    .delete_unreferenced_module_blobs(
+      cutoff,
+      MODULE_BLOB_GC_BATCH_SIZE,
+      |hashes| async move {
+        stream::iter(hashes)
+          .map(|hash| {
+            let bucket = bucket.clone();
+            async move {
+              let path = s3_paths::module_blob_path(&hash);
+              // The original goes last, like when it was uploaded.
+              let paths = [
+                format!("{path}{PRECOMPRESSED_BROTLI_SUFFIX}"),
+                format!("{path}{PRECOMPRESSED_ZSTD_SUFFIX}"),
+                path,
+              ];
+              for path in paths {
+                if let Err(err) = bucket.delete_file(path.into()).await {
+                  error!("failed to delete module blob {hash}: {err}");
+                  return None;
+                }
+              }
+              Some(hash)
+            }
+          })
+          .buffer_unordered(32)
+          .filter_map(futures::future::ready)
+          .collect::<Vec<_>>()
+          .await
+      },
+    )
+    .await?;
+  tracing::info!(deleted = deleted.len(), "deleted unreferenced module blobs");
+  Ok(())
+}
+
+/// Permanently deletes packages and package versions that were deleted longer
+/// ago than the retention window, see [tombstones].
+#[instrument(name = "POST /tasks/purge_tombstones", skip(req), err)]
+pub async fn purge_tombstones_handler(req: Request<Body>) -> ApiResult<()> {
+  let db = req.data::<Database>().unwrap().clone();
+  let buckets = req.data::<Buckets>().unwrap().clone();
+  let cutoff = req.data::<TombstoneRetention>().unwrap().cutoff();
+  let (versions, packages) =
+    tombstones::purge_tombstones(&db, &buckets, cutoff).await?;
+  tracing::info!(versions, packages, "purged tombstones");
+  Ok(())
+}
+
+const PUBLISH_UPLOAD_CLEANUP_BATCH_SIZE: i64 = 100;
+
+/// Delete the chunks of publish uploads that expired before they were
+/// committed.
+#[instrument(name = "POST /tasks/clean_publish_uploads", skip(req), err)]
+pub async fn clean_publish_uploads_handler(
+  req: Request<Body>,
+) -> ApiResult<()> {
+  let db = req.data::<Database>().unwrap();
+  let buckets = req.data::<Buckets>().unwrap();
+  clean_publish_uploads(db, buckets).await?;
+  Ok(())
+}
+
+pub async fn clean_publish_uploads(
+  db: &Database,
+  buckets: &Buckets,
+) -> Result<(), anyhow::Error> {
+  let expired = db
+    .list_expired_publish_uploads(PUBLISH_UPLOAD_CLEANUP_BATCH_SIZE)
+    .await?;
+  let mut deleted = 0;
+  for id in expired {
+    let path = crate::tarball::publish_upload_path(id);
+    if let Err(err) = buckets
+      .publishing_bucket
+      .delete_directory(path.into())
+      .await
+    {
+      error!("failed to delete chunks of publish upload {id}: {err}");
+      continue;
+    }
+    db.delete_publish_upload(id).await?;
+    deleted += 1;
+  }
+  tracing::info!(deleted, "cleaned up expired publish uploads");
+  Ok(())
+}
+
+const DATA_EXPORT_CLEANUP_BATCH_SIZE: i64 = 100;
+
+/// Delete the archives of data exports that are older than the retention
+/// window, and mark the exports as expired.
+#[instrument(name = "POST /tasks/clean_data_exports", skip(req), err)]
+pub async fn clean_data_exports_handler(req: Request<Body>) -> ApiResult<()> {
+  let db = req.data::<Database>().unwrap();
+  let buckets = req.data::<Buckets>().unwrap();
+  clean_data_exports(db, buckets).await?;
+  Ok(())
+}
+
+pub async fn clean_data_exports(
+  db: &Database,
+  buckets: &Buckets,
+) -> Result<(), anyhow::Error> {
+  let cutoff =
+    Utc::now() - Duration::days(crate::data_export::DATA_EXPORT_RETENTION_DAYS);
+  let expired = db
+    .list_expired_data_exports(cutoff, DATA_EXPORT_CLEANUP_BATCH_SIZE)
+    .await?;
+  let mut deleted = 0;
+  for id in expired {
+    let path = crate::data_export::data_export_path(id);
+    if let Err(err) = buckets.publishing_bucket.delete_file(path.into()).await {
+      error!("failed to delete archive of data export {id}: {err}");
+      continue;
+    }
+    db.expire_data_export(id).await?;
+    deleted += 1;
+  }
+  tracing::info!(deleted, "cleaned up expired data exports");
+  Ok(())
+}
+
+/// Send the weekly digest of new versions to the members of scopes that want
+/// it, see [emails::send_scope_digests].
+#[instrument(name = "POST /tasks/send_scope_digests", skip(req), err)]
+pub async fn send_scope_digests_handler(req: Request<Body>) -> ApiResult<()> {
+  let db = req.data::<Database>().unwrap();
+  let email_sender = req.data::<Option<EmailSender>>().unwrap();
+  let registry_url = req.data::<RegistryUrl>().unwrap();
+  send_scope_digests(db, email_sender.as_ref(), &registry_url.0).await?;
+  Ok(())
+}
+
+pub async fn send_scope_digests(
+  db: &Database,
+  email_sender: Option<&EmailSender>,
+  registry_url: &Url,
+) -> Result<(), anyhow::Error> {
+  let Some(email_sender) = email_sender else {
+    tracing::info!("skipping scope digests, sending emails is not configured");
+    return Ok(());
+  };
+  let sent = emails::send_scope_digests(db, email_sender, registry_url).await?;
+  tracing::info!(sent, "sent scope digests");
+  Ok(())
+}
+
+/// Run an orphan GC job, which deletes the objects in the buckets that belong
+/// to versions or publishing tasks that don't exist. With `?dryRun=true`, the
+/// orphaned objects are only recorded. Admins can review the job with
+/// `GET /api/admin/orphan_gc_jobs/:id/objects`.
+#[instrument(name = "POST /tasks/gc_orphaned_objects", skip(req), err)]
+pub async fn gc_orphaned_objects_handler(req: Request<Body>) -> ApiResult<()> {
+  let db = req.data::<Database>().unwrap();
+  let buckets = req.data::<Buckets>().unwrap();
+  let dry_run = match req.query("dryRun").map(|s| s.as_str()) {
+    None | Some("false") => false,
+    Some("true") => true,
+    Some(_) => {
+      return Err(ApiError::MalformedRequest {
+        msg: "dryRun query param must be 'true' or 'false'".into(),
+      });
+    }
+  };
+  gc_orphaned_objects(db, buckets, dry_run).await?;
+  Ok(())
+}
+
+pub async fn gc_orphaned_objects(
+  db: &Database,
+  buckets: &Buckets,
+  dry_run: bool,
+) -> Result<(), anyhow::Error> {
+  let Some(job) = db.create_orphan_gc_job(None, dry_run).await? else {
+    tracing::info!("skipping orphan GC, another job is still running");
+    return Ok(());
+  };
+  crate::orphan_gc::gc_orphaned_objects(db.clone(), buckets.clone(), job).await;
+  Ok(())
+}
+
 #[instrument(name = "POST /tasks/clean_download_counts_4h", skip(req), err)]
 pub async fn clean_download_counts_4h_handler(
   req: Request<Body>,
 ) -> ApiResult<()> {
-  let db = req.data::<Database>().unwrap().clone();
+  let db = req.data::<Database>().unwrap();
+  clean_download_counts_4h(db).await?;
+  Ok(())
+}
+
+pub async fn clean_download_counts_4h(
+  db: &Database,
+) -> Result<(), anyhow::Error> {
   let cutoff = Utc::now() - Duration::days(7);
   let deleted = db.cleanup_download_counts_4h(cutoff).await?;
   tracing::info!(deleted, "cleaned up old 4h download counts");
   Ok(())
 }
 
+/// Recompute the weekly and monthly downloads of all packages, which the stats
+/// and the package list sort by.
+#[instrument(name = "POST /tasks/refresh_download_rollups", skip(req), err)]
+pub async fn refresh_download_rollups_handler(
+  req: Request<Body>,
+) -> ApiResult<()> {
+  let db = req.data::<Database>().unwrap().clone();
+  let packages = db.refresh_package_download_rollups().await?;
+  tracing::info!(packages, "refreshed download rollups");
+  Ok(())
+}
+
+/// Rebuild the indexes of the search backends that keep one from the
+/// database, which catches up on changes whose index updates were lost.
+#[instrument(name = "POST /tasks/rebuild_search_index", skip(req), err)]
+pub async fn rebuild_search_index_handler(req: Request<Body>) -> ApiResult<()> {
+  let db = req.data::<Database>().unwrap();
+  let package_search = req.data::<PackageSearch>().unwrap();
+  for (backend, documents) in package_search.rebuild_indexes(db).await? {
+    tracing::info!(backend, documents, "rebuilt search index");
+  }
+  Ok(())
+}
+
 async fn insert_analytics_download_entries(
   db: &Database,
   records: Vec<cloudflare::DownloadRecord>,
@@ -457,6 +836,32 @@ async fn insert_analytics_download_entries(
   Ok(())
 }
 
+fn deserialize_module_download_count_from_analytics(
+  record: cloudflare::DownloadRecord,
+) -> Option<ModuleDownloadCount> {
+  let time_bucket = chrono::NaiveDateTime::parse_from_str(
+    &record.time_bucket,
+    "%Y-%m-%d %H:%M:%S",
+  )
+  .ok()?
+  .and_utc();
+  let scope = ScopeName::new(record.scope).ok()?;
+  let package = PackageName::new(record.package).ok()?;
+  let version = Version::new(&record.ver).ok()?;
+  let path = percent_encoding::percent_decode_str(&record.path?)
+    .decode_utf8()
+    .ok()?
+    .into_owned();
+  Some(ModuleDownloadCount {
+    time_bucket,
+    scope,
+    package,
+    version,
+    path,
+    count: i64::from_str(&record.count).ok()?,
+  })
+}
+
 fn deserialize_version_download_count_from_analytics(
   record: cloudflare::DownloadRecord,
   kind: DownloadKind,
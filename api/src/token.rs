@@ -1,14 +1,132 @@
 // Copyright 2024 the JSR authors. All rights reserved. MIT license.
 
+//! Tokens are stored as an Argon2id hash of their secret. Tokens are random,
+//! so a salt per token would add nothing, and all of them are hashed with
+//! [TOKEN_HASH_SALT], which keeps the hash deterministic: the token of a
+//! request is looked up by the hash of its secret.
+//!
+//! Tokens are also stored with the SHA-256 [lookup_hash] of their secret,
+//! which is checked before the secret is hashed with Argon2. Secrets are too
+//! random to be guessed from it, and it means that only the secrets of
+//! existing tokens are ever hashed with Argon2, see [find_token].
+//!
+//! Tokens created before Argon2 was used are stored with only the SHA-256
+//! hash of their secret, and are rehashed the next time they are used.
+
+use std::sync::LazyLock;
+use std::time::Duration;
+
 use crate::db::*;
+use argon2::Argon2;
+use argon2::PasswordHasher;
+use argon2::password_hash::SaltString;
 use chrono::DateTime;
 use chrono::Utc;
 use sha2::Digest;
 use uuid::Uuid;
 
-/// Generate a SHA256 hash of a string.
-pub fn hash(data: &str) -> String {
-  format!("{:x}", sha2::Sha256::digest(data.as_bytes()))
+/// The salt of the hashes of all tokens.
+const TOKEN_HASH_SALT: &[u8] = b"jsr-registry-api-tokens";
+
+/// The hashes of the secrets of recently used tokens, by their [lookup_hash],
+/// so that a token is not hashed with Argon2 on every request.
+static TOKEN_HASH_CACHE: LazyLock<moka::future::Cache<String, String>> =
+  LazyLock::new(|| {
+    moka::future::Cache::builder()
+      .max_capacity(65536)
+      .time_to_live(Duration::from_secs(3600))
+      .build()
+  });
+
+/// Hashes the secret of a token, as it is stored in the database.
+pub async fn hash(secret: &str) -> String {
+  let owned_secret = secret.to_owned();
+  tokio::task::spawn_blocking(move || {
+    let salt = SaltString::encode_b64(TOKEN_HASH_SALT).unwrap();
+    Argon2::default()
+      .hash_password(owned_secret.as_bytes(), &salt)
+      .unwrap()
+      .to_string()
+  })
+  .await
+  .unwrap()
+}
+
+/// The SHA-256 hash of the secret of a token, which tokens are looked up by
+/// before the secret is hashed with Argon2, and which secrets of tokens used to
+/// be stored as.
+pub fn lookup_hash(secret: &str) -> String {
+  format!("{:x}", sha2::Sha256::digest(secret.as_bytes()))
+}
+
+/// Finds the token with the given secret, or the previous secret of a
+/// rotated token that is still in its grace period. A token that is still
+/// stored with only its [lookup_hash] is rehashed.
+///
+/// Secrets that are not [well formed](is_well_formed), or whose [lookup_hash]
+/// does not belong to a token, are rejected before they are hashed, and only
+/// the hashes of secrets that belong to a token are cached. This way, made up
+/// secrets, which anyone can create, can neither make the API spend time on
+/// Argon2 nor push the hashes of real tokens out of the cache.
+pub async fn find_token(
+  db: &Database,
+  secret: &str,
+) -> anyhow::Result<Option<Token>> {
+  if !is_well_formed(secret) {
+    return Ok(None);
+  }
+  let lookup_hash = lookup_hash(secret);
+  let hash = match TOKEN_HASH_CACHE.get(&lookup_hash).await {
+    Some(hash) => hash,
+    None => {
+      if !db.token_lookup_hash_exists(&lookup_hash).await? {
+        return Ok(None);
+      }
+      hash(secret).await
+    }
+  };
+  let token = match db.get_token_by_hash(&hash).await? {
+    Some(token) => token,
+    None => {
+      let Some(token) = db.get_token_by_hash(&lookup_hash).await? else {
+        return Ok(None);
+      };
+      db.rehash_token(token.id, &lookup_hash, &hash).await?;
+      token
+    }
+  };
+  TOKEN_HASH_CACHE.insert(lookup_hash, hash).await;
+  Ok(Some(token))
+}
+
+/// Whether a secret looks like one made by [generate_token]: a known prefix,
+/// 29 base62 characters and a matching checksum.
+fn is_well_formed(secret: &str) -> bool {
+  if secret.len() != 40 || !secret.is_ascii() {
+    return false;
+  }
+  let (token_without_checksum, checksum_string) = secret.split_at(34);
+  let Some((prefix, random_string)) = token_without_checksum.split_once('_')
+  else {
+    return false;
+  };
+  if ![TokenType::Web, TokenType::Device, TokenType::Personal]
+    .iter()
+    .any(|token_type| token_type.prefix() == prefix)
+  {
+    return false;
+  }
+  if !random_string
+    .bytes()
+    .chain(checksum_string.bytes())
+    .all(|byte| BASE62.contains(&byte))
+  {
+    return false;
+  }
+  let mut hasher = crc32fast::Hasher::new();
+  hasher.update(token_without_checksum.as_bytes());
+  let checksum = hasher.finalize();
+  checksum_string == format!("{:0>6}", encode_base62(checksum))
 }
 
 pub async fn create_token(
@@ -20,10 +138,11 @@ pub async fn create_token(
   permissions: Option<Permissions>,
 ) -> anyhow::Result<String> {
   let token_string = generate_token(token_type);
-  let hashed_token = hash(&token_string);
+  let hashed_token = hash(&token_string).await;
 
   db.insert_token(NewToken {
     hash: hashed_token,
+    lookup_hash: lookup_hash(&token_string),
     user_id,
     r#type: token_type,
     description,
@@ -78,9 +197,16 @@ pub fn encode_base62(mut num: u32) -> String {
 
 #[cfg(test)]
 mod tests {
+  use super::NewToken;
+  use super::TOKEN_HASH_CACHE;
   use super::TokenType;
   use super::encode_base62;
+  use super::find_token;
   use super::generate_token;
+  use super::hash;
+  use super::is_well_formed;
+  use super::lookup_hash;
+  use crate::util::test::TestSetup;
 
   #[test]
   fn test_encode_base62() {
@@ -98,4 +224,73 @@ mod tests {
     assert!(token.starts_with("jsrw_"));
     assert!(!token.contains(' '));
   }
+
+  #[test]
+  fn test_is_well_formed() {
+    for token_type in [TokenType::Web, TokenType::Device, TokenType::Personal] {
+      assert!(is_well_formed(&generate_token(token_type)));
+    }
+    let token = generate_token(TokenType::Personal);
+    assert!(!is_well_formed(""));
+    assert!(!is_well_formed(&token[..39]));
+    assert!(!is_well_formed(&format!("{token}0")));
+    assert!(!is_well_formed(&token.replacen("jsrp", "jsrx", 1)));
+    assert!(!is_well_formed(&format!(
+      "{}-{}",
+      &token[..10],
+      &token[11..]
+    )));
+    let mut chars = token.chars().collect::<Vec<_>>();
+    chars[10] = if chars[10] == 'a' { 'b' } else { 'a' };
+    assert!(!is_well_formed(&chars.into_iter().collect::<String>()));
+  }
+
+  #[tokio::test]
+  async fn rehash_legacy_token() {
+    let t = TestSetup::new().await;
+    let db = t.db();
+
+    let secret = generate_token(TokenType::Personal);
+    let token = db
+      .insert_token(NewToken {
+        hash: lookup_hash(&secret),
+        lookup_hash: lookup_hash(&secret),
+        user_id: t.user1.user.id,
+        r#type: TokenType::Personal,
+        description: Some("legacy".to_owned()),
+        expires_at: None,
+        permissions: None,
+      })
+      .await
+      .unwrap();
+
+    let found = find_token(&db, &secret).await.unwrap().unwrap();
+    assert_eq!(found.id, token.id);
+    let rehashed = db.get_token(t.user1.user.id, token.id).await.unwrap();
+    let hash = hash(&secret).await;
+    assert!(hash.starts_with("$argon2id$"));
+    assert_eq!(rehashed.unwrap().hash, hash);
+    assert!(
+      db.get_token_by_hash(&lookup_hash(&secret))
+        .await
+        .unwrap()
+        .is_none()
+    );
+
+    let found = find_token(&db, &secret).await.unwrap().unwrap();
+    assert_eq!(found.id, token.id);
+    assert!(TOKEN_HASH_CACHE.get(&lookup_hash(&secret)).await.is_some());
+
+    // Made up secrets are rejected by their lookup hash, before they would be
+    // hashed with Argon2.
+    let unknown = generate_token(TokenType::Personal);
+    assert!(
+      !db
+        .token_lookup_hash_exists(&lookup_hash(&unknown))
+        .await
+        .unwrap()
+    );
+    assert!(find_token(&db, &unknown).await.unwrap().is_none());
+    assert!(TOKEN_HASH_CACHE.get(&lookup_hash(&unknown)).await.is_none());
+  }
 }
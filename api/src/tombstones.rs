@@ -0,0 +1,151 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+//! Deleted packages and package versions are kept as tombstones: their rows
+//! stay in the database with `deleted_at` set, and are left out of all other
+//! queries. Their objects in the buckets are kept too, so that staff can
+//! restore them through the admin API.
+//!
+//! Once a tombstone is older than the [TombstoneRetention] window, the
+//! `purge_tombstones` task deletes it for good, along with its objects.
+
+use chrono::DateTime;
+use chrono::Utc;
+use tracing::error;
+use tracing::instrument;
+
+use crate::db::Database;
+use crate::db::PackageVersion;
+use crate::ids::PackageName;
+use crate::ids::ScopeName;
+use crate::ids::Version;
+use crate::s3::Buckets;
+use crate::sbom::SbomFormat;
+
+/// The maximum number of package versions purged in one run.
+const PURGE_BATCH_SIZE: i64 = 100;
+
+/// How long deleted packages and package versions can be restored for.
+#[derive(Debug, Clone, Copy)]
+pub struct TombstoneRetention {
+  pub days: u32,
+}
+
+impl TombstoneRetention {
+  /// Tombstones that were created before this are purged.
+  pub fn cutoff(&self) -> DateTime<Utc> {
+    Utc::now() - chrono::Duration::days(self.days as i64)
+  }
+
+  /// When a tombstone created at `deleted_at` will be purged.
+  pub fn purge_at(&self, deleted_at: DateTime<Utc>) -> DateTime<Utc> {
+    deleted_at + chrono::Duration::days(self.days as i64)
+  }
+}
+
+/// Purges the package versions, and then the packages, that were deleted
+/// before `cutoff`. Returns the number of purged versions and packages.
+#[instrument(name = "purge_tombstones", skip(db, buckets), err)]
+pub async fn purge_tombstones(
+  db: &Database,
+  buckets: &Buckets,
+  cutoff: DateTime<Utc>,
+) -> Result<(usize, u64), anyhow::Error> {
+  let expired = db
+    .list_expired_package_version_tombstones(cutoff, PURGE_BATCH_SIZE)
+    .await?;
+  let mut purged_versions = 0;
+  for version in expired {
+    let PackageVersion {
+      scope,
+      name,
+      version,
+      ..
+    } = version;
+    if let Err(err) =
+      purge_package_version(db, buckets, &scope, &name, &version).await
+    {
+      error!("failed to purge @{scope}/{name}@{version}: {err:#}");
+      continue;
+    }
+    purged_versions += 1;
+  }
+
+  // A package can only be deleted once all its versions are, so its versions
+  // expire first. Packages that still have versions are left for a later run.
+  let purged_packages = db.purge_expired_packages(cutoff).await?;
+
+  Ok((purged_versions, purged_packages))
+}
+
+/// Deletes the objects of a deleted package version, and then its rows.
+async fn purge_package_version(
+  db: &Database,
+  buckets: &Buckets,
+  scope: &ScopeName,
+  package: &PackageName,
+  version: &Version,
+) -> Result<(), anyhow::Error> {
+  // The doc nodes of other versions, deleted or not, may be stored as deltas
+  // against this version.
+  let other_versions = db
+    .list_package_versions_including_deleted(scope, package)
+    .await?
+    .into_iter()
+    .map(|v| v.version)
+    .filter(|v| v != version)
+    .collect::<Vec<_>>();
+  crate::docs::detach_doc_nodes_deltas(
+    db,
+    scope,
+    package,
+    version,
+    &other_versions,
+    buckets,
+  )
+  .await?;
+
+  let v1_path = crate::s3_paths::docs_v1_path(scope, package, version);
+  let v2_path = crate::s3_paths::docs_v2_path(scope, package, version);
+  buckets.docs_bucket.delete_file(v1_path.into()).await?;
+  buckets.docs_bucket.delete_file(v2_path.into()).await?;
+  let archive_path =
+    crate::s3_paths::docs_archive_path(scope, package, version);
+  buckets.docs_bucket.delete_file(archive_path.into()).await?;
+  let markdown_path =
+    crate::s3_paths::docs_markdown_path(scope, package, version);
+  buckets
+    .docs_bucket
+    .delete_file(markdown_path.into())
+    .await?;
+  let type_graph_path =
+    crate::s3_paths::docs_type_graph_path(scope, package, version);
+  buckets
+    .docs_bucket
+    .delete_file(type_graph_path.into())
+    .await?;
+  for format in [SbomFormat::CycloneDx, SbomFormat::Spdx] {
+    let sbom_path = crate::s3_paths::sbom_path(scope, package, version, format);
+    buckets.docs_bucket.delete_file(sbom_path.into()).await?;
+  }
+
+  let path = crate::s3_paths::version_metadata(scope, package, version);
+  buckets.modules_bucket.delete_file(path.into()).await?;
+
+  // The files themselves are shared with other versions, and deleted by
+  // `gc_module_blobs` once no version refers to them anymore.
+  let path = crate::s3_paths::version_files_manifest(scope, package, version);
+  buckets.modules_bucket.delete_file(path.into()).await?;
+
+  let path = crate::s3_paths::file_path_root_directory(scope, package, version);
+  buckets.modules_bucket.delete_directory(path.into()).await?;
+
+  let path = crate::s3_paths::quarantine_directory(scope, package, version);
+  buckets
+    .publishing_bucket
+    .delete_directory(path.into())
+    .await?;
+
+  db.purge_package_version(scope, package, version).await?;
+
+  Ok(())
+}
@@ -1,6 +1,7 @@
 // Copyright 2024 the JSR authors. All rights reserved. MIT license.
 use std::collections::HashMap;
 use std::io::Write;
+use std::sync::Arc;
 use std::sync::OnceLock;
 
 use tree_sitter_highlight::Highlight;
@@ -103,6 +104,40 @@ impl comrak::adapters::SyntaxHighlighterAdapter for ComrakAdapter {
   }
 }
 
+/// Version of the pre-rendered source views. Bump this whenever the output of
+/// [render_source_view] changes, so that views rendered by an older version
+/// are no longer served.
+pub const SOURCE_VIEW_VERSION: u32 = 1;
+
+/// Renders a source file with syntax highlighting and line numbers, as shown
+/// in the file browser. The language is inferred from the file extension.
+pub fn render_source_view(path: &str, source: &str) -> std::io::Result<String> {
+  use comrak::adapters::SyntaxHighlighterAdapter;
+
+  let highlighter = deno_doc::html::comrak::ComrakHighlightWrapperAdapter(
+    Some(Arc::new(ComrakAdapter {
+      show_line_numbers: true,
+    })),
+  );
+
+  let lang = std::path::Path::new(path)
+    .extension()
+    .map(|ext| ext.to_string_lossy());
+  let lang = lang.as_deref().map(|ext| match ext {
+    "mts" | "cts" => "ts",
+    "mjs" | "cjs" => "js",
+    ext => ext,
+  });
+
+  let mut out = vec![];
+  highlighter.write_pre_tag(&mut out, Default::default())?;
+  highlighter.write_code_tag(&mut out, Default::default())?;
+  highlighter.write_highlighted(&mut out, lang, source)?;
+  out.extend(b"</code></pre>");
+
+  String::from_utf8(out).map_err(std::io::Error::other)
+}
+
 macro_rules! highlighter {
     [$($name:literal -> $class:literal,)*] => {
       /// The capture names to configure on the highlighter. If this is not
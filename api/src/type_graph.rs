@@ -0,0 +1,235 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+//! Computes which exported symbols of a package version reference which
+//! others in their type signatures, across all entrypoints, for the type
+//! dependency graph in the docs UI.
+//!
+//! The references are read from the doc nodes, which deno_doc builds from the
+//! fast check type graph, so they reflect the public types of each symbol
+//! rather than its implementation.
+
+use crate::db::ExportsMap;
+use deno_doc::DeclarationDef;
+use deno_doc::ParseOutput;
+use deno_doc::node::DeclarationKind;
+use indexmap::IndexMap;
+use indexmap::IndexSet;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+use url::Url;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypeGraph {
+  pub nodes: Vec<TypeGraphNode>,
+  pub edges: Vec<TypeGraphEdge>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypeGraphNode {
+  /// The export key of the entrypoint the symbol is exported from.
+  pub export: String,
+  pub symbol: String,
+  /// The kind of declaration, as named by deno_doc, e.g. `function` or
+  /// `typeAlias`. Re-exports have the kind `reference`.
+  pub kind: String,
+}
+
+/// A reference from the type signature of the `from` node to the `to` node,
+/// both indexes into [TypeGraph::nodes].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypeGraphEdge {
+  pub from: usize,
+  pub to: usize,
+}
+
+fn declaration_kind(def: &DeclarationDef) -> &'static str {
+  match def {
+    DeclarationDef::Function(_) => "function",
+    DeclarationDef::Variable(_) => "variable",
+    DeclarationDef::Enum(_) => "enum",
+    DeclarationDef::Class(_) => "class",
+    DeclarationDef::TypeAlias(_) => "typeAlias",
+    DeclarationDef::Namespace(_) => "namespace",
+    DeclarationDef::Interface(_) => "interface",
+    DeclarationDef::Reference(_) => "reference",
+  }
+}
+
+pub fn generate_type_graph(
+  exports: &ExportsMap,
+  doc_nodes: &ParseOutput,
+) -> TypeGraph {
+  let mut graph = TypeGraph::default();
+  let mut references = vec![];
+  // The node indexes of the exported symbols by name, in export order.
+  let mut nodes_by_name = IndexMap::<String, Vec<usize>>::new();
+
+  for (export, path) in exports.iter() {
+    let Some(path) = path.strip_prefix('.') else {
+      continue;
+    };
+    let Ok(specifier) = Url::parse(&format!("file://{path}")) else {
+      continue;
+    };
+    let Some(document) = doc_nodes.get(&specifier) else {
+      continue;
+    };
+
+    for symbol in &document.symbols {
+      let declarations = symbol
+        .declarations
+        .iter()
+        .filter(|decl| decl.declaration_kind != DeclarationKind::Private)
+        .collect::<Vec<_>>();
+      let Some(first) = declarations.first() else {
+        continue;
+      };
+
+      let mut names = IndexSet::new();
+      for decl in &declarations {
+        if let Ok(value) = serde_json::to_value(&decl.def) {
+          collect_referenced_names(&value, &mut names);
+        }
+      }
+      names.shift_remove(&*symbol.name);
+
+      let index = graph.nodes.len();
+      graph.nodes.push(TypeGraphNode {
+        export: export.clone(),
+        symbol: symbol.name.to_string(),
+        kind: declaration_kind(&first.def).to_string(),
+      });
+      nodes_by_name
+        .entry(symbol.name.to_string())
+        .or_default()
+        .push(index);
+      references.push(names);
+    }
+  }
+
+  for (from, names) in references.into_iter().enumerate() {
+    let mut targets = IndexSet::new();
+    for name in names {
+      let Some(candidates) = nodes_by_name.get(&name) else {
+        continue;
+      };
+      // A name exported from several entrypoints most likely refers to the
+      // symbol of the same entrypoint.
+      let export = &graph.nodes[from].export;
+      let to = candidates
+        .iter()
+        .find(|index| graph.nodes[**index].export == *export)
+        .unwrap_or(&candidates[0]);
+      targets.insert(*to);
+    }
+    graph
+      .edges
+      .extend(targets.into_iter().map(|to| TypeGraphEdge { from, to }));
+  }
+
+  graph
+}
+
+/// Collects the root identifiers of the types referenced in a serialized
+/// declaration, excluding type parameters.
+fn collect_referenced_names(value: &Value, names: &mut IndexSet<String>) {
+  fn root_ident(name: &str) -> String {
+    name.split('.').next().unwrap_or(name).to_string()
+  }
+
+  match value {
+    Value::Object(map) => {
+      match (map.get("kind").and_then(Value::as_str), map.get("value")) {
+        (Some("typeRef"), Some(type_ref)) => {
+          let resolution = type_ref.get("resolution");
+          let resolution_kind = resolution
+            .and_then(|resolution| resolution.get("kind"))
+            .and_then(Value::as_str);
+          if resolution_kind != Some("typeParam") {
+            // Prefer the original name of symbols imported under another
+            // name.
+            let name = resolution
+              .filter(|_| resolution_kind == Some("import"))
+              .and_then(|resolution| resolution.get("name"))
+              .and_then(Value::as_str)
+              .or_else(|| type_ref.get("typeName").and_then(Value::as_str));
+            if let Some(name) = name {
+              names.insert(root_ident(name));
+            }
+          }
+        }
+        (Some("typeQuery"), Some(Value::String(name))) => {
+          names.insert(root_ident(name));
+        }
+        _ => {}
+      }
+      // `extends` of classes is the name of the super class.
+      if let Some(Value::String(name)) = map.get("extends") {
+        names.insert(root_ident(name));
+      }
+      for value in map.values() {
+        collect_referenced_names(value, names);
+      }
+    }
+    Value::Array(values) => {
+      for value in values {
+        collect_referenced_names(value, names);
+      }
+    }
+    _ => {}
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn collects_referenced_names() {
+    let value = json!({
+      "kind": "function",
+      "def": {
+        "params": [{
+          "kind": "identifier",
+          "name": "options",
+          "tsType": {
+            "repr": "Options",
+            "kind": "typeRef",
+            "value": { "typeName": "Options", "resolution": { "kind": "local" } }
+          }
+        }],
+        "returnType": {
+          "repr": "",
+          "kind": "typeRef",
+          "value": {
+            "typeName": "Promise",
+            "typeParams": [{
+              "repr": "",
+              "kind": "typeRef",
+              "value": {
+                "typeName": "Res",
+                "resolution": {
+                  "kind": "import",
+                  "specifier": "./result.ts",
+                  "name": "Result"
+                }
+              }
+            }, {
+              "repr": "T",
+              "kind": "typeRef",
+              "value": { "typeName": "T", "resolution": { "kind": "typeParam" } }
+            }]
+          }
+        }
+      }
+    });
+
+    let mut names = IndexSet::new();
+    collect_referenced_names(&value, &mut names);
+    assert_eq!(
+      names.into_iter().collect::<Vec<_>>(),
+      vec!["Options", "Promise", "Result"]
+    );
+  }
+}
@@ -1,6 +1,7 @@
 // Copyright 2024 the JSR authors. All rights reserved. MIT license.
 use futures::FutureExt;
 use hyper::Body;
+use hyper::Method;
 use hyper::Request;
 use hyper::Response;
 use hyper::StatusCode;
@@ -8,6 +9,9 @@ use hyper::body;
 use hyper::header;
 use hyper::header::COOKIE;
 use oauth2::http::HeaderName;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use routerify::RequestInfo;
 use routerify::prelude::RequestExt;
 use routerify_query::RequestQueryExt;
 use serde::Serialize;
@@ -22,15 +26,22 @@ use tracing::instrument;
 use url::Url;
 use uuid::Uuid;
 
+use crate::RegistryUrl;
 use crate::api::ApiError;
 use crate::db::Database;
+use crate::db::PackageVisibility;
 use crate::db::Permissions;
-use crate::external::github::verify_oidc_token;
+use crate::events;
+use crate::events::DomainEvent;
+use crate::external::github::GITHUB_OIDC_ISSUER;
+use crate::external::github::GitHubClaims;
 use crate::iam::IamInfo;
 use crate::iam::ReqIamExt as _;
 use crate::ids::PackageName;
 use crate::ids::ScopeName;
 use crate::ids::Version;
+use crate::oidc;
+use crate::oidc::OidcVerifier;
 
 pub const USER_AGENT: &str = "JSR";
 
@@ -456,7 +467,12 @@ where
   }
 }
 
-#[instrument(name = "auth", skip(req), err, fields(token.kind, user.id, repo.id))]
+#[instrument(
+  name = "auth",
+  skip(req),
+  err,
+  fields(token.kind, user.id, repo.id, oidc.issuer, oidc.subject)
+)]
 pub async fn auth_middleware(req: Request<Body>) -> ApiResult<Request<Body>> {
   let db = req.data::<Database>().unwrap();
   let token = extract_token_and_sudo(&req);
@@ -467,9 +483,7 @@ pub async fn auth_middleware(req: Request<Body>) -> ApiResult<Request<Body>> {
     match token {
       Some((AuthorizationToken::Bearer(token), sudo)) => {
         span.record("token.kind", field::display("bearer"));
-        if let Some(token) =
-          db.get_token_by_hash(&crate::token::hash(token)).await?
-        {
+        if let Some(token) = crate::token::find_token(db, token).await? {
           if let Some(expires_at) = token.expires_at
             && expires_at < chrono::Utc::now()
           {
@@ -483,6 +497,17 @@ pub async fn auth_middleware(req: Request<Body>) -> ApiResult<Request<Body>> {
             return Err(ApiError::Blocked);
           }
 
+          if db.touch_token(token.id).await? {
+            events::emit(
+              db,
+              DomainEvent::TokenUsed {
+                token_id: token.id,
+                user_id: user.id,
+              },
+            )
+            .await;
+          }
+
           IamInfo::from((token, user, sudo))
         } else {
           return Err(ApiError::InvalidBearerToken);
@@ -491,7 +516,9 @@ pub async fn auth_middleware(req: Request<Body>) -> ApiResult<Request<Body>> {
       Some((AuthorizationToken::GithubOIDC(token), _)) => {
         span.record("token.kind", field::display("githuboidc"));
 
-        let claims = verify_oidc_token(token).await?;
+        let verifier = req.data::<OidcVerifier>().unwrap();
+        let claims: GitHubClaims =
+          verifier.verify(token, GITHUB_OIDC_ISSUER, None).await?;
         span.record("repo.id", field::display(claims.repository_id));
 
         let aud: GithubOidcTokenAud = serde_json::from_str(&claims.aud)
@@ -506,9 +533,33 @@ pub async fn auth_middleware(req: Request<Body>) -> ApiResult<Request<Body>> {
 
         IamInfo::from((claims.repository_id, aud, user))
       }
+      Some((AuthorizationToken::Oidc(token), _)) => {
+        span.record("token.kind", field::display("oidc"));
+
+        let verifier = req.data::<OidcVerifier>().unwrap();
+        let registry_url = req.data::<RegistryUrl>().unwrap();
+        let publisher = verifier
+          .verify_publisher(db, &oidc::audience(&registry_url.0), token)
+          .await?;
+        span.record("oidc.issuer", field::display(&publisher.issuer));
+        span.record("oidc.subject", field::display(&publisher.subject));
+
+        IamInfo::from(publisher)
+      }
       None => IamInfo::anonymous(),
     };
 
+  // Read-only credentials may be used for any request that does not change
+  // anything, so the handlers don't need to check for them individually.
+  if iam_info
+    .permissions
+    .as_ref()
+    .is_some_and(|permissions| permissions.is_read_only())
+    && !matches!(*req.method(), Method::GET | Method::HEAD)
+  {
+    return Err(ApiError::MissingPermission);
+  }
+
   req.set_context(iam_info);
 
   Ok(req)
@@ -538,6 +589,7 @@ where
 enum AuthorizationToken<'s> {
   Bearer(&'s str),
   GithubOIDC(&'s str),
+  Oidc(&'s str),
 }
 
 static X_JSR_SUDO: HeaderName = header::HeaderName::from_static("x-jsr-sudo");
@@ -578,6 +630,9 @@ fn extract_token_and_sudo(
     if let Some(token) = auth.strip_prefix("githuboidc ") {
       return Some((AuthorizationToken::GithubOIDC(token), sudo));
     }
+    if let Some(token) = auth.strip_prefix("oidc ") {
+      return Some((AuthorizationToken::Oidc(token), sudo));
+    }
   }
 
   None
@@ -628,6 +683,347 @@ pub fn pagination(req: &Request<Body>) -> (i64, i64) {
   (start, limit)
 }
 
+/// The locales the caller prefers, most preferred first. An explicit `locale`
+/// query parameter takes precedence over the `Accept-Language` header, so that
+/// URL-keyed caches can still serve localized responses.
+pub fn preferred_locales(req: &Request<Body>) -> Vec<String> {
+  if let Some(locale) = req.query("locale") {
+    return normalize_locale(locale).into_iter().collect();
+  }
+  req
+    .headers()
+    .get(header::ACCEPT_LANGUAGE)
+    .and_then(|value| value.to_str().ok())
+    .map(parse_accept_language)
+    .unwrap_or_default()
+}
+
+/// Parses an `Accept-Language` header into normalized locales ordered by
+/// quality. Entries with equal quality keep their header order; wildcards and
+/// `q=0` entries are dropped.
+pub fn parse_accept_language(header: &str) -> Vec<String> {
+  let mut locales = header
+    .split(',')
+    .filter_map(|entry| {
+      let mut parts = entry.split(';');
+      let tag = parts.next()?.trim();
+      let quality = parts
+        .find_map(|param| param.trim().strip_prefix("q="))
+        .map(|q| q.parse::<f32>().unwrap_or(0.0))
+        .unwrap_or(1.0);
+      if quality <= 0.0 {
+        return None;
+      }
+      Some((normalize_locale(tag)?, quality))
+    })
+    .collect::<Vec<_>>();
+  locales.sort_by(|a, b| b.1.total_cmp(&a.1));
+  locales.into_iter().map(|(locale, _)| locale).collect()
+}
+
+static LOCALE_RE: Lazy<Regex> =
+  Lazy::new(|| Regex::new(r"^[a-z]{2,3}(-[a-z0-9]{1,8})*$").unwrap());
+
+/// Lowercases a BCP 47 language tag (accepting `_` as separator), returning
+/// `None` if it is not well formed.
+pub fn normalize_locale(locale: &str) -> Option<String> {
+  let locale = locale.trim().to_ascii_lowercase().replace('_', "-");
+  LOCALE_RE.is_match(&locale).then_some(locale)
+}
+
+/// Picks the available locale that best matches the preferred locales. For
+/// each preferred locale an exact match wins, then a match on the primary
+/// language subtag (`de-ch` matches `de` and `de-de`).
+pub fn best_locale_match<'a>(
+  preferred: &[String],
+  available: &'a [&'a str],
+) -> Option<&'a str> {
+  fn language(locale: &str) -> &str {
+    locale.split('-').next().unwrap()
+  }
+
+  preferred.iter().find_map(|preferred| {
+    available
+      .iter()
+      .find(|available| **available == preferred)
+      .or_else(|| {
+        available
+          .iter()
+          .find(|available| language(available) == language(preferred))
+      })
+      .copied()
+  })
+}
+
+/// Wrap a handler whose response depends on [`preferred_locales`], marking it
+/// with `Vary: Accept-Language` so caches do not mix up translations.
+pub fn vary_accept_language<H, HF>(
+  handler: H,
+) -> impl Fn(Request<Body>) -> ApiHandlerFuture<Response<Body>>
+where
+  H: Send + Sync + Fn(Request<Body>) -> HF + Send + 'static,
+  HF: Future<Output = ApiResult<Response<Body>>> + Send + 'static,
+{
+  let handler = Arc::new(handler);
+  move |req: Request<Body>| {
+    let handler = handler.clone();
+    async move {
+      let mut res = handler(req).await?;
+      res.headers_mut().append(
+        header::VARY,
+        header::HeaderValue::from_static("Accept-Language"),
+      );
+      Ok(res)
+    }
+    .boxed()
+  }
+}
+
+/// Wrap a handler so a successful response carries a strong `ETag` computed
+/// from a hash of its body, unless the handler set one itself. Requests with a
+/// matching `If-None-Match` are then answered with a `304 Not Modified` by
+/// [`etag_middleware`].
+///
+/// Apply it outside of the cache wrappers: a `private` response can't be purged
+/// from browser caches when the data changes, so with an `ETag` to revalidate
+/// against it is marked `no-cache` instead of being kept for the full duration.
+/// `public` responses keep their CDN caching, which purges them on change.
+pub fn etag<H, HF>(
+  handler: H,
+) -> impl Fn(Request<Body>) -> ApiHandlerFuture<Response<Body>>
+where
+  H: Send + Sync + Fn(Request<Body>) -> HF + Send + 'static,
+  HF: Future<Output = ApiResult<Response<Body>>> + Send + 'static,
+{
+  let handler = Arc::new(handler);
+  move |req: Request<Body>| {
+    let handler = handler.clone();
+    async move {
+      let res = handler(req).await?;
+      if res.status() != StatusCode::OK {
+        return Ok(res);
+      }
+
+      let (mut parts, body) = res.into_parts();
+      if !parts.headers.contains_key(header::ETAG) {
+        let bytes = body::to_bytes(body).await.map_err(anyhow::Error::from)?;
+        parts.headers.insert(header::ETAG, compute_etag(&bytes));
+        let res = Response::from_parts(parts, Body::from(bytes));
+        return Ok(mark_private_no_cache(res));
+      }
+      Ok(mark_private_no_cache(Response::from_parts(parts, body)))
+    }
+    .boxed()
+  }
+}
+
+fn compute_etag(bytes: &[u8]) -> header::HeaderValue {
+  use sha2::Digest;
+  let hash = sha2::Sha256::digest(bytes);
+  let hash = hash[..16]
+    .iter()
+    .map(|byte| format!("{byte:02x}"))
+    .collect::<String>();
+  header::HeaderValue::from_str(&format!("\"{hash}\"")).unwrap()
+}
+
+fn mark_private_no_cache(mut res: Response<Body>) -> Response<Body> {
+  let is_private = res
+    .headers()
+    .get(header::CACHE_CONTROL)
+    .and_then(|value| value.to_str().ok())
+    .is_some_and(|value| value.starts_with("private"));
+  if is_private {
+    res.headers_mut().insert(
+      header::CACHE_CONTROL,
+      header::HeaderValue::from_static("private, no-cache"),
+    );
+  }
+  res
+}
+
+/// Whether an `If-None-Match` header matches `etag`, using the weak comparison
+/// that RFC 9110 requires for it.
+fn if_none_match_matches(if_none_match: &str, etag: &str) -> bool {
+  let etag = etag.trim_start_matches("W/");
+  if_none_match.split(',').map(str::trim).any(|candidate| {
+    candidate == "*" || candidate.trim_start_matches("W/") == etag
+  })
+}
+
+/// Answers conditional `GET` and `HEAD` requests with a `304 Not Modified` if
+/// the `If-None-Match` header matches the `ETag` of the response, as set by
+/// [`etag`]. The caching headers of the full response are kept.
+pub async fn etag_middleware(
+  res: Response<Body>,
+  req_info: RequestInfo,
+) -> ApiResult<Response<Body>> {
+  if !matches!(*req_info.method(), Method::GET | Method::HEAD)
+    || res.status() != StatusCode::OK
+  {
+    return Ok(res);
+  }
+  let Some(if_none_match) = req_info
+    .headers()
+    .get(header::IF_NONE_MATCH)
+    .and_then(|value| value.to_str().ok())
+  else {
+    return Ok(res);
+  };
+  let matches = res
+    .headers()
+    .get(header::ETAG)
+    .and_then(|value| value.to_str().ok())
+    .is_some_and(|etag| if_none_match_matches(if_none_match, etag));
+  if !matches {
+    return Ok(res);
+  }
+
+  let (mut parts, _) = res.into_parts();
+  parts.status = StatusCode::NOT_MODIFIED;
+  parts.headers.remove(header::CONTENT_TYPE);
+  parts.headers.remove(header::CONTENT_LENGTH);
+  Ok(Response::from_parts(parts, Body::empty()))
+}
+
+/// The scope targeted by a request to `/api/scopes/:scope/...`.
+pub fn target_scope(path: &str) -> Option<ScopeName> {
+  let scope = path.strip_prefix("/api/scopes/")?.split('/').next()?;
+  ScopeName::try_from(scope).ok()
+}
+
+/// The scope and package targeted by a request to
+/// `/api/scopes/:scope/packages/:package/...`.
+fn target_package(path: &str) -> Option<(ScopeName, PackageName)> {
+  let mut parts = path.strip_prefix("/api/scopes/")?.split('/');
+  let scope = ScopeName::try_from(parts.next()?).ok()?;
+  if parts.next()? != "packages" {
+    return None;
+  }
+  let package = PackageName::try_from(parts.next()?).ok()?;
+  Some((scope, package))
+}
+
+/// The secret the load balancer sends with every request it forwards.
+pub struct OriginSecret(pub String);
+
+/// Rejects requests that did not come through the load balancer, so that
+/// nobody can reach the API directly and pick the client IP that
+/// `x-forwarded-for` reports for them. Background tasks are sent by Cloud
+/// Tasks and metrics are scraped directly, so those routes are left to their
+/// own authentication.
+pub async fn origin_secret_middleware(
+  req: Request<Body>,
+) -> ApiResult<Request<Body>> {
+  let path = req.uri().path();
+  if path.starts_with("/tasks/") || path == "/metrics" {
+    return Ok(req);
+  }
+  let OriginSecret(secret) = req.data::<OriginSecret>().unwrap();
+  let authorized = req
+    .headers()
+    .get("x-jsr-origin-secret")
+    .and_then(|value| value.to_str().ok())
+    .is_some_and(|value| value == secret.as_str());
+  if !authorized {
+    return Err(ApiError::DirectOriginRequest);
+  }
+  Ok(req)
+}
+
+/// Makes reads go to the primary database instead of the read replica for a
+/// while when a request may write, so that this request and the ones after it
+/// see the write even if the replica has not caught up yet.
+pub async fn db_stickiness_middleware(
+  req: Request<Body>,
+) -> ApiResult<Request<Body>> {
+  if !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+    req.data::<Database>().unwrap().stick_to_primary();
+  }
+  Ok(req)
+}
+
+/// Marks a request whose response is about a private package, so that
+/// [`private_package_headers_middleware`] keeps it out of shared caches.
+#[derive(Clone, Copy, Debug)]
+pub struct PrivatePackage;
+
+/// Answers reads of a private package by anyone who is not a member of its
+/// scope as if the package did not exist. Writes are left to the permission
+/// checks of their handlers. Must run after `auth_middleware`.
+pub async fn private_package_middleware(
+  req: Request<Body>,
+) -> ApiResult<Request<Body>> {
+  if !matches!(*req.method(), Method::GET | Method::HEAD) {
+    return Ok(req);
+  }
+  let Some((scope, package)) = target_package(req.uri().path()) else {
+    return Ok(req);
+  };
+
+  let db = req.data::<Database>().unwrap();
+  if db.get_package_visibility(&scope, &package).await?
+    == Some(PackageVisibility::Private)
+  {
+    req.iam().check_private_package_read_access(&scope).await?;
+    req.set_context(PrivatePackage);
+  }
+  Ok(req)
+}
+
+/// Requests that read a scope, or one of its packages, under the old name of a
+/// renamed scope are answered with a redirect to the new name.
+pub async fn scope_redirect_middleware(
+  req: Request<Body>,
+) -> ApiResult<Request<Body>> {
+  if !matches!(*req.method(), Method::GET | Method::HEAD) {
+    return Ok(req);
+  }
+  let Some(scope) = target_scope(req.uri().path()) else {
+    return Ok(req);
+  };
+
+  let db = req.data::<Database>().unwrap();
+  if db.get_scope_cached(&scope).await?.is_some() {
+    return Ok(req);
+  }
+  let Some(redirect) = db.get_scope_redirect(&scope).await? else {
+    return Ok(req);
+  };
+  match target_package(req.uri().path()) {
+    Some((_, package)) => Err(ApiError::PackageMoved {
+      scope: redirect.target_scope,
+      package,
+    }),
+    None => Err(ApiError::ScopeMoved {
+      scope: redirect.target_scope,
+    }),
+  }
+}
+
+/// Responses about private packages must never be served from a shared cache,
+/// including on the routes that are otherwise identity-independent.
+pub async fn private_package_headers_middleware(
+  mut res: Response<Body>,
+  req_info: RequestInfo,
+) -> ApiResult<Response<Body>> {
+  if req_info.context::<PrivatePackage>().is_some() {
+    let headers = res.headers_mut();
+    headers.remove(&X_JSR_CACHE_SHARED);
+    let is_public = headers
+      .get(header::CACHE_CONTROL)
+      .and_then(|value| value.to_str().ok())
+      .is_some_and(|value| value.starts_with("public"));
+    if is_public {
+      headers.insert(
+        header::CACHE_CONTROL,
+        header::HeaderValue::from_static("private, no-cache"),
+      );
+    }
+  }
+  Ok(res)
+}
+
 pub struct DocsQueries<'a> {
   pub all_symbols: bool,
   pub entrypoint: Option<&'a str>,
@@ -836,7 +1232,12 @@ pub mod test {
       FakeS3Tester::new();
     });
   }
+  use crate::util::best_locale_match;
+  use crate::util::if_none_match_matches;
+  use crate::util::parse_accept_language;
   use crate::util::sanitize_redirect_url;
+  use crate::util::target_package;
+  use crate::util::target_scope;
   use hyper::Body;
   use hyper::HeaderMap;
   use hyper::Response;
@@ -990,6 +1391,7 @@ pub mod test {
           Some(250),
           Some(200),
           Some(1000),
+          None,
         )
         .await
         .unwrap();
@@ -1006,18 +1408,32 @@ pub mod test {
         github_client: github_oauth2_client.clone(),
         gitlab_client: gitlab_oauth2_client.clone(),
         algolia_client: None,
+        package_search: crate::search::PackageSearch::postgres(),
         email_sender: None,
         license_store: license_store.clone(),
+        // Only the rules scanner, as there is no clamd locally.
+        malware_scanners: crate::malware_scan::MalwareScanners::new(vec![
+          Arc::new(crate::malware_scan::RulesScanner),
+        ]),
         registry_url,
         npm_url: "http://npm.jsr-tests.test".parse().unwrap(),
-        publish_queue: None,           // no queue locally
-        npm_tarball_build_queue: None, // no queue locally
-        analytics_engine_config: None, // no analytics engine locally
-        cache_purge_client: None,      // no Cloudflare purge locally
+        npm_signer: crate::npm::NpmSigner(None), // npm tarballs are not signed
+        publish_queue: None,                     // no queue locally
+        npm_tarball_build_queue: None,           // no queue locally
+        analytics_engine_config: None,           // no analytics engine locally
+        cache_purge_client: None,                // no Cloudflare purge locally
         // No secret key, so the login captcha is not verified in tests.
         turnstile: crate::external::cloudflare::Turnstile(None),
-        expose_api: true,   // api enabled
-        expose_tasks: true, // task endpoints enabled
+        member_cooldown: crate::iam::MemberCooldown {
+          hours: 24,
+          min_weekly_downloads: 1000,
+        },
+        tombstone_retention: crate::tombstones::TombstoneRetention { days: 30 },
+        expose_api: true,    // api enabled
+        expose_tasks: true,  // task endpoints enabled
+        metrics_token: None, // metrics not served
+        origin_secret: None, // requests are not sent through the lb
+        trusted_proxy_hops: 0,
       });
 
       let service = routerify::RequestServiceBuilder::new(router)
@@ -1326,6 +1742,74 @@ pub mod test {
     assert!(t.staff_user.user.is_staff);
   }
 
+  #[test]
+  fn accept_language() {
+    assert_eq!(
+      parse_accept_language("de-CH, fr;q=0.9, en;q=0.8, *;q=0.5"),
+      vec!["de-ch", "fr", "en"]
+    );
+    assert_eq!(parse_accept_language("en;q=0.5, ja"), vec!["ja", "en"]);
+    assert_eq!(parse_accept_language("pt_BR, es;q=0"), vec!["pt-br"]);
+    assert!(parse_accept_language("").is_empty());
+    assert!(parse_accept_language("*").is_empty());
+
+    let available = ["de", "pt-br", "zh-hant"];
+    let best = |preferred: &[&str]| {
+      let preferred =
+        preferred.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+      best_locale_match(&preferred, &available)
+    };
+    assert_eq!(best(&["de-ch"]), Some("de"));
+    assert_eq!(best(&["pt-br", "de"]), Some("pt-br"));
+    assert_eq!(best(&["pt"]), Some("pt-br"));
+    assert_eq!(best(&["fr", "zh-hant-tw"]), Some("zh-hant"));
+    assert_eq!(best(&["fr"]), None);
+    assert_eq!(best(&[]), None);
+  }
+
+  #[test]
+  fn if_none_match() {
+    let etag = r#""abc""#;
+    assert!(if_none_match_matches(r#""abc""#, etag));
+    assert!(if_none_match_matches(r#"W/"abc""#, etag));
+    assert!(if_none_match_matches(r#""def", "abc""#, etag));
+    assert!(if_none_match_matches("*", etag));
+    assert!(!if_none_match_matches(r#""def""#, etag));
+    assert!(!if_none_match_matches("abc", etag));
+  }
+
+  #[test]
+  fn target_scope_from_path() {
+    assert_eq!(
+      target_scope("/api/scopes/std/packages/fs").map(|s| s.to_string()),
+      Some("std".to_string())
+    );
+    assert_eq!(
+      target_scope("/api/scopes/std").map(|s| s.to_string()),
+      Some("std".to_string())
+    );
+    assert!(target_scope("/api/scopes").is_none());
+    assert!(target_scope("/api/packages").is_none());
+  }
+
+  #[test]
+  fn target_package_from_path() {
+    let target = |path| {
+      target_package(path).map(|(scope, package)| format!("{scope}/{package}"))
+    };
+    assert_eq!(
+      target("/api/scopes/std/packages/fs/versions/1.0.0/docs").as_deref(),
+      Some("std/fs")
+    );
+    assert_eq!(
+      target("/api/scopes/std/packages/fs").as_deref(),
+      Some("std/fs")
+    );
+    assert!(target("/api/scopes/std/packages").is_none());
+    assert!(target("/api/scopes/std/members/fs").is_none());
+    assert!(target("/api/packages").is_none());
+  }
+
   #[test]
   fn sanitize_url_test() {
     assert_eq!(sanitize_redirect_url("/foo"), "/foo");
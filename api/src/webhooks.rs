@@ -0,0 +1,334 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+//! Webhooks that scope admins register to be notified of events in their
+//! scope.
+//!
+//! [dispatch], called by [crate::events::emit] for the domain events that
+//! webhooks can subscribe to, queues a delivery for every webhook of a scope
+//! that subscribes to the event. [deliver_pending_webhooks], run periodically by the
+//! `deliver_webhooks` task, sends the due deliveries as JSON payloads signed
+//! with the secret of their webhook, and retries failed ones with exponential
+//! backoff until they run out of attempts.
+//!
+//! Webhooks must not be able to reach the internal network of the registry,
+//! so they are only ever sent to public addresses: URLs with other addresses
+//! are refused when they are registered, and host names are resolved by
+//! [PublicAddressResolver], which leaves out the addresses that are not
+//! public, at the time of every delivery.
+
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::OnceLock;
+
+use chrono::Utc;
+use futures::StreamExt;
+use futures::stream;
+use hmac::Hmac;
+use hmac::Mac;
+use serde_json::json;
+use sha2::Sha256;
+use tracing::error;
+use tracing::instrument;
+
+use crate::db::Database;
+use crate::db::PendingWebhookDelivery;
+use crate::db::WebhookDeliveryStatus;
+use crate::db::WebhookEvent;
+use crate::ids::ScopeName;
+
+/// The HMAC-SHA256 of the body, keyed with the secret of the webhook, as
+/// `sha256=<hex>`.
+pub const SIGNATURE_HEADER: &str = "x-jsr-signature-256";
+pub const EVENT_HEADER: &str = "x-jsr-event";
+/// The id of the delivery, which stays the same across retries.
+pub const DELIVERY_HEADER: &str = "x-jsr-delivery";
+
+/// Deliveries that failed this many times are not retried anymore.
+pub const MAX_DELIVERY_ATTEMPTS: i32 = 8;
+const DELIVERY_TIMEOUT: std::time::Duration =
+  std::time::Duration::from_secs(10);
+/// How long a claimed delivery is not handed out again, so that it is retried
+/// if the task dies while sending it.
+const DELIVERY_LEASE_MINUTES: i64 = 5;
+const DELIVERY_BATCH_SIZE: i64 = 100;
+const DELIVERY_PARALLELISM: usize = 16;
+/// Bounds a single run of the delivery task.
+const MAX_DELIVERY_BATCHES: usize = 10;
+
+/// Queues a delivery of `data` to every webhook of `scope` subscribed to
+/// `event`. Failing to queue is logged rather than returned, so that the
+/// action that caused the event is not failed by it.
+pub async fn dispatch(
+  db: &Database,
+  scope: &ScopeName,
+  event: WebhookEvent,
+  data: serde_json::Value,
+) {
+  let payload = json!({
+    "event": event,
+    "scope": scope,
+    "createdAt": Utc::now(),
+    "data": data,
+  });
+  if let Err(err) = db.enqueue_webhook_deliveries(scope, event, payload).await {
+    error!("failed to queue {event:?} webhook deliveries for @{scope}: {err}");
+  }
+}
+
+pub fn sign_payload(secret: &str, body: &[u8]) -> String {
+  // HMAC accepts keys of any length.
+  let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+  mac.update(body);
+  format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+/// How long to wait before retrying a delivery that failed `attempts` times:
+/// 30 seconds, doubling with every attempt, up to 6 hours.
+fn retry_delay(attempts: i32) -> chrono::Duration {
+  let exponent = (attempts - 1).clamp(0, 16) as u32;
+  let seconds = 30i64 * 2i64.pow(exponent);
+  chrono::Duration::seconds(seconds.min(6 * 60 * 60))
+}
+
+/// Whether webhooks may be sent to `ip`: addresses in private, loopback,
+/// link-local, unique local, shared (CGNAT) and other special purpose ranges
+/// are not public.
+pub fn is_public_address(ip: IpAddr) -> bool {
+  match ip {
+    IpAddr::V4(ip) => {
+      let [a, b, ..] = ip.octets();
+      !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // 0.0.0.0/8, "this network"
+        || a == 0
+        // 100.64.0.0/10, shared address space
+        || (a == 100 && (b & 0b1100_0000) == 64)
+        // 192.0.0.0/24, IETF protocol assignments
+        || ip.octets()[..3] == [192, 0, 0]
+        // 198.18.0.0/15, benchmarking
+        || (a == 198 && (b & 0xfe) == 18)
+        // 240.0.0.0/4, reserved
+        || a >= 240)
+    }
+    IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+      Some(ip) => is_public_address(IpAddr::V4(ip)),
+      None => {
+        !(ip.is_loopback()
+          || ip.is_unspecified()
+          || ip.is_unique_local()
+          || ip.is_unicast_link_local()
+          || ip.is_multicast()
+          // 64:ff9b::/96, NAT64, which can reach any IPv4 address
+          || ip.segments()[..6] == [0x64, 0xff9b, 0, 0, 0, 0]
+          // 2001:db8::/32, documentation
+          || ip.segments()[..2] == [0x2001, 0xdb8])
+      }
+    },
+  }
+}
+
+/// Resolves the host names of webhook URLs to their public addresses only,
+/// so that a host name can not be pointed at the internal network, even after
+/// the webhook was registered.
+struct PublicAddressResolver;
+
+impl reqwest::dns::Resolve for PublicAddressResolver {
+  fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+    Box::pin(resolve_public_addresses(name))
+  }
+}
+
+async fn resolve_public_addresses(
+  name: reqwest::dns::Name,
+) -> Result<reqwest::dns::Addrs, Box<dyn std::error::Error + Send + Sync>> {
+  let addrs = tokio::net::lookup_host((name.as_str(), 0))
+    .await?
+    .filter(|addr| is_public_address(addr.ip()))
+    .collect::<Vec<SocketAddr>>();
+  if addrs.is_empty() {
+    return Err(
+      format!("{} does not resolve to a public address", name.as_str()).into(),
+    );
+  }
+  Ok(Box::new(addrs.into_iter()))
+}
+
+fn http_client() -> &'static reqwest::Client {
+  static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+  CLIENT.get_or_init(|| {
+    reqwest::Client::builder()
+      .timeout(DELIVERY_TIMEOUT)
+      // The registered URL must answer itself, a redirect could point at
+      // anything.
+      .redirect(reqwest::redirect::Policy::none())
+      .dns_resolver(Arc::new(PublicAddressResolver))
+      // A proxy would resolve the host name itself.
+      .no_proxy()
+      .user_agent("JSR-Webhooks/1.0")
+      .build()
+      .unwrap()
+  })
+}
+
+/// Sends the deliveries that are due. Returns the number of deliveries that
+/// were attempted.
+#[instrument(name = "deliver_pending_webhooks", skip(db), err)]
+pub async fn deliver_pending_webhooks(
+  db: &Database,
+) -> Result<usize, sqlx::Error> {
+  let mut attempted = 0;
+  for _ in 0..MAX_DELIVERY_BATCHES {
+    let deliveries = db
+      .claim_pending_webhook_deliveries(
+        DELIVERY_BATCH_SIZE,
+        chrono::Duration::minutes(DELIVERY_LEASE_MINUTES),
+      )
+      .await?;
+    let claimed = deliveries.len();
+
+    let mut futs = stream::iter(deliveries)
+      .map(|delivery| deliver(db, delivery))
+      .buffer_unordered(DELIVERY_PARALLELISM);
+    while let Some(result) = futs.next().await {
+      result?;
+    }
+
+    attempted += claimed;
+    if claimed < DELIVERY_BATCH_SIZE as usize {
+      break;
+    }
+  }
+  Ok(attempted)
+}
+
+async fn deliver(
+  db: &Database,
+  delivery: PendingWebhookDelivery,
+) -> Result<(), sqlx::Error> {
+  let body = serde_json::to_vec(&delivery.payload).unwrap();
+  let event = serde_json::to_value(delivery.event).unwrap();
+
+  // Addresses in the URL itself are not resolved, so they are checked here.
+  let (response_status, error) = if has_private_address(&delivery.url) {
+    (None, Some("address not allowed".to_string()))
+  } else {
+    let res = http_client()
+      .post(&delivery.url)
+      .header(reqwest::header::CONTENT_TYPE, "application/json")
+      .header(EVENT_HEADER, event.as_str().unwrap())
+      .header(DELIVERY_HEADER, delivery.id.to_string())
+      .header(SIGNATURE_HEADER, sign_payload(&delivery.secret, &body))
+      .body(body)
+      .send()
+      .await;
+
+    match res {
+      Ok(res) if res.status().is_success() => {
+        (Some(res.status().as_u16() as i32), None)
+      }
+      Ok(res) => (
+        Some(res.status().as_u16() as i32),
+        Some(format!("unexpected response status {}", res.status())),
+      ),
+      // Only the kind of error is recorded, as the error itself could tell
+      // scope admins about the network the delivery was sent from.
+      Err(err) => (None, Some(delivery_error_class(&err).to_string())),
+    }
+  };
+
+  let attempts = delivery.attempts + 1;
+  let now = Utc::now();
+  let (status, next_attempt_at) = match &error {
+    None => (WebhookDeliveryStatus::Success, now),
+    Some(_) if attempts >= MAX_DELIVERY_ATTEMPTS => {
+      (WebhookDeliveryStatus::Failure, now)
+    }
+    Some(_) => (WebhookDeliveryStatus::Pending, now + retry_delay(attempts)),
+  };
+  crate::metrics::record_webhook_delivery(
+    error.is_none(),
+    status == WebhookDeliveryStatus::Pending,
+  );
+
+  db.finish_webhook_delivery_attempt(
+    delivery.id,
+    status,
+    response_status,
+    error.as_deref(),
+    next_attempt_at,
+  )
+  .await
+}
+
+/// Whether `url` has an IP address as its host that is not public.
+fn has_private_address(url: &str) -> bool {
+  let Ok(url) = url::Url::parse(url) else {
+    return false;
+  };
+  match url.host() {
+    Some(url::Host::Ipv4(ip)) => !is_public_address(IpAddr::V4(ip)),
+    Some(url::Host::Ipv6(ip)) => !is_public_address(IpAddr::V6(ip)),
+    _ => false,
+  }
+}
+
+fn delivery_error_class(err: &reqwest::Error) -> &'static str {
+  if err.is_timeout() {
+    "timeout"
+  } else if err.is_connect() {
+    "connection failed"
+  } else {
+    "request failed"
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  #[test]
+  fn sign_payload() {
+    // RFC 4231, test case 2
+    assert_eq!(
+      super::sign_payload("Jefe", b"what do ya want for nothing?"),
+      "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+  }
+
+  #[test]
+  fn retry_delay() {
+    assert_eq!(super::retry_delay(1), chrono::Duration::seconds(30));
+    assert_eq!(super::retry_delay(2), chrono::Duration::seconds(60));
+    assert_eq!(super::retry_delay(4), chrono::Duration::seconds(240));
+    assert_eq!(super::retry_delay(30), chrono::Duration::hours(6));
+  }
+
+  #[test]
+  fn is_public_address() {
+    for ip in [
+      "10.1.2.3",
+      "127.0.0.1",
+      "169.254.169.254",
+      "172.16.0.1",
+      "192.168.1.1",
+      "100.64.0.1",
+      "0.0.0.0",
+      "255.255.255.255",
+      "::1",
+      "::",
+      "fd00::1",
+      "fe80::1",
+      "::ffff:127.0.0.1",
+      "64:ff9b::a9fe:a9fe",
+    ] {
+      assert!(!super::is_public_address(ip.parse().unwrap()), "{ip}");
+    }
+    for ip in ["93.184.216.34", "100.128.0.1", "2606:2800:220:1::1"] {
+      assert!(super::is_public_address(ip.parse().unwrap()), "{ip}");
+    }
+  }
+}
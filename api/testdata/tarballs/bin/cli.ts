@@ -0,0 +1,3 @@
+import { greet } from "./mod.ts";
+
+console.log(greet("world"));
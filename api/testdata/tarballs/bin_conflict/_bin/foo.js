@@ -0,0 +1 @@
+console.log("clashes with the generated bin stub");
@@ -0,0 +1,5 @@
+import { hello } from "./mod.ts";
+
+Deno.test("hello", () => {
+  if (hello !== "Hello, world!") throw new Error("unexpected greeting");
+});
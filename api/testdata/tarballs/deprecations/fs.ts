@@ -0,0 +1,5 @@
+/** @deprecated */
+export const exists = true;
+
+/** Whether the file system is read only. */
+export const readonly = false;
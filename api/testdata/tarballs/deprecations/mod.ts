@@ -0,0 +1,13 @@
+/**
+ * Says hello.
+ *
+ * @deprecated Use {@linkcode greet} instead.
+ */
+export function hello(): string {
+  return "Hello, world!";
+}
+
+/** Greets someone. */
+export function greet(name: string): string {
+  return `Hello, ${name}!`;
+}
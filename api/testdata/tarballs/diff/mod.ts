@@ -0,0 +1,17 @@
+/**
+ * This is a test module.
+ *
+ * @module
+ */
+
+/**
+ * This is a test constant.
+ */
+export const hello: string = "Hello, world!";
+
+/**
+ * This is a test function.
+ */
+export function goodbye(): string {
+  return "Goodbye, world!";
+}
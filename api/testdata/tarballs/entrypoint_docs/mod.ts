@@ -0,0 +1,13 @@
+/**
+ * This is a test module.
+ *
+ * @module
+ */
+
+/**
+ * This is a test constant.
+ */
+export const hello = "Hello, world!";
+
+/** This is another test constant. */
+export const goodbye = "Goodbye, world!";
@@ -0,0 +1,8 @@
+export function add(a: number, b: number): number {
+  return a + b;
+}
+
+/** Subtracts `b` from `a`. */
+export function subtract(a: number, b: number): number {
+  return a - b;
+}
@@ -0,0 +1,5 @@
+/**
+ * @module
+ */
+
+export const a: string = "a";
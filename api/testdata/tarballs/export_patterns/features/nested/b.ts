@@ -0,0 +1,7 @@
+/**
+ * @module
+ */
+
+import { hello } from "../../mod.ts";
+
+export const b: string = hello;
@@ -0,0 +1,5 @@
+/**
+ * @module
+ */
+
+export const hello = "Hello, world!";
@@ -0,0 +1,2 @@
+import "jsr:@scope/foo@1.2.3";
+export const hello = "Hello, world!";
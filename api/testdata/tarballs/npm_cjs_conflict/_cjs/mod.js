@@ -0,0 +1 @@
+exports.hello = "Hello from a file that clashes with the CommonJS build!";
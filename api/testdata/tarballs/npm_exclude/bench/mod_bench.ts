@@ -0,0 +1,5 @@
+import { greet } from "../mod.ts";
+
+Deno.bench("greet", () => {
+  greet("Deno");
+});
@@ -0,0 +1,5 @@
+import { greet } from "./mod.ts";
+
+Deno.test("greet", () => {
+  if (greet("Deno") !== "Hello, Deno!") throw new Error("unexpected greeting");
+});
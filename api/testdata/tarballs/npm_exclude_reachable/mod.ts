@@ -0,0 +1,5 @@
+import { greeting } from "./internal/greeting.ts";
+
+export function greet(name: string): string {
+  return `${greeting}, ${name}!`;
+}
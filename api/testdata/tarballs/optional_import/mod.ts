@@ -0,0 +1,11 @@
+import "npm:express@4";
+
+export async function highlight(code: string): Promise<string> {
+  try {
+    const { default: chalk } = await import("npm:chalk@5");
+    await import("jsr:@scope/missing@1");
+    return chalk.yellow(code);
+  } catch {
+    return code;
+  }
+}
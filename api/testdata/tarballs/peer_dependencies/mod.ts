@@ -0,0 +1,2 @@
+import "npm:chalk@5";
+export const hello = "Hello, world!";
@@ -15,6 +15,7 @@ use serde::Serialize;
 use crate::ids::PackageName;
 use crate::ids::ScopeName;
 use crate::ids::Version;
+use crate::models::StatsDownloadedPackage;
 use crate::models::StatsPackage;
 use crate::models::StatsPackageVersion;
 
@@ -24,6 +25,10 @@ pub struct ApiStats {
   pub newest: Vec<ApiStatsPackage>,
   pub updated: Vec<ApiStatsPackageVersion>,
   pub featured: Vec<ApiStatsPackage>,
+  /// The packages with the most downloads over the last 7 days.
+  pub most_downloaded_this_week: Vec<ApiStatsDownloadedPackage>,
+  /// The packages with the most downloads over the last 30 days.
+  pub most_downloaded_this_month: Vec<ApiStatsDownloadedPackage>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -42,6 +47,24 @@ impl From<StatsPackage> for ApiStatsPackage {
   }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiStatsDownloadedPackage {
+  pub scope: ScopeName,
+  pub name: PackageName,
+  pub downloads: u64,
+}
+
+impl From<StatsDownloadedPackage> for ApiStatsDownloadedPackage {
+  fn from(p: StatsDownloadedPackage) -> Self {
+    Self {
+      scope: p.scope,
+      name: p.name,
+      downloads: p.downloads.max(0) as u64,
+    }
+  }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ApiStatsPackageVersion {
@@ -13,6 +13,30 @@ use sqlx::error::BoxDynError;
 #[cfg(feature = "sqlx")]
 use sqlx::postgres::PgValueRef;
 use thiserror::Error;
+use unicode_normalization::UnicodeNormalization;
+
+/// Reduces a scope or package name to a skeleton that is the same for names
+/// that look alike, like `rnoment` and `moment`, or `l0dash` and `lodash`.
+/// Hyphens are left out, like in the check for names that only differ in
+/// hyphens.
+///
+/// This is the part of the confusables of Unicode TR39 that applies to
+/// lowercase ASCII letters and digits, which names are restricted to. The
+/// `confusable_skeleton` SQL function must stay in sync with this.
+fn confusable_skeleton(name: &str) -> String {
+  name
+    .chars()
+    .filter_map(|c| match c {
+      '-' => None,
+      '0' => Some('o'),
+      '1' => Some('l'),
+      c => Some(c),
+    })
+    .collect::<String>()
+    .replace("rn", "m")
+    .replace("vv", "w")
+    .replace("cl", "d")
+}
 
 /// A scope name, like `user` or `admin`. The name is not prefixed with an @.
 /// The name must be at least 2 characters long, and at most 20 characters long.
@@ -23,6 +47,14 @@ pub struct ScopeName(String);
 
 impl ScopeName {
   pub fn new(name: String) -> Result<Self, ScopeNameValidateError> {
+    // Compatibility characters, like fullwidth letters, are folded into the
+    // ASCII characters that they stand for.
+    let name = if name.is_ascii() {
+      name
+    } else {
+      name.nfkc().collect::<String>()
+    };
+
     if name.len() < 2 {
       return Err(ScopeNameValidateError::TooShort);
     }
@@ -49,6 +81,11 @@ impl ScopeName {
 
     Ok(ScopeName(name))
   }
+
+  /// The skeleton that this name shares with lookalike scope names.
+  pub fn confusable_skeleton(&self) -> String {
+    confusable_skeleton(&self.0)
+  }
 }
 
 impl TryFrom<&str> for ScopeName {
@@ -297,6 +334,14 @@ pub struct PackageName(String);
 
 impl PackageName {
   pub fn new(name: String) -> Result<Self, PackageNameValidateError> {
+    // Compatibility characters, like fullwidth letters, are folded into the
+    // ASCII characters that they stand for.
+    let name = if name.is_ascii() {
+      name
+    } else {
+      name.nfkc().collect::<String>()
+    };
+
     if name.len() < 2 {
       return Err(PackageNameValidateError::TooShort);
     }
@@ -322,6 +367,11 @@ impl PackageName {
 
     Ok(PackageName(name))
   }
+
+  /// The skeleton that this name shares with lookalike package names.
+  pub fn confusable_skeleton(&self) -> String {
+    confusable_skeleton(&self.0)
+  }
 }
 
 impl TryFrom<&str> for PackageName {
@@ -509,10 +559,21 @@ impl serde::Serialize for ScopedPackageName {
 /// A package version, like '1.2.3' or '0.0.0-foo'. The version is not prefixed
 /// with a v.
 /// The version must be a valid semver version.
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+///
+/// Versions are ordered by semver precedence. Versions that only differ in
+/// build metadata, which has no precedence, are ordered by their build
+/// metadata, so that the order is total.
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Version(pub deno_semver::Version);
 
 impl Version {
+  /// Whether the version has a pre-release part, like '1.2.3-beta.1'. Build
+  /// metadata, like the '-1' in '1.2.3+build-1', does not make a version a
+  /// pre-release.
+  pub fn is_prerelease(&self) -> bool {
+    !self.0.pre.is_empty()
+  }
+
   pub fn new(specified: &str) -> Result<Self, VersionValidateError> {
     let version = deno_semver::Version::parse_standard(specified)
       .map_err(VersionValidateError::InvalidVersion)?;
@@ -527,6 +588,47 @@ impl Version {
   }
 }
 
+impl PartialOrd for Version {
+  fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Version {
+  fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+    self.0.cmp(&other.0).then_with(|| {
+      compare_build_identifiers(
+        self.0.build.iter().map(|id| &**id),
+        other.0.build.iter().map(|id| &**id),
+      )
+    })
+  }
+}
+
+/// Compares build metadata the way semver compares pre-release identifiers:
+/// numeric identifiers numerically and before alphanumeric ones, which are
+/// compared lexically. No build metadata comes before any build metadata.
+fn compare_build_identifiers<'a>(
+  a: impl ExactSizeIterator<Item = &'a str>,
+  b: impl ExactSizeIterator<Item = &'a str>,
+) -> std::cmp::Ordering {
+  use std::cmp::Ordering;
+
+  let (a_len, b_len) = (a.len(), b.len());
+  for (a, b) in a.zip(b) {
+    let ordering = match (a.parse::<u64>(), b.parse::<u64>()) {
+      (Ok(a), Ok(b)) => a.cmp(&b),
+      (Ok(_), Err(_)) => Ordering::Less,
+      (Err(_), Ok(_)) => Ordering::Greater,
+      (Err(_), Err(_)) => a.cmp(b),
+    };
+    if ordering != Ordering::Equal {
+      return ordering;
+    }
+  }
+  a_len.cmp(&b_len)
+}
+
 impl TryFrom<&str> for Version {
   type Error = VersionValidateError;
   fn try_from(value: &str) -> Result<Self, Self::Error> {
@@ -1074,6 +1176,35 @@ mod tests {
     assert!(PackageName::try_from("foo@").is_err());
   }
 
+  #[test]
+  fn test_name_normalization() {
+    // fullwidth letters are folded into ASCII
+    assert_eq!(ScopeName::try_from("ｆｏｏ").unwrap().as_str(), "foo");
+    assert_eq!(PackageName::try_from("ｂａｒ").unwrap().as_str(), "bar");
+    // lookalikes from other scripts are still rejected
+    assert!(ScopeName::try_from("\u{0430}bc").is_err());
+    assert!(PackageName::try_from("f\u{043e}o").is_err());
+  }
+
+  #[test]
+  fn test_confusable_skeleton() {
+    let skeleton =
+      |name: &str| ScopeName::try_from(name).unwrap().confusable_skeleton();
+    assert_eq!(skeleton("moment"), skeleton("rnoment"));
+    assert_eq!(skeleton("lodash"), skeleton("l0dash"));
+    assert_eq!(skeleton("lodash"), skeleton("1odash"));
+    assert_eq!(skeleton("web"), skeleton("vveb"));
+    assert_eq!(skeleton("deno"), skeleton("cleno"));
+    assert_eq!(skeleton("foo-bar"), skeleton("foobar"));
+    assert_ne!(skeleton("foo"), skeleton("bar"));
+    assert_ne!(skeleton("react"), skeleton("preact"));
+
+    let skeleton =
+      |name: &str| PackageName::try_from(name).unwrap().confusable_skeleton();
+    assert_eq!(skeleton("std"), "std");
+    assert_eq!(skeleton("r-n1"), "ml");
+  }
+
   #[test]
   fn test_version() {
     // Test valid versions
@@ -1093,6 +1224,45 @@ mod tests {
     assert!(Version::new("v1.2.3 ").is_err());
   }
 
+  #[test]
+  fn test_version_order() {
+    let version = |v: &str| Version::new(v).unwrap();
+    let mut versions = [
+      "1.0.0+build.10",
+      "1.0.0",
+      "1.0.0-beta.11",
+      "1.0.0+build.9",
+      "1.0.0-rc.1",
+      "1.0.0-beta.2",
+      "0.10.0",
+      "1.0.0+build.9.a",
+      "0.9.0",
+      "1.0.0-alpha",
+    ]
+    .map(version);
+    versions.sort();
+    assert_eq!(
+      versions.map(|v| v.to_string()),
+      [
+        "0.9.0",
+        "0.10.0",
+        "1.0.0-alpha",
+        "1.0.0-beta.2",
+        "1.0.0-beta.11",
+        "1.0.0-rc.1",
+        "1.0.0",
+        "1.0.0+build.9",
+        "1.0.0+build.9.a",
+        "1.0.0+build.10",
+      ]
+    );
+
+    assert!(!version("1.0.0").is_prerelease());
+    assert!(!version("1.0.0+build-1").is_prerelease());
+    assert!(version("1.0.0-beta.1").is_prerelease());
+    assert!(version("1.0.0-beta+build").is_prerelease());
+  }
+
   #[test]
   fn test_scoped_package_name() {
     // Test valid scoped package names
@@ -233,7 +233,17 @@ pub struct NewPublishingTask<'s> {
   pub user_id: Option<Uuid>,
 }
 
-#[derive(Debug)]
+/// A publish of several packages of a workspace at once. Its publishing tasks
+/// are processed together, and either all of them create their package
+/// version or none does.
+#[derive(Debug, Clone)]
+pub struct WorkspacePublish {
+  pub id: Uuid,
+  pub user_id: Option<Uuid>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Scope {
   pub scope: ScopeName,
   pub description: ScopeDescription,
@@ -243,6 +253,9 @@ pub struct Scope {
   pub package_limit: i32,
   pub new_package_per_week_limit: i32,
   pub publish_attempts_per_week_limit: i32,
+  /// The most bytes the files of all package versions in the scope may take
+  /// up.
+  pub storage_limit: i64,
   pub verify_oidc_actor: bool,
   pub require_publishing_from_ci: bool,
 }
@@ -287,6 +300,11 @@ impl FromRow<'_, sqlx::postgres::PgRow> for Scope {
         "publish_attempts_per_week_limit",
         "scope_publish_attempts_per_week_limit",
       )?,
+      storage_limit: try_get_row_or::<i64>(
+        row,
+        "storage_limit",
+        "scope_storage_limit",
+      )?,
       verify_oidc_actor: try_get_row_or(
         row,
         "verify_oidc_actor",
@@ -306,6 +324,8 @@ pub struct ScopeUsage {
   pub package: i32,
   pub new_package_per_week: i32,
   pub publish_attempts_per_week: i32,
+  /// The total size in bytes of the files of all package versions.
+  pub storage: i64,
 }
 
 #[cfg(feature = "sqlx")]
@@ -329,6 +349,7 @@ impl FromRow<'_, sqlx::postgres::PgRow> for ScopeUsage {
       )?
       .try_into()
       .unwrap(),
+      storage: try_get_row_or::<i64>(row, "storage", "usage_storage")?,
     })
   }
 }
@@ -365,7 +386,79 @@ pub struct NewScopeInvite<'s> {
   pub scope: &'s ScopeName,
 }
 
-#[derive(Debug)]
+/// A named group of members of a scope, that can be granted permissions on
+/// packages of the scope.
+#[derive(Debug, Clone)]
+pub struct ScopeTeam {
+  pub scope: ScopeName,
+  pub name: String,
+  pub description: String,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScopeTeamMember {
+  pub scope: ScopeName,
+  pub team: String,
+  pub user_id: Uuid,
+  pub created_at: DateTime<Utc>,
+}
+
+/// The permissions a team grants its members on a package.
+#[derive(Debug, Clone)]
+pub struct ScopeTeamPackage {
+  pub scope: ScopeName,
+  pub team: String,
+  pub package: PackageName,
+  pub can_publish: bool,
+  pub can_yank: bool,
+  pub can_manage_tokens: bool,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PackagePermission {
+  /// Publish new versions, and move dist tags.
+  Publish,
+  /// Yank and unyank versions.
+  Yank,
+  /// Link the GitHub repository that may publish the package, and create
+  /// access tokens that publish it.
+  ManageTokens,
+}
+
+impl std::fmt::Display for PackagePermission {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      PackagePermission::Publish => write!(f, "publish"),
+      PackagePermission::Yank => write!(f, "yank"),
+      PackagePermission::ManageTokens => write!(f, "manage_tokens"),
+    }
+  }
+}
+
+/// The permissions the teams of a member grant on a package, combined.
+#[derive(Debug, Clone, Copy)]
+pub struct PackageTeamAccess {
+  pub can_publish: bool,
+  pub can_yank: bool,
+  pub can_manage_tokens: bool,
+}
+
+impl PackageTeamAccess {
+  pub fn allows(&self, permission: PackagePermission) -> bool {
+    match permission {
+      PackagePermission::Publish => self.can_publish,
+      PackagePermission::Yank => self.can_yank,
+      PackagePermission::ManageTokens => self.can_manage_tokens,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Package {
   pub scope: ScopeName,
   pub name: PackageName,
@@ -379,6 +472,49 @@ pub struct Package {
   pub when_featured: Option<DateTime<Utc>>,
   pub is_archived: bool,
   pub readme_source: ReadmeSource,
+  pub localized_descriptions: LocalizedDescriptions,
+  /// Private packages are only visible to members of their scope.
+  pub visibility: PackageVisibility,
+}
+
+/// Translations of a package description, keyed by lowercase BCP 47 language
+/// tag (e.g. `de`, `pt-br`). The untranslated `description` is the fallback.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LocalizedDescriptions(pub IndexMap<String, String>);
+
+#[cfg(feature = "sqlx")]
+impl sqlx::Decode<'_, sqlx::Postgres> for LocalizedDescriptions {
+  fn decode(
+    value: sqlx::postgres::PgValueRef<'_>,
+  ) -> Result<Self, Box<dyn std::error::Error + 'static + Send + Sync>> {
+    if !value.is_null() {
+      let s: sqlx::types::Json<LocalizedDescriptions> =
+        sqlx::Decode::<'_, sqlx::Postgres>::decode(value)?;
+      Ok(s.0)
+    } else {
+      Ok(Default::default())
+    }
+  }
+}
+
+#[cfg(feature = "sqlx")]
+impl<'q> sqlx::Encode<'q, sqlx::Postgres> for LocalizedDescriptions {
+  fn encode_by_ref(
+    &self,
+    buf: &mut <sqlx::Postgres as Database>::ArgumentBuffer<'q>,
+  ) -> Result<IsNull, BoxDynError> {
+    <sqlx::types::Json<&LocalizedDescriptions> as sqlx::Encode<
+      '_,
+      sqlx::Postgres,
+    >>::encode_by_ref(&Json(self), buf)
+  }
+}
+
+#[cfg(feature = "sqlx")]
+impl sqlx::Type<sqlx::Postgres> for LocalizedDescriptions {
+  fn type_info() -> <sqlx::Postgres as sqlx::Database>::TypeInfo {
+    <sqlx::types::Json<LocalizedDescriptions> as sqlx::Type<sqlx::Postgres>>::type_info()
+  }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -393,6 +529,18 @@ pub enum ReadmeSource {
   JSDoc,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[cfg_attr(
+  feature = "sqlx",
+  sqlx(type_name = "package_visibility", rename_all = "lowercase")
+)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageVisibility {
+  Public,
+  Private,
+}
+
 #[cfg(feature = "sqlx")]
 impl FromRow<'_, sqlx::postgres::PgRow> for Package {
   fn from_row(row: &sqlx::postgres::PgRow) -> Result<Self, sqlx::Error> {
@@ -433,11 +581,17 @@ impl FromRow<'_, sqlx::postgres::PgRow> for Package {
         "readme_source",
         "package_readme_source",
       )?,
+      localized_descriptions: try_get_row_or(
+        row,
+        "localized_descriptions",
+        "package_localized_descriptions",
+      )?,
+      visibility: try_get_row_or(row, "visibility", "package_visibility")?,
     })
   }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct PackageVersion {
   pub scope: ScopeName,
   pub name: PackageName,
@@ -445,6 +599,8 @@ pub struct PackageVersion {
   pub user_id: Option<Uuid>,
   pub exports: ExportsMap,
   pub is_yanked: bool,
+  pub yanked_at: Option<DateTime<Utc>>,
+  pub yank_reason: Option<String>,
   pub readme_path: Option<PackagePath>,
   pub uses_npm: bool,
   pub meta: PackageVersionMeta,
@@ -462,6 +618,8 @@ pub struct PackageVersionWithNewerVersionsCount {
   pub user_id: Option<Uuid>,
   pub exports: ExportsMap,
   pub is_yanked: bool,
+  pub yanked_at: Option<DateTime<Utc>>,
+  pub yank_reason: Option<String>,
   pub readme_path: Option<PackagePath>,
   pub uses_npm: bool,
   pub newer_versions_count: i64,
@@ -489,6 +647,7 @@ pub struct NewPackageVersion<'s> {
 pub struct PackageVersionForResolution {
   pub version: Version,
   pub exports: ExportsMap,
+  pub is_yanked: bool,
 }
 
 #[derive(Debug)]
@@ -498,14 +657,65 @@ pub struct PackageVersionForMetadata {
   pub created_at: DateTime<Utc>,
 }
 
+/// A named release channel of a package, like `beta`, pointing at a version.
+#[derive(Debug, Clone)]
+pub struct PackageDistTag {
+  pub scope: ScopeName,
+  pub name: PackageName,
+  pub tag: String,
+  pub version: Version,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+/// A pending request to move a package to another scope.
+#[derive(Debug, Clone)]
+pub struct PackageTransfer {
+  pub scope: ScopeName,
+  pub name: PackageName,
+  pub target_scope: ScopeName,
+  pub requested_by: Uuid,
+  pub created_at: DateTime<Utc>,
+}
+
+/// Where a package that was moved to another scope lives now.
+#[derive(Debug, Clone)]
+pub struct PackageRedirect {
+  pub scope: ScopeName,
+  pub name: PackageName,
+  pub target_scope: ScopeName,
+  pub target_name: PackageName,
+  pub created_at: DateTime<Utc>,
+}
+
+/// A pending request to rename a scope, which staff approve.
+#[derive(Debug, Clone)]
+pub struct ScopeRename {
+  pub scope: ScopeName,
+  pub target_scope: ScopeName,
+  pub requested_by: Uuid,
+  pub created_at: DateTime<Utc>,
+}
+
+/// The name that a renamed scope has now.
+#[derive(Debug, Clone)]
+pub struct ScopeRedirect {
+  pub scope: ScopeName,
+  pub target_scope: ScopeName,
+  pub created_at: DateTime<Utc>,
+}
+
 #[derive(Debug)]
 pub struct PackageVersionForNpmVersionManifest {
   pub version: Version,
   pub is_yanked: bool,
+  pub yank_reason: Option<String>,
   pub created_at: DateTime<Utc>,
   pub npm_tarball_revision: i32,
   pub npm_tarball_sha1: String,
   pub npm_tarball_sha512: String,
+  pub npm_tarball_rekor_log_id: Option<String>,
+  pub meta: PackageVersionMeta,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -517,6 +727,135 @@ pub struct PackageVersionMeta {
   pub percentage_documented_symbols: f32,
   pub all_fast_check: bool, // mean no slow types
   pub has_provenance: bool,
+  /// Whether the package has examples, and all of them passed the example
+  /// check at publish time: they parse, their imports resolve and they only
+  /// import names that the package exports. They are not type-checked.
+  pub all_examples_resolve: bool,
+  /// Whether the package ships test files or a coverage summary.
+  pub has_tests: bool,
+  /// Per-factor details captured at publish time, used to explain the score.
+  pub score_details: PackageVersionScoreDetails,
+  /// Whether the npm tarball includes a CommonJS build, as requested with the
+  /// `npm.cjs` field of the config file.
+  pub npm_cjs: bool,
+  /// Whether the declarations of every entrypoint are rolled up into a single
+  /// file in the npm tarball, as requested with the `npm.dtsRollup` field of
+  /// the config file.
+  pub npm_dts_rollup: bool,
+  /// The `jsr:` and `npm:` specifiers from the `peerDependencies` field of the
+  /// config file.
+  pub npm_peer_dependencies: Vec<String>,
+  /// The `bin` field of the config file, mapping command names to the
+  /// entrypoint they run.
+  pub npm_bin: IndexMap<String, String>,
+  /// The subpath pattern entries (`./features/*`) of the `exports` field of
+  /// the config file. The exports of the version list every entrypoint they
+  /// expand to, while the npm tarball exports the patterns themselves.
+  pub export_patterns: IndexMap<String, String>,
+  /// The `npm.exclude` field of the config file: patterns of files that are
+  /// left out of the npm tarball.
+  pub npm_exclude: Vec<String>,
+  /// Whether the doc search index of this version left out symbols, or cut
+  /// off their docs, because the package exceeded the search index limits.
+  pub search_index_truncated: bool,
+  /// Entrypoints whose docs are missing from this version, because
+  /// generating them failed at publish time.
+  pub degraded_docs_entrypoints: Vec<DegradedDocsEntrypoint>,
+  /// The version of the [ScoreSchema] that was active when this version was
+  /// published. `0` for versions published before schemas were recorded,
+  /// which were scored with the weights of schema version 1.
+  pub score_schema_version: i32,
+}
+
+/// An entrypoint whose docs were left out of a package version.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DegradedDocsEntrypoint {
+  /// The path of the module of the entrypoint, e.g. `/utils.ts`.
+  pub path: String,
+  /// Why the docs of the entrypoint were left out.
+  pub reason: String,
+}
+
+/// The raw inputs behind the boolean score factors in [PackageVersionMeta].
+/// Versions published before this was recorded have all fields empty.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PackageVersionScoreDetails {
+  /// Paths of entrypoints that do not have a module doc.
+  pub entrypoints_without_module_doc: Vec<String>,
+  /// Paths of entrypoints that could not be fast checked (have slow types).
+  pub entrypoints_with_slow_types: Vec<String>,
+  pub documented_symbols: u32,
+  pub total_symbols: u32,
+  /// Number of readme and `@example` code blocks that were checked.
+  pub examples_checked: u32,
+  /// Where each failing example comes from, and why it failed.
+  pub failing_examples: Vec<String>,
+  /// Number of published test files.
+  pub test_files: u32,
+  /// The coverage summary attached by the publisher, if any.
+  pub coverage: Option<CoverageSummary>,
+  /// Documentation coverage of each export, in the order of the exports map.
+  pub entrypoint_docs: Vec<EntrypointDocsCoverage>,
+  /// Structure of the readme, if the package has one.
+  pub readme: Option<ReadmeQuality>,
+}
+
+/// Structure and quality signals parsed from the readme of a package version.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ReadmeQuality {
+  /// The headings of the readme, in document order.
+  pub headings: Vec<ReadmeHeading>,
+  /// Number of fenced or indented code blocks.
+  pub code_blocks: u32,
+  /// Whether the readme shows how to add or import the package, e.g. with
+  /// `deno add` or a `jsr:` specifier.
+  pub has_install_instructions: bool,
+  pub links: u32,
+  /// Relative links that do not point to a file in the package, and anchor
+  /// links that do not point to a heading of the readme.
+  pub broken_links: Vec<String>,
+  pub images: u32,
+  pub images_without_alt: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadmeHeading {
+  pub level: u8,
+  pub text: String,
+}
+
+/// How well a single export of a package version is documented.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct EntrypointDocsCoverage {
+  /// The export key, e.g. `.` or `./utils`.
+  pub export: String,
+  /// The path of the module the export points to, e.g. `/utils.ts`.
+  pub path: String,
+  pub has_module_doc: bool,
+  pub documented_symbols: u32,
+  pub total_symbols: u32,
+}
+
+/// A test coverage summary attached to a package version through the
+/// `coverage` field of the config file.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverageSummary {
+  pub lines: CoverageMetric,
+  pub functions: Option<CoverageMetric>,
+  pub branches: Option<CoverageMetric>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverageMetric {
+  pub total: u32,
+  pub covered: u32,
 }
 
 #[cfg(feature = "sqlx")]
@@ -547,11 +886,631 @@ impl<'q> sqlx::Encode<'q, sqlx::Postgres> for PackageVersionMeta {
   }
 }
 
-#[cfg(feature = "sqlx")]
-impl sqlx::Type<sqlx::Postgres> for PackageVersionMeta {
-  fn type_info() -> <sqlx::Postgres as sqlx::Database>::TypeInfo {
-    <sqlx::types::Json<PackageVersionMeta> as sqlx::Type<sqlx::Postgres>>::type_info()
-  }
+#[cfg(feature = "sqlx")]
+impl sqlx::Type<sqlx::Postgres> for PackageVersionMeta {
+  fn type_info() -> <sqlx::Postgres as sqlx::Database>::TypeInfo {
+    <sqlx::types::Json<PackageVersionMeta> as sqlx::Type<sqlx::Postgres>>::type_info()
+  }
+}
+
+/// The number of points each score factor contributes to the total score.
+/// The defaults are the weights of the latest built-in schema.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ScoreWeights {
+  pub has_readme: u32,
+  pub has_readme_examples: u32,
+  pub all_entrypoints_docs: u32,
+  pub percentage_documented_symbols: u32,
+  pub all_fast_check: u32,
+  pub has_provenance: u32,
+  pub has_description: u32,
+  pub at_least_one_runtime_compatible: u32,
+  pub multiple_runtimes_compatible: u32,
+  pub all_examples_resolve: u32,
+  pub has_tests: u32,
+}
+
+impl Default for ScoreWeights {
+  fn default() -> Self {
+    Self {
+      has_readme: 2,
+      has_readme_examples: 1,
+      all_entrypoints_docs: 1,
+      percentage_documented_symbols: 5,
+      all_fast_check: 5,
+      has_provenance: 1,
+      has_description: 1,
+      at_least_one_runtime_compatible: 1,
+      multiple_runtimes_compatible: 1,
+      all_examples_resolve: 1,
+      has_tests: 1,
+    }
+  }
+}
+
+#[cfg(feature = "sqlx")]
+impl sqlx::Decode<'_, sqlx::Postgres> for ScoreWeights {
+  fn decode(
+    value: sqlx::postgres::PgValueRef<'_>,
+  ) -> Result<Self, Box<dyn std::error::Error + 'static + Send + Sync>> {
+    let s: sqlx::types::Json<ScoreWeights> =
+      sqlx::Decode::<'_, sqlx::Postgres>::decode(value)?;
+    Ok(s.0)
+  }
+}
+
+#[cfg(feature = "sqlx")]
+impl<'q> sqlx::Encode<'q, sqlx::Postgres> for ScoreWeights {
+  fn encode_by_ref(
+    &self,
+    buf: &mut <sqlx::Postgres as Database>::ArgumentBuffer<'q>,
+  ) -> Result<IsNull, BoxDynError> {
+    <sqlx::types::Json<&ScoreWeights> as sqlx::Encode<'_, sqlx::Postgres>>::encode_by_ref(&Json(self), buf)
+  }
+}
+
+#[cfg(feature = "sqlx")]
+impl sqlx::Type<sqlx::Postgres> for ScoreWeights {
+  fn type_info() -> <sqlx::Postgres as sqlx::Database>::TypeInfo {
+    <sqlx::types::Json<ScoreWeights> as sqlx::Type<sqlx::Postgres>>::type_info()
+  }
+}
+
+/// A versioned set of score weights. The schema with the highest version is
+/// the one used to compute scores.
+#[derive(Debug, Clone)]
+pub struct ScoreSchema {
+  pub version: i32,
+  pub weights: ScoreWeights,
+  /// The total that maps to a score of 100%. This may be lower than the sum
+  /// of all weights, in which case some factors act as bonus points.
+  pub max_score: i32,
+  pub created_by: Option<Uuid>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl ScoreSchema {
+  /// The built-in schema, used when the database has no schema yet.
+  pub fn builtin() -> Self {
+    Self {
+      version: 3,
+      weights: ScoreWeights::default(),
+      max_score: 19,
+      created_by: None,
+      created_at: DateTime::<Utc>::MIN_UTC,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(
+  feature = "sqlx",
+  sqlx(type_name = "score_recompute_job_status", rename_all = "lowercase")
+)]
+pub enum ScoreRecomputeJobStatus {
+  Running,
+  Completed,
+  Failed,
+}
+
+/// A job that re-runs score generation for every package version.
+#[derive(Debug, Clone)]
+pub struct ScoreRecomputeJob {
+  pub id: Uuid,
+  pub status: ScoreRecomputeJobStatus,
+  pub total_versions: i32,
+  pub processed_versions: i32,
+  /// Versions whose doc nodes could not be loaded. Their stored score is left
+  /// untouched.
+  pub failed_versions: i32,
+  pub error: Option<String>,
+  pub created_by: Option<Uuid>,
+  pub finished_at: Option<DateTime<Utc>>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(
+  feature = "sqlx",
+  sqlx(type_name = "npm_tarball_rebuild_job_status", rename_all = "lowercase")
+)]
+pub enum NpmTarballRebuildJobStatus {
+  Running,
+  Completed,
+  Failed,
+}
+
+/// A job that builds the npm tarballs for the current revision for all
+/// versions of a package, a scope, or the whole registry.
+#[derive(Debug, Clone)]
+pub struct NpmTarballRebuildJob {
+  pub id: Uuid,
+  pub status: NpmTarballRebuildJobStatus,
+  /// Only versions in this scope are rebuilt. `None` covers the registry.
+  pub scope: Option<ScopeName>,
+  /// Only versions of this package are rebuilt. Always `None` if `scope` is.
+  pub name: Option<PackageName>,
+  /// Rebuild tarballs that already exist, and check that the result is
+  /// byte-identical to them.
+  pub verify: bool,
+  /// Number of versions built at the same time.
+  pub concurrency: i32,
+  pub total_versions: i32,
+  pub processed_versions: i32,
+  /// Versions whose tarball could not be built. Their existing tarballs, if
+  /// any, are left untouched.
+  pub failed_versions: i32,
+  pub error: Option<String>,
+  pub created_by: Option<Uuid>,
+  pub finished_at: Option<DateTime<Utc>>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(
+  feature = "sqlx",
+  sqlx(type_name = "orphan_gc_job_status", rename_all = "lowercase")
+)]
+pub enum OrphanGcJobStatus {
+  Running,
+  Completed,
+  Failed,
+}
+
+/// A job that looks for objects in the buckets that belong to versions or
+/// publishing tasks that don't exist, and deletes them.
+#[derive(Debug, Clone)]
+pub struct OrphanGcJob {
+  pub id: Uuid,
+  pub status: OrphanGcJobStatus,
+  /// Only report orphaned objects, without deleting them.
+  pub dry_run: bool,
+  pub scanned_packages: i32,
+  pub scanned_objects: i32,
+  pub orphaned_objects: i32,
+  pub deleted_objects: i32,
+  pub error: Option<String>,
+  /// `None` for jobs started by the scheduled task.
+  pub created_by: Option<Uuid>,
+  pub finished_at: Option<DateTime<Utc>>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OrphanedObject {
+  pub job_id: Uuid,
+  pub bucket: String,
+  pub path: String,
+  /// Whether the object was deleted. Always `false` in a dry run.
+  pub deleted: bool,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(
+  feature = "sqlx",
+  sqlx(type_name = "integrity_scrub_job_status", rename_all = "lowercase")
+)]
+pub enum IntegrityScrubJobStatus {
+  Running,
+  Completed,
+  Failed,
+}
+
+/// A job that downloads the stored artifacts of all versions of a package, a
+/// scope, or the whole registry, and compares them against their digests.
+#[derive(Debug, Clone)]
+pub struct IntegrityScrubJob {
+  pub id: Uuid,
+  pub status: IntegrityScrubJobStatus,
+  pub scope: Option<ScopeName>,
+  pub name: Option<PackageName>,
+  pub total_versions: i32,
+  pub checked_versions: i32,
+  pub checked_objects: i32,
+  /// Objects that are missing or don't match their digest.
+  pub failed_objects: i32,
+  pub error: Option<String>,
+  pub created_by: Option<Uuid>,
+  pub finished_at: Option<DateTime<Utc>>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IntegrityScrubFailure {
+  pub job_id: Uuid,
+  pub bucket: String,
+  pub path: String,
+  pub expected: String,
+  /// The digest of the stored object, `None` if it is missing.
+  pub actual: Option<String>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(
+  feature = "sqlx",
+  sqlx(type_name = "background_job_kind", rename_all = "snake_case")
+)]
+pub enum BackgroundJobKind {
+  NpmTarballBuild,
+  ScoreRecompute,
+  DataExport,
+  PublishFailureEmail,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(
+  feature = "sqlx",
+  sqlx(type_name = "background_job_status", rename_all = "lowercase")
+)]
+pub enum BackgroundJobStatus {
+  Pending,
+  Running,
+  Completed,
+  /// Failed `max_attempts` times, and is not retried unless an admin does.
+  Dead,
+}
+
+/// A unit of work in the background job queue.
+#[derive(Debug, Clone)]
+pub struct BackgroundJob {
+  pub id: Uuid,
+  pub kind: BackgroundJobKind,
+  pub payload: serde_json::Value,
+  pub status: BackgroundJobStatus,
+  /// Jobs with a higher priority are claimed first.
+  pub priority: i32,
+  pub attempts: i32,
+  pub max_attempts: i32,
+  /// When a pending job is due, or until when a running job is leased.
+  pub run_at: DateTime<Utc>,
+  pub last_error: Option<String>,
+  /// `None` for jobs that were not queued by an admin.
+  pub created_by: Option<Uuid>,
+  pub finished_at: Option<DateTime<Utc>>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct NewBackgroundJob<'s> {
+  pub kind: BackgroundJobKind,
+  pub payload: serde_json::Value,
+  pub priority: i32,
+  pub max_attempts: i32,
+  pub created_by: Option<&'s Uuid>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(
+  feature = "sqlx",
+  sqlx(type_name = "announcement_severity", rename_all = "lowercase")
+)]
+pub enum AnnouncementSeverity {
+  Info,
+  Warning,
+  Critical,
+}
+
+/// A registry-wide announcement written by staff.
+#[derive(Debug, Clone)]
+pub struct Announcement {
+  pub id: Uuid,
+  pub title: String,
+  /// Markdown.
+  pub body: String,
+  pub severity: AnnouncementSeverity,
+  /// The features affected by this announcement, e.g. `publish` or `npm`.
+  pub features: Vec<String>,
+  /// After this point the announcement is no longer shown as active, but it
+  /// stays in the feed.
+  pub expires_at: Option<DateTime<Utc>>,
+  pub created_by: Option<Uuid>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct NewAnnouncement<'s> {
+  pub title: &'s str,
+  pub body: &'s str,
+  pub severity: AnnouncementSeverity,
+  pub features: &'s [String],
+  pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// A feature that can be turned on for everyone, or only for some scopes and
+/// users.
+#[derive(Debug, Clone)]
+pub struct FeatureFlag {
+  pub name: String,
+  pub description: String,
+  /// Whether the flag is on for everyone.
+  pub enabled: bool,
+  /// The scopes the flag is on for, if it is not enabled for everyone.
+  pub scopes: Vec<ScopeName>,
+  /// The users the flag is on for, if it is not enabled for everyone.
+  pub user_ids: Vec<Uuid>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl FeatureFlag {
+  /// Whether the flag is on for an action on `scope` by `user_id`.
+  pub fn is_enabled_for(
+    &self,
+    scope: Option<&ScopeName>,
+    user_id: Option<Uuid>,
+  ) -> bool {
+    self.enabled
+      || scope.is_some_and(|scope| self.scopes.contains(scope))
+      || user_id.is_some_and(|user_id| self.user_ids.contains(&user_id))
+  }
+}
+
+#[derive(Debug)]
+pub struct NewFeatureFlag<'s> {
+  pub name: &'s str,
+  pub description: &'s str,
+  pub enabled: bool,
+  pub scopes: &'s [ScopeName],
+  pub user_ids: &'s [Uuid],
+}
+
+/// The default request rate limit that applies to a principal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+  feature = "sqlx",
+  sqlx(type_name = "rate_limit_tier", rename_all = "snake_case")
+)]
+pub enum RateLimitTier {
+  Anonymous,
+  User,
+  GithubActions,
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitTierLimit {
+  pub tier: RateLimitTier,
+  pub requests_per_minute: i32,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+/// Replaces the tier limit for requests to a scope.
+#[derive(Debug, Clone)]
+pub struct ScopeRateLimit {
+  pub scope: ScopeName,
+  pub requests_per_minute: i32,
+  pub reason: Option<String>,
+  pub created_by: Option<Uuid>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+/// Replaces the registry-wide defaults of the limits that publishes to a
+/// scope are checked against. Limits that are `None` are not overridden.
+#[derive(Debug, Clone)]
+pub struct ScopePublishLimits {
+  pub scope: ScopeName,
+  pub max_tarball_size: Option<i64>,
+  pub max_file_size: Option<i64>,
+  pub max_total_file_size: Option<i64>,
+  pub max_file_count: Option<i32>,
+  pub max_path_depth: Option<i32>,
+  pub max_path_length: Option<i32>,
+  pub max_module_count: Option<i32>,
+  pub reason: Option<String>,
+  pub created_by: Option<Uuid>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct NewScopePublishLimits<'s> {
+  pub max_tarball_size: Option<i64>,
+  pub max_file_size: Option<i64>,
+  pub max_total_file_size: Option<i64>,
+  pub max_file_count: Option<i32>,
+  pub max_path_depth: Option<i32>,
+  pub max_path_length: Option<i32>,
+  pub max_module_count: Option<i32>,
+  pub reason: Option<&'s str>,
+}
+
+/// Replaces the tier limit for requests made with a token.
+#[derive(Debug, Clone)]
+pub struct TokenRateLimit {
+  pub token_id: Uuid,
+  pub requests_per_minute: i32,
+  pub reason: Option<String>,
+  pub created_by: Option<Uuid>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+/// An issuer of OpenID Connect tokens that a scope trusts for publishing, in
+/// addition to the built-in CI providers.
+#[derive(Debug, Clone)]
+pub struct ScopeOidcIssuer {
+  pub id: Uuid,
+  pub scope: ScopeName,
+  pub issuer: String,
+  pub created_at: DateTime<Utc>,
+}
+
+/// A CI project that may publish a package with an OpenID Connect token.
+#[derive(Debug, Clone)]
+pub struct PackageTrustedPublisher {
+  pub id: Uuid,
+  pub scope: ScopeName,
+  pub package: PackageName,
+  pub issuer: String,
+  pub subject: String,
+  pub created_by: Option<Uuid>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(
+  feature = "sqlx",
+  sqlx(type_name = "reserved_name_kind", rename_all = "lowercase")
+)]
+pub enum ReservedNameKind {
+  Scope,
+  Package,
+}
+
+/// A scope or package name that can not be claimed through the API.
+#[derive(Debug, Clone)]
+pub struct ReservedName {
+  pub kind: ReservedNameKind,
+  /// For scopes, the name without hyphens.
+  pub name: String,
+  pub reason: Option<String>,
+  /// After this point the name can be claimed again.
+  pub expires_at: Option<DateTime<Utc>>,
+  pub created_by: Option<Uuid>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct NewReservedName<'s> {
+  pub kind: ReservedNameKind,
+  pub name: &'s str,
+  pub reason: Option<&'s str>,
+  pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+  feature = "sqlx",
+  sqlx(type_name = "webhook_event", rename_all = "snake_case")
+)]
+pub enum WebhookEvent {
+  VersionPublished,
+  VersionYanked,
+  /// A package starts depending on a package of the scope.
+  NewDependent,
+  ScopeMemberAdded,
+  ScopeMemberUpdated,
+  ScopeMemberRemoved,
+  /// Staff quarantined a version of a package of the scope.
+  VersionQuarantined,
+  VersionReleased,
+}
+
+#[cfg(feature = "sqlx")]
+impl sqlx::postgres::PgHasArrayType for WebhookEvent {
+  fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+    sqlx::postgres::PgTypeInfo::with_name("_webhook_event")
+  }
+}
+
+/// A URL a scope admin registered to be notified of events in the scope.
+#[derive(Debug, Clone)]
+pub struct ScopeWebhook {
+  pub id: Uuid,
+  pub scope: ScopeName,
+  pub url: String,
+  /// The key payloads are signed with. Never returned by the API.
+  pub secret: String,
+  pub events: Vec<WebhookEvent>,
+  pub description: String,
+  pub is_active: bool,
+  pub created_by: Option<Uuid>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct NewScopeWebhook<'s> {
+  pub scope: &'s ScopeName,
+  pub url: &'s str,
+  pub secret: &'s str,
+  pub events: &'s [WebhookEvent],
+  pub description: &'s str,
+  pub is_active: bool,
+}
+
+/// Changes to a webhook. Fields that are `None` are left as they are.
+#[derive(Debug, Default)]
+pub struct UpdateScopeWebhook<'s> {
+  pub url: Option<&'s str>,
+  pub secret: Option<&'s str>,
+  pub events: Option<&'s [WebhookEvent]>,
+  pub description: Option<&'s str>,
+  pub is_active: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(
+  feature = "sqlx",
+  sqlx(type_name = "webhook_delivery_status", rename_all = "lowercase")
+)]
+pub enum WebhookDeliveryStatus {
+  Pending,
+  Success,
+  Failure,
+}
+
+/// A payload queued for a webhook, along with the outcome of its last
+/// attempt.
+#[derive(Debug, Clone)]
+pub struct WebhookDelivery {
+  pub id: Uuid,
+  pub webhook_id: Uuid,
+  pub event: WebhookEvent,
+  pub payload: serde_json::Value,
+  pub status: WebhookDeliveryStatus,
+  pub attempts: i32,
+  pub next_attempt_at: DateTime<Utc>,
+  pub last_response_status: Option<i32>,
+  pub last_error: Option<String>,
+  pub delivered_at: Option<DateTime<Utc>>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+/// A delivery claimed by the delivery task, with where to send it.
+#[derive(Debug, Clone)]
+pub struct PendingWebhookDelivery {
+  pub id: Uuid,
+  pub webhook_id: Uuid,
+  pub event: WebhookEvent,
+  pub payload: serde_json::Value,
+  pub attempts: i32,
+  pub url: String,
+  pub secret: String,
 }
 
 #[derive(Debug)]
@@ -576,6 +1535,45 @@ pub struct NewPackageFile<'s> {
   pub checksum: Option<&'s str>,
 }
 
+/// An artifact generated from the documentation of a package version, stored
+/// in the docs bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(
+  feature = "sqlx",
+  sqlx(type_name = "doc_artifact_kind", rename_all = "snake_case")
+)]
+pub enum DocArtifactKind {
+  DocNodes,
+  Markdown,
+  TypeGraph,
+}
+
+/// The digest of a doc artifact, recorded when it was written.
+#[derive(Debug, Clone)]
+pub struct PackageVersionDocArtifact {
+  pub scope: ScopeName,
+  pub name: PackageName,
+  pub version: Version,
+  pub kind: DocArtifactKind,
+  /// The SHA-256 digest of the stored object, as `sha256-<hex>`.
+  pub checksum: String,
+  pub size: i64,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct NewPackageVersionDocArtifact<'s> {
+  pub scope: &'s ScopeName,
+  pub name: &'s PackageName,
+  pub version: &'s Version,
+  pub kind: DocArtifactKind,
+  pub checksum: &'s str,
+  pub size: i64,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
 #[cfg_attr(
@@ -701,6 +1699,11 @@ pub struct Token {
   /// `None` means the token has no permissions policy, which is equivalent to
   /// the token having all permissions.
   pub permissions: Option<Permissions>,
+  /// When the token was last used to authenticate a request. This is only
+  /// updated at most once a minute.
+  pub last_used_at: Option<DateTime<Utc>>,
+  /// When the secret of the token was last replaced.
+  pub rotated_at: Option<DateTime<Utc>>,
   pub updated_at: DateTime<Utc>,
   pub created_at: DateTime<Utc>,
 }
@@ -708,6 +1711,9 @@ pub struct Token {
 #[derive(Debug, Clone)]
 pub struct NewToken {
   pub hash: String,
+  /// The SHA-256 hash of the secret, which tokens are looked up by before the
+  /// secret is checked against `hash`.
+  pub lookup_hash: String,
   pub user_id: Uuid,
   pub r#type: TokenType,
   pub description: Option<String>,
@@ -718,11 +1724,27 @@ pub struct NewToken {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Permissions(pub Vec<Permission>);
 
+impl Permissions {
+  /// Whether these permissions only allow reading, and never changing,
+  /// anything the principal has access to.
+  pub fn is_read_only(&self) -> bool {
+    !self.0.is_empty()
+      && self
+        .0
+        .iter()
+        .all(|permission| matches!(permission, Permission::Read))
+  }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(tag = "permission")]
 pub enum Permission {
   #[serde(rename = "package/publish")]
   PackagePublish(PackagePublishPermission),
+  /// Read access to everything the principal has access to. Requests that
+  /// would change anything are rejected.
+  #[serde(rename = "read")]
+  Read,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -775,7 +1797,7 @@ impl sqlx::Type<sqlx::Postgres> for Permissions {
   }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct GithubRepository {
   pub id: i64,
   pub owner: String,
@@ -837,7 +1859,7 @@ pub struct NewAuthorization<'s> {
   pub expires_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExportsMap(IndexMap<String, String>);
 
 impl ExportsMap {
@@ -923,6 +1945,9 @@ pub struct PackageVersionDependency {
   pub dependency_name: String,
   pub dependency_constraint: String,
   pub dependency_path: String,
+  /// Whether the dependency is only imported through the optional dependency
+  /// pattern, so the package works without it.
+  pub is_optional: bool,
   pub updated_at: DateTime<Utc>,
   pub created_at: DateTime<Utc>,
 }
@@ -952,6 +1977,89 @@ pub struct NewPackageVersionDependency<'s> {
   pub dependency_name: &'s str,
   pub dependency_constraint: &'s str,
   pub dependency_path: &'s str,
+  pub is_optional: bool,
+}
+
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct PackageVersionDeprecation {
+  pub scope: ScopeName,
+  pub name: PackageName,
+  pub version: Version,
+  pub export: String,
+  pub symbol: String,
+  pub message: Option<String>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewPackageVersionDeprecation<'s> {
+  pub scope: &'s ScopeName,
+  pub name: &'s PackageName,
+  pub version: &'s Version,
+  pub export: &'s str,
+  pub symbol: &'s str,
+  pub message: Option<&'s str>,
+}
+
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct PackageVersionSymbol {
+  pub scope: ScopeName,
+  pub name: PackageName,
+  pub version: Version,
+  pub export: String,
+  pub symbol: String,
+  pub kind: String,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewPackageVersionSymbol<'s> {
+  pub scope: &'s ScopeName,
+  pub name: &'s PackageName,
+  pub version: &'s Version,
+  pub export: &'s str,
+  pub symbol: &'s str,
+  pub kind: &'s str,
+}
+
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct PackageVersionDocLink {
+  pub scope: ScopeName,
+  pub name: PackageName,
+  pub version: Version,
+  pub identifier: String,
+  pub path: String,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewPackageVersionDocLink<'s> {
+  pub scope: &'s ScopeName,
+  pub name: &'s PackageName,
+  pub version: &'s Version,
+  pub identifier: &'s str,
+  pub path: &'s str,
+}
+
+/// Markdown extensions a scope allows when rendering markdown through the
+/// render endpoint.
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct ScopeMarkdownSettings {
+  pub scope: ScopeName,
+  /// Whether raw HTML is kept, cleaned to the HTML subset of package docs,
+  /// instead of being escaped.
+  pub allow_html: bool,
+  /// Additional alert kinds, e.g. `danger` for `> [!DANGER]`.
+  pub admonitions: Vec<String>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
 }
 
 pub type PackageWithGitHubRepoAndMeta =
@@ -963,6 +2071,14 @@ pub struct StatsPackage {
   pub name: PackageName,
 }
 
+/// A package and how often it was downloaded over some period.
+#[derive(Debug)]
+pub struct StatsDownloadedPackage {
+  pub scope: ScopeName,
+  pub name: PackageName,
+  pub downloads: i64,
+}
+
 #[derive(Debug)]
 pub struct StatsPackageVersion {
   pub scope: ScopeName,
@@ -979,7 +2095,13 @@ pub struct NpmTarball {
   pub revision: i32,
   pub sha1: String,
   pub sha512: String,
+  /// The SHA-256 digest of the tarball, as `sha256-<hex>`. `None` for
+  /// tarballs built before digests were recorded.
+  pub sha256: Option<String>,
   pub size: i32,
+  /// The transparency log entry of the publish attestation signed for this
+  /// tarball, if any.
+  pub rekor_log_id: Option<String>,
   pub updated_at: DateTime<Utc>,
   pub created_at: DateTime<Utc>,
 }
@@ -992,11 +2114,30 @@ pub struct NewNpmTarball<'s> {
   pub revision: i32,
   pub sha1: &'s str,
   pub sha512: &'s str,
+  pub sha256: &'s str,
   pub size: i32,
 }
 
+/// Everything that is stored when a processed publishing task creates its
+/// package version.
+#[derive(Debug)]
+pub struct NewPublishedVersion<'s> {
+  pub publishing_task_id: Uuid,
+  pub package_version: NewPackageVersion<'s>,
+  pub files: &'s [NewPackageFile<'s>],
+  pub dependencies: &'s [NewPackageVersionDependency<'s>],
+  pub deprecations: &'s [NewPackageVersionDeprecation<'s>],
+  pub symbols: &'s [NewPackageVersionSymbol<'s>],
+  pub doc_links: &'s [NewPackageVersionDocLink<'s>],
+  pub doc_artifacts: &'s [NewPackageVersionDocArtifact<'s>],
+  pub npm_tarball: NewNpmTarball<'s>,
+  /// What the malware scan found, if it flagged the version. The version is
+  /// then reported and quarantined.
+  pub malware_scan_findings: Option<&'s str>,
+}
+
 /// Keys reference https://runtime-keys.proposal.wintercg.org/.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RuntimeCompat {
   #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -1043,6 +2184,34 @@ impl sqlx::Type<sqlx::Postgres> for RuntimeCompat {
   }
 }
 
+/// Narrows down a package search beyond the search query.
+#[derive(Debug, Clone, Default)]
+pub struct PackageSearchFilters {
+  /// Only packages that declare compatibility with every runtime that is set
+  /// to `Some(true)` here.
+  pub runtime_compat: RuntimeCompat,
+  /// Only packages whose latest version does, or does not, have provenance.
+  pub has_provenance: Option<bool>,
+  /// Only packages whose latest version has this license, ignoring case.
+  pub license: Option<String>,
+  /// Only packages with at least this score, in percent.
+  pub min_score: Option<u32>,
+}
+
+/// The number of packages matching a package search for each value of the
+/// search filters.
+#[derive(Debug, Clone, Default)]
+pub struct PackageSearchFacets {
+  pub browser: i64,
+  pub deno: i64,
+  pub node: i64,
+  pub workerd: i64,
+  pub bun: i64,
+  pub has_provenance: i64,
+  /// The most common licenses, most common first.
+  pub licenses: Vec<(String, i64)>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct VersionDownloadCount {
@@ -1054,6 +2223,27 @@ pub struct VersionDownloadCount {
   pub count: i64,
 }
 
+/// Fetches of a file of a package version, as scraped from the analytics
+/// engine. Only fetches of entrypoints are kept.
+#[derive(Debug, Clone)]
+pub struct ModuleDownloadCount {
+  pub scope: ScopeName,
+  pub package: PackageName,
+  pub version: Version,
+  /// The path of the file in the package, e.g. `/mod.ts`.
+  pub path: String,
+  pub time_bucket: DateTime<Utc>,
+  pub count: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct EntrypointDownloadDataPoint {
+  /// The export key of the entrypoint, e.g. `.` or `./utils`.
+  pub entrypoint: String,
+  pub time_bucket: DateTime<Utc>,
+  pub count: i64,
+}
+
 #[derive(Debug, Clone)]
 pub struct DownloadDataPoint {
   pub time_bucket: DateTime<Utc>,
@@ -1170,3 +2360,250 @@ impl FromRow<'_, sqlx::postgres::PgRow> for AuditLog {
     })
   }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[cfg_attr(
+  feature = "sqlx",
+  sqlx(type_name = "registry_change_kind", rename_all = "snake_case")
+)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistryChangeKind {
+  VersionPublished,
+  VersionYanked,
+  VersionUnyanked,
+  VersionDeleted,
+  PackageUpdated,
+  PackageDeleted,
+}
+
+/// An entry in the append-only change log that mirrors follow. `seq` is
+/// strictly increasing and is used as the cursor.
+#[derive(Debug, Clone)]
+pub struct RegistryChange {
+  pub seq: i64,
+  pub kind: RegistryChangeKind,
+  pub scope: ScopeName,
+  pub name: PackageName,
+  /// Set for changes to a single version.
+  pub version: Option<Version>,
+  pub created_at: DateTime<Utc>,
+}
+
+/// A resumable upload of a publish tarball, which is uploaded in chunks and
+/// turned into a publishing task once it is committed.
+#[derive(Debug, Clone)]
+pub struct PublishUpload {
+  pub id: Uuid,
+  pub user_id: Option<Uuid>,
+  pub package_scope: ScopeName,
+  pub package_name: PackageName,
+  pub package_version: Version,
+  pub config_file: PackagePath,
+  /// The hashes of the uploaded chunks, in order.
+  pub chunks: Vec<String>,
+  /// The number of bytes uploaded so far.
+  pub size: i64,
+  pub expires_at: DateTime<Utc>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+pub struct NewPublishUpload<'s> {
+  pub user_id: Option<Uuid>,
+  pub package_scope: &'s ScopeName,
+  pub package_name: &'s PackageName,
+  pub package_version: &'s Version,
+  pub config_file: &'s PackagePath,
+  pub expires_at: DateTime<Utc>,
+}
+
+/// A deleted package or package version, which can be restored until it is
+/// purged.
+#[derive(Debug, Clone)]
+pub struct Tombstone {
+  pub scope: ScopeName,
+  pub name: PackageName,
+  /// The deleted version, or `None` if the package itself was deleted.
+  pub version: Option<Version>,
+  pub deleted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(
+  feature = "sqlx",
+  sqlx(type_name = "data_export_status", rename_all = "lowercase")
+)]
+pub enum DataExportStatus {
+  Pending,
+  Completed,
+  Failed,
+  /// The archive was deleted after the retention window.
+  Expired,
+}
+
+/// An archive of all data about a user, requested by that user.
+#[derive(Debug, Clone)]
+pub struct DataExport {
+  pub id: Uuid,
+  pub user_id: Uuid,
+  pub status: DataExportStatus,
+  /// The size of the archive in bytes, once it is completed.
+  pub size: Option<i64>,
+  pub error: Option<String>,
+  pub completed_at: Option<DateTime<Utc>>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(
+  feature = "sqlx",
+  sqlx(type_name = "scheduled_job_status", rename_all = "lowercase")
+)]
+pub enum ScheduledJobStatus {
+  Succeeded,
+  Failed,
+}
+
+/// A periodic task run by the scheduler built into the API.
+#[derive(Debug, Clone)]
+pub struct ScheduledJob {
+  pub name: String,
+  /// The cron expression the job is scheduled with.
+  pub schedule: String,
+  pub next_run_at: DateTime<Utc>,
+  /// The current run, if the job is running.
+  pub run_id: Option<Uuid>,
+  pub running_since: Option<DateTime<Utc>>,
+  pub lease_expires_at: Option<DateTime<Utc>>,
+  pub last_started_at: Option<DateTime<Utc>>,
+  pub last_finished_at: Option<DateTime<Utc>>,
+  pub last_status: Option<ScheduledJobStatus>,
+  pub last_error: Option<String>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+/// Which notification emails a user wants to receive.
+#[derive(Debug, Clone)]
+pub struct NotificationPreferences {
+  pub user_id: Uuid,
+  pub scope_invites: bool,
+  pub publish_failures: bool,
+  pub scope_digests: bool,
+  /// Identifies the user in unsubscribe links, which work without signing in.
+  pub unsubscribe_token: Uuid,
+  pub last_digest_sent_at: Option<DateTime<Utc>>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+/// A version published to one of the scopes of a user, as listed in the
+/// weekly scope digest.
+#[derive(Debug, Clone)]
+pub struct ScopeDigestVersion {
+  pub scope: ScopeName,
+  pub name: PackageName,
+  pub version: Version,
+  pub created_at: DateTime<Utc>,
+}
+
+/// A user that is due to receive the weekly scope digest.
+#[derive(Debug, Clone)]
+pub struct ScopeDigestRecipient {
+  pub user_id: Uuid,
+  pub name: String,
+  pub email: String,
+  pub unsubscribe_token: Uuid,
+  pub last_digest_sent_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(
+  feature = "sqlx",
+  sqlx(type_name = "abuse_report_reason", rename_all = "lowercase")
+)]
+pub enum AbuseReportReason {
+  Malware,
+  Infringement,
+  Spam,
+  Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(
+  feature = "sqlx",
+  sqlx(type_name = "abuse_report_status", rename_all = "snake_case")
+)]
+pub enum AbuseReportStatus {
+  Reported,
+  UnderReview,
+  Actioned,
+  Dismissed,
+}
+
+impl AbuseReportStatus {
+  /// The name of the status in the API.
+  pub fn as_str(self) -> &'static str {
+    match self {
+      AbuseReportStatus::Reported => "reported",
+      AbuseReportStatus::UnderReview => "underReview",
+      AbuseReportStatus::Actioned => "actioned",
+      AbuseReportStatus::Dismissed => "dismissed",
+    }
+  }
+
+  /// Reports are reviewed before they are actioned, and can be dismissed at
+  /// any point before that. Actioned and dismissed reports are final.
+  pub fn can_transition_to(self, to: AbuseReportStatus) -> bool {
+    matches!(
+      (self, to),
+      (AbuseReportStatus::Reported, AbuseReportStatus::UnderReview)
+        | (AbuseReportStatus::Reported, AbuseReportStatus::Dismissed)
+        | (AbuseReportStatus::UnderReview, AbuseReportStatus::Actioned)
+        | (AbuseReportStatus::UnderReview, AbuseReportStatus::Dismissed)
+    )
+  }
+}
+
+/// A report of a malicious or infringing package, or version of a package.
+#[derive(Debug, Clone)]
+pub struct AbuseReport {
+  pub id: Uuid,
+  pub scope: ScopeName,
+  pub name: PackageName,
+  pub version: Option<Version>,
+  pub reason: AbuseReportReason,
+  pub description: String,
+  /// `None` if the reporter was deleted.
+  pub reporter_id: Option<Uuid>,
+  pub status: AbuseReportStatus,
+  /// The staff member that last moved the report to another status.
+  pub reviewer_id: Option<Uuid>,
+  pub resolution: Option<String>,
+  pub resolved_at: Option<DateTime<Utc>>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+/// A package version that is blocked from being downloaded.
+#[derive(Debug, Clone)]
+pub struct PackageVersionQuarantine {
+  pub scope: ScopeName,
+  pub name: PackageName,
+  pub version: Version,
+  pub reason: String,
+  /// The report that led to the quarantine, if any.
+  pub report_id: Option<Uuid>,
+  pub quarantined_by: Option<Uuid>,
+  pub created_at: DateTime<Utc>,
+}
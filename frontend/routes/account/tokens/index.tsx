@@ -125,6 +125,9 @@ function PersonalTokenRow({ token }: { token: Token }) {
         </p>
         <p class="text-sm sm:text-right">
           Created {twas(new Date(token.createdAt).getTime())}
+          {token.lastUsedAt
+            ? `, last used ${twas(new Date(token.lastUsedAt).getTime())}`
+            : ", never used"}
         </p>
       </div>
       <p class="text-sm text-secondary">
@@ -138,7 +141,12 @@ function PersonalTokenRow({ token }: { token: Token }) {
                   : `new versions of any package in @${perm.scope}`
               }`;
             }
-            return `has unknown permission: ${perm.permission}`;
+            if (perm.permission === "read") {
+              return "Has read-only access";
+            }
+            return `has unknown permission: ${
+              (perm as { permission: string }).permission
+            }`;
           }).join(", ")}
       </p>
     </li>
@@ -8,6 +8,8 @@ import { assertOk, path } from "../utils/api.ts";
 import type {
   Authorization,
   Permission,
+  PermissionPackagePublishPackage,
+  PermissionPackagePublishScope,
   PermissionPackagePublishVersion,
 } from "../utils/api_types.ts";
 import TbChevronRight from "tb-icons/TbChevronRight";
@@ -102,24 +104,28 @@ function PermissionTile({ permission }: { permission: Permission | null }) {
       description =
         "Including creating scopes, publishing any package, adding members, removing members, and more";
       break;
-    case "package/publish":
+    case "package/publish": {
       icon = <TbChevronRight class="w-12 h-12 shrink-0" />;
-      if ("package" in permission!) {
-        title = `Publish any version of @${permission!.scope}/${
-          permission!.package
-        }`;
+      const perm = permission as
+        | PermissionPackagePublishScope
+        | PermissionPackagePublishPackage;
+      if ("package" in perm) {
+        title = `Publish any version of @${perm.scope}/${perm.package}`;
         description =
-          `This application will be able to publish new versions of the package @${
-            permission!.scope
-          }/${permission!.package}`;
+          `This application will be able to publish new versions of the package @${perm.scope}/${perm.package}`;
       } else {
-        title = `Publishing any version in @${permission!.scope}`;
+        title = `Publishing any version in @${perm.scope}`;
         description =
-          `This application will be able to publish new versions of any existing package in the scope @${
-            permission!.scope
-          }`;
+          `This application will be able to publish new versions of any existing package in the scope @${perm.scope}`;
       }
       break;
+    }
+    case "read":
+      icon = <TbChevronRight class="w-12 h-12 shrink-0" />;
+      title = "Read-only access";
+      description =
+        "This application will be able to see everything you can see, but not change anything";
+      break;
 
     default:
       throw new Error("unreachable");
@@ -14,6 +14,7 @@ import { AllSymbols } from "../../../components/doc/AllSymbols.tsx";
 import { ComponentChildren } from "preact";
 import DiffVersionSelector from "../(_islands)/DiffVersionSelector.tsx";
 import { compileDocsRequestPath, DocsRequest } from "../../../utils/data.ts";
+import { asset } from "fresh/runtime";
 
 interface DocsProps {
   docs: Docs;
@@ -109,6 +110,7 @@ function SharedView({
         dangerouslySetInnerHTML={{ __html: docs.script }}
         defer
       />
+      <script type="module" src={asset("/scripts/doc-rendering.js")} />
 
       {docs.breadcrumbs && (
         <BreadcrumbsSticky content={docs.breadcrumbs} class={navRightClass}>
@@ -35,6 +35,8 @@ export default define.page<typeof handler>(function Deps(
       constraints: Set<string>;
       modules: Record<string, string | undefined>;
       defaultModule: boolean;
+      optional: boolean;
+      archived: boolean;
     }
   > = {};
 
@@ -45,8 +47,12 @@ export default define.page<typeof handler>(function Deps(
       constraints: new Set(),
       modules: {},
       defaultModule: false,
+      optional: true,
+      archived: false,
     };
     deps[key].constraints.add(dep.constraint);
+    deps[key].optional &&= dep.optional;
+    deps[key].archived ||= dep.archived;
     if (dep.path) {
       deps[key].modules[dep.path] = dep.kind === "jsr"
         ? `/${dep.name}/doc/${dep.path}/~`
@@ -101,6 +107,8 @@ export default define.page<typeof handler>(function Deps(
                     constraints={[...info.constraints]}
                     modules={Object.entries(info.modules)}
                     defaultModule={info.defaultModule}
+                    optional={info.optional}
+                    archived={info.archived}
                   />
                 ))}
               </Table>
@@ -119,12 +127,14 @@ export default define.page<typeof handler>(function Deps(
 });
 
 function Dependency(
-  { name, link, constraints, modules, defaultModule }: {
+  { name, link, constraints, modules, defaultModule, optional, archived }: {
     name: string;
     link: string;
     constraints: string[];
     modules: [path: string, link?: string][];
     defaultModule: boolean;
+    optional: boolean;
+    archived: boolean;
   },
 ) {
   return (
@@ -133,6 +143,15 @@ function Dependency(
         <a href={link} class="link">
           {name}
         </a>
+        {optional && <span class="ml-2 text-tertiary italic">(optional)</span>}
+        {archived && (
+          <span
+            class="ml-2 chip bg-jsr-yellow-400 dark:text-jsr-gray-800"
+            title="This package has been archived and will not receive further updates"
+          >
+            archived
+          </span>
+        )}
       </TableData>
       <TableData class="space-x-4">
         {constraints.map((constraint, idx) => (
@@ -1,7 +1,7 @@
 // Copyright 2024 the JSR authors. All rights reserved. MIT license.
 import { ComponentChildren } from "preact";
 import { HttpError, RouteConfig } from "fresh";
-import type { PackageScore } from "../../utils/api_types.ts";
+import type { PackageScore, ReadmeQuality } from "../../utils/api_types.ts";
 import { assertOk, path } from "../../utils/api.ts";
 import { define } from "../../util.ts";
 import { packageData } from "../../utils/data.ts";
@@ -106,6 +106,7 @@ function ScoreInfo(props: {
             module doc
           </a>{" "}
           in the main entrypoint of the package.
+          {score.readme && <ReadmeSuggestions readme={score.readme} />}
         </ScoreItem>
         <ScoreItem
           value={score.hasReadmeExamples}
@@ -141,6 +142,17 @@ function ScoreInfo(props: {
           </a>. Currently{" "}
           {Math.floor(score.percentageDocumentedSymbols * 100)}% of exported
           symbols are documented.
+          {score.entrypointDocs
+            .filter((entrypoint) =>
+              entrypoint.documentedSymbols < entrypoint.totalSymbols
+            )
+            .map((entrypoint) => (
+              <span key={entrypoint.export} class="block mt-1">
+                <code>{entrypoint.export}</code>:{" "}
+                {entrypoint.documentedSymbols} of {entrypoint.totalSymbols}{" "}
+                symbols documented
+              </span>
+            ))}
         </ScoreItem>
         <ScoreItem
           value={score.allFastCheck}
@@ -152,6 +164,32 @@ function ScoreInfo(props: {
             slow types
           </a>.
         </ScoreItem>
+        <ScoreItem
+          value={score.allExamplesResolve}
+          scoreValue={1}
+          title="Has examples that check against its exports"
+        >
+          The package should have examples in its README or in{" "}
+          <code>@example</code>{" "}
+          tags, and every example should only import symbols that the package
+          actually exports.
+        </ScoreItem>
+        <ScoreItem
+          value={score.hasTests}
+          scoreValue={1}
+          title="Has tests"
+        >
+          The package should include test files, or attach a coverage summary
+          with the <code>coverage</code> field of its config file.{" "}
+          {score.coverage && (
+            <>
+              Currently {Math.floor(
+                (score.coverage.lines.covered /
+                  Math.max(score.coverage.lines.total, 1)) * 100,
+              )}% of lines are covered.
+            </>
+          )}
+        </ScoreItem>
         <ScoreItem
           value={score.hasDescription}
           scoreValue={1}
@@ -211,6 +249,42 @@ function ScoreInfo(props: {
   );
 }
 
+function ReadmeSuggestions({ readme }: { readme: ReadmeQuality }) {
+  return (
+    <>
+      {readme.headings.length === 0 && (
+        <span class="block mt-1">
+          The README has no headings to structure its content.
+        </span>
+      )}
+      {!readme.hasInstallInstructions && (
+        <span class="block mt-1">
+          The README does not show how to add the package, for example with
+          {" "}
+          <code>deno add</code>.
+        </span>
+      )}
+      {readme.brokenLinks.length > 0 && (
+        <span class="block mt-1">
+          Broken links in the README:{" "}
+          {readme.brokenLinks.map((link, i) => (
+            <span key={link}>
+              {i > 0 && ", "}
+              <code>{link}</code>
+            </span>
+          ))}
+        </span>
+      )}
+      {readme.imagesWithoutAlt > 0 && (
+        <span class="block mt-1">
+          {readme.imagesWithoutAlt} of {readme.images}{" "}
+          images in the README have no alt text.
+        </span>
+      )}
+    </>
+  );
+}
+
 function ScoreItem(
   props: {
     title: string;
@@ -39,6 +39,8 @@ export default define.page<typeof handler>(
 
           <SelectReadmeSourceEditor source={data.package.readmeSource} />
 
+          <PackageVisibility visibility={data.package.visibility} />
+
           <ArchivePackage isArchived={data.package.isArchived} />
 
           <DeletePackage hasVersions={data.package.versionCount > 0} />
@@ -211,6 +213,38 @@ function RuntimeCompatEditorItem({ name, id, value }: {
   );
 }
 
+function PackageVisibility(props: { visibility: "public" | "private" }) {
+  const isPrivate = props.visibility === "private";
+  return (
+    <form class="flex flex-col items-start gap-4" method="POST">
+      <div>
+        <h2 class="text-xl font-sans font-bold">
+          {isPrivate ? "Make package public" : "Make package private"}
+        </h2>
+        <p class="text-secondary max-w-3xl">
+          {isPrivate
+            ? "This package is private: only members of the scope can see it, its versions and its documentation. Making it public makes it visible to everyone and adds it to search again."
+            : "Private packages are only visible to members of the scope, including through read-only tokens. Everyone else is told that the package does not exist, and it is removed from search."}
+        </p>
+      </div>
+
+      <input
+        type="hidden"
+        name="visibility"
+        value={isPrivate ? "public" : "private"}
+      />
+      <button
+        class="button-danger"
+        type="submit"
+        name="action"
+        value="updateVisibility"
+      >
+        {isPrivate ? "Make public" : "Make private"}
+      </button>
+    </form>
+  );
+}
+
 function ArchivePackage(props: { isArchived: boolean }) {
   if (!props.isArchived) {
     return (
@@ -373,6 +407,17 @@ export const handler = define.handlers({
           headers: { Location: `/@${scope}/${packageName}/settings` },
         });
       }
+      case "updateVisibility": {
+        const visibilityRes = await api.patch(
+          path`/scopes/${scope}/packages/${packageName}`,
+          { visibility: data.get("visibility") },
+        );
+        assertOk(visibilityRes);
+        return new Response(null, {
+          status: 303,
+          headers: { Location: `/@${scope}/${packageName}/settings` },
+        });
+      }
       case "deletePackage": {
         const deleteRes = await api.delete(
           path`/scopes/${scope}/packages/${packageName}`,
@@ -277,6 +277,12 @@ function Version({
                 {twas(new Date(version.createdAt).getTime())}
               </div>
             )}
+            {isPublished && version.yankedAt && (
+              <div class="text-sm select-none text-red-700 dark:text-red-400 z-0">
+                Yanked {twas(new Date(version.yankedAt).getTime())}
+                {version.yankReason && `: ${version.yankReason}`}
+              </div>
+            )}
           </div>
         </div>
         {isPublished && iam.canAdmin && (
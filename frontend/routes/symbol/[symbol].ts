@@ -0,0 +1,45 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+import { HttpError, RouteConfig } from "fresh";
+import { define } from "../../util.ts";
+import type { DocLink } from "../../utils/api_types.ts";
+import { assertOk, path } from "../../utils/api.ts";
+
+// Permalinks to a symbol, like `/symbol/@scope/pkg/Client#method`, resolve to
+// the documentation page of the symbol in the latest version, wherever it is
+// exported from. The fragment is kept by the browser across the redirect.
+export const handler = define.handlers({
+  async GET(ctx) {
+    const { scope, package: pkg, symbol } = ctx.params;
+
+    const resp = await ctx.state.api.get<DocLink>(
+      path`/scopes/${scope}/packages/${pkg}/versions/latest/symbols/${symbol}`,
+    );
+
+    if (
+      !resp.ok &&
+      (resp.code === "packageNotFound" ||
+        resp.code === "packageVersionNotFound")
+    ) {
+      throw new HttpError(404, "This package was not found.");
+    }
+
+    let location;
+    if (!resp.ok && resp.code === "entrypointOrSymbolNotFound") {
+      // The symbol was removed or renamed, fall back to the list of all
+      // symbols of the package.
+      location = `/@${scope}/${pkg}/doc/all_symbols`;
+    } else {
+      assertOk(resp);
+      location = `/@${scope}/${pkg}${resp.data.path}`;
+    }
+
+    return new Response(null, {
+      status: 302, // Found
+      headers: { Location: location },
+    });
+  },
+});
+
+export const config: RouteConfig = {
+  routeOverride: "/symbol/@:scope/:package/:symbol",
+};
@@ -0,0 +1,48 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+// Renders mermaid diagrams and KaTeX math in package docs and READMEs. The
+// API only emits the escaped source, so the libraries are loaded on demand
+// for pages that actually contain either.
+
+const MERMAID_URL =
+  "https://cdn.jsdelivr.net/npm/mermaid@11/dist/mermaid.esm.min.mjs";
+const KATEX_URL = "https://cdn.jsdelivr.net/npm/katex@0.16/dist/katex.mjs";
+const KATEX_CSS_URL =
+  "https://cdn.jsdelivr.net/npm/katex@0.16/dist/katex.min.css";
+
+async function renderMermaid() {
+  const diagrams = document.querySelectorAll(".ddoc pre.mermaid");
+  if (diagrams.length === 0) return;
+
+  const { default: mermaid } = await import(MERMAID_URL);
+  mermaid.initialize({
+    startOnLoad: false,
+    // "strict" sanitizes labels and disables click handlers in diagrams.
+    securityLevel: "strict",
+    theme: document.documentElement.classList.contains("dark")
+      ? "dark"
+      : "default",
+  });
+  await mermaid.run({ nodes: diagrams, suppressErrors: true });
+}
+
+async function renderMath() {
+  const elements = document.querySelectorAll(".ddoc .math");
+  if (elements.length === 0) return;
+
+  const link = document.createElement("link");
+  link.rel = "stylesheet";
+  link.href = KATEX_CSS_URL;
+  document.head.appendChild(link);
+
+  const { default: katex } = await import(KATEX_URL);
+  for (const element of elements) {
+    katex.render(element.textContent, element, {
+      displayMode: element.classList.contains("math-display"),
+      throwOnError: false,
+      trust: false,
+    });
+  }
+}
+
+renderMermaid().catch(console.error);
+renderMath().catch(console.error);
@@ -48,6 +48,8 @@ export interface ScopeQuota {
   newPackagePerWeekLimit: number;
   publishAttemptsPerWeekUsage: number;
   publishAttemptsPerWeekLimit: number;
+  storageUsage: number;
+  storageLimit: number;
 }
 
 export interface ScopeMember {
@@ -81,10 +83,23 @@ export interface PublishingTask {
   packageScope: string;
   packageName: string;
   packageVersion: string;
+  /** Only included when a single publishing task is fetched. */
+  previewDiff?: PublishPreviewDiff | null;
   createdAt: string;
   updatedAt: string;
 }
 
+export interface PublishPreviewDiff {
+  baseVersion: string;
+  files: {
+    added: string[];
+    removed: string[];
+    modified: string[];
+  };
+  /** The doc diff of the exported API. */
+  api: Record<string, unknown>;
+}
+
 export interface GithubRepository {
   id: number;
   owner: string;
@@ -107,6 +122,8 @@ export interface PackageScore {
   allEntrypointsDocs: boolean;
   percentageDocumentedSymbols: number;
   allFastCheck: boolean;
+  allExamplesResolve: boolean;
+  hasTests: boolean;
   hasProvenance: boolean;
 
   // package specific
@@ -114,7 +131,70 @@ export interface PackageScore {
   atLeastOneRuntimeCompatible: boolean;
   multipleRuntimesCompatible: boolean;
 
+  factors: PackageScoreFactor[];
+  total: number;
+  maxScore: number;
+  testFiles: number;
+  coverage: CoverageSummary | null;
+  entrypointDocs: EntrypointDocsCoverage[];
+  readme: ReadmeQuality | null;
+  schemaVersion: number;
+  publishedSchemaVersion: number;
+}
+
+export interface CoverageSummary {
+  lines: CoverageMetric;
+  functions: CoverageMetric | null;
+  branches: CoverageMetric | null;
+}
+
+export interface EntrypointDocsCoverage {
+  export: string;
+  path: string;
+  hasModuleDoc: boolean;
+  documentedSymbols: number;
+  totalSymbols: number;
+}
+
+export interface ReadmeQuality {
+  headings: ReadmeHeading[];
+  codeBlocks: number;
+  hasInstallInstructions: boolean;
+  links: number;
+  brokenLinks: string[];
+  images: number;
+  imagesWithoutAlt: number;
+}
+
+export interface ReadmeHeading {
+  level: number;
+  text: string;
+}
+
+export interface CoverageMetric {
   total: number;
+  covered: number;
+}
+
+export type PackageScoreFactorKind =
+  | "hasReadme"
+  | "hasReadmeExamples"
+  | "allEntrypointsDocs"
+  | "percentageDocumentedSymbols"
+  | "allFastCheck"
+  | "allExamplesResolve"
+  | "hasTests"
+  | "hasProvenance"
+  | "hasDescription"
+  | "atLeastOneRuntimeCompatible"
+  | "multipleRuntimesCompatible";
+
+export interface PackageScoreFactor {
+  factor: PackageScoreFactorKind;
+  weight: number;
+  points: number;
+  value: boolean | number;
+  hints: string[];
 }
 
 export interface Package {
@@ -128,20 +208,36 @@ export interface Package {
   versionCount: number;
   dependencyCount: number;
   dependentCount: number;
+  downloadsLast30Days: number;
   score: number | null;
   latestVersion: string | null;
   whenFeatured: string | null;
   isArchived: boolean;
   readmeSource: ReadmeSource;
+  visibility: PackageVisibility;
+  descriptionLocale: string | null;
+  localizedDescriptions: Record<string, string>;
 }
 
 export type ReadmeSource = "readme" | "jsdoc";
 
+export type PackageVisibility = "public" | "private";
+
+export interface PackageSnapshot {
+  scope: string;
+  package: string;
+  asOf: string;
+  latest: string | null;
+  versions: Record<string, { yanked: boolean; createdAt: string }>;
+}
+
 export interface PackageVersion {
   scope: string;
   package: string;
   version: string;
   yanked: boolean;
+  yankedAt: string | null;
+  yankReason: string | null;
   usesNpm: boolean;
   newerVersionsCount: number | null;
   rekorLogId: string | null;
@@ -151,6 +247,53 @@ export interface PackageVersion {
   createdAt: string;
 }
 
+export interface DistTag {
+  tag: string;
+  version: string;
+  updatedAt: string;
+}
+
+export interface ScopeTeam {
+  scope: string;
+  name: string;
+  description: string;
+  updatedAt: string;
+  createdAt: string;
+}
+
+export interface ScopeTeamMember {
+  scope: string;
+  team: string;
+  user: User;
+  createdAt: string;
+}
+
+export interface ScopeTeamPackage {
+  scope: string;
+  team: string;
+  package: string;
+  canPublish: boolean;
+  canYank: boolean;
+  canManageTokens: boolean;
+  updatedAt: string;
+  createdAt: string;
+}
+
+export interface PackageTransfer {
+  scope: string;
+  package: string;
+  targetScope: string;
+  requestedBy: string;
+  createdAt: string;
+}
+
+export interface ScopeRename {
+  scope: string;
+  targetScope: string;
+  requestedBy: string;
+  createdAt: string;
+}
+
 export interface PackageVersionWithUser extends PackageVersion {
   user?: User;
 }
@@ -158,6 +301,7 @@ export interface PackageVersionWithUser extends PackageVersion {
 export interface PackageVersionDocsContent {
   kind: "content";
   version: PackageVersionWithUser;
+  packageArchived: boolean;
   comrakCss: string;
   script: string;
   breadcrumbs: BreadcrumbsCtx | null;
@@ -229,16 +373,57 @@ export type PermissionPackagePublishVersion = {
   tarballHash: string;
 };
 
+export type PermissionRead = {
+  permission: "read";
+};
+
 export type Permission =
   | PermissionPackagePublishScope
   | PermissionPackagePublishPackage
-  | PermissionPackagePublishVersion;
+  | PermissionPackagePublishVersion
+  | PermissionRead;
 
 export interface Dependency {
   kind: "jsr" | "npm";
   name: string;
   constraint: string;
   path: string;
+  optional: boolean;
+  archived: boolean;
+}
+
+export interface Deprecation {
+  export: string;
+  symbol: string;
+  message: string | null;
+}
+
+export interface DocLink {
+  version: string;
+  identifier: string;
+  path: string;
+}
+
+export interface TypeGraph {
+  nodes: TypeGraphNode[];
+  edges: { from: number; to: number }[];
+}
+
+export interface TypeGraphNode {
+  export: string;
+  symbol: string;
+  kind: string;
+}
+
+export interface SymbolSearchResult {
+  scope: string;
+  package: string;
+  version: string;
+  export: string;
+  symbol: string;
+  kind: string;
+  score: number | null;
+  downloads: number;
 }
 
 export interface PackageVersionReference {
@@ -252,6 +437,12 @@ export interface StatsPackage {
   name: string;
 }
 
+export interface StatsDownloadedPackage {
+  scope: string;
+  name: string;
+  downloads: number;
+}
+
 export interface StatsPackageVersion {
   scope: string;
   package: string;
@@ -262,6 +453,8 @@ export interface Stats {
   newest: StatsPackage[];
   updated: StatsPackageVersion[];
   featured: StatsPackage[];
+  mostDownloadedThisWeek: StatsDownloadedPackage[];
+  mostDownloadedThisMonth: StatsDownloadedPackage[];
 }
 
 export interface List<T> {
@@ -269,6 +462,22 @@ export interface List<T> {
   total: number;
 }
 
+export interface PackageSearchResult extends List<Package> {
+  facets: PackageSearchFacets;
+}
+
+export interface PackageSearchFacets {
+  runtimeCompat: {
+    browser: number;
+    deno: number;
+    node: number;
+    workerd: number;
+    bun: number;
+  };
+  hasProvenance: number;
+  licenses: { license: string; count: number }[];
+}
+
 export interface Dependent {
   scope: string;
   package: string;
@@ -282,6 +491,8 @@ export interface Token {
   type: "web" | "device" | "personal";
   expiresAt: string | null;
   permissions: Permission[] | null;
+  lastUsedAt: string | null;
+  rotatedAt: string | null;
   updatedAt: string;
   createdAt: string;
 }
@@ -414,6 +625,7 @@ export interface AuditLog {
 export interface PackageDownloads {
   total: DownloadDataPoint[];
   recentVersions: PackageDownloadsRecentVersion[];
+  entrypoints: PackageDownloadsEntrypoint[];
 }
 
 export interface DownloadDataPoint {
@@ -426,3 +638,76 @@ export interface PackageDownloadsRecentVersion {
   version: string;
   downloads: DownloadDataPoint[];
 }
+
+export interface PackageDownloadsEntrypoint {
+  entrypoint: string;
+  downloads: { timeBucket: string; count: number }[];
+}
+
+export type AnnouncementSeverity = "info" | "warning" | "critical";
+
+export interface Announcement {
+  id: string;
+  title: string;
+  body: string;
+  severity: AnnouncementSeverity;
+  features: string[];
+  expiresAt: string | null;
+  updatedAt: string;
+  createdAt: string;
+}
+
+export type WebhookEvent =
+  | "version_published"
+  | "version_yanked"
+  | "new_dependent"
+  | "scope_member_added"
+  | "scope_member_updated"
+  | "scope_member_removed"
+  | "version_quarantined"
+  | "version_released";
+
+export interface Webhook {
+  id: string;
+  scope: string;
+  url: string;
+  events: WebhookEvent[];
+  description: string;
+  isActive: boolean;
+  updatedAt: string;
+  createdAt: string;
+}
+
+export type WebhookDeliveryStatus = "pending" | "success" | "failure";
+
+export interface WebhookDelivery {
+  id: string;
+  webhookId: string;
+  event: WebhookEvent;
+  payload: unknown;
+  status: WebhookDeliveryStatus;
+  attempts: number;
+  nextAttemptAt: string | null;
+  lastResponseStatus: number | null;
+  lastError: string | null;
+  deliveredAt: string | null;
+  createdAt: string;
+}
+
+export interface ValidateConfigResponse {
+  valid: boolean;
+  scope: string | null;
+  package: string | null;
+  version: string | null;
+  exports: Record<string, string> | null;
+  packageExists: boolean | null;
+  issues: ConfigIssue[];
+}
+
+export interface ConfigIssue {
+  severity: "error" | "warning" | "info";
+  code: string;
+  field: string | null;
+  message: string;
+  hint: string | null;
+}
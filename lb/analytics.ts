@@ -23,6 +23,33 @@ export function trackJSRDownload(
   }
 }
 
+/**
+ * Tracks fetches of the files of a package version, so downloads can be
+ * broken down by entrypoint. Which files are entrypoints is resolved by the
+ * registry when the data points are aggregated.
+ */
+export function trackJSRModuleDownload(
+  pathname: string,
+  userAgent: string | null,
+  env: WorkerEnv,
+): void {
+  const match = pathname.match(/^\/@([^/]+)\/([^/]+)\/([^/]+)(\/.+)$/);
+  if (match) {
+    const [, scope, packageName, version, path] = match;
+    env.DOWNLOADS?.writeDataPoint({
+      blobs: [
+        "jsr_module",
+        scope,
+        packageName,
+        version,
+        userAgent ?? "n/a",
+        path,
+      ],
+      indexes: [`@${scope}/${packageName}`],
+    });
+  }
+}
+
 export function trackNPMDownload(
   pathname: string,
   userAgent: string | null,
@@ -10,7 +10,17 @@ import {
   setSecurityHeaders,
 } from "./headers.ts";
 import { isBot } from "./bots.ts";
-import { trackJSRDownload, trackNPMDownload } from "./analytics.ts";
+import { resolveModuleFileBlob } from "./module_files.ts";
+import {
+  isPrivatePackage,
+  modulePathPackage,
+  npmPathPackage,
+} from "./private_packages.ts";
+import {
+  trackJSRDownload,
+  trackJSRModuleDownload,
+  trackNPMDownload,
+} from "./analytics.ts";
 
 export type Backend = "api" | "frontend" | "modules" | "npm";
 const MODULES = "modules";
@@ -79,6 +89,7 @@ export async function handleAPIRequest(
     env.REGISTRY_API_URL,
     rewritePath ? (path) => `/api${path}` : undefined,
     ctx,
+    env.ORIGIN_SECRET,
   );
 
   setSecurityHeaders(response, API);
@@ -100,17 +111,30 @@ export async function handleNPMRequest(
   }
 
   const url = new URL(request.url);
-  const response = await proxyToR2(
-    request,
-    env.NPM_BUCKET,
-    (path) => {
-      if (path === "/" || path === "/-/ping") {
-        return "/root.json";
-      }
-      return path;
-    },
-    ctx,
-  );
+  const pkg = npmPathPackage(url.pathname);
+  let response;
+  if (pkg && await isPrivatePackage(env.MODULES_BUCKET, pkg)) {
+    // The API checks that the requester may read the package.
+    response = await proxyToBackend(
+      request,
+      env.REGISTRY_API_URL,
+      (path) => `/api/npm${path}`,
+      ctx,
+      env.ORIGIN_SECRET,
+    );
+  } else {
+    response = await proxyToR2(
+      request,
+      env.NPM_BUCKET,
+      (path) => {
+        if (path === "/" || path === "/-/ping") {
+          return "/root.json";
+        }
+        return path;
+      },
+      ctx,
+    );
+  }
 
   setSecurityHeaders(response, NPM);
   setCORSHeaders(response, NPM);
@@ -300,12 +324,49 @@ async function handleModuleFileRoute(
   ctx?: ExecutionCtx,
 ): Promise<Response> {
   const url = new URL(request.url);
-  const response = await proxyToR2(
-    request,
-    env.MODULES_BUCKET,
-    undefined,
-    ctx,
-  );
+  let response;
+  try {
+    const pkg = modulePathPackage(url.pathname);
+    if (pkg && await isPrivatePackage(env.MODULES_BUCKET, pkg)) {
+      // The API checks that the requester may read the package.
+      const prefix = `/@${pkg.scope}/${pkg.name}/`;
+      const apiUrl = new URL(
+        `/api/scopes/${pkg.scope}/packages/${pkg.name}/module_file`,
+        url,
+      );
+      apiUrl.searchParams.set(
+        "path",
+        decodeURIComponent(url.pathname).slice(prefix.length),
+      );
+      response = await proxyToBackend(
+        new Request(apiUrl, request),
+        env.REGISTRY_API_URL,
+        undefined,
+        ctx,
+        env.ORIGIN_SECRET,
+      );
+    } else {
+      // Files of package versions are stored as blobs shared between versions.
+      const blob = await resolveModuleFileBlob(
+        env.MODULES_BUCKET,
+        url.pathname,
+      );
+      response = await proxyToR2(
+        request,
+        env.MODULES_BUCKET,
+        blob ? () => blob.path : undefined,
+        ctx,
+        true,
+        blob?.contentType,
+      );
+    }
+  } catch (error) {
+    console.error("R2 manifest error:", error);
+    response = new Response("Bad Gateway", {
+      status: 502,
+      headers: { "Content-Type": "text/plain" },
+    });
+  }
 
   setSecurityHeaders(response, MODULES);
   setCORSHeaders(response, MODULES);
@@ -315,6 +376,11 @@ async function handleModuleFileRoute(
 
   if ((response.ok || response.status === 304) && request.method === "GET") {
     trackJSRDownload(url.pathname, request.headers.get("User-Agent"), env);
+    trackJSRModuleDownload(
+      url.pathname,
+      request.headers.get("User-Agent"),
+      env,
+    );
   }
 
   return response;
@@ -0,0 +1,81 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+import type { PartialBucket } from "./types.ts";
+
+// Module files are stored once per distinct content, under `blobs/<hash>`, and
+// every package version has a manifest at `@scope/name/<version>_files.json`
+// that maps the paths of its files to those hashes. Versions published before
+// files were deduplicated have no manifest, and their files are stored under
+// their own path.
+interface FilesManifest {
+  files: Record<string, { hash: string; contentType: string | null }>;
+}
+
+export interface ModuleFileBlob {
+  /** The path of the blob in the bucket, like `/blobs/sha256-...`. */
+  path: string;
+  /** The content type of the file, which is not stored with the blob. */
+  contentType: string | null;
+}
+
+const VERSION_FILE_PATH = /^\/(@[^/]+\/[^/]+)\/(\d[^/]*)(\/.*)$/;
+
+// A manifest never changes once it is written, but a deleted version can be
+// published again, so manifests are only kept for a short while.
+const MANIFEST_CACHE_TTL_MS = 60 * 1000;
+const MANIFEST_CACHE_SIZE = 256;
+const manifestCache = new Map<
+  string,
+  { expires: number; manifest: FilesManifest | null }
+>();
+
+async function getManifest(
+  bucket: PartialBucket,
+  key: string,
+): Promise<FilesManifest | null> {
+  const now = Date.now();
+  const cached = manifestCache.get(key);
+  if (cached && cached.expires > now) return cached.manifest;
+
+  const object = await bucket.get(key);
+  const manifest = object && "body" in object
+    ? await object.json<FilesManifest>()
+    : null;
+  if (manifestCache.size >= MANIFEST_CACHE_SIZE) {
+    // Maps iterate in insertion order, so this evicts the oldest entry.
+    manifestCache.delete(manifestCache.keys().next().value!);
+  }
+  manifestCache.set(key, { expires: now + MANIFEST_CACHE_TTL_MS, manifest });
+  return manifest;
+}
+
+/**
+ * Finds the blob that stores the file of a package version at the given
+ * request path. Returns `null` for the metadata files of packages and versions,
+ * and for the files of versions without a manifest, which are all stored under
+ * their own path.
+ */
+export async function resolveModuleFileBlob(
+  bucket: PartialBucket,
+  path: string,
+): Promise<ModuleFileBlob | null> {
+  const match = VERSION_FILE_PATH.exec(decodeURIComponent(path));
+  if (!match) return null;
+  const [, packageName, version, filePath] = match;
+
+  const manifest = await getManifest(
+    bucket,
+    `${packageName}/${version}_files.json`,
+  );
+  const file = manifest?.files[filePath];
+  if (!file) return null;
+  return {
+    path: `/blobs/${encodeURIComponent(file.hash)}`,
+    contentType: file.contentType,
+  };
+}
+
+/** Only for tests. */
+export function clearManifestCache() {
+  manifestCache.clear();
+}
@@ -0,0 +1,77 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+import { assertEquals } from "@std/assert";
+import { clearManifestCache, resolveModuleFileBlob } from "./module_files.ts";
+import type { PartialBucket } from "./types.ts";
+
+function createManifestBucket(
+  manifests: Record<string, unknown>,
+  gets: string[] = [],
+): PartialBucket {
+  return {
+    get(key: string) {
+      gets.push(key);
+      const manifest = manifests[key];
+      if (!manifest) return Promise.resolve(null);
+      return Promise.resolve({
+        key,
+        body: new ReadableStream(),
+        json: () => Promise.resolve(manifest),
+      } as unknown as R2ObjectBody);
+    },
+  } as PartialBucket;
+}
+
+const MANIFEST = {
+  files: {
+    "/mod.ts": { hash: "sha256-abc", contentType: "text/typescript" },
+    "/data.bin": { hash: "sha256-def", contentType: null },
+  },
+};
+
+Deno.test("resolveModuleFileBlob finds files in the manifest", async () => {
+  clearManifestCache();
+  const gets: string[] = [];
+  const bucket = createManifestBucket(
+    { "@std/yaml/1.0.0_files.json": MANIFEST },
+    gets,
+  );
+
+  assertEquals(
+    await resolveModuleFileBlob(bucket, "/@std/yaml/1.0.0/mod.ts"),
+    { path: "/blobs/sha256-abc", contentType: "text/typescript" },
+  );
+  assertEquals(
+    await resolveModuleFileBlob(bucket, "/@std/yaml/1.0.0/data.bin"),
+    { path: "/blobs/sha256-def", contentType: null },
+  );
+  assertEquals(
+    await resolveModuleFileBlob(bucket, "/@std/yaml/1.0.0/missing.ts"),
+    null,
+  );
+  // The manifest is only fetched once.
+  assertEquals(gets, ["@std/yaml/1.0.0_files.json"]);
+});
+
+Deno.test("resolveModuleFileBlob skips metadata and old versions", async () => {
+  clearManifestCache();
+  const gets: string[] = [];
+  const bucket = createManifestBucket({}, gets);
+
+  assertEquals(
+    await resolveModuleFileBlob(bucket, "/@std/yaml/meta.json"),
+    null,
+  );
+  assertEquals(
+    await resolveModuleFileBlob(bucket, "/@std/yaml/1.0.0_meta.json"),
+    null,
+  );
+  assertEquals(gets, []);
+
+  // Versions published before files were deduplicated have no manifest.
+  assertEquals(
+    await resolveModuleFileBlob(bucket, "/@std/yaml/0.1.0/mod.ts"),
+    null,
+  );
+  assertEquals(gets, ["@std/yaml/0.1.0_files.json"]);
+});
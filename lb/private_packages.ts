@@ -0,0 +1,74 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+import type { PartialBucket } from "./types.ts";
+
+// The files of private packages are in the same buckets as those of public
+// packages, so they can not be served from there to everyone. The API marks a
+// package as private with an empty object at `@scope/name/_private` in the
+// modules bucket, which is never served itself. Requests for the files of a
+// marked package are proxied to the API instead, which checks that the
+// requester may read the package.
+
+export interface PackageName {
+  scope: string;
+  name: string;
+}
+
+// Whether a package is private only changes when its settings are changed, but
+// then it must take effect quickly, so markers are only kept for a short while.
+const MARKER_CACHE_TTL_MS = 60 * 1000;
+const MARKER_CACHE_SIZE = 1024;
+const markerCache = new Map<string, { expires: number; isPrivate: boolean }>();
+
+export async function isPrivatePackage(
+  bucket: PartialBucket,
+  pkg: PackageName,
+): Promise<boolean> {
+  const key = `@${pkg.scope}/${pkg.name}/_private`;
+  const now = Date.now();
+  const cached = markerCache.get(key);
+  if (cached && cached.expires > now) return cached.isPrivate;
+
+  const isPrivate = (await bucket.head(key)) !== null;
+  if (markerCache.size >= MARKER_CACHE_SIZE) {
+    // Maps iterate in insertion order, so this evicts the oldest entry.
+    markerCache.delete(markerCache.keys().next().value!);
+  }
+  markerCache.set(key, { expires: now + MARKER_CACHE_TTL_MS, isPrivate });
+  return isPrivate;
+}
+
+function decodePath(path: string): string {
+  try {
+    return decodeURIComponent(path);
+  } catch {
+    return path;
+  }
+}
+
+const MODULE_FILE_PACKAGE = /^\/@([^/]+)\/([^/]+)\//;
+
+/** The package of a path in the modules bucket, like `/@std/fs/meta.json`. */
+export function modulePathPackage(path: string): PackageName | null {
+  const match = MODULE_FILE_PACKAGE.exec(decodePath(path));
+  if (!match) return null;
+  return { scope: match[1], name: match[2] };
+}
+
+// `@jsr/<scope>__<name>`, as requested for the package metadata, in npm
+// tarball paths (`/~/<revision>/@jsr/<scope>__<name>/<version>.tgz`) and in
+// attestation paths (`/-/npm/v1/attestations/@jsr/<scope>__<name>@<version>`).
+const NPM_PACKAGE =
+  /^\/(?:~\/\d+\/|-\/npm\/v1\/attestations\/)?@jsr\/([a-z0-9-]+)__([a-z0-9-]+)(?:$|\/|@)/;
+
+/** The JSR package of a path in the npm bucket, like `/@jsr/std__fs`. */
+export function npmPathPackage(path: string): PackageName | null {
+  const match = NPM_PACKAGE.exec(decodePath(path));
+  if (!match) return null;
+  return { scope: match[1], name: match[2] };
+}
+
+/** Only for tests. */
+export function clearMarkerCache() {
+  markerCache.clear();
+}
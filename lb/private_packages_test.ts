@@ -0,0 +1,64 @@
+// Copyright 2024 the JSR authors. All rights reserved. MIT license.
+
+import { assertEquals } from "@std/assert";
+import {
+  clearMarkerCache,
+  isPrivatePackage,
+  modulePathPackage,
+  npmPathPackage,
+} from "./private_packages.ts";
+import type { PartialBucket } from "./types.ts";
+
+function createMarkerBucket(
+  markers: string[],
+  heads: string[] = [],
+): PartialBucket {
+  return {
+    head(key: string) {
+      heads.push(key);
+      if (!markers.includes(key)) return Promise.resolve(null);
+      return Promise.resolve({ key } as unknown as R2Object);
+    },
+  } as PartialBucket;
+}
+
+Deno.test("isPrivatePackage looks for the marker once", async () => {
+  clearMarkerCache();
+  const heads: string[] = [];
+  const bucket = createMarkerBucket(["@std/secret/_private"], heads);
+
+  const secret = { scope: "std", name: "secret" };
+  const fs = { scope: "std", name: "fs" };
+  assertEquals(await isPrivatePackage(bucket, secret), true);
+  assertEquals(await isPrivatePackage(bucket, secret), true);
+  assertEquals(await isPrivatePackage(bucket, fs), false);
+  assertEquals(await isPrivatePackage(bucket, fs), false);
+  assertEquals(heads, ["@std/secret/_private", "@std/fs/_private"]);
+});
+
+Deno.test("modulePathPackage", () => {
+  assertEquals(modulePathPackage("/@std/fs/meta.json"), {
+    scope: "std",
+    name: "fs",
+  });
+  assertEquals(modulePathPackage("/@std/fs/1.0.0/mod.ts"), {
+    scope: "std",
+    name: "fs",
+  });
+  assertEquals(modulePathPackage("/@std/fs"), null);
+  assertEquals(modulePathPackage("/blobs/sha256-abc"), null);
+});
+
+Deno.test("npmPathPackage", () => {
+  const fs = { scope: "std", name: "fs" };
+  assertEquals(npmPathPackage("/@jsr/std__fs"), fs);
+  assertEquals(npmPathPackage("/@jsr%2fstd__fs"), fs);
+  assertEquals(npmPathPackage("/~/11/@jsr/std__fs/1.0.0.tgz"), fs);
+  assertEquals(
+    npmPathPackage("/-/npm/v1/attestations/@jsr/std__fs@1.0.0"),
+    fs,
+  );
+  assertEquals(npmPathPackage("/"), null);
+  assertEquals(npmPathPackage("/-/npm/v1/keys"), null);
+  assertEquals(npmPathPackage("/@jsr/std"), null);
+});
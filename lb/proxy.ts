@@ -45,15 +45,47 @@ async function persistCacheWrite(
 // on the raw URL cross-serves HTML for module files (and vice versa). Bucket
 // entries are namespaced under a synthetic, non-routable host (which no real
 // request can ever target, so it can't be poisoned) keyed by the original host
-// + path so module and npm buckets also stay distinct.
-function bucketCacheKey(rawUrl: string): Request {
+// + path so module and npm buckets also stay distinct. Responses with a
+// precompressed body are kept apart per content encoding under a subdomain of
+// that host.
+function bucketCacheKey(rawUrl: string, encoding?: string): Request {
   const u = new URL(rawUrl);
+  const host = encoding
+    ? `${encoding}.bucket-cache.jsr.internal`
+    : "bucket-cache.jsr.internal";
   return new Request(
-    `https://bucket-cache.jsr.internal/${u.host}${u.pathname}${u.search}`,
+    `https://${host}/${u.host}${u.pathname}${u.search}`,
     { method: "GET" },
   );
 }
 
+// Content encodings of the precompressed copies the API uploads next to
+// module files, with the suffix of their key, in order of preference.
+const PRECOMPRESSED_ENCODINGS = [
+  { name: "br", suffix: ".br" },
+  { name: "zstd", suffix: ".zst" },
+] as const;
+
+type PrecompressedEncoding = typeof PRECOMPRESSED_ENCODINGS[number];
+
+// The most preferred precompressed encoding that an `Accept-Encoding` header
+// accepts, if any. Encodings with `q=0` are explicitly refused.
+export function negotiateEncoding(
+  acceptEncoding: string | null,
+): PrecompressedEncoding | undefined {
+  if (!acceptEncoding) return undefined;
+  const accepted = new Set<string>();
+  for (const entry of acceptEncoding.split(",")) {
+    const [name, ...params] = entry.split(";").map((part) => part.trim());
+    const q = params.find((param) => param.startsWith("q="));
+    if (q && Number(q.slice(2)) === 0) continue;
+    accepted.add(name.toLowerCase());
+  }
+  return PRECOMPRESSED_ENCODINGS.find((encoding) =>
+    accepted.has(encoding.name)
+  );
+}
+
 // Response header the API sets on routes whose body does not depend on the
 // requesting identity (no permission/member/sudo branch — e.g. docs/diff). It
 // lets the lb serve such responses from its shared (URL-keyed) cache even to
@@ -72,12 +104,15 @@ function isIdentityIndependent(res: Response): boolean {
 
 // Proxies an inbound request to a backend. The backend can be either an
 // HTTP URL (Cloud Run API) or a service-binding Fetcher (frontend Worker).
-// In both cases the caller receives the same cache + header semantics.
+// In both cases the caller receives the same cache + header semantics. An
+// HTTP backend is sent the origin secret, which the API requires so that it
+// can't be reached without going through the lb.
 export async function proxyToBackend(
   request: Request,
   backend: string | { fetch: (req: Request) => Promise<Response> },
   pathRewrite?: (path: string) => string,
   ctx?: ExecutionCtx,
+  originSecret?: string,
 ): Promise<Response> {
   const url = new URL(request.url);
   let path = url.pathname;
@@ -105,13 +140,18 @@ export async function proxyToBackend(
     headers.set("Host", new URL(backend).host);
   }
 
+  // The API keys rate limits by the client IP in X-Forwarded-For, so a value
+  // sent by the client must never reach it.
   const clientIP = request.headers.get("CF-Connecting-IP");
   if (clientIP) {
-    const existingForwarded = headers.get("X-Forwarded-For");
-    headers.set(
-      "X-Forwarded-For",
-      existingForwarded ? `${existingForwarded}, ${clientIP}` : clientIP,
-    );
+    headers.set("X-Forwarded-For", clientIP);
+  } else {
+    headers.delete("X-Forwarded-For");
+  }
+
+  headers.delete("X-JSR-Origin-Secret");
+  if (isUrlBackend && originSecret) {
+    headers.set("X-JSR-Origin-Secret", originSecret);
   }
 
   headers.set("X-Forwarded-Proto", url.protocol.slice(0, -1));
@@ -191,11 +231,18 @@ export async function proxyToBackend(
   }
 }
 
+// Serves an object of an R2 bucket. With `precompressed`, GET requests are
+// served the brotli or zstd compressed copy of the object if the client
+// accepts it and the copy exists, and the uncompressed object otherwise.
+// `contentType` replaces the content type stored with the object, for objects
+// that are shared between files of different types; `null` removes it.
 export async function proxyToR2(
   request: Request,
   bucket: PartialBucket,
   pathRewrite?: (path: string) => string,
   ctx?: ExecutionCtx,
+  precompressed = false,
+  contentType?: string | null,
 ): Promise<Response> {
   const url = new URL(request.url);
   let path = url.pathname;
@@ -204,7 +251,10 @@ export async function proxyToR2(
   }
   const key = decodeURIComponent(path.slice(1));
 
-  const cacheKey = bucketCacheKey(request.url);
+  const encoding = precompressed && request.method === "GET"
+    ? negotiateEncoding(request.headers.get("Accept-Encoding"))
+    : undefined;
+  const cacheKey = bucketCacheKey(request.url, encoding?.name);
   let cached: Response | undefined;
   try {
     cached = await caches.default?.match(cacheKey);
@@ -236,13 +286,23 @@ export async function proxyToR2(
       }
       const headers = new Headers();
       object.writeHttpMetadata(headers);
+      setContentType(headers, contentType);
       headers.set("etag", object.httpEtag);
       headers.set("content-length", object.size.toString());
       return new Response(null, { headers });
     } else {
-      const object = await bucket.get(key, {
-        onlyIf: request.headers,
-      });
+      // Objects uploaded before precompressed copies were introduced have
+      // none, so fall back to the object itself. That response is cached under
+      // the key of the encoding too, so the copy is only looked for once.
+      let object = encoding
+        ? await bucket.get(key + encoding.suffix, { onlyIf: request.headers })
+        : null;
+      const encoded = object !== null;
+      if (!object) {
+        object = await bucket.get(key, {
+          onlyIf: request.headers,
+        });
+      }
 
       if (!object) {
         return new Response("404 - Not Found", { status: 404 });
@@ -250,14 +310,28 @@ export async function proxyToR2(
 
       const headers = new Headers();
       object.writeHttpMetadata(headers);
+      setContentType(headers, contentType);
       headers.set("etag", object.httpEtag);
       headers.set("content-length", object.size.toString());
+      if (encoded) {
+        headers.set("content-encoding", encoding!.name);
+      }
+      if (precompressed) {
+        headers.set("Vary", "Accept-Encoding");
+      }
 
       if (!("body" in object)) {
         return new Response(null, { status: 304, headers });
       }
 
-      const response = new Response(object.body, { headers });
+      // `encodeBody: "manual"` passes the already compressed body through
+      // as-is, instead of the Workers runtime compressing it again.
+      const response = encoded
+        ? new Response(
+          object.body,
+          { headers, encodeBody: "manual" } as ResponseInit,
+        )
+        : new Response(object.body, { headers });
       const cache = caches.default;
       if (cache) {
         await persistCacheWrite(ctx, cache, cacheKey, response.clone());
@@ -273,6 +347,14 @@ export async function proxyToR2(
   }
 }
 
+function setContentType(headers: Headers, contentType?: string | null) {
+  if (contentType === null) {
+    headers.delete("content-type");
+  } else if (contentType !== undefined) {
+    headers.set("content-type", contentType);
+  }
+}
+
 async function cachedFetch(
   allowCache: boolean,
   // When true (authenticated request), the cache may only be read from / written
@@ -2,12 +2,15 @@
 // deno-lint-ignore-file require-await no-explicit-any
 
 import { assertEquals } from "@std/assert";
-import { proxyToBackend, proxyToR2 } from "./proxy.ts";
+import { negotiateEncoding, proxyToBackend, proxyToR2 } from "./proxy.ts";
 import type { PartialBucket } from "./types.ts";
 
 /** Minimal in-memory R2 bucket stub for testing. */
 function createFakeBucket(
-  objects: Record<string, { body: string; contentType?: string }>,
+  objects: Record<
+    string,
+    { body: string; contentType?: string; contentEncoding?: string }
+  >,
 ): PartialBucket {
   return {
     head(key: string): Promise<R2Object | null> {
@@ -31,6 +34,9 @@ function createFakeBucket(
         ssecKeyMd5: undefined,
         writeHttpMetadata(headers: Headers) {
           if (obj.contentType) headers.set("content-type", obj.contentType);
+          if (obj.contentEncoding) {
+            headers.set("content-encoding", obj.contentEncoding);
+          }
         },
       } as R2Object);
     },
@@ -65,6 +71,9 @@ function createFakeBucket(
         ssecKeyMd5: undefined,
         writeHttpMetadata(headers: Headers) {
           if (obj.contentType) headers.set("content-type", obj.contentType);
+          if (obj.contentEncoding) {
+            headers.set("content-encoding", obj.contentEncoding);
+          }
         },
         body,
         bodyUsed: false,
@@ -214,6 +223,92 @@ Deno.test("proxyToR2 namespaces cache keys away from the raw URL", async () => {
   }
 });
 
+Deno.test("negotiateEncoding prefers brotli, then zstd", () => {
+  assertEquals(negotiateEncoding(null), undefined);
+  assertEquals(negotiateEncoding("gzip, deflate"), undefined);
+  assertEquals(negotiateEncoding("gzip, deflate, br, zstd")?.name, "br");
+  assertEquals(negotiateEncoding("gzip, zstd")?.name, "zstd");
+  assertEquals(negotiateEncoding("br;q=0, zstd;q=0.5")?.name, "zstd");
+  assertEquals(negotiateEncoding("BR")?.name, "br");
+});
+
+Deno.test("proxyToR2 serves precompressed copies it is asked for", async () => {
+  const bucket = createFakeBucket({
+    "@std/yaml/1.0.0/mod.ts": {
+      body: "export {};",
+      contentType: "application/typescript",
+    },
+    "@std/yaml/1.0.0/mod.ts.br": {
+      body: "brotli",
+      contentType: "application/typescript",
+      contentEncoding: "br",
+    },
+  });
+  const url = "https://jsr.io/@std/yaml/1.0.0/mod.ts";
+
+  const res = await proxyToR2(
+    new Request(url, { headers: { "Accept-Encoding": "gzip, br" } }),
+    bucket,
+    undefined,
+    undefined,
+    true,
+  );
+  assertEquals(res.headers.get("content-encoding"), "br");
+  assertEquals(res.headers.get("vary"), "Accept-Encoding");
+  assertEquals(await res.text(), "brotli");
+
+  // There is no zstd copy, so the uncompressed file is served.
+  const res2 = await proxyToR2(
+    new Request(url, { headers: { "Accept-Encoding": "zstd" } }),
+    bucket,
+    undefined,
+    undefined,
+    true,
+  );
+  assertEquals(res2.headers.get("content-encoding"), null);
+  assertEquals(res2.headers.get("vary"), "Accept-Encoding");
+  assertEquals(await res2.text(), "export {};");
+
+  // Without `precompressed`, the copies are never looked at.
+  const res3 = await proxyToR2(
+    new Request(url, { headers: { "Accept-Encoding": "br" } }),
+    bucket,
+  );
+  assertEquals(res3.headers.get("content-encoding"), null);
+  assertEquals(await res3.text(), "export {};");
+});
+
+Deno.test("proxyToR2 replaces the stored content type", async () => {
+  const bucket = createFakeBucket({
+    "blobs/sha256-abc": {
+      body: "export {};",
+      contentType: "application/octet-stream",
+    },
+  });
+  const url = "https://jsr.io/@std/yaml/1.0.0/mod.ts";
+
+  const res = await proxyToR2(
+    new Request(url),
+    bucket,
+    () => "/blobs/sha256-abc",
+    undefined,
+    false,
+    "text/typescript",
+  );
+  assertEquals(res.headers.get("content-type"), "text/typescript");
+  assertEquals(await res.text(), "export {};");
+
+  const res2 = await proxyToR2(
+    new Request(url, { method: "HEAD" }),
+    bucket,
+    () => "/blobs/sha256-abc",
+    undefined,
+    false,
+    null,
+  );
+  assertEquals(res2.headers.get("content-type"), null);
+});
+
 // --- proxyToBackend tests ---
 
 /** In-memory Cache stub that records put/match calls for assertions. */
@@ -306,6 +401,79 @@ Deno.test("proxyToBackend caches anonymous GET with URL-only key", async () => {
   }
 });
 
+Deno.test("proxyToBackend only forwards the connecting client IP", async () => {
+  const forwardedFor: (string | null)[] = [];
+  const original = globalThis.fetch;
+  (globalThis as any).fetch = (input: RequestInfo | URL) => {
+    forwardedFor.push((input as Request).headers.get("X-Forwarded-For"));
+    return Promise.resolve(new Response("ok"));
+  };
+
+  try {
+    await proxyToBackend(
+      new Request("https://jsr.io/api/packages", {
+        method: "POST",
+        headers: {
+          "CF-Connecting-IP": "1.2.3.4",
+          "X-Forwarded-For": "5.6.7.8",
+        },
+      }),
+      BACKEND_URL,
+    );
+    await proxyToBackend(
+      new Request("https://jsr.io/api/packages", {
+        method: "POST",
+        headers: { "X-Forwarded-For": "5.6.7.8" },
+      }),
+      BACKEND_URL,
+    );
+    assertEquals(forwardedFor, ["1.2.3.4", null]);
+  } finally {
+    globalThis.fetch = original;
+  }
+});
+
+Deno.test("proxyToBackend sends the origin secret to the API only", async () => {
+  const secrets: (string | null)[] = [];
+  const original = globalThis.fetch;
+  (globalThis as any).fetch = (input: RequestInfo | URL) => {
+    secrets.push((input as Request).headers.get("X-JSR-Origin-Secret"));
+    return Promise.resolve(new Response("ok"));
+  };
+  const frontend = {
+    fetch(req: Request) {
+      secrets.push(req.headers.get("X-JSR-Origin-Secret"));
+      return Promise.resolve(new Response("ok"));
+    },
+  };
+
+  try {
+    const request = () =>
+      new Request("https://jsr.io/api/packages", {
+        method: "POST",
+        headers: { "X-JSR-Origin-Secret": "forged" },
+      });
+    await proxyToBackend(
+      request(),
+      BACKEND_URL,
+      undefined,
+      undefined,
+      "s3cr3t",
+    );
+    await proxyToBackend(request(), BACKEND_URL);
+    await proxyToBackend(
+      request(),
+      frontend,
+      undefined,
+      undefined,
+      "s3cr3t",
+    );
+    assertEquals(secrets, ["s3cr3t", null, null]);
+  } finally {
+    globalThis.fetch = original;
+  }
+});
+
 Deno.test("proxyToBackend caches a path-rewritten API request under the public URL package_api_cache_urls purges", async () => {
   // api.jsr.io serves the API without the `/api` prefix; the lb re-adds it via
   // pathRewrite before hitting the backend. The cache key must be the PUBLIC,
@@ -15,6 +15,11 @@ export type PartialBucket = Pick<R2Bucket, "get" | "head">;
 export interface WorkerEnv {
   REGISTRY_API_URL: string;
 
+  // Optional: omitted in local dev. Sent to the API with every request, which
+  // rejects requests without it, so the Cloud Run URL can't be called directly
+  // to forge the client IP the API rate limits by.
+  ORIGIN_SECRET?: string;
+
   // The frontend is a sibling Cloudflare Worker, wired up via a service
   // binding rather than an HTTP URL so traffic stays inside Cloudflare.
   FRONTEND: Fetcher;
@@ -107,6 +107,20 @@ resource "google_cloud_run_v2_service" "registry_api" {
         }
       }
 
+      # Only the LB worker knows the secret, so requests that call the
+      # `.run.app` URL directly are rejected.
+      env {
+        name  = "ORIGIN_SECRET"
+        value = random_password.origin_secret.result
+      }
+
+      # The LB worker sets X-Forwarded-For to the client IP, and Cloud Run
+      # appends the address of the worker.
+      env {
+        name  = "TRUSTED_PROXY_HOPS"
+        value = "1"
+      }
+
       env {
         name = "GITHUB_CLIENT_SECRET"
         value_source {
@@ -166,6 +180,16 @@ resource "google_cloud_run_v2_service" "registry_api" {
           }
         }
       }
+
+      env {
+        name = "NPM_SIGNING_KEY"
+        value_source {
+          secret_key_ref {
+            secret  = google_secret_manager_secret.npm_signing_key.id
+            version = "latest"
+          }
+        }
+      }
     }
 
     vpc_access {
@@ -374,6 +398,12 @@ resource "google_secret_manager_secret_iam_member" "cloudflare_api_token" {
   member    = "serviceAccount:${google_service_account.registry_api.email}"
 }
 
+resource "google_secret_manager_secret_iam_member" "npm_signing_key" {
+  secret_id = google_secret_manager_secret.npm_signing_key.id
+  role      = "roles/secretmanager.secretAccessor"
+  member    = "serviceAccount:${google_service_account.registry_api.email}"
+}
+
 resource "google_cloud_tasks_queue_iam_member" "publishing_tasks" {
   name   = google_cloud_tasks_queue.publishing_tasks.id
   role   = "roles/cloudtasks.enqueuer"
@@ -60,6 +60,13 @@ resource "cloudflare_workers_script" "jsr_lb" {
       name = "REGISTRY_API_URL"
       text = google_cloud_run_v2_service.registry_api.uri
       }, {
+      # Sent with every request to the API, which rejects requests without it
+      # (see ORIGIN_SECRET in cloud_run_api.tf), so the Cloud Run URL can't be
+      # called directly.
+      type = "secret_text"
+      name = "ORIGIN_SECRET"
+      text = random_password.origin_secret.result
+      }, {
       # Service binding to the frontend Worker. Terraform uploads new
       # versions via `cloudflare_worker_version.jsr_frontend` and
       # promotes them via `cloudflare_workers_deployment.jsr_frontend`;
@@ -106,6 +113,11 @@ resource "cloudflare_workers_script" "jsr_lb" {
   }
 }
 
+resource "random_password" "origin_secret" {
+  length  = 32
+  special = false
+}
+
 resource "cloudflare_workers_route" "jsr_root" {
   zone_id = var.cloudflare_zone_id
   pattern = "${var.domain_name}/*"
@@ -22,6 +22,10 @@ terraform {
       source  = "k-yomo/algolia"
       version = ">= 0.6.0, < 0.7.0"
     }
+    tls = {
+      source  = "hashicorp/tls"
+      version = ">= 4.0.0, < 5.0.0"
+    }
   }
 }
 
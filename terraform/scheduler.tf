@@ -44,6 +44,37 @@ resource "google_cloud_scheduler_job" "clean_download_counts_4h" {
   }
 }
 
+resource "google_cloud_scheduler_job" "refresh_download_rollups" {
+  name        = "refresh-download-rollups"
+  description = "Recompute the weekly and monthly downloads of all packages."
+  schedule    = "30 * * * *"
+  region      = "us-central1"
+
+  http_target {
+    http_method = "POST"
+    uri         = "${google_cloud_run_v2_service.registry_api_tasks.uri}/tasks/refresh_download_rollups"
+    oidc_token {
+      service_account_email = google_service_account.task_dispatcher.email
+    }
+  }
+}
+
+resource "google_cloud_scheduler_job" "rebuild_search_index" {
+  name             = "rebuild-search-index"
+  description      = "Rebuild the indexes of the external search engines from the database."
+  schedule         = "0 3 * * *"
+  region           = "us-central1"
+  attempt_deadline = "1800s"
+
+  http_target {
+    http_method = "POST"
+    uri         = "${google_cloud_run_v2_service.registry_api_tasks.uri}/tasks/rebuild_search_index"
+    oidc_token {
+      service_account_email = google_service_account.task_dispatcher.email
+    }
+  }
+}
+
 resource "google_cloud_scheduler_job" "requeue_stuck_publishing_tasks" {
   name        = "requeue-stuck-publishing-tasks"
   description = "Re-drive publishing tasks stranded in processing/processed so their meta.json is regenerated and the version becomes resolvable."
@@ -59,6 +90,21 @@ resource "google_cloud_scheduler_job" "requeue_stuck_publishing_tasks" {
   }
 }
 
+resource "google_cloud_scheduler_job" "expire_abandoned_publishing_tasks" {
+  name        = "expire-abandoned-publishing-tasks"
+  description = "Fail publishing tasks that never committed their version, clean their partial uploads, and free the version for re-publishing."
+  schedule    = "20 * * * *"
+  region      = "us-central1"
+
+  http_target {
+    http_method = "POST"
+    uri         = "${google_cloud_run_v2_service.registry_api_tasks.uri}/tasks/expire_abandoned_publishing_tasks"
+    oidc_token {
+      service_account_email = google_service_account.task_dispatcher.email
+    }
+  }
+}
+
 resource "google_cloud_scheduler_job" "scrape_download_counts" {
   name        = "scrape-download-counts"
   description = "Scrape download counts from Analytics Engine and insert them into Postgres."
@@ -73,3 +119,126 @@ resource "google_cloud_scheduler_job" "scrape_download_counts" {
     }
   }
 }
+
+resource "google_cloud_scheduler_job" "deliver_webhooks" {
+  name        = "deliver-webhooks"
+  description = "Send queued scope webhook deliveries and retry failed ones."
+  schedule    = "* * * * *"
+  region      = "us-central1"
+
+  http_target {
+    http_method = "POST"
+    uri         = "${google_cloud_run_v2_service.registry_api_tasks.uri}/tasks/deliver_webhooks"
+    oidc_token {
+      service_account_email = google_service_account.task_dispatcher.email
+    }
+  }
+}
+
+resource "google_cloud_scheduler_job" "run_jobs" {
+  name             = "run-jobs"
+  description      = "Run due background jobs, like npm tarball builds and score recomputations."
+  schedule         = "* * * * *"
+  region           = "us-central1"
+  attempt_deadline = "600s"
+
+  http_target {
+    http_method = "POST"
+    uri         = "${google_cloud_run_v2_service.registry_api_tasks.uri}/tasks/run_jobs"
+    oidc_token {
+      service_account_email = google_service_account.task_dispatcher.email
+    }
+  }
+}
+
+resource "google_cloud_scheduler_job" "gc_module_blobs" {
+  name        = "gc-module-blobs"
+  description = "Delete module blobs that no package version refers to anymore."
+  schedule    = "30 * * * *"
+  region      = "us-central1"
+
+  http_target {
+    http_method = "POST"
+    uri         = "${google_cloud_run_v2_service.registry_api_tasks.uri}/tasks/gc_module_blobs"
+    oidc_token {
+      service_account_email = google_service_account.task_dispatcher.email
+    }
+  }
+}
+
+resource "google_cloud_scheduler_job" "purge_tombstones" {
+  name        = "purge-tombstones"
+  description = "Permanently delete packages and versions whose retention window after deletion has passed."
+  schedule    = "50 * * * *"
+  region      = "us-central1"
+
+  http_target {
+    http_method = "POST"
+    uri         = "${google_cloud_run_v2_service.registry_api_tasks.uri}/tasks/purge_tombstones"
+    oidc_token {
+      service_account_email = google_service_account.task_dispatcher.email
+    }
+  }
+}
+
+resource "google_cloud_scheduler_job" "clean_publish_uploads" {
+  name        = "clean-publish-uploads"
+  description = "Delete the chunks of publish uploads that expired before they were committed."
+  schedule    = "45 * * * *"
+  region      = "us-central1"
+
+  http_target {
+    http_method = "POST"
+    uri         = "${google_cloud_run_v2_service.registry_api_tasks.uri}/tasks/clean_publish_uploads"
+    oidc_token {
+      service_account_email = google_service_account.task_dispatcher.email
+    }
+  }
+}
+
+resource "google_cloud_scheduler_job" "clean_data_exports" {
+  name        = "clean-data-exports"
+  description = "Delete the archives of data exports that are older than the retention window."
+  schedule    = "55 * * * *"
+  region      = "us-central1"
+
+  http_target {
+    http_method = "POST"
+    uri         = "${google_cloud_run_v2_service.registry_api_tasks.uri}/tasks/clean_data_exports"
+    oidc_token {
+      service_account_email = google_service_account.task_dispatcher.email
+    }
+  }
+}
+
+resource "google_cloud_scheduler_job" "gc_orphaned_objects" {
+  name             = "gc-orphaned-objects"
+  description      = "Delete objects in the buckets that belong to versions or publishing tasks that don't exist."
+  schedule         = "0 4 * * 0"
+  region           = "us-central1"
+  attempt_deadline = "1800s"
+
+  http_target {
+    http_method = "POST"
+    uri         = "${google_cloud_run_v2_service.registry_api_tasks.uri}/tasks/gc_orphaned_objects"
+    oidc_token {
+      service_account_email = google_service_account.task_dispatcher.email
+    }
+  }
+}
+
+resource "google_cloud_scheduler_job" "send_scope_digests" {
+  name             = "send-scope-digests"
+  description      = "Email scope members a digest of the versions published to their scopes in the last week."
+  schedule         = "0 9 * * 1"
+  region           = "us-central1"
+  attempt_deadline = "1800s"
+
+  http_target {
+    http_method = "POST"
+    uri         = "${google_cloud_run_v2_service.registry_api_tasks.uri}/tasks/send_scope_digests"
+    oidc_token {
+      service_account_email = google_service_account.task_dispatcher.email
+    }
+  }
+}
@@ -71,4 +71,19 @@ resource "google_secret_manager_secret_version" "cloudflare_api_token" {
   secret_data = var.cloudflare_api_token
 }
 
+resource "tls_private_key" "npm_signing_key" {
+  algorithm   = "ECDSA"
+  ecdsa_curve = "P256"
+}
 
+resource "google_secret_manager_secret" "npm_signing_key" {
+  secret_id = "npm-signing-key"
+  replication {
+    auto {}
+  }
+}
+
+resource "google_secret_manager_secret_version" "npm_signing_key" {
+  secret      = google_secret_manager_secret.npm_signing_key.id
+  secret_data = tls_private_key.npm_signing_key.private_key_pem_pkcs8
+}
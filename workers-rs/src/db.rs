@@ -4,6 +4,7 @@ use std::str::FromStr;
 
 use jsr_types::api::ApiMetrics;
 use jsr_types::api::ApiStats;
+use jsr_types::api::ApiStatsDownloadedPackage;
 use jsr_types::api::ApiStatsPackage;
 use jsr_types::api::ApiStatsPackageVersion;
 use jsr_types::ids::PackageName;
@@ -58,7 +59,20 @@ pub async fn ping(client: &Client) -> Result<i32> {
   Ok(row.get::<_, i32>(0))
 }
 
-/// `GET /api/stats`. Queries kept verbatim with `Database::package_stats`.
+fn downloaded_packages(rows: &[Row]) -> Result<Vec<ApiStatsDownloadedPackage>> {
+  let mut packages = Vec::with_capacity(rows.len());
+  for row in rows {
+    packages.push(ApiStatsDownloadedPackage {
+      scope: scope_name(row, "scope")?,
+      name: package_name(row, "name")?,
+      downloads: row.get::<_, i64>("downloads").max(0) as u64,
+    });
+  }
+  Ok(packages)
+}
+
+/// `GET /api/stats`. Queries kept verbatim with `Database::package_stats` and
+/// `Database::most_downloaded_packages`.
 pub async fn stats(client: &Client) -> Result<ApiStats> {
   let newest_rows = client
     .query(
@@ -100,6 +114,32 @@ pub async fn stats(client: &Client) -> Result<ApiStats> {
     .await
     .map_err(map_err)?;
 
+  let weekly_rows = client
+    .query(
+      r#"SELECT packages.scope as "scope", packages.name as "name", package_download_rollups.weekly_downloads as "downloads"
+      FROM package_download_rollups
+      JOIN packages ON packages.scope = package_download_rollups.scope AND packages.name = package_download_rollups.package
+      WHERE package_download_rollups.weekly_downloads > 0 AND NOT packages.is_archived AND packages.visibility = 'public'
+      ORDER BY package_download_rollups.weekly_downloads DESC, packages.scope ASC, packages.name ASC
+      LIMIT 10"#,
+      &[],
+    )
+    .await
+    .map_err(map_err)?;
+
+  let monthly_rows = client
+    .query(
+      r#"SELECT packages.scope as "scope", packages.name as "name", package_download_rollups.monthly_downloads as "downloads"
+      FROM package_download_rollups
+      JOIN packages ON packages.scope = package_download_rollups.scope AND packages.name = package_download_rollups.package
+      WHERE package_download_rollups.monthly_downloads > 0 AND NOT packages.is_archived AND packages.visibility = 'public'
+      ORDER BY package_download_rollups.monthly_downloads DESC, packages.scope ASC, packages.name ASC
+      LIMIT 10"#,
+      &[],
+    )
+    .await
+    .map_err(map_err)?;
+
   let mut newest = Vec::with_capacity(newest_rows.len());
   for row in &newest_rows {
     newest.push(ApiStatsPackage {
@@ -130,6 +170,8 @@ pub async fn stats(client: &Client) -> Result<ApiStats> {
     newest,
     updated,
     featured,
+    most_downloaded_this_week: downloaded_packages(&weekly_rows)?,
+    most_downloaded_this_month: downloaded_packages(&monthly_rows)?,
   })
 }
 